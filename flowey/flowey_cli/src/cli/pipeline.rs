@@ -1,10 +1,13 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use crate::pipeline_resolver::graph::emit_pipeline_graph;
+use crate::pipeline_resolver::graph::GraphFormat;
 use anyhow::Context;
 use flowey_core::node::FlowBackend;
 use flowey_core::pipeline::IntoPipeline;
 use flowey_core::pipeline::PipelineBackendHint;
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -116,6 +119,14 @@ enum PipelineBackendCli<P: clap::Subcommand> {
         /// in WSL2.
         #[clap(help_heading = "Global Options (flowey)", global = true, long)]
         windows_as_wsl: bool,
+
+        /// Maximum number of jobs to run concurrently. Jobs are only run once
+        /// all the jobs they depend on have finished.
+        ///
+        /// When running more than one job at a time, each job's output is
+        /// written to a log file under `<out_dir>/logs`.
+        #[clap(long, short = 'j', default_value = "1")]
+        jobs: NonZeroUsize,
     },
 }
 
@@ -129,6 +140,17 @@ pub struct Pipeline<P: clap::Subcommand> {
     #[clap(help_heading = "Global Options (flowey)", global = true, long)]
     viz_mode: Option<VizModeCli>,
 
+    /// Emit the pipeline's job dependency graph (annotated with the artifacts
+    /// passed between jobs, and the pipeline's critical path), instead of the
+    /// pipeline itself.
+    #[clap(
+        help_heading = "Global Options (flowey)",
+        global = true,
+        long,
+        conflicts_with = "viz_mode"
+    )]
+    emit_graph: Option<GraphFormat>,
+
     /// (debug) Filter the pipeline to only include the specified jobs.
     ///
     /// At this time, this will _not_ allow running a job without also running
@@ -184,6 +206,7 @@ pub fn run(self, flowey_crate: &str, repo_root: &Path) -> anyhow::Result<()> {
         let Self {
             project_pipeline,
             viz_mode,
+            emit_graph,
             include_jobs,
         } = self;
 
@@ -205,6 +228,10 @@ pub fn run(self, flowey_crate: &str, repo_root: &Path) -> anyhow::Result<()> {
                     return Ok(());
                 }
 
+                if let Some(format) = emit_graph {
+                    return emit_pipeline_graph(&resolved_pipeline, format);
+                }
+
                 if let Some(viz_mode) = viz_mode {
                     viz_pipeline(
                         viz_mode,
@@ -222,6 +249,7 @@ pub fn run(self, flowey_crate: &str, repo_root: &Path) -> anyhow::Result<()> {
                 out_dir,
                 persist_dir,
                 windows_as_wsl,
+                jobs,
             } => {
                 let mut resolved_pipeline =
                     resolve_pipeline(pipelines, PipelineBackendHint::Local)?;
@@ -233,6 +261,10 @@ pub fn run(self, flowey_crate: &str, repo_root: &Path) -> anyhow::Result<()> {
                     return Ok(());
                 }
 
+                if let Some(format) = emit_graph {
+                    return emit_pipeline_graph(&resolved_pipeline, format);
+                }
+
                 if let Some(viz_mode) = viz_mode {
                     viz_pipeline(
                         viz_mode,
@@ -246,6 +278,7 @@ pub fn run(self, flowey_crate: &str, repo_root: &Path) -> anyhow::Result<()> {
                         windows_as_wsl,
                         out_dir,
                         persist_dir,
+                        jobs,
                     )
                 }
             }
@@ -265,6 +298,10 @@ pub fn run(self, flowey_crate: &str, repo_root: &Path) -> anyhow::Result<()> {
                     return Ok(());
                 }
 
+                if let Some(format) = emit_graph {
+                    return emit_pipeline_graph(&resolved_pipeline, format);
+                }
+
                 if let Some(viz_mode) = viz_mode {
                     viz_pipeline(viz_mode, resolved_pipeline, FlowBackend::Ado, false)
                 } else {
@@ -303,6 +340,10 @@ pub fn run(self, flowey_crate: &str, repo_root: &Path) -> anyhow::Result<()> {
                     return Ok(());
                 }
 
+                if let Some(format) = emit_graph {
+                    return emit_pipeline_graph(&resolved_pipeline, format);
+                }
+
                 if let Some(viz_mode) = viz_mode {
                     viz_pipeline(viz_mode, resolved_pipeline, FlowBackend::Github, false)
                 } else {
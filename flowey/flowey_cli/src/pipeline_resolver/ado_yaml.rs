@@ -86,6 +86,7 @@ pub fn ado_yaml(
             platform,
             arch,
             cond_param_idx,
+            changed_paths_filter: _,
             ref ado_pool,
             gh_override_if: _,
             gh_global_env: _,
@@ -141,6 +142,11 @@ pub fn ado_yaml(
                     match platform {
                         FlowPlatform::Windows => "x86_64-pc-windows-msvc",
                         FlowPlatform::Linux(_) => "x86_64-unknown-linux-gnu",
+                        FlowPlatform::MacOs => match arch {
+                            FlowArch::X86_64 => "x86_64-apple-darwin",
+                            FlowArch::Aarch64 => "aarch64-apple-darwin",
+                            arch => anyhow::bail!("unsupported ADO arch {arch:?}"),
+                        },
                         platform => anyhow::bail!("unsupported ADO platform {platform:?}"),
                     },
                 )
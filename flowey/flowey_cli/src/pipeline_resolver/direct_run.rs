@@ -8,6 +8,7 @@
 use crate::pipeline_resolver::generic::ResolvedJobUseParameter;
 use crate::pipeline_resolver::generic::ResolvedPipeline;
 use crate::pipeline_resolver::generic::ResolvedPipelineJob;
+use anyhow::Context;
 use flowey_core::node::steps::rust::RustRuntimeServices;
 use flowey_core::node::FlowArch;
 use flowey_core::node::FlowBackend;
@@ -17,7 +18,9 @@
 use flowey_core::pipeline::internal::Parameter;
 use petgraph::prelude::NodeIndex;
 use petgraph::visit::EdgeRef;
+use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::num::NonZeroUsize;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -30,14 +33,50 @@ pub struct ResolvedRunnableNode {
     )>,
 }
 
+/// Set when flowey re-invokes itself to run a single job from the pipeline, as
+/// part of running multiple jobs in parallel. Contains the index of the job in
+/// the pipeline's topological order.
+const ENV_FLOWEY_DIRECT_RUN_JOB: &str = "FLOWEY_DIRECT_RUN_JOB";
+
 /// Directly run the pipeline using flowey
+///
+/// If `jobs` is greater than 1, independent jobs are run concurrently, with
+/// each job running in its own child flowey process.
 pub fn direct_run(
     pipeline: ResolvedPipeline,
     windows_as_wsl: bool,
     out_dir: PathBuf,
     persist_dir: PathBuf,
+    jobs: NonZeroUsize,
 ) -> anyhow::Result<()> {
-    direct_run_do_work(pipeline, windows_as_wsl, out_dir.clone(), persist_dir)?;
+    if let Ok(job) = std::env::var(ENV_FLOWEY_DIRECT_RUN_JOB) {
+        let job: usize = job
+            .parse()
+            .with_context(|| format!("invalid {ENV_FLOWEY_DIRECT_RUN_JOB}: {job}"))?;
+        let out_dir = std::path::absolute(out_dir)?;
+        let job_dir = out_dir.join(".jobs").join(format!("{job}"));
+        return direct_run_do_work(
+            pipeline,
+            windows_as_wsl,
+            out_dir,
+            job_dir,
+            persist_dir,
+            Some(job),
+        );
+    }
+
+    if jobs.get() == 1 {
+        direct_run_do_work(
+            pipeline,
+            windows_as_wsl,
+            out_dir.clone(),
+            out_dir.clone(),
+            persist_dir,
+            None,
+        )?;
+    } else {
+        direct_run_parallel(pipeline, windows_as_wsl, out_dir.clone(), jobs)?;
+    }
 
     // cleanup
     if out_dir.join(".job_artifacts").exists() {
@@ -46,19 +85,214 @@ pub fn direct_run(
     if out_dir.join(".work").exists() {
         fs_err::remove_dir_all(out_dir.join(".work"))?;
     }
+    if out_dir.join(".jobs").exists() {
+        fs_err::remove_dir_all(out_dir.join(".jobs"))?;
+    }
+
+    Ok(())
+}
+
+/// Run up to `max_jobs` jobs at once, scheduling each job as soon as all the
+/// jobs it depends on have completed.
+///
+/// Each job is run by re-invoking the current flowey binary with the same
+/// arguments, with [`ENV_FLOWEY_DIRECT_RUN_JOB`] set to the job to run. This
+/// ensures that jobs don't trample on one another's process-global state
+/// (e.g: the current working directory), and allows each job's output to be
+/// captured into its own log file.
+fn direct_run_parallel(
+    pipeline: ResolvedPipeline,
+    windows_as_wsl: bool,
+    out_dir: PathBuf,
+    max_jobs: NonZeroUsize,
+) -> anyhow::Result<()> {
+    fs_err::create_dir_all(&out_dir)?;
+    let out_dir = std::path::absolute(out_dir)?;
+    let log_dir = out_dir.join("logs");
+    fs_err::create_dir_all(&log_dir)?;
+
+    let ResolvedPipeline { graph, order, .. } = pipeline;
+
+    let present_idx = order
+        .iter()
+        .enumerate()
+        .map(|(i, idx)| (*idx, i))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut pending_deps = order
+        .iter()
+        .map(|&idx| {
+            let deps = graph
+                .edges_directed(idx, petgraph::Direction::Incoming)
+                .map(|e| e.source())
+                .collect::<BTreeSet<_>>();
+            (idx, deps)
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    let flow_arch = local_flow_arch();
+    let exe = std::env::current_exe()?;
+
+    let mut skipped_jobs = BTreeSet::new();
+    let mut running = Vec::<(NodeIndex, std::process::Child)>::new();
+    let mut failed_jobs = Vec::new();
+
+    loop {
+        // only schedule new jobs if everything has been going smoothly
+        while failed_jobs.is_empty() && running.len() < max_jobs.get() {
+            // prefer jobs that come first in the pipeline's topological order,
+            // to keep things somewhat predictable
+            let Some(idx) = order
+                .iter()
+                .copied()
+                .find(|idx| pending_deps.get(idx).is_some_and(|deps| deps.is_empty()))
+            else {
+                break;
+            };
+            pending_deps.remove(&idx);
+
+            let ResolvedPipelineJob {
+                ref label,
+                platform,
+                arch,
+                ..
+            } = graph[idx];
+
+            let skip = if graph
+                .edges_directed(idx, petgraph::Direction::Incoming)
+                .any(|e| skipped_jobs.contains(&e.source()))
+            {
+                log::error!("{label}: job depends on job that was skipped. skipping job...");
+                true
+            } else if arch != flow_arch {
+                // matches the sequential behavior, where jobs for other
+                // architectures are ignored (rather than skipped)
+                log::error!("{label}: mismatch between job arch and local arch. skipping job...");
+                false
+            } else if !local_platform_ok(platform, windows_as_wsl) {
+                log::error!(
+                    "{label}: mismatch between job platform and local platform. skipping job..."
+                );
+                true
+            } else {
+                let job = present_idx[&idx];
+                let log_path = log_dir.join(format!("job{job}.log"));
+                let log_file = fs_err::File::create(&log_path)?.into_parts().0;
+
+                // orange color
+                log::info!(
+                    "\x1B[0;33m### started: {label} ###\x1B[0m (log: {})",
+                    log_path.display()
+                );
+
+                let child = std::process::Command::new(&exe)
+                    .args(std::env::args_os().skip(1))
+                    .env(ENV_FLOWEY_DIRECT_RUN_JOB, job.to_string())
+                    .stdin(std::process::Stdio::null())
+                    .stdout(log_file.try_clone()?)
+                    .stderr(log_file)
+                    .spawn()
+                    .with_context(|| format!("failed to spawn job '{label}'"))?;
+                running.push((idx, child));
+                continue;
+            };
+
+            if skip {
+                skipped_jobs.insert(idx);
+            }
+            for deps in pending_deps.values_mut() {
+                deps.remove(&idx);
+            }
+        }
+
+        if running.is_empty() {
+            break;
+        }
+
+        // std doesn't offer a way to wait on multiple child processes at
+        // once, so just poll them
+        let mut finished = Vec::new();
+        for (i, (_, child)) in running.iter_mut().enumerate() {
+            if let Some(status) = child.try_wait()? {
+                finished.push((i, status));
+            }
+        }
+
+        if finished.is_empty() {
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            continue;
+        }
+
+        for (i, status) in finished.into_iter().rev() {
+            let (idx, _) = running.swap_remove(i);
+            let label = &graph[idx].label;
+            let log_path = log_dir.join(format!("job{}.log", present_idx[&idx]));
+
+            if status.success() {
+                // green color
+                log::info!("\x1B[0;32m### finished: {label} ###\x1B[0m");
+                for deps in pending_deps.values_mut() {
+                    deps.remove(&idx);
+                }
+            } else {
+                log::error!("### failed: {label} ({status}) ###");
+                log::error!("see {} for details", log_path.display());
+                failed_jobs.push(label.clone());
+                if !running.is_empty() {
+                    log::warn!("waiting for {} running job(s) to finish...", running.len());
+                }
+            }
+        }
+    }
+
+    if !failed_jobs.is_empty() {
+        anyhow::bail!("failed jobs: {}", failed_jobs.join(", "));
+    }
 
     Ok(())
 }
 
+/// The architecture flowey is currently running on
+fn local_flow_arch() -> FlowArch {
+    // xtask-fmt allow-target-arch oneoff-flowey
+    if cfg!(target_arch = "x86_64") {
+        FlowArch::X86_64
+    // xtask-fmt allow-target-arch oneoff-flowey
+    } else if cfg!(target_arch = "aarch64") {
+        FlowArch::Aarch64
+    } else {
+        unreachable!("flowey only runs on X86_64 or Aarch64 at the moment")
+    }
+}
+
+/// Whether jobs targeting `platform` can be run on the current machine
+fn local_platform_ok(platform: FlowPlatform, windows_as_wsl: bool) -> bool {
+    match platform {
+        FlowPlatform::Windows => cfg!(windows) || (cfg!(target_os = "linux") && windows_as_wsl),
+        FlowPlatform::Linux(_) => cfg!(target_os = "linux"),
+        FlowPlatform::MacOs => cfg!(target_os = "macos"),
+        platform => panic!("unknown platform {platform}"),
+    }
+}
+
+/// Run the jobs in the pipeline sequentially (or just `only_job`, if
+/// specified).
+///
+/// Job-specific scratch directories are created under `job_dir`.
 fn direct_run_do_work(
     pipeline: ResolvedPipeline,
     windows_as_wsl: bool,
     out_dir: PathBuf,
+    job_dir: PathBuf,
     persist_dir: PathBuf,
+    only_job: Option<usize>,
 ) -> anyhow::Result<()> {
     fs_err::create_dir_all(&out_dir)?;
     let out_dir = std::path::absolute(out_dir)?;
 
+    fs_err::create_dir_all(&job_dir)?;
+    let job_dir = std::path::absolute(job_dir)?;
+
     fs_err::create_dir_all(&persist_dir)?;
     let persist_dir = std::path::absolute(persist_dir)?;
 
@@ -83,7 +317,11 @@ fn direct_run_do_work(
 
     let mut skipped_jobs = BTreeSet::new();
 
-    for idx in order {
+    for (present_idx, idx) in order.into_iter().enumerate() {
+        if only_job.is_some_and(|job| job != present_idx) {
+            continue;
+        }
+
         let ResolvedPipelineJob {
             ref root_nodes,
             ref patches,
@@ -91,6 +329,7 @@ fn direct_run_do_work(
             platform,
             arch,
             cond_param_idx,
+            changed_paths_filter: _,
             ado_pool: _,
             ado_variables: _,
             gh_override_if: _,
@@ -117,15 +356,7 @@ fn direct_run_do_work(
             continue;
         }
 
-        // xtask-fmt allow-target-arch oneoff-flowey
-        let flow_arch = if cfg!(target_arch = "x86_64") {
-            FlowArch::X86_64
-        // xtask-fmt allow-target-arch oneoff-flowey
-        } else if cfg!(target_arch = "aarch64") {
-            FlowArch::Aarch64
-        } else {
-            unreachable!("flowey only runs on X86_64 or Aarch64 at the moment")
-        };
+        let flow_arch = local_flow_arch();
 
         match (arch, flow_arch) {
             (FlowArch::X86_64, FlowArch::X86_64) | (FlowArch::Aarch64, FlowArch::Aarch64) => (),
@@ -135,14 +366,7 @@ fn direct_run_do_work(
             }
         }
 
-        let platform_ok = match platform {
-            FlowPlatform::Windows => cfg!(windows) || (cfg!(target_os = "linux") && windows_as_wsl),
-            FlowPlatform::Linux(_) => cfg!(target_os = "linux"),
-            FlowPlatform::MacOs => cfg!(target_os = "macos"),
-            platform => panic!("unknown platform {platform}"),
-        };
-
-        if !platform_ok {
+        if !local_platform_ok(platform, windows_as_wsl) {
             log::error!("mismatch between job platform and local platform. skipping job...");
             log::info!("");
             if crate::running_in_wsl() && matches!(platform, FlowPlatform::Windows) {
@@ -270,13 +494,13 @@ fn direct_run_do_work(
             );
         }
 
-        if out_dir.join(".job_artifacts").exists() {
-            fs_err::remove_dir_all(out_dir.join(".job_artifacts"))?;
+        if job_dir.join(".job_artifacts").exists() {
+            fs_err::remove_dir_all(job_dir.join(".job_artifacts"))?;
         }
-        fs_err::create_dir_all(out_dir.join(".job_artifacts"))?;
+        fs_err::create_dir_all(job_dir.join(".job_artifacts"))?;
 
         for ResolvedJobArtifact { flowey_var, name } in artifacts_used {
-            let path = out_dir.join(".job_artifacts").join(name);
+            let path = job_dir.join(".job_artifacts").join(name);
             fs_err::create_dir_all(&path)?;
             copy_dir_all(out_dir.join("artifacts").join(name), &path)?;
 
@@ -287,10 +511,10 @@ fn direct_run_do_work(
             );
         }
 
-        if out_dir.join(".work").exists() {
-            fs_err::remove_dir_all(out_dir.join(".work"))?;
+        if job_dir.join(".work").exists() {
+            fs_err::remove_dir_all(job_dir.join(".work"))?;
         }
-        fs_err::create_dir_all(out_dir.join(".work"))?;
+        fs_err::create_dir_all(job_dir.join(".work"))?;
 
         let mut runtime_services = flowey_core::node::steps::rust::new_rust_runtime_services(
             &mut in_mem_var_db,
@@ -322,7 +546,7 @@ fn direct_run_do_work(
 
         for ResolvedRunnableNode { node_handle, steps } in nodes {
             for (idx, label, code) in steps {
-                let node_working_dir = out_dir.join(".work").join(format!(
+                let node_working_dir = job_dir.join(".work").join(format!(
                     "{}_{}",
                     node_handle.modpath().replace("::", "__"),
                     idx
@@ -72,6 +72,7 @@ pub struct ResolvedPipelineJob {
     pub gh_permissions: BTreeMap<NodeHandle, BTreeMap<GhPermission, GhPermissionValue>>,
     pub external_read_vars: BTreeSet<String>,
     pub cond_param_idx: Option<usize>,
+    pub changed_paths_filter: Option<Vec<String>>,
 
     pub parameters_used: Vec<ResolvedJobUseParameter>,
     // correspond to injected download nodes at the start of the job
@@ -166,6 +167,7 @@ pub fn resolve_pipeline(pipeline: Pipeline) -> anyhow::Result<ResolvedPipeline>
             platform,
             arch,
             cond_param_idx,
+            changed_paths_filter,
             ado_pool,
             ado_variables,
             gh_override_if,
@@ -228,6 +230,7 @@ pub fn resolve_pipeline(pipeline: Pipeline) -> anyhow::Result<ResolvedPipeline>
             platform,
             arch,
             cond_param_idx,
+            changed_paths_filter,
             external_read_vars,
             parameters_used,
             artifacts_used,
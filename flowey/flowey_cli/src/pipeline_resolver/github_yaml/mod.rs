@@ -76,6 +76,7 @@ pub fn github_yaml(
     };
 
     let mut github_jobs = BTreeMap::new();
+    let mut changed_paths_filters = BTreeMap::new();
 
     for job_idx in order {
         let ResolvedPipelineJob {
@@ -91,6 +92,7 @@ pub fn github_yaml(
             ref gh_pool,
             ref gh_permissions,
             cond_param_idx: _,
+            ref changed_paths_filter,
             ref parameters_used,
             ref artifacts_used,
             ref artifacts_published,
@@ -143,6 +145,8 @@ pub fn github_yaml(
                         (FlowPlatform::Windows, FlowArch::Aarch64) => "aarch64-pc-windows-msvc",
                         (FlowPlatform::Linux(_), FlowArch::X86_64) => "x86_64-unknown-linux-gnu",
                         (FlowPlatform::Linux(_), FlowArch::Aarch64) => "aarch64-unknown-linux-gnu",
+                        (FlowPlatform::MacOs, FlowArch::X86_64) => "x86_64-apple-darwin",
+                        (FlowPlatform::MacOs, FlowArch::Aarch64) => "aarch64-apple-darwin",
                         (platform, arch) => {
                             anyhow::bail!("unsupported platform {platform} / arch {arch}")
                         }
@@ -552,8 +556,29 @@ pub fn github_yaml(
             }
         }
 
+        let job_id = format!("job{}", job_idx.index());
+
+        let mut needs: Vec<String> = graph
+            .edges_directed(job_idx, petgraph::Direction::Incoming)
+            .map(|e| {
+                use petgraph::prelude::*;
+                format!("job{}", e.source().index())
+            })
+            .collect();
+
+        let mut condition = gh_override_if
+            .clone()
+            .unwrap_or_else(|| "github.event.pull_request.draft == false".to_string());
+
+        if let Some(paths) = changed_paths_filter {
+            needs.push(CHANGED_PATHS_JOB.into());
+            condition =
+                format!("({condition}) && needs.{CHANGED_PATHS_JOB}.outputs.{job_id} == 'true'");
+            changed_paths_filters.insert(job_id.clone(), paths.clone());
+        }
+
         github_jobs.insert(
-            format!("job{}", job_idx.index()),
+            job_id,
             github_yaml_defs::Job {
                 name: label.clone(),
                 runs_on: gh_pool.clone().map(|runner| runner_kind_to_yaml(&runner)),
@@ -561,24 +586,22 @@ pub fn github_yaml(
                     .iter()
                     .map(|k| (perm_kind_to_yaml(k.0), perm_val_to_yaml(k.1)))
                     .collect(),
-                needs: {
-                    graph
-                        .edges_directed(job_idx, petgraph::Direction::Incoming)
-                        .map(|e| {
-                            use petgraph::prelude::*;
-                            format!("job{}", e.source().index())
-                        })
-                        .collect()
-                },
-                r#if: gh_override_if
-                    .clone()
-                    .or_else(|| Some("github.event.pull_request.draft == false".to_string())),
+                needs,
+                r#if: Some(condition),
                 env: gh_global_env.clone(),
+                outputs: BTreeMap::new(),
                 steps: gh_steps,
             },
         );
     }
 
+    if !changed_paths_filters.is_empty() {
+        github_jobs.insert(
+            CHANGED_PATHS_JOB.into(),
+            changed_paths_job(&changed_paths_filters)?,
+        );
+    }
+
     let mut concurrency = None;
     let pipeline_trigger = github_yaml_defs::Triggers {
         workflow_call: None,
@@ -689,6 +712,92 @@ pub fn github_yaml(
     }
 }
 
+/// Id of the job which reports which path-filtered jobs need to run.
+const CHANGED_PATHS_JOB: &str = "changed_paths";
+
+/// Emit a job which checks which files were changed by the current PR, and
+/// reports (via a job output named after each filtered job's id) whether any
+/// of them fall under that job's path filter.
+///
+/// Outside of PRs, every filtered job is reported as needing to run.
+fn changed_paths_job(
+    filters: &BTreeMap<String, Vec<String>>,
+) -> anyhow::Result<github_yaml_defs::Job> {
+    let mut script = String::from(
+        r#"
+if [[ "$GITHUB_EVENT_NAME" == "pull_request" ]]; then
+  # PRs check out a merge commit, whose first parent is the target branch
+  CHANGED=$(git diff --name-only HEAD^1 HEAD)
+  echo "$CHANGED"
+fi
+
+changed() {
+  [[ "$GITHUB_EVENT_NAME" != "pull_request" ]] && return 0
+  local file prefix
+  while IFS= read -r file; do
+    for prefix in "$@"; do
+      [[ "$file" == "$prefix"* ]] && return 0
+    done
+  done <<< "$CHANGED"
+  return 1
+}
+"#,
+    );
+
+    let mut outputs = BTreeMap::new();
+    for (job_id, paths) in filters {
+        if paths.iter().any(|p| p.contains('\'')) {
+            anyhow::bail!("path filters for {job_id} cannot contain `'`");
+        }
+        let paths = paths
+            .iter()
+            .map(|p| format!("'{p}'"))
+            .collect::<Vec<_>>()
+            .join(" ");
+        script.push_str(&format!(
+            "echo \"{job_id}=$(changed {paths} && echo true || echo false)\" >> \"$GITHUB_OUTPUT\"\n"
+        ));
+        outputs.insert(
+            job_id.clone(),
+            format!("${{{{ steps.filter.outputs.{job_id} }}}}"),
+        );
+    }
+
+    let checkout: serde_yaml::Mapping = serde_yaml::from_str(
+        r#"
+            name: Checkout
+            uses: actions/checkout@v4
+            with:
+              fetch-depth: 2
+        "#,
+    )?;
+
+    let filter = {
+        let mut map = serde_yaml::Mapping::new();
+        map.insert("name".into(), "🌼🔎 Detect changed paths".into());
+        map.insert("id".into(), "filter".into());
+        map.insert("shell".into(), "bash".into());
+        map.insert("run".into(), script.trim_start().into());
+        map
+    };
+
+    Ok(github_yaml_defs::Job {
+        name: "detect changed paths".into(),
+        runs_on: Some(github_yaml_defs::Runner::GhHosted(
+            github_yaml_defs::RunnerOsLabel::UbuntuLatest,
+        )),
+        permissions: BTreeMap::from([(
+            github_yaml_defs::Permissions::Contents,
+            github_yaml_defs::PermissionValue::Read,
+        )]),
+        needs: Vec::new(),
+        r#if: Some("github.event.pull_request.draft == false".into()),
+        env: BTreeMap::new(),
+        outputs,
+        steps: vec![checkout.into(), filter.into()],
+    })
+}
+
 /// Resolve a flow as a sequence of GitHub YAML steps.
 ///
 /// These steps can then be marshalled into a well-formed GitHub pipeline yaml
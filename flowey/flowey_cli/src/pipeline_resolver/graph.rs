@@ -0,0 +1,291 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Render the resolved job dependency graph of a pipeline, for consumption by
+//! external graph tooling.
+//!
+//! Unlike [`viz`](super::viz), which is geared towards debugging flowey
+//! itself, the output here is meant to help pipeline authors understand the
+//! shape of their pipeline: each edge is labeled with the artifacts flowing
+//! along it, and the longest chain of dependant jobs (i.e: the pipeline's
+//! critical path) is highlighted.
+
+use crate::pipeline_resolver::generic::ResolvedPipeline;
+use flowey_core::node::NodeHandle;
+use petgraph::prelude::NodeIndex;
+use petgraph::visit::EdgeRef;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum GraphFormat {
+    /// Graphviz `.dot`
+    Dot,
+    /// Mermaid flowchart
+    Mermaid,
+}
+
+struct Job {
+    label: String,
+    platform: String,
+    /// Root nodes specific to this job (i.e: excluding any nodes which are
+    /// injected into every job in the pipeline)
+    nodes: Vec<&'static str>,
+    critical: bool,
+}
+
+struct Edge {
+    from: usize,
+    to: usize,
+    /// Artifacts (and how many jobs consume them) passed between the two jobs.
+    /// Empty if this is an explicit ordering dependency.
+    artifacts: Vec<(String, usize)>,
+    critical: bool,
+}
+
+pub fn emit_pipeline_graph(pipeline: &ResolvedPipeline, format: GraphFormat) -> anyhow::Result<()> {
+    let graph = &pipeline.graph;
+
+    let mut artifact_consumers = BTreeMap::<&str, usize>::new();
+    for job in graph.node_weights() {
+        for a in &job.artifacts_used {
+            *artifact_consumers.entry(&a.name).or_default() += 1;
+        }
+    }
+
+    // nodes that show up in every job are almost certainly pipeline-wide
+    // config, and would just add noise
+    let mut common_nodes: Option<BTreeSet<NodeHandle>> = None;
+    for job in graph.node_weights() {
+        let nodes = job.root_nodes.keys().copied().collect::<BTreeSet<_>>();
+        common_nodes = Some(match common_nodes {
+            Some(common) => common.intersection(&nodes).copied().collect(),
+            None => nodes,
+        });
+    }
+    let common_nodes = common_nodes.unwrap_or_default();
+
+    // edges are added per-artifact, so dedupe them
+    let mut edges = BTreeMap::<(NodeIndex, NodeIndex), Vec<(String, usize)>>::new();
+    for e in graph.edge_references() {
+        edges.entry((e.source(), e.target())).or_insert_with(|| {
+            let published = &graph[e.source()].artifacts_published;
+            graph[e.target()]
+                .artifacts_used
+                .iter()
+                .filter(|a| published.iter().any(|p| p.name == a.name))
+                .map(|a| (a.name.clone(), artifact_consumers[a.name.as_str()]))
+                .collect()
+        });
+    }
+
+    let critical_path = critical_path(pipeline);
+    let is_critical = |from: NodeIndex, to: NodeIndex| {
+        critical_path.windows(2).any(|w| w[0] == from && w[1] == to)
+    };
+
+    // present jobs in the same order as the rest of flowey (e.g:
+    // `--include-jobs`), so that ids can be cross-referenced
+    let present_idx = pipeline
+        .order
+        .iter()
+        .enumerate()
+        .map(|(i, idx)| (*idx, i))
+        .collect::<BTreeMap<_, _>>();
+
+    let jobs = pipeline
+        .order
+        .iter()
+        .map(|&idx| {
+            let job = &graph[idx];
+            Job {
+                label: job.label.clone(),
+                platform: format!("{} {}", job.platform, job.arch),
+                nodes: job
+                    .root_nodes
+                    .keys()
+                    .filter(|n| !common_nodes.contains(n))
+                    .map(|n| n.modpath())
+                    .collect(),
+                critical: critical_path.contains(&idx),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let edges = edges
+        .into_iter()
+        .map(|((from, to), artifacts)| Edge {
+            from: present_idx[&from],
+            to: present_idx[&to],
+            artifacts,
+            critical: is_critical(from, to),
+        })
+        .collect::<Vec<_>>();
+
+    let critical_path = critical_path
+        .iter()
+        .map(|idx| graph[*idx].label.as_str())
+        .collect::<Vec<_>>();
+
+    let out = match format {
+        GraphFormat::Dot => render_dot(&jobs, &edges, &critical_path)?,
+        GraphFormat::Mermaid => render_mermaid(&jobs, &edges, &critical_path)?,
+    };
+    print!("{out}");
+
+    Ok(())
+}
+
+/// Find the longest chain of dependant jobs in the pipeline.
+///
+/// Jobs on this path can't be parallelized with one another, so it represents
+/// a lower bound on the pipeline's end-to-end latency.
+fn critical_path(pipeline: &ResolvedPipeline) -> Vec<NodeIndex> {
+    let graph = &pipeline.graph;
+
+    // (length of longest path ending at this job, previous job on that path)
+    let mut longest = BTreeMap::<NodeIndex, (usize, Option<NodeIndex>)>::new();
+    for &idx in &pipeline.order {
+        let best = graph
+            .neighbors_directed(idx, petgraph::Direction::Incoming)
+            .map(|prev| (longest[&prev].0 + 1, Some(prev)))
+            .max_by_key(|(len, _)| *len)
+            .unwrap_or((1, None));
+        longest.insert(idx, best);
+    }
+
+    let mut path = Vec::new();
+    let mut cur = longest
+        .iter()
+        .max_by_key(|(_, (len, _))| *len)
+        .map(|(idx, _)| *idx);
+    while let Some(idx) = cur {
+        path.push(idx);
+        cur = longest[&idx].1;
+    }
+    path.reverse();
+    path
+}
+
+fn artifacts_label(artifacts: &[(String, usize)], newline: &str) -> String {
+    artifacts
+        .iter()
+        .map(|(name, consumers)| {
+            if *consumers > 1 {
+                format!("{name} (x{consumers})")
+            } else {
+                name.clone()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(newline)
+}
+
+fn render_dot(jobs: &[Job], edges: &[Edge], critical_path: &[&str]) -> anyhow::Result<String> {
+    fn escape(s: &str) -> String {
+        s.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    let mut out = String::new();
+    writeln!(out, "// critical path: {}", critical_path.join(" -> "))?;
+    writeln!(out, "digraph pipeline {{")?;
+    writeln!(out, "    rankdir=\"LR\";")?;
+    writeln!(out, "    node [shape=box];")?;
+
+    for (i, job) in jobs.iter().enumerate() {
+        let mut label = format!("{i}: {}\\n{}", escape(&job.label), job.platform);
+        if !job.nodes.is_empty() {
+            label.push_str("\\n");
+            for node in &job.nodes {
+                write!(label, "\\n{}\\l", escape(node))?;
+            }
+        }
+        write!(out, "    j{i} [label=\"{label}\"")?;
+        if job.critical {
+            write!(out, ", color=\"red\", penwidth=2")?;
+        }
+        writeln!(out, "];")?;
+    }
+
+    for edge in edges {
+        write!(out, "    j{} -> j{} [", edge.from, edge.to)?;
+        if edge.artifacts.is_empty() {
+            write!(out, "style=\"dashed\"")?;
+        } else {
+            write!(
+                out,
+                "label=\"{}\"",
+                escape(&artifacts_label(&edge.artifacts, "\n")).replace('\n', "\\n")
+            )?;
+        }
+        if edge.critical {
+            write!(out, ", color=\"red\", penwidth=2")?;
+        }
+        writeln!(out, "];")?;
+    }
+
+    writeln!(out, "}}")?;
+    Ok(out)
+}
+
+fn render_mermaid(jobs: &[Job], edges: &[Edge], critical_path: &[&str]) -> anyhow::Result<String> {
+    fn escape(s: &str) -> String {
+        s.replace('"', "#quot;")
+            .replace('<', "#lt;")
+            .replace('>', "#gt;")
+    }
+
+    let mut out = String::new();
+    writeln!(out, "%% critical path: {}", critical_path.join(" -> "))?;
+    writeln!(out, "flowchart LR")?;
+    writeln!(out, "    classDef critical stroke:red,stroke-width:3px")?;
+
+    for (i, job) in jobs.iter().enumerate() {
+        let mut label = format!("{i}: {}<br/>{}", escape(&job.label), job.platform);
+        if !job.nodes.is_empty() {
+            label.push_str("<br/>");
+            for node in &job.nodes {
+                write!(label, "<br/>{}", escape(node))?;
+            }
+        }
+        writeln!(out, "    j{i}[\"{label}\"]")?;
+    }
+
+    let mut critical_links = Vec::new();
+    for (link_idx, edge) in edges.iter().enumerate() {
+        if edge.artifacts.is_empty() {
+            writeln!(out, "    j{} -.-> j{}", edge.from, edge.to)?;
+        } else {
+            writeln!(
+                out,
+                "    j{} -->|\"{}\"| j{}",
+                edge.from,
+                escape(&artifacts_label(&edge.artifacts, "\n")).replace('\n', "<br/>"),
+                edge.to
+            )?;
+        }
+        if edge.critical {
+            critical_links.push(link_idx.to_string());
+        }
+    }
+
+    let critical_jobs = jobs
+        .iter()
+        .enumerate()
+        .filter(|(_, job)| job.critical)
+        .map(|(i, _)| format!("j{i}"))
+        .collect::<Vec<_>>();
+    if !critical_jobs.is_empty() {
+        writeln!(out, "    class {} critical", critical_jobs.join(","))?;
+    }
+    if !critical_links.is_empty() {
+        writeln!(
+            out,
+            "    linkStyle {} stroke:red,stroke-width:3px",
+            critical_links.join(",")
+        )?;
+    }
+
+    Ok(out)
+}
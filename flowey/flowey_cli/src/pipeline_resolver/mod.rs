@@ -13,4 +13,5 @@
 pub mod direct_run;
 pub mod generic;
 pub mod github_yaml;
+pub mod graph;
 pub mod viz;
@@ -73,6 +73,7 @@ fn viz_pipeline_generic(
             platform,
             arch,
             cond_param_idx: _,
+            changed_paths_filter: _,
             ref ado_pool,
             ado_variables: _,
             gh_override_if: _,
@@ -253,6 +254,7 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 platform: _,
                 arch: _,
                 cond_param_idx: _,
+                changed_paths_filter: _,
                 ado_pool,
                 ado_variables: _,
                 gh_override_if: _,
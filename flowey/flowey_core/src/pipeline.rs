@@ -29,6 +29,7 @@
 /// `flowey` prelude.
 pub mod user_facing {
     pub use super::AdoCiTriggers;
+    pub use super::AdoPool;
     pub use super::AdoPrTriggers;
     pub use super::AdoResourcesRepository;
     pub use super::AdoResourcesRepositoryRef;
@@ -295,6 +296,32 @@ pub enum GhRunner {
     RunnerGroup { group: String, labels: Vec<String> },
 }
 
+/// ADO agent pool
+// TODO: support a more structured format for demands
+// See https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/pool-demands
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdoPool {
+    /// Name of the agent pool.
+    pub name: String,
+    /// Demands the agent must satisfy (e.g: `ImageOverride -equals MyImage`).
+    pub demands: Vec<String>,
+}
+
+impl From<&str> for AdoPool {
+    fn from(name: &str) -> Self {
+        name.to_string().into()
+    }
+}
+
+impl From<String> for AdoPool {
+    fn from(name: String) -> Self {
+        Self {
+            name,
+            demands: Vec::new(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 #[must_use]
 pub struct UseParameter<T> {
@@ -550,6 +577,7 @@ pub fn new_job(
             platform,
             arch,
             cond_param_idx: None,
+            changed_paths_filter: None,
             ado_pool: None,
             ado_variables: BTreeMap::new(),
             gh_override_if: None,
@@ -879,8 +907,9 @@ pub struct PipelineJob<'a> {
 
 impl PipelineJob<'_> {
     /// (ADO only) specify which agent pool this job will be run on.
-    pub fn ado_set_pool(self, pool: impl AsRef<str>) -> Self {
-        self.ado_set_pool_with_demands(pool, Vec::new())
+    pub fn ado_set_pool(self, pool: impl Into<AdoPool>) -> Self {
+        self.pipeline.jobs[self.job_idx].ado_pool = Some(pool.into());
+        self
     }
 
     /// (ADO only) specify which agent pool this job will be run on, with
@@ -1046,6 +1075,29 @@ pub fn with_condition(self, cond: UseParameter<bool>) -> Self {
         self
     }
 
+    /// Only run the job if a pull request touches one or more files under the
+    /// specified repo-relative path prefixes (e.g: `"Guide/"`).
+    ///
+    /// Jobs which run in response to any other trigger (e.g: CI, schedule,
+    /// manual dispatch) are never skipped.
+    ///
+    /// Skipping a job will also skip any jobs which depend on it, so this
+    /// should only be used on jobs whose downstream jobs would be equally
+    /// uninteresting to run.
+    ///
+    /// Currently only implemented on GitHub Actions. Jobs are always run on
+    /// other backends.
+    pub fn only_run_on_changed_paths(
+        self,
+        paths: impl IntoIterator<Item = impl AsRef<str>>,
+    ) -> Self {
+        self.pipeline.jobs[self.job_idx]
+            .changed_paths_filter
+            .get_or_insert_with(Vec::new)
+            .extend(paths.into_iter().map(|p| p.as_ref().into()));
+        self
+    }
+
     /// Add a flow node which will be run as part of the job.
     pub fn dep_on<R: IntoRequest + 'static>(
         self,
@@ -1102,6 +1154,7 @@ pub trait IntoPipeline {
 /// Structs which should only be used by top-level flowey emitters. If you're a
 /// pipeline author, these are not types you need to care about!
 pub mod internal {
+    pub use super::AdoPool;
     use super::*;
     use std::collections::BTreeMap;
 
@@ -1138,6 +1191,7 @@ pub struct PipelineJobMetadata {
         pub platform: FlowPlatform,
         pub arch: FlowArch,
         pub cond_param_idx: Option<usize>,
+        pub changed_paths_filter: Option<Vec<String>>,
         // backend specific
         pub ado_pool: Option<AdoPool>,
         pub ado_variables: BTreeMap<String, String>,
@@ -1147,14 +1201,6 @@ pub struct PipelineJobMetadata {
         pub gh_permissions: BTreeMap<NodeHandle, BTreeMap<GhPermission, GhPermissionValue>>,
     }
 
-    // TODO: support a more structured format for demands
-    // See https://learn.microsoft.com/en-us/azure/devops/pipelines/yaml-schema/pool-demands
-    #[derive(Debug, Clone)]
-    pub struct AdoPool {
-        pub name: String,
-        pub demands: Vec<String>,
-    }
-
     #[derive(Debug)]
     pub struct ArtifactMeta {
         pub name: String,
@@ -0,0 +1,186 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! See [`BenchmarksCli`]
+
+use flowey::node::prelude::AdoResourcesRepositoryId;
+use flowey::node::prelude::FlowPlatformLinuxDistro;
+use flowey::node::prelude::GhPermission;
+use flowey::node::prelude::GhPermissionValue;
+use flowey::node::prelude::ReadVar;
+use flowey::pipeline::prelude::*;
+use flowey_lib_common::git_checkout::RepoSource;
+use flowey_lib_hvlite::_jobs::build_and_run_benchmarks::BenchmarkBaseline;
+use flowey_lib_hvlite::run_cargo_build::common::CommonTriple;
+use std::path::PathBuf;
+
+/// Name of the GitHub workflow, used to locate baseline results from previous
+/// runs on `main`.
+const GH_WORKFLOW_NAME: &str = "[flowey] OpenVMM Benchmarks";
+
+/// Run the repo's criterion benchmarks (along with timed VMM boots) on
+/// self-hosted runners, comparing the results against a baseline.
+///
+/// Each job publishes its results as a `{label}-benchmarks` artifact. When
+/// running in CI, the baseline is the artifact published by the most recent
+/// successful run on `main`.
+#[derive(clap::Args)]
+pub struct BenchmarksCli {
+    /// Directory containing `{label}-benchmarks.json` files to compare
+    /// against (e.g: artifacts downloaded from a previous run).
+    #[clap(long)]
+    baseline: Option<PathBuf>,
+
+    /// Slowdown (as a percentage of the baseline) past which a benchmark is
+    /// considered to have regressed.
+    #[clap(long, default_value = "10.0")]
+    threshold: f64,
+
+    #[clap(flatten)]
+    local_run_args: Option<crate::pipelines_shared::cfg_common_params::LocalRunArgs>,
+
+    /// Set custom path to search for / download VMM tests disk-images
+    #[clap(long)]
+    vmm_tests_disk_cache_dir: Option<PathBuf>,
+}
+
+impl IntoPipeline for BenchmarksCli {
+    fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipeline> {
+        let Self {
+            baseline,
+            threshold,
+            local_run_args,
+            vmm_tests_disk_cache_dir,
+        } = self;
+
+        let mut pipeline = Pipeline::new();
+
+        {
+            let branches: Vec<String> = vec!["main".into()];
+            pipeline
+                .gh_set_ci_triggers(GhCiTriggers {
+                    branches: branches.clone(),
+                    ..Default::default()
+                })
+                .gh_set_name(GH_WORKFLOW_NAME)
+                .ado_set_ci_triggers(AdoCiTriggers {
+                    branches,
+                    ..Default::default()
+                });
+        }
+
+        let openvmm_repo_source = match backend_hint {
+            PipelineBackendHint::Local => {
+                RepoSource::ExistingClone(ReadVar::from_static(crate::repo_root()))
+            }
+            PipelineBackendHint::Github => RepoSource::GithubSelf,
+            PipelineBackendHint::Ado => {
+                RepoSource::AdoResource(AdoResourcesRepositoryId::new_self())
+            }
+        };
+
+        match &openvmm_repo_source {
+            RepoSource::GithubSelf => {
+                pipeline.gh_set_flowey_bootstrap_template(
+                    crate::pipelines_shared::gh_flowey_bootstrap_template::get_template(),
+                );
+            }
+            RepoSource::AdoResource(_) => {
+                pipeline.ado_set_flowey_bootstrap_template(
+                    crate::pipelines_shared::ado_flowey_bootstrap_template::get_template(),
+                );
+            }
+            _ => {}
+        }
+
+        let cfg_common_params = crate::pipelines_shared::cfg_common_params::get_cfg_common_params(
+            &mut pipeline,
+            backend_hint,
+            local_run_args,
+        )?;
+
+        pipeline.inject_all_jobs_with(move |job| {
+            job.dep_on(&cfg_common_params)
+                .dep_on(|_| flowey_lib_hvlite::_jobs::cfg_versions::Request {})
+                .dep_on(
+                    |_| flowey_lib_hvlite::_jobs::cfg_hvlite_reposource::Params {
+                        hvlite_repo_source: openvmm_repo_source.clone(),
+                    },
+                )
+                .gh_grant_permissions::<flowey_lib_common::git_checkout::Node>([(
+                    GhPermission::Contents,
+                    GhPermissionValue::Read,
+                )])
+        });
+
+        let criterion_packages = ["sparse_mmap", "storvsp", "vmbus_ring"];
+
+        for (platform, target, gh_pool, ado_pool, extra_packages, vmm_boot_filter_expr) in [
+            (
+                FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                CommonTriple::X86_64_LINUX_GNU,
+                crate::pipelines_shared::gh_pools::linux_self_hosted(),
+                crate::pipelines_shared::ado_pools::linux_self_hosted(),
+                &[][..],
+                // same exclusions as the regular linux VMM tests job
+                "test(/^multiarch::openvmm_.*_boot$/) and not test(openhcl) and not test(pcat_x64)",
+            ),
+            (
+                FlowPlatform::Windows,
+                CommonTriple::X86_64_WINDOWS_MSVC,
+                crate::pipelines_shared::gh_pools::windows_intel_self_hosted(),
+                crate::pipelines_shared::ado_pools::windows_intel_self_hosted(),
+                &["whp"][..],
+                "test(/^multiarch::openvmm_.*_boot$/)",
+            ),
+        ] {
+            let label = format!("x64-{platform}");
+            let (pub_results, _use_results) = pipeline.new_artifact(format!("{label}-benchmarks"));
+
+            let baseline = match (&baseline, backend_hint) {
+                (Some(dir), _) => Some(BenchmarkBaseline::LocalDir(dir.clone())),
+                (None, PipelineBackendHint::Github) => Some(BenchmarkBaseline::GithubWorkflow {
+                    workflow: GH_WORKFLOW_NAME.into(),
+                    branch: "main".into(),
+                }),
+                (None, _) => None,
+            };
+
+            let mut job = pipeline
+                .new_job(platform, FlowArch::X86_64, format!("benchmarks [{label}]"))
+                .gh_set_pool(gh_pool)
+                .ado_set_pool(ado_pool)
+                .gh_grant_permissions::<flowey_lib_hvlite::_jobs::build_and_run_benchmarks::Node>([
+                    (GhPermission::Actions, GhPermissionValue::Read),
+                ])
+                .dep_on(
+                    |ctx| flowey_lib_hvlite::_jobs::build_and_run_benchmarks::Params {
+                        label: label.clone(),
+                        target: target.as_triple(),
+                        criterion_packages: criterion_packages
+                            .iter()
+                            .chain(extra_packages)
+                            .map(|x| x.to_string())
+                            .collect(),
+                        vmm_boot_filter_expr: Some(vmm_boot_filter_expr.into()),
+                        baseline,
+                        regression_threshold: threshold,
+                        artifact_dir: ctx.publish_artifact(pub_results),
+                        done: ctx.new_done_handle(),
+                    },
+                );
+
+            if let Some(vmm_tests_disk_cache_dir) = vmm_tests_disk_cache_dir.clone() {
+                job = job.dep_on(|_| {
+                    flowey_lib_hvlite::download_openvmm_vmm_tests_vhds::Request::CustomCacheDir(
+                        vmm_tests_disk_cache_dir,
+                    )
+                })
+            }
+
+            job.finish();
+        }
+
+        Ok(pipeline)
+    }
+}
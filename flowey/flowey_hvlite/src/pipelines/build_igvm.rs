@@ -20,6 +20,9 @@ pub enum OpenhclRecipeCli {
     X64Cvm,
     /// X64 OpenHCL, with CVM support using the dev kernel in VTL2
     X64CvmDevkern,
+    /// X64 OpenHCL, with CVM support, without the vmbus relay. Only devices
+    /// implemented in VTL2 are offered to VTL0.
+    X64CvmNoRelay,
     /// X64 OpenHCL booting VTL0 using a test linux-direct kernel + initrd (no
     /// UEFI).
     X64TestLinuxDirect,
@@ -291,6 +294,7 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                     }
                     OpenhclRecipeCli::X64Cvm => OpenhclIgvmRecipe::X64Cvm,
                     OpenhclRecipeCli::X64CvmDevkern => OpenhclIgvmRecipe::X64CvmDevkern,
+                    OpenhclRecipeCli::X64CvmNoRelay => OpenhclIgvmRecipe::X64CvmNoRelay,
                     OpenhclRecipeCli::Aarch64 => OpenhclIgvmRecipe::Aarch64,
                     OpenhclRecipeCli::Aarch64Devkern => OpenhclIgvmRecipe::Aarch64Devkern,
                 },
@@ -3,6 +3,7 @@
 
 //! See [`CheckinGatesCli`]
 
+use flowey::node::prelude::AdoResourcesRepositoryId;
 use flowey::node::prelude::FlowPlatformLinuxDistro;
 use flowey::node::prelude::GhPermission;
 use flowey::node::prelude::GhPermissionValue;
@@ -44,6 +45,11 @@ pub struct CheckinGatesCli {
     /// Set custom path to search for / download VMM tests disk-images
     #[clap(long)]
     vmm_tests_disk_cache_dir: Option<PathBuf>,
+
+    /// Also run instrumented unit and VMM tests, and publish a merged code
+    /// coverage report. Intended for scheduled (nightly) runs.
+    #[clap(long)]
+    coverage: bool,
 }
 
 impl IntoPipeline for CheckinGatesCli {
@@ -52,6 +58,7 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
             config,
             local_run_args,
             vmm_tests_disk_cache_dir,
+            coverage,
         } = self;
 
         let release = match config {
@@ -63,43 +70,69 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
 
         // configure pr/ci branch triggers and add gh pipeline name
         {
-            let branches = vec!["main".into(), "release/*".into()];
+            let branches: Vec<String> = vec!["main".into(), "release/*".into()];
             match config {
                 PipelineConfig::Ci => {
                     pipeline
                         .gh_set_ci_triggers(GhCiTriggers {
-                            branches,
+                            branches: branches.clone(),
                             ..Default::default()
                         })
-                        .gh_set_name("[flowey] OpenVMM CI");
+                        .gh_set_name("[flowey] OpenVMM CI")
+                        .ado_set_ci_triggers(AdoCiTriggers {
+                            branches,
+                            ..Default::default()
+                        });
                 }
                 PipelineConfig::Pr => {
                     pipeline
                         .gh_set_pr_triggers(GhPrTriggers {
-                            branches,
+                            branches: branches.clone(),
                             ..GhPrTriggers::new_draftable()
                         })
-                        .gh_set_name("[flowey] OpenVMM PR");
+                        .gh_set_name("[flowey] OpenVMM PR")
+                        .ado_set_pr_triggers(AdoPrTriggers {
+                            branches,
+                            ..Default::default()
+                        });
                 }
             }
+
+            if coverage {
+                let cron = "0 8 * * *".to_string();
+                pipeline
+                    .gh_add_schedule_trigger(GhScheduleTriggers { cron: cron.clone() })
+                    .ado_add_schedule_trigger(AdoScheduleTriggers {
+                        display_name: "Nightly code coverage".into(),
+                        branches: vec!["main".into()],
+                        exclude_branches: Vec::new(),
+                        cron,
+                    });
+            }
         }
 
-        let openvmm_repo_source = {
-            if matches!(backend_hint, PipelineBackendHint::Local) {
+        let openvmm_repo_source = match backend_hint {
+            PipelineBackendHint::Local => {
                 RepoSource::ExistingClone(ReadVar::from_static(crate::repo_root()))
-            } else if matches!(backend_hint, PipelineBackendHint::Github) {
-                RepoSource::GithubSelf
-            } else {
-                anyhow::bail!(
-                    "Unsupported backend: Checkin Gates only supports Local and Github backends"
-                );
+            }
+            PipelineBackendHint::Github => RepoSource::GithubSelf,
+            PipelineBackendHint::Ado => {
+                RepoSource::AdoResource(AdoResourcesRepositoryId::new_self())
             }
         };
 
-        if let RepoSource::GithubSelf = &openvmm_repo_source {
-            pipeline.gh_set_flowey_bootstrap_template(
-                crate::pipelines_shared::gh_flowey_bootstrap_template::get_template(),
-            );
+        match &openvmm_repo_source {
+            RepoSource::GithubSelf => {
+                pipeline.gh_set_flowey_bootstrap_template(
+                    crate::pipelines_shared::gh_flowey_bootstrap_template::get_template(),
+                );
+            }
+            RepoSource::AdoResource(_) => {
+                pipeline.ado_set_flowey_bootstrap_template(
+                    crate::pipelines_shared::ado_flowey_bootstrap_template::get_template(),
+                );
+            }
+            _ => {}
         }
 
         let cfg_common_params = crate::pipelines_shared::cfg_common_params::get_cfg_common_params(
@@ -141,12 +174,15 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
             pipeline.new_artifact("x64-windows-vmm-tests-archive");
         let (pub_vmm_tests_archive_windows_aarch64, use_vmm_tests_archive_windows_aarch64) =
             pipeline.new_artifact("aarch64-windows-vmm-tests-archive");
+        let (pub_vmm_tests_archive_linux_aarch64, use_vmm_tests_archive_linux_aarch64) =
+            pipeline.new_artifact("aarch64-linux-vmm-tests-archive");
 
         // wrap each publish handle in an option, so downstream code can
         // `.take()` the handle when emitting the corresponding job
         let mut pub_vmm_tests_archive_linux_x86 = Some(pub_vmm_tests_archive_linux_x86);
         let mut pub_vmm_tests_archive_windows_x86 = Some(pub_vmm_tests_archive_windows_x86);
         let mut pub_vmm_tests_archive_windows_aarch64 = Some(pub_vmm_tests_archive_windows_aarch64);
+        let mut pub_vmm_tests_archive_linux_aarch64 = Some(pub_vmm_tests_archive_linux_aarch64);
 
         // initialize the various "VmmTestsArtifactsBuilder" containers, which
         // are used to "skim off" various artifacts that the VMM test jobs
@@ -157,6 +193,8 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
             vmm_tests_artifact_builders::VmmTestsArtifactsBuilderWindowsX86::default();
         let mut vmm_tests_artifacts_windows_aarch64 =
             vmm_tests_artifact_builders::VmmTestsArtifactsBuilderWindowsAarch64::default();
+        let mut vmm_tests_artifacts_linux_aarch64 =
+            vmm_tests_artifact_builders::VmmTestsArtifactsBuilderLinuxAarch64::default();
 
         // We need to maintain a list of all jobs, so we can hang the "all good"
         // job off of them. This is requires because github status checks only allow
@@ -176,6 +214,12 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
             .gh_set_pool(crate::pipelines_shared::gh_pools::default_gh_hosted(
                 FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
             ))
+            .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+            ))
+            // the guide is self-contained, so only bother building it on PRs
+            // which touch it (or the code that builds it)
+            .only_run_on_changed_paths(["Guide/", "flowey/", ".github/"])
             .dep_on(
                 |ctx| flowey_lib_hvlite::_jobs::build_and_publish_guide::Params {
                     artifact_dir: ctx.publish_artifact(pub_guide),
@@ -211,6 +255,9 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                 .gh_set_pool(crate::pipelines_shared::gh_pools::default_x86_pool(
                     platform,
                 ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                    platform,
+                ))
                 .dep_on(|_ctx| {
                     flowey_lib_hvlite::build_rustdoc::Request::SetDenyWarnings(deny_warnings)
                 })
@@ -227,7 +274,11 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
         }
 
         // emit consolidated gh pages publish job
-        if matches!(config, PipelineConfig::Ci) {
+        //
+        // openvmm.dev is only ever published from GitHub, so there's no point
+        // in spinning up an ADO agent for this.
+        if matches!(config, PipelineConfig::Ci) && !matches!(backend_hint, PipelineBackendHint::Ado)
+        {
             let artifact_dir = if matches!(backend_hint, PipelineBackendHint::Local) {
                 let (publish, _use) = pipeline.new_artifact("gh-pages");
                 Some(publish)
@@ -269,6 +320,9 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                 .gh_set_pool(crate::pipelines_shared::gh_pools::default_gh_hosted(
                     FlowPlatform::Windows,
                 ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                    FlowPlatform::Windows,
+                ))
                 .dep_on(|ctx| flowey_lib_hvlite::_jobs::check_xtask_fmt::Request {
                     target: CommonTriple::X86_64_WINDOWS_MSVC,
                     done: ctx.new_done_handle(),
@@ -284,6 +338,9 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                 .gh_set_pool(crate::pipelines_shared::gh_pools::default_x86_pool(
                     FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
                 ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                    FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                ))
                 .dep_on(|ctx| flowey_lib_hvlite::_jobs::check_xtask_fmt::Request {
                     target: CommonTriple::X86_64_LINUX_GNU,
                     done: ctx.new_done_handle(),
@@ -328,6 +385,8 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                         Some(use_pipette_windows.clone());
                 }
                 CommonArch::Aarch64 => {
+                    vmm_tests_artifacts_linux_aarch64.use_pipette_windows =
+                        Some(use_pipette_windows.clone());
                     vmm_tests_artifacts_windows_aarch64.use_openvmm = Some(use_openvmm.clone());
                     vmm_tests_artifacts_windows_aarch64.use_pipette_windows =
                         Some(use_pipette_windows.clone());
@@ -356,6 +415,9 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                 .gh_set_pool(crate::pipelines_shared::gh_pools::default_x86_pool(
                     FlowPlatform::Windows,
                 ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                    FlowPlatform::Windows,
+                ))
                 .dep_on(
                     |ctx| flowey_lib_hvlite::_jobs::build_and_publish_vmgstool::Params {
                         target: CommonTriple::Common {
@@ -425,6 +487,9 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                 .gh_set_pool(crate::pipelines_shared::gh_pools::default_gh_hosted(
                     FlowPlatform::Windows,
                 ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                    FlowPlatform::Windows,
+                ))
                 .dep_on(|ctx| {
                     flowey_lib_hvlite::_jobs::build_and_publish_openvmm::Params {
                         target: CommonTriple::Common {
@@ -516,6 +581,9 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                         Some(use_guest_test_uefi.clone());
                 }
                 CommonArch::Aarch64 => {
+                    vmm_tests_artifacts_linux_aarch64.use_openvmm = Some(use_openvmm.clone());
+                    vmm_tests_artifacts_linux_aarch64.use_guest_test_uefi =
+                        Some(use_guest_test_uefi.clone());
                     vmm_tests_artifacts_windows_aarch64.use_guest_test_uefi =
                         Some(use_guest_test_uefi.clone());
                 }
@@ -530,6 +598,9 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                 .gh_set_pool(crate::pipelines_shared::gh_pools::default_x86_pool(
                     FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
                 ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                    FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                ))
                 .dep_on(|ctx| {
                     flowey_lib_hvlite::_jobs::build_and_publish_openvmm::Params {
                         target: CommonTriple::Common {
@@ -598,20 +669,58 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                 });
 
             // Hang building the linux VMM tests off this big linux job.
-            //
-            // No ARM64 VMM tests yet
-            if matches!(arch, CommonArch::X86_64) {
-                let pub_vmm_tests_archive_linux_x86 =
-                    pub_vmm_tests_archive_linux_x86.take().unwrap();
-                job = job.dep_on(|ctx| {
-                    flowey_lib_hvlite::_jobs::build_and_publish_nextest_vmm_tests_archive::Params {
-                        target: CommonTriple::X86_64_LINUX_GNU.as_triple(),
+            let (target, pub_vmm_tests_archive) = match arch {
+                CommonArch::X86_64 => (
+                    CommonTriple::X86_64_LINUX_GNU,
+                    pub_vmm_tests_archive_linux_x86.take().unwrap(),
+                ),
+                CommonArch::Aarch64 => (
+                    CommonTriple::AARCH64_LINUX_GNU,
+                    pub_vmm_tests_archive_linux_aarch64.take().unwrap(),
+                ),
+            };
+            job = job.dep_on(|ctx| {
+                flowey_lib_hvlite::_jobs::build_and_publish_nextest_vmm_tests_archive::Params {
+                    target: target.as_triple(),
+                    profile: CommonProfile::from_release(release),
+                    artifact_dir: ctx.publish_artifact(pub_vmm_tests_archive),
+                    done: ctx.new_done_handle(),
+                }
+            });
+
+            all_jobs.push(job.finish());
+        }
+
+        // emit macos build machine job
+        //
+        // OpenVMM doesn't support running VMs on macOS yet, so this exists
+        // purely to make sure the macOS build doesn't regress.
+        {
+            let (pub_openvmm, _use_openvmm) = pipeline.new_artifact("aarch64-macos-openvmm");
+
+            let job = pipeline
+                .new_job(
+                    FlowPlatform::MacOs,
+                    FlowArch::Aarch64,
+                    "build artifacts [aarch64-macos]",
+                )
+                .gh_set_pool(crate::pipelines_shared::gh_pools::default_gh_hosted(
+                    FlowPlatform::MacOs,
+                ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::macos_arm_self_hosted())
+                .dep_on(|ctx| {
+                    flowey_lib_hvlite::_jobs::build_and_publish_openvmm::Params {
+                        target: CommonTriple::Common {
+                            arch: CommonArch::Aarch64,
+                            platform: CommonPlatform::MacOs,
+                        },
                         profile: CommonProfile::from_release(release),
-                        artifact_dir: ctx.publish_artifact(pub_vmm_tests_archive_linux_x86),
+                        // FIXME: this relies on openvmm default features
+                        features: [].into(),
+                        artifact_dir: ctx.publish_artifact(pub_openvmm),
                         done: ctx.new_done_handle(),
                     }
                 });
-            }
 
             all_jobs.push(job.finish());
         }
@@ -655,6 +764,8 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                         Some(use_openhcl_igvm.clone());
                     vmm_tests_artifacts_windows_aarch64.use_pipette_linux_musl =
                         Some(use_pipette_linux_musl.clone());
+                    vmm_tests_artifacts_linux_aarch64.use_pipette_linux_musl =
+                        Some(use_pipette_linux_musl.clone());
                 }
             }
             let igvm_recipes = match arch {
@@ -682,6 +793,9 @@ fn into_pipeline(self, backend_hint: PipelineBackendHint) -> anyhow::Result<Pipe
                 .gh_set_pool(crate::pipelines_shared::gh_pools::default_x86_pool(
                     FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
                 ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                    FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                ))
                 .dep_on(|ctx| {
                     flowey_lib_hvlite::_jobs::build_and_publish_openhcl_igvm_from_recipe::Params {
                         igvm_files: igvm_recipes
@@ -723,6 +837,7 @@ struct ClippyUnitTestJobParams<'a> {
             platform: FlowPlatform,
             arch: FlowArch,
             gh_pool: GhRunner,
+            ado_pool: AdoPool,
             clippy_targets: Option<(&'a str, &'a [(Triple, bool)])>,
             unit_test_target: Option<(&'a str, Triple)>,
         }
@@ -731,6 +846,7 @@ struct ClippyUnitTestJobParams<'a> {
             platform,
             arch,
             gh_pool,
+            ado_pool,
             clippy_targets,
             unit_test_target,
         } in [
@@ -738,6 +854,7 @@ struct ClippyUnitTestJobParams<'a> {
                 platform: FlowPlatform::Windows,
                 arch: FlowArch::X86_64,
                 gh_pool: crate::pipelines_shared::gh_pools::windows_amd_self_hosted(),
+                ado_pool: crate::pipelines_shared::ado_pools::windows_amd_self_hosted(),
                 clippy_targets: Some((
                     "windows",
                     &[
@@ -754,6 +871,7 @@ struct ClippyUnitTestJobParams<'a> {
                 platform: FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
                 arch: FlowArch::X86_64,
                 gh_pool: crate::pipelines_shared::gh_pools::linux_self_hosted(),
+                ado_pool: crate::pipelines_shared::ado_pools::linux_self_hosted(),
                 clippy_targets: Some((
                     "linux, macos",
                     &[
@@ -771,6 +889,7 @@ struct ClippyUnitTestJobParams<'a> {
                 platform: FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
                 arch: FlowArch::X86_64,
                 gh_pool: crate::pipelines_shared::gh_pools::linux_self_hosted(),
+                ado_pool: crate::pipelines_shared::ado_pools::linux_self_hosted(),
                 clippy_targets: Some((
                     "linux-musl, misc nostd",
                     &[
@@ -784,12 +903,24 @@ struct ClippyUnitTestJobParams<'a> {
                 platform: FlowPlatform::Windows,
                 arch: FlowArch::Aarch64,
                 gh_pool: crate::pipelines_shared::gh_pools::windows_arm_self_hosted_baremetal(),
+                ado_pool: crate::pipelines_shared::ado_pools::windows_arm_self_hosted_baremetal(),
                 clippy_targets: None,
                 unit_test_target: Some((
                     "aarch64-windows",
                     target_lexicon::triple!("aarch64-pc-windows-msvc"),
                 )),
             },
+            ClippyUnitTestJobParams {
+                platform: FlowPlatform::MacOs,
+                arch: FlowArch::Aarch64,
+                gh_pool: crate::pipelines_shared::gh_pools::gh_hosted_macos(),
+                ado_pool: crate::pipelines_shared::ado_pools::macos_arm_self_hosted(),
+                clippy_targets: None,
+                unit_test_target: Some((
+                    "aarch64-macos",
+                    target_lexicon::triple!("aarch64-apple-darwin"),
+                )),
+            },
         ] {
             let mut job_name = Vec::new();
             if let Some((label, _)) = &clippy_targets {
@@ -813,7 +944,8 @@ struct ClippyUnitTestJobParams<'a> {
 
             let mut clippy_unit_test_job = pipeline
                 .new_job(platform, arch, job_name)
-                .gh_set_pool(gh_pool);
+                .gh_set_pool(gh_pool)
+                .ado_set_pool(ado_pool);
 
             if let Some((_, targets)) = clippy_targets {
                 for (target, also_check_misc_nostd_crates) in targets {
@@ -840,6 +972,7 @@ struct ClippyUnitTestJobParams<'a> {
                             profile: CommonProfile::from_release(release),
                             unstable_panic_abort_tests: None,
                             artifact_dir: pub_unit_test_junit_xml.map(|x| ctx.publish_artifact(x)),
+                            coverage_artifact_dir: None,
                             done: ctx.new_done_handle(),
                         }
                     })
@@ -875,12 +1008,18 @@ struct ClippyUnitTestJobParams<'a> {
             .map_err(|missing| {
                 anyhow::anyhow!("missing required windows-aarch64 vmm_tests artifact: {missing}")
             })?;
+        let vmm_tests_artifacts_linux_aarch64 = vmm_tests_artifacts_linux_aarch64
+            .finish()
+            .map_err(|missing| {
+                anyhow::anyhow!("missing required linux-aarch64 vmm_tests artifact: {missing}")
+            })?;
 
         // Emit VMM tests runner jobs
         struct VmmTestJobParams<'a> {
             platform: FlowPlatform,
             arch: FlowArch,
             gh_pool: GhRunner,
+            ado_pool: AdoPool,
             label: &'a str,
             target: CommonTriple,
             resolve_vmm_tests_artifacts: vmm_tests_artifact_builders::ResolveVmmTestsDepArtifacts,
@@ -890,6 +1029,7 @@ struct VmmTestJobParams<'a> {
             platform,
             arch,
             gh_pool,
+            ado_pool,
             label,
             target,
             resolve_vmm_tests_artifacts,
@@ -898,6 +1038,7 @@ struct VmmTestJobParams<'a> {
                 platform: FlowPlatform::Windows,
                 arch: FlowArch::X86_64,
                 gh_pool: crate::pipelines_shared::gh_pools::windows_intel_self_hosted_largedisk(),
+                ado_pool: crate::pipelines_shared::ado_pools::windows_intel_self_hosted_largedisk(),
                 label: "x64-windows-intel",
                 target: CommonTriple::X86_64_WINDOWS_MSVC,
                 resolve_vmm_tests_artifacts: vmm_tests_artifacts_windows_intel_x86,
@@ -906,6 +1047,7 @@ struct VmmTestJobParams<'a> {
                 platform: FlowPlatform::Windows,
                 arch: FlowArch::X86_64,
                 gh_pool: crate::pipelines_shared::gh_pools::windows_amd_self_hosted_largedisk(),
+                ado_pool: crate::pipelines_shared::ado_pools::windows_amd_self_hosted_largedisk(),
                 label: "x64-windows-amd",
                 target: CommonTriple::X86_64_WINDOWS_MSVC,
                 resolve_vmm_tests_artifacts: vmm_tests_artifacts_windows_amd_x86,
@@ -914,6 +1056,7 @@ struct VmmTestJobParams<'a> {
                 platform: FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
                 arch: FlowArch::X86_64,
                 gh_pool: crate::pipelines_shared::gh_pools::linux_self_hosted(),
+                ado_pool: crate::pipelines_shared::ado_pools::linux_self_hosted(),
                 label: "x64-linux",
                 target: CommonTriple::X86_64_LINUX_GNU,
                 resolve_vmm_tests_artifacts: vmm_tests_artifacts_linux_x86,
@@ -922,10 +1065,20 @@ struct VmmTestJobParams<'a> {
                 platform: FlowPlatform::Windows,
                 arch: FlowArch::Aarch64,
                 gh_pool: crate::pipelines_shared::gh_pools::windows_arm_self_hosted_baremetal(),
+                ado_pool: crate::pipelines_shared::ado_pools::windows_arm_self_hosted_baremetal(),
                 label: "aarch64-windows",
                 target: CommonTriple::AARCH64_WINDOWS_MSVC,
                 resolve_vmm_tests_artifacts: vmm_tests_artifacts_windows_aarch64,
             },
+            VmmTestJobParams {
+                platform: FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                arch: FlowArch::Aarch64,
+                gh_pool: crate::pipelines_shared::gh_pools::linux_arm_self_hosted_baremetal(),
+                ado_pool: crate::pipelines_shared::ado_pools::linux_arm_self_hosted_baremetal(),
+                label: "aarch64-linux",
+                target: CommonTriple::AARCH64_LINUX_GNU,
+                resolve_vmm_tests_artifacts: vmm_tests_artifacts_linux_aarch64,
+            },
         ] {
             let test_label = format!("{label}-vmm-tests");
 
@@ -955,12 +1108,14 @@ struct VmmTestJobParams<'a> {
                 CommonTriple::X86_64_WINDOWS_MSVC => &use_vmm_tests_archive_windows_x86,
                 CommonTriple::X86_64_LINUX_GNU => &use_vmm_tests_archive_linux_x86,
                 CommonTriple::AARCH64_WINDOWS_MSVC => &use_vmm_tests_archive_windows_aarch64,
+                CommonTriple::AARCH64_LINUX_GNU => &use_vmm_tests_archive_linux_aarch64,
                 _ => unreachable!(),
             };
 
             let mut vmm_tests_run_job = pipeline
                 .new_job(platform, arch, format!("run vmm-tests [{label}]"))
                 .gh_set_pool(gh_pool)
+                .ado_set_pool(ado_pool)
                 .dep_on(|ctx| {
                     flowey_lib_hvlite::_jobs::consume_and_test_nextest_vmm_tests_archive::Params {
                         junit_test_label: test_label,
@@ -987,6 +1142,123 @@ struct VmmTestJobParams<'a> {
             all_jobs.push(vmm_tests_run_job.finish());
         }
 
+        // emit code coverage jobs
+        //
+        // These are kept out of `all_jobs`, as coverage is informational, and
+        // shouldn't gate anything.
+        if coverage {
+            let mut use_lcov_artifacts = Vec::new();
+
+            for (platform, target) in [
+                (FlowPlatform::Windows, CommonTriple::X86_64_WINDOWS_MSVC),
+                (
+                    FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                    CommonTriple::X86_64_LINUX_GNU,
+                ),
+            ] {
+                let label = format!("x64-{platform}");
+                let (pub_lcov, use_lcov) =
+                    pipeline.new_artifact(format!("{label}-unit-tests-coverage"));
+                use_lcov_artifacts.push(use_lcov);
+
+                pipeline
+                    .new_job(
+                        platform,
+                        FlowArch::X86_64,
+                        format!("coverage: unit tests [{label}]"),
+                    )
+                    .gh_set_pool(crate::pipelines_shared::gh_pools::default_x86_pool(
+                        platform,
+                    ))
+                    .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                        platform,
+                    ))
+                    .dep_on(|ctx| {
+                        flowey_lib_hvlite::_jobs::build_and_run_nextest_unit_tests::Params {
+                            junit_test_label: format!("{label}-unit-tests"),
+                            nextest_profile:
+                                flowey_lib_hvlite::run_cargo_nextest_run::NextestProfile::Ci,
+                            fail_job_on_test_fail: false,
+                            target: target.as_triple(),
+                            profile: CommonProfile::Debug,
+                            unstable_panic_abort_tests: None,
+                            artifact_dir: None,
+                            coverage_artifact_dir: Some(ctx.publish_artifact(pub_lcov)),
+                            done: ctx.new_done_handle(),
+                        }
+                    })
+                    .finish();
+            }
+
+            {
+                let (pub_lcov, use_lcov) = pipeline.new_artifact("x64-linux-vmm-tests-coverage");
+                use_lcov_artifacts.push(use_lcov);
+
+                let mut job = pipeline
+                    .new_job(
+                        FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                        FlowArch::X86_64,
+                        "coverage: vmm tests [x64-linux]",
+                    )
+                    .gh_set_pool(crate::pipelines_shared::gh_pools::linux_self_hosted())
+                    .ado_set_pool(crate::pipelines_shared::ado_pools::linux_self_hosted())
+                    .dep_on(|ctx| {
+                        flowey_lib_hvlite::_jobs::build_and_run_nextest_vmm_tests::Params {
+                            junit_test_label: "x64-linux-vmm-tests".into(),
+                            target: CommonTriple::X86_64_LINUX_GNU.as_triple(),
+                            profile: CommonProfile::Debug,
+                            nextest_profile:
+                                flowey_lib_hvlite::run_cargo_nextest_run::NextestProfile::Ci,
+                            // same exclusions as the regular linux VMM tests job
+                            nextest_filter_expr: Some(
+                                "all() and not test(openhcl) and not test(pcat_x64)".into(),
+                            ),
+                            openhcl_custom_target: None,
+                            fail_job_on_test_fail: false,
+                            artifact_dir: None,
+                            coverage_artifact_dir: Some(ctx.publish_artifact(pub_lcov)),
+                            results: None,
+                            done: ctx.new_done_handle(),
+                        }
+                    });
+
+                if let Some(vmm_tests_disk_cache_dir) = vmm_tests_disk_cache_dir.clone() {
+                    job = job.dep_on(|_| {
+                        flowey_lib_hvlite::download_openvmm_vmm_tests_vhds::Request::CustomCacheDir(
+                            vmm_tests_disk_cache_dir,
+                        )
+                    })
+                }
+
+                job.finish();
+            }
+
+            let (pub_coverage, _use_coverage) = pipeline.new_artifact("code-coverage");
+            pipeline
+                .new_job(
+                    FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                    FlowArch::X86_64,
+                    "publish code coverage report",
+                )
+                .gh_set_pool(crate::pipelines_shared::gh_pools::default_x86_pool(
+                    FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                    FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                ))
+                .dep_on(|ctx| {
+                    flowey_lib_hvlite::_jobs::consolidate_and_publish_code_coverage::Params {
+                        lcov_dirs: use_lcov_artifacts
+                            .iter()
+                            .map(|x| ctx.use_artifact(x))
+                            .collect(),
+                        artifact_dir: ctx.publish_artifact(pub_coverage),
+                        done: ctx.new_done_handle(),
+                    }
+                })
+                .finish();
+        }
+
         // test the flowey local backend by running cargo xflowey build-igvm on x64
         {
             let job = pipeline
@@ -998,6 +1270,9 @@ struct VmmTestJobParams<'a> {
                 .gh_set_pool(crate::pipelines_shared::gh_pools::default_x86_pool(
                     FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
                 ))
+                .ado_set_pool(crate::pipelines_shared::ado_pools::default_x86_pool(
+                    FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu),
+                ))
                 .dep_on(
                     |ctx| flowey_lib_hvlite::_jobs::test_local_flowey_build_igvm::Request {
                         base_recipe: OpenhclIgvmRecipe::X64,
@@ -1008,7 +1283,10 @@ struct VmmTestJobParams<'a> {
             all_jobs.push(job);
         }
 
-        if matches!(config, PipelineConfig::Pr) {
+        // ADO reports the status of the pipeline as a whole, so this workaround
+        // isn't needed there.
+        if matches!(config, PipelineConfig::Pr) && !matches!(backend_hint, PipelineBackendHint::Ado)
+        {
             // Add a job that depends on all others as a workaround for
             // https://github.com/orgs/community/discussions/12395.
             //
@@ -1093,6 +1371,42 @@ pub fn finish(self) -> Result<ResolveVmmTestsDepArtifacts, &'static str> {
         }
     }
 
+    #[derive(Default)]
+    pub struct VmmTestsArtifactsBuilderLinuxAarch64 {
+        // windows build machine
+        pub use_pipette_windows: Option<UseArtifact>,
+        // linux build machine
+        pub use_openvmm: Option<UseArtifact>,
+        pub use_pipette_linux_musl: Option<UseArtifact>,
+        // any machine
+        pub use_guest_test_uefi: Option<UseArtifact>,
+    }
+
+    impl VmmTestsArtifactsBuilderLinuxAarch64 {
+        pub fn finish(self) -> Result<ResolveVmmTestsDepArtifacts, &'static str> {
+            let VmmTestsArtifactsBuilderLinuxAarch64 {
+                use_openvmm,
+                use_guest_test_uefi,
+                use_pipette_windows,
+                use_pipette_linux_musl,
+            } = self;
+
+            let use_guest_test_uefi = use_guest_test_uefi.ok_or("guest_test_uefi")?;
+            let use_openvmm = use_openvmm.ok_or("openvmm")?;
+            let use_pipette_linux_musl = use_pipette_linux_musl.ok_or("pipette_linux_musl")?;
+            let use_pipette_windows = use_pipette_windows.ok_or("pipette_windows")?;
+
+            Ok(Box::new(move |ctx| VmmTestsDepArtifacts {
+                artifact_dir_openvmm: Some(ctx.use_artifact(&use_openvmm)),
+                artifact_dir_pipette_windows: Some(ctx.use_artifact(&use_pipette_windows)),
+                artifact_dir_pipette_linux_musl: Some(ctx.use_artifact(&use_pipette_linux_musl)),
+                artifact_dir_guest_test_uefi: Some(ctx.use_artifact(&use_guest_test_uefi)),
+                // not currently required, since OpenHCL tests cannot be run on OpenVMM on linux
+                artifact_dir_openhcl_igvm_files: None,
+            }))
+        }
+    }
+
     #[derive(Default, Clone)]
     pub struct VmmTestsArtifactsBuilderWindowsX86 {
         // windows build machine
@@ -4,6 +4,7 @@
 use flowey::pipeline::prelude::*;
 use restore_packages::RestorePackagesCli;
 
+pub mod benchmarks;
 pub mod build_igvm;
 pub mod checkin_gates;
 pub mod custom_vmfirmwareigvm_dll;
@@ -33,6 +34,7 @@ pub enum OpenvmmPipelines {
 #[derive(clap::Subcommand)]
 pub enum OpenvmmPipelinesCi {
     CheckinGates(checkin_gates::CheckinGatesCli),
+    Benchmarks(benchmarks::BenchmarksCli),
 }
 
 impl IntoPipeline for OpenvmmPipelines {
@@ -60,6 +62,7 @@ fn into_pipeline(self, pipeline_hint: PipelineBackendHint) -> anyhow::Result<Pip
 
             OpenvmmPipelines::Ci(cmd) => match cmd {
                 OpenvmmPipelinesCi::CheckinGates(cmd) => cmd.into_pipeline(pipeline_hint),
+                OpenvmmPipelinesCi::Benchmarks(cmd) => cmd.into_pipeline(pipeline_hint),
             },
             OpenvmmPipelines::RestorePackages(cmd) => cmd.into_pipeline(pipeline_hint),
         }
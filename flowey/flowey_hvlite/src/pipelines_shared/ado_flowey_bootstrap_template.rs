@@ -0,0 +1,19 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! See [`get_template`]
+
+/// Get our internal flowey bootstrap template.
+///
+/// See [`Pipeline::ado_set_flowey_bootstrap_template`]
+///
+/// [`Pipeline::ado_set_flowey_bootstrap_template`]:
+///     flowey::pipeline::prelude::Pipeline::ado_set_flowey_bootstrap_template
+pub fn get_template() -> String {
+    let template = include_str!("ado_flowey_bootstrap_template.yml").to_string();
+
+    template.replace(
+        "{{RUSTUP_TOOLCHAIN}}",
+        flowey_lib_hvlite::_jobs::cfg_versions::RUSTUP_TOOLCHAIN,
+    )
+}
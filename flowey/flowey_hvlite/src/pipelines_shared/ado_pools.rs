@@ -0,0 +1,80 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Centralized list of constants enumerating available ADO build pools.
+//!
+//! These mirror the pools in [`gh_pools`](super::gh_pools). ADO has no
+//! equivalent to GitHub hosted runners, so jobs that would run on a GitHub
+//! hosted runner use the corresponding self-hosted pool instead.
+
+#![allow(unused)]
+
+use flowey::node::prelude::FlowPlatformLinuxDistro;
+use flowey::pipeline::prelude::*;
+
+pub fn default_x86_pool(platform: FlowPlatform) -> AdoPool {
+    match platform {
+        FlowPlatform::Windows => windows_amd_self_hosted(),
+        FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu) => linux_self_hosted(),
+        platform => panic!("unsupported platform {platform}"),
+    }
+}
+
+pub fn windows_amd_self_hosted() -> AdoPool {
+    "OpenVMM-ADO-Win-Pool-WestUS3".into()
+}
+
+pub fn windows_intel_self_hosted() -> AdoPool {
+    AdoPool {
+        name: "OpenVMM-ADO-Win-Pool-Intel-WestUS3".into(),
+        demands: vec!["ImageOverride -equals HvLite-CI-Win-Ge-Image-256GB".into()],
+    }
+}
+
+/// This overrides the default image with a larger disk image for use with
+/// jobs that require more than the default disk space (e.g. to ensure vmm_tests
+/// have enough space to download test VHDs)
+pub fn windows_amd_self_hosted_largedisk() -> AdoPool {
+    AdoPool {
+        name: "OpenVMM-ADO-Win-Pool-WestUS3".into(),
+        demands: vec!["ImageOverride -equals HvLite-CI-Win-Ge-Image-256GB".into()],
+    }
+}
+
+/// This overrides the default image with a larger disk image for use with
+/// jobs that require more than the default disk space (e.g. to ensure vmm_tests
+/// have enough space to download test VHDs)
+pub fn windows_intel_self_hosted_largedisk() -> AdoPool {
+    AdoPool {
+        name: "OpenVMM-ADO-Win-Pool-Intel-WestUS3".into(),
+        demands: vec!["ImageOverride -equals HvLite-CI-Win-Ge-Image-256GB".into()],
+    }
+}
+
+pub fn linux_self_hosted() -> AdoPool {
+    AdoPool {
+        name: "OpenVMM-ADO-Linux-Pool-WestUS3".into(),
+        demands: vec!["ImageOverride -equals MMSUbuntu22.04-256GB".into()],
+    }
+}
+
+pub fn windows_arm_self_hosted_baremetal() -> AdoPool {
+    AdoPool {
+        name: "OpenVMM-ADO-Win-ARM64-Baremetal".into(),
+        demands: vec!["Agent.OSArchitecture -equals ARM64".into()],
+    }
+}
+
+pub fn linux_arm_self_hosted_baremetal() -> AdoPool {
+    AdoPool {
+        name: "OpenVMM-ADO-Linux-ARM64-Baremetal".into(),
+        demands: vec!["Agent.OSArchitecture -equals ARM64".into()],
+    }
+}
+
+pub fn macos_arm_self_hosted() -> AdoPool {
+    AdoPool {
+        name: "OpenVMM-ADO-MacOS-ARM64".into(),
+        demands: vec!["Agent.OSArchitecture -equals ARM64".into()],
+    }
+}
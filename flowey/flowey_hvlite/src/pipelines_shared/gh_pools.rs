@@ -20,6 +20,7 @@ pub fn default_gh_hosted(platform: FlowPlatform) -> GhRunner {
     match platform {
         FlowPlatform::Windows => gh_hosted_windows(),
         FlowPlatform::Linux(FlowPlatformLinuxDistro::Ubuntu) => gh_hosted_linux(),
+        FlowPlatform::MacOs => gh_hosted_macos(),
         platform => panic!("unsupported platform {platform}"),
     }
 }
@@ -77,6 +78,11 @@ pub fn gh_hosted_linux() -> GhRunner {
     GhRunner::GhHosted(GhRunnerOsLabel::UbuntuLatest)
 }
 
+/// `macos-latest` runners are Apple Silicon (i.e: aarch64) machines.
+pub fn gh_hosted_macos() -> GhRunner {
+    GhRunner::GhHosted(GhRunnerOsLabel::MacOsLatest)
+}
+
 pub fn windows_arm_self_hosted_baremetal() -> GhRunner {
     GhRunner::SelfHosted(vec![
         "self-hosted".to_string(),
@@ -85,3 +91,12 @@ pub fn windows_arm_self_hosted_baremetal() -> GhRunner {
         "Baremetal".to_string(),
     ])
 }
+
+pub fn linux_arm_self_hosted_baremetal() -> GhRunner {
+    GhRunner::SelfHosted(vec![
+        "self-hosted".to_string(),
+        "Linux".to_string(),
+        "ARM64".to_string(),
+        "Baremetal".to_string(),
+    ])
+}
@@ -3,6 +3,8 @@
 
 //! Shared constants and helper functionality used across multiple pipelines.
 
+pub mod ado_flowey_bootstrap_template;
+pub mod ado_pools;
 pub mod cfg_common_params;
 pub mod gh_flowey_bootstrap_template;
 pub mod gh_pools;
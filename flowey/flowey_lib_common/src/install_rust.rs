@@ -191,7 +191,7 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
 
                             let sh = xshell::Shell::new()?;
                             match rt.platform() {
-                                FlowPlatform::Linux(_) => {
+                                FlowPlatform::Linux(_) | FlowPlatform::MacOs => {
                                     let interactive_prompt = Some("-y");
                                     let mut default_toolchain = Vec::new();
                                     if let Some(ver) = rust_toolchain {
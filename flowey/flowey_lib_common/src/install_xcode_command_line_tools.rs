@@ -0,0 +1,62 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Ensure the Xcode Command Line Tools are installed on macOS.
+//!
+//! These provide the system linker and C toolchain, which rustc (and any
+//! `cc`-based build scripts) rely on when targeting Apple platforms.
+//!
+//! Like `install_dist_pkg`, non-macOS platforms may take a dep on this node,
+//! and it will simply report that the tools were installed.
+
+use flowey::node::prelude::*;
+
+flowey_request! {
+    pub struct Request(pub WriteVar<SideEffect>);
+}
+
+new_flow_node!(struct Node);
+
+impl FlowNode for Node {
+    type Request = Request;
+
+    fn imports(_ctx: &mut ImportCtx<'_>) {}
+
+    fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
+        let done = requests.into_iter().map(|x| x.0).collect::<Vec<_>>();
+
+        if done.is_empty() {
+            return Ok(());
+        }
+
+        if !matches!(ctx.platform(), FlowPlatform::MacOs) {
+            ctx.emit_side_effect_step([], done);
+            return Ok(());
+        }
+
+        ctx.emit_rust_step("ensure Xcode Command Line Tools are installed", |ctx| {
+            done.claim(ctx);
+            |rt| {
+                let sh = xshell::Shell::new()?;
+
+                if let Ok(path) = xshell::cmd!(sh, "xcode-select -p").quiet().read() {
+                    log::info!("found Xcode Command Line Tools at {path}");
+                    return Ok(());
+                }
+
+                // the installer is a GUI prompt, and cannot be driven
+                // non-interactively
+                if matches!(rt.backend(), FlowBackend::Local) {
+                    xshell::cmd!(sh, "xcode-select --install").run()?;
+                    anyhow::bail!(
+                        "Please re-run once the Xcode Command Line Tools have finished installing."
+                    );
+                }
+
+                anyhow::bail!("Xcode Command Line Tools must be pre-installed on the runner")
+            }
+        });
+
+        Ok(())
+    }
+}
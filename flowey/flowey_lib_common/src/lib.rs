@@ -22,6 +22,7 @@
 pub mod copy_to_artifact_dir;
 pub mod download_azcopy;
 pub mod download_cargo_fuzz;
+pub mod download_cargo_llvm_cov;
 pub mod download_cargo_nextest;
 pub mod download_gh_cli;
 pub mod download_gh_release;
@@ -39,11 +40,15 @@
 pub mod install_nodejs;
 pub mod install_nuget_azure_credential_provider;
 pub mod install_rust;
+pub mod install_xcode_command_line_tools;
 pub mod nuget_install_package;
 pub mod publish_test_results;
+pub mod run_cargo_bench;
 pub mod run_cargo_build;
 pub mod run_cargo_clippy;
 pub mod run_cargo_doc;
+pub mod run_cargo_llvm_cov;
 pub mod run_cargo_nextest_archive;
 pub mod run_cargo_nextest_run;
 pub mod use_gh_cli;
+pub mod use_sccache;
@@ -0,0 +1,187 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Run criterion-based benchmarks via `cargo bench`, and collect the results.
+
+use flowey::node::prelude::*;
+use std::collections::BTreeMap;
+
+flowey_request! {
+    pub struct Request {
+        pub in_folder: ReadVar<PathBuf>,
+        /// Packages whose `[[bench]]` targets should be run
+        pub packages: Vec<String>,
+        pub target: target_lexicon::Triple,
+        pub extra_env: Option<ReadVar<BTreeMap<String, String>>>,
+        /// Wait for specified side-effects to resolve before running cargo-run.
+        ///
+        /// (e.g: to allow for some ambient packages / dependencies to get
+        /// installed).
+        pub pre_build_deps: Vec<ReadVar<SideEffect>>,
+        /// Mean time per iteration (in nanoseconds), keyed by criterion
+        /// benchmark ID.
+        pub results: WriteVar<BTreeMap<String, f64>>,
+    }
+}
+
+new_flow_node!(struct Node);
+
+impl FlowNode for Node {
+    type Request = Request;
+
+    fn imports(ctx: &mut ImportCtx<'_>) {
+        ctx.import::<crate::cfg_cargo_common_flags::Node>();
+        ctx.import::<crate::install_rust::Node>();
+        ctx.import::<crate::use_sccache::Node>();
+    }
+
+    fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
+        let rust_toolchain = ctx.reqv(crate::install_rust::Request::GetRustupToolchain);
+        let flags = ctx.reqv(crate::cfg_cargo_common_flags::Request::GetFlags);
+
+        for Request {
+            in_folder,
+            packages,
+            target,
+            extra_env,
+            pre_build_deps,
+            results,
+        } in requests
+        {
+            ctx.req(crate::install_rust::Request::InstallTargetTriple(
+                target.clone(),
+            ));
+
+            let sccache_env = ctx.reqv(|v| crate::use_sccache::Request::GetEnv {
+                in_folder: in_folder.clone(),
+                env: v,
+            });
+
+            ctx.emit_rust_step("cargo bench", |ctx| {
+                pre_build_deps.claim(ctx);
+                let rust_toolchain = rust_toolchain.clone().claim(ctx);
+                let flags = flags.clone().claim(ctx);
+                let in_folder = in_folder.claim(ctx);
+                let extra_env = extra_env.claim(ctx);
+                let sccache_env = sccache_env.claim(ctx);
+                let results = results.claim(ctx);
+                move |rt| {
+                    let rust_toolchain = rt.read(rust_toolchain);
+                    let flags = rt.read(flags);
+                    let in_folder = rt.read(in_folder);
+                    let mut with_env = rt.read(sccache_env);
+                    with_env.extend(extra_env.map(|x| rt.read(x)).unwrap_or_default());
+
+                    let crate::cfg_cargo_common_flags::Flags { locked, verbose } = flags;
+
+                    let target = target.to_string();
+
+                    // keep criterion's output separate from any results left
+                    // behind by previous (local) runs
+                    let criterion_home = std::env::current_dir()?.join("criterion").absolute()?;
+                    if criterion_home.exists() {
+                        fs_err::remove_dir_all(&criterion_home)?;
+                    }
+
+                    let mut args = Vec::new();
+
+                    args.push("bench");
+                    if verbose {
+                        args.push("--verbose");
+                    }
+                    if locked {
+                        args.push("--locked");
+                    }
+                    for package in &packages {
+                        args.push("-p");
+                        args.push(package);
+                    }
+                    // only run `[[bench]]` targets, as any libtest benches
+                    // would choke on the criterion-specific args below
+                    args.push("--bench");
+                    args.push("*");
+                    args.push("--target");
+                    args.push(&target);
+                    args.push("--");
+                    args.push("--noplot");
+
+                    let sh = xshell::Shell::new()?;
+
+                    sh.change_dir(in_folder);
+
+                    let mut cmd = if let Some(rust_toolchain) = &rust_toolchain {
+                        xshell::cmd!(sh, "rustup run {rust_toolchain} cargo")
+                    } else {
+                        xshell::cmd!(sh, "cargo")
+                    };
+
+                    for (key, val) in with_env {
+                        log::info!("env: {key}={val}");
+                        cmd = cmd.env(key, val);
+                    }
+
+                    cmd.env("CRITERION_HOME", &criterion_home)
+                        .args(args)
+                        .run()?;
+
+                    let mut collected = BTreeMap::new();
+                    collect_criterion_results(&criterion_home, &mut collected)?;
+                    for (id, mean) in &collected {
+                        log::info!("{id}: {mean:.1}ns");
+                    }
+
+                    rt.write(results, &collected);
+
+                    Ok(())
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize)]
+struct CriterionBenchmark {
+    full_id: String,
+}
+
+#[derive(Deserialize)]
+struct CriterionEstimates {
+    mean: CriterionEstimate,
+}
+
+#[derive(Deserialize)]
+struct CriterionEstimate {
+    point_estimate: f64,
+}
+
+/// Criterion stores the results of the most recent run of each benchmark in
+/// `{group}/{bench}/new/{benchmark,estimates}.json`.
+fn collect_criterion_results(
+    dir: &Path,
+    results: &mut BTreeMap<String, f64>,
+) -> anyhow::Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    for entry in fs_err::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_dir() {
+            continue;
+        }
+
+        if path.file_name().is_some_and(|name| name == "new") {
+            let benchmark: CriterionBenchmark =
+                serde_json::from_str(&fs_err::read_to_string(path.join("benchmark.json"))?)?;
+            let estimates: CriterionEstimates =
+                serde_json::from_str(&fs_err::read_to_string(path.join("estimates.json"))?)?;
+            results.insert(benchmark.full_id, estimates.mean.point_estimate);
+        } else {
+            collect_criterion_results(&path, results)?;
+        }
+    }
+
+    Ok(())
+}
@@ -47,6 +47,9 @@ pub enum CargoBuildOutput {
     ElfBin {
         bin: PathBuf,
     },
+    MachOBin {
+        bin: PathBuf,
+    },
     LinuxStaticLib {
         a: PathBuf,
     },
@@ -95,6 +98,7 @@ impl FlowNode for Node {
     fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<crate::cfg_cargo_common_flags::Node>();
         ctx.import::<crate::install_rust::Node>();
+        ctx.import::<crate::use_sccache::Node>();
     }
 
     fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
@@ -118,6 +122,11 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                 target.clone(),
             ));
 
+            let sccache_env = ctx.reqv(|v| crate::use_sccache::Request::GetEnv {
+                in_folder: in_folder.clone(),
+                env: v,
+            });
+
             ctx.emit_rust_step(format!("cargo build {crate_name}"), |ctx| {
                 pre_build_deps.claim(ctx);
                 let rust_toolchain = rust_toolchain.clone().claim(ctx);
@@ -125,11 +134,13 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                 let in_folder = in_folder.claim(ctx);
                 let output = output.claim(ctx);
                 let extra_env = extra_env.claim(ctx);
+                let sccache_env = sccache_env.claim(ctx);
                 move |rt| {
                     let rust_toolchain = rt.read(rust_toolchain);
                     let flags = rt.read(flags);
                     let in_folder = rt.read(in_folder);
-                    let with_env = extra_env.map(|x| rt.read(x)).unwrap_or_default();
+                    let mut with_env = rt.read(sccache_env);
+                    with_env.extend(extra_env.map(|x| rt.read(x)).unwrap_or_default());
 
                     let crate::cfg_cargo_common_flags::Flags { locked, verbose } = flags;
 
@@ -235,6 +246,7 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                     let check_paths = match do_rename_output(true)? {
                         CargoBuildOutput::WindowsBin { exe, pdb } => vec![exe, pdb],
                         CargoBuildOutput::ElfBin { bin } => vec![bin],
+                        CargoBuildOutput::MachOBin { bin } => vec![bin],
                         CargoBuildOutput::LinuxStaticLib { a } => vec![a],
                         CargoBuildOutput::LinuxDynamicLib { so } => vec![so],
                         CargoBuildOutput::WindowsStaticLib { lib, pdb } => vec![lib, pdb],
@@ -329,6 +341,10 @@ fn rename_output(
             let bin = do_rename("", false)?;
             CargoBuildOutput::ElfBin { bin }
         }
+        (CargoCrateType::Bin, target_lexicon::OperatingSystem::Darwin) => {
+            let bin = do_rename("", false)?;
+            CargoBuildOutput::MachOBin { bin }
+        }
         (CargoCrateType::DynamicLib, target_lexicon::OperatingSystem::Windows) => {
             let dll = do_rename("dll", false)?;
             let dll_lib = do_rename("dll.lib", false)?;
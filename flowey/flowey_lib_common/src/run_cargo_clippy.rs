@@ -41,6 +41,7 @@ impl FlowNode for Node {
     fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<crate::cfg_cargo_common_flags::Node>();
         ctx.import::<crate::install_rust::Node>();
+        ctx.import::<crate::use_sccache::Node>();
     }
 
     fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
@@ -66,6 +67,11 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                 target.clone(),
             ));
 
+            let sccache_env = ctx.reqv(|v| crate::use_sccache::Request::GetEnv {
+                in_folder: in_folder.clone(),
+                env: v,
+            });
+
             ctx.emit_rust_step("cargo clippy", |ctx| {
                 pre_build_deps.claim(ctx);
                 done.claim(ctx);
@@ -73,11 +79,13 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                 let flags = flags.clone().claim(ctx);
                 let in_folder = in_folder.claim(ctx);
                 let exclude = exclude.claim(ctx);
+                let sccache_env = sccache_env.claim(ctx);
                 move |rt| {
                     let rust_toolchain = rt.read(rust_toolchain);
                     let flags = rt.read(flags);
                     let in_folder = rt.read(in_folder);
                     let exclude = rt.read(exclude);
+                    let sccache_env = rt.read(sccache_env);
 
                     let crate::cfg_cargo_common_flags::Flags { locked, verbose } = flags;
 
@@ -145,6 +153,9 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                     if !matches!(rt.backend(), FlowBackend::Local) {
                         cmd = cmd.env("CARGO_INCREMENTAL", "0");
                     }
+                    for (key, val) in sccache_env {
+                        cmd = cmd.env(key, val);
+                    }
                     if let Some(env) = extra_env {
                         for (key, val) in env {
                             log::info!("env: {key}={val}");
@@ -0,0 +1,195 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Collect code coverage using `cargo llvm-cov`.
+//!
+//! Rather than building and running tests via `cargo llvm-cov` directly, this
+//! node hands out the env vars reported by `cargo llvm-cov show-env`. Injecting
+//! them into existing build / test invocations instruments the resulting
+//! binaries, and the profiles they emit can then be turned into an lcov report
+//! via [`Request::Report`].
+
+use crate::run_cargo_build::CargoBuildProfile;
+use flowey::node::prelude::*;
+use std::collections::BTreeMap;
+
+#[derive(Serialize, Deserialize)]
+pub struct Report {
+    /// Directory containing the workspace the coverage data was collected
+    /// from.
+    pub in_folder: ReadVar<PathBuf>,
+    /// Target the instrumented binaries were built for
+    pub target: target_lexicon::Triple,
+    /// Profile the instrumented binaries were built with
+    pub profile: CargoBuildProfile,
+    /// Wait for specified side-effects to resolve before generating the report
+    /// (e.g: for the instrumented tests to finish running).
+    pub pre_report_deps: Vec<ReadVar<SideEffect>>,
+    /// Path to the generated lcov file
+    pub lcov: WriteVar<PathBuf>,
+}
+
+flowey_request! {
+    pub enum Request {
+        /// Get the env vars required to instrument builds (and test runs) of
+        /// the workspace in `in_folder`.
+        ///
+        /// Any coverage data previously collected in `in_folder` is discarded.
+        GetEnv {
+            in_folder: ReadVar<PathBuf>,
+            env: WriteVar<BTreeMap<String, String>>,
+        },
+        /// Generate an lcov report from collected coverage data
+        Report(Report),
+    }
+}
+
+new_flow_node!(struct Node);
+
+impl FlowNode for Node {
+    type Request = Request;
+
+    fn imports(ctx: &mut ImportCtx<'_>) {
+        ctx.import::<crate::download_cargo_llvm_cov::Node>();
+        ctx.import::<crate::install_rust::Node>();
+    }
+
+    fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
+        let mut get_env = Vec::new();
+        let mut report = Vec::new();
+
+        for req in requests {
+            match req {
+                Request::GetEnv { in_folder, env } => get_env.push((in_folder, env)),
+                Request::Report(v) => report.push(v),
+            }
+        }
+
+        let get_env = get_env;
+        let report = report;
+
+        // -- end of req processing -- //
+
+        if get_env.is_empty() && report.is_empty() {
+            return Ok(());
+        }
+
+        let cargo_llvm_cov_installed =
+            ctx.reqv(crate::download_cargo_llvm_cov::Request::InstallWithCargo);
+        let rust_toolchain = ctx.reqv(crate::install_rust::Request::GetRustupToolchain);
+
+        for (in_folder, env) in get_env {
+            ctx.emit_rust_step("get cargo-llvm-cov env", |ctx| {
+                cargo_llvm_cov_installed.clone().claim(ctx);
+                let rust_toolchain = rust_toolchain.clone().claim(ctx);
+                let in_folder = in_folder.claim(ctx);
+                let env = env.claim(ctx);
+                move |rt| {
+                    let rust_toolchain = rt.read(rust_toolchain);
+                    let in_folder = rt.read(in_folder);
+
+                    let sh = xshell::Shell::new()?;
+                    sh.change_dir(in_folder);
+
+                    let llvm_cov_env = show_env(&sh, &rust_toolchain)?;
+
+                    // clean up artifacts and profiles left behind by any
+                    // previous instrumented runs
+                    let rust_toolchain = rust_toolchain.map(|s| format!("+{s}"));
+                    xshell::cmd!(sh, "cargo {rust_toolchain...} llvm-cov clean --workspace")
+                        .envs(&llvm_cov_env)
+                        .run()?;
+
+                    rt.write(env, &llvm_cov_env);
+
+                    Ok(())
+                }
+            });
+        }
+
+        for Report {
+            in_folder,
+            target,
+            profile,
+            pre_report_deps,
+            lcov,
+        } in report
+        {
+            ctx.emit_rust_step("generate cargo-llvm-cov lcov report", |ctx| {
+                cargo_llvm_cov_installed.clone().claim(ctx);
+                pre_report_deps.claim(ctx);
+                let rust_toolchain = rust_toolchain.clone().claim(ctx);
+                let in_folder = in_folder.claim(ctx);
+                let lcov = lcov.claim(ctx);
+                move |rt| {
+                    let rust_toolchain = rt.read(rust_toolchain);
+                    let in_folder = rt.read(in_folder);
+
+                    let sh = xshell::Shell::new()?;
+                    let out_path = sh.current_dir().join("coverage.lcov");
+                    sh.change_dir(in_folder);
+
+                    let llvm_cov_env = show_env(&sh, &rust_toolchain)?;
+
+                    let target = target.to_string();
+                    let profile: Vec<&str> = match &profile {
+                        CargoBuildProfile::Debug => vec![],
+                        CargoBuildProfile::Release => vec!["--release"],
+                        CargoBuildProfile::Custom(s) => vec!["--cargo-profile", s],
+                    };
+
+                    let rust_toolchain = rust_toolchain.map(|s| format!("+{s}"));
+                    xshell::cmd!(
+                        sh,
+                        "cargo {rust_toolchain...}
+                            llvm-cov report
+                            --lcov
+                            --output-path {out_path}
+                            --target {target}
+                            {profile...}
+                        "
+                    )
+                    .envs(&llvm_cov_env)
+                    .run()?;
+
+                    rt.write(lcov, &out_path.absolute()?);
+
+                    Ok(())
+                }
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Invoke `cargo llvm-cov show-env`, returning the env vars it reports.
+fn show_env(
+    sh: &xshell::Shell,
+    rust_toolchain: &Option<String>,
+) -> anyhow::Result<BTreeMap<String, String>> {
+    let rust_toolchain = rust_toolchain.as_ref().map(|s| format!("+{s}"));
+    let output = xshell::cmd!(sh, "cargo {rust_toolchain...} llvm-cov show-env").read()?;
+
+    let mut env = BTreeMap::new();
+    for line in output.lines() {
+        let Some((key, val)) = line.trim().split_once('=') else {
+            continue;
+        };
+        env.insert(key.to_owned(), unquote(val));
+    }
+
+    Ok(env)
+}
+
+/// `cargo llvm-cov show-env` shell-escapes values, using single quotes on Unix
+/// and double quotes on Windows.
+fn unquote(val: &str) -> String {
+    if let Some(val) = val.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        val.replace(r"'\''", "'").replace(r"\!", "!")
+    } else if let Some(val) = val.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        val.replace(r#"\""#, "\"")
+    } else {
+        val.to_owned()
+    }
+}
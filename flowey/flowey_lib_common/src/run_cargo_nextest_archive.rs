@@ -34,6 +34,7 @@ fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<crate::cfg_cargo_common_flags::Node>();
         ctx.import::<crate::download_cargo_nextest::Node>();
         ctx.import::<crate::install_rust::Node>();
+        ctx.import::<crate::use_sccache::Node>();
     }
 
     fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
@@ -64,6 +65,11 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                 target.clone(),
             ));
 
+            let sccache_env = ctx.reqv(|v| crate::use_sccache::Request::GetEnv {
+                in_folder: working_dir.clone(),
+                env: v,
+            });
+
             ctx.emit_rust_step(
                 format!("build + archive '{friendly_label}' nextests"),
                 |ctx| {
@@ -75,12 +81,15 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                     let archive_file = archive_file.claim(ctx);
                     let packages = packages.claim(ctx);
                     let extra_env = extra_env.claim(ctx);
+                    let sccache_env = sccache_env.claim(ctx);
                     move |rt| {
                         let cargo_flags = rt.read(cargo_flags);
                         let working_dir = rt.read(working_dir);
                         let rust_toolchain = rt.read(rust_toolchain);
                         let packages = rt.read(packages);
-                        let extra_env = rt.read(extra_env);
+                        let mut sccache_env = rt.read(sccache_env);
+                        sccache_env.extend(rt.read(extra_env));
+                        let extra_env = sccache_env;
 
                         let rust_toolchain = rust_toolchain.map(|s| format!("+{s}"));
                         let (build_args, build_env) =
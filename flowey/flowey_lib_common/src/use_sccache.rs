@@ -0,0 +1,236 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Route rustc invocations through [`sccache`], sharing a compilation cache
+//! between pipeline runs, and between jobs building on the same platform.
+//!
+//! The cache is keyed on the `Cargo.lock` of the workspace(s) being built, as
+//! well as the active Rust toolchain. If there's no exact hit, the most recent
+//! cache saved on the same platform is used as a starting point.
+//!
+//! Nodes which invoke cargo should merge the env vars reported via
+//! [`Request::GetEnv`] into the env of each cargo invocation.
+//!
+//! [`sccache`]: https://github.com/mozilla/sccache
+
+use crate::cache::CacheHit;
+use crate::cache::CacheResult;
+use flowey::node::prelude::*;
+use std::collections::BTreeMap;
+
+/// Upper bound on the size of the on-disk cache.
+///
+/// The cache gets saved via [`crate::cache`], which has limited storage (e.g:
+/// 10GB per repo on GitHub), so keep it reasonably small.
+const SCCACHE_CACHE_SIZE: &str = "2G";
+
+flowey_request! {
+    pub enum Request {
+        /// Whether to use sccache. If disabled, `GetEnv` will report an empty
+        /// set of env vars.
+        Enable(bool),
+        /// Version of `sccache` to use
+        Version(String),
+        /// Get the env vars required to wrap rustc with sccache
+        GetEnv {
+            /// Root of the workspace being built (used to locate `Cargo.lock`)
+            in_folder: ReadVar<PathBuf>,
+            env: WriteVar<BTreeMap<String, String>>,
+        },
+    }
+}
+
+new_flow_node!(struct Node);
+
+impl FlowNode for Node {
+    type Request = Request;
+
+    fn imports(ctx: &mut ImportCtx<'_>) {
+        ctx.import::<crate::cache::Node>();
+        ctx.import::<crate::download_gh_release::Node>();
+        ctx.import::<crate::install_dist_pkg::Node>();
+        ctx.import::<crate::install_rust::Node>();
+    }
+
+    fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
+        let mut enable = None;
+        let mut version = None;
+        let mut in_folders = Vec::new();
+        let mut get_env = Vec::new();
+
+        for req in requests {
+            match req {
+                Request::Enable(v) => same_across_all_reqs("Enable", &mut enable, v)?,
+                Request::Version(v) => same_across_all_reqs("Version", &mut version, v)?,
+                Request::GetEnv { in_folder, env } => {
+                    in_folders.push(in_folder);
+                    get_env.push(env);
+                }
+            }
+        }
+
+        let enable = enable.ok_or(anyhow::anyhow!("Missing essential request: Enable"))?;
+        let version = version.ok_or(anyhow::anyhow!("Missing essential request: Version"))?;
+        let get_env = get_env;
+
+        // -- end of req processing -- //
+
+        if get_env.is_empty() {
+            return Ok(());
+        }
+
+        let target = match (ctx.platform(), ctx.arch()) {
+            (FlowPlatform::Windows, FlowArch::X86_64) => Some("x86_64-pc-windows-msvc"),
+            (FlowPlatform::Linux(_), FlowArch::X86_64) => Some("x86_64-unknown-linux-musl"),
+            (FlowPlatform::Linux(_), FlowArch::Aarch64) => Some("aarch64-unknown-linux-musl"),
+            (FlowPlatform::MacOs, FlowArch::X86_64) => Some("x86_64-apple-darwin"),
+            (FlowPlatform::MacOs, FlowArch::Aarch64) => Some("aarch64-apple-darwin"),
+            _ => None,
+        };
+
+        let target = match target {
+            Some(target) if enable => target,
+            _ => {
+                if enable {
+                    log::warn!(
+                        "sccache is not available on {} {}, building without it",
+                        ctx.platform(),
+                        ctx.arch()
+                    );
+                }
+
+                ctx.emit_rust_step("report sccache disabled", |ctx| {
+                    let get_env = get_env.claim(ctx);
+                    move |rt| {
+                        rt.write_all(get_env, &BTreeMap::new());
+                        Ok(())
+                    }
+                });
+
+                return Ok(());
+            }
+        };
+
+        let sccache_bin = ctx.platform().binary("sccache");
+        let tag = format!("v{version}");
+        let file_name_base = format!("sccache-v{version}-{target}");
+        let file_name = format!(
+            "{file_name_base}.{}",
+            match ctx.platform() {
+                FlowPlatform::Windows => "zip",
+                _ => "tar.gz",
+            }
+        );
+
+        let sccache_zip = ctx.reqv(|v| crate::download_gh_release::Request {
+            repo_owner: "mozilla".into(),
+            repo_name: "sccache".into(),
+            needs_auth: false,
+            tag: tag.clone(),
+            file_name,
+            path: v,
+        });
+
+        let extract_zip_deps = crate::_util::extract::extract_zip_if_new_deps(ctx);
+        let sccache_bin = ctx.emit_rust_stepv("unpack sccache", |ctx| {
+            let extract_zip_deps = extract_zip_deps.claim(ctx);
+            let sccache_zip = sccache_zip.claim(ctx);
+            move |rt| {
+                let sccache_zip = rt.read(sccache_zip);
+
+                let extract_dir = crate::_util::extract::extract_zip_if_new(
+                    rt,
+                    extract_zip_deps,
+                    &sccache_zip,
+                    &tag,
+                )?;
+
+                Ok(extract_dir.join(file_name_base).join(sccache_bin))
+            }
+        });
+
+        let rust_installed = ctx.reqv(crate::install_rust::Request::EnsureInstalled);
+        let rust_toolchain = ctx.reqv(crate::install_rust::Request::GetRustupToolchain);
+
+        let cache_key_base = format!("sccache-{}-{}", ctx.platform(), ctx.arch());
+        let cache_key = ctx.emit_rust_stepv("compute sccache cache key", |ctx| {
+            rust_installed.claim(ctx);
+            let rust_toolchain = rust_toolchain.claim(ctx);
+            let in_folders = in_folders.claim(ctx);
+            let cache_key_base = cache_key_base.clone();
+            move |rt| {
+                let rust_toolchain = rt.read(rust_toolchain);
+
+                let sh = xshell::Shell::new()?;
+                let rustc_version = if let Some(rust_toolchain) = &rust_toolchain {
+                    xshell::cmd!(sh, "rustup run {rust_toolchain} rustc -V")
+                } else {
+                    xshell::cmd!(sh, "rustc -V")
+                }
+                .read()?;
+
+                let hasher = &mut rustc_hash::FxHasher::default();
+                std::hash::Hash::hash(&rustc_version, hasher);
+                let lockfiles = in_folders
+                    .into_iter()
+                    .map(|x| rt.read(x).join("Cargo.lock"))
+                    .collect::<std::collections::BTreeSet<_>>();
+                for lockfile in lockfiles {
+                    if lockfile.exists() {
+                        std::hash::Hash::hash(&fs_err::read_to_string(lockfile)?, hasher);
+                    }
+                }
+                let hash = std::hash::Hasher::finish(hasher);
+
+                Ok(format!("{cache_key_base}-{hash:08x?}"))
+            }
+        });
+
+        let cache_dir = ctx.emit_rust_stepv("create sccache cache dir", |_| {
+            |_| Ok(std::env::current_dir()?.absolute()?)
+        });
+
+        let hitvar = ctx.reqv(|v| crate::cache::Request {
+            label: "sccache".into(),
+            dir: cache_dir.clone(),
+            key: cache_key,
+            // a stale cache is still far better than building from scratch
+            restore_keys: Some(ReadVar::from_static(vec![format!("{cache_key_base}-")])),
+            hitvar: CacheResult::HitVar(v),
+        });
+
+        ctx.emit_rust_step("report sccache env", |ctx| {
+            let get_env = get_env.claim(ctx);
+            let sccache_bin = sccache_bin.claim(ctx);
+            let cache_dir = cache_dir.claim(ctx);
+            let hitvar = hitvar.claim(ctx);
+            move |rt| {
+                let sccache_bin = rt.read(sccache_bin);
+                let cache_dir = rt.read(cache_dir);
+
+                match rt.read(hitvar) {
+                    CacheHit::Hit => log::info!("restored sccache cache"),
+                    CacheHit::PartialHit => log::info!("restored stale sccache cache"),
+                    CacheHit::Miss => log::info!("no sccache cache available"),
+                }
+
+                let env = BTreeMap::from([
+                    (
+                        "RUSTC_WRAPPER".to_string(),
+                        sccache_bin.display().to_string(),
+                    ),
+                    ("SCCACHE_DIR".into(), cache_dir.display().to_string()),
+                    ("SCCACHE_CACHE_SIZE".into(), SCCACHE_CACHE_SIZE.into()),
+                    // sccache is unable to cache incremental builds
+                    ("CARGO_INCREMENTAL".into(), "0".into()),
+                ]);
+
+                rt.write_all(get_env, &env);
+
+                Ok(())
+            }
+        });
+
+        Ok(())
+    }
+}
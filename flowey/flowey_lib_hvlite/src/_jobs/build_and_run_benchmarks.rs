@@ -0,0 +1,327 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Build and run criterion benchmarks (and optionally, time VMM boots),
+//! publishing the results and comparing them against a baseline.
+//!
+//! Results are published as `{label}-benchmarks.json`, alongside a
+//! `{label}-comparison.md` report when a baseline is available. The job fails
+//! if any benchmark regressed past the configured threshold.
+
+use crate::run_cargo_build::common::CommonProfile;
+use crate::run_cargo_nextest_run::NextestProfile;
+use flowey::node::prelude::*;
+use flowey_lib_common::run_cargo_nextest_run::TestResults;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+/// Contents of a `{label}-benchmarks.json` results file
+#[derive(Serialize, Deserialize)]
+pub struct BenchmarkResults {
+    /// Commit the benchmarks were run against
+    pub commit: String,
+    /// Mean time (in nanoseconds), keyed by benchmark ID
+    pub results: BTreeMap<String, f64>,
+}
+
+/// Where to find results to compare against
+#[derive(Serialize, Deserialize)]
+pub enum BenchmarkBaseline {
+    /// A local directory containing a `{label}-benchmarks.json` file (e.g: an
+    /// artifact downloaded from a previous run).
+    LocalDir(PathBuf),
+    /// The `{label}-benchmarks` artifact published by the most recent
+    /// successful run of the specified GitHub workflow on `branch`.
+    GithubWorkflow { workflow: String, branch: String },
+}
+
+flowey_request! {
+    pub struct Params {
+        /// Friendly label used to name the published results (e.g: `x64-linux`)
+        pub label: String,
+        /// Build and run benchmarks for the specified target
+        pub target: target_lexicon::Triple,
+        /// Packages whose criterion benchmarks should be run
+        pub criterion_packages: Vec<String>,
+        /// If provided, run the VMM tests matching this nextest filter
+        /// expression, recording how long each test takes to boot and run.
+        pub vmm_boot_filter_expr: Option<String>,
+        /// Results to compare against
+        pub baseline: Option<BenchmarkBaseline>,
+        /// Slowdown (as a percentage of the baseline) past which a benchmark
+        /// is considered to have regressed
+        pub regression_threshold: f64,
+
+        pub artifact_dir: ReadVar<PathBuf>,
+        pub done: WriteVar<SideEffect>,
+    }
+}
+
+new_simple_flow_node!(struct Node);
+
+impl SimpleFlowNode for Node {
+    type Request = Params;
+
+    fn imports(ctx: &mut ImportCtx<'_>) {
+        ctx.import::<crate::_jobs::build_and_run_nextest_vmm_tests::Node>();
+        ctx.import::<crate::git_checkout_openvmm_repo::Node>();
+        ctx.import::<crate::init_cross_build::Node>();
+        ctx.import::<crate::init_openvmm_magicpath_lxutil::Node>();
+        ctx.import::<crate::install_openvmm_rust_build_essential::Node>();
+        ctx.import::<flowey_lib_common::run_cargo_bench::Node>();
+        ctx.import::<flowey_lib_common::use_gh_cli::Node>();
+    }
+
+    fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
+        let Params {
+            label,
+            target,
+            criterion_packages,
+            vmm_boot_filter_expr,
+            baseline,
+            regression_threshold,
+            artifact_dir,
+            done,
+        } = request;
+
+        let lxutil_arch = match target.architecture {
+            target_lexicon::Architecture::X86_64 => crate::download_lxutil::LxutilArch::X86_64,
+            target_lexicon::Architecture::Aarch64(_) => crate::download_lxutil::LxutilArch::Aarch64,
+            arch => anyhow::bail!("unsupported arch {arch}"),
+        };
+
+        let openvmm_repo_path = ctx.reqv(crate::git_checkout_openvmm_repo::req::GetRepoDir);
+
+        let commit = ctx.emit_rust_stepv("get benchmarked commit", |ctx| {
+            let openvmm_repo_path = openvmm_repo_path.clone().claim(ctx);
+            |rt| {
+                let sh = xshell::Shell::new()?;
+                sh.change_dir(rt.read(openvmm_repo_path));
+                Ok(xshell::cmd!(sh, "git rev-parse HEAD").read()?)
+            }
+        });
+
+        let pre_build_deps = vec![
+            ctx.reqv(crate::install_openvmm_rust_build_essential::Request),
+            // required due to build-scripts in the openvmm repo
+            ctx.reqv(|v| crate::init_openvmm_magicpath_lxutil::Request {
+                arch: lxutil_arch,
+                done: v,
+            }),
+        ];
+
+        let injected_env = ctx.reqv(|v| crate::init_cross_build::Request {
+            target: target.clone(),
+            injected_env: v,
+        });
+
+        let criterion_results = ctx.reqv(|v| flowey_lib_common::run_cargo_bench::Request {
+            in_folder: openvmm_repo_path.clone(),
+            packages: criterion_packages,
+            target: target.clone(),
+            extra_env: Some(injected_env),
+            pre_build_deps,
+            results: v,
+        });
+
+        let vmm_boot_results = vmm_boot_filter_expr.map(|nextest_filter_expr| {
+            let (results, write_results): (ReadVar<TestResults>, _) = ctx.new_var();
+            let done = ctx.reqv(|v| crate::_jobs::build_and_run_nextest_vmm_tests::Params {
+                junit_test_label: format!("{label}-vmm-boot-benchmarks"),
+                target: target.clone(),
+                profile: CommonProfile::Release,
+                nextest_profile: NextestProfile::Ci,
+                nextest_filter_expr: Some(nextest_filter_expr),
+                openhcl_custom_target: None,
+                fail_job_on_test_fail: true,
+                artifact_dir: None,
+                coverage_artifact_dir: None,
+                results: Some(write_results),
+                done: v,
+            });
+            (results, done)
+        });
+
+        let baseline_dir = match baseline {
+            None => None,
+            Some(BenchmarkBaseline::LocalDir(dir)) => Some(ReadVar::from_static(Some(dir))),
+            Some(BenchmarkBaseline::GithubWorkflow { workflow, branch }) => {
+                if matches!(ctx.backend(), FlowBackend::Github) {
+                    let gh_token = ctx.get_gh_context_var(GhContextVar::GITHUB__TOKEN);
+                    ctx.req(flowey_lib_common::use_gh_cli::Request::WithAuth(
+                        flowey_lib_common::use_gh_cli::GhCliAuth::AuthToken(gh_token),
+                    ));
+                }
+                let gh_cli = ctx.reqv(flowey_lib_common::use_gh_cli::Request::Get);
+
+                let artifact_name = format!("{label}-benchmarks");
+                Some(
+                    ctx.emit_rust_stepv("download baseline benchmark results", |ctx| {
+                        let gh_cli = gh_cli.claim(ctx);
+                        let openvmm_repo_path = openvmm_repo_path.clone().claim(ctx);
+                        move |rt| {
+                            let gh_cli = rt.read(gh_cli);
+                            let sh = xshell::Shell::new()?;
+                            let out_dir = sh.current_dir().join("baseline");
+                            sh.change_dir(rt.read(openvmm_repo_path));
+
+                            let run_id = xshell::cmd!(
+                                sh,
+                                "{gh_cli} run list
+                                --workflow {workflow}
+                                --branch {branch}
+                                --status success
+                                --limit 1
+                                --json databaseId
+                                --jq .[0].databaseId
+                            "
+                            )
+                            .read()?;
+
+                            if run_id.is_empty() {
+                                log::warn!("no successful runs of {workflow} on {branch} found");
+                                return Ok(None);
+                            }
+
+                            if xshell::cmd!(
+                            sh,
+                            "{gh_cli} run download {run_id} --name {artifact_name} --dir {out_dir}"
+                        )
+                            .run()
+                            .is_err()
+                            {
+                                log::warn!("run {run_id} did not publish {artifact_name}");
+                                return Ok(None);
+                            }
+
+                            Ok(Some(out_dir))
+                        }
+                    }),
+                )
+            }
+        };
+
+        ctx.emit_rust_step("publish and compare benchmark results", |ctx| {
+            done.claim(ctx);
+            let commit = commit.claim(ctx);
+            let criterion_results = criterion_results.claim(ctx);
+            let vmm_boot_results = vmm_boot_results.claim(ctx);
+            let baseline_dir = baseline_dir.claim(ctx);
+            let artifact_dir = artifact_dir.claim(ctx);
+            move |rt| {
+                let mut results = rt.read(criterion_results);
+
+                if let Some((vmm_boot_results, _)) = vmm_boot_results {
+                    let junit_xml = rt
+                        .read(vmm_boot_results)
+                        .junit_xml
+                        .context("VMM boot benchmarks require junit output")?;
+                    for (name, secs) in parse_junit_durations(&fs_err::read_to_string(junit_xml)?)?
+                    {
+                        results.insert(format!("vmm_boot/{name}"), secs * 1e9);
+                    }
+                }
+
+                let results = BenchmarkResults {
+                    commit: rt.read(commit),
+                    results,
+                };
+
+                let artifact_dir = rt.read(artifact_dir);
+                fs_err::write(
+                    artifact_dir.join(format!("{label}-benchmarks.json")),
+                    serde_json::to_string_pretty(&results)?,
+                )?;
+
+                let Some(baseline_dir) = baseline_dir.and_then(|x| rt.read(x)) else {
+                    log::info!("no baseline available, skipping comparison");
+                    return Ok(());
+                };
+
+                let baseline: BenchmarkResults = serde_json::from_str(&fs_err::read_to_string(
+                    baseline_dir.join(format!("{label}-benchmarks.json")),
+                )?)?;
+
+                let (report, regressions) = compare(&baseline, &results, regression_threshold)?;
+                log::info!("{report}");
+                fs_err::write(artifact_dir.join(format!("{label}-comparison.md")), report)?;
+
+                if !regressions.is_empty() {
+                    anyhow::bail!(
+                        "benchmarks regressed by more than {regression_threshold}%: {}",
+                        regressions.join(", ")
+                    );
+                }
+
+                Ok(())
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Compare `current` against `baseline`, returning a markdown report and the
+/// IDs of any benchmarks that slowed down by more than `threshold` percent.
+fn compare(
+    baseline: &BenchmarkResults,
+    current: &BenchmarkResults,
+    threshold: f64,
+) -> anyhow::Result<(String, Vec<String>)> {
+    let mut report = String::new();
+    let mut regressions = Vec::new();
+
+    writeln!(
+        report,
+        "Comparing {} against baseline {}\n",
+        current.commit, baseline.commit
+    )?;
+    writeln!(
+        report,
+        "| Benchmark | Baseline (ns) | Current (ns) | Change |"
+    )?;
+    writeln!(
+        report,
+        "| --------- | ------------: | -----------: | -----: |"
+    )?;
+
+    for (id, &new) in &current.results {
+        let Some(&old) = baseline.results.get(id) else {
+            writeln!(report, "| {id} | - | {new:.1} | new |")?;
+            continue;
+        };
+
+        let change = (new - old) / old * 100.0;
+        let regressed = change > threshold;
+        if regressed {
+            regressions.push(id.clone());
+        }
+
+        writeln!(
+            report,
+            "| {id} | {old:.1} | {new:.1} | {change:+.1}%{} |",
+            if regressed { " :x:" } else { "" }
+        )?;
+    }
+
+    Ok((report, regressions))
+}
+
+/// Extract the duration (in seconds) of each test case in a JUnit XML report.
+fn parse_junit_durations(xml: &str) -> anyhow::Result<Vec<(String, f64)>> {
+    fn attr<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+        let start = tag.find(&format!(" {name}=\""))? + name.len() + 3;
+        let len = tag[start..].find('"')?;
+        Some(&tag[start..start + len])
+    }
+
+    let mut durations = Vec::new();
+    for tag in xml.split("<testcase").skip(1) {
+        let tag = tag.split('>').next().unwrap_or_default();
+        let name = attr(tag, "name").context("testcase missing name")?;
+        let time = attr(tag, "time").context("testcase missing time")?;
+        durations.push((name.to_owned(), time.parse()?));
+    }
+
+    Ok(durations)
+}
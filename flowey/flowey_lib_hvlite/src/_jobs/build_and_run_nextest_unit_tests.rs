@@ -27,6 +27,9 @@ pub struct Params {
         pub fail_job_on_test_fail: bool,
         /// If provided, also publish junit.xml test results as an artifact.
         pub artifact_dir: Option<ReadVar<PathBuf>>,
+        /// If provided, collect code coverage while running the tests, and
+        /// publish the resulting lcov report as an artifact.
+        pub coverage_artifact_dir: Option<ReadVar<PathBuf>>,
         pub done: WriteVar<SideEffect>,
     }
 }
@@ -37,6 +40,7 @@ impl SimpleFlowNode for Node {
     type Request = Params;
 
     fn imports(ctx: &mut ImportCtx<'_>) {
+        ctx.import::<crate::run_cargo_llvm_cov::Node>();
         ctx.import::<flowey_lib_common::publish_test_results::Node>();
         ctx.import::<crate::build_nextest_unit_tests::Node>();
     }
@@ -50,12 +54,13 @@ fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Res
             nextest_profile,
             fail_job_on_test_fail,
             artifact_dir,
+            coverage_artifact_dir,
             done,
         } = request;
 
         let results = ctx.reqv(|v| crate::build_nextest_unit_tests::Request {
             profile,
-            target,
+            target: target.clone(),
             unstable_panic_abort_tests,
             build_mode: BuildNextestUnitTestMode::ImmediatelyRun {
                 nextest_profile,
@@ -65,6 +70,27 @@ fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Res
 
         let mut side_effects = Vec::new();
 
+        if let Some(coverage_artifact_dir) = coverage_artifact_dir {
+            let lcov = ctx.reqv(|v| crate::run_cargo_llvm_cov::Request::Report {
+                target,
+                profile,
+                pre_report_deps: vec![results.clone().into_side_effect()],
+                lcov: v,
+            });
+
+            let lcov_name = format!("{junit_test_label}.lcov");
+            side_effects.push(ctx.emit_rust_step("publish code coverage", |ctx| {
+                let lcov = lcov.claim(ctx);
+                let coverage_artifact_dir = coverage_artifact_dir.claim(ctx);
+                move |rt| {
+                    let lcov = rt.read(lcov);
+                    let coverage_artifact_dir = rt.read(coverage_artifact_dir);
+                    fs_err::copy(lcov, coverage_artifact_dir.join(lcov_name))?;
+                    Ok(())
+                }
+            }));
+        }
+
         let junit_xml = results.map(ctx, |r| r.junit_xml);
         let reported_results = ctx.reqv(|v| flowey_lib_common::publish_test_results::Request {
             junit_xml,
@@ -10,6 +10,7 @@
 use crate::run_cargo_build::common::CommonTriple;
 use crate::run_cargo_nextest_run::NextestProfile;
 use flowey::node::prelude::*;
+use flowey_lib_common::run_cargo_nextest_run::TestResults;
 use std::collections::BTreeMap;
 
 flowey_request! {
@@ -31,6 +32,12 @@ pub struct Params {
         pub fail_job_on_test_fail: bool,
         /// If provided, also publish junit.xml test results as an artifact.
         pub artifact_dir: Option<ReadVar<PathBuf>>,
+        /// If provided, collect code coverage while running the tests, and
+        /// publish the resulting lcov report as an artifact.
+        pub coverage_artifact_dir: Option<ReadVar<PathBuf>>,
+        /// If provided, also report the raw test results (e.g: to post-process
+        /// the junit.xml).
+        pub results: Option<WriteVar<TestResults>>,
         pub done: WriteVar<SideEffect>,
     }
 }
@@ -49,6 +56,7 @@ fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<crate::build_pipette::Node>();
         ctx.import::<crate::download_openvmm_vmm_tests_vhds::Node>();
         ctx.import::<crate::init_vmm_tests_env::Node>();
+        ctx.import::<crate::run_cargo_llvm_cov::Node>();
         ctx.import::<flowey_lib_common::publish_test_results::Node>();
     }
 
@@ -62,6 +70,8 @@ fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Res
             openhcl_custom_target,
             fail_job_on_test_fail,
             artifact_dir,
+            coverage_artifact_dir,
+            results: write_results,
             done,
         } = request;
 
@@ -192,9 +202,12 @@ struct TestTargets<'a> {
         let disk_images_dir =
             ctx.reqv(crate::download_openvmm_vmm_tests_vhds::Request::GetDownloadFolder);
 
-        let test_content_dir = ctx.persistent_dir().ok_or(anyhow::anyhow!(
-            "build and run VMM tests only works locally"
-        ))?;
+        let test_content_dir = match ctx.persistent_dir() {
+            Some(dir) => dir,
+            None => ctx.emit_rust_stepv("creating new test content dir", |_| {
+                |_| Ok(std::env::current_dir()?.absolute()?)
+            }),
+        };
 
         let (test_log_path, get_test_log_path) = ctx.new_var();
         let (openhcl_dump_path, get_openhcl_dump_path) = ctx.new_var();
@@ -215,7 +228,7 @@ struct TestTargets<'a> {
 
         let results = ctx.reqv(|v| crate::build_nextest_vmm_tests::Request {
             profile,
-            target,
+            target: target.clone(),
             build_mode: BuildNextestVmmTestsMode::ImmediatelyRun {
                 nextest_profile,
                 nextest_filter_expr,
@@ -233,6 +246,31 @@ struct TestTargets<'a> {
             FlowPlatformKind::Unix => "/will/not/exist",
         }));
 
+        if let Some(coverage_artifact_dir) = coverage_artifact_dir {
+            let lcov = ctx.reqv(|v| crate::run_cargo_llvm_cov::Request::Report {
+                target,
+                profile,
+                pre_report_deps: vec![results.clone().into_side_effect()],
+                lcov: v,
+            });
+
+            let lcov_name = format!("{junit_test_label}.lcov");
+            side_effects.push(ctx.emit_rust_step("publish code coverage", |ctx| {
+                let lcov = lcov.claim(ctx);
+                let coverage_artifact_dir = coverage_artifact_dir.claim(ctx);
+                move |rt| {
+                    let lcov = rt.read(lcov);
+                    let coverage_artifact_dir = rt.read(coverage_artifact_dir);
+                    fs_err::copy(lcov, coverage_artifact_dir.join(lcov_name))?;
+                    Ok(())
+                }
+            }));
+        }
+
+        if let Some(write_results) = write_results {
+            results.write_into(ctx, write_results, |r| r);
+        }
+
         let junit_xml = results.map(ctx, |r| r.junit_xml);
         let reported_results = ctx.reqv(|v| flowey_lib_common::publish_test_results::Request {
             junit_xml,
@@ -65,6 +65,7 @@ fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<flowey_lib_common::nuget_install_package::Node>();
         ctx.import::<flowey_lib_common::run_cargo_nextest_run::Node>();
         ctx.import::<flowey_lib_common::use_gh_cli::Node>();
+        ctx.import::<flowey_lib_common::use_sccache::Node>();
     }
 
     fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
@@ -188,6 +189,14 @@ fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Res
             crate::init_openvmm_cargo_config_deny_warnings::Request::DenyWarnings(deny_warnings),
         );
 
+        // CI jobs start from a clean slate, and would otherwise rebuild the
+        // world every time. Local builds already benefit from incremental
+        // compilation.
+        ctx.req(flowey_lib_common::use_sccache::Request::Enable(!matches!(
+            ctx.backend(),
+            FlowBackend::Local
+        )));
+
         Ok(())
     }
 }
@@ -16,6 +16,7 @@
 // a `Version(ReadVar<String>)`, but that shouldn't be a serious blocker.
 pub const AZCOPY: &str = "10.27.0-20241030";
 pub const AZURE_CLI: &str = "2.56.0";
+pub const CARGO_LLVM_COV: &str = "0.6.14";
 pub const FUZZ: &str = "0.12.0";
 pub const GH_CLI: &str = "2.52.0";
 pub const LXUTIL: &str = "10.0.26100.1-240331-1435.ge-release";
@@ -30,6 +31,7 @@
 pub const OPENHCL_KERNEL_STABLE_VERSION: &str = "6.6.51.7";
 pub const OPENVMM_DEPS: &str = "0.1.0-20241014.2";
 pub const PROTOC: &str = "27.1";
+pub const SCCACHE: &str = "0.8.2";
 
 flowey_request! {
     pub struct Request {}
@@ -48,6 +50,7 @@ fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<crate::download_uefi_mu_msvm::Node>();
         ctx.import::<flowey_lib_common::download_azcopy::Node>();
         ctx.import::<flowey_lib_common::download_cargo_fuzz::Node>();
+        ctx.import::<flowey_lib_common::download_cargo_llvm_cov::Node>();
         ctx.import::<flowey_lib_common::download_cargo_nextest::Node>();
         ctx.import::<flowey_lib_common::download_gh_cli::Node>();
         ctx.import::<flowey_lib_common::download_mdbook_admonish::Node>();
@@ -57,6 +60,7 @@ fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<flowey_lib_common::install_azure_cli::Node>();
         ctx.import::<flowey_lib_common::install_nodejs::Node>();
         ctx.import::<flowey_lib_common::install_rust::Node>();
+        ctx.import::<flowey_lib_common::use_sccache::Node>();
     }
 
     #[rustfmt::skip]
@@ -70,6 +74,7 @@ fn emit(_requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<
         ctx.req(crate::download_uefi_mu_msvm::Request::Version(MU_MSVM.into()));
         ctx.req(flowey_lib_common::download_azcopy::Request::Version(AZCOPY.into()));
         ctx.req(flowey_lib_common::download_cargo_fuzz::Request::Version(FUZZ.into()));
+        ctx.req(flowey_lib_common::download_cargo_llvm_cov::Request::Version(CARGO_LLVM_COV.into()));
         ctx.req(flowey_lib_common::download_cargo_nextest::Request::Version(NEXTEST.into()));
         ctx.req(flowey_lib_common::download_gh_cli::Request::Version(GH_CLI.into()));
         ctx.req(flowey_lib_common::download_mdbook::Request::Version(MDBOOK.into()));
@@ -78,6 +83,7 @@ fn emit(_requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<
         ctx.req(flowey_lib_common::download_protoc::Request::Version(PROTOC.into()));
         ctx.req(flowey_lib_common::install_azure_cli::Request::Version(AZURE_CLI.into()));
         ctx.req(flowey_lib_common::install_nodejs::Request::Version(NODEJS.into()));
+        ctx.req(flowey_lib_common::use_sccache::Request::Version(SCCACHE.into()));
         if !matches!(ctx.backend(), FlowBackend::Ado) {
             ctx.req(flowey_lib_common::install_rust::Request::Version(RUSTUP_TOOLCHAIN.into()));
         }
@@ -168,6 +168,7 @@ fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Res
                     let xtask_bin = match xtask {
                         crate::build_xtask::XtaskOutput::LinuxBin { bin, dbg: _ } => bin,
                         crate::build_xtask::XtaskOutput::WindowsBin { exe, pdb: _ } => exe,
+                        crate::build_xtask::XtaskOutput::MacOsBin { bin } => bin,
                     };
 
                     let sh = xshell::Shell::new()?;
@@ -38,6 +38,7 @@ fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Res
                 let xtask = match rt.read(xtask) {
                     crate::build_xtask::XtaskOutput::LinuxBin { bin, .. } => bin,
                     crate::build_xtask::XtaskOutput::WindowsBin { exe, .. } => exe,
+                    crate::build_xtask::XtaskOutput::MacOsBin { bin } => bin,
                 };
 
                 let sh = xshell::Shell::new()?;
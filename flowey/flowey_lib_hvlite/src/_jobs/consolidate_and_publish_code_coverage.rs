@@ -0,0 +1,327 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Merge the lcov reports collected by various code coverage jobs, and publish
+//! them as a single artifact containing:
+//!
+//! - `lcov.info`: the merged lcov report
+//! - `crates/{crate}.info`: the merged lcov report, split per-crate
+//! - `html/`: a rendered HTML report (with per-crate reports under
+//!   `html/crates/{crate}/`)
+//! - `summary.md`: a table of per-crate line coverage
+
+use flowey::node::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+
+flowey_request! {
+    pub struct Params {
+        /// Artifact directories containing `*.lcov` reports to merge
+        pub lcov_dirs: Vec<ReadVar<PathBuf>>,
+        pub artifact_dir: ReadVar<PathBuf>,
+        pub done: WriteVar<SideEffect>,
+    }
+}
+
+new_simple_flow_node!(struct Node);
+
+impl SimpleFlowNode for Node {
+    type Request = Params;
+
+    fn imports(ctx: &mut ImportCtx<'_>) {
+        ctx.import::<crate::git_checkout_openvmm_repo::Node>();
+        ctx.import::<flowey_lib_common::copy_to_artifact_dir::Node>();
+        ctx.import::<flowey_lib_common::install_dist_pkg::Node>();
+    }
+
+    fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
+        let Params {
+            lcov_dirs,
+            artifact_dir,
+            done,
+        } = request;
+
+        if !matches!(ctx.platform(), FlowPlatform::Linux(_)) {
+            anyhow::bail!("code coverage reports can only be generated on linux");
+        }
+
+        // for `genhtml`
+        let lcov_installed = ctx.reqv(|v| flowey_lib_common::install_dist_pkg::Request::Install {
+            package_names: vec!["lcov".into()],
+            done: v,
+        });
+
+        let openvmm_repo_path = ctx.reqv(crate::git_checkout_openvmm_repo::req::GetRepoDir);
+
+        let coverage_dir = ctx.emit_rust_stepv("generate code coverage report", |ctx| {
+            lcov_installed.claim(ctx);
+            let lcov_dirs = lcov_dirs.claim(ctx);
+            let openvmm_repo_path = openvmm_repo_path.claim(ctx);
+            move |rt| {
+                let lcov_dirs = lcov_dirs
+                    .into_iter()
+                    .map(|x| rt.read(x))
+                    .collect::<Vec<_>>();
+                let openvmm_repo_path = rt.read(openvmm_repo_path);
+
+                let mut coverage = BTreeMap::new();
+                for dir in lcov_dirs {
+                    for entry in fs_err::read_dir(dir)? {
+                        let path = entry?.path();
+                        if path.extension().is_some_and(|ext| ext == "lcov") {
+                            log::info!("merging {}", path.display());
+                            let contents = fs_err::read_to_string(&path)?;
+                            parse_lcov(&contents, &openvmm_repo_path, &mut coverage)
+                                .with_context(|| format!("parsing {}", path.display()))?;
+                        }
+                    }
+                }
+
+                let mut crates = BTreeMap::<_, BTreeMap<_, _>>::new();
+                for (path, file) in &coverage {
+                    let Some(name) = crate_name(&openvmm_repo_path, path)? else {
+                        log::warn!("could not find crate for {}", path.display());
+                        continue;
+                    };
+                    crates.entry(name).or_default().insert(path.clone(), file);
+                }
+
+                let coverage_dir = std::env::current_dir()?.join("code_coverage").absolute()?;
+                fs_err::create_dir_all(coverage_dir.join("crates"))?;
+                fs_err::create_dir_all(coverage_dir.join("html").join("crates"))?;
+
+                let sh = xshell::Shell::new()?;
+
+                let info = coverage_dir.join("lcov.info");
+                write_lcov(&info, &openvmm_repo_path, coverage.iter())?;
+                let html_dir = coverage_dir.join("html");
+                xshell::cmd!(
+                    sh,
+                    "genhtml
+                        --quiet
+                        --prefix {openvmm_repo_path}
+                        --title openvmm
+                        --output-directory {html_dir}
+                        {info}
+                    "
+                )
+                .run()?;
+
+                let mut summary = String::new();
+                writeln!(summary, "| Crate | Lines | Covered | Coverage |")?;
+                writeln!(summary, "| ----- | ----: | ------: | -------: |")?;
+
+                let (mut total_lines, mut total_hit) = (0, 0);
+                for (name, files) in &crates {
+                    let info = coverage_dir.join("crates").join(format!("{name}.info"));
+                    write_lcov(
+                        &info,
+                        &openvmm_repo_path,
+                        files.iter().map(|(path, file)| (path, *file)),
+                    )?;
+
+                    let html_dir = coverage_dir.join("html").join("crates").join(name);
+                    xshell::cmd!(
+                        sh,
+                        "genhtml
+                            --quiet
+                            --prefix {openvmm_repo_path}
+                            --title {name}
+                            --output-directory {html_dir}
+                            {info}
+                        "
+                    )
+                    .run()?;
+
+                    let (lines, hit) = files.values().fold((0, 0), |(lines, hit), file| {
+                        (lines + file.lines.len(), hit + file.lines_hit())
+                    });
+                    writeln!(
+                        summary,
+                        "| [{name}](html/crates/{name}/index.html) | {lines} | {hit} | {} |",
+                        percent(hit, lines)
+                    )?;
+
+                    total_lines += lines;
+                    total_hit += hit;
+                }
+
+                writeln!(
+                    summary,
+                    "| **total** | {total_lines} | {total_hit} | {} |",
+                    percent(total_hit, total_lines)
+                )?;
+                fs_err::write(coverage_dir.join("summary.md"), summary)?;
+
+                log::info!("overall line coverage: {}", percent(total_hit, total_lines));
+
+                Ok(coverage_dir)
+            }
+        });
+
+        let files = coverage_dir.map(ctx, |p| vec![("code_coverage".into(), p)]);
+        ctx.req(flowey_lib_common::copy_to_artifact_dir::Request {
+            debug_label: "code coverage report".into(),
+            artifact_dir,
+            files,
+            done,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct FileCoverage {
+    /// Function name -> (line, hits)
+    functions: BTreeMap<String, (u32, u64)>,
+    /// Line -> hits
+    lines: BTreeMap<u32, u64>,
+}
+
+impl FileCoverage {
+    fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|hits| **hits != 0).count()
+    }
+}
+
+/// Parse an lcov report, merging its contents into `coverage`.
+///
+/// Only line and function coverage is retained.
+fn parse_lcov(
+    contents: &str,
+    repo_root: &Path,
+    coverage: &mut BTreeMap<PathBuf, FileCoverage>,
+) -> anyhow::Result<()> {
+    let mut current = None;
+    for line in contents.lines() {
+        let line = line.trim();
+
+        if let Some(path) = line.strip_prefix("SF:") {
+            current = resolve_source_path(repo_root, path);
+            continue;
+        }
+
+        if line == "end_of_record" {
+            current = None;
+            continue;
+        }
+
+        // skip records for source files outside the repo
+        let Some(file) = current
+            .as_ref()
+            .map(|p| coverage.entry(p.clone()).or_default())
+        else {
+            continue;
+        };
+
+        if let Some(rest) = line.strip_prefix("FN:") {
+            let (line_no, name) = rest.split_once(',').context("malformed FN record")?;
+            file.functions.entry(name.into()).or_default().0 = line_no.parse()?;
+        } else if let Some(rest) = line.strip_prefix("FNDA:") {
+            let (hits, name) = rest.split_once(',').context("malformed FNDA record")?;
+            let entry = &mut file.functions.entry(name.into()).or_default().1;
+            *entry = entry.saturating_add(hits.parse()?);
+        } else if let Some(rest) = line.strip_prefix("DA:") {
+            let mut fields = rest.split(',');
+            let line_no = fields.next().context("malformed DA record")?.parse()?;
+            let hits = fields.next().context("malformed DA record")?.parse()?;
+            let entry = file.lines.entry(line_no).or_default();
+            *entry = entry.saturating_add(hits);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reports get generated on different machines (and platforms), so source
+/// paths are resolved relative to the repo root by stripping leading
+/// components until the path exists in the local checkout.
+fn resolve_source_path(repo_root: &Path, path: &str) -> Option<PathBuf> {
+    let path = path.replace('\\', "/");
+
+    // skip coverage of dependencies and the standard library
+    if path.contains("/.cargo/") || path.contains("/rustc/") {
+        return None;
+    }
+
+    let components = path
+        .split('/')
+        .filter(|c| !c.is_empty())
+        .collect::<Vec<_>>();
+    (0..components.len())
+        .map(|i| PathBuf::from(components[i..].join("/")))
+        .find(|rel| repo_root.join(rel).is_file())
+}
+
+/// Find the name of the crate containing the (repo-relative) source file
+/// `path`.
+fn crate_name(repo_root: &Path, path: &Path) -> anyhow::Result<Option<String>> {
+    for dir in path.ancestors().skip(1) {
+        let cargo_toml = repo_root.join(dir).join("Cargo.toml");
+        if !cargo_toml.exists() {
+            continue;
+        }
+
+        let contents = fs_err::read_to_string(cargo_toml)?;
+        let mut in_package = false;
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.starts_with('[') {
+                in_package = line == "[package]";
+                continue;
+            }
+
+            let Some((key, val)) = line.split_once('=') else {
+                continue;
+            };
+            if in_package && key.trim() == "name" {
+                return Ok(Some(val.trim().trim_matches('"').to_owned()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn write_lcov<'a>(
+    out: &Path,
+    repo_root: &Path,
+    files: impl Iterator<Item = (&'a PathBuf, &'a FileCoverage)>,
+) -> anyhow::Result<()> {
+    let mut s = String::new();
+    for (path, file) in files {
+        writeln!(s, "SF:{}", repo_root.join(path).display())?;
+        for (name, (line, _)) in &file.functions {
+            writeln!(s, "FN:{line},{name}")?;
+        }
+        for (name, (_, hits)) in &file.functions {
+            writeln!(s, "FNDA:{hits},{name}")?;
+        }
+        writeln!(s, "FNF:{}", file.functions.len())?;
+        writeln!(
+            s,
+            "FNH:{}",
+            file.functions
+                .values()
+                .filter(|(_, hits)| *hits != 0)
+                .count()
+        )?;
+        for (line, hits) in &file.lines {
+            writeln!(s, "DA:{line},{hits}")?;
+        }
+        writeln!(s, "LF:{}", file.lines.len())?;
+        writeln!(s, "LH:{}", file.lines_hit())?;
+        writeln!(s, "end_of_record")?;
+    }
+
+    fs_err::write(out, s)?;
+    Ok(())
+}
+
+fn percent(hit: usize, total: usize) -> String {
+    if total == 0 {
+        return "-".into();
+    }
+    format!("{:.1}%", hit as f64 * 100.0 / total as f64)
+}
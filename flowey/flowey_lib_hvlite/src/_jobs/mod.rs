@@ -18,6 +18,7 @@
 pub mod build_and_publish_rustdoc;
 pub mod build_and_publish_vmgs_lib;
 pub mod build_and_publish_vmgstool;
+pub mod build_and_run_benchmarks;
 pub mod build_and_run_doc_tests;
 pub mod build_and_run_nextest_unit_tests;
 pub mod build_and_run_nextest_vmm_tests;
@@ -27,6 +28,7 @@
 pub mod cfg_versions;
 pub mod check_clippy;
 pub mod check_xtask_fmt;
+pub mod consolidate_and_publish_code_coverage;
 pub mod consolidate_and_publish_gh_pages;
 pub mod consume_and_test_nextest_unit_tests_archive;
 pub mod consume_and_test_nextest_vmm_tests_archive;
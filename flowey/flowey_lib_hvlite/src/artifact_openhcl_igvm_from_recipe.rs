@@ -14,6 +14,7 @@ pub(crate) fn recipe_to_filename(flavor: &OpenhclIgvmRecipe) -> &str {
         OpenhclIgvmRecipe::X64TestLinuxDirectDevkern => "openhcl-direct-dev",
         OpenhclIgvmRecipe::X64Cvm => "openhcl-cvm",
         OpenhclIgvmRecipe::X64CvmDevkern => "openhcl-cvm-dev",
+        OpenhclIgvmRecipe::X64CvmNoRelay => "openhcl-cvm-norelay",
         OpenhclIgvmRecipe::Aarch64 => "openhcl-aarch64",
         OpenhclIgvmRecipe::Aarch64Devkern => "openhcl-aarch64-dev",
         OpenhclIgvmRecipe::LocalOnlyCustom(_) => unreachable!(),
@@ -29,6 +30,7 @@ pub(crate) fn filename_to_recipe(filename: &str) -> Option<OpenhclIgvmRecipe> {
         "openhcl-direct-dev" => OpenhclIgvmRecipe::X64TestLinuxDirectDevkern,
         "openhcl-cvm" => OpenhclIgvmRecipe::X64Cvm,
         "openhcl-cvm-dev" => OpenhclIgvmRecipe::X64CvmDevkern,
+        "openhcl-cvm-norelay" => OpenhclIgvmRecipe::X64CvmNoRelay,
         "openhcl-aarch64" => OpenhclIgvmRecipe::Aarch64,
         "openhcl-aarch64-dev" => OpenhclIgvmRecipe::Aarch64Devkern,
         _ => return None,
@@ -50,6 +50,9 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                                 fs_err::copy(bin, artifact_dir.join("openvmm"))?;
                                 fs_err::copy(dbg, artifact_dir.join("openvmm.dbg"))?;
                             }
+                            OpenvmmOutput::MacOsBin { bin } => {
+                                fs_err::copy(bin, artifact_dir.join("openvmm"))?;
+                            }
                         }
 
                         Ok(())
@@ -95,11 +98,18 @@ fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Res
                 move |rt| {
                     let artifact_dir = rt.read(artifact_dir);
 
-                    let output = if artifact_dir.join("openvmm").exists() {
+                    let output = if artifact_dir.join("openvmm").exists()
+                        && artifact_dir.join("openvmm.dbg").exists()
+                    {
                         OpenvmmOutput::LinuxBin {
                             bin: artifact_dir.join("openvmm"),
                             dbg: artifact_dir.join("openvmm.dbg"),
                         }
+                    } else if artifact_dir.join("openvmm").exists() {
+                        // macOS builds don't split out debug info
+                        OpenvmmOutput::MacOsBin {
+                            bin: artifact_dir.join("openvmm"),
+                        }
                     } else if artifact_dir.join("openvmm.exe").exists()
                         && artifact_dir.join("openvmm.pdb").exists()
                     {
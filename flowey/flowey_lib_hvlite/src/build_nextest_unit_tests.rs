@@ -65,6 +65,7 @@ fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<crate::install_openvmm_rust_build_essential::Node>();
         ctx.import::<crate::run_cargo_nextest_run::Node>();
         ctx.import::<crate::init_cross_build::Node>();
+        ctx.import::<crate::run_cargo_llvm_cov::Node>();
         ctx.import::<flowey_lib_common::run_cargo_nextest_archive::Node>();
     }
 
@@ -135,6 +136,7 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                     let xtask_bin = match xtask {
                         crate::build_xtask::XtaskOutput::LinuxBin { bin, dbg: _ } => bin,
                         crate::build_xtask::XtaskOutput::WindowsBin { exe, pdb: _ } => exe,
+                        crate::build_xtask::XtaskOutput::MacOsBin { bin } => bin,
                     };
 
                     let sh = xshell::Shell::new()?;
@@ -195,14 +197,14 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
             // CI, and at some point, we should actually improve the testing
             // story on windows, so that we can run with FeatureSet::All in CI.
             //
-            // On windows, we can't run with all features, as many crates
-            // require openSSL for crypto, which isn't supported yet.
+            // On windows (and macOS), we can't run with all features, as many
+            // crates require openSSL for crypto, which isn't supported yet.
             //
             // Adding the the "ci" feature is also used to skip certain tests
             // that fail in CI.
             let features = if matches!(
                 target.operating_system,
-                target_lexicon::OperatingSystem::Windows
+                target_lexicon::OperatingSystem::Windows | target_lexicon::OperatingSystem::Darwin
             ) {
                 FeatureSet::Specific(vec!["ci".into()])
             } else {
@@ -214,6 +216,18 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                 injected_env: v,
             });
 
+            let coverage_env = ctx.reqv(|v| crate::run_cargo_llvm_cov::Request::GetEnv {
+                target: target.clone(),
+                env: v,
+            });
+
+            let injected_env = injected_env
+                .zip(ctx, coverage_env)
+                .map(ctx, move |(mut a, b)| {
+                    a.extend(b);
+                    a
+                });
+
             let build_params =
                 flowey_lib_common::run_cargo_nextest_run::build_params::NextestBuildParams {
                     packages: test_packages.clone(),
@@ -66,6 +66,7 @@ fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<crate::git_checkout_openvmm_repo::Node>();
         ctx.import::<crate::init_openvmm_magicpath_openhcl_sysroot::Node>();
         ctx.import::<crate::init_cross_build::Node>();
+        ctx.import::<crate::run_cargo_llvm_cov::Node>();
         ctx.import::<flowey_lib_common::run_cargo_nextest_archive::Node>();
     }
 
@@ -109,6 +110,18 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                 injected_env: v,
             });
 
+            let coverage_env = ctx.reqv(|v| crate::run_cargo_llvm_cov::Request::GetEnv {
+                target: target.clone(),
+                env: v,
+            });
+
+            let injected_env = injected_env
+                .zip(ctx, coverage_env)
+                .map(ctx, move |(mut a, b)| {
+                    a.extend(b);
+                    a
+                });
+
             let build_params =
                 flowey_lib_common::run_cargo_nextest_run::build_params::NextestBuildParams {
                     packages: ReadVar::from_static(TestPackages::Crates {
@@ -89,6 +89,7 @@ pub enum OpenhclIgvmRecipe {
     X64TestLinuxDirectDevkern,
     X64Cvm,
     X64CvmDevkern,
+    X64CvmNoRelay,
     Aarch64,
     Aarch64Devkern,
 }
@@ -198,6 +199,20 @@ pub fn recipe_details(&self, profile: OpenvmmHclBuildProfile) -> OpenhclIgvmReci
                 with_interactive,
                 with_sidecar_details: false,
             },
+            Self::X64CvmNoRelay => OpenhclIgvmRecipeDetails {
+                local_only: None,
+                igvm_manifest: in_repo_template(
+                    "openhcl-x64-cvm-norelay-dev.json",
+                    "openhcl-x64-cvm-norelay-release.json",
+                ),
+                openhcl_kernel_package: OpenhclKernelPackage::Cvm,
+                openvmm_hcl_features: base_openvmm_hcl_features(),
+                target: CommonTriple::X86_64_LINUX_MUSL,
+                vtl0_kernel_type: None,
+                with_uefi: true,
+                with_interactive,
+                with_sidecar_details: false,
+            },
             Self::Aarch64 => OpenhclIgvmRecipeDetails {
                 local_only: None,
                 igvm_manifest: in_repo_template(
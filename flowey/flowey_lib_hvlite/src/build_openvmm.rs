@@ -26,6 +26,7 @@ pub struct OpenvmmBuildParams {
 pub enum OpenvmmOutput {
     WindowsBin { exe: PathBuf, pdb: PathBuf },
     LinuxBin { bin: PathBuf, dbg: PathBuf },
+    MacOsBin { bin: PathBuf },
 }
 
 flowey_request! {
@@ -131,6 +132,9 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                                 dbg: dbg.unwrap(),
                             }
                         }
+                        crate::run_cargo_build::CargoBuildOutput::MachOBin { bin } => {
+                            OpenvmmOutput::MacOsBin { bin }
+                        }
                         _ => unreachable!(),
                     };
 
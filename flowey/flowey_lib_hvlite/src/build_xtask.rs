@@ -12,6 +12,7 @@
 pub enum XtaskOutput {
     LinuxBin { bin: PathBuf, dbg: PathBuf },
     WindowsBin { exe: PathBuf, pdb: PathBuf },
+    MacOsBin { bin: PathBuf },
 }
 
 flowey_request! {
@@ -60,6 +61,9 @@ fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Res
                             dbg: dbg.unwrap(),
                         }
                     }
+                    crate::run_cargo_build::CargoBuildOutput::MachOBin { bin } => {
+                        XtaskOutput::MacOsBin { bin }
+                    }
                     _ => unreachable!(),
                 };
 
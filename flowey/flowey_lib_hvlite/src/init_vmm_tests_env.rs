@@ -174,7 +174,8 @@ fn process_request(request: Self::Request, ctx: &mut NodeCtx<'_>) -> anyhow::Res
                         crate::build_openvmm::OpenvmmOutput::WindowsBin { exe, pdb: _ } => {
                             fs_err::copy(exe, test_content_dir.join("openvmm.exe"))?;
                         }
-                        crate::build_openvmm::OpenvmmOutput::LinuxBin { bin, dbg: _ } => {
+                        crate::build_openvmm::OpenvmmOutput::LinuxBin { bin, dbg: _ }
+                        | crate::build_openvmm::OpenvmmOutput::MacOsBin { bin } => {
                             let dst = test_content_dir.join("openvmm");
                             fs_err::copy(bin, dst.clone())?;
 
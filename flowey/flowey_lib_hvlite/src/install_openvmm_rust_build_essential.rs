@@ -6,6 +6,7 @@
 //!
 //! Notably - this node installs both the required Rust toolchain, as well as a
 //! protobuf compiler (which is transitively required by most OpenVMM crates).
+//! On macOS, it also ensures the Xcode Command Line Tools are available.
 
 use flowey::node::prelude::*;
 
@@ -22,6 +23,7 @@ fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<crate::init_openvmm_magicpath_protoc::Node>();
         ctx.import::<crate::init_openvmm_cargo_config_deny_warnings::Node>();
         ctx.import::<flowey_lib_common::install_rust::Node>();
+        ctx.import::<flowey_lib_common::install_xcode_command_line_tools::Node>();
     }
 
     fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
@@ -33,6 +35,7 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
             ctx.reqv(crate::init_openvmm_cargo_config_deny_warnings::Request::Done),
             ctx.reqv(crate::init_openvmm_magicpath_protoc::Request),
             ctx.reqv(flowey_lib_common::install_rust::Request::EnsureInstalled),
+            ctx.reqv(flowey_lib_common::install_xcode_command_line_tools::Request),
         ];
 
         ctx.emit_side_effect_step(side_effects, requests.into_iter().map(|x| x.0));
@@ -59,6 +59,7 @@
 pub mod install_git_credential_manager;
 pub mod install_openvmm_rust_build_essential;
 pub mod run_cargo_build;
+pub mod run_cargo_llvm_cov;
 pub mod run_cargo_nextest_run;
 pub mod run_igvmfilegen;
 pub mod run_split_debug_info;
@@ -28,6 +28,9 @@ pub enum CargoBuildOutput {
         bin: PathBuf,
         dbg: Option<PathBuf>,
     },
+    MachOBin {
+        bin: PathBuf,
+    },
     LinuxStaticLib {
         a: PathBuf,
     },
@@ -65,6 +68,7 @@ pub fn from_base_cargo_build_output(
                 Self::WindowsDynamicLib { dll, dll_lib, pdb }
             }
             Base::UefiBin { efi, pdb } => Self::UefiBin { efi, pdb },
+            Base::MachOBin { bin } => Self::MachOBin { bin },
 
             Base::ElfBin { bin } => Self::ElfBin { bin, dbg: elf_dbg },
         }
@@ -305,6 +309,7 @@ fn imports(ctx: &mut ImportCtx<'_>) {
         ctx.import::<crate::init_openvmm_magicpath_openhcl_sysroot::Node>();
         ctx.import::<crate::run_split_debug_info::Node>();
         ctx.import::<crate::init_cross_build::Node>();
+        ctx.import::<crate::run_cargo_llvm_cov::Node>();
         ctx.import::<flowey_lib_common::run_cargo_build::Node>();
     }
 
@@ -361,6 +366,18 @@ fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<(
                 injected_env: v,
             });
 
+            let coverage_env = ctx.reqv(|v| crate::run_cargo_llvm_cov::Request::GetEnv {
+                target: target.clone(),
+                env: v,
+            });
+
+            let injected_env = injected_env
+                .zip(ctx, coverage_env)
+                .map(ctx, move |(mut a, b)| {
+                    a.extend(b);
+                    a
+                });
+
             let extra_env = if let Some(extra_env) = extra_env {
                 extra_env
                     .zip(ctx, injected_env)
@@ -0,0 +1,136 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Collect code coverage in the context of the HvLite repo.
+//!
+//! Coverage is only collected in jobs that request a [`Request::Report`].
+//! Nodes that build and run code in the HvLite repo should route the env vars
+//! returned by [`Request::GetEnv`] into their builds, which will be empty
+//! whenever coverage isn't being collected.
+//!
+//! Uses the generic [`flowey_lib_common::run_cargo_llvm_cov::Node`]
+//! under-the-hood.
+
+use crate::run_cargo_build::common::CommonArch;
+use crate::run_cargo_build::common::CommonPlatform;
+use crate::run_cargo_build::common::CommonProfile;
+use crate::run_cargo_build::common::CommonTriple;
+use flowey::node::prelude::*;
+use flowey_lib_common::run_cargo_build::CargoBuildProfile;
+use std::collections::BTreeMap;
+
+flowey_request! {
+    pub enum Request {
+        /// Get the env vars required to instrument binaries built for `target`.
+        ///
+        /// Only binaries that run natively on the current machine are
+        /// instrumented (i.e: cross-compiled guest binaries are not).
+        GetEnv {
+            target: target_lexicon::Triple,
+            env: WriteVar<BTreeMap<String, String>>,
+        },
+        /// Collect coverage from instrumented binaries built for `target`,
+        /// writing the resulting lcov report into `lcov`.
+        Report {
+            target: target_lexicon::Triple,
+            profile: CommonProfile,
+            /// Wait for specified side-effects to resolve before generating
+            /// the report (e.g: for the instrumented tests to finish running).
+            pre_report_deps: Vec<ReadVar<SideEffect>>,
+            lcov: WriteVar<PathBuf>,
+        },
+    }
+}
+
+new_flow_node!(struct Node);
+
+impl FlowNode for Node {
+    type Request = Request;
+
+    fn imports(ctx: &mut ImportCtx<'_>) {
+        ctx.import::<crate::git_checkout_openvmm_repo::Node>();
+        ctx.import::<flowey_lib_common::run_cargo_llvm_cov::Node>();
+    }
+
+    fn emit(requests: Vec<Self::Request>, ctx: &mut NodeCtx<'_>) -> anyhow::Result<()> {
+        let mut get_env = Vec::new();
+        let mut report = Vec::new();
+
+        for req in requests {
+            match req {
+                Request::GetEnv { target, env } => get_env.push((target, env)),
+                Request::Report {
+                    target,
+                    profile,
+                    pre_report_deps,
+                    lcov,
+                } => report.push((target, profile, pre_report_deps, lcov)),
+            }
+        }
+
+        let get_env = get_env;
+        let report = report;
+
+        // -- end of req processing -- //
+
+        let native_target = CommonTriple::Common {
+            arch: match ctx.arch() {
+                FlowArch::X86_64 => CommonArch::X86_64,
+                FlowArch::Aarch64 => CommonArch::Aarch64,
+                arch => anyhow::bail!("unsupported arch {arch}"),
+            },
+            platform: match ctx.platform() {
+                FlowPlatform::Windows => CommonPlatform::WindowsMsvc,
+                FlowPlatform::Linux(_) => CommonPlatform::LinuxGnu,
+                FlowPlatform::MacOs => CommonPlatform::MacOs,
+                platform => anyhow::bail!("unsupported platform {platform}"),
+            },
+        }
+        .as_triple();
+
+        // avoid spinning up cargo-llvm-cov unless coverage is actually being
+        // collected in this job
+        let coverage_env = if !report.is_empty() {
+            let openvmm_repo_path = ctx.reqv(crate::git_checkout_openvmm_repo::req::GetRepoDir);
+
+            for (target, profile, pre_report_deps, lcov) in report {
+                if target != native_target {
+                    anyhow::bail!("cannot collect coverage from non-native target {target}");
+                }
+
+                ctx.req(flowey_lib_common::run_cargo_llvm_cov::Request::Report(
+                    flowey_lib_common::run_cargo_llvm_cov::Report {
+                        in_folder: openvmm_repo_path.clone(),
+                        target,
+                        profile: match profile {
+                            CommonProfile::Release => CargoBuildProfile::Release,
+                            CommonProfile::Debug => CargoBuildProfile::Debug,
+                        },
+                        pre_report_deps,
+                        lcov,
+                    },
+                ));
+            }
+
+            Some(
+                ctx.reqv(|v| flowey_lib_common::run_cargo_llvm_cov::Request::GetEnv {
+                    in_folder: openvmm_repo_path,
+                    env: v,
+                }),
+            )
+        } else {
+            None
+        };
+
+        for (target, env) in get_env {
+            match &coverage_env {
+                Some(coverage_env) if target == native_target => {
+                    coverage_env.write_into(ctx, env, |env| env)
+                }
+                _ => env.write_static(ctx, BTreeMap::new()),
+            }
+        }
+
+        Ok(())
+    }
+}
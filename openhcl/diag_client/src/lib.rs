@@ -16,6 +16,7 @@
 use futures::AsyncReadExt;
 use futures::AsyncWrite;
 use futures::AsyncWriteExt;
+use guid::Guid;
 use inspect::Node;
 use inspect::ValueKind;
 use inspect_proto::InspectResponse2;
@@ -737,6 +738,92 @@ pub async fn core_dump(
         Ok(())
     }
 
+    /// Profiles VTL2 for `duration`, writing the resulting profile to
+    /// `writer`.
+    ///
+    /// `profiler_args` are passed through to the profiler running in VTL2.
+    pub async fn profile(
+        &self,
+        duration: Duration,
+        profiler_args: Vec<String>,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> anyhow::Result<()> {
+        let (conn, socket) = self.connect_data().await?;
+
+        // The call completes once the profiler exits, after it has finished
+        // writing to (and closed) the data connection.
+        let call = async {
+            self.ttrpc
+                .call()
+                .start(
+                    azure_profiler_proto::AzureProfiler::Profile,
+                    azure_profiler_proto::ProfileRequest {
+                        conn,
+                        duration: duration.as_secs(),
+                        profiler_args,
+                    },
+                )
+                .await
+                .map_err(grpc_status)
+                .context("profiling failed")
+        };
+        let copy = async {
+            futures::io::copy(socket, &mut writer)
+                .await
+                .context("failed to read profile")
+        };
+
+        futures::try_join!(call, copy)?;
+        Ok(())
+    }
+
+    /// Captures the ring buffer traffic of the relayed vmbus channel with
+    /// instance ID `instance_id` (and its subchannels), writing it to `writer`
+    /// in pcapng format.
+    ///
+    /// The capture stops after `duration`, or once `max_bytes` of packet data
+    /// have been captured.
+    pub async fn vmbus_capture(
+        &self,
+        instance_id: Guid,
+        duration: Duration,
+        max_bytes: u64,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> anyhow::Result<VmbusCaptureStats> {
+        let (conn, socket) = self.connect_data().await?;
+
+        // The call completes once the capture has finished, after the data
+        // connection has been closed.
+        let call = async {
+            self.ttrpc
+                .call()
+                .start(
+                    diag_proto::OpenhclDiag::VmbusCapture,
+                    diag_proto::VmbusCaptureRequest {
+                        conn,
+                        instance_id: instance_id.to_string(),
+                        duration_sec: duration.as_secs(),
+                        max_bytes,
+                    },
+                )
+                .await
+                .map_err(grpc_status)
+                .context("vmbus capture failed")
+        };
+        let copy = async {
+            futures::io::copy(socket, &mut writer)
+                .await
+                .context("failed to read capture")
+        };
+
+        let (response, _) = futures::try_join!(call, copy)?;
+        Ok(VmbusCaptureStats {
+            packets: response.packets,
+            bytes: response.bytes,
+            resyncs: response.resyncs,
+        })
+    }
+
     /// Restarts the Underhill worker.
     pub async fn restart(&self) -> anyhow::Result<()> {
         self.ttrpc
@@ -963,6 +1050,17 @@ pub async fn wait(self) -> anyhow::Result<ExitStatus> {
     }
 }
 
+/// Statistics for a completed vmbus capture.
+#[derive(Debug)]
+pub struct VmbusCaptureStats {
+    /// The number of packets captured.
+    pub packets: u64,
+    /// The number of packet bytes captured.
+    pub bytes: u64,
+    /// The number of times the capture fell behind and lost packets.
+    pub resyncs: u64,
+}
+
 /// Process exit status.
 #[derive(Debug)]
 pub struct ExitStatus {
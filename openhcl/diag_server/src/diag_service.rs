@@ -18,6 +18,8 @@
 use diag_proto::OpenhclDiag;
 use diag_proto::StartRequest;
 use diag_proto::UnderhillDiag;
+use diag_proto::VmbusCaptureRequest;
+use diag_proto::VmbusCaptureResponse;
 use diag_proto::WaitRequest;
 use diag_proto::WaitResponse;
 use diag_proto::FILE_LINE_MAX;
@@ -30,6 +32,7 @@
 use futures::FutureExt;
 use futures::StreamExt;
 use futures_concurrency::stream::Merge;
+use guid::Guid;
 use inspect::InspectionBuilder;
 use inspect_proto::InspectRequest;
 use inspect_proto::InspectResponse2;
@@ -87,11 +90,38 @@ pub enum DiagRequest {
     Save(FailableRpc<(), Vec<u8>>),
     /// Setup network trace
     PacketCapture(FailableRpc<PacketCaptureParams<Socket>, PacketCaptureParams<Socket>>),
+    /// Capture the ring buffer traffic of a relayed vmbus channel
+    VmbusCapture(FailableRpc<VmbusCaptureParams, VmbusCaptureResult>),
     /// Profile VTL2
     #[cfg(feature = "profiler")]
     Profile(FailableRpc<profiler_worker::ProfilerRequest, ()>),
 }
 
+/// Parameters for capturing the traffic of a relayed vmbus channel.
+#[derive(Debug, mesh::MeshPayload)]
+pub struct VmbusCaptureParams {
+    /// The instance ID of the channel. All of the channel's open subchannels
+    /// are captured too.
+    pub instance_id: Guid,
+    /// How long to capture for.
+    pub duration: std::time::Duration,
+    /// The maximum number of packet bytes to capture.
+    pub max_bytes: u64,
+    /// The connection to write the capture to, in pcapng format.
+    pub conn: Socket,
+}
+
+/// The result of a vmbus capture.
+#[derive(Debug, mesh::MeshPayload)]
+pub struct VmbusCaptureResult {
+    /// The number of packets captured.
+    pub packets: u64,
+    /// The number of packet bytes captured.
+    pub bytes: u64,
+    /// The number of times the capture fell behind and lost packets.
+    pub resyncs: u64,
+}
+
 /// Additional parameters provided as part of a delayed start request.
 #[derive(Debug, mesh::MeshPayload)]
 pub struct StartParams {
@@ -254,12 +284,16 @@ async fn handle_diag2_request(
         &self,
         _driver: &(impl Driver + Spawn + Clone),
         req: OpenhclDiag,
-        _ctx: CancelContext,
+        mut ctx: CancelContext,
     ) {
         match req {
             OpenhclDiag::Ping((), response) => {
                 response.send(Ok(()));
             }
+            OpenhclDiag::VmbusCapture(request, response) => response.send(grpc_result(
+                ctx.until_cancelled(self.handle_vmbus_capture(request))
+                    .await,
+            )),
         }
     }
 
@@ -641,6 +675,32 @@ async fn handle_packet_capture(
         Ok(NetworkPacketCaptureResponse { num_streams })
     }
 
+    async fn handle_vmbus_capture(
+        &self,
+        request: VmbusCaptureRequest,
+    ) -> anyhow::Result<VmbusCaptureResponse> {
+        let instance_id = request
+            .instance_id
+            .parse()
+            .context("invalid channel instance ID")?;
+        let conn = self.take_connection(request.conn).await?;
+        let params = VmbusCaptureParams {
+            instance_id,
+            duration: std::time::Duration::from_secs(request.duration_sec),
+            max_bytes: request.max_bytes,
+            conn: conn.into_inner(),
+        };
+        let result = self
+            .request_send
+            .call_failable(DiagRequest::VmbusCapture, params)
+            .await?;
+        Ok(VmbusCaptureResponse {
+            packets: result.packets,
+            bytes: result.bytes,
+            resyncs: result.resyncs,
+        })
+    }
+
     async fn handle_profile(&self, request: ProfileRequest) -> anyhow::Result<()> {
         let conn = self.take_connection(request.conn).await?;
         #[cfg(feature = "profiler")]
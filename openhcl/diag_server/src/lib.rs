@@ -11,6 +11,8 @@
 
 pub use diag_service::DiagRequest;
 pub use diag_service::StartParams;
+pub use diag_service::VmbusCaptureParams;
+pub use diag_service::VmbusCaptureResult;
 
 use anyhow::Context;
 use futures::AsyncWriteExt;
@@ -142,6 +142,12 @@ enum Command {
         /// Write verbose information about the connection state.
         #[clap(short, long)]
         verbose: bool,
+        /// Only write messages at this severity or higher.
+        #[clap(short, long)]
+        level: Option<KmsgLevel>,
+        /// Only write messages matching this regular expression.
+        #[clap(short, long)]
+        grep: Option<regex::Regex>,
         /// Read kmsg from the VM's serial port.
         ///
         /// This only works on Hyper-V.
@@ -243,6 +249,21 @@ enum Command {
         #[clap(short)]
         output: Option<PathBuf>,
     },
+    /// Samples CPU usage in VTL2 for a period of time, and writes the
+    /// resulting profile.
+    ///
+    /// OpenHCL must have been built with the `profiler` feature.
+    Profile {
+        /// How long to profile for, in seconds.
+        #[clap(short, long, default_value = "10", value_parser = |arg: &str| -> Result<Duration, std::num::ParseIntError> {Ok(Duration::from_secs(arg.parse()?))})]
+        duration: Duration,
+        /// The output file. Defaults to stdout.
+        #[clap(short)]
+        output: Option<PathBuf>,
+        /// Extra arguments to pass to the profiler.
+        #[clap(last = true)]
+        profiler_args: Vec<String>,
+    },
     /// Sets up a relay between a virtual socket and a TCP client on the host.
     VsockTcpRelay {
         vsock_port: u32,
@@ -279,6 +300,25 @@ enum Command {
         #[clap(short('s'), long, default_value = "65535", value_parser = clap::value_parser!(u16).range(1..))]
         snaplen: u16,
     },
+    /// Captures the ring buffer traffic of a relayed vmbus channel, and all of
+    /// its subchannels, in pcapng format.
+    ///
+    /// VTL2 is not in the data path of relayed channels, so the rings are
+    /// sampled periodically, and packets may be lost when the rings are busy.
+    /// The number of times this happened is reported at the end of the capture.
+    VmbusCapture {
+        /// The instance ID of the channel.
+        instance_id: guid::Guid,
+        /// How long to capture for, in seconds.
+        #[clap(short, long, default_value = "10", value_parser = |arg: &str| -> Result<Duration, std::num::ParseIntError> {Ok(Duration::from_secs(arg.parse()?))})]
+        duration: Duration,
+        /// The maximum number of packet bytes to capture.
+        #[clap(long, default_value = "67108864")]
+        max_bytes: u64,
+        /// The output file. Defaults to stdout.
+        #[clap(short)]
+        output: Option<PathBuf>,
+    },
 }
 
 #[derive(Debug, Clone, Args)]
@@ -346,6 +386,32 @@ enum CrashType {
     UhPanic,
 }
 
+/// Kernel log levels, from most to least severe.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum KmsgLevel {
+    Emerg,
+    Alert,
+    Crit,
+    Err,
+    Warn,
+    Notice,
+    Info,
+    Debug,
+}
+
+/// Filters applied to kmsg entries before they are written.
+struct KmsgFilter {
+    level: Option<KmsgLevel>,
+    grep: Option<regex::Regex>,
+}
+
+impl KmsgFilter {
+    fn matches(&self, level: u8, message: &str) -> bool {
+        self.level.is_none_or(|max| level <= max as u8)
+            && self.grep.as_ref().is_none_or(|re| re.is_match(message))
+    }
+}
+
 #[derive(Debug, Error)]
 #[error("bad environment variable, expected VAR=value")]
 struct BadEnvString;
@@ -569,12 +635,15 @@ pub fn main() -> anyhow::Result<()> {
                 follow,
                 reconnect,
                 verbose,
+                level,
+                grep,
                 #[cfg(windows)]
                 serial,
                 #[cfg(windows)]
                 pipe_path,
             } => {
                 let is_terminal = std::io::stdout().is_terminal();
+                let filter = KmsgFilter { level, grep };
 
                 #[cfg(windows)]
                 if serial {
@@ -603,8 +672,12 @@ pub fn main() -> anyhow::Result<()> {
                     while let Some(line) = lines.next().await {
                         let line = line?;
                         if let Some(message) = kmsg::SyslogParsedEntry::new(&line) {
-                            println!("{}", message.display(is_terminal));
-                        } else {
+                            if filter.matches(message.level, message.message) {
+                                println!("{}", message.display(is_terminal));
+                            }
+                        } else if filter.level.is_none() && filter.matches(0, &line) {
+                            // Not a kernel message, so it has no level to
+                            // filter on.
                             println!("{line}");
                         }
                     }
@@ -630,7 +703,9 @@ pub fn main() -> anyhow::Result<()> {
                         match data {
                             Ok(data) => {
                                 let message = kmsg::KmsgParsedEntry::new(&data)?;
-                                println!("{}", message.display(is_terminal));
+                                if filter.matches(message.level, &message.message.to_string()) {
+                                    println!("{}", message.display(is_terminal));
+                                }
                             }
                             Err(err) if reconnect && err.kind() == ErrorKind::ConnectionReset => {
                                 if verbose {
@@ -753,6 +828,25 @@ pub fn main() -> anyhow::Result<()> {
                     .collect::<Result<Vec<_>, std::io::Error>>()?;
                 capture_packets(client, streams, seconds).await;
             }
+            Command::VmbusCapture {
+                instance_id,
+                duration,
+                max_bytes,
+                output,
+            } => {
+                ensure_not_terminal(&output)?;
+
+                let client = new_client(driver.clone(), &vm)?;
+                let file = create_or_stderr(&output)?;
+                eprintln!("Capturing for {}s", duration.as_secs());
+                let stats = client
+                    .vmbus_capture(instance_id, duration, max_bytes, AllowStdIo::new(file))
+                    .await?;
+                eprintln!(
+                    "Captured {} packets ({} bytes), fell behind {} times",
+                    stats.packets, stats.bytes, stats.resyncs
+                );
+            }
             Command::CoreDump {
                 verbose,
                 pid,
@@ -802,6 +896,20 @@ pub fn main() -> anyhow::Result<()> {
                     .await
                     .context("failed to copy trace file")?;
             }
+            Command::Profile {
+                duration,
+                output,
+                profiler_args,
+            } => {
+                ensure_not_terminal(&output)?;
+
+                let client = new_client(driver.clone(), &vm)?;
+                let file = create_or_stderr(&output)?;
+                eprintln!("Profiling for {}s", duration.as_secs());
+                client
+                    .profile(duration, profiler_args, AllowStdIo::new(file))
+                    .await?;
+            }
             Command::VsockTcpRelay {
                 vsock_port,
                 tcp_port,
@@ -107,6 +107,13 @@ pub struct ShimParams {
     /// Memory used by the shim.
     pub used: MemoryRange,
     pub bounce_buffer: Option<MemoryRange>,
+    /// The deflate compressed kernel base address.
+    pub compressed_kernel_base: u64,
+    /// The size of the compressed kernel, in bytes. This is 0 if the kernel
+    /// is not compressed.
+    pub compressed_kernel_size: u64,
+    /// The range the compressed kernel is decompressed into.
+    pub kernel_image: MemoryRange,
 }
 
 impl ShimParams {
@@ -133,6 +140,10 @@ pub fn new(shim_base_address: u64, raw: &ShimParamsRaw) -> Self {
             used_end,
             bounce_buffer_start,
             bounce_buffer_size,
+            compressed_kernel_offset,
+            compressed_kernel_size,
+            kernel_image_offset,
+            kernel_image_size,
         } = raw;
 
         let isolation_type = get_isolation_type(supported_isolation_type);
@@ -166,6 +177,12 @@ pub fn new(shim_base_address: u64, raw: &ShimParamsRaw) -> Self {
                     ..shim_base_address.wrapping_add_signed(used_end),
             ),
             bounce_buffer,
+            compressed_kernel_base: shim_base_address.wrapping_add_signed(compressed_kernel_offset),
+            compressed_kernel_size,
+            kernel_image: {
+                let base = shim_base_address.wrapping_add_signed(kernel_image_offset);
+                MemoryRange::new(base..base + kernel_image_size)
+            },
         }
     }
 
@@ -203,6 +220,23 @@ pub fn initrd(&self) -> &'static [u8] {
         unsafe { slice::from_raw_parts(self.initrd_base as *const u8, self.initrd_size as usize) }
     }
 
+    /// Get the compressed kernel as a byte slice, if the kernel was compressed
+    /// at file build time.
+    pub fn compressed_kernel(&self) -> Option<&'static [u8]> {
+        if self.compressed_kernel_size == 0 {
+            return None;
+        }
+        // SAFETY: The compressed kernel base and size are set at file build
+        // time, and the host must relocate the whole region if relocations
+        // are performed.
+        Some(unsafe {
+            slice::from_raw_parts(
+                self.compressed_kernel_base as *const u8,
+                self.compressed_kernel_size as usize,
+            )
+        })
+    }
+
     /// Get the [`ParavisorCommandLine`] structure that describes the command
     /// line information.
     pub fn command_line(&self) -> &'static ParavisorCommandLine {
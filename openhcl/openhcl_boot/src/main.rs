@@ -518,6 +518,47 @@ const fn zeroed<T: FromZeroes>() -> T {
     unsafe { core::mem::MaybeUninit::<T>::zeroed().assume_init() }
 }
 
+/// Decompresses the kernel to its load address, if it was compressed at file
+/// build time.
+///
+/// This must run after VTL2 memory has been accepted and the imported regions
+/// have been verified, since the kernel image range is not imported and the
+/// compressed kernel may be.
+fn decompress_kernel(p: &ShimParams) {
+    use miniz_oxide::inflate::core::decompress;
+    use miniz_oxide::inflate::core::inflate_flags::TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF;
+    use miniz_oxide::inflate::core::DecompressorOxide;
+    use miniz_oxide::inflate::TINFLStatus;
+
+    let Some(compressed) = p.compressed_kernel() else {
+        return;
+    };
+
+    // SAFETY: The kernel image range is set at file build time, is part of
+    // VTL2 memory that has been accepted, and is not used by anything else
+    // before the kernel is entered.
+    let image = unsafe {
+        core::slice::from_raw_parts_mut(
+            p.kernel_image.start() as *mut u8,
+            p.kernel_image.len() as usize,
+        )
+    };
+
+    // The decompressor state is too large to comfortably keep on the stack.
+    let mut decompressor = off_stack!(Option<DecompressorOxide>, None);
+    let decompressor = decompressor.insert(DecompressorOxide::new());
+    let (status, read, written) = decompress(
+        decompressor,
+        compressed,
+        image,
+        0,
+        TINFL_FLAG_USING_NON_WRAPPING_OUTPUT_BUF,
+    );
+    if status != TINFLStatus::Done || read != compressed.len() || written != image.len() {
+        panic!("failed to decompress kernel: {status:?}, read {read}, wrote {written}");
+    }
+}
+
 fn shim_main(shim_params_raw_offset: isize) -> ! {
     let p = shim_parameters(shim_params_raw_offset);
 
@@ -610,6 +651,7 @@ fn shim_main(shim_params_raw_offset: isize) -> ! {
     setup_vtl2_vp(partition_info);
     setup_vtl2_memory(&p, partition_info);
     verify_imported_regions_hash(&p);
+    decompress_kernel(&p);
 
     let mut sidecar_params = off_stack!(PageAlign<SidecarParams>, zeroed());
     let mut sidecar_output = off_stack!(PageAlign<SidecarOutput>, zeroed());
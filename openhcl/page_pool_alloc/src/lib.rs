@@ -18,6 +18,7 @@
 use hvdef::HV_PAGE_SIZE;
 use inspect::Inspect;
 use parking_lot::Mutex;
+use std::collections::BTreeMap;
 use std::num::NonZeroU64;
 use std::sync::Arc;
 use thiserror::Error;
@@ -77,6 +78,24 @@ fn name(&self) -> &str {
     }
 }
 
+/// Usage statistics for a [`PagePool`].
+#[derive(Debug, Default, Inspect)]
+pub struct PagePoolStats {
+    /// The total number of pages in the pool.
+    pub total_pages: u64,
+    /// The number of free pages.
+    pub free_pages: u64,
+    /// The number of free ranges. Freed allocations are not coalesced with
+    /// their neighbors, so this grows as the pool fragments.
+    pub free_ranges: u64,
+    /// The size of the largest free range, which is the largest allocation
+    /// that can currently succeed.
+    pub largest_free_range_pages: u64,
+    /// The number of allocated pages, by device name.
+    #[inspect(iter_by_key)]
+    pub allocated_pages: BTreeMap<String, u64>,
+}
+
 #[derive(Debug)]
 struct PagePoolInner {
     /// The internal state of the pool.
@@ -86,11 +105,40 @@ struct PagePoolInner {
     device_ids: Vec<DeviceId>,
 }
 
+impl PagePoolInner {
+    fn stats(&self) -> PagePoolStats {
+        let mut stats = PagePoolStats::default();
+        for state in &self.state {
+            match *state {
+                State::Free { size_pages, .. } => {
+                    stats.total_pages += size_pages;
+                    stats.free_pages += size_pages;
+                    stats.free_ranges += 1;
+                    stats.largest_free_range_pages = stats.largest_free_range_pages.max(size_pages);
+                }
+                State::Allocated {
+                    size_pages,
+                    device_id,
+                    ..
+                } => {
+                    stats.total_pages += size_pages;
+                    *stats
+                        .allocated_pages
+                        .entry(self.device_ids[device_id].name().to_owned())
+                        .or_default() += size_pages;
+                }
+            }
+        }
+        stats
+    }
+}
+
 // Manually implement inspect so device_ids can be rendered as strings, not
 // their actual usize index.
 impl Inspect for PagePoolInner {
     fn inspect(&self, req: inspect::Request<'_>) {
         req.respond()
+            .field("stats", self.stats())
             .field("device_ids", inspect::iter_by_index(&self.device_ids))
             .child("state", |req| {
                 let mut resp = req.respond();
@@ -269,6 +317,11 @@ pub fn allocator_spawner(&self) -> PagePoolAllocatorSpawner {
         }
     }
 
+    /// Returns the current usage statistics for the pool.
+    pub fn stats(&self) -> PagePoolStats {
+        self.inner.lock().stats()
+    }
+
     // TODO: save method and restore
 }
 
@@ -291,6 +344,11 @@ impl PagePoolAllocatorSpawner {
     pub fn allocator(&self, device_name: String) -> anyhow::Result<PagePoolAllocator> {
         PagePoolAllocator::new(&self.inner, self.typ, device_name)
     }
+
+    /// Returns the current usage statistics for the pool.
+    pub fn stats(&self) -> PagePoolStats {
+        self.inner.lock().stats()
+    }
 }
 
 /// A page allocator for memory.
@@ -523,6 +581,43 @@ fn test_basic_alloc() {
         assert_eq!(inner.state.len(), 2);
     }
 
+    #[test]
+    fn test_stats() {
+        let pool = PagePool::new_shared_visibility_pool(
+            &[MemoryRangeWithNode {
+                range: MemoryRange::from_4k_gpn_range(10..40),
+                vnode: 0,
+            }],
+            0,
+        )
+        .unwrap();
+        let alloc = pool.allocator("test".into()).unwrap();
+        let alloc2 = pool.allocator("test2".into()).unwrap();
+
+        let a1 = alloc.alloc(5.try_into().unwrap(), "alloc1".into()).unwrap();
+        let _a2 = alloc.alloc(5.try_into().unwrap(), "alloc2".into()).unwrap();
+        let _a3 = alloc2
+            .alloc(10.try_into().unwrap(), "alloc3".into())
+            .unwrap();
+
+        let stats = pool.stats();
+        assert_eq!(stats.total_pages, 30);
+        assert_eq!(stats.free_pages, 10);
+        assert_eq!(stats.free_ranges, 1);
+        assert_eq!(stats.largest_free_range_pages, 10);
+        assert_eq!(stats.allocated_pages["test"], 10);
+        assert_eq!(stats.allocated_pages["test2"], 10);
+
+        drop(a1);
+
+        let stats = pool.allocator_spawner().stats();
+        assert_eq!(stats.total_pages, 30);
+        assert_eq!(stats.free_pages, 15);
+        assert_eq!(stats.free_ranges, 2);
+        assert_eq!(stats.largest_free_range_pages, 10);
+        assert_eq!(stats.allocated_pages["test"], 5);
+    }
+
     #[test]
     fn test_duplicate_device_name() {
         let pool = PagePool::new_shared_visibility_pool(
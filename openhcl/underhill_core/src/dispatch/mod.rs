@@ -9,6 +9,7 @@
 use self::vtl2_settings_worker::DeviceInterfaces;
 use crate::emuplat::netvsp::RuntimeSavedState;
 use crate::emuplat::EmuplatServicing;
+use crate::liveness::LivenessTicker;
 use crate::nvme_manager::NvmeManager;
 use crate::reference_time::ReferenceTime;
 use crate::servicing;
@@ -19,6 +20,8 @@
 use crate::ControlRequest;
 use anyhow::Context;
 use async_trait::async_trait;
+use diag_server::VmbusCaptureParams;
+use diag_server::VmbusCaptureResult;
 use futures::FutureExt;
 use futures::StreamExt;
 use futures_concurrency::future::Join;
@@ -43,6 +46,7 @@
 use page_pool_alloc::PagePool;
 use pal_async::task::Spawn;
 use pal_async::task::Task;
+use pal_async::DefaultPool;
 use parking_lot::Mutex;
 use socket2::Socket;
 use state_unit::SavedStateUnit;
@@ -77,6 +81,7 @@ pub enum UhVmRpc {
     Save(FailableRpc<(), Vec<u8>>),
     ClearHalt(Rpc<(), bool>), // TODO: remove this, and use DebugRequest::Resume
     PacketCapture(FailableRpc<PacketCaptureParams<Socket>, PacketCaptureParams<Socket>>),
+    VmbusCapture(FailableRpc<VmbusCaptureParams, VmbusCaptureResult>),
 }
 
 #[async_trait]
@@ -195,6 +200,7 @@ pub async fn run<T: MeshPayload>(
         correlation_id: Option<Guid>,
         mut vm_rpc: mesh::Receiver<UhVmRpc>,
         mut worker_rpc: mesh::Receiver<WorkerRpc<T>>,
+        mut liveness: LivenessTicker,
     ) -> Option<LoadedVmState<T>> {
         if autostart_vps {
             self.start(correlation_id).await;
@@ -238,6 +244,7 @@ enum Event<T> {
                 VtlCrash(VtlCrash),
                 ServicingRequest(GuestSaveRequest),
                 ShutdownRequest(Rpc<ShutdownParams, ShutdownResult>),
+                LivenessTick,
             }
 
             let event: Event<T> = futures::select! { // merge semantics
@@ -253,6 +260,7 @@ enum Event<T> {
                     let (recv, _) = self.shutdown_relay.as_mut().unwrap();
                     recv.select_next_some().await
                 }.fuse() => Event::ShutdownRequest(message),
+                _ = liveness.tick().fuse() => Event::LivenessTick,
             };
 
             match event {
@@ -345,6 +353,7 @@ enum Event<T> {
                         })
                         .await
                     }
+                    UhVmRpc::VmbusCapture(rpc) => self.handle_vmbus_capture(rpc).await,
                 },
                 Event::ServicingRequest(message) => {
                     // Explicitly destructure the message for easier tracking of its changes.
@@ -377,6 +386,18 @@ enum Event<T> {
                 Event::ShutdownRequest(rpc) => {
                     rpc.handle(|msg| async {
                         if matches!(msg.shutdown_type, ShutdownType::Hibernate) {
+                            // Don't prepare for hibernate if the guest has
+                            // already reported that it can't.
+                            let (_, send_guest) =
+                                self.shutdown_relay.as_ref().expect("active shutdown_relay");
+                            if let Ok(Some(capabilities)) =
+                                send_guest.call(ShutdownRpc::Capabilities, ()).await
+                            {
+                                if !capabilities.hibernate {
+                                    tracing::warn!("guest does not support hibernate");
+                                    return ShutdownResult::NotSupported;
+                                }
+                            }
                             self.handle_hibernate_request(false).await;
                         }
                         let (_, send_guest) =
@@ -401,6 +422,7 @@ enum Event<T> {
                     .await
                 }
                 Event::VtlCrash(vtl_crash) => self.notify_of_vtl_crash(vtl_crash),
+                Event::LivenessTick => {}
             }
         };
 
@@ -571,6 +593,59 @@ async fn handle_hibernate_request(&self, rollback: bool) {
         }
     }
 
+    /// Starts capturing the ring traffic of a relayed vmbus channel, completing
+    /// `rpc` when the capture finishes.
+    async fn handle_vmbus_capture(&self, rpc: FailableRpc<VmbusCaptureParams, VmbusCaptureResult>) {
+        let Rpc(params, response) = rpc;
+        let Some(host_vmbus_relay) = &self.host_vmbus_relay else {
+            response.send(Err(RemoteError::new(anyhow::anyhow!(
+                "vmbus relay is not enabled"
+            ))));
+            return;
+        };
+        let rings = match host_vmbus_relay.capture_rings(params.instance_id).await {
+            Ok(rings) => rings,
+            Err(err) => {
+                response.send(Err(RemoteError::new(err)));
+                return;
+            }
+        };
+
+        // Capture on a separate thread, since writes to the connection block
+        // while the client is behind.
+        std::thread::Builder::new()
+            .name("vmbus capture".into())
+            .spawn(move || {
+                let result = DefaultPool::run_with(|driver| async move {
+                    params.conn.set_nonblocking(false)?;
+                    let stats = vmbus_relay::capture_rings(
+                        &driver,
+                        rings,
+                        &params.conn,
+                        vmbus_relay::CaptureLimits {
+                            duration: params.duration,
+                            max_bytes: params.max_bytes,
+                        },
+                    )
+                    .await?;
+                    tracing::info!(
+                        instance_id = %params.instance_id,
+                        packets = stats.packets,
+                        bytes = stats.bytes,
+                        resyncs = stats.resyncs,
+                        "vmbus capture complete"
+                    );
+                    anyhow::Ok(VmbusCaptureResult {
+                        packets: stats.packets,
+                        bytes: stats.bytes,
+                        resyncs: stats.resyncs,
+                    })
+                });
+                response.send(result.map_err(RemoteError::new));
+            })
+            .unwrap();
+    }
+
     async fn start(&mut self, correlation_id: Option<Guid>) {
         self.state_units.start().await;
 
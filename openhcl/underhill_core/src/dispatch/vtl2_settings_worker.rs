@@ -10,6 +10,7 @@
 use disk_backend::resolve::ResolveDiskParameters;
 use disk_backend::Disk;
 use disk_backend_resources::AutoFormattedDiskHandle;
+use disk_backend_resources::WriteCacheMode;
 use disk_blockdevice::OpenBlockDeviceConfig;
 use futures::StreamExt;
 use guest_emulation_transport::api::platform_settings::DevicePlatformSettings;
@@ -23,6 +24,7 @@
 use mesh::CancelContext;
 use nvme_resources::NamespaceDefinition;
 use nvme_resources::NvmeControllerHandle;
+use nvme_resources::NvmeControllerRequest;
 use scsidisk_resources::SimpleScsiDiskHandle;
 use scsidisk_resources::SimpleScsiDvdHandle;
 use scsidisk_resources::SimpleScsiDvdRequest;
@@ -57,6 +59,7 @@
 use vm_resource::ResolveError;
 use vm_resource::Resource;
 use vm_resource::ResourceResolver;
+use vmcore::vm_task::VmTaskDriverSource;
 
 #[derive(Error, Debug)]
 enum Error<'a> {
@@ -89,6 +92,8 @@ enum Error<'a> {
     },
     #[error("no SCSI controller {0}")]
     StorageScsiControllerNotFound(Guid),
+    #[error("no NVMe controller {0}")]
+    StorageNvmeControllerNotFound(Guid),
     #[error("failed to add disk")]
     StorageScsiPathInUse(#[source] anyhow::Error),
     #[error("failed to add disk at lun {0}")]
@@ -99,6 +104,10 @@ enum Error<'a> {
     StorageRemoveDiskFailed(u8, #[source] anyhow::Error),
     #[error("failed to change media at lun {0}")]
     StorageChangeMediaFailed(u8, #[source] anyhow::Error),
+    #[error("failed to add namespace {0}")]
+    StorageAddNamespaceFailed(u32, #[source] anyhow::Error),
+    #[error("failed to remove namespace {0}")]
+    StorageRemoveNamespaceFailed(u32, #[source] anyhow::Error),
     #[error("failed to modify networking instance {0}")]
     NetworkingModifyNicFailed(Guid, #[source] anyhow::Error),
     #[error("failed to add network interface {0}")]
@@ -155,6 +164,7 @@ fn code(&self) -> Vtl2SettingsErrorCode {
             Error::StorageScsiControllerNotFound(_) => {
                 Vtl2SettingsErrorCode::StorageScsiControllerNotFound
             }
+            Error::StorageNvmeControllerNotFound(_) => Vtl2SettingsErrorCode::InternalFailure,
             Error::StorageScsiPathInUse(_) => Vtl2SettingsErrorCode::StorageAttachDiskFailed,
             Error::StorageAttachDiskFailed(..) => Vtl2SettingsErrorCode::StorageAttachDiskFailed,
             Error::StorageScsiPathNotInUse(_) => Vtl2SettingsErrorCode::StorageRmDiskFailed,
@@ -178,6 +188,8 @@ fn code(&self) -> Vtl2SettingsErrorCode {
             Error::StorageChangeMediaFailed { .. } => {
                 Vtl2SettingsErrorCode::StorageChangeMediaFailed
             }
+            Error::StorageAddNamespaceFailed(..) => Vtl2SettingsErrorCode::StorageAttachDiskFailed,
+            Error::StorageRemoveNamespaceFailed(..) => Vtl2SettingsErrorCode::StorageRmDiskFailed,
         }
     }
 }
@@ -208,6 +220,8 @@ pub enum Vtl2ConfigAcquireResource {
     AddDisk(Guid, underhill_config::ScsiDisk),
     RmDisk(Guid, underhill_config::ScsiDisk),
     ChangeMedia(Guid, StorageDisk),
+    AddNamespace(Guid, underhill_config::NvmeNamespace),
+    RmNamespace(Guid, u32),
     ModifyNic((Guid, Option<Guid>)),
     AddNic((Guid, Option<Guid>, Option<u16>)),
     RemoveNic(Guid),
@@ -221,6 +235,8 @@ pub enum Vtl2ConfigCommit {
     ),
     RmDisk(Guid, storvsp_resources::ScsiPath),
     ChangeMedia(Guid, StorageDevicePath, Option<Resource<DiskHandleKind>>),
+    AddNamespace(Guid, NamespaceDefinition),
+    RmNamespace(Guid, u32),
     ModifyNic((Guid, Option<Guid>)),
     AddNic((Guid, Option<Guid>, Option<u16>)),
     RemoveNic(Guid),
@@ -237,6 +253,7 @@ pub struct Vtl2SettingsWorker {
 pub struct DeviceInterfaces {
     scsi_dvds: HashMap<StorageDevicePath, mesh::Sender<SimpleScsiDvdRequest>>,
     scsi_request: HashMap<Guid, mesh::Sender<ScsiControllerRequest>>,
+    nvme_request: HashMap<Guid, mesh::Sender<NvmeControllerRequest>>,
     use_nvme_vfio: bool,
 }
 
@@ -392,6 +409,22 @@ async fn acquire_configuration_resources(
                     .await?;
                     to_commits.push(Vtl2ConfigCommit::ChangeMedia(guid, path, disk_type));
                 }
+                Vtl2ConfigAcquireResource::AddNamespace(guid, namespace) => {
+                    let namespace = make_nvme_disk_config(
+                        ctx,
+                        &StorageContext {
+                            uevent_listener,
+                            use_nvme_vfio: self.interfaces.use_nvme_vfio,
+                        },
+                        &namespace,
+                        false,
+                    )
+                    .await?;
+                    to_commits.push(Vtl2ConfigCommit::AddNamespace(guid, namespace));
+                }
+                Vtl2ConfigAcquireResource::RmNamespace(guid, nsid) => {
+                    to_commits.push(Vtl2ConfigCommit::RmNamespace(guid, nsid));
+                }
                 Vtl2ConfigAcquireResource::ModifyNic(nic_settings) => {
                     to_commits.push(Vtl2ConfigCommit::ModifyNic(nic_settings));
                 }
@@ -478,6 +511,25 @@ async fn commit_configuration_changes(
                     .await
                     .map_err(|e| Error::StorageChangeMediaFailed(lun, e))?;
                 }
+                Vtl2ConfigCommit::AddNamespace(controller_id, namespace) => {
+                    let nsid = namespace.nsid;
+                    self.interfaces
+                        .nvme_request
+                        .get(&controller_id)
+                        .ok_or(Error::StorageNvmeControllerNotFound(controller_id))?
+                        .call_failable(NvmeControllerRequest::AddNamespace, namespace)
+                        .await
+                        .map_err(|err| Error::StorageAddNamespaceFailed(nsid, err.into()))?;
+                }
+                Vtl2ConfigCommit::RmNamespace(controller_id, nsid) => {
+                    self.interfaces
+                        .nvme_request
+                        .get(&controller_id)
+                        .ok_or(Error::StorageNvmeControllerNotFound(controller_id))?
+                        .call_failable(NvmeControllerRequest::RemoveNamespace, nsid)
+                        .await
+                        .map_err(|err| Error::StorageRemoveNamespaceFailed(nsid, err.into()))?;
+                }
                 Vtl2ConfigCommit::ModifyNic(nic_settings) => {
                     let instance_id = nic_settings.0;
                     self.device_config_send
@@ -544,13 +596,14 @@ pub async fn disk_from_disk_type<'a>(
     disk_type: Resource<DiskHandleKind>,
     read_only: bool,
     resolver: &ResourceResolver,
+    driver_source: &VmTaskDriverSource,
 ) -> Result<Disk, Vtl2SettingsErrorInfo> {
     let disk = resolver
         .resolve(
             disk_type,
             ResolveDiskParameters {
                 read_only,
-                _async_trait_workaround: &(),
+                driver_source,
             },
         )
         .await
@@ -570,6 +623,9 @@ fn modify_storage_configuration(
     if let Err(e) = modify_scsi_configuration(old_settings, new_settings, todos) {
         errors.push(e);
     }
+    if let Err(e) = modify_nvme_configuration(old_settings, new_settings, todos) {
+        errors.push(e);
+    }
 }
 
 fn modify_ide_configuration(
@@ -713,6 +769,63 @@ fn modify_scsi_disks_configuration(
     Ok(())
 }
 
+fn modify_nvme_configuration(
+    old_settings: &Vtl2SettingsDynamic,
+    new_settings: &Vtl2SettingsDynamic,
+    todos: &mut Vec<Vtl2ConfigAcquireResource>,
+) -> Result<(), Vtl2SettingsErrorInfo> {
+    let old_controller_map = create_device_map_from_settings(&old_settings.nvme_controllers);
+    let new_controller_map = create_device_map_from_settings(&new_settings.nvme_controllers);
+
+    let (controllers_to_check, controllers_to_remove, controllers_to_add) =
+        calculate_device_change_from_map(&old_controller_map, &new_controller_map);
+
+    if !controllers_to_add.is_empty() || !controllers_to_remove.is_empty() {
+        return Err(Error::StorageCannotAddRemoveControllerAtRuntime.into());
+    }
+
+    for (old_controller, new_controller) in controllers_to_check {
+        let instance_id = old_controller.instance_id;
+
+        // A modified namespace is replaced by removing and re-adding it.
+        let remove_namespaces = old_controller.namespaces.iter().filter(|old_ns| {
+            !new_controller
+                .namespaces
+                .iter()
+                .any(|new_ns| new_ns == *old_ns)
+        });
+        let add_namespaces = new_controller.namespaces.iter().filter(|new_ns| {
+            !old_controller
+                .namespaces
+                .iter()
+                .any(|old_ns| old_ns == *new_ns)
+        });
+
+        // Remove all the namespaces before adding any, so that a replaced
+        // namespace's ID is free by the time it is added back.
+        for namespace in remove_namespaces {
+            if namespace.physical_devices.is_striping() {
+                return Err(Error::StripStorageCannotChangeControllerAtRuntime.into());
+            }
+            todos.push(Vtl2ConfigAcquireResource::RmNamespace(
+                instance_id,
+                namespace.nsid,
+            ));
+        }
+        for namespace in add_namespaces {
+            if namespace.physical_devices.is_striping() {
+                return Err(Error::StripStorageCannotChangeControllerAtRuntime.into());
+            }
+            todos.push(Vtl2ConfigAcquireResource::AddNamespace(
+                instance_id,
+                namespace.clone(),
+            ));
+        }
+    }
+
+    Ok(())
+}
+
 fn calculate_scsi_disks_change<'a>(
     old_controller_map: &'a HashMap<Guid, &underhill_config::ScsiController>,
     new_controller_map: &'a HashMap<Guid, &underhill_config::ScsiController>,
@@ -885,6 +998,11 @@ pub struct UhVpciDeviceConfig {
     pub resource: Resource<PciDeviceHandleKind>,
 }
 
+pub struct UhNvmeControllerConfig {
+    pub config: UhVpciDeviceConfig,
+    pub request: mesh::Sender<NvmeControllerRequest>,
+}
+
 #[derive(Debug, Eq, PartialEq, Hash)]
 pub enum StorageDevicePath {
     Ide(IdePath),
@@ -1078,7 +1196,10 @@ async fn make_disk_type_from_physical_device(
         }
     })?;
 
-    Ok(Resource::new(OpenBlockDeviceConfig { file }))
+    Ok(Resource::new(OpenBlockDeviceConfig {
+        file,
+        write_cache: WriteCacheMode::WriteBack,
+    }))
 }
 
 fn make_disk_config_inner(
@@ -1395,22 +1516,27 @@ async fn make_nvme_controller_config(
     storage_context: &StorageContext<'_>,
     controller: &underhill_config::NvmeController,
     is_restoring: bool,
-) -> Result<UhVpciDeviceConfig, Vtl2SettingsErrorInfo> {
+) -> Result<UhNvmeControllerConfig, Vtl2SettingsErrorInfo> {
     let mut namespaces = Vec::new();
     for namespace in &controller.namespaces {
         namespaces
             .push(make_nvme_disk_config(ctx, storage_context, namespace, is_restoring).await?);
     }
 
-    Ok(UhVpciDeviceConfig {
-        instance_id: controller.instance_id,
-        resource: NvmeControllerHandle {
-            subsystem_id: controller.instance_id,
-            namespaces,
-            max_io_queues: 64,
-            msix_count: 64,
-        }
-        .into_resource(),
+    let (send, recv) = mesh::channel();
+    Ok(UhNvmeControllerConfig {
+        config: UhVpciDeviceConfig {
+            instance_id: controller.instance_id,
+            resource: NvmeControllerHandle {
+                subsystem_id: controller.instance_id,
+                namespaces,
+                max_io_queues: 64,
+                msix_count: 64,
+                requests: Some(recv),
+            }
+            .into_resource(),
+        },
+        request: send,
     })
 }
 
@@ -1426,7 +1552,7 @@ pub async fn create_storage_controllers_from_vtl2_settings(
     (
         Option<UhIdeControllerConfig>,
         Vec<UhScsiControllerConfig>,
-        Vec<UhVpciDeviceConfig>,
+        Vec<UhNvmeControllerConfig>,
     ),
     Vtl2SettingsErrorInfo,
 > {
@@ -1706,6 +1832,12 @@ fn instance_id(&self) -> Guid {
     }
 }
 
+impl HasInstanceId for underhill_config::NvmeController {
+    fn instance_id(&self) -> Guid {
+        self.instance_id
+    }
+}
+
 impl HasInstanceId for underhill_config::IdeController {
     fn instance_id(&self) -> Guid {
         self.instance_id
@@ -1784,7 +1916,7 @@ pub async fn new(
         let fixed = vtl2_settings.map_or_else(Default::default, |s| s.fixed.clone());
         let dynamic = vtl2_settings.map(|s| &s.dynamic);
 
-        let (ide_controller, scsi_controllers, vpci_devices) = if let Some(dynamic) = &dynamic {
+        let (ide_controller, scsi_controllers, nvme_controllers) = if let Some(dynamic) = &dynamic {
             create_storage_controllers_from_vtl2_settings(
                 &mut context,
                 uevent_listener,
@@ -1830,6 +1962,15 @@ pub async fn new(
             })
             .collect();
 
+        let mut nvme_request = HashMap::new();
+        let vpci_devices = nvme_controllers
+            .into_iter()
+            .map(|c| {
+                nvme_request.insert(c.config.instance_id, c.request);
+                c.config
+            })
+            .collect();
+
         let cfg = InitialControllers {
             ide_controller,
             vmbus_devices,
@@ -1838,6 +1979,7 @@ pub async fn new(
             device_interfaces: DeviceInterfaces {
                 scsi_dvds,
                 scsi_request,
+                nvme_request,
                 use_nvme_vfio,
             },
         };
@@ -5,6 +5,7 @@
 use inspect::Request;
 use inspect::Response;
 use inspect::SensitivityLevel;
+use page_pool_alloc::PagePoolAllocatorSpawner;
 use pal_async::timer::PolledTimer;
 use std::time::Duration;
 use vmcore::vm_task::VmTaskDriver;
@@ -14,15 +15,22 @@ fn inspect_meminfo(req: Request<'_>) {
     const FIELDS: &[&str] = &[
         "MemTotal",
         "MemFree",
+        "MemAvailable",
+        "Buffers",
+        "Cached",
         "Mapped",
         "Slab",
         "AnonPages",
+        "SReclaimable",
         "SUnreclaim",
         "KernelStack",
         "PageTables",
         "Shmem",
         "Percpu",
+        "VmallocUsed",
         "Committed_AS",
+        "CmaTotal",
+        "CmaFree",
     ];
 
     let mut resp = req.respond();
@@ -114,7 +122,9 @@ fn inner(req: Request<'_>) -> Option<()> {
 /// Writes inspection results based on the contents of /proc/<pid>/status for userspace processes
 fn inspect_userspace_procs(req: Request<'_>) {
     const KTHREADD_PID_STRING: &str = "2"; // String so we don't have to parse to check it
-    const FIELDS: &[&str] = &["VmSize", "VmPeak", "VmRSS", "VmHWM", "RssAnon"];
+    const FIELDS: &[&str] = &[
+        "VmSize", "VmPeak", "VmRSS", "VmHWM", "RssAnon", "RssFile", "RssShmem",
+    ];
     let mut resp = req.respond();
 
     fn inner(resp: &mut Response<'_>) -> anyhow::Result<()> {
@@ -191,7 +201,13 @@ pub fn inspect_proc(req: Request<'_>) {
 }
 
 /// Used for periodic automatic logging.
-pub async fn periodic_telemetry_task(driver: VmTaskDriver) {
+///
+/// Logs every `interval`, defaulting to once a day.
+pub async fn periodic_telemetry_task(
+    driver: VmTaskDriver,
+    interval: Option<Duration>,
+    shared_vis_pool: Option<PagePoolAllocatorSpawner>,
+) {
     let mut timer = PolledTimer::new(&driver);
     // Wait 15 minutes before initial logging to give the guest time to boot and begin doing work
     timer.sleep(Duration::from_secs(60 * 15)).await;
@@ -202,7 +218,11 @@ pub async fn periodic_telemetry_task(driver: VmTaskDriver) {
             inspect::adhoc_mut(|r| {
                 r.respond()
                     .child("meminfo", inspect_meminfo)
-                    .child("processes", inspect_userspace_procs);
+                    .child("processes", inspect_userspace_procs)
+                    .field(
+                        "shared_vis_pool",
+                        shared_vis_pool.as_ref().map(|pool| pool.stats()),
+                    );
             }),
         );
         inspection.resolve().await;
@@ -210,7 +230,8 @@ pub async fn periodic_telemetry_task(driver: VmTaskDriver) {
         let json = results.json();
         // The below message needs to be valid JSON for ease of processing
         tracing::info!("{{\"periodic_memory_status\":{}}}", json);
-        // Wait a day before logging again
-        timer.sleep(Duration::from_secs(60 * 60 * 24)).await;
+        timer
+            .sleep(interval.unwrap_or(Duration::from_secs(60 * 60 * 24)))
+            .await;
     }
 }
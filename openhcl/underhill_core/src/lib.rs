@@ -13,10 +13,12 @@
 mod get_tracing;
 mod inspect_internal;
 mod inspect_proc;
+mod liveness;
 mod loader;
 mod nvme_manager;
 mod options;
 mod reference_time;
+mod sandbox;
 mod servicing;
 mod threadpool_vm_task_backend;
 mod vmbus_relay_unit;
@@ -31,6 +33,9 @@
 
 use crate::diag::DiagWorker;
 use crate::dispatch::UhVmRpc;
+use crate::sandbox::ProcessKind;
+use crate::sandbox::Sandbox;
+use crate::sandbox::SandboxMode;
 use crate::worker::UnderhillEnvCfg;
 use crate::worker::UnderhillRemoteConsoleCfg;
 use crate::worker::UnderhillVmWorker;
@@ -55,6 +60,7 @@
 use mesh_process::try_run_mesh_host;
 use mesh_process::Mesh;
 use mesh_process::ProcessConfig;
+use mesh_process::SandboxProfile;
 use mesh_tracing::RemoteTracer;
 use mesh_tracing::TracingBackend;
 use mesh_worker::launch_local_worker;
@@ -164,6 +170,9 @@ pub fn main() -> anyhow::Result<()> {
             if let Some(remote_tracer) = params.tracer {
                 init_tracing(tracing_driver, remote_tracer).context("failed to init tracing")?;
             }
+            if let Some(mut sandbox) = params.sandbox {
+                sandbox.finalize().context("failed to finalize sandbox")?;
+            }
             params.runner.run(RegisteredWorkers).await;
             Ok(())
         }
@@ -276,6 +285,8 @@ struct Workers {
     vm: WorkerHandle,
     #[inspect(skip)]
     vm_rpc: mesh::Sender<UhVmRpc>,
+    #[inspect(display)]
+    sandbox_mode: SandboxMode,
     vnc: Option<WorkerHandle>,
     #[cfg(feature = "gdb")]
     gdb: Option<WorkerHandle>,
@@ -285,16 +296,38 @@ struct Workers {
 struct MeshHostParams {
     tracer: Option<RemoteTracer>,
     runner: WorkerHostRunner,
+    sandbox: Option<Sandbox>,
 }
 
 async fn launch_mesh_host(
     mesh: &Mesh,
     name: &str,
+    kind: ProcessKind,
+    sandbox_mode: SandboxMode,
     tracer: Option<RemoteTracer>,
 ) -> anyhow::Result<WorkerHost> {
     let (host, runner) = mesh_worker::worker_host();
-    mesh.launch_host(ProcessConfig::new(name), MeshHostParams { tracer, runner })
-        .await?;
+    let (config, sandbox) = if sandbox_mode == SandboxMode::Off {
+        (ProcessConfig::new(name), None)
+    } else {
+        let sandbox = Sandbox {
+            kind,
+            mode: sandbox_mode,
+        };
+        (
+            ProcessConfig::new_with_sandbox(name, Box::new(sandbox)),
+            Some(sandbox),
+        )
+    };
+    mesh.launch_host(
+        config,
+        MeshHostParams {
+            tracer,
+            runner,
+            sandbox,
+        },
+    )
+    .await?;
     Ok(host)
 }
 
@@ -304,6 +337,13 @@ async fn launch_workers(
     control_send: mesh::Sender<ControlRequest>,
     opt: Options,
 ) -> anyhow::Result<Workers> {
+    // Dumps are collected by spawning a process, which is not allowed by the
+    // strict sandbox.
+    let hang_dump = opt.hang_dump && opt.sandbox_mode != SandboxMode::Strict;
+    if opt.hang_dump && !hang_dump {
+        tracing::warn!("hang dumps are not supported in strict sandbox mode, disabling");
+    }
+
     let env_cfg = UnderhillEnvCfg {
         vmbus_max_version: opt.vmbus_max_version,
         vmbus_enable_mnf: opt.vmbus_enable_mnf,
@@ -322,6 +362,9 @@ async fn launch_workers(
         no_sidecar_hotplug: opt.no_sidecar_hotplug,
         gdbstub: opt.gdbstub,
         hide_isolation: opt.hide_isolation,
+        memory_telemetry_interval: opt.memory_telemetry_interval,
+        hang_dump,
+        no_relay: opt.no_relay,
     };
 
     let (mut remote_console_cfg, framebuffer_access) =
@@ -334,9 +377,15 @@ async fn launch_workers(
 
         let input_send = remote_console_cfg.input.sender();
 
-        let vnc_host = launch_mesh_host(mesh, "vnc", Some(tracing.tracer()))
-            .await
-            .context("spawning vnc process failed")?;
+        let vnc_host = launch_mesh_host(
+            mesh,
+            "vnc",
+            ProcessKind::Vnc,
+            opt.sandbox_mode,
+            Some(tracing.tracer()),
+        )
+        .await
+        .context("spawning vnc process failed")?;
 
         vnc_worker = Some(
             vnc_host
@@ -361,9 +410,15 @@ async fn launch_workers(
         let listener = VmListener::bind(VmAddress::vsock_any(opt.gdbstub_port))
             .context("failed to bind socket")?;
 
-        let gdb_host = launch_mesh_host(mesh, "gdb", Some(tracing.tracer()))
-            .await
-            .context("failed to spawn gdb host process")?;
+        let gdb_host = launch_mesh_host(
+            mesh,
+            "gdb",
+            ProcessKind::Gdb,
+            opt.sandbox_mode,
+            Some(tracing.tracer()),
+        )
+        .await
+        .context("failed to spawn gdb host process")?;
 
         // Get the VP count of this machine. It's too early to read it directly
         // from IGVM parameters, but the kernel already has the IGVM parsed VP
@@ -396,9 +451,15 @@ async fn launch_workers(
     // Spawn the worker in a separate process in case the diagnostics server (in
     // this process) is used to run gdbserver against it, or in case it needs to
     // be restarted.
-    let host = launch_mesh_host(mesh, "vm", Some(tracing.tracer()))
-        .await
-        .context("failed to launch worker process")?;
+    let host = launch_mesh_host(
+        mesh,
+        "vm",
+        ProcessKind::Vm,
+        opt.sandbox_mode,
+        Some(tracing.tracer()),
+    )
+    .await
+    .context("failed to launch worker process")?;
 
     let vm_worker = host
         .start_worker(
@@ -416,6 +477,7 @@ async fn launch_workers(
     Ok(Workers {
         vm: vm_worker,
         vm_rpc,
+        sandbox_mode: opt.sandbox_mode,
         vnc: vnc_worker,
         #[cfg(feature = "gdb")]
         gdb: gdbstub_worker,
@@ -561,9 +623,15 @@ enum Event {
                                 anyhow::bail!("previous restart still in progress");
                             }
 
-                            let host = launch_mesh_host(mesh, "vm", Some(tracing.tracer()))
-                                .await
-                                .context("failed to launch worker process")?;
+                            let host = launch_mesh_host(
+                                mesh,
+                                "vm",
+                                ProcessKind::Vm,
+                                workers.sandbox_mode,
+                                Some(tracing.tracer()),
+                            )
+                            .await
+                            .context("failed to launch worker process")?;
 
                             workers.vm.restart(&host);
                             Ok(())
@@ -613,6 +681,16 @@ enum Event {
 
                         workers.vm_rpc.send(UhVmRpc::PacketCapture(rpc));
                     }
+                    diag_server::DiagRequest::VmbusCapture(rpc) => {
+                        let Some(workers) = &mut workers else {
+                            rpc.complete(Err(RemoteError::new(anyhow::anyhow!(
+                                "worker has not been started yet"
+                            ))));
+                            continue;
+                        };
+
+                        workers.vm_rpc.send(UhVmRpc::VmbusCapture(rpc));
+                    }
                     diag_server::DiagRequest::Resume(rpc) => {
                         let Some(workers) = &mut workers else {
                             rpc.complete(Err(RemoteError::new(anyhow::anyhow!(
@@ -654,11 +732,28 @@ enum Event {
                     #[cfg(feature = "profiler")]
                     diag_server::DiagRequest::Profile(rpc) => {
                         let Rpc(rpc_params, rpc_sender) = rpc;
+                        let sandbox_mode = workers
+                            .as_ref()
+                            .map_or_else(SandboxMode::default, |w| w.sandbox_mode);
+                        // The profiler needs to spawn the profiler binary,
+                        // which the strict sandbox does not allow.
+                        if sandbox_mode == SandboxMode::Strict {
+                            rpc_sender.send(Err(RemoteError::new(anyhow::anyhow!(
+                                "profiling is not supported in strict sandbox mode"
+                            ))));
+                            continue;
+                        }
                         // Create profiler host if there is none created before
                         if profiler_host.is_none() {
-                            match launch_mesh_host(mesh, "profiler", Some(tracing.tracer()))
-                                .await
-                                .context("failed to launch profiler host")
+                            match launch_mesh_host(
+                                mesh,
+                                "profiler",
+                                ProcessKind::Profiler,
+                                sandbox_mode,
+                                Some(tracing.tracer()),
+                            )
+                            .await
+                            .context("failed to launch profiler host")
                             {
                                 Ok(host) => {
                                     profiler_host = Some(host);
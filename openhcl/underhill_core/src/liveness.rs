@@ -0,0 +1,225 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Liveness watchdog for critical VTL2 tasks.
+//!
+//! Tasks register with the watchdog and must check in periodically. A
+//! dedicated monitor thread (which keeps running even if every executor in the
+//! process is stuck) reports any task that fails to check in within its
+//! timeout to the host, and can optionally capture a dump of this process.
+//!
+//! The host notification is sent through the GET, so a hang of the GET thread
+//! itself is only visible in the VTL2 log.
+
+use guest_emulation_transport::api::EventLogId;
+use guest_emulation_transport::GuestEmulationTransportClient;
+use pal_async::driver::Driver;
+use pal_async::timer::PolledTimer;
+use parking_lot::Mutex;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
+
+const UNDERHILL_DUMP_PATH: &str = "/bin/underhill-dump";
+const UNDERHILL_CRASH_PATH: &str = "/bin/underhill-crash";
+
+/// How often the monitor thread checks for hung tasks.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Watches registered tasks for hangs.
+///
+/// The monitor thread exits when this is dropped.
+pub struct LivenessWatchdog {
+    tasks: Arc<Mutex<Vec<Weak<TaskState>>>>,
+    _stop: mpsc::Sender<()>,
+}
+
+struct TaskState {
+    name: String,
+    timeout: Duration,
+    last_check_in: Mutex<Instant>,
+}
+
+impl LivenessWatchdog {
+    /// Starts the monitor thread.
+    ///
+    /// If `dump_on_hang` is set, the first detected hang also sends a dump of
+    /// this process to the host.
+    pub fn new(get_client: GuestEmulationTransportClient, dump_on_hang: bool) -> Self {
+        let tasks = Arc::new(Mutex::new(Vec::new()));
+        let (stop_send, stop_recv) = mpsc::channel();
+        let mut monitor = Monitor {
+            tasks: tasks.clone(),
+            hung: Vec::new(),
+            get_client,
+            dump_on_hang,
+        };
+        std::thread::Builder::new()
+            .name("liveness".into())
+            .spawn(move || {
+                while let Err(mpsc::RecvTimeoutError::Timeout) =
+                    stop_recv.recv_timeout(POLL_INTERVAL)
+                {
+                    monitor.check();
+                }
+            })
+            .unwrap();
+
+        Self {
+            tasks,
+            _stop: stop_send,
+        }
+    }
+
+    /// Registers a task, which is considered hung if it goes longer than
+    /// `timeout` between check-ins.
+    ///
+    /// The task is unregistered when the returned handle is dropped.
+    pub fn register(&self, name: impl Into<String>, timeout: Duration) -> LivenessHandle {
+        let state = Arc::new(TaskState {
+            name: name.into(),
+            timeout,
+            last_check_in: Mutex::new(Instant::now()),
+        });
+        let mut tasks = self.tasks.lock();
+        tasks.retain(|task| task.strong_count() > 0);
+        tasks.push(Arc::downgrade(&state));
+        LivenessHandle { state }
+    }
+}
+
+/// A task registered with a [`LivenessWatchdog`].
+pub struct LivenessHandle {
+    state: Arc<TaskState>,
+}
+
+impl LivenessHandle {
+    /// Reports that the task is still making progress.
+    pub fn check_in(&self) {
+        *self.state.last_check_in.lock() = Instant::now();
+    }
+}
+
+/// Checks in a [`LivenessHandle`] on a fixed interval, for tasks that are
+/// otherwise idle while waiting for work.
+///
+/// This detects stalls of whichever executor polls [`Self::tick`], as well as
+/// of the task itself if it only ticks between units of work.
+pub struct LivenessTicker {
+    handle: LivenessHandle,
+    timer: PolledTimer,
+    interval: Duration,
+}
+
+impl LivenessTicker {
+    pub fn new(driver: &impl Driver, handle: LivenessHandle, interval: Duration) -> Self {
+        Self {
+            handle,
+            timer: PolledTimer::new(driver),
+            interval,
+        }
+    }
+
+    /// Checks in, then waits for the next interval to elapse and checks in
+    /// again.
+    pub async fn tick(&mut self) {
+        self.handle.check_in();
+        self.timer.sleep(self.interval).await;
+        self.handle.check_in();
+    }
+
+    /// Ticks forever.
+    pub async fn run(mut self) {
+        loop {
+            self.tick().await;
+        }
+    }
+}
+
+struct Monitor {
+    tasks: Arc<Mutex<Vec<Weak<TaskState>>>>,
+    /// Names of the tasks currently reported as hung.
+    hung: Vec<String>,
+    get_client: GuestEmulationTransportClient,
+    dump_on_hang: bool,
+}
+
+impl Monitor {
+    fn check(&mut self) {
+        let tasks = self
+            .tasks
+            .lock()
+            .iter()
+            .filter_map(|task| task.upgrade())
+            .collect::<Vec<_>>();
+
+        let now = Instant::now();
+        let mut newly_hung = false;
+        for task in &tasks {
+            let elapsed = now.saturating_duration_since(*task.last_check_in.lock());
+            let was_hung = self.hung.contains(&task.name);
+            if elapsed > task.timeout {
+                if !was_hung {
+                    tracing::error!(
+                        task = task.name.as_str(),
+                        elapsed_ms = elapsed.as_millis() as u64,
+                        timeout_ms = task.timeout.as_millis() as u64,
+                        "VTL2 task hang detected"
+                    );
+                    self.get_client.event_log(EventLogId::VTL2_HANG_DETECTED);
+                    self.hung.push(task.name.clone());
+                    newly_hung = true;
+                }
+            } else if was_hung {
+                tracing::info!(task = task.name.as_str(), "VTL2 task recovered");
+                self.hung.retain(|name| name != &task.name);
+            }
+        }
+
+        // Forget about tasks that were unregistered while hung.
+        self.hung
+            .retain(|name| tasks.iter().any(|task| &task.name == name));
+
+        if newly_hung && self.dump_on_hang {
+            // Only dump once, since dumping the process is slow and
+            // disruptive, and later hangs are likely related to the first.
+            self.dump_on_hang = false;
+            if let Err(err) = dump_self() {
+                tracing::error!(
+                    error = err.as_ref() as &dyn std::error::Error,
+                    "failed to dump hung process"
+                );
+            }
+        }
+    }
+}
+
+/// Sends a dump of this process to the host, by piping the output of
+/// `underhill-dump` into `underhill-crash`.
+///
+/// Signal 0 is reported, to distinguish this from a real crash.
+fn dump_self() -> anyhow::Result<()> {
+    let pid = std::process::id().to_string();
+    let comm = fs_err::read_to_string("/proc/self/comm")?;
+
+    tracing::info!("dumping hung process");
+    let mut dump = Command::new(UNDERHILL_DUMP_PATH)
+        .arg(&pid)
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut crash = Command::new(UNDERHILL_CRASH_PATH)
+        .args([pid.as_str(), pid.as_str(), "0", comm.trim_end()])
+        .stdin(dump.stdout.take().unwrap())
+        .spawn()?;
+
+    let dump_status = dump.wait()?;
+    let crash_status = crash.wait()?;
+    if !dump_status.success() || !crash_status.success() {
+        anyhow::bail!("dump failed: dump {dump_status}, crash {crash_status}");
+    }
+    Ok(())
+}
@@ -5,6 +5,7 @@
 
 #![warn(missing_docs)]
 
+use crate::sandbox::SandboxMode;
 use anyhow::bail;
 use anyhow::Context;
 use std::path::PathBuf;
@@ -113,6 +114,24 @@ pub struct Options {
     /// (OPENHCL_NO_SIDECAR_HOTPLUG=1) Leave sidecar VPs remote even if they
     /// hit exits.
     pub no_sidecar_hotplug: bool,
+
+    /// (OPENHCL_MEMORY_TELEMETRY_INTERVAL=\<number\>) How often, in seconds,
+    /// to log VTL2 memory usage to the host. Defaults to once a day.
+    pub memory_telemetry_interval: Option<u64>,
+
+    /// (OPENHCL_HANG_DUMP=1) Write a dump of the worker process the first
+    /// time the liveness watchdog detects a hung task.
+    pub hang_dump: bool,
+
+    /// (OPENHCL_SECCOMP=off|audit|enforce|strict) How to apply the seccomp
+    /// filters of the worker processes. Defaults to audit. Strict mode
+    /// disables hang dumps and profiling.
+    pub sandbox_mode: SandboxMode,
+
+    /// (OPENHCL_NO_RELAY=1) Run without the vmbus relay, so that the guest
+    /// only sees devices implemented in VTL2, and not any devices offered by
+    /// the host.
+    pub no_relay: bool,
 }
 
 impl Options {
@@ -181,6 +200,14 @@ fn parse_bool(value: Option<std::ffi::OsString>) -> bool {
         let no_sidecar_hotplug = parse_legacy_env_bool("OPENHCL_NO_SIDECAR_HOTPLUG");
         let gdbstub = parse_legacy_env_bool("OPENHCL_GDBSTUB");
         let gdbstub_port = parse_legacy_env_number("OPENHCL_GDBSTUB_PORT")?.map(|x| x as u32);
+        let memory_telemetry_interval =
+            parse_legacy_env_number("OPENHCL_MEMORY_TELEMETRY_INTERVAL")?;
+        let hang_dump = parse_env_bool("OPENHCL_HANG_DUMP");
+        let sandbox_mode = std::env::var_os("OPENHCL_SECCOMP")
+            .map(|v| v.to_string_lossy().parse())
+            .transpose()?
+            .unwrap_or_default();
+        let no_relay = parse_env_bool("OPENHCL_NO_RELAY");
 
         let mut args = std::env::args().chain(extra_args);
         // Skip our own filename.
@@ -234,6 +261,10 @@ fn parse_bool(value: Option<std::ffi::OsString>) -> bool {
             hide_isolation,
             halt_on_guest_halt,
             no_sidecar_hotplug,
+            memory_telemetry_interval,
+            hang_dump,
+            sandbox_mode,
+            no_relay,
         })
     }
 
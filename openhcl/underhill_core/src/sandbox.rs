@@ -0,0 +1,380 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Seccomp sandboxing of the mesh host processes launched by the control
+//! process.
+//!
+//! Each host process is launched with a seccomp filter allowing only the
+//! syscalls in its [`ProcessKind`]'s policy: the syscalls needed by every
+//! process (the Rust and musl runtimes, mesh IPC, io-uring, tracing), plus
+//! those needed by the workers that run in that process. This limits what a
+//! compromised device emulator or translation path can do.
+//!
+//! The control process itself (including the diagnostics server) is not
+//! sandboxed, since it launches and restarts the host processes.
+
+use anyhow::Context;
+use mesh::MeshPayload;
+use mesh_process::SandboxProfile;
+use pal::unix::process::Builder;
+use pal::unix::process::SandboxFailureMode;
+use seccompiler::BpfProgram;
+use seccompiler::SeccompAction;
+use seccompiler::SeccompFilter;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// How the seccomp filters are applied.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, MeshPayload)]
+pub enum SandboxMode {
+    /// No filters are applied.
+    Off,
+    /// Syscalls outside the policy are logged to the kernel log, but allowed.
+    ///
+    /// This is the default.
+    #[default]
+    Audit,
+    /// Syscalls outside the policy fail with `EPERM`.
+    Enforce,
+    /// Syscalls outside the policy kill the process, and syscalls which are
+    /// only needed for diagnostics (process creation, profiling) are removed
+    /// from the policy. Once started, a host process can no longer execute
+    /// new programs.
+    Strict,
+}
+
+impl SandboxMode {
+    fn mismatch_action(&self) -> Option<SeccompAction> {
+        match self {
+            SandboxMode::Off => None,
+            SandboxMode::Audit => Some(SeccompAction::Log),
+            SandboxMode::Enforce => Some(SeccompAction::Errno(libc::EPERM as u32)),
+            SandboxMode::Strict => Some(SeccompAction::KillProcess),
+        }
+    }
+}
+
+impl FromStr for SandboxMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mode = match s {
+            "off" => Self::Off,
+            "audit" => Self::Audit,
+            "enforce" => Self::Enforce,
+            "strict" => Self::Strict,
+            _ => {
+                anyhow::bail!("invalid sandbox mode {s:?}, expected off, audit, enforce or strict")
+            }
+        };
+        Ok(mode)
+    }
+}
+
+impl fmt::Display for SandboxMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            SandboxMode::Off => "off",
+            SandboxMode::Audit => "audit",
+            SandboxMode::Enforce => "enforce",
+            SandboxMode::Strict => "strict",
+        })
+    }
+}
+
+/// The kind of host process, which determines its syscall policy.
+#[derive(Debug, Copy, Clone, MeshPayload)]
+pub enum ProcessKind {
+    /// The VM worker, running all the device emulators.
+    Vm,
+    /// The VNC server.
+    Vnc,
+    /// The gdbstub.
+    Gdb,
+    /// The profiler, which spawns the profiler binary.
+    Profiler,
+}
+
+/// A named set of syscalls, forming part of a process's policy.
+struct SyscallGroup {
+    name: &'static str,
+    syscalls: &'static [libc::c_long],
+    /// Whether the group is allowed in strict mode.
+    strict: bool,
+}
+
+/// Syscalls used by every process.
+static RUNTIME: SyscallGroup = SyscallGroup {
+    name: "runtime",
+    syscalls: &[
+        // Memory management.
+        libc::SYS_brk,
+        libc::SYS_madvise,
+        libc::SYS_membarrier,
+        libc::SYS_mmap,
+        libc::SYS_mprotect,
+        libc::SYS_mremap,
+        libc::SYS_munmap,
+        // Threads and synchronization, including binding the thread pool to
+        // CPUs.
+        libc::SYS_clone,
+        libc::SYS_exit,
+        libc::SYS_exit_group,
+        libc::SYS_futex,
+        libc::SYS_getcpu,
+        libc::SYS_getpid,
+        libc::SYS_gettid,
+        libc::SYS_sched_getaffinity,
+        libc::SYS_sched_setaffinity,
+        libc::SYS_sched_yield,
+        libc::SYS_set_robust_list,
+        libc::SYS_set_tid_address,
+        // Signals, including for panics and aborts.
+        libc::SYS_rt_sigaction,
+        libc::SYS_rt_sigprocmask,
+        libc::SYS_rt_sigreturn,
+        libc::SYS_sigaltstack,
+        libc::SYS_tgkill,
+        // Time.
+        libc::SYS_clock_gettime,
+        libc::SYS_clock_nanosleep,
+        libc::SYS_nanosleep,
+        // File descriptors.
+        libc::SYS_close,
+        libc::SYS_dup,
+        libc::SYS_dup3,
+        libc::SYS_faccessat,
+        libc::SYS_faccessat2,
+        libc::SYS_fcntl,
+        libc::SYS_fdatasync,
+        libc::SYS_fstat,
+        libc::SYS_getdents64,
+        libc::SYS_ioctl,
+        libc::SYS_lseek,
+        libc::SYS_newfstatat,
+        libc::SYS_openat,
+        libc::SYS_pipe2,
+        libc::SYS_pread64,
+        libc::SYS_pwrite64,
+        libc::SYS_read,
+        libc::SYS_readlinkat,
+        libc::SYS_readv,
+        libc::SYS_write,
+        libc::SYS_writev,
+        // Polling and async IO.
+        libc::SYS_epoll_create1,
+        libc::SYS_epoll_ctl,
+        libc::SYS_epoll_pwait,
+        libc::SYS_eventfd2,
+        libc::SYS_io_uring_enter,
+        libc::SYS_io_uring_register,
+        libc::SYS_io_uring_setup,
+        libc::SYS_ppoll,
+        libc::SYS_timerfd_create,
+        libc::SYS_timerfd_settime,
+        // Mesh IPC.
+        libc::SYS_recvfrom,
+        libc::SYS_recvmsg,
+        libc::SYS_sendmsg,
+        libc::SYS_sendto,
+        libc::SYS_shutdown,
+        libc::SYS_socketpair,
+        // Miscellaneous.
+        libc::SYS_getegid,
+        libc::SYS_geteuid,
+        libc::SYS_getgid,
+        libc::SYS_getrandom,
+        libc::SYS_getuid,
+        libc::SYS_prctl,
+        libc::SYS_prlimit64,
+        libc::SYS_uname,
+    ],
+    strict: true,
+};
+
+/// Legacy syscalls used by every process, which only exist on x86_64.
+#[cfg(guest_arch = "x86_64")]
+static RUNTIME_LEGACY: SyscallGroup = SyscallGroup {
+    name: "runtime (legacy)",
+    syscalls: &[
+        libc::SYS_access,
+        libc::SYS_arch_prctl,
+        libc::SYS_dup2,
+        libc::SYS_epoll_wait,
+        libc::SYS_lstat,
+        libc::SYS_open,
+        libc::SYS_pipe,
+        libc::SYS_poll,
+        libc::SYS_readlink,
+        libc::SYS_stat,
+    ],
+    strict: true,
+};
+
+/// Executing the host process itself, which happens after the filter is
+/// applied.
+static EXEC: SyscallGroup = SyscallGroup {
+    name: "exec",
+    syscalls: &[libc::SYS_execve],
+    strict: true,
+};
+
+/// Creating, configuring and accepting sockets (vsock, netlink).
+static SOCKETS: SyscallGroup = SyscallGroup {
+    name: "sockets",
+    syscalls: &[
+        libc::SYS_accept4,
+        libc::SYS_bind,
+        libc::SYS_connect,
+        libc::SYS_getpeername,
+        libc::SYS_getsockname,
+        libc::SYS_getsockopt,
+        libc::SYS_listen,
+        libc::SYS_setsockopt,
+        libc::SYS_socket,
+    ],
+    strict: true,
+};
+
+/// Managing device and guest memory, and VMGS/device files.
+static DEVICES: SyscallGroup = SyscallGroup {
+    name: "devices",
+    syscalls: &[
+        libc::SYS_fallocate,
+        libc::SYS_fstatfs,
+        libc::SYS_fsync,
+        libc::SYS_ftruncate,
+        libc::SYS_memfd_create,
+        libc::SYS_mlock,
+        libc::SYS_munlock,
+        libc::SYS_statfs,
+        libc::SYS_sysinfo,
+    ],
+    strict: true,
+};
+
+/// Spawning and waiting for child processes (dump collection, the profiler
+/// binary).
+static SPAWN: SyscallGroup = SyscallGroup {
+    name: "spawn",
+    syscalls: &[libc::SYS_execve, libc::SYS_kill, libc::SYS_wait4],
+    strict: false,
+};
+
+/// Collecting performance counters.
+static PROFILING: SyscallGroup = SyscallGroup {
+    name: "profiling",
+    syscalls: &[libc::SYS_perf_event_open],
+    strict: false,
+};
+
+impl ProcessKind {
+    /// The syscall groups allowed for this kind of process, excluding
+    /// [`EXEC`].
+    fn policy(&self) -> Vec<&'static SyscallGroup> {
+        let mut groups = vec![&RUNTIME];
+        #[cfg(guest_arch = "x86_64")]
+        groups.push(&RUNTIME_LEGACY);
+        match self {
+            ProcessKind::Vm => groups.extend([&SOCKETS, &DEVICES, &SPAWN]),
+            ProcessKind::Vnc | ProcessKind::Gdb => groups.push(&SOCKETS),
+            ProcessKind::Profiler => groups.extend([&SOCKETS, &SPAWN, &PROFILING]),
+        }
+        groups
+    }
+}
+
+/// The sandbox for a host process.
+#[derive(Debug, Copy, Clone, MeshPayload)]
+pub struct Sandbox {
+    pub kind: ProcessKind,
+    pub mode: SandboxMode,
+}
+
+impl Sandbox {
+    /// Builds the filter for the process. If `allow_exec`, the filter allows
+    /// the process to be executed, for applying before launch.
+    fn filter(&self, allow_exec: bool) -> anyhow::Result<Option<SeccompFilter>> {
+        let Some(mismatch_action) = self.mode.mismatch_action() else {
+            return Ok(None);
+        };
+
+        let strict = self.mode == SandboxMode::Strict;
+        let mut rules = BTreeMap::new();
+        for group in self
+            .kind
+            .policy()
+            .into_iter()
+            .chain(allow_exec.then_some(&EXEC))
+        {
+            if strict && !group.strict {
+                tracing::debug!(
+                    kind = ?self.kind,
+                    group = group.name,
+                    "syscall group not allowed in strict mode"
+                );
+                continue;
+            }
+            for &syscall in group.syscalls {
+                rules.insert(syscall, Vec::new());
+            }
+        }
+
+        let filter = SeccompFilter::new(
+            rules,
+            mismatch_action,
+            SeccompAction::Allow,
+            std::env::consts::ARCH
+                .try_into()
+                .context("unsupported seccomp architecture")?,
+        )
+        .context("failed to build seccomp filter")?;
+
+        Ok(Some(filter))
+    }
+}
+
+impl SandboxProfile for Sandbox {
+    fn apply(&mut self, builder: &mut Builder<'_>) {
+        match self.filter(true) {
+            Ok(Some(filter)) => {
+                // Only refuse to launch the process without its filter in
+                // strict mode.
+                builder.set_seccomp_filter(filter).set_sandbox_failure_mode(
+                    if self.mode == SandboxMode::Strict {
+                        SandboxFailureMode::Error
+                    } else {
+                        SandboxFailureMode::Warn
+                    },
+                );
+            }
+            Ok(None) => {}
+            Err(err) => {
+                // Launching without the sandbox is preferable to not being
+                // able to launch the VM at all.
+                tracing::error!(
+                    kind = ?self.kind,
+                    error = err.as_ref() as &dyn std::error::Error,
+                    "failed to build seccomp filter, launching without it"
+                );
+            }
+        }
+    }
+
+    /// In strict mode, stacks a second filter without [`EXEC`], so that the
+    /// process can no longer execute new programs.
+    fn finalize(&mut self) -> anyhow::Result<()> {
+        if self.mode != SandboxMode::Strict {
+            return Ok(());
+        }
+        if let Some(filter) = self.filter(false)? {
+            let program: BpfProgram = filter
+                .try_into()
+                .context("failed to compile seccomp filter")?;
+            seccompiler::apply_filter_all_threads(&program)
+                .context("failed to apply seccomp filter")?;
+        }
+        Ok(())
+    }
+}
@@ -11,6 +11,7 @@
 use state_unit::SpawnedUnit;
 use state_unit::StateUnit;
 use state_unit::UnitBuilder;
+use vmbus_relay::CaptureRings;
 use vmbus_relay::HostVmbusTransport;
 use vmbus_relay::InterceptChannelRequest;
 use vmbus_relay::RequestFromHandle;
@@ -53,6 +54,15 @@ pub async fn intercept_channel(
             .await
             .context("failed to make call")?
     }
+
+    /// Gets the ring buffers of the open channels with instance ID `id`, for
+    /// capturing their traffic.
+    pub async fn capture_rings(&self, id: Guid) -> anyhow::Result<Vec<CaptureRings>> {
+        self.relay_send
+            .call(RequestFromHandle::CaptureRings, id)
+            .await
+            .context("failed to make call")?
+    }
 }
 
 /// A newtype over [`HostVmbusTransport`] implementing [`StateUnit`].
@@ -36,6 +36,8 @@
 use crate::emuplat::tpm::resources::GetTpmRequestAkCertHelperHandle;
 use crate::emuplat::vga_proxy::UhRegisterHostIoFastPath;
 use crate::emuplat::EmuplatServicing;
+use crate::liveness::LivenessTicker;
+use crate::liveness::LivenessWatchdog;
 use crate::loader::vtl0_config::MeasuredVtl0Info;
 use crate::loader::vtl2_config::RuntimeParameters;
 use crate::loader::LoadKind;
@@ -248,8 +250,22 @@ pub struct UnderhillVmWorker {
     get_thread: JoinHandle<()>,
     _get_watchdog_task: Option<pal_async::task::Task<()>>,
     threadpool: AffinitizedThreadpool,
+
+    liveness: LivenessWatchdog,
+    get_heartbeat_task: pal_async::task::Task<()>,
+    vm_liveness: LivenessTicker,
 }
 
+/// How often tasks watched by the liveness watchdog tick.
+const LIVENESS_TICK_INTERVAL: Duration = Duration::from_secs(5);
+/// How long the GET thread can go without running before it is considered
+/// hung.
+const GET_HANG_TIMEOUT: Duration = Duration::from_secs(60);
+/// How long the VM control loop can go without running before it is
+/// considered hung. This is longer than for the GET, since the control loop
+/// legitimately blocks while stopping, saving, and restoring the VM.
+const VM_CONTROL_HANG_TIMEOUT: Duration = Duration::from_secs(300);
+
 /// Underhill configuration specified via env-vars / CLI flags.
 #[derive(Debug, MeshPayload, Clone)]
 pub struct UnderhillEnvCfg {
@@ -295,6 +311,13 @@ pub struct UnderhillEnvCfg {
     pub gdbstub: bool,
     /// Hide the isolation mode from the guest.
     pub hide_isolation: bool,
+    /// How often to log VTL2 memory usage, in seconds.
+    pub memory_telemetry_interval: Option<u64>,
+    /// Dump the worker process when a hung task is detected.
+    pub hang_dump: bool,
+    /// Run without the vmbus relay, offering only VTL2-implemented devices to
+    /// the guest.
+    pub no_relay: bool,
 }
 
 /// Bundle of config + runtime objects for hooking into the underhill remote
@@ -406,8 +429,13 @@ fn run(self, worker_rpc: mesh::Receiver<WorkerRpc<Self::State>>) -> anyhow::Resu
             self.servicing_correlation_id,
             self.vm_rpc,
             worker_rpc,
+            self.vm_liveness,
         ));
         tracing::info!("terminating worker");
+        // Stop watching for hangs, and release the heartbeat's hold on the GET
+        // thread so that it can exit.
+        drop(self.get_heartbeat_task);
+        drop(self.liveness);
         self.get_thread.join().unwrap();
         if let Some(state) = state {
             let params = UnderhillWorkerParameters {
@@ -517,7 +545,7 @@ async fn new_or_restart(
 
         // Build the VM.
         let mut vm = new_underhill_vm(
-            get_spawner,
+            get_spawner.clone(),
             &threadpool,
             early_init_driver,
             UhVmParams {
@@ -560,12 +588,34 @@ async fn new_or_restart(
             r.context("failed to restore")?;
         }
 
+        // Watch for hangs of the GET thread and of the VM control loop. Both
+        // tick from the GET thread's timers, since threadpool threads may be
+        // busy running VPs.
+        let liveness = LivenessWatchdog::new(get_client.clone(), params.env_cfg.hang_dump);
+        let get_heartbeat_task = get_spawner.spawn(
+            "GET heartbeat",
+            LivenessTicker::new(
+                &get_spawner,
+                liveness.register("get", GET_HANG_TIMEOUT),
+                LIVENESS_TICK_INTERVAL,
+            )
+            .run(),
+        );
+        let vm_liveness = LivenessTicker::new(
+            &get_spawner,
+            liveness.register("vm_control", VM_CONTROL_HANG_TIMEOUT),
+            LIVENESS_TICK_INTERVAL,
+        );
+
         Ok(Self {
             vm,
             env_cfg: params.env_cfg,
             vm_rpc: params.vm_rpc,
             get_thread,
             _get_watchdog_task: None,
+            liveness,
+            get_heartbeat_task,
+            vm_liveness,
             threadpool,
             is_post_servicing,
             servicing_correlation_id: correlation_id,
@@ -1336,7 +1386,14 @@ async fn new_underhill_vm(
         // If the guest is isolated but we are hiding this fact, then don't
         // start the relay--the guest will not be able to use relayed channels
         // since it will not be able to put their ring buffers in shared memory.
-        with_vmbus_relay = !hide_isolation;
+        //
+        // In the no-relay profile, the guest only sees the devices implemented
+        // in VTL2. This also disables the hvsock relay and the interception of
+        // host channels (such as the shutdown IC), which depend on the relay.
+        with_vmbus_relay = !hide_isolation && !env_cfg.no_relay;
+    }
+    if env_cfg.no_relay {
+        tracing::info!("running without the vmbus relay");
     }
 
     // also construct the VMGS nice and early, as much like the GET, it also
@@ -1797,7 +1854,13 @@ async fn new_underhill_vm(
 
     let periodic_telemetry_task = tp.spawn(
         "periodic_telemetry_collection",
-        crate::inspect_proc::periodic_telemetry_task(driver_source.simple()),
+        crate::inspect_proc::periodic_telemetry_task(
+            driver_source.simple(),
+            env_cfg.memory_telemetry_interval.map(Duration::from_secs),
+            shared_vis_pages_pool
+                .as_ref()
+                .map(|p| p.allocator_spawner()),
+        ),
     );
 
     let nvme_manager = if env_cfg.nvme_vfio {
@@ -2139,7 +2202,9 @@ async fn new_underhill_vm(
                         read_only,
                         disk_parameters,
                     } => {
-                        let disk = disk_from_disk_type(disk_type, read_only, &resolver).await?;
+                        let disk =
+                            disk_from_disk_type(disk_type, read_only, &resolver, &driver_source)
+                                .await?;
                         let scsi_disk = Arc::new(scsidisk::SimpleScsiDisk::new(
                             disk.clone(),
                             disk_parameters.unwrap_or_default(),
@@ -2603,11 +2668,20 @@ async fn new_underhill_vm(
 
         let vmbus = VmbusServerHandle::new(&tp, state_units.add("vmbus"), vmbus)?;
         if let Some((relay_channel, hvsock_relay)) = relay_channels {
+            let filter_policy = dps
+                .general
+                .vtl2_settings
+                .as_ref()
+                .map(|settings| relay_filter_policy(&settings.fixed.vmbus_relay_policy))
+                .unwrap_or_default();
+
             let vmbus_relay = vmbus_relay::HostVmbusTransport::new(
                 tp.driver(0).clone(),
                 Arc::clone(vmbus.control()),
                 relay_channel,
                 hvsock_relay,
+                filter_policy,
+                device_memory.clone(),
             )
             .await
             .expect("failed to create host vmbus transport");
@@ -2928,6 +3002,32 @@ async fn new_underhill_vm(
     Ok(loaded_vm)
 }
 
+/// Converts the VTL2 settings vmbus relay policy to the relay's filter policy.
+fn relay_filter_policy(
+    policy: &underhill_config::VmbusRelayPolicy,
+) -> vmbus_relay::ChannelFilterPolicy {
+    let action = |action| match action {
+        underhill_config::VmbusChannelAction::Allow => vmbus_relay::ChannelFilterAction::Allow,
+        underhill_config::VmbusChannelAction::Deny => vmbus_relay::ChannelFilterAction::Deny,
+        underhill_config::VmbusChannelAction::TerminateInVtl2 => {
+            vmbus_relay::ChannelFilterAction::TerminateInVtl2
+        }
+    };
+
+    vmbus_relay::ChannelFilterPolicy {
+        default_action: action(policy.default_action),
+        rules: policy
+            .rules
+            .iter()
+            .map(|rule| vmbus_relay::ChannelFilterRule {
+                interface_id: rule.interface_id,
+                instance_id: rule.instance_id,
+                action: action(rule.action),
+            })
+            .collect(),
+    }
+}
+
 fn validate_isolated_configuration(dps: &DevicePlatformSettings) -> Result<(), anyhow::Error> {
     let General {
         // Attested to
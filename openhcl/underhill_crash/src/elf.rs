@@ -52,3 +52,12 @@ pub struct Elf64_Nhdr {
 
 /// Auxiliary information
 pub const PT_NOTE: u32 = 4;
+
+/// Core file
+pub const ET_CORE: u16 = 4;
+
+/// Machine type of the running kernel
+#[cfg(target_arch = "x86_64")]
+pub const EM_HOST: u16 = 62; // EM_X86_64
+#[cfg(target_arch = "aarch64")]
+pub const EM_HOST: u16 = 183; // EM_AARCH64
@@ -19,12 +19,16 @@
 use crate::elf::Elf64_Ehdr;
 use crate::elf::Elf64_Nhdr;
 use crate::elf::Elf64_Phdr;
+use crate::elf::EM_HOST;
+use crate::elf::ET_CORE;
 use crate::elf::PT_NOTE;
 use crate::proto::check_header;
 use crate::proto::make_header;
+use flate2::write::GzEncoder;
 use fs_err::os::unix::fs::OpenOptionsExt;
 use fs_err::File;
 use futures::io::AllowStdIo;
+use futures::io::Cursor;
 use futures::AsyncRead;
 use futures::AsyncReadExt;
 use futures::FutureExt;
@@ -37,6 +41,7 @@
 use std::fs::OpenOptions;
 use std::io::ErrorKind;
 use std::io::Read;
+use std::io::Write;
 use std::os::fd::AsRawFd;
 use std::pin::pin;
 use tracing_subscriber::fmt::time::uptime;
@@ -138,7 +143,7 @@ async fn send_dump(
         anyhow::bail!("Nix dump files are not supported by the host");
     }
 
-    let max_dump_size = {
+    let (max_dump_size, compress) = {
         let cfg_rq = crash::DumpConfigRequestV1 {
             header: make_header(None, crash::MessageType::REQUEST_GET_NIX_DUMP_CONFIG_V1),
         };
@@ -150,14 +155,14 @@ async fn send_dump(
             anyhow::bail!("The host does not allow sending crash dump files");
         }
         let dump_type = cfg.dump_type;
-        if dump_type != crash::DumpType::ELF {
+        if dump_type != crash::DumpType::ELF && dump_type != crash::DumpType::ELF_GZIP {
             anyhow::bail!("The host does not accept ELF core dump files");
         }
 
-        cfg.max_dump_size
+        (cfg.max_dump_size, dump_type == crash::DumpType::ELF_GZIP)
     };
 
-    tracing::debug!(max_dump_size, "Got host config");
+    tracing::debug!(max_dump_size, compress, "Got host config");
 
     let dump_start_rq = crash::DumpStartRequestV1 {
         header: make_header(None, crash::MessageType::REQUEST_NIX_DUMP_START_V1),
@@ -184,6 +189,7 @@ async fn send_dump(
             dump_stream,
             dump_start_resp.header,
             max_dump_size as usize,
+            compress,
         );
 
         if let Err(e) = streamer.insert_kmsg_note(&mut buf).await {
@@ -194,19 +200,29 @@ async fn send_dump(
             tracing::error!("Error occurred while streaming dump: {:?}", e);
         }
 
+        if let Err(e) = streamer.flush().await {
+            tracing::error!("Error occurred while flushing dump: {:?}", e);
+        }
+
         if let Err(e) = streamer.complete(os_version).await {
             tracing::error!("Error occurred while completing dump: {:?}", e);
         }
 
         // Compute stats
         let wrote_bytes_total = streamer.wrote_bytes_total();
+        let sent_bytes_total = streamer.sent_bytes_total();
         let nanos = now.elapsed().as_nanos();
         let speed = if nanos != 0 {
             (wrote_bytes_total as u128) * 1_000_000_000 / nanos
         } else {
             0
         };
-        tracing::info!(size = wrote_bytes_total, speed, "Reported crash");
+        tracing::info!(
+            size = wrote_bytes_total,
+            sent = sent_bytes_total,
+            speed,
+            "Reported crash"
+        );
 
         Ok::<(), anyhow::Error>(())
     });
@@ -274,17 +290,27 @@ pub fn main() -> ! {
 
     let os_version_major = os_version.major();
     let os_version_minor = os_version.minor();
-    tracing::error!(
-        ?crate_revision,
-        ?options.comm,
-        ?options.pid,
-        ?options.tid,
-        ?options.sig,
-        ?os_version_major,
-        ?os_version_minor,
-        ?options.timeout,
-        "Process crashed"
-    );
+    if options.kernel {
+        tracing::error!(
+            ?crate_revision,
+            ?os_version_major,
+            ?os_version_minor,
+            ?options.timeout,
+            "Kernel crashed"
+        );
+    } else {
+        tracing::error!(
+            ?crate_revision,
+            ?options.comm,
+            ?options.pid,
+            ?options.tid,
+            ?options.sig,
+            ?os_version_major,
+            ?os_version_minor,
+            ?options.timeout,
+            "Process crashed"
+        );
+    }
 
     // The watchdog thread
 
@@ -297,12 +323,19 @@ pub fn main() -> ! {
     // Send the dump file
 
     if let Err(e) = block_with_io(|driver| async move {
-        let mut dump_stream = AllowStdIo::new(std::io::stdin());
         let pipe = vmbus_user_channel::message_pipe(
             &driver,
             vmbus_user_channel::open_uio_device(&crash::CRASHDUMP_GUID)?,
         )?;
-        send_dump(pipe, &mut dump_stream, &os_version).await?;
+        if options.kernel {
+            // There's no process to dump, so send an otherwise empty core
+            // file, which will get the kernel log inserted into it.
+            let mut dump_stream = Cursor::new(kernel_core_header());
+            send_dump(pipe, &mut dump_stream, &os_version).await?;
+        } else {
+            let mut dump_stream = AllowStdIo::new(std::io::stdin());
+            send_dump(pipe, &mut dump_stream, &os_version).await?;
+        }
 
         Ok::<(), anyhow::Error>(())
     }) {
@@ -313,6 +346,45 @@ pub fn main() -> ! {
     std::process::exit(libc::EXIT_SUCCESS)
 }
 
+/// Builds the headers of an ELF core file with no contents other than an
+/// empty notes segment, for reporting kernel crashes.
+fn kernel_core_header() -> Vec<u8> {
+    let ehdr_size = size_of::<Elf64_Ehdr>();
+    let phdr_size = size_of::<Elf64_Phdr>();
+
+    let mut e_ident = [0; 16];
+    // magic, 64-bit, little-endian, current version
+    e_ident[..7].copy_from_slice(b"\x7fELF\x02\x01\x01");
+    let ehdr = Elf64_Ehdr {
+        e_ident,
+        e_type: ET_CORE,
+        e_machine: EM_HOST,
+        e_version: 1,
+        e_entry: 0,
+        e_phoff: ehdr_size as u64,
+        e_shoff: 0,
+        e_flags: 0,
+        e_ehsize: ehdr_size as u16,
+        e_phentsize: phdr_size as u16,
+        e_phnum: 1,
+        e_shentsize: 0,
+        e_shnum: 0,
+        e_shstrndx: 0,
+    };
+    let notes_phdr = Elf64_Phdr {
+        p_type: PT_NOTE,
+        p_flags: 0,
+        p_offset: (ehdr_size + phdr_size) as u64,
+        p_vaddr: 0,
+        p_paddr: 0,
+        p_filesz: 0,
+        p_memsz: 0,
+        p_align: 0,
+    };
+
+    [ehdr.as_bytes(), notes_phdr.as_bytes()].concat()
+}
+
 /// provides useful functions for streaming a core dump
 /// and maintains state
 struct DumpStreamer<'a> {
@@ -321,9 +393,14 @@ struct DumpStreamer<'a> {
 
     header: Header,
     max_dump_size: usize,
+    /// Compresses the dump before it is sent, if the host asked for it.
+    encoder: Option<GzEncoder<Vec<u8>>>,
 
     read_bytes_total: usize,
+    /// Bytes of the (uncompressed) dump written so far.
     wrote_bytes_total: usize,
+    /// Bytes sent to the host so far.
+    sent_bytes_total: usize,
 }
 
 impl<'a> DumpStreamer<'a> {
@@ -332,14 +409,19 @@ fn new(
         dump_stream: &'a mut (impl AsyncRead + Unpin),
         header: Header,
         max_dump_size: usize,
+        compress: bool,
     ) -> Self {
         Self {
             dump_stream,
             writer,
             header,
             max_dump_size,
+            // favor speed, as the crash must be reported before the watchdog
+            // fires
+            encoder: compress.then(|| GzEncoder::new(Vec::new(), flate2::Compression::fast())),
             read_bytes_total: 0,
             wrote_bytes_total: 0,
+            sent_bytes_total: 0,
         }
     }
 
@@ -363,12 +445,33 @@ async fn read(&mut self, buf: &mut [u8], fill: bool) -> usize {
         n
     }
 
-    /// write data to the host
+    /// write data to the host, compressing it first if required
     async fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
-        if self.wrote_bytes_total < self.max_dump_size {
-            let can_write_bytes = if self.wrote_bytes_total + data.len() > self.max_dump_size {
+        self.wrote_bytes_total += data.len();
+        if let Some(encoder) = &mut self.encoder {
+            encoder.write_all(data)?;
+            let compressed = std::mem::take(encoder.get_mut());
+            self.send(&compressed).await
+        } else {
+            self.send(data).await
+        }
+    }
+
+    /// send any data still buffered by the compressor to the host
+    async fn flush(&mut self) -> anyhow::Result<()> {
+        if let Some(encoder) = self.encoder.take() {
+            let compressed = encoder.finish()?;
+            self.send(&compressed).await?;
+        }
+        Ok(())
+    }
+
+    /// send data to the host as-is
+    async fn send(&mut self, data: &[u8]) -> anyhow::Result<()> {
+        if self.sent_bytes_total < self.max_dump_size {
+            let can_write_bytes = if self.sent_bytes_total + data.len() > self.max_dump_size {
                 tracing::error!("Dump has been partially sent due to the dump size limit");
-                self.max_dump_size - self.wrote_bytes_total
+                self.max_dump_size - self.sent_bytes_total
             } else {
                 data.len()
             };
@@ -390,7 +493,7 @@ async fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
                         Some(&self.header),
                         crash::MessageType::REQUEST_NIX_DUMP_WRITE_V1,
                     ),
-                    offset: self.wrote_bytes_total as u64,
+                    offset: self.sent_bytes_total as u64,
                     size: data.len() as u32,
                 };
                 self.writer.send(dump_write_rq.as_bytes()).await?;
@@ -398,7 +501,7 @@ async fn write(&mut self, data: &[u8]) -> anyhow::Result<()> {
                 // Send the dump data
                 self.writer.send(data).await?;
 
-                self.wrote_bytes_total += data.len();
+                self.sent_bytes_total += data.len();
             }
         }
         Ok(())
@@ -616,4 +719,8 @@ async fn complete(&mut self, os_version: &OsVersionInfo) -> anyhow::Result<()> {
     fn wrote_bytes_total(&self) -> usize {
         self.wrote_bytes_total
     }
+
+    fn sent_bytes_total(&self) -> usize {
+        self.sent_bytes_total
+    }
 }
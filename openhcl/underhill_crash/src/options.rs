@@ -12,7 +12,12 @@
 // to save on compiled file size. We don't need all the features a crate can provide.
 /// underhill crash environment settings and command-line parameters.
 /// The order of command-line arguments is expected to be: {pid} {tid} {signal} {command line}
+///
+/// Alternatively, the single argument `kernel` reports a kernel crash, in which
+/// case there is no process to dump.
 pub struct Options {
+    /// Report a kernel crash, rather than a process crash
+    pub kernel: bool,
     /// PID of the process
     pub pid: u32,
     /// TID of the faulted thread
@@ -41,14 +46,21 @@ pub(crate) fn parse() -> Self {
                 .expect(Self::usage())
         };
 
-        let pid = parse_number(args.next());
-        let tid = parse_number(args.next());
-        let sig = parse_number(args.next());
-        let comm = args
-            .next()
-            .expect(Self::usage())
-            .to_string_lossy()
-            .into_owned();
+        let first = args.next();
+        let kernel = first.as_deref() == Some("kernel".as_ref());
+        let (pid, tid, sig, comm) = if kernel {
+            (0, 0, 0, "kernel".into())
+        } else {
+            let pid = parse_number(first);
+            let tid = parse_number(args.next());
+            let sig = parse_number(args.next());
+            let comm = args
+                .next()
+                .expect(Self::usage())
+                .to_string_lossy()
+                .into_owned();
+            (pid, tid, sig, comm)
+        };
 
         if args.next().is_some() {
             panic!("{}", Self::usage());
@@ -65,6 +77,7 @@ pub(crate) fn parse() -> Self {
         let verbose = verbose_var == "1" || verbose_var.to_ascii_lowercase() == "true";
 
         Self {
+            kernel,
             pid,
             tid,
             sig,
@@ -76,7 +89,7 @@ pub(crate) fn parse() -> Self {
     }
 
     fn usage() -> &'static str {
-        "Usage: {pid} {tid} {signal} {command line}
+        "Usage: {pid} {tid} {signal} {command line} | kernel
         Environment Variables:
         \tUNDERHILL_CRASH_TIMEOUT - Timeout duration in seconds, default 15
         \tUNDERHILL_CRASH_VERBOSE - Be verbose, default false"
@@ -0,0 +1,76 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Watches the kernel log for oopses, reporting them to the host via
+//! `underhill-crash`.
+//!
+//! Only oopses that the kernel survives can be reported this way. A kernel
+//! panic takes down user mode along with it.
+
+use std::io::ErrorKind;
+use std::io::Read;
+use std::process::Command;
+use std::time::Duration;
+
+const UNDERHILL_CRASH_PATH: &str = "/bin/underhill-crash";
+
+/// Prefixes of kernel log messages which start an oops report.
+const OOPS_PREFIXES: &[&str] = &[
+    "Oops",
+    "BUG:",
+    "kernel BUG at",
+    "general protection fault",
+    "Unable to handle kernel",
+];
+
+/// How long to wait after the start of an oops, so that the rest of the
+/// report (registers, stack, etc.) makes it into the kernel log before it
+/// gets sent.
+const OOPS_SETTLE_TIME: Duration = Duration::from_secs(1);
+
+/// Spawns a thread which reports the first kernel oops of this boot.
+pub fn spawn_oops_watcher() {
+    std::thread::spawn(|| {
+        if let Err(err) = watch_for_oops() {
+            log::error!("kernel oops watcher failed: {:#}", err);
+        }
+    });
+}
+
+fn watch_for_oops() -> anyhow::Result<()> {
+    // Read from the start of the log, to also catch oopses which happened
+    // before init started.
+    let mut kmsg = fs_err::File::open("/dev/kmsg")?;
+    let mut buf = vec![0; 8192];
+    loop {
+        let n = match kmsg.read(&mut buf) {
+            Ok(n) => n,
+            // The reader fell behind, and missed some messages.
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => continue,
+            Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        let Ok(entry) = kmsg::KmsgParsedEntry::new(&buf[..n]) else {
+            continue;
+        };
+
+        // Only consider messages from the kernel itself.
+        if entry.facility != 0 {
+            continue;
+        }
+
+        let message = entry.message.as_raw();
+        if OOPS_PREFIXES.iter().any(|p| message.starts_with(p)) {
+            log::error!("kernel oops detected, reporting crash");
+            std::thread::sleep(OOPS_SETTLE_TIME);
+
+            // Don't wait on the child: init's main loop reaps all processes,
+            // and might get to it first.
+            Command::new(UNDERHILL_CRASH_PATH).arg("kernel").spawn()?;
+
+            // Later oopses are likely fallout from the first one.
+            return Ok(());
+        }
+    }
+}
@@ -7,6 +7,7 @@
 // UNSAFETY: Calling libc functions to set up global system state.
 #![allow(unsafe_code)]
 
+mod kernel_crash;
 mod options;
 mod syslog;
 
@@ -567,6 +568,11 @@ fn do_main() -> anyhow::Result<()> {
         log::info!("registered vfio-pci as driver for nvme");
     }
 
+    // Crash dumps are disabled for CVMs, see `core_pattern` above.
+    if !underhill_confidentiality::confidential_filtering_enabled() {
+        kernel_crash::spawn_oops_watcher();
+    }
+
     // Start loading modules in parallel.
     std::thread::spawn(|| {
         if let Err(err) = load_modules("/lib/modules") {
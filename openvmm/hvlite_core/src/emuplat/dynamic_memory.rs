@@ -0,0 +1,52 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Dynamic memory backing over the guest memory manager.
+
+use async_trait::async_trait;
+use hyperv_dm_resources::DynamicMemoryBacking;
+use hyperv_dm_resources::DynamicMemoryBackingHandleKind;
+use hyperv_dm_resources::ResolvedDynamicMemoryBacking;
+use membacking::DynamicMemoryControl;
+use memory_range::MemoryRange;
+use std::convert::Infallible;
+use std::sync::Arc;
+use vm_resource::PlatformResource;
+use vm_resource::ResolveResource;
+
+/// Platform resolver for the dynamic memory backing.
+pub struct DynamicMemoryBackingResolver(pub DynamicMemoryControl);
+
+impl ResolveResource<DynamicMemoryBackingHandleKind, PlatformResource>
+    for DynamicMemoryBackingResolver
+{
+    type Output = ResolvedDynamicMemoryBacking;
+    type Error = Infallible;
+
+    fn resolve(
+        &self,
+        _resource: PlatformResource,
+        _input: (),
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(ResolvedDynamicMemoryBacking(Arc::new(
+            MemoryManagerBacking(self.0.clone()),
+        )))
+    }
+}
+
+struct MemoryManagerBacking(DynamicMemoryControl);
+
+#[async_trait]
+impl DynamicMemoryBacking for MemoryManagerBacking {
+    fn hot_add_range(&self) -> Option<MemoryRange> {
+        self.0.hot_add_range()
+    }
+
+    async fn hot_add(&self, range: MemoryRange) -> anyhow::Result<()> {
+        Ok(self.0.hot_add(range).await?)
+    }
+
+    async fn discard(&self, range: MemoryRange) -> anyhow::Result<()> {
+        Ok(self.0.discard(range).await?)
+    }
+}
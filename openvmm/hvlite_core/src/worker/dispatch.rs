@@ -54,6 +54,7 @@
 use inspect::Inspect;
 use membacking::GuestMemoryBuilder;
 use membacking::GuestMemoryManager;
+use membacking::HotAddError;
 use membacking::SharedMemoryBacking;
 use memory_range::MemoryRange;
 use mesh::error::RemoteError;
@@ -148,6 +149,9 @@
 
 const WDAT_PORT: u16 = 0x30;
 
+/// The alignment of hot-added RAM, matching Linux's memory block size.
+const HOT_ADD_ALIGNMENT: u64 = 128 << 20;
+
 /// Creates a thread to run low-performance devices on.
 pub fn new_device_thread() -> (JoinHandle<()>, DefaultDriver) {
     let pool = DefaultPool::new();
@@ -245,13 +249,14 @@ async fn open_simple_disk(
     resolver: &ResourceResolver,
     disk_type: Resource<DiskHandleKind>,
     read_only: bool,
+    driver_source: &VmTaskDriverSource,
 ) -> anyhow::Result<Disk> {
     let disk = resolver
         .resolve(
             disk_type,
             ResolveDiskParameters {
                 read_only,
-                _async_trait_workaround: &(),
+                driver_source,
             },
         )
         .await?;
@@ -382,7 +387,37 @@ struct InitializedVm {
 }
 
 trait BuildTopology<T: ArchTopology + Inspect> {
-    fn to_topology(&self) -> anyhow::Result<ProcessorTopology<T>>;
+    /// Builds the topology, assigning VPs to the virtual NUMA nodes in
+    /// `vnodes` (indexed by VP index).
+    fn to_topology(&self, vnodes: Vec<u32>) -> anyhow::Result<ProcessorTopology<T>>;
+}
+
+/// Returns the virtual NUMA node of each VP, indexed by VP index, or an empty
+/// list if the VM has no NUMA configuration.
+fn numa_vp_vnodes(memory: &MemoryConfig, proc_count: u32) -> anyhow::Result<Vec<u32>> {
+    let nodes = &memory.numa_nodes;
+    if nodes.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mem_size: u64 = nodes.iter().map(|node| node.mem_size).sum();
+    if mem_size != memory.mem_size {
+        anyhow::bail!(
+            "NUMA node memory sizes add up to {mem_size:#x} bytes, but the VM has {:#x} bytes of RAM",
+            memory.mem_size
+        );
+    }
+    let vnodes = nodes
+        .iter()
+        .enumerate()
+        .flat_map(|(vnode, node)| std::iter::repeat(vnode as u32).take(node.vp_count as usize))
+        .collect::<Vec<_>>();
+    if vnodes.len() != proc_count as usize {
+        anyhow::bail!(
+            "NUMA node processor counts add up to {}, but the VM has {proc_count} processors",
+            vnodes.len()
+        );
+    }
+    Ok(vnodes)
 }
 
 trait ExtractTopologyConfig {
@@ -413,9 +448,10 @@ fn to_config(&self) -> ProcessorTopologyConfig<X86TopologyConfig> {
 }
 
 impl BuildTopology<X86Topology> for ProcessorTopologyConfig<X86TopologyConfig> {
-    fn to_topology(&self) -> anyhow::Result<ProcessorTopology<X86Topology>> {
+    fn to_topology(&self, vnodes: Vec<u32>) -> anyhow::Result<ProcessorTopology<X86Topology>> {
         let mut builder = TopologyBuilder::from_host_topology()?;
         builder.apic_id_offset(self.arch.apic_id_offset);
+        builder.vnodes(vnodes);
         if let Some(smt) = self.enable_smt {
             builder.smt_enabled(smt);
         }
@@ -454,7 +490,7 @@ fn to_config(&self) -> ProcessorTopologyConfig<Aarch64TopologyConfig> {
 }
 
 impl BuildTopology<Aarch64Topology> for ProcessorTopologyConfig<Aarch64TopologyConfig> {
-    fn to_topology(&self) -> anyhow::Result<ProcessorTopology<Aarch64Topology>> {
+    fn to_topology(&self, vnodes: Vec<u32>) -> anyhow::Result<ProcessorTopology<Aarch64Topology>> {
         let gic = if let Some(gic_config) = &self.arch.gic_config {
             GicInfo {
                 gic_distributor_base: gic_config.gic_distributor_base,
@@ -468,6 +504,7 @@ fn to_topology(&self) -> anyhow::Result<ProcessorTopology<Aarch64Topology>> {
         };
 
         let mut builder = TopologyBuilder::new_aarch64(gic);
+        builder.vnodes(vnodes);
         if let Some(smt) = self.enable_smt {
             builder.smt_enabled(smt);
         }
@@ -515,7 +552,7 @@ struct LoadedVmInner {
     processor_topology: ProcessorTopology,
     hypervisor_cfg: HypervisorConfig,
     vmbus_redirect: bool,
-    vmbus_devices: Vec<SpawnedUnit<ChannelUnit<dyn VmbusDevice>>>,
+    vmbus_devices: Vec<(DeviceVtl, Guid, SpawnedUnit<ChannelUnit<dyn VmbusDevice>>)>,
 
     input_distributor: SpawnedUnit<InputDistributor>,
     vtl2_framebuffer_gpa_base: Option<u64>,
@@ -756,7 +793,9 @@ async fn new_with_hypervisor<P, H>(
             None
         };
 
-        let processor_topology = cfg.processor_topology.to_topology()?;
+        let vp_vnodes = numa_vp_vnodes(&cfg.memory, cfg.processor_topology.proc_count)
+            .context("invalid NUMA configuration")?;
+        let processor_topology = cfg.processor_topology.to_topology(vp_vnodes)?;
 
         let proto = hypervisor
             .new_partition(virt::ProtoPartitionConfig {
@@ -804,14 +843,51 @@ async fn new_with_hypervisor<P, H>(
         };
 
         // Choose the memory layout of the VM.
-        let mem_layout = MemoryLayout::new(
-            physical_address_size,
-            cfg.memory.mem_size,
-            &cfg.memory.mmio_gaps,
-            vtl2_range,
-        )
+        let mem_layout = if cfg.memory.numa_nodes.is_empty() {
+            MemoryLayout::new(
+                physical_address_size,
+                cfg.memory.mem_size,
+                &cfg.memory.mmio_gaps,
+                vtl2_range,
+            )
+        } else {
+            MemoryLayout::new_with_numa(
+                physical_address_size,
+                &cfg.memory
+                    .numa_nodes
+                    .iter()
+                    .map(|node| node.mem_size)
+                    .collect::<Vec<_>>(),
+                &cfg.memory.mmio_gaps,
+                vtl2_range,
+            )
+        }
         .context("invalid memory configuration")?;
 
+        // Reserve address space above everything else for hot-added RAM,
+        // aligned to the guest's hot-add granularity.
+        let hot_add_range = if cfg.memory.hot_add_size != 0 {
+            let start = mem_layout
+                .end_of_ram_or_mmio()
+                .max(vtl2_range.map_or(0, |r| r.end()))
+                .checked_next_multiple_of(HOT_ADD_ALIGNMENT);
+            let len = cfg
+                .memory
+                .hot_add_size
+                .checked_next_multiple_of(HOT_ADD_ALIGNMENT);
+            let end = start
+                .zip(len)
+                .and_then(|(start, len)| start.checked_add(len));
+            match (start, end) {
+                (Some(start), Some(end)) if end <= 1 << physical_address_size => {
+                    Some(MemoryRange::new(start..end))
+                }
+                _ => anyhow::bail!("not enough guest address space for hot-added memory"),
+            }
+        } else {
+            None
+        };
+
         let mut memory_builder = GuestMemoryBuilder::new();
         memory_builder = memory_builder
             .existing_backing(shared_memory)
@@ -823,6 +899,14 @@ async fn new_with_hypervisor<P, H>(
                     .unwrap_or_default(),
             )
             .prefetch_ram(cfg.memory.prefetch_memory)
+            .hot_add_range(hot_add_range)
+            .host_numa_nodes(
+                cfg.memory
+                    .numa_nodes
+                    .iter()
+                    .map(|node| node.host_node)
+                    .collect(),
+            )
             .x86_legacy_support(
                 matches!(cfg.load_mode, LoadMode::Pcat { .. }) || cfg.chipset.with_hyperv_vga,
             );
@@ -948,7 +1032,7 @@ async fn load(
         let mut resolver = ResourceResolver::new();
 
         let (vmgs_client, vmgs_task) = if let Some(vmgs_file) = cfg.vmgs_disk {
-            let disk = open_simple_disk(&resolver, vmgs_file, false).await?;
+            let disk = open_simple_disk(&resolver, vmgs_file, false, &driver_source).await?;
             let vmgs = if cfg.format_vmgs {
                 vmgs::Vmgs::format_new(disk)
                     .await
@@ -982,6 +1066,33 @@ async fn load(
         let halt_vps = Arc::new(halt_vps);
 
         resolver.add_resolver(vmm_core::platform_resolvers::HaltResolver(halt_vps.clone()));
+        resolver.add_resolver(emuplat::dynamic_memory::DynamicMemoryBackingResolver(
+            memory_manager.dynamic_memory_control(),
+        ));
+
+        // Allow devices with out-of-process backends (such as vhost-user) to
+        // map guest RAM.
+        #[cfg(unix)]
+        {
+            let ram = memory_manager
+                .export_ram(true)
+                .context("failed to export guest RAM")?;
+            resolver.add_resolver(vmm_core::platform_resolvers::SharedGuestMemoryResolver(
+                shared_memory_resources::SharedGuestMemory {
+                    fd: Arc::new(ram.mappable),
+                    regions: ram
+                        .ranges
+                        .into_iter()
+                        .map(
+                            |(range, offset)| shared_memory_resources::SharedMemoryRegion {
+                                range,
+                                offset,
+                            },
+                        )
+                        .collect(),
+                },
+            ));
+        }
 
         // Save the serial handles for restart.
         //
@@ -1240,9 +1351,10 @@ async fn load(
                         read_only,
                         disk_parameters,
                     } => {
-                        let disk = open_simple_disk(&resolver, disk_type, read_only)
-                            .await
-                            .context("failed to open IDE disk")?;
+                        let disk =
+                            open_simple_disk(&resolver, disk_type, read_only, &driver_source)
+                                .await
+                                .context("failed to open IDE disk")?;
 
                         // Only disks get accelerator channels. DVDs dont.
                         let scsi_disk = ScsiControllerDisk::new(Arc::new(SimpleScsiDisk::new(
@@ -1346,7 +1458,7 @@ async fn load(
                     read_only,
                 } = disk_cfg;
 
-                let disk = open_simple_disk(&resolver, disk_type, read_only)
+                let disk = open_simple_disk(&resolver, disk_type, read_only, &driver_source)
                     .await
                     .context("failed to open floppy disk")?;
                 tracing::trace!("floppy opened based on config into DriveRibbon");
@@ -1936,16 +2048,15 @@ async fn add_virtio_vpci(
                     .context("failed to find vmbus for vtl2"),
             }
             .with_context(|| format!("failed to resolve vmbus resource {}", resource.id()))?;
-            vmbus_devices.push(
-                offer_vmbus_device_handle_unit(
-                    &driver_source,
-                    &state_units,
-                    vmbus,
-                    &resolver,
-                    resource,
-                )
-                .await?,
-            );
+            let (instance_id, unit) = offer_vmbus_device_handle_unit(
+                &driver_source,
+                &state_units,
+                vmbus,
+                &resolver,
+                resource,
+            )
+            .await?;
+            vmbus_devices.push((vtl, instance_id, unit));
         }
 
         // add virtio devices
@@ -2337,6 +2448,7 @@ async fn load_firmware(&mut self, vtl2_only: bool) -> anyhow::Result<()> {
             } => {
                 let madt = acpi_builder.build_madt();
                 let srat = acpi_builder.build_srat();
+                let slit = acpi_builder.build_slit();
                 let pptt = cache_topology.is_some().then(|| acpi_builder.build_pptt());
                 let load_settings = super::vm_loaders::uefi::UefiLoadSettings {
                     debugging: enable_debugging,
@@ -2357,6 +2469,7 @@ async fn load_firmware(&mut self, vtl2_only: bool) -> anyhow::Result<()> {
                     load_settings,
                     &madt,
                     &srat,
+                    slit.as_deref(),
                     pptt.as_deref(),
                 )?;
 
@@ -2376,6 +2489,7 @@ async fn load_firmware(&mut self, vtl2_only: bool) -> anyhow::Result<()> {
             } => {
                 let madt = acpi_builder.build_madt();
                 let srat = acpi_builder.build_srat();
+                let slit = acpi_builder.build_slit();
                 const ENTROPY_SIZE: usize = 64;
                 let mut entropy = [0u8; ENTROPY_SIZE];
                 getrandom::getrandom(&mut entropy).unwrap();
@@ -2389,7 +2503,7 @@ async fn load_firmware(&mut self, vtl2_only: bool) -> anyhow::Result<()> {
                     acpi_tables: super::vm_loaders::igvm::AcpiTables {
                         madt: &madt,
                         srat: &srat,
-                        slit: None,
+                        slit: slit.as_deref(),
                         pptt: None,
                     },
                     vtl2_base_address,
@@ -2557,6 +2671,25 @@ enum Event {
                         })
                         .await
                     }
+                    VmRpc::Restore(rpc) => {
+                        rpc.handle_failable(|saved_state| {
+                            let this = &mut self;
+                            async move {
+                                if this.running {
+                                    anyhow::bail!("vm must be paused to restore");
+                                }
+                                if !this.inner.partition.supports_reset() {
+                                    anyhow::bail!("reset not supported");
+                                }
+                                let saved_state = saved_state
+                                    .parse()
+                                    .context("failed to decode saved state")?;
+                                this.reset(false).await?;
+                                this.restore(saved_state).await
+                            }
+                        })
+                        .await
+                    }
                     VmRpc::Nmi(rpc) => rpc.handle_sync(|vpindex| {
                         if vpindex < self.inner.processor_topology.vp_count() {
                             // Send an NMI MSI to the processor. We could raise
@@ -2592,7 +2725,7 @@ enum Event {
                                     DeviceVtl::Vtl2 => this.inner.vtl2_vmbus_server.as_ref(),
                                 }
                                 .context("no vmbus available")?;
-                                let device = offer_vmbus_device_handle_unit(
+                                let (instance_id, unit) = offer_vmbus_device_handle_unit(
                                     &this.inner.driver_source,
                                     &this.state_units,
                                     vmbus,
@@ -2600,13 +2733,36 @@ enum Event {
                                     resource,
                                 )
                                 .await?;
-                                this.inner.vmbus_devices.push(device);
+                                this.inner.vmbus_devices.push((vtl, instance_id, unit));
                                 this.state_units.start_stopped_units().await;
                                 anyhow::Ok(())
                             }
                         })
                         .await
                     }
+                    VmRpc::RemoveVmbusDevice(rpc) => {
+                        rpc.handle_failable(|(vtl, instance_id)| {
+                            let this = &mut self;
+                            async move {
+                                let index = this
+                                    .inner
+                                    .vmbus_devices
+                                    .iter()
+                                    .position(|(device_vtl, id, _)| {
+                                        *device_vtl == vtl && *id == instance_id
+                                    })
+                                    .with_context(|| {
+                                        format!("no vmbus device with instance ID {instance_id}")
+                                    })?;
+                                let (_, _, unit) = this.inner.vmbus_devices.remove(index);
+                                // Revoke the channel so that the guest sees the
+                                // device go away.
+                                unit.remove().await.revoke().await;
+                                anyhow::Ok(())
+                            }
+                        })
+                        .await
+                    }
                     VmRpc::ConnectHvsock(Rpc((mut ctx, service_id, vtl), response)) => {
                         if let Some(relay) = self.hvsock_relay(vtl) {
                             let fut = relay.connect(&mut ctx, service_id);
@@ -2655,6 +2811,21 @@ enum Event {
                     VmRpc::WriteMemory(rpc) => rpc.handle_failable_sync(|(gpa, bytes)| {
                         self.inner.gm.write_at(gpa, bytes.as_slice())
                     }),
+                    VmRpc::AddMemory(rpc) => {
+                        rpc.handle_failable(|size| {
+                            let dynamic_memory = self.inner.memory_manager.dynamic_memory_control();
+                            async move {
+                                // Keep the added ranges aligned to the guest's
+                                // memory block size so that the guest can
+                                // online them.
+                                let len = size
+                                    .checked_next_multiple_of(HOT_ADD_ALIGNMENT)
+                                    .ok_or(HotAddError::NoSpace(size))?;
+                                dynamic_memory.hot_add_any(len).await
+                            }
+                        })
+                        .await
+                    }
                 },
             }
         }
@@ -47,6 +47,7 @@ pub fn load_uefi(
     load_settings: UefiLoadSettings,
     madt: &[u8],
     srat: &[u8],
+    slit: Option<&[u8]>,
     pptt: Option<&[u8]>,
 ) -> Result<Vec<Register>, Error> {
     assert!(mem_layout.mmio().len() >= 2, "UEFI expects 2 MMIO gaps");
@@ -149,6 +150,10 @@ pub fn load_uefi(
         });
     }
 
+    if let Some(slit) = slit {
+        cfg.add_raw(config::BlobStructureType::Slit, slit);
+    }
+
     if let Some(pptt) = pptt {
         cfg.add_raw(config::BlobStructureType::Pptt, pptt);
     }
@@ -209,6 +209,24 @@ pub struct MemoryConfig {
     pub mem_size: u64,
     pub mmio_gaps: Vec<MemoryRange>,
     pub prefetch_memory: bool,
+    pub hot_add_size: u64,
+    /// The guest's virtual NUMA nodes. If empty, all RAM is in node 0.
+    ///
+    /// If present, the node sizes must add up to `mem_size`, and the node VP
+    /// counts must add up to the processor count.
+    pub numa_nodes: Vec<NumaNodeConfig>,
+}
+
+/// A virtual NUMA node.
+#[derive(Debug, Clone, MeshPayload)]
+pub struct NumaNodeConfig {
+    /// The size of the node's RAM, in bytes.
+    pub mem_size: u64,
+    /// The number of VPs in the node. VPs are assigned to nodes in order of VP
+    /// index.
+    pub vp_count: u32,
+    /// The host NUMA node to allocate the node's RAM from.
+    pub host_node: Option<u32>,
 }
 
 #[derive(Debug, MeshPayload, Default)]
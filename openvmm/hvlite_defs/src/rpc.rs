@@ -5,6 +5,7 @@
 
 use crate::config::DeviceVtl;
 use guid::Guid;
+use memory_range::MemoryRange;
 use mesh::error::RemoteError;
 use mesh::payload::message::ProtobufMessage;
 use mesh::rpc::FailableRpc;
@@ -19,18 +20,23 @@
 #[derive(MeshPayload)]
 pub enum VmRpc {
     Save(FailableRpc<(), ProtobufMessage>),
+    /// Resets the VM's devices and restores them from state previously
+    /// returned by [`VmRpc::Save`]. The VM must be paused.
+    Restore(FailableRpc<ProtobufMessage, ()>),
     Resume(Rpc<(), bool>),
     Pause(Rpc<(), bool>),
     ClearHalt(Rpc<(), bool>),
     Reset(FailableRpc<(), ()>),
     Nmi(Rpc<u32, ()>),
     AddVmbusDevice(FailableRpc<(DeviceVtl, Resource<VmbusDeviceHandleKind>), ()>),
+    RemoveVmbusDevice(FailableRpc<(DeviceVtl, Guid), ()>),
     ConnectHvsock(FailableRpc<(CancelContext, Guid, DeviceVtl), unix_socket::UnixStream>),
     PulseSaveRestore(Rpc<(), Result<(), PulseSaveRestoreError>>),
     StartReloadIgvm(FailableRpc<File, ()>),
     CompleteReloadIgvm(FailableRpc<bool, ()>),
     ReadMemory(FailableRpc<(u64, usize), Vec<u8>>),
     WriteMemory(FailableRpc<(u64, Vec<u8>), ()>),
+    AddMemory(FailableRpc<u64, MemoryRange>),
 }
 
 #[derive(Debug, MeshPayload, thiserror::Error)]
@@ -52,17 +58,20 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let s = match self {
             VmRpc::Reset(_) => "Reset",
             VmRpc::Save(_) => "Save",
+            VmRpc::Restore(_) => "Restore",
             VmRpc::Resume(_) => "Resume",
             VmRpc::Pause(_) => "Pause",
             VmRpc::ClearHalt(_) => "ClearHalt",
             VmRpc::Nmi(_) => "Nmi",
             VmRpc::AddVmbusDevice(_) => "AddVmbusDevice",
+            VmRpc::RemoveVmbusDevice(_) => "RemoveVmbusDevice",
             VmRpc::ConnectHvsock(_) => "ConnectHvsock",
             VmRpc::PulseSaveRestore(_) => "PulseSaveRestore",
             VmRpc::StartReloadIgvm(_) => "StartReloadIgvm",
             VmRpc::CompleteReloadIgvm(_) => "CompleteReloadIgvm",
             VmRpc::ReadMemory(_) => "ReadMemory",
             VmRpc::WriteMemory(_) => "WriteMemory",
+            VmRpc::AddMemory(_) => "AddMemory",
         };
         f.pad(s)
     }
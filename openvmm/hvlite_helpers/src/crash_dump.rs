@@ -22,6 +22,9 @@
 
 /// Spawns a crash dump handling task and returns a resource to instantiate a
 /// guest crash device.
+///
+/// Dumps are gzip-compressed by the guest before being sent, and are saved as
+/// `underhill.*.core.gz` files in `dump_path`.
 pub fn spawn_dump_handler(
     spawner: impl Spawn,
     dump_path: PathBuf,
@@ -31,19 +34,24 @@ pub fn spawn_dump_handler(
 
     let (send, recv) = channel::<FailableRpc<_, _>>();
     let task = spawner.spawn("crash_dumps", async move {
-        handle_dump_requests(&dump_path, recv).await
+        handle_dump_requests(&dump_path, true, recv).await
     });
     let config = GuestCrashDeviceHandle {
         request_dump: send,
         max_dump_size: max_file_size.unwrap_or(DEFAULT_MAX_DUMP_SIZE),
+        compress: true,
     };
     (config.into_resource(), task)
 }
 
 /// Handles dump requests from the crash dump device by opening files in the
 /// provided path.
+///
+/// `compressed` should match the setting the device was configured with, and
+/// determines the file extension used.
 pub async fn handle_dump_requests(
     dump_path: &Path,
+    compressed: bool,
     mut recv: mesh::Receiver<
         mesh::rpc::Rpc<OneshotReceiver<()>, Result<File, mesh::error::RemoteError>>,
     >,
@@ -58,7 +66,7 @@ pub async fn handle_dump_requests(
         rpc.handle_failable_sync(|done| {
             let tempfile = tempfile::Builder::new()
                 .prefix("underhill.")
-                .suffix(".core")
+                .suffix(if compressed { ".core.gz" } else { ".core" })
                 .tempfile_in(dump_path)
                 .context("failed to create file")?;
 
@@ -11,7 +11,9 @@
 ///
 /// If the file ends with .vhd and is a fixed VHD1, it will be opened using
 /// the user-mode VHD parser. Otherwise, if the file ends with .vhd or
-/// .vhdx, the file will be opened using the kernel-mode VHD parser.
+/// .vhdx, the file will be opened using the kernel-mode VHD parser on Windows.
+/// On other hosts, .vhdx files (including differencing chains) are opened
+/// using the native VHDX parser.
 pub fn open_disk_type(path: &Path, read_only: bool) -> anyhow::Result<Resource<DiskHandleKind>> {
     Ok(match path.extension().and_then(|s| s.to_str()) {
         Some("vhd") => {
@@ -45,7 +47,9 @@ pub fn open_disk_type(path: &Path, read_only: bool) -> anyhow::Result<Resource<D
                 ))
             }
             #[cfg(not(windows))]
-            anyhow::bail!("VHDX not supported on Linux");
+            {
+                Resource::new(disk_vhdx::open_chain(path, read_only)?)
+            }
         }
         Some("iso") if !read_only => {
             anyhow::bail!("iso file cannot be opened as read/write")
@@ -66,7 +70,7 @@ pub fn open_disk_type(path: &Path, read_only: bool) -> anyhow::Result<Resource<D
                 .write(!read_only)
                 .open(path)?;
 
-            Resource::new(disk_backend_resources::FileDiskHandle(file))
+            Resource::new(disk_backend_resources::FileDiskHandle::new(file))
         }
     })
 }
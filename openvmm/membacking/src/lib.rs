@@ -90,12 +90,18 @@ pub enum RemoteProcess {}
 /// On Unix, this is an empty (uninhabitable) enum.
 pub type RemoteProcess = sys::RemoteProcess;
 
+pub use mapping_manager::Protection;
 pub use memory_manager::DeviceMemoryMapper;
+pub use memory_manager::DynamicMemoryControl;
+pub use memory_manager::ExportRamError;
+pub use memory_manager::ExportedRam;
 pub use memory_manager::GuestMemoryBuilder;
 pub use memory_manager::GuestMemoryClient;
 pub use memory_manager::GuestMemoryManager;
+pub use memory_manager::HotAddError;
 pub use memory_manager::MemoryBuildError;
 pub use memory_manager::PartitionAttachError;
 pub use memory_manager::RamVisibility;
 pub use memory_manager::RamVisibilityControl;
 pub use memory_manager::SharedMemoryBacking;
+pub use region_manager::DirtyTrackingError;
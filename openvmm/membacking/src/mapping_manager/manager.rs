@@ -136,6 +136,43 @@ pub async fn remove_mappings(&self, range: MemoryRange) {
             .await
             .unwrap();
     }
+
+    /// Changes the access allowed to `range` through every VA mapper, in all
+    /// processes, returning once all of them have applied the change.
+    ///
+    /// The protection persists across mapping changes and also applies to
+    /// mappings established later. Partitions that map memory from a VA
+    /// mapper may observe the change as well.
+    pub async fn set_protection(&self, range: MemoryRange, protection: Protection) {
+        self.req_send
+            .call(MappingRequest::SetProtection, (range, protection))
+            .await
+            .unwrap();
+    }
+}
+
+/// The access allowed to guest memory through the VA mappers.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, MeshPayload, Inspect)]
+pub enum Protection {
+    /// No access.
+    NoAccess,
+    /// Read-only access.
+    ReadOnly,
+    /// Read-write access, if the memory is mapped writable. This is the
+    /// default.
+    ReadWrite,
+}
+
+impl Protection {
+    /// Returns whether a range with this protection is accessible, and if so,
+    /// whether it is writable, given the writability of the underlying mapping.
+    pub(crate) fn access(self, writable: bool) -> Option<bool> {
+        match self {
+            Protection::NoAccess => None,
+            Protection::ReadOnly => Some(false),
+            Protection::ReadWrite => Some(writable),
+        }
+    }
 }
 
 /// A mapping request message.
@@ -146,6 +183,7 @@ pub enum MappingRequest {
     SendMappings(MapperId, MemoryRange),
     AddMapping(Rpc<MappingParams, ()>),
     RemoveMappings(Rpc<MemoryRange, ()>),
+    SetProtection(Rpc<(MemoryRange, Protection), ()>),
     Inspect(inspect::Deferred),
 }
 
@@ -153,10 +191,22 @@ pub enum MappingRequest {
 struct MappingManagerTask {
     #[inspect(with = "inspect_mappings")]
     mappings: Vec<Mapping>,
+    /// Sorted, non-overlapping ranges with non-default protection.
+    #[inspect(with = "inspect_protections")]
+    protections: Vec<(MemoryRange, Protection)>,
     #[inspect(skip)]
     mappers: Mappers,
 }
 
+fn inspect_protections(protections: &Vec<(MemoryRange, Protection)>) -> impl '_ + Inspect {
+    inspect::adhoc(move |req| {
+        let mut resp = req.respond();
+        for (range, protection) in protections {
+            resp.field(&range.to_string(), protection);
+        }
+    })
+}
+
 fn inspect_mappings(mappings: &Vec<Mapping>) -> impl '_ + Inspect {
     inspect::adhoc(move |req| {
         let mut resp = req.respond();
@@ -166,13 +216,39 @@ fn inspect_mappings(mappings: &Vec<Mapping>) -> impl '_ + Inspect {
                 inspect::adhoc(|req| {
                     req.respond()
                         .field("writable", mapping.params.writable)
-                        .hex("file_offset", mapping.params.file_offset);
+                        .hex("file_offset", mapping.params.file_offset)
+                        .field("mapper_count", mapping.active_mappers.len())
+                        .field(
+                            "usage",
+                            inspect::adhoc(|req| inspect_usage(req, &mapping.params)),
+                        );
                 }),
             );
         }
     })
 }
 
+/// Reports the host memory usage of a mapping's backing memory, to help
+/// diagnose host memory pressure.
+fn inspect_usage(req: inspect::Request<'_>, params: &MappingParams) {
+    match sparse_mmap::shared_memory_usage(&params.mappable, params.file_offset, params.range.len())
+    {
+        Ok(usage) => {
+            req.respond()
+                .field("allocated_bytes", usage.allocated)
+                .field("resident_bytes", usage.resident)
+                // Allocated shared memory that is not resident has been
+                // swapped out.
+                .field(
+                    "swapped_bytes",
+                    usage.allocated.saturating_sub(usage.resident),
+                )
+                .field("huge_pages", usage.huge_pages);
+        }
+        Err(err) => req.value(err.to_string().into()),
+    }
+}
+
 struct Mapping {
     params: MappingParams,
     active_mappers: Vec<MapperId>,
@@ -205,13 +281,17 @@ struct MapperComm {
 /// A request to a VA mapper.
 #[derive(MeshPayload)]
 pub enum MapperRequest {
-    /// Map the specified mapping.
-    Map(MappingParams),
+    /// Map the specified mapping, then apply the non-default protections that
+    /// overlap it.
+    Map(MappingParams, Vec<(MemoryRange, Protection)>),
     /// There is no mapping for the specified range, so release anything waiting
     /// on such a mapping to arrive.
     NoMapping(MemoryRange),
     /// Unmap the specified range and send a response when it's done.
     Unmap(Rpc<MemoryRange, ()>),
+    /// Change the protection of any mapped memory in the specified range and
+    /// send a response when it's done.
+    Protect(Rpc<(MemoryRange, Protection), ()>),
 }
 
 impl MappingManagerTask {
@@ -221,6 +301,7 @@ fn new() -> Self {
                 mappers: Slab::new(),
             },
             mappings: Vec::new(),
+            protections: Vec::new(),
         }
     }
 
@@ -240,6 +321,10 @@ async fn run(&mut self, req_recv: &mut mesh::MpscReceiver<MappingRequest>) {
                 MappingRequest::RemoveMappings(rpc) => {
                     rpc.handle(|range| self.remove_mappings(range)).await
                 }
+                MappingRequest::SetProtection(rpc) => {
+                    rpc.handle(|(range, protection)| self.set_protection(range, protection))
+                        .await
+                }
                 MappingRequest::Inspect(deferred) => deferred.inspect(&mut *self),
             }
         }
@@ -288,7 +373,13 @@ fn send_mappings(&mut self, id: MapperId, mut range: MemoryRange) {
             let this_range = MemoryRange::new(range.start()..this_end);
             let req = if let Some(params) = params {
                 tracing::debug!(range = %this_range, full_range = %params.range, "sending mapping for range");
-                MapperRequest::Map(params)
+                let protections = self
+                    .protections
+                    .iter()
+                    .filter(|(range, _)| range.overlaps(&params.range))
+                    .copied()
+                    .collect();
+                MapperRequest::Map(params, protections)
             } else {
                 tracing::debug!(range = %this_range, "no mapping for range");
                 MapperRequest::NoMapping(this_range)
@@ -327,6 +418,46 @@ async fn remove_mappings(&mut self, range: MemoryRange) {
         mappers.dedup();
         self.mappers.invalidate(&mappers, range).await;
     }
+
+    async fn set_protection(&mut self, range: MemoryRange, protection: Protection) {
+        tracing::debug!(%range, ?protection, "setting protection");
+
+        // Replace any existing protections in the range, splitting the ones
+        // that extend past it.
+        let mut protections = Vec::with_capacity(self.protections.len() + 2);
+        for &(other, other_protection) in &self.protections {
+            if !other.overlaps(&range) {
+                protections.push((other, other_protection));
+                continue;
+            }
+            if other.start() < range.start() {
+                protections.push((
+                    MemoryRange::new(other.start()..range.start()),
+                    other_protection,
+                ));
+            }
+            if other.end() > range.end() {
+                protections.push((MemoryRange::new(range.end()..other.end()), other_protection));
+            }
+        }
+        if protection != Protection::ReadWrite {
+            protections.push((range, protection));
+        }
+        protections.sort_by_key(|(range, _)| range.start());
+        self.protections = protections;
+
+        // Mappers that receive a mapping in this range later will get the new
+        // protection along with it.
+        let mut mappers = self
+            .mappings
+            .iter()
+            .filter(|mapping| mapping.params.range.overlaps(&range))
+            .flat_map(|mapping| mapping.active_mappers.iter().copied())
+            .collect::<Vec<_>>();
+        mappers.sort();
+        mappers.dedup();
+        self.mappers.protect(&mappers, range, protection).await;
+    }
 }
 
 impl Mappers {
@@ -346,4 +477,21 @@ async fn invalidate(&self, ids: &[MapperId], range: MemoryRange) {
         }))
         .await;
     }
+
+    async fn protect(&self, ids: &[MapperId], range: MemoryRange, protection: Protection) {
+        tracing::debug!(mapper_count = ids.len(), %range, "sending protections");
+        join_all(ids.iter().map(|&MapperId(i)| async move {
+            if let Err(err) = self.mappers[i]
+                .req_send
+                .call(MapperRequest::Protect, (range, protection))
+                .await
+            {
+                tracing::warn!(
+                    error = &err as &dyn std::error::Error,
+                    "mapper dropped protect request"
+                );
+            }
+        }))
+        .await;
+    }
 }
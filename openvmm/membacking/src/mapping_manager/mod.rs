@@ -10,6 +10,7 @@
 
 pub use manager::MappingManager;
 pub use manager::MappingManagerClient;
+pub use manager::Protection;
 pub use mappable::Mappable;
 pub use va_mapper::VaMapper;
 pub use va_mapper::VaMapperError;
@@ -27,6 +27,7 @@
 use super::manager::MapperRequest;
 use super::manager::MappingParams;
 use super::manager::MappingRequest;
+use super::manager::Protection;
 use crate::RemoteProcess;
 use futures::executor::block_on;
 use guestmem::GuestMemoryAccess;
@@ -90,6 +91,8 @@ fn complete(&mut self, range: MemoryRange, writable: Option<bool>) -> Option<boo
 
 struct MapperTask {
     inner: Arc<MapperInner>,
+    /// The currently mapped ranges and whether each is writable.
+    mapped: Vec<(MemoryRange, bool)>,
 }
 
 impl MapperTask {
@@ -102,13 +105,17 @@ async fn run(mut self, mut req_recv: mesh::Receiver<MapperRequest>) {
                         .mapping
                         .unmap(range.start() as usize, range.len() as usize)
                         .expect("invalidate request should be valid");
+                    remove_mapped(&mut self.mapped, range);
                 }),
-                MapperRequest::Map(MappingParams {
-                    range,
-                    mappable,
-                    writable,
-                    file_offset,
-                }) => {
+                MapperRequest::Map(
+                    MappingParams {
+                        range,
+                        mappable,
+                        writable,
+                        file_offset,
+                    },
+                    protections,
+                ) => {
                     tracing::debug!(%range, "mapping received for range");
 
                     self.inner
@@ -122,8 +129,38 @@ async fn run(mut self, mut req_recv: mesh::Receiver<MapperRequest>) {
                         )
                         .expect("oom mapping file");
 
-                    self.wake_waiters(range, Some(writable));
+                    remove_mapped(&mut self.mapped, range);
+                    self.mapped.push((range, writable));
+
+                    // Apply the protections before waking any waiters so that
+                    // they cannot access the memory with the wrong protection.
+                    // Protections are sorted by address.
+                    let mut next = range.start();
+                    for &(protected, protection) in &protections {
+                        let protected = protected.intersection(&range);
+                        self.protect(protected, range, writable, protection);
+                        if next < protected.start() {
+                            self.wake_waiters(
+                                MemoryRange::new(next..protected.start()),
+                                Some(writable),
+                            );
+                        }
+                        self.wake_waiters(protected, protection.access(writable));
+                        next = protected.end();
+                    }
+                    if next < range.end() {
+                        self.wake_waiters(MemoryRange::new(next..range.end()), Some(writable));
+                    }
                 }
+                MapperRequest::Protect(rpc) => rpc.handle_sync(|(range, protection)| {
+                    tracing::debug!(%range, ?protection, "protect received");
+                    split_mapped(&mut self.mapped, range);
+                    for &(mapped, writable) in &self.mapped {
+                        if range.contains(&mapped) {
+                            self.protect(mapped, mapped, writable, protection);
+                        }
+                    }
+                }),
                 MapperRequest::NoMapping(range) => {
                     // Wake up waiters. They'll see a failure when they try to
                     // access the VA.
@@ -138,6 +175,28 @@ async fn run(mut self, mut req_recv: mesh::Receiver<MapperRequest>) {
         let _ = self.inner.mapping.unmap(0, self.inner.mapping.len());
     }
 
+    /// Applies `protection` to `range`, which is within the mapping at
+    /// `mapped`.
+    fn protect(
+        &self,
+        range: MemoryRange,
+        mapped: MemoryRange,
+        writable: bool,
+        protection: Protection,
+    ) {
+        assert!(mapped.contains(&range));
+        let access = protection.access(writable);
+        self.inner
+            .mapping
+            .protect(
+                range.start() as usize,
+                range.len() as usize,
+                access.is_some(),
+                access == Some(true),
+            )
+            .expect("protect request should be valid");
+    }
+
     fn wake_waiters(&mut self, range: MemoryRange, writable: Option<bool>) {
         let mut waiters = self.inner.waiters.lock();
         let waiters = waiters.as_mut().unwrap();
@@ -153,6 +212,30 @@ fn wake_waiters(&mut self, range: MemoryRange, writable: Option<bool>) {
     }
 }
 
+/// Splits the entries of `mapped` that straddle either end of `range`, so that
+/// each entry is either entirely within `range` or entirely outside it.
+fn split_mapped(mapped: &mut Vec<(MemoryRange, bool)>, range: MemoryRange) {
+    for addr in [range.start(), range.end()] {
+        // The entries do not overlap, so at most one can contain `addr`.
+        if let Some(i) = mapped
+            .iter()
+            .position(|(m, _)| m.start() < addr && addr < m.end())
+        {
+            let (m, writable) = mapped[i];
+            let (left, right) = m.split_at_offset(addr - m.start());
+            mapped[i] = (left, writable);
+            mapped.push((right, writable));
+        }
+    }
+}
+
+/// Removes `range` from `mapped`, keeping the parts of any entries that are
+/// outside `range`.
+fn remove_mapped(mapped: &mut Vec<(MemoryRange, bool)>, range: MemoryRange) {
+    split_mapped(mapped, range);
+    mapped.retain(|(m, _)| !range.contains(m));
+}
+
 #[derive(Debug, Error)]
 pub enum VaMapperError {
     #[error("failed to communicate with the memory manager")]
@@ -229,6 +312,7 @@ pub(crate) async fn new(
             .spawn({
                 let runner = MapperTask {
                     inner: inner.clone(),
+                    mapped: Vec::new(),
                 };
                 || block_on(runner.run(req_recv))
             })
@@ -299,3 +383,71 @@ fn page_fault(
         PageFaultAction::Retry
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::remove_mapped;
+    use super::split_mapped;
+    use memory_range::MemoryRange;
+    use std::ops::Range;
+
+    fn sorted(mapped: &[(MemoryRange, bool)]) -> Vec<(Range<u64>, bool)> {
+        let mut v: Vec<(Range<u64>, bool)> = mapped.iter().map(|&(r, w)| (r.into(), w)).collect();
+        v.sort_by_key(|(r, _)| r.start);
+        v
+    }
+
+    #[test]
+    fn test_split_mapped() {
+        let mut mapped = vec![
+            (MemoryRange::new(0..0x4000), true),
+            (MemoryRange::new(0x4000..0x8000), false),
+        ];
+        split_mapped(&mut mapped, MemoryRange::new(0x2000..0x5000));
+        assert_eq!(
+            sorted(&mapped),
+            [
+                (0..0x2000, true),
+                (0x2000..0x4000, true),
+                (0x4000..0x5000, false),
+                (0x5000..0x8000, false),
+            ]
+        );
+
+        // Splitting at existing boundaries is a no-op.
+        split_mapped(&mut mapped, MemoryRange::new(0x4000..0x8000));
+        assert_eq!(mapped.len(), 4);
+    }
+
+    #[test]
+    fn test_remove_mapped() {
+        let mut mapped = vec![
+            (MemoryRange::new(0..0x4000), true),
+            (MemoryRange::new(0x4000..0x8000), false),
+            (MemoryRange::new(0x10000..0x11000), true),
+        ];
+
+        // Removing the middle of an entry keeps both ends.
+        remove_mapped(&mut mapped, MemoryRange::new(0x1000..0x2000));
+        assert_eq!(
+            sorted(&mapped),
+            [
+                (0..0x1000, true),
+                (0x2000..0x4000, true),
+                (0x4000..0x8000, false),
+                (0x10000..0x11000, true),
+            ]
+        );
+
+        // Removing across entries trims the partially covered ones.
+        remove_mapped(&mut mapped, MemoryRange::new(0x3000..0x10000));
+        assert_eq!(
+            sorted(&mapped),
+            [
+                (0..0x1000, true),
+                (0x2000..0x3000, true),
+                (0x10000..0x11000, true),
+            ]
+        );
+    }
+}
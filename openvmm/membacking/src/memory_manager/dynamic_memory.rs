@@ -0,0 +1,235 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Support for adding and reclaiming guest RAM at runtime, for use by a
+//! dynamic memory device or the VM's management interface.
+
+use super::RamRegion;
+use super::RAM_PRIORITY;
+use crate::mapping_manager::Mappable;
+use crate::region_manager::AddRegionError;
+use crate::region_manager::MapParams;
+use crate::region_manager::RegionHandle;
+use crate::region_manager::RegionManagerClient;
+use futures::lock::Mutex;
+use inspect::Inspect;
+use memory_range::MemoryRange;
+use std::io;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// Errors returned by [`DynamicMemoryControl::hot_add`] and
+/// [`DynamicMemoryControl::hot_add_any`].
+#[derive(Debug, Error)]
+pub enum HotAddError {
+    /// The range is outside the hot-add range.
+    #[error("{0} is not within the hot-add range")]
+    OutOfRange(MemoryRange),
+    /// The range overlaps memory that has already been added.
+    #[error("{0} has already been added")]
+    AlreadyAdded(MemoryRange),
+    /// There is no free space of the requested size in the hot-add range.
+    #[error("no room for {0:#x} bytes in the hot-add range")]
+    NoSpace(u64),
+    /// Couldn't allocate memory.
+    #[error("failed to allocate memory")]
+    AllocationFailed(#[source] io::Error),
+    /// Couldn't create the RAM region.
+    #[error("failed to add ram region")]
+    Region(#[source] AddRegionError),
+}
+
+/// A client for adding and reclaiming guest RAM at runtime.
+///
+/// Hot-added RAM is backed by a new shared memory object per range, mapped at
+/// RAM priority. Reclaimed (ballooned) RAM stays mapped, but its backing
+/// memory is released to the host.
+#[derive(Debug, Clone, Inspect)]
+pub struct DynamicMemoryControl {
+    #[inspect(skip)]
+    region_manager: RegionManagerClient,
+    hot_add_range: Option<MemoryRange>,
+    #[inspect(skip)]
+    guest_ram: Mappable,
+    #[inspect(skip)]
+    ram_regions: Arc<Vec<RamRegion>>,
+    #[inspect(skip)]
+    hot_added: Arc<Mutex<Vec<HotAddedRegion>>>,
+    #[inspect(flatten)]
+    stats: Arc<ReclaimStats>,
+}
+
+#[derive(Debug, Default, Inspect)]
+struct ReclaimStats {
+    /// Total bytes of guest RAM whose backing memory was released to the host.
+    reclaimed_bytes: AtomicU64,
+    /// Number of ranges whose backing memory could not be released.
+    reclaim_failures: AtomicU64,
+}
+
+#[derive(Debug)]
+struct HotAddedRegion {
+    range: MemoryRange,
+    mappable: Mappable,
+    _handle: RegionHandle,
+}
+
+impl DynamicMemoryControl {
+    pub(super) fn new(
+        region_manager: RegionManagerClient,
+        hot_add_range: Option<MemoryRange>,
+        guest_ram: Mappable,
+        ram_regions: Arc<Vec<RamRegion>>,
+    ) -> Self {
+        Self {
+            region_manager,
+            hot_add_range,
+            guest_ram,
+            ram_regions,
+            hot_added: Default::default(),
+            stats: Default::default(),
+        }
+    }
+
+    /// Returns the guest physical address range reserved for hot-added RAM.
+    pub fn hot_add_range(&self) -> Option<MemoryRange> {
+        self.hot_add_range
+    }
+
+    /// Allocates new RAM for `range` and maps it into the guest.
+    pub async fn hot_add(&self, range: MemoryRange) -> Result<(), HotAddError> {
+        if !self
+            .hot_add_range
+            .is_some_and(|hot_add_range| hot_add_range.contains(&range))
+        {
+            return Err(HotAddError::OutOfRange(range));
+        }
+
+        let mut hot_added = self.hot_added.lock().await;
+        if hot_added.iter().any(|region| region.range.overlaps(&range)) {
+            return Err(HotAddError::AlreadyAdded(range));
+        }
+
+        self.add_region(&mut hot_added, range).await
+    }
+
+    /// Allocates `len` bytes of new RAM at the highest free address in the
+    /// hot-add range and maps it into the guest, returning the chosen range.
+    ///
+    /// Allocating from the top of the range keeps these ranges clear of a
+    /// dynamic memory device, which hot-adds from the bottom.
+    pub async fn hot_add_any(&self, len: u64) -> Result<MemoryRange, HotAddError> {
+        let hot_add_range = self.hot_add_range.ok_or(HotAddError::NoSpace(len))?;
+        let mut hot_added = self.hot_added.lock().await;
+        let mut added: Vec<_> = hot_added.iter().map(|region| region.range).collect();
+        added.sort_by_key(|range| std::cmp::Reverse(range.start()));
+        let mut end = hot_add_range.end();
+        for range in added {
+            if end - range.end() >= len {
+                break;
+            }
+            end = range.start();
+        }
+        if len == 0 || end - hot_add_range.start() < len {
+            return Err(HotAddError::NoSpace(len));
+        }
+        let range = MemoryRange::new(end - len..end);
+        self.add_region(&mut hot_added, range).await?;
+        Ok(range)
+    }
+
+    async fn add_region(
+        &self,
+        hot_added: &mut Vec<HotAddedRegion>,
+        range: MemoryRange,
+    ) -> Result<(), HotAddError> {
+        let mappable: Mappable = sparse_mmap::alloc_shared_memory(
+            range
+                .len()
+                .try_into()
+                .map_err(|_| HotAddError::AllocationFailed(io::ErrorKind::OutOfMemory.into()))?,
+        )
+        .map_err(HotAddError::AllocationFailed)?
+        .into();
+
+        let handle = self
+            .region_manager
+            .new_region("ram_hot_add".into(), range, RAM_PRIORITY)
+            .await
+            .map_err(HotAddError::Region)?;
+
+        handle
+            .add_mapping(MemoryRange::new(0..range.len()), mappable.clone(), 0, true)
+            .await;
+
+        handle
+            .map(MapParams {
+                writable: true,
+                executable: true,
+                prefetch: false,
+            })
+            .await;
+
+        hot_added.push(HotAddedRegion {
+            range,
+            mappable,
+            _handle: handle,
+        });
+        Ok(())
+    }
+
+    /// Releases the host memory backing `range`, which must be RAM. The range
+    /// reads as zero afterwards.
+    ///
+    /// On Linux, this punches a hole in the shared memory object, which
+    /// returns the pages to the host immediately. (`MADV_DONTNEED` would only
+    /// drop this process's mapping of them.)
+    pub async fn discard(&self, range: MemoryRange) -> io::Result<()> {
+        let r = self.discard_inner(range).await;
+        if r.is_ok() {
+            self.stats
+                .reclaimed_bytes
+                .fetch_add(range.len(), Ordering::Relaxed);
+        } else {
+            self.stats.reclaim_failures.fetch_add(1, Ordering::Relaxed);
+        }
+        r
+    }
+
+    async fn discard_inner(&self, range: MemoryRange) -> io::Result<()> {
+        let mut remaining = range.len();
+        let mut offset = 0;
+        for region in self.ram_regions.iter() {
+            let overlap = region.range.intersection(&range);
+            if !overlap.is_empty() {
+                sparse_mmap::discard_shared_memory(
+                    &self.guest_ram,
+                    offset + (overlap.start() - region.range.start()),
+                    overlap.len(),
+                )?;
+                remaining -= overlap.len();
+            }
+            offset += region.range.len();
+        }
+        for region in self.hot_added.lock().await.iter() {
+            let overlap = region.range.intersection(&range);
+            if !overlap.is_empty() {
+                sparse_mmap::discard_shared_memory(
+                    &region.mappable,
+                    overlap.start() - region.range.start(),
+                    overlap.len(),
+                )?;
+                remaining -= overlap.len();
+            }
+        }
+        if remaining != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{range} is not entirely ram"),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -4,15 +4,20 @@
 //! Hvlite's memory manager.
 
 mod device_memory;
+mod dynamic_memory;
 
 pub use device_memory::DeviceMemoryMapper;
+pub use dynamic_memory::DynamicMemoryControl;
+pub use dynamic_memory::HotAddError;
 
 use crate::mapping_manager::Mappable;
 use crate::mapping_manager::MappingManager;
 use crate::mapping_manager::MappingManagerClient;
+use crate::mapping_manager::Protection;
 use crate::mapping_manager::VaMapper;
 use crate::mapping_manager::VaMapperError;
 use crate::partition_mapper::PartitionMapper;
+use crate::region_manager::DirtyTrackingError;
 use crate::region_manager::MapParams;
 use crate::region_manager::RegionHandle;
 use crate::region_manager::RegionManager;
@@ -52,6 +57,9 @@ pub struct GuestMemoryManager {
 
     vtl0_alias_map_offset: Option<u64>,
     pin_mappings: bool,
+
+    /// Shared so that all users see the same set of hot-added ranges.
+    dynamic_memory: DynamicMemoryControl,
 }
 
 #[derive(Debug)]
@@ -89,6 +97,35 @@ pub enum MemoryBuildError {
     /// Memory layout incompatible with x86 legacy support.
     #[error("x86 support requires RAM to start at 0 and contain at least 1MB")]
     InvalidRamForX86,
+    /// Couldn't bind a NUMA node's RAM to its host node.
+    #[error("failed to bind RAM for vnode {vnode} to host node {host_node}")]
+    NumaBindFailed {
+        /// The virtual NUMA node.
+        vnode: u32,
+        /// The host NUMA node.
+        host_node: u32,
+        /// The error.
+        #[source]
+        err: std::io::Error,
+    },
+}
+
+/// Errors returned by [`GuestMemoryManager::export_ram`].
+#[derive(Error, Debug)]
+pub enum ExportRamError {
+    /// Couldn't create a new handle to the memory.
+    #[error("failed to share the ram backing")]
+    Share(#[source] std::io::Error),
+}
+
+/// Guest RAM exported for mapping by another process.
+#[derive(Debug, MeshPayload)]
+pub struct ExportedRam {
+    /// The shared memory object backing guest RAM. This can only be mapped
+    /// writable if the RAM was exported writable.
+    pub mappable: sparse_mmap::Mappable,
+    /// Each RAM range present at boot, and its offset within `mappable`.
+    pub ranges: Vec<(MemoryRange, u64)>,
 }
 
 /// A builder for [`GuestMemoryManager`].
@@ -98,6 +135,8 @@ pub struct GuestMemoryBuilder {
     prefetch_ram: bool,
     pin_mappings: bool,
     x86_legacy_support: bool,
+    hot_add_range: Option<MemoryRange>,
+    host_nodes: Vec<Option<u32>>,
 }
 
 impl GuestMemoryBuilder {
@@ -109,6 +148,8 @@ pub fn new() -> Self {
             pin_mappings: false,
             prefetch_ram: false,
             x86_legacy_support: false,
+            hot_add_range: None,
+            host_nodes: Vec::new(),
         }
     }
 
@@ -158,6 +199,26 @@ pub fn x86_legacy_support(mut self, enable: bool) -> Self {
         self
     }
 
+    /// Reserves a guest physical address range for RAM that is hot-added at
+    /// runtime via [`DynamicMemoryControl::hot_add`].
+    ///
+    /// The range must not overlap RAM, MMIO, or the VTL2 range.
+    pub fn hot_add_range(mut self, range: Option<MemoryRange>) -> Self {
+        self.hot_add_range = range;
+        self
+    }
+
+    /// Specifies the host NUMA node to allocate each virtual NUMA node's RAM
+    /// from, indexed by virtual node.
+    ///
+    /// Virtual nodes without an entry, or with `None`, use the host's default
+    /// allocation policy. This has no effect when using an existing backing,
+    /// which keeps the binding it was originally allocated with.
+    pub fn host_numa_nodes(mut self, host_nodes: Vec<Option<u32>>) -> Self {
+        self.host_nodes = host_nodes;
+        self
+    }
+
     /// Builds the memory backing, allocating memory if existing memory was not
     /// provided by [`existing_backing`](Self::existing_backing).
     pub async fn build(
@@ -169,13 +230,29 @@ pub async fn build(
         let memory = if let Some(memory) = self.existing_mapping {
             memory.guest_ram
         } else {
-            sparse_mmap::alloc_shared_memory(
+            let memory = sparse_mmap::alloc_shared_memory(
                 ram_size
                     .try_into()
                     .map_err(|_| MemoryBuildError::RamTooLarge(ram_size))?,
             )
-            .map_err(MemoryBuildError::AllocationFailed)?
-            .into()
+            .map_err(MemoryBuildError::AllocationFailed)?;
+
+            // RAM ranges are laid out in order in the backing object, so each
+            // range's offset is the total size of the ranges before it.
+            let mut offset = 0;
+            for range in mem_layout.ram() {
+                if let Some(&Some(host_node)) = self.host_nodes.get(range.vnode as usize) {
+                    sparse_mmap::bind_shared_memory(&memory, offset, range.range.len(), host_node)
+                        .map_err(|err| MemoryBuildError::NumaBindFailed {
+                            vnode: range.vnode,
+                            host_node,
+                            err,
+                        })?;
+                }
+                offset += range.range.len();
+            }
+
+            memory.into()
         };
 
         // Spawn a thread to handle memory requests.
@@ -188,8 +265,9 @@ pub async fn build(
             .spawn(move || pool.run())
             .unwrap();
 
-        let max_addr =
-            (mem_layout.end_of_ram_or_mmio()).max(mem_layout.vtl2_range().map_or(0, |r| r.end()));
+        let max_addr = (mem_layout.end_of_ram_or_mmio())
+            .max(mem_layout.vtl2_range().map_or(0, |r| r.end()))
+            .max(self.hot_add_range.map_or(0, |r| r.end()));
 
         let vtl0_alias_map_mask = if self.vtl0_alias_map {
             let mask = 1 << (mem_layout.physical_address_size() - 1);
@@ -285,15 +363,24 @@ pub async fn build(
             start += range.len();
         }
 
+        let ram_regions = Arc::new(ram_regions);
+        let dynamic_memory = DynamicMemoryControl::new(
+            region_manager.client().clone(),
+            self.hot_add_range,
+            memory.clone(),
+            ram_regions.clone(),
+        );
+
         let gm = GuestMemoryManager {
             guest_ram: memory,
             _thread: thread,
-            ram_regions: Arc::new(ram_regions),
+            ram_regions,
             mapping_manager,
             region_manager,
             va_mapper,
             vtl0_alias_map_offset: vtl0_alias_map_mask,
             pin_mappings: self.pin_mappings,
+            dynamic_memory,
         };
         Ok(gm)
     }
@@ -324,6 +411,15 @@ pub async fn guest_memory(&self) -> Result<GuestMemory, VaMapperError> {
             self.mapping_manager.new_mapper().await?,
         ))
     }
+
+    /// Changes the access allowed to `range` through every [`GuestMemory`]
+    /// object returned by [`Self::guest_memory`], in all processes.
+    ///
+    /// Returns once the change has been applied everywhere, so the caller can
+    /// rely on no further accesses violating the new protection.
+    pub async fn set_protection(&self, range: MemoryRange, protection: Protection) {
+        self.mapping_manager.set_protection(range, protection).await
+    }
 }
 
 // The region priority for RAM. Overrides anything else.
@@ -345,6 +441,11 @@ pub fn device_memory_mapper(&self) -> DeviceMemoryMapper {
         DeviceMemoryMapper::new(self.region_manager.client().clone())
     }
 
+    /// Returns an object for adding and reclaiming guest RAM at runtime.
+    pub fn dynamic_memory_control(&self) -> DynamicMemoryControl {
+        self.dynamic_memory.clone()
+    }
+
     /// Returns an object for manipulating the visibility state of different RAM
     /// regions.
     pub fn ram_visibility_control(&self) -> RamVisibilityControl {
@@ -353,6 +454,35 @@ pub fn ram_visibility_control(&self) -> RamVisibilityControl {
         }
     }
 
+    /// Enables or disables dirty page tracking for guest RAM.
+    ///
+    /// Only the RAM ranges present at boot are tracked, not hot-added RAM.
+    pub async fn set_dirty_tracking(&self, enable: bool) -> Result<(), DirtyTrackingError> {
+        for region in self.ram_regions.iter() {
+            region.handle.set_dirty_tracking(enable).await?;
+        }
+        Ok(())
+    }
+
+    /// Atomically fetches and clears the dirty page bitmap for each guest RAM
+    /// range, which must have dirty page tracking enabled via
+    /// [`Self::set_dirty_tracking`].
+    ///
+    /// Bit N of each bitmap is set if the 4KB page at offset `N * 4096` in the
+    /// range has been written since the previous call.
+    pub async fn get_and_clear_dirty_bitmaps(
+        &self,
+    ) -> Result<Vec<(MemoryRange, Vec<u64>)>, DirtyTrackingError> {
+        let mut bitmaps = Vec::with_capacity(self.ram_regions.len());
+        for region in self.ram_regions.iter() {
+            bitmaps.push((
+                region.range,
+                region.handle.get_and_clear_dirty_bitmap().await?,
+            ));
+        }
+        Ok(bitmaps)
+    }
+
     /// Returns the shared memory resources that can be used to reconstruct the
     /// memory backing.
     ///
@@ -365,6 +495,29 @@ pub fn shared_memory_backing(&self) -> SharedMemoryBacking {
         SharedMemoryBacking { guest_ram }
     }
 
+    /// Exports guest RAM so that another process, such as a vhost-user device
+    /// backend or an introspection tool, can map it.
+    ///
+    /// This always exports all of the RAM present at boot: the shared memory
+    /// object backing it cannot be restricted to a subset of its pages, so
+    /// the other process must be trusted with all of guest RAM. If `writable`
+    /// is false, the returned object can only be mapped read-only.
+    pub fn export_ram(&self, writable: bool) -> Result<ExportedRam, ExportRamError> {
+        let mappable = sparse_mmap::share_shared_memory(&self.guest_ram, writable)
+            .map_err(ExportRamError::Share)?;
+        let mut offset = 0;
+        let ranges = self
+            .ram_regions
+            .iter()
+            .map(|region| {
+                let start = offset;
+                offset += region.range.len();
+                (region.range, start)
+            })
+            .collect();
+        Ok(ExportedRam { mappable, ranges })
+    }
+
     /// Attaches the guest memory to a partition, mapping it to the guest
     /// physical address space.
     ///
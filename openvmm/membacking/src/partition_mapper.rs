@@ -31,6 +31,8 @@ pub enum PartitionMapperError {
     Map(#[source] virt::Error),
     #[error("failed to pin range to partition")]
     Pin(#[source] virt::Error),
+    #[error("failed to update dirty page tracking")]
+    DirtyTracking(#[source] virt::Error),
 }
 
 impl PartitionMapper {
@@ -142,6 +144,46 @@ pub fn unmap_region(&mut self, range: MemoryRange) {
         }
     }
 
+    /// Enables or disables dirty page tracking for the regions in `range`.
+    ///
+    /// As with `unmap_region`, `range` must fully contain any regions it
+    /// overlaps.
+    pub fn set_dirty_tracking(
+        &self,
+        range: MemoryRange,
+        enable: bool,
+    ) -> Result<(), PartitionMapperError> {
+        if let Some(partition) = self.partition.upgrade() {
+            partition
+                .set_dirty_tracking(
+                    range.start().checked_add(self.offset).unwrap(),
+                    range.len(),
+                    enable,
+                )
+                .map_err(PartitionMapperError::DirtyTracking)?;
+        }
+        Ok(())
+    }
+
+    /// Fetches and clears the dirty pages for the regions in `range`, setting
+    /// the corresponding bits in `bitmap`.
+    pub fn get_and_clear_dirty_bitmap(
+        &self,
+        range: MemoryRange,
+        bitmap: &mut [u64],
+    ) -> Result<(), PartitionMapperError> {
+        if let Some(partition) = self.partition.upgrade() {
+            partition
+                .get_and_clear_dirty_bitmap(
+                    range.start().checked_add(self.offset).unwrap(),
+                    range.len(),
+                    bitmap,
+                )
+                .map_err(PartitionMapperError::DirtyTracking)?;
+        }
+        Ok(())
+    }
+
     /// Notifies the partition that a new mapping has been mapped into a
     /// previously mapped region.
     pub async fn notify_new_mapping(&mut self, range: MemoryRange) {
@@ -11,6 +11,7 @@
 use inspect::Inspect;
 use inspect::InspectMut;
 use memory_range::MemoryRange;
+use mesh::error::RemoteError;
 use mesh::rpc::Rpc;
 use mesh::rpc::RpcSend;
 use mesh::MeshPayload;
@@ -45,6 +46,10 @@ struct Region {
     is_active: bool,
     params: RegionParams,
     mappings: Vec<RegionMapping>,
+    dirty_tracking: bool,
+    /// The region was remapped while dirty tracking was enabled, so the next
+    /// bitmap must report every page as dirty.
+    all_dirty: bool,
 }
 
 #[derive(Debug, MeshPayload)]
@@ -96,6 +101,7 @@ fn inspect_regions(regions: &Vec<Region>) -> impl '_ + Inspect {
                         .field("map_params", region.map_params)
                         .field("is_active", region.is_active)
                         .field("priority", region.params.priority)
+                        .field("dirty_tracking", region.dirty_tracking)
                         .field(
                             "mappings",
                             inspect::adhoc(|req| {
@@ -144,6 +150,8 @@ enum RegionRequest {
     AddPartition(
         LocalOnly<Rpc<PartitionMapper, Result<(), crate::partition_mapper::PartitionMapperError>>>,
     ),
+    SetDirtyTracking(Rpc<(RegionId, bool), Result<(), DirtyTrackingError>>),
+    GetDirtyBitmap(Rpc<RegionId, Result<Vec<u64>, DirtyTrackingError>>),
     Inspect(inspect::Deferred),
 }
 
@@ -170,6 +178,22 @@ pub enum AddRegionError {
     OverlapError { existing: String, new: String },
 }
 
+/// An error returned by the dirty page tracking methods of [`RegionHandle`].
+#[derive(Debug, Error, MeshPayload)]
+pub enum DirtyTrackingError {
+    #[error("dirty page tracking is not enabled for region {name}")]
+    NotEnabled { name: String },
+    #[error("partition failed to track dirty pages for region {name}")]
+    Partition {
+        name: String,
+        #[source]
+        source: RemoteError,
+    },
+}
+
+/// The page size used for dirty page bitmaps.
+const DIRTY_PAGE_SIZE: u64 = 4096;
+
 impl RegionManagerTask {
     fn new(mapping_manager: MappingManagerClient) -> Self {
         Self {
@@ -206,6 +230,12 @@ async fn run(&mut self, req_recv: &mut mesh::MpscReceiver<RegionRequest>) {
                 RegionRequest::UnmapRegion(rpc) => {
                     rpc.handle(|id| self.unmap_region(id, false)).await
                 }
+                RegionRequest::SetDirtyTracking(rpc) => {
+                    rpc.handle_sync(|(id, enable)| self.set_dirty_tracking(id, enable))
+                }
+                RegionRequest::GetDirtyBitmap(rpc) => {
+                    rpc.handle_sync(|id| self.get_dirty_bitmap(id))
+                }
                 RegionRequest::Inspect(deferred) => {
                     deferred.inspect(&mut *self);
                 }
@@ -224,6 +254,9 @@ async fn add_partition(
                 partition
                     .map_region(region.params.range, region.map_params.unwrap())
                     .await?;
+                if region.dirty_tracking {
+                    partition.set_dirty_tracking(region.params.range, true)?;
+                }
             }
         }
         self.inner.partitions.push(partition);
@@ -271,10 +304,67 @@ fn add_region(&mut self, params: RegionParams) -> Result<RegionId, AddRegionErro
             is_active: false,
             params,
             mappings: Vec::new(),
+            dirty_tracking: false,
+            all_dirty: false,
         });
         Ok(id)
     }
 
+    fn set_dirty_tracking(&mut self, id: RegionId, enable: bool) -> Result<(), DirtyTrackingError> {
+        let index = self.region_index(id);
+        let region = &mut self.regions[index];
+        if region.dirty_tracking == enable {
+            return Ok(());
+        }
+        if let Some(range) = region.active_range() {
+            for (i, partition) in self.inner.partitions.iter().enumerate() {
+                if let Err(err) = partition.set_dirty_tracking(range, enable) {
+                    // Restore the partitions that have already been updated.
+                    for partition in &self.inner.partitions[..i] {
+                        let _ = partition.set_dirty_tracking(range, !enable);
+                    }
+                    return Err(DirtyTrackingError::Partition {
+                        name: region.params.name.clone(),
+                        source: RemoteError::new(err),
+                    });
+                }
+            }
+        }
+        region.dirty_tracking = enable;
+        region.all_dirty = false;
+        Ok(())
+    }
+
+    fn get_dirty_bitmap(&mut self, id: RegionId) -> Result<Vec<u64>, DirtyTrackingError> {
+        let index = self.region_index(id);
+        let region = &mut self.regions[index];
+        if !region.dirty_tracking {
+            return Err(DirtyTrackingError::NotEnabled {
+                name: region.params.name.clone(),
+            });
+        }
+        let pages = region.params.range.len() / DIRTY_PAGE_SIZE;
+        let mut bitmap = vec![0; pages.div_ceil(64) as usize];
+        if let Some(range) = region.active_range() {
+            for partition in &self.inner.partitions {
+                partition
+                    .get_and_clear_dirty_bitmap(range, &mut bitmap)
+                    .map_err(|err| DirtyTrackingError::Partition {
+                        name: region.params.name.clone(),
+                        source: RemoteError::new(err),
+                    })?;
+            }
+        }
+        if region.all_dirty {
+            bitmap.fill(!0);
+            if pages % 64 != 0 {
+                *bitmap.last_mut().unwrap() = (1 << (pages % 64)) - 1;
+            }
+            region.all_dirty = false;
+        }
+        Ok(bitmap)
+    }
+
     /// Enables the highest priority region in `range`. Panics if any regions in
     /// `range` are already enabled.
     async fn enable_best_region(&mut self, mut range: MemoryRange) {
@@ -464,6 +554,17 @@ async fn enable_region(&mut self, region: &mut Region) {
                 .map_region(region.params.range, map_params)
                 .await
                 .expect("cannot recover from failed mapping");
+
+            if region.dirty_tracking {
+                partition
+                    .set_dirty_tracking(region.params.range, true)
+                    .expect("cannot recover from failed dirty tracking");
+            }
+        }
+
+        // The contents may have changed while the region was inactive.
+        if region.dirty_tracking {
+            region.all_dirty = true;
         }
 
         region.is_active = true;
@@ -616,6 +717,29 @@ pub async fn remove_mappings(&self, range: MemoryRange) {
             .await;
     }
 
+    /// Enables or disables dirty page tracking for this region.
+    ///
+    /// Tracking persists while the region is unmapped and remapped; the first
+    /// bitmap after a remap reports every page as dirty.
+    pub async fn set_dirty_tracking(&self, enable: bool) -> Result<(), DirtyTrackingError> {
+        self.req_send
+            .call(RegionRequest::SetDirtyTracking, (self.id.unwrap(), enable))
+            .await
+            .unwrap()
+    }
+
+    /// Atomically fetches and clears the dirty page bitmap for this region,
+    /// which must have dirty page tracking enabled.
+    ///
+    /// Bit N is set if the 4KB page at offset `N * 4096` in the region has been
+    /// written since the previous call.
+    pub async fn get_and_clear_dirty_bitmap(&self) -> Result<Vec<u64>, DirtyTrackingError> {
+        self.req_send
+            .call(RegionRequest::GetDirtyBitmap, self.id.unwrap())
+            .await
+            .unwrap()
+    }
+
     /// Tears the region down, waiting for all mappings to be unreferenced.
     pub async fn teardown(mut self) {
         let _ = self
@@ -703,4 +827,36 @@ async fn remove(&mut self, id: RegionId) {
 
         let _low = task.add(0, 0x1000..0x8000).await.unwrap();
     }
+
+    #[async_test]
+    async fn test_dirty_tracking(spawn: impl Spawn) {
+        let mm = MappingManager::new(spawn, 0x200000);
+        let mut task = RegionManagerTask::new(mm.client().clone());
+        let params = MapParams {
+            executable: true,
+            writable: true,
+            prefetch: false,
+        };
+        let id = task
+            .add_region(RegionParams {
+                priority: 0,
+                name: "ram".into(),
+                range: MemoryRange::new(0..0x43000),
+            })
+            .unwrap();
+        task.map_region(id, params).await;
+
+        task.get_dirty_bitmap(id).unwrap_err();
+        task.set_dirty_tracking(id, true).unwrap();
+        assert_eq!(task.get_dirty_bitmap(id).unwrap(), [0, 0]);
+
+        // Remapping the region marks every page dirty, once.
+        task.unmap_region(id, false).await;
+        task.map_region(id, params).await;
+        assert_eq!(task.get_dirty_bitmap(id).unwrap(), [!0, 0x7]);
+        assert_eq!(task.get_dirty_bitmap(id).unwrap(), [0, 0]);
+
+        task.set_dirty_tracking(id, false).unwrap();
+        task.get_dirty_bitmap(id).unwrap_err();
+    }
 }
@@ -27,6 +27,9 @@
 use hvlite_defs::config::Vtl2BaseAddressType;
 use hvlite_defs::config::X2ApicConfig;
 use hvlite_defs::config::DEFAULT_PCAT_BOOT_ORDER;
+use net_backend_resources::consomme::DhcpOption;
+use net_backend_resources::consomme::PortForward;
+use net_backend_resources::rate_limit::RateLimit;
 use std::ffi::OsString;
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -61,6 +64,33 @@ pub struct Options {
     #[clap(long)]
     pub prefetch: bool,
 
+    /// enable dynamic memory, adjusting guest RAM between `min` and `max` based
+    /// on the guest's memory pressure. `--memory` is the startup size.
+    /// `buffer` is the percentage of committed memory to keep available.
+    #[clap(
+        long,
+        value_name = "min=SIZE,max=SIZE[,buffer=PERCENT]",
+        requires("hv")
+    )]
+    pub dynamic_memory: Option<DynamicMemoryCli>,
+
+    /// reserve guest physical address space for up to SIZE bytes of RAM to be
+    /// added at runtime with the `add-memory` interactive command
+    #[clap(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_memory,
+        conflicts_with("dynamic_memory")
+    )]
+    pub hot_add_memory: Option<u64>,
+
+    /// add a virtual NUMA node with `mem` bytes of RAM and `vps` processors,
+    /// optionally allocating its RAM from host NUMA node `host`. Can be
+    /// specified multiple times; the node sizes must add up to `--memory` and
+    /// the processor counts to `--processors`.
+    #[clap(long, value_name = "mem=SIZE,vps=COUNT[,host=NODE]")]
+    pub numa_node: Vec<NumaNodeCli>,
+
     /// start in paused state
     #[clap(short = 'P', long)]
     pub paused: bool,
@@ -81,6 +111,12 @@ pub struct Options {
     #[clap(long)]
     pub hv: bool,
 
+    /// how the timesync IC sets the guest's time (off | once | continuous).
+    /// `once` sets the time when the guest boots; `continuous` also resets it
+    /// after the VM resumes and sends periodic samples to correct for drift.
+    #[clap(long, value_name = "MODE", default_value = "continuous")]
+    pub timesync: TimesyncCli,
+
     /// enable vtl2 - only supported in WHP and simulated without hypervisor support currently
     ///
     /// Currently implies --get.
@@ -140,16 +176,37 @@ pub struct Options {
 valid disk kinds:
     `mem:<len>`                    memory backed disk
         <len>: length of ramdisk, e.g.: `1G`
+    `memfile:<len>:\<path\>`         memory backed disk, persisted to a file
+        <len>: length of ramdisk, e.g.: `1G`
+        \<path\>: path to snapshot file, created if missing
     `memdiff:<disk>`               memory backed diff disk
         <disk>: lower disk, e.g.: `file:base.img`
+    `overlay:<disk>`               memory backed overlay that can be committed
+                                   to or discarded from the base disk with the
+                                   `overlay` interactive command
+        <disk>: base disk, e.g.: `file:base.img`
+    `checksum:\<path\>:<disk>`       verify sector checksums on read, failing on mismatch
+        \<path\>: path to checksum file, created if missing
+        <disk>: inner disk, e.g.: `file:disk.img`
+    `checksum-log:\<path\>:<disk>`   as `checksum`, logging mismatches instead
+        \<path\>: path to checksum file, created if missing
+        <disk>: inner disk, e.g.: `file:disk.img`
     `file:\<path\>`                  file-backed disk
         \<path\>: path to file
+    `uring:\<path\>`                 file-backed disk using io_uring (Linux only)
+        \<path\>: path to file
+    `uring-sqpoll:\<path\>`          as `uring`, with kernel submission queue polling
+        \<path\>: path to file
+    `writethrough:<disk>`          persist every write before completing it
+        <disk>: file-backed disk, e.g.: `file:disk.img`
 
 flags:
     `ro`                           open disk as read-only
     `dvd`                          specifies that device is cd/dvd and it is read_only
     `vtl2`                         assign this disk to VTL2
     `uh`                           relay this disk to VTL0 through Underhill
+    `nbd=<port>`                   export this disk over NBD on localhost, read-only
+                                   while the VM is running
 "#)]
     #[clap(long, value_name = "FILE")]
     pub disk: Vec<DiskCli>,
@@ -163,14 +220,35 @@ pub struct Options {
 valid disk kinds:
     `mem:<len>`                    memory backed disk
         <len>: length of ramdisk, e.g.: `1G`
+    `memfile:<len>:\<path\>`         memory backed disk, persisted to a file
+        <len>: length of ramdisk, e.g.: `1G`
+        \<path\>: path to snapshot file, created if missing
     `memdiff:<disk>`               memory backed diff disk
         <disk>: lower disk, e.g.: `file:base.img`
+    `overlay:<disk>`               memory backed overlay that can be committed
+                                   to or discarded from the base disk with the
+                                   `overlay` interactive command
+        <disk>: base disk, e.g.: `file:base.img`
+    `checksum:\<path\>:<disk>`       verify sector checksums on read, failing on mismatch
+        \<path\>: path to checksum file, created if missing
+        <disk>: inner disk, e.g.: `file:disk.img`
+    `checksum-log:\<path\>:<disk>`   as `checksum`, logging mismatches instead
+        \<path\>: path to checksum file, created if missing
+        <disk>: inner disk, e.g.: `file:disk.img`
     `file:\<path\>`                  file-backed disk
         \<path\>: path to file
+    `uring:\<path\>`                 file-backed disk using io_uring (Linux only)
+        \<path\>: path to file
+    `uring-sqpoll:\<path\>`          as `uring`, with kernel submission queue polling
+        \<path\>: path to file
+    `writethrough:<disk>`          persist every write before completing it
+        <disk>: file-backed disk, e.g.: `file:disk.img`
 
 flags:
     `ro`                           open disk as read-only
     `vtl2`                         assign this disk to VTL2
+    `nbd=<port>`                   export this disk over NBD on localhost, read-only
+                                   while the VM is running
 "#)]
     #[clap(long)]
     pub nvme: Vec<DiskCli>,
@@ -183,10 +261,43 @@ pub struct Options {
     #[clap(long)]
     pub nic: bool,
 
-    /// expose a virtual NIC with the given backend (consomme | dio | tap | none)
+    /// expose a virtual NIC with the given backend (consomme | dio | tap |
+    /// vhost-user | xdp | none)
     ///
     /// Prefix with `uh:` to add this NIC via Mana emulation through Underhill,
     /// or `vtl2:` to assign this NIC to VTL2.
+    ///
+    /// For vhost-user, specify the path of the backend's socket, e.g.
+    /// `vhost-user:/tmp/vhost-user0.sock`. Guest RAM is shared with the
+    /// backend process.
+    ///
+    /// For xdp, specify the host interface, e.g. `xdp:eth1`. Packets are only
+    /// received from the first `queues=<n>` queues of the interface, so its
+    /// queue count should be set to match (see `ethtool -L`). This requires
+    /// `CAP_NET_ADMIN`, `CAP_BPF`, and `CAP_IPC_LOCK`.
+    ///
+    /// For consomme, append `,<tcp|udp>:[<host address>:]<host port>[:<guest port>]`
+    /// (repeatable) to forward host ports to the guest, e.g.
+    /// `consomme,tcp:2222:22`. Forwarding rules can also be added and removed
+    /// at runtime with the `port-forward` interactive command.
+    ///
+    /// The consomme DHCP server can be configured by appending
+    /// `,lease=<mac>=<ip>`, `,dns=<ip>`, `,ntp=<ip>`, `,boot-server=<name>`, or
+    /// `,boot-file=<name>` (DHCP options 66 and 67), e.g.
+    /// `consomme,boot-server=10.0.0.1,boot-file=pxelinux.0` for PXE boot.
+    ///
+    /// Prefix with `rsc:` to allow the guest to enable receive segment
+    /// coalescing.
+    ///
+    /// Prefix with `pcap=<path>:` to capture the NIC's packets to a pcapng
+    /// file. Capture can also be started and stopped at runtime with the
+    /// `pcap` interactive command.
+    ///
+    /// Prefix with `egress=<rate>[,<burst>]:` or `ingress=<rate>[,<burst>]:`
+    /// to limit the bandwidth of packets sent or received by the guest, in
+    /// bytes per second, e.g. `egress=1000000,64000:consomme`. The limits can
+    /// be adjusted at runtime via the endpoint's `egress` and `ingress`
+    /// inspect nodes.
     #[clap(long)]
     pub net: Vec<NicConfigCli>,
 
@@ -238,35 +349,43 @@ pub struct Options {
     #[clap(long, conflicts_with("virtio_console"))]
     pub virtio_console_pci: bool,
 
-    /// COM1 binding (console | stderr | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | term[=\<program\>] | none)
+    /// COM1 binding (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none)
     #[clap(long, value_name = "SERIAL")]
     pub com1: Option<SerialConfigCli>,
 
-    /// COM2 binding (console | stderr | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | term[=\<program\>] | none)
+    /// COM2 binding (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none)
     #[clap(long, value_name = "SERIAL")]
     pub com2: Option<SerialConfigCli>,
 
-    /// COM3 binding (console | stderr | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | term[=\<program\>] | none)
+    /// COM3 binding (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none)
     #[clap(long, value_name = "SERIAL")]
     pub com3: Option<SerialConfigCli>,
 
-    /// COM4 binding (console | stderr | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | term[=\<program\>] | none)
+    /// COM4 binding (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none)
     #[clap(long, value_name = "SERIAL")]
     pub com4: Option<SerialConfigCli>,
 
-    /// virtio serial binding (console | stderr | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | term[=\<program\>] | none)
+    /// virtio serial binding (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none)
     #[clap(long, value_name = "SERIAL")]
     pub virtio_serial: Option<SerialConfigCli>,
 
-    /// vmbus com1 serial binding (console | stderr | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | term[=\<program\>] | none)
+    /// vmbus com1 serial binding (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none)
     #[structopt(long, value_name = "SERIAL")]
     pub vmbus_com1_serial: Option<SerialConfigCli>,
 
-    /// vmbus com2 serial binding (console | stderr | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | term[=\<program\>] | none)
+    /// vmbus com2 serial binding (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none)
     #[structopt(long, value_name = "SERIAL")]
     pub vmbus_com2_serial: Option<SerialConfigCli>,
 
-    /// debugcon binding (port:serial, where port is a u16, and serial is (console | stderr | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | term[=\<program\>] | none))
+    /// vmbus com3 serial binding (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none)
+    #[structopt(long, value_name = "SERIAL")]
+    pub vmbus_com3_serial: Option<SerialConfigCli>,
+
+    /// vmbus com4 serial binding (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none)
+    #[structopt(long, value_name = "SERIAL")]
+    pub vmbus_com4_serial: Option<SerialConfigCli>,
+
+    /// debugcon binding (port:serial, where port is a u16, and serial is (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | term[=\<program\>] | none))
     #[clap(long, value_name = "SERIAL")]
     pub debugcon: Option<DebugconSerialConfigCli>,
 
@@ -341,7 +460,7 @@ pub struct Options {
     pub virtio_pmem: Option<String>,
 
     /// expose a virtio network with the given backend (dio | vmnic | tap |
-    /// none)
+    /// vhost-user | xdp | none)
     ///
     /// Prefix with `uh:` to add this NIC via Mana emulation through Underhill,
     /// or `vtl2:` to assign this NIC to VTL2.
@@ -427,6 +546,11 @@ pub struct Options {
     #[clap(long)]
     pub mana: Vec<NicConfigCli>,
 
+    /// expose the RDMA function on emulated MANA devices (see --mana), with
+    /// queue pairs connected through a loopback fabric
+    #[clap(long, requires("mana"))]
+    pub mana_rdma: bool,
+
     /// use a specific hypervisor interface
     #[clap(long, value_parser = parse_hypervisor)]
     pub hypervisor: Option<Hypervisor>,
@@ -458,10 +582,29 @@ pub struct Options {
 valid disk kinds:
     `mem:<len>`                    memory backed disk
         <len>: length of ramdisk, e.g.: `1G`
+    `memfile:<len>:\<path\>`         memory backed disk, persisted to a file
+        <len>: length of ramdisk, e.g.: `1G`
+        \<path\>: path to snapshot file, created if missing
     `memdiff:<disk>`               memory backed diff disk
         <disk>: lower disk, e.g.: `file:base.img`
+    `overlay:<disk>`               memory backed overlay that can be committed
+                                   to or discarded from the base disk with the
+                                   `overlay` interactive command
+        <disk>: base disk, e.g.: `file:base.img`
+    `checksum:\<path\>:<disk>`       verify sector checksums on read, failing on mismatch
+        \<path\>: path to checksum file, created if missing
+        <disk>: inner disk, e.g.: `file:disk.img`
+    `checksum-log:\<path\>:<disk>`   as `checksum`, logging mismatches instead
+        \<path\>: path to checksum file, created if missing
+        <disk>: inner disk, e.g.: `file:disk.img`
     `file:\<path\>`                  file-backed disk
         \<path\>: path to file
+    `uring:\<path\>`                 file-backed disk using io_uring (Linux only)
+        \<path\>: path to file
+    `uring-sqpoll:\<path\>`          as `uring`, with kernel submission queue polling
+        \<path\>: path to file
+    `writethrough:<disk>`          persist every write before completing it
+        <disk>: file-backed disk, e.g.: `file:disk.img`
 
 flags:
     `ro`                           open disk as read-only
@@ -481,10 +624,29 @@ pub struct Options {
 valid disk kinds:
     `mem:<len>`                    memory backed disk
         <len>: length of ramdisk, e.g.: `1G`
+    `memfile:<len>:\<path\>`         memory backed disk, persisted to a file
+        <len>: length of ramdisk, e.g.: `1G`
+        \<path\>: path to snapshot file, created if missing
     `memdiff:<disk>`               memory backed diff disk
         <disk>: lower disk, e.g.: `file:base.img`
+    `overlay:<disk>`               memory backed overlay that can be committed
+                                   to or discarded from the base disk with the
+                                   `overlay` interactive command
+        <disk>: base disk, e.g.: `file:base.img`
+    `checksum:\<path\>:<disk>`       verify sector checksums on read, failing on mismatch
+        \<path\>: path to checksum file, created if missing
+        <disk>: inner disk, e.g.: `file:disk.img`
+    `checksum-log:\<path\>:<disk>`   as `checksum`, logging mismatches instead
+        \<path\>: path to checksum file, created if missing
+        <disk>: inner disk, e.g.: `file:disk.img`
     `file:\<path\>`                  file-backed disk
         \<path\>: path to file
+    `uring:\<path\>`                 file-backed disk using io_uring (Linux only)
+        \<path\>: path to file
+    `uring-sqpoll:\<path\>`          as `uring`, with kernel submission queue polling
+        \<path\>: path to file
+    `writethrough:<disk>`          persist every write before completing it
+        <disk>: file-backed disk, e.g.: `file:disk.img`
 
 flags:
     `ro`                           open disk as read-only
@@ -546,6 +708,68 @@ fn from_str(s: &str) -> Result<Self, Self::Err> {
     }
 }
 
+#[derive(Clone)]
+pub struct NumaNodeCli {
+    pub mem_size: u64,
+    pub vp_count: u32,
+    pub host_node: Option<u32>,
+}
+
+impl FromStr for NumaNodeCli {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mem_size = None;
+        let mut vp_count = None;
+        let mut host_node = None;
+        for opt in s.split(',') {
+            let (key, value) = opt.split_once('=').context("expected <key>=<value>")?;
+            match key {
+                "mem" => mem_size = Some(parse_memory(value)?),
+                "vps" => vp_count = Some(value.parse().context("invalid processor count")?),
+                "host" => host_node = Some(value.parse().context("invalid host node")?),
+                _ => anyhow::bail!("unknown NUMA node option: {key}"),
+            }
+        }
+        Ok(Self {
+            mem_size: mem_size.context("missing mem")?,
+            vp_count: vp_count.context("missing vps")?,
+            host_node,
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct DynamicMemoryCli {
+    pub minimum: u64,
+    pub maximum: u64,
+    pub buffer_percent: u32,
+}
+
+impl FromStr for DynamicMemoryCli {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut minimum = None;
+        let mut maximum = None;
+        let mut buffer_percent = 20;
+        for opt in s.split(',') {
+            let (key, value) = opt.split_once('=').context("expected <key>=<value>")?;
+            match key {
+                "min" => minimum = Some(parse_memory(value)?),
+                "max" => maximum = Some(parse_memory(value)?),
+                "buffer" => buffer_percent = value.parse().context("invalid buffer percentage")?,
+                _ => anyhow::bail!("unknown dynamic memory option: {key}"),
+            }
+        }
+        Ok(Self {
+            minimum: minimum.context("missing min")?,
+            maximum: maximum.context("missing max")?,
+            buffer_percent,
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct FsArgsWithOptions {
     /// The file system tag.
@@ -581,13 +805,20 @@ pub enum VirtioBusCli {
     Vpci,
 }
 
+#[derive(Copy, Clone, clap::ValueEnum)]
+pub enum TimesyncCli {
+    Off,
+    Once,
+    Continuous,
+}
+
 #[derive(clap::ValueEnum, Clone, Copy)]
 pub enum SecureBootTemplateCli {
     Windows,
     UefiCa,
 }
 
-fn parse_memory(s: &str) -> anyhow::Result<u64> {
+pub(crate) fn parse_memory(s: &str) -> anyhow::Result<u64> {
     || -> Option<u64> {
         let mut b = s.as_bytes();
         if s.ends_with('B') {
@@ -624,25 +855,74 @@ fn parse_number(s: &str) -> Result<u64, std::num::ParseIntError> {
 pub enum DiskCliKind {
     // mem:<len>
     Memory(u64),
+    // memfile:<len>:<path>
+    MemoryFile {
+        len: u64,
+        path: PathBuf,
+    },
     // memdiff:<kind>
     MemoryDiff(Box<DiskCliKind>),
+    // overlay:<kind>
+    Overlay(Box<DiskCliKind>),
     // prwrap:<kind>
     PersistentReservationsWrapper(Box<DiskCliKind>),
+    // prshared:<state_file>:<kind>
+    SharedPersistentReservationsWrapper {
+        state_file: PathBuf,
+        disk: Box<DiskCliKind>,
+    },
+    // checksum:<checksum_file>:<kind> or checksum-log:<checksum_file>:<kind>
+    Checksum {
+        checksum_file: PathBuf,
+        fail: bool,
+        disk: Box<DiskCliKind>,
+    },
     // file:<path>
     File(PathBuf),
-    // blob:<type>:<url>
+    // uring:<path> or uring-sqpoll:<path>
+    Uring {
+        path: PathBuf,
+        sq_poll: bool,
+    },
+    // writethrough:<kind>
+    WriteThrough(Box<DiskCliKind>),
+    // blob:<type>:<url> or blobcache:<cache_file>:<type>:<url>
     Blob {
         kind: BlobKind,
         url: String,
+        cache: Option<PathBuf>,
     },
-    // crypt:<cipher>:<key_file>:<kind>
+    // crypt:<cipher>:<key>:<kind>
     Crypt {
         cipher: DiskCipher,
-        key_file: PathBuf,
+        key: DiskKeyCli,
         disk: Box<DiskCliKind>,
     },
 }
 
+// <key_file> | keyring=<description>
+#[derive(Clone)]
+pub enum DiskKeyCli {
+    File(PathBuf),
+    #[cfg(target_os = "linux")]
+    Keyring(String),
+}
+
+impl FromStr for DiskKeyCli {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let key = match s.strip_prefix("keyring=") {
+            #[cfg(target_os = "linux")]
+            Some(description) => DiskKeyCli::Keyring(description.to_string()),
+            #[cfg(not(target_os = "linux"))]
+            Some(_) => anyhow::bail!("keyring keys are only supported on Linux"),
+            None => DiskKeyCli::File(PathBuf::from(s)),
+        };
+        Ok(key)
+    }
+}
+
 #[derive(ValueEnum, Clone, Copy)]
 pub enum DiskCipher {
     #[clap(name = "xts-aes-256")]
@@ -664,10 +944,48 @@ fn from_str(s: &str) -> anyhow::Result<Self> {
             None => DiskCliKind::File(PathBuf::from(s)),
             Some((kind, arg)) => match kind {
                 "mem" => DiskCliKind::Memory(parse_memory(arg)?),
+                "memfile" => {
+                    let (len, path) = arg.split_once(':').context("expected len:path")?;
+                    DiskCliKind::MemoryFile {
+                        len: parse_memory(len)?,
+                        path: PathBuf::from(path),
+                    }
+                }
                 "memdiff" => DiskCliKind::MemoryDiff(Box::new(arg.parse()?)),
+                "overlay" => DiskCliKind::Overlay(Box::new(arg.parse()?)),
                 "prwrap" => DiskCliKind::PersistentReservationsWrapper(Box::new(arg.parse()?)),
+                "prshared" => {
+                    let (state_file, kind) =
+                        arg.split_once(':').context("expected state_file:kind")?;
+                    DiskCliKind::SharedPersistentReservationsWrapper {
+                        state_file: PathBuf::from(state_file),
+                        disk: Box::new(kind.parse()?),
+                    }
+                }
+                "checksum" | "checksum-log" => {
+                    let (checksum_file, kind_arg) =
+                        arg.split_once(':').context("expected checksum_file:kind")?;
+                    DiskCliKind::Checksum {
+                        checksum_file: PathBuf::from(checksum_file),
+                        fail: kind == "checksum",
+                        disk: Box::new(kind_arg.parse()?),
+                    }
+                }
                 "file" => DiskCliKind::File(PathBuf::from(arg)),
-                "blob" => {
+                "uring" | "uring-sqpoll" => DiskCliKind::Uring {
+                    path: PathBuf::from(arg),
+                    sq_poll: kind == "uring-sqpoll",
+                },
+                "writethrough" => DiskCliKind::WriteThrough(Box::new(arg.parse()?)),
+                "blob" | "blobcache" => {
+                    let (cache, arg) = if kind == "blobcache" {
+                        let (cache, arg) = arg
+                            .split_once(':')
+                            .context("expected cache_file:kind:url")?;
+                        (Some(PathBuf::from(cache)), arg)
+                    } else {
+                        (None, arg)
+                    };
                     let (blob_kind, url) = arg.split_once(':').context("expected kind:url")?;
                     let blob_kind = match blob_kind {
                         "flat" => BlobKind::Flat,
@@ -677,17 +995,18 @@ fn from_str(s: &str) -> anyhow::Result<Self> {
                     DiskCliKind::Blob {
                         kind: blob_kind,
                         url: url.to_string(),
+                        cache,
                     }
                 }
                 "crypt" => {
                     let (cipher, (key, kind)) = arg
                         .split_once(':')
                         .and_then(|(cipher, arg)| Some((cipher, arg.split_once(':')?)))
-                        .context("expected cipher:key_file:kind")?;
+                        .context("expected cipher:key:kind")?;
                     DiskCliKind::Crypt {
                         cipher: ValueEnum::from_str(cipher, false)
                             .map_err(|err| anyhow::anyhow!("invalid cipher: {err}"))?,
-                        key_file: PathBuf::from(key),
+                        key: key.parse()?,
                         disk: Box::new(kind.parse()?),
                     }
                 }
@@ -717,6 +1036,7 @@ pub struct DiskCli {
     pub read_only: bool,
     pub is_dvd: bool,
     pub underhill: Option<UnderhillDiskSource>,
+    pub nbd_port: Option<u16>,
 }
 
 #[derive(Copy, Clone)]
@@ -736,6 +1056,7 @@ fn from_str(s: &str) -> anyhow::Result<Self> {
         let mut is_dvd = false;
         let mut underhill = None;
         let mut vtl = DeviceVtl::Vtl0;
+        let mut nbd_port = None;
         for opt in opts {
             let mut s = opt.split('=');
             let opt = s.next().unwrap();
@@ -750,6 +1071,10 @@ fn from_str(s: &str) -> anyhow::Result<Self> {
                 }
                 "uh" => underhill = Some(UnderhillDiskSource::Scsi),
                 "uh-nvme" => underhill = Some(UnderhillDiskSource::Nvme),
+                "nbd" => {
+                    let port = s.next().context("missing nbd port")?;
+                    nbd_port = Some(port.parse().context("invalid nbd port")?);
+                }
                 opt => anyhow::bail!("unknown option: '{opt}'"),
             }
         }
@@ -764,6 +1089,7 @@ fn from_str(s: &str) -> anyhow::Result<Self> {
             read_only,
             is_dvd,
             underhill,
+            nbd_port,
         })
     }
 }
@@ -868,7 +1194,7 @@ fn from_str(s: &str) -> Result<Self, Self::Err> {
     }
 }
 
-/// (console | stderr | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | none)
+/// (console | stderr | log[=\<path\>] | listen=\<path\> | listen=tcp:\<ip\>:\<port\> | listen=telnet:\<ip\>:\<port\> | listen=reconnect:\<path\> | listen=shared:\<pipe\> | listen=vsock:\<port\> | connect=\<path\> | connect=vsock:\<port\> | none)
 #[derive(Clone)]
 pub enum SerialConfigCli {
     None,
@@ -877,6 +1203,22 @@ pub enum SerialConfigCli {
     Stderr,
     Pipe(PathBuf),
     Tcp(SocketAddr),
+    Telnet(SocketAddr),
+    /// A Windows named pipe that multiple clients can attach to at once.
+    SharedPipe(PathBuf),
+    /// A bounded in-memory log, optionally mirrored to rotated files.
+    Log(Option<PathBuf>),
+    /// A Unix socket that the guest-facing port stays connected across.
+    Reconnecting {
+        path: PathBuf,
+        connect: bool,
+    },
+    /// A VSOCK port, listening for connections or connecting to the parent
+    /// partition, that the guest-facing port stays connected across.
+    Vsock {
+        port: u32,
+        connect: bool,
+    },
 }
 
 impl FromStr for SerialConfigCli {
@@ -898,10 +1240,39 @@ fn from_str(s: &str) -> Result<Self, Self::Err> {
                         .parse()
                         .map_err(|err| format!("invalid tcp address: {err}"))?;
                     SerialConfigCli::Tcp(addr)
+                } else if let Some(tcp) = s.strip_prefix("telnet:") {
+                    let addr = tcp
+                        .parse()
+                        .map_err(|err| format!("invalid tcp address: {err}"))?;
+                    SerialConfigCli::Telnet(addr)
+                } else if let Some(path) = s.strip_prefix("shared:") {
+                    SerialConfigCli::SharedPipe(path.into())
+                } else if let Some(port) = s.strip_prefix("vsock:") {
+                    SerialConfigCli::Vsock {
+                        port: parse_vsock_port(port)?,
+                        connect: false,
+                    }
+                } else if let Some(path) = s.strip_prefix("reconnect:") {
+                    SerialConfigCli::Reconnecting {
+                        path: path.into(),
+                        connect: false,
+                    }
                 } else {
                     SerialConfigCli::Pipe(s.into())
                 }
             }
+            "log" => SerialConfigCli::Log(None),
+            s if s.starts_with("log=") => {
+                SerialConfigCli::Log(Some(PathBuf::from(s.strip_prefix("log=").unwrap())))
+            }
+            s if s.starts_with("connect=vsock:") => SerialConfigCli::Vsock {
+                port: parse_vsock_port(s.strip_prefix("connect=vsock:").unwrap())?,
+                connect: true,
+            },
+            s if s.starts_with("connect=") => SerialConfigCli::Reconnecting {
+                path: s.strip_prefix("connect=").unwrap().into(),
+                connect: true,
+            },
             _ => return Err("invalid serial configuration".into()),
         };
 
@@ -909,29 +1280,75 @@ fn from_str(s: &str) -> Result<Self, Self::Err> {
     }
 }
 
+fn parse_vsock_port(s: &str) -> Result<u32, String> {
+    parse_number(s)
+        .map_err(|_| "could not parse vsock port".to_owned())?
+        .try_into()
+        .map_err(|_| "vsock port must be 32-bit".to_owned())
+}
+
 #[derive(Clone)]
 pub enum EndpointConfigCli {
     None,
-    Consomme { cidr: Option<String> },
-    Dio { id: Option<String> },
-    Tap { name: String },
+    Consomme {
+        cidr: Option<String>,
+        ports: Vec<PortForward>,
+        dhcp: Vec<DhcpOption>,
+    },
+    Dio {
+        id: Option<String>,
+    },
+    Tap {
+        name: String,
+    },
+    VhostUser {
+        path: String,
+    },
+    Xdp {
+        interface: String,
+    },
 }
 
 impl FromStr for EndpointConfigCli {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = s.split(',');
+        let s = rules.next().unwrap();
+        let mut ports = Vec::new();
+        let mut dhcp = Vec::new();
+        for rule in rules {
+            if rule.contains('=') {
+                dhcp.push(rule.parse::<DhcpOption>().map_err(|err| err.to_string())?);
+            } else {
+                ports.push(rule.parse::<PortForward>().map_err(|err| err.to_string())?);
+            }
+        }
         let ret = match s.split(':').collect::<Vec<_>>().as_slice() {
             ["none"] => EndpointConfigCli::None,
             ["consomme", s @ ..] => EndpointConfigCli::Consomme {
                 cidr: s.first().map(|&s| s.to_owned()),
+                ports,
+                dhcp,
             },
+            _ if !ports.is_empty() => {
+                return Err("port forwarding is only supported for consomme".into())
+            }
+            _ if !dhcp.is_empty() => {
+                return Err("dhcp options are only supported for consomme".into())
+            }
             ["dio", s @ ..] => EndpointConfigCli::Dio {
                 id: s.first().map(|s| (*s).to_owned()),
             },
             ["tap", name] => EndpointConfigCli::Tap {
                 name: (*name).to_owned(),
             },
+            ["vhost-user", path @ ..] if !path.is_empty() => EndpointConfigCli::VhostUser {
+                path: path.join(":"),
+            },
+            ["xdp", interface] => EndpointConfigCli::Xdp {
+                interface: (*interface).to_owned(),
+            },
             _ => return Err("invalid network backend".into()),
         };
 
@@ -945,6 +1362,10 @@ pub struct NicConfigCli {
     pub endpoint: EndpointConfigCli,
     pub max_queues: Option<u16>,
     pub underhill: bool,
+    pub pcap: Option<String>,
+    pub enable_rsc: bool,
+    pub egress: RateLimit,
+    pub ingress: RateLimit,
 }
 
 impl FromStr for NicConfigCli {
@@ -954,12 +1375,21 @@ fn from_str(mut s: &str) -> Result<Self, Self::Err> {
         let mut vtl = DeviceVtl::Vtl0;
         let mut max_queues = None;
         let mut underhill = false;
+        let mut pcap = None;
+        let mut enable_rsc = false;
+        let mut egress = RateLimit::default();
+        let mut ingress = RateLimit::default();
         while let Some((opt, rest)) = s.split_once(':') {
             if let Some((opt, val)) = opt.split_once('=') {
                 match opt {
                     "queues" => {
                         max_queues = Some(val.parse().map_err(|_| "failed to parse queue count")?);
                     }
+                    "pcap" => pcap = Some(val.to_owned()),
+                    "egress" => egress = val.parse::<RateLimit>().map_err(|err| err.to_string())?,
+                    "ingress" => {
+                        ingress = val.parse::<RateLimit>().map_err(|err| err.to_string())?
+                    }
                     _ => break,
                 }
             } else {
@@ -968,6 +1398,7 @@ fn from_str(mut s: &str) -> Result<Self, Self::Err> {
                         vtl = DeviceVtl::Vtl2;
                     }
                     "uh" => underhill = true,
+                    "rsc" => enable_rsc = true,
                     _ => break,
                 }
             }
@@ -984,6 +1415,10 @@ fn from_str(mut s: &str) -> Result<Self, Self::Err> {
             endpoint,
             max_queues,
             underhill,
+            pcap,
+            enable_rsc,
+            egress,
+            ingress,
         })
     }
 }
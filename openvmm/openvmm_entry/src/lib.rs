@@ -29,7 +29,10 @@
 use cli_args::UefiConsoleModeCli;
 use cli_args::VirtioBusCli;
 use disk_backend_resources::layer::DiskLayerHandle;
+use disk_backend_resources::layer::PersistentRamDiskLayerHandle;
 use disk_backend_resources::layer::RamDiskLayerHandle;
+use disk_backend_resources::OverlayDiskRequest;
+use disk_backend_resources::WriteCacheMode;
 use floppy_resources::FloppyDiskConfig;
 use framebuffer::FramebufferAccess;
 use framebuffer::FRAMEBUFFER_SIZE;
@@ -50,6 +53,7 @@
 use hvlite_defs::config::LateMapVtl0MemoryPolicy;
 use hvlite_defs::config::LoadMode;
 use hvlite_defs::config::MemoryConfig;
+use hvlite_defs::config::NumaNodeConfig;
 use hvlite_defs::config::ProcessorTopologyConfig;
 use hvlite_defs::config::SerialInformation;
 use hvlite_defs::config::VirtioBus;
@@ -79,7 +83,10 @@
 use mesh_worker::WorkerEvent;
 use mesh_worker::WorkerHandle;
 use meshworker::VmmMesh;
+use net_backend_resources::consomme::ConsommeRequest;
+use net_backend_resources::consomme::PortForward;
 use net_backend_resources::mac_address::MacAddress;
+use net_backend_resources::packet_capture::PacketCaptureRequest;
 use pal_async::pipe::PolledPipe;
 use pal_async::socket::PolledSocket;
 use pal_async::task::Spawn;
@@ -94,6 +101,7 @@
 use serial_io::SerialIo;
 use sparse_mmap::alloc_shared_memory;
 use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fmt::Write as _;
 use std::future::pending;
 use std::io;
@@ -171,8 +179,16 @@ struct VmResources {
     console_in: Option<Box<dyn AsyncWrite + Send + Unpin>>,
     framebuffer_access: Option<FramebufferAccess>,
     shutdown_ic: Option<mesh::Sender<hyperv_ic_resources::shutdown::ShutdownRpc>>,
+    kvp_ic: Option<mesh::Sender<hyperv_ic_resources::kvp::KvpRpc>>,
+    vss_ic: Option<mesh::Sender<hyperv_ic_resources::vss::VssRpc>>,
+    fcopy_ic: Option<mesh::Sender<hyperv_ic_resources::fcopy::FcopyRpc>>,
     scsi_rpc: Option<mesh::Sender<ScsiControllerRequest>>,
     ged_rpc: Option<mesh::Sender<get_resources::ged::GuestEmulationRequest>>,
+    serial_logs: BTreeMap<String, mesh::Sender<Rpc<(), Vec<u8>>>>,
+    nbd_vm_running: Option<mesh::CellUpdater<bool>>,
+    overlay_disks: Vec<mesh::Sender<OverlayDiskRequest>>,
+    consomme_nics: BTreeMap<usize, mesh::Sender<ConsommeRequest>>,
+    packet_captures: BTreeMap<usize, mesh::Sender<PacketCaptureRequest>>,
     #[cfg(windows)]
     switch_ports: Vec<vmswitch::kernel::SwitchPort>,
 }
@@ -202,6 +218,7 @@ fn vm_config_from_command_line(
     };
 
     let console_state: RefCell<Option<ConsoleState<'_>>> = RefCell::new(None);
+    let serial_logs = RefCell::new(BTreeMap::new());
     let setup_serial = |name: &str, cli_cfg, device| -> anyhow::Result<_> {
         Ok(match cli_cfg {
             SerialConfigCli::Console => {
@@ -245,6 +262,24 @@ fn vm_config_from_command_line(
             SerialConfigCli::Tcp(addr) => {
                 Some(serial_io::bind_tcp_serial(&addr).context("failed to bind serial")?)
             }
+            SerialConfigCli::Telnet(addr) => {
+                Some(serial_io::bind_telnet_serial(&addr).context("failed to bind serial")?)
+            }
+            SerialConfigCli::SharedPipe(path) => {
+                Some(serial_io::bind_shared_serial(&path).context("failed to bind serial")?)
+            }
+            SerialConfigCli::Log(path) => {
+                let (send, recv) = mesh::channel();
+                serial_logs.borrow_mut().insert(name.to_owned(), send);
+                Some(serial_io::serial_logger(path.as_deref(), recv))
+            }
+            SerialConfigCli::Reconnecting { path, connect } => Some(
+                serial_io::reconnecting_serial(&path, connect)
+                    .context("failed to set up reconnecting serial")?,
+            ),
+            SerialConfigCli::Vsock { port, connect } => Some(
+                serial_io::vsock_serial(port, connect).context("failed to set up vsock serial")?,
+            ),
             SerialConfigCli::NewConsole(app) => {
                 let path = console_relay::random_console_path();
                 let config =
@@ -288,7 +323,16 @@ fn vm_config_from_command_line(
                     .detach();
                 Some(io.config)
             }
-            SerialConfigCli::Tcp(_addr) => anyhow::bail!("TCP virtio serial not supported"),
+            SerialConfigCli::Tcp(_addr) | SerialConfigCli::Telnet(_addr) => {
+                anyhow::bail!("TCP virtio serial not supported")
+            }
+            SerialConfigCli::Reconnecting { .. } | SerialConfigCli::Vsock { .. } => {
+                anyhow::bail!("reconnecting virtio serial not supported")
+            }
+            SerialConfigCli::SharedPipe(_) => {
+                anyhow::bail!("shared pipe virtio serial not supported")
+            }
+            SerialConfigCli::Log(_) => anyhow::bail!("logging virtio serial not supported"),
             SerialConfigCli::NewConsole(app) => {
                 let path = console_relay::random_console_path();
 
@@ -402,6 +446,38 @@ fn vm_config_from_command_line(
     } else {
         false
     };
+    if let Some(vmbus_com3_cfg) = setup_serial(
+        "vmbus_com3",
+        opt.vmbus_com3_serial
+            .clone()
+            .unwrap_or(SerialConfigCli::None),
+        "vmbus_com3",
+    )? {
+        vmbus_devices.push((
+            openhcl_vtl,
+            VmbusSerialDeviceHandle {
+                port: VmbusSerialPort::Com3,
+                backend: vmbus_com3_cfg,
+            }
+            .into_resource(),
+        ));
+    }
+    if let Some(vmbus_com4_cfg) = setup_serial(
+        "vmbus_com4",
+        opt.vmbus_com4_serial
+            .clone()
+            .unwrap_or(SerialConfigCli::None),
+        "vmbus_com4",
+    )? {
+        vmbus_devices.push((
+            openhcl_vtl,
+            VmbusSerialDeviceHandle {
+                port: VmbusSerialPort::Com4,
+                backend: vmbus_com4_cfg,
+            }
+            .into_resource(),
+        ));
+    }
     let debugcon_cfg = setup_serial(
         "debugcon",
         opt.debugcon
@@ -411,7 +487,10 @@ fn vm_config_from_command_line(
         "debugcon",
     )?;
 
-    let mut resources = VmResources::default();
+    let mut resources = VmResources {
+        serial_logs: serial_logs.into_inner(),
+        ..Default::default()
+    };
     let mut console_str = "";
     if let Some(ConsoleState { device, input }) = console_state.into_inner() {
         resources.console_in = Some(input);
@@ -447,6 +526,7 @@ fn vm_config_from_command_line(
         read_only,
         is_dvd,
         underhill,
+        nbd_port,
     } in &opt.disk
     {
         storage.add(
@@ -456,6 +536,7 @@ fn vm_config_from_command_line(
             kind,
             is_dvd,
             read_only,
+            nbd_port,
         )?;
     }
 
@@ -474,6 +555,7 @@ fn vm_config_from_command_line(
             kind,
             is_dvd,
             read_only,
+            None,
         )?;
     }
 
@@ -483,6 +565,7 @@ fn vm_config_from_command_line(
         read_only,
         is_dvd,
         underhill,
+        nbd_port,
     } in &opt.nvme
     {
         storage.add(
@@ -492,6 +575,7 @@ fn vm_config_from_command_line(
             kind,
             is_dvd,
             read_only,
+            nbd_port,
         )?;
     }
 
@@ -504,7 +588,7 @@ fn vm_config_from_command_line(
                 read_only,
             } = disk;
             Ok(FloppyDiskConfig {
-                disk_type: disk_open(kind, read_only)?,
+                disk_type: disk_open(kind, read_only, &mut resources.overlay_disks)?,
                 read_only,
             })
         })
@@ -528,7 +612,13 @@ fn vm_config_from_command_line(
                     subordinate_instance_id: None,
                     max_sub_channels: None,
                 });
-                (vpci_instance_id, GdmaDeviceHandle { vports: Vec::new() })
+                (
+                    vpci_instance_id,
+                    GdmaDeviceHandle {
+                        vports: Vec::new(),
+                        rdma: false,
+                    },
+                )
             });
             mana.1.vports.push(VportDefinition {
                 mac_address: vport.mac_address,
@@ -543,9 +633,17 @@ fn vm_config_from_command_line(
         let nic_config = parse_endpoint(
             &NicConfigCli {
                 vtl: DeviceVtl::Vtl0,
-                endpoint: EndpointConfigCli::Consomme { cidr: None },
+                endpoint: EndpointConfigCli::Consomme {
+                    cidr: None,
+                    ports: Vec::new(),
+                    dhcp: Vec::new(),
+                },
                 max_queues: None,
                 underhill: false,
+                pcap: None,
+                enable_rsc: false,
+                egress: Default::default(),
+                ingress: Default::default(),
             },
             &mut nic_index,
             &mut resources,
@@ -603,7 +701,15 @@ fn vm_config_from_command_line(
     for vport in &opt.mana {
         let vport = parse_endpoint(vport, &mut nic_index, &mut resources)?;
         mana_nics[vport.vtl as usize]
-            .get_or_insert_with(|| (Guid::new_random(), GdmaDeviceHandle { vports: Vec::new() }))
+            .get_or_insert_with(|| {
+                (
+                    Guid::new_random(),
+                    GdmaDeviceHandle {
+                        vports: Vec::new(),
+                        rdma: opt.mana_rdma,
+                    },
+                )
+            })
             .1
             .vports
             .push(VportDefinition {
@@ -846,7 +952,8 @@ fn vm_config_from_command_line(
         let (send, guest_request_recv) = mesh::channel();
         resources.ged_rpc = Some(send);
         let vmgs_disk = if let Some(disk) = &opt.get_vmgs {
-            disk_open(disk, false).context("failed to open GET vmgs disk")?
+            disk_open(disk, false, &mut resources.overlay_disks)
+                .context("failed to open GET vmgs disk")?
         } else {
             disk_backend_resources::LayeredDiskHandle::single_layer(RamDiskLayerHandle {
                 len: Some(vmgs_format::VMGS_DEFAULT_CAPACITY),
@@ -1119,6 +1226,56 @@ fn vm_config_from_command_line(
             DeviceVtl::Vtl0,
             hyperv_ic_resources::shutdown::ShutdownIcHandle { recv }.into_resource(),
         ));
+
+        let (send, recv) = mesh::channel();
+        resources.kvp_ic = Some(send);
+        vmbus_devices.push((
+            DeviceVtl::Vtl0,
+            hyperv_ic_resources::kvp::KvpIcHandle { recv }.into_resource(),
+        ));
+
+        let (send, recv) = mesh::channel();
+        resources.vss_ic = Some(send);
+        vmbus_devices.push((
+            DeviceVtl::Vtl0,
+            hyperv_ic_resources::vss::VssIcHandle { recv }.into_resource(),
+        ));
+
+        let (send, recv) = mesh::channel();
+        resources.fcopy_ic = Some(send);
+        vmbus_devices.push((
+            DeviceVtl::Vtl0,
+            hyperv_ic_resources::fcopy::FcopyIcHandle { recv }.into_resource(),
+        ));
+
+        if let Some(dm) = &opt.dynamic_memory {
+            vmbus_devices.push((
+                DeviceVtl::Vtl0,
+                hyperv_dm_resources::DynamicMemoryHandle {
+                    startup: opt.memory,
+                    minimum: dm.minimum,
+                    maximum: dm.maximum,
+                    buffer_percent: dm.buffer_percent,
+                }
+                .into_resource(),
+            ));
+        }
+
+        let timesync_mode = match opt.timesync {
+            cli_args::TimesyncCli::Off => None,
+            cli_args::TimesyncCli::Once => Some(hyperv_ic_resources::timesync::TimesyncMode::Once),
+            cli_args::TimesyncCli::Continuous => {
+                Some(hyperv_ic_resources::timesync::TimesyncMode::Continuous {
+                    interval: Duration::from_secs(5),
+                })
+            }
+        };
+        if let Some(mode) = timesync_mode {
+            vmbus_devices.push((
+                DeviceVtl::Vtl0,
+                hyperv_ic_resources::timesync::TimesyncIcHandle { mode }.into_resource(),
+            ));
+        }
     }
 
     if let Some(hive_path) = &opt.imc {
@@ -1249,6 +1406,21 @@ fn vm_config_from_command_line(
             mem_size: opt.memory,
             mmio_gaps,
             prefetch_memory: opt.prefetch,
+            hot_add_size: opt
+                .dynamic_memory
+                .as_ref()
+                .map_or(opt.hot_add_memory.unwrap_or(0), |dm| {
+                    dm.maximum.saturating_sub(opt.memory)
+                }),
+            numa_nodes: opt
+                .numa_node
+                .iter()
+                .map(|node| NumaNodeConfig {
+                    mem_size: node.mem_size,
+                    vp_count: node.vp_count,
+                    host_node: node.host_node,
+                })
+                .collect(),
         },
         processor_topology: ProcessorTopologyConfig {
             proc_count: opt.processors,
@@ -1369,10 +1541,17 @@ fn parse_endpoint(
     index: &mut usize,
     resources: &mut VmResources,
 ) -> anyhow::Result<NicConfig> {
-    let _ = resources;
     let endpoint = match &cli_cfg.endpoint {
-        EndpointConfigCli::Consomme { cidr } => {
-            net_backend_resources::consomme::ConsommeHandle { cidr: cidr.clone() }.into_resource()
+        EndpointConfigCli::Consomme { cidr, ports, dhcp } => {
+            let (send, recv) = mesh::channel();
+            resources.consomme_nics.insert(*index, send);
+            net_backend_resources::consomme::ConsommeHandle {
+                cidr: cidr.clone(),
+                ports: ports.clone(),
+                dhcp: dhcp.clone(),
+                requests: Some(recv),
+            }
+            .into_resource()
         }
         EndpointConfigCli::None => net_backend_resources::null::NullHandle.into_resource(),
         EndpointConfigCli::Dio { id } => {
@@ -1395,11 +1574,64 @@ fn parse_endpoint(
                 bail!("cannot use dio on non-windows platforms")
             }
         }
-        EndpointConfigCli::Tap { name } => {
-            net_backend_resources::tap::TapHandle { name: name.clone() }.into_resource()
+        EndpointConfigCli::Tap { name } => net_backend_resources::tap::TapHandle {
+            name: name.clone(),
+            queue_count: cli_cfg.max_queues.unwrap_or(1),
+        }
+        .into_resource(),
+        EndpointConfigCli::VhostUser { path } => {
+            #[cfg(unix)]
+            {
+                let socket = std::os::unix::net::UnixStream::connect(path)
+                    .with_context(|| format!("failed to connect to vhost-user socket {path}"))?;
+                net_backend_resources::vhost_user::VhostUserHandle {
+                    socket,
+                    max_queues: cli_cfg.max_queues.unwrap_or(1),
+                }
+                .into_resource()
+            }
+
+            #[cfg(not(unix))]
+            {
+                let _ = path;
+                bail!("cannot use vhost-user on non-unix platforms")
+            }
+        }
+        EndpointConfigCli::Xdp { interface } => net_backend_resources::xdp::XdpHandle {
+            interface: interface.clone(),
+            queue_count: cli_cfg.max_queues.unwrap_or(1),
         }
+        .into_resource(),
     };
 
+    // Wrap every endpoint so that bandwidth limits can be applied at runtime.
+    let endpoint = net_backend_resources::rate_limit::RateLimitHandle {
+        endpoint,
+        egress: cli_cfg.egress,
+        ingress: cli_cfg.ingress,
+    }
+    .into_resource();
+
+    // Wrap every endpoint so that packet capture can be started at runtime.
+    let file = cli_cfg
+        .pcap
+        .as_ref()
+        .map(|path| {
+            fs_err::File::create(path)
+                .context("failed to create packet capture file")
+                .map(Into::into)
+        })
+        .transpose()?;
+    let (send, recv) = mesh::channel();
+    resources.packet_captures.insert(*index, send);
+    let endpoint = net_backend_resources::packet_capture::PacketCaptureHandle {
+        name: format!("nic{index}"),
+        endpoint,
+        file,
+        requests: Some(recv),
+    }
+    .into_resource();
+
     // Pick a random MAC address.
     let mut mac_address = [0x00, 0x15, 0x5D, 0, 0, 0];
     getrandom::getrandom(&mut mac_address[3..]).expect("rng failure");
@@ -1418,6 +1650,7 @@ fn parse_endpoint(
         endpoint,
         mac_address: mac_address.into(),
         max_queues: cli_cfg.max_queues,
+        enable_rsc: cli_cfg.enable_rsc,
     })
 }
 
@@ -1428,6 +1661,7 @@ struct NicConfig {
     mac_address: MacAddress,
     endpoint: Resource<NetEndpointHandleKind>,
     max_queues: Option<u16>,
+    enable_rsc: bool,
 }
 
 impl NicConfig {
@@ -1439,52 +1673,188 @@ fn into_netvsp_handle(self) -> (DeviceVtl, Resource<VmbusDeviceHandleKind>) {
                 mac_address: self.mac_address,
                 endpoint: self.endpoint,
                 max_queues: self.max_queues,
+                enable_rsc: self.enable_rsc,
             }
             .into_resource(),
         )
     }
 }
 
-fn disk_open(disk_cli: &DiskCliKind, read_only: bool) -> anyhow::Result<Resource<DiskHandleKind>> {
+/// How long the kernel's submission queue polling thread spins without IO
+/// before going to sleep, for `uring-sqpoll` disks.
+const URING_SQ_POLL_IDLE_MS: u32 = 100;
+
+/// How often `memfile` disks write their contents to their backing file.
+const RAM_DISK_FLUSH_INTERVAL_MS: u64 = 30000;
+
+/// Opens the disk described by `disk_cli`.
+///
+/// The request channels of any overlay disks are appended to `overlays`.
+fn disk_open(
+    disk_cli: &DiskCliKind,
+    read_only: bool,
+    overlays: &mut Vec<mesh::Sender<OverlayDiskRequest>>,
+) -> anyhow::Result<Resource<DiskHandleKind>> {
     let disk_type = match disk_cli {
         &DiskCliKind::Memory(len) => {
             Resource::new(disk_backend_resources::LayeredDiskHandle::single_layer(
                 RamDiskLayerHandle { len: Some(len) },
             ))
         }
+        DiskCliKind::MemoryFile { len, path } => {
+            let file = fs_err::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            Resource::new(disk_backend_resources::LayeredDiskHandle::single_layer(
+                PersistentRamDiskLayerHandle {
+                    len: Some(*len),
+                    file: file.into(),
+                    flush_interval_ms: Some(RAM_DISK_FLUSH_INTERVAL_MS),
+                },
+            ))
+        }
         DiskCliKind::File(path) => open_disk_type(path, read_only)
             .with_context(|| format!("failed to open {}", path.display()))?,
-        DiskCliKind::Blob { kind, url } => Resource::new(disk_backend_resources::BlobDiskHandle {
-            url: url.to_owned(),
-            format: match kind {
-                cli_args::BlobKind::Flat => disk_backend_resources::BlobDiskFormat::Flat,
-                cli_args::BlobKind::Vhd1 => disk_backend_resources::BlobDiskFormat::FixedVhd1,
-            },
-        }),
+        DiskCliKind::Uring { path, sq_poll } => {
+            let file = fs_err::OpenOptions::new()
+                .read(true)
+                .write(!read_only)
+                .open(path)?;
+            Resource::new(disk_backend_resources::UringFileDiskHandle {
+                file: file.into(),
+                sq_poll_idle_ms: sq_poll.then_some(URING_SQ_POLL_IDLE_MS),
+                write_cache: WriteCacheMode::WriteBack,
+            })
+        }
+        DiskCliKind::WriteThrough(inner) => {
+            let (path, sq_poll) = match &**inner {
+                DiskCliKind::File(path) => (path, None),
+                DiskCliKind::Uring { path, sq_poll } => (path, Some(*sq_poll)),
+                _ => anyhow::bail!("write-through caching is only supported for file disks"),
+            };
+            let file = fs_err::OpenOptions::new()
+                .read(true)
+                .write(!read_only)
+                .open(path)?
+                .into();
+            match sq_poll {
+                None => Resource::new(disk_backend_resources::FileDiskHandle {
+                    file,
+                    write_cache: WriteCacheMode::WriteThrough,
+                }),
+                Some(sq_poll) => Resource::new(disk_backend_resources::UringFileDiskHandle {
+                    file,
+                    sq_poll_idle_ms: sq_poll.then_some(URING_SQ_POLL_IDLE_MS),
+                    write_cache: WriteCacheMode::WriteThrough,
+                }),
+            }
+        }
+        DiskCliKind::Blob { kind, url, cache } => {
+            Resource::new(disk_backend_resources::BlobDiskHandle {
+                url: url.to_owned(),
+                format: match kind {
+                    cli_args::BlobKind::Flat => disk_backend_resources::BlobDiskFormat::Flat,
+                    cli_args::BlobKind::Vhd1 => disk_backend_resources::BlobDiskFormat::FixedVhd1,
+                },
+                cache: cache
+                    .as_ref()
+                    .map(|path| {
+                        fs_err::OpenOptions::new()
+                            .read(true)
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .open(path)
+                            .context("failed to create blob cache file")
+                    })
+                    .transpose()?
+                    .map(Into::into),
+            })
+        }
         DiskCliKind::MemoryDiff(inner) => {
             Resource::new(disk_backend_resources::LayeredDiskHandle {
                 layers: vec![
                     RamDiskLayerHandle { len: None }.into_resource().into(),
-                    DiskLayerHandle(disk_open(inner, true)?)
+                    DiskLayerHandle(disk_open(inner, true, overlays)?)
                         .into_resource()
                         .into(),
                 ],
             })
         }
-        DiskCliKind::PersistentReservationsWrapper(inner) => Resource::new(
-            disk_backend_resources::DiskWithReservationsHandle(disk_open(inner, read_only)?),
-        ),
-        DiskCliKind::Crypt {
+        DiskCliKind::Overlay(inner) => {
+            let base = disk_open(inner, read_only, overlays)?;
+            let (send, recv) = mesh::channel();
+            overlays.push(send);
+            Resource::new(disk_backend_resources::OverlayDiskHandle {
+                base,
+                read_only_base: read_only,
+                requests: Some(recv),
+            })
+        }
+        DiskCliKind::PersistentReservationsWrapper(inner) => {
+            Resource::new(disk_backend_resources::DiskWithReservationsHandle(
+                disk_open(inner, read_only, overlays)?,
+            ))
+        }
+        DiskCliKind::SharedPersistentReservationsWrapper { state_file, disk } => {
+            Resource::new(disk_backend_resources::DiskWithSharedReservationsHandle {
+                disk: disk_open(disk, read_only, overlays)?,
+                state_file: fs_err::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(false)
+                    .open(state_file)
+                    .context("failed to open reservation state file")?
+                    .into(),
+            })
+        }
+        DiskCliKind::Checksum {
+            checksum_file,
+            fail,
             disk,
-            cipher,
-            key_file,
-        } => Resource::new(disk_crypt_resources::DiskCryptHandle {
-            disk: disk_open(disk, read_only)?,
-            cipher: match cipher {
-                cli_args::DiskCipher::XtsAes256 => disk_crypt_resources::Cipher::XtsAes256,
+        } => Resource::new(disk_backend_resources::ChecksumDiskHandle {
+            disk: disk_open(disk, read_only, overlays)?,
+            checksum_file: fs_err::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(checksum_file)
+                .context("failed to open disk checksum file")?
+                .into(),
+            policy: if *fail {
+                disk_backend_resources::ChecksumMismatchPolicy::Fail
+            } else {
+                disk_backend_resources::ChecksumMismatchPolicy::Log
             },
-            key: fs_err::read(key_file).context("failed to read key file")?,
         }),
+        DiskCliKind::Crypt { disk, cipher, key } => {
+            Resource::new(disk_crypt_resources::DiskCryptHandle {
+                disk: disk_open(disk, read_only, overlays)?,
+                cipher: match cipher {
+                    cli_args::DiskCipher::XtsAes256 => disk_crypt_resources::Cipher::XtsAes256,
+                },
+                key: match key {
+                    cli_args::DiskKeyCli::File(path) => disk_crypt_resources::FileDiskKeyHandle(
+                        fs_err::File::open(path)
+                            .context("failed to open key file")?
+                            .into(),
+                    )
+                    .into_resource(),
+                    #[cfg(target_os = "linux")]
+                    cli_args::DiskKeyCli::Keyring(description) => {
+                        disk_crypt_resources::KeyringDiskKeyHandle {
+                            description: description.clone(),
+                        }
+                        .into_resource()
+                    }
+                },
+            })
+        }
     };
 
     Ok(disk_type)
@@ -1619,7 +1989,9 @@ enum InteractiveCommand {
         lun: u8,
         #[clap(long)]
         ram: Option<u64>,
-        file_path: Option<PathBuf>,
+        /// The disk to add, using the same syntax as `--disk` (e.g. a file
+        /// path, or `memdiff:file:base.img`).
+        disk: Option<DiskCliKind>,
     },
 
     /// Hot remove a disk.
@@ -1633,6 +2005,30 @@ enum InteractiveCommand {
         lun: u8,
     },
 
+    /// Hot add a netvsp NIC, using the same syntax as `--net`.
+    ///
+    /// Only netvsp NICs can be hot added; virtio-net NICs require PCI
+    /// hotplug, which is not yet supported.
+    AddNic {
+        /// The instance ID to give the NIC. If omitted, a random one is used.
+        #[clap(long)]
+        instance_id: Option<Guid>,
+        /// Add a virtio-net NIC. This is not yet supported and always fails.
+        #[clap(long)]
+        virtio: bool,
+        /// The NIC to add (e.g. `consomme` or `tap:tap0`).
+        nic: NicConfigCli,
+    },
+
+    /// Hot remove a NIC.
+    RmNic {
+        /// Remove a NIC offered to VTL2.
+        #[clap(long)]
+        vtl2: bool,
+        /// The instance ID of the NIC to remove.
+        instance_id: Guid,
+    },
+
     /// Inspect program state.
     #[clap(visible_alias = "x")]
     Inspect {
@@ -1704,6 +2100,60 @@ enum InteractiveCommand {
     #[clap(visible_alias = "ch")]
     ClearHalt,
 
+    /// Exchange key/value data with the guest via the KVP IC.
+    Kvp {
+        #[clap(subcommand)]
+        command: KvpCommand,
+    },
+
+    /// Quiesce or resume the guest's file systems via the VSS IC, e.g. to
+    /// take a consistent snapshot of its disks.
+    Vss {
+        #[clap(subcommand)]
+        command: VssCommand,
+    },
+
+    /// Commit or discard the contents of an overlay disk.
+    Overlay {
+        /// The index of the overlay disk, counting from 0 in the order the
+        /// overlay disks were opened.
+        index: usize,
+        #[clap(subcommand)]
+        command: OverlayCommand,
+    },
+
+    /// Add or remove a host-to-guest port forwarding rule on a consomme NIC.
+    PortForward {
+        /// The index of the NIC, counting from 0 in the order the NICs were
+        /// added.
+        nic: usize,
+        #[clap(subcommand)]
+        command: PortForwardCommand,
+    },
+
+    /// Start or stop capturing a NIC's packets to a pcapng file.
+    Pcap {
+        /// The index of the NIC, counting from 0 in the order the NICs were
+        /// added.
+        nic: usize,
+        #[clap(subcommand)]
+        command: PcapCommand,
+    },
+
+    /// Copy a file from the host into the guest via the file copy IC.
+    CopyToGuest {
+        /// Overwrite the guest file if it already exists.
+        #[clap(long)]
+        overwrite: bool,
+        /// Create the guest directory if it does not exist.
+        #[clap(long)]
+        create_path: bool,
+        /// The host file to copy.
+        src: PathBuf,
+        /// The full path of the destination file in the guest.
+        dest: String,
+    },
+
     /// Update the image in VTL2.
     ServiceVtl2 {
         /// Just restart the user-mode paravisor process, not the full
@@ -1743,10 +2193,209 @@ enum InteractiveCommand {
         file: Option<PathBuf>,
     },
 
+    /// Add RAM to the running VM, at the top of the range reserved with
+    /// `--hot-add-memory`. The guest must be told about the new RAM
+    /// separately, e.g. via Linux's memory probe interface.
+    AddMemory {
+        /// How many bytes to add, rounded up to 128MB.
+        #[clap(value_parser = cli_args::parse_memory)]
+        size: u64,
+    },
+
+    /// Dump the buffered output of a serial port using the `log` backend.
+    SerialLog {
+        /// The serial port name (e.g. com1, vmbus_com2).
+        port: String,
+        /// File to save the output to. If omitted, the output is printed.
+        #[clap(long, short = 'f')]
+        file: Option<PathBuf>,
+    },
+
     /// Inject an artificial panic into OpenVMM
     Panic,
 }
 
+#[derive(clap::Subcommand)]
+enum KvpCommand {
+    /// Set a value.
+    Set {
+        /// The pool to write to.
+        #[clap(long, value_enum, default_value_t = KvpPoolCli::External)]
+        pool: KvpPoolCli,
+        /// Store the value as a 32-bit integer.
+        #[clap(long, conflicts_with = "u64")]
+        u32: bool,
+        /// Store the value as a 64-bit integer.
+        #[clap(long)]
+        u64: bool,
+        key: String,
+        value: String,
+    },
+    /// Get a value.
+    Get {
+        /// The pool to read from.
+        #[clap(long, value_enum, default_value_t = KvpPoolCli::External)]
+        pool: KvpPoolCli,
+        key: String,
+    },
+    /// Delete a value.
+    Delete {
+        /// The pool to delete from.
+        #[clap(long, value_enum, default_value_t = KvpPoolCli::External)]
+        pool: KvpPoolCli,
+        key: String,
+    },
+    /// List the values in a pool.
+    ///
+    /// The guest reports information such as its OS version and IP addresses
+    /// in the `auto` pool.
+    Enum {
+        /// The pool to list.
+        #[clap(value_enum, default_value_t = KvpPoolCli::Auto)]
+        pool: KvpPoolCli,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum VssCommand {
+    /// Flush and freeze the guest's file systems.
+    Freeze,
+    /// Thaw the guest's file systems after a freeze.
+    Thaw,
+}
+
+#[derive(clap::Subcommand)]
+enum OverlayCommand {
+    /// Write the overlay's contents to the base disk, emptying the overlay.
+    Commit,
+    /// Discard the overlay's contents, reverting to the base disk's contents.
+    Discard,
+}
+
+#[derive(clap::Subcommand)]
+enum PortForwardCommand {
+    /// Forward a host port to the guest.
+    Add {
+        /// The rule, as `<tcp|udp>:[<host address>:]<host port>[:<guest port>]`.
+        rule: PortForward,
+    },
+    /// Stop forwarding a host port.
+    Remove {
+        /// The rule, as `<tcp|udp>:<host port>`.
+        rule: PortForward,
+    },
+}
+
+#[derive(clap::Subcommand)]
+enum PcapCommand {
+    /// Start capturing to a new file, replacing any capture in progress.
+    Start {
+        /// The path of the pcapng file to create.
+        path: PathBuf,
+    },
+    /// Stop capturing.
+    Stop,
+}
+
+#[derive(Copy, Clone, clap::ValueEnum)]
+enum KvpPoolCli {
+    External,
+    Guest,
+    Auto,
+    AutoExternal,
+}
+
+impl From<KvpPoolCli> for hyperv_ic_resources::kvp::KvpPool {
+    fn from(pool: KvpPoolCli) -> Self {
+        match pool {
+            KvpPoolCli::External => hyperv_ic_resources::kvp::KvpPool::External,
+            KvpPoolCli::Guest => hyperv_ic_resources::kvp::KvpPool::Guest,
+            KvpPoolCli::Auto => hyperv_ic_resources::kvp::KvpPool::Auto,
+            KvpPoolCli::AutoExternal => hyperv_ic_resources::kvp::KvpPool::AutoExternal,
+        }
+    }
+}
+
+async fn kvp_command(
+    kvp: &mesh::Sender<hyperv_ic_resources::kvp::KvpRpc>,
+    command: KvpCommand,
+) -> anyhow::Result<()> {
+    use hyperv_ic_resources::kvp::KvpRpc;
+    use hyperv_ic_resources::kvp::Value;
+
+    // Don't wait forever if the guest is not responding.
+    let mut ctx = CancelContext::new().with_timeout(Duration::from_secs(5));
+    match command {
+        KvpCommand::Set {
+            pool,
+            u32,
+            u64,
+            key,
+            value,
+        } => {
+            let value = if u32 {
+                Value::U32(value.parse()?)
+            } else if u64 {
+                Value::U64(value.parse()?)
+            } else {
+                Value::String(value)
+            };
+            let params = hyperv_ic_resources::kvp::SetParams {
+                pool: pool.into(),
+                key,
+                value,
+            };
+            ctx.until_cancelled(kvp.call(KvpRpc::Set, params))
+                .await???;
+        }
+        KvpCommand::Get { pool, key } => {
+            let params = hyperv_ic_resources::kvp::GetParams {
+                pool: pool.into(),
+                key,
+            };
+            match ctx
+                .until_cancelled(kvp.call(KvpRpc::Get, params))
+                .await???
+            {
+                Some(value) => println!("{}", kvp_value_string(&value)),
+                None => println!("not found"),
+            }
+        }
+        KvpCommand::Delete { pool, key } => {
+            let params = hyperv_ic_resources::kvp::DeleteParams {
+                pool: pool.into(),
+                key,
+            };
+            ctx.until_cancelled(kvp.call(KvpRpc::Delete, params))
+                .await???;
+        }
+        KvpCommand::Enum { pool } => {
+            for index in 0.. {
+                let params = hyperv_ic_resources::kvp::EnumerateParams {
+                    pool: pool.into(),
+                    index,
+                };
+                let Some(kv) = ctx
+                    .until_cancelled(kvp.call(KvpRpc::Enumerate, params))
+                    .await???
+                else {
+                    break;
+                };
+                println!("{}: {}", kv.key, kvp_value_string(&kv.value));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn kvp_value_string(value: &hyperv_ic_resources::kvp::Value) -> String {
+    match value {
+        hyperv_ic_resources::kvp::Value::String(s) => s.clone(),
+        hyperv_ic_resources::kvp::Value::U32(n) => n.to_string(),
+        hyperv_ic_resources::kvp::Value::U64(n) => n.to_string(),
+    }
+}
+
 struct CommandParser {
     app: clap::Command,
 }
@@ -1782,6 +2431,8 @@ fn new_hvsock_service_id(port: u32) -> Guid {
 
 async fn run_control(driver: &DefaultDriver, mesh: &VmmMesh, opt: Options) -> anyhow::Result<()> {
     let (mut vm_config, mut resources) = vm_config_from_command_line(driver, &opt)?;
+    // Hot-added NICs are numbered after the ones specified on the command line.
+    let mut nic_index = opt.net.len() + opt.virtio_net.len() + usize::from(opt.nic);
 
     let mut vnc_worker = None;
     if opt.gfx || opt.vnc {
@@ -1789,7 +2440,10 @@ async fn run_control(driver: &DefaultDriver, mesh: &VmmMesh, opt: Options) -> an
             .with_context(|| format!("binding to VNC port {}", opt.vnc_port))?;
 
         let input_send = vm_config.input.sender();
-        let framebuffer = resources.framebuffer_access.expect("synth video enabled");
+        let framebuffer = resources
+            .framebuffer_access
+            .take()
+            .expect("synth video enabled");
 
         let vnc_host = mesh
             .make_host("vnc", None)
@@ -1867,6 +2521,8 @@ async fn run_control(driver: &DefaultDriver, mesh: &VmmMesh, opt: Options) -> an
 
     if !opt.paused {
         vm_rpc.call(VmRpc::Resume, ()).await?;
+    } else if let Some(vm_running) = &mut resources.nbd_vm_running {
+        vm_running.set(false).await;
     }
 
     let paravisor_diag = Arc::new(diag_client::DiagClient::from_dialer(
@@ -1887,7 +2543,7 @@ async fn run_control(driver: &DefaultDriver, mesh: &VmmMesh, opt: Options) -> an
     let (console_command_send, console_command_recv) = mesh::channel();
     let (inspect_completion_engine_send, inspect_completion_engine_recv) = mesh::channel();
 
-    let mut console_in = resources.console_in;
+    let mut console_in = resources.console_in.take();
     thread::Builder::new()
         .name("stdio-thread".to_string())
         .spawn(move || {
@@ -2201,6 +2857,9 @@ enum Event {
                 match r {
                     Ok(sc) => match sc {
                         StateChange::Pause(success) => {
+                            if let Some(vm_running) = &mut resources.nbd_vm_running {
+                                vm_running.set(false).await;
+                            }
                             if success {
                                 tracing::info!("pause complete");
                             } else {
@@ -2251,6 +2910,9 @@ enum Event {
                         hyperv_ic_resources::shutdown::ShutdownResult::AlreadyInProgress => {
                             tracing::error!("shutdown already in progress");
                         }
+                        hyperv_ic_resources::shutdown::ShutdownResult::NotSupported => {
+                            tracing::error!("guest does not support the requested shutdown type");
+                        }
                         hyperv_ic_resources::shutdown::ShutdownResult::Failed(hr) => {
                             tracing::error!("shutdown failed with error code {hr:#x}");
                         }
@@ -2325,6 +2987,13 @@ fn state_change<U: 'static + Send>(
                 );
             }
             InteractiveCommand::Resume => {
+                // Make any NBD exports read-only before the VM starts
+                // running.
+                if state_change_task.is_none() {
+                    if let Some(vm_running) = &mut resources.nbd_vm_running {
+                        vm_running.set(true).await;
+                    }
+                }
                 state_change(
                     driver,
                     &vm_rpc,
@@ -2390,22 +3059,173 @@ fn state_change<U: 'static + Send>(
             InteractiveCommand::ClearHalt => {
                 vm_rpc.call(VmRpc::ClearHalt, ()).await.ok();
             }
+            InteractiveCommand::Vss { command } => {
+                if let Some(vss) = &resources.vss_ic {
+                    let rpc = match command {
+                        VssCommand::Freeze => hyperv_ic_resources::vss::VssRpc::Freeze,
+                        VssCommand::Thaw => hyperv_ic_resources::vss::VssRpc::Thaw,
+                    };
+                    let result = async {
+                        // Freezing can take a while if the guest has a lot
+                        // of dirty data to flush.
+                        CancelContext::new()
+                            .with_timeout(Duration::from_secs(60))
+                            .until_cancelled(vss.call(rpc, ()))
+                            .await???;
+                        anyhow::Ok(())
+                    }
+                    .await;
+                    match result {
+                        Ok(()) => println!("done"),
+                        Err(err) => eprintln!("error: {:#}", err),
+                    }
+                } else {
+                    println!("no vss ic configured");
+                }
+            }
+            InteractiveCommand::Overlay { index, command } => {
+                if let Some(overlay) = resources.overlay_disks.get(index) {
+                    let result = async {
+                        match command {
+                            OverlayCommand::Commit => {
+                                overlay
+                                    .call_failable(OverlayDiskRequest::Commit, ())
+                                    .await?
+                            }
+                            OverlayCommand::Discard => {
+                                overlay.call(OverlayDiskRequest::Discard, ()).await?
+                            }
+                        }
+                        anyhow::Ok(())
+                    }
+                    .await;
+                    match result {
+                        Ok(()) => println!("done"),
+                        Err(err) => eprintln!("error: {:#}", err),
+                    }
+                } else {
+                    println!("no overlay disk {index}");
+                }
+            }
+            InteractiveCommand::PortForward { nic, command } => {
+                if let Some(consomme) = resources.consomme_nics.get(&nic) {
+                    let result = async {
+                        match command {
+                            PortForwardCommand::Add { rule } => {
+                                consomme
+                                    .call_failable(ConsommeRequest::ForwardPort, rule)
+                                    .await?
+                            }
+                            PortForwardCommand::Remove { rule } => {
+                                consomme
+                                    .call_failable(
+                                        ConsommeRequest::UnforwardPort,
+                                        (rule.protocol, rule.host_port),
+                                    )
+                                    .await?
+                            }
+                        }
+                        anyhow::Ok(())
+                    }
+                    .await;
+                    match result {
+                        Ok(()) => println!("done"),
+                        Err(err) => eprintln!("error: {:#}", err),
+                    }
+                } else {
+                    println!("no consomme NIC {nic}");
+                }
+            }
+            InteractiveCommand::Pcap { nic, command } => {
+                if let Some(pcap) = resources.packet_captures.get(&nic) {
+                    let result = async {
+                        match command {
+                            PcapCommand::Start { path } => {
+                                let file = fs_err::File::create(path)
+                                    .context("failed to create packet capture file")?;
+                                pcap.call_failable(PacketCaptureRequest::Start, file.into())
+                                    .await?
+                            }
+                            PcapCommand::Stop => {
+                                pcap.call_failable(PacketCaptureRequest::Stop, ()).await?
+                            }
+                        }
+                        anyhow::Ok(())
+                    }
+                    .await;
+                    match result {
+                        Ok(()) => println!("done"),
+                        Err(err) => eprintln!("error: {:#}", err),
+                    }
+                } else {
+                    println!("no NIC {nic}");
+                }
+            }
+            InteractiveCommand::CopyToGuest {
+                overwrite,
+                create_path,
+                src,
+                dest,
+            } => {
+                if let Some(fcopy) = &resources.fcopy_ic {
+                    let params = (|| {
+                        let (path, file_name) = dest
+                            .rsplit_once(['/', '\\'])
+                            .context("destination must be a full path")?;
+                        anyhow::Ok(hyperv_ic_resources::fcopy::CopyFileParams {
+                            file: fs_err::File::open(&src)?.into(),
+                            path: path.to_owned(),
+                            file_name: file_name.to_owned(),
+                            overwrite,
+                            create_path,
+                        })
+                    })();
+                    let params = match params {
+                        Ok(params) => params,
+                        Err(err) => {
+                            eprintln!("error: {:#}", err);
+                            continue;
+                        }
+                    };
+                    // Large files can take a while, so copy in the background.
+                    let recv = fcopy.call(hyperv_ic_resources::fcopy::FcopyRpc::CopyFile, params);
+                    driver
+                        .spawn("copy-to-guest", async move {
+                            match recv.await {
+                                Ok(Ok(())) => println!("copied {}", dest),
+                                Ok(Err(err)) => eprintln!("error copying {}: {:#}", dest, err),
+                                Err(err) => eprintln!("error copying {}: {:#}", dest, err),
+                            }
+                        })
+                        .detach();
+                } else {
+                    println!("no fcopy ic configured");
+                }
+            }
+            InteractiveCommand::Kvp { command } => {
+                if let Some(kvp) = &resources.kvp_ic {
+                    if let Err(err) = kvp_command(kvp, command).await {
+                        eprintln!("error: {:#}", err);
+                    }
+                } else {
+                    println!("no kvp ic configured");
+                }
+            }
             InteractiveCommand::AddDisk {
                 read_only,
                 target,
                 path,
                 lun,
                 ram,
-                file_path,
+                disk,
                 is_dvd,
             } => {
                 let action = async {
                     let scsi = resources.scsi_rpc.as_ref().context("no scsi controller")?;
                     let disk_type = match ram {
                         None => {
-                            let path = file_path.context("no filename passed")?;
-                            open_disk_type(path.as_ref(), read_only)
-                                .with_context(|| format!("failed to open {}", path.display()))?
+                            let disk = disk.context("no disk passed")?;
+                            disk_open(&disk, read_only || is_dvd, &mut resources.overlay_disks)?
                         }
                         Some(size) => {
                             Resource::new(disk_backend_resources::LayeredDiskHandle::single_layer(
@@ -2459,6 +3279,51 @@ fn state_change<U: 'static + Send>(
                     tracing::error!(error = error.as_error(), "error removing disk")
                 }
             }
+            InteractiveCommand::AddNic {
+                instance_id,
+                virtio,
+                nic,
+            } => {
+                let action = async {
+                    if virtio {
+                        anyhow::bail!(
+                            "cannot hot add virtio-net NICs: PCI hotplug is not supported, use --virtio-net at startup instead"
+                        );
+                    }
+                    if nic.underhill {
+                        anyhow::bail!("cannot hot add underhill NICs");
+                    }
+                    let mut nic_config = parse_endpoint(&nic, &mut nic_index, &mut resources)?;
+                    nic_config.instance_id = instance_id.unwrap_or_else(Guid::new_random);
+                    let instance_id = nic_config.instance_id;
+                    vm_rpc
+                        .call_failable(VmRpc::AddVmbusDevice, nic_config.into_netvsp_handle())
+                        .await?;
+                    anyhow::Ok(instance_id)
+                };
+
+                match action.await {
+                    Ok(instance_id) => println!("added NIC {instance_id}"),
+                    Err(error) => tracing::error!(error = error.as_error(), "error adding NIC"),
+                }
+            }
+            InteractiveCommand::RmNic { vtl2, instance_id } => {
+                let vtl = if vtl2 {
+                    DeviceVtl::Vtl2
+                } else {
+                    DeviceVtl::Vtl0
+                };
+                let action = async {
+                    vm_rpc
+                        .call_failable(VmRpc::RemoveVmbusDevice, (vtl, instance_id))
+                        .await?;
+                    anyhow::Ok(())
+                };
+
+                if let Err(error) = action.await {
+                    tracing::error!(error = error.as_error(), "error removing NIC")
+                }
+            }
             InteractiveCommand::Inspect {
                 recursive,
                 limit,
@@ -2605,6 +3470,21 @@ fn state_change<U: 'static + Send>(
                 vm_worker.stop();
                 quit = true;
             }
+            InteractiveCommand::SerialLog { port, file } => {
+                if let Some(log) = resources.serial_logs.get(&port) {
+                    let bytes = log.call(|rpc| rpc, ()).await?;
+                    if let Some(file) = file {
+                        if let Err(err) = fs_err::write(file, bytes) {
+                            eprintln!("error: {err:?}");
+                        }
+                    } else {
+                        io::stdout().write_all(&bytes)?;
+                        println!();
+                    }
+                } else {
+                    eprintln!("error: no serial log configured for {port}");
+                }
+            }
             InteractiveCommand::ReadMemory { gpa, size, file } => {
                 let size = size as usize;
                 let data = vm_rpc.call(VmRpc::ReadMemory, (gpa, size)).await?;
@@ -2707,6 +3587,12 @@ fn state_change<U: 'static + Send>(
                     eprintln!("error: {err:?}");
                 }
             }
+            InteractiveCommand::AddMemory { size } => {
+                match vm_rpc.call(VmRpc::AddMemory, size).await? {
+                    Ok(range) => println!("added {range}"),
+                    Err(err) => eprintln!("error: {err:?}"),
+                }
+            }
             InteractiveCommand::Input { .. } | InteractiveCommand::InputMode => unreachable!(),
         }
     }
@@ -249,8 +249,108 @@ pub fn bind_serial(path: &Path) -> io::Result<Resource<SerialBackendHandle>> {
     Ok(OpenSocketSerialConfig::from(UnixListener::bind(path)?).into_resource())
 }
 
+#[cfg(windows)]
+pub fn bind_shared_serial(path: &Path) -> anyhow::Result<Resource<SerialBackendHandle>> {
+    use serial_socket::windows::MultiClientConfig;
+    use serial_socket::windows::OpenWindowsPipeSerialConfig;
+
+    /// The maximum number of clients that can attach to a shared serial pipe.
+    const MAX_SHARED_PIPE_CLIENTS: u32 = 8;
+
+    let pipe = pal::windows::pipe::new_named_pipe(
+        path,
+        winapi::um::winnt::GENERIC_READ | winapi::um::winnt::GENERIC_WRITE,
+        pal::windows::pipe::Disposition::Create,
+        pal::windows::pipe::PipeMode::Byte,
+    )?;
+    Ok(OpenWindowsPipeSerialConfig {
+        pipe: Some(pipe),
+        multi_client: Some(MultiClientConfig {
+            path: path
+                .to_str()
+                .context("pipe path is not valid utf-8")?
+                .to_owned(),
+            max_clients: MAX_SHARED_PIPE_CLIENTS,
+        }),
+    }
+    .into_resource())
+}
+
+#[cfg(not(windows))]
+pub fn bind_shared_serial(_path: &Path) -> anyhow::Result<Resource<SerialBackendHandle>> {
+    anyhow::bail!("shared serial pipes are only supported on windows")
+}
+
 pub fn bind_tcp_serial(addr: &SocketAddr) -> anyhow::Result<Resource<SerialBackendHandle>> {
     let listener = std::net::TcpListener::bind(addr)
         .with_context(|| format!("failed to bind tcp address {addr}"))?;
     Ok(OpenSocketSerialConfig::from(listener).into_resource())
 }
+
+pub fn bind_telnet_serial(addr: &SocketAddr) -> anyhow::Result<Resource<SerialBackendHandle>> {
+    let listener = std::net::TcpListener::bind(addr)
+        .with_context(|| format!("failed to bind tcp address {addr}"))?;
+    Ok(serial_socket::telnet::OpenTelnetSerialConfig::from(listener).into_resource())
+}
+
+pub fn reconnecting_serial(
+    path: &Path,
+    connect: bool,
+) -> anyhow::Result<Resource<SerialBackendHandle>> {
+    use serial_socket::reconnect::OpenReconnectingSerialConfig;
+
+    let config = if connect {
+        let path = path.to_str().context("socket path is not valid utf-8")?;
+        OpenReconnectingSerialConfig::connect(path)
+    } else {
+        cleanup_socket(path);
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("failed to bind {}", path.display()))?;
+        OpenReconnectingSerialConfig::listen(listener)
+    };
+    Ok(config.into_resource())
+}
+
+#[cfg(any(windows, target_os = "linux"))]
+pub fn vsock_serial(port: u32, connect: bool) -> anyhow::Result<Resource<SerialBackendHandle>> {
+    use serial_socket::reconnect::OpenReconnectingSerialConfig;
+
+    let config = if connect {
+        OpenReconnectingSerialConfig::connect_vsock_host(port)
+    } else {
+        OpenReconnectingSerialConfig::listen_vsock(port)
+            .with_context(|| format!("failed to bind vsock port {port}"))?
+    };
+    Ok(config.into_resource())
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn vsock_serial(_port: u32, _connect: bool) -> anyhow::Result<Resource<SerialBackendHandle>> {
+    anyhow::bail!("vsock serial is not supported on this platform")
+}
+
+/// The number of bytes of output retained in memory by the serial logger.
+const SERIAL_LOG_CAPACITY: usize = 1024 * 1024;
+/// The size at which serial log files are rotated.
+const SERIAL_LOG_FILE_SIZE: u64 = 16 * 1024 * 1024;
+/// The number of rotated serial log files to keep.
+const SERIAL_LOG_FILE_COUNT: u32 = 4;
+
+pub fn serial_logger(
+    path: Option<&Path>,
+    dump: mesh::Receiver<mesh::rpc::Rpc<(), Vec<u8>>>,
+) -> Resource<SerialBackendHandle> {
+    use serial_logger::LogFileConfig;
+    use serial_logger::SerialLoggerHandle;
+
+    SerialLoggerHandle {
+        capacity: SERIAL_LOG_CAPACITY,
+        file: path.map(|path| LogFileConfig {
+            path: path.to_string_lossy().into_owned(),
+            max_file_size: SERIAL_LOG_FILE_SIZE,
+            max_files: SERIAL_LOG_FILE_COUNT,
+        }),
+        dump: Some(dump),
+    }
+    .into_resource()
+}
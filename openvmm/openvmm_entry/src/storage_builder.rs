@@ -8,6 +8,8 @@
 use crate::disk_open;
 use crate::VmResources;
 use anyhow::Context;
+use disk_backend_resources::NbdExportDiskHandle;
+use disk_backend_resources::OverlayDiskRequest;
 use guid::Guid;
 use hvlite_defs::config::Config;
 use hvlite_defs::config::DeviceVtl;
@@ -20,10 +22,13 @@
 use nvme_resources::NvmeControllerHandle;
 use scsidisk_resources::SimpleScsiDiskHandle;
 use scsidisk_resources::SimpleScsiDvdHandle;
+use std::net::TcpListener;
 use storvsp_resources::ScsiControllerHandle;
 use storvsp_resources::ScsiDeviceAndPath;
 use storvsp_resources::ScsiPath;
+use vm_resource::kind::DiskHandleKind;
 use vm_resource::IntoResource;
+use vm_resource::Resource;
 use vtl2_settings_proto::storage_controller;
 use vtl2_settings_proto::Lun;
 use vtl2_settings_proto::StorageController;
@@ -37,6 +42,8 @@ pub(super) struct StorageBuilder {
     underhill_scsi_luns: Vec<Lun>,
     underhill_nvme_luns: Vec<Lun>,
     openhcl_vtl: Option<DeviceVtl>,
+    nbd_vm_running: Option<mesh::CellUpdater<bool>>,
+    overlay_disks: Vec<mesh::Sender<OverlayDiskRequest>>,
 }
 
 #[derive(Copy, Clone)]
@@ -77,6 +84,8 @@ pub fn new(openhcl_vtl: Option<DeviceVtl>) -> Self {
             underhill_scsi_luns: Vec::new(),
             underhill_nvme_luns: Vec::new(),
             openhcl_vtl,
+            nbd_vm_running: None,
+            overlay_disks: Vec::new(),
         }
     }
 
@@ -92,18 +101,41 @@ pub fn add(
         kind: &DiskCliKind,
         is_dvd: bool,
         read_only: bool,
+        nbd_port: Option<u16>,
     ) -> anyhow::Result<()> {
         if let Some(source) = underhill {
             if vtl != DeviceVtl::Vtl0 {
                 anyhow::bail!("underhill can only offer devices to vtl0");
             }
-            self.add_underhill(source.into(), target, kind, is_dvd, read_only)?;
+            self.add_underhill(source.into(), target, kind, is_dvd, read_only, nbd_port)?;
         } else {
-            self.add_inner(vtl, target, kind, is_dvd, read_only)?;
+            self.add_inner(vtl, target, kind, is_dvd, read_only, nbd_port)?;
         }
         Ok(())
     }
 
+    /// Wraps `disk` so that it is exported over NBD on localhost at `port`.
+    fn nbd_export(
+        &mut self,
+        disk: Resource<DiskHandleKind>,
+        port: u16,
+    ) -> anyhow::Result<Resource<DiskHandleKind>> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .with_context(|| format!("binding to nbd port {port}"))?;
+        // Assume the VM is running until told otherwise.
+        let vm_running = self
+            .nbd_vm_running
+            .get_or_insert_with(|| mesh::CellUpdater::new(true))
+            .cell();
+        Ok(NbdExportDiskHandle {
+            disk,
+            listener,
+            export_name: "openvmm".into(),
+            vm_running,
+        }
+        .into_resource())
+    }
+
     /// Returns the "sub device path" for assigning this into Underhill, or
     /// `None` if Underhill can't use this device as a source.
     fn add_inner(
@@ -113,8 +145,12 @@ fn add_inner(
         kind: &DiskCliKind,
         is_dvd: bool,
         read_only: bool,
+        nbd_port: Option<u16>,
     ) -> anyhow::Result<Option<u32>> {
-        let disk = disk_open(kind, read_only || is_dvd)?;
+        let mut disk = disk_open(kind, read_only || is_dvd, &mut self.overlay_disks)?;
+        if let Some(port) = nbd_port {
+            disk = self.nbd_export(disk, port)?;
+        }
         let location = match target {
             DiskLocation::Ide(channel, device) => {
                 let guest_media = if is_dvd {
@@ -218,10 +254,11 @@ fn add_underhill(
         kind: &DiskCliKind,
         is_dvd: bool,
         read_only: bool,
+        nbd_port: Option<u16>,
     ) -> anyhow::Result<()> {
         let vtl = self.openhcl_vtl.context("openhcl not configured")?;
         let sub_device_path = self
-            .add_inner(vtl, source, kind, is_dvd, read_only)?
+            .add_inner(vtl, source, kind, is_dvd, read_only, nbd_port)?
             .context("source device not supported by underhill")?;
 
         let (device_type, device_path) = match source {
@@ -290,6 +327,8 @@ pub fn build_config(
         scsi_sub_channels: u16,
     ) -> anyhow::Result<()> {
         config.ide_disks.append(&mut self.vtl0_ide_disks);
+        resources.nbd_vm_running = self.nbd_vm_running.take();
+        resources.overlay_disks.append(&mut self.overlay_disks);
 
         // Add an empty VTL0 SCSI controller even if there are no configured disks.
         if !self.vtl0_scsi_devices.is_empty() || config.vmbus.is_some() {
@@ -339,6 +378,7 @@ pub fn build_config(
                     namespaces: std::mem::take(&mut self.vtl0_nvme_namespaces),
                     max_io_queues: 64,
                     msix_count: 64,
+                    requests: None,
                 }
                 .into_resource(),
             });
@@ -371,6 +411,7 @@ pub fn build_config(
                     namespaces: std::mem::take(&mut self.vtl2_nvme_namespaces),
                     max_io_queues: 64,
                     msix_count: 64,
+                    requests: None,
                 }
                 .into_resource(),
             });
@@ -475,6 +475,8 @@ async fn create_vm(&mut self, request: vmservice::CreateVmRequest) -> anyhow::Re
                     .context("invalid memory configuration")?,
                 mmio_gaps: DEFAULT_MMIO_GAPS.into(),
                 prefetch_memory: false,
+                hot_add_size: 0,
+                numa_nodes: vec![],
             },
             chipset: chipset.chipset,
             processor_topology: ProcessorTopologyConfig {
@@ -684,12 +686,19 @@ fn modify_resource(
                 }
             }
             Resource::NicConfig(nic) => {
-                if request.r#type != vmservice::ModifyType::Add as i32 {
-                    anyhow::bail!("not supported yet");
+                if request.r#type == vmservice::ModifyType::Add as i32 {
+                    let config = parse_nic_config(nic)?;
+                    let recv = vm.worker_rpc.call_failable(VmRpc::AddVmbusDevice, config);
+                    Ok(async move { recv.await.map_err(anyhow::Error::from) }.boxed())
+                } else if request.r#type == vmservice::ModifyType::Remove as i32 {
+                    let instance_id = nic.nic_id.parse().context("invalid instance ID")?;
+                    let recv = vm
+                        .worker_rpc
+                        .call_failable(VmRpc::RemoveVmbusDevice, (DeviceVtl::Vtl0, instance_id));
+                    Ok(async move { recv.await.map_err(anyhow::Error::from) }.boxed())
+                } else {
+                    anyhow::bail!("unsupported request type {}", request.r#type);
                 }
-                let config = parse_nic_config(nic)?;
-                let recv = vm.worker_rpc.call_failable(VmRpc::AddVmbusDevice, config);
-                Ok(async move { recv.await.map_err(anyhow::Error::from) }.boxed())
             }
             Resource::VpmemDisk(_) => anyhow::bail!("vpmem not supported"),
             Resource::WindowsDevice(_) => anyhow::bail!("device assignment not supported"),
@@ -721,9 +730,11 @@ fn parse_nic_config(
         }
         .into_resource(),
         #[cfg(unix)]
-        Backend::Tap(tap) => {
-            net_backend_resources::tap::TapHandle { name: tap.name }.into_resource()
+        Backend::Tap(tap) => net_backend_resources::tap::TapHandle {
+            name: tap.name,
+            queue_count: 1,
         }
+        .into_resource(),
         _ => anyhow::bail!("unsupported backend"),
     };
     let cfg = NetvspHandle {
@@ -736,6 +747,7 @@ fn parse_nic_config(
             .into(),
         endpoint,
         max_queues: None,
+        enable_rsc: false,
     };
     Ok((DeviceVtl::Vtl0, cfg.into_resource()))
 }
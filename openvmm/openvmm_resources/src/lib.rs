@@ -28,27 +28,47 @@
 
     // Serial ports
     serial_core::disconnected::resolver::DisconnectedSerialBackendResolver,
+    serial_logger::SerialLoggerResolver,
     #[cfg(windows)]
     serial_socket::windows::WindowsPipeSerialResolver,
     serial_socket::net::SocketSerialResolver,
+    serial_socket::reconnect::ReconnectingSerialResolver,
+    serial_socket::telnet::TelnetSerialResolver,
 
     // Network backends
+    net_backend::channel::ChannelResolver,
     net_backend::null::NullResolver,
+    net_backend::rate_limit::RateLimitResolver,
+    net_backend::fault::FaultResolver,
+    net_packet_capture::resolver::PacketCaptureResolver,
     #[cfg(feature = "net_consomme")]
     net_consomme::resolver::ConsommeResolver,
     #[cfg(all(feature = "net_tap", target_os = "linux"))]
     net_tap::resolver::TapResolver,
+    #[cfg(unix)]
+    net_vhost_user::resolver::VhostUserResolver,
+    #[cfg(all(feature = "net_xdp", target_os = "linux"))]
+    net_xdp::resolver::XdpResolver,
     #[cfg(windows)]
     net_dio::resolver::DioResolver,
 
     // Disks
     disk_layered::resolver::LayeredDiskResolver,
+    disk_checksum::ChecksumDiskResolver,
+    disk_fault::FaultDiskResolver,
     #[cfg(feature = "disk_crypt")]
     disk_crypt::resolver::DiskCryptResolver,
+    #[cfg(feature = "disk_crypt")]
+    disk_crypt::resolver::FileDiskKeyResolver,
+    #[cfg(all(feature = "disk_crypt", target_os = "linux"))]
+    disk_crypt::resolver::KeyringDiskKeyResolver,
     disk_ramdisk::resolver::RamDiskResolver,
     disk_file::FileDiskResolver,
     disk_prwrap::DiskWithReservationsResolver,
+    disk_overlay::OverlayDiskResolver,
+    disk_nbd::NbdExportDiskResolver,
     disk_vhd1::Vhd1Resolver,
+    disk_vhdx::VhdxDiskResolver,
     #[cfg(windows)]
     disk_vhdmp::VhdmpDiskResolver,
     #[cfg(feature = "disk_blob")]
@@ -74,6 +94,7 @@
     guest_crash_device::resolver::GuestCrashDeviceResolver,
     guest_emulation_device::resolver::GuestEmulationDeviceResolver,
     guest_emulation_log::resolver::GuestEmulationLogResolver,
+    hyperv_dm::resolver::DynamicMemoryResolver,
     hyperv_ic::resolver::IcResolver,
     netvsp::resolver::NetvspResolver,
     storvsp::resolver::StorvspResolver,
@@ -20,6 +20,8 @@ pub mod artifacts {
         PIPETTE_WINDOWS_AARCH64,
         /// Pipette linux aarch64 executable
         PIPETTE_LINUX_AARCH64,
+        /// Pipette FreeBSD x86_64 executable
+        PIPETTE_FREEBSD_X64,
         /// Directory to put petri test logs in
         TEST_LOG_DIRECTORY,
     }
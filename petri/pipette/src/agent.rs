@@ -3,7 +3,7 @@
 
 //! The main pipette agent, which is run when the process starts.
 
-#![cfg(any(target_os = "linux", target_os = "windows"))]
+#![cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
 
 use anyhow::Context;
 use futures::FutureExt;
@@ -33,18 +33,21 @@ pub struct Agent {
 #[derive(Clone)]
 pub struct DiagnosticSender(Arc<mesh::Sender<DiagnosticFile>>);
 
+/// How long to keep retrying the connection to the host while the guest's
+/// socket transport is not yet available.
+const CONNECT_RETRY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Options for connecting to the host.
+#[derive(Debug, Default)]
+pub struct ConnectOptions {
+    /// The AF_VSOCK CID to connect to, instead of the host CID. Only used on
+    /// Linux.
+    pub vsock_cid: Option<u32>,
+}
+
 impl Agent {
-    pub async fn new(driver: DefaultDriver) -> anyhow::Result<Self> {
-        let socket = VmSocket::new()?;
-        // Extend the default timeout of 2 seconds, as tests are often run in
-        // parallel on a host, causing very heavy load on the overall system.
-        socket
-            .set_connect_timeout(Duration::from_secs(5))
-            .context("failed to set socket timeout")?;
-
-        let socket = socket
-            .connect(VmAddress::vsock_host(pipette_protocol::PIPETTE_VSOCK_PORT))
-            .context("failed to connect to vsock")?;
+    pub async fn new(driver: DefaultDriver, options: ConnectOptions) -> anyhow::Result<Self> {
+        let socket = connect(&driver, &options).await?;
         let socket =
             PolledSocket::new(&driver, socket).context("failed to create polled socket")?;
 
@@ -96,6 +99,78 @@ pub async fn run(mut self) -> anyhow::Result<()> {
     }
 }
 
+/// Connects to the host, waiting for the socket transport to become
+/// available.
+///
+/// On Linux, AF_VSOCK connects over whichever transport the kernel provides:
+/// `hv_sock` over VMBus, or virtio-vsock. In minimal direct-boot images,
+/// pipette may start before either is loaded, so transient failures are
+/// retried until [`CONNECT_RETRY_TIMEOUT`] elapses. FreeBSD only supports
+/// `hv_sock`, through AF_HYPERV.
+async fn connect(
+    driver: &DefaultDriver,
+    options: &ConnectOptions,
+) -> anyhow::Result<vmsocket::VmStream> {
+    let mut timer = PolledTimer::new(driver);
+    let deadline = std::time::Instant::now() + CONNECT_RETRY_TIMEOUT;
+    loop {
+        match try_connect(options) {
+            Ok(socket) => break Ok(socket),
+            Err(err) if is_transient(&err) && std::time::Instant::now() < deadline => {
+                timer.sleep(Duration::from_millis(250)).await;
+            }
+            Err(err) => break Err(err).context("failed to connect to vsock"),
+        }
+    }
+}
+
+fn try_connect(options: &ConnectOptions) -> std::io::Result<vmsocket::VmStream> {
+    let socket = VmSocket::new()?;
+    // Extend the default timeout of 2 seconds, as tests are often run in
+    // parallel on a host, causing very heavy load on the overall system.
+    socket.set_connect_timeout(Duration::from_secs(5))?;
+
+    #[cfg(target_os = "linux")]
+    let address = match options.vsock_cid {
+        Some(cid) => VmAddress::vsock(cid, pipette_protocol::PIPETTE_VSOCK_PORT),
+        None => VmAddress::vsock_host(pipette_protocol::PIPETTE_VSOCK_PORT),
+    };
+    #[cfg(not(target_os = "linux"))]
+    let address = {
+        let _ = options;
+        VmAddress::vsock_host(pipette_protocol::PIPETTE_VSOCK_PORT)
+    };
+    socket.connect(address)
+}
+
+/// Returns whether `err` indicates that the transport or the host is not
+/// ready yet.
+fn is_transient(err: &std::io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        matches!(
+            err.raw_os_error(),
+            Some(
+                libc::EAFNOSUPPORT
+                    | libc::ENODEV
+                    | libc::ENETUNREACH
+                    | libc::ECONNREFUSED
+                    | libc::ECONNRESET
+                    | libc::ETIMEDOUT
+            )
+        )
+    }
+    #[cfg(windows)]
+    {
+        matches!(
+            err.kind(),
+            std::io::ErrorKind::ConnectionRefused
+                | std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::TimedOut
+        )
+    }
+}
+
 async fn handle_request(
     driver: &DefaultDriver,
     req: PipetteRequest,
@@ -106,6 +181,18 @@ async fn handle_request(
             tracing::info!("ping");
         }),
         PipetteRequest::Execute(rpc) => rpc.handle_failable_sync(crate::execute::handle_execute),
+        PipetteRequest::ConfigureCrashDump(rpc) => {
+            rpc.handle_failable_sync(crate::crash::handle_configure_crash_dump)
+        }
+        PipetteRequest::Crash(rpc) => {
+            rpc.handle_failable_sync(|()| crate::crash::handle_crash(driver))
+        }
+        PipetteRequest::ListCrashDumps(rpc) => {
+            rpc.handle_failable_sync(|()| crate::crash::handle_list_crash_dumps())
+        }
+        PipetteRequest::GuestInfo(rpc) => {
+            rpc.handle_failable_sync(|()| crate::guest_info::handle_guest_info())
+        }
         PipetteRequest::Shutdown(rpc) => {
             rpc.handle_sync(|request| {
                 tracing::info!(shutdown_type = ?request.shutdown_type, "shutdown request");
@@ -143,6 +230,21 @@ async fn handle_request(
         }
         PipetteRequest::ReadFile(rpc) => rpc.handle_failable(read_file).await,
         PipetteRequest::WriteFile(rpc) => rpc.handle_failable(write_file).await,
+        PipetteRequest::PushFile(rpc) => {
+            rpc.handle_failable(crate::file_transfer::handle_push_file)
+                .await
+        }
+        PipetteRequest::PullFile(rpc) => {
+            rpc.handle_failable(crate::file_transfer::handle_pull_file)
+                .await
+        }
+        PipetteRequest::ConnectTcp(rpc) => {
+            rpc.handle_failable(|request| crate::tcp::handle_connect_tcp(driver, request))
+                .await
+        }
+        PipetteRequest::ListenTcp(rpc) => {
+            rpc.handle_failable_sync(|request| crate::tcp::handle_listen_tcp(driver, request))
+        }
     }
 }
 
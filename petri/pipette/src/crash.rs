@@ -0,0 +1,276 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Handlers for the crash dump requests.
+
+#![cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+// UNSAFETY: required for the Windows crash and registry APIs.
+#![cfg_attr(windows, allow(unsafe_code))]
+
+use pal_async::task::Spawn;
+use pal_async::timer::PolledTimer;
+use pal_async::DefaultDriver;
+use pipette_protocol::CrashDumpFile;
+use std::path::Path;
+use std::time::Duration;
+
+/// Runs `crash` after a short delay, so that the response to the request is
+/// sent first.
+fn crash_after_response(
+    driver: &DefaultDriver,
+    crash: impl 'static + Send + FnOnce() -> anyhow::Result<()>,
+) {
+    let mut timer = PolledTimer::new(driver);
+    driver
+        .spawn("crash", async move {
+            timer.sleep(Duration::from_millis(250)).await;
+            tracing::info!("crashing the guest");
+            if let Err(err) = crash() {
+                tracing::error!(
+                    error = err.as_ref() as &dyn std::error::Error,
+                    "failed to crash the guest"
+                );
+            }
+        })
+        .detach();
+}
+
+/// Appends the files under `dir`, recursively, to `dumps`. Does nothing if
+/// `dir` does not exist.
+fn find_dumps(dir: &Path, dumps: &mut Vec<CrashDumpFile>) -> anyhow::Result<()> {
+    let entries = match fs_err::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            find_dumps(&entry.path(), dumps)?;
+        } else if metadata.is_file() {
+            dumps.push(CrashDumpFile {
+                path: entry.path().to_string_lossy().into_owned(),
+                size: metadata.len(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn handle_configure_crash_dump(kind: pipette_protocol::CrashDumpKind) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    tracing::debug!(?kind, "configure crash dump request");
+
+    // Dumps are written by the distribution's kdump service, which controls
+    // what is included, so the kind is not used. Just make sure it will run.
+    let loaded = fs_err::read_to_string("/sys/kernel/kexec_crash_loaded")
+        .context("kexec is not supported by the kernel")?;
+    if loaded.trim() != "1" {
+        anyhow::bail!("no crash kernel is loaded, kdump must be installed and enabled");
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn handle_crash(driver: &DefaultDriver) -> anyhow::Result<()> {
+    use std::io::Write;
+
+    tracing::debug!("crash request");
+
+    // Enable sysrq and open the trigger now, so that failures are reported
+    // to the host.
+    fs_err::write("/proc/sys/kernel/sysrq", "1")?;
+    let mut trigger = fs_err::OpenOptions::new()
+        .write(true)
+        .open("/proc/sysrq-trigger")?;
+    crash_after_response(driver, move || {
+        trigger.write_all(b"c")?;
+        anyhow::bail!("the kernel did not crash")
+    });
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn handle_list_crash_dumps() -> anyhow::Result<Vec<CrashDumpFile>> {
+    tracing::debug!("list crash dumps request");
+
+    // kdump writes dumps to /var/crash, and pstore keeps the kernel log of
+    // the crash in firmware storage when it is available.
+    let mut dumps = Vec::new();
+    find_dumps("/var/crash".as_ref(), &mut dumps)?;
+    find_dumps("/sys/fs/pstore".as_ref(), &mut dumps)?;
+    Ok(dumps)
+}
+
+#[cfg(target_os = "freebsd")]
+pub fn handle_configure_crash_dump(kind: pipette_protocol::CrashDumpKind) -> anyhow::Result<()> {
+    tracing::debug!(?kind, "configure crash dump request");
+
+    // The kernel writes the dump to the configured dump device, and savecore
+    // copies it to /var/crash on the next boot. The dump contents are
+    // controlled by the kernel configuration, so the kind is not used.
+    let dumpdev = sysctl(&["-n", "kern.shutdown.dumpdevname"])?;
+    if dumpdev.trim().is_empty() {
+        anyhow::bail!("no dump device is configured, dumpdev must be set in rc.conf");
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "freebsd")]
+pub fn handle_crash(driver: &DefaultDriver) -> anyhow::Result<()> {
+    tracing::debug!("crash request");
+
+    crash_after_response(driver, || {
+        sysctl(&["debug.kdb.panic=1"])?;
+        anyhow::bail!("the kernel did not crash")
+    });
+    Ok(())
+}
+
+#[cfg(target_os = "freebsd")]
+pub fn handle_list_crash_dumps() -> anyhow::Result<Vec<CrashDumpFile>> {
+    tracing::debug!("list crash dumps request");
+
+    let mut dumps = Vec::new();
+    find_dumps("/var/crash".as_ref(), &mut dumps)?;
+    Ok(dumps)
+}
+
+/// Runs `sysctl` with `args`, returning its output.
+#[cfg(target_os = "freebsd")]
+fn sysctl(args: &[&str]) -> anyhow::Result<String> {
+    use anyhow::Context;
+
+    let output = std::process::Command::new("sysctl")
+        .args(args)
+        .output()
+        .context("failed to launch sysctl")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "sysctl failed: {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(windows)]
+fn system_root() -> std::path::PathBuf {
+    std::env::var_os("SystemRoot")
+        .unwrap_or_else(|| r"C:\Windows".into())
+        .into()
+}
+
+#[cfg(windows)]
+pub fn handle_configure_crash_dump(kind: pipette_protocol::CrashDumpKind) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use windows_sys::Win32::System::Registry::RegSetKeyValueW;
+    use windows_sys::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+    use windows_sys::Win32::System::Registry::REG_DWORD;
+
+    tracing::debug!(?kind, "configure crash dump request");
+
+    let crash_dump_enabled: u32 = match kind {
+        pipette_protocol::CrashDumpKind::Full => 1,
+        pipette_protocol::CrashDumpKind::Kernel => 2,
+        pipette_protocol::CrashDumpKind::Small => 3,
+    };
+    let key = wide(r"SYSTEM\CurrentControlSet\Control\CrashControl");
+    for (name, value) in [
+        ("CrashDumpEnabled", crash_dump_enabled),
+        ("AutoReboot", 1),
+        ("Overwrite", 1),
+        // Keep the dump even when disk space is low.
+        ("AlwaysKeepMemoryDump", 1),
+    ] {
+        let name_w = wide(name);
+        // SAFETY: calling with valid strings and a DWORD sized buffer.
+        let r = unsafe {
+            RegSetKeyValueW(
+                HKEY_LOCAL_MACHINE,
+                key.as_ptr(),
+                name_w.as_ptr(),
+                REG_DWORD,
+                std::ptr::from_ref(&value).cast(),
+                size_of::<u32>() as u32,
+            )
+        };
+        if r != 0 {
+            return Err(std::io::Error::from_raw_os_error(r as i32))
+                .with_context(|| format!("failed to set {name}"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn handle_crash(driver: &DefaultDriver) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+    use windows_sys::Win32::System::LibraryLoader::GetProcAddress;
+
+    type NtRaiseHardError =
+        unsafe extern "system" fn(i32, u32, u32, *const usize, u32, *mut u32) -> i32;
+    const STATUS_ASSERTION_FAILURE: i32 = 0xc0000420_u32 as i32;
+    const OPTION_SHUTDOWN_SYSTEM: u32 = 6;
+
+    tracing::debug!("crash request");
+
+    // Raising a hard error with the shutdown option bugchecks the system. It
+    // requires the shutdown privilege.
+    crate::shutdown::enable_shutdown_privilege()?;
+    let ntdll = wide("ntdll.dll");
+    // SAFETY: calling with valid strings. ntdll is always loaded, and
+    // NtRaiseHardError has the declared signature.
+    let nt_raise_hard_error: NtRaiseHardError = unsafe {
+        let module = GetModuleHandleW(ntdll.as_ptr());
+        let f = GetProcAddress(module, c"NtRaiseHardError".as_ptr().cast())
+            .context("failed to find NtRaiseHardError")?;
+        std::mem::transmute::<unsafe extern "system" fn() -> isize, NtRaiseHardError>(f)
+    };
+
+    crash_after_response(driver, move || {
+        let mut response = 0;
+        // SAFETY: calling as documented, with no parameters.
+        let status = unsafe {
+            nt_raise_hard_error(
+                STATUS_ASSERTION_FAILURE,
+                0,
+                0,
+                std::ptr::null(),
+                OPTION_SHUTDOWN_SYSTEM,
+                &mut response,
+            )
+        };
+        anyhow::bail!("the system did not crash: {status:#x}")
+    });
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn handle_list_crash_dumps() -> anyhow::Result<Vec<CrashDumpFile>> {
+    tracing::debug!("list crash dumps request");
+
+    let system_root = system_root();
+    let mut dumps = Vec::new();
+    let memory_dmp = system_root.join("MEMORY.DMP");
+    match fs_err::metadata(&memory_dmp) {
+        Ok(metadata) => dumps.push(CrashDumpFile {
+            path: memory_dmp.to_string_lossy().into_owned(),
+            size: metadata.len(),
+        }),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    find_dumps(&system_root.join("Minidump"), &mut dumps)?;
+    Ok(dumps)
+}
+
+#[cfg(windows)]
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain([0]).collect()
+}
@@ -3,10 +3,15 @@
 
 //! Handler for the execute request.
 
-#![cfg(any(target_os = "linux", target_os = "windows"))]
+#![cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
 
 use futures::executor::block_on;
 use futures::io::AllowStdIo;
+use futures::StreamExt;
+use futures_concurrency::future::Race;
+use pipette_protocol::ProcessControl;
+use pipette_protocol::WindowSize;
+use std::fs::File;
 use std::process::Stdio;
 
 pub fn handle_execute(
@@ -29,24 +34,66 @@ pub fn handle_execute(
             command.env_remove(name);
         }
     }
-    if request.stdin.is_some() {
-        command.stdin(Stdio::piped());
-    } else {
-        command.stdin(Stdio::null());
-    }
-    if request.stdout.is_some() {
-        command.stdout(Stdio::piped());
-    } else {
-        command.stdout(Stdio::null());
-    }
-    if request.stderr.is_some() {
-        command.stderr(Stdio::piped());
+    let pty = if let Some(size) = request.pty {
+        if request.stderr.is_some() {
+            anyhow::bail!("stderr cannot be redirected in pty mode");
+        }
+        Some(open_pty(&mut command, size)?)
     } else {
-        command.stderr(Stdio::null());
-    }
+        if request.stdin.is_some() {
+            command.stdin(Stdio::piped());
+        } else {
+            command.stdin(Stdio::null());
+        }
+        if request.stdout.is_some() {
+            command.stdout(Stdio::piped());
+        } else {
+            command.stdout(Stdio::null());
+        }
+        if request.stderr.is_some() {
+            command.stderr(Stdio::piped());
+        } else {
+            command.stderr(Stdio::null());
+        }
+        None
+    };
     let mut child = command.spawn()?;
+    // Close this process's handles to the pty secondary, so that reads from
+    // the primary fail once the child and its descendants have exited.
+    drop(command);
     let pid = child.id();
     let (send, recv) = mesh::oneshot();
+    let (exit_send, exit_recv) = mesh::oneshot::<()>();
+
+    if let Some(primary) = &pty {
+        if let Some(stdin_read) = request.stdin.take() {
+            let primary_write = primary.try_clone()?;
+            std::thread::spawn(move || {
+                let _ = block_on(futures::io::copy(
+                    stdin_read,
+                    &mut AllowStdIo::new(primary_write),
+                ));
+            });
+        }
+        // Always drain the output so that the process does not block writing
+        // to the terminal. The read fails with EIO once the terminal is
+        // closed, ending the relay.
+        let primary_read = primary.try_clone()?;
+        let stdout_write = request.stdout.take();
+        std::thread::spawn(move || {
+            let primary_read = AllowStdIo::new(primary_read);
+            let _ = match stdout_write {
+                Some(mut stdout_write) => {
+                    block_on(futures::io::copy(primary_read, &mut stdout_write))
+                }
+                None => block_on(futures::io::copy(primary_read, &mut futures::io::sink())),
+            };
+        });
+    }
+
+    if let Some(control) = request.control.take() {
+        std::thread::spawn(move || block_on(handle_control(pid, pty, control, exit_recv)));
+    }
 
     if let (Some(stdin_write), Some(stdin_read)) = (child.stdin.take(), request.stdin.take()) {
         std::thread::spawn(move || {
@@ -82,10 +129,83 @@ pub fn handle_execute(
         let status = convert_exit_status(exit_status);
         tracing::debug!(pid, ?status, "process exited");
         send.send(status);
+        drop(exit_send);
     });
     Ok(pipette_protocol::ExecuteResponse { pid, result: recv })
 }
 
+/// Opens a new pty of the given size and attaches it to `command`, returning
+/// the primary end.
+#[cfg(unix)]
+fn open_pty(command: &mut std::process::Command, size: WindowSize) -> anyhow::Result<File> {
+    use anyhow::Context;
+
+    let (primary, secondary) = crate::pty::new_pty(size).context("failed to create pty")?;
+    command
+        .stdin(secondary.try_clone()?)
+        .stdout(secondary.try_clone()?)
+        .stderr(secondary);
+    crate::pty::set_controlling_terminal(command);
+    Ok(primary)
+}
+
+#[cfg(windows)]
+fn open_pty(_command: &mut std::process::Command, _size: WindowSize) -> anyhow::Result<File> {
+    anyhow::bail!("pty mode is not supported on Windows")
+}
+
+/// Handles control requests for the process `pid` until it exits.
+async fn handle_control(
+    pid: u32,
+    pty: Option<File>,
+    mut control: mesh::Receiver<ProcessControl>,
+    exited: mesh::OneshotReceiver<()>,
+) {
+    let handle = async {
+        while let Some(request) = control.next().await {
+            match request {
+                ProcessControl::Resize(rpc) => {
+                    rpc.handle_failable_sync(|size| resize(pty.as_ref(), size))
+                }
+                ProcessControl::Signal(rpc) => {
+                    rpc.handle_failable_sync(|signal| send_signal(pid, signal))
+                }
+            }
+        }
+    };
+    let exited = async {
+        let _ = exited.await;
+    };
+    (handle, exited).race().await;
+}
+
+#[cfg(unix)]
+fn resize(pty: Option<&File>, size: WindowSize) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    tracing::debug!(?size, "resize request");
+    let primary = pty.context("process was not started with a pty")?;
+    crate::pty::set_window_size(primary, size).context("failed to set window size")
+}
+
+#[cfg(windows)]
+fn resize(_pty: Option<&File>, _size: WindowSize) -> anyhow::Result<()> {
+    anyhow::bail!("process was not started with a pty")
+}
+
+#[cfg(unix)]
+fn send_signal(pid: u32, signal: i32) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    tracing::debug!(pid, signal, "signal request");
+    crate::pty::send_signal(pid, signal).with_context(|| format!("failed to send signal {signal}"))
+}
+
+#[cfg(windows)]
+fn send_signal(_pid: u32, _signal: i32) -> anyhow::Result<()> {
+    anyhow::bail!("signals are not supported on Windows")
+}
+
 fn convert_exit_status(exit_status: std::process::ExitStatus) -> pipette_protocol::ExitStatus {
     if let Some(code) = exit_status.code() {
         return pipette_protocol::ExitStatus::Normal(code);
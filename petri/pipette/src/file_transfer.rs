@@ -0,0 +1,98 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Handlers for the file push and pull requests.
+
+#![cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+
+use futures::AsyncReadExt;
+use futures::AsyncWriteExt;
+use mesh::pipe::ReadPipe;
+use pipette_protocol::FileDigest;
+use pipette_protocol::PullFileRequest;
+use pipette_protocol::PushFileRequest;
+use pipette_protocol::FILE_TRANSFER_CHUNK_SIZE;
+use sha2::Digest;
+use sha2::Sha256;
+use std::io::Read;
+use std::io::Write;
+
+pub async fn handle_push_file(mut request: PushFileRequest) -> anyhow::Result<()> {
+    tracing::debug!(
+        path = request.path,
+        size = request.digest.size,
+        "beginning file push request"
+    );
+
+    // Receive into a file next to the destination, so that the destination
+    // is only replaced once the contents have been verified.
+    let partial_path = format!("{}.pipette-partial", request.path);
+    if let Err(err) = receive_file(&partial_path, &mut request.receiver, request.digest).await {
+        let _ = fs_err::remove_file(&partial_path);
+        return Err(err);
+    }
+    fs_err::rename(&partial_path, &request.path)?;
+
+    tracing::debug!("file push request complete");
+    Ok(())
+}
+
+async fn receive_file(
+    path: &str,
+    receiver: &mut ReadPipe,
+    expected: FileDigest,
+) -> anyhow::Result<()> {
+    let mut file = fs_err::File::create(path)?;
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut buf = vec![0; FILE_TRANSFER_CHUNK_SIZE];
+    loop {
+        let n = receiver.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        size += n as u64;
+        if size > expected.size {
+            anyhow::bail!("received more than the expected {} bytes", expected.size);
+        }
+        file.write_all(&buf[..n])?;
+        hasher.update(&buf[..n]);
+    }
+    file.sync_all()?;
+
+    let digest = FileDigest {
+        size,
+        sha256: hasher.finalize().into(),
+    };
+    if digest != expected {
+        anyhow::bail!("received contents do not match: expected {expected:?}, got {digest:?}");
+    }
+    Ok(())
+}
+
+pub async fn handle_pull_file(mut request: PullFileRequest) -> anyhow::Result<FileDigest> {
+    tracing::debug!(path = request.path, "beginning file pull request");
+
+    let mut file = fs_err::File::open(&request.path)?;
+    request.size.send(file.metadata()?.len());
+
+    let mut hasher = Sha256::new();
+    let mut size = 0;
+    let mut buf = vec![0; FILE_TRANSFER_CHUNK_SIZE];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.sender.write_all(&buf[..n]).await?;
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    request.sender.close().await?;
+
+    tracing::debug!(size, "file pull request complete");
+    Ok(FileDigest {
+        size,
+        sha256: hasher.finalize().into(),
+    })
+}
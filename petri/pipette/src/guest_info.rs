@@ -0,0 +1,487 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Handler for the guest info request.
+
+#![cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+// UNSAFETY: required for enumerating network interfaces and filesystems, and
+// for the Windows and FreeBSD system information APIs.
+#![allow(unsafe_code)]
+
+use pipette_protocol::GuestInfo;
+use pipette_protocol::IpAddress;
+use pipette_protocol::MountedFilesystem;
+use pipette_protocol::NetworkInterface;
+
+/// Returns the interface named `name` in `interfaces`, adding it if it is not
+/// already present.
+fn interface<'a>(
+    interfaces: &'a mut Vec<NetworkInterface>,
+    name: &str,
+) -> &'a mut NetworkInterface {
+    let index = match interfaces.iter().position(|i| i.name == name) {
+        Some(index) => index,
+        None => {
+            interfaces.push(NetworkInterface {
+                name: name.to_owned(),
+                mac_address: None,
+                addresses: Vec::new(),
+            });
+            interfaces.len() - 1
+        }
+    };
+    &mut interfaces[index]
+}
+
+fn format_mac_address(mac: &[u8]) -> String {
+    mac.iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+#[cfg(target_os = "linux")]
+pub fn handle_guest_info() -> anyhow::Result<GuestInfo> {
+    tracing::debug!("guest info request");
+
+    let read = |path: &str| fs_err::read_to_string(path).map(|s| s.trim().to_owned());
+    Ok(GuestInfo {
+        hostname: read("/proc/sys/kernel/hostname")?,
+        os_name: os_release_name().unwrap_or_else(|| "Linux".to_owned()),
+        os_version: read("/proc/sys/kernel/osrelease")?,
+        os_build: read("/proc/sys/kernel/version")?,
+        interfaces: interfaces()?,
+        filesystems: filesystems()?,
+        kernel_cmdline: Some(read("/proc/cmdline")?),
+    })
+}
+
+/// Returns the distribution's `PRETTY_NAME` from os-release, if there is one.
+/// Minimal environments such as initrds may not have one.
+#[cfg(target_os = "linux")]
+fn os_release_name() -> Option<String> {
+    let os_release = std::fs::read_to_string("/etc/os-release")
+        .or_else(|_| std::fs::read_to_string("/usr/lib/os-release"))
+        .ok()?;
+    os_release.lines().find_map(|line| {
+        let value = line.strip_prefix("PRETTY_NAME=")?;
+        Some(value.trim_matches(|c| c == '"' || c == '\'').to_owned())
+    })
+}
+
+#[cfg(target_os = "freebsd")]
+pub fn handle_guest_info() -> anyhow::Result<GuestInfo> {
+    use std::ffi::CStr;
+
+    tracing::debug!("guest info request");
+
+    // SAFETY: utsname is plain data, and is filled in by uname.
+    let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+    // SAFETY: calling uname as documented, with a valid pointer.
+    if unsafe { libc::uname(&mut uts) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // SAFETY: uname fills in each field with a null-terminated string.
+    let field = |f: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(f.as_ptr()) }
+            .to_string_lossy()
+            .trim()
+            .to_owned()
+    };
+    Ok(GuestInfo {
+        hostname: field(&uts.nodename),
+        os_name: field(&uts.sysname),
+        os_version: field(&uts.release),
+        os_build: field(&uts.version),
+        interfaces: interfaces()?,
+        filesystems: filesystems()?,
+        kernel_cmdline: None,
+    })
+}
+
+#[cfg(unix)]
+fn interfaces() -> anyhow::Result<Vec<NetworkInterface>> {
+    use std::ffi::CStr;
+    use std::net::Ipv4Addr;
+    use std::net::Ipv6Addr;
+
+    let mut ifaddrs = std::ptr::null_mut();
+    // SAFETY: calling getifaddrs as documented.
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let mut interfaces = Vec::new();
+    let mut p = ifaddrs;
+    while !p.is_null() {
+        // SAFETY: p is an entry in the list returned by getifaddrs, which is
+        // valid until it is freed below. The name is a valid C string, and
+        // the address and netmask, when present, point to socket addresses
+        // of the family they declare.
+        unsafe {
+            let ifa = &*p;
+            p = ifa.ifa_next;
+            let name = CStr::from_ptr(ifa.ifa_name).to_string_lossy();
+            let interface = interface(&mut interfaces, &name);
+            if ifa.ifa_addr.is_null() {
+                continue;
+            }
+            match (*ifa.ifa_addr).sa_family as i32 {
+                libc::AF_INET => {
+                    let addr = &*ifa.ifa_addr.cast::<libc::sockaddr_in>();
+                    let prefix_len = if ifa.ifa_netmask.is_null() {
+                        32
+                    } else {
+                        let mask = &*ifa.ifa_netmask.cast::<libc::sockaddr_in>();
+                        mask.sin_addr.s_addr.count_ones() as u8
+                    };
+                    interface.addresses.push(IpAddress {
+                        address: Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)).to_string(),
+                        prefix_len,
+                    });
+                }
+                libc::AF_INET6 => {
+                    let addr = &*ifa.ifa_addr.cast::<libc::sockaddr_in6>();
+                    let prefix_len = if ifa.ifa_netmask.is_null() {
+                        128
+                    } else {
+                        let mask = &*ifa.ifa_netmask.cast::<libc::sockaddr_in6>();
+                        mask.sin6_addr
+                            .s6_addr
+                            .iter()
+                            .map(|b| b.count_ones() as u8)
+                            .sum()
+                    };
+                    interface.addresses.push(IpAddress {
+                        address: Ipv6Addr::from(addr.sin6_addr.s6_addr).to_string(),
+                        prefix_len,
+                    });
+                }
+                #[cfg(target_os = "linux")]
+                libc::AF_PACKET => {
+                    let addr = &*ifa.ifa_addr.cast::<libc::sockaddr_ll>();
+                    let len = (addr.sll_halen as usize).min(addr.sll_addr.len());
+                    if len != 0 {
+                        interface.mac_address = Some(format_mac_address(&addr.sll_addr[..len]));
+                    }
+                }
+                #[cfg(target_os = "freebsd")]
+                libc::AF_LINK => {
+                    // The link-layer address follows the interface name.
+                    let addr = &*ifa.ifa_addr.cast::<libc::sockaddr_dl>();
+                    let start = addr.sdl_nlen as usize;
+                    let end = (start + addr.sdl_alen as usize).min(addr.sdl_data.len());
+                    if start < end {
+                        let mac: Vec<u8> =
+                            addr.sdl_data[start..end].iter().map(|&b| b as u8).collect();
+                        interface.mac_address = Some(format_mac_address(&mac));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // SAFETY: freeing the list returned by getifaddrs, which is no longer
+    // referenced.
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Ok(interfaces)
+}
+
+#[cfg(target_os = "linux")]
+fn filesystems() -> anyhow::Result<Vec<MountedFilesystem>> {
+    // Fields in the mount table escape spaces and other special characters
+    // as octal sequences, such as `\040`.
+    fn unescape(field: &str) -> String {
+        let bytes = field.as_bytes();
+        let mut out = Vec::with_capacity(bytes.len());
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'\\' {
+                if let Some(c) = field
+                    .get(i + 1..i + 4)
+                    .and_then(|s| u8::from_str_radix(s, 8).ok())
+                {
+                    out.push(c);
+                    i += 4;
+                    continue;
+                }
+            }
+            out.push(bytes[i]);
+            i += 1;
+        }
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    let mounts = fs_err::read_to_string("/proc/self/mounts")?;
+    Ok(mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let source = fields.next()?;
+            let mount_point = fields.next()?;
+            let fs_type = fields.next()?;
+            Some(MountedFilesystem {
+                source: unescape(source),
+                mount_point: unescape(mount_point),
+                fs_type: unescape(fs_type),
+            })
+        })
+        .collect())
+}
+
+#[cfg(target_os = "freebsd")]
+fn filesystems() -> anyhow::Result<Vec<MountedFilesystem>> {
+    use std::ffi::CStr;
+
+    let mut mounts = std::ptr::null_mut();
+    // SAFETY: calling getmntinfo as documented. The returned array is owned
+    // by libc and stays valid until the next call on this thread.
+    let count = unsafe { libc::getmntinfo(&mut mounts, libc::MNT_NOWAIT) };
+    if count <= 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // SAFETY: getmntinfo returned `count` entries at `mounts`.
+    let mounts = unsafe { std::slice::from_raw_parts(mounts, count as usize) };
+    // SAFETY: the statfs names are null-terminated strings.
+    let field = |f: &[libc::c_char]| {
+        unsafe { CStr::from_ptr(f.as_ptr()) }
+            .to_string_lossy()
+            .into_owned()
+    };
+    Ok(mounts
+        .iter()
+        .map(|mount| MountedFilesystem {
+            source: field(&mount.f_mntfromname),
+            mount_point: field(&mount.f_mntonname),
+            fs_type: field(&mount.f_fstypename),
+        })
+        .collect())
+}
+
+#[cfg(windows)]
+pub fn handle_guest_info() -> anyhow::Result<GuestInfo> {
+    use windows_sys::Wdk::System::SystemServices::RtlGetVersion;
+    use windows_sys::Win32::System::SystemInformation::OSVERSIONINFOW;
+
+    tracing::debug!("guest info request");
+
+    // SAFETY: OSVERSIONINFOW is a plain old data structure.
+    let mut version: OSVERSIONINFOW = unsafe { std::mem::zeroed() };
+    version.dwOSVersionInfoSize = size_of::<OSVERSIONINFOW>() as u32;
+    // SAFETY: calling RtlGetVersion with a valid, sized structure. It always
+    // succeeds.
+    unsafe { RtlGetVersion(&mut version) };
+
+    Ok(GuestInfo {
+        hostname: hostname()?,
+        os_name: product_name().unwrap_or_else(|| "Windows".to_owned()),
+        os_version: format!("{}.{}", version.dwMajorVersion, version.dwMinorVersion),
+        os_build: version.dwBuildNumber.to_string(),
+        interfaces: interfaces()?,
+        filesystems: filesystems(),
+        kernel_cmdline: None,
+    })
+}
+
+#[cfg(windows)]
+fn wide(s: &str) -> Vec<u16> {
+    s.encode_utf16().chain([0]).collect()
+}
+
+#[cfg(windows)]
+fn from_wide(s: &[u16]) -> String {
+    let len = s.iter().position(|&c| c == 0).unwrap_or(s.len());
+    String::from_utf16_lossy(&s[..len])
+}
+
+#[cfg(windows)]
+fn hostname() -> anyhow::Result<String> {
+    use windows_sys::Win32::System::SystemInformation::ComputerNameDnsHostname;
+    use windows_sys::Win32::System::SystemInformation::GetComputerNameExW;
+
+    let mut buf = [0u16; 256];
+    let mut len = buf.len() as u32;
+    // SAFETY: calling with a buffer of the specified length.
+    if unsafe { GetComputerNameExW(ComputerNameDnsHostname, buf.as_mut_ptr(), &mut len) } == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(from_wide(&buf[..len as usize]))
+}
+
+/// Returns the product name, such as `Windows Server 2022 Datacenter`.
+#[cfg(windows)]
+fn product_name() -> Option<String> {
+    use windows_sys::Win32::Foundation::ERROR_SUCCESS;
+    use windows_sys::Win32::System::Registry::RegGetValueW;
+    use windows_sys::Win32::System::Registry::HKEY_LOCAL_MACHINE;
+    use windows_sys::Win32::System::Registry::RRF_RT_REG_SZ;
+
+    let key = wide(r"SOFTWARE\Microsoft\Windows NT\CurrentVersion");
+    let value = wide("ProductName");
+    let mut buf = [0u16; 256];
+    let mut size = size_of_val(&buf) as u32;
+    // SAFETY: calling with valid strings and a buffer of the specified size.
+    let r = unsafe {
+        RegGetValueW(
+            HKEY_LOCAL_MACHINE,
+            key.as_ptr(),
+            value.as_ptr(),
+            RRF_RT_REG_SZ,
+            std::ptr::null_mut(),
+            buf.as_mut_ptr().cast(),
+            &mut size,
+        )
+    };
+    (r == ERROR_SUCCESS).then(|| from_wide(&buf))
+}
+
+#[cfg(windows)]
+fn interfaces() -> anyhow::Result<Vec<NetworkInterface>> {
+    use std::net::Ipv4Addr;
+    use std::net::Ipv6Addr;
+    use windows_sys::Win32::Foundation::ERROR_BUFFER_OVERFLOW;
+    use windows_sys::Win32::Foundation::ERROR_SUCCESS;
+    use windows_sys::Win32::NetworkManagement::IpHelper::GetAdaptersAddresses;
+    use windows_sys::Win32::NetworkManagement::IpHelper::GAA_FLAG_SKIP_ANYCAST;
+    use windows_sys::Win32::NetworkManagement::IpHelper::GAA_FLAG_SKIP_DNS_SERVER;
+    use windows_sys::Win32::NetworkManagement::IpHelper::GAA_FLAG_SKIP_MULTICAST;
+    use windows_sys::Win32::NetworkManagement::IpHelper::IP_ADAPTER_ADDRESSES_LH;
+    use windows_sys::Win32::Networking::WinSock::AF_INET;
+    use windows_sys::Win32::Networking::WinSock::AF_INET6;
+    use windows_sys::Win32::Networking::WinSock::AF_UNSPEC;
+    use windows_sys::Win32::Networking::WinSock::SOCKADDR_IN;
+    use windows_sys::Win32::Networking::WinSock::SOCKADDR_IN6;
+
+    let flags = GAA_FLAG_SKIP_ANYCAST | GAA_FLAG_SKIP_MULTICAST | GAA_FLAG_SKIP_DNS_SERVER;
+
+    // Use a u64 buffer to get sufficient alignment for the adapter addresses.
+    let mut buf = Vec::<u64>::new();
+    loop {
+        let mut size = (buf.len() * 8) as u32;
+        // SAFETY: calling with a buffer of the specified size.
+        let r = unsafe {
+            GetAdaptersAddresses(
+                AF_UNSPEC.into(),
+                flags,
+                std::ptr::null_mut(),
+                buf.as_mut_ptr().cast(),
+                &mut size,
+            )
+        };
+        match r {
+            ERROR_SUCCESS => break,
+            ERROR_BUFFER_OVERFLOW => buf.resize((size as usize).div_ceil(8), 0),
+            err => return Err(std::io::Error::from_raw_os_error(err as i32).into()),
+        }
+    }
+
+    let mut interfaces = Vec::new();
+    let mut adapter_p = if buf.is_empty() {
+        std::ptr::null()
+    } else {
+        buf.as_ptr().cast::<IP_ADAPTER_ADDRESSES_LH>()
+    };
+    while !adapter_p.is_null() {
+        // SAFETY: walking the list written to the buffer by
+        // GetAdaptersAddresses, trusting that it produced valid entries.
+        unsafe {
+            let adapter = &*adapter_p;
+            adapter_p = adapter.Next;
+
+            let mut name_len = 0;
+            while *adapter.FriendlyName.add(name_len) != 0 {
+                name_len += 1;
+            }
+            let name = from_wide(std::slice::from_raw_parts(adapter.FriendlyName, name_len));
+            let interface = interface(&mut interfaces, &name);
+
+            let mac_len =
+                (adapter.PhysicalAddressLength as usize).min(adapter.PhysicalAddress.len());
+            if mac_len != 0 {
+                interface.mac_address =
+                    Some(format_mac_address(&adapter.PhysicalAddress[..mac_len]));
+            }
+
+            let mut unicast_p = adapter.FirstUnicastAddress;
+            while !unicast_p.is_null() {
+                let unicast = &*unicast_p;
+                unicast_p = unicast.Next;
+                let sockaddr = unicast.Address.lpSockaddr;
+                let address = match (*sockaddr).sa_family {
+                    AF_INET => {
+                        let addr = &*sockaddr.cast::<SOCKADDR_IN>();
+                        Ipv4Addr::from(u32::from_be(addr.sin_addr.S_un.S_addr)).to_string()
+                    }
+                    AF_INET6 => {
+                        let addr = &*sockaddr.cast::<SOCKADDR_IN6>();
+                        Ipv6Addr::from(addr.sin6_addr.u.Byte).to_string()
+                    }
+                    _ => continue,
+                };
+                interface.addresses.push(IpAddress {
+                    address,
+                    prefix_len: unicast.OnLinkPrefixLength,
+                });
+            }
+        }
+    }
+    Ok(interfaces)
+}
+
+/// Returns the volumes mounted at drive letters. Drives without a mounted
+/// volume, such as empty optical drives, are skipped.
+#[cfg(windows)]
+fn filesystems() -> Vec<MountedFilesystem> {
+    use windows_sys::Win32::Storage::FileSystem::GetLogicalDrives;
+    use windows_sys::Win32::Storage::FileSystem::GetVolumeInformationW;
+    use windows_sys::Win32::Storage::FileSystem::GetVolumeNameForVolumeMountPointW;
+
+    // SAFETY: GetLogicalDrives has no safety requirements.
+    let drives = unsafe { GetLogicalDrives() };
+    (0..26)
+        .filter(|i| drives & (1 << i) != 0)
+        .filter_map(|i| {
+            let mount_point = format!("{}:\\", (b'A' + i as u8) as char);
+            let root = wide(&mount_point);
+            let mut fs_type = [0u16; 32];
+            // SAFETY: calling with a valid root path and a buffer of the
+            // specified length.
+            let ok = unsafe {
+                GetVolumeInformationW(
+                    root.as_ptr(),
+                    std::ptr::null_mut(),
+                    0,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    fs_type.as_mut_ptr(),
+                    fs_type.len() as u32,
+                )
+            };
+            if ok == 0 {
+                return None;
+            }
+            let mut volume = [0u16; 64];
+            // SAFETY: calling with a valid root path and a buffer of the
+            // specified length.
+            let source = if unsafe {
+                GetVolumeNameForVolumeMountPointW(
+                    root.as_ptr(),
+                    volume.as_mut_ptr(),
+                    volume.len() as u32,
+                )
+            } != 0
+            {
+                from_wide(&volume)
+            } else {
+                mount_point.clone()
+            };
+            Some(MountedFilesystem {
+                source,
+                mount_point,
+                fs_type: from_wide(&fs_type),
+            })
+        })
+        .collect()
+}
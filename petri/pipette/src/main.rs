@@ -5,8 +5,13 @@
 //! commands and other requests from the host.
 
 mod agent;
+mod crash;
 mod execute;
+mod file_transfer;
+mod guest_info;
+mod pty;
 mod shutdown;
+mod tcp;
 mod trace;
 #[cfg(windows)]
 mod winsvc;
@@ -18,15 +23,38 @@ fn main() -> anyhow::Result<()> {
     anyhow::bail!("unsupported on macos")
 }
 
-#[cfg(any(target_os = "linux", target_os = "windows"))]
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
 fn main() -> anyhow::Result<()> {
     #[cfg(windows)]
     if std::env::args().nth(1).as_deref() == Some("--service") {
         return winsvc::start_service();
     }
 
+    let options = parse_args()?;
     pal_async::DefaultPool::run_with(|driver| async move {
-        let agent = agent::Agent::new(driver).await?;
+        let agent = agent::Agent::new(driver, options).await?;
         agent.run().await
     })
 }
+
+/// Parses the command line. On Linux, `--vsock-cid <cid>` connects to the
+/// given AF_VSOCK CID instead of the host CID.
+#[cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+fn parse_args() -> anyhow::Result<agent::ConnectOptions> {
+    use anyhow::Context;
+
+    #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+    let mut options = agent::ConnectOptions::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            #[cfg(target_os = "linux")]
+            "--vsock-cid" => {
+                let cid = args.next().context("missing value for --vsock-cid")?;
+                options.vsock_cid = Some(cid.parse().context("invalid --vsock-cid")?);
+            }
+            _ => anyhow::bail!("unknown argument {arg}"),
+        }
+    }
+    Ok(options)
+}
@@ -0,0 +1,90 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Unix pseudo-terminal and signal support for the execute request.
+
+#![cfg(any(target_os = "linux", target_os = "freebsd"))]
+// UNSAFETY: calling libc terminal and process APIs.
+#![allow(unsafe_code)]
+
+use pipette_protocol::WindowSize;
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+use std::ptr::null_mut;
+
+/// Opens a new pseudo-terminal with the given window size, returning the
+/// primary and secondary ends.
+pub fn new_pty(size: WindowSize) -> io::Result<(File, File)> {
+    let mut winsize = to_winsize(size);
+    let mut primary = 0;
+    let mut secondary = 0;
+    // SAFETY: calling openpty as documented, with valid pointers.
+    let (primary, secondary) = unsafe {
+        if libc::openpty(
+            &mut primary,
+            &mut secondary,
+            null_mut(),
+            null_mut(),
+            &mut winsize,
+        ) < 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        (File::from_raw_fd(primary), File::from_raw_fd(secondary))
+    };
+    // Don't leak the pty into other processes launched by the agent.
+    for file in [&primary, &secondary] {
+        // SAFETY: calling fcntl on an owned fd.
+        if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok((primary, secondary))
+}
+
+/// Sets the window size of the pseudo-terminal with primary end `primary`,
+/// notifying the foreground process group with `SIGWINCH`.
+pub fn set_window_size(primary: &File, size: WindowSize) -> io::Result<()> {
+    let winsize = to_winsize(size);
+    // SAFETY: calling TIOCSWINSZ on an owned fd with a valid winsize.
+    if unsafe { libc::ioctl(primary.as_raw_fd(), libc::TIOCSWINSZ, &winsize) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Configures `command` to run in a new session with its standard input as
+/// the controlling terminal.
+pub fn set_controlling_terminal(command: &mut Command) {
+    // SAFETY: the closure only calls async-signal-safe functions.
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() < 0 || libc::ioctl(0, libc::TIOCSCTTY, 0) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Sends `signal` to the process `pid`.
+pub fn send_signal(pid: u32, signal: i32) -> io::Result<()> {
+    // SAFETY: kill has no memory safety requirements.
+    if unsafe { libc::kill(pid as libc::pid_t, signal) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn to_winsize(size: WindowSize) -> libc::winsize {
+    libc::winsize {
+        ws_row: size.rows,
+        ws_col: size.cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
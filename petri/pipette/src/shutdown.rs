@@ -3,7 +3,7 @@
 
 //! Handler for the power off request.
 
-#![cfg(any(target_os = "linux", target_os = "windows"))]
+#![cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
 // UNSAFETY: required for Windows shutdown API
 #![cfg_attr(windows, allow(unsafe_code))]
 
@@ -34,8 +34,66 @@ pub fn handle_shutdown(request: pipette_protocol::ShutdownRequest) -> anyhow::Re
     }
 }
 
+#[cfg(target_os = "freebsd")]
+pub fn handle_shutdown(request: pipette_protocol::ShutdownRequest) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    let flag = match request.shutdown_type {
+        pipette_protocol::ShutdownType::PowerOff => "-p",
+        pipette_protocol::ShutdownType::Reboot => "-r",
+    };
+    let output = std::process::Command::new("shutdown")
+        .args([flag, "now"])
+        .output()
+        .context("failed to launch shutdown")?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        anyhow::bail!("failed to shut down: {}", output.status);
+    }
+}
+
 #[cfg(windows)]
 pub fn handle_shutdown(request: pipette_protocol::ShutdownRequest) -> anyhow::Result<()> {
+    use anyhow::Context;
+    use std::ptr::null_mut;
+    use windows_sys::Win32::System::Shutdown::InitiateShutdownW;
+    use windows_sys::Win32::System::Shutdown::SHTDN_REASON_FLAG_PLANNED;
+    use windows_sys::Win32::System::Shutdown::SHTDN_REASON_MAJOR_OTHER;
+    use windows_sys::Win32::System::Shutdown::SHTDN_REASON_MINOR_OTHER;
+    use windows_sys::Win32::System::Shutdown::SHUTDOWN_FORCE_OTHERS;
+    use windows_sys::Win32::System::Shutdown::SHUTDOWN_FORCE_SELF;
+    use windows_sys::Win32::System::Shutdown::SHUTDOWN_GRACE_OVERRIDE;
+    use windows_sys::Win32::System::Shutdown::SHUTDOWN_POWEROFF;
+    use windows_sys::Win32::System::Shutdown::SHUTDOWN_RESTART;
+
+    enable_shutdown_privilege()?;
+
+    let flag = match request.shutdown_type {
+        pipette_protocol::ShutdownType::PowerOff => SHUTDOWN_POWEROFF,
+        pipette_protocol::ShutdownType::Reboot => SHUTDOWN_RESTART,
+    };
+
+    // SAFETY: calling as documented
+    let win32_err = unsafe {
+        InitiateShutdownW(
+            null_mut(),
+            null_mut(),
+            0,
+            SHUTDOWN_GRACE_OVERRIDE | SHUTDOWN_FORCE_SELF | SHUTDOWN_FORCE_OTHERS | flag,
+            SHTDN_REASON_MAJOR_OTHER | SHTDN_REASON_MINOR_OTHER | SHTDN_REASON_FLAG_PLANNED,
+        )
+    };
+    if win32_err != 0 {
+        return Err(std::io::Error::from_raw_os_error(win32_err as i32))
+            .context("failed to initiate shutdown");
+    }
+    Ok(())
+}
+
+/// Enables the shutdown privilege on the current process.
+#[cfg(windows)]
+pub fn enable_shutdown_privilege() -> anyhow::Result<()> {
     use anyhow::Context;
     use std::os::windows::io::AsRawHandle;
     use std::os::windows::io::FromRawHandle;
@@ -49,20 +107,9 @@ pub fn handle_shutdown(request: pipette_protocol::ShutdownRequest) -> anyhow::Re
     use windows_sys::Win32::Security::TOKEN_ADJUST_PRIVILEGES;
     use windows_sys::Win32::Security::TOKEN_PRIVILEGES;
     use windows_sys::Win32::Security::TOKEN_QUERY;
-    use windows_sys::Win32::System::Shutdown::InitiateShutdownW;
-    use windows_sys::Win32::System::Shutdown::SHTDN_REASON_FLAG_PLANNED;
-    use windows_sys::Win32::System::Shutdown::SHTDN_REASON_MAJOR_OTHER;
-    use windows_sys::Win32::System::Shutdown::SHTDN_REASON_MINOR_OTHER;
-    use windows_sys::Win32::System::Shutdown::SHUTDOWN_FORCE_OTHERS;
-    use windows_sys::Win32::System::Shutdown::SHUTDOWN_FORCE_SELF;
-    use windows_sys::Win32::System::Shutdown::SHUTDOWN_GRACE_OVERRIDE;
-    use windows_sys::Win32::System::Shutdown::SHUTDOWN_POWEROFF;
-    use windows_sys::Win32::System::Shutdown::SHUTDOWN_RESTART;
     use windows_sys::Win32::System::Threading::GetCurrentProcess;
     use windows_sys::Win32::System::Threading::OpenProcessToken;
 
-    // Enable the shutdown privilege on the current process.
-
     // SAFETY: calling as documented
     let token = unsafe {
         let mut token = 0;
@@ -100,25 +147,6 @@ pub fn handle_shutdown(request: pipette_protocol::ShutdownRequest) -> anyhow::Re
         return Err(std::io::Error::last_os_error()).context("failed to adjust token privileges");
     }
 
-    let flag = match request.shutdown_type {
-        pipette_protocol::ShutdownType::PowerOff => SHUTDOWN_POWEROFF,
-        pipette_protocol::ShutdownType::Reboot => SHUTDOWN_RESTART,
-    };
-
-    // SAFETY: calling as documented
-    let win32_err = unsafe {
-        InitiateShutdownW(
-            null_mut(),
-            null_mut(),
-            0,
-            SHUTDOWN_GRACE_OVERRIDE | SHUTDOWN_FORCE_SELF | SHUTDOWN_FORCE_OTHERS | flag,
-            SHTDN_REASON_MAJOR_OTHER | SHTDN_REASON_MINOR_OTHER | SHTDN_REASON_FLAG_PLANNED,
-        )
-    };
-    if win32_err != 0 {
-        return Err(std::io::Error::from_raw_os_error(win32_err as i32))
-            .context("failed to initiate shutdown");
-    }
     Ok(())
 }
 
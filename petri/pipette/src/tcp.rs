@@ -0,0 +1,143 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Handlers for the TCP forwarding requests.
+
+#![cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
+
+use anyhow::Context;
+use futures::AsyncWriteExt;
+use futures_concurrency::future::Race;
+use futures_concurrency::future::TryJoin;
+use pal_async::socket::PolledSocket;
+use pal_async::task::Spawn;
+use pal_async::DefaultDriver;
+use pipette_protocol::ConnectTcpRequest;
+use pipette_protocol::ListenTcpRequest;
+use pipette_protocol::TcpConnection;
+use pipette_protocol::TcpStreamPipes;
+use std::net::Shutdown;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+
+pub async fn handle_connect_tcp(
+    driver: &DefaultDriver,
+    request: ConnectTcpRequest,
+) -> anyhow::Result<()> {
+    tracing::debug!(address = request.address, "connect tcp request");
+
+    let address: SocketAddr = request.address.parse().context("invalid address")?;
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(address),
+        socket2::Type::STREAM,
+        Some(socket2::Protocol::TCP),
+    )?;
+    let mut socket = PolledSocket::new(driver, socket)?;
+    socket
+        .connect(&address.into())
+        .await
+        .with_context(|| format!("failed to connect to {address}"))?;
+
+    let socket = socket.convert::<TcpStream>();
+    driver
+        .spawn("tcp-relay", async move {
+            if let Err(err) = relay(socket, request.pipes).await {
+                tracing::debug!(error = &err as &dyn std::error::Error, "tcp relay failed");
+            }
+        })
+        .detach();
+    Ok(())
+}
+
+pub fn handle_listen_tcp(
+    driver: &DefaultDriver,
+    request: ListenTcpRequest,
+) -> anyhow::Result<String> {
+    tracing::debug!(address = request.address, "listen tcp request");
+
+    let address: SocketAddr = request.address.parse().context("invalid address")?;
+    let listener =
+        TcpListener::bind(address).with_context(|| format!("failed to listen on {address}"))?;
+    let local_address = listener.local_addr()?;
+    let mut listener = PolledSocket::new(driver, listener)?;
+
+    let ListenTcpRequest {
+        address: _,
+        connections,
+        cancel,
+    } = request;
+    driver
+        .spawn("tcp-listener", {
+            let driver = driver.clone();
+            async move {
+                let accept = async {
+                    loop {
+                        let (socket, peer_address) = match listener.accept().await {
+                            Ok(r) => r,
+                            Err(err) => {
+                                tracing::error!(
+                                    error = &err as &dyn std::error::Error,
+                                    "failed to accept tcp connection"
+                                );
+                                break;
+                            }
+                        };
+                        tracing::debug!(%peer_address, "accepted tcp connection");
+                        let socket = match PolledSocket::new(&driver, socket) {
+                            Ok(socket) => socket,
+                            Err(err) => {
+                                tracing::error!(
+                                    error = &err as &dyn std::error::Error,
+                                    "failed to create polled socket"
+                                );
+                                continue;
+                            }
+                        };
+                        let (pipes, remote_pipes) = TcpStreamPipes::pair();
+                        connections.send(TcpConnection {
+                            peer_address: peer_address.to_string(),
+                            pipes: remote_pipes,
+                        });
+                        driver
+                            .spawn("tcp-relay", async move {
+                                if let Err(err) = relay(socket, pipes).await {
+                                    tracing::debug!(
+                                        error = &err as &dyn std::error::Error,
+                                        "tcp relay failed"
+                                    );
+                                }
+                            })
+                            .detach();
+                    }
+                };
+                let cancel = async {
+                    let _ = cancel.await;
+                };
+                (accept, cancel).race().await;
+                tracing::debug!(%local_address, "stopped listening");
+            }
+        })
+        .detach();
+
+    Ok(local_address.to_string())
+}
+
+/// Relays data between `socket` and `pipes` until both directions are closed.
+async fn relay(socket: PolledSocket<TcpStream>, pipes: TcpStreamPipes) -> std::io::Result<()> {
+    let (mut read, mut write) = socket.split();
+    let TcpStreamPipes {
+        mut receiver,
+        mut sender,
+    } = pipes;
+    let outgoing = async {
+        futures::io::copy(&mut read, &mut sender).await?;
+        sender.close().await
+    };
+    let incoming = async {
+        futures::io::copy(&mut receiver, &mut write).await?;
+        write.get().shutdown(Shutdown::Write)
+    };
+    (outgoing, incoming).try_join().await?;
+    Ok(())
+}
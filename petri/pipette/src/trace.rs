@@ -3,7 +3,7 @@
 
 //! [`tracing`] support.
 
-#![cfg(any(target_os = "linux", target_os = "windows"))]
+#![cfg(any(target_os = "linux", target_os = "freebsd", target_os = "windows"))]
 
 use std::sync::Arc;
 use tracing_subscriber::filter::Targets;
@@ -63,7 +63,7 @@ async fn service_main_inner(driver: DefaultDriver) -> anyhow::Result<()> {
     set_status(service::ServiceState::StartPending)?;
 
     let run = async {
-        let agent = Agent::new(driver).await?;
+        let agent = Agent::new(driver, Default::default()).await?;
         set_status(service::ServiceState::Running)?;
         agent.run().await
     };
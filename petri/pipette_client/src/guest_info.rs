@@ -0,0 +1,124 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Typed information about the guest, queried from the agent.
+
+use crate::PipetteClient;
+use anyhow::Context;
+use pipette_protocol::PipetteRequest;
+use std::net::IpAddr;
+
+pub use pipette_protocol::MountedFilesystem;
+
+/// Information about the guest's identity and configuration, from
+/// [`PipetteClient::guest_info`].
+#[derive(Debug)]
+pub struct GuestInfo {
+    /// The guest's host name.
+    pub hostname: String,
+    /// The name of the operating system, such as `Ubuntu 22.04.4 LTS`.
+    pub os_name: String,
+    /// The operating system version: the kernel release on Linux, or the
+    /// major and minor version on Windows.
+    pub os_version: String,
+    /// The operating system build: the kernel build string on Linux, or the
+    /// build number on Windows.
+    pub os_build: String,
+    /// The network interfaces.
+    pub interfaces: Vec<NetworkInterface>,
+    /// The mounted filesystems.
+    pub filesystems: Vec<MountedFilesystem>,
+    /// The kernel command line, on Linux.
+    pub kernel_cmdline: Option<String>,
+}
+
+impl GuestInfo {
+    /// Returns the network interface named `name`.
+    pub fn interface(&self, name: &str) -> Option<&NetworkInterface> {
+        self.interfaces.iter().find(|i| i.name == name)
+    }
+
+    /// Returns the filesystem mounted at `mount_point`. If there are several,
+    /// the last one mounted is returned.
+    pub fn filesystem(&self, mount_point: &str) -> Option<&MountedFilesystem> {
+        self.filesystems
+            .iter()
+            .rev()
+            .find(|f| f.mount_point == mount_point)
+    }
+}
+
+/// A network interface in the guest.
+#[derive(Debug)]
+pub struct NetworkInterface {
+    /// The name of the interface.
+    pub name: String,
+    /// The MAC address, as lower case hex bytes separated by colons.
+    pub mac_address: Option<String>,
+    /// The IP addresses assigned to the interface.
+    pub addresses: Vec<InterfaceAddress>,
+}
+
+impl NetworkInterface {
+    /// Returns whether `address` is assigned to the interface.
+    pub fn has_address(&self, address: impl Into<IpAddr>) -> bool {
+        let address = address.into();
+        self.addresses.iter().any(|a| a.address == address)
+    }
+}
+
+/// An IP address assigned to a network interface.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct InterfaceAddress {
+    /// The address.
+    pub address: IpAddr,
+    /// The length of the network prefix, in bits.
+    pub prefix_len: u8,
+}
+
+impl PipetteClient {
+    /// Queries the guest's host name, OS version, network interfaces, mounted
+    /// filesystems, and kernel command line.
+    pub async fn guest_info(&self) -> anyhow::Result<GuestInfo> {
+        let info = self
+            .send
+            .call(PipetteRequest::GuestInfo, ())
+            .await?
+            .map_err(anyhow::Error::from)
+            .context("failed to query guest info")?;
+
+        let interfaces = info
+            .interfaces
+            .into_iter()
+            .map(|interface| {
+                let addresses = interface
+                    .addresses
+                    .into_iter()
+                    .map(|a| {
+                        Ok(InterfaceAddress {
+                            address: a.address.parse().with_context(|| {
+                                format!("guest returned an invalid address {:?}", a.address)
+                            })?,
+                            prefix_len: a.prefix_len,
+                        })
+                    })
+                    .collect::<anyhow::Result<_>>()?;
+                Ok(NetworkInterface {
+                    name: interface.name,
+                    mac_address: interface.mac_address,
+                    addresses,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(GuestInfo {
+            hostname: info.hostname,
+            os_name: info.os_name,
+            os_version: info.os_version,
+            os_build: info.os_build,
+            interfaces,
+            filesystems: info.filesystems,
+            kernel_cmdline: info.kernel_cmdline,
+        })
+    }
+}
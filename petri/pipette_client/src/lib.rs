@@ -6,10 +6,14 @@
 #![warn(missing_docs)]
 #![forbid(unsafe_code)]
 
+pub mod guest_info;
 pub mod process;
 mod send;
 pub mod shell;
+pub mod tcp;
 
+pub use pipette_protocol::CrashDumpFile;
+pub use pipette_protocol::CrashDumpKind;
 pub use pipette_protocol::PIPETTE_VSOCK_PORT;
 
 use crate::send::PipetteSender;
@@ -17,6 +21,7 @@
 use futures::io::BufReader;
 use futures::AsyncBufReadExt;
 use futures::AsyncRead;
+use futures::AsyncReadExt;
 use futures::AsyncWrite;
 use futures::AsyncWriteExt;
 use futures::StreamExt;
@@ -26,12 +31,20 @@
 use pal_async::task::Spawn;
 use pal_async::task::Task;
 use pipette_protocol::DiagnosticFile;
+use pipette_protocol::FileDigest;
 use pipette_protocol::PipetteBootstrap;
 use pipette_protocol::PipetteRequest;
+use pipette_protocol::PullFileRequest;
+use pipette_protocol::PushFileRequest;
 use pipette_protocol::ReadFileRequest;
 use pipette_protocol::WriteFileRequest;
+use pipette_protocol::FILE_TRANSFER_CHUNK_SIZE;
+use sha2::Digest;
+use sha2::Sha256;
 use shell::UnixShell;
 use shell::WindowsShell;
+use std::io::Read;
+use std::io::Write;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -132,6 +145,46 @@ async fn shutdown(&self, shutdown_type: pipette_protocol::ShutdownType) -> anyho
         Ok(())
     }
 
+    /// Configures the guest to write a crash dump of the given kind when it
+    /// crashes.
+    ///
+    /// On Linux, dumps are written by the distribution's kdump service, which
+    /// must already have loaded a crash kernel, and `kind` is not used. On
+    /// Windows, the setting may not take effect until the guest reboots.
+    pub async fn configure_crash_dump(&self, kind: CrashDumpKind) -> anyhow::Result<()> {
+        self.send
+            .call(PipetteRequest::ConfigureCrashDump, kind)
+            .await?
+            .map_err(anyhow::Error::from)
+            .context("failed to configure crash dumps")
+    }
+
+    /// Crashes the guest: panics the kernel on Linux, or bugchecks Windows.
+    ///
+    /// The guest crashes shortly after this returns. Use
+    /// [`Self::list_crash_dumps`] after the guest restarts to find the
+    /// resulting dump.
+    pub async fn crash(&self) -> anyhow::Result<()> {
+        match self.send.call(PipetteRequest::Crash, ()).await {
+            Ok(r) => r
+                .map_err(anyhow::Error::from)
+                .context("failed to crash the guest")?,
+            Err(_) => {
+                // Presumably the guest crashed before the response was sent.
+            }
+        }
+        Ok(())
+    }
+
+    /// Lists the crash dump files left in the guest by previous crashes.
+    pub async fn list_crash_dumps(&self) -> anyhow::Result<Vec<CrashDumpFile>> {
+        self.send
+            .call(PipetteRequest::ListCrashDumps, ())
+            .await?
+            .map_err(anyhow::Error::from)
+            .context("failed to list crash dumps")
+    }
+
     /// Reads the full contents of a file.
     pub async fn read_file(&self, path: impl AsRef<str>) -> anyhow::Result<Vec<u8>> {
         let (recv_pipe, send_pipe) = mesh::pipe::pipe();
@@ -194,12 +247,195 @@ pub async fn write_file(
         request_result.map_err(anyhow::Error::from)
     }
 
+    /// Copies the file at `host_path` to `guest_path` in the guest.
+    ///
+    /// The file is sent in chunks, calling `progress` after each one. The
+    /// guest only replaces `guest_path` once the full contents have been
+    /// received and match the digest of the host file.
+    pub async fn copy_to_guest(
+        &self,
+        host_path: impl AsRef<Path>,
+        guest_path: impl AsRef<str>,
+        mut progress: impl FnMut(TransferProgress),
+    ) -> anyhow::Result<()> {
+        let host_path = host_path.as_ref();
+        let guest_path = guest_path.as_ref();
+
+        // Compute the digest up front so that the guest can verify the
+        // contents before committing them.
+        let digest = file_digest(host_path)?;
+        let mut file = fs_err::File::open(host_path)?;
+
+        let (recv_pipe, mut send_pipe) = mesh::pipe::pipe();
+        let req = PushFileRequest {
+            path: guest_path.to_string(),
+            digest,
+            receiver: recv_pipe,
+        };
+
+        let request_future = self
+            .send
+            .call(PipetteRequest::PushFile, req)
+            .map_err(anyhow::Error::from);
+
+        let transfer_future = async {
+            let mut buf = vec![0; FILE_TRANSFER_CHUNK_SIZE];
+            let mut transferred = 0;
+            loop {
+                let n = file.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                send_pipe.write_all(&buf[..n]).await?;
+                transferred += n as u64;
+                progress(TransferProgress {
+                    transferred,
+                    total: digest.size,
+                });
+            }
+            send_pipe.close().await?;
+            anyhow::Ok(())
+        };
+
+        tracing::debug!(
+            host_path = %host_path.display(),
+            guest_path,
+            size = digest.size,
+            "beginning file push"
+        );
+        let (request_result, ()) = (request_future, transfer_future).try_join().await?;
+        request_result
+            .map_err(anyhow::Error::from)
+            .with_context(|| format!("failed to write {guest_path} in the guest"))?;
+
+        tracing::debug!("file push complete");
+        Ok(())
+    }
+
+    /// Copies the file at `guest_path` in the guest to `host_path`.
+    ///
+    /// The file is received in chunks, calling `progress` after each one.
+    /// `host_path` is only replaced once the full contents have been received
+    /// and match the digest computed by the guest.
+    pub async fn copy_from_guest(
+        &self,
+        guest_path: impl AsRef<str>,
+        host_path: impl AsRef<Path>,
+        mut progress: impl FnMut(TransferProgress),
+    ) -> anyhow::Result<()> {
+        let guest_path = guest_path.as_ref();
+        let host_path = host_path.as_ref();
+
+        let (mut recv_pipe, send_pipe) = mesh::pipe::pipe();
+        let (size_send, size_recv) = mesh::oneshot();
+        let req = PullFileRequest {
+            path: guest_path.to_string(),
+            size: size_send,
+            sender: send_pipe,
+        };
+
+        let request_future = self
+            .send
+            .call(PipetteRequest::PullFile, req)
+            .map_err(anyhow::Error::from);
+
+        // Receive into a file next to the destination, so that the
+        // destination is only replaced once the contents have been verified.
+        let mut partial_path = host_path.as_os_str().to_owned();
+        partial_path.push(".partial");
+        let partial_path = PathBuf::from(partial_path);
+
+        let transfer_future = async {
+            // If the guest fails to open the file, it drops the size sender.
+            // Let the request report the failure.
+            let Ok(total) = size_recv.await else {
+                return Ok(None);
+            };
+            let mut file = fs_err::File::create(&partial_path)?;
+            let mut hasher = Sha256::new();
+            let mut buf = vec![0; FILE_TRANSFER_CHUNK_SIZE];
+            let mut transferred = 0;
+            loop {
+                let n = recv_pipe.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                file.write_all(&buf[..n])?;
+                hasher.update(&buf[..n]);
+                transferred += n as u64;
+                progress(TransferProgress { transferred, total });
+            }
+            anyhow::Ok(Some(FileDigest {
+                size: transferred,
+                sha256: hasher.finalize().into(),
+            }))
+        };
+
+        tracing::debug!(
+            guest_path,
+            host_path = %host_path.display(),
+            "beginning file pull"
+        );
+        let result = (request_future, transfer_future).try_join().await.and_then(
+            |(request_result, received)| {
+                let expected = request_result
+                    .map_err(anyhow::Error::from)
+                    .with_context(|| format!("failed to read {guest_path} in the guest"))?;
+                let received = received.context("guest did not send the file size")?;
+                if received != expected {
+                    anyhow::bail!(
+                        "received contents do not match: expected {expected:?}, got {received:?}"
+                    );
+                }
+                Ok(())
+            },
+        );
+        if let Err(err) = result {
+            let _ = fs_err::remove_file(&partial_path);
+            return Err(err);
+        }
+        fs_err::rename(&partial_path, host_path)?;
+
+        tracing::debug!("file pull complete");
+        Ok(())
+    }
+
     /// Waits for the agent to exit.
     pub async fn wait(self) -> Result<(), mesh::RecvError> {
         self.watch.await
     }
 }
 
+/// The progress of a transfer by [`PipetteClient::copy_to_guest`] or
+/// [`PipetteClient::copy_from_guest`].
+#[derive(Debug, Copy, Clone)]
+pub struct TransferProgress {
+    /// The number of bytes transferred so far.
+    pub transferred: u64,
+    /// The size of the file being transferred.
+    pub total: u64,
+}
+
+/// Computes the size and digest of the file at `path`.
+fn file_digest(path: &Path) -> anyhow::Result<FileDigest> {
+    let mut file = fs_err::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; FILE_TRANSFER_CHUNK_SIZE];
+    let mut size = 0;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+        size += n as u64;
+    }
+    Ok(FileDigest {
+        size,
+        sha256: hasher.finalize().into(),
+    })
+}
+
 async fn replay_logs(log: mesh::pipe::ReadPipe) {
     let mut lines = BufReader::new(log).lines();
     while let Some(line) = lines.next().await {
@@ -12,8 +12,11 @@
 use mesh::error::RemoteResultExt;
 use mesh::pipe::ReadPipe;
 use mesh::pipe::WritePipe;
+use mesh::rpc::RpcSend;
 use pipette_protocol::EnvPair;
 use pipette_protocol::PipetteRequest;
+use pipette_protocol::ProcessControl;
+use pipette_protocol::WindowSize;
 use std::fmt;
 
 /// A builder for launching a command inside the guest.
@@ -29,6 +32,7 @@ pub struct Command<'a> {
     stderr: Option<Stdio>,
     env: Vec<EnvPair>,
     clear_env: bool,
+    pty: Option<WindowSize>,
 }
 
 impl<'a> Command<'a> {
@@ -43,6 +47,7 @@ pub(super) fn new(client: &'a PipetteClient, program: impl AsRef<str>) -> Self {
             stderr: None,
             env: Vec::new(),
             clear_env: false,
+            pty: None,
         }
     }
 
@@ -111,6 +116,19 @@ pub fn stderr(&mut self, stderr: impl Into<Stdio>) -> &mut Self {
         self
     }
 
+    /// Runs the command attached to a new pseudo-terminal with the given
+    /// initial window size, for driving interactive programs.
+    ///
+    /// The terminal's input and output are relayed through the child's stdin
+    /// and stdout. The command's standard error is written to the terminal
+    /// too, so any stderr configuration is ignored.
+    ///
+    /// Only supported for Linux guests.
+    pub fn pty(&mut self, rows: u16, cols: u16) -> &mut Self {
+        self.pty = Some(WindowSize { rows, cols });
+        self
+    }
+
     /// Spawns the command, defaulting to inheriting (relaying, really) the
     /// current process for stdin, stdout, and stderr.
     pub async fn spawn(&self) -> anyhow::Result<Child> {
@@ -147,11 +165,15 @@ async fn spawn_inner(
             .as_ref()
             .map_or(default_stdio, |x| &x.0)
             .pipes(StdioFd::Stdout);
-        let (stderr_read, stderr_write) = self
-            .stderr
-            .as_ref()
-            .map_or(default_stdio, |x| &x.0)
-            .pipes(StdioFd::Stderr);
+        let (stderr_read, stderr_write) = if self.pty.is_some() {
+            (None, None)
+        } else {
+            self.stderr
+                .as_ref()
+                .map_or(default_stdio, |x| &x.0)
+                .pipes(StdioFd::Stderr)
+        };
+        let (control_send, control_recv) = mesh::channel();
 
         let request = pipette_protocol::ExecuteRequest {
             program: self.program.clone(),
@@ -162,6 +184,8 @@ async fn spawn_inner(
             stderr: stderr_write,
             env: self.env.clone(),
             clear_env: self.clear_env,
+            pty: self.pty,
+            control: Some(control_recv),
         };
 
         let response = self
@@ -178,6 +202,7 @@ async fn spawn_inner(
             stderr: stderr_read,
             pid: response.pid,
             result: Ok(response.result),
+            control: control_send,
         })
     }
 }
@@ -286,6 +311,7 @@ pub struct Child {
     pub stderr: Option<ReadPipe>,
     pid: u32,
     result: Result<mesh::OneshotReceiver<pipette_protocol::ExitStatus>, ExitStatus>,
+    control: mesh::Sender<ProcessControl>,
 }
 
 impl Child {
@@ -294,6 +320,26 @@ pub fn id(&self) -> u32 {
         self.pid
     }
 
+    /// Resizes the child's pseudo-terminal, notifying it with `SIGWINCH`.
+    ///
+    /// Fails if the child was not started with [`Command::pty`].
+    pub async fn resize(&self, rows: u16, cols: u16) -> anyhow::Result<()> {
+        self.control
+            .call_failable(ProcessControl::Resize, WindowSize { rows, cols })
+            .await
+            .context("failed to resize terminal")
+    }
+
+    /// Sends `signal` to the child, such as `SIGINT` (2) to interrupt it.
+    ///
+    /// Only supported for Linux guests.
+    pub async fn signal(&self, signal: i32) -> anyhow::Result<()> {
+        self.control
+            .call_failable(ProcessControl::Signal, signal)
+            .await
+            .with_context(|| format!("failed to send signal {signal}"))
+    }
+
     /// Waits for the child to exit, returning the exit status.
     pub async fn wait(&mut self) -> Result<ExitStatus, mesh::RecvError> {
         match &mut self.result {
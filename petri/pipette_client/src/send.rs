@@ -8,13 +8,16 @@
 use mesh::rpc::RpcSend;
 use mesh::CancelContext;
 use pipette_protocol::PipetteRequest;
+use std::sync::Arc;
 use std::time::Duration;
 
-pub(crate) struct PipetteSender(mesh::Sender<PipetteRequest>);
+/// Cheaply cloneable, so that port forwarding tasks can issue requests.
+#[derive(Clone)]
+pub(crate) struct PipetteSender(Arc<mesh::Sender<PipetteRequest>>);
 
 impl PipetteSender {
     pub(crate) fn new(sender: mesh::Sender<PipetteRequest>) -> Self {
-        Self(sender)
+        Self(Arc::new(sender))
     }
 
     /// A wrapper around [`mesh::Sender::call`] that will sleep for 5 seconds on failure,
@@ -0,0 +1,225 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Forwarding of TCP connections between the host and the guest.
+
+use crate::send::PipetteSender;
+use crate::PipetteClient;
+use anyhow::Context;
+use futures::AsyncWriteExt;
+use futures::StreamExt;
+use futures_concurrency::future::TryJoin;
+use pal_async::socket::PolledSocket;
+use pal_async::task::Spawn;
+use pal_async::task::Task;
+use pal_async::DefaultDriver;
+use pipette_protocol::ConnectTcpRequest;
+use pipette_protocol::ListenTcpRequest;
+use pipette_protocol::PipetteRequest;
+use pipette_protocol::TcpConnection;
+use pipette_protocol::TcpStreamPipes;
+use std::net::Shutdown;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+
+/// A forwarding of TCP connections between the host and the guest, from
+/// [`PipetteClient::forward_tcp_to_guest`] or
+/// [`PipetteClient::forward_tcp_from_guest`].
+///
+/// Dropping this stops listening for new connections. Connections that have
+/// already been accepted are relayed until they are closed.
+pub struct TcpForward {
+    listen_address: SocketAddr,
+    _task: Task<()>,
+    _cancel: Option<mesh::OneshotSender<()>>,
+}
+
+impl TcpForward {
+    /// The address being listened on, on the host for
+    /// [`PipetteClient::forward_tcp_to_guest`] or in the guest for
+    /// [`PipetteClient::forward_tcp_from_guest`].
+    pub fn listen_address(&self) -> SocketAddr {
+        self.listen_address
+    }
+}
+
+impl PipetteClient {
+    /// Listens on `host_address` on the host, forwarding each accepted
+    /// connection to `guest_address` in the guest.
+    ///
+    /// Use port 0 in `host_address` to listen on any free port, and
+    /// [`TcpForward::listen_address`] to find it.
+    pub fn forward_tcp_to_guest(
+        &self,
+        driver: &DefaultDriver,
+        host_address: SocketAddr,
+        guest_address: SocketAddr,
+    ) -> anyhow::Result<TcpForward> {
+        let listener = TcpListener::bind(host_address)
+            .with_context(|| format!("failed to listen on {host_address}"))?;
+        let listen_address = listener.local_addr()?;
+        let mut listener = PolledSocket::new(driver, listener)?;
+        tracing::debug!(%listen_address, %guest_address, "forwarding tcp to guest");
+
+        let task = driver.spawn("pipette-tcp-forward", {
+            let driver = driver.clone();
+            let send = self.send.clone();
+            async move {
+                loop {
+                    let (socket, peer_address) = match listener.accept().await {
+                        Ok(r) => r,
+                        Err(err) => {
+                            tracing::error!(
+                                error = &err as &dyn std::error::Error,
+                                "failed to accept tcp connection"
+                            );
+                            break;
+                        }
+                    };
+                    tracing::debug!(%peer_address, %guest_address, "forwarding tcp connection");
+                    driver
+                        .spawn(
+                            "pipette-tcp-relay",
+                            connect_guest(driver.clone(), send.clone(), socket, guest_address),
+                        )
+                        .detach();
+                }
+            }
+        });
+
+        Ok(TcpForward {
+            listen_address,
+            _task: task,
+            _cancel: None,
+        })
+    }
+
+    /// Listens on `guest_address` in the guest, forwarding each accepted
+    /// connection to `host_address` on the host.
+    ///
+    /// Use port 0 in `guest_address` to listen on any free port, and
+    /// [`TcpForward::listen_address`] to find it.
+    pub async fn forward_tcp_from_guest(
+        &self,
+        driver: &DefaultDriver,
+        guest_address: SocketAddr,
+        host_address: SocketAddr,
+    ) -> anyhow::Result<TcpForward> {
+        let (connections_send, mut connections_recv) = mesh::channel();
+        let (cancel_send, cancel_recv) = mesh::oneshot();
+        let listen_address = self
+            .send
+            .call(
+                PipetteRequest::ListenTcp,
+                ListenTcpRequest {
+                    address: guest_address.to_string(),
+                    connections: connections_send,
+                    cancel: cancel_recv,
+                },
+            )
+            .await?
+            .map_err(anyhow::Error::from)
+            .with_context(|| format!("failed to listen on {guest_address} in the guest"))?;
+        let listen_address: SocketAddr = listen_address
+            .parse()
+            .context("guest returned an invalid address")?;
+        tracing::debug!(%listen_address, %host_address, "forwarding tcp from guest");
+
+        let task = driver.spawn("pipette-tcp-forward", {
+            let driver = driver.clone();
+            async move {
+                while let Some(TcpConnection {
+                    peer_address,
+                    pipes,
+                }) = connections_recv.next().await
+                {
+                    tracing::debug!(peer_address, %host_address, "forwarding tcp connection");
+                    driver
+                        .spawn(
+                            "pipette-tcp-relay",
+                            connect_host(driver.clone(), pipes, host_address),
+                        )
+                        .detach();
+                }
+            }
+        });
+
+        Ok(TcpForward {
+            listen_address,
+            _task: task,
+            _cancel: Some(cancel_send),
+        })
+    }
+}
+
+/// Connects to `guest_address` in the guest and relays `socket` to it.
+async fn connect_guest(
+    driver: DefaultDriver,
+    send: PipetteSender,
+    socket: TcpStream,
+    guest_address: SocketAddr,
+) {
+    let (pipes, remote_pipes) = TcpStreamPipes::pair();
+    let request = ConnectTcpRequest {
+        address: guest_address.to_string(),
+        pipes: remote_pipes,
+    };
+    let result = async {
+        send.call(PipetteRequest::ConnectTcp, request)
+            .await?
+            .map_err(anyhow::Error::from)
+            .with_context(|| format!("failed to connect to {guest_address} in the guest"))?;
+        relay(PolledSocket::new(&driver, socket)?, pipes).await?;
+        anyhow::Ok(())
+    };
+    if let Err(err) = result.await {
+        tracing::warn!(
+            error = err.as_ref() as &dyn std::error::Error,
+            "tcp forwarding failed"
+        );
+    }
+}
+
+/// Connects to `host_address` on the host and relays `pipes` to it.
+async fn connect_host(driver: DefaultDriver, pipes: TcpStreamPipes, host_address: SocketAddr) {
+    let result = async {
+        let socket = socket2::Socket::new(
+            socket2::Domain::for_address(host_address),
+            socket2::Type::STREAM,
+            Some(socket2::Protocol::TCP),
+        )?;
+        let mut socket = PolledSocket::new(&driver, socket)?;
+        socket
+            .connect(&host_address.into())
+            .await
+            .with_context(|| format!("failed to connect to {host_address}"))?;
+        relay(socket.convert(), pipes).await?;
+        anyhow::Ok(())
+    };
+    if let Err(err) = result.await {
+        tracing::warn!(
+            error = err.as_ref() as &dyn std::error::Error,
+            "tcp forwarding failed"
+        );
+    }
+}
+
+/// Relays data between `socket` and `pipes` until both directions are closed.
+async fn relay(socket: PolledSocket<TcpStream>, pipes: TcpStreamPipes) -> std::io::Result<()> {
+    let (mut read, mut write) = socket.split();
+    let TcpStreamPipes {
+        mut receiver,
+        mut sender,
+    } = pipes;
+    let outgoing = async {
+        futures::io::copy(&mut read, &mut sender).await?;
+        sender.close().await
+    };
+    let incoming = async {
+        futures::io::copy(&mut receiver, &mut write).await?;
+        write.get().shutdown(Shutdown::Write)
+    };
+    (outgoing, incoming).try_join().await?;
+    Ok(())
+}
@@ -16,6 +16,10 @@
 /// The port used for the pipette connection over AF_VSOCK.
 pub const PIPETTE_VSOCK_PORT: u32 = 0x1337;
 
+/// The size of the chunks that file contents are transferred in by
+/// [`PipetteRequest::PushFile`] and [`PipetteRequest::PullFile`].
+pub const FILE_TRANSFER_CHUNK_SIZE: usize = 256 * 1024;
+
 /// The bootstrap message sent from the agent to the host.
 #[derive(MeshPayload)]
 pub struct PipetteBootstrap {
@@ -45,6 +49,29 @@ pub enum PipetteRequest {
     ReadFile(FailableRpc<ReadFileRequest, ()>),
     /// Writes a file
     WriteFile(FailableRpc<WriteFileRequest, ()>),
+    /// Writes a file, replacing it only once the full contents have been
+    /// received and verified against the expected digest.
+    PushFile(FailableRpc<PushFileRequest, ()>),
+    /// Reads a file, returning the digest of the sent contents so that the
+    /// host can verify them.
+    PullFile(FailableRpc<PullFileRequest, FileDigest>),
+    /// Connects to a TCP address in the guest, relaying the connection over
+    /// the provided pipes. Completes once the connection is established.
+    ConnectTcp(FailableRpc<ConnectTcpRequest, ()>),
+    /// Listens on a TCP address in the guest, sending each accepted
+    /// connection to the host. Returns the bound address.
+    ListenTcp(FailableRpc<ListenTcpRequest, String>),
+    /// Queries information about the guest's identity and configuration.
+    GuestInfo(FailableRpc<(), GuestInfo>),
+    /// Configures the guest to write a crash dump of the given kind when it
+    /// crashes.
+    ConfigureCrashDump(FailableRpc<CrashDumpKind, ()>),
+    /// Crashes the guest: panics the kernel on Linux, or bugchecks Windows.
+    ///
+    /// The guest crashes shortly after the response is sent.
+    Crash(FailableRpc<(), ()>),
+    /// Lists the crash dump files left by previous guest crashes.
+    ListCrashDumps(FailableRpc<(), Vec<CrashDumpFile>>),
 }
 
 /// A request to execute a command inside the guest.
@@ -66,6 +93,15 @@ pub struct ExecuteRequest {
     pub env: Vec<EnvPair>,
     /// Whether to clear the environment before setting the new environment.
     pub clear_env: bool,
+    /// If set, runs the program attached to a new pseudo-terminal with this
+    /// initial window size. `stdin` and `stdout` are relayed to and from the
+    /// terminal, and `stderr` must not be set since the program's standard
+    /// error is written to the terminal too.
+    ///
+    /// Only supported on Linux.
+    pub pty: Option<WindowSize>,
+    /// The receiver for requests to control the running process.
+    pub control: Option<mesh::Receiver<ProcessControl>>,
 }
 
 impl std::fmt::Debug for ExecuteRequest {
@@ -79,6 +115,8 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             .field("stderr", &self.stderr.is_some())
             .field("env", &self.env)
             .field("clear_env", &self.clear_env)
+            .field("pty", &self.pty)
+            .field("control", &self.control.is_some())
             .finish()
     }
 }
@@ -92,6 +130,28 @@ pub struct EnvPair {
     pub value: Option<String>,
 }
 
+/// The size of a terminal window, in characters.
+#[derive(Copy, Clone, Debug, MeshPayload)]
+pub struct WindowSize {
+    /// The number of rows.
+    pub rows: u16,
+    /// The number of columns.
+    pub cols: u16,
+}
+
+/// A request to control a process started by [`PipetteRequest::Execute`].
+#[derive(MeshPayload)]
+pub enum ProcessControl {
+    /// Resizes the process's pseudo-terminal, which delivers `SIGWINCH` to
+    /// its foreground process group. Fails if the process was not started
+    /// with a pseudo-terminal.
+    Resize(FailableRpc<WindowSize, ()>),
+    /// Sends the given signal to the process.
+    ///
+    /// Only supported on Linux.
+    Signal(FailableRpc<i32, ()>),
+}
+
 /// The response to a request to execute a command inside the guest.
 #[derive(MeshPayload)]
 pub struct ExecuteResponse {
@@ -159,6 +219,166 @@ pub struct WriteFileRequest {
     pub receiver: ReadPipe,
 }
 
+/// The size and SHA-256 digest of a file's contents.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, MeshPayload)]
+pub struct FileDigest {
+    /// The size of the file, in bytes.
+    pub size: u64,
+    /// The SHA-256 digest of the file's contents.
+    pub sha256: [u8; 32],
+}
+
+/// A request to push a file to the guest.
+#[derive(MeshPayload)]
+pub struct PushFileRequest {
+    /// The path to write the file to.
+    pub path: String,
+    /// The expected size and digest of the contents.
+    pub digest: FileDigest,
+    /// The receiver of the contents of the file.
+    pub receiver: ReadPipe,
+}
+
+/// A request to pull a file from the guest.
+#[derive(MeshPayload)]
+pub struct PullFileRequest {
+    /// The path to read the file from.
+    pub path: String,
+    /// The sender for the size of the file, sent before the contents.
+    pub size: mesh::OneshotSender<u64>,
+    /// The sender for the contents of the file.
+    pub sender: WritePipe,
+}
+
+/// The pipes that the data of a TCP connection is relayed over.
+#[derive(MeshPayload)]
+pub struct TcpStreamPipes {
+    /// The receiver of data to write to the connection.
+    pub receiver: ReadPipe,
+    /// The sender of data read from the connection.
+    pub sender: WritePipe,
+}
+
+impl TcpStreamPipes {
+    /// Creates the pipes for both ends of a relayed connection.
+    pub fn pair() -> (Self, Self) {
+        let (a_receiver, b_sender) = mesh::pipe::pipe();
+        let (b_receiver, a_sender) = mesh::pipe::pipe();
+        (
+            Self {
+                receiver: a_receiver,
+                sender: a_sender,
+            },
+            Self {
+                receiver: b_receiver,
+                sender: b_sender,
+            },
+        )
+    }
+}
+
+/// A request to connect to a TCP address in the guest.
+#[derive(MeshPayload)]
+pub struct ConnectTcpRequest {
+    /// The address to connect to, such as `127.0.0.1:8080`.
+    pub address: String,
+    /// The pipes to relay the connection over.
+    pub pipes: TcpStreamPipes,
+}
+
+/// A request to listen on a TCP address in the guest.
+#[derive(MeshPayload)]
+pub struct ListenTcpRequest {
+    /// The address to listen on, such as `127.0.0.1:0`.
+    pub address: String,
+    /// The sender for accepted connections.
+    pub connections: mesh::Sender<TcpConnection>,
+    /// The guest stops listening when this is signaled or dropped. Accepted
+    /// connections are not affected.
+    pub cancel: mesh::OneshotReceiver<()>,
+}
+
+/// A TCP connection accepted in the guest.
+#[derive(MeshPayload)]
+pub struct TcpConnection {
+    /// The address of the connection's peer.
+    pub peer_address: String,
+    /// The pipes the connection is relayed over.
+    pub pipes: TcpStreamPipes,
+}
+
+/// Information about the guest's identity and configuration.
+#[derive(Debug, MeshPayload)]
+pub struct GuestInfo {
+    /// The guest's host name.
+    pub hostname: String,
+    /// The name of the operating system, such as `Ubuntu 22.04.4 LTS`.
+    pub os_name: String,
+    /// The operating system version: the kernel release on Linux, or the
+    /// major and minor version on Windows.
+    pub os_version: String,
+    /// The operating system build: the kernel build string on Linux, or the
+    /// build number on Windows.
+    pub os_build: String,
+    /// The network interfaces.
+    pub interfaces: Vec<NetworkInterface>,
+    /// The mounted filesystems.
+    pub filesystems: Vec<MountedFilesystem>,
+    /// The kernel command line, on Linux.
+    pub kernel_cmdline: Option<String>,
+}
+
+/// A network interface in the guest.
+#[derive(Debug, MeshPayload)]
+pub struct NetworkInterface {
+    /// The name of the interface.
+    pub name: String,
+    /// The MAC address, as lower case hex bytes separated by colons.
+    pub mac_address: Option<String>,
+    /// The IP addresses assigned to the interface.
+    pub addresses: Vec<IpAddress>,
+}
+
+/// An IP address assigned to a network interface.
+#[derive(Debug, MeshPayload)]
+pub struct IpAddress {
+    /// The IPv4 or IPv6 address.
+    pub address: String,
+    /// The length of the network prefix, in bits.
+    pub prefix_len: u8,
+}
+
+/// A filesystem mounted in the guest.
+#[derive(Debug, MeshPayload)]
+pub struct MountedFilesystem {
+    /// The mounted device or other source, such as `/dev/sda1`.
+    pub source: String,
+    /// The path the filesystem is mounted at.
+    pub mount_point: String,
+    /// The filesystem type, such as `ext4` or `NTFS`.
+    pub fs_type: String,
+}
+
+/// The kind of crash dump to write when the guest crashes.
+#[derive(Copy, Clone, Debug, MeshPayload)]
+pub enum CrashDumpKind {
+    /// A small dump with the crash context only.
+    Small,
+    /// A dump of kernel memory.
+    Kernel,
+    /// A dump of all memory.
+    Full,
+}
+
+/// A crash dump file in the guest.
+#[derive(Clone, Debug, MeshPayload)]
+pub struct CrashDumpFile {
+    /// The path to the file.
+    pub path: String,
+    /// The size of the file, in bytes.
+    pub size: u64,
+}
+
 /// A file that the guest client wishes to be logged on the host for diagnostic purposes.
 #[derive(MeshPayload)]
 pub struct DiagnosticFile {
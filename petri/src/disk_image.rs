@@ -67,7 +67,23 @@ pub fn build_agent_image(
                 ],
             )
         }
-        OsFlavor::FreeBsd | OsFlavor::Uefi => {
+        OsFlavor::FreeBsd => {
+            // FreeBSD doesn't use cloud-init, so we only need pipette (which
+            // is launched over the serial console).
+            build_disk_image(
+                b"pipette    ",
+                &[(
+                    "pipette",
+                    PathOrBinary::Path(&resolver.resolve(match arch {
+                        MachineArch::X86_64 => common_artifacts::PIPETTE_FREEBSD_X64.erase(),
+                        MachineArch::Aarch64 => {
+                            anyhow::bail!("pipette is not supported on FreeBSD aarch64")
+                        }
+                    })),
+                )],
+            )
+        }
+        OsFlavor::Uefi => {
             // No pipette binary yet.
             todo!()
         }
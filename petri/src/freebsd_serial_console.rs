@@ -0,0 +1,57 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Context;
+use futures::AsyncWriteExt;
+use pal_async::socket::WriteHalf;
+use unix_socket::UnixStream;
+
+/// Mounts the agent disk, found by its partition name, and starts pipette in
+/// the background.
+const LAUNCH_PIPETTE: &str =
+    "mount_msdosfs /dev/gpt/CIDATA /mnt && (/mnt/pipette > /dev/null 2>&1 &)\n";
+
+/// Launches pipette on a FreeBSD guest by logging in on the serial console.
+///
+/// The FreeBSD VM images don't include cloud-init, so nothing in the guest
+/// starts pipette from the agent disk. Instead, rely on the loader making
+/// COM1 the system console (since UEFI uses it for its console) and the
+/// images allowing root to log in there without a password.
+pub(crate) struct FreeBsdSerialConsole {
+    /// Writer to serial 0, the system console
+    write: WriteHalf<UnixStream>,
+    /// The lines read from serial 0 by the logging task
+    lines: mesh::Receiver<String>,
+}
+
+impl FreeBsdSerialConsole {
+    pub(crate) fn new(serial0_write: WriteHalf<UnixStream>, lines: mesh::Receiver<String>) -> Self {
+        Self {
+            write: serial0_write,
+            lines,
+        }
+    }
+
+    /// Waits for the login prompt, logs in, and starts pipette.
+    pub(crate) async fn launch_pipette(&mut self) -> anyhow::Result<()> {
+        // getty prints a banner naming the terminal before the login prompt,
+        // which itself has no trailing newline.
+        self.wait_for_line("(ttyu0)").await?;
+        self.write.write_all(b"root\n").await?;
+        // Don't send the command until the login completes, so that login
+        // doesn't consume it.
+        self.wait_for_line("Welcome to FreeBSD").await?;
+        self.write.write_all(LAUNCH_PIPETTE.as_bytes()).await?;
+        Ok(())
+    }
+
+    async fn wait_for_line(&mut self, pattern: &str) -> anyhow::Result<()> {
+        tracing::debug!(pattern, "waiting for serial console output");
+        loop {
+            let line = self.lines.recv().await.context("serial console closed")?;
+            if line.contains(pattern) {
+                break Ok(());
+            }
+        }
+    }
+}
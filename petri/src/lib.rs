@@ -11,6 +11,7 @@
 #![warn(missing_docs)]
 
 mod disk_image;
+mod freebsd_serial_console;
 mod linux_direct_serial_agent;
 mod openhcl_diag;
 mod tracing;
@@ -0,0 +1,178 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Measurements of how long each boot of a [`PetriVm`](crate::PetriVm) takes.
+
+use crate::tracing::trace_attachment;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde::Serializer;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long a boot took to reach each stage, measured from when the VM
+/// started running or was reset.
+///
+/// A stage is `None` if the boot has not reached it yet, or if it cannot be
+/// detected for the VM's firmware and guest.
+#[derive(Debug, Default, Copy, Clone, Serialize)]
+pub struct BootTimes {
+    /// Time until the UEFI firmware wrote to the serial console.
+    #[serde(rename = "uefi_ms", serialize_with = "serialize_ms")]
+    pub uefi: Option<Duration>,
+    /// Time until the firmware handed off to the guest OS loader, or for
+    /// Linux direct boot, until the kernel wrote to the serial console.
+    #[serde(rename = "kernel_ms", serialize_with = "serialize_ms")]
+    pub kernel: Option<Duration>,
+    /// Time until pipette connected.
+    #[serde(rename = "agent_ms", serialize_with = "serialize_ms")]
+    pub agent: Option<Duration>,
+}
+
+/// The maximum time each boot may take to reach each stage, from
+/// [`PetriVmConfig::with_boot_budget`](crate::PetriVmConfig::with_boot_budget).
+///
+/// Stages without a budget are not checked.
+#[derive(Debug, Default, Copy, Clone, Serialize)]
+pub struct BootBudget {
+    /// The budget for [`BootTimes::uefi`].
+    #[serde(rename = "uefi_ms", serialize_with = "serialize_ms")]
+    pub uefi: Option<Duration>,
+    /// The budget for [`BootTimes::kernel`].
+    #[serde(rename = "kernel_ms", serialize_with = "serialize_ms")]
+    pub kernel: Option<Duration>,
+    /// The budget for [`BootTimes::agent`].
+    #[serde(rename = "agent_ms", serialize_with = "serialize_ms")]
+    pub agent: Option<Duration>,
+}
+
+impl BootBudget {
+    fn check(&self, times: &BootTimes) -> anyhow::Result<()> {
+        for (stage, time, budget) in [
+            ("uefi", times.uefi, self.uefi),
+            ("kernel", times.kernel, self.kernel),
+            ("agent", times.agent, self.agent),
+        ] {
+            if let Some((time, budget)) = time.zip(budget) {
+                anyhow::ensure!(
+                    time <= budget,
+                    "boot took {time:?} to reach {stage}, over its budget of {budget:?}"
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+fn serialize_ms<S: Serializer>(time: &Option<Duration>, s: S) -> Result<S::Ok, S::Error> {
+    time.map(|time| time.as_secs_f64() * 1000.0).serialize(s)
+}
+
+/// A stage of a boot recorded by [`BootTimer`].
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum BootStage {
+    Uefi,
+    Kernel,
+    Agent,
+}
+
+/// Records the [`BootTimes`] of each boot of a VM, writing them to
+/// `boot_metrics.json` in the test's output directory as they are reached.
+#[derive(Clone)]
+pub(crate) struct BootTimer(Arc<Mutex<BootTimerState>>);
+
+struct BootTimerState {
+    path: PathBuf,
+    attached: bool,
+    budget: BootBudget,
+    start: Option<Instant>,
+    boots: Vec<BootTimes>,
+}
+
+#[derive(Serialize)]
+struct BootMetrics<'a> {
+    budget: &'a BootBudget,
+    boots: &'a [BootTimes],
+}
+
+impl BootTimer {
+    pub(crate) fn new(output_dir: &Path) -> Self {
+        Self(Arc::new(Mutex::new(BootTimerState {
+            path: output_dir.join("boot_metrics.json"),
+            attached: false,
+            budget: BootBudget::default(),
+            start: None,
+            boots: Vec::new(),
+        })))
+    }
+
+    pub(crate) fn set_budget(&self, budget: BootBudget) {
+        self.0.lock().budget = budget;
+    }
+
+    /// Starts timing a new boot.
+    pub(crate) fn start(&self) {
+        let mut state = self.0.lock();
+        state.start = Some(Instant::now());
+        state.boots.push(BootTimes::default());
+    }
+
+    /// Records that the current boot reached `stage`, unless it already has.
+    pub(crate) fn record(&self, stage: BootStage) {
+        let mut state = self.0.lock();
+        let Some(start) = state.start else {
+            return;
+        };
+        let times = state.boots.last_mut().unwrap();
+        let time = match stage {
+            BootStage::Uefi => &mut times.uefi,
+            BootStage::Kernel => &mut times.kernel,
+            BootStage::Agent => &mut times.agent,
+        };
+        if time.is_some() {
+            return;
+        }
+        let elapsed = start.elapsed();
+        *time = Some(elapsed);
+        tracing::info!(?stage, ?elapsed, "boot stage reached");
+        if let Err(err) = state.write() {
+            tracing::error!(
+                error = err.as_ref() as &dyn std::error::Error,
+                "failed to write boot metrics"
+            );
+        }
+    }
+
+    /// Returns the times of each boot so far, oldest first.
+    pub(crate) fn boots(&self) -> Vec<BootTimes> {
+        self.0.lock().boots.clone()
+    }
+
+    /// Fails if the current boot took longer than its budget to reach any
+    /// stage.
+    pub(crate) fn check_budget(&self) -> anyhow::Result<()> {
+        let state = self.0.lock();
+        match state.boots.last() {
+            Some(times) => state.budget.check(times),
+            None => Ok(()),
+        }
+    }
+}
+
+impl BootTimerState {
+    fn write(&mut self) -> anyhow::Result<()> {
+        let metrics = serde_json::to_vec_pretty(&BootMetrics {
+            budget: &self.budget,
+            boots: &self.boots,
+        })?;
+        fs_err::write(&self.path, metrics)?;
+        if !self.attached {
+            trace_attachment(&self.path);
+            self.attached = true;
+        }
+        Ok(())
+    }
+}
@@ -4,9 +4,12 @@
 //! Contains [`PetriVmConfig::new`], which builds a [`PetriVmConfig`] with all
 //! default settings for a given [`Firmware`] and [`MachineArch`].
 
+use crate::freebsd_serial_console::FreeBsdSerialConsole;
 use crate::linux_direct_serial_agent::LinuxDirectSerialAgent;
 use crate::openhcl_diag::OpenHclDiagHandler;
 use crate::tracing::trace_attachment;
+use crate::vm::boot_metrics::BootStage;
+use crate::vm::boot_metrics::BootTimer;
 use crate::vm::PetriVmResources;
 use crate::Firmware;
 use crate::PcatGuest;
@@ -60,6 +63,7 @@
 use pal_async::DefaultDriver;
 use petri_artifacts_common::artifacts as common_artifacts;
 use petri_artifacts_common::tags::MachineArch;
+use petri_artifacts_common::tags::OsFlavor;
 use petri_artifacts_core::AsArtifactHandle;
 use petri_artifacts_core::TestArtifacts;
 use petri_artifacts_vmm_test::artifacts as hvlite_artifacts;
@@ -72,6 +76,7 @@
 use sparse_mmap::alloc_shared_memory;
 use std::fmt::Write as _;
 use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use storvsp_resources::ScsiControllerHandle;
@@ -98,22 +103,25 @@ pub fn new(
         resolver: TestArtifacts,
         driver: &DefaultDriver,
     ) -> anyhow::Result<Self> {
-        // Use the current thread name for the test name, both cargo-test and
-        // cargo-nextest set this.
-        // FUTURE: If we ever want to use petri outside a testing context this
-        // will need to be revisited.
-        let current_thread = std::thread::current();
-        let test_name = current_thread.name().context("no thread name configured")?;
-        if test_name.is_empty() {
-            anyhow::bail!("thread name is empty");
-        }
-        if test_name == "main" {
-            anyhow::bail!("thread name is 'main', not running from test thread");
-        }
-        // Windows paths can't include colons, replace them.
-        let test_name = test_name.replace("::", "__");
+        let test_name = current_test_name()?;
+        let output_dir = resolver
+            .resolve(common_artifacts::TEST_LOG_DIRECTORY)
+            .join(test_name);
+        Self::new_in(firmware, arch, resolver, driver, output_dir, true)
+    }
+
+    /// Create a new VM configuration that logs to `output_dir`, initializing
+    /// tracing if `init_tracing` is set.
+    pub(crate) fn new_in(
+        firmware: Firmware,
+        arch: MachineArch,
+        resolver: TestArtifacts,
+        driver: &DefaultDriver,
+        output_dir: PathBuf,
+        init_tracing: bool,
+    ) -> anyhow::Result<Self> {
         let setup = PetriVmConfigSetupCore {
-            test_name: &test_name,
+            output_dir: &output_dir,
             arch,
             firmware: &firmware,
             resolver: &resolver,
@@ -130,7 +138,9 @@ pub fn new(
             .create_log_files()
             .context("failed to create test log files")?;
 
-        crate::tracing::try_init_tracing(petri_file.into())?;
+        if init_tracing {
+            crate::tracing::try_init_tracing(petri_file.into())?;
+        }
 
         let mut chipset = VmManifestBuilder::new(
             match firmware {
@@ -152,11 +162,14 @@ pub fn new(
 
         let load_mode = setup.load_firmware()?;
 
+        let boot_timer = BootTimer::new(&output_dir);
+
         let SerialData {
             mut emulated_serial_config,
             serial_tasks,
             linux_direct_serial_agent,
-        } = setup.configure_serial(guest_file, openhcl_file)?;
+            freebsd_serial_console,
+        } = setup.configure_serial(guest_file, openhcl_file, &boot_timer)?;
 
         let (video_dev, framebuffer, framebuffer_access) = match setup.config_video()? {
             Some((v, fb, fba)) => {
@@ -168,7 +181,24 @@ pub fn new(
 
         let mut devices = Vec::new();
 
-        let (firmware_event_send, firmware_event_recv) = mesh::mpsc_channel();
+        let (firmware_event_send, mut firmware_event_timed) = mesh::mpsc_channel();
+        // Note when the firmware hands off to the guest before passing the
+        // events on to the test.
+        let (firmware_event_forward, firmware_event_recv) = mesh::mpsc_channel();
+        let firmware_event_task = driver.spawn("petri-firmware-events", {
+            let boot_timer = boot_timer.clone();
+            async move {
+                while let Ok(event) = firmware_event_timed.recv().await {
+                    if matches!(
+                        event,
+                        FirmwareEvent::BootSuccess | FirmwareEvent::BootAttempt
+                    ) {
+                        boot_timer.record(BootStage::Kernel);
+                    }
+                    firmware_event_forward.send(event);
+                }
+            }
+        });
 
         let (with_vtl2, vtl2_vmbus, openhcl_diag_handler, ged, ged_send, mut vtl2_settings) =
             if firmware.is_openhcl() {
@@ -288,6 +318,8 @@ pub fn new(
                     DEFAULT_MMIO_GAPS.into()
                 },
                 prefetch_memory: false,
+                hot_add_size: 0,
+                numa_nodes: Vec::new(),
             },
             processor_topology: ProcessorTopologyConfig {
                 proc_count: 2,
@@ -376,6 +408,8 @@ pub fn new(
             resources: PetriVmResources {
                 serial_tasks,
                 firmware_event_recv,
+                _firmware_event_task: firmware_event_task,
+                boot_timer,
                 shutdown_ic_send,
                 expected_boot_event,
                 ged_send,
@@ -383,6 +417,7 @@ pub fn new(
                 vtl2_pipette_listener,
                 openhcl_diag_handler,
                 linux_direct_serial_agent,
+                freebsd_serial_console,
                 driver: driver.clone(),
                 resolver,
                 output_dir,
@@ -398,7 +433,7 @@ pub fn new(
 }
 
 struct PetriVmConfigSetupCore<'a> {
-    test_name: &'a str,
+    output_dir: &'a Path,
     arch: MachineArch,
     firmware: &'a Firmware,
     resolver: &'a TestArtifacts,
@@ -409,6 +444,7 @@ struct SerialData {
     emulated_serial_config: [Option<Resource<SerialBackendHandle>>; 4],
     serial_tasks: Vec<Task<anyhow::Result<()>>>,
     linux_direct_serial_agent: Option<LinuxDirectSerialAgent>,
+    freebsd_serial_console: Option<FreeBsdSerialConsole>,
 }
 
 struct TestLogFiles {
@@ -441,8 +477,7 @@ impl PetriVmConfigSetupCore<'_> {
     fn create_log_files(&self) -> anyhow::Result<TestLogFiles> {
         // DEVNOTE: This function runs before tracing is set up.
 
-        let test_log_dir = self.resolver.resolve(common_artifacts::TEST_LOG_DIRECTORY);
-        let output_dir = test_log_dir.join(self.test_name);
+        let output_dir = self.output_dir.to_owned();
         if output_dir.exists() {
             std::fs::remove_dir_all(&output_dir)?;
         }
@@ -479,25 +514,43 @@ fn configure_serial(
         &self,
         guest_file: File,
         openhcl_file: Option<File>,
+        boot_timer: &BootTimer,
     ) -> anyhow::Result<SerialData> {
         let mut serial_tasks = Vec::new();
 
-        let serial0_log_target = match self.firmware {
-            Firmware::LinuxDirect { .. } | Firmware::OpenhclLinuxDirect { .. } => LogTarget::Linux,
-            Firmware::Pcat { .. } => LogTarget::Pcat,
-            Firmware::Uefi { .. } | Firmware::OpenhclUefi { .. } => LogTarget::Uefi,
+        // The first output on serial0 shows the boot reached the firmware or
+        // kernel, whichever writes to it first.
+        let (serial0_log_target, serial0_boot_stage) = match self.firmware {
+            Firmware::LinuxDirect { .. } | Firmware::OpenhclLinuxDirect { .. } => {
+                (LogTarget::Linux, Some(BootStage::Kernel))
+            }
+            Firmware::Pcat { .. } => (LogTarget::Pcat, None),
+            Firmware::Uefi { .. } | Firmware::OpenhclUefi { .. } => {
+                (LogTarget::Uefi, Some(BootStage::Uefi))
+            }
         };
 
         let (serial0_host, serial0) = self
             .create_serial_stream()
             .context("failed to create serial0 stream")?;
         let (serial0_read, serial0_write) = serial0_host.split();
+        // FreeBSD guests are logged into over serial0 to start pipette, so
+        // send its output to the login code as well as the log.
+        let (serial0_lines_send, serial0_lines) =
+            if matches!(self.firmware.os_flavor(), OsFlavor::FreeBsd) {
+                let (send, recv) = mesh::channel();
+                (Some(send), Some(recv))
+            } else {
+                (None, None)
+            };
         let serial0_task = self
             .spawn_serial_task(
                 "serial0-console",
                 serial0_log_target,
                 serial0_read,
                 guest_file,
+                serial0_boot_stage.map(|stage| (boot_timer.clone(), stage)),
+                serial0_lines_send,
             )
             .context("failed to spawn serial0 task")?;
         serial_tasks.push(serial0_task);
@@ -512,6 +565,8 @@ fn configure_serial(
                     LogTarget::Openhcl,
                     serial2_host,
                     openhcl_file.unwrap(),
+                    None,
+                    None,
                 )
                 .context("failed to spawn serial2 task")?;
             serial_tasks.push(serial2_task);
@@ -529,12 +584,15 @@ fn configure_serial(
                 emulated_serial_config: [serial0, serial1, serial2, None],
                 serial_tasks,
                 linux_direct_serial_agent: Some(linux_direct_serial_agent),
+                freebsd_serial_console: None,
             })
         } else {
             Ok(SerialData {
                 emulated_serial_config: [serial0, None, serial2, None],
                 serial_tasks,
                 linux_direct_serial_agent: None,
+                freebsd_serial_console: serial0_lines
+                    .map(|lines| FreeBsdSerialConsole::new(serial0_write, lines)),
             })
         }
     }
@@ -557,6 +615,8 @@ fn spawn_serial_task(
         log_target: LogTarget,
         reader: impl AsyncRead + Unpin + Send + 'static,
         mut file: File,
+        boot_stage: Option<(BootTimer, BootStage)>,
+        lines: Option<mesh::Sender<String>>,
     ) -> anyhow::Result<Task<anyhow::Result<()>>> {
         Ok(self.driver.spawn(task_name, async move {
             let mut buf = Vec::new();
@@ -586,6 +646,13 @@ fn spawn_serial_task(
                     }
                 }
 
+                if let Some((boot_timer, stage)) = &boot_stage {
+                    boot_timer.record(*stage);
+                }
+                if let Some(lines) = &lines {
+                    lines.send(string_buf_trimmed.to_owned());
+                }
+
                 file.write_all(&buf)?;
             }
             Ok(())
@@ -831,6 +898,7 @@ fn load_boot_disk(
                         subsystem_id: BOOT_NVME_INSTANCE,
                         max_io_queues: 64,
                         msix_count: 64,
+                        requests: None,
                         namespaces: vec![NamespaceDefinition {
                             nsid: BOOT_NVME_NSID,
                             disk: LayeredDiskHandle {
@@ -1019,3 +1087,21 @@ fn get_expected_boot_event(&self) -> Option<FirmwareEvent> {
         }
     }
 }
+
+/// Returns the name of the running test, for naming its output directory.
+pub(crate) fn current_test_name() -> anyhow::Result<String> {
+    // Use the current thread name for the test name, both cargo-test and
+    // cargo-nextest set this.
+    // FUTURE: If we ever want to use petri outside a testing context this
+    // will need to be revisited.
+    let current_thread = std::thread::current();
+    let test_name = current_thread.name().context("no thread name configured")?;
+    if test_name.is_empty() {
+        anyhow::bail!("thread name is empty");
+    }
+    if test_name == "main" {
+        anyhow::bail!("thread name is 'main', not running from test thread");
+    }
+    // Windows paths can't include colons, replace them.
+    Ok(test_name.replace("::", "__"))
+}
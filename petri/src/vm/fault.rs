@@ -0,0 +1,88 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Controls for faults injected into a VM's disks and networks while it is
+//! running.
+
+pub use disk_backend_resources::DiskFaults;
+pub use net_backend_resources::fault::NetFaults;
+
+use disk_backend_resources::FaultDiskHandle;
+use net_backend_resources::fault::FaultHandle;
+use vm_resource::kind::DiskHandleKind;
+use vm_resource::kind::NetEndpointHandleKind;
+use vm_resource::IntoResource;
+use vm_resource::Resource;
+
+/// Changes the faults injected into a disk, from
+/// [`PetriVmConfig::with_fault_disk`](crate::PetriVmConfig::with_fault_disk).
+///
+/// Dropping the injector leaves the current faults in place.
+pub struct DiskFaultInjector(mesh::Sender<DiskFaults>);
+
+impl DiskFaultInjector {
+    /// Wraps `disk` so that faults can be injected into it.
+    pub(crate) fn wrap(disk: Resource<DiskHandleKind>) -> (Resource<DiskHandleKind>, Self) {
+        let (send, recv) = mesh::channel();
+        let disk = FaultDiskHandle {
+            disk,
+            faults: DiskFaults::default(),
+            update: recv,
+        }
+        .into_resource();
+        (disk, Self(send))
+    }
+
+    /// Replaces the injected faults. They apply to IOs issued after this
+    /// call.
+    pub fn set(&self, faults: DiskFaults) {
+        tracing::info!(?faults, "injecting disk faults");
+        self.0.send(faults);
+    }
+
+    /// Stops injecting faults.
+    pub fn clear(&self) {
+        self.set(DiskFaults::default());
+    }
+}
+
+/// Changes the faults injected into a NIC's network, from
+/// [`PetriVmConfig::with_fault_network`](crate::PetriVmConfig::with_fault_network).
+///
+/// Dropping the injector leaves the current faults in place.
+pub struct NetworkFaultInjector(mesh::Sender<NetFaults>);
+
+impl NetworkFaultInjector {
+    /// Wraps `endpoint` so that faults can be injected into it.
+    pub(crate) fn wrap(
+        endpoint: Resource<NetEndpointHandleKind>,
+    ) -> (Resource<NetEndpointHandleKind>, Self) {
+        let (send, recv) = mesh::channel();
+        let endpoint = FaultHandle {
+            endpoint,
+            faults: NetFaults::default(),
+            update: recv,
+        }
+        .into_resource();
+        (endpoint, Self(send))
+    }
+
+    /// Replaces the injected faults.
+    pub fn set(&self, faults: NetFaults) {
+        tracing::info!(?faults, "injecting network faults");
+        self.0.send(faults);
+    }
+
+    /// Stops injecting faults, reconnecting the link if it was down.
+    pub fn clear(&self) {
+        self.set(NetFaults::default());
+    }
+
+    /// Disconnects the link, as if the cable was unplugged.
+    pub fn link_down(&self) {
+        self.set(NetFaults {
+            link_down: true,
+            ..Default::default()
+        });
+    }
+}
@@ -0,0 +1,430 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A backend that runs test VMs on Hyper-V instead of OpenVMM, so that the
+//! same guests and OpenHCL images can be checked for behavioral differences
+//! between the two.
+//!
+//! VMs are provisioned with the Hyper-V PowerShell module, which must be
+//! installed, and tests must run as an administrator.
+
+mod powershell;
+
+use super::construct::current_test_name;
+use super::Firmware;
+use super::PcatGuest;
+use super::UefiGuest;
+use crate::disk_image::build_agent_image;
+use crate::ShutdownKind;
+use anyhow::Context;
+use guid::Guid;
+use pal_async::socket::PolledSocket;
+use pal_async::timer::PolledTimer;
+use pal_async::DefaultDriver;
+use petri_artifacts_common::artifacts as common_artifacts;
+use petri_artifacts_common::tags::MachineArch;
+use petri_artifacts_common::tags::OsFlavor;
+use petri_artifacts_core::TestArtifacts;
+use petri_artifacts_vmm_test::artifacts as hvlite_artifacts;
+use pipette_client::PipetteClient;
+use pipette_client::PIPETTE_VSOCK_PORT;
+use powershell::Generation;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use vmm_core_defs::HaltReason;
+use vmsocket::VmAddress;
+use vmsocket::VmListener;
+
+/// The template for hvsocket service IDs that correspond to vsock ports. The
+/// port is stored in the first field.
+const VSOCK_TEMPLATE: Guid = Guid::from_static_str("00000000-facb-11e6-bd58-64006a7986d3");
+
+/// Configuration state for a test VM running on Hyper-V.
+///
+/// This mirrors [`PetriVmConfig`](crate::PetriVmConfig), and accepts the same
+/// [`Firmware`] descriptions, so that a test can be run against both. Linux
+/// direct boot is not supported by Hyper-V.
+pub struct PetriVmConfigHyperV {
+    name: String,
+    firmware: Firmware,
+    arch: MachineArch,
+    processors: u32,
+    memory: u64,
+    secure_boot_template: Option<&'static str>,
+
+    resolver: TestArtifacts,
+    driver: DefaultDriver,
+    output_dir: PathBuf,
+    temp_dir: tempfile::TempDir,
+}
+
+impl PetriVmConfigHyperV {
+    /// Create a new Hyper-V VM configuration.
+    pub fn new(
+        firmware: Firmware,
+        arch: MachineArch,
+        resolver: TestArtifacts,
+        driver: &DefaultDriver,
+    ) -> anyhow::Result<Self> {
+        if firmware.is_linux_direct() {
+            anyhow::bail!("Hyper-V does not support Linux direct boot");
+        }
+
+        let test_name = current_test_name()?;
+        let output_dir = resolver
+            .resolve(common_artifacts::TEST_LOG_DIRECTORY)
+            .join(&test_name);
+        if output_dir.exists() {
+            std::fs::remove_dir_all(&output_dir)?;
+        }
+        std::fs::create_dir_all(&output_dir)?;
+        let petri_file = fs_err::File::create(output_dir.join("petri.log"))?;
+        crate::tracing::try_init_tracing(petri_file.into())?;
+
+        Ok(Self {
+            name: format!("petri-{test_name}"),
+            firmware,
+            arch,
+            processors: 2,
+            memory: 4 * crate::SIZE_1_GB,
+            secure_boot_template: None,
+            resolver,
+            driver: driver.clone(),
+            output_dir,
+            temp_dir: tempfile::tempdir()?,
+        })
+    }
+
+    /// Get the OS that the VM will boot into.
+    pub fn os_flavor(&self) -> OsFlavor {
+        self.firmware.os_flavor()
+    }
+
+    /// Set the VM to use a single processor.
+    pub fn with_single_processor(mut self) -> Self {
+        self.processors = 1;
+        self
+    }
+
+    /// Set the amount of memory for the VM, in bytes.
+    pub fn with_memory(mut self, memory: u64) -> Self {
+        self.memory = memory;
+        self
+    }
+
+    /// Enable secure boot with the Microsoft Windows template.
+    pub fn with_windows_secure_boot_template(mut self) -> Self {
+        assert!(self.firmware.is_uefi(), "secure boot requires UEFI");
+        self.secure_boot_template = Some("MicrosoftWindows");
+        self
+    }
+
+    /// Enable secure boot with the Microsoft UEFI CA template, as used by
+    /// Linux guests.
+    pub fn with_uefi_ca_secure_boot_template(mut self) -> Self {
+        assert!(self.firmware.is_uefi(), "secure boot requires UEFI");
+        self.secure_boot_template = Some("MicrosoftUEFICertificateAuthority");
+        self
+    }
+
+    /// Build and boot the VM, then wait for pipette to connect.
+    pub async fn run(self) -> anyhow::Result<(PetriVmHyperV, PipetteClient)> {
+        match self.os_flavor() {
+            OsFlavor::Linux => {}
+            // TODO: Windows guests launch pipette from an IMC hive, which
+            // Hyper-V has no way to inject yet.
+            OsFlavor::Windows => anyhow::bail!("Windows guests are not yet supported on Hyper-V"),
+            OsFlavor::FreeBsd | OsFlavor::Uefi => {
+                anyhow::bail!("pipette is not supported on this guest")
+            }
+        }
+        let mut vm = self.start(true).await?;
+        let client = vm.wait_for_agent().await?;
+        Ok((vm, client))
+    }
+
+    /// Build and boot the VM without waiting for pipette.
+    pub async fn run_without_agent(self) -> anyhow::Result<PetriVmHyperV> {
+        self.start(false).await
+    }
+
+    async fn start(self, with_agent: bool) -> anyhow::Result<PetriVmHyperV> {
+        let generation = match &self.firmware {
+            Firmware::Pcat { .. } => Generation::One,
+            Firmware::Uefi { .. } | Firmware::OpenhclUefi { .. } => Generation::Two,
+            Firmware::LinuxDirect | Firmware::OpenhclLinuxDirect => unreachable!(),
+        };
+        let (igvm, isolation) = match &self.firmware {
+            Firmware::OpenhclUefi {
+                isolation: Some(_), ..
+            } => (
+                Some(
+                    self.resolver
+                        .resolve(hvlite_artifacts::openhcl_igvm::LATEST_CVM_X64),
+                ),
+                Some("VBS"),
+            ),
+            Firmware::OpenhclUefi { .. } => (
+                Some(
+                    self.resolver
+                        .resolve(hvlite_artifacts::openhcl_igvm::LATEST_STANDARD_X64),
+                ),
+                Some("TrustedLaunch"),
+            ),
+            _ => (None, None),
+        };
+
+        tracing::info!(name = self.name, ?generation, "creating Hyper-V VM");
+        let vm_id = powershell::new_vm(
+            &self.name,
+            generation,
+            self.memory,
+            self.temp_dir.path(),
+            isolation,
+        )
+        .await
+        .context("failed to create VM")?;
+
+        // From here on, the VM is removed when this is dropped.
+        let mut vm = PetriVmHyperV {
+            vm_id,
+            driver: self.driver.clone(),
+            output_dir: self.output_dir.clone(),
+            pipette_listener: None,
+            removed: false,
+            _temp_dir: self.temp_dir,
+        };
+        let temp_dir = vm._temp_dir.path();
+
+        powershell::set_vm_processor(vm_id, self.processors).await?;
+        if let Generation::Two = generation {
+            powershell::set_vm_secure_boot(vm_id, self.secure_boot_template).await?;
+        }
+        if let Some(igvm) = igvm {
+            powershell::set_vm_firmware_file(vm_id, &igvm)
+                .await
+                .context("failed to set the OpenHCL firmware file")?;
+        }
+
+        // Boot from a differencing disk so that the shared image is not
+        // modified.
+        let boot_image = match &self.firmware {
+            Firmware::Pcat {
+                guest: PcatGuest::Vhd(vhd),
+            }
+            | Firmware::Uefi {
+                guest: UefiGuest::Vhd(vhd),
+            }
+            | Firmware::OpenhclUefi {
+                guest: UefiGuest::Vhd(vhd),
+                ..
+            } => Some(vhd.artifact),
+            Firmware::Uefi {
+                guest: guest @ UefiGuest::GuestTestUefi(_),
+            }
+            | Firmware::OpenhclUefi {
+                guest: guest @ UefiGuest::GuestTestUefi(_),
+                ..
+            } => Some(guest.artifact()),
+            Firmware::Pcat {
+                guest: PcatGuest::Iso(_),
+            } => anyhow::bail!("ISO guests are not yet supported on Hyper-V"),
+            _ => None,
+        };
+        let mut lun = 0;
+        if let Some(artifact) = boot_image {
+            let parent = self.resolver.resolve(artifact);
+            let path = temp_dir.join(format!(
+                "boot.{}",
+                parent.extension().and_then(|e| e.to_str()).unwrap_or("vhd")
+            ));
+            powershell::new_differencing_vhd(&path, &parent)
+                .await
+                .context("failed to create boot disk")?;
+            powershell::add_vm_hard_disk_drive(vm_id, generation, &path, lun).await?;
+            lun += 1;
+        }
+
+        if with_agent {
+            let path = temp_dir.join("agent.vhd");
+            build_agent_vhd(self.arch, self.firmware.os_flavor(), &self.resolver, &path)
+                .context("failed to build agent image")?;
+            powershell::add_vm_hard_disk_drive(vm_id, generation, &path, lun).await?;
+
+            let service_id = Guid {
+                data1: PIPETTE_VSOCK_PORT,
+                ..VSOCK_TEMPLATE
+            };
+            powershell::register_hvsocket_service(service_id, "petri pipette")
+                .await
+                .context("failed to register the pipette service")?;
+            let listener = VmListener::bind(VmAddress::hyperv_vsock(vm_id, PIPETTE_VSOCK_PORT))
+                .context("failed to bind to pipette listener")?;
+            vm.pipette_listener = Some(PolledSocket::new(&self.driver, listener)?);
+        }
+
+        let pipe_path = format!(r"\\.\pipe\petri-{vm_id}-com1");
+        powershell::set_vm_com_port(vm_id, 1, &pipe_path).await?;
+
+        tracing::info!("starting Hyper-V VM");
+        powershell::start_vm(vm_id)
+            .await
+            .context("failed to start VM")?;
+        spawn_serial_log(pipe_path, self.output_dir.join("guest.log"));
+
+        tracing::info!("VM ready");
+        Ok(vm)
+    }
+}
+
+/// Builds the agent disk image as a fixed VHD at `path`, since Hyper-V
+/// cannot attach raw images.
+fn build_agent_vhd(
+    arch: MachineArch,
+    os_flavor: OsFlavor,
+    resolver: &TestArtifacts,
+    path: &Path,
+) -> anyhow::Result<()> {
+    use std::io::Seek;
+
+    let mut image = build_agent_image(arch, os_flavor, resolver)?;
+    image.rewind()?;
+    let mut file = fs_err::File::create(path)?;
+    std::io::copy(&mut image, &mut file)?;
+    disk_vhd1::Vhd1Disk::make_fixed(file.file()).context("failed to make a VHD")?;
+    Ok(())
+}
+
+/// Copies the guest's serial output from the named pipe at `pipe_path` to
+/// `log_path` until the VM is turned off.
+fn spawn_serial_log(pipe_path: String, log_path: PathBuf) {
+    std::thread::Builder::new()
+        .name("petri-hyperv-serial".into())
+        .spawn(move || {
+            let result = (|| {
+                // Hyper-V creates the pipe when the VM starts, so retry
+                // briefly.
+                let mut attempts = 0;
+                let mut pipe = loop {
+                    match std::fs::File::open(&pipe_path) {
+                        Ok(pipe) => break pipe,
+                        Err(_) if attempts < 50 => {
+                            attempts += 1;
+                            std::thread::sleep(Duration::from_millis(100));
+                        }
+                        Err(err) => return Err(err),
+                    }
+                };
+                let mut log = std::fs::File::create(&log_path)?;
+                std::io::copy(&mut pipe, &mut log)?;
+                Ok(())
+            })();
+            if let Err(err) = result {
+                tracing::warn!(
+                    error = &err as &dyn std::error::Error,
+                    pipe_path,
+                    "failed to log serial output"
+                );
+            }
+        })
+        .expect("failed to spawn thread");
+}
+
+/// A test VM running on Hyper-V.
+///
+/// The VM is turned off and removed when this is dropped.
+pub struct PetriVmHyperV {
+    vm_id: Guid,
+    driver: DefaultDriver,
+    output_dir: PathBuf,
+    pipette_listener: Option<PolledSocket<VmListener>>,
+    removed: bool,
+    _temp_dir: tempfile::TempDir,
+}
+
+impl PetriVmHyperV {
+    /// The Hyper-V ID of the VM.
+    pub fn vm_id(&self) -> Guid {
+        self.vm_id
+    }
+
+    /// Wait for a connection from a pipette agent running in the guest.
+    /// Useful if you've rebooted the vm or are otherwise expecting a fresh
+    /// connection.
+    pub async fn wait_for_agent(&mut self) -> anyhow::Result<PipetteClient> {
+        let listener = self
+            .pipette_listener
+            .as_mut()
+            .context("the VM was started without pipette")?;
+
+        tracing::info!("listening for pipette connection");
+        let (conn, _) = listener
+            .accept()
+            .await
+            .context("failed to accept pipette connection")?;
+
+        tracing::info!("handshaking with pipette");
+        let client = PipetteClient::new(
+            &self.driver,
+            PolledSocket::new(&self.driver, conn)?,
+            &self.output_dir,
+        )
+        .await
+        .context("failed to connect to pipette")?;
+
+        tracing::info!("completed pipette handshake");
+        Ok(client)
+    }
+
+    /// Instruct the guest to shutdown via the Hyper-V shutdown IC.
+    pub async fn send_enlightened_shutdown(&mut self, kind: ShutdownKind) -> anyhow::Result<()> {
+        powershell::shutdown_vm(
+            self.vm_id,
+            match kind {
+                ShutdownKind::Shutdown => false,
+                ShutdownKind::Reboot => true,
+            },
+        )
+        .await
+    }
+
+    /// Wait for the VM to turn off.
+    ///
+    /// Hyper-V does not report why a VM halted, and restarts the VM itself
+    /// when the guest resets, so this only returns [`HaltReason::PowerOff`].
+    pub async fn wait_for_halt(&mut self) -> anyhow::Result<HaltReason> {
+        let mut timer = PolledTimer::new(&self.driver);
+        loop {
+            let state = powershell::vm_state(self.vm_id).await?;
+            if state == "Off" {
+                return Ok(HaltReason::PowerOff);
+            }
+            timer.sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Wait for the VM to turn off, returning the reason for the halt, and
+    /// remove the VM.
+    pub async fn wait_for_teardown(mut self) -> anyhow::Result<HaltReason> {
+        let halt_reason = self.wait_for_halt().await?;
+        tracing::info!(?halt_reason, "Got halt reason, removing VM");
+        self.removed = true;
+        powershell::remove_vm(self.vm_id).await?;
+        Ok(halt_reason)
+    }
+}
+
+impl Drop for PetriVmHyperV {
+    fn drop(&mut self) {
+        if !self.removed {
+            if let Err(err) = futures::executor::block_on(powershell::remove_vm(self.vm_id)) {
+                tracing::error!(
+                    error = err.as_ref() as &dyn std::error::Error,
+                    vm_id = %self.vm_id,
+                    "failed to remove VM"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Wrappers around the Hyper-V PowerShell cmdlets.
+
+use anyhow::Context;
+use guid::Guid;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Command;
+
+/// The generation of a Hyper-V VM.
+#[derive(Debug, Copy, Clone)]
+pub enum Generation {
+    /// A PCAT BIOS based VM.
+    One,
+    /// A UEFI based VM.
+    Two,
+}
+
+/// Quotes `s` as a PowerShell single-quoted string literal.
+fn quote(s: impl AsRef<OsStr>) -> String {
+    format!("'{}'", s.as_ref().to_string_lossy().replace('\'', "''"))
+}
+
+/// Runs `script`, stopping on the first error, and returns its trimmed
+/// standard output.
+async fn run(script: String) -> anyhow::Result<String> {
+    tracing::debug!(script, "running powershell");
+    let output = blocking::unblock(move || {
+        Command::new("powershell.exe")
+            .args(["-NoLogo", "-NoProfile", "-NonInteractive", "-Command"])
+            .arg(format!("$ErrorActionPreference = 'Stop'; {script}"))
+            .output()
+    })
+    .await
+    .context("failed to launch powershell")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "powershell failed ({}): {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+/// Creates a VM with no drives and returns its ID.
+pub async fn new_vm(
+    name: &str,
+    generation: Generation,
+    memory: u64,
+    path: &Path,
+    guest_state_isolation: Option<&str>,
+) -> anyhow::Result<Guid> {
+    let mut script = format!(
+        "(New-VM -Name {} -Generation {} -MemoryStartupBytes {memory} -Path {} -NoVHD",
+        quote(name),
+        match generation {
+            Generation::One => 1,
+            Generation::Two => 2,
+        },
+        quote(path),
+    );
+    if let Some(isolation) = guest_state_isolation {
+        script.push_str(&format!(" -GuestStateIsolationType {}", quote(isolation)));
+    }
+    script.push_str(").Id.Guid");
+    run(script)
+        .await?
+        .parse()
+        .context("New-VM returned an invalid VM ID")
+}
+
+/// Removes the VM, turning it off first if necessary.
+pub async fn remove_vm(vm_id: Guid) -> anyhow::Result<()> {
+    run(format!(
+        "$vm = Get-VM -Id {}; if ($vm.State -ne 'Off') {{ $vm | Stop-VM -TurnOff -Force }}; $vm | Remove-VM -Force",
+        quote(vm_id.to_string())
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Sets the number of virtual processors.
+pub async fn set_vm_processor(vm_id: Guid, count: u32) -> anyhow::Result<()> {
+    run(format!(
+        "Get-VM -Id {} | Set-VMProcessor -Count {count}",
+        quote(vm_id.to_string())
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Configures UEFI secure boot, with `template` if it is enabled.
+pub async fn set_vm_secure_boot(vm_id: Guid, template: Option<&str>) -> anyhow::Result<()> {
+    let script = match template {
+        Some(template) => format!(
+            "Get-VM -Id {} | Set-VMFirmware -EnableSecureBoot On -SecureBootTemplate {}",
+            quote(vm_id.to_string()),
+            quote(template)
+        ),
+        None => format!(
+            "Get-VM -Id {} | Set-VMFirmware -EnableSecureBoot Off",
+            quote(vm_id.to_string())
+        ),
+    };
+    run(script).await?;
+    Ok(())
+}
+
+/// Loads the paravisor from the IGVM file at `igvm_path` instead of the
+/// built-in firmware.
+pub async fn set_vm_firmware_file(vm_id: Guid, igvm_path: &Path) -> anyhow::Result<()> {
+    // There is no cmdlet for this, so modify the VM's settings through WMI.
+    run(format!(
+        r#"$vssd = Get-CimInstance -Namespace root\virtualization\v2 -ClassName Msvm_VirtualSystemSettingData -Filter "VirtualSystemIdentifier='{vm_id}' AND VirtualSystemType='Microsoft:Hyper-V:System:Realized'"
+$vssd.FirmwareFile = {}
+$vssd.GuestStateIsolationEnabled = $true
+$vsms = Get-CimInstance -Namespace root\virtualization\v2 -ClassName Msvm_VirtualSystemManagementService
+$serialized = ([Microsoft.Management.Infrastructure.Serialization.CimSerializer]::Create()).Serialize($vssd, [Microsoft.Management.Infrastructure.Serialization.InstanceSerializationOptions]::None)
+$result = $vsms | Invoke-CimMethod -MethodName ModifySystemSettings -Arguments @{{ SystemSettings = [System.Text.Encoding]::Unicode.GetString($serialized) }}
+if ($result.ReturnValue -ne 0) {{ throw "ModifySystemSettings failed: $($result.ReturnValue)" }}"#,
+        quote(igvm_path)
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Attaches the disk at `path` to the VM's first SCSI controller at `lun`,
+/// or to the first IDE controller for generation 1 VMs.
+pub async fn add_vm_hard_disk_drive(
+    vm_id: Guid,
+    generation: Generation,
+    path: &Path,
+    lun: u32,
+) -> anyhow::Result<()> {
+    let (controller_type, controller_number) = match generation {
+        Generation::One => ("IDE", 0),
+        Generation::Two => ("SCSI", 0),
+    };
+    run(format!(
+        "Get-VM -Id {} | Add-VMHardDiskDrive -ControllerType {controller_type} -ControllerNumber {controller_number} -ControllerLocation {lun} -Path {}",
+        quote(vm_id.to_string()),
+        quote(path),
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Creates a differencing disk at `path` on top of `parent_path`, so that the
+/// parent is not modified by the VM.
+pub async fn new_differencing_vhd(path: &Path, parent_path: &Path) -> anyhow::Result<()> {
+    run(format!(
+        "New-VHD -Differencing -Path {} -ParentPath {} | Out-Null",
+        quote(path),
+        quote(parent_path),
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Connects COM port `number` to the named pipe at `pipe_path`.
+pub async fn set_vm_com_port(vm_id: Guid, number: u32, pipe_path: &str) -> anyhow::Result<()> {
+    run(format!(
+        "Get-VM -Id {} | Set-VMComPort -Number {number} -Path {}",
+        quote(vm_id.to_string()),
+        quote(pipe_path),
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Registers the hvsocket service ID that pipette connects to, so that the
+/// host accepts its connections. This requires administrator privileges.
+pub async fn register_hvsocket_service(service_id: Guid, name: &str) -> anyhow::Result<()> {
+    run(format!(
+        r#"$key = Join-Path 'HKLM:\SOFTWARE\Microsoft\Windows NT\CurrentVersion\Virtualization\GuestCommunicationServices' {}
+if (-not (Test-Path $key)) {{ New-Item -Path $key -Force | Out-Null }}
+Set-ItemProperty -Path $key -Name ElementName -Value {}"#,
+        quote(service_id.to_string()),
+        quote(name),
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Starts the VM.
+pub async fn start_vm(vm_id: Guid) -> anyhow::Result<()> {
+    run(format!(
+        "Get-VM -Id {} | Start-VM",
+        quote(vm_id.to_string())
+    ))
+    .await?;
+    Ok(())
+}
+
+/// Returns the VM's state, such as `Running` or `Off`.
+pub async fn vm_state(vm_id: Guid) -> anyhow::Result<String> {
+    run(format!("(Get-VM -Id {}).State", quote(vm_id.to_string()))).await
+}
+
+/// Shuts the guest down through the shutdown integration component, or
+/// restarts it if `restart` is set.
+pub async fn shutdown_vm(vm_id: Guid, restart: bool) -> anyhow::Result<()> {
+    let script = if restart {
+        format!(
+            "Get-VM -Id {} | Restart-VM -Type Reboot -Force",
+            quote(vm_id.to_string())
+        )
+    } else {
+        format!("Get-VM -Id {} | Stop-VM -Force", quote(vm_id.to_string()))
+    };
+    run(script).await?;
+    Ok(())
+}
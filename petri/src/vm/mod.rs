@@ -8,15 +8,34 @@
 //! * The VM is interacted with through the methods in `runtime`.
 //! * The VM is either shut down by the code in `runtime`, or gets dropped and cleaned up automatically.
 
+mod boot_metrics;
 mod construct;
+mod fault;
+#[cfg(windows)]
+pub mod hyperv;
 mod modify;
 mod runtime;
+mod screenshot;
 mod start;
-
+mod topology;
+
+pub use boot_metrics::BootBudget;
+pub use boot_metrics::BootTimes;
+pub use fault::DiskFaultInjector;
+pub use fault::DiskFaults;
+pub use fault::NetFaults;
+pub use fault::NetworkFaultInjector;
 pub use runtime::PetriVm;
+pub use runtime::PetriVmSavedState;
+pub use screenshot::Screenshot;
+pub use topology::PetriNetwork;
+pub use topology::PetriSharedDisk;
+pub use topology::PetriTopology;
 
+use crate::freebsd_serial_console::FreeBsdSerialConsole;
 use crate::linux_direct_serial_agent::LinuxDirectSerialAgent;
 use crate::openhcl_diag::OpenHclDiagHandler;
+use crate::vm::boot_metrics::BootTimer;
 use framebuffer::FramebufferAccess;
 use fs_err::File;
 use get_resources::ged::FirmwareEvent;
@@ -79,6 +98,8 @@ pub struct PetriVmConfig {
 struct PetriVmResources {
     serial_tasks: Vec<Task<anyhow::Result<()>>>,
     firmware_event_recv: MpscReceiver<FirmwareEvent>,
+    _firmware_event_task: Task<()>,
+    boot_timer: BootTimer,
     shutdown_ic_send: Sender<ShutdownRpc>,
     expected_boot_event: Option<FirmwareEvent>,
     ged_send: Option<Arc<Sender<get_resources::ged::GuestEmulationRequest>>>,
@@ -86,6 +107,7 @@ struct PetriVmResources {
     vtl2_pipette_listener: Option<PolledSocket<UnixListener>>,
     openhcl_diag_handler: Option<OpenHclDiagHandler>,
     linux_direct_serial_agent: Option<LinuxDirectSerialAgent>,
+    freebsd_serial_console: Option<FreeBsdSerialConsole>,
 
     // Externally injected management stuff also needed at runtime.
     driver: DefaultDriver,
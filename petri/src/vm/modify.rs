@@ -3,18 +3,36 @@
 
 //! Helpers to modify a [`PetriVmConfig`] from its defaults.
 
+use crate::BootBudget;
+use crate::DiskFaultInjector;
+use crate::NetworkFaultInjector;
+use crate::PetriNetwork;
+use crate::PetriSharedDisk;
 use crate::PetriVmConfig;
 use chipset_resources::battery::BatteryDeviceHandleX64;
 use chipset_resources::battery::HostBatteryUpdate;
+use disk_backend_resources::layer::RamDiskLayerHandle;
+use disk_backend_resources::LayeredDiskHandle;
 use fs_err::File;
+use guid::Guid;
 use hvlite_defs::config::Config;
+use hvlite_defs::config::DeviceVtl;
 use hvlite_defs::config::LoadMode;
 use hvlite_defs::config::Vtl2BaseAddressType;
+use net_backend_resources::mac_address::MacAddress;
+use netvsp_resources::NetvspHandle;
 use petri_artifacts_common::tags::IsOpenhclIgvm;
 use petri_artifacts_core::ArtifactHandle;
+use scsidisk_resources::SimpleScsiDiskHandle;
+use storvsp_resources::ScsiControllerHandle;
+use storvsp_resources::ScsiDeviceAndPath;
+use storvsp_resources::ScsiPath;
 use tpm_resources::TpmDeviceHandle;
 use tpm_resources::TpmRegisterLayout;
+use vm_resource::kind::DiskHandleKind;
+use vm_resource::kind::NetEndpointHandleKind;
 use vm_resource::IntoResource;
+use vm_resource::Resource;
 use vmcore::non_volatile_store::resources::EphemeralNonVolatileStoreHandle;
 use vmotherboard::ChipsetDeviceHandle;
 use vtl2_settings_proto::Vtl2Settings;
@@ -191,6 +209,98 @@ pub fn with_vtl2_relocation_mode(mut self, mode: Vtl2BaseAddressType) -> Self {
         self
     }
 
+    /// Add a NIC connected to `network`, which may be shared with other VMs
+    /// in the same [`PetriTopology`](crate::PetriTopology).
+    ///
+    /// Returns the MAC address assigned to the NIC, for configuring the guest.
+    pub fn with_network(mut self, network: &PetriNetwork) -> anyhow::Result<(Self, MacAddress)> {
+        let (endpoint, mac_address) = network.port()?;
+        self.add_nic(endpoint, mac_address);
+        Ok((self, mac_address))
+    }
+
+    /// Add a NIC connected to `network`, like [`Self::with_network`], with
+    /// faults such as link flaps injected by the returned injector.
+    pub fn with_fault_network(
+        mut self,
+        network: &PetriNetwork,
+    ) -> anyhow::Result<(Self, MacAddress, NetworkFaultInjector)> {
+        let (endpoint, mac_address) = network.port()?;
+        let (endpoint, injector) = NetworkFaultInjector::wrap(endpoint);
+        self.add_nic(endpoint, mac_address);
+        Ok((self, mac_address, injector))
+    }
+
+    fn add_nic(&mut self, endpoint: Resource<NetEndpointHandleKind>, mac_address: MacAddress) {
+        self.config.vmbus_devices.push((
+            DeviceVtl::Vtl0,
+            NetvspHandle {
+                instance_id: Guid::new_random(),
+                mac_address,
+                endpoint,
+                max_queues: None,
+                enable_rsc: false,
+            }
+            .into_resource(),
+        ));
+    }
+
+    /// Attach `disk` to the VM on a new SCSI controller. The disk may also be
+    /// attached to other VMs in the same
+    /// [`PetriTopology`](crate::PetriTopology).
+    pub fn with_shared_disk(mut self, disk: &PetriSharedDisk) -> anyhow::Result<Self> {
+        self.add_scsi_disk(disk.disk()?);
+        Ok(self)
+    }
+
+    /// Attach an empty, memory-backed data disk of `size` bytes to the VM on a
+    /// new SCSI controller, with faults such as IO errors and latency
+    /// injected by the returned injector.
+    pub fn with_fault_disk(mut self, size: u64) -> (Self, DiskFaultInjector) {
+        let (disk, injector) = DiskFaultInjector::wrap(
+            LayeredDiskHandle::single_layer(RamDiskLayerHandle { len: Some(size) }).into_resource(),
+        );
+        self.add_scsi_disk(disk);
+        (self, injector)
+    }
+
+    /// Fail the test if any boot takes longer than `budget` to reach a stage.
+    ///
+    /// Each stage is checked when the test waits for the boot event or for
+    /// the agent, using
+    /// [`PetriVm::wait_for_successful_boot_event`](crate::PetriVm::wait_for_successful_boot_event)
+    /// or [`PetriVm::wait_for_agent`](crate::PetriVm::wait_for_agent).
+    pub fn with_boot_budget(self, budget: BootBudget) -> Self {
+        self.resources.boot_timer.set_budget(budget);
+        self
+    }
+
+    fn add_scsi_disk(&mut self, disk: Resource<DiskHandleKind>) {
+        self.config.vmbus_devices.push((
+            DeviceVtl::Vtl0,
+            ScsiControllerHandle {
+                instance_id: Guid::new_random(),
+                max_sub_channel_count: 1,
+                io_queue_depth: None,
+                devices: vec![ScsiDeviceAndPath {
+                    path: ScsiPath {
+                        path: 0,
+                        target: 0,
+                        lun: 0,
+                    },
+                    device: SimpleScsiDiskHandle {
+                        read_only: false,
+                        parameters: Default::default(),
+                        disk,
+                    }
+                    .into_resource(),
+                }],
+                requests: None,
+            }
+            .into_resource(),
+        ));
+    }
+
     /// This is intended for special one-off use cases. As soon as something
     /// is needed in multiple tests we should consider making it a supported
     /// pattern.
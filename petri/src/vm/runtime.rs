@@ -3,15 +3,22 @@
 
 //! Methods to interact with a running [`PetriVm`].
 
+use super::boot_metrics::BootStage;
 use super::PetriVmResources;
 use crate::openhcl_diag::OpenHclDiagHandler;
+use crate::tracing::trace_attachment;
 use crate::worker::Worker;
+use crate::BootTimes;
+use crate::Screenshot;
 use crate::ShutdownKind;
 use anyhow::Context;
+use framebuffer::View;
 use futures::FutureExt;
 use futures_concurrency::future::Race;
+use guid::Guid;
 use hvlite_defs::rpc::PulseSaveRestoreError;
 use hyperv_ic_resources::shutdown::ShutdownRpc;
+use mesh::payload::message::ProtobufMessage;
 use mesh::rpc::RpcSend;
 use mesh::CancelContext;
 use mesh::Receiver;
@@ -21,11 +28,18 @@
 use pal_async::task::Task;
 use pal_async::timer::PolledTimer;
 use pal_async::DefaultDriver;
+use parking_lot::Mutex;
 use petri_artifacts_common::tags::GuestQuirks;
+use petri_artifacts_common::tags::IsOpenhclIgvm;
+use petri_artifacts_common::tags::OsFlavor;
 use petri_artifacts_core::ArtifactHandle;
+use pipette_client::tcp::TcpForward;
 use pipette_client::PipetteClient;
+use pipette_client::TransferProgress;
 use std::future::Future;
+use std::net::SocketAddr;
 use std::path::Path;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 use unix_socket::UnixListener;
@@ -46,6 +60,8 @@ pub(super) struct PetriVmInner {
     pub(super) worker: Arc<Worker>,
     pub(super) watchdog_tasks: Vec<Task<()>>,
     pub(super) quirks: GuestQuirks,
+    pub(super) os_flavor: OsFlavor,
+    pub(super) framebuffer_view: Option<Arc<Mutex<View>>>,
 }
 
 struct PetriVmHaltReceiver {
@@ -53,6 +69,12 @@ struct PetriVmHaltReceiver {
     already_received: Option<Result<HaltReason, RecvError>>,
 }
 
+/// The saved device and processor state of a paused [`PetriVm`], from
+/// [`PetriVm::save`].
+///
+/// Guest memory is not included, since the VM keeps it while paused.
+pub struct PetriVmSavedState(ProtobufMessage);
+
 // Wrap a PetriVmInner function in [`PetriVm::wait_for_halt_or_internal`] to
 // provide better error handling.
 macro_rules! petri_vm_fn {
@@ -80,6 +102,15 @@ pub fn vtl2_vsock_path(&self) -> anyhow::Result<&Path> {
         self.inner.openhcl_diag().map(|x| &*x.vtl2_vsock_path)
     }
 
+    /// Get how long each boot of the VM took to reach each stage, oldest
+    /// first.
+    ///
+    /// These are also written to `boot_metrics.json` in the test's output
+    /// directory.
+    pub fn boot_times(&self) -> Vec<BootTimes> {
+        self.inner.resources.boot_timer.boots()
+    }
+
     /// Get the artifact resolver constructed for this VM.
     pub fn artifact_resolver(&self) -> &petri_artifacts_core::TestArtifacts {
         &self.inner.resources.resolver
@@ -146,7 +177,7 @@ pub async fn send_enlightened_shutdown(&mut self, kind: ShutdownKind) -> anyhow:
     );
     petri_vm_fn!(
         /// Restarts OpenHCL.
-        pub async fn restart_openhcl(&mut self, new_openhcl: ArtifactHandle<impl petri_artifacts_common::tags::IsOpenhclIgvm>) -> anyhow::Result<()>
+        pub async fn restart_openhcl(&mut self, new_openhcl: ArtifactHandle<impl IsOpenhclIgvm>) -> anyhow::Result<()>
     );
     petri_vm_fn!(
         /// Resets the hardware state of the VM, simulating a power cycle.
@@ -180,9 +211,279 @@ pub async fn wait_for_vtl2_agent(&mut self) -> anyhow::Result<PipetteClient>
         pub async fn modify_vtl2_settings(&mut self, settings: &vtl2_settings_proto::Vtl2Settings) -> anyhow::Result<()>
     );
 
+    petri_vm_fn!(
+        /// Pauses the VM and saves the state of its devices and processors.
+        ///
+        /// The VM stays paused until it is restored with [`PetriVm::restore`].
+        pub async fn save(&mut self) -> anyhow::Result<PetriVmSavedState>
+    );
+    petri_vm_fn!(
+        /// Resets the devices and processors of a VM paused by
+        /// [`PetriVm::save`], restores them from `saved_state`, and resumes the
+        /// VM.
+        pub async fn restore(&mut self, saved_state: PetriVmSavedState) -> anyhow::Result<()>
+    );
+
+    /// Saves and restores the VM `count` times using [`PetriVm::save`] and
+    /// [`PetriVm::restore`], verifying that the guest continues running
+    /// across each round trip.
+    ///
+    /// Before saving, a marker file with unique contents is written to the
+    /// guest with `agent`. After restoring, the same connection to the agent
+    /// must still respond, and the marker must be unchanged, showing that
+    /// the guest was not rebooted or otherwise disturbed.
+    pub async fn save_restore(
+        &mut self,
+        agent: &PipetteClient,
+        count: usize,
+    ) -> anyhow::Result<()> {
+        for i in 0..count {
+            let marker = self.write_marker(agent).await?;
+
+            tracing::info!(i, "saving vm");
+            let saved_state = self.save().await?;
+            tracing::info!(i, "restoring vm");
+            self.restore(saved_state)
+                .await
+                .with_context(|| format!("save + restore {i} failed"))?;
+
+            self.check_marker(agent, &marker)
+                .await
+                .with_context(|| format!("guest did not survive save + restore {i}"))?;
+        }
+        Ok(())
+    }
+
+    /// Services OpenHCL to `new_openhcl` with [`PetriVm::restart_openhcl`],
+    /// verifying that the guest workload survives.
+    ///
+    /// As with [`PetriVm::save_restore`], a marker file is written to the
+    /// guest with `agent` beforehand, and afterwards the same connection to
+    /// the agent must still respond and the marker must be unchanged.
+    pub async fn service_openhcl(
+        &mut self,
+        agent: &PipetteClient,
+        new_openhcl: ArtifactHandle<impl IsOpenhclIgvm>,
+    ) -> anyhow::Result<()> {
+        let marker = self.write_marker(agent).await?;
+        self.restart_openhcl(new_openhcl)
+            .await
+            .context("failed to service openhcl")?;
+        self.wait_for_vtl2_ready().await?;
+        self.check_marker(agent, &marker)
+            .await
+            .context("guest did not survive openhcl servicing")
+    }
+
+    /// Services OpenHCL from `old_openhcl` to `new_openhcl` and back again
+    /// with [`PetriVm::service_openhcl`], verifying that the guest workload
+    /// survives both the upgrade and the downgrade.
+    ///
+    /// The VM must have been started with `old_openhcl`, using
+    /// [`PetriVmConfig::with_custom_openhcl`](crate::PetriVmConfig::with_custom_openhcl)
+    /// if it is not the default for the firmware.
+    pub async fn service_openhcl_upgrade_downgrade(
+        &mut self,
+        agent: &PipetteClient,
+        old_openhcl: ArtifactHandle<impl IsOpenhclIgvm>,
+        new_openhcl: ArtifactHandle<impl IsOpenhclIgvm>,
+    ) -> anyhow::Result<()> {
+        tracing::info!("servicing openhcl: upgrade");
+        self.service_openhcl(agent, new_openhcl)
+            .await
+            .context("openhcl upgrade failed")?;
+        tracing::info!("servicing openhcl: downgrade");
+        self.service_openhcl(agent, old_openhcl)
+            .await
+            .context("openhcl downgrade failed")
+    }
+
+    /// Writes a marker file with unique contents to the guest, for
+    /// [`Self::check_marker`] to verify after an operation that should not
+    /// disturb the guest.
+    async fn write_marker(&mut self, agent: &PipetteClient) -> anyhow::Result<String> {
+        let marker = Guid::new_random().to_string();
+        let path = self.marker_path();
+        self.wait_for_halt_or(agent.write_file(path, marker.as_bytes()))
+            .await
+            .context("failed to write marker")?;
+        Ok(marker)
+    }
+
+    /// Checks that the agent still responds on the same connection, and that
+    /// the marker file from [`Self::write_marker`] is unchanged.
+    async fn check_marker(&mut self, agent: &PipetteClient, marker: &str) -> anyhow::Result<()> {
+        self.wait_for_halt_or(async { agent.ping().await.context("agent did not respond") })
+            .await?;
+        let path = self.marker_path();
+        let contents = self
+            .wait_for_halt_or(agent.read_file(path))
+            .await
+            .context("failed to read marker")?;
+        anyhow::ensure!(contents == marker.as_bytes(), "guest marker changed");
+        Ok(())
+    }
+
+    fn marker_path(&self) -> &'static str {
+        match self.inner.os_flavor {
+            OsFlavor::Windows => "C:\\petri_marker",
+            _ => "/petri_marker",
+        }
+    }
+
     petri_vm_fn!(pub(crate) async fn resume(&mut self) -> anyhow::Result<()>);
     petri_vm_fn!(pub(crate) async fn verify_save_restore(&mut self) -> anyhow::Result<()>);
     petri_vm_fn!(pub(crate) async fn launch_linux_direct_pipette(&mut self) -> anyhow::Result<()>);
+    petri_vm_fn!(pub(crate) async fn launch_freebsd_pipette(&mut self) -> anyhow::Result<()>);
+
+    /// Copies the file at `host_path` to `guest_path` in the guest using
+    /// `agent`, verifying the transferred contents.
+    pub async fn copy_to_guest(
+        &mut self,
+        agent: &PipetteClient,
+        host_path: impl AsRef<Path>,
+        guest_path: impl AsRef<str>,
+    ) -> anyhow::Result<()> {
+        let host_path = host_path.as_ref();
+        let guest_path = guest_path.as_ref();
+        tracing::info!(host_path = %host_path.display(), guest_path, "copying file to guest");
+        self.wait_for_halt_or(agent.copy_to_guest(host_path, guest_path, log_transfer_progress()))
+            .await
+    }
+
+    /// Copies the file at `guest_path` in the guest to `host_path` using
+    /// `agent`, verifying the transferred contents.
+    pub async fn copy_from_guest(
+        &mut self,
+        agent: &PipetteClient,
+        guest_path: impl AsRef<str>,
+        host_path: impl AsRef<Path>,
+    ) -> anyhow::Result<()> {
+        let guest_path = guest_path.as_ref();
+        let host_path = host_path.as_ref();
+        tracing::info!(guest_path, host_path = %host_path.display(), "copying file from guest");
+        self.wait_for_halt_or(agent.copy_from_guest(guest_path, host_path, log_transfer_progress()))
+            .await
+    }
+
+    /// Copies the crash dumps left in the guest by previous crashes into
+    /// `host_dir` using `agent`, returning the paths of the copies.
+    ///
+    /// Each copy is named after the dump's full guest path, so that dumps with
+    /// the same file name in different directories do not collide.
+    pub async fn collect_crash_dumps(
+        &mut self,
+        agent: &PipetteClient,
+        host_dir: impl AsRef<Path>,
+    ) -> anyhow::Result<Vec<PathBuf>> {
+        let host_dir = host_dir.as_ref();
+        let dumps = self.wait_for_halt_or(agent.list_crash_dumps()).await?;
+        let mut paths = Vec::new();
+        for dump in dumps {
+            let name = dump
+                .path
+                .trim_start_matches(['/', '\\'])
+                .replace(['/', '\\', ':'], "_");
+            let host_path = host_dir.join(name);
+            tracing::info!(
+                guest_path = dump.path,
+                size = dump.size,
+                "collecting crash dump"
+            );
+            self.copy_from_guest(agent, &dump.path, &host_path).await?;
+            paths.push(host_path);
+        }
+        Ok(paths)
+    }
+
+    /// Forwards connections to a local port on the host to `guest_address` in
+    /// the guest using `agent`, so that tests can reach services in the guest
+    /// without configuring guest networking.
+    ///
+    /// The host port is returned by [`TcpForward::listen_address`].
+    /// Forwarding stops when the returned object is dropped.
+    pub fn forward_tcp_to_guest(
+        &self,
+        agent: &PipetteClient,
+        guest_address: SocketAddr,
+    ) -> anyhow::Result<TcpForward> {
+        agent.forward_tcp_to_guest(
+            &self.inner.resources.driver,
+            (std::net::Ipv4Addr::LOCALHOST, 0).into(),
+            guest_address,
+        )
+    }
+
+    /// Forwards connections to `guest_address` in the guest to `host_address`
+    /// on the host using `agent`.
+    ///
+    /// If the port in `guest_address` is 0, the guest port is returned by
+    /// [`TcpForward::listen_address`]. Forwarding stops when the returned
+    /// object is dropped.
+    pub async fn forward_tcp_from_guest(
+        &mut self,
+        agent: &PipetteClient,
+        guest_address: SocketAddr,
+        host_address: SocketAddr,
+    ) -> anyhow::Result<TcpForward> {
+        let driver = self.inner.resources.driver.clone();
+        self.wait_for_halt_or(agent.forward_tcp_from_guest(&driver, guest_address, host_address))
+            .await
+    }
+
+    /// Captures the current contents of the VM's framebuffer.
+    ///
+    /// Fails if the VM has no video device, as with Linux direct boot or
+    /// isolated VMs.
+    pub fn screenshot(&self) -> anyhow::Result<Screenshot> {
+        let view = self
+            .inner
+            .framebuffer_view
+            .as_ref()
+            .context("the vm has no framebuffer")?;
+        Ok(Screenshot::capture(&mut view.lock()))
+    }
+
+    /// Captures the VM's framebuffer and saves it as `<name>.png` in the
+    /// test's output directory, attached to the test results.
+    pub fn save_screenshot(&self, name: &str) -> anyhow::Result<Screenshot> {
+        let screenshot = self.screenshot()?;
+        let path = self.inner.resources.output_dir.join(format!("{name}.png"));
+        screenshot.save(&path)?;
+        trace_attachment(path);
+        Ok(screenshot)
+    }
+
+    /// Captures the VM's framebuffer as in [`Self::save_screenshot`], and
+    /// fails if its [difference](Screenshot::difference) from the image at
+    /// `reference` is more than `tolerance`.
+    ///
+    /// On a mismatch, an image highlighting the differing pixels is saved as
+    /// `<name>_diff.png`.
+    pub fn assert_screenshot(
+        &self,
+        name: &str,
+        reference: impl AsRef<Path>,
+        tolerance: f64,
+    ) -> anyhow::Result<()> {
+        let screenshot = self.save_screenshot(name)?;
+        let reference = Screenshot::load(reference)?;
+        let difference = screenshot.difference(&reference)?;
+        if difference > tolerance {
+            let path = self
+                .inner
+                .resources
+                .output_dir
+                .join(format!("{name}_diff.png"));
+            screenshot.diff_image(&reference).save(&path)?;
+            trace_attachment(path);
+            anyhow::bail!(
+                "screenshot {name} differs from the reference by {difference:.4}, more than {tolerance}"
+            );
+        }
+        tracing::info!(name, difference, "screenshot matches the reference");
+        Ok(())
+    }
 
     /// Wrap the provided future in a race with the worker process's halt
     /// notification channel. This is useful for preventing a future from
@@ -268,6 +569,7 @@ async fn wait_for_successful_boot_event(&mut self) -> anyhow::Result<()> {
                 event == expected_event,
                 "Did not receive expected successful boot event"
             );
+            self.resources.boot_timer.check_budget()?;
         } else {
             tracing::warn!("Configured firmware does not emit a boot event, skipping");
         }
@@ -318,7 +620,7 @@ async fn send_enlightened_shutdown(&mut self, kind: ShutdownKind) -> anyhow::Res
 
     async fn restart_openhcl(
         &self,
-        new_openhcl: ArtifactHandle<impl petri_artifacts_common::tags::IsOpenhclIgvm>,
+        new_openhcl: ArtifactHandle<impl IsOpenhclIgvm>,
     ) -> anyhow::Result<()> {
         let ged_send = self
             .resources
@@ -355,12 +657,17 @@ async fn modify_vtl2_settings(
 
     async fn reset(&mut self) -> anyhow::Result<()> {
         tracing::info!("Resetting VM");
+        self.resources.boot_timer.start();
         self.worker.reset().await?;
         // On linux direct pipette won't auto start, start it over serial
         if let Some(agent) = self.resources.linux_direct_serial_agent.as_mut() {
             agent.reset();
             self.launch_linux_direct_pipette().await?;
         }
+        // Nor will it on FreeBSD, start it over the serial console
+        if self.resources.freebsd_serial_console.is_some() {
+            self.launch_freebsd_pipette().await?;
+        }
         Ok(())
     }
 
@@ -369,12 +676,15 @@ async fn test_inspect_openhcl(&self) -> anyhow::Result<()> {
     }
 
     async fn wait_for_agent(&mut self) -> anyhow::Result<PipetteClient> {
-        Self::wait_for_agent_core(
+        let client = Self::wait_for_agent_core(
             &self.resources.driver,
             &mut self.resources.pipette_listener,
             &self.resources.output_dir,
         )
-        .await
+        .await?;
+        self.resources.boot_timer.record(BootStage::Agent);
+        self.resources.boot_timer.check_budget()?;
+        Ok(client)
     }
 
     async fn wait_for_vtl2_ready(&mut self) -> anyhow::Result<()> {
@@ -421,6 +731,17 @@ async fn resume(&self) -> anyhow::Result<()> {
         Ok(())
     }
 
+    async fn save(&self) -> anyhow::Result<PetriVmSavedState> {
+        self.worker.pause().await?;
+        Ok(PetriVmSavedState(self.worker.save().await?))
+    }
+
+    async fn restore(&self, saved_state: PetriVmSavedState) -> anyhow::Result<()> {
+        self.worker.restore(saved_state.0).await?;
+        self.worker.resume().await?;
+        Ok(())
+    }
+
     async fn verify_save_restore(&self) -> anyhow::Result<()> {
         for i in 0..2 {
             let result = self.worker.pulse_save_restore().await?;
@@ -451,6 +772,16 @@ async fn launch_linux_direct_pipette(&mut self) -> anyhow::Result<()> {
         Ok(())
     }
 
+    async fn launch_freebsd_pipette(&mut self) -> anyhow::Result<()> {
+        // Start pipette by logging in on the serial console.
+        self.resources
+            .freebsd_serial_console
+            .as_mut()
+            .unwrap()
+            .launch_pipette()
+            .await
+    }
+
     async fn launch_vtl2_pipette(&mut self) -> anyhow::Result<()> {
         // Start pipette through DiagClient
         let res = self
@@ -479,3 +810,21 @@ fn openhcl_diag(&self) -> anyhow::Result<&OpenHclDiagHandler> {
         }
     }
 }
+
+/// Returns a file transfer progress callback that logs every 10%.
+fn log_transfer_progress() -> impl FnMut(TransferProgress) {
+    let mut next_percent = 10;
+    move |progress| {
+        let percent = (progress.transferred * 100)
+            .checked_div(progress.total)
+            .unwrap_or(100);
+        if percent >= next_percent {
+            tracing::info!(
+                transferred = progress.transferred,
+                total = progress.total,
+                "{percent}% transferred"
+            );
+            next_percent = (percent / 10 + 1) * 10;
+        }
+    }
+}
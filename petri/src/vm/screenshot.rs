@@ -0,0 +1,125 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Screenshots of the VM's framebuffer, and comparisons against reference
+//! images.
+
+use anyhow::Context;
+use framebuffer::View;
+use image::RgbaImage;
+use std::path::Path;
+
+/// A screenshot of the VM's framebuffer.
+#[derive(Debug, Clone)]
+pub struct Screenshot(RgbaImage);
+
+impl Screenshot {
+    /// Captures the current contents of the framebuffer.
+    pub(crate) fn capture(view: &mut View) -> Self {
+        // Our framebuffer uses 4 bytes per pixel, approximating an BGRA image,
+        // however it only actually contains BGR data. The fourth byte is
+        // effectively noise. We can set the 'alpha' value to 0xFF to make the
+        // image opaque, while we also convert it to RGBA.
+        const BYTES_PER_PIXEL: usize = 4;
+        let (width, height) = view.resolution();
+        let line_len = width as usize * BYTES_PER_PIXEL;
+
+        let mut data = vec![0; line_len * height as usize];
+        for (i, line) in (0..height).zip(data.chunks_exact_mut(line_len)) {
+            view.read_line(i, line);
+            for pixel in line.chunks_exact_mut(BYTES_PER_PIXEL) {
+                pixel.swap(0, 2);
+                pixel[3] = 0xFF;
+            }
+        }
+
+        Self(RgbaImage::from_raw(width.into(), height.into(), data).expect("buffer is sized"))
+    }
+
+    /// Loads a screenshot from an image file, such as a reference image.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let image =
+            image::open(path).with_context(|| format!("failed to load {}", path.display()))?;
+        Ok(Self(image.into_rgba8()))
+    }
+
+    /// Saves the screenshot as a PNG file.
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        self.0
+            .save_with_format(path, image::ImageFormat::Png)
+            .with_context(|| format!("failed to save {}", path.display()))
+    }
+
+    /// The width of the screenshot, in pixels.
+    pub fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    /// The height of the screenshot, in pixels.
+    pub fn height(&self) -> u32 {
+        self.0.height()
+    }
+
+    /// The pixels of the screenshot.
+    pub fn image(&self) -> &RgbaImage {
+        &self.0
+    }
+
+    /// Returns whether every pixel is the same color, as when nothing has
+    /// been drawn yet.
+    pub fn is_blank(&self) -> bool {
+        let mut pixels = self.0.pixels();
+        let first = pixels.next();
+        pixels.all(|p| Some(p) == first)
+    }
+
+    /// Computes how different this screenshot is from `other`, as the mean
+    /// absolute difference of the red, green, and blue channels over all
+    /// pixels, scaled to between 0.0 (identical) and 1.0 (black versus
+    /// white).
+    ///
+    /// Fails if the screenshots have different resolutions.
+    pub fn difference(&self, other: &Screenshot) -> anyhow::Result<f64> {
+        if self.0.dimensions() != other.0.dimensions() {
+            anyhow::bail!(
+                "resolution mismatch: {}x{} versus {}x{}",
+                self.width(),
+                self.height(),
+                other.width(),
+                other.height()
+            );
+        }
+        let total: u64 = self
+            .0
+            .pixels()
+            .zip(other.0.pixels())
+            .map(|(a, b)| (0..3).map(|c| u64::from(a[c].abs_diff(b[c]))).sum::<u64>())
+            .sum();
+        let channels = u64::from(self.width()) * u64::from(self.height()) * 3;
+        if channels == 0 {
+            return Ok(0.0);
+        }
+        Ok(total as f64 / (channels as f64 * 255.0))
+    }
+
+    /// Returns an image highlighting the pixels that differ from `other`, in
+    /// red over a dimmed copy of this screenshot. Used to diagnose failed
+    /// comparisons.
+    pub fn diff_image(&self, other: &Screenshot) -> Screenshot {
+        let mut diff = self.0.clone();
+        for (x, y, pixel) in diff.enumerate_pixels_mut() {
+            let differs = other
+                .0
+                .get_pixel_checked(x, y)
+                .map_or(true, |o| o.0[..3] != pixel.0[..3]);
+            *pixel = if differs {
+                image::Rgba([0xFF, 0, 0, 0xFF])
+            } else {
+                image::Rgba([pixel[0] / 4, pixel[1] / 4, pixel[2] / 4, 0xFF])
+            };
+        }
+        Screenshot(diff)
+    }
+}
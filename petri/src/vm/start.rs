@@ -9,14 +9,14 @@
 use crate::Firmware;
 use crate::PetriVm;
 use crate::PetriVmConfig;
+use crate::Screenshot;
 use anyhow::Context;
 use diag_client::DiagClient;
 use disk_backend_resources::FileDiskHandle;
-use framebuffer::FramebufferAccess;
+use framebuffer::View;
 use fs_err::File;
 use guid::Guid;
 use hvlite_defs::config::DeviceVtl;
-use image::ColorType;
 use mesh_process::Mesh;
 use mesh_process::ProcessConfig;
 use mesh_worker::WorkerHost;
@@ -24,6 +24,7 @@
 use pal_async::task::Task;
 use pal_async::timer::PolledTimer;
 use pal_async::DefaultDriver;
+use parking_lot::Mutex;
 use petri_artifacts_common::tags::MachineArch;
 use petri_artifacts_common::tags::OsFlavor;
 use petri_artifacts_core::TestArtifacts;
@@ -82,14 +83,21 @@ async fn run_core(self) -> anyhow::Result<PetriVm> {
             .context("failed to launch vm worker")?;
 
         let worker = Arc::new(worker);
+        let framebuffer_view = framebuffer_access
+            .map(|fba| fba.view().map(|view| Arc::new(Mutex::new(view))))
+            .transpose()
+            .context("failed to map framebuffer")?;
         let watchdog_tasks = Self::start_watchdog_tasks(
-            framebuffer_access,
+            framebuffer_view.clone(),
             worker.clone(),
             vtl2_vsock_path,
             &resources.output_dir,
             &resources.driver,
         )?;
 
+        // Time the boot from when the VM is resumed below.
+        resources.boot_timer.start();
+
         let mut vm = PetriVm::new(
             super::runtime::PetriVmInner {
                 resources,
@@ -97,6 +105,8 @@ async fn run_core(self) -> anyhow::Result<PetriVm> {
                 worker,
                 watchdog_tasks,
                 quirks: firmware.quirks(),
+                os_flavor: firmware.os_flavor(),
+                framebuffer_view,
             },
             halt_notif,
         );
@@ -166,7 +176,7 @@ pub async fn run_with_lazy_pipette(mut self) -> anyhow::Result<PetriVm> {
                     device: SimpleScsiDiskHandle {
                         read_only: true,
                         parameters: Default::default(),
-                        disk: FileDiskHandle(agent_disk).into_resource(),
+                        disk: FileDiskHandle::new(agent_disk).into_resource(),
                     }
                     .into_resource(),
                 }],
@@ -217,7 +227,7 @@ pub async fn run_with_lazy_pipette(mut self) -> anyhow::Result<PetriVm> {
                         device: SimpleScsiDiskHandle {
                             read_only: true,
                             parameters: Default::default(),
-                            disk: FileDiskHandle(uh_agent_disk).into_resource(),
+                            disk: FileDiskHandle::new(uh_agent_disk).into_resource(),
                         }
                         .into_resource(),
                     }],
@@ -228,19 +238,22 @@ pub async fn run_with_lazy_pipette(mut self) -> anyhow::Result<PetriVm> {
         }
 
         let is_linux_direct = self.firmware.is_linux_direct();
+        let is_freebsd = matches!(self.firmware.os_flavor(), OsFlavor::FreeBsd);
 
         // Start the VM.
         let mut vm = self.run_core().await?;
 
         if is_linux_direct {
             vm.launch_linux_direct_pipette().await?;
+        } else if is_freebsd {
+            vm.launch_freebsd_pipette().await?;
         }
 
         Ok(vm)
     }
 
     fn start_watchdog_tasks(
-        framebuffer_access: Option<FramebufferAccess>,
+        framebuffer_view: Option<Arc<Mutex<View>>>,
         worker: Arc<Worker>,
         vtl2_vsock_path: Option<PathBuf>,
         output_dir: &Path,
@@ -268,8 +281,7 @@ fn start_watchdog_tasks(
             trace_attachment(inspect_log_path);
         }));
 
-        if let Some(fba) = framebuffer_access {
-            let mut view = fba.view()?;
+        if let Some(view) = framebuffer_view {
             let mut timer = PolledTimer::new(driver);
             let screenshot_output_dir = output_dir.to_owned();
             tasks.push(driver.spawn("petri-watchdog-screenshot", async move {
@@ -279,37 +291,11 @@ fn start_watchdog_tasks(
                     count += 1;
                     tracing::info!(count, "Taking screenshot.");
 
-                    // Our framebuffer uses 4 bytes per pixel, approximating an
-                    // BGRA image, however it only actually contains BGR data.
-                    // The fourth byte is effectively noise. We can set the 'alpha'
-                    // value to 0xFF to make the image opaque, while we also
-                    // convert it to RGB to output it as a PNG.
-                    const BYTES_PER_PIXEL: usize = 4;
-                    let (width, height) = view.resolution();
-                    let (widthsize, heightsize) = (width as usize, height as usize);
-                    let len = widthsize * heightsize * BYTES_PER_PIXEL;
-
-                    let mut image = vec![0; len];
-                    for (i, line) in
-                        (0..height).zip(image.chunks_exact_mut(widthsize * BYTES_PER_PIXEL))
-                    {
-                        view.read_line(i, line);
-                        for pixel in line.chunks_exact_mut(BYTES_PER_PIXEL) {
-                            pixel.swap(0, 2);
-                            pixel[3] = 0xFF;
-                        }
-                    }
-
+                    let screenshot = Screenshot::capture(&mut view.lock());
                     let screenshot_path =
                         screenshot_output_dir.join(format!("screenshot_{}.png", count));
 
-                    if let Err(e) = image::save_buffer(
-                        &screenshot_path,
-                        &image,
-                        width.into(),
-                        height.into(),
-                        ColorType::Rgba8,
-                    ) {
+                    if let Err(e) = screenshot.save(&screenshot_path) {
                         tracing::error!(?e, "Failed to save screenshot");
                     } else {
                         tracing::info!(count, "Screenshot saved.");
@@ -0,0 +1,239 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Tests with multiple VMs, connected by shared networks and disks.
+
+use super::construct::current_test_name;
+use crate::Firmware;
+use crate::PetriVm;
+use crate::PetriVmConfig;
+use anyhow::Context;
+use disk_backend_resources::DiskWithSharedReservationsHandle;
+use disk_backend_resources::FileDiskHandle;
+use futures::stream::SelectAll;
+use futures::StreamExt;
+use net_backend_resources::channel::ChannelHandle;
+use net_backend_resources::mac_address::MacAddress;
+use pal_async::task::Spawn;
+use pal_async::task::Task;
+use pal_async::DefaultDriver;
+use parking_lot::Mutex;
+use petri_artifacts_common::artifacts as common_artifacts;
+use petri_artifacts_common::tags::MachineArch;
+use petri_artifacts_core::TestArtifacts;
+use pipette_client::PipetteClient;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::PathBuf;
+use vm_resource::kind::DiskHandleKind;
+use vm_resource::kind::NetEndpointHandleKind;
+use vm_resource::IntoResource;
+use vm_resource::Resource;
+
+/// A set of VMs in a single test, along with the networks and disks they
+/// share.
+///
+/// Each VM logs to a subdirectory of the test's output directory named after
+/// the VM.
+pub struct PetriTopology {
+    resolver: TestArtifacts,
+    driver: DefaultDriver,
+    output_dir: PathBuf,
+    vm_names: HashSet<String>,
+    network_count: u8,
+}
+
+impl PetriTopology {
+    /// Create a new, empty topology for the running test.
+    pub fn new(resolver: TestArtifacts, driver: &DefaultDriver) -> anyhow::Result<Self> {
+        let test_name = current_test_name()?;
+        let output_dir = resolver
+            .resolve(common_artifacts::TEST_LOG_DIRECTORY)
+            .join(test_name);
+        if output_dir.exists() {
+            std::fs::remove_dir_all(&output_dir)?;
+        }
+        std::fs::create_dir_all(&output_dir)?;
+        let petri_file = File::create(output_dir.join("petri.log"))?;
+        crate::tracing::try_init_tracing(petri_file)?;
+
+        Ok(Self {
+            resolver,
+            driver: driver.clone(),
+            output_dir,
+            vm_names: HashSet::new(),
+            network_count: 0,
+        })
+    }
+
+    /// Create the configuration for a VM named `name` in the topology.
+    ///
+    /// Use [`PetriVmConfig::with_network`] and
+    /// [`PetriVmConfig::with_shared_disk`] to connect it to the rest of the
+    /// topology.
+    pub fn vm(
+        &mut self,
+        name: &str,
+        firmware: Firmware,
+        arch: MachineArch,
+    ) -> anyhow::Result<PetriVmConfig> {
+        if !self.vm_names.insert(name.to_owned()) {
+            anyhow::bail!("a vm named {name} already exists");
+        }
+        PetriVmConfig::new_in(
+            firmware,
+            arch,
+            self.resolver.clone(),
+            &self.driver,
+            self.output_dir.join(name),
+            false,
+        )
+    }
+
+    /// Create a network that VMs in the topology can be connected to, as if
+    /// by an Ethernet switch.
+    ///
+    /// The network's ID is part of the MAC addresses it assigns, so a
+    /// topology can have at most 255 networks.
+    pub fn network(&mut self) -> anyhow::Result<PetriNetwork> {
+        self.network_count = self
+            .network_count
+            .checked_add(1)
+            .context("too many networks in the topology")?;
+        Ok(PetriNetwork::new(&self.driver, self.network_count))
+    }
+
+    /// Create a disk of `size` bytes that can be attached to multiple VMs in
+    /// the topology.
+    pub fn shared_disk(&self, size: u64) -> anyhow::Result<PetriSharedDisk> {
+        let file = tempfile::tempfile().context("failed to create temp file")?;
+        file.set_len(size).context("failed to set disk size")?;
+        let reservations =
+            tempfile::tempfile().context("failed to create reservation state file")?;
+        Ok(PetriSharedDisk { file, reservations })
+    }
+
+    /// Start all the VMs in `configs` concurrently, waiting for each one's
+    /// agent to connect.
+    pub async fn run_all(
+        configs: Vec<PetriVmConfig>,
+    ) -> anyhow::Result<Vec<(PetriVm, PipetteClient)>> {
+        futures::future::try_join_all(configs.into_iter().map(|config| config.run())).await
+    }
+}
+
+/// A network shared by VMs in a [`PetriTopology`].
+///
+/// The network forwards Ethernet frames between the connected VMs, learning
+/// which VM each MAC address belongs to. It does not provide DHCP or any other
+/// services, so guests must be configured with static addresses.
+pub struct PetriNetwork {
+    id: u8,
+    next_port: Mutex<u8>,
+    new_port: mesh::Sender<(mesh::Sender<Vec<u8>>, mesh::MpscReceiver<Vec<u8>>)>,
+    _task: Task<()>,
+}
+
+impl PetriNetwork {
+    fn new(driver: &DefaultDriver, id: u8) -> Self {
+        let (new_port, new_port_recv) = mesh::channel();
+        let task = driver.spawn(format!("petri-network-{id}"), switch(new_port_recv));
+        Self {
+            id,
+            next_port: Mutex::new(0),
+            new_port,
+            _task: task,
+        }
+    }
+
+    /// Create a new port on the network, returning the endpoint to give to a
+    /// NIC and a MAC address for it that is unique within the topology.
+    ///
+    /// The port number is part of the MAC address, so a network can have at
+    /// most 255 ports.
+    pub fn port(&self) -> anyhow::Result<(Resource<NetEndpointHandleKind>, MacAddress)> {
+        let port = {
+            let mut next_port = self.next_port.lock();
+            *next_port = next_port
+                .checked_add(1)
+                .context("too many ports on the network")?;
+            *next_port
+        };
+        let (send, switch_recv) = mesh::mpsc_channel();
+        let (switch_send, recv) = mesh::channel();
+        self.new_port.send((switch_send, switch_recv));
+        let endpoint = ChannelHandle { send, recv }.into_resource();
+        // Use a locally administered unicast address.
+        let mac_address = MacAddress::new([0x02, 0, 0, 0, self.id, port]);
+        Ok((endpoint, mac_address))
+    }
+}
+
+/// Forwards frames between the ports of a network.
+async fn switch(
+    mut new_port: mesh::Receiver<(mesh::Sender<Vec<u8>>, mesh::MpscReceiver<Vec<u8>>)>,
+) {
+    let mut ports = Vec::<mesh::Sender<Vec<u8>>>::new();
+    let mut recv = SelectAll::new();
+    let mut learned = HashMap::<[u8; 6], usize>::new();
+    loop {
+        futures::select! {
+            port = new_port.select_next_some() => {
+                let (send, port_recv) = port;
+                let index = ports.len();
+                ports.push(send);
+                recv.push(port_recv.map(move |frame| (index, frame)));
+            }
+            (index, frame) = recv.select_next_some() => {
+                let Some((dest, src)) = frame.get(..6).zip(frame.get(6..12)) else {
+                    continue;
+                };
+                let (dest, src): ([u8; 6], [u8; 6]) = (dest.try_into().unwrap(), src.try_into().unwrap());
+                learned.insert(src, index);
+                // Flood multicast, broadcast, and unknown destinations.
+                match learned.get(&dest) {
+                    Some(&port) if dest[0] & 1 == 0 => {
+                        if port != index {
+                            ports[port].send(frame);
+                        }
+                    }
+                    _ => {
+                        for (port, send) in ports.iter().enumerate() {
+                            if port != index {
+                                send.send(frame.clone());
+                            }
+                        }
+                    }
+                }
+            }
+            complete => break,
+        }
+    }
+}
+
+/// A disk shared by VMs in a [`PetriTopology`].
+///
+/// Writes from each VM go directly to the same backing file, so the guests
+/// must coordinate access, as with a cluster file system. SCSI persistent
+/// reservations are shared by all the VMs, so failover clustering can use the
+/// disk.
+pub struct PetriSharedDisk {
+    file: File,
+    reservations: File,
+}
+
+impl PetriSharedDisk {
+    /// Returns a handle to the disk to attach to a VM.
+    pub fn disk(&self) -> anyhow::Result<Resource<DiskHandleKind>> {
+        Ok(DiskWithSharedReservationsHandle {
+            disk: FileDiskHandle::new(self.file.try_clone().context("failed to clone disk file")?)
+                .into_resource(),
+            state_file: self
+                .reservations
+                .try_clone()
+                .context("failed to clone reservation state file")?,
+        }
+        .into_resource())
+    }
+}
@@ -6,6 +6,7 @@
 use hvlite_defs::rpc::VmRpc;
 use hvlite_defs::worker::VmWorkerParameters;
 use hvlite_defs::worker::VM_WORKER;
+use mesh::payload::message::ProtobufMessage;
 use mesh::rpc::RpcSend;
 use mesh_worker::WorkerHandle;
 use mesh_worker::WorkerHost;
@@ -46,6 +47,19 @@ pub(crate) async fn resume(&self) -> Result<bool, mesh::RecvError> {
         self.rpc.call(VmRpc::Resume, ()).await
     }
 
+    pub(crate) async fn pause(&self) -> Result<bool, mesh::RecvError> {
+        self.rpc.call(VmRpc::Pause, ()).await
+    }
+
+    pub(crate) async fn save(&self) -> anyhow::Result<ProtobufMessage> {
+        Ok(self.rpc.call(VmRpc::Save, ()).await??)
+    }
+
+    pub(crate) async fn restore(&self, saved_state: ProtobufMessage) -> anyhow::Result<()> {
+        self.rpc.call(VmRpc::Restore, saved_state).await??;
+        Ok(())
+    }
+
     pub(crate) async fn reset(&self) -> anyhow::Result<()> {
         self.rpc.call(VmRpc::Reset, ()).await??;
         Ok(())
@@ -0,0 +1,277 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A channel with credit-based flow control.
+//!
+//! Unlike [`channel`](crate::channel()), the sender of a bounded channel can
+//! only have a limited number of messages outstanding. The receiver returns
+//! credits to the sender as it consumes messages, so a slow receiver applies
+//! backpressure to the sender instead of queuing messages without bound.
+//!
+//! Both halves can be sent to other processes like any other channel. Any
+//! credits held by a half travel with it.
+
+use crate::channel;
+use crate::Receiver;
+use crate::RecvError;
+use crate::Sender;
+use crate::TryRecvError;
+use mesh_node::message::MeshField;
+use mesh_protobuf::Protobuf;
+use std::fmt::Debug;
+use std::future::poll_fn;
+use std::task::Context;
+use std::task::Poll;
+use thiserror::Error;
+
+/// Creates a new bounded channel, returning the sender and receiver.
+///
+/// At most `limit` messages can be sent but not yet received.
+///
+/// # Panics
+///
+/// Panics if `limit` is zero.
+pub fn bounded_channel<T: 'static + Send>(limit: u32) -> (BoundedSender<T>, BoundedReceiver<T>) {
+    assert!(limit > 0, "bounded channel limit must be non-zero");
+    let (data_send, data_recv) = channel();
+    let (credit_send, credit_recv) = channel();
+    (
+        BoundedSender {
+            data: data_send,
+            credits: credit_recv,
+            available: limit,
+            peer_closed: false,
+            stalled_sends: 0,
+        },
+        BoundedReceiver {
+            data: data_recv,
+            credits: credit_send,
+            limit,
+            consumed: 0,
+        },
+    )
+}
+
+/// An error returned by [`BoundedSender::try_send`].
+#[derive(Debug, Error)]
+pub enum TrySendError<T> {
+    /// The receiver has not yet consumed enough messages to allow this one to
+    /// be sent.
+    #[error("channel full")]
+    Full(T),
+}
+
+/// The sending half of a channel returned by [`bounded_channel`].
+#[derive(Protobuf)]
+#[mesh(
+    no_upcast,
+    bound = "T: MeshField",
+    resource = "mesh_node::resource::Resource"
+)]
+pub struct BoundedSender<T> {
+    data: Sender<T>,
+    credits: Receiver<u32>,
+    /// The number of messages that can be sent without waiting for credits.
+    available: u32,
+    /// The receiver has been dropped or has failed, so any message can be
+    /// sent (and dropped) without waiting.
+    peer_closed: bool,
+    stalled_sends: u64,
+}
+
+impl<T> Debug for BoundedSender<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoundedSender")
+            .field("data", &self.data)
+            .field("available", &self.available)
+            .field("peer_closed", &self.peer_closed)
+            .field("stalled_sends", &self.stalled_sends)
+            .finish()
+    }
+}
+
+impl<T: 'static + Send> BoundedSender<T> {
+    /// Sends a message if the channel has capacity for it, or returns it in
+    /// [`TrySendError::Full`] if not.
+    pub fn try_send(&mut self, message: T) -> Result<(), TrySendError<T>> {
+        self.collect_credits(None);
+        if self.available == 0 && !self.peer_closed {
+            return Err(TrySendError::Full(message));
+        }
+        self.send_now(message);
+        Ok(())
+    }
+
+    /// Sends a message, waiting for the channel to have capacity for it.
+    ///
+    /// As with [`Sender::send`], the message is dropped if the receiver has
+    /// been dropped.
+    pub async fn send(&mut self, message: T) {
+        self.collect_credits(None);
+        if self.available == 0 && !self.peer_closed {
+            self.stalled_sends += 1;
+            poll_fn(|cx| self.poll_ready(cx)).await;
+        }
+        self.send_now(message);
+    }
+
+    /// Polls for the channel to have capacity for another message.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.collect_credits(Some(cx));
+        if self.available > 0 || self.peer_closed {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    /// Returns the number of sends that had to wait for the receiver to
+    /// consume messages before proceeding.
+    pub fn stalled_sends(&self) -> u64 {
+        self.stalled_sends
+    }
+
+    fn send_now(&mut self, message: T) {
+        self.available = self.available.saturating_sub(1);
+        self.data.send(message);
+    }
+
+    /// Takes any credits returned by the receiver, registering `cx` for wakeup
+    /// if there are none.
+    fn collect_credits(&mut self, mut cx: Option<&mut Context<'_>>) {
+        while !self.peer_closed {
+            let r = match &mut cx {
+                Some(cx) => match self.credits.poll_recv(cx) {
+                    Poll::Ready(r) => r.map_err(|err| match err {
+                        RecvError::Closed => TryRecvError::Closed,
+                        RecvError::Error(err) => TryRecvError::Error(err),
+                    }),
+                    Poll::Pending => break,
+                },
+                None => self.credits.try_recv(),
+            };
+            match r {
+                Ok(n) => self.available = self.available.saturating_add(n),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Closed | TryRecvError::Error(_)) => self.peer_closed = true,
+            }
+        }
+    }
+}
+
+/// The receiving half of a channel returned by [`bounded_channel`].
+#[derive(Protobuf)]
+#[mesh(
+    no_upcast,
+    bound = "T: MeshField",
+    resource = "mesh_node::resource::Resource"
+)]
+pub struct BoundedReceiver<T> {
+    data: Receiver<T>,
+    credits: Sender<u32>,
+    limit: u32,
+    /// The number of messages received since credits were last returned.
+    consumed: u32,
+}
+
+impl<T> Debug for BoundedReceiver<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BoundedReceiver")
+            .field("data", &self.data)
+            .field("limit", &self.limit)
+            .field("consumed", &self.consumed)
+            .finish()
+    }
+}
+
+impl<T: 'static + Send> BoundedReceiver<T> {
+    /// Returns the maximum number of messages that can be queued.
+    pub fn limit(&self) -> u32 {
+        self.limit
+    }
+
+    /// Consumes and returns the next message, if there is one.
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        let message = self.data.try_recv()?;
+        self.consumed_one();
+        Ok(message)
+    }
+
+    /// Consumes and returns the next message, waiting until one is available.
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// Polls for the next message.
+    pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
+        let message = std::task::ready!(self.data.poll_recv(cx))?;
+        self.consumed_one();
+        Poll::Ready(Ok(message))
+    }
+
+    fn consumed_one(&mut self) {
+        self.consumed += 1;
+        // Batch credit updates to avoid a round trip per message.
+        if self.consumed >= self.limit.div_ceil(2) {
+            self.credits.send(self.consumed);
+            self.consumed = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bounded_channel;
+    use super::TrySendError;
+    use futures::FutureExt;
+    use pal_async::async_test;
+
+    #[test]
+    fn test_backpressure() {
+        let (mut send, mut recv) = bounded_channel::<u32>(4);
+        for i in 0..4 {
+            send.try_send(i).unwrap();
+        }
+        assert!(matches!(send.try_send(4), Err(TrySendError::Full(4))));
+        assert_eq!(recv.try_recv().unwrap(), 0);
+        // Credits are returned in batches of half the limit.
+        assert!(matches!(send.try_send(4), Err(TrySendError::Full(4))));
+        assert_eq!(recv.try_recv().unwrap(), 1);
+        send.try_send(4).unwrap();
+        send.try_send(5).unwrap();
+        assert!(matches!(send.try_send(6), Err(TrySendError::Full(6))));
+        for i in 2..6 {
+            assert_eq!(recv.try_recv().unwrap(), i);
+        }
+    }
+
+    #[async_test]
+    async fn test_stalled_send() {
+        let (mut send, mut recv) = bounded_channel::<u32>(1);
+        send.send(0).await;
+        let mut stalled = Box::pin(send.send(1));
+        assert!((&mut stalled).now_or_never().is_none());
+        assert_eq!(recv.recv().await.unwrap(), 0);
+        stalled.await;
+        assert_eq!(recv.recv().await.unwrap(), 1);
+        assert_eq!(send.stalled_sends(), 1);
+    }
+
+    #[test]
+    fn test_receiver_dropped() {
+        let (mut send, recv) = bounded_channel::<u32>(1);
+        send.try_send(0).unwrap();
+        drop(recv);
+        send.try_send(1).unwrap();
+    }
+
+    #[test]
+    fn test_send_across() {
+        let (send, mut recv) = crate::channel();
+        let (mut bsend, brecv) = bounded_channel::<u32>(2);
+        send.send(brecv);
+        let mut brecv = recv.try_recv().unwrap();
+        bsend.try_send(1).unwrap();
+        assert_eq!(brecv.try_recv().unwrap(), 1);
+    }
+}
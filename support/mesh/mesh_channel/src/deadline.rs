@@ -134,6 +134,38 @@ pub fn remove(&self, id: &mut DeadlineId) {
     }
 }
 
+/// A deadline registered with the global deadline set, which is removed when
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct DeadlineTimer {
+    deadline: Option<Deadline>,
+    id: DeadlineId,
+}
+
+impl DeadlineTimer {
+    /// Returns a new timer that expires at `deadline`, or never if `None`.
+    pub fn new(deadline: Option<Deadline>) -> Self {
+        Self {
+            deadline,
+            id: DeadlineId::default(),
+        }
+    }
+
+    /// Polls for the deadline to pass.
+    pub fn poll_expired(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        match self.deadline {
+            Some(deadline) => DeadlineSet::global().poll(cx, &mut self.id, deadline),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl Drop for DeadlineTimer {
+    fn drop(&mut self) {
+        DeadlineSet::global().remove(&mut self.id);
+    }
+}
+
 /// Runs the deadline thread.
 fn run(inner: &Mutex<Inner>) {
     loop {
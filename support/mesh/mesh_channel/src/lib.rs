@@ -2,6 +2,7 @@
 // Licensed under the MIT License.
 
 mod bidir;
+pub mod bounded;
 pub mod cancel;
 pub mod cell;
 mod deadline;
@@ -11,6 +12,8 @@
 pub mod rpc;
 
 use bidir::Channel;
+use cancel::Deadline;
+use deadline::DeadlineTimer;
 use mesh_node::local_node::Port;
 use mesh_node::message::MeshField;
 use mesh_protobuf::Downcast;
@@ -22,6 +25,7 @@
 use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Duration;
 use thiserror::Error;
 
 /// An error representing a failure of a channel.
@@ -87,6 +91,47 @@ pub enum RecvError {
     Error(#[from] ChannelError),
 }
 
+/// An error returned when receiving with a timeout or deadline.
+#[derive(Debug, Error)]
+pub enum RecvTimeoutError {
+    #[error("timed out")]
+    TimedOut,
+    #[error("channel closed")]
+    Closed,
+    #[error("channel failure")]
+    Error(#[from] ChannelError),
+}
+
+impl From<RecvError> for RecvTimeoutError {
+    fn from(err: RecvError) -> Self {
+        match err {
+            RecvError::Closed => Self::Closed,
+            RecvError::Error(err) => Self::Error(err),
+        }
+    }
+}
+
+/// Polls `poll_recv` until it completes or `deadline` passes.
+///
+/// Since a message is only consumed when `poll_recv` returns it, the returned
+/// future can be dropped at any time without losing a message.
+fn recv_until<'a, T>(
+    mut poll_recv: impl 'a + FnMut(&mut Context<'_>) -> Poll<Result<T, RecvError>> + Unpin,
+    deadline: Option<Deadline>,
+) -> impl Future<Output = Result<T, RecvTimeoutError>> + Unpin + 'a
+where
+    T: 'a,
+{
+    let mut timer = DeadlineTimer::new(deadline);
+    core::future::poll_fn(move |cx| {
+        if let Poll::Ready(r) = poll_recv(cx) {
+            return Poll::Ready(r.map_err(Into::into));
+        }
+        std::task::ready!(timer.poll_expired(cx));
+        Poll::Ready(Err(RecvTimeoutError::TimedOut))
+    })
+}
+
 /// The sending half of a channel returned by [`channel`].
 #[derive(Protobuf)]
 #[mesh(
@@ -300,6 +345,10 @@ pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
     ///
     /// Returns immediately when the channel is closed or failed.
     ///
+    /// The returned future is cancellation safe: if it is dropped before it
+    /// completes (e.g. because another branch of a `select` completed first),
+    /// no message is lost.
+    ///
     /// ```rust
     /// # use mesh_channel::*;
     /// # futures::executor::block_on(async {
@@ -316,6 +365,47 @@ pub fn recv(&mut self) -> impl Future<Output = Result<T, RecvError>> + Unpin + '
         core::future::poll_fn(|cx| self.poll_recv(cx))
     }
 
+    /// Consumes and returns the next message, waiting until one is available
+    /// or until `deadline` passes.
+    ///
+    /// Like [`Self::recv`], the returned future is cancellation safe.
+    ///
+    /// ```rust
+    /// # use mesh_channel::*;
+    /// # use mesh_channel::cancel::Deadline;
+    /// # use std::time::Duration;
+    /// # futures::executor::block_on(async {
+    /// let (send, mut recv) = channel::<u32>();
+    /// let deadline = Deadline::now() + Duration::from_millis(10);
+    /// assert!(matches!(
+    ///     recv.recv_deadline(deadline).await.unwrap_err(),
+    ///     RecvTimeoutError::TimedOut
+    /// ));
+    /// send.send(5);
+    /// assert_eq!(recv.recv_deadline(deadline).await.unwrap(), 5);
+    /// # });
+    /// ```
+    pub fn recv_deadline(
+        &mut self,
+        deadline: Deadline,
+    ) -> impl Future<Output = Result<T, RecvTimeoutError>> + Unpin + '_ {
+        recv_until(|cx| self.poll_recv(cx), Some(deadline))
+    }
+
+    /// Consumes and returns the next message, waiting until one is available
+    /// or until `timeout` has elapsed.
+    ///
+    /// Like [`Self::recv`], the returned future is cancellation safe.
+    pub fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<T, RecvTimeoutError>> + Unpin + '_ {
+        recv_until(
+            |cx| self.poll_recv(cx),
+            Deadline::now().checked_add(timeout),
+        )
+    }
+
     /// Polls for the next message.
     pub fn poll_recv(&mut self, cx: &mut Context<'_>) -> Poll<Result<T, RecvError>> {
         self.0.poll_recv(cx).map_ok(|x| x.0)
@@ -446,7 +536,9 @@ pub fn send(self, value: T) {
 
 /// The receiving half of a channel returned by [`oneshot`].
 ///
-/// A value is received by `poll`ing or `await`ing the channel.
+/// A value is received by `poll`ing or `await`ing the channel. To wait in a
+/// `select` without giving up the receiver, poll or await `&mut receiver`
+/// instead; the value is not lost if that future is dropped.
 #[derive(Protobuf)]
 #[mesh(
     no_upcast,
@@ -487,6 +579,33 @@ impl<T: MeshField, U: MeshField> Downcast<OneshotReceiver<U>> for OneshotReceive
 {
 }
 
+impl<T: 'static + Send> OneshotReceiver<T> {
+    /// Waits for the value until `deadline` passes.
+    ///
+    /// On timeout, the receiver can be polled or awaited again to continue
+    /// waiting for the value.
+    pub fn recv_deadline(
+        &mut self,
+        deadline: Deadline,
+    ) -> impl Future<Output = Result<T, RecvTimeoutError>> + Unpin + '_ {
+        recv_until(|cx| Pin::new(&mut *self).poll(cx), Some(deadline))
+    }
+
+    /// Waits for the value until `timeout` has elapsed.
+    ///
+    /// On timeout, the receiver can be polled or awaited again to continue
+    /// waiting for the value.
+    pub fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> impl Future<Output = Result<T, RecvTimeoutError>> + Unpin + '_ {
+        recv_until(
+            |cx| Pin::new(&mut *self).poll(cx),
+            Deadline::now().checked_add(timeout),
+        )
+    }
+}
+
 impl<T: MeshField> OneshotReceiver<T> {
     /// Upcasts this receiver to one that can receive values whose encoding is a
     /// superset of `T`'s.
@@ -658,6 +777,10 @@ pub fn sender(&mut self) -> MpscSender<T> {
     ///
     /// Returns immediately when the channel is closed or failed.
     ///
+    /// The returned future is cancellation safe: if it is dropped before it
+    /// completes (e.g. because another branch of a `select` completed first),
+    /// no message is lost.
+    ///
     /// ```rust
     /// # use mesh_channel::*;
     /// # futures::executor::block_on(async {
@@ -848,6 +971,38 @@ async fn test_oneshot() {
         ));
     }
 
+    #[async_test]
+    async fn test_recv_timeout() {
+        let (send, mut recv) = channel::<u32>();
+        assert!(matches!(
+            recv.recv_timeout(Duration::from_millis(10))
+                .await
+                .unwrap_err(),
+            RecvTimeoutError::TimedOut
+        ));
+        send.send(5);
+        assert_eq!(recv.recv_timeout(Duration::MAX).await.unwrap(), 5);
+        drop(send);
+        assert!(matches!(
+            recv.recv_timeout(Duration::MAX).await.unwrap_err(),
+            RecvTimeoutError::Closed
+        ));
+    }
+
+    #[async_test]
+    async fn test_oneshot_timeout() {
+        let (send, mut recv) = oneshot::<u32>();
+        assert!(matches!(
+            recv.recv_timeout(Duration::from_millis(10))
+                .await
+                .unwrap_err(),
+            RecvTimeoutError::TimedOut
+        ));
+        // The receiver is still usable after a timeout.
+        send.send(5);
+        assert_eq!(recv.await.unwrap(), 5);
+    }
+
     #[async_test]
     async fn test_mpsc() {
         let (send, mut recv) = mpsc_channel::<u32>();
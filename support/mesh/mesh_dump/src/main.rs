@@ -0,0 +1,102 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A tool to print captured mesh messages in a human-readable form.
+//!
+//! If the message type is specified with `--type`, fields are shown by name
+//! and values are interpreted using the type's description. Otherwise, fields
+//! are shown by number and values are interpreted on a best-effort basis from
+//! the wire format.
+
+use anyhow::Context;
+use clap::Parser;
+use mesh_protobuf::protofile::message_description;
+use mesh_protobuf::protofile::message_to_text;
+use mesh_protobuf::protofile::MessageDescription;
+use std::io::Read;
+use std::path::PathBuf;
+
+/// Prints an encoded mesh message in a human-readable form.
+#[derive(Parser)]
+struct Options {
+    /// The file containing the encoded message. Reads from stdin if not
+    /// specified.
+    input: Option<PathBuf>,
+    /// The input is hex-encoded. Whitespace is ignored.
+    #[clap(long)]
+    hex: bool,
+    /// The number of bytes to skip before the message, e.g. to skip a
+    /// transport header.
+    #[clap(long, default_value_t = 0)]
+    offset: usize,
+    /// The fully-qualified protobuf name of the message type, e.g.
+    /// `inspect.Node`. Use `--list-types` to list the known types.
+    #[clap(long = "type", value_name = "NAME")]
+    message_type: Option<String>,
+    /// List the known message types and exit.
+    #[clap(long, conflicts_with_all = ["input", "message_type"])]
+    list_types: bool,
+}
+
+/// Returns the descriptions of the message types that can be specified with
+/// `--type`: the inspect protocol types, plus the saved state types linked into
+/// this tool.
+fn known_types() -> impl Iterator<Item = MessageDescription<'static>> {
+    [
+        message_description::<inspect::Node>(),
+        message_description::<inspect::Entry>(),
+        message_description::<inspect::Value>(),
+        message_description::<inspect::Error>(),
+    ]
+    .into_iter()
+    .chain(vmcore::save_restore::saved_state_roots().copied())
+}
+
+/// Returns the fully-qualified name of a message type.
+fn type_name(description: &MessageDescription<'_>) -> String {
+    let url = description.type_url().to_string();
+    url.strip_prefix("type.googleapis.com/")
+        .unwrap_or(&url)
+        .to_owned()
+}
+
+fn main() -> anyhow::Result<()> {
+    let options = Options::parse();
+    if options.list_types {
+        let mut names = known_types().map(|d| type_name(&d)).collect::<Vec<_>>();
+        names.sort();
+        for name in names {
+            println!("{name}");
+        }
+        return Ok(());
+    }
+    let description = options
+        .message_type
+        .as_deref()
+        .map(|name| {
+            known_types()
+                .find(|d| type_name(d) == name)
+                .with_context(|| format!("unknown message type {name}"))
+        })
+        .transpose()?;
+    let mut data = match &options.input {
+        Some(path) => fs_err::read(path)?,
+        None => {
+            let mut data = Vec::new();
+            std::io::stdin()
+                .read_to_end(&mut data)
+                .context("failed to read stdin")?;
+            data
+        }
+    };
+    if options.hex {
+        let text = String::from_utf8(data).context("hex input is not valid utf-8")?;
+        let text: String = text.split_whitespace().collect();
+        data = hex::decode(text).context("invalid hex input")?;
+    }
+    let data = data
+        .get(options.offset..)
+        .context("offset is past the end of the input")?;
+    println!("{}", message_to_text(data, description));
+    Ok(())
+}
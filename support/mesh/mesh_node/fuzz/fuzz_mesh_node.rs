@@ -0,0 +1,340 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Fuzzer for the mesh node wire protocol.
+//!
+//! Events from remote nodes are parsed and applied to the local node's port
+//! state machine. The remote node may be a less-trusted process, so feed the
+//! node a mix of structured and raw events while ports are concurrently sent,
+//! closed, and reconnected.
+
+#![cfg_attr(all(target_os = "linux", target_env = "gnu"), no_main)]
+
+use arbitrary::Arbitrary;
+use mesh_node::common::Address;
+use mesh_node::common::NodeId;
+use mesh_node::common::PortId;
+use mesh_node::common::Uuid;
+use mesh_node::local_node::Connect;
+use mesh_node::local_node::HandlePortEvent;
+use mesh_node::local_node::LocalNode;
+use mesh_node::local_node::NodeError;
+use mesh_node::local_node::OutgoingEvent;
+use mesh_node::local_node::Port;
+use mesh_node::local_node::PortControl;
+use mesh_node::local_node::PortWithHandler;
+use mesh_node::local_node::RemoteNodeHandle;
+use mesh_node::local_node::SendEvent;
+use mesh_node::message::Message;
+use mesh_node::resource::SerializedMessage;
+use xtask_fuzz::fuzz_target;
+
+/// The number of ports initially peered with ports on the remote node.
+const PORT_COUNT: u8 = 4;
+
+const LOCAL_NODE: NodeId = NodeId(Uuid([0x11; 16]));
+const REMOTE_NODE: NodeId = NodeId(Uuid([0x22; 16]));
+
+fn local_port(i: u8) -> PortId {
+    PortId(Uuid([0x30 + i % PORT_COUNT; 16]))
+}
+
+fn remote_port(i: u8) -> PortId {
+    PortId(Uuid([0x40 + i % PORT_COUNT; 16]))
+}
+
+// Wire event types. These must match the protocol definitions in mesh_node.
+const MESSAGE: u8 = 1;
+const CLOSE_PORT: u8 = 2;
+const CHANGE_PEER: u8 = 3;
+const ACKNOWLEDGE_CHANGE_PEER: u8 = 4;
+const ACKNOWLEDGE_PORT: u8 = 5;
+const FAIL_PORT: u8 = 6;
+const COMPRESSED_MESSAGE: u8 = 7;
+const RESYNC_PORT: u8 = 8;
+
+#[derive(Debug, Arbitrary)]
+enum NodeSel {
+    Zero,
+    Local,
+    Remote,
+    Other([u8; 16]),
+}
+
+impl NodeSel {
+    fn uuid(&self) -> [u8; 16] {
+        match self {
+            NodeSel::Zero => [0; 16],
+            NodeSel::Local => LOCAL_NODE.0 .0,
+            NodeSel::Remote => REMOTE_NODE.0 .0,
+            NodeSel::Other(id) => *id,
+        }
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+enum PortSel {
+    Zero,
+    Local(u8),
+    Remote(u8),
+    Other([u8; 16]),
+}
+
+impl PortSel {
+    fn uuid(&self) -> [u8; 16] {
+        match self {
+            PortSel::Zero => [0; 16],
+            PortSel::Local(i) => local_port(*i).0 .0,
+            PortSel::Remote(i) => remote_port(*i).0 .0,
+            PortSel::Other(id) => *id,
+        }
+    }
+}
+
+/// A resource (port or OS resource) attached to an event, in the wire format
+/// of `ResourceData`.
+#[derive(Debug, Arbitrary)]
+struct ResourceInput {
+    id: PortSel,
+    next_local_seq: u64,
+    old_node: NodeSel,
+    old_port: PortSel,
+    peer_node: NodeSel,
+    peer_port: PortSel,
+}
+
+impl ResourceInput {
+    fn write(&self, buf: &mut Vec<u8>) {
+        buf.extend(self.id.uuid());
+        buf.extend(self.next_local_seq.to_le_bytes());
+        buf.extend(0u64.to_le_bytes());
+        buf.extend(self.old_node.uuid());
+        buf.extend(self.old_port.uuid());
+        buf.extend(self.peer_node.uuid());
+        buf.extend(self.peer_port.uuid());
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+enum EventData {
+    Message(Vec<u8>),
+    ClosePort,
+    ChangePeer {
+        node: NodeSel,
+        port: PortSel,
+        seq_delta: u64,
+    },
+    AcknowledgeChangePeer,
+    AcknowledgePort,
+    FailPort(NodeSel),
+    CompressedMessage {
+        uncompressed_size: u64,
+        data: Vec<u8>,
+    },
+    ResyncPort(PortSel),
+    Raw {
+        event_type: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl EventData {
+    /// Writes the event's data, returning the event type.
+    fn write(&self, buf: &mut Vec<u8>) -> u8 {
+        match self {
+            EventData::Message(data) => {
+                buf.extend(data);
+                MESSAGE
+            }
+            EventData::ClosePort => CLOSE_PORT,
+            EventData::ChangePeer {
+                node,
+                port,
+                seq_delta,
+            } => {
+                buf.extend(node.uuid());
+                buf.extend(port.uuid());
+                buf.extend(seq_delta.to_le_bytes());
+                buf.extend(0u64.to_le_bytes());
+                CHANGE_PEER
+            }
+            EventData::AcknowledgeChangePeer => ACKNOWLEDGE_CHANGE_PEER,
+            EventData::AcknowledgePort => ACKNOWLEDGE_PORT,
+            EventData::FailPort(node) => {
+                buf.extend(node.uuid());
+                FAIL_PORT
+            }
+            EventData::CompressedMessage {
+                uncompressed_size,
+                data,
+            } => {
+                buf.extend(uncompressed_size.to_le_bytes());
+                buf.extend(data);
+                COMPRESSED_MESSAGE
+            }
+            EventData::ResyncPort(port) => {
+                buf.extend(port.uuid());
+                RESYNC_PORT
+            }
+            EventData::Raw { event_type, data } => {
+                buf.extend(data);
+                *event_type
+            }
+        }
+    }
+}
+
+/// An event in the wire format of `Event`, followed by its resources and data.
+#[derive(Debug, Arbitrary)]
+struct EventInput {
+    port: PortSel,
+    flags: u8,
+    seq: u64,
+    resources: Vec<ResourceInput>,
+    data: EventData,
+}
+
+impl EventInput {
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        let event_type = self.data.write(&mut data);
+        let mut buf = Vec::new();
+        buf.extend(self.port.uuid());
+        buf.push(event_type);
+        buf.push(self.flags);
+        buf.extend([0; 6]);
+        buf.extend(self.seq.to_le_bytes());
+        buf.extend((self.resources.len() as u32).to_le_bytes());
+        buf.extend((data.len() as u32).to_le_bytes());
+        for resource in &self.resources {
+            resource.write(&mut buf);
+        }
+        buf.extend(data);
+        buf
+    }
+}
+
+#[derive(Debug, Arbitrary)]
+enum Action {
+    /// Deliver a well-formed event from the remote node.
+    Event(EventInput),
+    /// Deliver arbitrary bytes as an event from the remote node.
+    RawEvent(Vec<u8>),
+    /// Send a message from a local port.
+    Send { port: u8, data: Vec<u8> },
+    /// Send a new port from a local port.
+    SendPort { port: u8 },
+    /// Close a local port.
+    Close { port: u8 },
+    /// Lose the connection to the remote node.
+    ConnectionLost,
+    /// Re-establish the connection to the remote node.
+    Reconnect,
+    /// Fail the remote node.
+    Fail,
+}
+
+#[derive(Debug, Arbitrary)]
+struct FuzzInput {
+    reconnect: bool,
+    actions: Vec<Action>,
+}
+
+struct NullConnect;
+
+impl Connect for NullConnect {
+    fn connect(&self, _node_id: NodeId, _handle: RemoteNodeHandle) {}
+}
+
+/// A connection that serializes and discards outgoing events.
+struct Sink;
+
+impl SendEvent for Sink {
+    fn event(&self, event: OutgoingEvent<'_>) {
+        let mut buf = Vec::with_capacity(event.len());
+        let mut os_resources = Vec::new();
+        event.write_to(&mut buf, &mut os_resources);
+    }
+}
+
+/// A port handler that decodes and discards incoming messages.
+struct Handler;
+
+impl HandlePortEvent for Handler {
+    fn message(&mut self, _control: &mut PortControl<'_>, message: Message) {
+        let _ = message.parse::<(Vec<u8>, Vec<Port>)>();
+    }
+
+    fn close(&mut self, _control: &mut PortControl<'_>) {}
+
+    fn fail(&mut self, _control: &mut PortControl<'_>, _err: NodeError) {}
+
+    fn drain(&mut self) -> Vec<Message> {
+        Vec::new()
+    }
+}
+
+fn do_fuzz(input: FuzzInput) {
+    let node = LocalNode::with_id(LOCAL_NODE, Box::new(NullConnect));
+    let remote = node.add_remote(REMOTE_NODE);
+    if input.reconnect {
+        remote.enable_reconnect();
+    }
+    remote.connect(Sink);
+
+    let mut ports: Vec<Option<PortWithHandler<Handler>>> = (0..PORT_COUNT)
+        .map(|i| {
+            let port = node.add_port(local_port(i), Address::new(REMOTE_NODE, remote_port(i)));
+            Some(port.set_handler(Handler))
+        })
+        .collect();
+
+    // Keep the local halves of sent ports alive so that the remote halves stay
+    // peered.
+    let mut sent_ports = Vec::new();
+
+    for action in input.actions {
+        xtask_fuzz::fuzz_eprintln!("{:?}", action);
+        match action {
+            Action::Event(event) => {
+                node.event(&REMOTE_NODE, &event.serialize(), &mut Vec::new());
+            }
+            Action::RawEvent(data) => {
+                node.event(&REMOTE_NODE, &data, &mut Vec::new());
+            }
+            Action::Send { port, data } => {
+                if let Some(port) = &ports[(port % PORT_COUNT) as usize] {
+                    port.send(Message::serialized(SerializedMessage {
+                        data,
+                        resources: Vec::new(),
+                    }));
+                }
+            }
+            Action::SendPort { port } => {
+                if let Some(port) = &ports[(port % PORT_COUNT) as usize] {
+                    let (left, right) = Port::new_pair();
+                    port.send(Message::new((left,)));
+                    sent_ports.push(right.set_handler(Handler));
+                }
+            }
+            Action::Close { port } => {
+                ports[(port % PORT_COUNT) as usize] = None;
+            }
+            Action::ConnectionLost => remote.connection_lost("fuzz connection lost"),
+            Action::Reconnect => {
+                remote.connect(Sink);
+            }
+            Action::Fail => remote.fail("fuzz failure"),
+        }
+    }
+
+    // Tear down the ports before the node to exercise the close paths.
+    drop(ports);
+    drop(sent_ports);
+    node.fail_all_nodes();
+}
+
+fuzz_target!(|input: FuzzInput| {
+    xtask_fuzz::init_tracing_if_repro();
+    do_fuzz(input)
+});
@@ -0,0 +1,169 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A bounded record of the most recent port events exchanged with remote
+//! nodes, for debugging lost messages and port failures after the fact.
+//!
+//! Every [`LocalNode`](crate::local_node::LocalNode) records its events
+//! automatically. Use [`recent_events`] to retrieve them (e.g. to report them
+//! via inspect), or [`install_panic_hook`] to dump them to stderr when the
+//! process panics.
+
+use crate::common::NodeId;
+use crate::common::PortId;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::io::Write;
+use std::sync::Arc;
+use std::sync::Once;
+use std::sync::Weak;
+use std::time::Instant;
+
+/// The number of events retained per node.
+pub const CAPACITY: usize = 256;
+
+/// The direction of a recorded event.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// The event was sent to the peer node.
+    Sent,
+    /// The event was received from the peer node.
+    Received,
+}
+
+/// A recorded port event.
+#[derive(Debug, Clone)]
+pub struct EventRecord {
+    /// When the event was recorded.
+    pub time: Instant,
+    /// Whether the event was sent or received.
+    pub direction: Direction,
+    /// The event type, such as `message` or `fail_port`.
+    pub event_type: &'static str,
+    /// The destination port on the receiving node.
+    pub port: PortId,
+    /// The remote node the event was sent to or received from.
+    pub peer: NodeId,
+    /// The event's sequence number.
+    pub seq: u64,
+    /// The size of the serialized event, in bytes.
+    pub size: usize,
+}
+
+/// The event log for a single node.
+#[derive(Debug)]
+pub(crate) struct FlightRecorder {
+    node: NodeId,
+    events: Mutex<VecDeque<EventRecord>>,
+}
+
+static RECORDERS: Mutex<Vec<Weak<FlightRecorder>>> = Mutex::new(Vec::new());
+
+impl FlightRecorder {
+    /// Creates a new recorder for `node` and registers it globally.
+    pub fn new(node: NodeId) -> Arc<Self> {
+        let this = Arc::new(Self {
+            node,
+            events: Mutex::new(VecDeque::with_capacity(CAPACITY)),
+        });
+        let mut recorders = RECORDERS.lock();
+        recorders.retain(|r| r.strong_count() > 0);
+        recorders.push(Arc::downgrade(&this));
+        this
+    }
+
+    /// Records an event, evicting the oldest event if the log is full.
+    pub fn record(&self, record: EventRecord) {
+        let mut events = self.events.lock();
+        if events.len() == CAPACITY {
+            events.pop_front();
+        }
+        events.push_back(record);
+    }
+
+    fn events(&self) -> Vec<EventRecord> {
+        self.events.lock().iter().cloned().collect()
+    }
+}
+
+/// Returns the recorded events for each live node in the process, oldest
+/// first.
+pub fn recent_events() -> Vec<(NodeId, Vec<EventRecord>)> {
+    let recorders: Vec<_> = RECORDERS
+        .lock()
+        .iter()
+        .filter_map(|r| r.upgrade())
+        .collect();
+    recorders.iter().map(|r| (r.node, r.events())).collect()
+}
+
+/// Installs a panic hook that writes the recorded events for each live node
+/// to stderr before running the existing panic hook.
+///
+/// Only the first call has any effect.
+pub fn install_panic_hook() {
+    static ONCE: Once = Once::new();
+    ONCE.call_once(|| {
+        let panic_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            // Don't block on the registry if the panic occurred while it was
+            // held.
+            if let Some(recorders) = RECORDERS.try_lock() {
+                let recorders: Vec<_> = recorders.iter().filter_map(|r| r.upgrade()).collect();
+                let now = Instant::now();
+                let mut stderr = std::io::stderr().lock();
+                for recorder in recorders {
+                    let Some(events) = recorder.events.try_lock() else {
+                        continue;
+                    };
+                    let _ = writeln!(stderr, "mesh node {:?} recent events:", recorder.node);
+                    for event in events.iter() {
+                        let _ = writeln!(
+                            stderr,
+                            "  -{:?} {:?} {} port={:?} peer={:?} seq={} size={}",
+                            now - event.time,
+                            event.direction,
+                            event.event_type,
+                            event.port,
+                            event.peer,
+                            event.seq,
+                            event.size,
+                        );
+                    }
+                }
+            }
+            panic_hook(info);
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(seq: u64) -> EventRecord {
+        EventRecord {
+            time: Instant::now(),
+            direction: Direction::Sent,
+            event_type: "message",
+            port: PortId::new(),
+            peer: NodeId::new(),
+            seq,
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn test_bounded() {
+        let recorder = FlightRecorder::new(NodeId::new());
+        for seq in 0..CAPACITY as u64 + 10 {
+            recorder.record(record(seq));
+        }
+        let events = recorder.events();
+        assert_eq!(events.len(), CAPACITY);
+        assert_eq!(events[0].seq, 10);
+        assert!(recent_events()
+            .iter()
+            .any(|(node, _)| *node == recorder.node));
+    }
+}
@@ -4,7 +4,9 @@
 //! Implementation of mesh port and node communication model.
 
 pub mod common;
+pub mod flight_recorder;
 pub mod local_node;
 pub mod message;
 pub mod resource;
+pub mod stats;
 pub mod upcast;
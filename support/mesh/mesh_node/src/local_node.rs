@@ -6,10 +6,16 @@
 use crate::common::Address;
 use crate::common::NodeId;
 use crate::common::PortId;
+use crate::flight_recorder::Direction;
+use crate::flight_recorder::EventRecord;
+use crate::flight_recorder::FlightRecorder;
 use crate::message::Message;
 use crate::resource::OsResource;
 use crate::resource::Resource;
 use crate::resource::SerializedMessage;
+use crate::stats::NodeStats;
+use crate::stats::PortStats;
+use crate::stats::RemoteNodeStats;
 use futures_channel::oneshot;
 use mesh_protobuf::buffer::write_with;
 use mesh_protobuf::buffer::Buf;
@@ -28,14 +34,19 @@
 use std::fmt;
 use std::fmt::Debug;
 use std::fmt::Display;
+use std::io::Read;
+use std::io::Write;
 use std::marker::PhantomData;
 use std::num::Wrapping;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicIsize;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::sync::Weak;
 use std::task::Waker;
+use std::time::Instant;
 use thiserror::Error;
 use zerocopy::AsBytes;
 use zerocopy::FromBytes;
@@ -300,7 +311,11 @@ pub fn send(&self, message: Message) {
         let peer_seq = {
             let mut state = self.inner.state.lock();
             assert!(!state.is_local_closed);
-            state.next_peer_and_seq()
+            let peer_seq = state.next_peer_and_seq();
+            if peer_seq.is_some() {
+                state.messages_sent += 1;
+            }
+            peer_seq
         };
 
         if let Some((peer, seq)) = peer_seq {
@@ -353,7 +368,9 @@ pub fn with_port_and_handler<R>(&self, f: impl FnOnce(&mut PortControl<'_>, &mut
         let mut state = self.raw.inner.state.lock();
         let state = &mut *state;
         let peer_and_seq = match &state.activity {
-            PortActivity::Peered(peer) => Some((peer, &mut state.next_local_seq)),
+            PortActivity::Peered(peer) => {
+                Some((peer, &mut state.next_local_seq, &mut state.messages_sent))
+            }
             _ => None,
         };
         let mut control = PortControl {
@@ -431,11 +448,43 @@ fn drop(&mut self) {
     }
 }
 
+/// The default size at which messages are compressed before being sent to a
+/// remote node.
+///
+/// This is large enough that only bulk data, such as saved state, is
+/// compressed.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 1024 * 1024;
+
+/// The maximum uncompressed size of a compressed message.
+///
+/// The size is chosen by the remote node, so bound it to avoid a small
+/// message decompressing into an arbitrarily large allocation. Larger messages
+/// are sent uncompressed.
+const MAX_DECOMPRESSED_SIZE: usize = 0x4000_0000;
+
 /// The inner state for [`LocalNode`].
 #[derive(Debug)]
 struct LocalNodeInner {
     id: NodeId,
     state: Mutex<LocalNodeState>,
+    /// The minimum message size to compress, or `usize::MAX` to disable
+    /// compression.
+    compression_threshold: AtomicUsize,
+    recorder: Arc<FlightRecorder>,
+    /// The number of ports that failed while associated with this node.
+    failed_ports: AtomicU64,
+    /// The number of events from remote nodes that failed to parse or
+    /// process.
+    invalid_events: AtomicU64,
+}
+
+/// All the local nodes in the process, for [`crate::stats::node_stats`].
+static NODES: Mutex<Vec<Weak<LocalNodeInner>>> = Mutex::new(Vec::new());
+
+/// Returns the counters for each live node in the process.
+pub(crate) fn all_node_stats() -> Vec<NodeStats> {
+    let nodes: Vec<_> = NODES.lock().iter().filter_map(Weak::upgrade).collect();
+    nodes.iter().map(|node| node.stats()).collect()
 }
 
 /// A 64-bit message sequence number.
@@ -479,6 +528,36 @@ struct RemoteNode {
     failed: AtomicBool,
     node_error: Mutex<Result<(), NodeError>>,
     handle_count: AtomicIsize,
+    /// Whether the remote node has indicated that it can receive compressed
+    /// messages.
+    compression_supported: AtomicBool,
+    counters: RemoteNodeCounters,
+    /// If reconnect is enabled, the sequence number of the last event sent to
+    /// each remote port, used to detect events lost with a connection.
+    ///
+    /// Entries are removed once the remote port will not be sent any more
+    /// events by this node, such as when it acknowledges a close or a peer
+    /// change.
+    sent_seqs: RwLock<HashMap<PortId, AtomicU64>>,
+    /// Whether reconnect is enabled, so that `sent_seqs` must be updated.
+    reconnect_enabled: AtomicBool,
+    /// Whether the connection was lost and is waiting to be re-established.
+    reconnecting: AtomicBool,
+}
+
+/// Traffic counters for a remote node, reported via [`RemoteNodeStats`].
+#[derive(Debug, Default)]
+struct RemoteNodeCounters {
+    events_sent: AtomicU64,
+    events_received: AtomicU64,
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    resources_sent: AtomicU64,
+    resources_received: AtomicU64,
+    deferred_events: AtomicU64,
+    reconnects: AtomicU64,
 }
 
 impl Debug for RemoteNode {
@@ -516,6 +595,11 @@ fn new(local_node: Arc<LocalNodeInner>, id: NodeId) -> (Arc<Self>, RemoteNodeHan
             failed: AtomicBool::new(false),
             node_error: Mutex::new(Ok(())),
             handle_count: AtomicIsize::new(1),
+            compression_supported: AtomicBool::new(false),
+            counters: Default::default(),
+            sent_seqs: Default::default(),
+            reconnect_enabled: AtomicBool::new(false),
+            reconnecting: AtomicBool::new(false),
         });
         let handle = RemoteNodeHandle {
             id,
@@ -525,25 +609,128 @@ fn new(local_node: Arc<LocalNodeInner>, id: NodeId) -> (Arc<Self>, RemoteNodeHan
     }
 
     /// Provides a connection for the remote node, flushing any deferred events.
+    ///
+    /// If this replaces a lost connection, first resynchronizes the ports
+    /// peered with the remote node.
     fn connect(self: &Arc<Self>, conn: Box<dyn SendEvent>) -> bool {
-        let events = {
+        // Find the ports to resynchronize before taking the state lock, since
+        // port locks cannot be taken while the state lock is held.
+        let resync_ports = if self.reconnecting.load(Ordering::SeqCst) {
+            self.local_node.peered_ports(self)
+        } else {
+            Vec::new()
+        };
+        let (events, sent_seqs) = {
             let mut state = self.state.write();
             match &mut *state {
                 RemoteNodeState::Queuing(v) => {
                     let v = std::mem::take(v.get_mut());
+                    // Capture the events sent on the old connection before
+                    // any are sent on the new one.
+                    let sent_seqs = self.reconnecting.swap(false, Ordering::SeqCst).then(|| {
+                        self.sent_seqs
+                            .read()
+                            .iter()
+                            .map(|(&port_id, seq)| (port_id, Wrapping(seq.load(Ordering::Relaxed))))
+                            .collect::<HashMap<_, _>>()
+                    });
                     *state = RemoteNodeState::Active(conn);
-                    v
+                    (v, sent_seqs)
                 }
                 _ => return false,
             }
         };
         self.check_failed();
+        if let Some(sent_seqs) = sent_seqs {
+            self.resync(&sent_seqs, &resync_ports);
+        }
         for event in events {
             self.event(event.port_id, event.seq, event.event);
         }
         true
     }
 
+    /// Marks the connection as lost. Events are queued until a new
+    /// connection is provided via `connect`.
+    ///
+    /// Returns false if reconnect is not enabled or the node is not connected.
+    fn connection_lost(&self) -> bool {
+        if !self.reconnect_enabled.load(Ordering::SeqCst) {
+            return false;
+        }
+        let old = {
+            let mut state = self.state.write();
+            // The node may have failed without being able to update the state.
+            if self.failed.load(Ordering::SeqCst) || !matches!(&*state, RemoteNodeState::Active(_))
+            {
+                return false;
+            }
+            self.reconnecting.store(true, Ordering::SeqCst);
+            std::mem::replace(&mut *state, RemoteNodeState::Queuing(Default::default()))
+        };
+        // Drop the old connection outside the lock.
+        drop(old);
+        true
+    }
+
+    /// Sends a resync event for each of `ports` over a new connection, given
+    /// the last sequence number sent to each remote port over the old one.
+    fn resync(self: &Arc<Self>, sent_seqs: &HashMap<PortId, Seq>, ports: &[(PortId, PortId)]) {
+        self.counters.reconnects.fetch_add(1, Ordering::Relaxed);
+        if let RemoteNodeState::Active(conn) = &*self.state.read() {
+            for &(port_id, peer_port_id) in ports {
+                // If the port hasn't sent anything, use sequence number zero
+                // so that the peer only checks that the port still exists.
+                let first_seq = sent_seqs
+                    .get(&peer_port_id)
+                    .map_or(Wrapping(0), |&seq| seq + Wrapping(1));
+                conn.event(OutgoingEvent::new(
+                    peer_port_id,
+                    first_seq,
+                    PortEvent::Resync(port_id),
+                    self,
+                ));
+            }
+        }
+        // Forget about ports that are no longer peered.
+        self.sent_seqs
+            .write()
+            .retain(|id, _| ports.iter().any(|&(_, peer_id)| peer_id == *id));
+    }
+
+    /// Records that the event with sequence number `seq` was sent to
+    /// `port_id`.
+    fn record_sent(&self, port_id: PortId, seq: Seq) {
+        // Take the write lock only to track a new port.
+        if let Some(last) = self.sent_seqs.read().get(&port_id) {
+            last.fetch_max(seq.0, Ordering::Relaxed);
+            return;
+        }
+        self.sent_seqs
+            .write()
+            .entry(port_id)
+            .or_default()
+            .fetch_max(seq.0, Ordering::Relaxed);
+    }
+
+    /// Stops tracking the events sent to `port_id`, since it will not need to
+    /// be resynchronized with the events through `last_seq`.
+    ///
+    /// The entry is kept if a later event has been sent to the port, by
+    /// another local port that has since become its peer.
+    fn forget_port(&self, port_id: PortId, last_seq: Seq) {
+        if !self.reconnect_enabled.load(Ordering::Relaxed) {
+            return;
+        }
+        let mut sent_seqs = self.sent_seqs.write();
+        if sent_seqs
+            .get(&port_id)
+            .is_some_and(|seq| seq.load(Ordering::Relaxed) <= last_seq.0)
+        {
+            sent_seqs.remove(&port_id);
+        }
+    }
+
     fn check_failed(&self) {
         if self.failed.load(Ordering::SeqCst) {
             let _old = std::mem::replace(&mut *self.state.write(), RemoteNodeState::Failed);
@@ -566,6 +753,9 @@ fn fail(&self, err: NodeError) {
     fn event(self: &Arc<Self>, port_id: PortId, seq: Seq, event: PortEvent) {
         match &*self.state.read() {
             RemoteNodeState::Queuing(v) => {
+                self.counters
+                    .deferred_events
+                    .fetch_add(1, Ordering::Relaxed);
                 v.lock().push(DeferredEvent {
                     port_id,
                     seq,
@@ -574,12 +764,28 @@ fn event(self: &Arc<Self>, port_id: PortId, seq: Seq, event: PortEvent) {
             }
             RemoteNodeState::Failed => (),
             RemoteNodeState::Active(conn) => {
+                // Events with sequence number zero are not part of the port's
+                // ordered event stream and so are not resynchronized.
+                if self.reconnect_enabled.load(Ordering::Relaxed) && seq.0 != 0 {
+                    self.record_sent(port_id, seq);
+                }
                 conn.event(OutgoingEvent::new(port_id, seq, event, self))
             }
         }
         self.check_failed();
     }
 
+    /// Returns the minimum size of messages to compress for this node.
+    fn compression_threshold(&self) -> usize {
+        if self.compression_supported.load(Ordering::Relaxed) {
+            self.local_node
+                .compression_threshold
+                .load(Ordering::Relaxed)
+        } else {
+            usize::MAX
+        }
+    }
+
     /// Returns whether the remote node connection has failed.
     fn node_status(&self) -> Result<(), NodeError> {
         if !self.failed.load(Ordering::SeqCst) {
@@ -587,6 +793,37 @@ fn node_status(&self) -> Result<(), NodeError> {
         }
         self.node_error.lock().clone()
     }
+
+    /// Returns a snapshot of the node's counters.
+    fn stats(&self) -> RemoteNodeStats {
+        let state = if self.failed.load(Ordering::SeqCst) {
+            "failed"
+        } else {
+            match &*self.state.read() {
+                RemoteNodeState::Queuing(_) if self.reconnecting.load(Ordering::SeqCst) => {
+                    "reconnecting"
+                }
+                RemoteNodeState::Queuing(_) => "connecting",
+                RemoteNodeState::Failed => "failed",
+                RemoteNodeState::Active(_) => "connected",
+            }
+        };
+        let c = &self.counters;
+        RemoteNodeStats {
+            node: self.id,
+            state,
+            events_sent: c.events_sent.load(Ordering::Relaxed),
+            events_received: c.events_received.load(Ordering::Relaxed),
+            messages_sent: c.messages_sent.load(Ordering::Relaxed),
+            messages_received: c.messages_received.load(Ordering::Relaxed),
+            bytes_sent: c.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: c.bytes_received.load(Ordering::Relaxed),
+            resources_sent: c.resources_sent.load(Ordering::Relaxed),
+            resources_received: c.resources_received.load(Ordering::Relaxed),
+            deferred_events: c.deferred_events.load(Ordering::Relaxed),
+            reconnects: c.reconnects.load(Ordering::Relaxed),
+        }
+    }
 }
 
 /// The interior state of a port.
@@ -598,14 +835,20 @@ struct PortInner {
 
 /// A control object used by [`HandlePortEvent`] operations.
 pub struct PortControl<'a> {
-    peer_and_seq: Option<(&'a PortRef, &'a mut Seq)>,
+    /// The peer, the next sequence number, and the sent message counter.
+    peer_and_seq: Option<(&'a PortRef, &'a mut Seq, &'a mut u64)>,
     events: &'a mut PendingEvents,
 }
 
 impl<'a> PortControl<'a> {
-    fn peered(peer: &'a PortRef, seq: &'a mut Seq, events: &'a mut PendingEvents) -> Self {
+    fn peered(
+        peer: &'a PortRef,
+        seq: &'a mut Seq,
+        messages_sent: &'a mut u64,
+        events: &'a mut PendingEvents,
+    ) -> Self {
         Self {
-            peer_and_seq: Some((peer, seq)),
+            peer_and_seq: Some((peer, seq, messages_sent)),
             events,
         }
     }
@@ -619,9 +862,10 @@ fn unpeered(events: &'a mut PendingEvents) -> Self {
 
     /// Sends a message to the peer port.
     pub fn respond(&mut self, message: Message) {
-        if let Some((port_ref, seq)) = &mut self.peer_and_seq {
+        if let Some((port_ref, seq, messages_sent)) = &mut self.peer_and_seq {
             let this = **seq;
             **seq += Wrapping(1);
+            **messages_sent += 1;
             self.events
                 .push(port_ref.clone(), this, PortEvent::Message(message))
         }
@@ -727,6 +971,14 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 #[error("remote node dropped")]
 struct RemoteNodeDropped;
 
+#[derive(Debug, Error)]
+#[error("port events lost with connection to remote node")]
+struct LostEvents;
+
+#[derive(Debug, Error)]
+#[error("connection to remote node lost while port was in transit")]
+struct LostInTransit(#[source] Box<dyn std::error::Error + Send + Sync>);
+
 trait HandlePortEventAndAny: HandlePortEvent {
     fn as_any(&mut self) -> &mut dyn Any;
     fn into_any(self: Box<Self>) -> Box<dyn Any>;
@@ -759,6 +1011,9 @@ struct PortInnerState {
 
     next_local_seq: Seq,
     is_local_closed: bool,
+
+    messages_sent: u64,
+    messages_received: u64,
 }
 
 /// A [`HandlePortEvent`] implementation that just queues the messages.
@@ -824,8 +1079,17 @@ fn is_empty(&self) -> bool {
 #[derive(Clone, Debug)]
 enum PortActivity {
     Peered(PortRef),
-    Sending { peer: PortRef, target: PortRef },
-    Proxying { peer: PortRef, target: PortRef },
+    Sending {
+        peer: PortRef,
+        target: PortRef,
+    },
+    /// Forwarding events to `target` until `peer` acknowledges the peer
+    /// change event, which was sent with sequence number `change_seq`.
+    Proxying {
+        peer: PortRef,
+        target: PortRef,
+        change_seq: Seq,
+    },
     Failed(NodeError),
     Done,
     Unreachable,
@@ -882,6 +1146,8 @@ fn new(activity: PortActivity) -> Self {
             event_queue: EventQueue::new(),
             handler: Box::<QueuingHandler>::default(),
             is_local_closed: false,
+            messages_sent: 0,
+            messages_received: 0,
         }
     }
 
@@ -889,9 +1155,12 @@ fn new(activity: PortActivity) -> Self {
     fn fail(&mut self, pending_events: &mut PendingEvents, err: NodeError) {
         match std::mem::replace(&mut self.activity, PortActivity::Failed(err.clone())) {
             PortActivity::Peered(peer) => {
+                // No more events will be sent to the peer.
+                pending_events.forget(&peer, self.next_local_seq);
                 pending_events.push(peer, Wrapping(0), PortEvent::FailPort(err));
             }
-            PortActivity::Sending { peer, target } | PortActivity::Proxying { peer, target } => {
+            PortActivity::Sending { peer, target }
+            | PortActivity::Proxying { peer, target, .. } => {
                 pending_events.push(peer, Wrapping(0), PortEvent::FailPort(err.clone()));
                 pending_events.push(target, Wrapping(0), PortEvent::FailPort(err.clone()));
             }
@@ -932,6 +1201,11 @@ enum EventError {
     // Field is stored solely for logging via debug, not actually dead.
     UnknownEventType(#[allow(dead_code)] protocol::EventType),
     MissingOsResource,
+    // Field is stored solely for logging via debug, not actually dead.
+    Decompress(#[allow(dead_code)] std::io::Error),
+    DecompressedSizeMismatch,
+    // Field is stored solely for logging via debug, not actually dead.
+    DecompressedSizeTooLarge(#[allow(dead_code)] u64),
 }
 
 /// A list of pending local and remote events to send. This is used to avoid
@@ -939,6 +1213,7 @@ enum EventError {
 struct PendingEvents {
     local_events: VecDeque<(Arc<PortInner>, Seq, PortEvent)>,
     remote_events: Vec<(Arc<RemoteNode>, PortId, Seq, PortEvent)>,
+    forgotten_ports: Vec<(Arc<RemoteNode>, PortId, Seq)>,
     wakers: Vec<Waker>,
 }
 
@@ -947,6 +1222,7 @@ fn new() -> Self {
         Self {
             local_events: VecDeque::new(),
             remote_events: Vec::new(),
+            forgotten_ports: Vec::new(),
             wakers: Vec::new(),
         }
     }
@@ -983,6 +1259,11 @@ fn process(mut self) {
         for (remote_node, port_id, seq, event) in self.remote_events.drain(..) {
             remote_node.event(port_id, seq, event);
         }
+        // Forget ports only after sending the events above, which may include
+        // final events to them.
+        for (remote_node, port_id, last_seq) in self.forgotten_ports {
+            remote_node.forget_port(port_id, last_seq);
+        }
         for waker in self.wakers {
             waker.wake();
         }
@@ -1003,6 +1284,16 @@ fn push(&mut self, port: PortRef, seq: Seq, event: PortEvent) {
         }
     }
 
+    /// Notes that `port` will not be sent any more events after `last_seq`, so
+    /// that it does not need to be resynchronized if the connection to its
+    /// node is lost.
+    fn forget(&mut self, port: &PortRef, last_seq: Seq) {
+        if let PortRef::RemotePort(remote_node, port_id) = port {
+            self.forgotten_ports
+                .push((remote_node.clone(), *port_id, last_seq));
+        }
+    }
+
     fn wake(&mut self, waker: Waker) {
         self.wakers.push(waker);
     }
@@ -1060,10 +1351,12 @@ fn on_event(
                     while let Some(port_event) = self.event_queue.pop() {
                         match port_event {
                             PortEvent::Message(message) => {
+                                self.messages_received += 1;
                                 self.handler.message(
                                     &mut PortControl::peered(
                                         peer,
                                         &mut self.next_local_seq,
+                                        &mut self.messages_sent,
                                         pending_events,
                                     ),
                                     message,
@@ -1080,12 +1373,14 @@ fn on_event(
                                         PortEvent::ClosePort,
                                     );
                                 }
+                                pending_events.forget(peer, self.next_local_seq);
                                 return Ok(PortEventResult::Done);
                             }
                             PortEvent::ChangePeer(new_peer, seq_delta) => {
                                 assert!(new_peer.is_compatible_node(&self.local_node));
                                 new_peer.node_status()?;
                                 let old_peer = std::mem::replace(peer, new_peer);
+                                pending_events.forget(&old_peer, self.next_local_seq);
                                 pending_events.push(
                                     old_peer,
                                     self.next_local_seq,
@@ -1096,7 +1391,9 @@ fn on_event(
                             PortEvent::AcknowledgeChangePeer => {
                                 break 'error PortError::AckChangePeerInvalidState;
                             }
-                            PortEvent::AcknowledgePort | PortEvent::FailPort(_) => unreachable!(),
+                            PortEvent::AcknowledgePort
+                            | PortEvent::FailPort(_)
+                            | PortEvent::Resync(_) => unreachable!(),
                         }
                     }
                     return Ok(PortEventResult::None);
@@ -1107,8 +1404,14 @@ fn on_event(
                     self.event_queue.add(seq, event);
                     return Ok(PortEventResult::None);
                 }
-                PortActivity::Proxying { peer: _, target } => {
+                PortActivity::Proxying {
+                    peer,
+                    target,
+                    change_seq,
+                } => {
+                    let peer = peer.clone();
                     let target = target.clone();
+                    let change_seq = *change_seq;
 
                     self.event_queue.add(seq, event);
 
@@ -1119,6 +1422,9 @@ fn on_event(
                                 if !self.event_queue.is_empty() {
                                     break 'error PortError::EventAfterProxyEnd;
                                 }
+                                // The peer has received every event this port
+                                // sent it, through the peer change.
+                                pending_events.forget(&peer, change_seq);
                                 return Ok(PortEventResult::Done);
                             }
                             event => {
@@ -1128,6 +1434,7 @@ fn on_event(
                                     self.set_activity(PortActivity::Proxying {
                                         peer: new_peer.clone(),
                                         target: target.clone(),
+                                        change_seq,
                                     });
                                 }
                                 pending_events.push(target.clone(), next_seq, event);
@@ -1180,6 +1487,7 @@ fn start_proxy(
         self.set_activity(PortActivity::Proxying {
             peer: peer.clone(),
             target: target.clone(),
+            change_seq,
         });
 
         pending_events.push(peer, change_seq, PortEvent::ChangePeer(target, delta));
@@ -1211,7 +1519,6 @@ fn on_event(
         pending_events: &mut PendingEvents,
     ) {
         let mut state = self.state.lock();
-        let mut disassociate = false;
         match state.on_event(remote_node_id, seq, event, pending_events) {
             Ok(PortEventResult::None) => {}
             Ok(PortEventResult::Done) => {
@@ -1219,21 +1526,58 @@ fn on_event(
                 state
                     .handler
                     .close(&mut PortControl::unpeered(pending_events));
-                disassociate = true;
-            }
-            Err(err) => {
-                state.fail(pending_events, err.clone());
-                state
-                    .handler
-                    .fail(&mut PortControl::unpeered(pending_events), err);
-                disassociate = true;
+                self.disassociate(&mut state);
             }
+            Err(err) => self.fail_locked(&mut state, pending_events, err),
         }
+        drop(state);
+    }
 
-        if disassociate {
-            self.disassociate(&mut state);
+    /// Fails the port, notifying its handler and any peers.
+    fn fail_locked(
+        &self,
+        state: &mut PortInnerState,
+        pending_events: &mut PendingEvents,
+        err: NodeError,
+    ) {
+        if let Some(local_node) = state.local_node.as_ref().and_then(Weak::upgrade) {
+            local_node.failed_ports.fetch_add(1, Ordering::Relaxed);
+        }
+        state.fail(pending_events, err.clone());
+        state
+            .handler
+            .fail(&mut PortControl::unpeered(pending_events), err);
+        self.disassociate(state);
+    }
+
+    /// Handles a resync event from the peer port on `remote_node_id`, failing
+    /// the port if any events before `first_seq` were lost.
+    fn resync(&self, remote_node_id: &NodeId, first_seq: Seq, pending_events: &mut PendingEvents) {
+        let mut state = self.state.lock();
+        let PortActivity::Peered(PortRef::RemotePort(node, _)) = &state.activity else {
+            return;
+        };
+        if node.id != *remote_node_id {
+            return;
+        }
+        // Any events before `first_seq` must have been received, either
+        // processed or still waiting in the queue for an earlier event.
+        let queue = &state.event_queue;
+        let missing = first_seq - queue.next_peer_seq;
+        let lost = first_seq > queue.next_peer_seq
+            && (missing.0 > queue.heap.len() as u64
+                || (0..missing.0).any(|i| {
+                    let seq = queue.next_peer_seq + Wrapping(i);
+                    !queue.heap.iter().any(|Reverse(SeqValue(s, _))| *s == seq)
+                }));
+        if lost {
+            tracing::debug!(port = ?self.id, ?remote_node_id, "port events lost on reconnect");
+            self.fail_locked(
+                &mut state,
+                pending_events,
+                NodeError::new(remote_node_id, LostEvents),
+            );
         }
-        drop(state);
     }
 
     /// Starts proxying incoming events.
@@ -1345,7 +1689,9 @@ fn set_handler(&self, mut handler: Box<dyn HandlePortEventAndAny>) {
             let state = &mut *state;
             let messages = state.handler.drain();
             let peer_and_seq = match &state.activity {
-                PortActivity::Peered(peer) => Some((peer, &mut state.next_local_seq)),
+                PortActivity::Peered(peer) => {
+                    Some((peer, &mut state.next_local_seq, &mut state.messages_sent))
+                }
                 _ => None,
             };
             let mut control = PortControl {
@@ -1366,6 +1712,33 @@ fn set_handler(&self, mut handler: Box<dyn HandlePortEventAndAny>) {
         pending_events.process();
     }
 
+    /// Returns a snapshot of the port's counters.
+    fn stats(&self) -> PortStats {
+        let state = self.state.lock();
+        let (activity, peer_node) = match &state.activity {
+            PortActivity::Peered(peer) => (
+                "peered",
+                match peer {
+                    PortRef::LocalPort(_) => None,
+                    PortRef::RemotePort(node, _) => Some(node.id),
+                },
+            ),
+            PortActivity::Sending { .. } => ("sending", None),
+            PortActivity::Proxying { .. } => ("proxying", None),
+            PortActivity::Failed(_) => ("failed", None),
+            PortActivity::Done => ("done", None),
+            PortActivity::Unreachable => unreachable!(),
+        };
+        PortStats {
+            port: self.id,
+            state: activity,
+            peer_node,
+            messages_sent: state.messages_sent,
+            messages_received: state.messages_received,
+            pending_events: state.event_queue.heap.len(),
+        }
+    }
+
     fn clear_queue(&self, drain: bool) -> Box<dyn HandlePortEventAndAny> {
         let mut state = self.state.lock();
         let messages = if drain {
@@ -1418,6 +1791,44 @@ pub fn disconnect(&self) {
         self.fail(RemoteNodeDisconnected)
     }
 
+    /// Enables [`Self::connection_lost`] for this node.
+    ///
+    /// This must be called before the first call to [`Self::connect`].
+    pub fn enable_reconnect(&self) {
+        if let Some(remote_node) = self.remote_node.upgrade() {
+            remote_node.reconnect_enabled.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// Reports that the connection to the remote node was lost, but that the
+    /// caller will try to re-establish it by calling [`Self::connect`] again.
+    ///
+    /// Events are queued until the new connection is established. Then, ports
+    /// that are peered with ports on the remote node are resynchronized: those
+    /// that lost events with the old connection fail, and the rest continue
+    /// normally. Ports that were in the process of being sent to or from the
+    /// remote node fail immediately.
+    ///
+    /// The caller is responsible for giving up, via [`Self::fail`], if the
+    /// connection cannot be re-established.
+    ///
+    /// Returns false if the connection should not be re-established, either
+    /// because reconnect has not been enabled with [`Self::enable_reconnect`]
+    /// or because the node is not connected. In this case, this is equivalent
+    /// to [`Self::fail`].
+    pub fn connection_lost(
+        &self,
+        err: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+    ) -> bool {
+        if let Some(remote_node) = self.remote_node.upgrade() {
+            remote_node
+                .local_node
+                .connection_lost(&remote_node, err.into())
+        } else {
+            false
+        }
+    }
+
     pub fn fail(&self, err: impl Into<Box<dyn std::error::Error + Send + Sync>>) {
         if let Some(remote_node) = self.remote_node.upgrade() {
             remote_node
@@ -1456,6 +1867,9 @@ enum PortEvent {
     AcknowledgeChangePeer,
     AcknowledgePort,
     FailPort(NodeError),
+    /// Outgoing only: resynchronizes the peer port after a reconnect. Contains
+    /// the local port ID.
+    Resync(PortId),
 }
 
 /// An event to be sent to a remote node.
@@ -1469,6 +1883,12 @@ pub struct OutgoingEvent<'a> {
 
 enum EventAndEncoder {
     Message(Encoder<Message, <Message as DefaultEncoding>::Encoding, Resource>),
+    Compressed {
+        /// The [`protocol::CompressedMessageData`] header followed by the
+        /// compressed message.
+        data: Vec<u8>,
+        resources: Vec<Resource>,
+    },
     Other(PortEvent), // guaranteed to not be PortEvent::Message
 }
 
@@ -1479,18 +1899,79 @@ fn new(port_id: PortId, seq: Seq, event: PortEvent, remote_node: &'a Arc<RemoteN
             PortEvent::Message(message) => {
                 let message = Encoder::new(message);
                 len += message.resource_count() * size_of::<protocol::ResourceData>();
-                len += message.len();
-                EventAndEncoder::Message(message)
+                if message.len() >= remote_node.compression_threshold() {
+                    let (data, resources) = message.encode();
+                    match compress(&data) {
+                        Some(compressed) => {
+                            len += compressed.len();
+                            EventAndEncoder::Compressed {
+                                data: compressed,
+                                resources,
+                            }
+                        }
+                        None => {
+                            len += data.len();
+                            EventAndEncoder::Message(Encoder::new(Message::serialized(
+                                SerializedMessage { data, resources },
+                            )))
+                        }
+                    }
+                } else {
+                    len += message.len();
+                    EventAndEncoder::Message(message)
+                }
             }
             PortEvent::ChangePeer(_, _) => {
                 len += size_of::<protocol::ChangePeerData>();
                 EventAndEncoder::Other(event)
             }
+            PortEvent::Resync(_) => {
+                len += size_of::<protocol::ResyncPortData>();
+                EventAndEncoder::Other(event)
+            }
             event @ (PortEvent::ClosePort
             | PortEvent::AcknowledgeChangePeer
             | PortEvent::AcknowledgePort
             | PortEvent::FailPort(_)) => EventAndEncoder::Other(event),
         };
+        let counters = &remote_node.counters;
+        counters.events_sent.fetch_add(1, Ordering::Relaxed);
+        counters.bytes_sent.fetch_add(len as u64, Ordering::Relaxed);
+        let resource_count = match &event {
+            EventAndEncoder::Message(message) => Some(message.resource_count()),
+            EventAndEncoder::Compressed { resources, .. } => Some(resources.len()),
+            EventAndEncoder::Other(_) => None,
+        };
+        if let Some(resource_count) = resource_count {
+            counters.messages_sent.fetch_add(1, Ordering::Relaxed);
+            counters
+                .resources_sent
+                .fetch_add(resource_count as u64, Ordering::Relaxed);
+        }
+        remote_node.local_node.recorder.record(EventRecord {
+            time: Instant::now(),
+            direction: Direction::Sent,
+            event_type: match &event {
+                EventAndEncoder::Message(_) => protocol::EventType::MESSAGE,
+                EventAndEncoder::Compressed { .. } => protocol::EventType::COMPRESSED_MESSAGE,
+                EventAndEncoder::Other(event) => match event {
+                    PortEvent::Message(_) => unreachable!(),
+                    PortEvent::ClosePort => protocol::EventType::CLOSE_PORT,
+                    PortEvent::ChangePeer(_, _) => protocol::EventType::CHANGE_PEER,
+                    PortEvent::AcknowledgeChangePeer => {
+                        protocol::EventType::ACKNOWLEDGE_CHANGE_PEER
+                    }
+                    PortEvent::AcknowledgePort => protocol::EventType::ACKNOWLEDGE_PORT,
+                    PortEvent::FailPort(_) => protocol::EventType::FAIL_PORT,
+                    PortEvent::Resync(_) => protocol::EventType::RESYNC_PORT,
+                },
+            }
+            .name(),
+            port: port_id,
+            peer: remote_node.id,
+            seq: seq.0,
+            size: len,
+        });
         Self {
             port_id,
             seq,
@@ -1522,6 +2003,7 @@ fn write_split(
     ) {
         let mut header = protocol::Event {
             port_id: self.port_id.0.into(),
+            flags: protocol::EVENT_FLAG_COMPRESSION,
             seq: self.seq.0,
             ..protocol::Event::new_zeroed()
         };
@@ -1569,6 +2051,16 @@ fn write_split(
                         .as_bytes(),
                     );
                 }
+                PortEvent::Resync(port_id) => {
+                    header.event_type = protocol::EventType::RESYNC_PORT;
+                    header.message_size = size_of::<protocol::ResyncPortData>() as u32;
+                    buf.append(
+                        protocol::ResyncPortData {
+                            port: port_id.0.into(),
+                        }
+                        .as_bytes(),
+                    );
+                }
             },
             EventAndEncoder::Message(message) => {
                 let mut resources = Vec::new();
@@ -1579,16 +2071,29 @@ fn write_split(
                     message.resource_count() * size_of::<protocol::ResourceData>(),
                     |mut resource_buf, mut message_buf| {
                         message.encode_into(&mut message_buf, &mut resources);
-                        for resource in resources {
-                            let data = match resource {
-                                Resource::Port(port) => port.prepare_to_send(self.remote_node),
-                                Resource::Os(r) => {
-                                    os_resources.extend([r]);
-                                    protocol::ResourceData::new_zeroed()
-                                }
-                            };
-                            resource_buf.append(data.as_bytes());
-                        }
+                        write_resources(
+                            &mut resource_buf,
+                            resources,
+                            self.remote_node,
+                            os_resources,
+                        );
+                    },
+                );
+            }
+            EventAndEncoder::Compressed { data, resources } => {
+                header.event_type = protocol::EventType::COMPRESSED_MESSAGE;
+                header.message_size = data.len() as u32;
+                header.resource_count = resources.len() as u32;
+                buf.write_split(
+                    resources.len() * size_of::<protocol::ResourceData>(),
+                    |mut resource_buf, mut message_buf| {
+                        message_buf.append(&data);
+                        write_resources(
+                            &mut resource_buf,
+                            resources,
+                            self.remote_node,
+                            os_resources,
+                        );
                     },
                 );
             }
@@ -1599,6 +2104,68 @@ fn write_split(
     }
 }
 
+/// Writes the resource data for an outgoing message to `buf`, adding the OS
+/// resources to `os_resources`.
+fn write_resources(
+    buf: &mut Buf<'_>,
+    resources: Vec<Resource>,
+    remote_node: &Arc<RemoteNode>,
+    os_resources: &mut impl Extend<OsResource>,
+) {
+    for resource in resources {
+        let data = match resource {
+            Resource::Port(port) => port.prepare_to_send(remote_node),
+            Resource::Os(r) => {
+                os_resources.extend([r]);
+                protocol::ResourceData::new_zeroed()
+            }
+        };
+        buf.append(data.as_bytes());
+    }
+}
+
+/// Compresses an encoded message, returning `None` if compression does not
+/// make the message smaller.
+fn compress(data: &[u8]) -> Option<Vec<u8>> {
+    if data.len() > MAX_DECOMPRESSED_SIZE {
+        return None;
+    }
+    let header = protocol::CompressedMessageData {
+        uncompressed_size: data.len() as u64,
+    };
+    let mut encoder = flate2::write::DeflateEncoder::new(
+        Vec::with_capacity(data.len() / 2),
+        flate2::Compression::fast(),
+    );
+    encoder.get_mut().extend_from_slice(header.as_bytes());
+    encoder.write_all(data).ok()?;
+    let compressed = encoder.finish().ok()?;
+    (compressed.len() < data.len()).then_some(compressed)
+}
+
+/// Decompresses the message data of a compressed message event.
+fn decompress(message: &[u8]) -> Result<Vec<u8>, EventError> {
+    let header =
+        protocol::CompressedMessageData::read_from_prefix(message).ok_or(EventError::Truncated)?;
+    if header.uncompressed_size > MAX_DECOMPRESSED_SIZE as u64 {
+        return Err(EventError::DecompressedSizeTooLarge(
+            header.uncompressed_size,
+        ));
+    }
+    let compressed = &message[size_of_val(&header)..];
+    let mut data = Vec::new();
+    // Read one byte past the expected size to detect data that decompresses
+    // to more than the header claims.
+    flate2::read::DeflateDecoder::new(compressed)
+        .take(header.uncompressed_size + 1)
+        .read_to_end(&mut data)
+        .map_err(EventError::Decompress)?;
+    if data.len() as u64 != header.uncompressed_size {
+        return Err(EventError::DecompressedSizeMismatch);
+    }
+    Ok(data)
+}
+
 /// Trait for sending events to a remote node.
 pub trait SendEvent: Send + Sync {
     fn event(&self, event: OutgoingEvent<'_>);
@@ -1620,7 +2187,16 @@ pub fn with_id(node_id: NodeId, connector: Box<dyn Connect>) -> Self {
                 nodes: HashMap::new(),
                 shutdown: None,
             }),
+            compression_threshold: AtomicUsize::new(DEFAULT_COMPRESSION_THRESHOLD),
+            recorder: FlightRecorder::new(node_id),
+            failed_ports: AtomicU64::new(0),
+            invalid_events: AtomicU64::new(0),
         });
+        {
+            let mut nodes = NODES.lock();
+            nodes.retain(|node| node.strong_count() > 0);
+            nodes.push(Arc::downgrade(&node));
+        }
         Self {
             inner: node,
             connector: Mutex::new(Some(connector)),
@@ -1632,6 +2208,22 @@ pub fn id(&self) -> NodeId {
         self.inner.id
     }
 
+    /// Returns a snapshot of the node's traffic and failure counters.
+    pub fn stats(&self) -> NodeStats {
+        self.inner.stats()
+    }
+
+    /// Sets the minimum size of messages to compress before sending them to
+    /// remote nodes, or `None` to disable compression.
+    ///
+    /// Messages are only compressed for remote nodes that have indicated
+    /// support for receiving compressed messages.
+    pub fn set_compression_threshold(&self, threshold: Option<usize>) {
+        self.inner
+            .compression_threshold
+            .store(threshold.unwrap_or(usize::MAX), Ordering::Relaxed);
+    }
+
     #[cfg(test)]
     fn is_empty(&self) -> bool {
         self.inner.state.lock().ports.is_empty()
@@ -1737,9 +2329,19 @@ pub fn event(&self, remote_node_id: &NodeId, event: &[u8], os_resources: &mut Ve
 
         match parse() {
             Some((header, resources, message)) => {
+                self.inner.recorder.record(EventRecord {
+                    time: Instant::now(),
+                    direction: Direction::Received,
+                    event_type: header.event_type.name(),
+                    port: PortId(header.port_id.into()),
+                    peer: *remote_node_id,
+                    seq: header.seq,
+                    size: event.len(),
+                });
                 if let Err(error) =
                     self.on_parsed_event(remote_node_id, &header, &resources, message, os_resources)
                 {
+                    self.inner.invalid_events.fetch_add(1, Ordering::Relaxed);
                     tracing::error!(
                         node = ?self.inner.id,
                         port = ?PortId(header.port_id.into()),
@@ -1750,6 +2352,7 @@ pub fn event(&self, remote_node_id: &NodeId, event: &[u8], os_resources: &mut Ve
                 }
             }
             None => {
+                self.inner.invalid_events.fetch_add(1, Ordering::Relaxed);
                 tracing::error!(
                     node = ?self.inner.id,
                     "node event parse failure"
@@ -1776,13 +2379,67 @@ fn on_parsed_event(
             event_type = ?header.event_type,
             "port event"
         );
-        let port = self
-            .get_local_port(port_id)
-            .ok_or(EventError::UnknownPort)?;
+        let port = {
+            let state = self.inner.state.lock();
+            if let Some(remote_node) = state.nodes.get(remote_node_id) {
+                if header.flags & protocol::EVENT_FLAG_COMPRESSION != 0 {
+                    remote_node
+                        .compression_supported
+                        .store(true, Ordering::Relaxed);
+                }
+                let counters = &remote_node.counters;
+                counters.events_received.fetch_add(1, Ordering::Relaxed);
+                counters.bytes_received.fetch_add(
+                    (size_of_val(header) + size_of_val(resource_data) + message.len()) as u64,
+                    Ordering::Relaxed,
+                );
+                if matches!(
+                    header.event_type,
+                    protocol::EventType::MESSAGE | protocol::EventType::COMPRESSED_MESSAGE
+                ) {
+                    counters.messages_received.fetch_add(1, Ordering::Relaxed);
+                    counters
+                        .resources_received
+                        .fetch_add(resource_data.len() as u64, Ordering::Relaxed);
+                }
+            }
+            state.ports.get(&port_id).cloned()
+        };
+
+        if header.event_type == protocol::EventType::RESYNC_PORT {
+            let data =
+                protocol::ResyncPortData::read_from_prefix(message).ok_or(EventError::Truncated)?;
+            let mut events = PendingEvents::new();
+            match port {
+                Some(port) => port.resync(remote_node_id, seq, &mut events),
+                None => {
+                    // The port is gone, possibly without its peer learning
+                    // about it. Fail the peer so that it doesn't wait forever.
+                    events.push(
+                        PortRef::RemotePort(
+                            self.get_remote(*remote_node_id),
+                            PortId(data.port.into()),
+                        ),
+                        Wrapping(0),
+                        PortEvent::FailPort(NodeError::local(LostEvents)),
+                    );
+                }
+            }
+            events.process();
+            return Ok(());
+        }
+
+        let port = port.ok_or(EventError::UnknownPort)?;
         let mut os_resources = os_resources.drain(..);
 
         let port_event = match header.event_type {
-            protocol::EventType::MESSAGE => {
+            protocol::EventType::MESSAGE | protocol::EventType::COMPRESSED_MESSAGE => {
+                let data = if header.event_type == protocol::EventType::COMPRESSED_MESSAGE {
+                    decompress(message)?
+                } else {
+                    message.to_vec()
+                };
+
                 // Consume all the ports.
                 let mut resources = Vec::with_capacity(resource_data.len());
                 for data in resource_data {
@@ -1794,10 +2451,7 @@ fn on_parsed_event(
                     };
                     resources.push(r);
                 }
-                let m = Message::serialized(SerializedMessage {
-                    data: message.to_vec(),
-                    resources,
-                });
+                let m = Message::serialized(SerializedMessage { data, resources });
                 PortEvent::Message(m)
             }
             protocol::EventType::CLOSE_PORT => PortEvent::ClosePort,
@@ -1940,6 +2594,26 @@ fn get_port(&self, address: Address) -> Option<PortRef> {
 }
 
 impl LocalNodeInner {
+    /// Returns a snapshot of the node's counters.
+    fn stats(&self) -> NodeStats {
+        // Port locks are taken before the node lock elsewhere, so collect the
+        // nodes and ports before examining them.
+        let (nodes, ports): (Vec<_>, Vec<_>) = {
+            let state = self.state.lock();
+            (
+                state.nodes.values().cloned().collect(),
+                state.ports.values().cloned().collect(),
+            )
+        };
+        NodeStats {
+            node: self.id,
+            failed_ports: self.failed_ports.load(Ordering::Relaxed),
+            invalid_events: self.invalid_events.load(Ordering::Relaxed),
+            remote_nodes: nodes.iter().map(|node| node.stats()).collect(),
+            ports: ports.iter().map(|port| port.stats()).collect(),
+        }
+    }
+
     /// Fails all the remote nodes.
     fn fail_all_nodes(&self, err: NodeError) {
         let nodes = std::mem::take(&mut self.state.lock().nodes);
@@ -1979,7 +2653,60 @@ fn disconnect_remote(&self, remote_node: &Arc<RemoteNode>, err: NodeError) {
 
         // Fail the node so that no new ports will reference it.
         remote_node.fail(err.clone());
+        self.fail_remote_ports(remote_node, err, false);
 
+        // Finally, forget the node.
+        self.state.lock().nodes.remove(&remote_node.id);
+    }
+
+    /// Handles a lost connection to a remote node that may be reconnected.
+    fn connection_lost(
+        &self,
+        remote_node: &Arc<RemoteNode>,
+        err: Box<dyn std::error::Error + Send + Sync>,
+    ) -> bool {
+        tracing::debug!(node = ?self.id, remote_node = ?remote_node.id, "connection lost");
+        if remote_node.connection_lost() {
+            // Ports that were being sent to or from the node may have lost
+            // their handshake events, so fail them. Peered ports will be
+            // resynchronized when the connection is re-established.
+            self.fail_remote_ports(
+                remote_node,
+                NodeError::new(&remote_node.id, LostInTransit(err)),
+                true,
+            );
+            true
+        } else {
+            self.disconnect_remote(remote_node, NodeError::new(&remote_node.id, err));
+            false
+        }
+    }
+
+    /// Returns the local and peer port IDs of each port peered with a port on
+    /// `remote_node`.
+    fn peered_ports(&self, remote_node: &Arc<RemoteNode>) -> Vec<(PortId, PortId)> {
+        let ports: Vec<_> = self.state.lock().ports.values().cloned().collect();
+        ports
+            .iter()
+            .filter_map(|port| match &port.state.lock().activity {
+                PortActivity::Peered(PortRef::RemotePort(node, peer_port_id))
+                    if node.id == remote_node.id =>
+                {
+                    Some((port.id, *peer_port_id))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Fails the ports associated with `remote_node`, or only those that are
+    /// being sent to or from it if `in_transit_only`.
+    fn fail_remote_ports(
+        &self,
+        remote_node: &Arc<RemoteNode>,
+        err: NodeError,
+        in_transit_only: bool,
+    ) {
         // Capture all the ports in order to fail the ones associated with this
         // node.
         let ports: Vec<_> = self.state.lock().ports.values().cloned().collect();
@@ -1997,7 +2724,6 @@ fn disconnect_remote(&self, remote_node: &Arc<RemoteNode>, err: NodeError) {
                     peer: PortRef::RemotePort(node, _),
                     ..
                 }
-                | PortActivity::Peered(PortRef::RemotePort(node, _))
                 | PortActivity::Sending {
                     peer: PortRef::RemotePort(node, _),
                     ..
@@ -2006,6 +2732,9 @@ fn disconnect_remote(&self, remote_node: &Arc<RemoteNode>, err: NodeError) {
                     target: PortRef::RemotePort(node, _),
                     ..
                 } if node.id == remote_node.id => true,
+                PortActivity::Peered(PortRef::RemotePort(node, _)) => {
+                    !in_transit_only && node.id == remote_node.id
+                }
                 _ => false,
             };
             if fail {
@@ -2027,9 +2756,6 @@ fn disconnect_remote(&self, remote_node: &Arc<RemoteNode>, err: NodeError) {
             }
         }
         pending_events.process();
-
-        // Finally, forget the node.
-        self.state.lock().nodes.remove(&remote_node.id);
     }
 }
 
@@ -2312,6 +3038,194 @@ fn test_remote() {
         assert!(node2.node.is_empty());
     }
 
+    #[test]
+    fn test_compressed_message() {
+        let (node, node2, _h) = new_two_node_mesh();
+        node.node.set_compression_threshold(Some(64));
+        {
+            let (mut left, mut right) = new_remote_port_pair(&node, &node2);
+            // Let the first node learn that the second supports compression.
+            right.send(bmsg(b"abc"));
+            assert_eq!(left.try_recv().unwrap().data, b"abc");
+            let remote = node.node.inner.state.lock().nodes[&node2.node.id()].clone();
+            assert_eq!(remote.compression_threshold(), 64);
+
+            let (left2, right2) = <Channel>::new_pair();
+            left.send(SerializedMessage {
+                data: vec![0xcc; 4096],
+                resources: vec![Resource::Port(right2.into())],
+            });
+            let r = right.try_recv().unwrap();
+            assert_eq!(r.data, vec![0xcc; 4096]);
+            let mut right2 =
+                <Channel>::from(Port::try_from(r.resources.into_iter().next().unwrap()).unwrap());
+            left2.send(bmsg(b"def"));
+            assert_eq!(right2.try_recv().unwrap().data, b"def");
+        }
+        assert!(node.node.is_empty());
+        assert!(node2.node.is_empty());
+    }
+
+    #[test]
+    fn test_decompress_size() {
+        let data = vec![0xcc; 4096];
+        let compressed = compress(&data).unwrap();
+        assert_eq!(decompress(&compressed).unwrap(), data);
+
+        // The header must match the decompressed size exactly.
+        let mut header =
+            protocol::CompressedMessageData::read_from_prefix(&compressed[..]).unwrap();
+        for size in [4095, 4097] {
+            header.uncompressed_size = size;
+            let mut message = compressed.clone();
+            message[..size_of_val(&header)].copy_from_slice(header.as_bytes());
+            assert!(matches!(
+                decompress(&message),
+                Err(EventError::DecompressedSizeMismatch)
+            ));
+        }
+
+        // Sizes beyond the limit are rejected before decompressing.
+        header.uncompressed_size = MAX_DECOMPRESSED_SIZE as u64 + 1;
+        let mut message = compressed.clone();
+        message[..size_of_val(&header)].copy_from_slice(header.as_bytes());
+        assert!(matches!(
+            decompress(&message),
+            Err(EventError::DecompressedSizeTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_stats() {
+        let (node, node2, _h) = new_two_node_mesh();
+        let (left, mut right) = new_remote_port_pair(&node, &node2);
+        left.send(bmsg(b"abc"));
+        left.send(bmsg(b"def"));
+        assert_eq!(right.try_recv().unwrap().data, b"abc");
+        assert_eq!(right.try_recv().unwrap().data, b"def");
+
+        let stats = node.node.stats();
+        let remote = stats
+            .remote_nodes
+            .iter()
+            .find(|r| r.node == node2.node.id())
+            .unwrap();
+        assert_eq!(remote.state, "connected");
+        assert_eq!(remote.messages_sent, 2);
+        assert!(remote.bytes_sent > 0);
+
+        let stats2 = node2.node.stats();
+        let remote2 = stats2
+            .remote_nodes
+            .iter()
+            .find(|r| r.node == node.node.id())
+            .unwrap();
+        assert_eq!(remote2.messages_received, 2);
+        assert_eq!(remote2.bytes_received, remote.bytes_sent);
+        assert_eq!(stats2.invalid_events, 0);
+    }
+
+    /// A connection that loses all events.
+    struct LoseEvents;
+
+    impl SendEvent for LoseEvents {
+        fn event(&self, _event: OutgoingEvent<'_>) {}
+    }
+
+    fn new_reconnectable_remote(
+        node: &Arc<RemoteLocalNode>,
+        other: &Arc<RemoteLocalNode>,
+    ) -> RemoteNodeHandle {
+        let handle = node.node.add_remote(other.node.id());
+        handle.enable_reconnect();
+        handle
+    }
+
+    fn events_from(node: &Arc<RemoteLocalNode>, other: &Arc<RemoteLocalNode>) -> EventsFrom {
+        EventsFrom {
+            node_id: node.node.id(),
+            node: other.clone(),
+        }
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let node = Arc::new(RemoteLocalNode::new());
+        let node2 = Arc::new(RemoteLocalNode::new());
+        let h = new_reconnectable_remote(&node, &node2);
+        let h2 = new_reconnectable_remote(&node2, &node);
+        h.connect(events_from(&node, &node2));
+        h2.connect(events_from(&node2, &node));
+
+        let (mut left, mut right) = new_remote_port_pair(&node, &node2);
+        left.send(bmsg(b"abc"));
+        assert_eq!(right.try_recv().unwrap().data, b"abc");
+
+        // Messages sent while disconnected are delivered after reconnecting.
+        assert!(h.connection_lost(RemoteNodeDisconnected));
+        assert!(h2.connection_lost(RemoteNodeDisconnected));
+        left.send(bmsg(b"def"));
+        assert!(matches!(right.try_recv().unwrap_err(), TryRecvError::Empty));
+        h.connect(events_from(&node, &node2));
+        h2.connect(events_from(&node2, &node));
+        assert_eq!(right.try_recv().unwrap().data, b"def");
+        right.send(bmsg(b"ghi"));
+        assert_eq!(left.try_recv().unwrap().data, b"ghi");
+
+        let stats = node.node.stats();
+        assert_eq!(stats.remote_nodes[0].reconnects, 1);
+        assert_eq!(stats.failed_ports, 0);
+    }
+
+    #[test]
+    fn test_reconnect_lost_event() {
+        let node = Arc::new(RemoteLocalNode::new());
+        let node2 = Arc::new(RemoteLocalNode::new());
+        let h = new_reconnectable_remote(&node, &node2);
+        let h2 = new_reconnectable_remote(&node2, &node);
+        h.connect(LoseEvents);
+        h2.connect(events_from(&node2, &node));
+
+        let (mut left, mut right) = new_remote_port_pair(&node, &node2);
+        left.send(bmsg(b"abc"));
+        h.connection_lost(RemoteNodeDisconnected);
+        h.connect(events_from(&node, &node2));
+
+        // The lost message is detected, failing both ports.
+        assert!(matches!(
+            right.try_recv().unwrap_err(),
+            TryRecvError::Failed
+        ));
+        assert!(matches!(left.try_recv().unwrap_err(), TryRecvError::Failed));
+    }
+
+    #[test]
+    fn test_reconnect_forgets_closed_ports() {
+        let node = Arc::new(RemoteLocalNode::new());
+        let node2 = Arc::new(RemoteLocalNode::new());
+        let h = new_reconnectable_remote(&node, &node2);
+        let h2 = new_reconnectable_remote(&node2, &node);
+        h.connect(events_from(&node, &node2));
+        h2.connect(events_from(&node2, &node));
+
+        let sent_ports =
+            |h: &RemoteNodeHandle| h.remote_node.upgrade().unwrap().sent_seqs.read().len();
+
+        let (left, mut right) = new_remote_port_pair(&node, &node2);
+        left.send(bmsg(b"abc"));
+        assert_eq!(right.try_recv().unwrap().data, b"abc");
+        assert_eq!(sent_ports(&h), 1);
+
+        // Once the close is acknowledged, neither side tracks the ports.
+        drop(left);
+        assert!(matches!(
+            right.try_recv().unwrap_err(),
+            TryRecvError::Closed
+        ));
+        assert_eq!(sent_ports(&h), 0);
+        assert_eq!(sent_ports(&h2), 0);
+    }
+
     #[test]
     fn test_send_port() {
         let (node, node2, _h) = new_two_node_mesh();
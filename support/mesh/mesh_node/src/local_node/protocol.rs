@@ -36,7 +36,8 @@ fn from(value: Uuid) -> Self {
 pub struct Event {
     pub port_id: Uuid,
     pub event_type: EventType,
-    pub reserved: [u8; 7],
+    pub flags: u8,
+    pub reserved: [u8; 6],
     pub seq: u64,
     pub resource_count: u32,
     pub message_size: u32,
@@ -51,9 +52,40 @@ pub enum EventType: u8 {
         ACKNOWLEDGE_CHANGE_PEER = 4,
         ACKNOWLEDGE_PORT = 5,
         FAIL_PORT = 6,
+        COMPRESSED_MESSAGE = 7,
+        RESYNC_PORT = 8,
     }
 }
 
+impl EventType {
+    /// Returns the name of the event type, for diagnostics.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::MESSAGE => "message",
+            Self::CLOSE_PORT => "close_port",
+            Self::CHANGE_PEER => "change_peer",
+            Self::ACKNOWLEDGE_CHANGE_PEER => "acknowledge_change_peer",
+            Self::ACKNOWLEDGE_PORT => "acknowledge_port",
+            Self::FAIL_PORT => "fail_port",
+            Self::COMPRESSED_MESSAGE => "compressed_message",
+            Self::RESYNC_PORT => "resync_port",
+            _ => "unknown",
+        }
+    }
+}
+
+/// Set in [`Event::flags`] by nodes that can receive
+/// [`EventType::COMPRESSED_MESSAGE`] events.
+pub const EVENT_FLAG_COMPRESSION: u8 = 1;
+
+/// The prefix of the message data for a [`EventType::COMPRESSED_MESSAGE`]
+/// event, followed by the deflate-compressed message.
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct CompressedMessageData {
+    pub uncompressed_size: u64,
+}
+
 #[repr(C)]
 #[derive(AsBytes, FromBytes, FromZeroes)]
 pub struct ChangePeerData {
@@ -69,6 +101,19 @@ pub struct FailPortData {
     pub node: Uuid,
 }
 
+/// The data for a [`EventType::RESYNC_PORT`] event, sent for each peered port
+/// when a lost connection to a node is re-established.
+///
+/// The event's `seq` is the sequence number of the first event that the
+/// sending port will send over the new connection. The receiving port fails
+/// if any events before that were lost with the old connection.
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct ResyncPortData {
+    /// The sending port, to notify if the receiving port no longer exists.
+    pub port: Uuid,
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
 pub struct ResourceData {
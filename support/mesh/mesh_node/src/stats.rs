@@ -0,0 +1,85 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Traffic and failure counters for mesh nodes, for monitoring the health of a
+//! running mesh.
+//!
+//! Use [`LocalNode::stats`](crate::local_node::LocalNode::stats) to get the
+//! counters for a single node, or [`node_stats`] to get them for every live
+//! node in the process (e.g. to report them via inspect).
+
+use crate::common::NodeId;
+use crate::common::PortId;
+
+/// A snapshot of the counters for a local node.
+#[derive(Debug, Clone)]
+pub struct NodeStats {
+    /// The local node's ID.
+    pub node: NodeId,
+    /// The number of ports that have failed while communicating with a remote
+    /// node.
+    pub failed_ports: u64,
+    /// The number of events from remote nodes that could not be processed.
+    pub invalid_events: u64,
+    /// Counters for each known remote node.
+    pub remote_nodes: Vec<RemoteNodeStats>,
+    /// Counters for each port that is communicating with a remote node.
+    ///
+    /// Ports whose peers are local to this node are not included.
+    pub ports: Vec<PortStats>,
+}
+
+/// A snapshot of the counters for a remote node connection.
+#[derive(Debug, Clone)]
+pub struct RemoteNodeStats {
+    /// The remote node's ID.
+    pub node: NodeId,
+    /// The connection state, such as `connected` or `reconnecting`.
+    pub state: &'static str,
+    /// The number of events sent to the remote node.
+    pub events_sent: u64,
+    /// The number of events received from the remote node.
+    pub events_received: u64,
+    /// The number of messages sent to the remote node.
+    pub messages_sent: u64,
+    /// The number of messages received from the remote node.
+    pub messages_received: u64,
+    /// The number of bytes of events sent to the remote node.
+    pub bytes_sent: u64,
+    /// The number of bytes of events received from the remote node.
+    pub bytes_received: u64,
+    /// The number of resources (ports and OS resources) sent to the remote
+    /// node.
+    pub resources_sent: u64,
+    /// The number of resources received from the remote node.
+    pub resources_received: u64,
+    /// The number of events that had to be queued because the connection to
+    /// the remote node was not yet established.
+    pub deferred_events: u64,
+    /// The number of times the connection to the remote node was
+    /// re-established after being lost.
+    pub reconnects: u64,
+}
+
+/// A snapshot of the counters for a port.
+#[derive(Debug, Clone)]
+pub struct PortStats {
+    /// The port's ID.
+    pub port: PortId,
+    /// The port state, such as `peered` or `proxying`.
+    pub state: &'static str,
+    /// The node of the port's peer, if the port is peered with a remote port.
+    pub peer_node: Option<NodeId>,
+    /// The number of messages sent from the port.
+    pub messages_sent: u64,
+    /// The number of messages received by the port.
+    pub messages_received: u64,
+    /// The number of events received out of order and waiting for earlier
+    /// events.
+    pub pending_events: usize,
+}
+
+/// Returns the counters for each live node in the process.
+pub fn node_stats() -> Vec<NodeStats> {
+    crate::local_node::all_node_stats()
+}
@@ -61,6 +61,23 @@
 /// and use it to break into the mesh.
 const INVITATION_ENV_NAME: &str = "MESH_WORKER_INVITATION";
 
+/// The environment variable that, when set to a non-empty value, enables
+/// dumping each node's recent mesh events to stderr when a process panics.
+/// Child processes inherit it, so setting it for the top-level process enables
+/// it for the whole mesh.
+const DUMP_EVENTS_ON_PANIC_ENV_NAME: &str = "MESH_DUMP_EVENTS_ON_PANIC";
+
+/// Installs the flight recorder panic hook if it was requested via
+/// [`DUMP_EVENTS_ON_PANIC_ENV_NAME`].
+///
+/// This is off by default since the dump is verbose, and the same events are
+/// available via inspect.
+fn maybe_install_panic_hook() {
+    if std::env::var_os(DUMP_EVENTS_ON_PANIC_ENV_NAME).map_or(false, |x| !x.is_empty()) {
+        mesh::flight_recorder::install_panic_hook();
+    }
+}
+
 #[derive(Protobuf)]
 struct Invitation {
     node_name: String,
@@ -89,6 +106,7 @@ pub fn try_run_mesh_host<U, Fut, F, T>(base_name: &str, f: F) -> anyhow::Result<
 {
     block_on(async {
         if let Some(r) = node_from_environment().await? {
+            maybe_install_panic_hook();
             let NodeResult {
                 node_name,
                 node,
@@ -385,6 +403,8 @@ fn inspect(&self, req: inspect::Request<'_>) {
 impl Mesh {
     /// Creates a new mesh with the given name.
     pub fn new(mesh_name: String) -> anyhow::Result<Self> {
+        maybe_install_panic_hook();
+
         #[cfg(windows)]
         let job = {
             let job = pal::windows::job::Job::new().context("failed to create job object")?;
@@ -508,7 +528,74 @@ enum HostRequest {
 }
 
 fn inspect_host(resp: &mut inspect::Response<'_>) {
-    resp.field("tasks", inspect_task::inspect_task_list());
+    resp.field("tasks", inspect_task::inspect_task_list())
+        .field("mesh_events", inspect::adhoc(inspect_mesh_events))
+        .field("mesh_stats", inspect::adhoc(inspect_mesh_stats));
+}
+
+fn inspect_mesh_stats(req: inspect::Request<'_>) {
+    let mut resp = req.respond();
+    for node in mesh::stats::node_stats() {
+        resp.child(&format!("{:?}", node.node), |req| {
+            let mut resp = req.respond();
+            resp.counter("failed_ports", node.failed_ports)
+                .counter("invalid_events", node.invalid_events)
+                .child("remote_nodes", |req| {
+                    let mut resp = req.respond();
+                    for remote in &node.remote_nodes {
+                        resp.child(&format!("{:?}", remote.node), |req| {
+                            req.respond()
+                                .field("state", remote.state)
+                                .counter("events_sent", remote.events_sent)
+                                .counter("events_received", remote.events_received)
+                                .counter("messages_sent", remote.messages_sent)
+                                .counter("messages_received", remote.messages_received)
+                                .counter("bytes_sent", remote.bytes_sent)
+                                .counter("bytes_received", remote.bytes_received)
+                                .counter("resources_sent", remote.resources_sent)
+                                .counter("resources_received", remote.resources_received)
+                                .counter("deferred_events", remote.deferred_events)
+                                .counter("reconnects", remote.reconnects);
+                        });
+                    }
+                })
+                .child("ports", |req| {
+                    let mut resp = req.respond();
+                    for port in &node.ports {
+                        resp.child(&format!("{:?}", port.port), |req| {
+                            req.respond()
+                                .field("state", port.state)
+                                .display_debug("peer_node", &port.peer_node)
+                                .counter("messages_sent", port.messages_sent)
+                                .counter("messages_received", port.messages_received)
+                                .field("pending_events", port.pending_events);
+                        });
+                    }
+                });
+        });
+    }
+}
+
+fn inspect_mesh_events(req: inspect::Request<'_>) {
+    let now = std::time::Instant::now();
+    let mut resp = req.respond();
+    for (node, events) in mesh::flight_recorder::recent_events() {
+        resp.child(&format!("{node:?}"), |req| {
+            let mut resp = req.respond();
+            for (i, event) in events.iter().enumerate() {
+                resp.child(&i.to_string(), |req| {
+                    req.respond()
+                        .display_debug("age", &(now - event.time))
+                        .display_debug("direction", &event.direction)
+                        .field("event_type", event.event_type)
+                        .display_debug("port", &event.port)
+                        .display_debug("peer", &event.peer)
+                        .field("seq", event.seq)
+                        .field("size", event.size);
+                });
+            }
+        });
+    }
 }
 
 #[derive(Inspect)]
@@ -5,8 +5,10 @@
 //! to generate `.proto` files that are binary compatible with the associated
 //! Rust types.
 
+mod text;
 mod writer;
 
+pub use text::message_to_text;
 #[cfg(feature = "std")]
 pub use writer::DescriptorWriter;
 
@@ -0,0 +1,429 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Code to decode encoded messages to a human-readable text form, for
+//! debugging.
+
+use super::FieldDescriptor;
+use super::FieldKind;
+use super::FieldType;
+use super::MessageDescription;
+use super::MessageDescriptor;
+use super::SequenceType;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+/// The maximum nesting depth of messages to decode. Deeper messages are
+/// elided, so that a malicious or corrupt message cannot overflow the stack.
+const MAX_DEPTH: usize = 64;
+
+/// Decodes an encoded message to a JSON-like text form, for debugging.
+///
+/// If `description` is provided, it is used to name the message's fields and
+/// to interpret their values. Otherwise, or for any field that doesn't match
+/// the description, the field is shown by number and its value is interpreted
+/// on a best-effort basis from the wire format.
+///
+/// Resources (such as ports and OS handles) are not part of the encoded data
+/// and are shown as placeholders.
+pub fn message_to_text(data: &[u8], description: Option<MessageDescription<'_>>) -> String {
+    let message = match description {
+        Some(MessageDescription::Internal(tld)) => Some(tld.message),
+        Some(MessageDescription::External { .. }) | None => None,
+    };
+    let mut s = String::new();
+    match parse_fields(data) {
+        Some(fields) => write_message(&mut s, &fields, message, 0),
+        None => write_bytes(&mut s, data),
+    }
+    s
+}
+
+#[derive(Copy, Clone)]
+enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Variable(&'a [u8]),
+    Fixed32(u32),
+    MeshMessage { data: &'a [u8], resources: u64 },
+    Resource,
+}
+
+fn read_varint(data: &mut &[u8]) -> Option<u64> {
+    let mut r = 0;
+    for shift in (0..64).step_by(7) {
+        let (&b, rest) = data.split_first()?;
+        *data = rest;
+        r |= (b as u64 & 0x7f) << shift;
+        if b & 0x80 == 0 {
+            return Some(r);
+        }
+    }
+    None
+}
+
+fn read_bytes<'a>(data: &mut &'a [u8], len: u64) -> Option<&'a [u8]> {
+    let len = usize::try_from(len).ok()?;
+    if data.len() < len {
+        return None;
+    }
+    let (v, rest) = data.split_at(len);
+    *data = rest;
+    Some(v)
+}
+
+/// Parses the wire format of a message, returning `None` if it is malformed.
+fn parse_fields(mut data: &[u8]) -> Option<Vec<(u32, WireValue<'_>)>> {
+    let mut fields = Vec::new();
+    while !data.is_empty() {
+        let key = read_varint(&mut data)?;
+        let number = u32::try_from(key >> 3).ok().filter(|&n| n != 0)?;
+        let value = match key & 7 {
+            0 => WireValue::Varint(read_varint(&mut data)?),
+            1 => WireValue::Fixed64(u64::from_le_bytes(
+                read_bytes(&mut data, 8)?.try_into().unwrap(),
+            )),
+            2 => {
+                let len = read_varint(&mut data)?;
+                WireValue::Variable(read_bytes(&mut data, len)?)
+            }
+            5 => WireValue::Fixed32(u32::from_le_bytes(
+                read_bytes(&mut data, 4)?.try_into().unwrap(),
+            )),
+            6 => {
+                let resources = read_varint(&mut data)?;
+                let len = read_varint(&mut data)?;
+                WireValue::MeshMessage {
+                    data: read_bytes(&mut data, len)?,
+                    resources,
+                }
+            }
+            7 => WireValue::Resource,
+            _ => return None,
+        };
+        fields.push((number, value));
+    }
+    Some(fields)
+}
+
+fn write_message(
+    s: &mut String,
+    fields: &[(u32, WireValue<'_>)],
+    message: Option<&MessageDescriptor<'_>>,
+    depth: usize,
+) {
+    if fields.is_empty() {
+        s.push_str("{}");
+        return;
+    }
+    if depth >= MAX_DEPTH {
+        s.push_str("{ ... }");
+        return;
+    }
+    let depth = depth + 1;
+    s.push_str("{ ");
+    for (i, &(number, value)) in fields.iter().enumerate() {
+        if i > 0 {
+            s.push_str(", ");
+        }
+        let field = message.and_then(|message| find_field(message, number));
+        match field {
+            Some(field) => {
+                s.push_str(field.name);
+                s.push_str(": ");
+                write_field(s, value, &field.field_type, message, depth);
+            }
+            None => {
+                let _ = write!(s, "#{number}: ");
+                write_untyped(s, value, depth);
+            }
+        }
+    }
+    s.push_str(" }");
+}
+
+fn find_field<'a>(message: &MessageDescriptor<'a>, number: u32) -> Option<&'a FieldDescriptor<'a>> {
+    message
+        .fields
+        .iter()
+        .chain(message.oneofs.iter().flat_map(|oneof| oneof.variants))
+        .find(|field| field.field_number == number)
+}
+
+fn write_field(
+    s: &mut String,
+    value: WireValue<'_>,
+    field_type: &FieldType<'_>,
+    parent: Option<&MessageDescriptor<'_>>,
+    depth: usize,
+) {
+    match field_type.sequence_type {
+        Some(SequenceType::Map(key)) => {
+            let key = FieldType::builtin(key);
+            let value_type = FieldType {
+                sequence_type: None,
+                ..*field_type
+            };
+            write_tuple(
+                s,
+                value,
+                &[key, value_type],
+                &["key", "value"],
+                parent,
+                depth,
+            );
+        }
+        Some(SequenceType::Repeated) => match (field_type.kind, value) {
+            // Repeated scalars may be packed into a single field.
+            (FieldKind::Builtin(ty), WireValue::Variable(data))
+                if !matches!(ty, "string" | "bytes") =>
+            {
+                write_packed(s, data, ty, depth)
+            }
+            _ => write_value(s, value, field_type.kind, parent, depth),
+        },
+        _ => write_value(s, value, field_type.kind, parent, depth),
+    }
+}
+
+fn write_value(
+    s: &mut String,
+    value: WireValue<'_>,
+    kind: FieldKind<'_>,
+    parent: Option<&MessageDescriptor<'_>>,
+    depth: usize,
+) {
+    match kind {
+        FieldKind::Builtin(ty) => {
+            if !write_builtin(s, value, ty) {
+                write_untyped(s, value, depth);
+            }
+        }
+        FieldKind::Message(f) => match f() {
+            MessageDescription::Internal(tld) => write_nested(s, value, Some(tld.message), depth),
+            MessageDescription::External { .. } => write_untyped(s, value, depth),
+        },
+        FieldKind::Local(name) => {
+            let message = parent
+                .and_then(|parent| parent.messages.iter().find(|message| message.name == name));
+            write_nested(s, value, message, depth)
+        }
+        FieldKind::Tuple(field_types) => {
+            let names = (1..=field_types.len())
+                .map(|i| format!("field{i}"))
+                .collect::<Vec<_>>();
+            let names = names.iter().map(|n| n.as_str()).collect::<Vec<_>>();
+            write_tuple(s, value, field_types, &names, parent, depth);
+        }
+        FieldKind::KeyValue(field_types) => {
+            write_tuple(s, value, field_types, &["key", "value"], parent, depth)
+        }
+        FieldKind::External { .. } => write_untyped(s, value, depth),
+    }
+}
+
+/// Writes a value whose type is an anonymous message with the given fields.
+fn write_tuple(
+    s: &mut String,
+    value: WireValue<'_>,
+    field_types: &[FieldType<'_>],
+    names: &[&str],
+    parent: Option<&MessageDescriptor<'_>>,
+    depth: usize,
+) {
+    let fields = field_types
+        .iter()
+        .zip(names)
+        .enumerate()
+        .map(|(i, (&ty, name))| FieldDescriptor::new("", ty, name, i as u32 + 1))
+        .collect::<Vec<_>>();
+    // Nested local types are resolved relative to the enclosing message.
+    let messages = parent.map_or(&[][..], |parent| parent.messages);
+    let message = MessageDescriptor::new("", "", &fields, &[], messages);
+    write_nested(s, value, Some(&message), depth);
+}
+
+fn write_nested(
+    s: &mut String,
+    value: WireValue<'_>,
+    message: Option<&MessageDescriptor<'_>>,
+    depth: usize,
+) {
+    let data = match value {
+        WireValue::Variable(data) | WireValue::MeshMessage { data, .. } => data,
+        _ => return write_untyped(s, value, depth),
+    };
+    match parse_fields(data) {
+        Some(fields) => write_message(s, &fields, message, depth),
+        None => write_untyped(s, value, depth),
+    }
+}
+
+fn write_packed(s: &mut String, packed: &[u8], ty: &str, depth: usize) {
+    let start = s.len();
+    s.push('[');
+    let mut first = true;
+    let mut data = packed;
+    while !data.is_empty() {
+        let value = match ty {
+            "double" | "fixed64" | "sfixed64" => read_bytes(&mut data, 8)
+                .map(|v| WireValue::Fixed64(u64::from_le_bytes(v.try_into().unwrap()))),
+            "float" | "fixed32" | "sfixed32" => read_bytes(&mut data, 4)
+                .map(|v| WireValue::Fixed32(u32::from_le_bytes(v.try_into().unwrap()))),
+            _ => read_varint(&mut data).map(WireValue::Varint),
+        };
+        let Some(value) = value else {
+            s.truncate(start);
+            return write_bytes(s, packed);
+        };
+        if !first {
+            s.push_str(", ");
+        }
+        first = false;
+        if !write_builtin(s, value, ty) {
+            write_untyped(s, value, depth);
+        }
+    }
+    s.push(']');
+}
+
+/// Writes a value of builtin type `ty`, returning false if the wire type does
+/// not match.
+fn write_builtin(s: &mut String, value: WireValue<'_>, ty: &str) -> bool {
+    let _ = match (ty, value) {
+        ("bool", WireValue::Varint(n)) => write!(s, "{}", n != 0),
+        ("uint32" | "uint64", WireValue::Varint(n)) => write!(s, "{n}"),
+        ("int32", WireValue::Varint(n)) => write!(s, "{}", n as i32),
+        ("int64", WireValue::Varint(n)) => write!(s, "{}", n as i64),
+        ("sint32" | "sint64", WireValue::Varint(n)) => {
+            write!(s, "{}", ((n >> 1) as i64) ^ -((n & 1) as i64))
+        }
+        ("fixed64", WireValue::Fixed64(n)) => write!(s, "{n}"),
+        ("sfixed64", WireValue::Fixed64(n)) => write!(s, "{}", n as i64),
+        ("double", WireValue::Fixed64(n)) => write!(s, "{}", f64::from_bits(n)),
+        ("fixed32", WireValue::Fixed32(n)) => write!(s, "{n}"),
+        ("sfixed32", WireValue::Fixed32(n)) => write!(s, "{}", n as i32),
+        ("float", WireValue::Fixed32(n)) => write!(s, "{}", f32::from_bits(n)),
+        ("string", WireValue::Variable(data)) => match core::str::from_utf8(data) {
+            Ok(v) => write!(s, "{v:?}"),
+            Err(_) => return false,
+        },
+        ("bytes", WireValue::Variable(data)) => {
+            write_bytes(s, data);
+            Ok(())
+        }
+        _ => return false,
+    };
+    true
+}
+
+/// Writes a value without any type information.
+fn write_untyped(s: &mut String, value: WireValue<'_>, depth: usize) {
+    let _ = match value {
+        WireValue::Varint(n) => write!(s, "{n}"),
+        WireValue::Fixed64(n) => write!(s, "{n:#x}"),
+        WireValue::Fixed32(n) => write!(s, "{n:#x}"),
+        WireValue::Variable(data) => {
+            // Guess at the contents. Prefer strings since short strings often
+            // also parse as messages.
+            if let Some(v) = core::str::from_utf8(data)
+                .ok()
+                .filter(|v| !v.chars().any(|c| c.is_control()))
+            {
+                write!(s, "{v:?}")
+            } else if let Some(fields) = parse_fields(data) {
+                write_message(s, &fields, None, depth);
+                Ok(())
+            } else {
+                write_bytes(s, data);
+                Ok(())
+            }
+        }
+        WireValue::MeshMessage { data, resources } => {
+            let _ = write!(s, "<{resources} resources> ");
+            match parse_fields(data) {
+                Some(fields) => write_message(s, &fields, None, depth),
+                None => write_bytes(s, data),
+            }
+            Ok(())
+        }
+        WireValue::Resource => write!(s, "<resource>"),
+    };
+}
+
+fn write_bytes(s: &mut String, data: &[u8]) {
+    s.push_str("b\"");
+    let _ = write!(s, "{}", data.escape_ascii());
+    s.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::message_to_text;
+    use crate::protofile::message_description;
+    use crate::Protobuf;
+    use alloc::string::String;
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    #[derive(Protobuf)]
+    #[mesh(package = "test")]
+    struct Inner {
+        #[mesh(1)]
+        name: String,
+    }
+
+    #[derive(Protobuf)]
+    #[mesh(package = "test")]
+    struct Outer {
+        #[mesh(1)]
+        count: u32,
+        #[mesh(2)]
+        values: Vec<i64>,
+        #[mesh(3)]
+        inner: Inner,
+        #[mesh(4)]
+        flag: bool,
+    }
+
+    #[test]
+    fn test_message_to_text() {
+        let data = crate::encode(Outer {
+            count: 5,
+            values: vec![-1, 2],
+            inner: Inner { name: "abc".into() },
+            flag: true,
+        });
+        assert_eq!(
+            message_to_text(&data, Some(message_description::<Outer>())),
+            r#"{ count: 5, values: [-1, 2], inner: { name: "abc" }, flag: true }"#
+        );
+        assert_eq!(
+            message_to_text(&data, None),
+            r#"{ #1: 5, #2: b"\x01\x04", #3: { #1: "abc" }, #4: 1 }"#
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested() {
+        // Each level is a message whose field 1 is the next level, ending
+        // with an integer.
+        let mut data = vec![0x08, 0x01];
+        for _ in 0..10000 {
+            let mut outer = vec![0x0a];
+            let mut len = data.len();
+            while len >= 0x80 {
+                outer.push(len as u8 | 0x80);
+                len >>= 7;
+            }
+            outer.push(len as u8);
+            outer.extend_from_slice(&data);
+            data = outer;
+        }
+        let text = message_to_text(&data, None);
+        assert!(text.starts_with("{ #1: { #1: "));
+        assert!(text.contains("{ ... }"));
+    }
+}
@@ -7,7 +7,9 @@
 mod common;
 mod point_to_point;
 mod protocol;
+mod tcp;
 mod unix_node;
+mod vsock;
 
 #[cfg(windows)]
 pub mod windows {
@@ -42,8 +42,11 @@
 /// port, for example.
 ///
 /// There is no support for OS resources (handles or file descriptors) in this
-/// mesh implementation. Any attempt to send OS resources will fail the
-/// underlying channel.
+/// mesh implementation. Ports can still be sent, but when a message contains
+/// OS resources, the resources are closed on the sending side and the message
+/// is sent without them. The receiver then fails to decode the message and
+/// observes a [`mesh_channel::RecvError::Error`] on the channel, rather than
+/// the whole mesh failing.
 #[must_use]
 pub struct PointToPointMesh {
     task: Task<()>,
@@ -258,8 +261,11 @@ fn event(&self, event: OutgoingEvent<'_>) {
             // Still send the message so that the receiving side gets an error
             // when decoding. Otherwise, the only other option at this point is
             // to fail the whole connection, which is probably not what you
-            // want.
-            tracing::warn!("cannot send OS resources across a point-to-point connection");
+            // want. The resources are closed when dropped here.
+            tracing::warn!(
+                count = resources.len(),
+                "cannot send OS resources across a point-to-point connection"
+            );
         }
         self.0.send(v);
     }
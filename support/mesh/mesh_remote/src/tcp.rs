@@ -0,0 +1,163 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! TCP transport for point-to-point meshes.
+//!
+//! This allows a two-node mesh to span machines, e.g. to run workers on a
+//! remote host. The connection can optionally be upgraded before the mesh
+//! starts (for example, to wrap it in TLS) via the `_with` variants of the
+//! constructors, which keeps this crate independent of any particular TLS
+//! implementation.
+//!
+//! OS resources cannot cross a TCP connection. See [`PointToPointMesh`] for
+//! how messages containing them are handled.
+
+use crate::PointToPointMesh;
+use futures::AsyncRead;
+use futures::AsyncWrite;
+use mesh_node::local_node::Port;
+use pal_async::driver::Driver;
+use pal_async::socket::PolledSocket;
+use pal_async::task::Spawn;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::net::TcpListener;
+use std::net::TcpStream;
+
+impl PointToPointMesh {
+    /// Connects to a remote node listening on `addr` and makes a new mesh
+    /// over the connection, with initial port `port`.
+    pub async fn connect_tcp(
+        driver: &(impl Driver + Spawn),
+        addr: SocketAddr,
+        port: Port,
+    ) -> io::Result<Self> {
+        Self::connect_tcp_with(driver, addr, port, |socket| async { Ok(socket) }).await
+    }
+
+    /// Like [`Self::connect_tcp`], but calls `upgrade` on the connected socket
+    /// before starting the mesh.
+    ///
+    /// Use this to establish a secure channel, such as TLS, over the
+    /// connection.
+    pub async fn connect_tcp_with<F, Fut, S>(
+        driver: &(impl Driver + Spawn),
+        addr: SocketAddr,
+        port: Port,
+        upgrade: F,
+    ) -> io::Result<Self>
+    where
+        F: FnOnce(PolledSocket<TcpStream>) -> Fut,
+        Fut: Future<Output = io::Result<S>>,
+        S: 'static + AsyncRead + AsyncWrite + Send + Unpin,
+    {
+        let socket = socket2::Socket::new(
+            socket2::Domain::for_address(addr),
+            socket2::Type::STREAM,
+            Some(socket2::Protocol::TCP),
+        )?;
+        let mut socket = PolledSocket::new(driver, socket)?;
+        socket.connect(&addr.into()).await?;
+        let socket = tcp_stream(socket.convert())?;
+        let conn = upgrade(socket).await?;
+        Ok(Self::new(driver, conn, port))
+    }
+
+    /// Accepts a connection from a remote node on `listener` and makes a new
+    /// mesh over the connection, with initial port `port`.
+    ///
+    /// Returns the mesh and the address of the remote node.
+    pub async fn accept_tcp(
+        driver: &(impl Driver + Spawn),
+        listener: &mut PolledSocket<TcpListener>,
+        port: Port,
+    ) -> io::Result<(Self, SocketAddr)> {
+        Self::accept_tcp_with(driver, listener, port, |socket| async { Ok(socket) }).await
+    }
+
+    /// Like [`Self::accept_tcp`], but calls `upgrade` on the accepted socket
+    /// before starting the mesh.
+    ///
+    /// Use this to establish a secure channel, such as TLS, over the
+    /// connection, and to authenticate the remote node.
+    pub async fn accept_tcp_with<F, Fut, S>(
+        driver: &(impl Driver + Spawn),
+        listener: &mut PolledSocket<TcpListener>,
+        port: Port,
+        upgrade: F,
+    ) -> io::Result<(Self, SocketAddr)>
+    where
+        F: FnOnce(PolledSocket<TcpStream>) -> Fut,
+        Fut: Future<Output = io::Result<S>>,
+        S: 'static + AsyncRead + AsyncWrite + Send + Unpin,
+    {
+        let (socket, remote_addr) = listener.accept().await?;
+        let socket = tcp_stream(PolledSocket::new(driver, socket)?)?;
+        let conn = upgrade(socket).await?;
+        Ok((Self::new(driver, conn, port), remote_addr))
+    }
+}
+
+fn tcp_stream(socket: PolledSocket<TcpStream>) -> io::Result<PolledSocket<TcpStream>> {
+    // Mesh events are typically small and latency sensitive.
+    socket.get().set_nodelay(true)?;
+    Ok(socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::PointToPointMesh;
+    use mesh_channel::channel;
+    use mesh_channel::RecvError;
+    use pal_async::async_test;
+    use pal_async::socket::PolledSocket;
+    use pal_async::DefaultDriver;
+    use std::net::Ipv4Addr;
+    use std::net::TcpListener;
+    use test_with_tracing::test;
+
+    #[async_test]
+    async fn test_tcp(driver: DefaultDriver) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = PolledSocket::new(&driver, listener).unwrap();
+
+        let (a, ax) = channel::<u32>();
+        let (bx, mut b) = channel::<u32>();
+        let ((left, _), right) = futures::future::try_join(
+            PointToPointMesh::accept_tcp(&driver, &mut listener, ax.into()),
+            PointToPointMesh::connect_tcp(&driver, addr, bx.into()),
+        )
+        .await
+        .unwrap();
+
+        a.send(5);
+        assert_eq!(b.recv().await.unwrap(), 5);
+        left.shutdown().await;
+        right.shutdown().await;
+    }
+
+    #[async_test]
+    async fn test_tcp_os_resource(driver: DefaultDriver) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let mut listener = PolledSocket::new(&driver, listener).unwrap();
+
+        let (a, ax) = channel::<std::fs::File>();
+        let (bx, mut b) = channel::<std::fs::File>();
+        let ((left, _), right) = futures::future::try_join(
+            PointToPointMesh::accept_tcp(&driver, &mut listener, ax.into()),
+            PointToPointMesh::connect_tcp(&driver, addr, bx.into()),
+        )
+        .await
+        .unwrap();
+
+        // The file cannot cross the connection, so the receiver gets an error
+        // instead of the message.
+        a.send(std::fs::File::open(std::env::current_exe().unwrap()).unwrap());
+        assert!(matches!(b.recv().await.unwrap_err(), RecvError::Error(_)));
+        left.shutdown().await;
+        right.shutdown().await;
+    }
+}
@@ -48,9 +48,11 @@
 use pal_async::socket::PolledSocket;
 use pal_async::task::Spawn;
 use pal_async::task::Task;
+use pal_async::timer::PolledTimer;
 use parking_lot::Mutex;
 use socket2::Socket;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::future::poll_fn;
@@ -61,6 +63,8 @@
 use std::os::unix::prelude::*;
 use std::pin::pin;
 use std::sync::Arc;
+use std::sync::Weak;
+use std::time::Duration;
 use thiserror::Error;
 use tracing::instrument;
 use unicycle::FuturesUnordered;
@@ -89,6 +93,10 @@
 
 const MAX_SMALL_EVENT_SIZE: usize = MAX_PACKET_SIZE - size_of::<protocol::PacketHeader>();
 
+/// How long to wait for the leader to re-establish a lost connection between
+/// two followers before failing it.
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// A node within a mesh that uses Unix sockets to communicate.
 ///
 /// Each pairwise connection between two nodes in the mesh communicates via a
@@ -98,6 +106,10 @@
 /// sends a request to the leader node to establish a connection. The leader
 /// creates a new socket pair and sends one end to each of the nodes, which the
 /// two nodes can use to communicate.
+///
+/// If a connection made this way is lost, both nodes ask the leader for a new
+/// one, and the ports peered across the connection continue if no events were
+/// lost with it. Connections to the leader itself are not re-established.
 pub struct UnixNode {
     driver: Arc<dyn SpawnDriver>,
     local_node: Arc<LocalNode>,
@@ -117,17 +129,23 @@ pub struct UnixNode {
 enum LeaderRequest {
     Connect(NodeId),
     Invite(Port, mesh_channel::Sender<Invitation>),
+    /// The connection to the node was lost. The leader provides a new one once
+    /// both nodes have asked for it.
+    Reconnect(NodeId),
 }
 
 #[derive(Debug, Protobuf)]
 #[mesh(resource = "Resource")]
 enum FollowerRequest {
+    /// A connection to the node, and whether it should be re-established if
+    /// lost.
     Connect(
         NodeId,
         #[mesh(
             encoding = "mesh_protobuf::encoding::OptionField<mesh_protobuf::encoding::ResourceField<OwnedFd>>"
         )]
         Option<Socket>,
+        bool,
     ),
 }
 
@@ -156,11 +174,18 @@ async fn run_follower(
     local_node: &Arc<LocalNode>,
     mut recv: mesh_channel::Receiver<FollowerRequest>,
     pending_connections: Arc<Mutex<HashMap<NodeId, RemoteNodeHandle>>>,
+    reconnector: Reconnector,
     tasks: &mesh_channel::Sender<SmallTask>,
 ) {
     while let Ok(req) = recv.recv().await {
         match req {
-            FollowerRequest::Connect(target_id, fd) => {
+            FollowerRequest::Connect(target_id, fd, reconnect) => {
+                if let Some(send) = reconnector.take_pending(&target_id) {
+                    tracing::debug!(?target_id, "got reconnection from leader");
+                    let _ = send.send(fd);
+                    continue;
+                }
+
                 tracing::debug!(?target_id, "got connection request from leader");
                 let handle = pending_connections.lock().remove(&target_id);
                 let handle = handle.unwrap_or_else(|| local_node.get_remote_handle(target_id));
@@ -172,6 +197,7 @@ async fn run_follower(
                         target_id,
                         handle,
                         UnixSocket::new(driver, fd),
+                        reconnect.then(|| reconnector.clone()),
                     );
                 } else {
                     tracing::warn!(?target_id, "leader provided failed connection");
@@ -179,6 +205,42 @@ async fn run_follower(
             }
         }
     }
+    // Without a leader, lost connections can no longer be re-established.
+    reconnector.close();
+}
+
+/// Sends a new connection between `remote_id` and `target_id` to both nodes, or
+/// a failed connection to `remote_id` if the target is unknown.
+fn connect_followers(
+    senders: &HashMap<NodeId, mesh_channel::Sender<FollowerRequest>>,
+    remote_id: NodeId,
+    target_id: NodeId,
+    reconnect: bool,
+) {
+    let remote = senders
+        .get(&remote_id)
+        .expect("sender must exist to receive from it");
+    let mut fd = None;
+    if let Some(target) = senders.get(&target_id) {
+        match new_socket_pair() {
+            Ok((left, right)) => {
+                tracing::trace!(?target, "send to");
+                target.send(FollowerRequest::Connect(remote_id, Some(left), reconnect));
+                fd = Some(right);
+            }
+            Err(err) => {
+                tracing::warn!(
+                    ?target_id,
+                    ?remote_id,
+                    error = &err as &dyn std::error::Error,
+                    "failed to create socket pair for connection request"
+                );
+            }
+        }
+    } else {
+        tracing::warn!(?target_id, ?remote_id, "could not find target for remote");
+    }
+    remote.send(FollowerRequest::Connect(target_id, fd, reconnect));
 }
 
 /// Processes incoming requests from a follower to the leader. Runs until there
@@ -194,6 +256,9 @@ async fn run_leader(
 ) {
     let mut senders = HashMap::new();
     let mut receivers = Vec::new();
+    // Followers that lost their connection to a target and are waiting for
+    // the target to ask for a new one, as (follower, target).
+    let mut reconnects = HashSet::new();
     for (remote_id, recv, send) in followers.list {
         receivers.push((remote_id, recv));
         senders.insert(remote_id, send);
@@ -213,30 +278,22 @@ async fn run_leader(
             Ok(req) => match req {
                 LeaderRequest::Connect(target_id) => {
                     tracing::debug!(?target_id, ?remote_id, "connection request");
-                    let remote = senders
-                        .get(&remote_id)
-                        .expect("sender must exist to receive from it");
-                    let mut fd = None;
-                    if let Some(target) = senders.get(&target_id) {
-                        match new_socket_pair() {
-                            Ok((left, right)) => {
-                                tracing::trace!(?target, "send to");
-                                target.send(FollowerRequest::Connect(remote_id, Some(left)));
-                                fd = Some(right);
-                            }
-                            Err(err) => {
-                                tracing::warn!(
-                                    ?target_id,
-                                    ?remote_id,
-                                    error = &err as &dyn std::error::Error,
-                                    "failed to create socket pair for connection request"
-                                );
-                            }
-                        }
+                    // Connections to this node cannot be re-established, since
+                    // the requests to do so would be sent over them.
+                    let reconnect = remote_id != local_node.id() && target_id != local_node.id();
+                    connect_followers(&senders, remote_id, target_id, reconnect);
+                }
+                LeaderRequest::Reconnect(target_id) => {
+                    tracing::debug!(?target_id, ?remote_id, "reconnection request");
+                    if reconnects.remove(&(target_id, remote_id))
+                        || !senders.contains_key(&target_id)
+                    {
+                        // Both nodes have lost the connection, or the target
+                        // is gone and the remote will get a failed connection.
+                        connect_followers(&senders, remote_id, target_id, true);
                     } else {
-                        tracing::warn!(?target_id, ?remote_id, "could not find target for remote");
+                        reconnects.insert((remote_id, target_id));
                     }
-                    remote.send(FollowerRequest::Connect(target_id, fd));
                 }
                 LeaderRequest::Invite(port, send) => {
                     tracing::debug!(?remote_id, "invitation request");
@@ -261,6 +318,7 @@ async fn run_leader(
                                 remote_addr.node,
                                 handle,
                                 UnixSocket::new(driver, left),
+                                None,
                             );
                             local_node
                                 .add_port(local_port_id, remote_addr)
@@ -304,10 +362,26 @@ async fn run_leader(
                 }
                 senders.remove(&remote_id);
                 receivers.swap_remove(index);
+                // Fail any reconnections waiting on the removed follower.
+                reconnects.retain(|&(follower_id, target_id)| {
+                    if target_id == remote_id {
+                        if let Some(send) = senders.get(&follower_id) {
+                            send.send(FollowerRequest::Connect(target_id, None, true));
+                        }
+                    }
+                    follower_id != remote_id && target_id != remote_id
+                });
             }
         }
     };
 
+    // The new leader does not know about waiting reconnections, so fail them.
+    for (follower_id, target_id) in reconnects {
+        if let Some(send) = senders.get(&follower_id) {
+            send.send(FollowerRequest::Connect(target_id, None, true));
+        }
+    }
+
     if let Some((new_leader_id, new_leader_followers_sink)) = new_leader_info {
         if let Some(new_leader_send) = senders.get(&new_leader_id) {
             tracing::debug!(?new_leader_id, "resigning leadership");
@@ -317,8 +391,12 @@ async fn run_leader(
                 if new_leader_id != *remote_id {
                     match new_socket_pair() {
                         Ok((left, right)) => {
-                            send.send(FollowerRequest::Connect(new_leader_id, Some(left)));
-                            new_leader_send.send(FollowerRequest::Connect(*remote_id, Some(right)));
+                            send.send(FollowerRequest::Connect(new_leader_id, Some(left), false));
+                            new_leader_send.send(FollowerRequest::Connect(
+                                *remote_id,
+                                Some(right),
+                                false,
+                            ));
                         }
                         Err(err) => {
                             tracing::error!(
@@ -495,12 +573,16 @@ fn drop(&mut self) {
 }
 
 /// Starts a connection processing task.
+///
+/// If `reconnector` is provided, then the connection is re-established via the
+/// leader if it is lost.
 fn start_connection(
     tasks: &mesh_channel::Sender<SmallTask>,
     local_node: &Arc<LocalNode>,
     remote_id: NodeId,
     handle: RemoteNodeHandle,
     socket: UnixSocket,
+    reconnector: Option<Reconnector>,
 ) {
     #[allow(clippy::disallowed_methods)] // TODO
     let (send, recv) = mpsc::unbounded();
@@ -509,10 +591,21 @@ fn start_connection(
         send: send.clone(),
         socket: socket.clone(),
     };
+    if reconnector.is_some() {
+        handle.enable_reconnect();
+    }
     if handle.connect(sender) {
         let task = SmallTask::new("run_connection", {
             let local_node = local_node.clone();
-            run_connection(local_node, remote_id, send, recv, socket, handle)
+            run_connection(
+                local_node,
+                remote_id,
+                send,
+                recv,
+                socket,
+                handle,
+                reconnector,
+            )
         });
         tasks.send(task);
         tracing::debug!(?remote_id, "connected");
@@ -524,19 +617,80 @@ fn start_connection(
     }
 }
 
-/// Runs the packet processing loop.
+/// Runs the packet processing loop, re-establishing the connection via
+/// `reconnector` each time it is lost.
 #[instrument(skip_all, fields(local_id = ?local_node.id(), remote_id = ?remote_id))]
 async fn run_connection(
     local_node: Arc<LocalNode>,
     remote_id: NodeId,
-    send_send: mpsc::UnboundedSender<SenderCommand>,
-    send_recv: mpsc::UnboundedReceiver<SenderCommand>,
-    socket: Arc<UnixSocket>,
+    mut send_send: mpsc::UnboundedSender<SenderCommand>,
+    mut send_recv: mpsc::UnboundedReceiver<SenderCommand>,
+    mut socket: Arc<UnixSocket>,
     handle: RemoteNodeHandle,
+    reconnector: Option<Reconnector>,
 ) {
+    loop {
+        let r = run_socket(&local_node, &remote_id, &send_send, send_recv, &socket).await;
+        tracing::trace!("connection done");
+        let Some(reconnector) = &reconnector else {
+            match r {
+                Ok(()) => handle.disconnect(),
+                Err(err) => handle.fail(err),
+            }
+            return;
+        };
+
+        // The remote node may still be running, so try to reconnect. If it is
+        // gone, the leader will provide a failed connection.
+        let err = match r {
+            Ok(()) => ConnectionError::Closed,
+            Err(err) => ConnectionError::Receive(err),
+        };
+        if !handle.connection_lost(err) {
+            return;
+        }
+        tracing::debug!("connection lost, reconnecting");
+        let fd = match reconnector.reconnect(remote_id).await {
+            Ok(fd) => fd,
+            Err(err) => {
+                tracing::debug!(
+                    error = &err as &dyn std::error::Error,
+                    "failed to reconnect"
+                );
+                handle.fail(err);
+                return;
+            }
+        };
+
+        #[allow(clippy::disallowed_methods)] // TODO
+        let (send, recv) = mpsc::unbounded();
+        socket = Arc::new(UnixSocket::new(reconnector.driver.as_ref(), fd));
+        let sender = PacketSender {
+            send: send.clone(),
+            socket: socket.clone(),
+        };
+        if !handle.connect(sender) {
+            // The node failed while reconnecting.
+            return;
+        }
+        tracing::debug!("reconnected");
+        send_send = send;
+        send_recv = recv;
+    }
+}
+
+/// Runs the packet processing loop for a single socket, returning when the
+/// connection is done.
+async fn run_socket(
+    local_node: &LocalNode,
+    remote_id: &NodeId,
+    send_send: &mpsc::UnboundedSender<SenderCommand>,
+    send_recv: mpsc::UnboundedReceiver<SenderCommand>,
+    socket: &UnixSocket,
+) -> Result<(), ReceiveError> {
     let mut retained_fds = VecDeque::new();
     let mut recv = pin!(async {
-        let r = run_receive(&local_node, &remote_id, &socket, &send_send).await;
+        let r = run_receive(local_node, remote_id, socket, send_send).await;
         match &r {
             Ok(_) => {
                 tracing::debug!("incoming socket disconnected");
@@ -549,7 +703,7 @@ async fn run_connection(
     }
     .fuse());
     let mut send = pin!(async {
-        match run_send(send_recv, &socket, &mut retained_fds).await {
+        match run_send(send_recv, socket, &mut retained_fds).await {
             Ok(_) => {
                 tracing::debug!("sending is done");
             }
@@ -559,7 +713,7 @@ async fn run_connection(
         }
     }
     .fuse());
-    let r = futures::select! { // race semantics
+    futures::select! { // race semantics
         r = recv => {
             // Notify the remote node that no more data will be sent.
             tracing::trace!("read complete, shutting down writes");
@@ -581,14 +735,18 @@ async fn run_connection(
                 }
             }
         }
-    };
-    tracing::trace!("connection done");
-    match r {
-        Ok(()) => handle.disconnect(),
-        Err(err) => handle.fail(err),
     }
 }
 
+/// The reason a connection that may be re-established was lost.
+#[derive(Debug, Error)]
+enum ConnectionError {
+    #[error("connection closed")]
+    Closed,
+    #[error("error receiving")]
+    Receive(#[source] ReceiveError),
+}
+
 #[derive(Debug, Error)]
 enum ReceiveError {
     #[error("i/o error")]
@@ -781,6 +939,11 @@ fn with_id(
                 pending_connections: pending_connections.clone(),
             }),
         ));
+        let reconnector = Reconnector {
+            driver: driver.clone(),
+            to_leader: Arc::downgrade(&to_leader),
+            pending: Arc::new(Mutex::new(Some(HashMap::new()))),
+        };
         let (task_send, mut task_recv) = channel::<SmallTask>();
         let task_send = Arc::new(task_send);
         let (drop_send, drop_recv) = oneshot();
@@ -816,6 +979,7 @@ fn with_id(
                     &local_node,
                     from_leader,
                     pending_connections,
+                    reconnector,
                     &tasks,
                 )
                 .await
@@ -935,6 +1099,7 @@ async fn join_generic(
             invitation.address.remote_addr.node,
             handle,
             UnixSocket::new(this.driver.as_ref(), invitation.fd.into()),
+            None,
         );
 
         // Get the ports to communicate with the leader from the initial
@@ -999,6 +1164,64 @@ fn connect(&self, node_id: NodeId, handle: RemoteNodeHandle) {
     }
 }
 
+/// Re-establishes lost connections to other followers by asking the leader for
+/// new ones.
+#[derive(Clone)]
+struct Reconnector {
+    driver: Arc<dyn SpawnDriver>,
+    to_leader: Weak<mesh_channel::Sender<LeaderRequest>>,
+    /// The connections waiting for the leader, or `None` if there is no longer
+    /// a leader.
+    pending: Arc<Mutex<Option<HashMap<NodeId, ReconnectSender>>>>,
+}
+
+type ReconnectSender = futures::channel::oneshot::Sender<Option<Socket>>;
+
+#[derive(Debug, Error)]
+enum ReconnectError {
+    #[error("no leader to reconnect through")]
+    NoLeader,
+    #[error("leader provided failed connection")]
+    Failed,
+    #[error("timed out waiting for the leader to reconnect")]
+    TimedOut,
+}
+
+impl Reconnector {
+    /// Asks the leader for a new connection to `remote_id`.
+    async fn reconnect(&self, remote_id: NodeId) -> Result<Socket, ReconnectError> {
+        let (send, recv) = futures::channel::oneshot::channel();
+        self.pending
+            .lock()
+            .as_mut()
+            .ok_or(ReconnectError::NoLeader)?
+            .insert(remote_id, send);
+        let to_leader = self.to_leader.upgrade().ok_or(ReconnectError::NoLeader)?;
+        to_leader.send(LeaderRequest::Reconnect(remote_id));
+        drop(to_leader);
+
+        let mut timer = PolledTimer::new(self.driver.as_ref());
+        let r = futures::select! { // race semantics
+            r = recv.fuse() => r.ok().flatten().ok_or(ReconnectError::Failed),
+            _ = timer.sleep(RECONNECT_TIMEOUT).fuse() => Err(ReconnectError::TimedOut),
+        };
+        if r.is_err() {
+            self.take_pending(&remote_id);
+        }
+        r
+    }
+
+    /// Takes the pending reconnection to `remote_id`, if there is one.
+    fn take_pending(&self, remote_id: &NodeId) -> Option<ReconnectSender> {
+        self.pending.lock().as_mut()?.remove(remote_id)
+    }
+
+    /// Fails any pending and future reconnections.
+    fn close(&self) {
+        self.pending.lock().take();
+    }
+}
+
 /// Creates an AF_UNIX socket pair of the appropriate type.
 fn new_socket_pair() -> Result<(Socket, Socket), io::Error> {
     let ty = if USE_SEQPACKET {
@@ -0,0 +1,56 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Hyper-V socket and vsock transport for point-to-point meshes.
+//!
+//! This allows a process on the host to join a mesh with a node running in a
+//! guest or in VTL2, without going through an intermediate protocol.
+//!
+//! As with other point-to-point meshes, OS resources cannot cross the
+//! connection. See [`PointToPointMesh`] for details.
+
+#![cfg(any(windows, target_os = "linux"))]
+
+use crate::PointToPointMesh;
+use mesh_node::local_node::Port;
+use pal_async::driver::Driver;
+use pal_async::socket::PolledSocket;
+use pal_async::task::Spawn;
+use std::io;
+use vmsocket::VmAddress;
+use vmsocket::VmListener;
+use vmsocket::VmSocket;
+use vmsocket::VmStream;
+
+impl PointToPointMesh {
+    /// Connects `socket` to a remote node listening on `addr` and makes a new
+    /// mesh over the connection, with initial port `port`.
+    ///
+    /// The caller can configure `socket` before connecting, e.g. to set the
+    /// connection timeout or to target a VM's VTL2.
+    pub async fn connect_vmsocket(
+        driver: &(impl Driver + Spawn),
+        socket: VmSocket,
+        addr: VmAddress,
+        port: Port,
+    ) -> io::Result<Self> {
+        let mut socket = PolledSocket::<socket2::Socket>::new(driver, socket.into())?;
+        socket.connect(&addr.into()).await?;
+        let socket: PolledSocket<VmStream> = socket.convert();
+        Ok(Self::new(driver, socket, port))
+    }
+
+    /// Accepts a connection from a remote node on `listener` and makes a new
+    /// mesh over the connection, with initial port `port`.
+    ///
+    /// Returns the mesh and the address of the remote node.
+    pub async fn accept_vmsocket(
+        driver: &(impl Driver + Spawn),
+        listener: &mut PolledSocket<VmListener>,
+        port: Port,
+    ) -> io::Result<(Self, VmAddress)> {
+        let (socket, remote_addr) = listener.accept().await?;
+        let socket = PolledSocket::new(driver, socket)?;
+        Ok((Self::new(driver, socket, port), remote_addr))
+    }
+}
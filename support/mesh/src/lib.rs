@@ -13,6 +13,7 @@ pub mod payload {
     pub use mesh_protobuf::*;
 }
 
+pub use mesh_channel::bounded;
 pub use mesh_channel::cancel::Cancel;
 pub use mesh_channel::cancel::CancelContext;
 pub use mesh_channel::cancel::CancelReason;
@@ -35,6 +36,7 @@ pub mod payload {
 pub use mesh_channel::OneshotSender;
 pub use mesh_channel::Receiver;
 pub use mesh_channel::RecvError;
+pub use mesh_channel::RecvTimeoutError;
 pub use mesh_channel::Sender;
 pub use mesh_channel::TryRecvError;
 pub use mesh_derive::MeshPayload;
@@ -42,9 +44,11 @@ pub mod payload {
 pub use mesh_node::common::NodeId;
 pub use mesh_node::common::PortId;
 pub use mesh_node::common::Uuid;
+pub use mesh_node::flight_recorder;
 pub use mesh_node::local_node;
 pub use mesh_node::message;
 pub use mesh_node::message::MeshPayload;
 pub use mesh_node::message::Message;
 pub use mesh_node::resource;
+pub use mesh_node::stats;
 pub use mesh_node::upcast;
@@ -51,9 +51,9 @@ pub mod unix {
     #[cfg(target_os = "linux")]
     pub use super::sys::epoll::EpollPool;
 
-    #[cfg(target_os = "macos")]
+    #[cfg(any(target_os = "macos", target_os = "freebsd"))]
     pub use super::sys::kqueue::KqueueDriver;
-    #[cfg(target_os = "macos")]
+    #[cfg(any(target_os = "macos", target_os = "freebsd"))]
     pub use super::sys::kqueue::KqueuePool;
 }
 
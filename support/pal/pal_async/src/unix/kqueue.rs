@@ -200,17 +200,9 @@ fn run<Fut: Future>(self: &Arc<Self>, fut: Fut) -> Fut::Output {
                     }
                 });
 
-                let mut events = [libc::kevent64_s {
-                    ident: 0,
-                    filter: 0,
-                    flags: 0,
-                    fflags: 0,
-                    data: 0,
-                    udata: 0,
-                    ext: [0; 2],
-                }; 8];
+                let mut events = [empty_event(); 8];
                 let n = while_eintr(|| self.kqfd.run(&[], &mut events, timeout.as_ref()))
-                    .expect("kevent64 failed unexpectedly");
+                    .expect("kevent failed unexpectedly");
 
                 // Block unnecessary wakeups.
                 let _ = self.state.lock().state.wake();
@@ -250,7 +242,7 @@ fn run<Fut: Future>(self: &Arc<Self>, fut: Fut) -> Fut::Output {
 
                 state = self.state.lock();
 
-                // Free any FdReadyOp objects that were deleted while in the kevent64 call.
+                // Free any FdReadyOp objects that were deleted while in the kevent call.
                 to_delete.append(&mut state.fd_ready_to_delete);
             }
         }
@@ -261,12 +253,13 @@ impl KqueueBackend {
     fn post_user_event(&self) {
         self.kqfd
             .run(
-                &[libc::kevent64_s {
-                    filter: libc::EVFILT_USER,
-                    flags: libc::EV_ADD | libc::EV_ONESHOT,
-                    fflags: libc::NOTE_TRIGGER,
-                    ..empty_event()
-                }],
+                &[new_event(
+                    0,
+                    libc::EVFILT_USER,
+                    libc::EV_ADD | libc::EV_ONESHOT,
+                    libc::NOTE_TRIGGER,
+                    0,
+                )],
                 &mut [],
                 Some(&zero_timespec()),
             )
@@ -277,16 +270,28 @@ fn post_user_event(&self) {
 #[derive(Debug)]
 struct KqueueFd(File);
 
-fn empty_event() -> libc::kevent64_s {
-    libc::kevent64_s {
-        ident: 0,
-        filter: 0,
-        flags: 0,
-        fflags: 0,
-        data: 0,
-        udata: 0,
-        ext: [0; 2],
-    }
+// On macOS, `kevent64` is needed for pointer-sized user data. FreeBSD's
+// `kevent` has pointer-sized fields already.
+#[cfg(target_os = "macos")]
+type KEvent = libc::kevent64_s;
+#[cfg(target_os = "freebsd")]
+type KEvent = libc::kevent;
+
+fn empty_event() -> KEvent {
+    // SAFETY: the kevent structure is plain data, and zero is a valid value
+    // for every field. The set of fields varies between OS versions, so it
+    // cannot be portably initialized with a struct literal.
+    unsafe { std::mem::zeroed() }
+}
+
+fn new_event(ident: usize, filter: i16, flags: u16, fflags: u32, udata: usize) -> KEvent {
+    let mut event = empty_event();
+    event.ident = ident as _;
+    event.filter = filter;
+    event.flags = flags;
+    event.fflags = fflags;
+    event.udata = udata as _;
+    event
 }
 
 fn zero_timespec() -> libc::timespec {
@@ -305,11 +310,12 @@ fn new() -> Result<Self, Errno> {
 
     fn run(
         &self,
-        changelist: &[libc::kevent64_s],
-        eventlist: &mut [libc::kevent64_s],
+        changelist: &[KEvent],
+        eventlist: &mut [KEvent],
         timeout: Option<&libc::timespec>,
     ) -> Result<usize, Errno> {
         // SAFETY: safe to call with any fd.
+        #[cfg(target_os = "macos")]
         let n = unsafe {
             libc::kevent64(
                 self.0.as_raw_fd(),
@@ -322,6 +328,19 @@ fn run(
             )
             .syscall_result()?
         };
+        // SAFETY: safe to call with any fd.
+        #[cfg(target_os = "freebsd")]
+        let n = unsafe {
+            libc::kevent(
+                self.0.as_raw_fd(),
+                changelist.as_ptr(),
+                changelist.len() as i32,
+                eventlist.as_mut_ptr(),
+                eventlist.len() as i32,
+                timeout.map_or(null_mut(), |t| t),
+            )
+            .syscall_result()?
+        };
         Ok(n as usize)
     }
 }
@@ -367,23 +386,23 @@ fn new_fd_ready(&self, fd: RawFd) -> io::Result<Self::FdReady> {
                 interests: PollInterestSet::default(),
             }),
         });
-        let udata = Arc::as_ptr(&op) as usize as u64;
+        let udata = Arc::as_ptr(&op) as usize;
         self.inner.kqfd.run(
             &[
-                libc::kevent64_s {
-                    ident: fd as u64,
-                    filter: libc::EVFILT_READ,
-                    flags: libc::EV_ADD | libc::EV_CLEAR,
+                new_event(
+                    fd as usize,
+                    libc::EVFILT_READ,
+                    libc::EV_ADD | libc::EV_CLEAR,
+                    0,
                     udata,
-                    ..empty_event()
-                },
-                libc::kevent64_s {
-                    ident: fd as u64,
-                    filter: libc::EVFILT_WRITE,
-                    flags: libc::EV_ADD | libc::EV_CLEAR,
+                ),
+                new_event(
+                    fd as usize,
+                    libc::EVFILT_WRITE,
+                    libc::EV_ADD | libc::EV_CLEAR,
+                    0,
                     udata,
-                    ..empty_event()
-                },
+                ),
             ],
             &mut [],
             Some(&zero_timespec()),
@@ -415,23 +434,13 @@ fn drop(&mut self) {
             .kqfd
             .run(
                 &[
-                    libc::kevent64_s {
-                        ident: self.fd as u64,
-                        filter: libc::EVFILT_READ,
-                        flags: libc::EV_DELETE,
-                        ..empty_event()
-                    },
-                    libc::kevent64_s {
-                        ident: self.fd as u64,
-                        filter: libc::EVFILT_WRITE,
-                        flags: libc::EV_DELETE,
-                        ..empty_event()
-                    },
+                    new_event(self.fd as usize, libc::EVFILT_READ, libc::EV_DELETE, 0, 0),
+                    new_event(self.fd as usize, libc::EVFILT_WRITE, libc::EV_DELETE, 0, 0),
                 ],
                 &mut [],
                 Some(&zero_timespec()),
             )
-            .expect("keven64 unexpectedly failed");
+            .expect("kevent unexpectedly failed");
 
         // SAFETY: Reclaiming the reference added in new_fd_ready.
         let op = unsafe { Arc::from_raw(Arc::as_ptr(&self.op)) };
@@ -19,7 +19,7 @@
 
         pub use epoll::EpollDriver as DefaultDriver;
         pub use epoll::EpollPool as DefaultPool;
-    } else if #[cfg(target_os = "macos")] {
+    } else if #[cfg(any(target_os = "macos", target_os = "freebsd"))] {
         pub mod kqueue;
 
         pub use kqueue::KqueueDriver as DefaultDriver;
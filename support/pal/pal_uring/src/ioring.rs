@@ -146,6 +146,7 @@ pub fn process(&mut self) {
         }
     }
 
+    /// Returns true if there are no completions waiting to be processed.
     pub fn is_empty(&self) -> bool {
         // SAFETY: there is only one instance of this type per io-uring, so
         // there cannot be other concurrent users of the completion ring.
@@ -163,8 +164,22 @@ impl IoRing {
     ///            number of outstanding I/Os, rather it's the maximum number of I/Os that the IoRing client
     ///            can allow to batch (either in the submission or completion paths).
     pub fn new(size: u32) -> Result<(IoRing, IoCompletionRing), io::Error> {
+        Self::from_ring(IoUring::builder().build(size)?, size)
+    }
+
+    /// Creates a new `IoRing` like [`Self::new`], but with a kernel thread
+    /// that polls the submission queue, so that submitting IO usually does
+    /// not require a system call.
+    ///
+    /// The kernel thread goes to sleep after `idle_ms` milliseconds without
+    /// any submissions.
+    pub fn new_sqpoll(size: u32, idle_ms: u32) -> Result<(IoRing, IoCompletionRing), io::Error> {
+        Self::from_ring(IoUring::builder().setup_sqpoll(idle_ms).build(size)?, size)
+    }
+
+    fn from_ring(ring: IoUring, size: u32) -> Result<(IoRing, IoCompletionRing), io::Error> {
         let inner = Arc::new(RingInner {
-            ring: IoUring::builder().build(size)?,
+            ring,
             state: Mutex::new(RingState {
                 iocbs: Slab::new(),
                 queue: VecDeque::with_capacity(size as usize),
@@ -357,6 +372,32 @@ pub unsafe fn push(&self, sqe: squeue::Entry, submit: bool) {
         }
     }
 
+    /// Registers `fds` with the ring, so that they can be referenced by
+    /// index with [`io_uring::types::Fixed`] to avoid per-IO file reference
+    /// counting in the kernel.
+    pub fn register_files(&self, fds: &[RawFd]) -> io::Result<()> {
+        self.inner.ring.submitter().register_files(fds)
+    }
+
+    /// Registers `buffers` with the ring, so that they can be referenced by
+    /// index in `ReadFixed` and `WriteFixed` operations to avoid per-IO page
+    /// pinning in the kernel.
+    ///
+    /// # Safety
+    ///
+    /// The caller must ensure that the buffers remain valid until the ring is
+    /// dropped.
+    pub unsafe fn register_buffers(&self, buffers: &[libc::iovec]) -> io::Result<()> {
+        // SAFETY: guaranteed by caller.
+        unsafe { self.inner.ring.submitter().register_buffers(buffers) }
+    }
+
+    /// Returns the number of IOs that have been issued with
+    /// [`Self::new_io`] and not yet released.
+    pub fn pending_io_count(&self) -> usize {
+        self.inner.pending_io_count.load(Ordering::Relaxed)
+    }
+
     /// Checks whether the specified opcode is supported by this `IoRing`.
     pub fn probe(&self, opcode: u8) -> bool {
         let mut probe = io_uring::Probe::new();
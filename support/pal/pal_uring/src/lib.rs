@@ -17,5 +17,8 @@
 mod threadpool;
 mod uring;
 
+pub use ioring::IoCompletionRing;
+pub use ioring::IoMemory;
+pub use ioring::IoRing;
 pub use threadpool::*;
 pub use uring::*;
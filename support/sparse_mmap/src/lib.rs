@@ -14,7 +14,11 @@
 pub mod windows;
 
 pub use sys::alloc_shared_memory;
+pub use sys::bind_shared_memory;
+pub use sys::discard_shared_memory;
 pub use sys::new_mappable_from_file;
+pub use sys::share_shared_memory;
+pub use sys::shared_memory_usage;
 pub use sys::AsMappableRef;
 pub use sys::Mappable;
 pub use sys::MappableRef;
@@ -30,6 +34,17 @@
 use zerocopy::AsBytes;
 use zerocopy::FromBytes;
 
+/// The host memory usage of a range of a shared memory object.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SharedMemoryUsage {
+    /// Bytes that have been allocated, whether resident or swapped out.
+    pub allocated: u64,
+    /// Bytes that are resident in host memory.
+    pub resident: u64,
+    /// Whether the object is backed by huge pages (hugetlbfs).
+    pub huge_pages: bool,
+}
+
 /// Must be called before using try_copy on Unix platforms.
 pub fn initialize_try_copy() {
     #[cfg(unix)]
@@ -5,6 +5,7 @@
 
 #![cfg(unix)]
 
+use crate::SharedMemoryUsage;
 use pal::unix::SyscallResult;
 use std::ffi::c_void;
 use std::fs::File;
@@ -306,6 +307,37 @@ pub unsafe fn mmap_anonymous(
         Ok(())
     }
 
+    /// Changes the access allowed to mapped memory in the mapping.
+    ///
+    /// The range must be within a single mapping. Reserved but unmapped
+    /// memory must not be protected, since that would make it accessible.
+    pub fn protect(
+        &self,
+        offset: usize,
+        len: usize,
+        readable: bool,
+        writable: bool,
+    ) -> io::Result<()> {
+        let _ = self.validate_offset_len(offset, len)?;
+        assert!(
+            readable || !writable,
+            "write-only protection is not supported"
+        );
+        let mut prot = libc::PROT_NONE;
+        if readable {
+            prot |= libc::PROT_READ;
+        }
+        if writable {
+            prot |= libc::PROT_WRITE;
+        }
+        // SAFETY: the range is within the reservation, so changing its
+        // protection cannot affect any other memory.
+        unsafe {
+            libc::mprotect(self.address.add(offset), len, prot).syscall_result()?;
+        }
+        Ok(())
+    }
+
     /// Unmaps memory from the mapping.
     pub fn unmap(&self, offset: usize, len: usize) -> io::Result<()> {
         let _ = self.validate_offset_len(offset, len)?;
@@ -343,6 +375,207 @@ fn drop(&mut self) {
         }
     }
 }
+/// Releases the memory backing `len` bytes at `offset` in a shared memory
+/// object allocated by [`alloc_shared_memory`]. The range reads as zero
+/// afterwards, and existing mappings of it remain valid.
+#[cfg(target_os = "linux")]
+pub fn discard_shared_memory(
+    mappable: &impl AsMappableRef,
+    offset: u64,
+    len: u64,
+) -> io::Result<()> {
+    // SAFETY: calling according to the documented contract with a valid fd.
+    unsafe {
+        libc::fallocate(
+            mappable.as_fd().as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset as i64,
+            len as i64,
+        )
+        .syscall_result()?;
+    }
+    Ok(())
+}
+
+/// Releases the memory backing `len` bytes at `offset` in a shared memory
+/// object allocated by [`alloc_shared_memory`].
+///
+/// This is not supported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn discard_shared_memory(
+    _mappable: &impl AsMappableRef,
+    _offset: u64,
+    _len: u64,
+) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Returns a new file descriptor for the shared memory object `mappable`,
+/// which can only be mapped writable if `writable` is true.
+///
+/// The file descriptor can be sent to another process to share the memory.
+#[cfg(target_os = "linux")]
+pub fn share_shared_memory(mappable: &impl AsMappableRef, writable: bool) -> io::Result<Mappable> {
+    // Reopening the object through procfs creates a new open file description
+    // with its own access mode, which the kernel enforces for mmap.
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(writable)
+        .open(format!("/proc/self/fd/{}", mappable.as_fd().as_raw_fd()))?;
+    Ok(file.into())
+}
+
+/// Returns a new file descriptor for the shared memory object `mappable`,
+/// which can only be mapped writable if `writable` is true.
+///
+/// Read-only sharing is not supported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn share_shared_memory(mappable: &impl AsMappableRef, writable: bool) -> io::Result<Mappable> {
+    if !writable {
+        return Err(io::ErrorKind::Unsupported.into());
+    }
+    mappable.as_fd().try_clone_to_owned()
+}
+
+/// Binds the memory backing `len` bytes at `offset` in a shared memory object
+/// allocated by [`alloc_shared_memory`] to host NUMA node `node`.
+///
+/// This only affects pages that have not yet been allocated, so call it before
+/// the memory is first used.
+#[cfg(target_os = "linux")]
+pub fn bind_shared_memory(
+    mappable: &impl AsMappableRef,
+    offset: u64,
+    len: u64,
+    node: u32,
+) -> io::Result<()> {
+    const MPOL_BIND: libc::c_int = 2;
+
+    let len: usize = len
+        .try_into()
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+
+    // The memory policy of a shared memory object can be set through any
+    // mapping of it, and it persists after the mapping is gone.
+    let mapping = SparseMapping::new(len)?;
+    mapping.map_file(0, len, mappable.as_fd(), offset, true)?;
+
+    let mut node_mask = vec![0u64; node as usize / 64 + 1];
+    node_mask[node as usize / 64] |= 1 << (node % 64);
+
+    // SAFETY: the range is mapped, and the mask buffer is large enough for the
+    // passed bit count (which the kernel treats as one more than the count).
+    unsafe {
+        let ret = libc::syscall(
+            libc::SYS_mbind,
+            mapping.as_ptr(),
+            len,
+            MPOL_BIND,
+            node_mask.as_ptr(),
+            node_mask.len() * 64 + 1,
+            0,
+        );
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Binds the memory backing `len` bytes at `offset` in a shared memory object
+/// allocated by [`alloc_shared_memory`] to host NUMA node `node`.
+///
+/// This is not supported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn bind_shared_memory(
+    _mappable: &impl AsMappableRef,
+    _offset: u64,
+    _len: u64,
+    _node: u32,
+) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Returns the host memory usage of `len` bytes at `offset` in the shared
+/// memory object `mappable`.
+///
+/// This maps the range temporarily to query residency, so it is relatively
+/// expensive for large ranges.
+#[cfg(target_os = "linux")]
+pub fn shared_memory_usage(
+    mappable: &impl AsMappableRef,
+    offset: u64,
+    len: u64,
+) -> io::Result<SharedMemoryUsage> {
+    let fd = mappable.as_fd().as_raw_fd();
+    let end = offset + len;
+
+    // SAFETY: calling according to the documented contract with a valid fd
+    // and an appropriately sized buffer.
+    let huge_pages = unsafe {
+        let mut stat = std::mem::zeroed::<libc::statfs>();
+        libc::fstatfs(fd, &mut stat).syscall_result()?;
+        stat.f_type as i64 == libc::HUGETLBFS_MAGIC as i64
+    };
+
+    // Walk the allocated (non-hole) extents of the object. Swapped-out pages
+    // are still allocated.
+    let mut allocated = 0;
+    let mut pos = offset;
+    while pos < end {
+        // SAFETY: calling according to the documented contract with a valid
+        // fd.
+        let data = unsafe { libc::lseek(fd, pos as i64, libc::SEEK_DATA) };
+        if data < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENXIO) {
+                break;
+            }
+            return Err(err);
+        }
+        // SAFETY: as above.
+        let hole = unsafe { libc::lseek(fd, data, libc::SEEK_HOLE) };
+        if hole < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let data = (data as u64).min(end);
+        let hole = (hole as u64).min(end);
+        allocated += hole - data;
+        pos = hole;
+    }
+
+    let len: usize = len
+        .try_into()
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let mapping = SparseMapping::new(len)?;
+    mapping.map_file(0, len, mappable.as_fd(), offset, false)?;
+    let mut pages = vec![0u8; len.div_ceil(page_size())];
+    // SAFETY: the range is mapped, and the vector has an entry for each page.
+    unsafe {
+        libc::mincore(mapping.as_ptr(), len, pages.as_mut_ptr()).syscall_result()?;
+    }
+    let resident = pages.iter().filter(|&&p| p & 1 != 0).count() as u64 * page_size() as u64;
+
+    Ok(SharedMemoryUsage {
+        allocated,
+        resident: resident.min(len as u64),
+        huge_pages,
+    })
+}
+
+/// Returns the host memory usage of `len` bytes at `offset` in the shared
+/// memory object `mappable`.
+///
+/// This is not supported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn shared_memory_usage(
+    _mappable: &impl AsMappableRef,
+    _offset: u64,
+    _len: u64,
+) -> io::Result<SharedMemoryUsage> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
 #[cfg(target_os = "linux")]
 fn new_memfd() -> io::Result<File> {
     // SAFETY: creating and truncating a new file descriptor according to
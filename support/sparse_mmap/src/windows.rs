@@ -5,6 +5,7 @@
 
 #![cfg(windows)]
 
+use crate::SharedMemoryUsage;
 use pal::windows::BorrowedHandleExt;
 use pal::windows::Process;
 use parking_lot::Mutex;
@@ -22,6 +23,7 @@
 use Memory::UnmapViewOfFile2;
 use Memory::VirtualAlloc2;
 use Memory::VirtualFreeEx;
+use Memory::VirtualProtectEx;
 use Memory::MEMORY_MAPPED_VIEW_ADDRESS;
 use Memory::MEM_COMMIT;
 use Memory::MEM_RELEASE;
@@ -653,6 +655,41 @@ fn unmap_internal(&self, mappings: &mut MappingList, offset: usize, end: usize)
         start_index
     }
 
+    /// Changes the access allowed to mapped memory in the mapping.
+    ///
+    /// The range must be within a single mapping.
+    pub fn protect(
+        &self,
+        offset: usize,
+        len: usize,
+        readable: bool,
+        writable: bool,
+    ) -> io::Result<()> {
+        let _ = self.validate_offset_len(offset, len)?;
+        let protection = match (readable, writable) {
+            (false, false) => PAGE_NOACCESS,
+            (true, false) => PAGE_READONLY,
+            (true, true) => PAGE_READWRITE,
+            (false, true) => panic!("write-only protection is not supported"),
+        };
+        let mut old_protection = 0;
+        // SAFETY: the range is within the reservation, so changing its
+        // protection cannot affect any other memory.
+        unsafe {
+            if VirtualProtectEx(
+                self.process.as_ref().handle() as isize,
+                self.address.wrapping_add(offset),
+                len,
+                protection,
+                &mut old_protection,
+            ) == 0
+            {
+                return Err(Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
     /// Unmaps a range of mappings.
     pub fn unmap(&self, offset: usize, len: usize) -> io::Result<()> {
         let end = self.validate_offset_len(offset, len)?;
@@ -691,6 +728,59 @@ pub fn alloc_shared_memory(size: usize) -> io::Result<OwnedHandle> {
     }
 }
 
+/// Returns a new handle to the shared memory object `mappable`, which can only
+/// be mapped writable if `writable` is true.
+///
+/// The handle can be sent to another process to share the memory.
+pub fn share_shared_memory(mappable: &impl AsMappableRef, writable: bool) -> io::Result<Mappable> {
+    let access = if writable {
+        SECTION_MAP_READ | SECTION_MAP_WRITE
+    } else {
+        SECTION_MAP_READ
+    };
+    mappable.as_handle().duplicate(false, Some(access))
+}
+
+/// Releases the memory backing `len` bytes at `offset` in a shared memory
+/// object allocated by [`alloc_shared_memory`].
+///
+/// This is not yet supported on Windows, where pagefile-backed sections can
+/// only be decommitted through a mapped view.
+pub fn discard_shared_memory(
+    _mappable: &impl AsMappableRef,
+    _offset: u64,
+    _len: u64,
+) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Returns the host memory usage of `len` bytes at `offset` in the shared
+/// memory object `mappable`.
+///
+/// This is not yet supported on Windows.
+pub fn shared_memory_usage(
+    _mappable: &impl AsMappableRef,
+    _offset: u64,
+    _len: u64,
+) -> io::Result<SharedMemoryUsage> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Binds the memory backing `len` bytes at `offset` in a shared memory object
+/// allocated by [`alloc_shared_memory`] to host NUMA node `node`.
+///
+/// This is not yet supported on Windows, where the preferred node of a
+/// pagefile-backed section can only be set for the whole section when it is
+/// created.
+pub fn bind_shared_memory(
+    _mappable: &impl AsMappableRef,
+    _offset: u64,
+    _len: u64,
+    _node: u32,
+) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
 #[cfg(test)]
 mod tests {
     use super::alloc_shared_memory;
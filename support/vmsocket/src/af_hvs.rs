@@ -0,0 +1,162 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! AF_HYPERV support for FreeBSD, via the `hv_sock` driver.
+//!
+//! Unlike Linux, FreeBSD does not support Hyper-V sockets through AF_VSOCK.
+//! Its AF_HYPERV addresses only contain a VSOCK-style port, which the driver
+//! maps to a service ID, and always refer to the host when connecting.
+
+// UNSAFETY: Initializing and reading raw socket addresses.
+#![allow(unsafe_code)]
+
+use crate::VmListener;
+use crate::VmSocket;
+use crate::VmStream;
+use mesh::payload::os_resource;
+use socket2::SockAddr;
+use socket2::Socket;
+use socket2::Type;
+use std::io;
+use std::os::unix::prelude::*;
+use std::time::Duration;
+
+/// The FreeBSD AF_HYPERV address family, from `sys/dev/hyperv/hvsock`.
+const AF_HYPERV: libc::c_int = 43;
+
+/// `struct sockaddr_hvs`.
+#[repr(C)]
+#[derive(Copy, Clone)]
+struct SockAddrHvs {
+    sa_len: u8,
+    sa_family: libc::sa_family_t,
+    hvs_port: u32,
+    hvs_zero: [u8; 10],
+}
+
+#[derive(Debug)]
+pub struct Address {
+    pub(crate) port: u32,
+}
+
+impl Address {
+    pub fn new(port: u32) -> Self {
+        Self { port }
+    }
+
+    pub fn vsock_any(port: u32) -> Self {
+        Self::new(port)
+    }
+
+    pub fn vsock_host(port: u32) -> Self {
+        Self::new(port)
+    }
+
+    pub fn into_sock_addr(self) -> SockAddr {
+        let address = SockAddrHvs {
+            sa_len: size_of::<SockAddrHvs>() as u8,
+            sa_family: AF_HYPERV as libc::sa_family_t,
+            hvs_port: self.port,
+            hvs_zero: [0; 10],
+        };
+
+        // SAFETY: initializing storage as documented.
+        let (_, address) = unsafe {
+            SockAddr::try_init(|storage, len| {
+                assert!(*len as usize >= size_of_val(&address));
+                let storage: &mut SockAddrHvs = &mut *storage.cast();
+                *storage = address;
+                *len = size_of_val(&address) as libc::socklen_t;
+                Ok(())
+            })
+            .unwrap()
+        };
+
+        address
+    }
+
+    pub fn try_from_sock_addr(addr: &SockAddr) -> Option<Self> {
+        if (addr.len() as usize) < size_of::<SockAddrHvs>() {
+            return None;
+        }
+        // SAFETY: buffer is large enough.
+        let addr = unsafe { &*addr.as_ptr().cast::<SockAddrHvs>() };
+        if addr.sa_family != AF_HYPERV as libc::sa_family_t {
+            return None;
+        }
+        Some(Self::new(addr.hvs_port))
+    }
+}
+
+impl VmSocket {
+    pub(crate) fn new_inner() -> io::Result<Self> {
+        Ok(Self(Socket::new(AF_HYPERV.into(), Type::STREAM, None)?))
+    }
+
+    /// Sets the connection timeout for this socket.
+    ///
+    /// `hv_sock` on FreeBSD has no option to configure this, so this has no
+    /// effect. Connections time out after the driver's fixed timeout.
+    pub fn set_connect_timeout(&self, duration: Duration) -> io::Result<()> {
+        let _ = duration;
+        Ok(())
+    }
+}
+
+impl AsFd for VmSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl From<VmSocket> for OwnedFd {
+    fn from(fd: VmSocket) -> Self {
+        fd.0.into()
+    }
+}
+
+impl From<OwnedFd> for VmSocket {
+    fn from(fd: OwnedFd) -> Self {
+        Self(fd.into())
+    }
+}
+
+impl AsFd for VmListener {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl From<VmListener> for OwnedFd {
+    fn from(fd: VmListener) -> Self {
+        fd.0.into()
+    }
+}
+
+impl From<OwnedFd> for VmListener {
+    fn from(fd: OwnedFd) -> Self {
+        Self(fd.into())
+    }
+}
+
+impl AsFd for VmStream {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl From<VmStream> for OwnedFd {
+    fn from(fd: VmStream) -> Self {
+        fd.0.into()
+    }
+}
+
+impl From<OwnedFd> for VmStream {
+    fn from(fd: OwnedFd) -> Self {
+        Self(fd.into())
+    }
+}
+
+os_resource!(VmSocket, OwnedFd);
+os_resource!(VmStream, OwnedFd);
+os_resource!(VmListener, OwnedFd);
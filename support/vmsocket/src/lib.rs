@@ -1,21 +1,25 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-//! Support for `AF_HYPERV` (on Windows) and `AF_VSOCK` (on Linux) socket families.
+//! Support for `AF_HYPERV` (on Windows and FreeBSD) and `AF_VSOCK` (on Linux)
+//! socket families.
 //!
 //! This crate abstracts over the differences between these and provides unified
 //! [`VmStream`] and [`VmListener`] types.
 
-#![cfg(any(windows, target_os = "linux"))]
+#![cfg(any(windows, target_os = "linux", target_os = "freebsd"))]
 #![warn(missing_docs)]
 
 cfg_if::cfg_if! {
     if #[cfg(windows)] {
         mod af_hyperv;
         use af_hyperv as sys;
-    } else if #[cfg(unix)] {
+    } else if #[cfg(target_os = "linux")] {
         mod af_vsock;
         use af_vsock as sys;
+    } else if #[cfg(target_os = "freebsd")] {
+        mod af_hvs;
+        use af_hvs as sys;
     }
 }
 
@@ -33,15 +37,17 @@ impl std::fmt::Display for VmAddress {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         #[cfg(windows)]
         write!(f, "{}:{}", self.vm_id(), self.service_id())?;
-        #[cfg(unix)]
+        #[cfg(target_os = "linux")]
         write!(f, "{}:{}", self.cid(), self.port())?;
+        #[cfg(target_os = "freebsd")]
+        write!(f, "{}", self.port())?;
         Ok(())
     }
 }
 
 impl VmAddress {
     /// Creates a new AF_VSOCK address from `cid` and `port`.
-    #[cfg(unix)]
+    #[cfg(target_os = "linux")]
     pub fn vsock(cid: u32, port: u32) -> Self {
         Self(sys::Address::new(cid, port))
     }
@@ -85,13 +91,14 @@ pub fn vsock_host(port: u32) -> Self {
     }
 
     /// Creates a new address from the specified [`SockAddr`] when the address
-    /// is an `AF_HYPERV` (on Windows) or `AF_VSOCK` (on Linux) address.
+    /// is an `AF_HYPERV` (on Windows and FreeBSD) or `AF_VSOCK` (on Linux)
+    /// address.
     pub fn try_from_sock_addr(addr: &SockAddr) -> Option<Self> {
         Some(Self(sys::Address::try_from_sock_addr(addr)?))
     }
 
     /// Gets the VSOCK CID.
-    #[cfg(unix)]
+    #[cfg(target_os = "linux")]
     pub fn cid(&self) -> u32 {
         self.0.cid
     }
@@ -12,6 +12,7 @@
 pub mod fadt;
 pub mod madt;
 pub mod pptt;
+pub mod slit;
 pub mod srat;
 
 #[allow(non_camel_case_types)]
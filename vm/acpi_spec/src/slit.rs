@@ -0,0 +1,47 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use super::Table;
+use crate::packed_nums::*;
+use core::mem::size_of;
+use static_assertions::const_assert_eq;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+use zerocopy::Unaligned;
+
+/// The System Locality Information Table header.
+///
+/// This is followed by a `localities` x `localities` matrix of relative
+/// distances, where entry `i * localities + j` is the distance from locality
+/// (proximity domain) `i` to locality `j`.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, AsBytes, FromBytes, FromZeroes, Unaligned)]
+pub struct SlitHeader {
+    pub localities: u64_ne,
+}
+
+const_assert_eq!(size_of::<SlitHeader>(), 8);
+
+impl SlitHeader {
+    pub fn new(localities: u64) -> SlitHeader {
+        SlitHeader {
+            localities: localities.into(),
+        }
+    }
+}
+
+impl Table for SlitHeader {
+    const SIGNATURE: [u8; 4] = *b"SLIT";
+}
+
+pub const SLIT_REVISION: u8 = 1;
+
+/// The distance from a locality to itself.
+pub const SLIT_LOCAL_DISTANCE: u8 = 10;
+
+/// The conventional distance between two different localities.
+pub const SLIT_REMOTE_DISTANCE: u8 = 20;
+
+/// Distance value indicating that a locality is unreachable.
+pub const SLIT_UNREACHABLE: u8 = 0xff;
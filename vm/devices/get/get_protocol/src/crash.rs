@@ -29,6 +29,8 @@ pub enum DumpType: u32 {
         NONE = 0x00000000,
         ELF = 0x00000001,
         KDUMP = 0x00000002,
+        /// A gzip-compressed ELF core dump
+        ELF_GZIP = 0x00000003,
     }
 }
 
@@ -344,6 +344,7 @@ pub enum EventLogId: u32 {
         DEK_DECRYPTION_FAILED = 12,
         WATCHDOG_TIMEOUT_RESET = 13,
         BOOT_ATTEMPT = 14,
+        VTL2_HANG_DETECTED = 15,
     }
 }
 
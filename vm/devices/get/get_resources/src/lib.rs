@@ -36,6 +36,8 @@ pub struct GuestCrashDeviceHandle {
         pub request_dump: mesh::Sender<FailableRpc<mesh::OneshotReceiver<()>, File>>,
         /// The maximum size of the dump that the device will write.
         pub max_dump_size: u64,
+        /// Ask the guest to gzip-compress dumps before sending them.
+        pub compress: bool,
     }
 
     impl ResourceId<VmbusDeviceHandleKind> for GuestCrashDeviceHandle {
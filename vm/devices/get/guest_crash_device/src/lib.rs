@@ -43,6 +43,7 @@ pub struct GuestCrashDevice {
     #[inspect(skip)]
     request_dump: mesh::Sender<FailableRpc<mesh::OneshotReceiver<()>, File>>,
     max_dump_size: u64,
+    compress: bool,
 }
 
 /// The internal guest crash channel.
@@ -114,13 +115,17 @@ impl GuestCrashDevice {
     /// `request_dump` to retrieve the file to write to. When the dump completes
     /// successfully, the device will send an empty message to the provided
     /// oneshot channel.
+    ///
+    /// If `compress` is set, the guest is asked to gzip-compress the dump.
     pub fn new(
         request_dump: mesh::Sender<FailableRpc<mesh::OneshotReceiver<()>, File>>,
         max_dump_size: u64,
+        compress: bool,
     ) -> Self {
         Self {
             request_dump,
             max_dump_size,
+            compress,
         }
     }
 
@@ -131,8 +136,9 @@ pub fn into_inner(
     ) -> (
         mesh::Sender<FailableRpc<mesh::OneshotReceiver<()>, File>>,
         u64,
+        bool,
     ) {
-        (self.request_dump, self.max_dump_size)
+        (self.request_dump, self.max_dump_size, self.compress)
     }
 }
 
@@ -220,7 +226,11 @@ async fn process_inner(&mut self, channel: &mut GuestCrashChannel) -> anyhow::Re
                                 },
                                 config: crash::ConfigV1 {
                                     max_dump_size: self.max_dump_size,
-                                    dump_type: crash::DumpType::ELF,
+                                    dump_type: if self.compress {
+                                        crash::DumpType::ELF_GZIP
+                                    } else {
+                                        crash::DumpType::ELF
+                                    },
                                 },
                             })?;
                         }
@@ -32,7 +32,11 @@ fn resolve(
     ) -> Result<Self::Output, Self::Error> {
         Ok(SimpleDeviceWrapper::new(
             input.driver_source.simple(),
-            GuestCrashDevice::new(resource.request_dump, resource.max_dump_size),
+            GuestCrashDevice::new(
+                resource.request_dump,
+                resource.max_dump_size,
+                resource.compress,
+            ),
         )
         .into())
     }
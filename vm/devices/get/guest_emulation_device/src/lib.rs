@@ -160,6 +160,7 @@ pub enum GuestEvent {
     DekDecryptionFailed,
     WatchdogTimeoutReset,
     BootAttempt,
+    Vtl2HangDetected,
 }
 
 /// VMBUS device that implements the host side of the Guest Emulation Transport protocol.
@@ -988,6 +989,7 @@ fn handle_event_log(
             get_protocol::EventLogId::DEK_DECRYPTION_FAILED => GuestEvent::DekDecryptionFailed,
             get_protocol::EventLogId::BOOT_ATTEMPT => GuestEvent::BootAttempt,
             get_protocol::EventLogId::WATCHDOG_TIMEOUT_RESET => GuestEvent::WatchdogTimeoutReset,
+            get_protocol::EventLogId::VTL2_HANG_DETECTED => GuestEvent::Vtl2HangDetected,
             _ => {
                 // TODO: logged but ignored for now.
                 tracing::error!(event_log_id = msg.event_log_id.0, "unknown event log id");
@@ -77,7 +77,7 @@ async fn resolve(
                         disk,
                         ResolveDiskParameters {
                             read_only: false,
-                            _async_trait_workaround: &(),
+                            driver_source: input.driver_source,
                         },
                     )
                     .await
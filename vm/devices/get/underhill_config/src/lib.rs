@@ -172,6 +172,35 @@ pub struct NicDevice {
     pub max_sub_channels: Option<u16>,
 }
 
+#[derive(Debug, Copy, Clone, Eq, PartialEq, MeshPayload, Inspect)]
+pub enum VmbusChannelAction {
+    /// Relay the channel to the guest.
+    Allow,
+    /// Hide the channel from both the guest and VTL2 devices.
+    Deny,
+    /// Only allow the channel to be handled by a VTL2 device.
+    TerminateInVtl2,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, MeshPayload, Inspect)]
+pub struct VmbusChannelRule {
+    /// The interface ID to match, or any if `None`.
+    pub interface_id: Option<Guid>,
+    /// The instance ID to match, or any if `None`.
+    pub instance_id: Option<Guid>,
+    /// The action to take for matching channels.
+    pub action: VmbusChannelAction,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, MeshPayload, Inspect)]
+pub struct VmbusRelayPolicy {
+    /// The action for channels that do not match any rule.
+    pub default_action: VmbusChannelAction,
+    /// Rules evaluated in order; the first match wins.
+    #[inspect(iter_by_index)]
+    pub rules: Vec<VmbusChannelRule>,
+}
+
 #[derive(Debug, Clone, MeshPayload, Inspect)]
 pub struct Vtl2SettingsFixed {
     /// number of sub-channels for the SCSI controller
@@ -180,6 +209,8 @@ pub struct Vtl2SettingsFixed {
     pub io_ring_size: u32,
     /// Max bounce buffer pages active per cpu
     pub max_bounce_buffer_pages: Option<u32>,
+    /// Filtering policy for channels offered by the host through the vmbus relay
+    pub vmbus_relay_policy: VmbusRelayPolicy,
 }
 
 #[derive(Debug, Clone, MeshPayload, Inspect)]
@@ -295,6 +326,8 @@ pub enum Vtl2SettingsErrorCode {
     UnsupportedSchemaNamespace => (Underhill, Configuration),
     /// Empty namespace chunk
     EmptyNamespaceChunk => (Underhill, Configuration),
+    /// Invalid vmbus relay channel policy
+    VmbusRelayInvalidPolicy => (Underhill, Configuration),
     /// Change storage controller at runtime
     StorageCannotAddRemoveControllerAtRuntime => (Storage, Configuration),
     /// SCSI LUN exceeds max limits (64)
@@ -496,4 +496,45 @@ fn namespace_test_empty_nic_devices_protobuf() {
         let settings = crate::Vtl2Settings::read_from(&buf, old_settings).unwrap();
         assert_eq!(0, settings.dynamic.nic_devices.len());
     }
+
+    #[test]
+    fn validation_test_vmbus_relay_policy() {
+        let settings = crate::Vtl2Settings::read_from(
+            include_bytes!("vtl2s_test_vmbus_relay_policy.json"),
+            Default::default(),
+        )
+        .unwrap();
+        let policy = &settings.fixed.vmbus_relay_policy;
+        assert_eq!(policy.default_action, crate::VmbusChannelAction::Deny);
+        assert_eq!(
+            policy.rules,
+            [
+                crate::VmbusChannelRule {
+                    interface_id: Some(Guid::from_static_str(
+                        "ba6163d9-04a1-4d29-b605-72e2ffb1dc7f"
+                    )),
+                    instance_id: None,
+                    action: crate::VmbusChannelAction::Allow,
+                },
+                crate::VmbusChannelRule {
+                    interface_id: None,
+                    instance_id: Some(Guid::from_static_str(
+                        "f8615163-df3e-46c5-913f-f2d2f965ed0e"
+                    )),
+                    action: crate::VmbusChannelAction::TerminateInVtl2,
+                },
+            ]
+        );
+
+        let err = crate::Vtl2Settings::read_from(
+            br#"{ "version": "V1", "fixed": { "vmbus_relay_policy": { "rules": [ {} ] } } }"#,
+            Default::default(),
+        )
+        .unwrap_err();
+        let ParseError::Validation(err) = err else {
+            panic!("wrong error {err:?}")
+        };
+        let [err] = err.errors.try_into().unwrap();
+        assert_eq!(err.code(), Vtl2SettingsErrorCode::VmbusRelayInvalidPolicy);
+    }
 }
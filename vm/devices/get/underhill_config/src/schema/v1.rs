@@ -37,6 +37,10 @@ pub(crate) enum Error<'a> {
     EmptyNamespaceChunk(&'a str),
     #[error("invalid instance ID '{0}'")]
     InvalidInstanceId(&'a str, #[source] guid::ParseError),
+    #[error("invalid vmbus interface ID '{0}'")]
+    InvalidInterfaceId(&'a str, #[source] guid::ParseError),
+    #[error("vmbus relay channel rule has unknown action")]
+    VmbusRelayActionUnknown,
     #[error("invalid ntfs guid '{0}'")]
     InvalidNtfsGuid(&'a str, #[source] guid::ParseError),
     #[error("controller already exists")]
@@ -80,6 +84,9 @@ fn code(&self) -> Vtl2SettingsErrorCode {
             }
             Error::EmptyNamespaceChunk(_) => Vtl2SettingsErrorCode::EmptyNamespaceChunk,
             Error::InvalidInstanceId { .. } => Vtl2SettingsErrorCode::InvalidInstanceId,
+            Error::InvalidInterfaceId(_, _) | Error::VmbusRelayActionUnknown => {
+                Vtl2SettingsErrorCode::VmbusRelayInvalidPolicy
+            }
             Error::InvalidNtfsGuid(_, _) => Vtl2SettingsErrorCode::StorageInvalidNtfsFormatGuid,
             Error::StorageControllerGuidAlreadyExists => {
                 Vtl2SettingsErrorCode::StorageControllerGuidAlreadyExists
@@ -161,6 +168,13 @@ fn parse_instance_id(instance_id: &str) -> Result<Guid, Error<'_>> {
         .map_err(|err| Error::InvalidInstanceId(instance_id, err))
 }
 
+fn parse_optional_guid<'a>(
+    guid: Option<&'a str>,
+    err: impl FnOnce(&'a str, guid::ParseError) -> Error<'a>,
+) -> Result<Option<Guid>, Error<'a>> {
+    guid.map(|s| s.parse().map_err(|e| err(s, e))).transpose()
+}
+
 fn parse_ntfs_guid(ntfs_guid: Option<&str>) -> Result<Option<Guid>, Error<'_>> {
     ntfs_guid
         .map(|guid| {
@@ -522,12 +536,80 @@ fn parse_schema(
 impl ParseSchema<crate::Vtl2SettingsFixed> for Vtl2SettingsFixed {
     fn parse_schema(
         &self,
-        _errors: &mut ParseErrors<'_>,
+        errors: &mut ParseErrors<'_>,
     ) -> Result<crate::Vtl2SettingsFixed, ParsingStopped> {
         Ok(crate::Vtl2SettingsFixed {
             scsi_sub_channels: self.scsi_sub_channels.map_or(0, |x| x as u16),
             io_ring_size: self.io_ring_size.unwrap_or(256),
             max_bounce_buffer_pages: self.max_bounce_buffer_pages,
+            vmbus_relay_policy: self
+                .vmbus_relay_policy
+                .as_ref()
+                .map(|policy| policy.parse(errors))
+                .transpose()?
+                .unwrap_or(crate::VmbusRelayPolicy {
+                    default_action: crate::VmbusChannelAction::Allow,
+                    rules: Vec::new(),
+                }),
+        })
+    }
+}
+
+impl ParseSchema<crate::VmbusChannelAction> for vmbus_channel_rule::Action {
+    fn parse_schema(
+        &self,
+        _errors: &mut ParseErrors<'_>,
+    ) -> Result<crate::VmbusChannelAction, ParsingStopped> {
+        match self {
+            vmbus_channel_rule::Action::Allow => Ok(crate::VmbusChannelAction::Allow),
+            vmbus_channel_rule::Action::Deny => Ok(crate::VmbusChannelAction::Deny),
+            vmbus_channel_rule::Action::TerminateInVtl2 => {
+                Ok(crate::VmbusChannelAction::TerminateInVtl2)
+            }
+            vmbus_channel_rule::Action::Unknown => Err(Error::VmbusRelayActionUnknown.into()),
+        }
+    }
+}
+
+impl ParseSchema<crate::VmbusChannelRule> for VmbusChannelRule {
+    fn parse_schema(
+        &self,
+        errors: &mut ParseErrors<'_>,
+    ) -> Result<crate::VmbusChannelRule, ParsingStopped> {
+        Ok(crate::VmbusChannelRule {
+            interface_id: parse_optional_guid(
+                self.interface_id.as_deref(),
+                Error::InvalidInterfaceId,
+            )?,
+            instance_id: parse_optional_guid(
+                self.instance_id.as_deref(),
+                Error::InvalidInstanceId,
+            )?,
+            action: self.action().parse(errors)?,
+        })
+    }
+}
+
+impl ParseSchema<crate::VmbusRelayPolicy> for VmbusRelayPolicy {
+    fn parse_schema(
+        &self,
+        errors: &mut ParseErrors<'_>,
+    ) -> Result<crate::VmbusRelayPolicy, ParsingStopped> {
+        let default_action = if self.default_action.is_some() {
+            self.default_action().parse(errors)?
+        } else {
+            crate::VmbusChannelAction::Allow
+        };
+
+        let rules = self
+            .rules
+            .iter()
+            .flat_map(|rule| rule.parse(errors).collect_error(errors))
+            .collect();
+
+        Ok(crate::VmbusRelayPolicy {
+            default_action,
+            rules,
         })
     }
 }
@@ -0,0 +1,573 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Implementation of the Hyper-V Dynamic Memory device.
+//!
+//! This device adjusts the amount of RAM available to the guest at runtime,
+//! based on the memory pressure the guest reports. Memory is reclaimed by
+//! asking the guest to balloon pages, whose host backing is then released. It
+//! is returned by unballooning those pages or, once there are no ballooned
+//! pages left, by hot-adding new RAM.
+
+#![warn(missing_docs)]
+#![forbid(unsafe_code)]
+
+pub mod resolver;
+
+use anyhow::Context as _;
+use async_trait::async_trait;
+use hyperv_dm_protocol::Capabilities;
+use hyperv_dm_protocol::CapabilitiesResponseFlags;
+use hyperv_dm_protocol::Header;
+use hyperv_dm_protocol::MessageType;
+use hyperv_dm_protocol::PageRange;
+use hyperv_dm_protocol::Version;
+use hyperv_dm_protocol::MAX_MESSAGE_SIZE;
+use hyperv_dm_protocol::PAGE_SIZE;
+use hyperv_dm_resources::DynamicMemoryBacking;
+use hyperv_dm_resources::DynamicMemoryHandle;
+use inspect::Inspect;
+use inspect::InspectMut;
+use memory_range::MemoryRange;
+use std::sync::Arc;
+use task_control::Cancelled;
+use task_control::StopTask;
+use thiserror::Error;
+use vmbus_async::async_dgram::AsyncRecvExt;
+use vmbus_async::async_dgram::AsyncSendExt;
+use vmbus_async::pipe::MessagePipe;
+use vmbus_channel::bus::OfferParams;
+use vmbus_channel::channel::ChannelOpenError;
+use vmbus_channel::gpadl_ring::GpadlRingMem;
+use vmbus_channel::simple::SaveRestoreSimpleVmbusDevice;
+use vmbus_channel::simple::SimpleVmbusDevice;
+use vmbus_channel::RawAsyncChannel;
+use vmcore::save_restore::SavedStateNotSupported;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy_helpers::FromBytesExt;
+
+/// The number of pages in a hot-add block, matching Linux's memory block size.
+const HOT_ADD_BLOCK_PAGES: u64 = (128 << 20) / PAGE_SIZE;
+
+/// The maximum number of pages to balloon or add in one operation, so that
+/// memory is adjusted gradually.
+const MAX_ADJUST_PAGES: u64 = (1 << 30) / PAGE_SIZE;
+
+/// How far the guest must be above its target, in pages, before ballooning.
+const BALLOON_HYSTERESIS_PAGES: u64 = (64 << 20) / PAGE_SIZE;
+
+/// The maximum number of page ranges in one unballoon request.
+const MAX_UNBALLOON_RANGES: usize =
+    (MAX_MESSAGE_SIZE - size_of::<hyperv_dm_protocol::UnballoonRequest>()) / size_of::<PageRange>();
+
+#[derive(Debug, Error)]
+enum Error {
+    #[error("channel i/o error")]
+    Io(#[source] std::io::Error),
+    #[error("bad packet")]
+    BadPacket,
+}
+
+/// The memory policy, in pages.
+#[derive(Debug, Copy, Clone, Inspect)]
+struct Policy {
+    startup_pages: u64,
+    minimum_pages: u64,
+    maximum_pages: u64,
+    buffer_percent: u32,
+}
+
+/// A Hyper-V Dynamic Memory device.
+#[derive(InspectMut)]
+pub struct DynamicMemoryDevice {
+    #[inspect(skip)]
+    backing: Arc<dyn DynamicMemoryBacking>,
+    hot_add_range: Option<MemoryRange>,
+    policy: Policy,
+    /// The number of pages at the start of the hot-add range that are backed
+    /// by RAM. These stay backed if the channel is reopened, e.g. across a
+    /// guest reboot, so that they can be offered to the guest again.
+    backed_pages: u64,
+}
+
+impl DynamicMemoryDevice {
+    /// Returns a new dynamic memory device using `backing` to add and reclaim
+    /// guest RAM, with the policy in `handle`.
+    pub fn new(
+        backing: Arc<dyn DynamicMemoryBacking>,
+        handle: &DynamicMemoryHandle,
+    ) -> anyhow::Result<Self> {
+        if handle.minimum > handle.startup || handle.startup > handle.maximum {
+            anyhow::bail!("dynamic memory requires minimum <= startup <= maximum");
+        }
+        let hot_add_range = backing.hot_add_range();
+        let hot_add_pages = hot_add_range.map_or(0, |range| range.page_count_4k());
+        let startup_pages = handle.startup / PAGE_SIZE;
+        let maximum_pages = (handle.maximum / PAGE_SIZE).min(startup_pages + hot_add_pages);
+        Ok(Self {
+            backing,
+            hot_add_range,
+            policy: Policy {
+                startup_pages,
+                minimum_pages: handle.minimum / PAGE_SIZE,
+                maximum_pages,
+                buffer_percent: handle.buffer_percent,
+            },
+            backed_pages: 0,
+        })
+    }
+}
+
+#[doc(hidden)]
+#[derive(InspectMut)]
+pub struct DynamicMemoryChannel {
+    #[inspect(mut)]
+    pipe: MessagePipe<GpadlRingMem>,
+    state: ChannelState,
+}
+
+#[derive(Inspect)]
+#[inspect(external_tag)]
+enum ChannelState {
+    Version,
+    Capabilities {
+        #[inspect(display)]
+        version: Version,
+    },
+    Ready(#[inspect(flatten)] Box<ReadyState>),
+}
+
+#[derive(Inspect)]
+struct ReadyState {
+    #[inspect(display)]
+    version: Version,
+    #[inspect(debug)]
+    capabilities: Capabilities,
+    /// The guest's committed pages, from its last status report.
+    committed_pages: u64,
+    /// The guest's available pages, from its last status report.
+    available_pages: u64,
+    target_pages: u64,
+    hot_added_pages: u64,
+    /// The offset in pages into the hot-add range at which to add next.
+    hot_add_next: u64,
+    hot_add_failed: bool,
+    ballooned_pages: u64,
+    #[inspect(with = "Vec::len")]
+    ballooned: Vec<MemoryRange>,
+    #[inspect(debug)]
+    operation: Option<Operation>,
+    next_transaction_id: u32,
+}
+
+#[derive(Debug)]
+enum Operation {
+    Balloon { requested: u64, received: u64 },
+    Unballoon { pages: u64 },
+    HotAdd { pages: u64 },
+}
+
+impl ReadyState {
+    fn new(version: Version, capabilities: Capabilities) -> Self {
+        Self {
+            version,
+            capabilities,
+            committed_pages: 0,
+            available_pages: 0,
+            target_pages: 0,
+            hot_added_pages: 0,
+            hot_add_next: 0,
+            hot_add_failed: false,
+            ballooned_pages: 0,
+            ballooned: Vec::new(),
+            operation: None,
+            next_transaction_id: 1,
+        }
+    }
+
+    fn transaction_id(&mut self) -> u32 {
+        let transaction_id = self.next_transaction_id;
+        self.next_transaction_id = self.next_transaction_id.wrapping_add(1);
+        transaction_id
+    }
+
+    fn current_pages(&self, policy: &Policy) -> u64 {
+        (policy.startup_pages + self.hot_added_pages).saturating_sub(self.ballooned_pages)
+    }
+}
+
+fn unexpected(header: Header) -> Result<(), Error> {
+    tracelimit::warn_ratelimited!(
+        message_type = ?header.message_type,
+        "unexpected dynamic memory message"
+    );
+    Ok(())
+}
+
+impl DynamicMemoryChannel {
+    async fn process(&mut self, dev: &mut DynamicMemoryDevice) -> Result<(), Error> {
+        let mut buf = vec![0; MAX_MESSAGE_SIZE];
+        loop {
+            let n = self.pipe.recv(&mut buf).await.map_err(Error::Io)?;
+            let header = Header::read_from_prefix(&buf[..n]).ok_or(Error::BadPacket)?;
+            // Raw packets are padded, so use the size from the header.
+            let message = buf[..n]
+                .get(..header.size as usize)
+                .ok_or(Error::BadPacket)?;
+            self.handle_message(dev, header, message).await?;
+        }
+    }
+
+    async fn send(&mut self, parts: &[&[u8]]) -> Result<(), Error> {
+        let message = parts.concat();
+        self.pipe.send(&message).await.map_err(Error::Io)
+    }
+
+    async fn handle_message(
+        &mut self,
+        dev: &mut DynamicMemoryDevice,
+        header: Header,
+        message: &[u8],
+    ) -> Result<(), Error> {
+        match header.message_type {
+            MessageType::VERSION_REQUEST => {
+                // The guest may renegotiate at any time, e.g. if its driver is
+                // reloaded.
+                let request = hyperv_dm_protocol::VersionRequest::read_from_prefix(message)
+                    .ok_or(Error::BadPacket)?;
+                let accepted = hyperv_dm_protocol::SUPPORTED_VERSIONS.contains(&request.version);
+                self.send(&[hyperv_dm_protocol::VersionResponse {
+                    header: Header {
+                        message_type: MessageType::VERSION_RESPONSE,
+                        size: size_of::<hyperv_dm_protocol::VersionResponse>() as u16,
+                        transaction_id: header.transaction_id,
+                    },
+                    is_accepted: accepted.into(),
+                }
+                .as_bytes()])
+                    .await?;
+                self.state = if accepted {
+                    tracing::info!(version = %request.version, "dynamic memory version negotiated");
+                    ChannelState::Capabilities {
+                        version: request.version,
+                    }
+                } else {
+                    ChannelState::Version
+                };
+            }
+            MessageType::CAPABILITIES_REPORT => {
+                let ChannelState::Capabilities { version } = self.state else {
+                    return unexpected(header);
+                };
+                let report = hyperv_dm_protocol::CapabilitiesReport::read_from_prefix(message)
+                    .ok_or(Error::BadPacket)?;
+                let capabilities = report.capabilities;
+                self.send(&[hyperv_dm_protocol::CapabilitiesResponse {
+                    header: Header {
+                        message_type: MessageType::CAPABILITIES_RESPONSE,
+                        size: size_of::<hyperv_dm_protocol::CapabilitiesResponse>() as u16,
+                        transaction_id: header.transaction_id,
+                    },
+                    flags: CapabilitiesResponseFlags::new().with_is_accepted(true),
+                }
+                .as_bytes()])
+                    .await?;
+                tracing::info!(?capabilities, "dynamic memory ready");
+                self.state = ChannelState::Ready(Box::new(ReadyState::new(version, capabilities)));
+            }
+            MessageType::STATUS_REPORT => {
+                let ChannelState::Ready(state) = &mut self.state else {
+                    return unexpected(header);
+                };
+                let report = hyperv_dm_protocol::StatusReport::read_from_prefix(message)
+                    .ok_or(Error::BadPacket)?;
+                state.committed_pages = report.num_committed;
+                state.available_pages = report.num_avail;
+                if state.operation.is_none() {
+                    self.adjust(dev).await?;
+                }
+            }
+            MessageType::BALLOON_RESPONSE => {
+                let ChannelState::Ready(state) = &mut self.state else {
+                    return unexpected(header);
+                };
+                let Some(Operation::Balloon {
+                    requested,
+                    received,
+                }) = &mut state.operation
+                else {
+                    return unexpected(header);
+                };
+                let (response, rest) =
+                    hyperv_dm_protocol::BalloonResponse::read_from_prefix_split(message)
+                        .ok_or(Error::BadPacket)?;
+                let ranges = rest
+                    .chunks_exact(size_of::<PageRange>())
+                    .take(response.more_pages.range_count() as usize)
+                    .map(|range| PageRange::read_from(range).unwrap());
+                for range in ranges {
+                    let start = range.start_page();
+                    let range = MemoryRange::from_4k_gpn_range(start..start + range.page_count());
+                    if let Err(err) = dev.backing.discard(range).await {
+                        tracelimit::warn_ratelimited!(
+                            error = err.as_ref() as &dyn std::error::Error,
+                            %range,
+                            "failed to release ballooned memory"
+                        );
+                    }
+                    *received += range.page_count_4k();
+                    state.ballooned_pages += range.page_count_4k();
+                    state.ballooned.push(range);
+                }
+                if !response.more_pages.more_pages() {
+                    tracing::debug!(
+                        requested = *requested,
+                        received = *received,
+                        "balloon complete"
+                    );
+                    state.operation = None;
+                }
+            }
+            MessageType::UNBALLOON_RESPONSE => {
+                let ChannelState::Ready(state) = &mut self.state else {
+                    return unexpected(header);
+                };
+                let Some(Operation::Unballoon { pages }) = state.operation else {
+                    return unexpected(header);
+                };
+                tracing::debug!(pages, "unballoon complete");
+                state.operation = None;
+            }
+            MessageType::HOT_ADD_RESPONSE => {
+                let ChannelState::Ready(state) = &mut self.state else {
+                    return unexpected(header);
+                };
+                let Some(Operation::HotAdd { pages }) = state.operation else {
+                    return unexpected(header);
+                };
+                let response = hyperv_dm_protocol::HotAddResponse::read_from_prefix(message)
+                    .ok_or(Error::BadPacket)?;
+                state.hot_added_pages += response.page_count as u64;
+                if response.result == 0 || (response.page_count as u64) < pages {
+                    // Don't keep asking a guest that cannot add memory.
+                    tracelimit::warn_ratelimited!(
+                        requested = pages,
+                        added = response.page_count,
+                        "guest failed to hot-add memory"
+                    );
+                    state.hot_add_failed = true;
+                }
+                state.operation = None;
+            }
+            MessageType::INFO_MESSAGE => {}
+            MessageType::ERROR => {
+                tracelimit::warn_ratelimited!("guest reported a dynamic memory error");
+            }
+            _ => return unexpected(header),
+        }
+        Ok(())
+    }
+
+    /// Moves the guest's memory toward its target, based on its last status
+    /// report.
+    async fn adjust(&mut self, dev: &mut DynamicMemoryDevice) -> Result<(), Error> {
+        let ChannelState::Ready(state) = &mut self.state else {
+            unreachable!()
+        };
+        let policy = &dev.policy;
+        let current = state.current_pages(policy);
+        let target = (state.committed_pages * (100 + policy.buffer_percent as u64) / 100)
+            .clamp(policy.minimum_pages, policy.maximum_pages);
+        state.target_pages = target;
+
+        if target > current {
+            let pages = (target - current).min(MAX_ADJUST_PAGES);
+            if state.ballooned_pages != 0 {
+                self.unballoon(pages).await?;
+            } else if state.capabilities.hot_add() && !state.hot_add_failed {
+                self.hot_add(dev, pages).await?;
+            }
+        } else if state.capabilities.balloon() && current - target > BALLOON_HYSTERESIS_PAGES {
+            let pages = (current - target).min(MAX_ADJUST_PAGES);
+            let header = Header {
+                message_type: MessageType::BALLOON_REQUEST,
+                size: size_of::<hyperv_dm_protocol::BalloonRequest>() as u16,
+                transaction_id: state.transaction_id(),
+            };
+            state.operation = Some(Operation::Balloon {
+                requested: pages,
+                received: 0,
+            });
+            self.send(&[hyperv_dm_protocol::BalloonRequest {
+                header,
+                num_pages: pages as u32,
+                reserved: 0,
+            }
+            .as_bytes()])
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Returns up to `pages` ballooned pages to the guest.
+    async fn unballoon(&mut self, pages: u64) -> Result<(), Error> {
+        let ChannelState::Ready(state) = &mut self.state else {
+            unreachable!()
+        };
+        let mut ranges = Vec::new();
+        let mut remaining = pages;
+        while remaining != 0 {
+            let Some(range) = state.ballooned.pop() else {
+                break;
+            };
+            let (range, rest) = if range.page_count_4k() > remaining {
+                range.split_at_offset(remaining * PAGE_SIZE)
+            } else {
+                (range, MemoryRange::EMPTY)
+            };
+            if !rest.is_empty() {
+                state.ballooned.push(rest);
+            }
+            remaining -= range.page_count_4k();
+            ranges.push(
+                PageRange::new()
+                    .with_start_page(range.start_4k_gpn())
+                    .with_page_count(range.page_count_4k()),
+            );
+        }
+        let pages = pages - remaining;
+        state.ballooned_pages -= pages;
+        state.operation = Some(Operation::Unballoon { pages });
+
+        let transaction_id = state.transaction_id();
+        let chunks = ranges.chunks(MAX_UNBALLOON_RANGES);
+        let count = chunks.len();
+        for (i, chunk) in chunks.enumerate() {
+            let request = hyperv_dm_protocol::UnballoonRequest {
+                header: Header {
+                    message_type: MessageType::UNBALLOON_REQUEST,
+                    size: (size_of::<hyperv_dm_protocol::UnballoonRequest>() + size_of_val(chunk))
+                        as u16,
+                    transaction_id,
+                },
+                more_pages: hyperv_dm_protocol::MorePages::new().with_more_pages(i + 1 < count),
+                range_count: chunk.len() as u32,
+            };
+            self.send(&[request.as_bytes(), chunk.as_bytes()]).await?;
+        }
+        Ok(())
+    }
+
+    /// Hot-adds enough memory to cover `pages`, in whole hot-add blocks.
+    async fn hot_add(&mut self, dev: &mut DynamicMemoryDevice, pages: u64) -> Result<(), Error> {
+        let ChannelState::Ready(state) = &mut self.state else {
+            unreachable!()
+        };
+        let Some(hot_add_range) = dev.hot_add_range else {
+            return Ok(());
+        };
+        let offset = state.hot_add_next;
+        let pages = pages
+            .next_multiple_of(HOT_ADD_BLOCK_PAGES)
+            .min(hot_add_range.page_count_4k() - offset);
+        if pages == 0 {
+            return Ok(());
+        }
+
+        // Back any part of the range that was not backed for a previous
+        // guest.
+        if offset + pages > dev.backed_pages {
+            let start = hot_add_range.start_4k_gpn();
+            let range =
+                MemoryRange::from_4k_gpn_range(start + dev.backed_pages..start + offset + pages);
+            if let Err(err) = dev
+                .backing
+                .hot_add(range)
+                .await
+                .with_context(|| format!("failed to back {range}"))
+            {
+                tracelimit::error_ratelimited!(
+                    error = err.as_ref() as &dyn std::error::Error,
+                    "hot-add failed"
+                );
+                state.hot_add_failed = true;
+                return Ok(());
+            }
+            dev.backed_pages = offset + pages;
+        }
+
+        state.hot_add_next += pages;
+        state.operation = Some(Operation::HotAdd { pages });
+        let request = hyperv_dm_protocol::HotAddRequest {
+            header: Header {
+                message_type: MessageType::HOT_ADD_REQUEST,
+                size: size_of::<hyperv_dm_protocol::HotAddRequest>() as u16,
+                transaction_id: state.transaction_id(),
+            },
+            range: PageRange::new()
+                .with_start_page(hot_add_range.start_4k_gpn() + offset)
+                .with_page_count(pages),
+            // Let the guest choose the hot-add region.
+            region: PageRange::new(),
+        };
+        self.send(&[request.as_bytes()]).await
+    }
+}
+
+#[async_trait]
+impl SimpleVmbusDevice for DynamicMemoryDevice {
+    type SavedState = SavedStateNotSupported;
+    type Runner = DynamicMemoryChannel;
+
+    fn offer(&self) -> OfferParams {
+        OfferParams {
+            interface_name: "dynamic_memory".to_owned(),
+            instance_id: hyperv_dm_protocol::INSTANCE_ID,
+            interface_id: hyperv_dm_protocol::INTERFACE_ID,
+            ..Default::default()
+        }
+    }
+
+    fn inspect(&mut self, req: inspect::Request<'_>, runner: Option<&mut Self::Runner>) {
+        req.respond().merge(self).merge(runner);
+    }
+
+    fn open(
+        &mut self,
+        channel: RawAsyncChannel<GpadlRingMem>,
+    ) -> Result<Self::Runner, ChannelOpenError> {
+        let pipe = MessagePipe::new_raw(channel)?;
+        Ok(DynamicMemoryChannel {
+            pipe,
+            state: ChannelState::Version,
+        })
+    }
+
+    async fn run(
+        &mut self,
+        stop: &mut StopTask<'_>,
+        runner: &mut Self::Runner,
+    ) -> Result<(), Cancelled> {
+        stop.until_stopped(async {
+            match runner.process(self).await {
+                Ok(()) => {}
+                Err(err) => {
+                    tracing::error!(
+                        error = &err as &dyn std::error::Error,
+                        "dynamic memory error"
+                    )
+                }
+            }
+        })
+        .await
+    }
+
+    fn supports_save_restore(
+        &mut self,
+    ) -> Option<
+        &mut dyn SaveRestoreSimpleVmbusDevice<SavedState = Self::SavedState, Runner = Self::Runner>,
+    > {
+        None
+    }
+}
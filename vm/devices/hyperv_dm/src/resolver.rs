@@ -0,0 +1,45 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resource resolver for the dynamic memory device.
+
+use crate::DynamicMemoryDevice;
+use async_trait::async_trait;
+use hyperv_dm_resources::DynamicMemoryBackingHandleKind;
+use hyperv_dm_resources::DynamicMemoryHandle;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::VmbusDeviceHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::IntoResource;
+use vm_resource::PlatformResource;
+use vm_resource::ResourceResolver;
+use vmbus_channel::resources::ResolveVmbusDeviceHandleParams;
+use vmbus_channel::resources::ResolvedVmbusDevice;
+use vmbus_channel::simple::SimpleDeviceWrapper;
+
+/// Resource resolver for the dynamic memory device.
+pub struct DynamicMemoryResolver;
+
+declare_static_async_resolver! {
+    DynamicMemoryResolver,
+    (VmbusDeviceHandleKind, DynamicMemoryHandle),
+}
+
+#[async_trait]
+impl AsyncResolveResource<VmbusDeviceHandleKind, DynamicMemoryHandle> for DynamicMemoryResolver {
+    type Output = ResolvedVmbusDevice;
+    type Error = anyhow::Error;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        resource: DynamicMemoryHandle,
+        input: ResolveVmbusDeviceHandleParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let backing = resolver
+            .resolve::<DynamicMemoryBackingHandleKind, _>(PlatformResource.into_resource(), ())
+            .await?;
+        let device = DynamicMemoryDevice::new(backing.0, &resource)?;
+        Ok(SimpleDeviceWrapper::new(input.driver_source.simple(), device).into())
+    }
+}
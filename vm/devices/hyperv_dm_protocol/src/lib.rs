@@ -0,0 +1,309 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Hyper-V Dynamic Memory protocol definitions.
+//!
+//! The guest drives the initial handshake: it sends a version request and then
+//! reports its capabilities, after which it periodically sends memory status
+//! reports. The host uses these reports to decide when to balloon memory out of
+//! the guest or to give memory back, either by unballooning previously
+//! ballooned pages or by hot-adding new memory.
+
+#![allow(dead_code)]
+
+use bitfield_struct::bitfield;
+use guid::Guid;
+use open_enum::open_enum;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+/// The interface ID for the dynamic memory device.
+pub const INTERFACE_ID: Guid = Guid::from_static_str("525074dc-8985-46e2-8057-a307dc18a502");
+
+/// The instance ID for the dynamic memory device.
+pub const INSTANCE_ID: Guid = Guid::from_static_str("4a37a9d7-4d0c-4d32-b4ba-1a18a2d55d0d");
+
+/// The page size used by the protocol.
+pub const PAGE_SIZE: u64 = 4096;
+
+/// The maximum size of a message in either direction.
+pub const MAX_MESSAGE_SIZE: usize = 4096;
+
+/// Protocol version, with the major version in the high 16 bits.
+#[repr(transparent)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, AsBytes, FromBytes, FromZeroes)]
+pub struct Version(pub u32);
+
+impl Version {
+    /// Returns a version from its components.
+    pub const fn new(major: u16, minor: u16) -> Self {
+        Self(((major as u32) << 16) | minor as u32)
+    }
+
+    /// The Windows 7 version of the protocol.
+    pub const WIN7: Self = Self::new(0, 3);
+    /// The Windows 8 version of the protocol.
+    pub const WIN8: Self = Self::new(1, 0);
+    /// The Windows 10 version of the protocol.
+    pub const WIN10: Self = Self::new(2, 0);
+}
+
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.0 >> 16, self.0 & 0xffff)
+    }
+}
+
+/// The versions supported by the host, in order of preference.
+pub const SUPPORTED_VERSIONS: &[Version] = &[Version::WIN10, Version::WIN8, Version::WIN7];
+
+open_enum! {
+    /// The message type.
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub enum MessageType: u16 {
+        /// An error report.
+        ERROR = 0,
+        /// Guest-to-host version request.
+        VERSION_REQUEST = 1,
+        /// Host-to-guest version response.
+        VERSION_RESPONSE = 2,
+        /// Guest-to-host capabilities report.
+        CAPABILITIES_REPORT = 3,
+        /// Host-to-guest capabilities response.
+        CAPABILITIES_RESPONSE = 4,
+        /// Guest-to-host memory status report.
+        STATUS_REPORT = 5,
+        /// Host-to-guest request to balloon pages out of the guest.
+        BALLOON_REQUEST = 6,
+        /// Guest-to-host response with ballooned pages.
+        BALLOON_RESPONSE = 7,
+        /// Host-to-guest request to return ballooned pages to the guest.
+        UNBALLOON_REQUEST = 8,
+        /// Guest-to-host unballoon completion.
+        UNBALLOON_RESPONSE = 9,
+        /// Host-to-guest hot-add request.
+        HOT_ADD_REQUEST = 10,
+        /// Guest-to-host hot-add completion.
+        HOT_ADD_RESPONSE = 11,
+        /// Guest-to-host informational message.
+        INFO_MESSAGE = 12,
+    }
+}
+
+/// The common message header.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct Header {
+    /// The message type.
+    pub message_type: MessageType,
+    /// The size of the message in bytes, including this header.
+    pub size: u16,
+    /// The transaction ID, used to match responses to requests.
+    pub transaction_id: u32,
+}
+
+/// A range of guest pages.
+#[bitfield(u64)]
+#[derive(AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct PageRange {
+    /// The first page number.
+    #[bits(40)]
+    pub start_page: u64,
+    /// The number of pages.
+    #[bits(24)]
+    pub page_count: u64,
+}
+
+/// The maximum page count for a single [`PageRange`].
+pub const MAX_PAGE_RANGE_COUNT: u64 = (1 << 24) - 1;
+
+/// Guest-to-host version request.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct VersionRequest {
+    /// The message header.
+    pub header: Header,
+    /// The requested version.
+    pub version: Version,
+    /// Version request flags.
+    pub flags: VersionRequestFlags,
+}
+
+/// Flags for [`VersionRequest`].
+#[bitfield(u32)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct VersionRequestFlags {
+    /// The guest will not try another version if this one is rejected.
+    pub is_last_attempt: bool,
+    #[bits(31)]
+    _reserved: u32,
+}
+
+/// Host-to-guest version response.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct VersionResponse {
+    /// The message header.
+    pub header: Header,
+    /// Whether the version was accepted, in bit 0.
+    pub is_accepted: u64,
+}
+
+/// The guest's capabilities.
+#[bitfield(u64)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct Capabilities {
+    /// The guest supports ballooning.
+    pub balloon: bool,
+    /// The guest supports memory hot-add.
+    pub hot_add: bool,
+    /// The required hot-add alignment, as a power of two in MB.
+    #[bits(4)]
+    pub hot_add_alignment: u8,
+    #[bits(58)]
+    _reserved: u64,
+}
+
+/// Guest-to-host capabilities report.
+#[repr(C, packed)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct CapabilitiesReport {
+    /// The message header.
+    pub header: Header,
+    /// The guest's capabilities.
+    pub capabilities: Capabilities,
+    /// The minimum number of pages the guest requires.
+    pub min_page_count: u64,
+    /// The highest page number the guest can address.
+    pub max_page_number: u32,
+}
+
+/// Flags for [`CapabilitiesResponse`].
+#[bitfield(u64)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct CapabilitiesResponseFlags {
+    /// The capabilities were accepted.
+    pub is_accepted: bool,
+    /// The host may hot-remove memory.
+    pub hot_remove: bool,
+    /// The guest should not send status reports.
+    pub suppress_pressure_reports: bool,
+    #[bits(61)]
+    _reserved: u64,
+}
+
+/// Host-to-guest capabilities response.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct CapabilitiesResponse {
+    /// The message header.
+    pub header: Header,
+    /// The response flags.
+    pub flags: CapabilitiesResponseFlags,
+}
+
+/// Guest-to-host memory status report, sent about once per second.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct StatusReport {
+    /// The message header.
+    pub header: Header,
+    /// The number of pages available to the guest.
+    pub num_avail: u64,
+    /// The number of pages committed by the guest.
+    pub num_committed: u64,
+    /// The size of the guest's page file, in pages.
+    pub page_file_size: u64,
+    /// The number of zeroed free pages.
+    pub zero_free: u64,
+    /// The number of page file writes since the last report.
+    pub page_file_writes: u32,
+    /// Reserved.
+    pub io_diff: u32,
+}
+
+/// Host-to-guest balloon request.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct BalloonRequest {
+    /// The message header.
+    pub header: Header,
+    /// The number of pages to balloon.
+    pub num_pages: u32,
+    /// Reserved.
+    pub reserved: u32,
+}
+
+/// Flags for [`BalloonResponse`] and [`UnballoonRequest`].
+#[bitfield(u32)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct MorePages {
+    /// More messages follow for this transaction.
+    pub more_pages: bool,
+    /// The number of page ranges following the message, for
+    /// [`BalloonResponse`].
+    #[bits(31)]
+    pub range_count: u32,
+}
+
+/// Guest-to-host balloon response, followed by `range_count` [`PageRange`]s.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct BalloonResponse {
+    /// The message header.
+    pub header: Header,
+    /// Reserved.
+    pub reserved: u32,
+    /// Continuation flag and range count.
+    pub more_pages: MorePages,
+}
+
+/// Host-to-guest unballoon request, followed by `range_count` [`PageRange`]s.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct UnballoonRequest {
+    /// The message header.
+    pub header: Header,
+    /// Continuation flag. The range count field is unused.
+    pub more_pages: MorePages,
+    /// The number of page ranges following the message.
+    pub range_count: u32,
+}
+
+/// Host-to-guest hot-add request.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct HotAddRequest {
+    /// The message header.
+    pub header: Header,
+    /// The pages to add.
+    pub range: PageRange,
+    /// The hot-add region containing `range`, or zero to let the guest choose
+    /// an aligned region.
+    pub region: PageRange,
+}
+
+/// Guest-to-host hot-add response.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct HotAddResponse {
+    /// The message header.
+    pub header: Header,
+    /// The number of pages successfully added.
+    pub page_count: u32,
+    /// Nonzero on success.
+    pub result: u32,
+}
+
+/// Guest-to-host informational message, followed by `info_size` bytes.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct InfoMessage {
+    /// The message header.
+    pub header: Header,
+    /// Reserved.
+    pub reserved: u32,
+    /// The size of the information that follows.
+    pub info_size: u32,
+}
@@ -0,0 +1,71 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resource definitions for the Hyper-V Dynamic Memory device.
+
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+
+use async_trait::async_trait;
+use memory_range::MemoryRange;
+use mesh::MeshPayload;
+use std::sync::Arc;
+use vm_resource::kind::VmbusDeviceHandleKind;
+use vm_resource::CanResolveTo;
+use vm_resource::ResourceId;
+use vm_resource::ResourceKind;
+
+/// A handle to a dynamic memory device.
+///
+/// All sizes are in bytes. The VM boots with `startup` bytes of RAM, and the
+/// device adjusts the guest's memory between `minimum` and `maximum` based on
+/// the memory pressure the guest reports.
+#[derive(MeshPayload)]
+pub struct DynamicMemoryHandle {
+    /// The amount of RAM the VM is started with.
+    pub startup: u64,
+    /// The minimum amount of RAM to leave the guest when ballooning.
+    pub minimum: u64,
+    /// The maximum amount of RAM to give the guest, including hot-added
+    /// memory.
+    pub maximum: u64,
+    /// The percentage of the guest's committed memory to keep available as a
+    /// buffer when adjusting memory.
+    pub buffer_percent: u32,
+}
+
+impl ResourceId<VmbusDeviceHandleKind> for DynamicMemoryHandle {
+    const ID: &'static str = "dynamic_memory";
+}
+
+/// Resource kind for the platform's dynamic memory backing.
+///
+/// This is resolved with [`vm_resource::PlatformResource`].
+pub enum DynamicMemoryBackingHandleKind {}
+
+impl ResourceKind for DynamicMemoryBackingHandleKind {
+    const NAME: &'static str = "dynamic_memory_backing";
+}
+
+impl CanResolveTo<ResolvedDynamicMemoryBacking> for DynamicMemoryBackingHandleKind {
+    type Input<'a> = ();
+}
+
+/// A resolved dynamic memory backing.
+pub struct ResolvedDynamicMemoryBacking(pub Arc<dyn DynamicMemoryBacking>);
+
+/// The platform's support for adding and reclaiming guest RAM at runtime.
+#[async_trait]
+pub trait DynamicMemoryBacking: Send + Sync {
+    /// Returns the guest physical address range reserved for hot-added
+    /// memory, if any.
+    fn hot_add_range(&self) -> Option<MemoryRange>;
+
+    /// Backs `range` with new RAM and maps it into the guest. `range` must be
+    /// within [`hot_add_range`](Self::hot_add_range).
+    async fn hot_add(&self, range: MemoryRange) -> anyhow::Result<()>;
+
+    /// Releases the host memory backing `range`, which the guest has ballooned
+    /// out. The range stays mapped and reads as zero until it is used again.
+    async fn discard(&self, range: MemoryRange) -> anyhow::Result<()>;
+}
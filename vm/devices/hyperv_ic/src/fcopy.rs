@@ -0,0 +1,596 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The guest file copy IC.
+
+use crate::common;
+use crate::common::Error;
+use async_trait::async_trait;
+use blocking::unblock;
+use futures::stream::once;
+use futures::FutureExt;
+use futures::StreamExt;
+use futures_concurrency::stream::Merge;
+use guid::Guid;
+use hyperv_ic_protocol::fcopy::FcopyOperation;
+use hyperv_ic_protocol::fcopy::DATA_FRAGMENT_SIZE;
+use hyperv_ic_protocol::fcopy::FCOPY_VERSIONS;
+use hyperv_ic_protocol::fcopy::FRAMEWORK_VERSIONS;
+use hyperv_ic_resources::fcopy::CopyFileParams;
+use hyperv_ic_resources::fcopy::FcopyError;
+use hyperv_ic_resources::fcopy::FcopyRpc;
+use inspect::Inspect;
+use inspect::InspectMut;
+use mesh::rpc::Rpc;
+use std::fs::File;
+use std::io;
+use std::pin::pin;
+use std::sync::Arc;
+use task_control::Cancelled;
+use task_control::StopTask;
+use vmbus_async::pipe::MessagePipe;
+use vmbus_channel::bus::ChannelType;
+use vmbus_channel::bus::OfferParams;
+use vmbus_channel::channel::ChannelOpenError;
+use vmbus_channel::gpadl_ring::GpadlRingMem;
+use vmbus_channel::simple::SaveRestoreSimpleVmbusDevice;
+use vmbus_channel::simple::SimpleVmbusDevice;
+use vmbus_channel::RawAsyncChannel;
+use zerocopy::AsBytes;
+use zerocopy::FromZeroes;
+
+/// A guest file copy IC device.
+#[derive(InspectMut)]
+pub struct FcopyIc {
+    #[inspect(skip)]
+    recv: mesh::Receiver<FcopyRpc>,
+    #[inspect(skip)]
+    wait_ready: Vec<Rpc<(), ()>>,
+}
+
+#[doc(hidden)]
+#[derive(InspectMut)]
+pub struct FcopyChannel {
+    #[inspect(mut)]
+    pipe: MessagePipe<GpadlRingMem>,
+    state: ChannelState,
+    #[inspect(with = "Option::is_some")]
+    pending: Option<mesh::OneshotSender<Result<(), FcopyError>>>,
+}
+
+#[derive(Inspect)]
+#[inspect(external_tag)]
+enum ChannelState {
+    SendVersion,
+    WaitVersion,
+    Ready {
+        #[inspect(display)]
+        framework_version: hyperv_ic_protocol::Version,
+        #[inspect(display)]
+        message_version: hyperv_ic_protocol::Version,
+        state: ReadyState,
+    },
+}
+
+#[derive(Inspect)]
+#[inspect(external_tag)]
+enum ReadyState {
+    Ready,
+    Copying(#[inspect(flatten)] Box<Copy>),
+}
+
+#[derive(Inspect)]
+struct Copy {
+    /// The host file. This is `None` if the copy was interrupted by a
+    /// save/restore, in which case the guest file is cancelled.
+    #[inspect(skip)]
+    file: Option<Arc<File>>,
+    #[inspect(skip)]
+    start: Box<hyperv_ic_protocol::fcopy::StartCopyMessage>,
+    file_size: u64,
+    offset: u64,
+    /// Data read from the file but not yet sent to the guest.
+    #[inspect(skip)]
+    data: Vec<u8>,
+    #[inspect(debug)]
+    phase: Phase,
+    /// Whether the message for `phase` has been sent and the response is
+    /// outstanding.
+    waiting: bool,
+    #[inspect(skip)]
+    error: Option<FcopyError>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Phase {
+    Start,
+    Write,
+    Complete,
+    Cancel,
+}
+
+impl FcopyIc {
+    /// Returns a new file copy IC, using `recv` to receive copy requests.
+    pub fn new(recv: mesh::Receiver<FcopyRpc>) -> Self {
+        Self {
+            recv,
+            wait_ready: Vec::new(),
+        }
+    }
+
+    fn open_channel(
+        &mut self,
+        channel: RawAsyncChannel<GpadlRingMem>,
+        restore_state: Option<ChannelState>,
+    ) -> Result<FcopyChannel, ChannelOpenError> {
+        let pipe = MessagePipe::new(channel)?;
+        Ok(FcopyChannel::new(pipe, restore_state))
+    }
+}
+
+fn header(operation: FcopyOperation) -> hyperv_ic_protocol::fcopy::FcopyHeader {
+    hyperv_ic_protocol::fcopy::FcopyHeader {
+        operation,
+        service_id0: Guid::ZERO,
+        service_id1: Guid::ZERO,
+    }
+}
+
+/// Encodes `s` as a null-terminated UTF-16 string into `dest`.
+fn encode_utf16(s: &str, dest: &mut [u16]) -> Result<(), FcopyError> {
+    for (i, c) in s.encode_utf16().chain([0]).enumerate() {
+        *dest.get_mut(i).ok_or(FcopyError::PathTooLong)? = c;
+    }
+    Ok(())
+}
+
+impl Copy {
+    fn new(params: CopyFileParams) -> Result<Self, FcopyError> {
+        let file_size = params
+            .file
+            .metadata()
+            .map_err(|err| FcopyError::Read(err.to_string()))?
+            .len();
+        let mut start = hyperv_ic_protocol::fcopy::StartCopyMessage::new_box_zeroed();
+        start.header = header(FcopyOperation::START_FILE_COPY);
+        let mut name = [0; hyperv_ic_protocol::fcopy::MAX_PATH];
+        encode_utf16(&params.file_name, &mut name)?;
+        start.file_name = name;
+        encode_utf16(&params.path, &mut name)?;
+        start.path_name = name;
+        let mut flags = 0;
+        if params.overwrite {
+            flags |= hyperv_ic_protocol::fcopy::FLAG_OVERWRITE;
+        }
+        if params.create_path {
+            flags |= hyperv_ic_protocol::fcopy::FLAG_CREATE_PATH;
+        }
+        start.copy_flags = flags;
+        start.file_size = file_size;
+        Ok(Self {
+            file: Some(Arc::new(params.file)),
+            start,
+            file_size,
+            offset: 0,
+            data: Vec::new(),
+            phase: Phase::Start,
+            waiting: false,
+            error: None,
+        })
+    }
+
+    /// Returns a copy that just cancels the guest file.
+    fn cancelled(waiting: bool) -> Self {
+        Self {
+            file: None,
+            start: hyperv_ic_protocol::fcopy::StartCopyMessage::new_box_zeroed(),
+            file_size: 0,
+            offset: 0,
+            data: Vec::new(),
+            phase: Phase::Cancel,
+            waiting,
+            error: None,
+        }
+    }
+
+    fn fail(&mut self, error: FcopyError) {
+        self.error = Some(error);
+        self.phase = Phase::Cancel;
+        self.waiting = false;
+    }
+
+    /// Reads the next fragment of the file into `data`, if it is not already
+    /// there.
+    ///
+    /// The read runs on the blocking thread pool. It reads at an explicit
+    /// offset rather than advancing the file position, so it can be cancelled
+    /// and retried.
+    async fn read_data(&mut self) -> io::Result<()> {
+        if self.data.is_empty() {
+            let len = (self.file_size - self.offset).min(DATA_FRAGMENT_SIZE as u64) as usize;
+            let file = self.file.clone().expect("file is present until cancelled");
+            let offset = self.offset;
+            self.data = unblock(move || {
+                let mut data = vec![0; len];
+                let mut n = 0;
+                while n < len {
+                    match read_at(&file, &mut data[n..], offset + n as u64) {
+                        Ok(0) => break,
+                        Ok(m) => n += m,
+                        Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                data.truncate(n);
+                Ok(data)
+            })
+            .await?;
+            if self.data.is_empty() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::FileExt::read_at(file, buf, offset)
+    }
+    #[cfg(windows)]
+    {
+        std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+    }
+}
+
+fn status_error(status: u32) -> FcopyError {
+    match status {
+        hyperv_ic_protocol::fcopy::STATUS_ALREADY_EXISTS => FcopyError::AlreadyExists,
+        hyperv_ic_protocol::fcopy::STATUS_DISK_FULL => FcopyError::DiskFull,
+        status => FcopyError::Failed(status),
+    }
+}
+
+impl FcopyChannel {
+    fn new(pipe: MessagePipe<GpadlRingMem>, restore_state: Option<ChannelState>) -> Self {
+        Self {
+            pipe,
+            state: restore_state.unwrap_or(ChannelState::SendVersion),
+            pending: None,
+        }
+    }
+
+    async fn process(&mut self, ic: &mut FcopyIc) -> Result<(), Error> {
+        enum Event {
+            StateMachine(Result<(), Error>),
+            Request(FcopyRpc),
+        }
+
+        loop {
+            let event = pin!((
+                once(
+                    self.process_state_machine(&mut ic.wait_ready)
+                        .map(Event::StateMachine)
+                ),
+                (&mut ic.recv).map(Event::Request),
+            )
+                .merge())
+            .next()
+            .await
+            .unwrap();
+            match event {
+                Event::StateMachine(r) => {
+                    r?;
+                }
+                Event::Request(req) => match req {
+                    FcopyRpc::WaitReady(rpc) => match self.state {
+                        ChannelState::SendVersion | ChannelState::WaitVersion => {
+                            ic.wait_ready.push(rpc)
+                        }
+                        ChannelState::Ready { .. } => rpc.complete(()),
+                    },
+                    FcopyRpc::CopyFile(rpc) => match self.state {
+                        ChannelState::SendVersion | ChannelState::WaitVersion => {
+                            rpc.complete(Err(FcopyError::NotReady))
+                        }
+                        ChannelState::Ready { ref mut state, .. } => match state {
+                            ReadyState::Ready => {
+                                let (params, send) = (rpc.0, rpc.1);
+                                match Copy::new(params) {
+                                    Ok(copy) => {
+                                        self.pending = Some(send);
+                                        *state = ReadyState::Copying(Box::new(copy));
+                                    }
+                                    Err(err) => send.send(Err(err)),
+                                }
+                            }
+                            ReadyState::Copying(_) => rpc.complete(Err(FcopyError::InProgress)),
+                        },
+                    },
+                },
+            }
+        }
+    }
+
+    async fn process_state_machine(
+        &mut self,
+        wait_ready: &mut Vec<Rpc<(), ()>>,
+    ) -> Result<(), Error> {
+        match self.state {
+            ChannelState::SendVersion => {
+                common::send_version_request(&mut self.pipe, FRAMEWORK_VERSIONS, FCOPY_VERSIONS)
+                    .await?;
+                self.state = ChannelState::WaitVersion;
+            }
+            ChannelState::WaitVersion => {
+                let (framework_version, message_version) =
+                    common::read_version_response(&mut self.pipe).await?;
+                self.state = ChannelState::Ready {
+                    framework_version,
+                    message_version,
+                    state: ReadyState::Ready,
+                };
+                for rpc in wait_ready.drain(..) {
+                    rpc.complete(());
+                }
+            }
+            ChannelState::Ready {
+                ref mut state,
+                framework_version,
+                message_version,
+            } => {
+                let ReadyState::Copying(copy) = state else {
+                    return std::future::pending().await;
+                };
+                if !copy.waiting {
+                    let write;
+                    let header_only;
+                    let message: &[u8] = match copy.phase {
+                        Phase::Start => copy.start.as_bytes(),
+                        Phase::Write => {
+                            if let Err(err) = copy.read_data().await {
+                                copy.fail(FcopyError::Read(err.to_string()));
+                                return Ok(());
+                            }
+                            let mut data = [0; DATA_FRAGMENT_SIZE];
+                            data[..copy.data.len()].copy_from_slice(&copy.data);
+                            write = Box::new(hyperv_ic_protocol::fcopy::WriteMessage {
+                                header: header(FcopyOperation::WRITE_TO_FILE),
+                                pad: 0,
+                                offset: copy.offset,
+                                size: copy.data.len() as u32,
+                                data,
+                            });
+                            write.as_bytes()
+                        }
+                        Phase::Complete => {
+                            header_only = header(FcopyOperation::COMPLETE_FCOPY);
+                            header_only.as_bytes()
+                        }
+                        Phase::Cancel => {
+                            header_only = header(FcopyOperation::CANCEL_FCOPY);
+                            header_only.as_bytes()
+                        }
+                    };
+                    common::send_request(
+                        &mut self.pipe,
+                        framework_version,
+                        message_version,
+                        hyperv_ic_protocol::MessageType::GUEST_INTERFACE,
+                        &[message],
+                    )
+                    .await?;
+                    copy.waiting = true;
+                } else {
+                    let (status, _) = common::read_response(&mut self.pipe).await?;
+                    copy.waiting = false;
+                    if copy.file.is_none() && copy.phase != Phase::Cancel {
+                        // This is the response to a message sent before
+                        // restore. Cancel the copy now.
+                        copy.phase = Phase::Cancel;
+                        return Ok(());
+                    }
+                    let result = match (copy.phase, status) {
+                        (Phase::Start, 0) => {
+                            copy.phase = if copy.file_size == 0 {
+                                Phase::Complete
+                            } else {
+                                Phase::Write
+                            };
+                            None
+                        }
+                        // The guest did not create the file, so there is
+                        // nothing to cancel.
+                        (Phase::Start, status) => Some(Err(status_error(status))),
+                        (Phase::Write, 0) => {
+                            copy.offset += copy.data.len() as u64;
+                            copy.data.clear();
+                            if copy.offset >= copy.file_size {
+                                copy.phase = Phase::Complete;
+                            }
+                            None
+                        }
+                        (Phase::Write, status) => {
+                            copy.fail(status_error(status));
+                            None
+                        }
+                        (Phase::Complete, 0) => Some(Ok(())),
+                        (Phase::Complete, status) => Some(Err(status_error(status))),
+                        (Phase::Cancel, _) => {
+                            Some(Err(copy.error.take().unwrap_or(FcopyError::Failed(0))))
+                        }
+                    };
+                    if let Some(result) = result {
+                        if let Some(send) = self.pending.take() {
+                            send.send(result);
+                        }
+                        *state = ReadyState::Ready;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SimpleVmbusDevice for FcopyIc {
+    type SavedState = save_restore::state::SavedState;
+    type Runner = FcopyChannel;
+
+    fn offer(&self) -> OfferParams {
+        OfferParams {
+            interface_name: "fcopy_ic".to_owned(),
+            instance_id: hyperv_ic_protocol::fcopy::INSTANCE_ID,
+            interface_id: hyperv_ic_protocol::fcopy::INTERFACE_ID,
+            channel_type: ChannelType::Pipe { message_mode: true },
+            ..Default::default()
+        }
+    }
+
+    fn inspect(&mut self, req: inspect::Request<'_>, runner: Option<&mut Self::Runner>) {
+        req.respond().merge(self).merge(runner);
+    }
+
+    fn open(
+        &mut self,
+        channel: RawAsyncChannel<GpadlRingMem>,
+    ) -> Result<Self::Runner, ChannelOpenError> {
+        self.open_channel(channel, None)
+    }
+
+    async fn run(
+        &mut self,
+        stop: &mut StopTask<'_>,
+        runner: &mut Self::Runner,
+    ) -> Result<(), Cancelled> {
+        stop.until_stopped(async {
+            match runner.process(self).await {
+                Ok(()) => {}
+                Err(err) => {
+                    tracing::error!(error = &err as &dyn std::error::Error, "fcopy ic error")
+                }
+            }
+        })
+        .await
+    }
+
+    fn supports_save_restore(
+        &mut self,
+    ) -> Option<
+        &mut dyn SaveRestoreSimpleVmbusDevice<SavedState = Self::SavedState, Runner = Self::Runner>,
+    > {
+        Some(self)
+    }
+}
+
+mod save_restore {
+    use super::*;
+
+    pub mod state {
+        use mesh::payload::Protobuf;
+        use vmcore::save_restore::SavedStateRoot;
+
+        #[derive(Copy, Clone, Eq, PartialEq, Protobuf)]
+        #[mesh(package = "fcopy_ic")]
+        pub struct Version {
+            #[mesh(1)]
+            pub major: u16,
+            #[mesh(2)]
+            pub minor: u16,
+        }
+
+        impl From<hyperv_ic_protocol::Version> for Version {
+            fn from(version: hyperv_ic_protocol::Version) -> Self {
+                Self {
+                    major: version.major,
+                    minor: version.minor,
+                }
+            }
+        }
+
+        impl From<Version> for hyperv_ic_protocol::Version {
+            fn from(version: Version) -> Self {
+                Self {
+                    major: version.major,
+                    minor: version.minor,
+                }
+            }
+        }
+
+        /// The host file cannot be saved, so a copy in progress is cancelled
+        /// after restore.
+        #[derive(Protobuf, SavedStateRoot)]
+        #[mesh(package = "fcopy_ic")]
+        pub struct SavedState {
+            #[mesh(1)]
+            pub version: Option<(Version, Version)>,
+            #[mesh(2)]
+            pub waiting_on_version: bool,
+            #[mesh(3)]
+            pub copying: bool,
+            #[mesh(4)]
+            pub waiting_on_response: bool,
+        }
+    }
+
+    impl SaveRestoreSimpleVmbusDevice for FcopyIc {
+        fn save_open(&mut self, runner: &Self::Runner) -> state::SavedState {
+            let (version, copying, waiting_on_response) = if let ChannelState::Ready {
+                framework_version,
+                message_version,
+                state,
+            } = &runner.state
+            {
+                let (copying, waiting) = match state {
+                    ReadyState::Ready => (false, false),
+                    ReadyState::Copying(copy) => (true, copy.waiting),
+                };
+                (
+                    Some(((*framework_version).into(), (*message_version).into())),
+                    copying,
+                    waiting,
+                )
+            } else {
+                (None, false, false)
+            };
+            state::SavedState {
+                version,
+                waiting_on_version: matches!(runner.state, ChannelState::WaitVersion),
+                copying,
+                waiting_on_response,
+            }
+        }
+
+        fn restore_open(
+            &mut self,
+            saved_state: Self::SavedState,
+            channel: RawAsyncChannel<GpadlRingMem>,
+        ) -> Result<Self::Runner, ChannelOpenError> {
+            let state = if let Some((framework, message)) = saved_state.version {
+                let state = if saved_state.copying {
+                    // Any outstanding response is consumed before the cancel
+                    // is sent.
+                    let mut copy = Copy::cancelled(saved_state.waiting_on_response);
+                    if saved_state.waiting_on_response {
+                        copy.phase = Phase::Write;
+                    }
+                    ReadyState::Copying(Box::new(copy))
+                } else {
+                    ReadyState::Ready
+                };
+                ChannelState::Ready {
+                    framework_version: framework.into(),
+                    message_version: message.into(),
+                    state,
+                }
+            } else if saved_state.waiting_on_version {
+                ChannelState::WaitVersion
+            } else {
+                ChannelState::SendVersion
+            };
+            self.open_channel(channel, Some(state))
+        }
+    }
+}
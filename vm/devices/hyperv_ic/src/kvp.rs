@@ -0,0 +1,532 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The KVP (key-value pair) IC.
+
+use crate::common;
+use crate::common::Error;
+use async_trait::async_trait;
+use futures::stream::once;
+use futures::FutureExt;
+use futures::StreamExt;
+use futures_concurrency::stream::Merge;
+use hyperv_ic_protocol::kvp::FRAMEWORK_VERSIONS;
+use hyperv_ic_protocol::kvp::KVP_VERSIONS;
+use hyperv_ic_protocol::kvp::MAX_KEY_SIZE;
+use hyperv_ic_protocol::kvp::MAX_VALUE_SIZE;
+use hyperv_ic_resources::kvp::KeyValue;
+use hyperv_ic_resources::kvp::KvpError;
+use hyperv_ic_resources::kvp::KvpPool;
+use hyperv_ic_resources::kvp::KvpRpc;
+use hyperv_ic_resources::kvp::Value;
+use inspect::Inspect;
+use inspect::InspectMut;
+use mesh::rpc::Rpc;
+use std::collections::VecDeque;
+use std::pin::pin;
+use task_control::Cancelled;
+use task_control::StopTask;
+use vmbus_async::pipe::MessagePipe;
+use vmbus_channel::bus::ChannelType;
+use vmbus_channel::bus::OfferParams;
+use vmbus_channel::channel::ChannelOpenError;
+use vmbus_channel::gpadl_ring::GpadlRingMem;
+use vmbus_channel::simple::SaveRestoreSimpleVmbusDevice;
+use vmbus_channel::simple::SimpleVmbusDevice;
+use vmbus_channel::RawAsyncChannel;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+/// A KVP IC device.
+#[derive(InspectMut)]
+pub struct KvpIc {
+    #[inspect(skip)]
+    recv: mesh::Receiver<KvpRpc>,
+    #[inspect(skip)]
+    wait_ready: Vec<Rpc<(), ()>>,
+}
+
+#[doc(hidden)]
+#[derive(InspectMut)]
+pub struct KvpChannel {
+    #[inspect(mut)]
+    pipe: MessagePipe<GpadlRingMem>,
+    state: ChannelState,
+    #[inspect(with = "VecDeque::len")]
+    queued: VecDeque<KvpRpc>,
+    #[inspect(with = "Option::is_some")]
+    in_flight: Option<KvpRpc>,
+}
+
+#[derive(Inspect)]
+#[inspect(external_tag)]
+enum ChannelState {
+    SendVersion,
+    WaitVersion,
+    Ready {
+        #[inspect(display)]
+        framework_version: hyperv_ic_protocol::Version,
+        #[inspect(display)]
+        message_version: hyperv_ic_protocol::Version,
+        state: ReadyState,
+    },
+}
+
+#[derive(Inspect)]
+#[inspect(external_tag)]
+enum ReadyState {
+    Ready,
+    WaitResponse,
+}
+
+impl KvpIc {
+    /// Returns a new KVP IC, using `recv` to receive requests.
+    pub fn new(recv: mesh::Receiver<KvpRpc>) -> Self {
+        Self {
+            recv,
+            wait_ready: Vec::new(),
+        }
+    }
+
+    fn open_channel(
+        &mut self,
+        channel: RawAsyncChannel<GpadlRingMem>,
+        restore_state: Option<ChannelState>,
+    ) -> Result<KvpChannel, ChannelOpenError> {
+        let pipe = MessagePipe::new(channel)?;
+        Ok(KvpChannel::new(pipe, restore_state))
+    }
+}
+
+impl KvpChannel {
+    fn new(pipe: MessagePipe<GpadlRingMem>, restore_state: Option<ChannelState>) -> Self {
+        Self {
+            pipe,
+            state: restore_state.unwrap_or(ChannelState::SendVersion),
+            queued: VecDeque::new(),
+            in_flight: None,
+        }
+    }
+
+    async fn process(&mut self, ic: &mut KvpIc) -> Result<(), Error> {
+        enum Event {
+            StateMachine(Result<(), Error>),
+            Request(KvpRpc),
+        }
+
+        loop {
+            let event = pin!((
+                once(
+                    self.process_state_machine(&mut ic.wait_ready)
+                        .map(Event::StateMachine)
+                ),
+                (&mut ic.recv).map(Event::Request),
+            )
+                .merge())
+            .next()
+            .await
+            .unwrap();
+            match event {
+                Event::StateMachine(r) => {
+                    r?;
+                }
+                Event::Request(req) => match (req, &self.state) {
+                    (
+                        KvpRpc::WaitReady(rpc),
+                        ChannelState::SendVersion | ChannelState::WaitVersion,
+                    ) => ic.wait_ready.push(rpc),
+                    (req, ChannelState::SendVersion | ChannelState::WaitVersion) => {
+                        fail(req, KvpError::NotReady)
+                    }
+                    (KvpRpc::WaitReady(rpc), ChannelState::Ready { .. }) => rpc.complete(()),
+                    (req, ChannelState::Ready { .. }) => self.queued.push_back(req),
+                },
+            }
+        }
+    }
+
+    async fn process_state_machine(
+        &mut self,
+        wait_ready: &mut Vec<Rpc<(), ()>>,
+    ) -> Result<(), Error> {
+        match self.state {
+            ChannelState::SendVersion => {
+                common::send_version_request(&mut self.pipe, FRAMEWORK_VERSIONS, KVP_VERSIONS)
+                    .await?;
+                self.state = ChannelState::WaitVersion;
+            }
+            ChannelState::WaitVersion => {
+                let (framework_version, message_version) =
+                    common::read_version_response(&mut self.pipe).await?;
+                self.state = ChannelState::Ready {
+                    framework_version,
+                    message_version,
+                    state: ReadyState::Ready,
+                };
+                for rpc in wait_ready.drain(..) {
+                    rpc.complete(());
+                }
+            }
+            ChannelState::Ready {
+                ref mut state,
+                framework_version,
+                message_version,
+            } => match state {
+                ReadyState::Ready => {
+                    let Some(req) = self.queued.front() else {
+                        return std::future::pending().await;
+                    };
+                    match encode_request(req) {
+                        Ok((header, message)) => {
+                            common::send_request(
+                                &mut self.pipe,
+                                framework_version,
+                                message_version,
+                                hyperv_ic_protocol::MessageType::KVP_EXCHANGE,
+                                &[header.as_bytes(), message.as_slice()],
+                            )
+                            .await?;
+                            self.in_flight = self.queued.pop_front();
+                            *state = ReadyState::WaitResponse;
+                        }
+                        Err(err) => fail(self.queued.pop_front().unwrap(), err),
+                    }
+                }
+                ReadyState::WaitResponse => {
+                    let (status, message) = common::read_response(&mut self.pipe).await?;
+                    // There is no request to complete if the response was
+                    // outstanding across a save/restore.
+                    if let Some(req) = self.in_flight.take() {
+                        complete(req, status, &message);
+                    }
+                    *state = ReadyState::Ready;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+fn protocol_pool(pool: KvpPool) -> hyperv_ic_protocol::kvp::KvpPool {
+    match pool {
+        KvpPool::External => hyperv_ic_protocol::kvp::KvpPool::EXTERNAL,
+        KvpPool::Guest => hyperv_ic_protocol::kvp::KvpPool::GUEST,
+        KvpPool::Auto => hyperv_ic_protocol::kvp::KvpPool::AUTO,
+        KvpPool::AutoExternal => hyperv_ic_protocol::kvp::KvpPool::AUTO_EXTERNAL,
+    }
+}
+
+/// Encodes `s` as a null-terminated UTF-16 string into `dest`, returning the
+/// size in bytes.
+fn encode_utf16(s: &str, dest: &mut [u16]) -> Result<u32, KvpError> {
+    let mut len = 0;
+    for c in s.encode_utf16().chain([0]) {
+        *dest.get_mut(len).ok_or(KvpError::TooLong)? = c;
+        len += 1;
+    }
+    Ok((len * 2) as u32)
+}
+
+fn decode_utf16(data: &[u8]) -> String {
+    let chars = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .take_while(|&c| c != 0)
+        .collect::<Vec<_>>();
+    String::from_utf16_lossy(&chars)
+}
+
+fn encode_value(
+    key: &str,
+    value: Option<&Value>,
+) -> Result<hyperv_ic_protocol::kvp::Value, KvpError> {
+    let mut data = hyperv_ic_protocol::kvp::Value::new_zeroed();
+    data.key_size = encode_utf16(key, &mut data.key)?;
+    match value {
+        None => {}
+        Some(Value::String(s)) => {
+            let mut value = [0u16; MAX_VALUE_SIZE / 2];
+            data.value_type = hyperv_ic_protocol::kvp::ValueType::STRING;
+            data.value_size = encode_utf16(s, &mut value)?;
+            data.value.copy_from_slice(value.as_bytes());
+        }
+        Some(&Value::U32(n)) => {
+            data.value_type = hyperv_ic_protocol::kvp::ValueType::U32;
+            data.value_size = size_of_val(&n) as u32;
+            data.value[..4].copy_from_slice(&n.to_le_bytes());
+        }
+        Some(&Value::U64(n)) => {
+            data.value_type = hyperv_ic_protocol::kvp::ValueType::U64;
+            data.value_size = size_of_val(&n) as u32;
+            data.value[..8].copy_from_slice(&n.to_le_bytes());
+        }
+    }
+    Ok(data)
+}
+
+fn decode_value(data: &hyperv_ic_protocol::kvp::Value) -> Result<KeyValue, KvpError> {
+    let key_size = (data.key_size as usize).min(MAX_KEY_SIZE);
+    let key = decode_utf16(&data.key.as_bytes()[..key_size]);
+    let value = &data.value[..(data.value_size as usize).min(MAX_VALUE_SIZE)];
+    let value = match data.value_type {
+        hyperv_ic_protocol::kvp::ValueType::STRING
+        | hyperv_ic_protocol::kvp::ValueType::EXPAND_STRING => Value::String(decode_utf16(value)),
+        hyperv_ic_protocol::kvp::ValueType::U32 => {
+            Value::U32(u32::read_from_prefix(value).ok_or(KvpError::InvalidResponse)?)
+        }
+        hyperv_ic_protocol::kvp::ValueType::U64 => {
+            Value::U64(u64::read_from_prefix(value).ok_or(KvpError::InvalidResponse)?)
+        }
+        _ => return Err(KvpError::InvalidResponse),
+    };
+    Ok(KeyValue { key, value })
+}
+
+fn encode_request(req: &KvpRpc) -> Result<(hyperv_ic_protocol::kvp::KvpHeader, Vec<u8>), KvpError> {
+    let (operation, pool, message) = match req {
+        KvpRpc::WaitReady(_) => unreachable!(),
+        KvpRpc::Set(rpc) => {
+            let message = hyperv_ic_protocol::kvp::GetSetMessage {
+                data: encode_value(&rpc.0.key, Some(&rpc.0.value))?,
+            };
+            (
+                hyperv_ic_protocol::kvp::KvpOperation::SET,
+                rpc.0.pool,
+                message.as_bytes().to_vec(),
+            )
+        }
+        KvpRpc::Delete(rpc) => {
+            let mut message = hyperv_ic_protocol::kvp::DeleteMessage::new_zeroed();
+            message.key_size = encode_utf16(&rpc.0.key, &mut message.key)?;
+            (
+                hyperv_ic_protocol::kvp::KvpOperation::DELETE,
+                rpc.0.pool,
+                message.as_bytes().to_vec(),
+            )
+        }
+        KvpRpc::Get(rpc) => {
+            let message = hyperv_ic_protocol::kvp::GetSetMessage {
+                data: encode_value(&rpc.0.key, None)?,
+            };
+            (
+                hyperv_ic_protocol::kvp::KvpOperation::GET,
+                rpc.0.pool,
+                message.as_bytes().to_vec(),
+            )
+        }
+        KvpRpc::Enumerate(rpc) => {
+            let message = hyperv_ic_protocol::kvp::EnumerateMessage {
+                index: rpc.0.index,
+                data: FromZeroes::new_zeroed(),
+            };
+            (
+                hyperv_ic_protocol::kvp::KvpOperation::ENUMERATE,
+                rpc.0.pool,
+                message.as_bytes().to_vec(),
+            )
+        }
+    };
+    let header = hyperv_ic_protocol::kvp::KvpHeader {
+        operation,
+        pool: protocol_pool(pool),
+        pad: 0,
+    };
+    Ok((header, message))
+}
+
+fn fail(req: KvpRpc, err: KvpError) {
+    match req {
+        KvpRpc::WaitReady(rpc) => rpc.complete(()),
+        KvpRpc::Set(rpc) => rpc.complete(Err(err)),
+        KvpRpc::Delete(rpc) => rpc.complete(Err(err)),
+        KvpRpc::Get(rpc) => rpc.complete(Err(err)),
+        KvpRpc::Enumerate(rpc) => rpc.complete(Err(err)),
+    }
+}
+
+fn check_status(status: u32) -> Result<(), KvpError> {
+    match status {
+        0 => Ok(()),
+        status => Err(KvpError::Failed(status)),
+    }
+}
+
+fn decode_get(status: u32, message: &[u8]) -> Result<Option<Value>, KvpError> {
+    if status == hyperv_ic_protocol::kvp::STATUS_NO_MORE_ITEMS {
+        return Ok(None);
+    }
+    check_status(status)?;
+    let message = hyperv_ic_protocol::kvp::GetSetMessage::read_from_prefix(message)
+        .ok_or(KvpError::InvalidResponse)?;
+    Ok(Some(decode_value(&message.data)?.value))
+}
+
+fn decode_enumerate(status: u32, message: &[u8]) -> Result<Option<KeyValue>, KvpError> {
+    if status == hyperv_ic_protocol::kvp::STATUS_NO_MORE_ITEMS {
+        return Ok(None);
+    }
+    check_status(status)?;
+    let message = hyperv_ic_protocol::kvp::EnumerateMessage::read_from_prefix(message)
+        .ok_or(KvpError::InvalidResponse)?;
+    Ok(Some(decode_value(&message.data)?))
+}
+
+fn complete(req: KvpRpc, status: u32, message: &[u8]) {
+    // Skip the KVP header to get to the operation-specific message.
+    let message = message
+        .get(size_of::<hyperv_ic_protocol::kvp::KvpHeader>()..)
+        .unwrap_or_default();
+    match req {
+        KvpRpc::WaitReady(rpc) => rpc.complete(()),
+        KvpRpc::Set(rpc) => rpc.complete(check_status(status)),
+        KvpRpc::Delete(rpc) => rpc.complete(check_status(status)),
+        KvpRpc::Get(rpc) => rpc.complete(decode_get(status, message)),
+        KvpRpc::Enumerate(rpc) => rpc.complete(decode_enumerate(status, message)),
+    }
+}
+
+#[async_trait]
+impl SimpleVmbusDevice for KvpIc {
+    type SavedState = save_restore::state::SavedState;
+    type Runner = KvpChannel;
+
+    fn offer(&self) -> OfferParams {
+        OfferParams {
+            interface_name: "kvp_ic".to_owned(),
+            instance_id: hyperv_ic_protocol::kvp::INSTANCE_ID,
+            interface_id: hyperv_ic_protocol::kvp::INTERFACE_ID,
+            channel_type: ChannelType::Pipe { message_mode: true },
+            ..Default::default()
+        }
+    }
+
+    fn inspect(&mut self, req: inspect::Request<'_>, runner: Option<&mut Self::Runner>) {
+        req.respond().merge(self).merge(runner);
+    }
+
+    fn open(
+        &mut self,
+        channel: RawAsyncChannel<GpadlRingMem>,
+    ) -> Result<Self::Runner, ChannelOpenError> {
+        self.open_channel(channel, None)
+    }
+
+    async fn run(
+        &mut self,
+        stop: &mut StopTask<'_>,
+        runner: &mut Self::Runner,
+    ) -> Result<(), Cancelled> {
+        stop.until_stopped(async {
+            match runner.process(self).await {
+                Ok(()) => {}
+                Err(err) => {
+                    tracing::error!(error = &err as &dyn std::error::Error, "kvp ic error")
+                }
+            }
+        })
+        .await
+    }
+
+    fn supports_save_restore(
+        &mut self,
+    ) -> Option<
+        &mut dyn SaveRestoreSimpleVmbusDevice<SavedState = Self::SavedState, Runner = Self::Runner>,
+    > {
+        Some(self)
+    }
+}
+
+mod save_restore {
+    use super::*;
+
+    pub mod state {
+        use mesh::payload::Protobuf;
+        use vmcore::save_restore::SavedStateRoot;
+
+        #[derive(Copy, Clone, Eq, PartialEq, Protobuf)]
+        #[mesh(package = "kvp_ic")]
+        pub struct Version {
+            #[mesh(1)]
+            pub major: u16,
+            #[mesh(2)]
+            pub minor: u16,
+        }
+
+        impl From<hyperv_ic_protocol::Version> for Version {
+            fn from(version: hyperv_ic_protocol::Version) -> Self {
+                Self {
+                    major: version.major,
+                    minor: version.minor,
+                }
+            }
+        }
+
+        impl From<Version> for hyperv_ic_protocol::Version {
+            fn from(version: Version) -> Self {
+                Self {
+                    major: version.major,
+                    minor: version.minor,
+                }
+            }
+        }
+
+        /// Queued requests are not saved; their callers see the RPC fail.
+        #[derive(Protobuf, SavedStateRoot)]
+        #[mesh(package = "kvp_ic")]
+        pub struct SavedState {
+            #[mesh(1)]
+            pub version: Option<(Version, Version)>,
+            #[mesh(2)]
+            pub waiting_on_version: bool,
+            #[mesh(3)]
+            pub waiting_on_response: bool,
+        }
+    }
+
+    impl SaveRestoreSimpleVmbusDevice for KvpIc {
+        fn save_open(&mut self, runner: &Self::Runner) -> state::SavedState {
+            let (version, waiting_on_response) = if let ChannelState::Ready {
+                framework_version,
+                message_version,
+                state,
+            } = &runner.state
+            {
+                (
+                    Some(((*framework_version).into(), (*message_version).into())),
+                    matches!(state, ReadyState::WaitResponse),
+                )
+            } else {
+                (None, false)
+            };
+            state::SavedState {
+                version,
+                waiting_on_version: matches!(runner.state, ChannelState::WaitVersion),
+                waiting_on_response,
+            }
+        }
+
+        fn restore_open(
+            &mut self,
+            saved_state: Self::SavedState,
+            channel: RawAsyncChannel<GpadlRingMem>,
+        ) -> Result<Self::Runner, ChannelOpenError> {
+            let state = if let Some((framework, message)) = saved_state.version {
+                ChannelState::Ready {
+                    framework_version: framework.into(),
+                    message_version: message.into(),
+                    state: if saved_state.waiting_on_response {
+                        ReadyState::WaitResponse
+                    } else {
+                        ReadyState::Ready
+                    },
+                }
+            } else if saved_state.waiting_on_version {
+                ChannelState::WaitVersion
+            } else {
+                ChannelState::SendVersion
+            };
+            self.open_channel(channel, Some(state))
+        }
+    }
+}
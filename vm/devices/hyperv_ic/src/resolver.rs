@@ -3,8 +3,16 @@
 
 //! Resource resolvers for the ICs.
 
+use crate::fcopy::FcopyIc;
+use crate::kvp::KvpIc;
 use crate::shutdown::ShutdownIc;
+use crate::timesync::TimesyncIc;
+use crate::vss::VssIc;
+use hyperv_ic_resources::fcopy::FcopyIcHandle;
+use hyperv_ic_resources::kvp::KvpIcHandle;
 use hyperv_ic_resources::shutdown::ShutdownIcHandle;
+use hyperv_ic_resources::timesync::TimesyncIcHandle;
+use hyperv_ic_resources::vss::VssIcHandle;
 use std::convert::Infallible;
 use vm_resource::declare_static_resolver;
 use vm_resource::kind::VmbusDeviceHandleKind;
@@ -19,6 +27,10 @@
 declare_static_resolver! {
     IcResolver,
     (VmbusDeviceHandleKind, ShutdownIcHandle),
+    (VmbusDeviceHandleKind, KvpIcHandle),
+    (VmbusDeviceHandleKind, VssIcHandle),
+    (VmbusDeviceHandleKind, TimesyncIcHandle),
+    (VmbusDeviceHandleKind, FcopyIcHandle),
 }
 
 impl ResolveResource<VmbusDeviceHandleKind, ShutdownIcHandle> for IcResolver {
@@ -36,3 +48,68 @@ fn resolve(
         )
     }
 }
+
+impl ResolveResource<VmbusDeviceHandleKind, KvpIcHandle> for IcResolver {
+    type Output = ResolvedVmbusDevice;
+    type Error = Infallible;
+
+    fn resolve(
+        &self,
+        resource: KvpIcHandle,
+        input: ResolveVmbusDeviceHandleParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(
+            SimpleDeviceWrapper::new(input.driver_source.simple(), KvpIc::new(resource.recv))
+                .into(),
+        )
+    }
+}
+
+impl ResolveResource<VmbusDeviceHandleKind, VssIcHandle> for IcResolver {
+    type Output = ResolvedVmbusDevice;
+    type Error = Infallible;
+
+    fn resolve(
+        &self,
+        resource: VssIcHandle,
+        input: ResolveVmbusDeviceHandleParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(
+            SimpleDeviceWrapper::new(input.driver_source.simple(), VssIc::new(resource.recv))
+                .into(),
+        )
+    }
+}
+
+impl ResolveResource<VmbusDeviceHandleKind, TimesyncIcHandle> for IcResolver {
+    type Output = ResolvedVmbusDevice;
+    type Error = Infallible;
+
+    fn resolve(
+        &self,
+        resource: TimesyncIcHandle,
+        input: ResolveVmbusDeviceHandleParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(SimpleDeviceWrapper::new(
+            input.driver_source.simple(),
+            TimesyncIc::new(input.driver_source.simple(), resource.mode),
+        )
+        .into())
+    }
+}
+
+impl ResolveResource<VmbusDeviceHandleKind, FcopyIcHandle> for IcResolver {
+    type Output = ResolvedVmbusDevice;
+    type Error = Infallible;
+
+    fn resolve(
+        &self,
+        resource: FcopyIcHandle,
+        input: ResolveVmbusDeviceHandleParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(
+            SimpleDeviceWrapper::new(input.driver_source.simple(), FcopyIc::new(resource.recv))
+                .into(),
+        )
+    }
+}
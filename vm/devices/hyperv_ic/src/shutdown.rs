@@ -3,13 +3,17 @@
 
 //! The shutdown IC.
 
+use crate::common;
+use crate::common::Error;
 use async_trait::async_trait;
 use futures::stream::once;
 use futures::FutureExt;
 use futures::StreamExt;
 use futures_concurrency::stream::Merge;
 use hyperv_ic_protocol::shutdown::FRAMEWORK_VERSIONS;
+use hyperv_ic_protocol::shutdown::HIBERNATE_VERSION;
 use hyperv_ic_protocol::shutdown::SHUTDOWN_VERSIONS;
+use hyperv_ic_resources::shutdown::ShutdownCapabilities;
 use hyperv_ic_resources::shutdown::ShutdownParams;
 use hyperv_ic_resources::shutdown::ShutdownResult;
 use hyperv_ic_resources::shutdown::ShutdownRpc;
@@ -17,13 +21,9 @@
 use inspect::Inspect;
 use inspect::InspectMut;
 use mesh::rpc::Rpc;
-use std::io::IoSlice;
 use std::pin::pin;
 use task_control::Cancelled;
 use task_control::StopTask;
-use thiserror::Error;
-use vmbus_async::async_dgram::AsyncRecvExt;
-use vmbus_async::async_dgram::AsyncSendExt;
 use vmbus_async::pipe::MessagePipe;
 use vmbus_channel::bus::ChannelType;
 use vmbus_channel::bus::OfferParams;
@@ -33,9 +33,6 @@
 use vmbus_channel::simple::SimpleVmbusDevice;
 use vmbus_channel::RawAsyncChannel;
 use zerocopy::AsBytes;
-use zerocopy::FromBytes;
-use zerocopy::FromZeroes;
-use zerocopy_helpers::FromBytesExt;
 
 /// A shutdown IC device.
 #[derive(InspectMut)]
@@ -78,18 +75,6 @@ enum ReadyState {
     WaitShutdown,
 }
 
-#[derive(Debug, Error)]
-enum Error {
-    #[error("ring buffer error")]
-    Ring(#[source] std::io::Error),
-    #[error("truncated message")]
-    TruncatedMessage,
-    #[error("invalid version response")]
-    InvalidVersionResponse,
-    #[error("no supported versions")]
-    NoSupportedVersions,
-}
-
 impl ShutdownIc {
     /// Returns a new shutdown IC, using `recv` to receive shutdown requests.
     pub fn new(recv: mesh::Receiver<ShutdownRpc>) -> Self {
@@ -151,6 +136,13 @@ enum Event {
                         ChannelState::SendVersion | ChannelState::WaitVersion => {
                             rpc.complete(ShutdownResult::NotReady)
                         }
+                        ChannelState::Ready {
+                            message_version, ..
+                        } if matches!(rpc.0.shutdown_type, ShutdownType::Hibernate)
+                            && !capabilities(message_version).hibernate =>
+                        {
+                            rpc.complete(ShutdownResult::NotSupported)
+                        }
                         ChannelState::Ready { ref mut state, .. } => match state {
                             ReadyState::Ready => {
                                 self.pending_shutdown = Some(rpc.1);
@@ -161,6 +153,12 @@ enum Event {
                             }
                         },
                     },
+                    ShutdownRpc::Capabilities(rpc) => match self.state {
+                        ChannelState::SendVersion | ChannelState::WaitVersion => rpc.complete(None),
+                        ChannelState::Ready {
+                            message_version, ..
+                        } => rpc.complete(Some(capabilities(message_version))),
+                    },
                 },
             }
         }
@@ -172,51 +170,13 @@ async fn process_state_machine(
     ) -> Result<(), Error> {
         match self.state {
             ChannelState::SendVersion => {
-                let message_versions = SHUTDOWN_VERSIONS;
-
-                let message = hyperv_ic_protocol::NegotiateMessage {
-                    framework_version_count: FRAMEWORK_VERSIONS.len() as u16,
-                    message_version_count: message_versions.len() as u16,
-                    ..FromZeroes::new_zeroed()
-                };
-
-                let header = hyperv_ic_protocol::Header {
-                    message_type: hyperv_ic_protocol::MessageType::VERSION_NEGOTIATION,
-                    message_size: (size_of_val(&message)
-                        + size_of_val(FRAMEWORK_VERSIONS)
-                        + size_of_val(message_versions)) as u16,
-                    status: 0,
-                    transaction_id: 0,
-                    flags: hyperv_ic_protocol::HeaderFlags::new()
-                        .with_transaction(true)
-                        .with_request(true),
-                    ..FromZeroes::new_zeroed()
-                };
-
-                self.pipe
-                    .send_vectored(&[
-                        IoSlice::new(header.as_bytes()),
-                        IoSlice::new(message.as_bytes()),
-                        IoSlice::new(FRAMEWORK_VERSIONS.as_bytes()),
-                        IoSlice::new(message_versions.as_bytes()),
-                    ])
-                    .await
-                    .map_err(Error::Ring)?;
-
+                common::send_version_request(&mut self.pipe, FRAMEWORK_VERSIONS, SHUTDOWN_VERSIONS)
+                    .await?;
                 self.state = ChannelState::WaitVersion;
             }
             ChannelState::WaitVersion => {
-                let (_result, buf) = read_response(&mut self.pipe).await?;
-                let (message, rest) =
-                    hyperv_ic_protocol::NegotiateMessage::read_from_prefix_split(buf.as_slice())
-                        .ok_or(Error::TruncatedMessage)?;
-                if message.framework_version_count != 1 || message.message_version_count != 1 {
-                    return Err(Error::NoSupportedVersions);
-                }
-                let [framework_version, message_version] =
-                    <[hyperv_ic_protocol::Version; 2]>::read_from_prefix(rest)
-                        .ok_or(Error::TruncatedMessage)?;
-
+                let (framework_version, message_version) =
+                    common::read_version_response(&mut self.pipe).await?;
                 self.state = ChannelState::Ready {
                     framework_version,
                     message_version,
@@ -247,31 +207,19 @@ async fn process_state_machine(
                         flags,
                         message: [0; 2048],
                     });
-                    let header = hyperv_ic_protocol::Header {
+                    common::send_request(
+                        &mut self.pipe,
                         framework_version,
-                        message_type: hyperv_ic_protocol::MessageType::SHUTDOWN,
-                        message_size: size_of_val(message.as_ref()) as u16,
                         message_version,
-                        status: 0,
-                        transaction_id: 0,
-                        flags: hyperv_ic_protocol::HeaderFlags::new()
-                            .with_transaction(true)
-                            .with_request(true),
-                        ..FromZeroes::new_zeroed()
-                    };
-
-                    self.pipe
-                        .send_vectored(&[
-                            IoSlice::new(header.as_bytes()),
-                            IoSlice::new(message.as_bytes()),
-                        ])
-                        .await
-                        .map_err(Error::Ring)?;
+                        hyperv_ic_protocol::MessageType::SHUTDOWN,
+                        &[message.as_bytes()],
+                    )
+                    .await?;
 
                     *state = ReadyState::WaitShutdown;
                 }
                 ReadyState::WaitShutdown => {
-                    let (status, _) = read_response(&mut self.pipe).await?;
+                    let (status, _) = common::read_response(&mut self.pipe).await?;
                     let result = if status == 0 {
                         ShutdownResult::Ok
                     } else {
@@ -288,22 +236,12 @@ async fn process_state_machine(
     }
 }
 
-async fn read_response(pipe: &mut MessagePipe<GpadlRingMem>) -> Result<(u32, Vec<u8>), Error> {
-    let mut buf = vec![0; hyperv_ic_protocol::MAX_MESSAGE_SIZE];
-    let n = pipe.recv(&mut buf).await.map_err(Error::Ring)?;
-    let buf = &buf[..n];
-    let (header, rest) =
-        hyperv_ic_protocol::Header::read_from_prefix_split(buf).ok_or(Error::TruncatedMessage)?;
-
-    if header.transaction_id != 0 || !header.flags.transaction() || !header.flags.response() {
-        return Err(Error::InvalidVersionResponse);
+/// Returns the capabilities of a guest that negotiated `message_version`.
+fn capabilities(message_version: hyperv_ic_protocol::Version) -> ShutdownCapabilities {
+    ShutdownCapabilities {
+        hibernate: (message_version.major, message_version.minor)
+            >= (HIBERNATE_VERSION.major, HIBERNATE_VERSION.minor),
     }
-
-    let rest = rest
-        .get(..header.message_size as usize)
-        .ok_or(Error::TruncatedMessage)?;
-
-    Ok((header.status, rest.to_vec()))
 }
 
 #[async_trait]
@@ -0,0 +1,429 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The timesync IC.
+
+use crate::common;
+use crate::common::Error;
+use async_trait::async_trait;
+use hyperv_ic_protocol::timesync::TimesyncFlags;
+use hyperv_ic_protocol::timesync::FRAMEWORK_VERSIONS;
+use hyperv_ic_protocol::timesync::TIMESYNC_VERSIONS;
+use hyperv_ic_protocol::timesync::WINDOWS_EPOCH_DELTA;
+use hyperv_ic_resources::timesync::TimesyncMode;
+use inspect::Inspect;
+use inspect::InspectMut;
+use pal_async::timer::Instant;
+use pal_async::timer::PolledTimer;
+use std::time::Duration;
+use std::time::SystemTime;
+use task_control::Cancelled;
+use task_control::StopTask;
+use vmbus_async::pipe::MessagePipe;
+use vmbus_channel::bus::ChannelType;
+use vmbus_channel::bus::OfferParams;
+use vmbus_channel::channel::ChannelOpenError;
+use vmbus_channel::gpadl_ring::GpadlRingMem;
+use vmbus_channel::simple::SaveRestoreSimpleVmbusDevice;
+use vmbus_channel::simple::SimpleVmbusDevice;
+use vmbus_channel::RawAsyncChannel;
+use vmcore::vm_task::VmTaskDriver;
+use zerocopy::AsBytes;
+
+/// A timesync IC device.
+#[derive(InspectMut)]
+pub struct TimesyncIc {
+    #[inspect(skip)]
+    driver: VmTaskDriver,
+    #[inspect(debug)]
+    mode: TimesyncMode,
+}
+
+#[doc(hidden)]
+#[derive(InspectMut)]
+pub struct TimesyncChannel {
+    #[inspect(mut)]
+    pipe: MessagePipe<GpadlRingMem>,
+    state: ChannelState,
+    #[inspect(skip)]
+    timer: PolledTimer,
+    /// Whether the guest's time should be set at the next opportunity.
+    sync_pending: bool,
+    #[inspect(skip)]
+    next_sample: Option<Instant>,
+    syncs_sent: u64,
+    samples_sent: u64,
+    #[inspect(with = "|x| x.as_micros() as u64")]
+    round_trip_time: Duration,
+    #[inspect(skip)]
+    last_sync: Option<SyncPoint>,
+    #[inspect(skip)]
+    running: RunningTime,
+}
+
+#[derive(Inspect)]
+#[inspect(external_tag)]
+enum ChannelState {
+    SendVersion,
+    WaitVersion,
+    Ready {
+        #[inspect(display)]
+        framework_version: hyperv_ic_protocol::Version,
+        #[inspect(display)]
+        message_version: hyperv_ic_protocol::Version,
+        state: ReadyState,
+    },
+}
+
+#[derive(Inspect)]
+#[inspect(external_tag)]
+enum ReadyState {
+    Ready,
+    WaitResponse {
+        #[inspect(skip)]
+        sent: Instant,
+        #[inspect(skip)]
+        sync: Option<SyncPoint>,
+    },
+}
+
+/// The host time and VM running time at which the guest's time was last set.
+#[derive(Copy, Clone)]
+struct SyncPoint {
+    host_time: SystemTime,
+    running_time: Duration,
+}
+
+/// Tracks how long the VM has been running, to estimate how far the guest's
+/// clock has fallen behind the host's while the VM was paused.
+#[derive(Default)]
+struct RunningTime {
+    total: Duration,
+    since: Option<std::time::Instant>,
+}
+
+impl RunningTime {
+    fn start(&mut self) {
+        self.since = Some(std::time::Instant::now());
+    }
+
+    fn stop(&mut self) {
+        if let Some(since) = self.since.take() {
+            self.total += since.elapsed();
+        }
+    }
+
+    fn get(&self) -> Duration {
+        self.total + self.since.map_or(Duration::ZERO, |since| since.elapsed())
+    }
+}
+
+impl TimesyncIc {
+    /// Returns a new timesync IC.
+    pub fn new(driver: VmTaskDriver, mode: TimesyncMode) -> Self {
+        Self { driver, mode }
+    }
+
+    fn open_channel(
+        &mut self,
+        channel: RawAsyncChannel<GpadlRingMem>,
+        restore_state: Option<ChannelState>,
+    ) -> Result<TimesyncChannel, ChannelOpenError> {
+        let pipe = MessagePipe::new(channel)?;
+        Ok(TimesyncChannel::new(
+            pipe,
+            PolledTimer::new(&self.driver),
+            restore_state,
+        ))
+    }
+}
+
+impl TimesyncChannel {
+    fn new(
+        pipe: MessagePipe<GpadlRingMem>,
+        timer: PolledTimer,
+        restore_state: Option<ChannelState>,
+    ) -> Self {
+        Self {
+            pipe,
+            state: restore_state.unwrap_or(ChannelState::SendVersion),
+            timer,
+            sync_pending: false,
+            next_sample: None,
+            syncs_sent: 0,
+            samples_sent: 0,
+            round_trip_time: Duration::ZERO,
+            last_sync: None,
+            running: RunningTime::default(),
+        }
+    }
+
+    /// Returns how far the guest's clock is estimated to be behind the host's
+    /// since the guest's time was last set, in milliseconds.
+    fn drift_ms(&self) -> Option<i64> {
+        let sync = self.last_sync?;
+        let host_elapsed = SystemTime::now().duration_since(sync.host_time).ok()?;
+        let running_elapsed = self.running.get().saturating_sub(sync.running_time);
+        Some(host_elapsed.as_millis() as i64 - running_elapsed.as_millis() as i64)
+    }
+
+    async fn process(&mut self, mode: TimesyncMode) -> Result<(), Error> {
+        loop {
+            self.process_state_machine(mode).await?;
+        }
+    }
+
+    async fn process_state_machine(&mut self, mode: TimesyncMode) -> Result<(), Error> {
+        match self.state {
+            ChannelState::SendVersion => {
+                common::send_version_request(&mut self.pipe, FRAMEWORK_VERSIONS, TIMESYNC_VERSIONS)
+                    .await?;
+                self.state = ChannelState::WaitVersion;
+            }
+            ChannelState::WaitVersion => {
+                let (framework_version, message_version) =
+                    common::read_version_response(&mut self.pipe).await?;
+                self.state = ChannelState::Ready {
+                    framework_version,
+                    message_version,
+                    state: ReadyState::Ready,
+                };
+                self.sync_pending = true;
+            }
+            ChannelState::Ready {
+                ref mut state,
+                framework_version,
+                message_version,
+            } => match *state {
+                ReadyState::Ready => {
+                    let sync = if self.sync_pending {
+                        true
+                    } else if let TimesyncMode::Continuous { interval } = mode {
+                        let next_sample = *self
+                            .next_sample
+                            .get_or_insert_with(|| Instant::now() + interval);
+                        self.timer.sleep_until(next_sample).await;
+                        self.next_sample = Some(next_sample + interval);
+                        false
+                    } else {
+                        std::future::pending().await
+                    };
+
+                    let host_time = SystemTime::now();
+                    let message = hyperv_ic_protocol::timesync::TimesyncMessage {
+                        parent_time: windows_time(host_time),
+                        child_time: 0,
+                        round_trip_time: (self.round_trip_time.as_nanos() / 100) as u64,
+                        flags: TimesyncFlags::new().with_sync(sync).with_sample(!sync),
+                    };
+                    common::send_request(
+                        &mut self.pipe,
+                        framework_version,
+                        message_version,
+                        hyperv_ic_protocol::MessageType::TIME_SYNC,
+                        &[message.as_bytes()],
+                    )
+                    .await?;
+
+                    if sync {
+                        self.sync_pending = false;
+                        self.syncs_sent += 1;
+                    } else {
+                        self.samples_sent += 1;
+                    }
+                    *state = ReadyState::WaitResponse {
+                        sent: Instant::now(),
+                        sync: sync.then(|| SyncPoint {
+                            host_time,
+                            running_time: self.running.get(),
+                        }),
+                    };
+                }
+                ReadyState::WaitResponse { sent, sync } => {
+                    let (status, _) = common::read_response(&mut self.pipe).await?;
+                    if status != 0 {
+                        tracing::debug!(status, "guest failed timesync message");
+                    }
+                    self.round_trip_time = Instant::now() - sent;
+                    if let Some(sync) = sync {
+                        self.last_sync = Some(sync);
+                    }
+                    *state = ReadyState::Ready;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// Converts `time` to the Windows time format, in 100ns units since
+/// 1601-01-01.
+fn windows_time(time: SystemTime) -> u64 {
+    let since_unix = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO);
+    (since_unix.as_nanos() / 100) as u64 + WINDOWS_EPOCH_DELTA
+}
+
+#[async_trait]
+impl SimpleVmbusDevice for TimesyncIc {
+    type SavedState = save_restore::state::SavedState;
+    type Runner = TimesyncChannel;
+
+    fn offer(&self) -> OfferParams {
+        OfferParams {
+            interface_name: "timesync_ic".to_owned(),
+            instance_id: hyperv_ic_protocol::timesync::INSTANCE_ID,
+            interface_id: hyperv_ic_protocol::timesync::INTERFACE_ID,
+            channel_type: ChannelType::Pipe { message_mode: true },
+            ..Default::default()
+        }
+    }
+
+    fn inspect(&mut self, req: inspect::Request<'_>, runner: Option<&mut Self::Runner>) {
+        let mut resp = req.respond();
+        resp.merge(self);
+        if let Some(runner) = runner {
+            resp.field("drift_ms", runner.drift_ms()).merge(runner);
+        }
+    }
+
+    fn open(
+        &mut self,
+        channel: RawAsyncChannel<GpadlRingMem>,
+    ) -> Result<Self::Runner, ChannelOpenError> {
+        self.open_channel(channel, None)
+    }
+
+    async fn run(
+        &mut self,
+        stop: &mut StopTask<'_>,
+        runner: &mut Self::Runner,
+    ) -> Result<(), Cancelled> {
+        // The guest's clock did not advance while the VM was stopped, so set
+        // it again.
+        if let TimesyncMode::Continuous { .. } = self.mode {
+            if matches!(runner.state, ChannelState::Ready { .. }) {
+                runner.sync_pending = true;
+            }
+        }
+        runner.running.start();
+        let mode = self.mode;
+        let r = stop
+            .until_stopped(async {
+                match runner.process(mode).await {
+                    Ok(()) => {}
+                    Err(err) => {
+                        tracing::error!(error = &err as &dyn std::error::Error, "timesync ic error")
+                    }
+                }
+            })
+            .await;
+        runner.running.stop();
+        r
+    }
+
+    fn supports_save_restore(
+        &mut self,
+    ) -> Option<
+        &mut dyn SaveRestoreSimpleVmbusDevice<SavedState = Self::SavedState, Runner = Self::Runner>,
+    > {
+        Some(self)
+    }
+}
+
+mod save_restore {
+    use super::*;
+
+    pub mod state {
+        use mesh::payload::Protobuf;
+        use vmcore::save_restore::SavedStateRoot;
+
+        #[derive(Copy, Clone, Eq, PartialEq, Protobuf)]
+        #[mesh(package = "timesync_ic")]
+        pub struct Version {
+            #[mesh(1)]
+            pub major: u16,
+            #[mesh(2)]
+            pub minor: u16,
+        }
+
+        impl From<hyperv_ic_protocol::Version> for Version {
+            fn from(version: hyperv_ic_protocol::Version) -> Self {
+                Self {
+                    major: version.major,
+                    minor: version.minor,
+                }
+            }
+        }
+
+        impl From<Version> for hyperv_ic_protocol::Version {
+            fn from(version: Version) -> Self {
+                Self {
+                    major: version.major,
+                    minor: version.minor,
+                }
+            }
+        }
+
+        #[derive(Protobuf, SavedStateRoot)]
+        #[mesh(package = "timesync_ic")]
+        pub struct SavedState {
+            #[mesh(1)]
+            pub version: Option<(Version, Version)>,
+            #[mesh(2)]
+            pub waiting_on_version: bool,
+            #[mesh(3)]
+            pub waiting_on_response: bool,
+        }
+    }
+
+    impl SaveRestoreSimpleVmbusDevice for TimesyncIc {
+        fn save_open(&mut self, runner: &Self::Runner) -> state::SavedState {
+            let (version, waiting_on_response) = if let ChannelState::Ready {
+                framework_version,
+                message_version,
+                state,
+            } = &runner.state
+            {
+                (
+                    Some(((*framework_version).into(), (*message_version).into())),
+                    matches!(state, ReadyState::WaitResponse { .. }),
+                )
+            } else {
+                (None, false)
+            };
+            state::SavedState {
+                version,
+                waiting_on_version: matches!(runner.state, ChannelState::WaitVersion),
+                waiting_on_response,
+            }
+        }
+
+        fn restore_open(
+            &mut self,
+            saved_state: Self::SavedState,
+            channel: RawAsyncChannel<GpadlRingMem>,
+        ) -> Result<Self::Runner, ChannelOpenError> {
+            let state = if let Some((framework, message)) = saved_state.version {
+                ChannelState::Ready {
+                    framework_version: framework.into(),
+                    message_version: message.into(),
+                    state: if saved_state.waiting_on_response {
+                        ReadyState::WaitResponse {
+                            sent: Instant::now(),
+                            sync: None,
+                        }
+                    } else {
+                        ReadyState::Ready
+                    },
+                }
+            } else if saved_state.waiting_on_version {
+                ChannelState::WaitVersion
+            } else {
+                ChannelState::SendVersion
+            };
+            self.open_channel(channel, Some(state))
+        }
+    }
+}
@@ -0,0 +1,418 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The VSS (backup) IC.
+
+use crate::common;
+use crate::common::Error;
+use async_trait::async_trait;
+use futures::stream::once;
+use futures::FutureExt;
+use futures::StreamExt;
+use futures_concurrency::stream::Merge;
+use hyperv_ic_protocol::vss::VssOperation;
+use hyperv_ic_protocol::vss::FRAMEWORK_VERSIONS;
+use hyperv_ic_protocol::vss::VSS_VERSIONS;
+use hyperv_ic_resources::vss::VssError;
+use hyperv_ic_resources::vss::VssRpc;
+use inspect::Inspect;
+use inspect::InspectMut;
+use mesh::rpc::Rpc;
+use std::pin::pin;
+use task_control::Cancelled;
+use task_control::StopTask;
+use vmbus_async::pipe::MessagePipe;
+use vmbus_channel::bus::ChannelType;
+use vmbus_channel::bus::OfferParams;
+use vmbus_channel::channel::ChannelOpenError;
+use vmbus_channel::gpadl_ring::GpadlRingMem;
+use vmbus_channel::simple::SaveRestoreSimpleVmbusDevice;
+use vmbus_channel::simple::SimpleVmbusDevice;
+use vmbus_channel::RawAsyncChannel;
+use zerocopy::AsBytes;
+use zerocopy::FromZeroes;
+
+/// A VSS IC device.
+#[derive(InspectMut)]
+pub struct VssIc {
+    #[inspect(skip)]
+    recv: mesh::Receiver<VssRpc>,
+    #[inspect(skip)]
+    wait_ready: Vec<Rpc<(), ()>>,
+}
+
+#[doc(hidden)]
+#[derive(InspectMut)]
+pub struct VssChannel {
+    #[inspect(mut)]
+    pipe: MessagePipe<GpadlRingMem>,
+    state: ChannelState,
+    frozen: bool,
+    #[inspect(with = "Option::is_some")]
+    pending: Option<mesh::OneshotSender<Result<(), VssError>>>,
+}
+
+#[derive(Inspect)]
+#[inspect(external_tag)]
+enum ChannelState {
+    SendVersion,
+    WaitVersion,
+    Ready {
+        #[inspect(display)]
+        framework_version: hyperv_ic_protocol::Version,
+        #[inspect(display)]
+        message_version: hyperv_ic_protocol::Version,
+        state: ReadyState,
+    },
+}
+
+#[derive(Inspect)]
+#[inspect(external_tag)]
+enum ReadyState {
+    Ready,
+    SendRequest(#[inspect(skip)] Operation),
+    WaitResponse(#[inspect(skip)] Operation),
+}
+
+#[derive(Copy, Clone)]
+enum Operation {
+    Freeze,
+    Thaw,
+}
+
+impl VssIc {
+    /// Returns a new VSS IC, using `recv` to receive freeze and thaw
+    /// requests.
+    pub fn new(recv: mesh::Receiver<VssRpc>) -> Self {
+        Self {
+            recv,
+            wait_ready: Vec::new(),
+        }
+    }
+
+    fn open_channel(
+        &mut self,
+        channel: RawAsyncChannel<GpadlRingMem>,
+        restore_state: Option<(ChannelState, bool)>,
+    ) -> Result<VssChannel, ChannelOpenError> {
+        let pipe = MessagePipe::new(channel)?;
+        Ok(VssChannel::new(pipe, restore_state))
+    }
+}
+
+impl VssChannel {
+    fn new(pipe: MessagePipe<GpadlRingMem>, restore_state: Option<(ChannelState, bool)>) -> Self {
+        let (state, frozen) = restore_state.unwrap_or((ChannelState::SendVersion, false));
+        Self {
+            pipe,
+            state,
+            frozen,
+            pending: None,
+        }
+    }
+
+    async fn process(&mut self, ic: &mut VssIc) -> Result<(), Error> {
+        enum Event {
+            StateMachine(Result<(), Error>),
+            Request(VssRpc),
+        }
+
+        loop {
+            let event = pin!((
+                once(
+                    self.process_state_machine(&mut ic.wait_ready)
+                        .map(Event::StateMachine)
+                ),
+                (&mut ic.recv).map(Event::Request),
+            )
+                .merge())
+            .next()
+            .await
+            .unwrap();
+            match event {
+                Event::StateMachine(r) => {
+                    r?;
+                }
+                Event::Request(req) => match req {
+                    VssRpc::WaitReady(rpc) => match self.state {
+                        ChannelState::SendVersion | ChannelState::WaitVersion => {
+                            ic.wait_ready.push(rpc)
+                        }
+                        ChannelState::Ready { .. } => rpc.complete(()),
+                    },
+                    VssRpc::Freeze(rpc) => self.start(rpc, Operation::Freeze),
+                    VssRpc::Thaw(rpc) => self.start(rpc, Operation::Thaw),
+                },
+            }
+        }
+    }
+
+    fn start(&mut self, rpc: Rpc<(), Result<(), VssError>>, op: Operation) {
+        let ChannelState::Ready { ref mut state, .. } = self.state else {
+            rpc.complete(Err(VssError::NotReady));
+            return;
+        };
+        if !matches!(state, ReadyState::Ready) {
+            rpc.complete(Err(VssError::InProgress));
+            return;
+        }
+        match (op, self.frozen) {
+            (Operation::Freeze, true) => rpc.complete(Err(VssError::AlreadyFrozen)),
+            (Operation::Thaw, false) => rpc.complete(Err(VssError::NotFrozen)),
+            _ => {
+                self.pending = Some(rpc.1);
+                *state = ReadyState::SendRequest(op);
+            }
+        }
+    }
+
+    async fn process_state_machine(
+        &mut self,
+        wait_ready: &mut Vec<Rpc<(), ()>>,
+    ) -> Result<(), Error> {
+        match self.state {
+            ChannelState::SendVersion => {
+                common::send_version_request(&mut self.pipe, FRAMEWORK_VERSIONS, VSS_VERSIONS)
+                    .await?;
+                self.state = ChannelState::WaitVersion;
+            }
+            ChannelState::WaitVersion => {
+                let (framework_version, message_version) =
+                    common::read_version_response(&mut self.pipe).await?;
+                self.state = ChannelState::Ready {
+                    framework_version,
+                    message_version,
+                    state: ReadyState::Ready,
+                };
+                for rpc in wait_ready.drain(..) {
+                    rpc.complete(());
+                }
+            }
+            ChannelState::Ready {
+                ref mut state,
+                framework_version,
+                message_version,
+            } => match *state {
+                ReadyState::Ready => std::future::pending().await,
+                ReadyState::SendRequest(op) => {
+                    let message = hyperv_ic_protocol::vss::VssMessage {
+                        operation: match op {
+                            Operation::Freeze => VssOperation::FREEZE,
+                            Operation::Thaw => VssOperation::THAW,
+                        },
+                        ..FromZeroes::new_zeroed()
+                    };
+                    common::send_request(
+                        &mut self.pipe,
+                        framework_version,
+                        message_version,
+                        hyperv_ic_protocol::MessageType::VSS,
+                        &[message.as_bytes()],
+                    )
+                    .await?;
+                    *state = ReadyState::WaitResponse(op);
+                }
+                ReadyState::WaitResponse(op) => {
+                    let (status, _) = common::read_response(&mut self.pipe).await?;
+                    let result = if status == 0 {
+                        self.frozen = matches!(op, Operation::Freeze);
+                        Ok(())
+                    } else {
+                        Err(VssError::Failed(status))
+                    };
+                    if let Some(send) = self.pending.take() {
+                        send.send(result);
+                    }
+                    *state = ReadyState::Ready;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SimpleVmbusDevice for VssIc {
+    type SavedState = save_restore::state::SavedState;
+    type Runner = VssChannel;
+
+    fn offer(&self) -> OfferParams {
+        OfferParams {
+            interface_name: "vss_ic".to_owned(),
+            instance_id: hyperv_ic_protocol::vss::INSTANCE_ID,
+            interface_id: hyperv_ic_protocol::vss::INTERFACE_ID,
+            channel_type: ChannelType::Pipe { message_mode: true },
+            ..Default::default()
+        }
+    }
+
+    fn inspect(&mut self, req: inspect::Request<'_>, runner: Option<&mut Self::Runner>) {
+        req.respond().merge(self).merge(runner);
+    }
+
+    fn open(
+        &mut self,
+        channel: RawAsyncChannel<GpadlRingMem>,
+    ) -> Result<Self::Runner, ChannelOpenError> {
+        self.open_channel(channel, None)
+    }
+
+    async fn run(
+        &mut self,
+        stop: &mut StopTask<'_>,
+        runner: &mut Self::Runner,
+    ) -> Result<(), Cancelled> {
+        stop.until_stopped(async {
+            match runner.process(self).await {
+                Ok(()) => {}
+                Err(err) => {
+                    tracing::error!(error = &err as &dyn std::error::Error, "vss ic error")
+                }
+            }
+        })
+        .await
+    }
+
+    fn supports_save_restore(
+        &mut self,
+    ) -> Option<
+        &mut dyn SaveRestoreSimpleVmbusDevice<SavedState = Self::SavedState, Runner = Self::Runner>,
+    > {
+        Some(self)
+    }
+}
+
+mod save_restore {
+    use super::*;
+
+    pub mod state {
+        use mesh::payload::Protobuf;
+        use vmcore::save_restore::SavedStateRoot;
+
+        #[derive(Copy, Clone, Eq, PartialEq, Protobuf)]
+        #[mesh(package = "vss_ic")]
+        pub struct Version {
+            #[mesh(1)]
+            pub major: u16,
+            #[mesh(2)]
+            pub minor: u16,
+        }
+
+        impl From<hyperv_ic_protocol::Version> for Version {
+            fn from(version: hyperv_ic_protocol::Version) -> Self {
+                Self {
+                    major: version.major,
+                    minor: version.minor,
+                }
+            }
+        }
+
+        impl From<Version> for hyperv_ic_protocol::Version {
+            fn from(version: Version) -> Self {
+                Self {
+                    major: version.major,
+                    minor: version.minor,
+                }
+            }
+        }
+
+        #[derive(Copy, Clone, Eq, PartialEq, Protobuf)]
+        #[mesh(package = "vss_ic")]
+        pub enum Operation {
+            #[mesh(1)]
+            Freeze,
+            #[mesh(2)]
+            Thaw,
+        }
+
+        #[derive(Protobuf, SavedStateRoot)]
+        #[mesh(package = "vss_ic")]
+        pub struct SavedState {
+            #[mesh(1)]
+            pub version: Option<(Version, Version)>,
+            #[mesh(2)]
+            pub waiting_on_version: bool,
+            #[mesh(3)]
+            pub frozen: bool,
+            #[mesh(4)]
+            pub request: Option<Operation>,
+            #[mesh(5)]
+            pub waiting_on_response: bool,
+        }
+    }
+
+    impl From<Operation> for state::Operation {
+        fn from(op: Operation) -> Self {
+            match op {
+                Operation::Freeze => Self::Freeze,
+                Operation::Thaw => Self::Thaw,
+            }
+        }
+    }
+
+    impl From<state::Operation> for Operation {
+        fn from(op: state::Operation) -> Self {
+            match op {
+                state::Operation::Freeze => Self::Freeze,
+                state::Operation::Thaw => Self::Thaw,
+            }
+        }
+    }
+
+    impl SaveRestoreSimpleVmbusDevice for VssIc {
+        fn save_open(&mut self, runner: &Self::Runner) -> state::SavedState {
+            let (version, request, waiting_on_response) = if let ChannelState::Ready {
+                framework_version,
+                message_version,
+                state,
+            } = &runner.state
+            {
+                let (request, waiting) = match *state {
+                    ReadyState::Ready => (None, false),
+                    ReadyState::SendRequest(op) => (Some(op.into()), false),
+                    ReadyState::WaitResponse(op) => (Some(op.into()), true),
+                };
+                (
+                    Some(((*framework_version).into(), (*message_version).into())),
+                    request,
+                    waiting,
+                )
+            } else {
+                (None, None, false)
+            };
+            state::SavedState {
+                version,
+                waiting_on_version: matches!(runner.state, ChannelState::WaitVersion),
+                frozen: runner.frozen,
+                request,
+                waiting_on_response,
+            }
+        }
+
+        fn restore_open(
+            &mut self,
+            saved_state: Self::SavedState,
+            channel: RawAsyncChannel<GpadlRingMem>,
+        ) -> Result<Self::Runner, ChannelOpenError> {
+            let state = if let Some((framework, message)) = saved_state.version {
+                let state = match saved_state.request {
+                    None => ReadyState::Ready,
+                    Some(op) if saved_state.waiting_on_response => {
+                        ReadyState::WaitResponse(op.into())
+                    }
+                    Some(op) => ReadyState::SendRequest(op.into()),
+                };
+                ChannelState::Ready {
+                    framework_version: framework.into(),
+                    message_version: message.into(),
+                    state,
+                }
+            } else if saved_state.waiting_on_version {
+                ChannelState::WaitVersion
+            } else {
+                ChannelState::SendVersion
+            };
+            self.open_channel(channel, Some((state, saved_state.frozen)))
+        }
+    }
+}
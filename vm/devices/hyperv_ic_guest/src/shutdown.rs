@@ -36,6 +36,7 @@
 use zerocopy::FromZeroes;
 use zerocopy_helpers::FromBytesExt;
 
+const E_NOTIMPL: u32 = 0x80004001;
 const E_FAIL: u32 = 0x80004005;
 
 /// A shutdown IC client device.
@@ -285,7 +286,12 @@ async fn handle_shutdown_notification(
         };
 
         // Notify the internal listener and wait for a response.
-        let result = ic.send_shutdown_notification.call(|x| x, params).await;
+        let status = match ic.send_shutdown_notification.call(|x| x, params).await {
+            Ok(ShutdownResult::Ok) => 0,
+            Ok(ShutdownResult::Failed(status)) => status,
+            Ok(ShutdownResult::NotSupported) => E_NOTIMPL,
+            Ok(ShutdownResult::NotReady | ShutdownResult::AlreadyInProgress) | Err(_) => E_FAIL,
+        };
 
         // Respond to the request.
         let response = hyperv_ic_protocol::Header {
@@ -293,7 +299,7 @@ async fn handle_shutdown_notification(
             message_version: *message_version,
             message_type: hyperv_ic_protocol::MessageType::SHUTDOWN,
             message_size: 0,
-            status: if result.is_ok() { 0 } else { E_FAIL },
+            status,
             transaction_id: header.transaction_id,
             flags: hyperv_ic_protocol::HeaderFlags::new()
                 .with_transaction(header.flags.transaction())
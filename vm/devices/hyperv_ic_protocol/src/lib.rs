@@ -174,6 +174,10 @@ pub mod shutdown {
         Version::new(3, 2),
     ];
 
+    /// The first message version that supports hibernate requests. Guests only
+    /// offer this version if they are able to hibernate.
+    pub const HIBERNATE_VERSION: Version = Version::new(3, 2);
+
     /// The message for shutdown initiated from the host.
     #[repr(C)]
     #[derive(AsBytes, FromBytes, FromZeroes)]
@@ -207,3 +211,363 @@ pub struct ShutdownFlags {
     /// Reason code for '[ShutdownMessage]', from Windows SDK.
     pub const SHTDN_REASON_FLAG_PLANNED: u32 = 0x80000000;
 }
+
+/// Protocol for the KVP (key-value pair) IC.
+pub mod kvp {
+    use crate::Version;
+    use guid::Guid;
+    use open_enum::open_enum;
+    use zerocopy::AsBytes;
+    use zerocopy::FromBytes;
+    use zerocopy::FromZeroes;
+
+    /// The unique vmbus interface ID of the KVP IC.
+    pub const INTERFACE_ID: Guid = Guid::from_static_str("a9a0f4e7-5a45-4d96-b827-8a841e8c03e6");
+    /// The unique vmbus instance ID of the KVP IC.
+    pub const INSTANCE_ID: Guid = Guid::from_static_str("242ff919-07db-4180-9c2e-b86cb68c8c55");
+
+    /// Supported framework versions.
+    pub const FRAMEWORK_VERSIONS: &[Version] = &[Version::new(1, 0), Version::new(3, 0)];
+
+    /// Supported message versions.
+    pub const KVP_VERSIONS: &[Version] =
+        &[Version::new(1, 0), Version::new(3, 0), Version::new(4, 0)];
+
+    /// The maximum size of a key, in bytes, including the null terminator.
+    pub const MAX_KEY_SIZE: usize = 512;
+    /// The maximum size of a value, in bytes, including the null terminator.
+    pub const MAX_VALUE_SIZE: usize = 2048;
+
+    open_enum! {
+        /// The KVP operation.
+        #[derive(AsBytes, FromBytes, FromZeroes)]
+        pub enum KvpOperation: u8 {
+            /// Get a value by key.
+            GET = 0,
+            /// Set a value.
+            SET = 1,
+            /// Delete a value.
+            DELETE = 2,
+            /// Get a value by index.
+            ENUMERATE = 3,
+            /// Get a NIC's IP configuration.
+            GET_IP_INFO = 4,
+            /// Set a NIC's IP configuration.
+            SET_IP_INFO = 5,
+        }
+    }
+
+    open_enum! {
+        /// The pool containing the values.
+        #[derive(AsBytes, FromBytes, FromZeroes)]
+        pub enum KvpPool: u8 {
+            /// Values written by the host.
+            EXTERNAL = 0,
+            /// Values written by the guest.
+            GUEST = 1,
+            /// Values automatically populated by the guest, such as the OS
+            /// version and IP addresses.
+            AUTO = 2,
+            /// Host-provided values, such as the host name.
+            AUTO_EXTERNAL = 3,
+            /// Reserved.
+            AUTO_INTERNAL = 4,
+        }
+    }
+
+    open_enum! {
+        /// The type of a value.
+        #[derive(AsBytes, FromBytes, FromZeroes)]
+        pub enum ValueType: u32 {
+            /// A null-terminated UTF-16 string.
+            STRING = 1,
+            /// A null-terminated UTF-16 string with unexpanded environment
+            /// variable references.
+            EXPAND_STRING = 2,
+            /// A 32-bit integer.
+            U32 = 4,
+            /// A 64-bit integer.
+            U64 = 11,
+        }
+    }
+
+    /// Status returned by the guest when a key is not found or when an
+    /// enumeration is complete.
+    pub const STATUS_NO_MORE_ITEMS: u32 = 0x80070103;
+
+    /// The KVP message header, following the IC message header.
+    #[repr(C)]
+    #[derive(AsBytes, FromBytes, FromZeroes, Debug)]
+    pub struct KvpHeader {
+        /// The operation.
+        pub operation: KvpOperation,
+        /// The target pool.
+        pub pool: KvpPool,
+        /// Padding.
+        pub pad: u16,
+    }
+
+    /// A key and value.
+    #[repr(C)]
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub struct Value {
+        /// The value type.
+        pub value_type: ValueType,
+        /// The size of the key in bytes, including the null terminator.
+        pub key_size: u32,
+        /// The size of the value in bytes. For strings, this includes the
+        /// null terminator.
+        pub value_size: u32,
+        /// The UTF-16 key.
+        pub key: [u16; MAX_KEY_SIZE / 2],
+        /// The value, in a format determined by `value_type`.
+        pub value: [u8; MAX_VALUE_SIZE],
+    }
+
+    /// The message for [`KvpOperation::GET`] and [`KvpOperation::SET`].
+    #[repr(C)]
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub struct GetSetMessage {
+        /// The key and value. For get requests, the value is filled in by the
+        /// guest.
+        pub data: Value,
+    }
+
+    /// The message for [`KvpOperation::DELETE`].
+    #[repr(C)]
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub struct DeleteMessage {
+        /// The size of the key in bytes, including the null terminator.
+        pub key_size: u32,
+        /// The UTF-16 key.
+        pub key: [u16; MAX_KEY_SIZE / 2],
+    }
+
+    /// The message for [`KvpOperation::ENUMERATE`].
+    #[repr(C)]
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub struct EnumerateMessage {
+        /// The index of the value to get.
+        pub index: u32,
+        /// The key and value, filled in by the guest.
+        pub data: Value,
+    }
+}
+
+/// Protocol for the VSS (backup) IC.
+pub mod vss {
+    use crate::Version;
+    use guid::Guid;
+    use open_enum::open_enum;
+    use zerocopy::AsBytes;
+    use zerocopy::FromBytes;
+    use zerocopy::FromZeroes;
+
+    /// The unique vmbus interface ID of the VSS IC.
+    pub const INTERFACE_ID: Guid = Guid::from_static_str("35fa2e29-ea23-4236-96ae-3a6ebacba440");
+    /// The unique vmbus instance ID of the VSS IC.
+    pub const INSTANCE_ID: Guid = Guid::from_static_str("2dd1ce17-079e-403c-b352-a1921ee207ee");
+
+    /// Supported framework versions.
+    pub const FRAMEWORK_VERSIONS: &[Version] = &[Version::new(3, 0)];
+
+    /// Supported message versions.
+    pub const VSS_VERSIONS: &[Version] =
+        &[Version::new(3, 0), Version::new(4, 0), Version::new(5, 0)];
+
+    open_enum! {
+        /// The VSS operation.
+        #[derive(AsBytes, FromBytes, FromZeroes)]
+        pub enum VssOperation: u8 {
+            /// Create a shadow copy.
+            CREATE = 0,
+            /// Delete a shadow copy.
+            DELETE = 1,
+            /// Query the guest's backup capabilities.
+            HOT_BACKUP = 2,
+            /// Query dynamic disk information.
+            GET_DM_INFO = 3,
+            /// The backup is complete.
+            BU_COMPLETE = 4,
+            /// Quiesce the guest file systems.
+            FREEZE = 5,
+            /// Resume the guest file systems.
+            THAW = 6,
+            /// Recover after a backup.
+            AUTO_RECOVER = 7,
+        }
+    }
+
+    /// The VSS message, following the IC message header.
+    #[repr(C)]
+    #[derive(AsBytes, FromBytes, FromZeroes, Debug)]
+    pub struct VssMessage {
+        /// The operation.
+        pub operation: VssOperation,
+        /// Reserved.
+        pub reserved: [u8; 7],
+        /// Operation-specific flags, returned by the guest for
+        /// [`VssOperation::HOT_BACKUP`] and [`VssOperation::GET_DM_INFO`].
+        pub flags: u32,
+    }
+
+    /// Returned in [`VssMessage::flags`] by [`VssOperation::HOT_BACKUP`] when
+    /// the guest does not support auto recovery.
+    pub const HOT_BACKUP_NO_AUTO_RECOVERY: u32 = 5;
+}
+
+/// Protocol for the timesync IC.
+pub mod timesync {
+    use crate::Version;
+    use bitfield_struct::bitfield;
+    use guid::Guid;
+    use zerocopy::AsBytes;
+    use zerocopy::FromBytes;
+    use zerocopy::FromZeroes;
+
+    /// The unique vmbus interface ID of the timesync IC.
+    pub const INTERFACE_ID: Guid = Guid::from_static_str("9527e630-d0ae-497b-adce-e80ab0175caf");
+    /// The unique vmbus instance ID of the timesync IC.
+    pub const INSTANCE_ID: Guid = Guid::from_static_str("2497f4de-e9fa-4204-80e4-4b75c46419c0");
+
+    /// Supported framework versions.
+    pub const FRAMEWORK_VERSIONS: &[Version] = &[Version::new(1, 0), Version::new(3, 0)];
+
+    /// Supported message versions.
+    ///
+    /// Version 4 adds a reference time to each sample, which is not yet
+    /// supported.
+    pub const TIMESYNC_VERSIONS: &[Version] = &[Version::new(1, 0), Version::new(3, 0)];
+
+    /// The timesync message from the host.
+    #[repr(C, packed)]
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub struct TimesyncMessage {
+        /// The host's UTC time, in 100ns units since 1601-01-01.
+        pub parent_time: u64,
+        /// Unused.
+        pub child_time: u64,
+        /// The round trip time of the previous sample, in 100ns units.
+        pub round_trip_time: u64,
+        /// Flags.
+        pub flags: TimesyncFlags,
+    }
+
+    /// Timesync message flags.
+    #[bitfield(u8)]
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub struct TimesyncFlags {
+        /// The guest should set its clock to the host time immediately.
+        pub sync: bool,
+        /// The guest should use the host time as a sample to gradually
+        /// correct its clock.
+        pub sample: bool,
+        /// Reserved -- must be zero.
+        #[bits(6)]
+        _reserved: u8,
+    }
+
+    /// The difference between the Windows epoch (1601-01-01) and the Unix
+    /// epoch (1970-01-01), in 100ns units.
+    pub const WINDOWS_EPOCH_DELTA: u64 = 116_444_736_000_000_000;
+}
+
+/// Protocol for the guest file copy IC, part of the guest services
+/// interface.
+pub mod fcopy {
+    use crate::Version;
+    use guid::Guid;
+    use open_enum::open_enum;
+    use zerocopy::AsBytes;
+    use zerocopy::FromBytes;
+    use zerocopy::FromZeroes;
+
+    /// The unique vmbus interface ID of the file copy IC.
+    pub const INTERFACE_ID: Guid = Guid::from_static_str("34d14be3-dee4-41c8-9ae7-6b174977c192");
+    /// The unique vmbus instance ID of the file copy IC.
+    pub const INSTANCE_ID: Guid = Guid::from_static_str("8e5cf6dd-1ad6-4e6f-9b63-3a8d2a0bbf2e");
+
+    /// Supported framework versions.
+    pub const FRAMEWORK_VERSIONS: &[Version] = &[Version::new(3, 0)];
+
+    /// Supported message versions.
+    pub const FCOPY_VERSIONS: &[Version] = &[Version::new(1, 1)];
+
+    /// The maximum length of a file or directory name, in UTF-16 code units,
+    /// including the null terminator.
+    pub const MAX_PATH: usize = 260;
+
+    /// The maximum amount of file data in a single
+    /// [`FcopyOperation::WRITE_TO_FILE`] message.
+    pub const DATA_FRAGMENT_SIZE: usize = 6 * 1024;
+
+    open_enum! {
+        /// The file copy operation.
+        #[derive(AsBytes, FromBytes, FromZeroes)]
+        pub enum FcopyOperation: u32 {
+            /// Create the file.
+            START_FILE_COPY = 0,
+            /// Write data to the file.
+            WRITE_TO_FILE = 1,
+            /// Close the file.
+            COMPLETE_FCOPY = 2,
+            /// Close and delete the file.
+            CANCEL_FCOPY = 3,
+        }
+    }
+
+    /// The file copy message header, following the IC message header.
+    #[repr(C, packed)]
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub struct FcopyHeader {
+        /// The operation.
+        pub operation: FcopyOperation,
+        /// Reserved.
+        pub service_id0: Guid,
+        /// Reserved.
+        pub service_id1: Guid,
+    }
+
+    /// Overwrite the file if it already exists.
+    pub const FLAG_OVERWRITE: u32 = 0x1;
+    /// Create the destination directory if it does not exist.
+    pub const FLAG_CREATE_PATH: u32 = 0x2;
+
+    /// The message for [`FcopyOperation::START_FILE_COPY`].
+    #[repr(C, packed)]
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub struct StartCopyMessage {
+        /// The header.
+        pub header: FcopyHeader,
+        /// The UTF-16 file name.
+        pub file_name: [u16; MAX_PATH],
+        /// The UTF-16 path of the destination directory.
+        pub path_name: [u16; MAX_PATH],
+        /// Flags (`FLAG_*`).
+        pub copy_flags: u32,
+        /// The size of the file in bytes.
+        pub file_size: u64,
+    }
+
+    /// The message for [`FcopyOperation::WRITE_TO_FILE`].
+    #[repr(C, packed)]
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub struct WriteMessage {
+        /// The header.
+        pub header: FcopyHeader,
+        /// Padding.
+        pub pad: u32,
+        /// The offset in the file to write to.
+        pub offset: u64,
+        /// The number of valid bytes in `data`.
+        pub size: u32,
+        /// The file data.
+        pub data: [u8; DATA_FRAGMENT_SIZE],
+    }
+
+    /// Returned by the guest when the file exists and
+    /// [`FLAG_OVERWRITE`] was not specified.
+    pub const STATUS_ALREADY_EXISTS: u32 = 0x80070050;
+    /// Returned by the guest when it is out of disk space.
+    pub const STATUS_DISK_FULL: u32 = 0x80070070;
+}
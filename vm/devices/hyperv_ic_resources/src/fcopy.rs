@@ -0,0 +1,72 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resource definitions for the guest file copy IC.
+
+use mesh::rpc::Rpc;
+use mesh::MeshPayload;
+use std::fs::File;
+use thiserror::Error;
+use vm_resource::kind::VmbusDeviceHandleKind;
+use vm_resource::ResourceId;
+
+/// A handle to a file copy IC.
+#[derive(MeshPayload)]
+pub struct FcopyIcHandle {
+    /// The channel by which to receive file copy requests.
+    pub recv: mesh::Receiver<FcopyRpc>,
+}
+
+impl ResourceId<VmbusDeviceHandleKind> for FcopyIcHandle {
+    const ID: &'static str = "fcopy_ic";
+}
+
+/// An RPC request to the file copy IC.
+#[derive(MeshPayload)]
+pub enum FcopyRpc {
+    /// Wait for the file copy IC to be ready.
+    WaitReady(Rpc<(), ()>),
+    /// Copy a file into the guest.
+    CopyFile(Rpc<CopyFileParams, Result<(), FcopyError>>),
+}
+
+/// Parameters for [`FcopyRpc::CopyFile`].
+#[derive(Debug, MeshPayload)]
+pub struct CopyFileParams {
+    /// The host file to copy.
+    pub file: File,
+    /// The guest directory to copy the file to.
+    pub path: String,
+    /// The name of the file in the guest.
+    pub file_name: String,
+    /// Overwrite the guest file if it exists.
+    pub overwrite: bool,
+    /// Create the guest directory if it does not exist.
+    pub create_path: bool,
+}
+
+/// An error from a file copy request.
+#[derive(Debug, Error, MeshPayload)]
+pub enum FcopyError {
+    /// The IC is not ready to send requests.
+    #[error("the file copy ic is not ready")]
+    NotReady,
+    /// Another copy is in progress.
+    #[error("a file copy is already in progress")]
+    InProgress,
+    /// The path or file name is too long.
+    #[error("path too long")]
+    PathTooLong,
+    /// The host file could not be read.
+    #[error("failed to read host file: {0}")]
+    Read(String),
+    /// The guest file already exists.
+    #[error("the guest file already exists")]
+    AlreadyExists,
+    /// The guest is out of disk space.
+    #[error("the guest disk is full")]
+    DiskFull,
+    /// The guest failed the request with the given status code.
+    #[error("guest failed request with status {0:#x}")]
+    Failed(u32),
+}
@@ -0,0 +1,127 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resource definitions for the KVP IC.
+
+use mesh::rpc::Rpc;
+use mesh::MeshPayload;
+use thiserror::Error;
+use vm_resource::kind::VmbusDeviceHandleKind;
+use vm_resource::ResourceId;
+
+/// A handle to a KVP IC.
+#[derive(MeshPayload)]
+pub struct KvpIcHandle {
+    /// The channel by which to receive KVP requests.
+    pub recv: mesh::Receiver<KvpRpc>,
+}
+
+impl ResourceId<VmbusDeviceHandleKind> for KvpIcHandle {
+    const ID: &'static str = "kvp_ic";
+}
+
+/// An RPC request to the KVP IC.
+#[derive(MeshPayload)]
+pub enum KvpRpc {
+    /// Wait for the KVP IC to be ready.
+    WaitReady(Rpc<(), ()>),
+    /// Set a value in a pool.
+    Set(Rpc<SetParams, Result<(), KvpError>>),
+    /// Delete a value from a pool.
+    Delete(Rpc<DeleteParams, Result<(), KvpError>>),
+    /// Get a value from a pool by key, returning `None` if the key is not
+    /// present.
+    Get(Rpc<GetParams, Result<Option<Value>, KvpError>>),
+    /// Get a key and value from a pool by index, returning `None` if the
+    /// index is past the end of the pool.
+    Enumerate(Rpc<EnumerateParams, Result<Option<KeyValue>, KvpError>>),
+}
+
+/// A KVP pool.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, MeshPayload)]
+pub enum KvpPool {
+    /// Values written by the host, readable by the guest.
+    External,
+    /// Values written by the guest.
+    Guest,
+    /// Values automatically populated by the guest, such as the OS version
+    /// and IP addresses.
+    Auto,
+    /// Values automatically populated by the host, such as the host name.
+    AutoExternal,
+}
+
+/// A KVP value.
+#[derive(Debug, Clone, PartialEq, Eq, MeshPayload)]
+pub enum Value {
+    /// A string.
+    String(String),
+    /// A 32-bit integer.
+    U32(u32),
+    /// A 64-bit integer.
+    U64(u64),
+}
+
+/// A key and its value.
+#[derive(Debug, Clone, PartialEq, Eq, MeshPayload)]
+pub struct KeyValue {
+    /// The key.
+    pub key: String,
+    /// The value.
+    pub value: Value,
+}
+
+/// Parameters for [`KvpRpc::Set`].
+#[derive(Debug, MeshPayload)]
+pub struct SetParams {
+    /// The target pool.
+    pub pool: KvpPool,
+    /// The key.
+    pub key: String,
+    /// The value.
+    pub value: Value,
+}
+
+/// Parameters for [`KvpRpc::Delete`].
+#[derive(Debug, MeshPayload)]
+pub struct DeleteParams {
+    /// The target pool.
+    pub pool: KvpPool,
+    /// The key.
+    pub key: String,
+}
+
+/// Parameters for [`KvpRpc::Get`].
+#[derive(Debug, MeshPayload)]
+pub struct GetParams {
+    /// The target pool.
+    pub pool: KvpPool,
+    /// The key.
+    pub key: String,
+}
+
+/// Parameters for [`KvpRpc::Enumerate`].
+#[derive(Debug, MeshPayload)]
+pub struct EnumerateParams {
+    /// The target pool.
+    pub pool: KvpPool,
+    /// The index of the value.
+    pub index: u32,
+}
+
+/// An error from a KVP request.
+#[derive(Debug, Error, MeshPayload)]
+pub enum KvpError {
+    /// The IC is not ready to send requests.
+    #[error("the kvp ic is not ready")]
+    NotReady,
+    /// The key or value is too long.
+    #[error("key or value too long")]
+    TooLong,
+    /// The guest sent an invalid response.
+    #[error("invalid response from guest")]
+    InvalidResponse,
+    /// The guest failed the request with the given status code.
+    #[error("guest failed request with status {0:#x}")]
+    Failed(u32),
+}
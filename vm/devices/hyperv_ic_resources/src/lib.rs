@@ -6,4 +6,8 @@
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 
+pub mod fcopy;
+pub mod kvp;
 pub mod shutdown;
+pub mod timesync;
+pub mod vss;
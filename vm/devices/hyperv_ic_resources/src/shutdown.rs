@@ -26,6 +26,16 @@ pub enum ShutdownRpc {
     WaitReady(Rpc<(), ()>),
     /// Send a shutdown request to the guest.
     Shutdown(Rpc<ShutdownParams, ShutdownResult>),
+    /// Query the power state transitions supported by the guest. Returns
+    /// `None` if the IC is not ready.
+    Capabilities(Rpc<(), Option<ShutdownCapabilities>>),
+}
+
+/// The power state transitions supported by the guest.
+#[derive(Debug, MeshPayload, Clone, Copy, PartialEq, Eq)]
+pub struct ShutdownCapabilities {
+    /// The guest can hibernate.
+    pub hibernate: bool,
 }
 
 /// Guest shutdown parameters.
@@ -57,6 +67,8 @@ pub enum ShutdownResult {
     NotReady,
     /// A shutdown is already in progress.
     AlreadyInProgress,
+    /// The guest does not support the requested shutdown type.
+    NotSupported,
     /// The shutdown failed with the given error code.
     Failed(u32),
 }
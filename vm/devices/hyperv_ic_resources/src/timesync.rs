@@ -0,0 +1,34 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resource definitions for the timesync IC.
+
+use mesh::MeshPayload;
+use std::time::Duration;
+use vm_resource::kind::VmbusDeviceHandleKind;
+use vm_resource::ResourceId;
+
+/// A handle to a timesync IC.
+#[derive(MeshPayload)]
+pub struct TimesyncIcHandle {
+    /// How to synchronize the guest's time.
+    pub mode: TimesyncMode,
+}
+
+impl ResourceId<VmbusDeviceHandleKind> for TimesyncIcHandle {
+    const ID: &'static str = "timesync_ic";
+}
+
+/// The time synchronization behavior.
+#[derive(Debug, Copy, Clone, MeshPayload)]
+pub enum TimesyncMode {
+    /// Set the guest's time once, when the guest first connects.
+    Once,
+    /// Set the guest's time when it connects and whenever the VM resumes,
+    /// and send time samples at the given interval so that the guest can
+    /// correct for drift.
+    Continuous {
+        /// The interval between samples.
+        interval: Duration,
+    },
+}
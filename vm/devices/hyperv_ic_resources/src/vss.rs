@@ -0,0 +1,53 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resource definitions for the VSS (backup) IC.
+
+use mesh::rpc::Rpc;
+use mesh::MeshPayload;
+use thiserror::Error;
+use vm_resource::kind::VmbusDeviceHandleKind;
+use vm_resource::ResourceId;
+
+/// A handle to a VSS IC.
+#[derive(MeshPayload)]
+pub struct VssIcHandle {
+    /// The channel by which to receive VSS requests.
+    pub recv: mesh::Receiver<VssRpc>,
+}
+
+impl ResourceId<VmbusDeviceHandleKind> for VssIcHandle {
+    const ID: &'static str = "vss_ic";
+}
+
+/// An RPC request to the VSS IC.
+#[derive(MeshPayload)]
+pub enum VssRpc {
+    /// Wait for the VSS IC to be ready.
+    WaitReady(Rpc<(), ()>),
+    /// Quiesce the guest's file systems so that a consistent snapshot of its
+    /// disks can be taken.
+    Freeze(Rpc<(), Result<(), VssError>>),
+    /// Resume the guest's file systems after a [`VssRpc::Freeze`].
+    Thaw(Rpc<(), Result<(), VssError>>),
+}
+
+/// An error from a VSS request.
+#[derive(Debug, Error, MeshPayload)]
+pub enum VssError {
+    /// The IC is not ready to send requests.
+    #[error("the vss ic is not ready")]
+    NotReady,
+    /// Another request is in progress.
+    #[error("a vss request is already in progress")]
+    InProgress,
+    /// The guest file systems are already frozen.
+    #[error("the guest is already frozen")]
+    AlreadyFrozen,
+    /// The guest file systems are not frozen.
+    #[error("the guest is not frozen")]
+    NotFrozen,
+    /// The guest failed the request with the given status code.
+    #[error("guest failed request with status {0:#x}")]
+    Failed(u32),
+}
@@ -490,7 +490,7 @@ enum Event {
 
         loop {
             let event = poll_fn(|cx| {
-                if let Poll::Ready(wqe) = self.queues.poll_sq(self.sq_id, cx) {
+                if let Poll::Ready((_, wqe)) = self.queues.poll_sq(self.sq_id, cx) {
                     return Poll::Ready(Event::Sqe(wqe));
                 }
                 if self.rx_buf_count < max_rx_buf {
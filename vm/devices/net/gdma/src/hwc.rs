@@ -3,6 +3,8 @@
 
 use crate::bnic::BasicNic;
 use crate::dma::DmaRegion;
+use crate::ib::MemoryRegion;
+use crate::ib::RdmaDevice;
 use crate::queues::QueueAllocError;
 use crate::queues::Queues;
 use anyhow::anyhow;
@@ -11,13 +13,20 @@
 use gdma_defs::GdmaChangeMsixVectorIndexForEq;
 use gdma_defs::GdmaCreateDmaRegionReq;
 use gdma_defs::GdmaCreateDmaRegionResp;
+use gdma_defs::GdmaCreateMrReq;
+use gdma_defs::GdmaCreateMrResp;
+use gdma_defs::GdmaCreatePdReq;
+use gdma_defs::GdmaCreatePdResp;
 use gdma_defs::GdmaCreateQueueReq;
 use gdma_defs::GdmaCreateQueueResp;
+use gdma_defs::GdmaDestroyMrReq;
+use gdma_defs::GdmaDestroyPdReq;
 use gdma_defs::GdmaDevId;
 use gdma_defs::GdmaDevType;
 use gdma_defs::GdmaDisableQueueReq;
 use gdma_defs::GdmaGenerateTestEventReq;
 use gdma_defs::GdmaListDevicesResp;
+use gdma_defs::GdmaMrType;
 use gdma_defs::GdmaQueryMaxResourcesResp;
 use gdma_defs::GdmaQueueType;
 use gdma_defs::GdmaRegisterDeviceResp;
@@ -48,6 +57,7 @@
 use guestmem::Limit;
 use guestmem::MemoryRead;
 use guestmem::MemoryWrite;
+use parking_lot::Mutex;
 use slab::Slab;
 use std::future::poll_fn;
 use std::sync::Arc;
@@ -63,6 +73,11 @@
     instance: 1,
 };
 
+const RDMA_DEV_ID: GdmaDevId = GdmaDevId {
+    ty: GdmaDevType::GDMA_DEVICE_MANA_IB,
+    instance: 1,
+};
+
 pub struct HwControl {
     state: HwState,
     _eq_id: u32,
@@ -71,6 +86,7 @@ pub struct HwControl {
     rq_id: u32,
 
     bnic_enabled: bool,
+    rdma_enabled: bool,
 }
 
 impl InspectTaskMut<HwControl> for Devices {
@@ -84,19 +100,26 @@ fn inspect_mut(&mut self, req: inspect::Request<'_>, hwc: Option<&mut HwControl>
                     .field("sq_id", hwc.sq_id)
                     .field("rq_id", hwc.rq_id);
             })
-            .field("bnic/enabled", hwc.bnic_enabled);
+            .field("bnic/enabled", hwc.bnic_enabled)
+            .field("rdma/enabled", hwc.rdma_enabled)
+            .field("pds", hwc.state.pds.len())
+            .field("mrs", hwc.state.mrs.lock().len());
         }
-        resp.field_mut("bnic", &mut self.bnic);
+        resp.field_mut("bnic", &mut self.bnic)
+            .field_mut("rdma", &mut self.rdma);
     }
 }
 
 pub struct Devices {
     pub bnic: BasicNic,
+    pub rdma: Option<RdmaDevice>,
 }
 
 pub struct HwState {
     pub queues: Arc<Queues>,
     pub dma_regions: Slab<DmaRegion>,
+    pub pds: Slab<()>,
+    pub mrs: Arc<Mutex<Slab<MemoryRegion>>>,
 }
 
 impl HwState {
@@ -116,10 +139,14 @@ pub fn get_dma_region(
     }
 
     pub fn remove_dma_region(&mut self, gdma_region: u64) -> anyhow::Result<()> {
+        self.take_dma_region(gdma_region)?;
+        Ok(())
+    }
+
+    pub fn take_dma_region(&mut self, gdma_region: u64) -> anyhow::Result<DmaRegion> {
         self.dma_regions
             .try_remove(gdma_region.wrapping_sub(1) as usize)
-            .context("invalid gdma region")?;
-        Ok(())
+            .context("invalid gdma region")
     }
 }
 
@@ -182,6 +209,8 @@ pub fn new(
             state: HwState {
                 queues,
                 dma_regions: Slab::new(),
+                pds: Slab::new(),
+                mrs: Default::default(),
             },
             _eq_id: eq_id,
             cq_id,
@@ -189,6 +218,7 @@ pub fn new(
             rq_id,
 
             bnic_enabled: false,
+            rdma_enabled: false,
         })
     }
 
@@ -196,7 +226,7 @@ async fn process(&mut self, devices: &mut Devices) -> anyhow::Result<()> {
         tracing::info!("starting hwc");
 
         loop {
-            let sqe = poll_fn(|cx| self.state.queues.poll_sq(self.sq_id, cx)).await;
+            let (_, sqe) = poll_fn(|cx| self.state.queues.poll_sq(self.sq_id, cx)).await;
             let (rqe_offset, rqe) = poll_fn(|cx| self.state.queues.poll_rq(self.rq_id, cx)).await;
 
             let queues = self.state.queues.clone();
@@ -234,7 +264,7 @@ async fn process(&mut self, devices: &mut Devices) -> anyhow::Result<()> {
                 .context("response message too small")?;
 
             let r = match hdr.req.msg_type >> 16 {
-                0 => self.handle_req(&hdr, read, write),
+                0 => self.handle_req(devices, &hdr, read, write),
                 _ => {
                     // Device specific.
                     if hdr.dev_id == BNIC_DEV_ID && self.bnic_enabled {
@@ -242,6 +272,12 @@ async fn process(&mut self, devices: &mut Devices) -> anyhow::Result<()> {
                             .bnic
                             .handle_req(&mut self.state, &hdr, read, write)
                             .await
+                    } else if let Some(rdma) = devices
+                        .rdma
+                        .as_mut()
+                        .filter(|_| hdr.dev_id == RDMA_DEV_ID && self.rdma_enabled)
+                    {
+                        rdma.handle_req(&mut self.state, &hdr, read, write).await
                     } else {
                         Err(anyhow!("unknown device {:?}", hdr.dev_id))
                     }
@@ -284,6 +320,7 @@ async fn process(&mut self, devices: &mut Devices) -> anyhow::Result<()> {
 
     fn handle_req(
         &mut self,
+        devices: &Devices,
         hdr: &GdmaReqHdr,
         mut read: Limit<WqeAccess<'_>>,
         mut write: Limit<WqeAccess<'_>>,
@@ -343,6 +380,10 @@ fn handle_req(
                 };
                 resp.devs[0] = HWC_DEV_ID;
                 resp.devs[1] = BNIC_DEV_ID;
+                if devices.rdma.is_some() {
+                    resp.devs[2] = RDMA_DEV_ID;
+                    resp.num_of_devs += 1;
+                }
 
                 write
                     .write(resp.as_bytes())
@@ -350,15 +391,19 @@ fn handle_req(
                 size_of_val(&resp)
             }
             GdmaRequestType::GDMA_REGISTER_DEVICE => {
-                if hdr.dev_id != BNIC_DEV_ID {
+                let enabled = if hdr.dev_id == BNIC_DEV_ID {
+                    &mut self.bnic_enabled
+                } else if hdr.dev_id == RDMA_DEV_ID && devices.rdma.is_some() {
+                    &mut self.rdma_enabled
+                } else {
                     anyhow::bail!("invalid device id: {:?}", hdr.dev_id);
-                }
+                };
 
-                if self.bnic_enabled {
-                    anyhow::bail!("bnic already enabled");
+                if *enabled {
+                    anyhow::bail!("device {:?} already enabled", hdr.dev_id);
                 }
 
-                self.bnic_enabled = true;
+                *enabled = true;
 
                 let resp = GdmaRegisterDeviceResp {
                     pdid: 0,
@@ -393,19 +438,22 @@ fn handle_req(
             }
             GdmaRequestType::GDMA_CREATE_QUEUE => {
                 let req: GdmaCreateQueueReq = read.read_plain().context("reading queue request")?;
-                if req.queue_type != GdmaQueueType::GDMA_EQ {
-                    anyhow::bail!("unsupported queue type: {:?}", req.queue_type);
-                }
-
                 let region = self.state.get_dma_region(req.gdma_region, req.queue_size)?;
 
-                let eq_id = self
-                    .state
-                    .queues
-                    .alloc_eq(region.clone(), req.eq_pci_msix_index)
-                    .context("failed to allocate queue")?;
+                let queue_index = match req.queue_type {
+                    GdmaQueueType::GDMA_EQ => self
+                        .state
+                        .queues
+                        .alloc_eq(region.clone(), req.eq_pci_msix_index),
+                    GdmaQueueType::GDMA_CQ => self
+                        .state
+                        .queues
+                        .alloc_cq(region.clone(), req.cq_parent_eq_id),
+                    ty => anyhow::bail!("unsupported queue type: {:?}", ty),
+                }
+                .context("failed to allocate queue")?;
 
-                let resp = GdmaCreateQueueResp { queue_index: eq_id };
+                let resp = GdmaCreateQueueResp { queue_index };
                 write
                     .write(resp.as_bytes())
                     .context("writing queue response")?;
@@ -418,16 +466,93 @@ fn handle_req(
                 let req: GdmaDisableQueueReq = read
                     .read_plain()
                     .context("failed to read disable queue request")?;
-                if req.queue_type != GdmaQueueType::GDMA_EQ {
-                    anyhow::bail!("unsupported queue type: {:?}", req.queue_type);
-                }
                 if req.alloc_res_id_on_creation != 1 {
                     tracing::warn!(
                         value = req.alloc_res_id_on_creation,
                         "mystery value not set to 1"
                     );
                 }
-                self.state.queues.free_eq(req.queue_index)?;
+                match req.queue_type {
+                    GdmaQueueType::GDMA_EQ => self.state.queues.free_eq(req.queue_index)?,
+                    GdmaQueueType::GDMA_CQ => self.state.queues.free_cq(req.queue_index)?,
+                    ty => anyhow::bail!("unsupported queue type: {:?}", ty),
+                }
+                0
+            }
+            GdmaRequestType::GDMA_CREATE_PD => {
+                let _req: GdmaCreatePdReq =
+                    read.read_plain().context("reading create pd request")?;
+                let index = self.state.pds.insert(());
+                let resp = GdmaCreatePdResp {
+                    pd_handle: index as u64 + 1,
+                    pd_id: index as u32 + 1,
+                    reserved: 0,
+                };
+                write
+                    .write(resp.as_bytes())
+                    .context("writing create pd response")?;
+                size_of_val(&resp)
+            }
+            GdmaRequestType::GDMA_DESTROY_PD => {
+                let req: GdmaDestroyPdReq =
+                    read.read_plain().context("reading destroy pd request")?;
+                if self
+                    .state
+                    .mrs
+                    .lock()
+                    .iter()
+                    .any(|(_, mr)| mr.pd_handle() == req.pd_handle)
+                {
+                    anyhow::bail!("pd still has memory regions");
+                }
+                self.state
+                    .pds
+                    .try_remove(req.pd_handle.wrapping_sub(1) as usize)
+                    .context("invalid pd handle")?;
+                0
+            }
+            GdmaRequestType::GDMA_CREATE_MR => {
+                let req: GdmaCreateMrReq =
+                    read.read_plain().context("reading create mr request")?;
+                if !self
+                    .state
+                    .pds
+                    .contains(req.pd_handle.wrapping_sub(1) as usize)
+                {
+                    anyhow::bail!("invalid pd handle");
+                }
+                if req.mr_type != GdmaMrType::GDMA_MR_TYPE_GVA {
+                    anyhow::bail!("unsupported mr type: {:?}", req.mr_type);
+                }
+
+                // Take ownership of the DMA region.
+                let region = self.state.take_dma_region(req.dma_region_handle)?;
+                let key = self.state.mrs.lock().insert(MemoryRegion::new(
+                    req.pd_handle,
+                    region,
+                    req.virtual_address,
+                    req.access_flags,
+                )) as u32
+                    + 1;
+
+                let resp = GdmaCreateMrResp {
+                    mr_handle: key.into(),
+                    lkey: key,
+                    rkey: key,
+                };
+                write
+                    .write(resp.as_bytes())
+                    .context("writing create mr response")?;
+                size_of_val(&resp)
+            }
+            GdmaRequestType::GDMA_DESTROY_MR => {
+                let req: GdmaDestroyMrReq =
+                    read.read_plain().context("reading destroy mr request")?;
+                self.state
+                    .mrs
+                    .lock()
+                    .try_remove(req.mr_handle.wrapping_sub(1) as usize)
+                    .context("invalid mr handle")?;
                 0
             }
             GdmaRequestType::GDMA_CHANGE_MSIX_FOR_EQ => {
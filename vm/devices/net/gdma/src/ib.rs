@@ -0,0 +1,647 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Emulation of the MANA RDMA (IB) device function.
+//!
+//! This supports reliable connected queue pairs with send and receive
+//! operations. There is no physical fabric: queue pairs on the same device are
+//! connected to each other through an internal loopback.
+
+use crate::dma::DmaRegion;
+use crate::hwc::HwState;
+use crate::queues::Queues;
+use anyhow::Context;
+use gdma_defs::access::WqeAccess;
+use gdma_defs::ib::ManaIbCommandCode;
+use gdma_defs::ib::ManaIbCreateAdapterReq;
+use gdma_defs::ib::ManaIbCreateAdapterResp;
+use gdma_defs::ib::ManaIbCreateRcQpReq;
+use gdma_defs::ib::ManaIbCreateRcQpResp;
+use gdma_defs::ib::ManaIbDestroyAdapterReq;
+use gdma_defs::ib::ManaIbDestroyRcQpReq;
+use gdma_defs::ib::ManaIbGetAdapterCapResp;
+use gdma_defs::ib::ManaIbQpState;
+use gdma_defs::ib::ManaIbSetQpStateReq;
+use gdma_defs::ib::ManaRdmaCqe;
+use gdma_defs::ib::ManaRdmaCqeType;
+use gdma_defs::ib::ManaRdmaSendOob;
+use gdma_defs::ib::MANA_RDMA_CQE_FLUSH_ERROR;
+use gdma_defs::ib::MANA_RDMA_CQE_LOCAL_LENGTH_ERROR;
+use gdma_defs::ib::MANA_RDMA_CQE_LOCAL_PROTECTION_ERROR;
+use gdma_defs::ib::MANA_RDMA_CQE_LOCAL_QP_OPERATION_ERROR;
+use gdma_defs::ib::MANA_RDMA_CQE_REMOTE_INVALID_REQUEST;
+use gdma_defs::ib::MANA_RDMA_CQE_SUCCESS;
+use gdma_defs::ib::MANA_RDMA_WQE_SEND;
+use gdma_defs::ib::MANA_RDMA_WQE_SEND_IMM;
+use gdma_defs::GdmaReqHdr;
+use gdma_defs::Sge;
+use gdma_defs::Wqe;
+use gdma_defs::GDMA_ACCESS_FLAG_LOCAL_WRITE;
+use guestmem::ranges::PagedRange;
+use guestmem::Limit;
+use guestmem::MemoryRead;
+use guestmem::MemoryWrite;
+use inspect::Inspect;
+use inspect::InspectMut;
+use parking_lot::Mutex;
+use slab::Slab;
+use std::future::poll_fn;
+use std::sync::Arc;
+use std::task::Context as TaskContext;
+use std::task::Poll;
+use task_control::AsyncRun;
+use task_control::InspectTaskMut;
+use task_control::StopTask;
+use task_control::TaskControl;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+
+/// The only adapter handle, since each device supports a single adapter.
+const ADAPTER_HANDLE: u64 = 1;
+
+/// Offset the queue pair numbers seen by the guest.
+const QP_NUM_OFFSET: u32 = 0x100;
+
+const MAX_QPS: usize = 32;
+const MAX_SGES: u32 = 30;
+
+/// A registered memory region.
+pub struct MemoryRegion {
+    pd_handle: u64,
+    region: DmaRegion,
+    iova: u64,
+    access_flags: u32,
+}
+
+impl MemoryRegion {
+    pub fn new(pd_handle: u64, region: DmaRegion, iova: u64, access_flags: u32) -> Self {
+        Self {
+            pd_handle,
+            region,
+            iova,
+            access_flags,
+        }
+    }
+
+    pub fn pd_handle(&self) -> u64 {
+        self.pd_handle
+    }
+}
+
+pub struct RdmaDevice {
+    task: TaskControl<FabricState, FabricTask>,
+}
+
+impl InspectMut for RdmaDevice {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        req.respond()
+            .field("adapter", self.task.has_state())
+            .merge(&mut self.task);
+    }
+}
+
+impl RdmaDevice {
+    pub fn new() -> Self {
+        Self {
+            task: TaskControl::new(FabricState),
+        }
+    }
+
+    pub async fn handle_req(
+        &mut self,
+        state: &mut HwState,
+        hdr: &GdmaReqHdr,
+        mut read: Limit<WqeAccess<'_>>,
+        mut write: Limit<WqeAccess<'_>>,
+    ) -> anyhow::Result<usize> {
+        tracing::debug!(msg_type = ?ManaIbCommandCode(hdr.req.msg_type), "rdma request");
+
+        let response_len = match ManaIbCommandCode(hdr.req.msg_type) {
+            ManaIbCommandCode::MANA_IB_GET_ADAPTER_CAP => {
+                let resp = ManaIbGetAdapterCapResp {
+                    max_sq_id: state.queues.max_sqs(),
+                    max_rq_id: state.queues.max_rqs(),
+                    max_cq_id: state.queues.max_cqs(),
+                    max_qp_count: MAX_QPS as u32,
+                    max_cq_count: state.queues.max_cqs(),
+                    max_mr_count: 1024,
+                    max_pd_count: 1024,
+                    max_inbound_read_limit: 0,
+                    max_outbound_read_limit: 0,
+                    mw_count: 0,
+                    max_srq_count: 0,
+                    max_qp_wr: 256,
+                    max_send_sge_count: MAX_SGES,
+                    max_recv_sge_count: MAX_SGES,
+                };
+                write.write(resp.as_bytes())?;
+                size_of_val(&resp)
+            }
+            ManaIbCommandCode::MANA_IB_CREATE_ADAPTER => {
+                let req: ManaIbCreateAdapterReq = read
+                    .read_plain()
+                    .context("reading create adapter request")?;
+                if self.task.has_state() {
+                    anyhow::bail!("adapter already created");
+                }
+                self.task.insert(
+                    &state.queues.driver,
+                    "gdma-rdma",
+                    FabricTask {
+                        queues: state.queues.clone(),
+                        mrs: state.mrs.clone(),
+                        notify_eq_id: req.notify_eq_id,
+                        qps: Slab::new(),
+                    },
+                );
+                self.task.start();
+
+                let resp = ManaIbCreateAdapterResp {
+                    adapter: ADAPTER_HANDLE,
+                };
+                write.write(resp.as_bytes())?;
+                size_of_val(&resp)
+            }
+            ManaIbCommandCode::MANA_IB_DESTROY_ADAPTER => {
+                let req: ManaIbDestroyAdapterReq = read
+                    .read_plain()
+                    .context("reading destroy adapter request")?;
+                self.update(req.adapter, |fabric| {
+                    if !fabric.qps.is_empty() {
+                        anyhow::bail!("adapter still has queue pairs");
+                    }
+                    Ok(())
+                })
+                .await?;
+                self.task.stop().await;
+                self.task.remove();
+                0
+            }
+            ManaIbCommandCode::MANA_IB_CREATE_RC_QP => {
+                let req: ManaIbCreateRcQpReq =
+                    read.read_plain().context("reading create rc qp request")?;
+                let resp = self
+                    .update(req.adapter, |fabric| fabric.create_qp(state, &req))
+                    .await?;
+                write.write(resp.as_bytes())?;
+                size_of_val(&resp)
+            }
+            ManaIbCommandCode::MANA_IB_DESTROY_RC_QP => {
+                let req: ManaIbDestroyRcQpReq =
+                    read.read_plain().context("reading destroy rc qp request")?;
+                self.update(req.adapter, |fabric| {
+                    let qp = fabric
+                        .qp_index(req.qp_handle)
+                        .and_then(|index| fabric.qps.try_remove(index))
+                        .context("invalid qp handle")?;
+                    state.queues.free_wq(true, qp.sq_id).unwrap();
+                    state.queues.free_wq(false, qp.rq_id).unwrap();
+                    Ok(())
+                })
+                .await?;
+                0
+            }
+            ManaIbCommandCode::MANA_IB_SET_QP_STATE => {
+                let req: ManaIbSetQpStateReq =
+                    read.read_plain().context("reading set qp state request")?;
+                self.update(req.adapter, |fabric| {
+                    let qp = fabric
+                        .qp_index(req.qp_handle)
+                        .and_then(|index| fabric.qps.get_mut(index))
+                        .context("invalid qp handle")?;
+                    match req.state {
+                        ManaIbQpState::RESET | ManaIbQpState::INIT => {
+                            // Stop processing, but leave any posted WQEs in
+                            // place.
+                            qp.pending_send = None;
+                        }
+                        ManaIbQpState::RTR | ManaIbQpState::RTS => {
+                            if req.state == ManaIbQpState::RTR {
+                                qp.dest_qp_num = req.dest_qp_num;
+                            }
+                        }
+                        ManaIbQpState::ERR => {}
+                        new_state => anyhow::bail!("unsupported qp state {:?}", new_state),
+                    }
+                    tracing::debug!(
+                        qp_handle = req.qp_handle,
+                        old_state = ?qp.state,
+                        new_state = ?req.state,
+                        dest_qp_num = qp.dest_qp_num,
+                        "qp state change"
+                    );
+                    qp.state = req.state;
+                    Ok(())
+                })
+                .await?;
+                0
+            }
+            n => anyhow::bail!("unsupported request {:?}", n),
+        };
+        Ok(response_len)
+    }
+
+    /// Stops the data path and updates its state.
+    async fn update<R>(
+        &mut self,
+        adapter: u64,
+        f: impl FnOnce(&mut FabricTask) -> anyhow::Result<R>,
+    ) -> anyhow::Result<R> {
+        if adapter != ADAPTER_HANDLE || !self.task.has_state() {
+            anyhow::bail!("invalid adapter handle");
+        }
+        self.task.stop().await;
+        let r = f(self.task.state_mut().unwrap());
+        self.task.start();
+        r
+    }
+}
+
+struct Qp {
+    pd_handle: u64,
+    sq_id: u32,
+    rq_id: u32,
+    send_cq_id: u32,
+    recv_cq_id: u32,
+    state: ManaIbQpState,
+    dest_qp_num: u32,
+    /// A send WQE waiting for a receive WQE on the destination queue pair.
+    pending_send: Option<(u32, Wqe)>,
+}
+
+impl Inspect for Qp {
+    fn inspect(&self, req: inspect::Request<'_>) {
+        req.respond()
+            .field("pd_handle", self.pd_handle)
+            .field("sq_id", self.sq_id)
+            .field("rq_id", self.rq_id)
+            .field("send_cq_id", self.send_cq_id)
+            .field("recv_cq_id", self.recv_cq_id)
+            .field("state", self.state.0)
+            .field("dest_qp_num", self.dest_qp_num)
+            .field("send_pending", self.pending_send.is_some());
+    }
+}
+
+pub struct FabricTask {
+    queues: Arc<Queues>,
+    mrs: Arc<Mutex<Slab<MemoryRegion>>>,
+    notify_eq_id: u32,
+    qps: Slab<Qp>,
+}
+
+struct FabricState;
+
+impl InspectTaskMut<FabricTask> for FabricState {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>, task: Option<&mut FabricTask>) {
+        let mut resp = req.respond();
+        if let Some(task) = task {
+            resp.field("notify_eq_id", task.notify_eq_id).fields(
+                "qps",
+                task.qps
+                    .iter()
+                    .map(|(index, qp)| (index as u32 + QP_NUM_OFFSET, qp)),
+            );
+        }
+    }
+}
+
+enum Event {
+    /// A send WQE can be delivered to a receive WQE.
+    Deliver {
+        index: usize,
+        rqe_offset: u32,
+        rqe: Wqe,
+    },
+    /// A send WQE must be completed with an error.
+    FailSend { index: usize, error: u8 },
+    /// A receive WQE must be flushed.
+    FlushRecv { index: usize, rqe_offset: u32 },
+}
+
+impl FabricTask {
+    fn create_qp(
+        &mut self,
+        state: &mut HwState,
+        req: &ManaIbCreateRcQpReq,
+    ) -> anyhow::Result<ManaIbCreateRcQpResp> {
+        if self.qps.len() >= MAX_QPS {
+            anyhow::bail!("out of queue pairs");
+        }
+        if !state.pds.contains(req.pd_handle.wrapping_sub(1) as usize) {
+            anyhow::bail!("invalid pd handle");
+        }
+        if req.max_send_sge > MAX_SGES || req.max_recv_sge > MAX_SGES {
+            anyhow::bail!("too many sges");
+        }
+        if req.sq_dma_region == req.rq_dma_region {
+            anyhow::bail!("sq and rq use the same dma region");
+        }
+        for cq_id in [req.send_cq_id, req.recv_cq_id] {
+            if !state.queues.has_cq(cq_id) {
+                anyhow::bail!("invalid cq id {cq_id}");
+            }
+        }
+
+        // Validate both regions before allocating anything, so that a failed
+        // request leaves the regions with the driver.
+        let sq_region = state.get_dma_region(req.sq_dma_region, req.sq_size)?;
+        let rq_region = state.get_dma_region(req.rq_dma_region, req.rq_size)?;
+        let sq_id = state
+            .queues
+            .alloc_wq(true, sq_region.clone())
+            .context("failed to allocate sq")?;
+        let rq_id = match state.queues.alloc_wq(false, rq_region.clone()) {
+            Ok(rq_id) => rq_id,
+            Err(err) => {
+                state.queues.free_wq(true, sq_id).unwrap();
+                return Err(err).context("failed to allocate rq");
+            }
+        };
+
+        // Take ownership of the DMA regions. These were validated above, and
+        // they are distinct, so this cannot fail.
+        state.remove_dma_region(req.sq_dma_region).unwrap();
+        state.remove_dma_region(req.rq_dma_region).unwrap();
+
+        let index = self.qps.insert(Qp {
+            pd_handle: req.pd_handle,
+            sq_id,
+            rq_id,
+            send_cq_id: req.send_cq_id,
+            recv_cq_id: req.recv_cq_id,
+            state: ManaIbQpState::RESET,
+            dest_qp_num: 0,
+            pending_send: None,
+        });
+        let qp_num = index as u32 + QP_NUM_OFFSET;
+        Ok(ManaIbCreateRcQpResp {
+            qp_handle: qp_num.into(),
+            qp_num,
+            sq_id,
+            rq_id,
+            reserved: 0,
+        })
+    }
+
+    fn qp_index(&self, qp_handle: u64) -> Option<usize> {
+        let index = (qp_handle as usize).wrapping_sub(QP_NUM_OFFSET as usize);
+        self.qps.contains(index).then_some(index)
+    }
+
+    async fn process(&mut self) {
+        loop {
+            match poll_fn(|cx| self.poll_event(cx)).await {
+                Event::Deliver {
+                    index,
+                    rqe_offset,
+                    rqe,
+                } => self.deliver(index, rqe_offset, &rqe),
+                Event::FailSend { index, error } => self.fail_send(index, error),
+                Event::FlushRecv { index, rqe_offset } => {
+                    let qp = &self.qps[index];
+                    self.post_recv_completion(
+                        qp,
+                        &ManaRdmaCqe {
+                            cqe_type: ManaRdmaCqeType::RECV,
+                            vendor_error: MANA_RDMA_CQE_FLUSH_ERROR,
+                            reserved: 0,
+                            wqe_offset: rqe_offset,
+                            msg_len: 0,
+                            src_qp_num: 0,
+                            imm_data: 0,
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    fn poll_event(&mut self, cx: &mut TaskContext<'_>) -> Poll<Event> {
+        for index in 0..self.qps.capacity() {
+            let Some(qp) = self.qps.get_mut(index) else {
+                continue;
+            };
+            match qp.state {
+                ManaIbQpState::RTS => {}
+                ManaIbQpState::ERR => {
+                    if let Poll::Ready((rqe_offset, _)) = self.queues.poll_rq(qp.rq_id, cx) {
+                        return Poll::Ready(Event::FlushRecv { index, rqe_offset });
+                    }
+                }
+                _ => continue,
+            }
+
+            if qp.pending_send.is_none() {
+                match self.queues.poll_sq(qp.sq_id, cx) {
+                    Poll::Ready(sqe) => qp.pending_send = Some(sqe),
+                    Poll::Pending => continue,
+                }
+            }
+
+            if qp.state == ManaIbQpState::ERR {
+                return Poll::Ready(Event::FailSend {
+                    index,
+                    error: MANA_RDMA_CQE_FLUSH_ERROR,
+                });
+            }
+
+            // The destination must be connected back to this queue pair.
+            let qp_num = index as u32 + QP_NUM_OFFSET;
+            let dest_qp_num = qp.dest_qp_num;
+            let dest = self
+                .qp_index(dest_qp_num.into())
+                .map(|dest_index| &self.qps[dest_index])
+                .filter(|dest| {
+                    matches!(dest.state, ManaIbQpState::RTR | ManaIbQpState::RTS)
+                        && dest.dest_qp_num == qp_num
+                });
+
+            let Some(dest) = dest else {
+                return Poll::Ready(Event::FailSend {
+                    index,
+                    error: MANA_RDMA_CQE_REMOTE_INVALID_REQUEST,
+                });
+            };
+
+            // Wait for a receive buffer.
+            if let Poll::Ready((rqe_offset, rqe)) = self.queues.poll_rq(dest.rq_id, cx) {
+                return Poll::Ready(Event::Deliver {
+                    index,
+                    rqe_offset,
+                    rqe,
+                });
+            }
+        }
+        Poll::Pending
+    }
+
+    fn deliver(&mut self, index: usize, rqe_offset: u32, rqe: &Wqe) {
+        let qp = &mut self.qps[index];
+        let (sqe_offset, sqe) = qp.pending_send.take().unwrap();
+        let dest_qp_num = qp.dest_qp_num;
+        let qp_num = index as u32 + QP_NUM_OFFSET;
+        let dest_index = self.qp_index(dest_qp_num.into()).unwrap();
+        let qp = &self.qps[index];
+        let dest = &self.qps[dest_index];
+
+        let oob = ManaRdmaSendOob::read_from_prefix(sqe.oob()).unwrap();
+        let (cqe_type, imm_data) = match oob.flags.wqe_type() {
+            MANA_RDMA_WQE_SEND => (ManaRdmaCqeType::RECV, 0),
+            MANA_RDMA_WQE_SEND_IMM => (ManaRdmaCqeType::RECV_IMM, oob.immediate),
+            ty => {
+                tracing::warn!(ty, "unsupported rdma wqe type");
+                self.qps[index].pending_send = Some((sqe_offset, sqe));
+                self.fail_send(index, MANA_RDMA_CQE_LOCAL_QP_OPERATION_ERROR);
+                return;
+            }
+        };
+
+        let mut msg_len = 0;
+        let (send_error, recv_error) = match self.gather(qp.pd_handle, sqe.sgl()) {
+            Ok(data) => {
+                msg_len = data.len() as u32;
+                match self.scatter(dest.pd_handle, rqe.sgl(), &data) {
+                    Ok(()) => (MANA_RDMA_CQE_SUCCESS, MANA_RDMA_CQE_SUCCESS),
+                    Err(err) => (MANA_RDMA_CQE_REMOTE_INVALID_REQUEST, err),
+                }
+            }
+            Err(err) => (err, MANA_RDMA_CQE_FLUSH_ERROR),
+        };
+
+        tracing::trace!(
+            qp_num,
+            dest_qp_num,
+            msg_len,
+            send_error,
+            recv_error,
+            "rdma send"
+        );
+
+        self.post_recv_completion(
+            dest,
+            &ManaRdmaCqe {
+                cqe_type,
+                vendor_error: recv_error,
+                reserved: 0,
+                wqe_offset: rqe_offset,
+                msg_len,
+                src_qp_num: qp_num,
+                imm_data,
+            },
+        );
+
+        if oob.flags.signaled() || send_error != MANA_RDMA_CQE_SUCCESS {
+            self.post_send_completion(qp, sqe_offset, send_error);
+        }
+
+        // Errors on a reliable connection are fatal to both sides.
+        if send_error != MANA_RDMA_CQE_SUCCESS {
+            self.qps[index].state = ManaIbQpState::ERR;
+        }
+        if recv_error != MANA_RDMA_CQE_SUCCESS {
+            self.qps[dest_index].state = ManaIbQpState::ERR;
+        }
+    }
+
+    fn fail_send(&mut self, index: usize, error: u8) {
+        let qp = &mut self.qps[index];
+        let (sqe_offset, _) = qp.pending_send.take().unwrap();
+        qp.state = ManaIbQpState::ERR;
+        self.post_send_completion(&self.qps[index], sqe_offset, error);
+    }
+
+    fn post_send_completion(&self, qp: &Qp, sqe_offset: u32, error: u8) {
+        let cqe = ManaRdmaCqe {
+            cqe_type: ManaRdmaCqeType::SEND,
+            vendor_error: error,
+            reserved: 0,
+            wqe_offset: sqe_offset,
+            msg_len: 0,
+            src_qp_num: 0,
+            imm_data: 0,
+        };
+        self.queues
+            .post_cq(qp.send_cq_id, cqe.as_bytes(), qp.sq_id, true);
+    }
+
+    fn post_recv_completion(&self, qp: &Qp, cqe: &ManaRdmaCqe) {
+        self.queues
+            .post_cq(qp.recv_cq_id, cqe.as_bytes(), qp.rq_id, false);
+    }
+
+    /// Reads the data described by `sgl`.
+    fn gather(&self, pd_handle: u64, sgl: &[Sge]) -> Result<Vec<u8>, u8> {
+        let mrs = self.mrs.lock();
+        let mut data = Vec::new();
+        for sge in sgl {
+            let range = mr_range(&mrs, pd_handle, sge, 0)?;
+            let start = data.len();
+            data.resize(start + sge.size as usize, 0);
+            self.queues
+                .gm
+                .read_range(&range, &mut data[start..])
+                .map_err(|_| MANA_RDMA_CQE_LOCAL_PROTECTION_ERROR)?;
+        }
+        Ok(data)
+    }
+
+    /// Writes `data` to the buffers described by `sgl`.
+    fn scatter(&self, pd_handle: u64, sgl: &[Sge], mut data: &[u8]) -> Result<(), u8> {
+        let capacity: u64 = sgl.iter().map(|sge| sge.size as u64).sum();
+        if data.len() as u64 > capacity {
+            return Err(MANA_RDMA_CQE_LOCAL_LENGTH_ERROR);
+        }
+        let mrs = self.mrs.lock();
+        for sge in sgl {
+            if data.is_empty() {
+                break;
+            }
+            let (this, rest) = data.split_at(data.len().min(sge.size as usize));
+            let sge = Sge {
+                size: this.len() as u32,
+                ..*sge
+            };
+            let range = mr_range(&mrs, pd_handle, &sge, GDMA_ACCESS_FLAG_LOCAL_WRITE)?;
+            self.queues
+                .gm
+                .write_range(&range, this)
+                .map_err(|_| MANA_RDMA_CQE_LOCAL_PROTECTION_ERROR)?;
+            data = rest;
+        }
+        Ok(())
+    }
+}
+
+/// Looks up the guest memory referenced by `sge`, checking that it is within a
+/// memory region in the protection domain with the required access.
+fn mr_range<'a>(
+    mrs: &'a Slab<MemoryRegion>,
+    pd_handle: u64,
+    sge: &Sge,
+    access_flags: u32,
+) -> Result<PagedRange<'a>, u8> {
+    let mr = mrs
+        .get((sge.mem_key as usize).wrapping_sub(1))
+        .filter(|mr| mr.pd_handle == pd_handle && mr.access_flags & access_flags == access_flags)
+        .ok_or(MANA_RDMA_CQE_LOCAL_PROTECTION_ERROR)?;
+    let offset = sge
+        .address
+        .checked_sub(mr.iova)
+        .and_then(|offset| offset.try_into().ok())
+        .ok_or(MANA_RDMA_CQE_LOCAL_PROTECTION_ERROR)?;
+    mr.region
+        .range()
+        .try_subrange(offset, sge.size as usize)
+        .ok_or(MANA_RDMA_CQE_LOCAL_PROTECTION_ERROR)
+}
+
+impl AsyncRun<FabricTask> for FabricState {
+    async fn run(
+        &mut self,
+        stop: &mut StopTask<'_>,
+        task: &mut FabricTask,
+    ) -> Result<(), task_control::Cancelled> {
+        stop.until_stopped(task.process()).await
+    }
+}
@@ -4,6 +4,7 @@
 mod bnic;
 mod dma;
 mod hwc;
+mod ib;
 mod queues;
 pub mod resolver;
 
@@ -179,10 +180,17 @@ pub fn new(
             destroying_hwc: false,
             hwc: TaskControl::new(Devices {
                 bnic: bnic::BasicNic::new(vports),
+                rdma: None,
             }),
         }
     }
 
+    /// Exposes the RDMA device function, which supports reliable connected
+    /// queue pairs looped back within this device.
+    pub fn enable_rdma(&mut self) {
+        self.hwc.task_mut().rdma = Some(ib::RdmaDevice::new());
+    }
+
     fn read_regmap(&self, offset: usize, data: &mut [u8]) {
         data.copy_from_slice(&self.regmap.as_bytes()[offset..offset + data.len()]);
     }
@@ -399,6 +399,11 @@ pub fn free_eq(&self, eq_id: u32) -> Result<(), QueueNotFound> {
         Ok(())
     }
 
+    /// Returns whether `cq_id` refers to an allocated CQ.
+    pub fn has_cq(&self, cq_id: u32) -> bool {
+        self.cq(cq_id).is_some()
+    }
+
     fn sq(&self, sq_id: u32) -> Option<MappedMutexGuard<'_, Wq>> {
         MutexGuard::try_map(
             self.sqs
@@ -476,9 +481,9 @@ pub fn post_eq(&self, eq_id: u32, ty: u8, data: &[u8]) {
         }
     }
 
-    pub fn poll_sq(&self, sq_id: u32, cx: &mut Context<'_>) -> Poll<Wqe> {
+    pub fn poll_sq(&self, sq_id: u32, cx: &mut Context<'_>) -> Poll<(u32, Wqe)> {
         if let Some(mut sq) = self.sq(sq_id) {
-            sq.poll_wqe(&self.gm, cx).map(|x| x.1)
+            sq.poll_wqe(&self.gm, cx)
         } else {
             Poll::Pending
         }
@@ -45,12 +45,14 @@ async fn resolve(
         resource: GdmaDeviceHandle,
         input: ResolvePciDeviceHandleParams<'_>,
     ) -> Result<Self::Output, Self::Error> {
+        let driver_source = input.driver_source;
         let vports = try_join_all(resource.vports.into_iter().map(|vport| async move {
             let endpoint = resolver
                 .resolve(
                     vport.endpoint,
                     ResolveEndpointParams {
                         mac_address: vport.mac_address,
+                        driver_source,
                     },
                 )
                 .await
@@ -63,13 +65,16 @@ async fn resolve(
         }))
         .await?;
 
-        let device = GdmaDevice::new(
+        let mut device = GdmaDevice::new(
             input.driver_source,
             input.guest_memory.clone(),
             input.register_msi,
             vports,
             input.register_mmio,
         );
+        if resource.rdma {
+            device.enable_rdma();
+        }
         Ok(device.into())
     }
 }
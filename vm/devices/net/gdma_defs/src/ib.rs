@@ -0,0 +1,165 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! GDMA RDMA (MANA IB) definitions
+
+use bitfield_struct::bitfield;
+use open_enum::open_enum;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+open_enum! {
+    pub enum ManaIbCommandCode: u32 {
+        MANA_IB_GET_ADAPTER_CAP = 0x30001,
+        MANA_IB_CREATE_ADAPTER = 0x30002,
+        MANA_IB_DESTROY_ADAPTER = 0x30003,
+        MANA_IB_CREATE_RC_QP = 0x3000a,
+        MANA_IB_DESTROY_RC_QP = 0x3000b,
+        MANA_IB_SET_QP_STATE = 0x3000d,
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaIbGetAdapterCapResp {
+    pub max_sq_id: u32,
+    pub max_rq_id: u32,
+    pub max_cq_id: u32,
+    pub max_qp_count: u32,
+    pub max_cq_count: u32,
+    pub max_mr_count: u32,
+    pub max_pd_count: u32,
+    pub max_inbound_read_limit: u32,
+    pub max_outbound_read_limit: u32,
+    pub mw_count: u32,
+    pub max_srq_count: u32,
+    pub max_qp_wr: u32,
+    pub max_send_sge_count: u32,
+    pub max_recv_sge_count: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaIbCreateAdapterReq {
+    pub notify_eq_id: u32,
+    pub adapter_caps: u32,
+    pub feature_flags: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaIbCreateAdapterResp {
+    pub adapter: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaIbDestroyAdapterReq {
+    pub adapter: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaIbCreateRcQpReq {
+    pub adapter: u64,
+    pub pd_handle: u64,
+    pub sq_dma_region: u64,
+    pub rq_dma_region: u64,
+    pub send_cq_id: u32,
+    pub recv_cq_id: u32,
+    pub sq_size: u32,
+    pub rq_size: u32,
+    pub max_send_sge: u32,
+    pub max_recv_sge: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaIbCreateRcQpResp {
+    pub qp_handle: u64,
+    pub qp_num: u32,
+    pub sq_id: u32,
+    pub rq_id: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaIbDestroyRcQpReq {
+    pub adapter: u64,
+    pub qp_handle: u64,
+}
+
+open_enum! {
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub enum ManaIbQpState: u32 {
+        RESET = 0,
+        INIT = 1,
+        RTR = 2,
+        RTS = 3,
+        SQD = 4,
+        SQE = 5,
+        ERR = 6,
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaIbSetQpStateReq {
+    pub adapter: u64,
+    pub qp_handle: u64,
+    pub state: ManaIbQpState,
+    pub dest_qp_num: u32,
+}
+
+pub const MANA_RDMA_WQE_SEND: u8 = 0;
+pub const MANA_RDMA_WQE_SEND_IMM: u8 = 1;
+
+#[bitfield(u32)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct ManaRdmaSendOobFlags {
+    #[bits(5)]
+    pub wqe_type: u8,
+    pub fence: bool,
+    pub signaled: bool,
+    pub solicited: bool,
+    #[bits(24)]
+    pub psn: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaRdmaSendOob {
+    pub flags: ManaRdmaSendOobFlags,
+    pub immediate: u32,
+}
+
+open_enum! {
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub enum ManaRdmaCqeType: u8 {
+        SEND = 1,
+        RECV = 2,
+        RECV_IMM = 3,
+    }
+}
+
+pub const MANA_RDMA_CQE_SUCCESS: u8 = 0;
+pub const MANA_RDMA_CQE_LOCAL_LENGTH_ERROR: u8 = 1;
+pub const MANA_RDMA_CQE_LOCAL_PROTECTION_ERROR: u8 = 2;
+pub const MANA_RDMA_CQE_LOCAL_QP_OPERATION_ERROR: u8 = 3;
+pub const MANA_RDMA_CQE_REMOTE_INVALID_REQUEST: u8 = 4;
+pub const MANA_RDMA_CQE_FLUSH_ERROR: u8 = 5;
+
+/// The completion data for RDMA send and receive work queues.
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ManaRdmaCqe {
+    pub cqe_type: ManaRdmaCqeType,
+    pub vendor_error: u8,
+    pub reserved: u16,
+    pub wqe_offset: u32,
+    pub msg_len: u32,
+    pub src_qp_num: u32,
+    pub imm_data: u32,
+}
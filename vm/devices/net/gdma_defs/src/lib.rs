@@ -6,6 +6,7 @@
 
 pub mod access;
 pub mod bnic;
+pub mod ib;
 
 use bitfield_struct::bitfield;
 use inspect::Inspect;
@@ -307,6 +308,10 @@ pub enum GdmaRequestType : u32 {
         GDMA_CREATE_DMA_REGION = 25,
         GDMA_DMA_REGION_ADD_PAGES = 26,
         GDMA_DESTROY_DMA_REGION = 27,
+        GDMA_CREATE_PD = 29,
+        GDMA_DESTROY_PD = 30,
+        GDMA_CREATE_MR = 31,
+        GDMA_DESTROY_MR = 32,
         GDMA_CHANGE_MSIX_FOR_EQ = 81,
     }
 }
@@ -338,6 +343,7 @@ pub enum GdmaDevType: u16 {
         GDMA_DEVICE_NONE = 0,
         GDMA_DEVICE_HWC = 1,
         GDMA_DEVICE_MANA = 2,
+        GDMA_DEVICE_MANA_IB = 3,
     }
 }
 
@@ -590,3 +596,63 @@ pub struct GdmaChangeMsixVectorIndexForEq {
     pub reserved1: u32,
     pub reserved2: u32,
 }
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct GdmaCreatePdReq {
+    pub flags: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct GdmaCreatePdResp {
+    pub pd_handle: u64,
+    pub pd_id: u32,
+    pub reserved: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct GdmaDestroyPdReq {
+    pub pd_handle: u64,
+}
+
+open_enum! {
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub enum GdmaMrType: u32 {
+        GDMA_MR_TYPE_GPA = 1,
+        GDMA_MR_TYPE_GVA = 2,
+    }
+}
+
+pub const GDMA_ACCESS_FLAG_LOCAL_READ: u32 = 0x1;
+pub const GDMA_ACCESS_FLAG_LOCAL_WRITE: u32 = 0x2;
+pub const GDMA_ACCESS_FLAG_REMOTE_READ: u32 = 0x4;
+pub const GDMA_ACCESS_FLAG_REMOTE_WRITE: u32 = 0x8;
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct GdmaCreateMrReq {
+    pub pd_handle: u64,
+    pub mr_type: GdmaMrType,
+    pub reserved1: u32,
+    pub dma_region_handle: u64,
+    pub virtual_address: u64,
+    pub access_flags: u32,
+    pub reserved2: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct GdmaCreateMrResp {
+    pub mr_handle: u64,
+    pub lkey: u32,
+    pub rkey: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct GdmaDestroyMrReq {
+    pub mr_handle: u64,
+}
@@ -18,6 +18,8 @@
 pub struct GdmaDeviceHandle {
     /// The vports to instantiate on the NIC.
     pub vports: Vec<VportDefinition>,
+    /// Whether to expose the RDMA device function.
+    pub rdma: bool,
 }
 
 impl ResourceId<PciDeviceHandleKind> for GdmaDeviceHandle {
@@ -8,9 +8,13 @@
 // UNSAFETY: bindgen generated code.
 #![allow(unsafe_code)]
 
+use nix::ioctl_read_bad;
+use nix::ioctl_write_int_bad;
 use nix::ioctl_write_ptr_bad;
+use nix::request_code_read;
 use nix::request_code_write;
 use std::os::raw::c_int;
+use std::os::raw::c_uint;
 
 // Generated using:
 //
@@ -40,3 +44,16 @@
     request_code_write!(b'T', 202, size_of::<c_int>()),
     gen_if::ifreq
 );
+
+// #define TUNGETFEATURES _IOR('T', 207, unsigned int)
+ioctl_read_bad!(
+    tun_get_features,
+    request_code_read!(b'T', 207, size_of::<c_uint>()),
+    c_uint
+);
+
+// #define TUNSETOFFLOAD  _IOW('T', 208, unsigned int)
+ioctl_write_int_bad!(
+    tun_set_offload,
+    request_code_write!(b'T', 208, size_of::<c_uint>())
+);
@@ -9,15 +9,47 @@
 use crate::bnic_driver::WqConfig;
 use crate::gdma_driver::GdmaDriver;
 use crate::mana::ResourceArena;
+use crate::queues::Cq;
+use crate::queues::DoorbellPage;
+use crate::queues::Wq;
 use chipset_device::mmio::ExternallyManagedMmioIntercepts;
 use gdma::VportConfig;
+use gdma_defs::ib::ManaIbCommandCode;
+use gdma_defs::ib::ManaIbCreateAdapterReq;
+use gdma_defs::ib::ManaIbCreateAdapterResp;
+use gdma_defs::ib::ManaIbCreateRcQpReq;
+use gdma_defs::ib::ManaIbCreateRcQpResp;
+use gdma_defs::ib::ManaIbDestroyAdapterReq;
+use gdma_defs::ib::ManaIbDestroyRcQpReq;
+use gdma_defs::ib::ManaIbQpState;
+use gdma_defs::ib::ManaIbSetQpStateReq;
+use gdma_defs::ib::ManaRdmaCqe;
+use gdma_defs::ib::ManaRdmaCqeType;
+use gdma_defs::ib::ManaRdmaSendOob;
+use gdma_defs::ib::ManaRdmaSendOobFlags;
+use gdma_defs::ib::MANA_RDMA_CQE_SUCCESS;
+use gdma_defs::ib::MANA_RDMA_WQE_SEND_IMM;
+use gdma_defs::GdmaCreateMrReq;
+use gdma_defs::GdmaCreateMrResp;
+use gdma_defs::GdmaCreatePdReq;
+use gdma_defs::GdmaCreatePdResp;
+use gdma_defs::GdmaCreateQueueReq;
+use gdma_defs::GdmaCreateQueueResp;
+use gdma_defs::GdmaDestroyMrReq;
+use gdma_defs::GdmaDestroyPdReq;
 use gdma_defs::GdmaDevType;
+use gdma_defs::GdmaMrType;
 use gdma_defs::GdmaQueueType;
+use gdma_defs::GdmaRequestType;
+use gdma_defs::Sge;
+use gdma_defs::GDMA_ACCESS_FLAG_LOCAL_WRITE;
 use net_backend::null::NullEndpoint;
 use pal_async::async_test;
+use pal_async::timer::PolledTimer;
 use pal_async::DefaultDriver;
 use pci_core::msi::MsiInterruptSet;
 use std::sync::Arc;
+use std::time::Duration;
 use test_with_tracing::test;
 use user_driver::emulated::DeviceSharedMemory;
 use user_driver::emulated::EmulatedDevice;
@@ -26,6 +58,8 @@
 use user_driver::HostDmaAllocator;
 use vmcore::vm_task::SingleDriverBackend;
 use vmcore::vm_task::VmTaskDriverSource;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
 
 #[async_test]
 async fn test_gdma(driver: DefaultDriver) {
@@ -159,3 +193,304 @@ async fn test_gdma(driver: DefaultDriver) {
     .unwrap();
     arena.destroy(&mut gdma).await;
 }
+
+#[async_test]
+async fn test_rdma_loopback(driver: DefaultDriver) {
+    let mem = DeviceSharedMemory::new(256 * 1024, 0);
+    let mut msi_set = MsiInterruptSet::new();
+    let mut device = gdma::GdmaDevice::new(
+        &VmTaskDriverSource::new(SingleDriverBackend::new(driver.clone())),
+        mem.guest_memory().clone(),
+        &mut msi_set,
+        vec![VportConfig {
+            mac_address: [1, 2, 3, 4, 5, 6].into(),
+            endpoint: Box::new(NullEndpoint::new()),
+        }],
+        &mut ExternallyManagedMmioIntercepts,
+    );
+    device.enable_rdma();
+    let device = EmulatedDevice::new(device, msi_set, mem);
+
+    let mut gdma = GdmaDriver::new(&driver, device, 1).await.unwrap();
+    let dev_id = gdma
+        .list_devices()
+        .await
+        .unwrap()
+        .iter()
+        .copied()
+        .find(|dev_id| dev_id.ty == GdmaDevType::GDMA_DEVICE_MANA_IB)
+        .unwrap();
+
+    let device_props = gdma.register_device(dev_id).await.unwrap();
+    let doorbell = DoorbellPage::new(gdma.doorbell(), device_props.db_id);
+    let buffer = Arc::new(
+        gdma.device()
+            .host_allocator()
+            .allocate_dma_buffer(7 * PAGE_SIZE)
+            .unwrap(),
+    );
+    let page = |n| buffer.subblock(n * PAGE_SIZE, PAGE_SIZE);
+    let mut arena = ResourceArena::new();
+    let mut regions = Vec::new();
+    for n in 0..7 {
+        regions.push(
+            gdma.create_dma_region(&mut arena, dev_id, page(n))
+                .await
+                .unwrap(),
+        );
+    }
+
+    let (eq_id, _) = gdma
+        .create_eq(
+            &mut arena,
+            dev_id,
+            regions[0],
+            PAGE_SIZE as u32,
+            device_props.pdid,
+            device_props.db_id,
+            0,
+        )
+        .await
+        .unwrap();
+
+    let cq_resp: GdmaCreateQueueResp = gdma
+        .request(
+            GdmaRequestType::GDMA_CREATE_QUEUE.0,
+            dev_id,
+            GdmaCreateQueueReq {
+                queue_type: GdmaQueueType::GDMA_CQ,
+                gdma_region: regions[1],
+                queue_size: PAGE_SIZE as u32,
+                cq_parent_eq_id: eq_id,
+                ..FromZeroes::new_zeroed()
+            },
+        )
+        .await
+        .unwrap();
+    arena.take_dma_region(regions[1]);
+    let mut cq = Cq::new_cq(page(1), doorbell.clone(), cq_resp.queue_index);
+
+    // Register the data page as a memory region at an arbitrary IO address.
+    let iova = 0x10000;
+    let pd: GdmaCreatePdResp = gdma
+        .request(
+            GdmaRequestType::GDMA_CREATE_PD.0,
+            dev_id,
+            GdmaCreatePdReq {
+                flags: 0,
+                reserved: 0,
+            },
+        )
+        .await
+        .unwrap();
+    let mr: GdmaCreateMrResp = gdma
+        .request(
+            GdmaRequestType::GDMA_CREATE_MR.0,
+            dev_id,
+            GdmaCreateMrReq {
+                pd_handle: pd.pd_handle,
+                mr_type: GdmaMrType::GDMA_MR_TYPE_GVA,
+                reserved1: 0,
+                dma_region_handle: regions[6],
+                virtual_address: iova,
+                access_flags: GDMA_ACCESS_FLAG_LOCAL_WRITE,
+                reserved2: 0,
+            },
+        )
+        .await
+        .unwrap();
+    arena.take_dma_region(regions[6]);
+    let data = page(6);
+
+    let adapter = gdma
+        .request::<_, ManaIbCreateAdapterResp>(
+            ManaIbCommandCode::MANA_IB_CREATE_ADAPTER.0,
+            dev_id,
+            ManaIbCreateAdapterReq {
+                notify_eq_id: eq_id,
+                adapter_caps: 0,
+                feature_flags: 0,
+            },
+        )
+        .await
+        .unwrap()
+        .adapter;
+
+    // Invalid requests fail without consuming the DMA regions.
+    for (sq, rq, cq_id) in [(2, 2, cq.id()), (2, 3, cq.id() + 1)] {
+        gdma.request::<_, ManaIbCreateRcQpResp>(
+            ManaIbCommandCode::MANA_IB_CREATE_RC_QP.0,
+            dev_id,
+            ManaIbCreateRcQpReq {
+                adapter,
+                pd_handle: pd.pd_handle,
+                sq_dma_region: regions[sq],
+                rq_dma_region: regions[rq],
+                send_cq_id: cq.id(),
+                recv_cq_id: cq_id,
+                sq_size: PAGE_SIZE as u32,
+                rq_size: PAGE_SIZE as u32,
+                max_send_sge: 1,
+                max_recv_sge: 1,
+            },
+        )
+        .await
+        .unwrap_err();
+    }
+
+    let mut qps = Vec::new();
+    for (sq, rq) in [(2, 3), (4, 5)] {
+        let qp: ManaIbCreateRcQpResp = gdma
+            .request(
+                ManaIbCommandCode::MANA_IB_CREATE_RC_QP.0,
+                dev_id,
+                ManaIbCreateRcQpReq {
+                    adapter,
+                    pd_handle: pd.pd_handle,
+                    sq_dma_region: regions[sq],
+                    rq_dma_region: regions[rq],
+                    send_cq_id: cq.id(),
+                    recv_cq_id: cq.id(),
+                    sq_size: PAGE_SIZE as u32,
+                    rq_size: PAGE_SIZE as u32,
+                    max_send_sge: 1,
+                    max_recv_sge: 1,
+                },
+            )
+            .await
+            .unwrap();
+        arena.take_dma_region(regions[sq]);
+        arena.take_dma_region(regions[rq]);
+        let sq = Wq::new_sq(page(sq), doorbell.clone(), qp.sq_id);
+        let rq = Wq::new_rq(page(rq), doorbell.clone(), qp.rq_id);
+        qps.push((qp, sq, rq));
+    }
+
+    // Connect the queue pairs to each other.
+    for (i, state) in [ManaIbQpState::RTR, ManaIbQpState::RTS]
+        .into_iter()
+        .flat_map(|state| [(0, state), (1, state)])
+    {
+        gdma.request::<_, ()>(
+            ManaIbCommandCode::MANA_IB_SET_QP_STATE.0,
+            dev_id,
+            ManaIbSetQpStateReq {
+                adapter,
+                qp_handle: qps[i].0.qp_handle,
+                state,
+                dest_qp_num: qps[1 - i].0.qp_num,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    let message = b"hello over rdma";
+    data.write_at(0, message);
+
+    let [(qp0, sq0, _), (_, _, rq1)] = &mut qps[..] else {
+        unreachable!()
+    };
+    rq1.push(
+        &(),
+        [Sge {
+            address: iova + 0x800,
+            mem_key: mr.lkey,
+            size: 0x100,
+        }],
+        None,
+        0,
+    )
+    .unwrap();
+    rq1.commit();
+    sq0.push(
+        &ManaRdmaSendOob {
+            flags: ManaRdmaSendOobFlags::new()
+                .with_wqe_type(MANA_RDMA_WQE_SEND_IMM)
+                .with_signaled(true),
+            immediate: 0x1234,
+        },
+        [Sge {
+            address: iova,
+            mem_key: mr.lkey,
+            size: message.len() as u32,
+        }],
+        None,
+        0,
+    )
+    .unwrap();
+    sq0.commit();
+
+    let mut timer = PolledTimer::new(&driver);
+    let mut send_cqe = None;
+    let mut recv_cqe = None;
+    while send_cqe.is_none() || recv_cqe.is_none() {
+        match cq.pop() {
+            Some(cqe) => {
+                let rdma_cqe = ManaRdmaCqe::read_from_prefix(&cqe.data[..]).unwrap();
+                if cqe.params.is_send_wq() {
+                    assert_eq!(cqe.params.wq_number(), sq0.id());
+                    send_cqe = Some(rdma_cqe);
+                } else {
+                    assert_eq!(cqe.params.wq_number(), rq1.id());
+                    recv_cqe = Some(rdma_cqe);
+                }
+            }
+            None => timer.sleep(Duration::from_millis(10)).await,
+        }
+    }
+
+    let send_cqe = send_cqe.unwrap();
+    assert_eq!(send_cqe.cqe_type, ManaRdmaCqeType::SEND);
+    assert_eq!(send_cqe.vendor_error, MANA_RDMA_CQE_SUCCESS);
+
+    let recv_cqe = recv_cqe.unwrap();
+    assert_eq!(recv_cqe.cqe_type, ManaRdmaCqeType::RECV_IMM);
+    assert_eq!(recv_cqe.vendor_error, MANA_RDMA_CQE_SUCCESS);
+    assert_eq!(recv_cqe.msg_len, message.len() as u32);
+    assert_eq!(recv_cqe.src_qp_num, qp0.qp_num);
+    assert_eq!(recv_cqe.imm_data, 0x1234);
+
+    let mut received = [0; 15];
+    data.read_at(0x800, &mut received);
+    assert_eq!(&received, message);
+
+    for (qp, _, _) in qps {
+        gdma.request::<_, ()>(
+            ManaIbCommandCode::MANA_IB_DESTROY_RC_QP.0,
+            dev_id,
+            ManaIbDestroyRcQpReq {
+                adapter,
+                qp_handle: qp.qp_handle,
+            },
+        )
+        .await
+        .unwrap();
+    }
+    gdma.request::<_, ()>(
+        ManaIbCommandCode::MANA_IB_DESTROY_ADAPTER.0,
+        dev_id,
+        ManaIbDestroyAdapterReq { adapter },
+    )
+    .await
+    .unwrap();
+    gdma.request::<_, ()>(
+        GdmaRequestType::GDMA_DESTROY_MR.0,
+        dev_id,
+        GdmaDestroyMrReq {
+            mr_handle: mr.mr_handle,
+        },
+    )
+    .await
+    .unwrap();
+    gdma.request::<_, ()>(
+        GdmaRequestType::GDMA_DESTROY_PD.0,
+        dev_id,
+        GdmaDestroyPdReq {
+            pd_handle: pd.pd_handle,
+        },
+    )
+    .await
+    .unwrap();
+    arena.destroy(&mut gdma).await;
+}
@@ -0,0 +1,177 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Channel endpoint implementation, which exchanges Ethernet frames over mesh
+//! channels.
+//!
+//! This is useful for connecting VMs in different processes through a
+//! software switch, such as in tests.
+
+use crate::linearize;
+use crate::resolve::ResolveEndpointParams;
+use crate::resolve::ResolvedEndpoint;
+use crate::BufferAccess;
+use crate::Endpoint;
+use crate::Queue;
+use crate::QueueConfig;
+use crate::RssConfig;
+use crate::RxId;
+use crate::RxMetadata;
+use crate::TxId;
+use crate::TxSegment;
+use async_trait::async_trait;
+use inspect::InspectMut;
+use net_backend_resources::channel::ChannelHandle;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use vm_resource::declare_static_resolver;
+use vm_resource::kind::NetEndpointHandleKind;
+use vm_resource::ResolveResource;
+
+/// A resolver for [`ChannelHandle`].
+pub struct ChannelResolver;
+
+declare_static_resolver! {
+    ChannelResolver,
+    (NetEndpointHandleKind, ChannelHandle),
+}
+
+impl ResolveResource<NetEndpointHandleKind, ChannelHandle> for ChannelResolver {
+    type Output = ResolvedEndpoint;
+    type Error = Infallible;
+
+    fn resolve(
+        &self,
+        resource: ChannelHandle,
+        _input: ResolveEndpointParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(ChannelEndpoint::new(resource.send, resource.recv).into())
+    }
+}
+
+/// A networking backend that sends transmitted packets to a channel, and
+/// receives packets from another.
+#[derive(InspectMut)]
+#[inspect(skip)]
+pub struct ChannelEndpoint {
+    send: mesh::MpscSender<Vec<u8>>,
+    recv: Arc<Mutex<mesh::Receiver<Vec<u8>>>>,
+}
+
+impl ChannelEndpoint {
+    /// Returns a new endpoint that sends packets transmitted by the guest to
+    /// `send`, and delivers packets from `recv` to the guest.
+    pub fn new(send: mesh::MpscSender<Vec<u8>>, recv: mesh::Receiver<Vec<u8>>) -> Self {
+        Self {
+            send,
+            recv: Arc::new(Mutex::new(recv)),
+        }
+    }
+}
+
+#[async_trait]
+impl Endpoint for ChannelEndpoint {
+    fn endpoint_type(&self) -> &'static str {
+        "channel"
+    }
+
+    async fn get_queues(
+        &mut self,
+        config: Vec<QueueConfig<'_>>,
+        _rss: Option<&RssConfig<'_>>,
+        queues: &mut Vec<Box<dyn Queue>>,
+    ) -> anyhow::Result<()> {
+        // Only one queue is supported by default, but deliver all received
+        // packets to the first queue regardless.
+        queues.extend(config.into_iter().enumerate().map(|(i, config)| {
+            Box::new(ChannelQueue {
+                pool: config.pool,
+                send: self.send.clone(),
+                recv: (i == 0).then(|| self.recv.clone()),
+                rx_avail: config.initial_rx.to_vec().into(),
+                rx_done: VecDeque::new(),
+            }) as _
+        }));
+        Ok(())
+    }
+
+    async fn stop(&mut self) {}
+
+    fn is_ordered(&self) -> bool {
+        true
+    }
+}
+
+#[derive(InspectMut)]
+#[inspect(skip)]
+struct ChannelQueue {
+    pool: Box<dyn BufferAccess>,
+    send: mesh::MpscSender<Vec<u8>>,
+    recv: Option<Arc<Mutex<mesh::Receiver<Vec<u8>>>>>,
+    rx_avail: VecDeque<RxId>,
+    rx_done: VecDeque<RxId>,
+}
+
+impl Queue for ChannelQueue {
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(recv) = &self.recv {
+            let mut recv = recv.lock();
+            // Only receive packets when there are buffers to put them in, so
+            // that the channel queues them in the meantime.
+            while let Some(&rx_id) = self.rx_avail.front() {
+                let Poll::Ready(Ok(packet)) = recv.poll_recv(cx) else {
+                    break;
+                };
+                self.rx_avail.pop_front();
+                self.pool.write_packet(
+                    rx_id,
+                    &RxMetadata {
+                        offset: 0,
+                        len: packet.len(),
+                        ..Default::default()
+                    },
+                    &packet,
+                );
+                self.rx_done.push_back(rx_id);
+            }
+        }
+        if self.rx_done.is_empty() {
+            Poll::Pending
+        } else {
+            Poll::Ready(())
+        }
+    }
+
+    fn rx_avail(&mut self, done: &[RxId]) {
+        self.rx_avail.extend(done);
+    }
+
+    fn rx_poll(&mut self, packets: &mut [RxId]) -> anyhow::Result<usize> {
+        let n = packets.len().min(self.rx_done.len());
+        for (d, s) in packets.iter_mut().zip(self.rx_done.drain(..n)) {
+            *d = s;
+        }
+        Ok(n)
+    }
+
+    fn tx_avail(&mut self, mut segments: &[TxSegment]) -> anyhow::Result<(bool, usize)> {
+        let n = segments.len();
+        while !segments.is_empty() {
+            let packet = linearize(self.pool.as_ref(), &mut segments)?;
+            self.send.send(packet);
+        }
+        Ok((true, n))
+    }
+
+    fn tx_poll(&mut self, _done: &mut [TxId]) -> anyhow::Result<usize> {
+        Ok(0)
+    }
+
+    fn buffer_access(&mut self) -> Option<&mut dyn BufferAccess> {
+        Some(self.pool.as_mut())
+    }
+}
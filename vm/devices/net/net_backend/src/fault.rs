@@ -0,0 +1,241 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Fault injecting endpoint, which drops packets and flaps the link of an
+//! inner endpoint.
+//!
+//! This is useful for testing how guests handle unreliable networks. The
+//! faults can be replaced while the VM is running.
+
+use crate::resolve::ResolveEndpointParams;
+use crate::resolve::ResolvedEndpoint;
+use crate::BufferAccess;
+use crate::Endpoint;
+use crate::EndpointAction;
+use crate::MultiQueueSupport;
+use crate::Queue;
+use crate::QueueConfig;
+use crate::RssConfig;
+use crate::RxId;
+use crate::TxId;
+use crate::TxOffloadSupport;
+use crate::TxSegment;
+use async_trait::async_trait;
+use futures::FutureExt;
+use futures::StreamExt;
+use futures_concurrency::future::Race;
+use inspect::InspectMut;
+use net_backend_resources::fault::FaultHandle;
+use net_backend_resources::fault::NetFaults;
+use parking_lot::Mutex;
+use std::future::pending;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::NetEndpointHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::ResolveError;
+use vm_resource::ResourceResolver;
+
+/// A resolver for [`FaultHandle`].
+pub struct FaultResolver;
+
+declare_static_async_resolver! {
+    FaultResolver,
+    (NetEndpointHandleKind, FaultHandle),
+}
+
+#[async_trait]
+impl AsyncResolveResource<NetEndpointHandleKind, FaultHandle> for FaultResolver {
+    type Output = ResolvedEndpoint;
+    type Error = ResolveError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        resource: FaultHandle,
+        input: ResolveEndpointParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let inner: ResolvedEndpoint = resolver.resolve(resource.endpoint, input).await?;
+        Ok(FaultEndpoint::new(inner.0, resource.faults, resource.update).into())
+    }
+}
+
+/// An endpoint that injects faults into an inner endpoint.
+pub struct FaultEndpoint {
+    endpoint: Box<dyn Endpoint>,
+    faults: Arc<Mutex<NetFaults>>,
+    update: mesh::Receiver<NetFaults>,
+    /// The link status to report before waiting for updates, if the link
+    /// starts out down.
+    initial_link: Option<bool>,
+}
+
+impl FaultEndpoint {
+    /// Returns a new endpoint wrapping `endpoint`, injecting `faults` until
+    /// they are replaced by a new set received from `update`.
+    pub fn new(
+        endpoint: Box<dyn Endpoint>,
+        faults: NetFaults,
+        update: mesh::Receiver<NetFaults>,
+    ) -> Self {
+        Self {
+            endpoint,
+            faults: Arc::new(Mutex::new(faults)),
+            update,
+            initial_link: faults.link_down.then_some(false),
+        }
+    }
+}
+
+impl InspectMut for FaultEndpoint {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        req.respond()
+            .merge(self.endpoint.as_mut())
+            .field("faults", inspect::AsDebug(*self.faults.lock()));
+    }
+}
+
+#[async_trait]
+impl Endpoint for FaultEndpoint {
+    fn endpoint_type(&self) -> &'static str {
+        self.endpoint.endpoint_type()
+    }
+
+    async fn get_queues(
+        &mut self,
+        config: Vec<QueueConfig<'_>>,
+        rss: Option<&RssConfig<'_>>,
+        queues: &mut Vec<Box<dyn Queue>>,
+    ) -> anyhow::Result<()> {
+        let mut inner = Vec::new();
+        self.endpoint.get_queues(config, rss, &mut inner).await?;
+        queues.extend(inner.into_iter().map(|queue| {
+            Box::new(FaultQueue {
+                queue,
+                faults: self.faults.clone(),
+            }) as _
+        }));
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        self.endpoint.stop().await
+    }
+
+    fn is_ordered(&self) -> bool {
+        self.endpoint.is_ordered()
+    }
+
+    fn tx_offload_support(&self) -> TxOffloadSupport {
+        self.endpoint.tx_offload_support()
+    }
+
+    fn multiqueue_support(&self) -> MultiQueueSupport {
+        self.endpoint.multiqueue_support()
+    }
+
+    fn tx_fast_completions(&self) -> bool {
+        self.endpoint.tx_fast_completions()
+    }
+
+    async fn set_data_path_to_guest_vf(&self, use_vf: bool) -> anyhow::Result<()> {
+        self.endpoint.set_data_path_to_guest_vf(use_vf).await
+    }
+
+    async fn get_data_path_to_guest_vf(&self) -> anyhow::Result<bool> {
+        self.endpoint.get_data_path_to_guest_vf().await
+    }
+
+    async fn wait_for_endpoint_action(&mut self) -> EndpointAction {
+        enum Event {
+            Update(NetFaults),
+            Endpoint(EndpointAction),
+        }
+        if let Some(connected) = self.initial_link.take() {
+            return EndpointAction::LinkStatusNotify(connected);
+        }
+        loop {
+            let update_recv = &mut self.update;
+            let update = async {
+                match update_recv.next().await {
+                    Some(faults) => Event::Update(faults),
+                    None => pending().await,
+                }
+            };
+            let action = self
+                .endpoint
+                .wait_for_endpoint_action()
+                .map(Event::Endpoint);
+            match (update, action).race().await {
+                Event::Update(faults) => {
+                    tracing::info!(?faults, "updating injected network faults");
+                    let old = std::mem::replace(&mut *self.faults.lock(), faults);
+                    if old.link_down != faults.link_down {
+                        return EndpointAction::LinkStatusNotify(!faults.link_down);
+                    }
+                }
+                Event::Endpoint(action) => break action,
+            }
+        }
+    }
+
+    fn link_speed(&self) -> u64 {
+        self.endpoint.link_speed()
+    }
+}
+
+struct FaultQueue {
+    queue: Box<dyn Queue>,
+    faults: Arc<Mutex<NetFaults>>,
+}
+
+impl InspectMut for FaultQueue {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        self.queue.inspect_mut(req)
+    }
+}
+
+#[async_trait]
+impl Queue for FaultQueue {
+    async fn update_target_vp(&mut self, target_vp: u32) {
+        self.queue.update_target_vp(target_vp).await
+    }
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.queue.poll_ready(cx)
+    }
+
+    fn rx_avail(&mut self, done: &[RxId]) {
+        self.queue.rx_avail(done)
+    }
+
+    fn rx_poll(&mut self, packets: &mut [RxId]) -> anyhow::Result<usize> {
+        let n = self.queue.rx_poll(packets)?;
+        let faults = *self.faults.lock();
+        if n != 0 && (faults.link_down || faults.drop_rx) {
+            // Give the buffers straight back to the inner queue.
+            self.queue.rx_avail(&packets[..n]);
+            return Ok(0);
+        }
+        Ok(n)
+    }
+
+    fn tx_avail(&mut self, segments: &[TxSegment]) -> anyhow::Result<(bool, usize)> {
+        let faults = *self.faults.lock();
+        if faults.link_down || faults.drop_tx {
+            // Complete the packets without sending them.
+            return Ok((true, segments.len()));
+        }
+        self.queue.tx_avail(segments)
+    }
+
+    fn tx_poll(&mut self, done: &mut [TxId]) -> anyhow::Result<usize> {
+        self.queue.tx_poll(done)
+    }
+
+    fn buffer_access(&mut self) -> Option<&mut dyn BufferAccess> {
+        self.queue.buffer_access()
+    }
+}
@@ -4,8 +4,11 @@
 //! This module defines a trait and implementations thereof for network
 //! backends.
 
+pub mod channel;
+pub mod fault;
 pub mod loopback;
 pub mod null;
+pub mod rate_limit;
 pub mod resolve;
 pub mod tests;
 
@@ -0,0 +1,481 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Rate limiting endpoint, which limits the bandwidth of an inner endpoint.
+//!
+//! This is useful for emulating constrained networks in tests.
+//!
+//! Each direction is limited by a token bucket, where each token is a byte.
+//! A packet is allowed through as long as the bucket is not empty, which may
+//! leave the bucket in debt until enough time passes to pay it off.
+
+use crate::next_packet;
+use crate::resolve::ResolveEndpointParams;
+use crate::resolve::ResolvedEndpoint;
+use crate::BufferAccess;
+use crate::Endpoint;
+use crate::EndpointAction;
+use crate::MultiQueueSupport;
+use crate::Queue;
+use crate::QueueConfig;
+use crate::RssConfig;
+use crate::RxBufferSegment;
+use crate::RxId;
+use crate::RxMetadata;
+use crate::TxId;
+use crate::TxOffloadSupport;
+use crate::TxSegment;
+use async_trait::async_trait;
+use futures::FutureExt;
+use guestmem::GuestMemory;
+use inspect::AtomicMut;
+use inspect::Inspect;
+use inspect::InspectMut;
+use net_backend_resources::rate_limit::RateLimit;
+use net_backend_resources::rate_limit::RateLimitHandle;
+use pal_async::timer::Instant;
+use pal_async::timer::PolledTimer;
+use parking_lot::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::NetEndpointHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::ResolveError;
+use vm_resource::ResourceResolver;
+
+/// A resolver for [`RateLimitHandle`].
+pub struct RateLimitResolver;
+
+declare_static_async_resolver! {
+    RateLimitResolver,
+    (NetEndpointHandleKind, RateLimitHandle),
+}
+
+#[async_trait]
+impl AsyncResolveResource<NetEndpointHandleKind, RateLimitHandle> for RateLimitResolver {
+    type Output = ResolvedEndpoint;
+    type Error = ResolveError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        resource: RateLimitHandle,
+        input: ResolveEndpointParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let inner: ResolvedEndpoint = resolver.resolve(resource.endpoint, input).await?;
+        Ok(RateLimitEndpoint::new(inner.0, resource.egress, resource.ingress).into())
+    }
+}
+
+/// An endpoint that limits the bandwidth of an inner endpoint.
+pub struct RateLimitEndpoint {
+    endpoint: Box<dyn Endpoint>,
+    limits: Arc<Limits>,
+}
+
+struct Limits {
+    egress: TokenBucket,
+    ingress: TokenBucket,
+}
+
+impl RateLimitEndpoint {
+    /// Returns a new endpoint wrapping `endpoint`, with initial limits for
+    /// packets sent (`egress`) and received (`ingress`) by the guest.
+    pub fn new(endpoint: Box<dyn Endpoint>, egress: RateLimit, ingress: RateLimit) -> Self {
+        Self {
+            endpoint,
+            limits: Arc::new(Limits {
+                egress: TokenBucket::new(egress),
+                ingress: TokenBucket::new(ingress),
+            }),
+        }
+    }
+}
+
+impl InspectMut for RateLimitEndpoint {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        req.respond()
+            .merge(self.endpoint.as_mut())
+            .field("egress", &self.limits.egress)
+            .field("ingress", &self.limits.ingress);
+    }
+}
+
+#[async_trait]
+impl Endpoint for RateLimitEndpoint {
+    fn endpoint_type(&self) -> &'static str {
+        self.endpoint.endpoint_type()
+    }
+
+    async fn get_queues(
+        &mut self,
+        config: Vec<QueueConfig<'_>>,
+        rss: Option<&RssConfig<'_>>,
+        queues: &mut Vec<Box<dyn Queue>>,
+    ) -> anyhow::Result<()> {
+        let mut timers = Vec::with_capacity(config.len());
+        let mut rx_bytes = Vec::with_capacity(config.len());
+        let config = config
+            .into_iter()
+            .map(|config| {
+                let bytes = Arc::new(AtomicU64::new(0));
+                timers.push(PolledTimer::new(config.driver.as_ref()));
+                rx_bytes.push(bytes.clone());
+                QueueConfig {
+                    pool: Box::new(CountingBufferAccess {
+                        pool: config.pool,
+                        rx_bytes: bytes,
+                    }),
+                    initial_rx: config.initial_rx,
+                    driver: config.driver,
+                }
+            })
+            .collect();
+
+        let mut inner = Vec::new();
+        self.endpoint.get_queues(config, rss, &mut inner).await?;
+        queues.extend(inner.into_iter().zip(timers).zip(rx_bytes).map(
+            |((queue, timer), rx_bytes)| {
+                Box::new(RateLimitQueue {
+                    queue,
+                    limits: self.limits.clone(),
+                    rx_bytes,
+                    timer,
+                    deadline: None,
+                    rx_throttled: false,
+                }) as _
+            },
+        ));
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        self.endpoint.stop().await
+    }
+
+    fn is_ordered(&self) -> bool {
+        self.endpoint.is_ordered()
+    }
+
+    fn tx_offload_support(&self) -> TxOffloadSupport {
+        self.endpoint.tx_offload_support()
+    }
+
+    fn multiqueue_support(&self) -> MultiQueueSupport {
+        self.endpoint.multiqueue_support()
+    }
+
+    fn tx_fast_completions(&self) -> bool {
+        self.endpoint.tx_fast_completions()
+    }
+
+    async fn set_data_path_to_guest_vf(&self, use_vf: bool) -> anyhow::Result<()> {
+        self.endpoint.set_data_path_to_guest_vf(use_vf).await
+    }
+
+    async fn get_data_path_to_guest_vf(&self) -> anyhow::Result<bool> {
+        self.endpoint.get_data_path_to_guest_vf().await
+    }
+
+    async fn wait_for_endpoint_action(&mut self) -> EndpointAction {
+        self.endpoint.wait_for_endpoint_action().await
+    }
+
+    fn link_speed(&self) -> u64 {
+        let limit = |bucket: &TokenBucket| match bucket.rate.load(Ordering::Relaxed) {
+            0 => u64::MAX,
+            rate => rate.saturating_mul(8),
+        };
+        self.endpoint
+            .link_speed()
+            .min(limit(&self.limits.egress))
+            .min(limit(&self.limits.ingress))
+    }
+}
+
+/// A token bucket, where each token is a byte.
+struct TokenBucket {
+    /// The rate in bytes per second, or zero for no limit.
+    rate: AtomicU64,
+    /// The bucket size in bytes, or zero to use `rate`.
+    burst: AtomicU64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: i64,
+    last_refill: Instant,
+}
+
+impl Inspect for TokenBucket {
+    fn inspect(&self, req: inspect::Request<'_>) {
+        req.respond()
+            .field("rate", AtomicMut(&self.rate))
+            .field("burst", AtomicMut(&self.burst))
+            .field("tokens", self.state.lock().tokens);
+    }
+}
+
+impl TokenBucket {
+    fn new(limit: RateLimit) -> Self {
+        let this = Self {
+            rate: AtomicU64::new(limit.rate),
+            burst: AtomicU64::new(limit.burst),
+            state: Mutex::new(BucketState {
+                tokens: 0,
+                last_refill: Instant::now(),
+            }),
+        };
+        this.state.lock().tokens = this.size(limit.rate);
+        this
+    }
+
+    fn size(&self, rate: u64) -> i64 {
+        let burst = match self.burst.load(Ordering::Relaxed) {
+            0 => rate,
+            burst => burst,
+        };
+        burst.try_into().unwrap_or(i64::MAX)
+    }
+
+    /// Refills the bucket and returns the number of tokens in it, or `None`
+    /// if there is no limit.
+    fn available(&self, now: Instant) -> Option<i64> {
+        let rate = self.rate.load(Ordering::Relaxed);
+        if rate == 0 {
+            return None;
+        }
+        let size = self.size(rate);
+        let mut state = self.state.lock();
+        // Another queue may have refilled the bucket after `now` was sampled.
+        let elapsed = now.as_nanos().saturating_sub(state.last_refill.as_nanos());
+        let new = (elapsed as u128 * rate as u128 / 1_000_000_000)
+            .try_into()
+            .unwrap_or(i64::MAX);
+        // Only move the refill time forward once a token has been earned, so
+        // that frequent calls do not lose fractional tokens.
+        if new > 0 {
+            state.tokens = state.tokens.saturating_add(new).min(size);
+            state.last_refill = now;
+        }
+        Some(state.tokens)
+    }
+
+    fn consume(&self, bytes: u64) {
+        if bytes != 0 && self.rate.load(Ordering::Relaxed) != 0 {
+            let mut state = self.state.lock();
+            state.tokens = state
+                .tokens
+                .saturating_sub(bytes.try_into().unwrap_or(i64::MAX));
+        }
+    }
+
+    /// Returns how long until the bucket will no longer be empty.
+    fn wait_time(&self) -> Duration {
+        let rate = self.rate.load(Ordering::Relaxed).max(1);
+        let deficit = 1 - self.state.lock().tokens.min(0);
+        Duration::from_nanos(
+            (deficit as u128 * 1_000_000_000)
+                .div_ceil(rate as u128)
+                .try_into()
+                .unwrap_or(u64::MAX),
+        )
+    }
+}
+
+/// Wraps a queue's buffer pool to count the bytes received into it.
+struct CountingBufferAccess {
+    pool: Box<dyn BufferAccess>,
+    rx_bytes: Arc<AtomicU64>,
+}
+
+impl BufferAccess for CountingBufferAccess {
+    fn guest_memory(&self) -> &GuestMemory {
+        self.pool.guest_memory()
+    }
+
+    fn write_data(&mut self, id: RxId, data: &[u8]) {
+        self.pool.write_data(id, data)
+    }
+
+    fn guest_addresses(&mut self, id: RxId) -> &[RxBufferSegment] {
+        self.pool.guest_addresses(id)
+    }
+
+    fn capacity(&self, id: RxId) -> u32 {
+        self.pool.capacity(id)
+    }
+
+    fn write_header(&mut self, id: RxId, metadata: &RxMetadata) {
+        self.rx_bytes
+            .fetch_add(metadata.len as u64, Ordering::Relaxed);
+        self.pool.write_header(id, metadata)
+    }
+
+    fn write_packet(&mut self, id: RxId, metadata: &RxMetadata, data: &[u8]) {
+        self.rx_bytes
+            .fetch_add(metadata.len as u64, Ordering::Relaxed);
+        self.pool.write_packet(id, metadata, data)
+    }
+}
+
+struct RateLimitQueue {
+    queue: Box<dyn Queue>,
+    limits: Arc<Limits>,
+    rx_bytes: Arc<AtomicU64>,
+    timer: PolledTimer,
+    /// When to wake up to retry throttled packets.
+    deadline: Option<Instant>,
+    /// Receives are throttled, so the inner queue's readiness is ignored
+    /// until the deadline to avoid spinning on packets that cannot be
+    /// received yet.
+    rx_throttled: bool,
+}
+
+impl RateLimitQueue {
+    fn throttle(&mut self, now: Instant, bucket: &TokenBucket) {
+        let deadline = now.saturating_add(bucket.wait_time());
+        self.deadline = Some(self.deadline.map_or(deadline, |d| d.min(deadline)));
+    }
+}
+
+impl InspectMut for RateLimitQueue {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        req.respond()
+            .merge(self.queue.as_mut())
+            .field("rx_throttled", self.rx_throttled)
+            .field("throttled", self.deadline.is_some());
+    }
+}
+
+#[async_trait]
+impl Queue for RateLimitQueue {
+    async fn update_target_vp(&mut self, target_vp: u32) {
+        self.queue.update_target_vp(target_vp).await
+    }
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(deadline) = self.deadline {
+            if self.timer.sleep_until(deadline).poll_unpin(cx).is_ready() {
+                self.deadline = None;
+                self.rx_throttled = false;
+                return Poll::Ready(());
+            }
+            if self.rx_throttled {
+                return Poll::Pending;
+            }
+        }
+        self.queue.poll_ready(cx)
+    }
+
+    fn rx_avail(&mut self, done: &[RxId]) {
+        self.queue.rx_avail(done)
+    }
+
+    fn rx_poll(&mut self, packets: &mut [RxId]) -> anyhow::Result<usize> {
+        let limits = self.limits.clone();
+        let now = Instant::now();
+        if limits
+            .ingress
+            .available(now)
+            .is_some_and(|tokens| tokens <= 0)
+        {
+            self.rx_throttled = true;
+            self.throttle(now, &limits.ingress);
+            return Ok(0);
+        }
+        let n = self.queue.rx_poll(packets)?;
+        limits
+            .ingress
+            .consume(self.rx_bytes.swap(0, Ordering::Relaxed));
+        Ok(n)
+    }
+
+    fn tx_avail(&mut self, segments: &[TxSegment]) -> anyhow::Result<(bool, usize)> {
+        let limits = self.limits.clone();
+        let now = Instant::now();
+        let Some(tokens) = limits.egress.available(now) else {
+            return self.queue.tx_avail(segments);
+        };
+
+        // Allow packets through until the bucket is empty, letting the last
+        // one overdraw it.
+        let mut allowed = 0;
+        let mut budget = tokens;
+        let mut rest = segments;
+        while budget > 0 && !rest.is_empty() {
+            let (metadata, this, next) = next_packet(rest);
+            budget -= metadata.len as i64;
+            allowed += this.len();
+            rest = next;
+        }
+        if allowed < segments.len() {
+            self.throttle(now, &limits.egress);
+        }
+        if allowed == 0 {
+            return Ok((false, 0));
+        }
+
+        let (sync, n) = self.queue.tx_avail(&segments[..allowed])?;
+
+        // Charge for the packets the inner queue accepted.
+        let mut sent = &segments[..n];
+        let mut bytes = 0;
+        while !sent.is_empty() {
+            let (metadata, _, next) = next_packet(sent);
+            bytes += metadata.len as u64;
+            sent = next;
+        }
+        limits.egress.consume(bytes);
+        Ok((sync, n))
+    }
+
+    fn tx_poll(&mut self, done: &mut [TxId]) -> anyhow::Result<usize> {
+        self.queue.tx_poll(done)
+    }
+
+    fn buffer_access(&mut self) -> Option<&mut dyn BufferAccess> {
+        self.queue.buffer_access()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TokenBucket;
+    use net_backend_resources::rate_limit::RateLimit;
+    use pal_async::timer::Instant;
+    use std::time::Duration;
+
+    #[test]
+    fn test_token_bucket() {
+        let bucket = TokenBucket::new(RateLimit {
+            rate: 1000,
+            burst: 100,
+        });
+        let start = bucket.state.lock().last_refill;
+        assert_eq!(bucket.available(start), Some(100));
+
+        // Overdraw the bucket.
+        bucket.consume(150);
+        assert_eq!(bucket.available(start), Some(-50));
+        assert_eq!(bucket.wait_time(), Duration::from_millis(51));
+
+        // Refill at 1 byte per millisecond, up to the burst size.
+        let later = start + Duration::from_millis(60);
+        assert_eq!(bucket.available(later), Some(10));
+        let later = later + Duration::from_secs(1);
+        assert_eq!(bucket.available(later), Some(100));
+    }
+
+    #[test]
+    fn test_token_bucket_unlimited() {
+        let bucket = TokenBucket::new(RateLimit::default());
+        bucket.consume(1000);
+        assert_eq!(bucket.available(Instant::now()), None);
+    }
+}
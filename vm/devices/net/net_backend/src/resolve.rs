@@ -7,13 +7,16 @@
 use net_backend_resources::mac_address::MacAddress;
 use vm_resource::kind::NetEndpointHandleKind;
 use vm_resource::CanResolveTo;
+use vmcore::vm_task::VmTaskDriverSource;
 
-pub struct ResolveEndpointParams {
+pub struct ResolveEndpointParams<'a> {
     pub mac_address: MacAddress,
+    /// The driver source, for endpoints that need to run tasks.
+    pub driver_source: &'a VmTaskDriverSource,
 }
 
 impl CanResolveTo<ResolvedEndpoint> for NetEndpointHandleKind {
-    type Input<'a> = ResolveEndpointParams;
+    type Input<'a> = ResolveEndpointParams<'a>;
 }
 
 pub struct ResolvedEndpoint(pub Box<dyn Endpoint>);
@@ -26,9 +26,36 @@ impl ResourceId<NetEndpointHandleKind> for NullHandle {
     }
 }
 
+/// Channel backend.
+pub mod channel {
+    use mesh::MeshPayload;
+    use vm_resource::kind::NetEndpointHandleKind;
+    use vm_resource::ResourceId;
+
+    /// Handle to a network endpoint that exchanges Ethernet frames over mesh
+    /// channels, so that a switch in another process can connect VMs.
+    #[derive(MeshPayload)]
+    pub struct ChannelHandle {
+        /// Frames sent by the guest.
+        pub send: mesh::MpscSender<Vec<u8>>,
+        /// Frames to be received by the guest.
+        pub recv: mesh::Receiver<Vec<u8>>,
+    }
+
+    impl ResourceId<NetEndpointHandleKind> for ChannelHandle {
+        const ID: &'static str = "channel";
+    }
+}
+
 /// Consomme backend.
 pub mod consomme {
+    use crate::mac_address::MacAddress;
+    use mesh::rpc::FailableRpc;
     use mesh::MeshPayload;
+    use std::fmt::Display;
+    use std::net::Ipv4Addr;
+    use std::str::FromStr;
+    use thiserror::Error;
     use vm_resource::kind::NetEndpointHandleKind;
     use vm_resource::ResourceId;
 
@@ -37,11 +64,259 @@ pub mod consomme {
     pub struct ConsommeHandle {
         /// The CIDR of the network to use.
         pub cidr: Option<String>,
+        /// Host ports to forward to the guest.
+        pub ports: Vec<PortForward>,
+        /// Options for the built-in DHCP server.
+        pub dhcp: Vec<DhcpOption>,
+        /// Channel for changing the port forwarding rules at runtime.
+        pub requests: Option<mesh::Receiver<ConsommeRequest>>,
     }
 
     impl ResourceId<NetEndpointHandleKind> for ConsommeHandle {
         const ID: &'static str = "consomme";
     }
+
+    /// A request to a running Consomme endpoint.
+    #[derive(MeshPayload)]
+    pub enum ConsommeRequest {
+        /// Adds a port forwarding rule.
+        ForwardPort(FailableRpc<PortForward, ()>),
+        /// Removes the port forwarding rule for a host port.
+        UnforwardPort(FailableRpc<(PortProtocol, u16), ()>),
+    }
+
+    /// A rule forwarding a host port to a guest port.
+    #[derive(MeshPayload, Debug, Clone, PartialEq, Eq)]
+    pub struct PortForward {
+        /// The protocol to forward.
+        pub protocol: PortProtocol,
+        /// The IPv4 host address to bind. If `None`, all host addresses are
+        /// bound.
+        pub host_address: Option<[u8; 4]>,
+        /// The host port.
+        pub host_port: u16,
+        /// The guest port.
+        pub guest_port: u16,
+    }
+
+    /// The protocol of a [`PortForward`] rule.
+    #[derive(MeshPayload, Debug, Copy, Clone, PartialEq, Eq)]
+    pub enum PortProtocol {
+        /// TCP.
+        Tcp,
+        /// UDP.
+        Udp,
+    }
+
+    impl Display for PortForward {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            let protocol = match self.protocol {
+                PortProtocol::Tcp => "tcp",
+                PortProtocol::Udp => "udp",
+            };
+            write!(f, "{protocol}:")?;
+            if let Some(addr) = self.host_address {
+                write!(f, "{}:", Ipv4Addr::from(addr))?;
+            }
+            write!(f, "{}:{}", self.host_port, self.guest_port)
+        }
+    }
+
+    /// Error returned when parsing a [`PortForward`] fails.
+    #[derive(Debug, Error)]
+    #[error("invalid port forwarding rule, expected <tcp|udp>:[<host address>:]<host port>[:<guest port>]")]
+    pub struct InvalidPortForward;
+
+    impl FromStr for PortForward {
+        type Err = InvalidPortForward;
+
+        /// Parses a rule of the form
+        /// `<tcp|udp>:[<host address>:]<host port>[:<guest port>]`. If the
+        /// guest port is omitted, it is the same as the host port.
+        fn from_str(val: &str) -> Result<Self, InvalidPortForward> {
+            let mut parts = val.split(':');
+            let protocol = match parts.next() {
+                Some("tcp") => PortProtocol::Tcp,
+                Some("udp") => PortProtocol::Udp,
+                _ => return Err(InvalidPortForward),
+            };
+            let parts = parts.collect::<Vec<_>>();
+            let (host_address, ports) = match parts.as_slice() {
+                [addr, ports @ ..] if addr.contains('.') => (
+                    Some(
+                        addr.parse::<Ipv4Addr>()
+                            .map_err(|_| InvalidPortForward)?
+                            .octets(),
+                    ),
+                    ports,
+                ),
+                ports => (None, ports),
+            };
+            let port = |s: &str| s.parse::<u16>().map_err(|_| InvalidPortForward);
+            let (host_port, guest_port) = match ports {
+                [host] => (port(host)?, port(host)?),
+                [host, guest] => (port(host)?, port(guest)?),
+                _ => return Err(InvalidPortForward),
+            };
+            Ok(Self {
+                protocol,
+                host_address,
+                host_port,
+                guest_port,
+            })
+        }
+    }
+
+    /// A configuration option for the built-in DHCP server.
+    #[derive(MeshPayload, Debug, Clone, PartialEq, Eq)]
+    pub enum DhcpOption {
+        /// Always offer `ip_address` to the client with `mac_address`.
+        StaticLease {
+            /// The client's MAC address.
+            mac_address: MacAddress,
+            /// The IPv4 address to assign.
+            ip_address: [u8; 4],
+        },
+        /// A DNS server to offer, replacing the host's resolvers.
+        DnsServer([u8; 4]),
+        /// An NTP server to offer (option 42).
+        NtpServer([u8; 4]),
+        /// The TFTP boot server name (option 66). If this is an IPv4 address,
+        /// it is also used as the next server address.
+        BootServer(String),
+        /// The boot file name (option 67).
+        BootFile(String),
+    }
+
+    impl Display for DhcpOption {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            match self {
+                DhcpOption::StaticLease {
+                    mac_address,
+                    ip_address,
+                } => write!(f, "lease={mac_address}={}", Ipv4Addr::from(*ip_address)),
+                DhcpOption::DnsServer(addr) => write!(f, "dns={}", Ipv4Addr::from(*addr)),
+                DhcpOption::NtpServer(addr) => write!(f, "ntp={}", Ipv4Addr::from(*addr)),
+                DhcpOption::BootServer(name) => write!(f, "boot-server={name}"),
+                DhcpOption::BootFile(name) => write!(f, "boot-file={name}"),
+            }
+        }
+    }
+
+    /// Error returned when parsing a [`DhcpOption`] fails.
+    #[derive(Debug, Error)]
+    #[error("invalid dhcp option, expected lease=<mac>=<ip>, dns=<ip>, ntp=<ip>, boot-server=<name>, or boot-file=<name>")]
+    pub struct InvalidDhcpOption;
+
+    impl FromStr for DhcpOption {
+        type Err = InvalidDhcpOption;
+
+        /// Parses an option of the form `<name>=<value>`.
+        fn from_str(val: &str) -> Result<Self, InvalidDhcpOption> {
+            let addr = |s: &str| {
+                s.parse::<Ipv4Addr>()
+                    .map(|addr| addr.octets())
+                    .map_err(|_| InvalidDhcpOption)
+            };
+            // Names are limited by the size of the DHCP option length field.
+            let name = |s: &str| {
+                if s.is_empty() || s.len() > 255 {
+                    return Err(InvalidDhcpOption);
+                }
+                Ok(s.to_owned())
+            };
+            let (key, value) = val.split_once('=').ok_or(InvalidDhcpOption)?;
+            let option = match key {
+                "lease" => {
+                    let (mac_address, ip_address) =
+                        value.split_once('=').ok_or(InvalidDhcpOption)?;
+                    DhcpOption::StaticLease {
+                        mac_address: mac_address.parse().map_err(|_| InvalidDhcpOption)?,
+                        ip_address: addr(ip_address)?,
+                    }
+                }
+                "dns" => DhcpOption::DnsServer(addr(value)?),
+                "ntp" => DhcpOption::NtpServer(addr(value)?),
+                "boot-server" => DhcpOption::BootServer(name(value)?),
+                "boot-file" => DhcpOption::BootFile(name(value)?),
+                _ => return Err(InvalidDhcpOption),
+            };
+            Ok(option)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::DhcpOption;
+        use super::PortForward;
+        use super::PortProtocol;
+
+        #[test]
+        fn parse_port_forward() {
+            for (s, expected) in [
+                ("tcp:2222:22", (PortProtocol::Tcp, None, 2222, 22)),
+                ("udp:53", (PortProtocol::Udp, None, 53, 53)),
+                (
+                    "tcp:127.0.0.1:8080:80",
+                    (PortProtocol::Tcp, Some([127, 0, 0, 1]), 8080, 80),
+                ),
+            ] {
+                let rule: PortForward = s.parse().unwrap();
+                assert_eq!(
+                    (
+                        rule.protocol,
+                        rule.host_address,
+                        rule.host_port,
+                        rule.guest_port
+                    ),
+                    expected
+                );
+                assert_eq!(rule.to_string().parse::<PortForward>().unwrap(), rule);
+            }
+            for s in ["", "tcp", "icmp:1", "tcp:1:2:3", "udp:70000", "tcp:1.2.3:4"] {
+                assert!(s.parse::<PortForward>().is_err(), "{s}");
+            }
+        }
+
+        #[test]
+        fn parse_dhcp_option() {
+            for (s, expected) in [
+                (
+                    "lease=00-15-5d-00-00-01=10.0.0.5",
+                    DhcpOption::StaticLease {
+                        mac_address: [0x00, 0x15, 0x5d, 0x00, 0x00, 0x01].into(),
+                        ip_address: [10, 0, 0, 5],
+                    },
+                ),
+                ("dns=1.1.1.1", DhcpOption::DnsServer([1, 1, 1, 1])),
+                ("ntp=10.0.0.1", DhcpOption::NtpServer([10, 0, 0, 1])),
+                (
+                    "boot-server=10.0.0.1",
+                    DhcpOption::BootServer("10.0.0.1".into()),
+                ),
+                (
+                    "boot-file=pxelinux.0",
+                    DhcpOption::BootFile("pxelinux.0".into()),
+                ),
+            ] {
+                let option: DhcpOption = s.parse().unwrap();
+                assert_eq!(option, expected);
+                assert_eq!(option.to_string().parse::<DhcpOption>().unwrap(), option);
+            }
+            for s in [
+                "",
+                "dns",
+                "dns=",
+                "dns=1.2.3",
+                "lease=00-15-5d-00-00-01",
+                "lease=00-15-5d-00-00=10.0.0.5",
+                "boot-file=",
+                "tftp=10.0.0.1",
+            ] {
+                assert!(s.parse::<DhcpOption>().is_err(), "{s}");
+            }
+        }
+    }
 }
 
 /// Windows vmswitch DirectIO backend.
@@ -85,9 +360,194 @@ pub struct TapHandle {
         ///
         /// FUTURE: change this to a pre-opened `File`.
         pub name: String,
+        /// The number of queues to open. If greater than one, the device is
+        /// opened in multi-queue mode.
+        pub queue_count: u16,
     }
 
     impl ResourceId<NetEndpointHandleKind> for TapHandle {
         const ID: &'static str = "tap";
     }
 }
+
+/// vhost-user backend.
+#[cfg(unix)]
+pub mod vhost_user {
+    use mesh::MeshPayload;
+    use std::os::unix::net::UnixStream;
+    use vm_resource::kind::NetEndpointHandleKind;
+    use vm_resource::ResourceId;
+
+    /// A handle to a vhost-user network backend.
+    #[derive(MeshPayload)]
+    pub struct VhostUserHandle {
+        /// The socket connected to the backend.
+        pub socket: UnixStream,
+        /// The maximum number of queue pairs to use, if the backend supports
+        /// multiple queues.
+        pub max_queues: u16,
+    }
+
+    impl ResourceId<NetEndpointHandleKind> for VhostUserHandle {
+        const ID: &'static str = "vhost_user";
+    }
+}
+
+/// Linux AF_XDP backend.
+pub mod xdp {
+    use mesh::MeshPayload;
+    use vm_resource::kind::NetEndpointHandleKind;
+    use vm_resource::ResourceId;
+
+    /// A handle to a host network interface, accessed via AF_XDP sockets.
+    #[derive(MeshPayload)]
+    pub struct XdpHandle {
+        /// The name of the network interface.
+        pub interface: String,
+        /// The number of interface queues to bind to, starting at queue 0.
+        pub queue_count: u16,
+    }
+
+    impl ResourceId<NetEndpointHandleKind> for XdpHandle {
+        const ID: &'static str = "xdp";
+    }
+}
+
+/// Packet capture wrapper for another backend.
+pub mod packet_capture {
+    use mesh::rpc::FailableRpc;
+    use mesh::MeshPayload;
+    use std::fs::File;
+    use vm_resource::kind::NetEndpointHandleKind;
+    use vm_resource::Resource;
+    use vm_resource::ResourceId;
+
+    /// A handle to an endpoint that can capture the packets sent and received
+    /// by an inner endpoint to a pcapng file.
+    #[derive(MeshPayload)]
+    pub struct PacketCaptureHandle {
+        /// The name used to identify the endpoint in traces.
+        pub name: String,
+        /// The inner endpoint.
+        pub endpoint: Resource<NetEndpointHandleKind>,
+        /// If set, capture to this file from startup.
+        pub file: Option<File>,
+        /// Channel for starting and stopping capture at runtime.
+        pub requests: Option<mesh::Receiver<PacketCaptureRequest>>,
+    }
+
+    impl ResourceId<NetEndpointHandleKind> for PacketCaptureHandle {
+        const ID: &'static str = "packet_capture";
+    }
+
+    /// A request to a running packet capture endpoint.
+    #[derive(MeshPayload)]
+    pub enum PacketCaptureRequest {
+        /// Starts capturing to the file, replacing any capture in progress.
+        Start(FailableRpc<File, ()>),
+        /// Stops capturing.
+        Stop(FailableRpc<(), ()>),
+    }
+}
+
+/// Fault injecting wrapper for another backend.
+pub mod fault {
+    use mesh::MeshPayload;
+    use vm_resource::kind::NetEndpointHandleKind;
+    use vm_resource::Resource;
+    use vm_resource::ResourceId;
+
+    /// A handle to an endpoint that injects faults into an inner endpoint.
+    ///
+    /// This is useful for testing how the guest handles unreliable networks.
+    #[derive(MeshPayload)]
+    pub struct FaultHandle {
+        /// The inner endpoint.
+        pub endpoint: Resource<NetEndpointHandleKind>,
+        /// The faults to inject initially.
+        pub faults: NetFaults,
+        /// Receives replacements for the injected faults while the VM is
+        /// running.
+        pub update: mesh::Receiver<NetFaults>,
+    }
+
+    impl ResourceId<NetEndpointHandleKind> for FaultHandle {
+        const ID: &'static str = "fault";
+    }
+
+    /// The faults injected by a [`FaultHandle`] endpoint.
+    #[derive(Debug, Copy, Clone, Default, PartialEq, Eq, MeshPayload)]
+    pub struct NetFaults {
+        /// Report the link as disconnected to the guest, and drop all
+        /// packets. Setting and then clearing this flaps the link.
+        pub link_down: bool,
+        /// Drop packets sent by the guest.
+        pub drop_tx: bool,
+        /// Drop packets received by the guest.
+        pub drop_rx: bool,
+    }
+}
+
+/// Bandwidth limiting wrapper for another backend.
+pub mod rate_limit {
+    use mesh::MeshPayload;
+    use std::fmt;
+    use std::str::FromStr;
+    use thiserror::Error;
+    use vm_resource::kind::NetEndpointHandleKind;
+    use vm_resource::Resource;
+    use vm_resource::ResourceId;
+
+    /// A handle to an endpoint that limits the bandwidth of an inner endpoint.
+    ///
+    /// The limits can be changed at runtime via the endpoint's `egress` and
+    /// `ingress` inspect nodes.
+    #[derive(MeshPayload)]
+    pub struct RateLimitHandle {
+        /// The inner endpoint.
+        pub endpoint: Resource<NetEndpointHandleKind>,
+        /// The limit for packets sent by the guest.
+        pub egress: RateLimit,
+        /// The limit for packets received by the guest.
+        pub ingress: RateLimit,
+    }
+
+    impl ResourceId<NetEndpointHandleKind> for RateLimitHandle {
+        const ID: &'static str = "rate_limit";
+    }
+
+    /// A token bucket limit, where each token is a byte.
+    ///
+    /// Parsed from and formatted as `<rate>[,<burst>]`.
+    #[derive(Debug, Copy, Clone, Default, PartialEq, Eq, MeshPayload)]
+    pub struct RateLimit {
+        /// The sustained rate, in bytes per second. Zero means no limit.
+        pub rate: u64,
+        /// The number of bytes that can be sent in a burst. Zero means the
+        /// same as `rate`.
+        pub burst: u64,
+    }
+
+    /// An error returned when parsing a [`RateLimit`].
+    #[derive(Debug, Error)]
+    #[error("invalid rate limit, expected <bytes per second>[,<burst bytes>]")]
+    pub struct InvalidRateLimit;
+
+    impl FromStr for RateLimit {
+        type Err = InvalidRateLimit;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (rate, burst) = s.split_once(',').unwrap_or((s, "0"));
+            Ok(Self {
+                rate: rate.parse().map_err(|_| InvalidRateLimit)?,
+                burst: burst.parse().map_err(|_| InvalidRateLimit)?,
+            })
+        }
+    }
+
+    impl fmt::Display for RateLimit {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{},{}", self.rate, self.burst)
+        }
+    }
+}
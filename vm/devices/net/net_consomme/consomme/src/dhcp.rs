@@ -5,6 +5,7 @@
 use super::Client;
 use super::DropReason;
 use crate::ChecksumState;
+use crate::DhcpConfig;
 use crate::MIN_MTU;
 use smoltcp::phy::ChecksumCapabilities;
 use smoltcp::wire::DhcpMessageType;
@@ -25,21 +26,43 @@
 pub const DHCP_SERVER: u16 = 67;
 pub const DHCP_CLIENT: u16 = 68;
 
+/// Offset of the boot file name field in a BOOTP packet.
+const BOOTP_FILE_OFFSET: usize = 108;
+/// Length of the boot file name field, including the null terminator.
+const BOOTP_FILE_LEN: usize = 128;
+/// Offset of the options, following the magic cookie.
+const DHCP_OPTIONS_OFFSET: usize = 240;
+
+const DHCP_OPTION_PAD: u8 = 0;
+const DHCP_OPTION_NTP_SERVERS: u8 = 42;
+const DHCP_OPTION_TFTP_SERVER_NAME: u8 = 66;
+const DHCP_OPTION_BOOTFILE_NAME: u8 = 67;
+const DHCP_OPTION_END: u8 = 255;
+
 impl<T: Client> Access<'_, T> {
     pub(crate) fn handle_dhcp(&mut self, payload: &[u8]) -> Result<(), DropReason> {
         let dhcp_packet = DhcpPacket::new_checked(payload)?;
         let dhcp_req = DhcpRepr::parse(&dhcp_packet)?;
+        let client_ip = self
+            .inner
+            .state
+            .dhcp
+            .static_leases
+            .iter()
+            .find(|&&(mac, _)| mac == dhcp_req.client_hardware_address)
+            .map_or(self.inner.state.client_ip, |&(_, ip)| ip);
+
         let your_ip;
         let message_type;
         match dhcp_req.message_type {
             DhcpMessageType::Discover => {
-                your_ip = Some(self.inner.state.client_ip);
+                your_ip = Some(client_ip);
                 message_type = DhcpMessageType::Offer;
             }
             DhcpMessageType::Request => {
                 your_ip = match dhcp_req.requested_ip {
-                    Some(addr) if addr == self.inner.state.client_ip => Some(addr),
-                    None => Some(self.inner.state.client_ip),
+                    Some(addr) if addr == client_ip => Some(addr),
+                    None => Some(client_ip),
                     Some(_) => None,
                 };
                 message_type = DhcpMessageType::Ack;
@@ -47,6 +70,12 @@ pub(crate) fn handle_dhcp(&mut self, payload: &[u8]) -> Result<(), DropReason> {
             ty => return Err(DropReason::UnsupportedDhcp(ty)),
         }
 
+        if your_ip.is_some() && message_type == DhcpMessageType::Ack {
+            // Deliver subsequent traffic to the address the client just
+            // acquired, which differs from the default for static leases.
+            self.inner.state.client_ip = client_ip;
+        }
+
         let dns_servers = if self.inner.state.nameservers.is_empty() {
             None
         } else {
@@ -57,14 +86,21 @@ pub(crate) fn handle_dhcp(&mut self, payload: &[u8]) -> Result<(), DropReason> {
             Some(dns_servers)
         };
 
-        let resp_dhcp = if let Some(your_ip) = your_ip {
-            DhcpRepr {
+        let dhcp_config = &self.inner.state.dhcp;
+        let next_server = dhcp_config
+            .boot_server
+            .as_deref()
+            .and_then(|name| name.parse::<std::net::Ipv4Addr>().ok())
+            .map_or(self.inner.state.gateway_ip, Into::into);
+
+        let (resp_dhcp, extra_options) = if let Some(your_ip) = your_ip {
+            let resp_dhcp = DhcpRepr {
                 message_type,
                 transaction_id: dhcp_req.transaction_id,
                 client_hardware_address: dhcp_req.client_hardware_address,
                 client_ip: Ipv4Address::UNSPECIFIED,
                 your_ip,
-                server_ip: self.inner.state.gateway_ip,
+                server_ip: next_server,
                 router: Some(self.inner.state.gateway_ip),
                 subnet_mask: Some(self.inner.state.net_mask),
                 relay_agent_ip: Ipv4Address::UNSPECIFIED,
@@ -76,9 +112,10 @@ pub(crate) fn handle_dhcp(&mut self, payload: &[u8]) -> Result<(), DropReason> {
                 dns_servers,
                 max_size: None,
                 lease_duration: Some(86400),
-            }
+            };
+            (resp_dhcp, extra_options(dhcp_config))
         } else {
-            DhcpRepr {
+            let resp_dhcp = DhcpRepr {
                 message_type: DhcpMessageType::Nak,
                 transaction_id: dhcp_req.transaction_id,
                 client_hardware_address: dhcp_req.client_hardware_address,
@@ -96,8 +133,14 @@ pub(crate) fn handle_dhcp(&mut self, payload: &[u8]) -> Result<(), DropReason> {
                 dns_servers: None,
                 max_size: None,
                 lease_duration: None,
-            }
+            };
+            (resp_dhcp, Vec::new())
         };
+        let resp_dhcp_len = resp_dhcp.buffer_len() + extra_options.len();
+        let boot_file = dhcp_config
+            .boot_file
+            .as_deref()
+            .filter(|_| your_ip.is_some());
 
         let resp_udp = UdpRepr {
             src_port: DHCP_SERVER,
@@ -107,7 +150,7 @@ pub(crate) fn handle_dhcp(&mut self, payload: &[u8]) -> Result<(), DropReason> {
             src_addr: self.inner.state.gateway_ip,
             dst_addr: Ipv4Address::BROADCAST,
             protocol: IpProtocol::Udp,
-            payload_len: resp_udp.header_len() + resp_dhcp.buffer_len(),
+            payload_len: resp_udp.header_len() + resp_dhcp_len,
             hop_limit: 64,
         };
         let resp_eth = EthernetRepr {
@@ -126,10 +169,19 @@ pub(crate) fn handle_dhcp(&mut self, payload: &[u8]) -> Result<(), DropReason> {
             &mut resp_udp_packet,
             &IpAddress::Ipv4(resp_ipv4.src_addr),
             &IpAddress::Ipv4(resp_ipv4.dst_addr),
-            resp_dhcp.buffer_len(),
+            resp_dhcp_len,
             |udp_payload| {
-                let mut resp_dhcp_packet = DhcpPacket::new_unchecked(udp_payload);
+                let mut resp_dhcp_packet = DhcpPacket::new_unchecked(&mut *udp_payload);
                 resp_dhcp.emit(&mut resp_dhcp_packet).unwrap();
+                append_options(udp_payload, &extra_options);
+                if let Some(boot_file) = boot_file {
+                    // Legacy PXE clients read the boot file from the BOOTP
+                    // header rather than from option 67.
+                    if boot_file.len() < BOOTP_FILE_LEN {
+                        udp_payload[BOOTP_FILE_OFFSET..][..boot_file.len()]
+                            .copy_from_slice(boot_file.as_bytes());
+                    }
+                }
             },
             &ChecksumCapabilities::default(),
         );
@@ -138,9 +190,54 @@ pub(crate) fn handle_dhcp(&mut self, payload: &[u8]) -> Result<(), DropReason> {
             &resp_buffer[..resp_eth.buffer_len()
                 + resp_ipv4.buffer_len()
                 + resp_udp.header_len()
-                + resp_dhcp.buffer_len()],
+                + resp_dhcp_len],
             &ChecksumState::IPV4_ONLY,
         );
         Ok(())
     }
 }
+
+/// Encodes the options that smoltcp's [`DhcpRepr`] cannot represent.
+fn extra_options(config: &DhcpConfig) -> Vec<u8> {
+    fn push(options: &mut Vec<u8>, kind: u8, data: &[u8]) {
+        let data = &data[..data.len().min(u8::MAX.into())];
+        options.push(kind);
+        options.push(data.len() as u8);
+        options.extend_from_slice(data);
+    }
+
+    let mut options = Vec::new();
+    if !config.ntp_servers.is_empty() {
+        let servers = config
+            .ntp_servers
+            .iter()
+            .flat_map(|addr| addr.0)
+            .collect::<Vec<_>>();
+        push(&mut options, DHCP_OPTION_NTP_SERVERS, &servers);
+    }
+    if let Some(name) = &config.boot_server {
+        push(&mut options, DHCP_OPTION_TFTP_SERVER_NAME, name.as_bytes());
+    }
+    if let Some(name) = &config.boot_file {
+        push(&mut options, DHCP_OPTION_BOOTFILE_NAME, name.as_bytes());
+    }
+    options
+}
+
+/// Inserts `options` before the end option of the emitted DHCP packet.
+/// `packet` must have room for the additional options.
+fn append_options(packet: &mut [u8], options: &[u8]) {
+    if options.is_empty() {
+        return;
+    }
+    let mut offset = DHCP_OPTIONS_OFFSET;
+    loop {
+        match packet[offset] {
+            DHCP_OPTION_END => break,
+            DHCP_OPTION_PAD => offset += 1,
+            _ => offset += 2 + packet[offset + 1] as usize,
+        }
+    }
+    packet[offset..][..options.len()].copy_from_slice(options);
+    packet[offset + options.len()] = DHCP_OPTION_END;
+}
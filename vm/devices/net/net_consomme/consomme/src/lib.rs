@@ -64,6 +64,7 @@ struct MessageBindPort {
     protocol: IpProtocol,
     address: Option<Ipv4Addr>,
     port: u16,
+    guest_port: u16,
 }
 
 enum ConsommeMessage {
@@ -73,6 +74,7 @@ enum ConsommeMessage {
 }
 
 /// Provide dynamic updates during runtime.
+#[derive(Clone)]
 pub struct ConsommeControl {
     send: mesh::Sender<ConsommeMessage>,
 }
@@ -85,18 +87,36 @@ pub async fn bind_port(
         ip_addr: Option<Ipv4Addr>,
         port: u16,
     ) -> Result<(), ConsommeMessageError> {
-        self.send
-            .call(
-                ConsommeMessage::BindPort,
-                MessageBindPort {
-                    protocol,
-                    address: ip_addr,
-                    port,
-                },
-            )
-            .await
-            .map_err(ConsommeMessageError::Mesh)?
-            .map_err(ConsommeMessageError::Network)
+        self.forward_port(protocol, ip_addr, port, port).await
+    }
+
+    /// Binds host port `port` and forwards incoming connections (for TCP) or
+    /// datagrams (for UDP) to `guest_port` on the guest.
+    ///
+    /// The request is sent immediately, but it is processed the next time the
+    /// instance is polled. The returned future completes when the request has
+    /// been processed, and it can be dropped if the result is not needed.
+    pub fn forward_port(
+        &self,
+        protocol: IpProtocol,
+        ip_addr: Option<Ipv4Addr>,
+        port: u16,
+        guest_port: u16,
+    ) -> impl std::future::Future<Output = Result<(), ConsommeMessageError>> {
+        let recv = self.send.call(
+            ConsommeMessage::BindPort,
+            MessageBindPort {
+                protocol,
+                address: ip_addr,
+                port,
+                guest_port,
+            },
+        );
+        async move {
+            recv.await
+                .map_err(ConsommeMessageError::Mesh)?
+                .map_err(ConsommeMessageError::Network)
+        }
     }
 
     /// Unbinds a port previously reserved with bind_port()
@@ -105,18 +125,32 @@ pub async fn unbind_port(
         protocol: IpProtocol,
         port: u16,
     ) -> Result<(), ConsommeMessageError> {
-        self.send
-            .call(
-                ConsommeMessage::UnbindPort,
-                MessageBindPort {
-                    protocol,
-                    address: None,
-                    port,
-                },
-            )
-            .await
-            .map_err(ConsommeMessageError::Mesh)?
-            .map_err(ConsommeMessageError::Network)
+        self.unforward_port(protocol, port).await
+    }
+
+    /// Unbinds host port `port`, previously bound with
+    /// [`Self::forward_port`].
+    ///
+    /// Like [`Self::forward_port`], the request is sent immediately.
+    pub fn unforward_port(
+        &self,
+        protocol: IpProtocol,
+        port: u16,
+    ) -> impl std::future::Future<Output = Result<(), ConsommeMessageError>> {
+        let recv = self.send.call(
+            ConsommeMessage::UnbindPort,
+            MessageBindPort {
+                protocol,
+                address: None,
+                port,
+                guest_port: 0,
+            },
+        );
+        async move {
+            recv.await
+                .map_err(ConsommeMessageError::Mesh)?
+                .map_err(ConsommeMessageError::Network)
+        }
     }
 
     /// Updates dynamic network state
@@ -158,10 +192,27 @@ pub struct ConsommeState {
     pub client_mac: EthernetAddress,
     /// Current list of DNS resolvers.
     pub nameservers: Vec<Ipv4Address>,
+    /// Additional options for the DHCP server.
+    pub dhcp: DhcpConfig,
     /// Buffer for packet processing
     buffer: Box<[u8]>,
 }
 
+/// Additional options for the DHCP server.
+#[derive(Debug, Clone, Default)]
+pub struct DhcpConfig {
+    /// Addresses to assign to specific clients instead of `client_ip`, by MAC
+    /// address.
+    pub static_leases: Vec<(EthernetAddress, Ipv4Address)>,
+    /// NTP servers to offer (option 42).
+    pub ntp_servers: Vec<Ipv4Address>,
+    /// The TFTP boot server name (option 66). If this is an IPv4 address, it
+    /// is also offered as the next server address.
+    pub boot_server: Option<String>,
+    /// The boot file name (option 67).
+    pub boot_file: Option<String>,
+}
+
 /// An error indicating that the CIDR is invalid.
 #[derive(Debug, Error)]
 #[error("invalid CIDR")]
@@ -181,6 +232,7 @@ pub fn new() -> Result<Self, Error> {
             client_mac: EthernetAddress([0x0, 0x0, 0x0, 0x0, 0x1, 0x0]),
             net_mask: Ipv4Address::new(255, 255, 255, 0),
             nameservers,
+            dhcp: DhcpConfig::default(),
             buffer: Box::new([0; 65535]),
         })
     }
@@ -419,8 +471,16 @@ fn process_message(&mut self, message: ConsommeMessage) {
         match message {
             ConsommeMessage::BindPort(rpc) => {
                 rpc.handle_sync(|bind_message| match bind_message.protocol {
-                    IpProtocol::Tcp => self.bind_tcp_port(bind_message.address, bind_message.port),
-                    IpProtocol::Udp => self.bind_udp_port(bind_message.address, bind_message.port),
+                    IpProtocol::Tcp => self.bind_tcp_port(
+                        bind_message.address,
+                        bind_message.port,
+                        bind_message.guest_port,
+                    ),
+                    IpProtocol::Udp => self.bind_udp_port(
+                        bind_message.address,
+                        bind_message.port,
+                        bind_message.guest_port,
+                    ),
                     p => unimplemented!("Listen not supported for protocol {}", p),
                 });
             }
@@ -82,8 +82,8 @@ fn inspect(&self, req: inspect::Request<'_>) {
                 conn,
             );
         }
-        for port in self.listeners.keys() {
-            resp.field("listening port", port);
+        for (port, listener) in &self.listeners {
+            resp.field(&format!("listener:{}", port), listener);
         }
     }
 }
@@ -144,6 +144,8 @@ struct TcpConnection {
 struct TcpListener {
     #[inspect(skip)]
     socket: PolledSocket<Socket>,
+    /// The guest port that accepted connections are forwarded to.
+    guest_port: u16,
 }
 
 #[derive(Debug, PartialEq, Eq, Inspect)]
@@ -202,6 +204,7 @@ pub(crate) fn poll_tcp(&mut self, cx: &mut Context<'_>) {
             .retain(|port, listener| match listener.poll_listener(cx) {
                 Ok(result) => {
                     if let Some((socket, mut other_addr)) = result {
+                        let guest_port = listener.guest_port;
                         // Check for loopback requests and replace the dest port.
                         // This supports a guest owning both the sending and receiving ports.
                         if other_addr.ip.is_loopback() {
@@ -219,7 +222,7 @@ pub(crate) fn poll_tcp(&mut self, cx: &mut Context<'_>) {
 
                         let ft = FourTuple { dst: other_addr, src: SocketAddress {
                             ip: self.inner.state.client_ip,
-                            port: *port,
+                            port: guest_port,
                         } };
 
                         match self.inner.tcp.connections.entry(ft) {
@@ -350,6 +353,7 @@ pub(crate) fn bind_tcp_port(
         &mut self,
         ip_addr: Option<Ipv4Addr>,
         port: u16,
+        guest_port: u16,
     ) -> Result<(), DropReason> {
         match self.inner.tcp.listeners.entry(port) {
             hash_map::Entry::Occupied(_) => {
@@ -372,7 +376,7 @@ pub(crate) fn bind_tcp_port(
                     state: &mut self.inner.state,
                 };
 
-                let listener = TcpListener::new(&mut sender)?;
+                let listener = TcpListener::new(&mut sender, guest_port)?;
                 e.insert(listener);
             }
         }
@@ -1135,7 +1139,7 @@ fn handle_packet(
 }
 
 impl TcpListener {
-    pub fn new(sender: &mut Sender<'_, impl Client>) -> Result<Self, DropReason> {
+    pub fn new(sender: &mut Sender<'_, impl Client>, guest_port: u16) -> Result<Self, DropReason> {
         let socket =
             Socket::new(Domain::IPV4, Type::STREAM, Some(Protocol::TCP)).map_err(DropReason::Io)?;
 
@@ -1155,7 +1159,7 @@ pub fn new(sender: &mut Sender<'_, impl Client>) -> Result<Self, DropReason> {
             );
             return Err(DropReason::Io(err));
         }
-        Ok(Self { socket })
+        Ok(Self { socket, guest_port })
     }
 
     fn poll_listener(
@@ -64,6 +64,9 @@ struct UdpConnection {
     socket: Option<PolledSocket<UdpSocket>>,
     #[inspect(display)]
     guest_mac: EthernetAddress,
+    /// The host port, if this connection was created by forwarding a host
+    /// port to the guest.
+    host_port: Option<u16>,
     stats: Stats,
     #[inspect(mut)]
     recycle: bool,
@@ -198,7 +201,7 @@ pub(crate) fn handle_udp(
             port: udp.src_port,
         };
 
-        let conn = self.get_or_insert(guest_addr, None, Some(frame.src_addr))?;
+        let conn = self.get_or_insert(guest_addr, Some(frame.src_addr))?;
         match conn.socket.as_mut().unwrap().get().send_to(
             udp_packet.payload(),
             (Ipv4Addr::from(addresses.dst_addr), udp.dst_port),
@@ -221,20 +224,19 @@ pub(crate) fn handle_udp(
     fn get_or_insert(
         &mut self,
         guest_addr: SocketAddress,
-        host_addr: Option<Ipv4Addr>,
         guest_mac: Option<EthernetAddress>,
     ) -> Result<&mut UdpConnection, DropReason> {
         let entry = self.inner.udp.connections.entry(guest_addr);
         match entry {
             hash_map::Entry::Occupied(conn) => Ok(conn.into_mut()),
             hash_map::Entry::Vacant(e) => {
-                let socket = UdpSocket::bind((host_addr.unwrap_or(Ipv4Addr::UNSPECIFIED), 0))
-                    .map_err(DropReason::Io)?;
+                let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).map_err(DropReason::Io)?;
                 let socket =
                     PolledSocket::new(self.client.driver(), socket).map_err(DropReason::Io)?;
                 let conn = UdpConnection {
                     socket: Some(socket),
                     guest_mac: guest_mac.unwrap_or(self.inner.state.client_mac),
+                    host_port: None,
                     stats: Default::default(),
                     recycle: false,
                 };
@@ -254,27 +256,67 @@ fn handle_gateway_udp(&mut self, udp: &UdpPacket<&[u8]>) -> Result<bool, DropRea
         }
     }
 
+    /// Forwards UDP port `port` on the host to `guest_port` on the guest.
+    ///
+    /// Datagrams sent by the guest from `guest_port` are sent from the host
+    /// port, so that replies to them reach the guest as well.
     pub(crate) fn bind_udp_port(
         &mut self,
         ip_addr: Option<Ipv4Addr>,
         port: u16,
+        guest_port: u16,
     ) -> Result<(), DropReason> {
+        if self
+            .inner
+            .udp
+            .connections
+            .values()
+            .any(|conn| conn.host_port == Some(port))
+        {
+            tracing::warn!(port, "Duplicate UDP bind for port");
+            return Ok(());
+        }
+        let socket = match UdpSocket::bind((ip_addr.unwrap_or(Ipv4Addr::UNSPECIFIED), port)) {
+            Ok(socket) => socket,
+            Err(err) => {
+                tracing::warn!(
+                    port,
+                    error = &err as &dyn std::error::Error,
+                    "socket bind error"
+                );
+                return Err(DropReason::Io(err));
+            }
+        };
+        let socket = PolledSocket::new(self.client.driver(), socket).map_err(DropReason::Io)?;
         let guest_addr = SocketAddress {
-            ip: ip_addr.unwrap_or(Ipv4Addr::UNSPECIFIED).into(),
-            port,
+            ip: self.inner.state.client_ip,
+            port: guest_port,
         };
-        let _ = self.get_or_insert(guest_addr, ip_addr, None)?;
+        // Replace any existing connection for the guest port, so that its
+        // outgoing traffic uses the forwarded host port.
+        self.inner.udp.connections.insert(
+            guest_addr,
+            UdpConnection {
+                socket: Some(socket),
+                guest_mac: self.inner.state.client_mac,
+                host_port: Some(port),
+                stats: Default::default(),
+                recycle: false,
+            },
+        );
         Ok(())
     }
 
     pub(crate) fn unbind_udp_port(&mut self, port: u16) -> Result<(), DropReason> {
-        let guest_addr = SocketAddress {
-            ip: Ipv4Addr::UNSPECIFIED.into(),
-            port,
-        };
-        match self.inner.udp.connections.remove(&guest_addr) {
-            Some(_) => Ok(()),
-            None => Err(DropReason::PortNotBound),
+        let len = self.inner.udp.connections.len();
+        self.inner
+            .udp
+            .connections
+            .retain(|_, conn| conn.host_port != Some(port));
+        if self.inner.udp.connections.len() < len {
+            Ok(())
+        } else {
+            Err(DropReason::PortNotBound)
         }
     }
 }
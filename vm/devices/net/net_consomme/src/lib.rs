@@ -8,6 +8,7 @@
 use consomme::Consomme;
 use consomme::ConsommeControl;
 use consomme::ConsommeState;
+use futures::StreamExt;
 use inspect::Inspect;
 use inspect::InspectMut;
 use inspect_counters::Counter;
@@ -22,8 +23,12 @@
 use net_backend::TxOffloadSupport;
 use net_backend::TxSegment;
 use net_backend::TxSegmentType;
+use net_backend_resources::consomme::ConsommeRequest;
+use net_backend_resources::consomme::PortForward;
+use net_backend_resources::consomme::PortProtocol;
 use pal_async::driver::Driver;
 use parking_lot::Mutex;
+use smoltcp::wire::IpProtocol;
 use std::collections::VecDeque;
 use std::sync::Arc;
 use std::task::Context;
@@ -46,6 +51,8 @@ pub fn new_with_state(state: ConsommeState) -> Self {
         }
     }
 
+    /// Creates an endpoint whose port forwarding rules can be changed at
+    /// runtime with the returned control object.
     pub fn new_dynamic(state: ConsommeState) -> (Self, ConsommeControl) {
         let (consomme, control) = Consomme::new_dynamic(state);
         (
@@ -65,6 +72,42 @@ fn inspect_mut(&mut self, req: inspect::Request<'_>) {
     }
 }
 
+/// Handles management requests for an endpoint created with
+/// [`ConsommeEndpoint::new_dynamic`], until the request channel is closed.
+pub async fn handle_requests(
+    control: ConsommeControl,
+    mut requests: mesh::Receiver<ConsommeRequest>,
+) {
+    while let Some(req) = requests.next().await {
+        match req {
+            ConsommeRequest::ForwardPort(rpc) => {
+                rpc.handle_failable(|rule: PortForward| {
+                    control.forward_port(
+                        protocol(rule.protocol),
+                        rule.host_address.map(Into::into),
+                        rule.host_port,
+                        rule.guest_port,
+                    )
+                })
+                .await
+            }
+            ConsommeRequest::UnforwardPort(rpc) => {
+                rpc.handle_failable(|(protocol, port)| {
+                    control.unforward_port(crate::protocol(protocol), port)
+                })
+                .await
+            }
+        }
+    }
+}
+
+fn protocol(protocol: PortProtocol) -> IpProtocol {
+    match protocol {
+        PortProtocol::Tcp => IpProtocol::Tcp,
+        PortProtocol::Udp => IpProtocol::Udp,
+    }
+}
+
 #[async_trait]
 impl net_backend::Endpoint for ConsommeEndpoint {
     fn endpoint_type(&self) -> &'static str {
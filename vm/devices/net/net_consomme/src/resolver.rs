@@ -6,6 +6,10 @@
 use net_backend::resolve::ResolveEndpointParams;
 use net_backend::resolve::ResolvedEndpoint;
 use net_backend_resources::consomme::ConsommeHandle;
+use net_backend_resources::consomme::DhcpOption;
+use pal_async::task::Spawn;
+use smoltcp::wire::EthernetAddress;
+use smoltcp::wire::Ipv4Address;
 use thiserror::Error;
 use vm_resource::declare_static_resolver;
 use vm_resource::kind::NetEndpointHandleKind;
@@ -33,7 +37,7 @@ impl ResolveResource<NetEndpointHandleKind, ConsommeHandle> for ConsommeResolver
     fn resolve(
         &self,
         resource: ConsommeHandle,
-        input: ResolveEndpointParams,
+        input: ResolveEndpointParams<'_>,
     ) -> Result<Self::Output, Self::Error> {
         let mut state = ConsommeState::new().map_err(ResolveConsommeError::Consomme)?;
         state.client_mac.0 = input.mac_address.to_bytes();
@@ -42,7 +46,46 @@ fn resolve(
                 .set_cidr(cidr)
                 .map_err(ResolveConsommeError::InvalidCidr)?;
         }
-        let endpoint = ConsommeEndpoint::new_with_state(state);
+        let mut nameservers = Vec::new();
+        for option in &resource.dhcp {
+            match option {
+                DhcpOption::StaticLease {
+                    mac_address,
+                    ip_address,
+                } => state.dhcp.static_leases.push((
+                    EthernetAddress(mac_address.to_bytes()),
+                    Ipv4Address(*ip_address),
+                )),
+                DhcpOption::DnsServer(addr) => nameservers.push(Ipv4Address(*addr)),
+                DhcpOption::NtpServer(addr) => state.dhcp.ntp_servers.push(Ipv4Address(*addr)),
+                DhcpOption::BootServer(name) => state.dhcp.boot_server = Some(name.clone()),
+                DhcpOption::BootFile(name) => state.dhcp.boot_file = Some(name.clone()),
+            }
+        }
+        if !nameservers.is_empty() {
+            state.nameservers = nameservers;
+        }
+        let (endpoint, control) = ConsommeEndpoint::new_dynamic(state);
+        for rule in &resource.ports {
+            // The rules are applied when the endpoint first starts running.
+            // Bind failures are logged by consomme.
+            drop(control.forward_port(
+                crate::protocol(rule.protocol),
+                rule.host_address.map(Into::into),
+                rule.host_port,
+                rule.guest_port,
+            ));
+        }
+        if let Some(requests) = resource.requests {
+            input
+                .driver_source
+                .simple()
+                .spawn(
+                    "consomme-requests",
+                    crate::handle_requests(control, requests),
+                )
+                .detach();
+        }
         Ok(endpoint.into())
     }
 }
@@ -25,7 +25,7 @@ impl ResolveResource<NetEndpointHandleKind, WindowsDirectIoHandle> for DioResolv
     fn resolve(
         &self,
         resource: WindowsDirectIoHandle,
-        input: ResolveEndpointParams,
+        input: ResolveEndpointParams<'_>,
     ) -> Result<Self::Output, Self::Error> {
         let mut nic =
             vmswitch::dio::DioNic::new(Guid::new_random(), "nic", "nic", input.mac_address.into())
@@ -3,6 +3,8 @@
 
 //! `pcapng` compatible packet capture endpoint implementation.
 
+pub mod resolver;
+
 use async_trait::async_trait;
 use futures::lock::Mutex;
 use futures::FutureExt;
@@ -25,6 +27,7 @@
 use net_backend::TxId;
 use net_backend::TxOffloadSupport;
 use net_backend::TxSegment;
+use net_backend_resources::packet_capture::PacketCaptureRequest;
 use pcap_file::pcapng::blocks::enhanced_packet::EnhancedPacketBlock;
 use pcap_file::pcapng::blocks::interface_description::InterfaceDescriptionBlock;
 use pcap_file::pcapng::PcapNgWriter;
@@ -32,6 +35,7 @@
 use pcap_file::PcapError;
 use pcap_file::PcapResult;
 use std::borrow::Cow;
+use std::fs::File;
 use std::io::Write;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicUsize;
@@ -43,6 +47,9 @@
 use std::time::SystemTime;
 use std::time::UNIX_EPOCH;
 
+/// The default maximum number of bytes captured from each packet.
+pub const DEFAULT_SNAPLEN: u32 = 65535;
+
 /// Defines packet capture operations.
 #[derive(Debug, PartialEq, mesh::MeshPayload)]
 pub enum PacketCaptureOperation {
@@ -191,7 +198,49 @@ pub struct PacketCaptureEndpoint {
 
 impl InspectMut for PacketCaptureEndpoint {
     fn inspect_mut(&mut self, req: inspect::Request<'_>) {
-        self.current_mut().inspect_mut(req)
+        req.respond()
+            .merge(self.current_mut())
+            .field("pcap_enabled", self.pcap.enabled.load(Ordering::Relaxed));
+    }
+}
+
+/// Handles requests to start and stop capturing, until the request channel is
+/// closed.
+pub async fn handle_requests(
+    control: PacketCaptureEndpointControl,
+    mut requests: mesh::Receiver<PacketCaptureRequest>,
+) {
+    let control = &control;
+    while let Some(req) = requests.next().await {
+        match req {
+            PacketCaptureRequest::Start(rpc) => {
+                rpc.handle_failable(|file| async move {
+                    control
+                        .packet_capture(PacketCaptureParams {
+                            operation: PacketCaptureOperation::Start,
+                            op_data: Some(OperationData::OpStartData(StartData {
+                                snaplen: DEFAULT_SNAPLEN,
+                                writers: vec![file],
+                            })),
+                        })
+                        .await?;
+                    anyhow::Ok(())
+                })
+                .await
+            }
+            PacketCaptureRequest::Stop(rpc) => {
+                rpc.handle_failable(|()| async move {
+                    control
+                        .packet_capture(PacketCaptureParams::<File> {
+                            operation: PacketCaptureOperation::Stop,
+                            op_data: None,
+                        })
+                        .await?;
+                    anyhow::Ok(())
+                })
+                .await
+            }
+        }
     }
 }
 
@@ -214,6 +263,16 @@ pub fn new(endpoint: Box<dyn Endpoint>, id: String) -> (Self, PacketCaptureEndpo
         )
     }
 
+    /// Starts capturing packets to `writer` immediately, without waiting for
+    /// a request from [`PacketCaptureEndpointControl`].
+    ///
+    /// This must be called before the queues are created to take effect.
+    pub fn start_capture<W: Write + Send + Sync + 'static>(&mut self, snaplen: u32, writer: W) {
+        tracing::info!(id = self.id, "starting trace");
+        self.pcap
+            .update(PacketCaptureOptions::new_with_start(snaplen, writer));
+    }
+
     fn current(&self) -> &dyn Endpoint {
         self.endpoint.as_ref()
     }
@@ -313,28 +372,16 @@ enum Message {
                     let options = rpc.0;
                     let result = async {
                         let id = &self.id;
-                        let start = match options.operation {
+                        match options.operation {
                             PacketCaptureOperation::Start => {
                                 tracing::info!(id, "starting trace");
-                                true
                             }
                             PacketCaptureOperation::Stop => {
                                 tracing::info!(id, "stopping trace");
-                                false
                             }
                             _ => Err(anyhow::anyhow!("Unexpected packet capture option {id}"))?,
                         };
-
-                        // Keep the lock until all values are being set to make the update atomic.
-                        let mut pcap_writer = self.pcap.pcap_writer.lock();
-                        let restart_required = start != self.pcap.enabled.load(Ordering::Relaxed);
-                        self.pcap.snaplen.store(options.snaplen, Ordering::Relaxed);
-                        self.pcap
-                            .interface_descriptor_written
-                            .store(false, Ordering::Relaxed);
-                        self.pcap.enabled.store(start, Ordering::Relaxed);
-                        *pcap_writer = options.writer;
-                        anyhow::Ok(restart_required)
+                        anyhow::Ok(self.pcap.update(options))
                     }
                     .await;
                     let (result, restart_required) = match result {
@@ -370,13 +417,28 @@ impl Pcap {
     fn new(endpoint_control: Arc<mesh::Sender<PacketCaptureEndpointCommand>>) -> Self {
         Self {
             enabled: AtomicBool::new(false),
-            snaplen: AtomicUsize::new(65535),
+            snaplen: AtomicUsize::new(DEFAULT_SNAPLEN as usize),
             pcap_writer: parking_lot::Mutex::new(None),
             interface_descriptor_written: AtomicBool::new(false),
             endpoint_control,
         }
     }
 
+    /// Applies new capture options, returning whether the queues must be
+    /// restarted to start or stop capturing.
+    fn update(&self, options: PacketCaptureOptions) -> bool {
+        let start = options.operation == PacketCaptureOperation::Start;
+        // Keep the lock until all values are being set to make the update atomic.
+        let mut pcap_writer = self.pcap_writer.lock();
+        let restart_required = start != self.enabled.load(Ordering::Relaxed);
+        self.snaplen.store(options.snaplen, Ordering::Relaxed);
+        self.interface_descriptor_written
+            .store(false, Ordering::Relaxed);
+        self.enabled.store(start, Ordering::Relaxed);
+        *pcap_writer = options.writer;
+        restart_required
+    }
+
     fn write_packet(
         &self,
         buf: &[u8],
@@ -480,7 +542,9 @@ fn rx_poll(&mut self, packets: &mut [RxId]) -> anyhow::Result<usize> {
                         }
 
                         let copy_length = std::cmp::min(buf.len() - len, segment.len as usize);
-                        let _ = self.mem.read_at(segment.gpa, &mut buf[len..]);
+                        let _ = self
+                            .mem
+                            .read_at(segment.gpa, &mut buf[len..len + copy_length]);
                         len += copy_length;
                     }
 
@@ -521,7 +585,9 @@ fn tx_avail(&mut self, segments: &[TxSegment]) -> anyhow::Result<(bool, usize)>
                     }
 
                     let copy_length = std::cmp::min(buf.len() - len, segment.len as usize);
-                    let _ = self.mem.read_at(segment.gpa, &mut buf[len..]);
+                    let _ = self
+                        .mem
+                        .read_at(segment.gpa, &mut buf[len..len + copy_length]);
                     len += copy_length;
                 }
 
@@ -0,0 +1,55 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resolver for packet capture endpoints.
+
+use crate::PacketCaptureEndpoint;
+use crate::DEFAULT_SNAPLEN;
+use async_trait::async_trait;
+use net_backend::resolve::ResolveEndpointParams;
+use net_backend::resolve::ResolvedEndpoint;
+use net_backend_resources::packet_capture::PacketCaptureHandle;
+use pal_async::task::Spawn;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::NetEndpointHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::ResolveError;
+use vm_resource::ResourceResolver;
+
+/// A resolver for [`PacketCaptureHandle`].
+pub struct PacketCaptureResolver;
+
+declare_static_async_resolver! {
+    PacketCaptureResolver,
+    (NetEndpointHandleKind, PacketCaptureHandle),
+}
+
+#[async_trait]
+impl AsyncResolveResource<NetEndpointHandleKind, PacketCaptureHandle> for PacketCaptureResolver {
+    type Output = ResolvedEndpoint;
+    type Error = ResolveError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        resource: PacketCaptureHandle,
+        input: ResolveEndpointParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let driver_source = input.driver_source;
+        let inner: ResolvedEndpoint = resolver.resolve(resource.endpoint, input).await?;
+        let (mut endpoint, control) = PacketCaptureEndpoint::new(inner.0, resource.name);
+        if let Some(file) = resource.file {
+            endpoint.start_capture(DEFAULT_SNAPLEN, file);
+        }
+        if let Some(requests) = resource.requests {
+            driver_source
+                .simple()
+                .spawn(
+                    "packet-capture-requests",
+                    crate::handle_requests(control, requests),
+                )
+                .detach();
+        }
+        Ok(endpoint.into())
+    }
+}
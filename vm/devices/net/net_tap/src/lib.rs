@@ -7,6 +7,7 @@
 
 pub mod resolver;
 mod tap;
+mod vnet;
 
 use async_trait::async_trait;
 use futures::io::AsyncRead;
@@ -14,23 +15,30 @@
 use net_backend::linearize;
 use net_backend::BufferAccess;
 use net_backend::Endpoint;
+use net_backend::MultiQueueSupport;
 use net_backend::Queue;
 use net_backend::QueueConfig;
 use net_backend::RssConfig;
 use net_backend::RxId;
 use net_backend::RxMetadata;
 use net_backend::TxId;
+use net_backend::TxOffloadSupport;
 use net_backend::TxSegment;
+use net_backend::TxSegmentType;
 use pal_async::driver::Driver;
 use parking_lot::Mutex;
 use std::collections::VecDeque;
 use std::io::ErrorKind;
+use std::io::IoSlice;
 use std::io::Write;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
 use thiserror::Error;
+use vnet::VirtioNetHdr;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -38,23 +46,47 @@ pub enum Error {
     TapInterface(#[source] tap::Error),
 }
 
+/// The size of the indirection table reported to the guest. The TAP driver
+/// steers received packets to queues by its own flow hash, so the guest's
+/// table is not used.
+const INDIRECTION_TABLE_SIZE: u16 = 128;
+
+/// The largest packet that can be read from the TAP interface.
+const MAX_PACKET_SIZE: usize = 65535;
+
 /// An endpoint based on a TAP interface.
 pub struct TapEndpoint {
-    tap: Arc<Mutex<Option<tap::Tap>>>,
+    queues: Vec<Arc<Mutex<Option<tap::Tap>>>>,
+    vnet_hdr: bool,
 }
 
 impl TapEndpoint {
-    pub fn new(name: &str) -> Result<Self, Error> {
-        let tap = tap::Tap::new(name).map_err(Error::TapInterface)?;
+    /// Opens TAP interface `name` with `queue_count` queues.
+    ///
+    /// If `queue_count` is greater than one, the interface must have been
+    /// created in multi-queue mode (or must not exist yet).
+    pub fn new(name: &str, queue_count: u16) -> Result<Self, Error> {
+        let multi_queue = queue_count > 1;
+        let queues = (0..queue_count.max(1))
+            .map(|_| tap::Tap::new(name, multi_queue))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::TapInterface)?;
+        let vnet_hdr = queues[0].vnet_hdr();
         Ok(Self {
-            tap: Arc::new(Mutex::new(Some(tap))),
+            queues: queues
+                .into_iter()
+                .map(|tap| Arc::new(Mutex::new(Some(tap))))
+                .collect(),
+            vnet_hdr,
         })
     }
 }
 
 impl InspectMut for TapEndpoint {
     fn inspect_mut(&mut self, req: inspect::Request<'_>) {
-        req.respond();
+        req.respond()
+            .field("queues", self.queues.len())
+            .field("vnet_hdr", self.vnet_hdr);
     }
 }
 
@@ -66,34 +98,55 @@ fn endpoint_type(&self) -> &'static str {
 
     async fn get_queues(
         &mut self,
-        mut config: Vec<QueueConfig<'_>>,
+        config: Vec<QueueConfig<'_>>,
         _rss: Option<&RssConfig<'_>>,
         queues: &mut Vec<Box<dyn Queue>>,
     ) -> anyhow::Result<()> {
-        assert_eq!(config.len(), 1);
-        let config = config.drain(..).next().unwrap();
-
-        queues.push(Box::new(TapQueue::new(
-            config.driver.as_ref(),
-            self.tap.clone(),
-            config.pool,
-            config.initial_rx,
-        )?));
+        assert!(config.len() <= self.queues.len());
+        for (config, slot) in config.into_iter().zip(&self.queues) {
+            queues.push(Box::new(TapQueue::new(
+                config.driver.as_ref(),
+                slot.clone(),
+                config.pool,
+                config.initial_rx,
+            )?));
+        }
         Ok(())
     }
 
     async fn stop(&mut self) {
-        assert!(self.tap.lock().is_some(), "queue has not been dropped");
+        for slot in &self.queues {
+            assert!(slot.lock().is_some(), "queue has not been dropped");
+        }
     }
 
     fn is_ordered(&self) -> bool {
         true
     }
+
+    fn tx_offload_support(&self) -> TxOffloadSupport {
+        // Checksums and segmentation are offloaded to the kernel via the
+        // virtio-net header. The IPv4 header checksum is computed in software.
+        TxOffloadSupport {
+            ipv4_header: self.vnet_hdr,
+            tcp: self.vnet_hdr,
+            udp: self.vnet_hdr,
+            tso: self.vnet_hdr,
+        }
+    }
+
+    fn multiqueue_support(&self) -> MultiQueueSupport {
+        MultiQueueSupport {
+            max_queues: self.queues.len() as u16,
+            indirection_table_size: INDIRECTION_TABLE_SIZE,
+        }
+    }
 }
 
 struct TapQueue {
     slot: Arc<Mutex<Option<tap::Tap>>>,
     tap: Option<tap::PolledTap>,
+    vnet_hdr: bool,
     inner: Inner,
     buffer: Box<[u8]>,
 }
@@ -126,16 +179,18 @@ fn new(
         initial_rx: &[RxId],
     ) -> anyhow::Result<Self> {
         let tap = slot.lock().take().expect("queue is already in use");
+        let vnet_hdr = tap.vnet_hdr();
         let tap = tap.polled(driver)?;
         Ok(Self {
             slot,
             tap: Some(tap),
+            vnet_hdr,
             inner: Inner {
                 pool,
                 rx_free: initial_rx.iter().copied().collect(),
                 rx_ready: VecDeque::new(),
             },
-            buffer: Box::new([0; 65535]),
+            buffer: vec![0; size_of::<VirtioNetHdr>() + MAX_PACKET_SIZE].into(),
         })
     }
 }
@@ -155,15 +210,27 @@ fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
         while let Some(&rx) = self.inner.rx_free.front() {
             match Pin::new(&mut *tap).poll_read(cx, &mut self.buffer) {
                 Poll::Ready(Ok(read_len)) => {
-                    self.inner.pool.write_packet(
-                        rx,
-                        &RxMetadata {
-                            offset: 0,
-                            len: read_len,
-                            ..Default::default()
-                        },
-                        &self.buffer[..read_len],
-                    );
+                    let buffer = &self.buffer[..read_len];
+                    if self.vnet_hdr {
+                        let Some((hdr, packet)) = VirtioNetHdr::read_from_prefix(buffer)
+                            .map(|hdr| (hdr, &buffer[size_of::<VirtioNetHdr>()..]))
+                        else {
+                            continue;
+                        };
+                        self.inner
+                            .pool
+                            .write_packet(rx, &vnet::rx_metadata(&hdr, packet), packet);
+                    } else {
+                        self.inner.pool.write_packet(
+                            rx,
+                            &RxMetadata {
+                                offset: 0,
+                                len: read_len,
+                                ..Default::default()
+                            },
+                            buffer,
+                        );
+                    }
 
                     self.inner.rx_ready.push_back(rx);
                     self.inner.rx_free.pop_front();
@@ -201,8 +268,19 @@ fn tx_avail(&mut self, mut segments: &[TxSegment]) -> anyhow::Result<(bool, usiz
         // Synchronously send packets received from the guest to host's network.
         if let Some(tap) = self.tap.as_mut() {
             while !segments.is_empty() {
-                let packet = linearize(self.inner.pool.as_ref(), &mut segments)?;
-                match tap.write(&packet) {
+                let TxSegmentType::Head(meta) = &segments[0].ty else {
+                    unreachable!()
+                };
+                let meta = meta.clone();
+                let mut packet = linearize(self.inner.pool.as_ref(), &mut segments)?;
+                let result = if self.vnet_hdr {
+                    let hdr = vnet::tx_header(&meta, &mut packet);
+                    tap.write_vectored(&[IoSlice::new(hdr.as_bytes()), IoSlice::new(&packet)])
+                        .map(|n| n.saturating_sub(size_of::<VirtioNetHdr>()))
+                } else {
+                    tap.write(&packet)
+                };
+                match result {
                     Ok(bytes_written) => {
                         assert_eq!(
                             bytes_written,
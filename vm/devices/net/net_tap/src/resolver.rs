@@ -23,9 +23,9 @@ impl ResolveResource<NetEndpointHandleKind, TapHandle> for TapResolver {
     fn resolve(
         &self,
         resource: TapHandle,
-        _input: ResolveEndpointParams,
+        _input: ResolveEndpointParams<'_>,
     ) -> Result<Self::Output, Self::Error> {
-        let endpoint = TapEndpoint::new(&resource.name)?;
+        let endpoint = TapEndpoint::new(&resource.name, resource.queue_count)?;
         Ok(endpoint.into())
     }
 }
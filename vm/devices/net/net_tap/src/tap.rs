@@ -9,12 +9,15 @@
 use futures::AsyncRead;
 use linux_net_bindings::gen_if;
 use linux_net_bindings::gen_if_tun;
+use linux_net_bindings::tun_get_features;
 use linux_net_bindings::tun_set_iff;
+use linux_net_bindings::tun_set_offload;
 use pal_async::driver::Driver;
 use pal_async::pipe::PolledPipe;
 use std::ffi::CString;
 use std::fs::File;
 use std::io;
+use std::io::IoSlice;
 use std::io::Write;
 use std::os::raw::c_short;
 use std::os::unix::prelude::AsRawFd;
@@ -29,25 +32,44 @@ pub enum Error {
     TapNameTooLong(usize),
     #[error("failed to open /dev/net/tun")]
     OpenTunFailed(#[source] io::Error),
+    #[error("TUNGETFEATURES ioctl failed")]
+    GetFeatures(#[source] io::Error),
     #[error("TUNSETIFF ioctl failed")]
     SetTapAttributes(#[source] io::Error),
+    #[error("TUNSETOFFLOAD ioctl failed")]
+    SetOffload(#[source] io::Error),
+    #[error("the TUN/TAP driver does not support multiple queues")]
+    MultiQueueUnsupported,
     #[error("TAP name conversion to C string failed")]
     TapNameConversion(#[source] std::ffi::NulError),
 }
 
-/// Structure corresponding to a TAP interface.
+/// Structure corresponding to a queue of a TAP interface.
 #[derive(Debug)]
 pub struct Tap {
     tap: File,
+    vnet_hdr: bool,
 }
 
 impl Tap {
-    pub fn new(name: &str) -> Result<Self, Error> {
-        let tap = Self::open_tap_interface(name)?;
-        Ok(Self { tap })
+    /// Opens a queue of TAP interface `name`.
+    ///
+    /// If `multi_queue` is true, the interface is opened in multi-queue mode,
+    /// and each call attaches a new queue to the interface.
+    ///
+    /// Each packet is preceded by a virtio-net header if the TUN/TAP driver
+    /// supports it.
+    pub fn new(name: &str, multi_queue: bool) -> Result<Self, Error> {
+        let (tap, vnet_hdr) = Self::open_tap_interface(name, multi_queue)?;
+        Ok(Self { tap, vnet_hdr })
     }
 
-    fn open_tap_interface(tap_name: &str) -> Result<File, Error> {
+    /// Returns whether packets are preceded by a virtio-net header.
+    pub fn vnet_hdr(&self) -> bool {
+        self.vnet_hdr
+    }
+
+    fn open_tap_interface(tap_name: &str, multi_queue: bool) -> Result<(File, bool), Error> {
         // Open the TUN/TAP interface.
         //
         // - Packets received from this TAP interface (i.e., fom host's network)
@@ -66,6 +88,17 @@ fn open_tap_interface(tap_name: &str) -> Result<File, Error> {
             .open("/dev/net/tun")
             .map_err(Error::OpenTunFailed)?;
 
+        let mut features = 0;
+        // SAFETY: calling the ioctl according to implementation requirements.
+        unsafe {
+            tun_get_features(tap_file.as_raw_fd(), &mut features)
+                .map_err(|_e| Error::GetFeatures(io::Error::last_os_error()))?;
+        };
+        let vnet_hdr = features & gen_if_tun::IFF_VNET_HDR != 0;
+        if multi_queue && features & gen_if_tun::IFF_MULTI_QUEUE == 0 {
+            return Err(Error::MultiQueueUnsupported);
+        }
+
         // Set TAP interface attributes.
         let mut ifreq: gen_if::ifreq = Default::default();
 
@@ -83,20 +116,45 @@ fn open_tap_interface(tap_name: &str) -> Result<File, Error> {
             for i in 0..tap_name_length {
                 name_slice[i] = tap_name_bytes[i] as libc::c_char;
             }
-            ifreq.ifr_ifru.ifru_flags = (gen_if_tun::IFF_TAP | gen_if_tun::IFF_NO_PI) as c_short;
+            let mut flags = gen_if_tun::IFF_TAP | gen_if_tun::IFF_NO_PI;
+            if vnet_hdr {
+                flags |= gen_if_tun::IFF_VNET_HDR;
+            }
+            if multi_queue {
+                flags |= gen_if_tun::IFF_MULTI_QUEUE;
+            }
+            ifreq.ifr_ifru.ifru_flags = flags as c_short;
 
             // SAFETY: calling the ioctl according to implementation requirements.
             unsafe {
                 tun_set_iff(tap_file.as_raw_fd(), &ifreq)
                     .map_err(|_e| Error::SetTapAttributes(io::Error::last_os_error()))?;
             };
-            Ok(tap_file)
+
+            if vnet_hdr {
+                // Ask the kernel for fully checksummed, unsegmented packets,
+                // since there is no way to pass checksum or segmentation
+                // offload requests on to the guest. A persistent interface may
+                // still have offloads enabled by a previous user, so this must
+                // be done explicitly.
+                //
+                // Offloads for packets sent to the interface are specified per
+                // packet in the virtio-net header and do not need to be enabled.
+                //
+                // SAFETY: calling the ioctl according to implementation requirements.
+                unsafe {
+                    tun_set_offload(tap_file.as_raw_fd(), 0)
+                        .map_err(|_e| Error::SetOffload(io::Error::last_os_error()))?;
+                };
+            }
+            Ok((tap_file, vnet_hdr))
         }
     }
 
     pub fn polled(self, driver: &(impl Driver + ?Sized)) -> io::Result<PolledTap> {
         Ok(PolledTap {
             tap: PolledPipe::new(driver, self.tap)?,
+            vnet_hdr: self.vnet_hdr,
         })
     }
 }
@@ -104,12 +162,14 @@ pub fn polled(self, driver: &(impl Driver + ?Sized)) -> io::Result<PolledTap> {
 /// A version of [`Tap`] that implements [`AsyncRead`].
 pub struct PolledTap {
     tap: PolledPipe,
+    vnet_hdr: bool,
 }
 
 impl PolledTap {
     pub fn into_inner(self) -> Tap {
         Tap {
             tap: self.tap.into_inner(),
+            vnet_hdr: self.vnet_hdr,
         }
     }
 }
@@ -131,6 +191,10 @@ fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         self.tap.get().write(buf)
     }
 
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> io::Result<usize> {
+        self.tap.get().write_vectored(bufs)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
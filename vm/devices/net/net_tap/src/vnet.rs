@@ -0,0 +1,252 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The virtio-net header that precedes each packet on a TAP interface opened
+//! with `IFF_VNET_HDR`, used to offload checksum calculation and TCP
+//! segmentation of transmitted packets to the host kernel.
+
+use net_backend::L3Protocol;
+use net_backend::L4Protocol;
+use net_backend::RxChecksumState;
+use net_backend::RxMetadata;
+use net_backend::TxMetadata;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+/// `struct virtio_net_hdr`, in native byte order.
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct VirtioNetHdr {
+    pub flags: u8,
+    pub gso_type: u8,
+    pub hdr_len: u16,
+    pub gso_size: u16,
+    pub csum_start: u16,
+    pub csum_offset: u16,
+}
+
+pub const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+pub const VIRTIO_NET_HDR_F_DATA_VALID: u8 = 2;
+
+pub const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+pub const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_MIN_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const TCP_CHECKSUM_OFFSET: u16 = 16;
+const UDP_CHECKSUM_OFFSET: u16 = 6;
+
+/// Prepares `packet` for transmission with the offloads requested in `meta`,
+/// returning the virtio-net header to send with it.
+///
+/// The IPv4 header checksum is computed here, since the kernel does not offload
+/// it. For L4 checksum and segmentation offloads, the IP length fields and the
+/// L4 pseudo-header checksum are filled in, since the guest may leave them
+/// zero for segmentation offload.
+///
+/// If the offload metadata is inconsistent with the packet, the packet is sent
+/// without offloads.
+pub fn tx_header(meta: &TxMetadata, packet: &mut [u8]) -> VirtioNetHdr {
+    let mut hdr = VirtioNetHdr::new_zeroed();
+    let l4_checksum =
+        meta.offload_tcp_checksum || meta.offload_udp_checksum || meta.offload_tcp_segmentation;
+    if !meta.offload_ip_header_checksum && !l4_checksum {
+        return hdr;
+    }
+
+    let l2_len = meta.l2_len as usize;
+    let l4_start = l2_len + meta.l3_len as usize;
+    if l4_start > packet.len() {
+        return hdr;
+    }
+    let l4_len = packet.len() - l4_start;
+    let (ip, l4) = packet[l2_len..].split_at_mut(meta.l3_len as usize);
+
+    let tcp = meta.offload_tcp_checksum || meta.offload_tcp_segmentation;
+    let (protocol, csum_offset) = if tcp {
+        (IPPROTO_TCP, TCP_CHECKSUM_OFFSET)
+    } else {
+        (IPPROTO_UDP, UDP_CHECKSUM_OFFSET)
+    };
+
+    let (pseudo_sum, gso_type) = match meta.l3_protocol {
+        L3Protocol::Ipv4 if ip.len() >= IPV4_MIN_HEADER_LEN => {
+            if meta.offload_tcp_segmentation {
+                let Ok(total_len) = u16::try_from(ip.len() + l4_len) else {
+                    return hdr;
+                };
+                ip[2..4].copy_from_slice(&total_len.to_be_bytes());
+            }
+            if meta.offload_ip_header_checksum || meta.offload_tcp_segmentation {
+                ip[10..12].fill(0);
+                let checksum = !fold(sum(ip));
+                ip[10..12].copy_from_slice(&checksum.to_be_bytes());
+            }
+            (sum(&ip[12..20]), VIRTIO_NET_HDR_GSO_TCPV4)
+        }
+        L3Protocol::Ipv6 if ip.len() >= IPV6_HEADER_LEN => {
+            if meta.offload_tcp_segmentation {
+                let Ok(payload_len) = u16::try_from(ip.len() - IPV6_HEADER_LEN + l4_len) else {
+                    return hdr;
+                };
+                ip[4..6].copy_from_slice(&payload_len.to_be_bytes());
+            }
+            (sum(&ip[8..40]), VIRTIO_NET_HDR_GSO_TCPV6)
+        }
+        _ => return hdr,
+    };
+
+    if !l4_checksum || l4.len() < csum_offset as usize + 2 {
+        return hdr;
+    }
+
+    // Store the pseudo-header checksum, which the kernel completes with the
+    // checksum of the L4 header and payload.
+    let csum = &mut l4[csum_offset as usize..csum_offset as usize + 2];
+    let partial = fold(pseudo_sum + protocol as u64 + l4_len as u64);
+    csum.copy_from_slice(&partial.to_be_bytes());
+    hdr.flags = VIRTIO_NET_HDR_F_NEEDS_CSUM;
+    hdr.csum_start = l4_start as u16;
+    hdr.csum_offset = csum_offset;
+
+    if meta.offload_tcp_segmentation {
+        hdr.gso_type = gso_type;
+        hdr.gso_size = meta.max_tcp_segment_size;
+        hdr.hdr_len = (l4_start + meta.l4_len as usize) as u16;
+    }
+    hdr
+}
+
+/// Returns the receive metadata for `packet`, received with `hdr`.
+pub fn rx_metadata(hdr: &VirtioNetHdr, packet: &[u8]) -> RxMetadata {
+    let mut meta = RxMetadata {
+        offset: 0,
+        len: packet.len(),
+        ..Default::default()
+    };
+    let Some(ethertype) = packet.get(12..ETHERNET_HEADER_LEN) else {
+        return meta;
+    };
+    let ip = &packet[ETHERNET_HEADER_LEN..];
+    let protocol = match u16::from_be_bytes(ethertype.try_into().unwrap()) {
+        ETHERTYPE_IPV4 if ip.len() >= IPV4_MIN_HEADER_LEN => {
+            let header_len = (ip[0] as usize & 0xf) * 4;
+            if let Some(header) = ip.get(..header_len) {
+                meta.ip_checksum = if fold(sum(header)) == 0xffff {
+                    RxChecksumState::Good
+                } else {
+                    RxChecksumState::Bad
+                };
+            }
+            ip[9]
+        }
+        ETHERTYPE_IPV6 if ip.len() >= IPV6_HEADER_LEN => ip[6],
+        _ => return meta,
+    };
+    meta.l4_protocol = match protocol {
+        IPPROTO_TCP => L4Protocol::Tcp,
+        IPPROTO_UDP => L4Protocol::Udp,
+        _ => return meta,
+    };
+    if hdr.flags & VIRTIO_NET_HDR_F_DATA_VALID != 0 {
+        meta.l4_checksum = RxChecksumState::Good;
+    }
+    meta
+}
+
+/// Returns the one's complement sum of the big-endian 16-bit words in `data`,
+/// without folding.
+fn sum(data: &[u8]) -> u64 {
+    let mut chunks = data.chunks_exact(2);
+    let mut sum = chunks
+        .by_ref()
+        .map(|word| u16::from_be_bytes([word[0], word[1]]) as u64)
+        .sum();
+    if let [last] = chunks.remainder() {
+        sum += (*last as u64) << 8;
+    }
+    sum
+}
+
+/// Folds a one's complement sum to 16 bits.
+fn fold(mut sum: u64) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fold;
+    use super::sum;
+    use super::tx_header;
+    use super::VIRTIO_NET_HDR_F_NEEDS_CSUM;
+    use super::VIRTIO_NET_HDR_GSO_TCPV4;
+    use net_backend::L3Protocol;
+    use net_backend::TxMetadata;
+
+    /// Returns an IPv4 TCP packet with zeroed length and checksum fields, as
+    /// sent by a guest using segmentation offload.
+    fn tcp4_packet(payload_len: usize) -> Vec<u8> {
+        let mut packet = vec![0; 14 + 20 + 20 + payload_len];
+        packet[12..14].copy_from_slice(&0x0800u16.to_be_bytes());
+        let ip = &mut packet[14..34];
+        ip[0] = 0x45;
+        ip[8] = 64;
+        ip[9] = 6;
+        ip[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        ip[16..20].copy_from_slice(&[10, 0, 0, 2]);
+        let tcp = &mut packet[34..54];
+        tcp[0..2].copy_from_slice(&1234u16.to_be_bytes());
+        tcp[2..4].copy_from_slice(&80u16.to_be_bytes());
+        tcp[12] = 5 << 4;
+        for (i, b) in packet[54..].iter_mut().enumerate() {
+            *b = i as u8;
+        }
+        packet
+    }
+
+    #[test]
+    fn tso_ipv4() {
+        let mut packet = tcp4_packet(3000);
+        let meta = TxMetadata {
+            len: packet.len(),
+            offload_tcp_checksum: true,
+            offload_tcp_segmentation: true,
+            l3_protocol: L3Protocol::Ipv4,
+            l2_len: 14,
+            l3_len: 20,
+            l4_len: 20,
+            max_tcp_segment_size: 1460,
+            ..Default::default()
+        };
+        let hdr = tx_header(&meta, &mut packet);
+        assert_eq!(hdr.flags, VIRTIO_NET_HDR_F_NEEDS_CSUM);
+        assert_eq!(hdr.gso_type, VIRTIO_NET_HDR_GSO_TCPV4);
+        assert_eq!(hdr.gso_size, 1460);
+        assert_eq!(hdr.hdr_len, 54);
+        assert_eq!(hdr.csum_start, 34);
+        assert_eq!(hdr.csum_offset, 16);
+
+        // The IP header is complete.
+        assert_eq!(
+            u16::from_be_bytes([packet[16], packet[17]]) as usize,
+            packet.len() - 14
+        );
+        assert_eq!(fold(sum(&packet[14..34])), 0xffff);
+
+        // Completing the checksum as the kernel does yields a valid TCP
+        // checksum.
+        let checksum = !fold(sum(&packet[34..]));
+        packet[50..52].copy_from_slice(&checksum.to_be_bytes());
+        let pseudo = sum(&packet[26..34]) + 6 + (packet.len() - 34) as u64;
+        assert_eq!(fold(pseudo + sum(&packet[34..])), 0xffff);
+    }
+}
@@ -0,0 +1,160 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The front-end side of a vhost-user socket connection.
+
+// UNSAFETY: Calling sendmsg to pass file descriptors with SCM_RIGHTS.
+#![allow(unsafe_code)]
+
+use crate::protocol::Header;
+use crate::protocol::Request;
+use crate::protocol::FLAGS_REPLY;
+use crate::protocol::FLAGS_VERSION;
+use futures::AsyncReadExt;
+use pal_async::driver::Driver;
+use pal_async::interest::InterestSlot;
+use pal_async::interest::PollEvents;
+use pal_async::socket::PolledSocket;
+use std::future::poll_fn;
+use std::io;
+use std::io::IoSlice;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::fd::RawFd;
+use std::os::unix::net::UnixStream;
+use thiserror::Error;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+/// The most file descriptors sent with a single message.
+const MAX_FDS: usize = crate::protocol::MAX_MEMORY_REGIONS;
+
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    #[error("vhost-user socket error")]
+    Io(#[source] io::Error),
+    #[error("unexpected reply to {request:?}: {header:?}")]
+    UnexpectedReply { request: Request, header: Header },
+}
+
+pub struct Connection {
+    socket: PolledSocket<UnixStream>,
+}
+
+impl Connection {
+    pub fn new(driver: &(impl ?Sized + Driver), socket: UnixStream) -> io::Result<Self> {
+        Ok(Self {
+            socket: PolledSocket::new(driver, socket)?,
+        })
+    }
+
+    /// Sends `request` with `payload`, passing the file descriptors in `fds`.
+    pub async fn send(
+        &mut self,
+        request: Request,
+        payload: &[u8],
+        fds: &[BorrowedFd<'_>],
+    ) -> Result<(), ProtocolError> {
+        let header = Header {
+            request,
+            flags: FLAGS_VERSION,
+            size: payload.len() as u32,
+        };
+        let mut bufs = [IoSlice::new(header.as_bytes()), IoSlice::new(payload)];
+        let mut bufs = &mut bufs[..];
+        let mut fds = fds;
+        while !bufs.is_empty() {
+            let n = poll_fn(|cx| {
+                self.socket
+                    .poll_io(cx, InterestSlot::Write, PollEvents::OUT, |socket| {
+                        send_with_fds(socket.get(), bufs, fds)
+                    })
+            })
+            .await
+            .map_err(ProtocolError::Io)?;
+            // The file descriptors are sent with the first byte.
+            fds = &[];
+            IoSlice::advance_slices(&mut bufs, n);
+        }
+        Ok(())
+    }
+
+    /// Sends `request` and waits for its reply payload.
+    pub async fn call<T: AsBytes + FromBytes>(
+        &mut self,
+        request: Request,
+        payload: &[u8],
+    ) -> Result<T, ProtocolError> {
+        self.send(request, payload, &[]).await?;
+        let mut header = Header::new_zeroed();
+        self.socket
+            .read_exact(header.as_bytes_mut())
+            .await
+            .map_err(ProtocolError::Io)?;
+        if header.request != request
+            || header.flags & FLAGS_REPLY == 0
+            || header.size as usize != size_of::<T>()
+        {
+            return Err(ProtocolError::UnexpectedReply { request, header });
+        }
+        let mut reply = T::new_zeroed();
+        self.socket
+            .read_exact(reply.as_bytes_mut())
+            .await
+            .map_err(ProtocolError::Io)?;
+        Ok(reply)
+    }
+}
+
+#[repr(C)]
+struct CmsgScmRights {
+    hdr: libc::cmsghdr,
+    fds: [RawFd; MAX_FDS],
+}
+
+/// Sends data with `sendmsg`, passing `fds` via `SCM_RIGHTS`.
+// x86_64-unknown-linux-musl targets have a different type defn for
+// `libc::cmsghdr`, hence why these lints are being suppressed.
+#[allow(clippy::needless_update, clippy::useless_conversion)]
+fn send_with_fds(
+    socket: &UnixStream,
+    bufs: &[IoSlice<'_>],
+    fds: &[BorrowedFd<'_>],
+) -> io::Result<usize> {
+    assert!(fds.len() <= MAX_FDS);
+    let mut cmsg = CmsgScmRights {
+        hdr: libc::cmsghdr {
+            cmsg_level: libc::SOL_SOCKET,
+            cmsg_type: libc::SCM_RIGHTS,
+            cmsg_len: (size_of::<libc::cmsghdr>() + size_of_val(fds))
+                .try_into()
+                .unwrap(),
+
+            ..{
+                // SAFETY: type has no invariants
+                unsafe { std::mem::zeroed() }
+            }
+        },
+        fds: [0; MAX_FDS],
+    };
+    for (fdi, fdo) in fds.iter().zip(cmsg.fds.iter_mut()) {
+        *fdo = fdi.as_raw_fd();
+    }
+
+    // SAFETY: type has no invariants
+    let mut hdr: libc::msghdr = unsafe { std::mem::zeroed() };
+    hdr.msg_iov = bufs.as_ptr() as *mut libc::iovec;
+    hdr.msg_iovlen = bufs.len().try_into().unwrap();
+    if !fds.is_empty() {
+        hdr.msg_control = std::ptr::from_mut(&mut cmsg).cast::<libc::c_void>();
+        hdr.msg_controllen = cmsg.hdr.cmsg_len;
+    }
+    // SAFETY: calling with appropriately initialized buffers, which remain
+    // valid for the duration of the call.
+    let n = unsafe { libc::sendmsg(socket.as_raw_fd(), &hdr, libc::MSG_NOSIGNAL) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
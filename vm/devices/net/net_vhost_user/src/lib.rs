@@ -0,0 +1,602 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! An endpoint that connects to a vhost-user network backend, such as a DPDK
+//! or OVS dataplane process, over a Unix socket.
+//!
+//! Guest RAM is shared with the backend via the memory table, and packets are
+//! passed in virtqueues without copying: transmit descriptors point directly
+//! at the guest's packet data, and receive descriptors point directly at the
+//! guest's receive buffers. The virtqueues themselves, along with the
+//! virtio-net header for each packet, live in a separate shared region that is
+//! not visible to the guest.
+
+#![cfg(unix)]
+
+mod connection;
+mod protocol;
+pub mod resolver;
+mod ring;
+
+pub use connection::ProtocolError;
+
+use async_trait::async_trait;
+use connection::Connection;
+use guestmem::GuestMemory;
+use inspect::InspectMut;
+use net_backend::BufferAccess;
+use net_backend::Endpoint;
+use net_backend::MultiQueueSupport;
+use net_backend::Queue;
+use net_backend::QueueConfig;
+use net_backend::RssConfig;
+use net_backend::RxId;
+use net_backend::RxMetadata;
+use net_backend::TxId;
+use net_backend::TxSegment;
+use pal_async::driver::Driver;
+use pal_async::wait::PolledWait;
+use pal_event::Event;
+use protocol::MemoryHeader;
+use protocol::MemoryRegion;
+use protocol::Request;
+use protocol::VringAddr;
+use protocol::VringState;
+use ring::Buffer;
+use ring::Ring;
+use ring::RingError;
+use ring::RingLayout;
+use ring::RING_SIZE;
+use shared_memory_resources::SharedGuestMemory;
+use sparse_mmap::SparseMapping;
+use std::collections::VecDeque;
+use std::io;
+use std::os::fd::AsFd;
+use std::os::unix::net::UnixStream;
+use std::task::Context;
+use std::task::Poll;
+use thiserror::Error;
+use zerocopy::AsBytes;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Protocol(#[from] ProtocolError),
+    #[error("backend does not support VIRTIO_F_VERSION_1")]
+    NoVersion1,
+    #[error("guest memory has too many regions to share with the backend: {0}")]
+    TooManyRegions(usize),
+    #[error("failed to allocate the virtqueue region")]
+    AllocateRings(#[source] io::Error),
+    #[error("failed to poll the vhost-user socket")]
+    Socket(#[source] io::Error),
+}
+
+/// The size of the indirection table reported to the guest. The backend
+/// steers received packets to queues itself, so the guest's table is not
+/// used.
+const INDIRECTION_TABLE_SIZE: u16 = 128;
+
+/// The address of the virtqueue region in the memory table. This is above any
+/// guest physical address, so that it cannot overlap guest RAM.
+const RING_REGION_ADDRESS: u64 = 1 << 52;
+
+/// An endpoint connected to a vhost-user backend.
+pub struct VhostUserEndpoint {
+    conn: Connection,
+    features: u64,
+    protocol_features: u64,
+    /// The virtqueue region, addressed from offset zero.
+    rings: GuestMemory,
+    /// The rx and tx ring layouts for each queue pair.
+    layouts: Vec<[RingLayout; 2]>,
+    /// The indexes of the rings that have been started.
+    started: Vec<u32>,
+}
+
+impl VhostUserEndpoint {
+    /// Negotiates with the backend connected to `socket`, sharing guest RAM in
+    /// `memory` with it.
+    ///
+    /// Up to `max_queues` queue pairs are used, if the backend supports
+    /// multiple queues.
+    pub async fn new(
+        driver: &(impl ?Sized + Driver),
+        socket: UnixStream,
+        memory: &SharedGuestMemory,
+        max_queues: u16,
+    ) -> Result<Self, Error> {
+        let mut conn = Connection::new(driver, socket).map_err(Error::Socket)?;
+        conn.send(Request::SET_OWNER, &[], &[]).await?;
+
+        let backend_features: u64 = conn.call(Request::GET_FEATURES, &[]).await?;
+        if backend_features & protocol::VIRTIO_F_VERSION_1 == 0 {
+            return Err(Error::NoVersion1);
+        }
+        let mut features = protocol::VIRTIO_F_VERSION_1;
+        let mut protocol_features = 0;
+        let mut queue_pairs = 1;
+        if backend_features & protocol::VHOST_USER_F_PROTOCOL_FEATURES != 0 {
+            features |= protocol::VHOST_USER_F_PROTOCOL_FEATURES;
+            let backend_protocol_features: u64 =
+                conn.call(Request::GET_PROTOCOL_FEATURES, &[]).await?;
+            protocol_features = backend_protocol_features & protocol::VHOST_USER_PROTOCOL_F_MQ;
+            conn.send(
+                Request::SET_PROTOCOL_FEATURES,
+                protocol_features.as_bytes(),
+                &[],
+            )
+            .await?;
+            if protocol_features & protocol::VHOST_USER_PROTOCOL_F_MQ != 0
+                && backend_features & protocol::VIRTIO_NET_F_MQ != 0
+                && max_queues > 1
+            {
+                features |= protocol::VIRTIO_NET_F_MQ;
+                let backend_queue_pairs: u64 = conn.call(Request::GET_QUEUE_NUM, &[]).await?;
+                queue_pairs = backend_queue_pairs.clamp(1, max_queues.into()) as u16;
+            }
+        }
+        conn.send(Request::SET_FEATURES, features.as_bytes(), &[])
+            .await?;
+
+        // Merge guest RAM regions that are contiguous in both address and
+        // offset, to stay within the memory table limit.
+        let mut regions = Vec::<MemoryRegion>::new();
+        for region in &memory.regions {
+            if let Some(last) = regions.last_mut() {
+                if last.guest_phys_addr + last.memory_size == region.range.start()
+                    && last.mmap_offset + last.memory_size == region.offset
+                {
+                    last.memory_size += region.range.len();
+                    continue;
+                }
+            }
+            regions.push(MemoryRegion {
+                guest_phys_addr: region.range.start(),
+                memory_size: region.range.len(),
+                userspace_addr: region.range.start(),
+                mmap_offset: region.offset,
+            });
+        }
+        if regions.len() >= protocol::MAX_MEMORY_REGIONS {
+            return Err(Error::TooManyRegions(regions.len()));
+        }
+
+        let mut offset = 0;
+        let layouts = (0..queue_pairs)
+            .map(|_| {
+                [(); 2].map(|()| {
+                    let (layout, end) = RingLayout::new(offset, protocol::VIRTIO_NET_HDR_LEN);
+                    offset = end;
+                    layout
+                })
+            })
+            .collect::<Vec<_>>();
+        let len = offset as usize;
+        let fd = sparse_mmap::alloc_shared_memory(len).map_err(Error::AllocateRings)?;
+        let mapping = SparseMapping::new(len).map_err(Error::AllocateRings)?;
+        mapping
+            .map_file(0, len, &fd, 0, true)
+            .map_err(Error::AllocateRings)?;
+        let rings = GuestMemory::new("vhost-user-rings", mapping);
+        regions.push(MemoryRegion {
+            guest_phys_addr: RING_REGION_ADDRESS,
+            memory_size: len as u64,
+            userspace_addr: RING_REGION_ADDRESS,
+            mmap_offset: 0,
+        });
+
+        let mut payload = MemoryHeader {
+            num_regions: regions.len() as u32,
+            padding: 0,
+        }
+        .as_bytes()
+        .to_vec();
+        payload.extend_from_slice(regions.as_bytes());
+        let fds = (0..regions.len() - 1)
+            .map(|_| memory.fd.as_fd())
+            .chain([fd.as_fd()])
+            .collect::<Vec<_>>();
+        conn.send(Request::SET_MEM_TABLE, &payload, &fds).await?;
+
+        Ok(Self {
+            conn,
+            features,
+            protocol_features,
+            rings,
+            layouts,
+            started: Vec::new(),
+        })
+    }
+
+    async fn start_ring(&mut self, index: u32, driver: &dyn Driver) -> anyhow::Result<RingState> {
+        let layout = self.layouts[index as usize / 2][index as usize % 2];
+        let ring = Ring::new(self.rings.clone(), layout, RING_REGION_ADDRESS)?;
+        let (descriptor, used, available) = ring.addresses();
+        self.conn
+            .send(
+                Request::SET_VRING_NUM,
+                VringState {
+                    index,
+                    num: RING_SIZE.into(),
+                }
+                .as_bytes(),
+                &[],
+            )
+            .await?;
+        self.conn
+            .send(
+                Request::SET_VRING_ADDR,
+                VringAddr {
+                    index,
+                    flags: 0,
+                    descriptor,
+                    used,
+                    available,
+                    log: 0,
+                }
+                .as_bytes(),
+                &[],
+            )
+            .await?;
+        self.conn
+            .send(
+                Request::SET_VRING_BASE,
+                VringState { index, num: 0 }.as_bytes(),
+                &[],
+            )
+            .await?;
+        let kick = Event::new();
+        let call = Event::new();
+        self.conn
+            .send(
+                Request::SET_VRING_KICK,
+                u64::from(index).as_bytes(),
+                &[kick.as_fd()],
+            )
+            .await?;
+        self.conn
+            .send(
+                Request::SET_VRING_CALL,
+                u64::from(index).as_bytes(),
+                &[call.as_fd()],
+            )
+            .await?;
+        self.started.push(index);
+        // Without protocol features, the ring is enabled when its kick file
+        // descriptor is set.
+        if self.features & protocol::VHOST_USER_F_PROTOCOL_FEATURES != 0 {
+            self.conn
+                .send(
+                    Request::SET_VRING_ENABLE,
+                    VringState { index, num: 1 }.as_bytes(),
+                    &[],
+                )
+                .await?;
+        }
+        Ok(RingState {
+            ring,
+            kick,
+            call: PolledWait::new(driver, call)?,
+        })
+    }
+
+    async fn stop_ring(&mut self, index: u32) -> Result<(), ProtocolError> {
+        if self.features & protocol::VHOST_USER_F_PROTOCOL_FEATURES != 0 {
+            self.conn
+                .send(
+                    Request::SET_VRING_ENABLE,
+                    VringState { index, num: 0 }.as_bytes(),
+                    &[],
+                )
+                .await?;
+        }
+        let _: VringState = self
+            .conn
+            .call(
+                Request::GET_VRING_BASE,
+                VringState { index, num: 0 }.as_bytes(),
+            )
+            .await?;
+        Ok(())
+    }
+}
+
+impl InspectMut for VhostUserEndpoint {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        req.respond()
+            .hex("features", self.features)
+            .hex("protocol_features", self.protocol_features)
+            .field("queue_pairs", self.layouts.len())
+            .field("started_rings", self.started.len());
+    }
+}
+
+#[async_trait]
+impl Endpoint for VhostUserEndpoint {
+    fn endpoint_type(&self) -> &'static str {
+        "vhost-user"
+    }
+
+    async fn get_queues(
+        &mut self,
+        config: Vec<QueueConfig<'_>>,
+        _rss: Option<&RssConfig<'_>>,
+        queues: &mut Vec<Box<dyn Queue>>,
+    ) -> anyhow::Result<()> {
+        assert!(config.len() <= self.layouts.len());
+        for (i, config) in config.into_iter().enumerate() {
+            let rx = self
+                .start_ring(i as u32 * 2, config.driver.as_ref())
+                .await?;
+            let tx = self
+                .start_ring(i as u32 * 2 + 1, config.driver.as_ref())
+                .await?;
+            queues.push(Box::new(VhostUserQueue::new(
+                config.pool,
+                config.initial_rx,
+                rx,
+                tx,
+            )));
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        for index in std::mem::take(&mut self.started) {
+            if let Err(err) = self.stop_ring(index).await {
+                tracing::warn!(
+                    index,
+                    error = &err as &dyn std::error::Error,
+                    "failed to stop vhost-user ring"
+                );
+            }
+        }
+    }
+
+    fn multiqueue_support(&self) -> MultiQueueSupport {
+        MultiQueueSupport {
+            max_queues: self.layouts.len() as u16,
+            indirection_table_size: INDIRECTION_TABLE_SIZE,
+        }
+    }
+}
+
+/// A started virtqueue and its notification events.
+struct RingState {
+    ring: Ring,
+    kick: Event,
+    call: PolledWait<Event>,
+}
+
+impl RingState {
+    /// Consumes any pending backend notification, and registers for the next
+    /// one.
+    fn poll_call(&mut self, cx: &mut Context<'_>) {
+        loop {
+            match self.call.poll_wait(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(err)) => {
+                    tracing::warn!(
+                        error = &err as &dyn std::error::Error,
+                        "vhost-user call event failed"
+                    );
+                    break;
+                }
+                Poll::Pending => break,
+            }
+        }
+    }
+}
+
+struct VhostUserQueue {
+    pool: Box<dyn BufferAccess>,
+    rx: RingState,
+    tx: RingState,
+    /// The receive buffer posted in each rx descriptor chain, by head index.
+    rx_ids: Vec<Option<RxId>>,
+    /// The packet sent in each tx descriptor chain, by head index.
+    tx_ids: Vec<Option<TxId>>,
+    /// Receive buffers waiting for free descriptors.
+    rx_pending: VecDeque<RxId>,
+    rx_ready: VecDeque<RxId>,
+    tx_done: VecDeque<TxId>,
+    /// Set when the backend has violated the virtqueue protocol. The rings
+    /// are no longer processed after this.
+    broken: bool,
+}
+
+impl InspectMut for VhostUserQueue {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        req.respond()
+            .field("rx_ring", &self.rx.ring)
+            .field("tx_ring", &self.tx.ring)
+            .field("rx_pending", self.rx_pending.len())
+            .field("rx_ready", self.rx_ready.len())
+            .field("tx_done", self.tx_done.len())
+            .field("broken", self.broken);
+    }
+}
+
+impl VhostUserQueue {
+    fn new(pool: Box<dyn BufferAccess>, initial_rx: &[RxId], rx: RingState, tx: RingState) -> Self {
+        let mut this = Self {
+            pool,
+            rx,
+            tx,
+            rx_ids: vec![None; RING_SIZE.into()],
+            tx_ids: vec![None; RING_SIZE.into()],
+            rx_pending: initial_rx.iter().copied().collect(),
+            rx_ready: VecDeque::new(),
+            tx_done: VecDeque::new(),
+            broken: false,
+        };
+        this.post_rx_checked();
+        this
+    }
+
+    /// Posts pending receive buffers to the rx ring.
+    fn post_rx(&mut self) -> Result<(), RingError> {
+        let mut posted = false;
+        while let Some(&id) = self.rx_pending.front() {
+            let segments = self.pool.guest_addresses(id);
+            if segments.len() >= RING_SIZE.into() {
+                // The buffer can never fit in the ring. Return it to the
+                // guest empty.
+                self.pool.write_header(id, &RxMetadata::default());
+                self.rx_ready.push_back(id);
+                self.rx_pending.pop_front();
+                continue;
+            }
+            let Some(head) = self.rx.ring.push(
+                true,
+                segments.iter().map(|segment| Buffer {
+                    addr: segment.gpa,
+                    len: segment.len,
+                    write: true,
+                }),
+            )?
+            else {
+                break;
+            };
+            self.rx_ids[head as usize] = Some(id);
+            self.rx_pending.pop_front();
+            posted = true;
+        }
+        if posted {
+            self.rx.kick.signal();
+        }
+        Ok(())
+    }
+
+    fn post_rx_checked(&mut self) {
+        if self.broken {
+            return;
+        }
+        if let Err(err) = self.post_rx() {
+            self.set_broken(&err);
+        }
+    }
+
+    /// Marks the queue broken after a ring error, so that neither ring is
+    /// touched again.
+    fn set_broken(&mut self, err: &RingError) {
+        tracing::error!(
+            error = err as &dyn std::error::Error,
+            "vhost-user queue failed, disabling it"
+        );
+        self.broken = true;
+    }
+
+    /// Pushes a packet to the tx ring, returning its head index if there was
+    /// room.
+    fn push_tx(&mut self, segments: &[TxSegment]) -> Result<Option<u16>, RingError> {
+        self.tx
+            .ring
+            .push(
+                false,
+                segments.iter().map(|segment| Buffer {
+                    addr: segment.gpa,
+                    len: segment.len,
+                    write: false,
+                }),
+            )
+            .inspect_err(|err| self.set_broken(err))
+    }
+
+    /// Processes completed descriptor chains from both rings.
+    fn process_used(&mut self) -> Result<(), RingError> {
+        while let Some((head, len)) = self.rx.ring.pop_used()? {
+            let id = self.rx_ids[head as usize]
+                .take()
+                .ok_or(RingError::InvalidDescriptor(head.into()))?;
+            self.pool.write_header(
+                id,
+                &RxMetadata {
+                    offset: 0,
+                    len: (len as usize).saturating_sub(protocol::VIRTIO_NET_HDR_LEN as usize),
+                    ..Default::default()
+                },
+            );
+            self.rx_ready.push_back(id);
+        }
+        while let Some((head, _)) = self.tx.ring.pop_used()? {
+            let id = self.tx_ids[head as usize]
+                .take()
+                .ok_or(RingError::InvalidDescriptor(head.into()))?;
+            self.tx_done.push_back(id);
+        }
+        // Repost any buffers that were waiting for the descriptors just freed.
+        self.post_rx()
+    }
+}
+
+impl Queue for VhostUserQueue {
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        // Register for notifications before checking the rings, so that a
+        // completion that races with the check is not missed.
+        if !self.broken {
+            self.rx.poll_call(cx);
+            self.tx.poll_call(cx);
+            if let Err(err) = self.process_used() {
+                self.set_broken(&err);
+            }
+        }
+        if !self.rx_ready.is_empty() || !self.tx_done.is_empty() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn rx_avail(&mut self, done: &[RxId]) {
+        self.rx_pending.extend(done);
+        self.post_rx_checked();
+    }
+
+    fn rx_poll(&mut self, packets: &mut [RxId]) -> anyhow::Result<usize> {
+        let n = packets.len().min(self.rx_ready.len());
+        for (done, id) in packets[..n].iter_mut().zip(self.rx_ready.drain(..n)) {
+            *done = id;
+        }
+        Ok(n)
+    }
+
+    fn tx_avail(&mut self, segments: &[TxSegment]) -> anyhow::Result<(bool, usize)> {
+        if self.broken {
+            anyhow::bail!("vhost-user queue is disabled after a backend error");
+        }
+        let mut rest = segments;
+        while !rest.is_empty() {
+            let (metadata, this, next) = net_backend::next_packet(rest);
+            if this.len() >= RING_SIZE.into() {
+                // The packet can never fit in the ring. Drop it.
+                self.tx_done.push_back(metadata.id);
+            } else if let Some(head) = self.push_tx(this)? {
+                self.tx_ids[head as usize] = Some(metadata.id);
+            } else {
+                // The ring is full. The rest will be sent when descriptors
+                // are freed.
+                break;
+            }
+            rest = next;
+        }
+        let consumed = segments.len() - rest.len();
+        if consumed > 0 {
+            self.tx.kick.signal();
+        }
+        Ok((false, consumed))
+    }
+
+    fn tx_poll(&mut self, done: &mut [TxId]) -> anyhow::Result<usize> {
+        let n = done.len().min(self.tx_done.len());
+        for (done, id) in done[..n].iter_mut().zip(self.tx_done.drain(..n)) {
+            *done = id;
+        }
+        Ok(n)
+    }
+
+    fn buffer_access(&mut self) -> Option<&mut dyn BufferAccess> {
+        Some(self.pool.as_mut())
+    }
+}
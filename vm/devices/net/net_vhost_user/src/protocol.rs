@@ -0,0 +1,105 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Definitions from the vhost-user protocol specification, as implemented by
+//! QEMU, DPDK, and other vhost-user backends.
+
+use open_enum::open_enum;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+open_enum! {
+    /// A front-end request.
+    #[derive(AsBytes, FromBytes, FromZeroes)]
+    pub enum Request: u32 {
+        GET_FEATURES = 1,
+        SET_FEATURES = 2,
+        SET_OWNER = 3,
+        SET_MEM_TABLE = 5,
+        SET_VRING_NUM = 8,
+        SET_VRING_ADDR = 9,
+        SET_VRING_BASE = 10,
+        GET_VRING_BASE = 11,
+        SET_VRING_KICK = 12,
+        SET_VRING_CALL = 13,
+        GET_PROTOCOL_FEATURES = 15,
+        SET_PROTOCOL_FEATURES = 16,
+        GET_QUEUE_NUM = 17,
+        SET_VRING_ENABLE = 18,
+    }
+}
+
+/// The protocol version, in the low bits of [`Header::flags`].
+pub const FLAGS_VERSION: u32 = 1;
+/// Set in replies.
+pub const FLAGS_REPLY: u32 = 1 << 2;
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct Header {
+    pub request: Request,
+    pub flags: u32,
+    /// The size of the payload following the header.
+    pub size: u32,
+}
+
+/// Payload for `SET_VRING_NUM`, `SET_VRING_BASE`, `GET_VRING_BASE`, and
+/// `SET_VRING_ENABLE`.
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct VringState {
+    pub index: u32,
+    pub num: u32,
+}
+
+/// Payload for `SET_VRING_ADDR`. The addresses are in the front-end's address
+/// space, as described by the memory table.
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct VringAddr {
+    pub index: u32,
+    pub flags: u32,
+    pub descriptor: u64,
+    pub used: u64,
+    pub available: u64,
+    pub log: u64,
+}
+
+/// The header of the `SET_MEM_TABLE` payload, which is followed by
+/// `num_regions` [`MemoryRegion`]s. Each region's file descriptor is sent
+/// with the message, in the same order.
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct MemoryHeader {
+    pub num_regions: u32,
+    pub padding: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct MemoryRegion {
+    pub guest_phys_addr: u64,
+    pub memory_size: u64,
+    pub userspace_addr: u64,
+    pub mmap_offset: u64,
+}
+
+/// The maximum number of regions in `SET_MEM_TABLE`.
+pub const MAX_MEMORY_REGIONS: usize = 8;
+
+/// Set in the `SET_VRING_KICK` and `SET_VRING_CALL` payloads when no file
+/// descriptor is sent.
+pub const VRING_NOFD: u64 = 1 << 8;
+
+// Virtio and vhost-user feature bits.
+pub const VIRTIO_NET_F_MQ: u64 = 1 << 22;
+pub const VHOST_USER_F_PROTOCOL_FEATURES: u64 = 1 << 30;
+pub const VIRTIO_F_VERSION_1: u64 = 1 << 32;
+
+// Protocol feature bits.
+pub const VHOST_USER_PROTOCOL_F_MQ: u64 = 1 << 0;
+
+/// The length of the virtio-net header that precedes each packet, when
+/// `VIRTIO_F_VERSION_1` is negotiated.
+pub const VIRTIO_NET_HDR_LEN: u32 = 12;
@@ -0,0 +1,59 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use crate::VhostUserEndpoint;
+use async_trait::async_trait;
+use net_backend::resolve::ResolveEndpointParams;
+use net_backend::resolve::ResolvedEndpoint;
+use net_backend_resources::vhost_user::VhostUserHandle;
+use shared_memory_resources::SharedGuestMemoryHandleKind;
+use thiserror::Error;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::NetEndpointHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::IntoResource;
+use vm_resource::PlatformResource;
+use vm_resource::ResolveError;
+use vm_resource::ResourceResolver;
+
+pub struct VhostUserResolver;
+
+declare_static_async_resolver! {
+    VhostUserResolver,
+    (NetEndpointHandleKind, VhostUserHandle),
+}
+
+#[derive(Debug, Error)]
+pub enum ResolveVhostUserError {
+    #[error("guest memory cannot be shared with the vhost-user backend")]
+    SharedMemory(#[source] ResolveError),
+    #[error("failed to connect to the vhost-user backend")]
+    Endpoint(#[source] super::Error),
+}
+
+#[async_trait]
+impl AsyncResolveResource<NetEndpointHandleKind, VhostUserHandle> for VhostUserResolver {
+    type Output = ResolvedEndpoint;
+    type Error = ResolveVhostUserError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        resource: VhostUserHandle,
+        input: ResolveEndpointParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let memory = resolver
+            .resolve::<SharedGuestMemoryHandleKind, _>(PlatformResource.into_resource(), ())
+            .await
+            .map_err(ResolveVhostUserError::SharedMemory)?;
+        let endpoint = VhostUserEndpoint::new(
+            &input.driver_source.simple(),
+            resource.socket,
+            &memory,
+            resource.max_queues,
+        )
+        .await
+        .map_err(ResolveVhostUserError::Endpoint)?;
+        Ok(endpoint.into())
+    }
+}
@@ -0,0 +1,345 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The driver side of a split virtqueue, in memory shared with the backend.
+
+use guestmem::GuestMemory;
+use inspect::Inspect;
+use std::sync::atomic::fence;
+use std::sync::atomic::Ordering;
+use thiserror::Error;
+use virtio::spec::queue::Descriptor;
+use virtio::spec::queue::DescriptorFlags;
+use virtio::spec::queue::UsedElement;
+use virtio::spec::queue::AVAIL_OFFSET_IDX;
+use virtio::spec::queue::AVAIL_OFFSET_RING;
+use virtio::spec::queue::USED_OFFSET_IDX;
+use virtio::spec::queue::USED_OFFSET_RING;
+
+/// The number of descriptors in each ring.
+pub const RING_SIZE: u16 = 256;
+
+const PAGE_SIZE: u64 = 4096;
+
+/// A buffer in a descriptor chain.
+#[derive(Debug, Copy, Clone)]
+pub struct Buffer {
+    pub addr: u64,
+    pub len: u32,
+    /// The backend writes to this buffer.
+    pub write: bool,
+}
+
+#[derive(Debug, Error)]
+pub enum RingError {
+    #[error("backend returned invalid descriptor {0}")]
+    InvalidDescriptor(u32),
+    #[error("shared memory access error")]
+    Memory(#[source] guestmem::GuestMemoryError),
+}
+
+/// The location of a ring's structures in the shared region.
+#[derive(Debug, Copy, Clone)]
+pub struct RingLayout {
+    /// The offset of the descriptor table.
+    pub descriptors: u64,
+    /// The offset of the available ring.
+    pub available: u64,
+    /// The offset of the used ring.
+    pub used: u64,
+    /// The offset of an array of per-descriptor scratch space, used for
+    /// virtio-net headers.
+    pub scratch: u64,
+    /// The number of bytes of scratch space per descriptor.
+    pub scratch_len: u32,
+}
+
+impl RingLayout {
+    /// Returns the layout of a ring at `base`, and the offset of the end of
+    /// the ring's structures, rounded up to a page.
+    pub fn new(base: u64, scratch_len: u32) -> (Self, u64) {
+        let size = RING_SIZE as u64;
+        let descriptors = base;
+        let available = descriptors + size * size_of::<Descriptor>() as u64;
+        // flags, idx, ring, used_event
+        let used = (available + 6 + size * 2).next_multiple_of(PAGE_SIZE);
+        // flags, idx, ring, avail_event
+        let scratch = used + 6 + size * size_of::<UsedElement>() as u64;
+        let end = (scratch + size * scratch_len as u64).next_multiple_of(PAGE_SIZE);
+        (
+            Self {
+                descriptors,
+                available,
+                used,
+                scratch,
+                scratch_len,
+            },
+            end,
+        )
+    }
+}
+
+/// A split virtqueue owned by the driver.
+///
+/// Addresses within the shared region are offsets into `mem`; `region_addr`
+/// is the address of the start of the region as described to the backend in
+/// the memory table.
+#[derive(Inspect)]
+pub struct Ring {
+    #[inspect(skip)]
+    mem: GuestMemory,
+    #[inspect(skip)]
+    layout: RingLayout,
+    #[inspect(hex)]
+    region_addr: u64,
+    /// Free descriptor indexes.
+    #[inspect(with = "Vec::len")]
+    free: Vec<u16>,
+    /// The next descriptor in each in-flight chain, if any.
+    #[inspect(skip)]
+    next: Vec<Option<u16>>,
+    avail_idx: u16,
+    last_used_idx: u16,
+}
+
+impl Ring {
+    /// Returns a new, empty ring.
+    pub fn new(mem: GuestMemory, layout: RingLayout, region_addr: u64) -> Result<Self, RingError> {
+        let (_, end) = RingLayout::new(layout.descriptors, layout.scratch_len);
+        mem.fill_at(layout.descriptors, 0, (end - layout.descriptors) as usize)
+            .map_err(RingError::Memory)?;
+        Ok(Self {
+            mem,
+            layout,
+            region_addr,
+            free: (0..RING_SIZE).rev().collect(),
+            next: vec![None; RING_SIZE.into()],
+            avail_idx: 0,
+            last_used_idx: 0,
+        })
+    }
+
+    /// Returns the backend's addresses of the descriptor table, used ring, and
+    /// available ring.
+    pub fn addresses(&self) -> (u64, u64, u64) {
+        (
+            self.region_addr + self.layout.descriptors,
+            self.region_addr + self.layout.used,
+            self.region_addr + self.layout.available,
+        )
+    }
+
+    /// Returns the backend's address of the scratch space for descriptor
+    /// `index`.
+    pub fn scratch_addr(&self, index: u16) -> u64 {
+        self.region_addr + self.scratch_offset(index)
+    }
+
+    fn scratch_offset(&self, index: u16) -> u64 {
+        self.layout.scratch + index as u64 * self.layout.scratch_len as u64
+    }
+
+    /// The number of free descriptors.
+    pub fn free_count(&self) -> usize {
+        self.free.len()
+    }
+
+    /// Adds a descriptor chain to the available ring, with the scratch space
+    /// of its head descriptor as the first buffer, followed by `buffers`.
+    ///
+    /// Returns the head descriptor index, or `None` if there are not enough
+    /// free descriptors. The backend must be notified separately.
+    pub fn push(
+        &mut self,
+        scratch_write: bool,
+        buffers: impl ExactSizeIterator<Item = Buffer>,
+    ) -> Result<Option<u16>, RingError> {
+        if self.free.len() < buffers.len() + 1 {
+            return Ok(None);
+        }
+        let head = self.free.pop().unwrap();
+        let scratch = Buffer {
+            addr: self.scratch_addr(head),
+            len: self.layout.scratch_len,
+            write: scratch_write,
+        };
+        let mut index = head;
+        let mut buffers = std::iter::once(scratch).chain(buffers).peekable();
+        while let Some(buffer) = buffers.next() {
+            let next = if buffers.peek().is_some() {
+                Some(self.free.pop().unwrap())
+            } else {
+                None
+            };
+            self.next[index as usize] = next;
+            let desc = Descriptor {
+                address: buffer.addr.into(),
+                length: buffer.len.into(),
+                flags_raw: u16::from(
+                    DescriptorFlags::new()
+                        .with_next(next.is_some())
+                        .with_write(buffer.write),
+                )
+                .into(),
+                next: next.unwrap_or(0).into(),
+            };
+            self.mem
+                .write_plain(
+                    self.layout.descriptors + index as u64 * size_of::<Descriptor>() as u64,
+                    &desc,
+                )
+                .map_err(RingError::Memory)?;
+            if let Some(next) = next {
+                index = next;
+            }
+        }
+
+        let slot = self.avail_idx % RING_SIZE;
+        self.mem
+            .write_plain(
+                self.layout.available + AVAIL_OFFSET_RING + slot as u64 * 2,
+                &head,
+            )
+            .map_err(RingError::Memory)?;
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        // Publish the descriptors and ring entry before the index.
+        fence(Ordering::Release);
+        self.mem
+            .write_plain(self.layout.available + AVAIL_OFFSET_IDX, &self.avail_idx)
+            .map_err(RingError::Memory)?;
+        Ok(Some(head))
+    }
+
+    /// Removes the next chain from the used ring, returning its head
+    /// descriptor index and the number of bytes the backend wrote to it.
+    pub fn pop_used(&mut self) -> Result<Option<(u16, u32)>, RingError> {
+        let used_idx: u16 = self
+            .mem
+            .read_plain(self.layout.used + USED_OFFSET_IDX)
+            .map_err(RingError::Memory)?;
+        if used_idx == self.last_used_idx {
+            return Ok(None);
+        }
+        // Read the ring entry only after observing the index.
+        fence(Ordering::Acquire);
+        let slot = self.last_used_idx % RING_SIZE;
+        let element: UsedElement = self
+            .mem
+            .read_plain(
+                self.layout.used + USED_OFFSET_RING + slot as u64 * size_of::<UsedElement>() as u64,
+            )
+            .map_err(RingError::Memory)?;
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        let id = element.id.get();
+        let head = u16::try_from(id)
+            .ok()
+            .filter(|&head| head < RING_SIZE && !self.free.contains(&head))
+            .ok_or(RingError::InvalidDescriptor(id))?;
+        let mut index = Some(head);
+        while let Some(i) = index {
+            index = self.next[i as usize].take();
+            self.free.push(i);
+        }
+        Ok(Some((head, element.len.get())))
+    }
+
+    /// Returns whether the used ring has entries to pop.
+    pub fn has_used(&self) -> Result<bool, RingError> {
+        let used_idx: u16 = self
+            .mem
+            .read_plain(self.layout.used + USED_OFFSET_IDX)
+            .map_err(RingError::Memory)?;
+        Ok(used_idx != self.last_used_idx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Buffer;
+    use super::Ring;
+    use super::RingLayout;
+    use super::RING_SIZE;
+    use guestmem::GuestMemory;
+    use virtio::spec::queue::Descriptor;
+    use virtio::spec::queue::UsedElement;
+    use virtio::spec::queue::AVAIL_OFFSET_IDX;
+    use virtio::spec::queue::AVAIL_OFFSET_RING;
+    use virtio::spec::queue::USED_OFFSET_IDX;
+    use virtio::spec::queue::USED_OFFSET_RING;
+
+    #[test]
+    fn push_and_pop() {
+        let (layout, end) = RingLayout::new(0x1000, 12);
+        let mem = GuestMemory::allocate(end as usize);
+        let mut ring = Ring::new(mem.clone(), layout, 0x10000000).unwrap();
+
+        let head = ring
+            .push(
+                true,
+                [
+                    Buffer {
+                        addr: 0x2000,
+                        len: 100,
+                        write: true,
+                    },
+                    Buffer {
+                        addr: 0x3000,
+                        len: 200,
+                        write: true,
+                    },
+                ]
+                .into_iter(),
+            )
+            .unwrap()
+            .unwrap();
+        assert_eq!(ring.free_count(), RING_SIZE as usize - 3);
+
+        // Check the chain as the backend would see it.
+        assert_eq!(
+            mem.read_plain::<u16>(layout.available + AVAIL_OFFSET_IDX)
+                .unwrap(),
+            1
+        );
+        assert_eq!(
+            mem.read_plain::<u16>(layout.available + AVAIL_OFFSET_RING)
+                .unwrap(),
+            head
+        );
+        let mut index = head;
+        let mut chain = Vec::new();
+        loop {
+            let desc: Descriptor = mem
+                .read_plain(layout.descriptors + index as u64 * 16)
+                .unwrap();
+            chain.push((desc.address.get(), desc.length.get(), desc.flags().write()));
+            if !desc.flags().next() {
+                break;
+            }
+            index = desc.next.get();
+        }
+        assert_eq!(
+            chain,
+            [
+                (ring.scratch_addr(head), 12, true),
+                (0x2000, 100, true),
+                (0x3000, 200, true)
+            ]
+        );
+
+        assert!(!ring.has_used().unwrap());
+        mem.write_plain(
+            layout.used + USED_OFFSET_RING,
+            &UsedElement {
+                id: (head as u32).into(),
+                len: 112.into(),
+            },
+        )
+        .unwrap();
+        mem.write_plain(layout.used + USED_OFFSET_IDX, &1u16)
+            .unwrap();
+        assert_eq!(ring.pop_used().unwrap(), Some((head, 112)));
+        assert_eq!(ring.pop_used().unwrap(), None);
+        assert_eq!(ring.free_count(), RING_SIZE as usize);
+    }
+}
@@ -0,0 +1,209 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The XDP program that redirects received packets to AF_XDP sockets.
+
+// UNSAFETY: Calling the bpf syscall.
+#![allow(unsafe_code)]
+
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+const BPF_MAP_CREATE: libc::c_int = 0;
+const BPF_MAP_UPDATE_ELEM: libc::c_int = 2;
+const BPF_PROG_LOAD: libc::c_int = 5;
+const BPF_LINK_CREATE: libc::c_int = 28;
+
+const BPF_MAP_TYPE_XSKMAP: u32 = 17;
+const BPF_PROG_TYPE_XDP: u32 = 6;
+const BPF_XDP: u32 = 37;
+
+const BPF_PSEUDO_MAP_FD: u8 = 1;
+const BPF_FUNC_REDIRECT_MAP: i32 = 51;
+const XDP_PASS: i32 = 2;
+
+/// The offset of `rx_queue_index` in `struct xdp_md`.
+const XDP_MD_RX_QUEUE_INDEX: i16 = 16;
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+struct MapCreateAttr {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+struct MapUpdateAttr {
+    map_fd: u32,
+    padding: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+struct LinkCreateAttr {
+    prog_fd: u32,
+    target_ifindex: u32,
+    attach_type: u32,
+    flags: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes)]
+struct Insn {
+    code: u8,
+    /// The destination register in the low nibble, the source register in
+    /// the high nibble.
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+impl Insn {
+    const fn new(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> Self {
+        Self {
+            code,
+            regs: dst | (src << 4),
+            off,
+            imm,
+        }
+    }
+}
+
+/// Issues bpf command `cmd`.
+fn bpf<T: AsBytes + FromBytes>(cmd: libc::c_int, attr: &mut T) -> io::Result<libc::c_long> {
+    let attr = attr.as_bytes_mut();
+    // SAFETY: `attr` is a valid attribute structure for `cmd`, and any
+    // pointers within it are valid for the duration of the call.
+    let r = unsafe { libc::syscall(libc::SYS_bpf, cmd, attr.as_mut_ptr(), attr.len()) };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(r)
+}
+
+/// Issues bpf command `cmd`, which returns a new file descriptor.
+fn bpf_fd<T: AsBytes + FromBytes>(cmd: libc::c_int, attr: &mut T) -> io::Result<OwnedFd> {
+    let fd = bpf(cmd, attr)?;
+    // SAFETY: the command returned a new file descriptor, which is now owned
+    // here.
+    Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+}
+
+/// An XDP program attached to an interface, which redirects packets received
+/// on each queue to the AF_XDP socket in the corresponding entry of an
+/// `XSKMAP`.
+///
+/// Packets received on queues without a socket are passed to the network
+/// stack as usual. The program is detached when this is dropped.
+pub struct Redirect {
+    map: OwnedFd,
+    _prog: OwnedFd,
+    _link: OwnedFd,
+}
+
+impl Redirect {
+    /// Attaches a new program to interface `ifindex`, with room for sockets
+    /// for `queue_count` queues.
+    pub fn attach(ifindex: u32, queue_count: u32) -> io::Result<Self> {
+        let map = bpf_fd(
+            BPF_MAP_CREATE,
+            &mut MapCreateAttr {
+                map_type: BPF_MAP_TYPE_XSKMAP,
+                key_size: 4,
+                value_size: 4,
+                max_entries: queue_count,
+                map_flags: 0,
+            },
+        )?;
+
+        // return bpf_redirect_map(&map, ctx->rx_queue_index, XDP_PASS);
+        let insns = [
+            // r2 = *(u32 *)(r1 + rx_queue_index)
+            Insn::new(0x61, 2, 1, XDP_MD_RX_QUEUE_INDEX, 0),
+            // r1 = map (64-bit immediate, two instructions)
+            Insn::new(0x18, 1, BPF_PSEUDO_MAP_FD, 0, map.as_raw_fd()),
+            Insn::new(0, 0, 0, 0, 0),
+            // r3 = XDP_PASS
+            Insn::new(0xb7, 3, 0, 0, XDP_PASS),
+            // call bpf_redirect_map
+            Insn::new(0x85, 0, 0, 0, BPF_FUNC_REDIRECT_MAP),
+            // exit
+            Insn::new(0x95, 0, 0, 0, 0),
+        ];
+        let license = c"GPL";
+        let prog = bpf_fd(
+            BPF_PROG_LOAD,
+            &mut ProgLoadAttr {
+                prog_type: BPF_PROG_TYPE_XDP,
+                insn_cnt: insns.len() as u32,
+                insns: insns.as_ptr() as u64,
+                license: license.as_ptr() as u64,
+                log_level: 0,
+                log_size: 0,
+                log_buf: 0,
+                kern_version: 0,
+                prog_flags: 0,
+            },
+        )?;
+
+        // Let the kernel choose between native and generic mode.
+        let link = bpf_fd(
+            BPF_LINK_CREATE,
+            &mut LinkCreateAttr {
+                prog_fd: prog.as_raw_fd() as u32,
+                target_ifindex: ifindex,
+                attach_type: BPF_XDP,
+                flags: 0,
+            },
+        )?;
+
+        Ok(Self {
+            map,
+            _prog: prog,
+            _link: link,
+        })
+    }
+
+    /// Redirects packets received on `queue` to `socket`.
+    pub fn insert(&self, queue: u32, socket: BorrowedFd<'_>) -> io::Result<()> {
+        let value = socket.as_raw_fd() as u32;
+        bpf(
+            BPF_MAP_UPDATE_ELEM,
+            &mut MapUpdateAttr {
+                map_fd: self.map.as_raw_fd() as u32,
+                padding: 0,
+                key: std::ptr::from_ref(&queue) as u64,
+                value: std::ptr::from_ref(&value) as u64,
+                flags: 0,
+            },
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,510 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! An endpoint based on AF_XDP sockets bound to a host network interface.
+//!
+//! Guest RAM is registered as the sockets' UMEM, so transmitted packets are
+//! passed to the kernel directly from guest memory, without a copy or a
+//! syscall per packet. Received packets land in a small set of host-owned
+//! frames at the end of the UMEM, and are copied to the guest's receive
+//! buffers, since the kernel may write up to a full frame into each buffer.
+//!
+//! An XDP program is attached to the interface to redirect packets received
+//! on queues `0..queue_count` to the sockets. The interface should be
+//! configured (e.g. with `ethtool -L`) to use only those queues, since packets
+//! received on other queues are passed to the host network stack instead.
+//!
+//! This requires `CAP_NET_ADMIN` and `CAP_BPF` (or `CAP_SYS_ADMIN`), and
+//! `CAP_IPC_LOCK` or a sufficient `RLIMIT_MEMLOCK` to pin guest RAM.
+
+#![cfg(target_os = "linux")]
+
+mod bpf;
+pub mod resolver;
+mod xsk;
+
+use async_trait::async_trait;
+use inspect::InspectMut;
+use net_backend::linearize;
+use net_backend::BufferAccess;
+use net_backend::Endpoint;
+use net_backend::MultiQueueSupport;
+use net_backend::Queue;
+use net_backend::QueueConfig;
+use net_backend::RssConfig;
+use net_backend::RxId;
+use net_backend::RxMetadata;
+use net_backend::TxId;
+use net_backend::TxSegment;
+use pal_async::driver::Driver;
+use pal_async::interest::InterestSlot;
+use pal_async::interest::PollEvents;
+use pal_async::socket::PolledSocket;
+use pal_async::timer::PolledTimer;
+use parking_lot::Mutex;
+use shared_memory_resources::SharedGuestMemory;
+use shared_memory_resources::SharedMemoryRegion;
+use sparse_mmap::SparseMapping;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::os::fd::AsFd;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to find interface {0}")]
+    Interface(String, #[source] io::Error),
+    #[error("failed to map guest memory for the UMEM")]
+    Umem(#[source] io::Error),
+    #[error("failed to attach the XDP program to the interface")]
+    Attach(#[source] io::Error),
+    #[error("failed to create the AF_XDP socket for queue {0}")]
+    Socket(u32, #[source] io::Error),
+}
+
+/// The size of the indirection table reported to the guest. The host NIC
+/// steers received packets to queues by its own RSS configuration, so the
+/// guest's table is not used.
+const INDIRECTION_TABLE_SIZE: u16 = 128;
+
+/// The number of entries in each ring.
+const RING_SIZE: u32 = 512;
+
+/// The size of each UMEM frame, which bounds the size of each packet.
+const FRAME_SIZE: u32 = 4096;
+
+/// How often to check for tx completions while packets are in flight, since
+/// the kernel does not signal them.
+const TX_COMPLETION_POLL_INTERVAL: Duration = Duration::from_micros(100);
+
+/// Guest RAM followed by the host-owned frames, registered as the UMEM.
+struct Umem {
+    mapping: SparseMapping,
+    regions: Vec<SharedMemoryRegion>,
+    /// The UMEM address of the first host-owned frame.
+    frames: u64,
+}
+
+impl Umem {
+    fn new(memory: &SharedGuestMemory, queue_count: u32) -> io::Result<Self> {
+        let ram_len = memory
+            .regions
+            .iter()
+            .map(|region| region.offset + region.range.len())
+            .max()
+            .unwrap_or(0)
+            .next_multiple_of(SparseMapping::page_size() as u64);
+        let frames_len = queue_count as u64 * 2 * RING_SIZE as u64 * FRAME_SIZE as u64;
+        let mapping = SparseMapping::new((ram_len + frames_len) as usize)?;
+        mapping.map_file(0, ram_len as usize, &*memory.fd, 0, true)?;
+        mapping.alloc(ram_len as usize, frames_len as usize)?;
+        Ok(Self {
+            mapping,
+            regions: memory.regions.clone(),
+            frames: ram_len,
+        })
+    }
+
+    fn config(&self) -> xsk::UmemConfig {
+        xsk::UmemConfig {
+            addr: self.mapping.as_ptr().cast(),
+            len: self.mapping.len(),
+            chunk_size: FRAME_SIZE,
+        }
+    }
+
+    /// Returns the UMEM address of `len` bytes of guest RAM at `gpa`, if they
+    /// are contiguous in the UMEM.
+    fn translate(&self, gpa: u64, len: u64) -> Option<u64> {
+        let region = self
+            .regions
+            .iter()
+            .find(|region| region.range.contains_addr(gpa))?;
+        (gpa + len <= region.range.end()).then(|| region.offset + (gpa - region.range.start()))
+    }
+
+    /// Returns the UMEM address of a packet's data, if it is contiguous in
+    /// the UMEM.
+    fn translate_packet(&self, segments: &[TxSegment]) -> Option<u64> {
+        let mut start = None;
+        let mut end = 0;
+        for segment in segments {
+            let addr = self.translate(segment.gpa, segment.len.into())?;
+            if start.is_some() && addr != end {
+                return None;
+            }
+            start.get_or_insert(addr);
+            end = addr + segment.len as u64;
+        }
+        start
+    }
+
+    /// Returns the UMEM address of host-owned frame `index`.
+    fn frame(&self, index: u32) -> u64 {
+        self.frames + index as u64 * FRAME_SIZE as u64
+    }
+}
+
+/// The state of a queue that persists across queue restarts.
+struct QueueState {
+    socket: xsk::Socket,
+    /// Free host-owned frames for transmitting packets that are not
+    /// contiguous in guest RAM.
+    tx_frames: Vec<u64>,
+    /// The guest packet for each in-flight tx descriptor, in submission
+    /// order. `None` if the packet was already completed to the guest.
+    tx_inflight: VecDeque<Option<TxId>>,
+}
+
+/// An endpoint based on AF_XDP sockets.
+pub struct XdpEndpoint {
+    // Detach the program before closing the sockets.
+    _redirect: bpf::Redirect,
+    interface: String,
+    queues: Vec<Arc<Mutex<Option<QueueState>>>>,
+    umem: Arc<Umem>,
+}
+
+impl XdpEndpoint {
+    /// Binds to queues `0..queue_count` of `interface`, sharing guest RAM in
+    /// `memory` with the kernel.
+    pub fn new(
+        interface: &str,
+        queue_count: u16,
+        memory: &SharedGuestMemory,
+    ) -> Result<Self, Error> {
+        let queue_count = queue_count.max(1) as u32;
+        let ifindex = xsk::interface_index(interface)
+            .map_err(|err| Error::Interface(interface.to_owned(), err))?;
+        let umem = Arc::new(Umem::new(memory, queue_count).map_err(Error::Umem)?);
+        let redirect = bpf::Redirect::attach(ifindex, queue_count).map_err(Error::Attach)?;
+
+        let mut sockets = Vec::<xsk::Socket>::new();
+        for queue_id in 0..queue_count {
+            let config = umem.config();
+            let umem_source = match sockets.first() {
+                None => xsk::Umem::Register(&config),
+                Some(first) => xsk::Umem::Share(first),
+            };
+            let mut socket = xsk::Socket::new(ifindex, queue_id, RING_SIZE, umem_source)
+                .map_err(|err| Error::Socket(queue_id, err))?;
+
+            // The first half of the queue's frames are for receiving.
+            let first_frame = queue_id * 2 * RING_SIZE;
+            for i in 0..RING_SIZE {
+                socket.fill(umem.frame(first_frame + i));
+            }
+            socket.publish_fill();
+
+            redirect
+                .insert(queue_id, socket.as_fd())
+                .map_err(|err| Error::Socket(queue_id, err))?;
+            sockets.push(socket);
+        }
+
+        let queues = sockets
+            .into_iter()
+            .enumerate()
+            .map(|(queue_id, socket)| {
+                let first_frame = queue_id as u32 * 2 * RING_SIZE + RING_SIZE;
+                Arc::new(Mutex::new(Some(QueueState {
+                    socket,
+                    tx_frames: (0..RING_SIZE)
+                        .map(|i| umem.frame(first_frame + i))
+                        .collect(),
+                    tx_inflight: VecDeque::new(),
+                })))
+            })
+            .collect();
+
+        Ok(Self {
+            _redirect: redirect,
+            interface: interface.to_owned(),
+            queues,
+            umem,
+        })
+    }
+}
+
+impl InspectMut for XdpEndpoint {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        req.respond()
+            .field("interface", &self.interface)
+            .field("queues", self.queues.len());
+    }
+}
+
+#[async_trait]
+impl Endpoint for XdpEndpoint {
+    fn endpoint_type(&self) -> &'static str {
+        "xdp"
+    }
+
+    async fn get_queues(
+        &mut self,
+        config: Vec<QueueConfig<'_>>,
+        _rss: Option<&RssConfig<'_>>,
+        queues: &mut Vec<Box<dyn Queue>>,
+    ) -> anyhow::Result<()> {
+        assert!(config.len() <= self.queues.len());
+        for (config, slot) in config.into_iter().zip(&self.queues) {
+            queues.push(Box::new(XdpQueue::new(
+                config.driver.as_ref(),
+                slot.clone(),
+                self.umem.clone(),
+                config.pool,
+                config.initial_rx,
+            )?));
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self) {
+        for slot in &self.queues {
+            assert!(slot.lock().is_some(), "queue has not been dropped");
+        }
+    }
+
+    fn multiqueue_support(&self) -> MultiQueueSupport {
+        MultiQueueSupport {
+            max_queues: self.queues.len() as u16,
+            indirection_table_size: INDIRECTION_TABLE_SIZE,
+        }
+    }
+}
+
+struct XdpQueue {
+    slot: Arc<Mutex<Option<QueueState>>>,
+    socket: Option<PolledSocket<xsk::Socket>>,
+    tx_frames: Vec<u64>,
+    tx_inflight: VecDeque<Option<TxId>>,
+    umem: Arc<Umem>,
+    pool: Box<dyn BufferAccess>,
+    rx_free: VecDeque<RxId>,
+    rx_ready: VecDeque<RxId>,
+    tx_done: VecDeque<TxId>,
+    timer: PolledTimer,
+    buffer: Box<[u8]>,
+}
+
+impl InspectMut for XdpQueue {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        req.respond()
+            .field("rx_free", self.rx_free.len())
+            .field("rx_ready", self.rx_ready.len())
+            .field("tx_frames", self.tx_frames.len())
+            .field("tx_inflight", self.tx_inflight.len())
+            .field("tx_done", self.tx_done.len());
+    }
+}
+
+impl Drop for XdpQueue {
+    fn drop(&mut self) {
+        if let Some(socket) = self.socket.take() {
+            *self.slot.lock() = Some(QueueState {
+                socket: socket.into_inner(),
+                tx_frames: std::mem::take(&mut self.tx_frames),
+                tx_inflight: std::mem::take(&mut self.tx_inflight),
+            });
+        }
+    }
+}
+
+impl XdpQueue {
+    fn new(
+        driver: &dyn Driver,
+        slot: Arc<Mutex<Option<QueueState>>>,
+        umem: Arc<Umem>,
+        pool: Box<dyn BufferAccess>,
+        initial_rx: &[RxId],
+    ) -> io::Result<Self> {
+        let QueueState {
+            socket,
+            tx_frames,
+            mut tx_inflight,
+        } = slot.lock().take().expect("queue is already in use");
+        // Packets still in flight from a previous instance of the queue were
+        // abandoned by their owner.
+        tx_inflight.iter_mut().for_each(|id| *id = None);
+        Ok(Self {
+            slot,
+            socket: Some(PolledSocket::new(driver, socket)?),
+            tx_frames,
+            tx_inflight,
+            umem,
+            pool,
+            rx_free: initial_rx.iter().copied().collect(),
+            rx_ready: VecDeque::new(),
+            tx_done: VecDeque::new(),
+            timer: PolledTimer::new(driver),
+            buffer: vec![0; FRAME_SIZE as usize].into(),
+        })
+    }
+
+    fn process_completions(&mut self) {
+        let socket = self.socket.as_mut().unwrap().get_mut();
+        while let Some(addr) = socket.completion() {
+            if let Some(Some(id)) = self.tx_inflight.pop_front() {
+                self.tx_done.push_back(id);
+            }
+            if addr >= self.umem.frames {
+                self.tx_frames.push(addr);
+            }
+        }
+    }
+
+    fn process_rx(&mut self, cx: &mut Context<'_>) {
+        let socket = self.socket.as_mut().unwrap();
+        let mut refilled = false;
+        while let Some(&rx) = self.rx_free.front() {
+            let Some(desc) = socket.get().rx_peek() else {
+                // Wait for more packets.
+                match socket.poll_io(cx, InterestSlot::Read, PollEvents::IN, |socket| {
+                    if socket.get().rx_peek().is_some() {
+                        Ok(())
+                    } else {
+                        Err(io::ErrorKind::WouldBlock.into())
+                    }
+                }) {
+                    Poll::Ready(Ok(())) => continue,
+                    Poll::Ready(Err(err)) => {
+                        tracing::warn!(
+                            error = &err as &dyn std::error::Error,
+                            "xdp socket poll failed"
+                        );
+                        break;
+                    }
+                    Poll::Pending => break,
+                }
+            };
+
+            let len = desc.len as usize;
+            let data = &mut self.buffer[..len.min(FRAME_SIZE as usize)];
+            if len <= data.len()
+                && len <= self.pool.capacity(rx) as usize
+                && self
+                    .umem
+                    .mapping
+                    .read_at(desc.data() as usize, data)
+                    .is_ok()
+            {
+                self.pool.write_packet(
+                    rx,
+                    &RxMetadata {
+                        offset: 0,
+                        len,
+                        ..Default::default()
+                    },
+                    data,
+                );
+                self.rx_ready.push_back(rx);
+                self.rx_free.pop_front();
+            }
+
+            let socket = socket.get_mut();
+            socket.rx_consume();
+            socket.fill(desc.buffer());
+            refilled = true;
+        }
+        if refilled {
+            socket.get_mut().publish_fill();
+        }
+    }
+}
+
+impl Queue for XdpQueue {
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        self.process_completions();
+        self.process_rx(cx);
+        if !self.rx_ready.is_empty() || !self.tx_done.is_empty() {
+            return Poll::Ready(());
+        }
+        if self.tx_inflight.iter().any(Option::is_some) {
+            // Check again for completions later.
+            if Pin::new(&mut self.timer.sleep(TX_COMPLETION_POLL_INTERVAL))
+                .poll(cx)
+                .is_ready()
+            {
+                cx.waker().wake_by_ref();
+            }
+        }
+        Poll::Pending
+    }
+
+    fn rx_avail(&mut self, done: &[RxId]) {
+        self.rx_free.extend(done);
+    }
+
+    fn rx_poll(&mut self, packets: &mut [RxId]) -> anyhow::Result<usize> {
+        let n = packets.len().min(self.rx_ready.len());
+        for (done, id) in packets[..n].iter_mut().zip(self.rx_ready.drain(..n)) {
+            *done = id;
+        }
+        Ok(n)
+    }
+
+    fn tx_avail(&mut self, segments: &[TxSegment]) -> anyhow::Result<(bool, usize)> {
+        let socket = self.socket.as_mut().unwrap().get_mut();
+        let mut rest = segments;
+        let mut sent = false;
+        while !rest.is_empty() && socket.tx_free() > 0 {
+            let (metadata, this, next) = net_backend::next_packet(rest);
+            let len = metadata.len as u32;
+            if len > FRAME_SIZE {
+                // Segmentation offload is not supported, so this is
+                // malformed. Drop it.
+                self.tx_done.push_back(metadata.id);
+            } else if let Some(addr) = self.umem.translate_packet(this) {
+                // Send directly from guest memory.
+                socket.tx(xsk::Desc {
+                    addr,
+                    len,
+                    options: 0,
+                });
+                self.tx_inflight.push_back(Some(metadata.id));
+                sent = true;
+            } else if let Some(frame) = self.tx_frames.pop() {
+                // Copy to a host-owned frame, after which the guest's buffer
+                // can be released.
+                let mut packet = this;
+                let data = linearize(self.pool.as_ref(), &mut packet)?;
+                self.umem.mapping.write_at(frame as usize, &data)?;
+                socket.tx(xsk::Desc {
+                    addr: frame,
+                    len,
+                    options: 0,
+                });
+                self.tx_inflight.push_back(None);
+                self.tx_done.push_back(metadata.id);
+                sent = true;
+            } else {
+                // Wait for frames to be freed.
+                break;
+            }
+            rest = next;
+        }
+        if sent {
+            socket.publish_tx();
+        }
+        Ok((false, segments.len() - rest.len()))
+    }
+
+    fn tx_poll(&mut self, done: &mut [TxId]) -> anyhow::Result<usize> {
+        self.process_completions();
+        let n = done.len().min(self.tx_done.len());
+        for (done, id) in done[..n].iter_mut().zip(self.tx_done.drain(..n)) {
+            *done = id;
+        }
+        Ok(n)
+    }
+
+    fn buffer_access(&mut self) -> Option<&mut dyn BufferAccess> {
+        Some(self.pool.as_mut())
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use crate::XdpEndpoint;
+use async_trait::async_trait;
+use net_backend::resolve::ResolveEndpointParams;
+use net_backend::resolve::ResolvedEndpoint;
+use net_backend_resources::xdp::XdpHandle;
+use shared_memory_resources::SharedGuestMemoryHandleKind;
+use thiserror::Error;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::NetEndpointHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::IntoResource;
+use vm_resource::PlatformResource;
+use vm_resource::ResolveError;
+use vm_resource::ResourceResolver;
+
+pub struct XdpResolver;
+
+declare_static_async_resolver! {
+    XdpResolver,
+    (NetEndpointHandleKind, XdpHandle),
+}
+
+#[derive(Debug, Error)]
+pub enum ResolveXdpError {
+    #[error("guest memory cannot be shared with the kernel")]
+    SharedMemory(#[source] ResolveError),
+    #[error("failed to create the xdp endpoint")]
+    Endpoint(#[source] super::Error),
+}
+
+#[async_trait]
+impl AsyncResolveResource<NetEndpointHandleKind, XdpHandle> for XdpResolver {
+    type Output = ResolvedEndpoint;
+    type Error = ResolveXdpError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        resource: XdpHandle,
+        _input: ResolveEndpointParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let memory = resolver
+            .resolve::<SharedGuestMemoryHandleKind, _>(PlatformResource.into_resource(), ())
+            .await
+            .map_err(ResolveXdpError::SharedMemory)?;
+        let endpoint = XdpEndpoint::new(&resource.interface, resource.queue_count, &memory)
+            .map_err(ResolveXdpError::Endpoint)?;
+        Ok(endpoint.into())
+    }
+}
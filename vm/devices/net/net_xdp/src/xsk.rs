@@ -0,0 +1,493 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! AF_XDP sockets and their shared memory rings.
+
+// UNSAFETY: Creating and configuring AF_XDP sockets, and accessing the rings
+// the kernel maps into the process.
+#![allow(unsafe_code)]
+
+use std::ffi::CString;
+use std::io;
+use std::os::fd::AsFd;
+use std::os::fd::AsRawFd;
+use std::os::fd::BorrowedFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::ptr::NonNull;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+const AF_XDP: libc::c_int = 44;
+const SOL_XDP: libc::c_int = 283;
+
+const XDP_MMAP_OFFSETS: libc::c_int = 1;
+const XDP_RX_RING: libc::c_int = 2;
+const XDP_TX_RING: libc::c_int = 3;
+const XDP_UMEM_REG: libc::c_int = 4;
+const XDP_UMEM_FILL_RING: libc::c_int = 5;
+const XDP_UMEM_COMPLETION_RING: libc::c_int = 6;
+
+const XDP_PGOFF_RX_RING: libc::off_t = 0;
+const XDP_PGOFF_TX_RING: libc::off_t = 0x80000000;
+const XDP_UMEM_PGOFF_FILL_RING: libc::off_t = 0x100000000;
+const XDP_UMEM_PGOFF_COMPLETION_RING: libc::off_t = 0x180000000;
+
+const XDP_SHARED_UMEM: u16 = 1 << 0;
+const XDP_COPY: u16 = 1 << 1;
+const XDP_USE_NEED_WAKEUP: u16 = 1 << 3;
+
+const XDP_UMEM_UNALIGNED_CHUNK_FLAG: u32 = 1 << 0;
+const XDP_RING_NEED_WAKEUP: u32 = 1 << 0;
+
+/// In unaligned chunk mode, received descriptor addresses carry the offset of
+/// the packet within its buffer in the upper bits.
+const XSK_UNALIGNED_BUF_OFFSET_SHIFT: u32 = 48;
+const XSK_UNALIGNED_BUF_ADDR_MASK: u64 = (1 << XSK_UNALIGNED_BUF_OFFSET_SHIFT) - 1;
+
+#[repr(C)]
+#[derive(AsBytes)]
+struct UmemReg {
+    addr: u64,
+    len: u64,
+    chunk_size: u32,
+    headroom: u32,
+    flags: u32,
+    tx_metadata_len: u32,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, AsBytes, FromBytes, FromZeroes)]
+struct RingOffset {
+    producer: u64,
+    consumer: u64,
+    desc: u64,
+    flags: u64,
+}
+
+#[repr(C)]
+#[derive(Debug, Default, AsBytes, FromBytes, FromZeroes)]
+struct MmapOffsets {
+    rx: RingOffset,
+    tx: RingOffset,
+    fr: RingOffset,
+    cr: RingOffset,
+}
+
+#[repr(C)]
+#[derive(AsBytes)]
+struct SockaddrXdp {
+    family: u16,
+    flags: u16,
+    ifindex: u32,
+    queue_id: u32,
+    shared_umem_fd: u32,
+}
+
+/// An rx or tx ring descriptor.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct Desc {
+    /// The address of the packet in the UMEM.
+    pub addr: u64,
+    pub len: u32,
+    pub options: u32,
+}
+
+impl Desc {
+    /// Returns the address of the buffer the packet was received into, to
+    /// return to the fill ring.
+    pub fn buffer(&self) -> u64 {
+        self.addr & XSK_UNALIGNED_BUF_ADDR_MASK
+    }
+
+    /// Returns the address of the packet data.
+    pub fn data(&self) -> u64 {
+        self.buffer() + (self.addr >> XSK_UNALIGNED_BUF_OFFSET_SHIFT)
+    }
+}
+
+/// The UMEM registration parameters.
+pub struct UmemConfig {
+    /// The base address of the UMEM, which must be page aligned and remain
+    /// mapped for the lifetime of the socket.
+    pub addr: *mut u8,
+    pub len: usize,
+    /// The maximum size of each packet buffer.
+    pub chunk_size: u32,
+}
+
+/// Returns the index of network interface `name`.
+pub fn interface_index(name: &str) -> io::Result<u32> {
+    let name = CString::new(name).map_err(|_| io::ErrorKind::InvalidInput)?;
+    // SAFETY: passing a valid C string.
+    let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+    if index == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(index)
+}
+
+/// How a new socket gets its UMEM.
+pub enum Umem<'a> {
+    /// Register a new UMEM.
+    Register(&'a UmemConfig),
+    /// Share the UMEM registered with another socket.
+    Share(&'a Socket),
+}
+
+/// A ring mapped from a socket.
+struct Ring<T> {
+    mapping: NonNull<libc::c_void>,
+    mapping_len: usize,
+    producer: NonNull<AtomicU32>,
+    consumer: NonNull<AtomicU32>,
+    flags: NonNull<AtomicU32>,
+    descs: NonNull<T>,
+    size: u32,
+    /// The local copy of the producer index (for rings this process produces
+    /// to) or consumer index (for rings this process consumes from).
+    local: u32,
+}
+
+// SAFETY: the ring's memory is owned by the ring and accessed only through
+// `&mut self` or atomics.
+unsafe impl<T: Send> Send for Ring<T> {}
+
+impl<T: Copy> Ring<T> {
+    fn map(
+        fd: BorrowedFd<'_>,
+        offsets: &RingOffset,
+        page_offset: libc::off_t,
+        size: u32,
+        produce: bool,
+    ) -> io::Result<Self> {
+        let mapping_len = offsets.desc as usize + size as usize * size_of::<T>();
+        // SAFETY: mapping a new region of the socket's rings. The result is
+        // checked below.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                mapping_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd.as_raw_fd(),
+                page_offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let mapping = NonNull::new(ptr).unwrap();
+        // SAFETY: the offsets are within the mapping, as reported by the
+        // kernel.
+        let at = |offset: u64| unsafe { mapping.byte_add(offset as usize) };
+        let producer = at(offsets.producer).cast::<AtomicU32>();
+        let consumer = at(offsets.consumer).cast::<AtomicU32>();
+        // SAFETY: the indexes are aligned u32s within the mapping.
+        let local = unsafe {
+            if produce {
+                producer.as_ref().load(Ordering::Relaxed)
+            } else {
+                consumer.as_ref().load(Ordering::Relaxed)
+            }
+        };
+        Ok(Self {
+            mapping,
+            mapping_len,
+            producer,
+            consumer,
+            flags: at(offsets.flags).cast(),
+            descs: at(offsets.desc).cast(),
+            size,
+            local,
+        })
+    }
+
+    fn producer(&self) -> &AtomicU32 {
+        // SAFETY: the index is an aligned u32 within the mapping.
+        unsafe { self.producer.as_ref() }
+    }
+
+    fn consumer(&self) -> &AtomicU32 {
+        // SAFETY: the index is an aligned u32 within the mapping.
+        unsafe { self.consumer.as_ref() }
+    }
+
+    fn needs_wakeup(&self) -> bool {
+        // SAFETY: the flags are an aligned u32 within the mapping.
+        unsafe { self.flags.as_ref() }.load(Ordering::Relaxed) & XDP_RING_NEED_WAKEUP != 0
+    }
+
+    /// For a producer ring, returns the number of free entries.
+    fn free(&self) -> u32 {
+        self.size
+            - self
+                .local
+                .wrapping_sub(self.consumer().load(Ordering::Acquire))
+    }
+
+    /// For a producer ring, writes an entry without publishing it.
+    fn push(&mut self, value: T) {
+        assert!(self.free() > 0);
+        let index = self.local & (self.size - 1);
+        // SAFETY: the index is within the descriptor array, and the kernel
+        // does not access the entry until it is published.
+        unsafe { self.descs.add(index as usize).write_volatile(value) };
+        self.local = self.local.wrapping_add(1);
+    }
+
+    /// For a producer ring, publishes the entries written by `push`.
+    fn publish(&self) {
+        self.producer().store(self.local, Ordering::Release);
+    }
+
+    /// For a consumer ring, returns the next entry without consuming it.
+    fn peek(&self) -> Option<T> {
+        if self.producer().load(Ordering::Acquire) == self.local {
+            return None;
+        }
+        let index = self.local & (self.size - 1);
+        // SAFETY: the index is within the descriptor array, and the kernel
+        // does not modify the entry until it is consumed.
+        Some(unsafe { self.descs.add(index as usize).read_volatile() })
+    }
+
+    /// For a consumer ring, consumes the entry returned by `peek`.
+    fn consume(&mut self) {
+        self.local = self.local.wrapping_add(1);
+        self.consumer().store(self.local, Ordering::Release);
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        // SAFETY: unmapping the ring's own mapping, which is no longer
+        // referenced.
+        unsafe {
+            libc::munmap(self.mapping.as_ptr(), self.mapping_len);
+        }
+    }
+}
+
+/// An AF_XDP socket bound to an interface queue, with its own fill and
+/// completion rings.
+pub struct Socket {
+    // Drop the rings before closing the socket.
+    fill: Ring<u64>,
+    completion: Ring<u64>,
+    rx: Ring<Desc>,
+    tx: Ring<Desc>,
+    fd: OwnedFd,
+}
+
+fn setsockopt<T: AsBytes>(fd: BorrowedFd<'_>, name: libc::c_int, value: &T) -> io::Result<()> {
+    let value = value.as_bytes();
+    // SAFETY: passing a valid buffer for the option.
+    let r = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            SOL_XDP,
+            name,
+            value.as_ptr().cast(),
+            value.len() as libc::socklen_t,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+impl Socket {
+    /// Creates a socket bound to queue `queue_id` of interface `ifindex`,
+    /// with rings of `ring_size` entries.
+    ///
+    pub fn new(ifindex: u32, queue_id: u32, ring_size: u32, umem: Umem<'_>) -> io::Result<Self> {
+        assert!(ring_size.is_power_of_two());
+        // SAFETY: creating a new socket. The result is checked below.
+        let fd = unsafe { libc::socket(AF_XDP, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the socket is newly created and owned here.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        if let Umem::Register(umem) = umem {
+            setsockopt(
+                fd.as_fd(),
+                XDP_UMEM_REG,
+                &UmemReg {
+                    addr: umem.addr as u64,
+                    len: umem.len as u64,
+                    chunk_size: umem.chunk_size,
+                    headroom: 0,
+                    flags: XDP_UMEM_UNALIGNED_CHUNK_FLAG,
+                    tx_metadata_len: 0,
+                },
+            )?;
+        }
+        setsockopt(fd.as_fd(), XDP_UMEM_FILL_RING, &ring_size)?;
+        setsockopt(fd.as_fd(), XDP_UMEM_COMPLETION_RING, &ring_size)?;
+        setsockopt(fd.as_fd(), XDP_RX_RING, &ring_size)?;
+        setsockopt(fd.as_fd(), XDP_TX_RING, &ring_size)?;
+
+        let mut offsets = MmapOffsets::new_zeroed();
+        let mut len = size_of_val(&offsets) as libc::socklen_t;
+        // SAFETY: passing a valid buffer for the option.
+        let r = unsafe {
+            libc::getsockopt(
+                fd.as_raw_fd(),
+                SOL_XDP,
+                XDP_MMAP_OFFSETS,
+                offsets.as_bytes_mut().as_mut_ptr().cast(),
+                &mut len,
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        if len as usize != size_of_val(&offsets) {
+            // Kernels before 5.4 do not report the flags offsets.
+            return Err(io::ErrorKind::Unsupported.into());
+        }
+
+        let fill = Ring::map(
+            fd.as_fd(),
+            &offsets.fr,
+            XDP_UMEM_PGOFF_FILL_RING,
+            ring_size,
+            true,
+        )?;
+        let completion = Ring::map(
+            fd.as_fd(),
+            &offsets.cr,
+            XDP_UMEM_PGOFF_COMPLETION_RING,
+            ring_size,
+            false,
+        )?;
+        let rx = Ring::map(fd.as_fd(), &offsets.rx, XDP_PGOFF_RX_RING, ring_size, false)?;
+        let tx = Ring::map(fd.as_fd(), &offsets.tx, XDP_PGOFF_TX_RING, ring_size, true)?;
+
+        // Use copy mode even if the driver supports zero-copy, since
+        // zero-copy drivers reject buffers that cross pages that are not
+        // contiguous for DMA, and guest packets can be anywhere in RAM.
+        let flags = XDP_COPY | XDP_USE_NEED_WAKEUP;
+        let (flags, shared_umem_fd) = match umem {
+            Umem::Register(_) => (flags, 0),
+            Umem::Share(shared) => (flags | XDP_SHARED_UMEM, shared.fd.as_raw_fd() as u32),
+        };
+        let addr = SockaddrXdp {
+            family: AF_XDP as u16,
+            flags,
+            ifindex,
+            queue_id,
+            shared_umem_fd,
+        };
+        // SAFETY: passing a valid address.
+        let r = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                std::ptr::from_ref(&addr).cast(),
+                size_of_val(&addr) as libc::socklen_t,
+            )
+        };
+        if r < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            fill,
+            completion,
+            rx,
+            tx,
+            fd,
+        })
+    }
+
+    /// The number of free fill ring entries.
+    pub fn fill_free(&self) -> u32 {
+        self.fill.free()
+    }
+
+    /// Adds a buffer to the fill ring. Call [`Self::publish_fill`] to make it
+    /// available to the kernel.
+    pub fn fill(&mut self, addr: u64) {
+        self.fill.push(addr);
+    }
+
+    /// Makes buffers added to the fill ring available to the kernel, waking
+    /// it if necessary.
+    pub fn publish_fill(&mut self) {
+        self.fill.publish();
+        if self.fill.needs_wakeup() {
+            // SAFETY: a zero-length receive just wakes the kernel.
+            unsafe {
+                libc::recvfrom(
+                    self.fd.as_raw_fd(),
+                    std::ptr::null_mut(),
+                    0,
+                    libc::MSG_DONTWAIT,
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                );
+            }
+        }
+    }
+
+    /// Returns the next received packet, if any.
+    pub fn rx_peek(&self) -> Option<Desc> {
+        self.rx.peek()
+    }
+
+    /// Consumes the packet returned by [`Self::rx_peek`].
+    pub fn rx_consume(&mut self) {
+        self.rx.consume()
+    }
+
+    /// The number of free tx ring entries.
+    pub fn tx_free(&self) -> u32 {
+        self.tx.free()
+    }
+
+    /// Adds a packet to the tx ring. Call [`Self::publish_tx`] to send it.
+    pub fn tx(&mut self, desc: Desc) {
+        self.tx.push(desc);
+    }
+
+    /// Sends the packets added to the tx ring.
+    pub fn publish_tx(&mut self) {
+        self.tx.publish();
+        if self.tx.needs_wakeup() {
+            // SAFETY: a zero-length send just wakes the kernel. Errors such as
+            // EAGAIN and ENOBUFS are transient and are retried on the next
+            // send.
+            unsafe {
+                libc::sendto(
+                    self.fd.as_raw_fd(),
+                    std::ptr::null(),
+                    0,
+                    libc::MSG_DONTWAIT,
+                    std::ptr::null(),
+                    0,
+                );
+            }
+        }
+    }
+
+    /// Returns the address of the next completed tx packet, if any.
+    pub fn completion(&mut self) -> Option<u64> {
+        let addr = self.completion.peek()?;
+        self.completion.consume();
+        Some(addr)
+    }
+}
+
+impl AsFd for Socket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
@@ -53,10 +53,21 @@ pub fn new(buffers: Arc<GuestBuffers>) -> Self {
     }
 
     fn offset(&self, id: RxId) -> u32 {
-        id.0 * self.buffers.sub_allocation_size
+        self.buffers.offset(id)
     }
 }
 
+/// The location and checksum state of a packet in a receive buffer, as
+/// described by its RNDIS header.
+#[derive(Debug, Copy, Clone)]
+pub struct RxPacket {
+    /// The offset of the packet data from the start of the buffer.
+    pub offset: u32,
+    /// The length of the packet data.
+    pub len: u32,
+    pub checksum: rndisprot::RxTcpIpChecksumInfo,
+}
+
 impl GuestBuffers {
     pub fn new(
         mem: GuestMemory,
@@ -78,6 +89,22 @@ pub fn new(
         })
     }
 
+    fn offset(&self, id: RxId) -> u32 {
+        id.0 * self.sub_allocation_size
+    }
+
+    fn read_at(&self, offset: u32, mut buf: &mut [u8]) {
+        let mut offset = offset as usize;
+        while !buf.is_empty() {
+            let len = (PAGE_SIZE - offset % PAGE_SIZE).min(buf.len());
+            let (this, next) = buf.split_at_mut(len);
+            self.locked_pages.pages()[offset / PAGE_SIZE][offset % PAGE_SIZE..][..len]
+                .atomic_read(this);
+            buf = next;
+            offset += len;
+        }
+    }
+
     fn write_at(&self, offset: u32, mut buf: &[u8]) {
         let mut offset = offset as usize;
         while !buf.is_empty() {
@@ -89,6 +116,144 @@ fn write_at(&self, offset: u32, mut buf: &[u8]) {
             offset += len;
         }
     }
+
+    /// Reads back the location and checksum state of the packet in receive
+    /// buffer `id`.
+    ///
+    /// Returns `None` if the header is not one written by this module, which
+    /// can only happen if the guest modified a buffer it does not own.
+    pub fn read_packet(&self, id: RxId) -> Option<RxPacket> {
+        let mut header = RxHeader::new_zeroed();
+        self.read_at(self.offset(id), header.as_bytes_mut());
+        let offset = header
+            .packet
+            .data_offset
+            .checked_add(size_of::<rndisprot::MessageHeader>() as u32)?;
+        let end = offset.checked_add(header.packet.data_length)?;
+        if header.header.message_type != rndisprot::MESSAGE_TYPE_PACKET_MSG
+            || header.checksum.header.typ != rndisprot::PPI_TCP_IP_CHECKSUM
+            || offset < RX_HEADER_LEN
+            || end > self.sub_allocation_size - BROKEN_CO_NETVSC_FOOTER_LEN
+        {
+            return None;
+        }
+        Some(RxPacket {
+            offset,
+            len: header.packet.data_length,
+            checksum: header.checksum.checksum,
+        })
+    }
+
+    /// Reads data at `offset` within receive buffer `id`.
+    pub fn read_data(&self, id: RxId, offset: u32, data: &mut [u8]) {
+        assert!(offset as usize + data.len() <= self.sub_allocation_size as usize);
+        self.read_at(self.offset(id) + offset, data);
+    }
+
+    /// Writes data at `offset` within receive buffer `id`.
+    pub fn write_data(&self, id: RxId, offset: u32, data: &[u8]) {
+        assert!(offset as usize + data.len() <= self.sub_allocation_size as usize);
+        self.write_at(self.offset(id) + offset, data);
+    }
+
+    /// Rewrites the RNDIS header of receive buffer `id` to describe `packet`
+    /// as one fragment of a larger packet spanning multiple receive buffers.
+    pub fn write_fragment_header(
+        &self,
+        id: RxId,
+        packet: &RxPacket,
+        packet_id: rndisprot::PacketIdInfo,
+    ) {
+        #[repr(C)]
+        #[derive(zerocopy::AsBytes, Debug)]
+        struct Header {
+            header: RxHeader,
+            packet_id: PacketIdPpi,
+        }
+
+        #[repr(C)]
+        #[derive(zerocopy::AsBytes, Debug)]
+        struct PacketIdPpi {
+            header: rndisprot::PerPacketInfo,
+            packet_id: rndisprot::PacketIdInfo,
+        }
+
+        let header = Header {
+            header: self.rx_header(
+                packet.offset,
+                packet.len,
+                size_of::<PacketIdPpi>() as u32,
+                packet.checksum,
+            ),
+            packet_id: PacketIdPpi {
+                header: rndisprot::PerPacketInfo {
+                    size: size_of::<PacketIdPpi>() as u32,
+                    typ: rndisprot::PACKET_INFO_ID,
+                    per_packet_information_offset: size_of::<rndisprot::PerPacketInfo>() as u32,
+                },
+                packet_id,
+            },
+        };
+
+        self.write_at(self.offset(id), header.as_bytes());
+    }
+
+    /// Builds the RNDIS header for a packet at `offset` in a receive buffer,
+    /// with `extra_ppi_len` bytes of additional per-packet info following the
+    /// checksum info.
+    fn rx_header(
+        &self,
+        offset: u32,
+        len: u32,
+        extra_ppi_len: u32,
+        checksum: rndisprot::RxTcpIpChecksumInfo,
+    ) -> RxHeader {
+        RxHeader {
+            header: rndisprot::MessageHeader {
+                message_type: rndisprot::MESSAGE_TYPE_PACKET_MSG,
+                // Always claim the full suballocation length to avoid needing
+                // to track this more accurately. This needs to match the
+                // transfer page length but is not otherwise constrained for
+                // packet messages.
+                message_length: self.sub_allocation_size,
+            },
+            packet: rndisprot::Packet {
+                data_offset: offset - size_of::<rndisprot::MessageHeader>() as u32,
+                data_length: len,
+                oob_data_offset: 0,
+                oob_data_length: 0,
+                num_oob_data_elements: 0,
+                per_packet_info_offset: size_of::<rndisprot::Packet>() as u32,
+                per_packet_info_length: size_of::<ChecksumPpi>() as u32 + extra_ppi_len,
+                vc_handle: 0,
+                reserved: 0,
+            },
+            checksum: ChecksumPpi {
+                header: rndisprot::PerPacketInfo {
+                    size: size_of::<ChecksumPpi>() as u32,
+                    typ: rndisprot::PPI_TCP_IP_CHECKSUM,
+                    per_packet_information_offset: size_of::<rndisprot::PerPacketInfo>() as u32,
+                },
+                checksum,
+            },
+        }
+    }
+}
+
+/// The RNDIS header at the start of each receive buffer holding a packet.
+#[repr(C)]
+#[derive(zerocopy::AsBytes, zerocopy::FromBytes, FromZeroes, Debug)]
+struct RxHeader {
+    header: rndisprot::MessageHeader,
+    packet: rndisprot::Packet,
+    checksum: ChecksumPpi,
+}
+
+#[repr(C)]
+#[derive(zerocopy::AsBytes, zerocopy::FromBytes, FromZeroes, Debug)]
+struct ChecksumPpi {
+    header: rndisprot::PerPacketInfo,
+    checksum: rndisprot::RxTcpIpChecksumInfo,
 }
 
 // Reserve this many bytes for the RNDIS headers.
@@ -158,21 +323,6 @@ fn write_data(&mut self, id: RxId, data: &[u8]) {
     }
 
     fn write_header(&mut self, id: RxId, metadata: &RxMetadata) {
-        #[repr(C)]
-        #[derive(zerocopy::AsBytes, Debug)]
-        struct Header {
-            header: rndisprot::MessageHeader,
-            packet: rndisprot::Packet,
-            per_packet_info: PerPacketInfo,
-        }
-
-        #[repr(C)]
-        #[derive(zerocopy::AsBytes, Debug)]
-        struct PerPacketInfo {
-            header: rndisprot::PerPacketInfo,
-            checksum: rndisprot::RxTcpIpChecksumInfo,
-        }
-
         let checksum = rndisprot::RxTcpIpChecksumInfo::new_zeroed()
             .set_ip_checksum_failed(metadata.ip_checksum == RxChecksumState::Bad)
             .set_ip_checksum_succeeded(metadata.ip_checksum.is_valid())
@@ -198,36 +348,12 @@ struct PerPacketInfo {
                 metadata.l4_protocol == L4Protocol::Udp && metadata.l4_checksum.is_valid(),
             );
 
-        let header = Header {
-            header: rndisprot::MessageHeader {
-                message_type: rndisprot::MESSAGE_TYPE_PACKET_MSG,
-                // Always claim the full suballocation length to avoid needing
-                // to track this more accurately. This needs to match the
-                // transfer page length but is not otherwise constrained for
-                // packet messages.
-                message_length: self.buffers.sub_allocation_size,
-            },
-            packet: rndisprot::Packet {
-                data_offset: RX_HEADER_LEN - size_of::<rndisprot::MessageHeader>() as u32
-                    + metadata.offset as u32,
-                data_length: metadata.len as u32,
-                oob_data_offset: 0,
-                oob_data_length: 0,
-                num_oob_data_elements: 0,
-                per_packet_info_offset: size_of::<rndisprot::Packet>() as u32,
-                per_packet_info_length: size_of::<PerPacketInfo>() as u32,
-                vc_handle: 0,
-                reserved: 0,
-            },
-            per_packet_info: PerPacketInfo {
-                header: rndisprot::PerPacketInfo {
-                    size: size_of::<PerPacketInfo>() as u32,
-                    typ: rndisprot::PPI_TCP_IP_CHECKSUM,
-                    per_packet_information_offset: size_of::<rndisprot::PerPacketInfo>() as u32,
-                },
-                checksum,
-            },
-        };
+        let header = self.buffers.rx_header(
+            RX_HEADER_LEN + metadata.offset as u32,
+            metadata.len as u32,
+            0,
+            checksum,
+        );
 
         self.buffers.write_at(self.offset(id), header.as_bytes());
     }
@@ -9,6 +9,7 @@
 mod protocol;
 pub mod resolver;
 mod rndisprot;
+mod rsc;
 mod rx_bufs;
 mod saved_state;
 mod test;
@@ -57,6 +58,7 @@
 use pal_async::timer::Instant;
 use pal_async::timer::PolledTimer;
 use ring::gparange::MultiPagedRangeIter;
+use rsc::Rsc;
 use rx_bufs::RxBuffers;
 use rx_bufs::SubAllocationInUse;
 use std::cmp;
@@ -304,6 +306,7 @@ struct QueueState {
     queue: Box<dyn net_backend::Queue>,
     rx_buffer_range: RxBufferRange,
     target_vp_set: bool,
+    rsc: Option<Rsc>,
 }
 
 struct RxBufferRange {
@@ -433,6 +436,8 @@ struct QueueStats {
     rx_dropped_ring_full: Counter,
     spurious_wakes: Counter,
     rx_packets: Counter,
+    rx_rsc_packets: Counter,
+    rx_rsc_segments: Counter,
     tx_packets: Counter,
     tx_lso_packets: Counter,
     tx_checksum_packets: Counter,
@@ -634,6 +639,10 @@ struct OffloadConfig {
     lso4: bool,
     #[inspect(safe)]
     lso6: bool,
+    #[inspect(safe)]
+    rsc4: bool,
+    #[inspect(safe)]
+    rsc6: bool,
 }
 
 #[derive(Debug, Inspect, Clone)]
@@ -731,6 +740,8 @@ fn ndis_offload(&self) -> rndisprot::NdisOffload {
             },
             checksum,
             lso_v2,
+            rsc_ipv4: self.rsc4.into(),
+            rsc_ipv6: self.rsc6.into(),
             ..FromZeroes::new_zeroed()
         }
     }
@@ -833,6 +844,8 @@ fn restore(
             },
             lso4: offload_config.lso4,
             lso6: offload_config.lso6,
+            rsc4: offload_config.rsc4,
+            rsc6: offload_config.rsc6,
         };
 
         let pending_link_action = if let Some(pending) = pending_link_action {
@@ -942,6 +955,7 @@ pub struct Nic {
 pub struct NicBuilder {
     virtual_function: Option<Box<dyn VirtualFunction>>,
     limit_ring_buffer: bool,
+    enable_rsc: bool,
     max_queues: u16,
     get_guest_os_id: Option<Box<dyn Fn() -> HvGuestOsId + Send + Sync>>,
 }
@@ -952,6 +966,13 @@ pub fn limit_ring_buffer(mut self, limit: bool) -> Self {
         self
     }
 
+    /// Allows the guest to enable receive segment coalescing. This is off by
+    /// default.
+    pub fn enable_rsc(mut self, enable: bool) -> Self {
+        self.enable_rsc = enable;
+        self
+    }
+
     pub fn max_queues(mut self, max_queues: u16) -> Self {
         self.max_queues = max_queues;
         self
@@ -1004,7 +1025,9 @@ pub fn build(
         let tx_offloads = endpoint.tx_offload_support();
 
         // Always claim support for rx offloads since we can mark any given
-        // packet as having unknown checksum state.
+        // packet as having unknown checksum state. RSC is implemented here
+        // rather than by the endpoint, so it is also always supported unless
+        // disabled.
         let offload_support = OffloadConfig {
             checksum_rx: ChecksumOffloadConfig {
                 ipv4_header: true,
@@ -1022,6 +1045,8 @@ pub fn build(
             },
             lso4: tx_offloads.tso,
             lso6: tx_offloads.tso,
+            rsc4: self.enable_rsc,
+            rsc6: self.enable_rsc,
         };
 
         let driver = driver_source.simple();
@@ -1088,6 +1113,7 @@ pub fn builder() -> NicBuilder {
         NicBuilder {
             virtual_function: None,
             limit_ring_buffer: false,
+            enable_rsc: false,
             max_queues: !0,
             get_guest_os_id: None,
         }
@@ -1640,6 +1666,8 @@ fn saved_state(&self) -> saved_state::SavedState {
                         },
                         lso4: primary.offload_config.lso4,
                         lso6: primary.offload_config.lso6,
+                        rsc4: primary.offload_config.rsc4,
+                        rsc6: primary.offload_config.rsc6,
                     };
 
                     let control_messages = primary
@@ -3257,6 +3285,7 @@ fn handle_oid_set(
     ) -> Result<bool, OidError> {
         tracing::debug!(?oid, "oid set");
 
+        let rsc = (primary.offload_config.rsc4, primary.offload_config.rsc6);
         let mut restart_endpoint = false;
         match oid {
             rndisprot::Oid::OID_GEN_CURRENT_PACKET_FILTER => {
@@ -3287,6 +3316,8 @@ fn handle_oid_set(
                 return Err(OidError::UnknownOid);
             }
         }
+        // The queues must be restarted to start or stop coalescing.
+        restart_endpoint |= rsc != (primary.offload_config.rsc4, primary.offload_config.rsc6);
         Ok(restart_endpoint)
     }
 
@@ -3358,7 +3389,7 @@ fn oid_set_offload_parameters(
 
         tracing::debug!(?offload, "offload parameters");
         let rndisprot::NdisOffloadParameters {
-            header: _,
+            header,
             ipv4_checksum,
             tcp4_checksum,
             udp4_checksum,
@@ -3372,6 +3403,13 @@ fn oid_set_offload_parameters(
             tcp_connection_ipv6: _,
             reserved: _,
             flags: _,
+            ipsec_v2: _,
+            ipsec_v2_ipv4: _,
+            rsc_ipv4,
+            rsc_ipv6,
+            encapsulated_packet_task_offload: _,
+            encapsulation_types: _,
+            padding: _,
         } = offload;
 
         if lsov1 == rndisprot::OffloadParametersSimple::ENABLED {
@@ -3403,6 +3441,17 @@ fn oid_set_offload_parameters(
         if let Some(enable) = lsov2_ipv6.enable() {
             primary.offload_config.lso6 = enable && self.offload_support.lso6;
         }
+        // The RSC fields are only present in revision 3 and later.
+        if header.revision >= 3
+            && header.size as usize >= rndisprot::NDIS_SIZEOF_OFFLOAD_PARAMETERS_REVISION_3
+        {
+            if let Some(enable) = rsc_ipv4.enable() {
+                primary.offload_config.rsc4 = enable && self.offload_support.rsc4;
+            }
+            if let Some(enable) = rsc_ipv6.enable() {
+                primary.offload_config.rsc6 = enable && self.offload_support.rsc6;
+            }
+        }
         primary.pending_offload_change = true;
         Ok(())
     }
@@ -3489,6 +3538,12 @@ fn oid_set_rndis_config_parameter(
                         primary.offload_config.checksum_tx.udp6 = tx;
                         primary.offload_config.checksum_rx.udp6 = rx;
                     }
+                    "*RscIPv4" => {
+                        primary.offload_config.rsc4 = as_num != 0 && self.offload_support.rsc4;
+                    }
+                    "*RscIPv6" => {
+                        primary.offload_config.rsc6 = as_num != 0 && self.offload_support.rsc6;
+                    }
                     _ => {}
                 }
             }
@@ -4123,6 +4178,7 @@ async fn restart_queues(&mut self, c_state: &mut CoordinatorState) -> Result<(),
 
         let mut queues = Vec::new();
         let mut rx_buffers = Vec::new();
+        let mut rsc = None;
         {
             let buffers = &state.buffers;
             let guest_buffers = Arc::new(
@@ -4195,6 +4251,20 @@ async fn restart_queues(&mut self, c_state: &mut CoordinatorState) -> Result<(),
             let primary = state.state.primary.as_mut().unwrap();
             tracing::debug!(num_queues, "enabling endpoint");
 
+            // Coalesced packets span multiple receive buffers, which the guest
+            // must support.
+            let offload_config = &primary.offload_config;
+            if (offload_config.rsc4 || offload_config.rsc6)
+                && buffers.version >= Version::V61
+                && buffers.ndis_config.capabilities.rsc_over_vmbus()
+            {
+                rsc = Some((
+                    guest_buffers.clone(),
+                    offload_config.rsc4,
+                    offload_config.rsc6,
+                ));
+            }
+
             let rss = primary
                 .rss_state
                 .as_ref()
@@ -4226,6 +4296,9 @@ async fn restart_queues(&mut self, c_state: &mut CoordinatorState) -> Result<(),
                 queue,
                 target_vp_set: false,
                 rx_buffer_range: rx_buffer,
+                rsc: rsc
+                    .as_ref()
+                    .map(|(buffers, ipv4, ipv6)| Rsc::new(buffers.clone(), *ipv4, *ipv6)),
             });
         }
 
@@ -4714,7 +4787,7 @@ async fn main_loop(
             };
 
             let did_some_work = (!ring_full
-                && self.process_endpoint_rx(buffers, state, data, queue_state.queue.as_mut())?)
+                && self.process_endpoint_rx(buffers, state, data, queue_state)?)
                 | self.process_ring_buffer(buffers, state, data, queue_state)?
                 | (!ring_full
                     && self.process_endpoint_tx(state, data, queue_state.queue.as_mut())?)
@@ -4819,8 +4892,9 @@ fn process_endpoint_rx(
         buffers: &ChannelBuffers,
         state: &mut ActiveState,
         data: &mut ProcessingData,
-        epqueue: &mut dyn net_backend::Queue,
+        queue_state: &mut QueueState,
     ) -> Result<bool, WorkerError> {
+        let epqueue = queue_state.queue.as_mut();
         let n = epqueue
             .rx_poll(&mut data.rx_ready)
             .map_err(WorkerError::Endpoint)?;
@@ -4833,6 +4907,12 @@ fn process_endpoint_rx(
 
         state.rx_bufs.allocate(ready_ids.clone()).unwrap();
 
+        if let Some(rsc) = &mut queue_state.rsc {
+            let counts = rsc.coalesce(&data.rx_ready[..n]);
+            state.stats.rx_rsc_packets.add(counts.packets);
+            state.stats.rx_rsc_segments.add(counts.segments);
+        }
+
         // Always use the full suballocation size to avoid tracking the
         // message length. See RxBuf::header() for details.
         let len = buffers.recv_buffer.sub_allocation_size as usize;
@@ -36,11 +36,12 @@ async fn resolve(
                 resource.endpoint,
                 ResolveEndpointParams {
                     mac_address: resource.mac_address,
+                    driver_source: input.driver_source,
                 },
             )
             .await?;
 
-        let mut builder = Nic::builder();
+        let mut builder = Nic::builder().enable_rsc(resource.enable_rsc);
         if let Some(max_queues) = resource.max_queues {
             builder = builder.max_queues(max_queues);
         }
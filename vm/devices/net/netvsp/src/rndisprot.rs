@@ -539,7 +539,8 @@ pub struct PacketIdInfo {
     pub packet_id: u16,
 }
 
-const PACKET_INFO_ID: u16 = 1;
+/// The per-packet info type of [`PacketIdInfo`].
+pub const PACKET_INFO_ID: u32 = PPI_INTERNAL | 1;
 
 //
 //  Packet extension field contents associated with a Data message.
@@ -711,6 +712,9 @@ pub fn is_ipv6(self) -> bool {
 pub const PPI_TCP_IP_CHECKSUM: u32 = 0;
 pub const PPI_LSO: u32 = 2;
 
+/// Set in the per-packet info type for types internal to RNDIS over VMBus.
+pub const PPI_INTERNAL: u32 = 1 << 31;
+
 //
 //  Format of Information buffer passed in a SetRequest for the OID
 //  OID_GEN_RNDIS_CONFIG_PARAMETER.
@@ -883,8 +887,9 @@ pub struct NdisOffload {
     pub ipsec_v2: [u32; 8],
 
     // Receive Segment Coalescing information
-    pub rsc: u8,
-    pub reserved: [u8; 3],
+    pub rsc_ipv4: u8,
+    pub rsc_ipv6: u8,
+    pub reserved: [u8; 2],
 
     // NVGRE Encapsulated packet task offload information
     pub encapsulated_packet_task_offload_gre: [u32; 2],
@@ -1014,10 +1019,21 @@ pub struct NdisOffloadParameters {
     pub tcp_connection_ipv6: u8,
     pub reserved: u8,
     pub flags: u32,
+    pub ipsec_v2: u8,
+    pub ipsec_v2_ipv4: u8,
+    pub rsc_ipv4: OffloadParametersSimple,
+    pub rsc_ipv6: OffloadParametersSimple,
+    pub encapsulated_packet_task_offload: u8,
+    pub encapsulation_types: u8,
+    pub padding: [u8; 2],
 }
 
 pub const NDIS_SIZEOF_OFFLOAD_PARAMETERS_REVISION_1: usize = 20;
 
+pub const NDIS_SIZEOF_OFFLOAD_PARAMETERS_REVISION_3: usize =
+    std::mem::offset_of!(NdisOffloadParameters, encapsulation_types) + size_of::<u8>();
+const_assert_eq!(NDIS_SIZEOF_OFFLOAD_PARAMETERS_REVISION_3, 26);
+
 open_enum! {
     #[derive(AsBytes, FromBytes, FromZeroes)]
     pub enum OffloadParametersChecksum: u8 {
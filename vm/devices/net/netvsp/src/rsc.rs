@@ -0,0 +1,428 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Receive segment coalescing (RSC).
+//!
+//! Consecutive, in-order TCP segments of the same flow within a batch of
+//! received packets are indicated to the guest as a single large packet,
+//! reducing the guest's per-packet processing overhead.
+//!
+//! Since each receive buffer only holds an MTU-sized packet, the coalesced
+//! packet is described using RSC over VMBus: each segment stays in its own
+//! receive buffer, and the buffers are marked as fragments of one packet. The
+//! first fragment is the first segment with its IP length updated to cover
+//! the whole packet; each following fragment is just the TCP payload of the
+//! next segment. All the fragments must be sent in the same transfer page
+//! message.
+
+use crate::buffers::GuestBuffers;
+use crate::buffers::RxPacket;
+use crate::rndisprot;
+use net_backend::RxId;
+use std::sync::Arc;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const IPV4_HEADER_LEN: usize = 20;
+const IPV6_HEADER_LEN: usize = 40;
+const IP_PROTOCOL_TCP: u8 = 6;
+const TCP_MIN_HEADER_LEN: usize = 20;
+const TCP_MAX_HEADER_LEN: usize = 60;
+const TCP_FLAG_PSH: u8 = 0x08;
+const TCP_FLAG_ACK: u8 = 0x10;
+
+const MAX_HEADER_LEN: usize = ETHERNET_HEADER_LEN + IPV6_HEADER_LEN + TCP_MAX_HEADER_LEN;
+
+/// The largest IP length (or IPv6 payload length) of a coalesced packet.
+const MAX_IP_LEN: u32 = 0xffff;
+
+/// Per-queue RSC state.
+pub struct Rsc {
+    buffers: Arc<GuestBuffers>,
+    ipv4: bool,
+    ipv6: bool,
+    next_packet_id: u16,
+    group: Vec<Segment>,
+}
+
+/// The number of coalesced packets indicated, and the number of segments they
+/// contained.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct RscCounts {
+    pub packets: u64,
+    pub segments: u64,
+}
+
+#[derive(Clone)]
+struct Segment {
+    id: RxId,
+    packet: RxPacket,
+    headers: Headers,
+}
+
+#[derive(Clone)]
+struct Headers {
+    data: [u8; MAX_HEADER_LEN],
+    ipv6: bool,
+    /// The offset of the TCP header.
+    tcp_offset: usize,
+    /// The length of the Ethernet, IP, and TCP headers.
+    len: usize,
+    payload_len: u32,
+}
+
+impl Rsc {
+    /// Returns new RSC state for coalescing packets in `buffers`, for the IP
+    /// versions enabled by the guest.
+    pub fn new(buffers: Arc<GuestBuffers>, ipv4: bool, ipv6: bool) -> Self {
+        Self {
+            buffers,
+            ipv4,
+            ipv6,
+            next_packet_id: 0,
+            group: Vec::new(),
+        }
+    }
+
+    /// Coalesces the segments in `packets`, which are about to be sent to the
+    /// guest in order in a single transfer page message.
+    pub fn coalesce(&mut self, packets: &[RxId]) -> RscCounts {
+        let mut counts = RscCounts::default();
+        for &id in packets {
+            let segment = self.buffers.read_packet(id).and_then(|packet| {
+                let mut data = [0; MAX_HEADER_LEN];
+                let len = (packet.len as usize).min(MAX_HEADER_LEN);
+                self.buffers.read_data(id, packet.offset, &mut data[..len]);
+                let headers = Headers::parse(data, packet, self.ipv4, self.ipv6)?;
+                Some(Segment {
+                    id,
+                    packet,
+                    headers,
+                })
+            });
+
+            let Some(segment) = segment else {
+                self.flush(&mut counts);
+                continue;
+            };
+
+            if !self.group.is_empty() && !self.can_append(&segment.headers) {
+                self.flush(&mut counts);
+            }
+            let psh = segment.headers.flags() & TCP_FLAG_PSH != 0;
+            self.group.push(segment);
+            // A push ends the coalesced packet.
+            if psh {
+                self.flush(&mut counts);
+            }
+        }
+        self.flush(&mut counts);
+        counts
+    }
+
+    fn can_append(&self, next: &Headers) -> bool {
+        let first = &self.group[0].headers;
+        let payload_len: u32 = self.group.iter().map(|s| s.headers.payload_len).sum();
+        first.same_flow(next)
+            && next.seq() == first.seq().wrapping_add(payload_len)
+            && first.ip_len() + payload_len + next.payload_len <= MAX_IP_LEN
+    }
+
+    /// Rewrites the headers of the current group of segments, if there is
+    /// more than one, to indicate them as a single packet.
+    fn flush(&mut self, counts: &mut RscCounts) {
+        if self.group.len() > 1 {
+            let payload_len = self.group.iter().map(|s| s.headers.payload_len).sum();
+            let psh = self.group.last().unwrap().headers.flags() & TCP_FLAG_PSH;
+            let packet_id = self.next_packet_id;
+            self.next_packet_id = self.next_packet_id.wrapping_add(1);
+
+            let last = self.group.len() - 1;
+            for (i, segment) in self.group.iter().enumerate() {
+                let mut flags = rndisprot::PACKET_INFO_FLAGS_MULTI_SUBALLOC;
+                let packet = if i == 0 {
+                    flags |= rndisprot::PACKET_INFO_FLAGS_MULTI_SUBALLOC_FIRST_FRAGMENT;
+                    let mut headers = segment.headers.clone();
+                    headers.set_payload_len(payload_len);
+                    headers.data[headers.tcp_offset + 13] |= psh;
+                    self.buffers.write_data(
+                        segment.id,
+                        segment.packet.offset,
+                        &headers.data[..headers.len],
+                    );
+                    RxPacket {
+                        len: (segment.headers.len as u32) + segment.headers.payload_len,
+                        ..segment.packet
+                    }
+                } else {
+                    if i == last {
+                        flags |= rndisprot::PACKET_INFO_FLAGS_MULTI_SUBALLOC_LAST_FRAGMENT;
+                    }
+                    RxPacket {
+                        offset: segment.packet.offset + segment.headers.len as u32,
+                        len: segment.headers.payload_len,
+                        ..segment.packet
+                    }
+                };
+                self.buffers.write_fragment_header(
+                    segment.id,
+                    &packet,
+                    rndisprot::PacketIdInfo {
+                        version: rndisprot::PACKET_INFO_ID_VERSION_V1,
+                        flags,
+                        packet_id,
+                    },
+                );
+            }
+
+            counts.packets += 1;
+            counts.segments += self.group.len() as u64;
+        }
+        self.group.clear();
+    }
+}
+
+impl Headers {
+    /// Parses the headers of a TCP segment that is eligible for coalescing.
+    fn parse(data: [u8; MAX_HEADER_LEN], packet: RxPacket, ipv4: bool, ipv6: bool) -> Option<Self> {
+        // Only coalesce segments whose checksums have been validated, since
+        // the guest cannot validate the checksum of the coalesced packet.
+        let checksum = packet.checksum;
+        if !checksum.tcp_checksum_succeeded()
+            || checksum.tcp_checksum_failed()
+            || checksum.ip_checksum_failed()
+        {
+            return None;
+        }
+
+        let frame_len = packet.len as usize;
+        if frame_len < ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + TCP_MIN_HEADER_LEN {
+            return None;
+        }
+        let ip = ETHERNET_HEADER_LEN;
+        let (ipv6, tcp_offset, ip_end) = match u16::from_be_bytes([data[12], data[13]]) {
+            ETHERTYPE_IPV4 if ipv4 => {
+                // No IP options or fragmentation.
+                if data[ip] != 0x45
+                    || u16::from_be_bytes([data[ip + 6], data[ip + 7]]) & 0x3fff != 0
+                    || data[ip + 9] != IP_PROTOCOL_TCP
+                    || !checksum.ip_checksum_succeeded()
+                {
+                    return None;
+                }
+                let total_len = u16::from_be_bytes([data[ip + 2], data[ip + 3]]) as usize;
+                (false, ip + IPV4_HEADER_LEN, ip + total_len)
+            }
+            ETHERTYPE_IPV6 if ipv6 => {
+                // No extension headers.
+                if data[ip] >> 4 != 6 || data[ip + 6] != IP_PROTOCOL_TCP {
+                    return None;
+                }
+                let payload_len = u16::from_be_bytes([data[ip + 4], data[ip + 5]]) as usize;
+                (
+                    true,
+                    ip + IPV6_HEADER_LEN,
+                    ip + IPV6_HEADER_LEN + payload_len,
+                )
+            }
+            _ => return None,
+        };
+
+        let tcp_len = (data[tcp_offset + 12] >> 4) as usize * 4;
+        let len = tcp_offset + tcp_len;
+        if tcp_len < TCP_MIN_HEADER_LEN || ip_end > frame_len || len >= ip_end {
+            return None;
+        }
+
+        let headers = Self {
+            data,
+            ipv6,
+            tcp_offset,
+            len,
+            payload_len: (ip_end - len) as u32,
+        };
+
+        // Only coalesce data segments with no flags other than ACK and PSH.
+        if headers.flags() & !TCP_FLAG_PSH != TCP_FLAG_ACK {
+            return None;
+        }
+        Some(headers)
+    }
+
+    fn flags(&self) -> u8 {
+        self.data[self.tcp_offset + 13]
+    }
+
+    fn seq(&self) -> u32 {
+        u32::from_be_bytes(self.data[self.tcp_offset + 4..][..4].try_into().unwrap())
+    }
+
+    /// Returns the IPv4 total length or IPv6 payload length, excluding the
+    /// TCP payload.
+    fn ip_len(&self) -> u32 {
+        let ip_header_len = if self.ipv6 { 0 } else { IPV4_HEADER_LEN };
+        (ip_header_len + self.len - self.tcp_offset) as u32
+    }
+
+    /// Returns whether `other` is a segment of the same flow, with the same
+    /// IP and TCP header fields other than lengths, checksums, IP
+    /// identification, sequence number, and TCP flags.
+    fn same_flow(&self, other: &Self) -> bool {
+        if self.ipv6 != other.ipv6 || self.len != other.len {
+            return false;
+        }
+        let ip = ETHERNET_HEADER_LEN;
+        let tcp = self.tcp_offset;
+        let ignored = |i: usize| {
+            if i >= tcp {
+                // Sequence number, flags, and checksum.
+                matches!(i - tcp, 4..=7 | 13 | 16 | 17)
+            } else if i < ip {
+                false
+            } else if self.ipv6 {
+                // Payload length.
+                matches!(i - ip, 4 | 5)
+            } else {
+                // Total length, identification, and header checksum.
+                matches!(i - ip, 2..=5 | 10 | 11)
+            }
+        };
+        self.data[..self.len]
+            .iter()
+            .zip(&other.data[..self.len])
+            .enumerate()
+            .all(|(i, (a, b))| a == b || ignored(i))
+    }
+
+    /// Updates the IP length to cover `payload_len` bytes of TCP payload.
+    fn set_payload_len(&mut self, payload_len: u32) {
+        let ip = ETHERNET_HEADER_LEN;
+        let ip_len = (self.ip_len() + payload_len) as u16;
+        if self.ipv6 {
+            self.data[ip + 4..ip + 6].copy_from_slice(&ip_len.to_be_bytes());
+        } else {
+            self.data[ip + 2..ip + 4].copy_from_slice(&ip_len.to_be_bytes());
+            self.data[ip + 10..ip + 12].fill(0);
+            let checksum = ipv4_checksum(&self.data[ip..ip + IPV4_HEADER_LEN]);
+            self.data[ip + 10..ip + 12].copy_from_slice(&checksum.to_be_bytes());
+        }
+        self.payload_len = payload_len;
+    }
+}
+
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum = header
+        .chunks_exact(2)
+        .map(|c| u16::from_be_bytes([c[0], c[1]]) as u32)
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(seq: u32, flags: u8, payload_len: u16) -> Headers {
+        let mut data = [0; MAX_HEADER_LEN];
+        data[12..14].copy_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+        let ip = &mut data[ETHERNET_HEADER_LEN..];
+        ip[0] = 0x45;
+        ip[2..4].copy_from_slice(&(40 + payload_len).to_be_bytes());
+        ip[6] = 0x40;
+        ip[8] = 64;
+        ip[9] = IP_PROTOCOL_TCP;
+        ip[12..16].copy_from_slice(&[10, 0, 0, 1]);
+        ip[16..20].copy_from_slice(&[10, 0, 0, 2]);
+        let tcp = &mut ip[IPV4_HEADER_LEN..];
+        tcp[0..2].copy_from_slice(&80u16.to_be_bytes());
+        tcp[2..4].copy_from_slice(&50000u16.to_be_bytes());
+        tcp[4..8].copy_from_slice(&seq.to_be_bytes());
+        tcp[8..12].copy_from_slice(&1234u32.to_be_bytes());
+        tcp[12] = 5 << 4;
+        tcp[13] = flags;
+        tcp[14..16].copy_from_slice(&512u16.to_be_bytes());
+        Headers::parse(
+            data,
+            RxPacket {
+                offset: 0,
+                len: 54 + payload_len as u32,
+                checksum: rndisprot::RxTcpIpChecksumInfo(0)
+                    .set_ip_checksum_succeeded(true)
+                    .set_tcp_checksum_succeeded(true),
+            },
+            true,
+            true,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let headers = segment(1000, TCP_FLAG_ACK, 1448);
+        assert!(!headers.ipv6);
+        assert_eq!(headers.len, 54);
+        assert_eq!(headers.payload_len, 1448);
+        assert_eq!(headers.seq(), 1000);
+        assert_eq!(headers.ip_len(), 40);
+
+        // Pure ACKs and SYNs are not coalesced.
+        let mut data = headers.data;
+        data[16..18].copy_from_slice(&40u16.to_be_bytes());
+        let packet = RxPacket {
+            offset: 0,
+            len: 54,
+            checksum: rndisprot::RxTcpIpChecksumInfo(0)
+                .set_ip_checksum_succeeded(true)
+                .set_tcp_checksum_succeeded(true),
+        };
+        assert!(Headers::parse(data, packet, true, true).is_none());
+        let mut data = headers.data;
+        data[ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + 13] = 0x02;
+        assert!(Headers::parse(
+            data,
+            RxPacket {
+                len: 1502,
+                ..packet
+            },
+            true,
+            true
+        )
+        .is_none());
+
+        // Segments without a validated checksum are not coalesced.
+        let packet = RxPacket {
+            len: 1502,
+            checksum: rndisprot::RxTcpIpChecksumInfo(0).set_ip_checksum_succeeded(true),
+            ..packet
+        };
+        assert!(Headers::parse(headers.data, packet, true, true).is_none());
+    }
+
+    #[test]
+    fn test_same_flow() {
+        let a = segment(1000, TCP_FLAG_ACK, 1448);
+        let b = segment(2448, TCP_FLAG_ACK | TCP_FLAG_PSH, 100);
+        assert!(a.same_flow(&b));
+
+        let mut c = b.clone();
+        c.data[ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + 2] ^= 1;
+        assert!(!a.same_flow(&c));
+
+        let mut c = b.clone();
+        c.data[ETHERNET_HEADER_LEN + IPV4_HEADER_LEN + 8] ^= 1;
+        assert!(!a.same_flow(&c));
+    }
+
+    #[test]
+    fn test_set_payload_len() {
+        let mut headers = segment(1000, TCP_FLAG_ACK, 1448);
+        headers.set_payload_len(2896);
+        let ip = &headers.data[ETHERNET_HEADER_LEN..][..IPV4_HEADER_LEN];
+        assert_eq!(u16::from_be_bytes([ip[2], ip[3]]), 2936);
+        assert_eq!(ipv4_checksum(ip), 0);
+    }
+}
@@ -144,6 +144,10 @@ pub struct OffloadConfig {
     pub lso4: bool,
     #[mesh(4)]
     pub lso6: bool,
+    #[mesh(5)]
+    pub rsc4: bool,
+    #[mesh(6)]
+    pub rsc6: bool,
 }
 
 #[derive(Debug, Protobuf)]
@@ -26,6 +26,8 @@ pub struct NetvspHandle {
     /// Optionally, the maximum number of queues to expose to the guest. This
     /// will be further limited by the backend endpoint.
     pub max_queues: Option<u16>,
+    /// Allow the guest to enable receive segment coalescing.
+    pub enable_rsc: bool,
 }
 
 impl ResourceId<VmbusDeviceHandleKind> for NetvspHandle {
@@ -32,6 +32,8 @@
 use inspect::InspectMut;
 use inspect_counters::Counter;
 use serial_16550_resources::MmioOrIoPort;
+use serial_core::ModemControl;
+use serial_core::ModemStatus;
 use serial_core::SerialIo;
 use std::collections::VecDeque;
 use std::io::ErrorKind;
@@ -63,6 +65,11 @@ pub struct Serial16550 {
     interrupt: LineInterrupt,
     #[inspect(mut)]
     io: Box<dyn SerialIo>,
+    /// The modem status most recently reported by the backend.
+    backend_modem_status: ModemStatus,
+    /// The modem control lines most recently sent to the backend, or `None`
+    /// if they need to be resent.
+    sent_modem_control: Option<ModemControl>,
 
     // Volatile state
     state: State,
@@ -164,19 +171,36 @@ pub fn new(
             state: State::new(),
             interrupt,
             io,
+            backend_modem_status: ModemStatus::CONNECTED,
+            sent_modem_control: None,
             rx_waker: None,
             tx_waker: None,
             stats: Default::default(),
         };
         if this.io.is_connected() {
-            this.state.connect();
+            this.state.connect(this.backend_modem_status);
         }
         this.sync();
         Ok(this)
     }
 
-    /// Synchronize interrupt and waker state with device state.
+    /// Synchronize interrupt, waker, and backend modem state with device
+    /// state.
     fn sync(&mut self) {
+        let modem_control = self.state.modem_control();
+        if self.sent_modem_control != Some(modem_control) {
+            if self.io.supports_modem_lines() {
+                self.io.set_modem_control(modem_control);
+            } else if modem_control != ModemControl::default() {
+                tracing::debug!(
+                    port = self.debug_name,
+                    ?modem_control,
+                    "serial backend has no modem lines, ignoring modem control"
+                );
+            }
+            self.sent_modem_control = Some(modem_control);
+        }
+
         // Wake to poll if there are any bytes to write.
         if !self.state.tx_buffer.is_empty() {
             if let Some(waker) = self.tx_waker.take() {
@@ -252,7 +276,7 @@ fn poll_rx(&mut self, cx: &mut Context<'_>) -> Poll<()> {
                     break Poll::Ready(());
                 }
                 tracing::info!(port = self.debug_name, "serial connected");
-                self.state.connect();
+                self.state.connect(self.backend_modem_status);
             }
             if !self.state.should_poll_rx(self.wait_for_rts) {
                 // Wait for buffer space to read into, or to leave loopback mode.
@@ -293,6 +317,16 @@ fn poll_rx(&mut self, cx: &mut Context<'_>) -> Poll<()> {
         }
     }
 
+    fn poll_modem_status(&mut self, cx: &mut Context<'_>) {
+        while let Poll::Ready(status) = self.io.poll_modem_status(cx) {
+            tracing::debug!(port = self.debug_name, ?status, "modem status update");
+            self.backend_modem_status = status;
+            if self.state.msr.dcd() {
+                self.state.set_modem_status(status);
+            }
+        }
+    }
+
     fn register(&self, addr: u64) -> Option<Register> {
         if addr as u8 & (self.register_width - 1) != 0 {
             return None;
@@ -356,7 +390,8 @@ async fn stop(&mut self) {}
 
     async fn reset(&mut self) {
         self.state = State::new();
-        self.state.connect();
+        self.state.connect(self.backend_modem_status);
+        self.sent_modem_control = None;
         self.sync();
     }
 }
@@ -379,6 +414,7 @@ impl PollDevice for Serial16550 {
     fn poll_device(&mut self, cx: &mut Context<'_>) {
         let _ = self.poll_tx(cx);
         let _ = self.poll_rx(cx);
+        self.poll_modem_status(cx);
         self.sync();
     }
 }
@@ -402,15 +438,34 @@ fn new() -> Self {
     }
 
     /// Updates MSR when the modem connects.
-    fn connect(&mut self) {
+    fn connect(&mut self, status: ModemStatus) {
         self.update_msr(|this| {
             this.msr.set_dcd(true);
-            this.msr.set_cts(true);
-            this.msr.set_dsr(true);
-            this.msr.set_ri(false);
+            this.msr.set_cts(status.cts);
+            this.msr.set_dsr(status.dsr);
+            this.msr.set_ri(status.ri);
         });
     }
 
+    /// Updates MSR when the connected modem's status lines change.
+    fn set_modem_status(&mut self, status: ModemStatus) {
+        self.update_msr(|this| {
+            this.msr.set_cts(status.cts);
+            this.msr.set_dsr(status.dsr);
+            this.msr.set_ri(status.ri);
+        });
+    }
+
+    /// Returns the modem control lines to drive to the backend.
+    fn modem_control(&self) -> ModemControl {
+        // In loopback mode, the outputs are disconnected from the modem.
+        let loopback = self.mcr.loopback();
+        ModemControl {
+            dtr: self.mcr.dtr() && !loopback,
+            rts: self.mcr.rts() && !loopback,
+        }
+    }
+
     /// Updates MSR when the modem disconnects.
     fn disconnect(&mut self) {
         self.update_msr(|this| {
@@ -858,12 +913,222 @@ fn restore(&mut self, state: Self::SavedState) -> Result<(), RestoreError> {
                 rx_buffer: rx_buffer.into(),
             };
             if self.io.is_connected() {
-                self.state.connect();
+                self.state.connect(self.backend_modem_status);
             } else {
                 self.state.disconnect();
             }
+            self.sent_modem_control = None;
             self.sync();
             Ok(())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::spec::Register;
+    use super::Serial16550;
+    use chipset_device::pio::PortIoIntercept;
+    use chipset_device::poll_device::PollDevice;
+    use futures::AsyncRead;
+    use futures::AsyncWrite;
+    use inspect::InspectMut;
+    use parking_lot::Mutex;
+    use serial_16550_resources::MmioOrIoPort;
+    use serial_core::ModemControl;
+    use serial_core::ModemStatus;
+    use serial_core::SerialIo;
+    use std::collections::VecDeque;
+    use std::io;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::Context;
+    use std::task::Poll;
+    use vmcore::line_interrupt::LineInterrupt;
+
+    const BASE: u16 = 0x3f8;
+
+    #[derive(Default)]
+    struct ModemLines {
+        control: Option<ModemControl>,
+        status: VecDeque<ModemStatus>,
+    }
+
+    /// A connected backend that records modem control and reports queued
+    /// modem status changes.
+    #[derive(InspectMut)]
+    struct ModemIo {
+        #[inspect(skip)]
+        lines: Arc<Mutex<ModemLines>>,
+        supported: bool,
+    }
+
+    impl SerialIo for ModemIo {
+        fn is_connected(&self) -> bool {
+            true
+        }
+
+        fn poll_connect(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_disconnect(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Pending
+        }
+
+        fn supports_modem_lines(&self) -> bool {
+            self.supported
+        }
+
+        fn set_modem_control(&mut self, control: ModemControl) {
+            self.lines.lock().control = Some(control);
+        }
+
+        fn poll_modem_status(&mut self, _cx: &mut Context<'_>) -> Poll<ModemStatus> {
+            match self.lines.lock().status.pop_front() {
+                Some(status) => Poll::Ready(status),
+                None => Poll::Pending,
+            }
+        }
+    }
+
+    impl AsyncRead for ModemIo {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            _buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Pending
+        }
+    }
+
+    impl AsyncWrite for ModemIo {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn new_serial(supported: bool) -> (Serial16550, Arc<Mutex<ModemLines>>) {
+        let lines = Arc::new(Mutex::new(ModemLines::default()));
+        let serial = Serial16550::new(
+            "com1".to_string(),
+            MmioOrIoPort::IoPort(BASE),
+            1,
+            LineInterrupt::detached(),
+            Box::new(ModemIo {
+                lines: lines.clone(),
+                supported,
+            }),
+            false,
+        )
+        .unwrap();
+        (serial, lines)
+    }
+
+    fn read(serial: &mut Serial16550, register: Register) -> u8 {
+        let mut data = [0];
+        serial.io_read(BASE + register.0 as u16, &mut data).unwrap();
+        data[0]
+    }
+
+    fn write(serial: &mut Serial16550, register: Register, data: u8) {
+        serial.io_write(BASE + register.0 as u16, &[data]).unwrap();
+    }
+
+    fn poll(serial: &mut Serial16550) {
+        serial.poll_device(&mut Context::from_waker(futures::task::noop_waker_ref()));
+    }
+
+    const MCR_DTR: u8 = 0x01;
+    const MCR_RTS: u8 = 0x02;
+    const MCR_OUT2: u8 = 0x08;
+    const MCR_LOOPBACK: u8 = 0x10;
+
+    const MSR_DELTA_CTS: u8 = 0x01;
+    const MSR_TRAILING_RI: u8 = 0x04;
+    const MSR_CTS: u8 = 0x10;
+    const MSR_DSR: u8 = 0x20;
+    const MSR_RI: u8 = 0x40;
+    const MSR_DCD: u8 = 0x80;
+
+    #[test]
+    fn mcr_drives_modem_control() {
+        let (mut serial, lines) = new_serial(true);
+        assert_eq!(lines.lock().control, Some(ModemControl::default()));
+
+        write(&mut serial, Register::MCR, MCR_DTR | MCR_RTS);
+        assert_eq!(
+            lines.lock().control,
+            Some(ModemControl {
+                dtr: true,
+                rts: true
+            })
+        );
+        assert_eq!(read(&mut serial, Register::MCR), MCR_DTR | MCR_RTS);
+
+        // Loopback disconnects the outputs from the backend.
+        write(&mut serial, Register::MCR, MCR_DTR | MCR_RTS | MCR_LOOPBACK);
+        assert_eq!(lines.lock().control, Some(ModemControl::default()));
+    }
+
+    #[test]
+    fn unsupported_backend_ignores_modem_control() {
+        let (mut serial, lines) = new_serial(false);
+        write(&mut serial, Register::MCR, MCR_DTR | MCR_RTS);
+        assert_eq!(lines.lock().control, None);
+    }
+
+    #[test]
+    fn modem_status_updates_msr() {
+        let (mut serial, lines) = new_serial(true);
+        assert_eq!(
+            read(&mut serial, Register::MSR) & 0xf0,
+            MSR_DCD | MSR_CTS | MSR_DSR
+        );
+
+        lines.lock().status.push_back(ModemStatus {
+            cts: false,
+            dsr: true,
+            ri: true,
+        });
+        poll(&mut serial);
+        assert_eq!(
+            read(&mut serial, Register::MSR),
+            MSR_DCD | MSR_DSR | MSR_RI | MSR_DELTA_CTS
+        );
+        // Reading MSR clears the change bits.
+        assert_eq!(read(&mut serial, Register::MSR), MSR_DCD | MSR_DSR | MSR_RI);
+
+        lines.lock().status.push_back(ModemStatus::CONNECTED);
+        poll(&mut serial);
+        assert_eq!(
+            read(&mut serial, Register::MSR),
+            MSR_DCD | MSR_CTS | MSR_DSR | MSR_DELTA_CTS | MSR_TRAILING_RI
+        );
+    }
+
+    #[test]
+    fn loopback_reflects_mcr_in_msr() {
+        let (mut serial, _lines) = new_serial(true);
+        read(&mut serial, Register::MSR);
+        write(
+            &mut serial,
+            Register::MCR,
+            MCR_LOOPBACK | MCR_DTR | MCR_OUT2,
+        );
+        // DTR loops back to CTS and OUT2 to DCD.
+        assert_eq!(read(&mut serial, Register::MSR) & 0xf0, MSR_CTS | MSR_DCD);
+    }
+}
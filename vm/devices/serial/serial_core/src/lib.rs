@@ -11,6 +11,7 @@
 
 use futures::io::AsyncRead;
 use futures::io::AsyncWrite;
+use inspect::Inspect;
 use inspect::InspectMut;
 use std::task::Context;
 use std::task::Poll;
@@ -28,4 +29,62 @@ pub trait SerialIo: AsyncRead + AsyncWrite + Send + InspectMut + Unpin {
 
     /// Polls for the serial backend to disconnect.
     fn poll_disconnect(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>>;
+
+    /// Returns true if the backend carries modem control and status lines.
+    ///
+    /// Most backends (pipes, sockets, consoles) have no modem lines. These
+    /// return false, ignore [`SerialIo::set_modem_control`], and never
+    /// complete [`SerialIo::poll_modem_status`].
+    fn supports_modem_lines(&self) -> bool {
+        false
+    }
+
+    /// Updates the modem control lines driven by the serial device.
+    ///
+    /// Backends without a notion of modem lines ignore this.
+    fn set_modem_control(&mut self, control: ModemControl) {
+        let _ = control;
+    }
+
+    /// Polls for a change to the modem status lines driven by the backend.
+    ///
+    /// Backends without a notion of modem lines never complete this, in which
+    /// case devices derive the modem status from the connection state.
+    fn poll_modem_status(&mut self, cx: &mut Context<'_>) -> Poll<ModemStatus> {
+        let _ = cx;
+        Poll::Pending
+    }
+}
+
+/// The modem control lines driven by the serial device (DTE) toward the
+/// backend.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Inspect)]
+pub struct ModemControl {
+    /// Data terminal ready.
+    pub dtr: bool,
+    /// Request to send.
+    pub rts: bool,
+}
+
+/// The modem status lines driven by the backend toward the serial device.
+///
+/// Carrier detect is not included, since devices use it to report whether the
+/// backend is connected at all.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Inspect)]
+pub struct ModemStatus {
+    /// Clear to send.
+    pub cts: bool,
+    /// Data set ready.
+    pub dsr: bool,
+    /// Ring indicator.
+    pub ri: bool,
+}
+
+impl ModemStatus {
+    /// The status of a connected backend that does not track modem lines.
+    pub const CONNECTED: Self = Self {
+        cts: true,
+        dsr: true,
+        ri: false,
+    };
 }
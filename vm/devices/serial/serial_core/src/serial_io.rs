@@ -3,6 +3,8 @@
 
 //! Types to help in the implementation and use [`SerialIo`].
 
+use crate::ModemControl;
+use crate::ModemStatus;
 use crate::SerialIo;
 use futures::io::AsyncRead;
 use futures::io::AsyncWrite;
@@ -181,6 +183,28 @@ fn poll_disconnect(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
             Poll::Ready(Ok(()))
         }
     }
+
+    fn supports_modem_lines(&self) -> bool {
+        self.inner
+            .lock()
+            .as_ref()
+            .map_or(false, |s| s.supports_modem_lines())
+    }
+
+    fn set_modem_control(&mut self, control: ModemControl) {
+        if let Some(serial) = &mut *self.inner.lock() {
+            serial.set_modem_control(control);
+        }
+    }
+
+    fn poll_modem_status(&mut self, cx: &mut Context<'_>) -> Poll<ModemStatus> {
+        let mut inner = self.inner.lock();
+        if let Some(serial) = &mut *inner {
+            serial.poll_modem_status(cx)
+        } else {
+            Poll::Pending
+        }
+    }
 }
 
 impl<T: AsyncRead + Unpin> AsyncRead for DetachableIo<T> {
@@ -0,0 +1,238 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A serial backend that logs guest output to a bounded in-memory ring buffer,
+//! optionally mirrored to size-limited, rotated log files.
+//!
+//! This is intended for long-running VMs, where an unbounded serial log file
+//! would eventually fill the disk. The most recent output is available via
+//! inspect and can be retrieved on demand through
+//! [`SerialLoggerHandle::dump`]. The backend never provides guest input.
+
+#![warn(missing_docs)]
+
+mod rotate;
+
+use futures::AsyncRead;
+use futures::AsyncWrite;
+use inspect::InspectMut;
+use mesh::rpc::Rpc;
+use mesh::MeshPayload;
+use rotate::RotatingFile;
+use serial_core::resources::ResolveSerialBackendParams;
+use serial_core::resources::ResolvedSerialBackend;
+use serial_core::SerialIo;
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::task::Context;
+use std::task::Poll;
+use vm_resource::declare_static_resolver;
+use vm_resource::kind::SerialBackendHandle;
+use vm_resource::ResolveResource;
+use vm_resource::Resource;
+use vm_resource::ResourceId;
+
+/// A handle to a ring-buffer serial logger.
+#[derive(MeshPayload)]
+pub struct SerialLoggerHandle {
+    /// The number of bytes of most recent output to retain in memory.
+    pub capacity: usize,
+    /// Optional on-disk log configuration.
+    pub file: Option<LogFileConfig>,
+    /// Channel for requests to retrieve the buffered output.
+    pub dump: Option<mesh::Receiver<Rpc<(), Vec<u8>>>>,
+}
+
+/// Configuration for on-disk serial logs.
+#[derive(MeshPayload)]
+pub struct LogFileConfig {
+    /// The path of the active log file. Rotated files are stored alongside it
+    /// with a numeric suffix (`.1` being the most recent).
+    pub path: String,
+    /// The size at which the active file is rotated.
+    pub max_file_size: u64,
+    /// The number of rotated files to retain.
+    pub max_files: u32,
+}
+
+impl ResourceId<SerialBackendHandle> for SerialLoggerHandle {
+    const ID: &'static str = "serial_logger";
+}
+
+/// A resolver for [`SerialLoggerHandle`].
+pub struct SerialLoggerResolver;
+
+declare_static_resolver!(
+    SerialLoggerResolver,
+    (SerialBackendHandle, SerialLoggerHandle)
+);
+
+impl ResolveResource<SerialBackendHandle, SerialLoggerHandle> for SerialLoggerResolver {
+    type Output = ResolvedSerialBackend;
+    type Error = io::Error;
+
+    fn resolve(
+        &self,
+        rsrc: SerialLoggerHandle,
+        _input: ResolveSerialBackendParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(SerialLogger::new(rsrc)?.into())
+    }
+}
+
+/// The ring-buffer serial logger backend.
+pub struct SerialLogger {
+    ring: VecDeque<u8>,
+    capacity: usize,
+    file: Option<RotatingFile>,
+    dump: Option<mesh::Receiver<Rpc<(), Vec<u8>>>>,
+    total_bytes: u64,
+    file_errors: u64,
+}
+
+impl InspectMut for SerialLogger {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        req.respond()
+            .field("capacity", self.capacity)
+            .field("buffered", self.ring.len())
+            .field("total_bytes", self.total_bytes)
+            .field("file_errors", self.file_errors)
+            .field("file", &self.file)
+            .field_with("contents", || {
+                String::from_utf8_lossy(&self.contents()).into_owned()
+            });
+    }
+}
+
+impl SerialLogger {
+    /// Returns a new logger for the given configuration.
+    pub fn new(handle: SerialLoggerHandle) -> io::Result<Self> {
+        let file = handle
+            .file
+            .map(|f| RotatingFile::open(f.path, f.max_file_size, f.max_files))
+            .transpose()?;
+        Ok(Self {
+            ring: VecDeque::with_capacity(handle.capacity),
+            capacity: handle.capacity,
+            file,
+            dump: handle.dump,
+            total_bytes: 0,
+            file_errors: 0,
+        })
+    }
+
+    /// Returns the buffered output, oldest first.
+    pub fn contents(&self) -> Vec<u8> {
+        self.ring.iter().copied().collect()
+    }
+
+    fn log(&mut self, buf: &[u8]) {
+        self.total_bytes += buf.len() as u64;
+        let tail = &buf[buf.len().saturating_sub(self.capacity)..];
+        let overflow = (self.ring.len() + tail.len()).saturating_sub(self.capacity);
+        self.ring.drain(..overflow);
+        self.ring.extend(tail);
+
+        if let Some(file) = &mut self.file {
+            if let Err(err) = file.write(buf) {
+                // Keep logging to memory even if the disk is unavailable.
+                self.file_errors += 1;
+                tracing::warn!(
+                    path = %file.path().display(),
+                    error = &err as &dyn std::error::Error,
+                    "failed to write serial log file"
+                );
+            }
+        }
+    }
+}
+
+impl From<SerialLogger> for Resource<SerialBackendHandle> {
+    fn from(value: SerialLogger) -> Self {
+        // The buffered contents are not preserved.
+        Resource::new(SerialLoggerHandle {
+            capacity: value.capacity,
+            file: value.file.map(|f| f.into_config()),
+            dump: value.dump,
+        })
+    }
+}
+
+impl SerialIo for SerialLogger {
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    fn poll_connect(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_disconnect(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncRead for SerialLogger {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        _buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        // There is never any input, but the device polls for it continuously,
+        // so use this opportunity to service dump requests.
+        let this = self.get_mut();
+        while let Some(dump) = &mut this.dump {
+            match dump.poll_recv(cx) {
+                Poll::Ready(Ok(rpc)) => {
+                    let contents = this.contents();
+                    rpc.handle_sync(|()| contents);
+                }
+                Poll::Ready(Err(_)) => this.dump = None,
+                Poll::Pending => break,
+            }
+        }
+        Poll::Pending
+    }
+}
+
+impl AsyncWrite for SerialLogger {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut().log(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SerialLogger;
+    use super::SerialLoggerHandle;
+
+    #[test]
+    fn ring_keeps_most_recent_output() {
+        let mut logger = SerialLogger::new(SerialLoggerHandle {
+            capacity: 8,
+            file: None,
+            dump: None,
+        })
+        .unwrap();
+        logger.log(b"hello ");
+        logger.log(b"world");
+        assert_eq!(logger.contents(), b"lo world");
+        logger.log(b"0123456789");
+        assert_eq!(logger.contents(), b"23456789");
+        assert_eq!(logger.total_bytes, 21);
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Size-limited log files with rotation.
+
+use crate::LogFileConfig;
+use inspect::Inspect;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// A log file that is rotated once it reaches a size limit.
+///
+/// The active file is always at `path`. On rotation, `path` is renamed to
+/// `path.1`, `path.1` to `path.2`, and so on, and the file that would become
+/// `path.<max_files>` is deleted.
+#[derive(Inspect)]
+pub(crate) struct RotatingFile {
+    #[inspect(with = "|x| x.display().to_string()")]
+    path: PathBuf,
+    #[inspect(skip)]
+    file: File,
+    size: u64,
+    max_size: u64,
+    max_files: u32,
+    rotations: u64,
+}
+
+impl RotatingFile {
+    pub fn open(path: impl Into<PathBuf>, max_size: u64, max_files: u32) -> io::Result<Self> {
+        let path = path.into();
+        let file = File::options().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            max_files,
+            rotations: 0,
+        })
+    }
+
+    pub fn into_config(self) -> LogFileConfig {
+        LogFileConfig {
+            path: self.path.to_string_lossy().into_owned(),
+            max_file_size: self.max_size,
+            max_files: self.max_files,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn write(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            if self.size >= self.max_size {
+                self.rotate()?;
+            }
+            let room = (self.max_size - self.size).min(buf.len() as u64) as usize;
+            // Always make progress, even with a zero size limit.
+            let n = room.max(1);
+            self.file.write_all(&buf[..n])?;
+            self.size += n as u64;
+            buf = &buf[n..];
+        }
+        Ok(())
+    }
+
+    fn rotated_path(&self, n: u32) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{n}"));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        if self.max_files == 0 {
+            // No history is retained, so just start over.
+            self.file.set_len(0)?;
+        } else {
+            let _ = std::fs::remove_file(self.rotated_path(self.max_files));
+            for n in (1..self.max_files).rev() {
+                let from = self.rotated_path(n);
+                if from.exists() {
+                    std::fs::rename(&from, self.rotated_path(n + 1))?;
+                }
+            }
+            std::fs::rename(&self.path, self.rotated_path(1))?;
+            self.file = File::options()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .open(&self.path)?;
+        }
+        self.size = 0;
+        self.rotations += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RotatingFile;
+
+    #[test]
+    fn rotates_and_limits_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("com1.log");
+        let mut file = RotatingFile::open(&path, 4, 2).unwrap();
+        file.write(b"aaaabbbbccccdd").unwrap();
+
+        let read = |name: &str| std::fs::read(dir.path().join(name)).unwrap();
+        assert_eq!(read("com1.log"), b"dd");
+        assert_eq!(read("com1.log.1"), b"cccc");
+        assert_eq!(read("com1.log.2"), b"bbbb");
+        assert!(!dir.path().join("com1.log.3").exists());
+    }
+}
@@ -4,5 +4,7 @@
 //! Serial port backends based on sockets and Windows named pipes.
 
 pub mod net;
+pub mod reconnect;
+pub mod telnet;
 #[cfg(windows)]
 pub mod windows;
@@ -0,0 +1,479 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Unix socket or VM socket serial backend that keeps the guest-facing port
+//! connected across client disconnects.
+//!
+//! Unlike [`SocketSerialBackend`](crate::net::SocketSerialBackend), this
+//! backend never reports a disconnect to the serial device. When the client
+//! goes away, guest output is buffered (up to a fixed limit) and the backend
+//! waits for a new client to connect (in listen mode) or periodically retries
+//! the connection (in connect mode). The guest never sees carrier loss, so the
+//! device's FIFO and modem state are preserved across console reattachment.
+//!
+//! VM sockets (`AF_VSOCK` on Linux, `AF_HYPERV` on Windows) allow tooling on
+//! the host to reach the console of a VM running inside another VM, without
+//! relaying through files or pipes on the intermediate layer.
+
+use futures::AsyncRead;
+use futures::AsyncWrite;
+use inspect::InspectMut;
+use mesh::MeshPayload;
+use pal_async::driver::Driver;
+use pal_async::driver::PollImpl;
+use pal_async::socket::PolledSocket;
+use pal_async::timer::Instant;
+use pal_async::timer::PollTimer;
+use serial_core::resources::ResolveSerialBackendParams;
+use serial_core::resources::ResolvedSerialBackend;
+use serial_core::SerialIo;
+use socket2::Domain;
+use socket2::SockAddr;
+use socket2::Socket;
+use socket2::Type;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use unix_socket::UnixListener;
+use vm_resource::declare_static_resolver;
+use vm_resource::kind::SerialBackendHandle;
+use vm_resource::ResolveResource;
+use vm_resource::Resource;
+use vm_resource::ResourceId;
+
+/// The default interval between connection attempts in connect mode.
+pub const DEFAULT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The default number of bytes of guest output to retain while no client is
+/// connected.
+pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How the backend obtains client connections.
+#[derive(Debug, MeshPayload)]
+pub enum ReconnectTarget {
+    /// Accept connections on a bound Unix socket listener.
+    Listen(Socket),
+    /// Connect to the Unix socket at the given path, retrying on failure.
+    Connect(String),
+    /// Connect to the given VSOCK port on the parent partition, retrying on
+    /// failure.
+    ConnectVmHost(u32),
+}
+
+/// A resource for a reconnecting Unix socket serial backend.
+#[derive(Debug, MeshPayload)]
+pub struct OpenReconnectingSerialConfig {
+    /// The connection target.
+    pub target: ReconnectTarget,
+    /// The currently connected client, if any.
+    pub current: Option<Socket>,
+    /// The interval between connection attempts in connect mode.
+    pub retry_interval: Duration,
+    /// The maximum number of bytes of guest output to buffer while
+    /// disconnected. Older data is discarded first.
+    pub buffer_size: usize,
+}
+
+impl ResourceId<SerialBackendHandle> for OpenReconnectingSerialConfig {
+    const ID: &'static str = "reconnecting_socket";
+}
+
+impl OpenReconnectingSerialConfig {
+    /// Returns a config that accepts clients on `listener`.
+    pub fn listen(listener: UnixListener) -> Self {
+        Self {
+            target: ReconnectTarget::Listen(listener.into()),
+            current: None,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    /// Returns a config that connects to the socket at `path`.
+    pub fn connect(path: impl Into<String>) -> Self {
+        Self {
+            target: ReconnectTarget::Connect(path.into()),
+            current: None,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    /// Returns a config that accepts clients from any VM (including the
+    /// parent partition) on VSOCK port `port`.
+    #[cfg(any(windows, target_os = "linux"))]
+    pub fn listen_vsock(port: u32) -> io::Result<Self> {
+        let listener = vmsocket::VmListener::bind(vmsocket::VmAddress::vsock_any(port))?;
+        Ok(Self {
+            target: ReconnectTarget::Listen(listener.into()),
+            current: None,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        })
+    }
+
+    /// Returns a config that connects to VSOCK port `port` on the parent
+    /// partition.
+    pub fn connect_vsock_host(port: u32) -> Self {
+        Self {
+            target: ReconnectTarget::ConnectVmHost(port),
+            current: None,
+            retry_interval: DEFAULT_RETRY_INTERVAL,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+}
+
+pub struct ReconnectingSerialResolver;
+declare_static_resolver!(
+    ReconnectingSerialResolver,
+    (SerialBackendHandle, OpenReconnectingSerialConfig)
+);
+
+impl ResolveResource<SerialBackendHandle, OpenReconnectingSerialConfig>
+    for ReconnectingSerialResolver
+{
+    type Output = ResolvedSerialBackend;
+    type Error = io::Error;
+
+    fn resolve(
+        &self,
+        rsrc: OpenReconnectingSerialConfig,
+        input: ResolveSerialBackendParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(ReconnectingSerialBackend::new(input.driver, rsrc)?.into())
+    }
+}
+
+enum Target {
+    Listen(PolledSocket<Socket>),
+    Connect(String),
+    ConnectVmHost(u32),
+}
+
+/// An in-progress connection to the target.
+type ConnectFuture = Pin<Box<dyn Future<Output = io::Result<PolledSocket<Socket>>> + Send>>;
+
+/// Returns an unconnected socket and the address of VSOCK port `port` on the
+/// parent partition.
+fn vm_host_socket(port: u32) -> io::Result<(Socket, SockAddr)> {
+    #[cfg(any(windows, target_os = "linux"))]
+    {
+        Ok((
+            vmsocket::VmSocket::new()?.into(),
+            vmsocket::VmAddress::vsock_host(port).into(),
+        ))
+    }
+    #[cfg(not(any(windows, target_os = "linux")))]
+    {
+        let _ = port;
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Writes `buf` to the client in `current`, if there is one. Returns `None`
+/// if there is no client or the client disconnected.
+fn poll_write_client(
+    current: &mut Option<PolledSocket<Socket>>,
+    cx: &mut Context<'_>,
+    buf: &[u8],
+) -> Poll<Option<usize>> {
+    let Some(socket) = current else {
+        return Poll::Ready(None);
+    };
+    match ready!(Pin::new(socket).poll_write(cx, buf)) {
+        Ok(0) => {
+            drop_client(current, None);
+            Poll::Ready(None)
+        }
+        Ok(n) => Poll::Ready(Some(n)),
+        Err(err) => {
+            drop_client(current, Some(err));
+            Poll::Ready(None)
+        }
+    }
+}
+
+fn drop_client(current: &mut Option<PolledSocket<Socket>>, err: Option<io::Error>) {
+    if let Some(err) = err {
+        tracing::debug!(
+            error = &err as &dyn std::error::Error,
+            "serial client failed, waiting for reconnect"
+        );
+    } else {
+        tracing::debug!("serial client disconnected, waiting for reconnect");
+    }
+    *current = None;
+}
+
+#[derive(InspectMut)]
+pub struct ReconnectingSerialBackend {
+    #[inspect(skip)]
+    driver: Box<dyn Driver>,
+    #[inspect(skip)]
+    target: Target,
+    #[inspect(with = "Option::is_some")]
+    current: Option<PolledSocket<Socket>>,
+    #[inspect(with = "Option::is_some")]
+    connecting: Option<ConnectFuture>,
+    #[inspect(skip)]
+    timer: PollImpl<dyn PollTimer>,
+    #[inspect(skip)]
+    retry_deadline: Option<Instant>,
+    #[inspect(debug)]
+    retry_interval: Duration,
+    #[inspect(with = "VecDeque::len")]
+    buffered: VecDeque<u8>,
+    buffer_size: usize,
+    reconnects: u64,
+    dropped_bytes: u64,
+}
+
+impl ReconnectingSerialBackend {
+    pub fn new(driver: Box<dyn Driver>, config: OpenReconnectingSerialConfig) -> io::Result<Self> {
+        let target = match config.target {
+            ReconnectTarget::Listen(listener) => {
+                Target::Listen(PolledSocket::new(&driver, listener)?)
+            }
+            ReconnectTarget::Connect(path) => Target::Connect(path),
+            ReconnectTarget::ConnectVmHost(port) => Target::ConnectVmHost(port),
+        };
+        let current = config
+            .current
+            .map(|s| PolledSocket::new(&driver, s))
+            .transpose()?;
+        Ok(Self {
+            timer: driver.new_dyn_timer(),
+            driver,
+            target,
+            current,
+            connecting: None,
+            retry_deadline: None,
+            retry_interval: config.retry_interval,
+            buffered: VecDeque::new(),
+            buffer_size: config.buffer_size,
+            reconnects: 0,
+            dropped_bytes: 0,
+        })
+    }
+
+    /// Converts back to a config. Any buffered output is discarded.
+    pub fn into_config(self) -> OpenReconnectingSerialConfig {
+        OpenReconnectingSerialConfig {
+            target: match self.target {
+                Target::Listen(listener) => ReconnectTarget::Listen(listener.into_inner()),
+                Target::Connect(path) => ReconnectTarget::Connect(path),
+                Target::ConnectVmHost(port) => ReconnectTarget::ConnectVmHost(port),
+            },
+            current: self.current.map(PolledSocket::into_inner),
+            retry_interval: self.retry_interval,
+            buffer_size: self.buffer_size,
+        }
+    }
+
+    /// Starts a non-blocking connection to the target.
+    fn start_connect(&self) -> io::Result<ConnectFuture> {
+        let (socket, addr) = match &self.target {
+            Target::Listen(_) => unreachable!("listeners accept clients"),
+            Target::Connect(path) => (
+                Socket::new(Domain::UNIX, Type::STREAM, None)?,
+                SockAddr::unix(path)?,
+            ),
+            Target::ConnectVmHost(port) => vm_host_socket(*port)?,
+        };
+        let mut socket = PolledSocket::new(&self.driver, socket)?;
+        Ok(Box::pin(async move {
+            socket.connect(&addr).await?;
+            Ok(socket)
+        }))
+    }
+
+    /// Polls until a client is connected.
+    fn poll_reconnect(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        while self.current.is_none() {
+            if let Some(deadline) = self.retry_deadline {
+                ready!(self.timer.poll_timer(cx, Some(deadline)));
+                self.retry_deadline = None;
+            }
+            let r = if let Target::Listen(listener) = &mut self.target {
+                ready!(listener.poll_accept(cx))
+                    .and_then(|(socket, _)| PolledSocket::new(&self.driver, socket))
+            } else if let Some(connecting) = &mut self.connecting {
+                let r = ready!(connecting.as_mut().poll(cx));
+                self.connecting = None;
+                r
+            } else {
+                match self.start_connect() {
+                    Ok(connecting) => {
+                        self.connecting = Some(connecting);
+                        continue;
+                    }
+                    Err(err) => Err(err),
+                }
+            };
+            match r {
+                Ok(socket) => {
+                    tracing::debug!("serial client connected");
+                    self.current = Some(socket);
+                    self.reconnects += 1;
+                }
+                Err(err) => {
+                    if matches!(self.target, Target::Listen(_)) {
+                        tracing::debug!(
+                            error = &err as &dyn std::error::Error,
+                            "failed to accept serial client, retrying"
+                        );
+                    } else {
+                        tracing::debug!(
+                            error = &err as &dyn std::error::Error,
+                            "failed to connect to serial server, retrying"
+                        );
+                    }
+                    self.retry_deadline = Some(Instant::now() + self.retry_interval);
+                }
+            }
+        }
+        Poll::Ready(())
+    }
+
+    /// Appends guest output to the disconnected buffer, discarding the oldest
+    /// data if the buffer is full.
+    fn buffer(&mut self, buf: &[u8]) {
+        let buf = if buf.len() > self.buffer_size {
+            self.dropped_bytes += (buf.len() - self.buffer_size) as u64;
+            &buf[buf.len() - self.buffer_size..]
+        } else {
+            buf
+        };
+        let overflow = (self.buffered.len() + buf.len()).saturating_sub(self.buffer_size);
+        if overflow > 0 {
+            self.buffered.drain(..overflow);
+            self.dropped_bytes += overflow as u64;
+        }
+        self.buffered.extend(buf);
+    }
+
+    /// Writes any buffered output to a client, waiting for one to connect if
+    /// necessary.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        while !self.buffered.is_empty() {
+            ready!(self.poll_reconnect(cx));
+            let (front, _) = self.buffered.as_slices();
+            if let Some(n) = ready!(poll_write_client(&mut self.current, cx, front)) {
+                self.buffered.drain(..n);
+            }
+        }
+        Poll::Ready(())
+    }
+}
+
+impl From<ReconnectingSerialBackend> for Resource<SerialBackendHandle> {
+    fn from(value: ReconnectingSerialBackend) -> Self {
+        Resource::new(value.into_config())
+    }
+}
+
+impl SerialIo for ReconnectingSerialBackend {
+    fn is_connected(&self) -> bool {
+        // From the guest's perspective, the port is always connected.
+        true
+    }
+
+    fn poll_connect(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_disconnect(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Pending
+    }
+}
+
+impl AsyncRead for ReconnectingSerialBackend {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            ready!(this.poll_reconnect(cx));
+            // Opportunistically deliver anything buffered while the client
+            // was away, even if the guest is not currently writing.
+            let _ = this.poll_drain(cx);
+            let Some(current) = &mut this.current else {
+                continue;
+            };
+            match ready!(Pin::new(current).poll_read(cx, buf)) {
+                Ok(0) => drop_client(&mut this.current, None),
+                Ok(n) => break Poll::Ready(Ok(n)),
+                Err(err) => drop_client(&mut this.current, Some(err)),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for ReconnectingSerialBackend {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        loop {
+            // Don't stall the guest while no client is connected; buffer its
+            // output instead. This still registers for a wakeup when a client
+            // connects.
+            if this.poll_reconnect(cx).is_pending() {
+                this.buffer(buf);
+                break Poll::Ready(Ok(buf.len()));
+            }
+            // Deliver previously buffered output first to preserve ordering.
+            if this.buffered.is_empty() {
+                if let Some(n) = ready!(poll_write_client(&mut this.current, cx, buf)) {
+                    break Poll::Ready(Ok(n));
+                }
+            } else {
+                let (front, _) = this.buffered.as_slices();
+                if let Some(n) = ready!(poll_write_client(&mut this.current, cx, front)) {
+                    this.buffered.drain(..n);
+                }
+            }
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            // Without a client, there is nothing to flush to. Buffered output
+            // is delivered when the next client connects.
+            let Some(current) = &mut this.current else {
+                break Poll::Ready(Ok(()));
+            };
+            if this.buffered.is_empty() {
+                if let Err(err) = ready!(Pin::new(current).poll_flush(cx)) {
+                    drop_client(&mut this.current, Some(err));
+                }
+                break Poll::Ready(Ok(()));
+            }
+            let (front, _) = this.buffered.as_slices();
+            if let Some(n) = ready!(poll_write_client(&mut this.current, cx, front)) {
+                this.buffered.drain(..n);
+            }
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(current) = &mut this.current {
+            if let Err(err) = ready!(Pin::new(current).poll_close(cx)) {
+                drop_client(&mut this.current, Some(err));
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
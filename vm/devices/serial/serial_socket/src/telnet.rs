@@ -0,0 +1,879 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! TCP serial backend speaking the telnet protocol.
+//!
+//! This negotiates binary mode, character-at-a-time operation (server echo and
+//! suppress-go-ahead), window size reporting (NAWS, RFC 1073), and the RFC 2217
+//! COM port control option, so that standard terminal tools can attach to a
+//! guest serial port over the network. Telnet commands are stripped from the
+//! input stream, and IAC bytes in the guest's output are escaped.
+//!
+//! Modem lines are wired as a null modem: the client's DTR and RTS (set via
+//! RFC 2217 `SET-CONTROL`) drive the guest's DSR and CTS, and the guest's DTR
+//! and RTS are reported to the client as DSR/DCD and CTS via
+//! `NOTIFY-MODEMSTATE`.
+
+use futures::AsyncRead;
+use futures::AsyncWrite;
+use inspect::Inspect;
+use inspect::InspectMut;
+use mesh::MeshPayload;
+use pal_async::driver::Driver;
+use pal_async::interest::PollEvents;
+use pal_async::socket::PollReady;
+use pal_async::socket::PolledSocket;
+use serial_core::resources::ResolveSerialBackendParams;
+use serial_core::resources::ResolvedSerialBackend;
+use serial_core::ModemControl;
+use serial_core::ModemStatus;
+use serial_core::SerialIo;
+use socket2::Socket;
+use std::io;
+use std::net::TcpListener;
+use std::pin::Pin;
+use std::task::ready;
+use std::task::Context;
+use std::task::Poll;
+use std::task::Waker;
+use vm_resource::declare_static_resolver;
+use vm_resource::kind::SerialBackendHandle;
+use vm_resource::ResolveResource;
+use vm_resource::Resource;
+use vm_resource::ResourceId;
+
+mod cmd {
+    pub const SE: u8 = 240;
+    pub const SB: u8 = 250;
+    pub const WILL: u8 = 251;
+    pub const WONT: u8 = 252;
+    pub const DO: u8 = 253;
+    pub const DONT: u8 = 254;
+    pub const IAC: u8 = 255;
+}
+
+mod opt {
+    pub const BINARY: u8 = 0;
+    pub const ECHO: u8 = 1;
+    pub const SUPPRESS_GO_AHEAD: u8 = 3;
+    pub const NAWS: u8 = 31;
+    pub const COM_PORT: u8 = 44;
+}
+
+/// RFC 2217 client-to-server subnegotiation commands. Server responses use the
+/// same code plus [`com_port::SERVER_OFFSET`].
+mod com_port {
+    pub const SIGNATURE: u8 = 0;
+    pub const SET_BAUDRATE: u8 = 1;
+    pub const SET_DATASIZE: u8 = 2;
+    pub const SET_PARITY: u8 = 3;
+    pub const SET_STOPSIZE: u8 = 4;
+    pub const SET_CONTROL: u8 = 5;
+    pub const NOTIFY_MODEMSTATE: u8 = 7;
+    pub const PURGE_DATA: u8 = 12;
+    pub const SERVER_OFFSET: u8 = 100;
+}
+
+/// RFC 2217 `SET-CONTROL` values for the modem control lines.
+mod control {
+    pub const DTR_QUERY: u8 = 7;
+    pub const DTR_ON: u8 = 8;
+    pub const DTR_OFF: u8 = 9;
+    pub const RTS_QUERY: u8 = 10;
+    pub const RTS_ON: u8 = 11;
+    pub const RTS_OFF: u8 = 12;
+}
+
+/// RFC 2217 `NOTIFY-MODEMSTATE` bits.
+mod modem_state {
+    pub const DELTA_CTS: u8 = 0x01;
+    pub const DELTA_DSR: u8 = 0x02;
+    pub const DELTA_DCD: u8 = 0x08;
+    pub const CTS: u8 = 0x10;
+    pub const DSR: u8 = 0x20;
+    pub const DCD: u8 = 0x80;
+}
+
+const SIGNATURE: &[u8] = b"openvmm";
+
+#[derive(Debug, MeshPayload)]
+pub struct OpenTelnetSerialConfig {
+    pub current: Option<Socket>,
+    pub listener: Option<Socket>,
+}
+
+impl ResourceId<SerialBackendHandle> for OpenTelnetSerialConfig {
+    const ID: &'static str = "telnet";
+}
+
+impl From<TcpListener> for OpenTelnetSerialConfig {
+    fn from(listener: TcpListener) -> Self {
+        Self {
+            current: None,
+            listener: Some(listener.into()),
+        }
+    }
+}
+
+pub struct TelnetSerialResolver;
+declare_static_resolver!(
+    TelnetSerialResolver,
+    (SerialBackendHandle, OpenTelnetSerialConfig)
+);
+
+impl ResolveResource<SerialBackendHandle, OpenTelnetSerialConfig> for TelnetSerialResolver {
+    type Output = ResolvedSerialBackend;
+    type Error = io::Error;
+
+    fn resolve(
+        &self,
+        rsrc: OpenTelnetSerialConfig,
+        input: ResolveSerialBackendParams<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(TelnetSerialBackend::new(input.driver, rsrc)?.into())
+    }
+}
+
+/// Per-option negotiation state, following the Q method of RFC 1143 (without
+/// the queue bit, since options are never toggled after the initial offer).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum OptState {
+    No,
+    WantYes,
+    Yes,
+}
+
+/// The longest subnegotiation retained. Longer ones are truncated, which is
+/// enough for the options supported here.
+const MAX_SUBNEGOTIATION_LEN: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ParseState {
+    Data,
+    Cr,
+    Iac,
+    Negotiate(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// Serial line settings requested by an RFC 2217 client.
+///
+/// These are recorded for diagnostics only; the emulated UART's own line
+/// settings are controlled by the guest.
+#[derive(Debug, Default, Inspect)]
+struct ComPortSettings {
+    baud_rate: u32,
+    data_size: u8,
+    parity: u8,
+    stop_size: u8,
+    control: u8,
+}
+
+/// The telnet protocol state for a single connection.
+#[derive(Inspect)]
+struct Telnet {
+    #[inspect(skip)]
+    state: ParseState,
+    #[inspect(skip)]
+    local: Box<[OptState; 256]>,
+    #[inspect(skip)]
+    remote: Box<[OptState; 256]>,
+    #[inspect(skip)]
+    subnegotiation: Vec<u8>,
+    /// Encoded bytes waiting to be sent to the client.
+    #[inspect(with = "Vec::len")]
+    tx: Vec<u8>,
+    #[inspect(with = "|x| x.map(|(w, h)| format!(\"{w}x{h}\"))")]
+    window_size: Option<(u16, u16)>,
+    com_port: ComPortSettings,
+    /// The guest's modem control lines.
+    modem_control: ModemControl,
+    /// The modem status derived from the client's control lines.
+    modem_status: ModemStatus,
+    modem_status_changed: bool,
+}
+
+impl Telnet {
+    fn new() -> Self {
+        let mut this = Self {
+            state: ParseState::Data,
+            local: Box::new([OptState::No; 256]),
+            remote: Box::new([OptState::No; 256]),
+            subnegotiation: Vec::new(),
+            tx: Vec::new(),
+            window_size: None,
+            com_port: ComPortSettings::default(),
+            modem_control: ModemControl::default(),
+            // Report the default status so that any status left over from a
+            // previous connection is cleared.
+            modem_status: ModemStatus::CONNECTED,
+            modem_status_changed: true,
+        };
+        // Offer server echo and SGA so that the client switches to character
+        // mode, and ask for binary mode in both directions plus window size
+        // reports.
+        for opt in [opt::ECHO, opt::SUPPRESS_GO_AHEAD, opt::BINARY] {
+            this.local[opt as usize] = OptState::WantYes;
+            this.command(cmd::WILL, opt);
+        }
+        for opt in [opt::BINARY, opt::SUPPRESS_GO_AHEAD, opt::NAWS] {
+            this.remote[opt as usize] = OptState::WantYes;
+            this.command(cmd::DO, opt);
+        }
+        this
+    }
+
+    fn supports_local(opt: u8) -> bool {
+        matches!(
+            opt,
+            opt::BINARY | opt::ECHO | opt::SUPPRESS_GO_AHEAD | opt::COM_PORT
+        )
+    }
+
+    fn supports_remote(opt: u8) -> bool {
+        matches!(
+            opt,
+            opt::BINARY | opt::SUPPRESS_GO_AHEAD | opt::NAWS | opt::COM_PORT
+        )
+    }
+
+    fn command(&mut self, command: u8, opt: u8) {
+        self.tx.extend_from_slice(&[cmd::IAC, command, opt]);
+    }
+
+    fn remote_binary(&self) -> bool {
+        self.remote[opt::BINARY as usize] == OptState::Yes
+    }
+
+    /// Appends guest output to the transmit buffer, escaping IAC bytes.
+    fn send(&mut self, data: &[u8]) {
+        for &b in data {
+            if b == cmd::IAC {
+                self.tx.push(cmd::IAC);
+            }
+            self.tx.push(b);
+        }
+    }
+
+    /// Strips telnet commands from `buf` in place, returning the number of
+    /// data bytes remaining at the start of `buf`.
+    fn receive(&mut self, buf: &mut [u8]) -> usize {
+        let mut n = 0;
+        for i in 0..buf.len() {
+            let b = buf[i];
+            let data = match self.state {
+                ParseState::Data | ParseState::Cr => {
+                    let was_cr = self.state == ParseState::Cr;
+                    self.state = ParseState::Data;
+                    match b {
+                        cmd::IAC => {
+                            self.state = ParseState::Iac;
+                            None
+                        }
+                        // In NVT mode, CR is followed by NUL or LF. Drop the NUL.
+                        0 if was_cr => None,
+                        b'\r' if !self.remote_binary() => {
+                            self.state = ParseState::Cr;
+                            Some(b)
+                        }
+                        b => Some(b),
+                    }
+                }
+                ParseState::Iac => {
+                    self.state = ParseState::Data;
+                    match b {
+                        cmd::IAC => Some(cmd::IAC),
+                        cmd::WILL | cmd::WONT | cmd::DO | cmd::DONT => {
+                            self.state = ParseState::Negotiate(b);
+                            None
+                        }
+                        cmd::SB => {
+                            self.subnegotiation.clear();
+                            self.state = ParseState::Subnegotiation;
+                            None
+                        }
+                        // Other commands (NOP, AYT, BRK, ...) are ignored.
+                        _ => None,
+                    }
+                }
+                ParseState::Negotiate(command) => {
+                    self.state = ParseState::Data;
+                    self.negotiate(command, b);
+                    None
+                }
+                ParseState::Subnegotiation => {
+                    if b == cmd::IAC {
+                        self.state = ParseState::SubnegotiationIac;
+                    } else {
+                        self.push_subnegotiation(b);
+                    }
+                    None
+                }
+                ParseState::SubnegotiationIac => {
+                    match b {
+                        cmd::SE => {
+                            self.state = ParseState::Data;
+                            let sb = std::mem::take(&mut self.subnegotiation);
+                            self.handle_subnegotiation(&sb);
+                            self.subnegotiation = sb;
+                        }
+                        cmd::IAC => {
+                            self.state = ParseState::Subnegotiation;
+                            self.push_subnegotiation(cmd::IAC);
+                        }
+                        _ => {
+                            // Protocol violation. Resynchronize.
+                            self.state = ParseState::Data;
+                        }
+                    }
+                    None
+                }
+            };
+            if let Some(data) = data {
+                buf[n] = data;
+                n += 1;
+            }
+        }
+        n
+    }
+
+    fn negotiate(&mut self, command: u8, opt: u8) {
+        let (states, supported, yes, no) = match command {
+            cmd::DO | cmd::DONT => (
+                &mut self.local,
+                Self::supports_local(opt),
+                cmd::WILL,
+                cmd::WONT,
+            ),
+            _ => (
+                &mut self.remote,
+                Self::supports_remote(opt),
+                cmd::DO,
+                cmd::DONT,
+            ),
+        };
+        let state = &mut states[opt as usize];
+        let enable = matches!(command, cmd::DO | cmd::WILL);
+        let reply = match (*state, enable) {
+            (OptState::No, true) if supported => {
+                *state = OptState::Yes;
+                Some(yes)
+            }
+            (OptState::No, true) => Some(no),
+            (OptState::WantYes, true) => {
+                *state = OptState::Yes;
+                None
+            }
+            (OptState::WantYes, false) => {
+                *state = OptState::No;
+                None
+            }
+            (OptState::Yes, false) => {
+                *state = OptState::No;
+                Some(no)
+            }
+            (OptState::Yes, true) | (OptState::No, false) => None,
+        };
+        if let Some(reply) = reply {
+            self.command(reply, opt);
+        }
+    }
+
+    fn push_subnegotiation(&mut self, b: u8) {
+        if self.subnegotiation.len() < MAX_SUBNEGOTIATION_LEN {
+            self.subnegotiation.push(b);
+        }
+    }
+
+    fn handle_subnegotiation(&mut self, sb: &[u8]) {
+        match sb {
+            [opt::NAWS, w0, w1, h0, h1] => {
+                let size = (
+                    u16::from_be_bytes([*w0, *w1]),
+                    u16::from_be_bytes([*h0, *h1]),
+                );
+                tracing::debug!(width = size.0, height = size.1, "telnet window size");
+                self.window_size = Some(size);
+            }
+            [opt::COM_PORT, command, value @ ..] => self.handle_com_port(*command, value),
+            _ => {}
+        }
+    }
+
+    fn handle_com_port(&mut self, command: u8, value: &[u8]) {
+        let settings = &mut self.com_port;
+        let reply: Vec<u8> = match (command, value) {
+            (com_port::SIGNATURE, _) => SIGNATURE.to_vec(),
+            (com_port::SET_BAUDRATE, &[a, b, c, d]) => {
+                let baud = u32::from_be_bytes([a, b, c, d]);
+                if baud != 0 {
+                    settings.baud_rate = baud;
+                }
+                settings.baud_rate.to_be_bytes().to_vec()
+            }
+            (com_port::SET_DATASIZE, &[v]) => vec![update(&mut settings.data_size, v)],
+            (com_port::SET_PARITY, &[v]) => vec![update(&mut settings.parity, v)],
+            (com_port::SET_STOPSIZE, &[v]) => vec![update(&mut settings.stop_size, v)],
+            (com_port::SET_CONTROL, &[v]) => vec![self.set_control(v)],
+            (com_port::SET_CONTROL..=com_port::PURGE_DATA, _) => value.to_vec(),
+            _ => return,
+        };
+        self.com_port_reply(command, &reply);
+
+        // A value of zero is a query; otherwise store and echo the value.
+        fn update(setting: &mut u8, v: u8) -> u8 {
+            if v != 0 {
+                *setting = v;
+            }
+            *setting
+        }
+    }
+
+    fn com_port_reply(&mut self, command: u8, data: &[u8]) {
+        self.tx.extend_from_slice(&[
+            cmd::IAC,
+            cmd::SB,
+            opt::COM_PORT,
+            command + com_port::SERVER_OFFSET,
+        ]);
+        self.send(data);
+        self.tx.extend_from_slice(&[cmd::IAC, cmd::SE]);
+    }
+
+    /// Handles a `SET-CONTROL` request, returning the value to echo.
+    fn set_control(&mut self, v: u8) -> u8 {
+        let mut status = self.modem_status;
+        let reply = match v {
+            control::DTR_QUERY => {
+                if status.dsr {
+                    control::DTR_ON
+                } else {
+                    control::DTR_OFF
+                }
+            }
+            control::RTS_QUERY => {
+                if status.cts {
+                    control::RTS_ON
+                } else {
+                    control::RTS_OFF
+                }
+            }
+            control::DTR_ON | control::DTR_OFF => {
+                status.dsr = v == control::DTR_ON;
+                v
+            }
+            control::RTS_ON | control::RTS_OFF => {
+                status.cts = v == control::RTS_ON;
+                v
+            }
+            v => {
+                if v != 0 {
+                    self.com_port.control = v;
+                }
+                return self.com_port.control;
+            }
+        };
+        if status != self.modem_status {
+            tracing::debug!(?status, "telnet client modem status");
+            self.modem_status = status;
+            self.modem_status_changed = true;
+        }
+        reply
+    }
+
+    /// Updates the guest's modem control lines, notifying the client if it
+    /// negotiated the COM port option.
+    fn set_modem_control(&mut self, control: ModemControl) {
+        let old = std::mem::replace(&mut self.modem_control, control);
+        if old == control || self.remote[opt::COM_PORT as usize] != OptState::Yes {
+            return;
+        }
+        let mut state = 0;
+        if control.rts {
+            state |= modem_state::CTS;
+        }
+        if control.dtr {
+            state |= modem_state::DSR | modem_state::DCD;
+        }
+        if old.rts != control.rts {
+            state |= modem_state::DELTA_CTS;
+        }
+        if old.dtr != control.dtr {
+            state |= modem_state::DELTA_DSR | modem_state::DELTA_DCD;
+        }
+        self.com_port_reply(com_port::NOTIFY_MODEMSTATE, &[state]);
+    }
+}
+
+struct Connection {
+    socket: PolledSocket<Socket>,
+    telnet: Telnet,
+}
+
+impl Connection {
+    /// Writes pending telnet output to the socket.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.telnet.tx.is_empty() {
+            let n = ready!(Pin::new(&mut self.socket).poll_write(cx, &self.telnet.tx))?;
+            self.telnet.tx.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+pub struct TelnetSerialBackend {
+    driver: Box<dyn Driver>,
+    current: Option<Connection>,
+    listener: Option<PolledSocket<Socket>>,
+    modem_control: ModemControl,
+    read_waker: Option<Waker>,
+    modem_status_waker: Option<Waker>,
+}
+
+impl InspectMut for TelnetSerialBackend {
+    fn inspect_mut(&mut self, req: inspect::Request<'_>) {
+        let mut resp = req.respond();
+        resp.field(
+            "state",
+            if self.current.is_some() {
+                "connected"
+            } else if self.listener.is_some() {
+                "listening"
+            } else {
+                "done"
+            },
+        );
+        if let Some(current) = &self.current {
+            resp.field("telnet", &current.telnet);
+        }
+        resp.field("modem_control", self.modem_control);
+    }
+}
+
+impl TelnetSerialBackend {
+    pub fn new(driver: Box<dyn Driver>, config: OpenTelnetSerialConfig) -> io::Result<Self> {
+        // A connection handed over from a previous instance has already
+        // negotiated, but its state is lost, so start negotiation over.
+        let current = config
+            .current
+            .map(|s| {
+                Ok::<_, io::Error>(Connection {
+                    socket: PolledSocket::new(&driver, s)?,
+                    telnet: Telnet::new(),
+                })
+            })
+            .transpose()?;
+        let listener = config
+            .listener
+            .map(|s| PolledSocket::new(&driver, s))
+            .transpose()?;
+        Ok(Self {
+            driver: Box::new(driver),
+            current,
+            listener,
+            modem_control: ModemControl::default(),
+            read_waker: None,
+            modem_status_waker: None,
+        })
+    }
+
+    fn new_connection(&self, socket: Socket) -> io::Result<Connection> {
+        let mut telnet = Telnet::new();
+        telnet.modem_control = self.modem_control;
+        Ok(Connection {
+            socket: PolledSocket::new(&self.driver, socket)?,
+            telnet,
+        })
+    }
+
+    pub fn into_config(self) -> OpenTelnetSerialConfig {
+        OpenTelnetSerialConfig {
+            current: self.current.map(|c| c.socket.into_inner()),
+            listener: self.listener.map(PolledSocket::into_inner),
+        }
+    }
+}
+
+impl From<TelnetSerialBackend> for Resource<SerialBackendHandle> {
+    fn from(value: TelnetSerialBackend) -> Self {
+        Resource::new(value.into_config())
+    }
+}
+
+impl SerialIo for TelnetSerialBackend {
+    fn is_connected(&self) -> bool {
+        self.current.is_some()
+    }
+
+    fn poll_connect(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.current.is_some() {
+            Poll::Ready(Ok(()))
+        } else if let Some(listener) = &mut self.listener {
+            let (socket, _) = ready!(listener.poll_accept(cx))?;
+            self.current = Some(self.new_connection(socket)?);
+            Poll::Ready(Ok(()))
+        } else {
+            // This will never complete.
+            Poll::Pending
+        }
+    }
+
+    fn poll_disconnect(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if let Some(current) = &mut self.current {
+            ready!(current.socket.poll_ready(cx, PollEvents::RDHUP));
+            self.current = None;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn supports_modem_lines(&self) -> bool {
+        true
+    }
+
+    fn set_modem_control(&mut self, control: ModemControl) {
+        self.modem_control = control;
+        if let Some(current) = &mut self.current {
+            current.telnet.set_modem_control(control);
+            if !current.telnet.tx.is_empty() {
+                // Wake the reader to send the notification.
+                if let Some(waker) = self.read_waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+
+    fn poll_modem_status(&mut self, cx: &mut Context<'_>) -> Poll<ModemStatus> {
+        if let Some(current) = &mut self.current {
+            if std::mem::take(&mut current.telnet.modem_status_changed) {
+                return Poll::Ready(current.telnet.modem_status);
+            }
+        }
+        self.modem_status_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl AsyncRead for TelnetSerialBackend {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.read_waker = Some(cx.waker().clone());
+        loop {
+            let Some(current) = &mut this.current else {
+                return Poll::Ready(Ok(0));
+            };
+            // Send any negotiation replies. Failures are reported by the read
+            // below.
+            let _ = current.poll_drain(cx);
+            let r = ready!(Pin::new(&mut current.socket).poll_read(cx, buf));
+            match r {
+                Ok(0) => {
+                    this.current = None;
+                    return Poll::Ready(Ok(0));
+                }
+                Ok(n) => {
+                    let n = current.telnet.receive(&mut buf[..n]);
+                    if current.telnet.modem_status_changed {
+                        if let Some(waker) = this.modem_status_waker.take() {
+                            waker.wake();
+                        }
+                    }
+                    if n > 0 {
+                        return Poll::Ready(Ok(n));
+                    }
+                    // Only telnet commands were received. Send any replies and
+                    // read again.
+                }
+                Err(err) => return Poll::Ready(Err(err)),
+            }
+        }
+    }
+}
+
+impl AsyncWrite for TelnetSerialBackend {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let Some(current) = &mut self.current else {
+            return Poll::Ready(Ok(buf.len()));
+        };
+        // Finish sending previously accepted data before accepting more, to
+        // bound the buffer.
+        let r = ready!(current.poll_drain(cx));
+        if matches!(&r, Err(err) if err.kind() == io::ErrorKind::BrokenPipe) {
+            return Poll::Ready(Ok(buf.len()));
+        }
+        r?;
+        current.telnet.send(buf);
+        let _ = current.poll_drain(cx);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let Some(current) = &mut self.current else {
+            return Poll::Ready(Ok(()));
+        };
+        let r = ready!(current.poll_drain(cx));
+        if matches!(&r, Err(err) if err.kind() == io::ErrorKind::BrokenPipe) {
+            return Poll::Ready(Ok(()));
+        }
+        Poll::Ready(r)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let Some(current) = &mut self.current else {
+            return Poll::Ready(Ok(()));
+        };
+        let r = ready!(Pin::new(&mut current.socket).poll_close(cx));
+        if matches!(&r, Err(err) if err.kind() == io::ErrorKind::BrokenPipe) {
+            return Poll::Ready(Ok(()));
+        }
+        Poll::Ready(r)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cmd::*;
+    use super::com_port;
+    use super::control;
+    use super::opt;
+    use super::Telnet;
+    use serial_core::ModemControl;
+
+    fn receive(telnet: &mut Telnet, input: &[u8]) -> Vec<u8> {
+        let mut buf = input.to_vec();
+        let n = telnet.receive(&mut buf);
+        buf.truncate(n);
+        buf
+    }
+
+    #[test]
+    fn strips_commands_and_unescapes() {
+        let mut telnet = Telnet::new();
+        telnet.tx.clear();
+        let data = receive(&mut telnet, &[b'a', IAC, IAC, b'b', IAC, 241, b'c']);
+        assert_eq!(data, [b'a', IAC, b'b', b'c']);
+        assert!(telnet.tx.is_empty());
+    }
+
+    #[test]
+    fn negotiation_does_not_loop() {
+        let mut telnet = Telnet::new();
+        telnet.tx.clear();
+        // Acks of our own offers produce no replies.
+        receive(
+            &mut telnet,
+            &[
+                IAC,
+                DO,
+                opt::ECHO,
+                IAC,
+                WILL,
+                opt::BINARY,
+                IAC,
+                WONT,
+                opt::NAWS,
+            ],
+        );
+        assert!(telnet.tx.is_empty());
+        assert!(telnet.remote_binary());
+        // Unsupported requests are refused exactly once.
+        receive(&mut telnet, &[IAC, DO, 24]);
+        assert_eq!(telnet.tx, [IAC, WONT, 24]);
+    }
+
+    #[test]
+    fn window_size_and_escaping() {
+        let mut telnet = Telnet::new();
+        receive(
+            &mut telnet,
+            &[IAC, SB, opt::NAWS, 0, 80, 0, IAC, IAC, IAC, SE],
+        );
+        assert_eq!(telnet.window_size, Some((80, 255)));
+
+        telnet.tx.clear();
+        telnet.send(&[1, IAC, 2]);
+        assert_eq!(telnet.tx, [1, IAC, IAC, 2]);
+    }
+
+    #[test]
+    fn subnegotiation_is_bounded() {
+        let mut telnet = Telnet::new();
+        let mut input = vec![IAC, SB, opt::NAWS];
+        for _ in 0..1000 {
+            input.extend([b'x', IAC, IAC]);
+        }
+        input.extend([IAC, SE, b'a']);
+        let data = receive(&mut telnet, &input);
+        assert_eq!(data, [b'a']);
+        assert_eq!(telnet.subnegotiation.len(), super::MAX_SUBNEGOTIATION_LEN);
+    }
+
+    #[test]
+    fn modem_lines() {
+        let mut telnet = Telnet::new();
+        telnet.modem_status_changed = false;
+        receive(&mut telnet, &[IAC, WILL, opt::COM_PORT]);
+        telnet.tx.clear();
+
+        // The client's DTR drives the guest's DSR.
+        let set_dtr_off = [
+            IAC,
+            SB,
+            opt::COM_PORT,
+            com_port::SET_CONTROL,
+            control::DTR_OFF,
+            IAC,
+            SE,
+        ];
+        receive(&mut telnet, &set_dtr_off);
+        assert!(telnet.modem_status_changed);
+        assert!(!telnet.modem_status.dsr);
+        assert!(telnet.modem_status.cts);
+        assert_eq!(
+            telnet.tx,
+            [
+                IAC,
+                SB,
+                opt::COM_PORT,
+                com_port::SET_CONTROL + com_port::SERVER_OFFSET,
+                control::DTR_OFF,
+                IAC,
+                SE
+            ]
+        );
+
+        // The guest's RTS is reported as CTS.
+        telnet.tx.clear();
+        telnet.set_modem_control(ModemControl {
+            dtr: false,
+            rts: true,
+        });
+        assert_eq!(
+            telnet.tx,
+            [
+                IAC,
+                SB,
+                opt::COM_PORT,
+                com_port::NOTIFY_MODEMSTATE + com_port::SERVER_OFFSET,
+                0x11,
+                IAC,
+                SE
+            ]
+        );
+    }
+
+    #[test]
+    fn cr_nul_in_nvt_mode() {
+        let mut telnet = Telnet::new();
+        assert_eq!(receive(&mut telnet, b"a\r\0b\r\n"), b"a\rb\r\n");
+    }
+}
@@ -2,6 +2,14 @@
 // Licensed under the MIT License.
 
 //! Windows named pipe serial backend.
+//!
+//! By default, a single client may connect at a time. When
+//! [`OpenWindowsPipeSerialConfig::multi_client`] is set, additional pipe
+//! instances are created so that several clients (say, a logger and an
+//! interactive console) can attach to the same port at once. Guest output is
+//! sent to every client. Guest input is taken from one client at a time: the
+//! first client to send data owns the input until it disconnects, and input
+//! from other clients is discarded in the meantime.
 
 use futures::AsyncRead;
 use futures::AsyncWrite;
@@ -9,7 +17,10 @@
 use inspect::Inspect;
 use inspect::InspectMut;
 use mesh::MeshPayload;
+use pal::windows::pipe::new_named_pipe;
+use pal::windows::pipe::Disposition;
 use pal::windows::pipe::PipeExt;
+use pal::windows::pipe::PipeMode;
 use pal_async::driver::Driver;
 use pal_async::pipe::PolledPipe;
 use pal_async::windows::pipe::ListeningPipe;
@@ -31,11 +42,25 @@
 #[derive(Debug, MeshPayload)]
 pub struct OpenWindowsPipeSerialConfig {
     pub pipe: Option<File>,
+    /// If set, allow multiple clients to connect simultaneously.
+    pub multi_client: Option<MultiClientConfig>,
+}
+
+/// Configuration for a multi-client named pipe serial backend.
+#[derive(Debug, MeshPayload)]
+pub struct MultiClientConfig {
+    /// The pipe path, used to create additional pipe instances.
+    pub path: String,
+    /// The maximum number of simultaneously connected clients.
+    pub max_clients: u32,
 }
 
 impl From<File> for OpenWindowsPipeSerialConfig {
     fn from(pipe: File) -> Self {
-        Self { pipe: Some(pipe) }
+        Self {
+            pipe: Some(pipe),
+            multi_client: None,
+        }
     }
 }
 
@@ -86,7 +111,11 @@ fn resolve(
         rsrc: OpenWindowsPipeSerialConfig,
         input: ResolveSerialBackendParams<'_>,
     ) -> Result<Self::Output, Self::Error> {
-        Ok(WindowsPipeSerialBackend::new(input.driver, rsrc)?.into())
+        if rsrc.multi_client.is_some() {
+            Ok(MultiClientPipeSerialBackend::new(input.driver, rsrc)?.into())
+        } else {
+            Ok(WindowsPipeSerialBackend::new(input.driver, rsrc)?.into())
+        }
     }
 }
 
@@ -111,7 +140,10 @@ pub fn into_config(self) -> OpenWindowsPipeSerialConfig {
             PipeState::Listening(accept) => Some(accept.into_inner()),
             PipeState::Connected(pipe) => Some(pipe.into_inner()),
         };
-        OpenWindowsPipeSerialConfig { pipe: file }
+        OpenWindowsPipeSerialConfig {
+            pipe: file,
+            multi_client: None,
+        }
     }
 
     fn disconnect(&mut self) -> io::Result<()> {
@@ -233,3 +265,351 @@ fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result
         }
     }
 }
+
+/// The number of bytes of guest output to buffer for each client before
+/// discarding output for that client. This keeps a slow client from stalling
+/// the guest or the other clients.
+const CLIENT_BUFFER_LIMIT: usize = 64 * 1024;
+
+#[derive(Inspect)]
+struct Client {
+    #[inspect(skip)]
+    pipe: PolledPipe,
+    #[inspect(with = "Vec::len")]
+    tx: Vec<u8>,
+    dropped_bytes: u64,
+}
+
+impl Client {
+    /// Writes buffered output to the client.
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.tx.is_empty() {
+            let n = ready!(Pin::new(&mut self.pipe).poll_write(cx, &self.tx))?;
+            self.tx.drain(..n);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// A named pipe serial backend that accepts multiple simultaneous clients.
+#[derive(InspectMut)]
+pub struct MultiClientPipeSerialBackend {
+    #[inspect(skip)]
+    driver: Box<dyn Driver>,
+    path: String,
+    max_clients: u32,
+    #[inspect(with = "Option::is_some")]
+    listening: Option<ListeningPipe>,
+    #[inspect(iter_by_key)]
+    clients: std::collections::BTreeMap<u64, Client>,
+    input_owner: Option<u64>,
+    #[inspect(skip)]
+    next_id: u64,
+}
+
+impl MultiClientPipeSerialBackend {
+    pub fn new(driver: Box<dyn Driver>, config: OpenWindowsPipeSerialConfig) -> io::Result<Self> {
+        let multi_client = config
+            .multi_client
+            .expect("caller checked for multi-client config");
+        let mut this = Self {
+            driver,
+            path: multi_client.path,
+            max_clients: multi_client.max_clients.max(1),
+            listening: None,
+            clients: Default::default(),
+            input_owner: None,
+            next_id: 0,
+        };
+        if let Some(file) = config.pipe {
+            if file.is_pipe_connected()? {
+                this.add_client(file)?;
+            } else {
+                this.listening = Some(ListeningPipe::new(&this.driver, file)?);
+            }
+        }
+        this.listen()?;
+        Ok(this)
+    }
+
+    pub fn into_config(mut self) -> OpenWindowsPipeSerialConfig {
+        // Other clients are disconnected.
+        let pipe = self
+            .listening
+            .take()
+            .map(ListeningPipe::into_inner)
+            .or_else(|| {
+                let id = *self.clients.keys().next()?;
+                Some(self.clients.remove(&id).unwrap().pipe.into_inner())
+            });
+        OpenWindowsPipeSerialConfig {
+            pipe,
+            multi_client: Some(MultiClientConfig {
+                path: self.path,
+                max_clients: self.max_clients,
+            }),
+        }
+    }
+
+    fn add_client(&mut self, file: File) -> io::Result<()> {
+        let id = self.next_id;
+        self.next_id += 1;
+        tracing::debug!(
+            path = self.path.as_str(),
+            id,
+            "serial pipe client connected"
+        );
+        self.clients.insert(
+            id,
+            Client {
+                pipe: PolledPipe::new(&self.driver, file)?,
+                tx: Vec::new(),
+                dropped_bytes: 0,
+            },
+        );
+        Ok(())
+    }
+
+    fn remove_client(&mut self, id: u64) {
+        tracing::debug!(
+            path = self.path.as_str(),
+            id,
+            "serial pipe client disconnected"
+        );
+        self.clients.remove(&id);
+        if self.input_owner == Some(id) {
+            self.input_owner = None;
+        }
+        if let Err(err) = self.listen() {
+            tracing::error!(
+                error = &err as &dyn std::error::Error,
+                "failed to create named pipe instance"
+            );
+        }
+    }
+
+    /// Creates a new pipe instance for the next client, if there is room.
+    fn listen(&mut self) -> io::Result<()> {
+        if self.listening.is_none() && self.clients.len() < self.max_clients as usize {
+            let pipe = new_named_pipe(
+                &self.path,
+                winapi::um::winnt::GENERIC_READ | winapi::um::winnt::GENERIC_WRITE,
+                Disposition::Open,
+                PipeMode::Byte,
+            )?;
+            self.listening = Some(ListeningPipe::new(&self.driver, pipe)?);
+        }
+        Ok(())
+    }
+
+    /// Accepts any pending client connections. Returns ready once at least
+    /// one client is attached.
+    fn poll_accept(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(listening) = &mut self.listening {
+            let Poll::Ready(file) = listening.poll_unpin(cx) else {
+                break;
+            };
+            self.listening = None;
+            self.add_client(file?)?;
+            self.listen()?;
+        }
+        if self.clients.is_empty() {
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn accept(&mut self, cx: &mut Context<'_>) {
+        if let Poll::Ready(Err(err)) = self.poll_accept(cx) {
+            tracing::error!(
+                error = &err as &dyn std::error::Error,
+                "failed to accept serial pipe client"
+            );
+        }
+    }
+}
+
+impl From<MultiClientPipeSerialBackend> for Resource<SerialBackendHandle> {
+    fn from(value: MultiClientPipeSerialBackend) -> Self {
+        Resource::new(value.into_config())
+    }
+}
+
+impl SerialIo for MultiClientPipeSerialBackend {
+    fn is_connected(&self) -> bool {
+        !self.clients.is_empty()
+    }
+
+    fn poll_connect(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        if self.clients.is_empty() {
+            ready!(self.poll_accept(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_disconnect(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.accept(cx);
+        let closed = self
+            .clients
+            .iter_mut()
+            .filter_map(|(&id, client)| client.pipe.poll_closing(cx).is_ready().then_some(id))
+            .collect::<Vec<_>>();
+        for id in closed {
+            self.remove_client(id);
+        }
+        if self.clients.is_empty() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl AsyncRead for MultiClientPipeSerialBackend {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.accept(cx);
+        loop {
+            if this.clients.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+            let mut ready = None;
+            for (&id, client) in &mut this.clients {
+                if let Poll::Ready(r) = Pin::new(&mut client.pipe).poll_read(cx, buf) {
+                    ready = Some((id, r));
+                    break;
+                }
+            }
+            let Some((id, r)) = ready else {
+                return Poll::Pending;
+            };
+            match r {
+                Ok(0) | Err(_) => this.remove_client(id),
+                Ok(n) => {
+                    let owner = *this.input_owner.get_or_insert(id);
+                    if owner == id {
+                        return Poll::Ready(Ok(n));
+                    }
+                    // Another client owns the input. Drop this data.
+                }
+            }
+        }
+    }
+}
+
+impl AsyncWrite for MultiClientPipeSerialBackend {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        this.accept(cx);
+        let mut failed = Vec::new();
+        for (&id, client) in &mut this.clients {
+            if client.tx.len() + buf.len() > CLIENT_BUFFER_LIMIT {
+                client.dropped_bytes += buf.len() as u64;
+            } else {
+                client.tx.extend_from_slice(buf);
+            }
+            if let Poll::Ready(Err(_)) = client.poll_drain(cx) {
+                failed.push(id);
+            }
+        }
+        for id in failed {
+            this.remove_client(id);
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let mut failed = Vec::new();
+        let mut pending = false;
+        for (&id, client) in &mut this.clients {
+            match client.poll_drain(cx) {
+                Poll::Ready(Ok(())) => {}
+                Poll::Ready(Err(_)) => failed.push(id),
+                Poll::Pending => pending = true,
+            }
+        }
+        for id in failed {
+            this.remove_client(id);
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MultiClientConfig;
+    use super::MultiClientPipeSerialBackend;
+    use super::OpenWindowsPipeSerialConfig;
+    use futures::AsyncReadExt;
+    use futures::AsyncWriteExt;
+    use pal::windows::pipe::new_named_pipe;
+    use pal::windows::pipe::Disposition;
+    use pal::windows::pipe::PipeMode;
+    use pal_async::async_test;
+    use pal_async::pipe::PolledPipe;
+    use pal_async::DefaultDriver;
+    use serial_core::SerialIo;
+    use std::fs::OpenOptions;
+    use std::future::poll_fn;
+
+    #[async_test]
+    async fn multi_client_connect(driver: DefaultDriver) {
+        let path = format!(r"\\.\pipe\serial_socket_test_{}", std::process::id());
+        let pipe = new_named_pipe(
+            &path,
+            winapi::um::winnt::GENERIC_READ | winapi::um::winnt::GENERIC_WRITE,
+            Disposition::Create,
+            PipeMode::Byte,
+        )
+        .unwrap();
+        let mut backend = MultiClientPipeSerialBackend::new(
+            Box::new(driver.clone()),
+            OpenWindowsPipeSerialConfig {
+                pipe: Some(pipe),
+                multi_client: Some(MultiClientConfig {
+                    path: path.clone(),
+                    max_clients: 2,
+                }),
+            },
+        )
+        .unwrap();
+        assert!(!backend.is_connected());
+
+        let client = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)
+            .unwrap();
+        let mut client = PolledPipe::new(&driver, client).unwrap();
+        poll_fn(|cx| backend.poll_connect(cx)).await.unwrap();
+        assert!(backend.is_connected());
+
+        backend.write_all(b"hello").await.unwrap();
+        let mut buf = [0; 5];
+        client.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"hello");
+
+        client.write_all(b"world").await.unwrap();
+        let mut buf = [0; 5];
+        backend.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"world");
+    }
+}
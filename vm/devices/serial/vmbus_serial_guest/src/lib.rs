@@ -22,6 +22,7 @@
 use protocol::MessageVersions;
 use protocol::MAX_MESSAGE_SIZE;
 use protocol::UART_MSG_MAX_PAYLOAD;
+use serial_core::ModemStatus;
 use serial_core::SerialIo;
 use std::collections::VecDeque;
 use std::fmt::Debug;
@@ -131,6 +132,8 @@ pub struct VmbusSerialDriver {
     tx_waker: Option<Waker>,
     failed: bool,
     connected: bool,
+    modem_status: ModemStatus,
+    modem_status_changed: bool,
     stats: SerialStats,
 }
 
@@ -198,6 +201,8 @@ pub async fn new(
             tx_waker: None,
             failed: false,
             connected: false,
+            modem_status: ModemStatus::CONNECTED,
+            modem_status_changed: false,
             stats: Default::default(),
         };
         this.negotiate().await?;
@@ -337,6 +342,23 @@ fn handle_message(&mut self, buf: &[u8]) -> Result<(), ErrorInner> {
                         .ok_or(ErrorInner::TruncatedMessage)?;
 
                     self.connected = status.is_connected != 0;
+
+                    // Older hosts do not report modem lines, leaving DCD
+                    // clear even when connected.
+                    let msr = status.modem_status;
+                    let modem_status = if msr & 0x80 != 0 {
+                        ModemStatus {
+                            cts: msr & 0x10 != 0,
+                            dsr: msr & 0x20 != 0,
+                            ri: msr & 0x40 != 0,
+                        }
+                    } else {
+                        ModemStatus::CONNECTED
+                    };
+                    if self.connected && modem_status != self.modem_status {
+                        self.modem_status = modem_status;
+                        self.modem_status_changed = true;
+                    }
                 }
                 GuestNotifications::TX_COMPLETED => {
                     assert!(self.tx_in_flight);
@@ -372,6 +394,21 @@ fn poll_disconnect(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
         }
         Poll::Ready(Ok(()))
     }
+
+    fn supports_modem_lines(&self) -> bool {
+        true
+    }
+
+    fn poll_modem_status(&mut self, cx: &mut Context<'_>) -> Poll<ModemStatus> {
+        while !self.modem_status_changed {
+            if ready!(self.poll_outer(cx)).is_err() {
+                // The failure is reported through the other paths.
+                return Poll::Pending;
+            }
+        }
+        self.modem_status_changed = false;
+        Poll::Ready(self.modem_status)
+    }
 }
 
 impl AsyncRead for VmbusSerialDriver {
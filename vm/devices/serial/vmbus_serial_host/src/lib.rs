@@ -13,6 +13,7 @@
 use inspect_counters::Counter;
 use protocol::HostNotifications;
 use protocol::HostRequests;
+use serial_core::ModemStatus;
 use serial_core::SerialIo;
 use std::cmp::min;
 use std::collections::VecDeque;
@@ -78,6 +79,7 @@ pub struct Serial {
     #[inspect(mut)]
     io: Box<dyn SerialIo>,
     connected: bool,
+    modem_status: ModemStatus,
     stats: SerialStats,
 }
 
@@ -94,6 +96,7 @@ pub fn new(port: Port, io: Box<dyn SerialIo>) -> Self {
         Self {
             port,
             connected: io.is_connected(),
+            modem_status: ModemStatus::CONNECTED,
             io,
             stats: Default::default(),
         }
@@ -107,6 +110,10 @@ pub enum Port {
     Com1,
     /// COM2 instance id.
     Com2,
+    /// COM3 instance id.
+    Com3,
+    /// COM4 instance id.
+    Com4,
 }
 
 #[async_trait]
@@ -118,6 +125,8 @@ fn offer(&self) -> OfferParams {
         let (interface_name, instance_id) = match self.port {
             Port::Com1 => ("serial_com1".into(), protocol::UART_INTERFACE_INSTANCE_COM1),
             Port::Com2 => ("serial_com2".into(), protocol::UART_INTERFACE_INSTANCE_COM2),
+            Port::Com3 => ("serial_com3".into(), protocol::UART_INTERFACE_INSTANCE_COM3),
+            Port::Com4 => ("serial_com4".into(), protocol::UART_INTERFACE_INSTANCE_COM4),
         };
 
         OfferParams {
@@ -315,9 +324,20 @@ enum Event {
                 self.process_header(serial, &buf[..n])?;
             }
             Event::SendModemStatus => {
-                // Set 16550 CTS, DSR, DCD, and matching change registers.
-                // Underhill ignores this field, but set it for completelness.
-                let modem_status = if serial.connected { 0xbb } else { 0x0b };
+                // Set 16550 CTS, DSR, RI, DCD, and the CTS/DSR/DCD change
+                // bits.
+                //
+                // The protocol has no message for the guest's modem control
+                // lines, so DTR/RTS are not propagated to the backend.
+                let modem_status = if serial.connected {
+                    let status = serial.modem_status;
+                    0x0b | (u8::from(status.cts) << 4)
+                        | (u8::from(status.dsr) << 5)
+                        | (u8::from(status.ri) << 6)
+                        | 0x80
+                } else {
+                    0x0b
+                };
                 let is_connected = serial.connected.into();
 
                 let message = protocol::SetModumStatusMessage {
@@ -485,6 +505,14 @@ fn poll_tx(&mut self, cx: &mut Context<'_>, serial: &mut Serial) -> Poll<()> {
     }
 
     fn poll_rx(&mut self, cx: &mut Context<'_>, serial: &mut Serial) -> Poll<()> {
+        while let Poll::Ready(status) = serial.io.poll_modem_status(cx) {
+            if serial.modem_status != status {
+                tracing::debug!(?status, "modem status update");
+                serial.modem_status = status;
+                self.pending_modem_status = true;
+            }
+        }
+
         let mut buf = [0; 1024];
         loop {
             if !serial.connected {
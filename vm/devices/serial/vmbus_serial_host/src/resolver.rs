@@ -42,6 +42,8 @@ async fn resolve(
         let port = match resource.port {
             VmbusSerialPort::Com1 => Port::Com1,
             VmbusSerialPort::Com2 => Port::Com2,
+            VmbusSerialPort::Com3 => Port::Com3,
+            VmbusSerialPort::Com4 => Port::Com4,
         };
         let io = resolver
             .resolve(
@@ -353,7 +353,7 @@ fn default() -> Self {
 pub struct SetModumStatusMessage {
     /// The message header.
     pub header: Header,
-    /// 16550-style modem status. Ignored.
+    /// 16550-style modem status register contents.
     pub modem_status: u8,
     /// A boolean indicating if the modem is connected.
     pub is_connected: u8,
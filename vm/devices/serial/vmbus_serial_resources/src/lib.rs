@@ -33,4 +33,8 @@ pub enum VmbusSerialPort {
     Com1,
     /// A device to reemulate as "COM2".
     Com2,
+    /// A device to reemulate as "COM3".
+    Com3,
+    /// A device to reemulate as "COM4".
+    Com4,
 }
@@ -14,6 +14,7 @@
 
 pub mod pr;
 pub mod resolve;
+pub mod stats;
 pub mod sync_wrapper;
 
 use guestmem::AccessError;
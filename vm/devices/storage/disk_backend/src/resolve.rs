@@ -8,6 +8,7 @@
 use crate::InvalidDisk;
 use vm_resource::kind::DiskHandleKind;
 use vm_resource::CanResolveTo;
+use vmcore::vm_task::VmTaskDriverSource;
 
 impl CanResolveTo<ResolvedDisk> for DiskHandleKind {
     type Input<'a> = ResolveDiskParameters<'a>;
@@ -18,11 +19,8 @@ impl CanResolveTo<ResolvedDisk> for DiskHandleKind {
 pub struct ResolveDiskParameters<'a> {
     /// Whether the disk is being opened for read-only use.
     pub read_only: bool,
-    #[doc(hidden)]
-    // Workaround for async_trait not working well with GAT input parameters
-    // with missing lifetimes. Remove once we stop using async_trait for async
-    // resolvers.
-    pub _async_trait_workaround: &'a (),
+    /// The driver source, for disks that need to run tasks.
+    pub driver_source: &'a VmTaskDriverSource,
 }
 
 /// A resolved [`Disk`].
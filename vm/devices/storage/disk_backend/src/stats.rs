@@ -0,0 +1,44 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Statistics shared by disk backends.
+
+use crate::DiskError;
+use inspect::Inspect;
+use inspect_counters::SharedCounter;
+use std::future::Future;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+
+/// Cache flush counts and latencies, for inspect.
+#[derive(Debug, Default, Inspect)]
+pub struct FlushStats {
+    /// The number of flushes, including failed ones.
+    count: SharedCounter,
+    /// The number of failed flushes.
+    failed: SharedCounter,
+    /// The total time spent flushing, in microseconds.
+    total_us: SharedCounter,
+    /// The longest time spent in a single flush, in microseconds.
+    max_us: AtomicU64,
+}
+
+impl FlushStats {
+    /// Runs `flush`, recording its latency.
+    pub async fn measure(
+        &self,
+        flush: impl Future<Output = Result<(), DiskError>>,
+    ) -> Result<(), DiskError> {
+        let start = Instant::now();
+        let r = flush.await;
+        let us = start.elapsed().as_micros() as u64;
+        self.count.increment();
+        if r.is_err() {
+            self.failed.increment();
+        }
+        self.total_us.add(us);
+        self.max_us.fetch_max(us, Ordering::Relaxed);
+        r
+    }
+}
@@ -24,6 +24,25 @@ impl ResourceId<DiskLayerHandleKind> for RamDiskLayerHandle {
     const ID: &'static str = "ram";
 }
 
+/// RAM disk layer handle whose contents are loaded from and periodically
+/// persisted to a file.
+#[derive(MeshPayload)]
+pub struct PersistentRamDiskLayerHandle {
+    /// The size of the layer. If `None`, the layer will take its size from
+    /// the backing file, or from the lower disk if the backing file is empty.
+    pub len: Option<u64>,
+    /// The backing file. This should be empty when first used.
+    pub file: std::fs::File,
+    /// How often to write the layer's contents to the backing file, in
+    /// milliseconds. If `None`, the contents are only written when the layer
+    /// is dropped.
+    pub flush_interval_ms: Option<u64>,
+}
+
+impl ResourceId<DiskLayerHandleKind> for PersistentRamDiskLayerHandle {
+    const ID: &'static str = "ram_persistent";
+}
+
 /// Handle for a disk layer backed by a full disk.
 #[derive(MeshPayload)]
 pub struct DiskLayerHandle(pub Resource<DiskHandleKind>);
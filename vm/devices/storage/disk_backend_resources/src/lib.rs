@@ -8,6 +8,8 @@
 
 pub mod layer;
 
+use mesh::rpc::FailableRpc;
+use mesh::rpc::Rpc;
 use mesh::MeshPayload;
 use vm_resource::kind::DiskHandleKind;
 use vm_resource::kind::DiskLayerHandleKind;
@@ -20,12 +22,57 @@
 
 /// File-backed disk handle.
 #[derive(MeshPayload)]
-pub struct FileDiskHandle(pub std::fs::File);
+pub struct FileDiskHandle {
+    /// The backing file.
+    pub file: std::fs::File,
+    /// How writes are cached by the host.
+    pub write_cache: WriteCacheMode,
+}
+
+impl FileDiskHandle {
+    /// Returns a handle for a disk backed by `file`, using the default
+    /// write caching mode.
+    pub fn new(file: std::fs::File) -> Self {
+        Self {
+            file,
+            write_cache: WriteCacheMode::default(),
+        }
+    }
+}
 
 impl ResourceId<DiskHandleKind> for FileDiskHandle {
     const ID: &'static str = "file";
 }
 
+/// File-backed disk handle that issues IO through io_uring on Linux.
+///
+/// If io_uring is not available, this behaves the same as [`FileDiskHandle`].
+#[derive(MeshPayload)]
+pub struct UringFileDiskHandle {
+    /// The backing file.
+    pub file: std::fs::File,
+    /// If set, the kernel polls for submitted IO from a dedicated thread,
+    /// which goes to sleep after this many milliseconds without IO.
+    pub sq_poll_idle_ms: Option<u32>,
+    /// How writes are cached by the host.
+    pub write_cache: WriteCacheMode,
+}
+
+impl ResourceId<DiskHandleKind> for UringFileDiskHandle {
+    const ID: &'static str = "file_uring";
+}
+
+/// How a disk backend caches guest writes.
+#[derive(MeshPayload, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum WriteCacheMode {
+    /// Writes may be held in the host's cache until the guest issues a flush
+    /// or a write with FUA (force unit access) set.
+    #[default]
+    WriteBack,
+    /// Every write is persisted before it completes, as if FUA were set.
+    WriteThrough,
+}
+
 /// Disk handle for a disk that emulates persistent reservation support.
 #[derive(MeshPayload)]
 pub struct DiskWithReservationsHandle(pub Resource<DiskHandleKind>);
@@ -34,6 +81,133 @@ impl ResourceId<DiskHandleKind> for DiskWithReservationsHandle {
     const ID: &'static str = "prwrap";
 }
 
+/// Disk handle for a disk that emulates persistent reservation support, with
+/// the reservation state stored in a file so that it can be shared by multiple
+/// VMs attached to the same backing disk.
+#[derive(MeshPayload)]
+pub struct DiskWithSharedReservationsHandle {
+    /// The backing disk.
+    pub disk: Resource<DiskHandleKind>,
+    /// The file holding the reservation state. This should be empty when
+    /// first used.
+    pub state_file: std::fs::File,
+}
+
+impl ResourceId<DiskHandleKind> for DiskWithSharedReservationsHandle {
+    const ID: &'static str = "prwrap_shared";
+}
+
+/// Disk handle for a disk that records a checksum of each sector written to
+/// the inner disk in a sidecar file, and verifies the checksums on read.
+///
+/// This is useful for catching data corruption in the storage stack.
+#[derive(MeshPayload)]
+pub struct ChecksumDiskHandle {
+    /// The inner disk.
+    pub disk: Resource<DiskHandleKind>,
+    /// The file holding the checksums. This should be empty when first used,
+    /// and then used only with the same inner disk.
+    pub checksum_file: std::fs::File,
+    /// What to do when a sector does not match its checksum.
+    pub policy: ChecksumMismatchPolicy,
+}
+
+impl ResourceId<DiskHandleKind> for ChecksumDiskHandle {
+    const ID: &'static str = "checksum";
+}
+
+/// The action to take when a sector read from a [`ChecksumDiskHandle`] disk
+/// does not match its checksum.
+#[derive(MeshPayload, Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ChecksumMismatchPolicy {
+    /// Fail the read with a medium error.
+    Fail,
+    /// Log the mismatch and complete the read.
+    Log,
+}
+
+/// Disk handle for a disk that injects faults into IO to the inner disk.
+///
+/// This is useful for testing how the guest and the storage stack handle
+/// failing or slow disks.
+#[derive(MeshPayload)]
+pub struct FaultDiskHandle {
+    /// The inner disk.
+    pub disk: Resource<DiskHandleKind>,
+    /// The faults to inject initially.
+    pub faults: DiskFaults,
+    /// Receives replacements for the injected faults while the VM is running.
+    pub update: mesh::Receiver<DiskFaults>,
+}
+
+impl ResourceId<DiskHandleKind> for FaultDiskHandle {
+    const ID: &'static str = "fault";
+}
+
+/// The faults injected by a [`FaultDiskHandle`] disk.
+#[derive(MeshPayload, Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DiskFaults {
+    /// Fail reads with an IO error.
+    pub fail_reads: bool,
+    /// Fail writes with an IO error, without writing anything.
+    pub fail_writes: bool,
+    /// Fail cache flushes with an IO error.
+    pub fail_flushes: bool,
+    /// Write only the first half of the sectors of each multi-sector write
+    /// and then fail it, as if power was lost partway through.
+    pub torn_writes: bool,
+    /// Delay each IO by this many milliseconds before issuing it.
+    pub latency_ms: u32,
+}
+
+/// Disk handle for a disk with a sparse, in-memory copy-on-write overlay on
+/// top of a base disk.
+///
+/// Writes are held in the overlay until they are committed to the base disk
+/// or discarded via [`OverlayDiskRequest`].
+#[derive(MeshPayload)]
+pub struct OverlayDiskHandle {
+    /// The base disk.
+    pub base: Resource<DiskHandleKind>,
+    /// If true, the base disk is opened read-only, and the overlay cannot be
+    /// committed.
+    pub read_only_base: bool,
+    /// Request channel used to commit or discard the overlay.
+    pub requests: Option<mesh::Receiver<OverlayDiskRequest>>,
+}
+
+/// An overlay disk request.
+#[derive(MeshPayload)]
+pub enum OverlayDiskRequest {
+    /// Write the contents of the overlay to the base disk and flush it,
+    /// emptying the overlay.
+    Commit(FailableRpc<(), ()>),
+    /// Discard the contents of the overlay, reverting the disk to the
+    /// contents of the base disk.
+    Discard(Rpc<(), ()>),
+}
+
+impl ResourceId<DiskHandleKind> for OverlayDiskHandle {
+    const ID: &'static str = "overlay";
+}
+
+/// Disk handle for a disk that is also exported to the host over NBD.
+#[derive(MeshPayload)]
+pub struct NbdExportDiskHandle {
+    /// The disk to export.
+    pub disk: Resource<DiskHandleKind>,
+    /// The listener to accept NBD connections on.
+    pub listener: std::net::TcpListener,
+    /// The NBD export name.
+    pub export_name: String,
+    /// Whether the VM is running. The export is read-only while this is true.
+    pub vm_running: mesh::Cell<bool>,
+}
+
+impl ResourceId<DiskHandleKind> for NbdExportDiskHandle {
+    const ID: &'static str = "nbd_export";
+}
+
 /// Disk handle for a fixed VHD1 disk.
 #[derive(MeshPayload)]
 pub struct FixedVhd1DiskHandle(pub std::fs::File);
@@ -42,6 +216,20 @@ impl ResourceId<DiskHandleKind> for FixedVhd1DiskHandle {
     const ID: &'static str = "fixed_vhd1";
 }
 
+/// Disk handle for a VHDX disk, opened with the native (non-vhdmp) parser.
+#[derive(MeshPayload)]
+pub struct VhdxDiskHandle {
+    /// The VHDX file.
+    pub file: std::fs::File,
+    /// The parent files of a differencing disk, starting with the immediate
+    /// parent. Parents are always opened read-only.
+    pub parents: Vec<std::fs::File>,
+}
+
+impl ResourceId<DiskHandleKind> for VhdxDiskHandle {
+    const ID: &'static str = "vhdx";
+}
+
 /// Disk configuration for a striped disk.
 #[derive(MeshPayload)]
 pub struct StripedDiskHandle {
@@ -86,6 +274,9 @@ pub struct BlobDiskHandle {
     pub url: String,
     /// The format of the blob.
     pub format: BlobDiskFormat,
+    /// An optional local file used to cache blocks of the blob as they are
+    /// read. The file's existing contents are discarded.
+    pub cache: Option<std::fs::File>,
 }
 
 impl ResourceId<DiskHandleKind> for BlobDiskHandle {
@@ -0,0 +1,246 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A blob wrapper that caches blob data in a local file.
+
+use super::file::ReadAt;
+use super::file::WriteAt;
+use super::Blob;
+use async_trait::async_trait;
+use inspect::Inspect;
+use parking_lot::Mutex;
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// The default size of each cached block.
+///
+/// Misses are always fetched in whole blocks, so this also acts as a
+/// read-ahead size for the underlying blob.
+pub const DEFAULT_BLOCK_SIZE: u32 = 1024 * 1024;
+
+/// A blob that caches the contents of an inner blob in a local file.
+///
+/// Data is fetched from the inner blob in aligned blocks the first time it is
+/// read, and subsequent reads of the same blocks are satisfied from the cache
+/// file. This allows large remote blobs to be used without downloading them
+/// in full first.
+///
+/// The set of cached blocks is only tracked in memory, so the cache file's
+/// contents are not reused across instances.
+#[derive(Inspect)]
+#[inspect(bound = "T: Inspect")]
+pub struct CachedBlob<T> {
+    inner: T,
+    #[inspect(skip)]
+    cache: Arc<File>,
+    block_size: u64,
+    #[inspect(skip)]
+    present: Mutex<Vec<u64>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    #[inspect(skip)]
+    cache_write_failed: AtomicBool,
+}
+
+impl<T: Blob> CachedBlob<T> {
+    /// Returns a new blob that caches `inner` in `cache`, using blocks of
+    /// `block_size` bytes.
+    ///
+    /// `cache` will be extended to the size of the blob, but it does not need
+    /// to be preallocated. The existing contents of `cache` are ignored.
+    pub fn new(inner: T, cache: File, block_size: u32) -> io::Result<Self> {
+        if !block_size.is_power_of_two() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "block size must be a power of two",
+            ));
+        }
+        let block_size = block_size as u64;
+        cache.set_len(inner.len())?;
+        let block_count = inner.len().div_ceil(block_size);
+        Ok(Self {
+            cache: Arc::new(cache),
+            block_size,
+            present: Mutex::new(vec![0; block_count.div_ceil(64) as usize]),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            cache_write_failed: false.into(),
+            inner,
+        })
+    }
+
+    fn is_present(&self, block: u64) -> bool {
+        self.present.lock()[(block / 64) as usize] & (1 << (block % 64)) != 0
+    }
+
+    fn set_present(&self, blocks: Range<u64>) {
+        let mut present = self.present.lock();
+        for block in blocks {
+            present[(block / 64) as usize] |= 1 << (block % 64);
+        }
+    }
+
+    async fn read_cache(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let cache = self.cache.clone();
+        let len = buf.len();
+        let data = blocking::unblock(move || {
+            let mut data = vec![0; len];
+            let n = cache.read_at(&mut data, offset)?;
+            if n < data.len() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            io::Result::Ok(data)
+        })
+        .await?;
+        buf.copy_from_slice(&data);
+        Ok(())
+    }
+
+    async fn write_cache(&self, data: Vec<u8>, offset: u64) -> io::Result<()> {
+        let cache = self.cache.clone();
+        blocking::unblock(move || {
+            let n = cache.write_at(&data, offset)?;
+            if n < data.len() {
+                return Err(io::ErrorKind::WriteZero.into());
+            }
+            Ok(())
+        })
+        .await
+    }
+}
+
+#[async_trait]
+impl<T: Blob + Send + Sync> Blob for CachedBlob<T> {
+    async fn read(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        if buf.is_empty() {
+            return Ok(());
+        }
+        let end = offset
+            .checked_add(buf.len() as u64)
+            .filter(|&end| end <= self.len())
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+
+        let last_block = (end - 1) / self.block_size;
+        let mut block = offset / self.block_size;
+        while block <= last_block {
+            // Find the run of blocks that are all cached or all not cached, so
+            // that each run can be serviced with a single read.
+            let present = self.is_present(block);
+            let mut run_end = block + 1;
+            while run_end <= last_block && self.is_present(run_end) == present {
+                run_end += 1;
+            }
+
+            let run_offset = block * self.block_size;
+            let run_len = (run_end * self.block_size).min(self.len()) - run_offset;
+            let copy = offset.max(run_offset)..end.min(run_offset + run_len);
+            let dest = &mut buf[(copy.start - offset) as usize..(copy.end - offset) as usize];
+            if present {
+                self.hits.fetch_add(run_end - block, Ordering::Relaxed);
+                self.read_cache(dest, copy.start).await?;
+            } else {
+                // N.B. Concurrent reads of the same uncached blocks may each
+                //      fetch them. This is harmless since the data is the same.
+                self.misses.fetch_add(run_end - block, Ordering::Relaxed);
+                let mut data = vec![0; run_len as usize];
+                self.inner.read(&mut data, run_offset).await?;
+                dest.copy_from_slice(
+                    &data[(copy.start - run_offset) as usize..(copy.end - run_offset) as usize],
+                );
+                // Failing to populate the cache is not fatal; the blocks will
+                // just be fetched again next time.
+                match self.write_cache(data, run_offset).await {
+                    Ok(()) => self.set_present(block..run_end),
+                    Err(err) => {
+                        if !self.cache_write_failed.swap(true, Ordering::Relaxed) {
+                            tracing::warn!(
+                                error = &err as &dyn std::error::Error,
+                                "failed to write to blob cache"
+                            );
+                        }
+                    }
+                }
+            }
+            block = run_end;
+        }
+        Ok(())
+    }
+
+    fn len(&self) -> u64 {
+        self.inner.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CachedBlob;
+    use crate::blob::Blob;
+    use async_trait::async_trait;
+    use inspect::Inspect;
+    use pal_async::async_test;
+    use std::io;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    #[derive(Inspect)]
+    struct TestBlob {
+        #[inspect(skip)]
+        data: Vec<u8>,
+        #[inspect(skip)]
+        reads: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl Blob for TestBlob {
+        async fn read(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            let offset = offset as usize;
+            buf.copy_from_slice(&self.data[offset..offset + buf.len()]);
+            Ok(())
+        }
+
+        fn len(&self) -> u64 {
+            self.data.len() as u64
+        }
+    }
+
+    #[async_test]
+    async fn cached_read() {
+        let data = (0..0x2800).map(|i| i as u8).collect::<Vec<_>>();
+        let blob = CachedBlob::new(
+            TestBlob {
+                data: data.clone(),
+                reads: AtomicUsize::new(0),
+            },
+            tempfile::tempfile().unwrap(),
+            0x1000,
+        )
+        .unwrap();
+
+        let mut buf = vec![0; 0x100];
+        blob.read(&mut buf, 0x1f80).await.unwrap();
+        assert_eq!(buf, data[0x1f80..0x2080]);
+        assert_eq!(blob.inner.reads.load(Ordering::Relaxed), 1);
+
+        // Cached blocks are not fetched again, and the partial block at the
+        // end of the blob is handled.
+        let mut buf = vec![0; 0x1800];
+        blob.read(&mut buf, 0x1000).await.unwrap();
+        assert_eq!(buf, data[0x1000..]);
+        assert_eq!(blob.inner.reads.load(Ordering::Relaxed), 1);
+
+        // A read spanning cached and uncached blocks only fetches the
+        // uncached ones.
+        let mut buf = vec![0; 0x2800];
+        blob.read(&mut buf, 0).await.unwrap();
+        assert_eq!(buf, data);
+        assert_eq!(blob.inner.reads.load(Ordering::Relaxed), 2);
+
+        blob.read(&mut [0; 1], 0x2800).await.unwrap_err();
+    }
+}
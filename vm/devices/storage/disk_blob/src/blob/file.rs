@@ -57,10 +57,15 @@ fn len(&self) -> u64 {
 /// The semantics are slightly different between Windows and Unix--on Windows,
 /// each operation updates the current file pointer, whereas on Unix it does
 /// not.
-trait ReadAt {
+pub(super) trait ReadAt {
     fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize>;
 }
 
+/// The write counterpart to [`ReadAt`].
+pub(super) trait WriteAt {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize>;
+}
+
 #[cfg(windows)]
 impl ReadAt for File {
     fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
@@ -74,3 +79,17 @@ fn read_at(&self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
         std::os::unix::fs::FileExt::read_at(self, buf, offset)
     }
 }
+
+#[cfg(windows)]
+impl WriteAt for File {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        std::os::windows::fs::FileExt::seek_write(self, buf, offset)
+    }
+}
+
+#[cfg(unix)]
+impl WriteAt for File {
+    fn write_at(&self, buf: &[u8], offset: u64) -> io::Result<usize> {
+        std::os::unix::fs::FileExt::write_at(self, buf, offset)
+    }
+}
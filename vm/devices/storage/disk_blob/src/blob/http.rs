@@ -27,6 +27,9 @@
 use std::io;
 
 /// A blob backed by an HTTP/HTTPS connection.
+///
+/// This supports any server that implements HTTP range requests, including
+/// Azure Storage block and page blobs (typically accessed via a SAS URL).
 #[derive(Debug, Inspect)]
 pub struct HttpBlob {
     #[inspect(skip)]
@@ -42,6 +45,13 @@ pub struct HttpBlob {
 
 static TOKIO_RUNTIME: OnceCell<tokio::runtime::Runtime> = OnceCell::new();
 
+/// The Azure Storage REST API version to request.
+///
+/// Without this, Azure Storage handles anonymous and SAS requests using a
+/// legacy API version with more limited range read support. Other servers
+/// ignore the header.
+const AZURE_STORAGE_VERSION: &str = "2021-08-06";
+
 impl HttpBlob {
     /// Connects to `url` and returns an object to access it as a blob.
     pub async fn new(url: &str) -> anyhow::Result<Self> {
@@ -69,6 +79,7 @@ pub async fn new(url: &str) -> anyhow::Result<Self> {
                         Request::builder()
                             .uri(&uri)
                             .method("HEAD")
+                            .header("x-ms-version", AZURE_STORAGE_VERSION)
                             .body(Empty::new())
                             .unwrap(),
                     ),
@@ -133,6 +144,7 @@ async fn read(&self, mut buf: &mut [u8], offset: u64) -> io::Result<()> {
                 self.client.request(
                     Request::builder()
                         .uri(&self.uri)
+                        .header("x-ms-version", AZURE_STORAGE_VERSION)
                         .header(
                             hyper::header::RANGE,
                             format!("bytes={}-{}", offset, offset + buf.len() as u64 - 1,),
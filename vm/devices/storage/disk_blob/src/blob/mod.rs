@@ -3,6 +3,7 @@
 
 //! The blob trait and blob implementations.
 
+pub mod cache;
 pub mod file;
 pub mod http;
 
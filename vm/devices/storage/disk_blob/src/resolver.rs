@@ -3,7 +3,10 @@
 
 //! Resolver implementation for [`BlobDisk`].
 
+use crate::blob::cache::CachedBlob;
+use crate::blob::cache::DEFAULT_BLOCK_SIZE;
 use crate::blob::http::HttpBlob;
+use crate::blob::Blob;
 use crate::BlobDisk;
 use async_trait::async_trait;
 use disk_backend::resolve::ResolveDiskParameters;
@@ -36,11 +39,27 @@ async fn resolve(
         }
 
         let blob = HttpBlob::new(&rsrc.url).await?;
-        let disk = match rsrc.format {
-            BlobDiskFormat::Flat => BlobDisk::new(blob),
-            BlobDiskFormat::FixedVhd1 => BlobDisk::new_fixed_vhd1(blob).await?,
+        let disk = match rsrc.cache {
+            Some(cache) => {
+                open_disk(
+                    CachedBlob::new(blob, cache, DEFAULT_BLOCK_SIZE)?,
+                    rsrc.format,
+                )
+                .await?
+            }
+            None => open_disk(blob, rsrc.format).await?,
         };
 
         Ok(ResolvedDisk::new(disk)?)
     }
 }
+
+async fn open_disk(
+    blob: impl 'static + Blob + Send + Sync,
+    format: BlobDiskFormat,
+) -> anyhow::Result<BlobDisk> {
+    Ok(match format {
+        BlobDiskFormat::Flat => BlobDisk::new(blob),
+        BlobDiskFormat::FixedVhd1 => BlobDisk::new_fixed_vhd1(blob).await?,
+    })
+}
@@ -21,9 +21,11 @@
 use disk_backend::pr::ReservationType;
 use disk_backend::resolve::ResolveDiskParameters;
 use disk_backend::resolve::ResolvedDisk;
+use disk_backend::stats::FlushStats;
 use disk_backend::DiskError;
 use disk_backend::DiskIo;
 use disk_backend::UnmapBehavior;
+use disk_backend_resources::WriteCacheMode;
 use fs_err::PathExt;
 use guestmem::MemoryRead;
 use guestmem::MemoryWrite;
@@ -84,6 +86,7 @@ pub fn new(
 #[derive(MeshPayload)]
 pub struct OpenBlockDeviceConfig {
     pub file: fs::File,
+    pub write_cache: WriteCacheMode,
 }
 
 impl ResourceId<DiskHandleKind> for OpenBlockDeviceConfig {
@@ -109,7 +112,7 @@ async fn resolve(
         rsrc: OpenBlockDeviceConfig,
         input: ResolveDiskParameters<'_>,
     ) -> Result<Self::Output, Self::Error> {
-        let disk = BlockDevice::new(
+        let mut disk = BlockDevice::new(
             rsrc.file,
             input.read_only,
             self.uring.clone(),
@@ -119,6 +122,7 @@ async fn resolve(
         )
         .await
         .map_err(ResolveDiskError::NewDevice)?;
+        disk.set_write_cache(rsrc.write_cache);
         ResolvedDisk::new(disk).map_err(ResolveDiskError::InvalidDisk)
     }
 }
@@ -152,6 +156,9 @@ pub struct BlockDevice {
     device_type: DeviceType,
     supports_pr: bool,
     supports_fua: bool,
+    #[inspect(debug)]
+    write_cache: WriteCacheMode,
+    flush: FlushStats,
     #[inspect(skip)]
     _uevent_filter: Option<CallbackHandle>,
     resize_epoch: Arc<ResizeEpoch>,
@@ -309,6 +316,8 @@ pub async fn new(
             device_type: devmeta.device_type,
             supports_pr: devmeta.supports_pr,
             supports_fua: devmeta.fua,
+            write_cache: WriteCacheMode::WriteBack,
+            flush: FlushStats::default(),
             _uevent_filter: uevent_filter,
             resize_epoch,
             resized_acked: 0.into(),
@@ -319,6 +328,14 @@ pub async fn new(
         Ok(device)
     }
 
+    /// Sets how writes are cached by the host.
+    ///
+    /// In [`WriteCacheMode::WriteThrough`] mode, every write is issued as if
+    /// the guest had set FUA.
+    pub fn set_write_cache(&mut self, write_cache: WriteCacheMode) {
+        self.write_cache = write_cache;
+    }
+
     fn initiator(&self) -> &IoInitiator {
         self.uring.initiator()
     }
@@ -612,7 +629,8 @@ async fn write_vectored(
         fua: bool,
     ) -> Result<(), DiskError> {
         let io_size = buffers.len();
-        tracing::trace!(sector, io_size, "write_vectored");
+        let fua = fua || self.write_cache == WriteCacheMode::WriteThrough;
+        tracing::trace!(sector, io_size, fua, "write_vectored");
 
         // Ensure the write doesn't extend the file.
         if let DeviceType::File { sector_count } = self.device_type {
@@ -668,17 +686,21 @@ async fn write_vectored(
     }
 
     async fn sync_cache(&self) -> Result<(), DiskError> {
-        // SAFETY: No data buffers.
-        unsafe {
-            self.initiator()
-                .issue_io((), |_| {
-                    opcode::Fsync::new(types::Fd(self.file.as_raw_fd())).build()
-                })
-                .await
-                .0
-                .map_err(|err| self.map_io_error(err))?;
-        }
-        Ok(())
+        self.flush
+            .measure(async {
+                // SAFETY: No data buffers.
+                unsafe {
+                    self.initiator()
+                        .issue_io((), |_| {
+                            opcode::Fsync::new(types::Fd(self.file.as_raw_fd())).build()
+                        })
+                        .await
+                        .0
+                        .map_err(|err| self.map_io_error(err))?;
+                }
+                Ok(())
+            })
+            .await
     }
 
     async fn wait_resize(&self, sector_count: u64) -> u64 {
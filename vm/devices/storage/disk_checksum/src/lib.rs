@@ -0,0 +1,388 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A disk wrapper that records a CRC32 checksum of each sector written to the
+//! inner disk, and verifies the checksums when sectors are read back.
+//!
+//! The checksums are stored in a sidecar file, so they survive across VM
+//! runs. Sectors with no recorded checksum (because they have never been
+//! written through this disk, or because they were unmapped) are not
+//! verified. This is intended for catching data corruption bugs in the
+//! storage stack in tests, not for production use.
+
+#![forbid(unsafe_code)]
+
+mod sidecar;
+
+use async_trait::async_trait;
+use disk_backend::pr;
+use disk_backend::resolve::ResolveDiskParameters;
+use disk_backend::resolve::ResolvedDisk;
+use disk_backend::Disk;
+use disk_backend::DiskError;
+use disk_backend::DiskIo;
+use disk_backend::MediumErrorDetails;
+use disk_backend::UnmapBehavior;
+use disk_backend_resources::ChecksumDiskHandle;
+use disk_backend_resources::ChecksumMismatchPolicy;
+use guestmem::GuestMemory;
+use guestmem::MemoryRead;
+use inspect::Inspect;
+use inspect_counters::SharedCounter;
+use parking_lot::Mutex;
+use scsi_buffers::OwnedRequestBuffers;
+use scsi_buffers::RequestBuffers;
+use sidecar::Sidecar;
+use std::io;
+use thiserror::Error;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::DiskHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::ResolveError;
+use vm_resource::ResourceResolver;
+
+pub struct ChecksumDiskResolver;
+declare_static_async_resolver!(ChecksumDiskResolver, (DiskHandleKind, ChecksumDiskHandle));
+
+#[derive(Debug, Error)]
+pub enum ResolveChecksumDiskError {
+    #[error("failed to resolve inner disk")]
+    Resolve(#[source] ResolveError),
+    #[error("failed to open checksum file")]
+    Checksums(#[source] io::Error),
+    #[error("invalid disk")]
+    InvalidDisk(#[source] disk_backend::InvalidDisk),
+}
+
+#[async_trait]
+impl AsyncResolveResource<DiskHandleKind, ChecksumDiskHandle> for ChecksumDiskResolver {
+    type Output = ResolvedDisk;
+    type Error = ResolveChecksumDiskError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        rsrc: ChecksumDiskHandle,
+        input: ResolveDiskParameters<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let inner = resolver
+            .resolve(rsrc.disk, input)
+            .await
+            .map_err(ResolveChecksumDiskError::Resolve)?;
+
+        let disk = ChecksumDisk::new(inner.0, rsrc.checksum_file, rsrc.policy)
+            .map_err(ResolveChecksumDiskError::Checksums)?;
+        ResolvedDisk::new(disk).map_err(ResolveChecksumDiskError::InvalidDisk)
+    }
+}
+
+/// The size of each reusable bounce buffer. Larger writes allocate a buffer
+/// just for the write.
+const BOUNCE_BUFFER_SIZE: usize = 0x40000;
+
+/// The maximum number of idle bounce buffers to keep for reuse.
+const MAX_BOUNCE_BUFFERS: usize = 8;
+
+/// A disk wrapper that verifies sector checksums on read.
+///
+/// Writes are staged in a private bounce buffer so that the checksummed data
+/// cannot be changed by the guest while the write is in flight.
+#[derive(Inspect)]
+pub struct ChecksumDisk {
+    inner: Disk,
+    #[inspect(skip)]
+    sector_shift: u32,
+    #[inspect(debug)]
+    policy: ChecksumMismatchPolicy,
+    #[inspect(skip)]
+    sidecar: Sidecar,
+    #[inspect(skip)]
+    bounce_buffers: Mutex<Vec<GuestMemory>>,
+    /// The number of sectors read that matched their checksum.
+    verified_sectors: SharedCounter,
+    /// The number of sectors read that did not match their checksum.
+    mismatched_sectors: SharedCounter,
+}
+
+impl ChecksumDisk {
+    /// Wraps `inner`, storing checksums in `checksum_file`.
+    ///
+    /// `checksum_file` should be empty on first use, and then used only with
+    /// the same inner disk.
+    pub fn new(
+        inner: Disk,
+        checksum_file: std::fs::File,
+        policy: ChecksumMismatchPolicy,
+    ) -> io::Result<Self> {
+        let sidecar = Sidecar::new(checksum_file, inner.sector_size())?;
+        Ok(Self {
+            sector_shift: inner.sector_shift(),
+            inner,
+            policy,
+            sidecar,
+            bounce_buffers: Default::default(),
+            verified_sectors: Default::default(),
+            mismatched_sectors: Default::default(),
+        })
+    }
+
+    fn checksums(&self, data: &[u8]) -> Vec<u32> {
+        data.chunks_exact(1 << self.sector_shift)
+            .map(crc32fast::hash)
+            .collect()
+    }
+}
+
+impl DiskIo for ChecksumDisk {
+    fn disk_type(&self) -> &str {
+        "checksum"
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.inner.sector_count()
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.inner.sector_size()
+    }
+
+    fn disk_id(&self) -> Option<[u8; 16]> {
+        self.inner.disk_id()
+    }
+
+    fn physical_sector_size(&self) -> u32 {
+        self.inner.physical_sector_size()
+    }
+
+    fn is_fua_respected(&self) -> bool {
+        self.inner.is_fua_respected()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn pr(&self) -> Option<&dyn pr::PersistentReservation> {
+        self.inner.pr()
+    }
+
+    async fn read_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+    ) -> Result<(), DiskError> {
+        self.inner.read_vectored(buffers, sector).await?;
+
+        let mut data = vec![0; buffers.len()];
+        buffers.reader().read(&mut data)?;
+        let expected = self
+            .sidecar
+            .read(sector, data.len() >> self.sector_shift)
+            .await
+            .map_err(DiskError::Io)?;
+
+        let mut mismatched = false;
+        for ((n, expected), actual) in (sector..).zip(expected).zip(self.checksums(&data)) {
+            let Some(expected) = expected else {
+                continue;
+            };
+            if expected == actual {
+                self.verified_sectors.increment();
+            } else {
+                self.mismatched_sectors.increment();
+                mismatched = true;
+                tracing::error!(
+                    sector = n,
+                    expected,
+                    actual,
+                    policy = ?self.policy,
+                    "disk sector checksum mismatch"
+                );
+            }
+        }
+
+        if mismatched && self.policy == ChecksumMismatchPolicy::Fail {
+            return Err(DiskError::MediumError(
+                io::Error::new(io::ErrorKind::InvalidData, "sector checksum mismatch"),
+                MediumErrorDetails::GuardCheckFailed,
+            ));
+        }
+        Ok(())
+    }
+
+    async fn write_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+        fua: bool,
+    ) -> Result<(), DiskError> {
+        let mut data = vec![0; buffers.len()];
+        buffers.reader().read(&mut data)?;
+        let checksums = self.checksums(&data);
+
+        // Forget the old checksums first, so that a concurrent read of these
+        // sectors is not verified against them if it sees the new data.
+        self.sidecar
+            .clear(sector, checksums.len() as u64)
+            .await
+            .map_err(DiskError::Io)?;
+
+        // Reuse a pooled bounce buffer if the write fits in one.
+        let pooled = data.len() <= BOUNCE_BUFFER_SIZE;
+        let mem = if pooled {
+            self.bounce_buffers
+                .lock()
+                .pop()
+                .unwrap_or_else(|| GuestMemory::allocate(BOUNCE_BUFFER_SIZE))
+        } else {
+            GuestMemory::allocate(data.len())
+        };
+        mem.write_at(0, &data).unwrap();
+        let result = self
+            .inner
+            .write_vectored(
+                &OwnedRequestBuffers::linear(0, data.len(), false).buffer(&mem),
+                sector,
+                fua,
+            )
+            .await;
+        if pooled {
+            let mut bounce_buffers = self.bounce_buffers.lock();
+            if bounce_buffers.len() < MAX_BOUNCE_BUFFERS {
+                bounce_buffers.push(mem);
+            }
+        }
+        result?;
+
+        self.sidecar
+            .write(sector, &checksums)
+            .await
+            .map_err(DiskError::Io)
+    }
+
+    async fn sync_cache(&self) -> Result<(), DiskError> {
+        self.inner.sync_cache().await?;
+        self.sidecar.sync().await.map_err(DiskError::Io)
+    }
+
+    async fn wait_resize(&self, sector_count: u64) -> u64 {
+        self.inner.wait_resize(sector_count).await
+    }
+
+    async fn unmap(
+        &self,
+        sector: u64,
+        count: u64,
+        block_level_only: bool,
+    ) -> Result<(), DiskError> {
+        // The contents of unmapped sectors may change.
+        if self.inner.unmap_behavior() != UnmapBehavior::Ignored {
+            self.sidecar
+                .clear(sector, count)
+                .await
+                .map_err(DiskError::Io)?;
+        }
+        self.inner.unmap(sector, count, block_level_only).await
+    }
+
+    fn unmap_behavior(&self) -> UnmapBehavior {
+        self.inner.unmap_behavior()
+    }
+
+    fn optimal_unmap_sectors(&self) -> u32 {
+        self.inner.optimal_unmap_sectors()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChecksumDisk;
+    use disk_backend::Disk;
+    use disk_backend::DiskError;
+    use disk_backend_resources::ChecksumMismatchPolicy;
+    use disk_ramdisk::ram_disk;
+    use guestmem::GuestMemory;
+    use pal_async::async_test;
+    use scsi_buffers::OwnedRequestBuffers;
+
+    const SECTOR: usize = 512;
+
+    async fn write(disk: &Disk, mem: &GuestMemory, sector: u64, data: &[u8]) {
+        mem.write_at(0, data).unwrap();
+        disk.write_vectored(
+            &OwnedRequestBuffers::linear(0, data.len(), false).buffer(mem),
+            sector,
+            false,
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn read(
+        disk: &Disk,
+        mem: &GuestMemory,
+        sector: u64,
+        count: usize,
+    ) -> Result<(), DiskError> {
+        disk.read_vectored(
+            &OwnedRequestBuffers::linear(0, count * SECTOR, true).buffer(mem),
+            sector,
+        )
+        .await
+    }
+
+    /// Returns the inner disk and the checksum disk, with sectors 0 to 3
+    /// written through the checksum disk and sector 2 then corrupted.
+    async fn setup(mem: &GuestMemory, policy: ChecksumMismatchPolicy) -> (Disk, Disk) {
+        let inner = ram_disk(0x100000, false).unwrap();
+        let disk = Disk::new(
+            ChecksumDisk::new(inner.clone(), tempfile::tempfile().unwrap(), policy).unwrap(),
+        )
+        .unwrap();
+        write(&disk, mem, 0, &[1; 4 * SECTOR]).await;
+        read(&disk, mem, 0, 8).await.unwrap();
+        write(&inner, mem, 2, &[2; SECTOR]).await;
+        (inner, disk)
+    }
+
+    #[async_test]
+    async fn mismatch_fails() {
+        let mem = GuestMemory::allocate(0x10000);
+        let (_, disk) = setup(&mem, ChecksumMismatchPolicy::Fail).await;
+        read(&disk, &mem, 0, 2).await.unwrap();
+        assert!(matches!(
+            read(&disk, &mem, 0, 4).await,
+            Err(DiskError::MediumError(..))
+        ));
+
+        // Rewriting the sector through the checksum disk fixes it.
+        write(&disk, &mem, 2, &[3; SECTOR]).await;
+        read(&disk, &mem, 0, 4).await.unwrap();
+    }
+
+    #[async_test]
+    async fn mismatch_logs() {
+        let mem = GuestMemory::allocate(0x10000);
+        let (_, disk) = setup(&mem, ChecksumMismatchPolicy::Log).await;
+        read(&disk, &mem, 0, 4).await.unwrap();
+    }
+
+    #[async_test]
+    async fn large_write() {
+        // Larger than a pooled bounce buffer.
+        let len = super::BOUNCE_BUFFER_SIZE + SECTOR;
+        let mem = GuestMemory::allocate(len);
+        let disk = Disk::new(
+            ChecksumDisk::new(
+                ram_disk(0x100000, false).unwrap(),
+                tempfile::tempfile().unwrap(),
+                ChecksumMismatchPolicy::Fail,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        write(&disk, &mem, 0, &vec![4; len]).await;
+        write(&disk, &mem, 1, &[5; SECTOR]).await;
+        read(&disk, &mem, 0, len / SECTOR).await.unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The sidecar file holding the per-sector checksums.
+//!
+//! The file starts with a header identifying the sector size, followed by one
+//! 8-byte entry per sector. An entry is zero if no checksum is known for the
+//! sector, which is the case for sectors that were never written through the
+//! checksum disk, so a new sidecar file can start out empty (or sparse).
+
+use blocking::unblock;
+use parking_lot::Mutex;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::sync::Arc;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+const MAGIC: [u8; 8] = *b"OVMMCSUM";
+const HEADER_SIZE: u64 = size_of::<Header>() as u64;
+const ENTRY_SIZE: u64 = size_of::<u64>() as u64;
+
+/// Set in an entry that holds a valid checksum, in the low 32 bits.
+const ENTRY_VALID: u64 = 1 << 32;
+
+/// The size of the buffer used to clear large ranges of entries.
+const CLEAR_CHUNK_ENTRIES: u64 = 0x10000;
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+struct Header {
+    magic: [u8; 8],
+    sector_size: u32,
+    reserved: u32,
+}
+
+/// The sidecar file.
+///
+/// All file IO runs on the blocking thread pool, so that it does not stall
+/// the executor running the disk IO.
+pub(crate) struct Sidecar {
+    // The mutex serializes use of the file position.
+    file: Arc<Mutex<fs::File>>,
+}
+
+impl Sidecar {
+    /// Opens the sidecar in `file`, which may be empty.
+    ///
+    /// Fails if the file holds checksums for a different sector size.
+    pub fn new(mut file: fs::File, sector_size: u32) -> io::Result<Self> {
+        if file.metadata()?.len() == 0 {
+            let header = Header {
+                magic: MAGIC,
+                sector_size,
+                reserved: 0,
+            };
+            file.write_all(header.as_bytes())?;
+        } else {
+            let mut header = Header::new_zeroed();
+            file.rewind()?;
+            file.read_exact(header.as_bytes_mut())?;
+            if header.magic != MAGIC {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "not a disk checksum file",
+                ));
+            }
+            if header.sector_size != sector_size {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "checksum file sector size {} does not match disk sector size {}",
+                        header.sector_size, sector_size
+                    ),
+                ));
+            }
+        }
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+
+    /// Returns the checksums of `count` sectors starting at `sector`, or `None`
+    /// for sectors with no known checksum.
+    pub async fn read(&self, sector: u64, count: usize) -> io::Result<Vec<Option<u32>>> {
+        let file = self.file.clone();
+        unblock(move || {
+            let mut entries = vec![0u64; count];
+            let mut file = file.lock();
+            file.seek(SeekFrom::Start(entry_offset(sector)))?;
+            // Entries past the end of the file are zero.
+            let buf = entries.as_bytes_mut();
+            let mut n = 0;
+            while n < buf.len() {
+                match file.read(&mut buf[n..])? {
+                    0 => break,
+                    len => n += len,
+                }
+            }
+            Ok(entries
+                .into_iter()
+                .map(|entry| (entry & ENTRY_VALID != 0).then_some(entry as u32))
+                .collect())
+        })
+        .await
+    }
+
+    /// Sets the checksums of the sectors starting at `sector`.
+    pub async fn write(&self, sector: u64, checksums: &[u32]) -> io::Result<()> {
+        let entries: Vec<u64> = checksums
+            .iter()
+            .map(|&crc| ENTRY_VALID | crc as u64)
+            .collect();
+        let file = self.file.clone();
+        unblock(move || {
+            let mut file = file.lock();
+            file.seek(SeekFrom::Start(entry_offset(sector)))?;
+            file.write_all(entries.as_bytes())
+        })
+        .await
+    }
+
+    /// Forgets the checksums of `count` sectors starting at `sector`.
+    pub async fn clear(&self, sector: u64, count: u64) -> io::Result<()> {
+        let file = self.file.clone();
+        unblock(move || {
+            let mut file = file.lock();
+            // Entries past the end of the file are already zero.
+            let len = file.metadata()?.len();
+            let end = entry_offset(sector.saturating_add(count)).min(len);
+            let mut offset = entry_offset(sector);
+            if offset >= end {
+                return Ok(());
+            }
+            let zeroes = vec![0; (CLEAR_CHUNK_ENTRIES * ENTRY_SIZE).min(end - offset) as usize];
+            file.seek(SeekFrom::Start(offset))?;
+            while offset < end {
+                let n = (end - offset).min(zeroes.len() as u64);
+                file.write_all(&zeroes[..n as usize])?;
+                offset += n;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Flushes the checksums to stable storage.
+    pub async fn sync(&self) -> io::Result<()> {
+        let file = self.file.clone();
+        unblock(move || file.lock().sync_data()).await
+    }
+}
+
+fn entry_offset(sector: u64) -> u64 {
+    HEADER_SIZE.saturating_add(sector.saturating_mul(ENTRY_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sidecar;
+    use pal_async::async_test;
+
+    #[async_test]
+    async fn read_write_clear() {
+        let file = tempfile::tempfile().unwrap();
+        let sidecar = Sidecar::new(file.try_clone().unwrap(), 512).unwrap();
+        assert_eq!(sidecar.read(0, 2).await.unwrap(), [None, None]);
+
+        sidecar.write(1, &[0, 0x1234]).await.unwrap();
+        assert_eq!(
+            sidecar.read(0, 4).await.unwrap(),
+            [None, Some(0), Some(0x1234), None]
+        );
+
+        sidecar.clear(2, 100).await.unwrap();
+        assert_eq!(
+            sidecar.read(0, 4).await.unwrap(),
+            [None, Some(0), None, None]
+        );
+
+        // Reopening keeps the checksums, as long as the sector size matches.
+        let sidecar = Sidecar::new(file.try_clone().unwrap(), 512).unwrap();
+        assert_eq!(sidecar.read(1, 1).await.unwrap(), [Some(0)]);
+        assert!(Sidecar::new(file, 4096).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Support for reading disk encryption keys from the Linux kernel keyring.
+
+// UNSAFETY: Calling the keyring syscalls.
+#![allow(unsafe_code)]
+
+use std::ffi::CString;
+use std::io;
+
+const KEYCTL_READ: libc::c_long = 11;
+
+/// Reads the payload of the `user` key with the given description, searching
+/// the process's thread, process, and session keyrings.
+pub fn read_user_key(description: &str) -> io::Result<Vec<u8>> {
+    let description = CString::new(description)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+
+    // SAFETY: the strings are valid and nul terminated, and a null callout
+    // info and zero destination keyring are allowed.
+    let serial = unsafe {
+        libc::syscall(
+            libc::SYS_request_key,
+            c"user".as_ptr(),
+            description.as_ptr(),
+            std::ptr::null::<libc::c_char>(),
+            0,
+        )
+    };
+    if serial < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // The key may change size between calls, so loop until the buffer is big
+    // enough.
+    let mut buf = Vec::new();
+    loop {
+        // SAFETY: the buffer is valid for writes of its length.
+        let len = unsafe {
+            libc::syscall(
+                libc::SYS_keyctl,
+                KEYCTL_READ,
+                serial,
+                buf.as_mut_ptr(),
+                buf.len(),
+            )
+        };
+        if len < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let len = len as usize;
+        if len <= buf.len() {
+            buf.truncate(len);
+            return Ok(buf);
+        }
+        buf.resize(len, 0);
+    }
+}
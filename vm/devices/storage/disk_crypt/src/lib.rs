@@ -6,6 +6,8 @@
 
 #![warn(missing_docs)]
 
+#[cfg(target_os = "linux")]
+mod keyring;
 pub mod resolver;
 
 use block_crypto::XtsAes256;
@@ -186,3 +188,56 @@ fn optimal_unmap_sectors(&self) -> u32 {
 fn crypto_error(err: block_crypto::Error) -> DiskError {
     DiskError::Io(std::io::Error::new(std::io::ErrorKind::Other, err))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CryptDisk;
+    use super::NewDiskError;
+    use disk_backend::Disk;
+    use disk_crypt_resources::Cipher;
+    use disk_ramdisk::ram_disk;
+    use guestmem::GuestMemory;
+    use pal_async::async_test;
+    use scsi_buffers::OwnedRequestBuffers;
+
+    const KEY: [u8; 64] = [7; 64];
+
+    async fn read(disk: &Disk, mem: &GuestMemory, len: usize) -> Vec<u8> {
+        disk.read_vectored(&OwnedRequestBuffers::linear(0, len, true).buffer(mem), 0)
+            .await
+            .unwrap();
+        let mut buf = vec![0; len];
+        mem.read_at(0, &mut buf).unwrap();
+        buf
+    }
+
+    #[async_test]
+    async fn round_trip() {
+        let inner = ram_disk(0x10000, false).unwrap();
+        let disk =
+            Disk::new(CryptDisk::new(Cipher::XtsAes256, &KEY, inner.clone()).unwrap()).unwrap();
+
+        let mem = GuestMemory::allocate(0x1000);
+        let data: Vec<u8> = (0..0x1000).map(|i| i as u8).collect();
+        mem.write_at(0, &data).unwrap();
+        disk.write_vectored(
+            &OwnedRequestBuffers::linear(0, data.len(), false).buffer(&mem),
+            0,
+            false,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(read(&disk, &mem, data.len()).await, data);
+        assert_ne!(read(&inner, &mem, data.len()).await, data);
+    }
+
+    #[test]
+    fn invalid_key_size() {
+        let inner = ram_disk(0x10000, false).unwrap();
+        assert!(matches!(
+            CryptDisk::new(Cipher::XtsAes256, &KEY[..32], inner),
+            Err(NewDiskError::InvalidKeySize)
+        ));
+    }
+}
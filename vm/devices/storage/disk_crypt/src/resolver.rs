@@ -1,18 +1,26 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-//! Resource resolver for the encrypted disk device.
+//! Resource resolvers for the encrypted disk device and its keys.
 
 use crate::CryptDisk;
 use async_trait::async_trait;
 use disk_backend::resolve::ResolveDiskParameters;
 use disk_backend::resolve::ResolvedDisk;
 use disk_crypt_resources::DiskCryptHandle;
+use disk_crypt_resources::DiskKeyHandleKind;
+use disk_crypt_resources::FileDiskKeyHandle;
+#[cfg(target_os = "linux")]
+use disk_crypt_resources::KeyringDiskKeyHandle;
+use disk_crypt_resources::ResolvedDiskKey;
+use std::io::Read;
 use thiserror::Error;
 use vm_resource::declare_static_async_resolver;
+use vm_resource::declare_static_resolver;
 use vm_resource::kind::DiskHandleKind;
 use vm_resource::AsyncResolveResource;
 use vm_resource::ResolveError;
+use vm_resource::ResolveResource;
 use vm_resource::ResourceResolver;
 
 declare_static_async_resolver! {
@@ -29,6 +37,9 @@ pub enum DiskResolveError {
     /// Failed to resolve the inner disk.
     #[error("failed to resolve inner disk")]
     ResolveInner(#[source] ResolveError),
+    /// Failed to resolve the key.
+    #[error("failed to resolve key")]
+    ResolveKey(#[source] ResolveError),
     /// Failed to create the disk.
     #[error("failed to create disk")]
     NewDisk(#[source] crate::NewDiskError),
@@ -53,14 +64,86 @@ async fn resolve(
                 resource.disk,
                 ResolveDiskParameters {
                     read_only: input.read_only,
-                    _async_trait_workaround: &(),
+                    driver_source: input.driver_source,
                 },
             )
             .await
             .map_err(DiskResolveError::ResolveInner)?;
 
-        let disk = CryptDisk::new(resource.cipher, &resource.key, inner.0)
-            .map_err(DiskResolveError::NewDisk)?;
+        let key: ResolvedDiskKey = resolver
+            .resolve(resource.key, ())
+            .await
+            .map_err(DiskResolveError::ResolveKey)?;
+
+        let disk =
+            CryptDisk::new(resource.cipher, &key.0, inner.0).map_err(DiskResolveError::NewDisk)?;
         ResolvedDisk::new(disk).map_err(DiskResolveError::InvalidDisk)
     }
 }
+
+declare_static_resolver! {
+    FileDiskKeyResolver,
+    (DiskKeyHandleKind, FileDiskKeyHandle),
+}
+
+/// The resolver for [`FileDiskKeyHandle`].
+pub struct FileDiskKeyResolver;
+
+impl ResolveResource<DiskKeyHandleKind, FileDiskKeyHandle> for FileDiskKeyResolver {
+    type Output = ResolvedDiskKey;
+    type Error = std::io::Error;
+
+    fn resolve(
+        &self,
+        resource: FileDiskKeyHandle,
+        _input: (),
+    ) -> Result<Self::Output, Self::Error> {
+        let mut key = Vec::new();
+        (&resource.0).read_to_end(&mut key)?;
+        Ok(ResolvedDiskKey(key))
+    }
+}
+
+#[cfg(target_os = "linux")]
+declare_static_resolver! {
+    KeyringDiskKeyResolver,
+    (DiskKeyHandleKind, KeyringDiskKeyHandle),
+}
+
+/// The resolver for [`KeyringDiskKeyHandle`].
+#[cfg(target_os = "linux")]
+pub struct KeyringDiskKeyResolver;
+
+#[cfg(target_os = "linux")]
+impl ResolveResource<DiskKeyHandleKind, KeyringDiskKeyHandle> for KeyringDiskKeyResolver {
+    type Output = ResolvedDiskKey;
+    type Error = std::io::Error;
+
+    fn resolve(
+        &self,
+        resource: KeyringDiskKeyHandle,
+        _input: (),
+    ) -> Result<Self::Output, Self::Error> {
+        crate::keyring::read_user_key(&resource.description).map(ResolvedDiskKey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileDiskKeyResolver;
+    use disk_crypt_resources::FileDiskKeyHandle;
+    use std::io::Seek;
+    use std::io::Write;
+    use vm_resource::ResolveResource;
+
+    #[test]
+    fn file_key() {
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&[5; 64]).unwrap();
+        file.rewind().unwrap();
+        let key = FileDiskKeyResolver
+            .resolve(FileDiskKeyHandle(file), ())
+            .unwrap();
+        assert_eq!(key.0, [5; 64]);
+    }
+}
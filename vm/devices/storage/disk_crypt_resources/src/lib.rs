@@ -7,8 +7,10 @@
 
 use mesh::MeshPayload;
 use vm_resource::kind::DiskHandleKind;
+use vm_resource::CanResolveTo;
 use vm_resource::Resource;
 use vm_resource::ResourceId;
+use vm_resource::ResourceKind;
 
 /// A handle to an encrypted disk.
 #[derive(MeshPayload)]
@@ -17,8 +19,9 @@ pub struct DiskCryptHandle {
     pub disk: Resource<DiskHandleKind>,
     /// The cipher to use for encryption.
     pub cipher: Cipher,
-    /// The key. This must be appropriately sized for the cipher.
-    pub key: Vec<u8>,
+    /// The key. This must resolve to a key that is appropriately sized for
+    /// the cipher.
+    pub key: Resource<DiskKeyHandleKind>,
 }
 
 impl ResourceId<DiskHandleKind> for DiskCryptHandle {
@@ -34,3 +37,46 @@ pub enum Cipher {
     /// This requires a 512-bit key.
     XtsAes256,
 }
+
+/// Resource kind for disk encryption keys.
+///
+/// Keys are described by a resource so that they can be retrieved from the
+/// key store at the time the disk is opened, rather than passed around in the
+/// VM configuration.
+pub enum DiskKeyHandleKind {}
+
+impl ResourceKind for DiskKeyHandleKind {
+    const NAME: &'static str = "disk_key";
+}
+
+impl CanResolveTo<ResolvedDiskKey> for DiskKeyHandleKind {
+    type Input<'a> = ();
+}
+
+/// A resolved disk encryption key.
+pub struct ResolvedDiskKey(pub Vec<u8>);
+
+/// A handle to a key stored in a file. The entire contents of the file are
+/// used as the key.
+#[derive(MeshPayload)]
+pub struct FileDiskKeyHandle(pub std::fs::File);
+
+impl ResourceId<DiskKeyHandleKind> for FileDiskKeyHandle {
+    const ID: &'static str = "file";
+}
+
+/// A handle to a key stored in the Linux kernel keyring.
+///
+/// The key must be a `user` key that is accessible via the process's keyrings
+/// (for example, the session or user keyring).
+#[cfg(target_os = "linux")]
+#[derive(MeshPayload)]
+pub struct KeyringDiskKeyHandle {
+    /// The description of the key.
+    pub description: String,
+}
+
+#[cfg(target_os = "linux")]
+impl ResourceId<DiskKeyHandleKind> for KeyringDiskKeyHandle {
+    const ID: &'static str = "keyring";
+}
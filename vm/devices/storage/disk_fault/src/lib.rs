@@ -0,0 +1,328 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A disk wrapper that injects faults into IO to the inner disk: errors,
+//! latency, and torn writes.
+//!
+//! The faults can be replaced while the VM is running, so that tests can
+//! exercise the recovery paths in the guest and the storage stack. This is
+//! intended for testing, not for production use.
+
+#![forbid(unsafe_code)]
+
+use async_trait::async_trait;
+use disk_backend::pr;
+use disk_backend::resolve::ResolveDiskParameters;
+use disk_backend::resolve::ResolvedDisk;
+use disk_backend::Disk;
+use disk_backend::DiskError;
+use disk_backend::DiskIo;
+use disk_backend::UnmapBehavior;
+use disk_backend_resources::DiskFaults;
+use disk_backend_resources::FaultDiskHandle;
+use inspect::Inspect;
+use inspect_counters::SharedCounter;
+use parking_lot::Mutex;
+use scsi_buffers::RequestBuffers;
+use std::io;
+use std::time::Duration;
+use thiserror::Error;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::DiskHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::ResolveError;
+use vm_resource::ResourceResolver;
+
+pub struct FaultDiskResolver;
+declare_static_async_resolver!(FaultDiskResolver, (DiskHandleKind, FaultDiskHandle));
+
+#[derive(Debug, Error)]
+pub enum ResolveFaultDiskError {
+    #[error("failed to resolve inner disk")]
+    Resolve(#[source] ResolveError),
+    #[error("invalid disk")]
+    InvalidDisk(#[source] disk_backend::InvalidDisk),
+}
+
+#[async_trait]
+impl AsyncResolveResource<DiskHandleKind, FaultDiskHandle> for FaultDiskResolver {
+    type Output = ResolvedDisk;
+    type Error = ResolveFaultDiskError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        rsrc: FaultDiskHandle,
+        input: ResolveDiskParameters<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let inner = resolver
+            .resolve(rsrc.disk, input)
+            .await
+            .map_err(ResolveFaultDiskError::Resolve)?;
+
+        ResolvedDisk::new(FaultDisk::new(inner.0, rsrc.faults, rsrc.update))
+            .map_err(ResolveFaultDiskError::InvalidDisk)
+    }
+}
+
+/// A disk wrapper that injects faults into IO to the inner disk.
+#[derive(Inspect)]
+pub struct FaultDisk {
+    inner: Disk,
+    #[inspect(skip)]
+    sector_shift: u32,
+    #[inspect(with = "|x| inspect::AsDebug(x.lock().faults)")]
+    faults: Mutex<FaultState>,
+    /// The number of IOs failed by an injected fault.
+    injected_errors: SharedCounter,
+    /// The number of writes that were only partially written.
+    torn_writes: SharedCounter,
+}
+
+struct FaultState {
+    faults: DiskFaults,
+    update: mesh::Receiver<DiskFaults>,
+}
+
+fn injected_fault() -> DiskError {
+    DiskError::Io(io::Error::other("injected fault"))
+}
+
+impl FaultDisk {
+    /// Wraps `inner`, injecting `faults` until they are replaced by a new set
+    /// received from `update`.
+    pub fn new(inner: Disk, faults: DiskFaults, update: mesh::Receiver<DiskFaults>) -> Self {
+        Self {
+            sector_shift: inner.sector_shift(),
+            inner,
+            faults: Mutex::new(FaultState { faults, update }),
+            injected_errors: Default::default(),
+            torn_writes: Default::default(),
+        }
+    }
+
+    /// Returns the current faults, after applying any updates.
+    fn faults(&self) -> DiskFaults {
+        let mut state = self.faults.lock();
+        while let Ok(faults) = state.update.try_recv() {
+            tracing::info!(?faults, "updating injected disk faults");
+            state.faults = faults;
+        }
+        state.faults
+    }
+
+    /// Applies the latency fault, returning the current faults.
+    async fn delay(&self) -> DiskFaults {
+        let faults = self.faults();
+        if faults.latency_ms != 0 {
+            let latency = Duration::from_millis(faults.latency_ms.into());
+            blocking::unblock(move || std::thread::sleep(latency)).await;
+        }
+        faults
+    }
+
+    fn fail(&self) -> Result<(), DiskError> {
+        self.injected_errors.increment();
+        Err(injected_fault())
+    }
+}
+
+impl DiskIo for FaultDisk {
+    fn disk_type(&self) -> &str {
+        "fault"
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.inner.sector_count()
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.inner.sector_size()
+    }
+
+    fn disk_id(&self) -> Option<[u8; 16]> {
+        self.inner.disk_id()
+    }
+
+    fn physical_sector_size(&self) -> u32 {
+        self.inner.physical_sector_size()
+    }
+
+    fn is_fua_respected(&self) -> bool {
+        self.inner.is_fua_respected()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn pr(&self) -> Option<&dyn pr::PersistentReservation> {
+        self.inner.pr()
+    }
+
+    async fn read_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+    ) -> Result<(), DiskError> {
+        if self.delay().await.fail_reads {
+            return self.fail();
+        }
+        self.inner.read_vectored(buffers, sector).await
+    }
+
+    async fn write_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+        fua: bool,
+    ) -> Result<(), DiskError> {
+        let faults = self.delay().await;
+        if faults.fail_writes {
+            return self.fail();
+        }
+        let sectors = buffers.len() >> self.sector_shift;
+        if faults.torn_writes && sectors > 1 {
+            let written = (sectors / 2) << self.sector_shift;
+            self.inner
+                .write_vectored(&buffers.subrange(0, written), sector, fua)
+                .await?;
+            self.torn_writes.increment();
+            return self.fail();
+        }
+        self.inner.write_vectored(buffers, sector, fua).await
+    }
+
+    async fn sync_cache(&self) -> Result<(), DiskError> {
+        if self.delay().await.fail_flushes {
+            return self.fail();
+        }
+        self.inner.sync_cache().await
+    }
+
+    async fn wait_resize(&self, sector_count: u64) -> u64 {
+        self.inner.wait_resize(sector_count).await
+    }
+
+    async fn unmap(
+        &self,
+        sector: u64,
+        count: u64,
+        block_level_only: bool,
+    ) -> Result<(), DiskError> {
+        if self.delay().await.fail_writes {
+            return self.fail();
+        }
+        self.inner.unmap(sector, count, block_level_only).await
+    }
+
+    fn unmap_behavior(&self) -> UnmapBehavior {
+        self.inner.unmap_behavior()
+    }
+
+    fn optimal_unmap_sectors(&self) -> u32 {
+        self.inner.optimal_unmap_sectors()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FaultDisk;
+    use disk_backend::Disk;
+    use disk_backend::DiskError;
+    use disk_backend_resources::DiskFaults;
+    use disk_ramdisk::ram_disk;
+    use guestmem::GuestMemory;
+    use pal_async::async_test;
+    use scsi_buffers::OwnedRequestBuffers;
+
+    const SECTOR: usize = 512;
+
+    async fn write(
+        disk: &Disk,
+        mem: &GuestMemory,
+        sector: u64,
+        data: &[u8],
+    ) -> Result<(), DiskError> {
+        mem.write_at(0, data).unwrap();
+        disk.write_vectored(
+            &OwnedRequestBuffers::linear(0, data.len(), false).buffer(mem),
+            sector,
+            false,
+        )
+        .await
+    }
+
+    async fn read(
+        disk: &Disk,
+        mem: &GuestMemory,
+        sector: u64,
+        count: usize,
+    ) -> Result<Vec<u8>, DiskError> {
+        disk.read_vectored(
+            &OwnedRequestBuffers::linear(0, count * SECTOR, true).buffer(mem),
+            sector,
+        )
+        .await?;
+        let mut data = vec![0; count * SECTOR];
+        mem.read_at(0, &mut data).unwrap();
+        Ok(data)
+    }
+
+    #[async_test]
+    async fn update_faults() {
+        let mem = GuestMemory::allocate(0x10000);
+        let (send, recv) = mesh::channel();
+        let disk = Disk::new(FaultDisk::new(
+            ram_disk(0x100000, false).unwrap(),
+            DiskFaults {
+                fail_reads: true,
+                ..Default::default()
+            },
+            recv,
+        ))
+        .unwrap();
+
+        write(&disk, &mem, 0, &[1; SECTOR]).await.unwrap();
+        assert!(matches!(
+            read(&disk, &mem, 0, 1).await,
+            Err(DiskError::Io(_))
+        ));
+
+        send.send(DiskFaults {
+            fail_writes: true,
+            fail_flushes: true,
+            ..Default::default()
+        });
+        assert_eq!(read(&disk, &mem, 0, 1).await.unwrap(), [1; SECTOR]);
+        assert!(matches!(
+            write(&disk, &mem, 0, &[2; SECTOR]).await,
+            Err(DiskError::Io(_))
+        ));
+        assert!(matches!(disk.sync_cache().await, Err(DiskError::Io(_))));
+    }
+
+    #[async_test]
+    async fn torn_write() {
+        let mem = GuestMemory::allocate(0x10000);
+        let inner = ram_disk(0x100000, false).unwrap();
+        let (_send, recv) = mesh::channel();
+        let disk = Disk::new(FaultDisk::new(
+            inner.clone(),
+            DiskFaults {
+                torn_writes: true,
+                ..Default::default()
+            },
+            recv,
+        ))
+        .unwrap();
+
+        assert!(matches!(
+            write(&disk, &mem, 0, &[1; 4 * SECTOR]).await,
+            Err(DiskError::Io(_))
+        ));
+        let data = read(&inner, &mem, 0, 4).await.unwrap();
+        assert_eq!(data[..2 * SECTOR], [1; 2 * SECTOR]);
+        assert_eq!(data[2 * SECTOR..], [0; 2 * SECTOR]);
+    }
+}
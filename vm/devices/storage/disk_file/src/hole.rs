@@ -0,0 +1,35 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Support for deallocating ranges of a file.
+
+// UNSAFETY: Calling fallocate.
+#![allow(unsafe_code)]
+
+use std::fs;
+use std::io;
+use std::os::unix::prelude::*;
+
+/// Deallocates the storage backing `len` bytes at `offset` in `file`, without
+/// changing the file's size. The range subsequently reads as zeroes.
+pub fn punch_hole(file: &fs::File, offset: u64, len: u64) -> io::Result<()> {
+    let offset = offset
+        .try_into()
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let len = len
+        .try_into()
+        .map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+    // SAFETY: fallocate has no memory safety requirements.
+    let r = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset,
+            len,
+        )
+    };
+    if r < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
@@ -1,17 +1,24 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-#![forbid(unsafe_code)]
+#![cfg_attr(not(target_os = "linux"), forbid(unsafe_code))]
 
+#[cfg(target_os = "linux")]
+mod hole;
 mod readwriteat;
+#[cfg(target_os = "linux")]
+mod uring;
 
 use self::readwriteat::ReadWriteAt;
 use blocking::unblock;
 use disk_backend::resolve::ResolveDiskParameters;
 use disk_backend::resolve::ResolvedDisk;
+use disk_backend::stats::FlushStats;
 use disk_backend::DiskError;
 use disk_backend::DiskIo;
 use disk_backend_resources::FileDiskHandle;
+use disk_backend_resources::UringFileDiskHandle;
+use disk_backend_resources::WriteCacheMode;
 use guestmem::MemoryRead;
 use guestmem::MemoryWrite;
 use inspect::Inspect;
@@ -24,7 +31,11 @@
 use vm_resource::ResolveResource;
 
 pub struct FileDiskResolver;
-declare_static_resolver!(FileDiskResolver, (DiskHandleKind, FileDiskHandle));
+declare_static_resolver!(
+    FileDiskResolver,
+    (DiskHandleKind, FileDiskHandle),
+    (DiskHandleKind, UringFileDiskHandle)
+);
 
 #[derive(Debug, Error)]
 pub enum ResolveFileDiskError {
@@ -43,10 +54,29 @@ fn resolve(
         rsrc: FileDiskHandle,
         input: ResolveDiskParameters<'_>,
     ) -> Result<Self::Output, Self::Error> {
-        ResolvedDisk::new(
-            FileDisk::open(rsrc.0, input.read_only).map_err(ResolveFileDiskError::Io)?,
-        )
-        .map_err(ResolveFileDiskError::InvalidDisk)
+        let mut disk =
+            FileDisk::open(rsrc.file, input.read_only).map_err(ResolveFileDiskError::Io)?;
+        disk.set_write_cache(rsrc.write_cache);
+        ResolvedDisk::new(disk).map_err(ResolveFileDiskError::InvalidDisk)
+    }
+}
+
+impl ResolveResource<DiskHandleKind, UringFileDiskHandle> for FileDiskResolver {
+    type Output = ResolvedDisk;
+    type Error = ResolveFileDiskError;
+
+    fn resolve(
+        &self,
+        rsrc: UringFileDiskHandle,
+        input: ResolveDiskParameters<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let options = UringOptions {
+            sq_poll_idle_ms: rsrc.sq_poll_idle_ms,
+        };
+        let mut disk = FileDisk::open_uring(rsrc.file, input.read_only, &options)
+            .map_err(ResolveFileDiskError::Io)?;
+        disk.set_write_cache(rsrc.write_cache);
+        ResolvedDisk::new(disk).map_err(ResolveFileDiskError::InvalidDisk)
     }
 }
 
@@ -55,6 +85,21 @@ pub struct FileDisk {
     file: Arc<fs::File>,
     metadata: Metadata,
     sector_shift: u32,
+    optimal_unmap_sectors: u32,
+    #[inspect(debug)]
+    write_cache: WriteCacheMode,
+    flush: FlushStats,
+    #[cfg(target_os = "linux")]
+    uring: Option<uring::UringIo>,
+}
+
+/// Options for issuing file disk IO through io_uring.
+#[derive(Debug, Clone, Default)]
+pub struct UringOptions {
+    /// If set, use a kernel thread to poll for submitted IO, so that most IOs
+    /// can be issued without a system call. The thread goes to sleep after
+    /// this many milliseconds without IO.
+    pub sq_poll_idle_ms: Option<u32>,
 }
 
 #[derive(Debug, Inspect)]
@@ -67,13 +112,49 @@ pub struct Metadata {
 
 impl FileDisk {
     pub fn open(file: fs::File, read_only: bool) -> Result<Self, std::io::Error> {
+        let file_metadata = file.metadata()?;
         let metadata = Metadata {
-            disk_size: file.metadata()?.len(),
+            disk_size: file_metadata.len(),
             sector_size: 512,
             physical_sector_size: 4096,
             read_only,
         };
-        Ok(Self::with_metadata(file, metadata))
+        #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+        let mut disk = Self::with_metadata(file, metadata);
+        // Unmaps smaller than a file system block cannot free any space.
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::unix::fs::MetadataExt;
+            disk.optimal_unmap_sectors =
+                ((file_metadata.blksize() >> disk.sector_shift) as u32).max(1);
+        }
+        Ok(disk)
+    }
+
+    /// Opens the disk, issuing IO through io_uring if it is available.
+    ///
+    /// Falls back to the default IO path if io_uring cannot be used, including
+    /// on platforms other than Linux.
+    pub fn open_uring(
+        file: fs::File,
+        read_only: bool,
+        options: &UringOptions,
+    ) -> Result<Self, std::io::Error> {
+        #[cfg_attr(not(target_os = "linux"), allow(unused_mut))]
+        let mut disk = Self::open(file, read_only)?;
+        #[cfg(target_os = "linux")]
+        match uring::UringIo::new(&disk.file, options) {
+            Ok(io) => disk.uring = Some(io),
+            Err(err) => {
+                tracing::warn!(
+                    error = &err as &dyn std::error::Error,
+                    "io_uring is unavailable, falling back to blocking file io"
+                );
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        let _ = options;
+        Ok(disk)
     }
 
     /// Opens the disk using the specified metadata.
@@ -89,10 +170,25 @@ pub fn with_metadata(file: fs::File, metadata: Metadata) -> Self {
             file: Arc::new(file),
             metadata,
             sector_shift,
+            optimal_unmap_sectors: 1,
+            write_cache: WriteCacheMode::WriteBack,
+            flush: FlushStats::default(),
+            #[cfg(target_os = "linux")]
+            uring: None,
         }
     }
 
+    /// Sets how writes are cached by the host.
+    ///
+    /// In [`WriteCacheMode::WriteThrough`] mode, every write is issued as if
+    /// the guest had set FUA.
+    pub fn set_write_cache(&mut self, write_cache: WriteCacheMode) {
+        self.write_cache = write_cache;
+    }
+
     pub fn into_inner(self) -> fs::File {
+        #[cfg(target_os = "linux")]
+        drop(self.uring);
         Arc::try_unwrap(self.file).expect("no outstanding IOs")
     }
 }
@@ -102,9 +198,13 @@ pub async fn read(&self, buffers: &RequestBuffers<'_>, sector: u64) -> Result<()
         if ((sector << self.sector_shift) + buffers.len() as u64) > self.metadata.disk_size {
             return Err(DiskError::IllegalBlock);
         }
+        let offset = sector << self.sector_shift;
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = &self.uring {
+            return uring.read(buffers, offset).await;
+        }
         let mut buffer = vec![0; buffers.len()];
         let file = self.file.clone();
-        let offset = sector << self.sector_shift;
         let buffer = unblock(move || -> Result<_, std::io::Error> {
             file.read_at(&mut buffer, offset)?;
             Ok(buffer)
@@ -119,27 +219,45 @@ pub async fn write(
         &self,
         buffers: &RequestBuffers<'_>,
         sector: u64,
-        _fua: bool,
+        fua: bool,
     ) -> Result<(), DiskError> {
         if ((sector << self.sector_shift) + buffers.len() as u64) > self.metadata.disk_size {
             return Err(DiskError::IllegalBlock);
         }
+        let offset = sector << self.sector_shift;
+        let fua = fua || self.write_cache == WriteCacheMode::WriteThrough;
+        #[cfg(target_os = "linux")]
+        if let Some(uring) = &self.uring {
+            return uring.write(buffers, offset, fua).await;
+        }
         let mut buffer = vec![0; buffers.len()];
         let file = self.file.clone();
         buffers.reader().read(&mut buffer)?;
-        let offset = sector << self.sector_shift;
-        unblock(move || file.write_at(&buffer, offset))
-            .await
-            .map_err(DiskError::Io)?;
+        unblock(move || -> std::io::Result<()> {
+            file.write_at(&buffer, offset)?;
+            if fua {
+                file.sync_data()?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(DiskError::Io)?;
         Ok(())
     }
 
     pub async fn flush(&self) -> Result<(), DiskError> {
-        let file = self.file.clone();
-        unblock(move || file.sync_all())
+        self.flush
+            .measure(async {
+                #[cfg(target_os = "linux")]
+                if let Some(uring) = &self.uring {
+                    return uring.flush().await;
+                }
+                let file = self.file.clone();
+                unblock(move || file.sync_all())
+                    .await
+                    .map_err(DiskError::Io)
+            })
             .await
-            .map_err(DiskError::Io)?;
-        Ok(())
     }
 }
 
@@ -169,7 +287,7 @@ fn physical_sector_size(&self) -> u32 {
     }
 
     fn is_fua_respected(&self) -> bool {
-        false
+        true
     }
 
     async fn read_vectored(
@@ -195,14 +313,136 @@ async fn sync_cache(&self) -> Result<(), DiskError> {
 
     async fn unmap(
         &self,
-        _sector: u64,
-        _count: u64,
+        sector: u64,
+        count: u64,
         _block_level_only: bool,
     ) -> Result<(), DiskError> {
+        if sector
+            .checked_add(count)
+            .is_none_or(|end| end << self.sector_shift > self.metadata.disk_size)
+        {
+            return Err(DiskError::IllegalBlock);
+        }
+        if self.metadata.read_only {
+            return Err(DiskError::ReadOnly);
+        }
+        #[cfg(target_os = "linux")]
+        {
+            let file = self.file.clone();
+            let offset = sector << self.sector_shift;
+            let len = count << self.sector_shift;
+            match unblock(move || hole::punch_hole(&file, offset, len)).await {
+                Ok(()) => {}
+                // Unmap is advisory, so ignore file systems that cannot
+                // deallocate ranges.
+                Err(err) if err.raw_os_error() == Some(libc::EOPNOTSUPP) => {}
+                Err(err) => return Err(DiskError::Io(err)),
+            }
+        }
         Ok(())
     }
 
     fn unmap_behavior(&self) -> disk_backend::UnmapBehavior {
-        disk_backend::UnmapBehavior::Ignored
+        // FUTURE: support deallocation on Windows via FSCTL_SET_ZERO_DATA.
+        if cfg!(target_os = "linux") {
+            // The range reads as zeroes only if the file system supports
+            // punching holes.
+            disk_backend::UnmapBehavior::Unspecified
+        } else {
+            disk_backend::UnmapBehavior::Ignored
+        }
+    }
+
+    fn optimal_unmap_sectors(&self) -> u32 {
+        self.optimal_unmap_sectors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FileDisk;
+    use super::UringOptions;
+    use disk_backend::DiskError;
+    use disk_backend::DiskIo;
+    use guestmem::GuestMemory;
+    use pal_async::async_test;
+    use scsi_buffers::OwnedRequestBuffers;
+
+    #[async_test]
+    async fn uring_read_write() {
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(0x100000).unwrap();
+        let disk = FileDisk::open_uring(file, false, &UringOptions::default()).unwrap();
+
+        // Use sizes that do and do not fit in a registered buffer.
+        for len in [0x1000, 0x40000] {
+            let mem = GuestMemory::allocate(len * 2);
+            let data = (0..len).map(|i| (i / 512) as u8).collect::<Vec<_>>();
+            mem.write_at(0, &data).unwrap();
+
+            let buffers = OwnedRequestBuffers::linear(0, len, false);
+            disk.write_vectored(&buffers.buffer(&mem), 8, true)
+                .await
+                .unwrap();
+            disk.sync_cache().await.unwrap();
+
+            let buffers = OwnedRequestBuffers::linear(len as u64, len, true);
+            disk.read_vectored(&buffers.buffer(&mem), 8).await.unwrap();
+            let mut read_data = vec![0; len];
+            mem.read_at(len as u64, &mut read_data).unwrap();
+            assert_eq!(read_data, data);
+        }
+
+        // IO past the end of the disk fails.
+        let mem = GuestMemory::allocate(512);
+        let buffers = OwnedRequestBuffers::linear(0, 512, true);
+        disk.read_vectored(&buffers.buffer(&mem), 0x100000 / 512)
+            .await
+            .unwrap_err();
+    }
+
+    #[async_test]
+    async fn unmap() {
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(0x100000).unwrap();
+        let disk = FileDisk::open(file, false).unwrap();
+
+        let mem = GuestMemory::allocate(0x2000);
+        mem.write_at(0, &[0xaa; 0x2000]).unwrap();
+        let buffers = OwnedRequestBuffers::linear(0, 0x2000, false);
+        disk.write_vectored(&buffers.buffer(&mem), 0, false)
+            .await
+            .unwrap();
+
+        // Unmaps past the end of the disk fail.
+        assert!(matches!(
+            disk.unmap(0x100000 / 512 - 1, 2, false).await,
+            Err(DiskError::IllegalBlock)
+        ));
+
+        disk.unmap(0, 8, false).await.unwrap();
+
+        // The unmapped range reads as zeroes, assuming the temporary
+        // directory's file system supports punching holes.
+        #[cfg(target_os = "linux")]
+        {
+            let buffers = OwnedRequestBuffers::linear(0, 0x2000, true);
+            disk.read_vectored(&buffers.buffer(&mem), 0).await.unwrap();
+            let mut data = vec![0; 0x2000];
+            mem.read_at(0, &mut data).unwrap();
+            assert!(data[..0x1000].iter().all(|&b| b == 0));
+            assert!(data[0x1000..].iter().all(|&b| b == 0xaa));
+        }
+    }
+
+    #[async_test]
+    async fn unmap_read_only() {
+        let file = tempfile::tempfile().unwrap();
+        file.set_len(0x100000).unwrap();
+        let disk = FileDisk::open(file, true).unwrap();
+        assert!(matches!(
+            disk.unmap(0, 8, false).await,
+            Err(DiskError::ReadOnly)
+        ));
     }
 }
@@ -0,0 +1,363 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! An io_uring-based IO path for file disks on Linux.
+//!
+//! Each disk gets its own ring, serviced by a dedicated completion thread. The
+//! disk's file is registered with the ring as a fixed file, and a small pool
+//! of registered buffers is used to bounce data to and from guest memory.
+//! IOs that are too large for a registered buffer, or that are issued while
+//! all registered buffers are in use, bounce through an unregistered buffer
+//! instead.
+
+// UNSAFETY: Issuing io_uring operations that reference buffers.
+#![allow(unsafe_code)]
+
+use crate::UringOptions;
+use disk_backend::DiskError;
+use guestmem::MemoryRead;
+use guestmem::MemoryWrite;
+use inspect::Inspect;
+use io_uring::opcode;
+use io_uring::squeue;
+use io_uring::types;
+use io_uring::types::RwFlags;
+use pal_uring::IoMemory;
+use pal_uring::IoRing;
+use parking_lot::Mutex;
+use parking_lot::MutexGuard;
+use scsi_buffers::RequestBuffers;
+use std::fmt::Debug;
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::os::unix::prelude::*;
+use std::pin::Pin;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::task::Context;
+use std::task::Poll;
+use std::thread::JoinHandle;
+
+const RING_SIZE: u32 = 128;
+const BUFFER_SIZE: usize = 128 * 1024;
+const BUFFER_COUNT: u16 = 32;
+
+/// The index of the disk's file in the ring's registered file table.
+const FIXED_FILE: types::Fixed = types::Fixed(0);
+
+// Defined in the Linux UAPI headers, but only exported by libc for glibc.
+const RWF_DSYNC: RwFlags = 0x00000002;
+
+pub(crate) struct UringIo {
+    // N.B. The ring must be dropped before the registered buffers.
+    ring: Arc<IoRing>,
+    buffers: Option<Arc<BufferPool>>,
+    sq_poll: bool,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Debug for UringIo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UringIo")
+            .field("sq_poll", &self.sq_poll)
+            .field("registered_buffers", &self.buffers.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Inspect for UringIo {
+    fn inspect(&self, req: inspect::Request<'_>) {
+        req.respond()
+            .field("sq_poll", self.sq_poll)
+            .field("registered_buffers", self.buffers.is_some())
+            .field("ring", &*self.ring);
+    }
+}
+
+impl UringIo {
+    /// Creates a new ring for issuing IO to `file`.
+    ///
+    /// Fails if io_uring is not available or does not support the required
+    /// operations.
+    pub fn new(file: &fs::File, options: &UringOptions) -> io::Result<Self> {
+        let (ring, mut completion_ring) = match options.sq_poll_idle_ms {
+            Some(idle_ms) => IoRing::new_sqpoll(RING_SIZE, idle_ms)?,
+            None => IoRing::new(RING_SIZE)?,
+        };
+        for code in [
+            opcode::Read::CODE,
+            opcode::Write::CODE,
+            opcode::ReadFixed::CODE,
+            opcode::WriteFixed::CODE,
+            opcode::Fsync::CODE,
+        ] {
+            if !ring.probe(code) {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("io_uring opcode {code} is not supported"),
+                ));
+            }
+        }
+        ring.register_files(&[file.as_raw_fd()])?;
+
+        let pool = BufferPool::new();
+        // SAFETY: the buffers are kept alive by `self.buffers` until after
+        // the ring is dropped.
+        let buffers = match unsafe { ring.register_buffers(&pool.iovecs()) } {
+            Ok(()) => Some(Arc::new(pool)),
+            Err(err) => {
+                // This is typically due to RLIMIT_MEMLOCK.
+                tracing::warn!(
+                    error = &err as &dyn std::error::Error,
+                    "failed to register io_uring buffers, using unregistered buffers"
+                );
+                None
+            }
+        };
+
+        let ring = Arc::new(ring);
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let thread = std::thread::Builder::new()
+            .name("disk-file-uring".into())
+            .spawn({
+                let ring = ring.clone();
+                let shutdown = shutdown.clone();
+                move || {
+                    // Keep processing until shutdown, and then until any IOs
+                    // abandoned by their issuers complete, since those IOs may
+                    // still reference buffers.
+                    while !shutdown.load(Ordering::Acquire) || ring.pending_io_count() != 0 {
+                        ring.submit_and_wait();
+                        completion_ring.process();
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            ring,
+            buffers,
+            sq_poll: options.sq_poll_idle_ms.is_some(),
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    fn acquire_buffer(&self, len: usize) -> Option<FixedBuffer> {
+        if len > BUFFER_SIZE {
+            return None;
+        }
+        let pool = self.buffers.as_ref()?;
+        let index = pool.free.lock().pop()?;
+        Some(FixedBuffer {
+            pool: pool.clone(),
+            index,
+        })
+    }
+
+    pub async fn read(&self, buffers: &RequestBuffers<'_>, offset: u64) -> Result<(), DiskError> {
+        let len = buffers.len();
+        let io_len = len.try_into().map_err(|_| DiskError::InvalidInput)?;
+        if let Some(buffer) = self.acquire_buffer(len) {
+            let sqe = opcode::ReadFixed::new(FIXED_FILE, buffer.as_mut_ptr(), io_len, buffer.index)
+                .offset(offset)
+                .build();
+            // SAFETY: the IO only references the registered buffer, which is
+            // owned by the IO memory.
+            let (r, mem) = unsafe { RingIo::new(&self.ring, sqe, IoMemory::new(buffer)) }.await;
+            check_result(r, len)?;
+            let buffer: FixedBuffer = mem.downcast();
+            buffers.writer().write(&buffer.data()[..len])?;
+        } else {
+            let mut buffer = vec![0u8; len];
+            let sqe = opcode::Read::new(FIXED_FILE, buffer.as_mut_ptr(), io_len)
+                .offset(offset)
+                .build();
+            // SAFETY: the IO only references the buffer's heap allocation,
+            // which is owned by the IO memory.
+            let (r, mem) = unsafe { RingIo::new(&self.ring, sqe, IoMemory::new(buffer)) }.await;
+            check_result(r, len)?;
+            let buffer: Vec<u8> = mem.downcast();
+            buffers.writer().write(&buffer)?;
+        }
+        Ok(())
+    }
+
+    pub async fn write(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        offset: u64,
+        fua: bool,
+    ) -> Result<(), DiskError> {
+        let len = buffers.len();
+        let io_len = len.try_into().map_err(|_| DiskError::InvalidInput)?;
+        let rw_flags = if fua { RWF_DSYNC } else { 0 };
+        let (r, _) = if let Some(buffer) = self.acquire_buffer(len) {
+            buffers.reader().read(&mut buffer.data()[..len])?;
+            let sqe =
+                opcode::WriteFixed::new(FIXED_FILE, buffer.as_mut_ptr(), io_len, buffer.index)
+                    .offset(offset)
+                    .rw_flags(rw_flags)
+                    .build();
+            // SAFETY: the IO only references the registered buffer, which is
+            // owned by the IO memory.
+            unsafe { RingIo::new(&self.ring, sqe, IoMemory::new(buffer)) }.await
+        } else {
+            let mut buffer = vec![0u8; len];
+            buffers.reader().read(&mut buffer)?;
+            let sqe = opcode::Write::new(FIXED_FILE, buffer.as_ptr(), io_len)
+                .offset(offset)
+                .rw_flags(rw_flags)
+                .build();
+            // SAFETY: the IO only references the buffer's heap allocation,
+            // which is owned by the IO memory.
+            unsafe { RingIo::new(&self.ring, sqe, IoMemory::new(buffer)) }.await
+        };
+        check_result(r, len)
+    }
+
+    pub async fn flush(&self) -> Result<(), DiskError> {
+        let sqe = opcode::Fsync::new(FIXED_FILE).build();
+        // SAFETY: the IO does not reference any buffers.
+        let (r, _) = unsafe { RingIo::new(&self.ring, sqe, IoMemory::new(())) }.await;
+        check_result(r, 0)
+    }
+}
+
+impl Drop for UringIo {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::Release);
+        // Wake the completion thread.
+        //
+        // SAFETY: the IO does not reference any buffers.
+        unsafe { self.ring.push(opcode::Nop::new().build(), true) };
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+    }
+}
+
+fn check_result(r: i32, len: usize) -> Result<(), DiskError> {
+    if r < 0 {
+        return Err(DiskError::Io(io::Error::from_raw_os_error(-r)));
+    }
+    if r as usize != len {
+        // The file must have been truncated underneath the disk.
+        return Err(DiskError::Io(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(())
+}
+
+/// A pool of buffers registered with the ring.
+struct BufferPool {
+    buffers: Vec<Mutex<Box<[u8]>>>,
+    free: Mutex<Vec<u16>>,
+}
+
+impl BufferPool {
+    fn new() -> Self {
+        Self {
+            buffers: (0..BUFFER_COUNT)
+                .map(|_| Mutex::new(vec![0; BUFFER_SIZE].into()))
+                .collect(),
+            free: Mutex::new((0..BUFFER_COUNT).collect()),
+        }
+    }
+
+    fn iovecs(&self) -> Vec<libc::iovec> {
+        self.buffers
+            .iter()
+            .map(|buffer| {
+                let mut buffer = buffer.lock();
+                libc::iovec {
+                    iov_base: buffer.as_mut_ptr().cast(),
+                    iov_len: buffer.len(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A registered buffer that is owned until dropped.
+struct FixedBuffer {
+    pool: Arc<BufferPool>,
+    index: u16,
+}
+
+impl FixedBuffer {
+    fn data(&self) -> MutexGuard<'_, Box<[u8]>> {
+        self.pool.buffers[self.index as usize].lock()
+    }
+
+    fn as_mut_ptr(&self) -> *mut u8 {
+        self.data().as_mut_ptr()
+    }
+}
+
+impl Drop for FixedBuffer {
+    fn drop(&mut self) {
+        self.pool.free.lock().push(self.index);
+    }
+}
+
+/// A future for an IO issued to an [`IoRing`].
+///
+/// If the future is dropped before the IO completes, the IO memory is
+/// released by the completion thread once the IO completes.
+struct RingIo<'a> {
+    ring: &'a IoRing,
+    state: RingIoState,
+}
+
+enum RingIoState {
+    NotIssued(squeue::Entry, IoMemory),
+    Issued(usize),
+    Completed,
+}
+
+impl<'a> RingIo<'a> {
+    /// # Safety
+    ///
+    /// The caller must ensure that `sqe` is valid and only references memory
+    /// owned by `mem`.
+    unsafe fn new(ring: &'a IoRing, sqe: squeue::Entry, mem: IoMemory) -> Self {
+        Self {
+            ring,
+            state: RingIoState::NotIssued(sqe, mem),
+        }
+    }
+}
+
+impl Future for RingIo<'_> {
+    type Output = (i32, IoMemory);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match std::mem::replace(&mut this.state, RingIoState::Completed) {
+            RingIoState::NotIssued(sqe, mem) => {
+                // SAFETY: guaranteed by the caller of `RingIo::new`.
+                let idx = unsafe { this.ring.new_io(sqe, mem, cx.waker().clone(), true) };
+                this.state = RingIoState::Issued(idx);
+                Poll::Pending
+            }
+            RingIoState::Issued(idx) => {
+                let r = this.ring.poll_io(cx, idx);
+                if r.is_pending() {
+                    this.state = RingIoState::Issued(idx);
+                }
+                r
+            }
+            RingIoState::Completed => panic!("polled after completion"),
+        }
+    }
+}
+
+impl Drop for RingIo<'_> {
+    fn drop(&mut self) {
+        if let RingIoState::Issued(idx) = self.state {
+            self.ring.drop_io(idx);
+        }
+    }
+}
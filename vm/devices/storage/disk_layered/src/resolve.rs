@@ -7,6 +7,7 @@
 use super::LayerIo;
 use vm_resource::kind::DiskLayerHandleKind;
 use vm_resource::CanResolveTo;
+use vmcore::vm_task::VmTaskDriverSource;
 
 impl CanResolveTo<ResolvedDiskLayer> for DiskLayerHandleKind {
     type Input<'a> = ResolveDiskLayerParameters<'a>;
@@ -17,11 +18,8 @@ impl CanResolveTo<ResolvedDiskLayer> for DiskLayerHandleKind {
 pub struct ResolveDiskLayerParameters<'a> {
     /// Whether the layer is being opened for read-only use.
     pub read_only: bool,
-    #[doc(hidden)]
-    // Workaround for async_trait not working well with GAT input parameters
-    // with missing lifetimes. Remove once we stop using async_trait for async
-    // resolvers.
-    pub _async_trait_workaround: &'a (),
+    /// The driver source, for layers that need to run tasks.
+    pub driver_source: &'a VmTaskDriverSource,
 }
 
 /// A resolved [`DiskLayer`].
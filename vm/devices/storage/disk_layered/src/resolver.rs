@@ -74,7 +74,7 @@ async fn resolve(
                             desc.layer,
                             ResolveDiskLayerParameters {
                                 read_only: this_read_only,
-                                _async_trait_workaround: &(),
+                                driver_source: input.driver_source,
                             },
                         )
                         .await
@@ -113,7 +113,7 @@ async fn resolve(
                 resource.0,
                 ResolveDiskParameters {
                     read_only: input.read_only,
-                    _async_trait_workaround: &(),
+                    driver_source: input.driver_source,
                 },
             )
             .await?;
@@ -0,0 +1,190 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A disk wrapper that exports the inner disk to the host over the NBD
+//! protocol, so that backup and inspection tools can access the guest's disk
+//! while the VM is running.
+//!
+//! The export is read-only while the VM is running, and it is read-write
+//! while the VM is stopped (as long as the inner disk is writable).
+
+#![forbid(unsafe_code)]
+
+mod protocol;
+mod server;
+
+pub use server::NbdServer;
+
+use async_trait::async_trait;
+use disk_backend::pr;
+use disk_backend::resolve::ResolveDiskParameters;
+use disk_backend::resolve::ResolvedDisk;
+use disk_backend::Disk;
+use disk_backend::DiskError;
+use disk_backend::DiskIo;
+use disk_backend_resources::NbdExportDiskHandle;
+use inspect::Inspect;
+use pal_async::DefaultPool;
+use scsi_buffers::RequestBuffers;
+use std::future::Future;
+use thiserror::Error;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::DiskHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::ResolveError;
+use vm_resource::ResourceResolver;
+
+pub struct NbdExportDiskResolver;
+declare_static_async_resolver!(NbdExportDiskResolver, (DiskHandleKind, NbdExportDiskHandle));
+
+#[derive(Debug, Error)]
+pub enum ResolveNbdExportDiskError {
+    #[error("failed to resolve inner disk")]
+    Resolve(#[source] ResolveError),
+    #[error("failed to start nbd server thread")]
+    Thread(#[source] std::io::Error),
+    #[error("invalid disk")]
+    InvalidDisk(#[source] disk_backend::InvalidDisk),
+}
+
+#[async_trait]
+impl AsyncResolveResource<DiskHandleKind, NbdExportDiskHandle> for NbdExportDiskResolver {
+    type Output = ResolvedDisk;
+    type Error = ResolveNbdExportDiskError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        rsrc: NbdExportDiskHandle,
+        input: ResolveDiskParameters<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let inner = resolver
+            .resolve(rsrc.disk, input)
+            .await
+            .map_err(ResolveNbdExportDiskError::Resolve)?;
+
+        let disk = NbdExportDisk::new(inner.0, rsrc.export_name, rsrc.listener, rsrc.vm_running)
+            .map_err(ResolveNbdExportDiskError::Thread)?;
+
+        ResolvedDisk::new(disk).map_err(ResolveNbdExportDiskError::InvalidDisk)
+    }
+}
+
+/// A disk wrapper that serves the inner disk over NBD.
+///
+/// I/O from the guest is passed through unmodified. The NBD server runs on its
+/// own thread and is stopped when this disk is dropped.
+#[derive(Inspect)]
+pub struct NbdExportDisk {
+    inner: Disk,
+    export_name: String,
+    #[inspect(skip)]
+    _shutdown: mesh::OneshotSender<()>,
+}
+
+impl NbdExportDisk {
+    /// Wraps `inner`, serving it as `export_name` to clients that connect to
+    /// `listener`.
+    ///
+    /// `vm_running` tracks whether the VM is currently running. The export is
+    /// read-only while it is true.
+    pub fn new(
+        inner: Disk,
+        export_name: String,
+        listener: std::net::TcpListener,
+        vm_running: mesh::Cell<bool>,
+    ) -> std::io::Result<Self> {
+        let (shutdown_send, shutdown_recv) = mesh::oneshot();
+        let server = NbdServer::new(export_name.clone(), inner.clone(), vm_running);
+        std::thread::Builder::new()
+            .name(format!("nbd-{export_name}"))
+            .spawn(move || {
+                DefaultPool::run_with(|driver| async move {
+                    if let Err(err) = server.run(&driver, listener, shutdown_recv).await {
+                        tracing::error!(
+                            error = &err as &dyn std::error::Error,
+                            "nbd server failed"
+                        );
+                    }
+                })
+            })?;
+
+        Ok(Self {
+            inner,
+            export_name,
+            _shutdown: shutdown_send,
+        })
+    }
+}
+
+impl DiskIo for NbdExportDisk {
+    fn disk_type(&self) -> &str {
+        "nbd_export"
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.inner.sector_count()
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.inner.sector_size()
+    }
+
+    fn disk_id(&self) -> Option<[u8; 16]> {
+        self.inner.disk_id()
+    }
+
+    fn physical_sector_size(&self) -> u32 {
+        self.inner.physical_sector_size()
+    }
+
+    fn is_fua_respected(&self) -> bool {
+        self.inner.is_fua_respected()
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.is_read_only()
+    }
+
+    fn unmap(
+        &self,
+        sector: u64,
+        count: u64,
+        block_level_only: bool,
+    ) -> impl Future<Output = Result<(), DiskError>> + Send {
+        self.inner.unmap(sector, count, block_level_only)
+    }
+
+    fn unmap_behavior(&self) -> disk_backend::UnmapBehavior {
+        self.inner.unmap_behavior()
+    }
+
+    fn optimal_unmap_sectors(&self) -> u32 {
+        self.inner.optimal_unmap_sectors()
+    }
+
+    fn pr(&self) -> Option<&dyn pr::PersistentReservation> {
+        self.inner.pr()
+    }
+
+    async fn read_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+    ) -> Result<(), DiskError> {
+        self.inner.read_vectored(buffers, sector).await
+    }
+
+    async fn write_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+        fua: bool,
+    ) -> Result<(), DiskError> {
+        self.inner.write_vectored(buffers, sector, fua).await
+    }
+
+    fn sync_cache(&self) -> impl Future<Output = Result<(), DiskError>> + Send {
+        self.inner.sync_cache()
+    }
+}
@@ -0,0 +1,153 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Wire definitions for the NBD protocol, as described in
+//! <https://github.com/NetworkBlockDevice/nbd/blob/master/doc/proto.md>.
+//!
+//! Only the fixed newstyle handshake and simple replies are defined.
+
+#![allow(dead_code)]
+
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+type U16BE = zerocopy::byteorder::U16<zerocopy::byteorder::BigEndian>;
+type U32BE = zerocopy::byteorder::U32<zerocopy::byteorder::BigEndian>;
+type U64BE = zerocopy::byteorder::U64<zerocopy::byteorder::BigEndian>;
+
+/// "NBDMAGIC"
+pub const NBD_MAGIC: u64 = 0x4e42444d41474943;
+/// "IHAVEOPT"
+pub const IHAVEOPT: u64 = 0x49484156454f5054;
+pub const OPTION_REPLY_MAGIC: u64 = 0x3e889045565a9;
+pub const REQUEST_MAGIC: u32 = 0x25609513;
+pub const SIMPLE_REPLY_MAGIC: u32 = 0x67446698;
+
+// Handshake flags, sent by the server.
+pub const FLAG_FIXED_NEWSTYLE: u16 = 1 << 0;
+pub const FLAG_NO_ZEROES: u16 = 1 << 1;
+
+// Client flags.
+pub const FLAG_C_FIXED_NEWSTYLE: u32 = 1 << 0;
+pub const FLAG_C_NO_ZEROES: u32 = 1 << 1;
+
+// Transmission flags.
+pub const FLAG_HAS_FLAGS: u16 = 1 << 0;
+pub const FLAG_READ_ONLY: u16 = 1 << 1;
+pub const FLAG_SEND_FLUSH: u16 = 1 << 2;
+pub const FLAG_SEND_FUA: u16 = 1 << 3;
+pub const FLAG_SEND_TRIM: u16 = 1 << 5;
+pub const FLAG_CAN_MULTI_CONN: u16 = 1 << 8;
+
+// Options.
+pub const OPT_EXPORT_NAME: u32 = 1;
+pub const OPT_ABORT: u32 = 2;
+pub const OPT_LIST: u32 = 3;
+pub const OPT_INFO: u32 = 6;
+pub const OPT_GO: u32 = 7;
+
+// Option reply types.
+pub const REP_ACK: u32 = 1;
+pub const REP_SERVER: u32 = 2;
+pub const REP_INFO: u32 = 3;
+pub const REP_ERR_UNSUP: u32 = (1 << 31) | 1;
+pub const REP_ERR_POLICY: u32 = (1 << 31) | 2;
+pub const REP_ERR_INVALID: u32 = (1 << 31) | 3;
+pub const REP_ERR_UNKNOWN: u32 = (1 << 31) | 6;
+
+// Info types for NBD_OPT_INFO and NBD_OPT_GO.
+pub const INFO_EXPORT: u16 = 0;
+pub const INFO_BLOCK_SIZE: u16 = 3;
+
+// Commands.
+pub const CMD_READ: u16 = 0;
+pub const CMD_WRITE: u16 = 1;
+pub const CMD_DISC: u16 = 2;
+pub const CMD_FLUSH: u16 = 3;
+pub const CMD_TRIM: u16 = 4;
+
+// Command flags.
+pub const CMD_FLAG_FUA: u16 = 1 << 0;
+
+// Error values, which match the Linux errno values.
+pub const EPERM: u32 = 1;
+pub const EIO: u32 = 5;
+pub const EINVAL: u32 = 22;
+pub const ENOSPC: u32 = 28;
+pub const EOVERFLOW: u32 = 75;
+pub const ENOTSUP: u32 = 95;
+pub const ESHUTDOWN: u32 = 108;
+
+/// The length of the zero padding that follows the export data in reply to
+/// `NBD_OPT_EXPORT_NAME`, unless `NBD_FLAG_C_NO_ZEROES` was negotiated.
+pub const EXPORT_NAME_PADDING: usize = 124;
+
+/// The initial message sent by the server.
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct ServerGreeting {
+    pub magic: U64BE,
+    pub ihaveopt: U64BE,
+    pub handshake_flags: U16BE,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct OptionHeader {
+    pub magic: U64BE,
+    pub option: U32BE,
+    pub length: U32BE,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct OptionReplyHeader {
+    pub magic: U64BE,
+    pub option: U32BE,
+    pub reply_type: U32BE,
+    pub length: U32BE,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct ExportInfo {
+    pub size: U64BE,
+    pub transmission_flags: U16BE,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct InfoExport {
+    pub info_type: U16BE,
+    pub size: U64BE,
+    pub transmission_flags: U16BE,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct InfoBlockSize {
+    pub info_type: U16BE,
+    pub minimum: U32BE,
+    pub preferred: U32BE,
+    pub maximum: U32BE,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct Request {
+    pub magic: U32BE,
+    pub flags: U16BE,
+    pub command: U16BE,
+    pub cookie: U64BE,
+    pub offset: U64BE,
+    pub length: U32BE,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct SimpleReply {
+    pub magic: U32BE,
+    pub error: U32BE,
+    pub cookie: U64BE,
+}
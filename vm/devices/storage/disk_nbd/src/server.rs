@@ -0,0 +1,616 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! An NBD server exporting a single [`Disk`].
+
+use crate::protocol;
+use disk_backend::Disk;
+use disk_backend::DiskError;
+use disk_backend::UnmapBehavior;
+use futures::select_biased;
+use futures::AsyncRead;
+use futures::AsyncReadExt;
+use futures::AsyncWrite;
+use futures::AsyncWriteExt;
+use futures::FutureExt;
+use futures::StreamExt;
+use guestmem::GuestMemory;
+use pal_async::driver::Driver;
+use pal_async::socket::PolledSocket;
+use scsi_buffers::OwnedRequestBuffers;
+use std::io;
+use std::net::TcpListener;
+use thiserror::Error;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+/// The largest read or write request that will be accepted.
+const MAX_REQUEST_SIZE: u32 = 32 * 1024 * 1024;
+
+/// The largest option payload that will be accepted during the handshake.
+const MAX_OPTION_SIZE: u32 = 4096;
+
+#[derive(Debug, Error)]
+pub(crate) enum ConnectionError {
+    #[error("io error")]
+    Io(#[from] io::Error),
+    #[error("bad magic {0:#x}")]
+    BadMagic(u64),
+    #[error("client does not support the fixed newstyle handshake")]
+    NotFixedNewstyle,
+    #[error("option length {0:#x} is too large")]
+    OptionTooLarge(u32),
+    #[error("unknown export {0:?}")]
+    UnknownExport(String),
+    #[error("write request length {0:#x} is too large")]
+    RequestTooLarge(u32),
+}
+
+/// An NBD server that exports a disk under a single export name.
+///
+/// The export is only writable when the disk is writable and the VM is not
+/// running, so that clients cannot race with guest I/O. Since NBD clients
+/// learn whether an export is read-only during the handshake, clients that
+/// connect while the VM is stopped will see writes fail with `EPERM` once the
+/// VM starts running.
+pub struct NbdServer {
+    export_name: String,
+    disk: Disk,
+    vm_running: mesh::Cell<bool>,
+}
+
+impl NbdServer {
+    /// Returns a new server exporting `disk` as `export_name`.
+    ///
+    /// `vm_running` tracks whether the VM is currently running.
+    pub fn new(export_name: String, disk: Disk, vm_running: mesh::Cell<bool>) -> Self {
+        Self {
+            export_name,
+            disk,
+            vm_running,
+        }
+    }
+
+    /// Accepts and serves connections on `listener` until `shutdown` is
+    /// signaled or dropped.
+    pub async fn run(
+        &self,
+        driver: &(impl ?Sized + Driver),
+        listener: TcpListener,
+        shutdown: mesh::OneshotReceiver<()>,
+    ) -> io::Result<()> {
+        let mut listener = PolledSocket::new(driver, listener)?;
+        let mut shutdown = shutdown.fuse();
+        let mut connections = futures::stream::FuturesUnordered::new();
+        loop {
+            select_biased! {
+                _ = shutdown => break,
+                r = listener.accept().fuse() => {
+                    let (socket, addr) = match r {
+                        Ok(r) => r,
+                        Err(err) => {
+                            tracing::warn!(
+                                error = &err as &dyn std::error::Error,
+                                "failed to accept nbd connection"
+                            );
+                            continue;
+                        }
+                    };
+                    let socket = PolledSocket::new(driver, socket)?;
+                    tracing::info!(%addr, export = self.export_name, "nbd client connected");
+                    connections.push(async move {
+                        if let Err(err) = self.serve(socket).await {
+                            tracing::warn!(
+                                %addr,
+                                error = &err as &dyn std::error::Error,
+                                "nbd connection failed"
+                            );
+                        } else {
+                            tracing::info!(%addr, "nbd client disconnected");
+                        }
+                    });
+                }
+                _ = connections.select_next_some() => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn is_writable(&self) -> bool {
+        !self.disk.is_read_only() && !self.vm_running.get()
+    }
+
+    fn disk_size(&self) -> u64 {
+        self.disk.sector_count() << self.disk.sector_shift()
+    }
+
+    fn transmission_flags(&self) -> u16 {
+        let mut flags =
+            protocol::FLAG_HAS_FLAGS | protocol::FLAG_SEND_FLUSH | protocol::FLAG_SEND_FUA;
+        if self.is_writable() {
+            if self.disk.unmap_behavior() != UnmapBehavior::Ignored {
+                flags |= protocol::FLAG_SEND_TRIM;
+            }
+        } else {
+            flags |= protocol::FLAG_READ_ONLY | protocol::FLAG_CAN_MULTI_CONN;
+        }
+        flags
+    }
+
+    fn matches_export(&self, name: &[u8]) -> bool {
+        // An empty name selects the default export.
+        name.is_empty() || name == self.export_name.as_bytes()
+    }
+
+    /// Serves a single client connection.
+    pub(crate) async fn serve(
+        &self,
+        mut socket: impl AsyncRead + AsyncWrite + Unpin,
+    ) -> Result<(), ConnectionError> {
+        if self.handshake(&mut socket).await? {
+            self.transmit(&mut socket).await?;
+        }
+        Ok(())
+    }
+
+    /// Performs the fixed newstyle handshake. Returns `false` if the client
+    /// aborted the handshake.
+    async fn handshake(
+        &self,
+        socket: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    ) -> Result<bool, ConnectionError> {
+        let greeting = protocol::ServerGreeting {
+            magic: protocol::NBD_MAGIC.into(),
+            ihaveopt: protocol::IHAVEOPT.into(),
+            handshake_flags: (protocol::FLAG_FIXED_NEWSTYLE | protocol::FLAG_NO_ZEROES).into(),
+        };
+        socket.write_all(greeting.as_bytes()).await?;
+
+        let client_flags = u32::from_be_bytes(read_obj(socket).await?);
+        if client_flags & protocol::FLAG_C_FIXED_NEWSTYLE == 0 {
+            return Err(ConnectionError::NotFixedNewstyle);
+        }
+        let no_zeroes = client_flags & protocol::FLAG_C_NO_ZEROES != 0;
+
+        loop {
+            let header: protocol::OptionHeader = read_obj(socket).await?;
+            if header.magic.get() != protocol::IHAVEOPT {
+                return Err(ConnectionError::BadMagic(header.magic.get()));
+            }
+            let len = header.length.get();
+            if len > MAX_OPTION_SIZE {
+                return Err(ConnectionError::OptionTooLarge(len));
+            }
+            let mut data = vec![0; len as usize];
+            socket.read_exact(&mut data).await?;
+
+            let option = header.option.get();
+            match option {
+                protocol::OPT_EXPORT_NAME => {
+                    if !self.matches_export(&data) {
+                        // There is no way to report an error for this option,
+                        // so the connection must be closed.
+                        return Err(ConnectionError::UnknownExport(
+                            String::from_utf8_lossy(&data).into_owned(),
+                        ));
+                    }
+                    let info = protocol::ExportInfo {
+                        size: self.disk_size().into(),
+                        transmission_flags: self.transmission_flags().into(),
+                    };
+                    socket.write_all(info.as_bytes()).await?;
+                    if !no_zeroes {
+                        socket
+                            .write_all(&[0; protocol::EXPORT_NAME_PADDING])
+                            .await?;
+                    }
+                    return Ok(true);
+                }
+                protocol::OPT_ABORT => {
+                    send_option_reply(socket, option, protocol::REP_ACK, &[]).await?;
+                    return Ok(false);
+                }
+                protocol::OPT_LIST => {
+                    if !data.is_empty() {
+                        send_option_reply(socket, option, protocol::REP_ERR_INVALID, &[]).await?;
+                        continue;
+                    }
+                    let name = self.export_name.as_bytes();
+                    let mut reply = (name.len() as u32).to_be_bytes().to_vec();
+                    reply.extend_from_slice(name);
+                    send_option_reply(socket, option, protocol::REP_SERVER, &reply).await?;
+                    send_option_reply(socket, option, protocol::REP_ACK, &[]).await?;
+                }
+                protocol::OPT_INFO | protocol::OPT_GO => {
+                    let Some(name) = parse_info_request(&data) else {
+                        send_option_reply(socket, option, protocol::REP_ERR_INVALID, &[]).await?;
+                        continue;
+                    };
+                    if !self.matches_export(name) {
+                        send_option_reply(socket, option, protocol::REP_ERR_UNKNOWN, &[]).await?;
+                        continue;
+                    }
+                    let export = protocol::InfoExport {
+                        info_type: protocol::INFO_EXPORT.into(),
+                        size: self.disk_size().into(),
+                        transmission_flags: self.transmission_flags().into(),
+                    };
+                    send_option_reply(socket, option, protocol::REP_INFO, export.as_bytes())
+                        .await?;
+                    let sector_size = self.disk.sector_size();
+                    let block_size = protocol::InfoBlockSize {
+                        info_type: protocol::INFO_BLOCK_SIZE.into(),
+                        minimum: sector_size.into(),
+                        preferred: self.disk.physical_sector_size().max(sector_size).into(),
+                        maximum: MAX_REQUEST_SIZE.into(),
+                    };
+                    send_option_reply(socket, option, protocol::REP_INFO, block_size.as_bytes())
+                        .await?;
+                    send_option_reply(socket, option, protocol::REP_ACK, &[]).await?;
+                    if option == protocol::OPT_GO {
+                        return Ok(true);
+                    }
+                }
+                _ => {
+                    send_option_reply(socket, option, protocol::REP_ERR_UNSUP, &[]).await?;
+                }
+            }
+        }
+    }
+
+    /// Processes transmission phase requests until the client disconnects.
+    async fn transmit(
+        &self,
+        socket: &mut (impl AsyncRead + AsyncWrite + Unpin),
+    ) -> Result<(), ConnectionError> {
+        loop {
+            let request: protocol::Request = read_obj(socket).await?;
+            if request.magic.get() != protocol::REQUEST_MAGIC {
+                return Err(ConnectionError::BadMagic(request.magic.get().into()));
+            }
+            let cookie = request.cookie.get();
+            let offset = request.offset.get();
+            let length = request.length.get();
+            let fua = request.flags.get() & protocol::CMD_FLAG_FUA != 0;
+
+            let result = match request.command.get() {
+                protocol::CMD_READ => match self.read(offset, length).await {
+                    Ok(data) => {
+                        let mut reply = simple_reply(cookie, 0);
+                        reply.extend_from_slice(&data);
+                        socket.write_all(&reply).await?;
+                        continue;
+                    }
+                    Err(err) => Err(err),
+                },
+                protocol::CMD_WRITE => {
+                    if length > MAX_REQUEST_SIZE {
+                        return Err(ConnectionError::RequestTooLarge(length));
+                    }
+                    let mut data = vec![0; length as usize];
+                    socket.read_exact(&mut data).await?;
+                    self.write(offset, &data, fua).await
+                }
+                protocol::CMD_DISC => return Ok(()),
+                protocol::CMD_FLUSH => self.disk.sync_cache().await.map_err(map_disk_error),
+                protocol::CMD_TRIM => self.trim(offset, length).await,
+                _ => Err(protocol::EINVAL),
+            };
+
+            let error = result.err().unwrap_or(0);
+            socket.write_all(&simple_reply(cookie, error)).await?;
+        }
+    }
+
+    /// Validates a request range, returning the starting sector.
+    fn check_range(&self, offset: u64, length: u32, past_end_error: u32) -> Result<u64, u32> {
+        let sector_mask = self.disk.sector_size() as u64 - 1;
+        if offset & sector_mask != 0 || length as u64 & sector_mask != 0 {
+            return Err(protocol::EINVAL);
+        }
+        if offset
+            .checked_add(length.into())
+            .is_none_or(|end| end > self.disk_size())
+        {
+            return Err(past_end_error);
+        }
+        Ok(offset >> self.disk.sector_shift())
+    }
+
+    async fn read(&self, offset: u64, length: u32) -> Result<Vec<u8>, u32> {
+        if length > MAX_REQUEST_SIZE {
+            return Err(protocol::EOVERFLOW);
+        }
+        let sector = self.check_range(offset, length, protocol::EINVAL)?;
+        let mut data = vec![0; length as usize];
+        if length == 0 {
+            return Ok(data);
+        }
+        let mem = GuestMemory::allocate(data.len());
+        let buffers = OwnedRequestBuffers::linear(0, data.len(), true);
+        self.disk
+            .read_vectored(&buffers.buffer(&mem), sector)
+            .await
+            .map_err(map_disk_error)?;
+        mem.read_at(0, &mut data).map_err(|_| protocol::EIO)?;
+        Ok(data)
+    }
+
+    async fn write(&self, offset: u64, data: &[u8], fua: bool) -> Result<(), u32> {
+        if !self.is_writable() {
+            return Err(protocol::EPERM);
+        }
+        let sector = self.check_range(offset, data.len() as u32, protocol::ENOSPC)?;
+        if data.is_empty() {
+            return Ok(());
+        }
+        let mem = GuestMemory::allocate(data.len());
+        mem.write_at(0, data).map_err(|_| protocol::EIO)?;
+        let buffers = OwnedRequestBuffers::linear(0, data.len(), false);
+        self.disk
+            .write_vectored(&buffers.buffer(&mem), sector, fua)
+            .await
+            .map_err(map_disk_error)
+    }
+
+    async fn trim(&self, offset: u64, length: u32) -> Result<(), u32> {
+        if !self.is_writable() {
+            return Err(protocol::EPERM);
+        }
+        let sector = self.check_range(offset, length, protocol::ENOSPC)?;
+        let count = (length >> self.disk.sector_shift()) as u64;
+        if count == 0 {
+            return Ok(());
+        }
+        self.disk
+            .unmap(sector, count, false)
+            .await
+            .map_err(map_disk_error)
+    }
+}
+
+fn map_disk_error(err: DiskError) -> u32 {
+    tracing::debug!(error = &err as &dyn std::error::Error, "nbd request failed");
+    match err {
+        DiskError::ReadOnly => protocol::EPERM,
+        DiskError::IllegalBlock | DiskError::InvalidInput => protocol::EINVAL,
+        _ => protocol::EIO,
+    }
+}
+
+/// Parses the payload of `NBD_OPT_INFO` or `NBD_OPT_GO`, returning the
+/// requested export name.
+///
+/// The requested information types are ignored, since the server always
+/// sends all the information it supports.
+fn parse_info_request(data: &[u8]) -> Option<&[u8]> {
+    let (name_len, rest) = data.split_first_chunk::<4>()?;
+    let name_len = u32::from_be_bytes(*name_len) as usize;
+    let (name, rest) = rest.split_at_checked(name_len)?;
+    let (count, rest) = rest.split_first_chunk::<2>()?;
+    if rest.len() != u16::from_be_bytes(*count) as usize * 2 {
+        return None;
+    }
+    Some(name)
+}
+
+fn simple_reply(cookie: u64, error: u32) -> Vec<u8> {
+    protocol::SimpleReply {
+        magic: protocol::SIMPLE_REPLY_MAGIC.into(),
+        error: error.into(),
+        cookie: cookie.into(),
+    }
+    .as_bytes()
+    .to_vec()
+}
+
+async fn send_option_reply(
+    socket: &mut (impl AsyncWrite + Unpin),
+    option: u32,
+    reply_type: u32,
+    data: &[u8],
+) -> io::Result<()> {
+    let mut reply = protocol::OptionReplyHeader {
+        magic: protocol::OPTION_REPLY_MAGIC.into(),
+        option: option.into(),
+        reply_type: reply_type.into(),
+        length: (data.len() as u32).into(),
+    }
+    .as_bytes()
+    .to_vec();
+    reply.extend_from_slice(data);
+    socket.write_all(&reply).await
+}
+
+async fn read_obj<T: AsBytes + FromBytes>(socket: &mut (impl AsyncRead + Unpin)) -> io::Result<T> {
+    let mut obj = T::new_zeroed();
+    socket.read_exact(obj.as_bytes_mut()).await?;
+    Ok(obj)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NbdServer;
+    use crate::protocol;
+    use futures::AsyncReadExt;
+    use futures::AsyncWriteExt;
+    use pal_async::async_test;
+    use pal_async::socket::PolledSocket;
+    use pal_async::DefaultDriver;
+    use std::net::TcpListener;
+    use std::net::TcpStream;
+    use zerocopy::AsBytes;
+    use zerocopy::FromZeroes;
+
+    const DISK_SIZE: u64 = 0x100000;
+
+    type Socket = PolledSocket<TcpStream>;
+
+    async fn connect(driver: &DefaultDriver) -> (Socket, Socket) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (
+            PolledSocket::new(driver, client).unwrap(),
+            PolledSocket::new(driver, server).unwrap(),
+        )
+    }
+
+    fn server(running: bool) -> (NbdServer, mesh::CellUpdater<bool>) {
+        let disk = disk_ramdisk::ram_disk(DISK_SIZE, false).unwrap();
+        let mut updater = mesh::CellUpdater::new(running);
+        let server = NbdServer::new("disk0".into(), disk, updater.cell());
+        (server, updater)
+    }
+
+    async fn read<T: AsBytes + zerocopy::FromBytes>(client: &mut Socket) -> T {
+        let mut obj = T::new_zeroed();
+        client.read_exact(obj.as_bytes_mut()).await.unwrap();
+        obj
+    }
+
+    async fn send_option(client: &mut Socket, option: u32, data: &[u8]) {
+        let header = protocol::OptionHeader {
+            magic: protocol::IHAVEOPT.into(),
+            option: option.into(),
+            length: (data.len() as u32).into(),
+        };
+        client.write_all(header.as_bytes()).await.unwrap();
+        client.write_all(data).await.unwrap();
+    }
+
+    async fn read_option_reply(client: &mut Socket) -> (u32, Vec<u8>) {
+        let header: protocol::OptionReplyHeader = read(client).await;
+        assert_eq!(header.magic.get(), protocol::OPTION_REPLY_MAGIC);
+        let mut data = vec![0; header.length.get() as usize];
+        client.read_exact(&mut data).await.unwrap();
+        (header.reply_type.get(), data)
+    }
+
+    /// Reads the server greeting and sends the client flags.
+    async fn hello(client: &mut Socket) {
+        let greeting: protocol::ServerGreeting = read(client).await;
+        assert_eq!(greeting.magic.get(), protocol::NBD_MAGIC);
+        assert_eq!(greeting.ihaveopt.get(), protocol::IHAVEOPT);
+        let flags = protocol::FLAG_C_FIXED_NEWSTYLE | protocol::FLAG_C_NO_ZEROES;
+        client.write_all(&flags.to_be_bytes()).await.unwrap();
+    }
+
+    /// Negotiates the export via `NBD_OPT_GO`, returning the transmission
+    /// flags, or `None` if the export does not exist.
+    async fn go(client: &mut Socket, name: &str) -> Option<u16> {
+        let mut data = (name.len() as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(name.as_bytes());
+        data.extend_from_slice(&0u16.to_be_bytes());
+        send_option(client, protocol::OPT_GO, &data).await;
+
+        let mut flags = None;
+        loop {
+            let (reply_type, data) = read_option_reply(client).await;
+            match reply_type {
+                protocol::REP_INFO => {
+                    if data[..2] == protocol::INFO_EXPORT.to_be_bytes() {
+                        assert_eq!(data[2..10], DISK_SIZE.to_be_bytes());
+                        flags = Some(u16::from_be_bytes(data[10..12].try_into().unwrap()));
+                    }
+                }
+                protocol::REP_ACK => break flags,
+                protocol::REP_ERR_UNKNOWN => break None,
+                ty => panic!("unexpected reply {ty:#x}"),
+            }
+        }
+    }
+
+    fn request_header(command: u16, cookie: u64, offset: u64, length: u32) -> protocol::Request {
+        protocol::Request {
+            magic: protocol::REQUEST_MAGIC.into(),
+            flags: 0.into(),
+            command: command.into(),
+            cookie: cookie.into(),
+            offset: offset.into(),
+            length: length.into(),
+        }
+    }
+
+    async fn request(
+        client: &mut Socket,
+        command: u16,
+        cookie: u64,
+        offset: u64,
+        data: &[u8],
+        read_len: u32,
+    ) -> (u32, Vec<u8>) {
+        let length = if command == protocol::CMD_WRITE {
+            data.len() as u32
+        } else {
+            read_len
+        };
+        let request = request_header(command, cookie, offset, length);
+        client.write_all(request.as_bytes()).await.unwrap();
+        client.write_all(data).await.unwrap();
+        let reply: protocol::SimpleReply = read(client).await;
+        assert_eq!(reply.magic.get(), protocol::SIMPLE_REPLY_MAGIC);
+        assert_eq!(reply.cookie.get(), cookie);
+        let mut data = Vec::new();
+        if command == protocol::CMD_READ && reply.error.get() == 0 {
+            data.resize(read_len as usize, 0);
+            client.read_exact(&mut data).await.unwrap();
+        }
+        (reply.error.get(), data)
+    }
+
+    #[async_test]
+    async fn read_write_when_stopped(driver: DefaultDriver) {
+        let (server, _updater) = server(false);
+        let (mut client, socket) = connect(&driver).await;
+        let serve = server.serve(socket);
+        let client_ops = async {
+            hello(&mut client).await;
+            let flags = go(&mut client, "disk0").await.unwrap();
+            assert_eq!(flags & protocol::FLAG_READ_ONLY, 0);
+
+            let data = (0..1024).map(|i| i as u8).collect::<Vec<_>>();
+            let (error, _) = request(&mut client, protocol::CMD_WRITE, 1, 4096, &data, 0).await;
+            assert_eq!(error, 0);
+            let (error, read_data) =
+                request(&mut client, protocol::CMD_READ, 2, 4096, &[], 1024).await;
+            assert_eq!(error, 0);
+            assert_eq!(read_data, data);
+
+            // Unaligned and out-of-range requests fail.
+            let (error, _) = request(&mut client, protocol::CMD_READ, 3, 1, &[], 512).await;
+            assert_eq!(error, protocol::EINVAL);
+            let (error, _) =
+                request(&mut client, protocol::CMD_WRITE, 4, DISK_SIZE, &data, 0).await;
+            assert_eq!(error, protocol::ENOSPC);
+
+            let disc = request_header(protocol::CMD_DISC, 5, 0, 0);
+            client.write_all(disc.as_bytes()).await.unwrap();
+        };
+        let (r, ()) = futures::join!(serve, client_ops);
+        r.unwrap();
+    }
+
+    #[async_test]
+    async fn read_only_when_running(driver: DefaultDriver) {
+        let (server, mut updater) = server(false);
+        let (mut client, socket) = connect(&driver).await;
+        let serve = server.serve(socket);
+        let client_ops = async move {
+            hello(&mut client).await;
+            assert!(go(&mut client, "missing").await.is_none());
+            updater.set(true).await;
+            let flags = go(&mut client, "disk0").await.unwrap();
+            assert_ne!(flags & protocol::FLAG_READ_ONLY, 0);
+            let (error, _) = request(&mut client, protocol::CMD_WRITE, 1, 0, &[1; 512], 0).await;
+            assert_eq!(error, protocol::EPERM);
+            let (error, data) = request(&mut client, protocol::CMD_READ, 2, 0, &[], 512).await;
+            assert_eq!(error, 0);
+            assert_eq!(data, [0; 512]);
+            drop(client);
+        };
+        let (r, ()) = futures::join!(serve, client_ops);
+        assert!(r.is_err());
+    }
+}
@@ -0,0 +1,572 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A disk with a sparse, in-memory copy-on-write overlay on top of any other
+//! disk.
+//!
+//! Writes are kept in the overlay, and reads are satisfied from the overlay
+//! where present and from the base disk otherwise. The overlay can later be
+//! committed to the base disk, or discarded to reset the disk back to the
+//! base disk's contents, via [`OverlayDiskRequest`].
+
+#![forbid(unsafe_code)]
+
+use async_trait::async_trait;
+use disk_backend::resolve::ResolveDiskParameters;
+use disk_backend::resolve::ResolvedDisk;
+use disk_backend::Disk;
+use disk_backend::DiskError;
+use disk_backend::DiskIo;
+use disk_backend::UnmapBehavior;
+use disk_backend_resources::OverlayDiskHandle;
+use disk_backend_resources::OverlayDiskRequest;
+use futures::lock::Mutex;
+use futures::StreamExt;
+use guestmem::GuestMemory;
+use guestmem::MemoryRead;
+use guestmem::MemoryWrite;
+use inspect::Inspect;
+use pal_async::task::Spawn;
+use parking_lot::RwLock;
+use scsi_buffers::OwnedRequestBuffers;
+use scsi_buffers::RequestBuffers;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+use std::sync::Weak;
+use thiserror::Error;
+use vm_resource::declare_static_async_resolver;
+use vm_resource::kind::DiskHandleKind;
+use vm_resource::AsyncResolveResource;
+use vm_resource::ResolveError;
+use vm_resource::ResourceResolver;
+
+pub struct OverlayDiskResolver;
+declare_static_async_resolver!(OverlayDiskResolver, (DiskHandleKind, OverlayDiskHandle));
+
+#[derive(Debug, Error)]
+pub enum ResolveOverlayDiskError {
+    #[error("failed to resolve base disk")]
+    Resolve(#[source] ResolveError),
+    #[error("invalid disk")]
+    InvalidDisk(#[source] disk_backend::InvalidDisk),
+}
+
+#[async_trait]
+impl AsyncResolveResource<DiskHandleKind, OverlayDiskHandle> for OverlayDiskResolver {
+    type Output = ResolvedDisk;
+    type Error = ResolveOverlayDiskError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        rsrc: OverlayDiskHandle,
+        input: ResolveDiskParameters<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let base = resolver
+            .resolve(
+                rsrc.base,
+                ResolveDiskParameters {
+                    read_only: input.read_only || rsrc.read_only_base,
+                    driver_source: input.driver_source,
+                },
+            )
+            .await
+            .map_err(ResolveOverlayDiskError::Resolve)?;
+
+        let disk = OverlayDisk::new(base.0, input.read_only);
+
+        // Start a task to handle incoming commit and discard requests.
+        if let Some(requests) = rsrc.requests {
+            input
+                .driver_source
+                .simple()
+                .spawn(
+                    "overlay-disk-requests",
+                    handle_requests(Arc::downgrade(&disk.inner), requests),
+                )
+                .detach();
+        }
+
+        ResolvedDisk::new(disk).map_err(ResolveOverlayDiskError::InvalidDisk)
+    }
+}
+
+async fn handle_requests(inner: Weak<Inner>, mut requests: mesh::Receiver<OverlayDiskRequest>) {
+    while let Some(req) = requests.next().await {
+        let Some(inner) = inner.upgrade() else {
+            break;
+        };
+        match req {
+            OverlayDiskRequest::Commit(rpc) => {
+                rpc.handle_failable(|()| async move { inner.commit().await })
+                    .await
+            }
+            OverlayDiskRequest::Discard(rpc) => rpc.handle(|()| inner.discard()).await,
+        }
+    }
+}
+
+/// The number of sectors tracked by each overlay chunk. Each chunk tracks the
+/// sectors present in the overlay with a bit in a `u64`.
+const CHUNK_SECTORS: u64 = 64;
+
+/// An error committing the overlay to the base disk.
+#[derive(Debug, Error)]
+pub enum CommitError {
+    #[error("the base disk is read-only")]
+    ReadOnlyBase,
+    #[error("failed to write to the base disk")]
+    Write(#[source] DiskError),
+    #[error("failed to flush the base disk")]
+    Flush(#[source] DiskError),
+}
+
+/// A disk with an in-memory copy-on-write overlay over a base disk.
+pub struct OverlayDisk {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    base: Disk,
+    read_only: bool,
+    sector_shift: u32,
+    state: RwLock<OverlayState>,
+    /// Held for the duration of a commit or discard, so that they don't run
+    /// concurrently.
+    operation: Mutex<()>,
+}
+
+#[derive(Default)]
+struct OverlayState {
+    chunks: BTreeMap<u64, Chunk>,
+    /// The number of sectors present across all chunks.
+    sectors: u64,
+    /// Incremented on each write, so that commit can tell if a chunk was
+    /// rewritten while it was being committed.
+    generation: u64,
+}
+
+/// `CHUNK_SECTORS` consecutive sectors of the overlay, some of which may be
+/// present.
+struct Chunk {
+    data: Box<[u8]>,
+    /// Bit `n` is set if sector `n` of the chunk is present in the overlay.
+    present: u64,
+    generation: u64,
+}
+
+/// Returns the mask of the bits in `range`, which must be within
+/// `0..CHUNK_SECTORS`.
+fn chunk_mask(range: Range<u64>) -> u64 {
+    let len = range.end - range.start;
+    if len == CHUNK_SECTORS {
+        !0
+    } else {
+        ((1 << len) - 1) << range.start
+    }
+}
+
+/// Returns the runs of consecutive bits set in `present` within `range`.
+fn present_runs(present: u64, range: Range<u64>) -> impl Iterator<Item = Range<u64>> {
+    let mut next = range.start;
+    std::iter::from_fn(move || {
+        while next < range.end && present & (1 << next) == 0 {
+            next += 1;
+        }
+        if next == range.end {
+            return None;
+        }
+        let start = next;
+        while next < range.end && present & (1 << next) != 0 {
+            next += 1;
+        }
+        Some(start..next)
+    })
+}
+
+impl Inspect for OverlayDisk {
+    fn inspect(&self, req: inspect::Request<'_>) {
+        let state = self.inner.state.read();
+        req.respond()
+            .field("base", &self.inner.base)
+            .field("dirty_sectors", state.sectors)
+            .field("chunks", state.chunks.len());
+    }
+}
+
+impl OverlayDisk {
+    /// Wraps `base` with an empty overlay.
+    pub fn new(base: Disk, read_only: bool) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                sector_shift: base.sector_size().trailing_zeros(),
+                base,
+                read_only,
+                state: Default::default(),
+                operation: Default::default(),
+            }),
+        }
+    }
+
+    /// Writes the contents of the overlay to the base disk and flushes it,
+    /// emptying the overlay.
+    ///
+    /// Writes issued concurrently with the commit remain in the overlay.
+    pub async fn commit(&self) -> Result<(), CommitError> {
+        self.inner.commit().await
+    }
+
+    /// Discards the contents of the overlay, reverting the disk to the
+    /// contents of the base disk.
+    pub async fn discard(&self) {
+        self.inner.discard().await
+    }
+}
+
+impl Inner {
+    fn chunk_len(&self) -> usize {
+        (CHUNK_SECTORS as usize) << self.sector_shift
+    }
+
+    async fn commit(&self) -> Result<(), CommitError> {
+        if self.base.is_read_only() {
+            return Err(CommitError::ReadOnlyBase);
+        }
+        let _operation = self.operation.lock().await;
+
+        // Stage each chunk here while writing it to the base disk.
+        let mem = GuestMemory::allocate(self.chunk_len());
+        let buffers = OwnedRequestBuffers::linear(0, self.chunk_len(), false);
+        let staging = buffers.buffer(&mem);
+        let mut committed = 0;
+        let mut next = 0;
+        loop {
+            let (index, present, generation) = {
+                let state = self.state.read();
+                let Some((&index, chunk)) = state.chunks.range(next..).next() else {
+                    break;
+                };
+                mem.write_at(0, &chunk.data).unwrap();
+                (index, chunk.present, chunk.generation)
+            };
+
+            for run in present_runs(present, 0..CHUNK_SECTORS) {
+                let offset = (run.start as usize) << self.sector_shift;
+                let len = ((run.end - run.start) as usize) << self.sector_shift;
+                self.base
+                    .write_vectored(
+                        &staging.subrange(offset, len),
+                        index * CHUNK_SECTORS + run.start,
+                        false,
+                    )
+                    .await
+                    .map_err(CommitError::Write)?;
+            }
+
+            // Remove the committed chunk from the overlay, unless it was
+            // written again in the meantime.
+            let mut state = self.state.write();
+            if state
+                .chunks
+                .get(&index)
+                .is_some_and(|c| c.generation == generation)
+            {
+                state.chunks.remove(&index);
+                state.sectors -= u64::from(present.count_ones());
+            }
+            committed += present.count_ones();
+            next = index + 1;
+        }
+
+        self.base.sync_cache().await.map_err(CommitError::Flush)?;
+        tracing::info!(sectors = committed, "committed disk overlay");
+        Ok(())
+    }
+
+    async fn discard(&self) {
+        let _operation = self.operation.lock().await;
+        let mut state = self.state.write();
+        state.chunks.clear();
+        let sectors = std::mem::take(&mut state.sectors);
+        tracing::info!(sectors, "discarded disk overlay");
+    }
+}
+
+impl DiskIo for OverlayDisk {
+    fn disk_type(&self) -> &str {
+        "overlay"
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.inner.base.sector_count()
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.inner.base.sector_size()
+    }
+
+    fn disk_id(&self) -> Option<[u8; 16]> {
+        self.inner.base.disk_id()
+    }
+
+    fn physical_sector_size(&self) -> u32 {
+        self.inner.base.physical_sector_size()
+    }
+
+    fn is_fua_respected(&self) -> bool {
+        // Writes only reach the base disk on commit.
+        false
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.inner.read_only
+    }
+
+    async fn read_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+    ) -> Result<(), DiskError> {
+        let inner = &*self.inner;
+        let shift = inner.sector_shift;
+        let end = sector + (buffers.len() >> shift) as u64;
+        if end > self.sector_count() {
+            return Err(DiskError::IllegalBlock);
+        }
+
+        // Copy the sectors present in the overlay, collecting the ranges that
+        // must be read from the base disk.
+        let mut holes = Vec::new();
+        {
+            let state = inner.state.read();
+            let mut next = sector;
+            let chunks = sector / CHUNK_SECTORS..end.div_ceil(CHUNK_SECTORS);
+            for (&index, chunk) in state.chunks.range(chunks) {
+                let chunk_start = index * CHUNK_SECTORS;
+                let range = sector.max(chunk_start) - chunk_start
+                    ..end.min(chunk_start + CHUNK_SECTORS) - chunk_start;
+                for run in present_runs(chunk.present, range) {
+                    let start = chunk_start + run.start;
+                    if start > next {
+                        holes.push(next..start);
+                    }
+                    buffers
+                        .subrange(
+                            ((start - sector) as usize) << shift,
+                            ((run.end - run.start) as usize) << shift,
+                        )
+                        .writer()
+                        .write(
+                            &chunk.data[(run.start as usize) << shift..(run.end as usize) << shift],
+                        )?;
+                    next = chunk_start + run.end;
+                }
+            }
+            if next < end {
+                holes.push(next..end);
+            }
+        }
+
+        for hole in holes {
+            let buffers = buffers.subrange(
+                ((hole.start - sector) as usize) << shift,
+                ((hole.end - hole.start) as usize) << shift,
+            );
+            inner.base.read_vectored(&buffers, hole.start).await?;
+        }
+        Ok(())
+    }
+
+    async fn write_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+        _fua: bool,
+    ) -> Result<(), DiskError> {
+        let inner = &*self.inner;
+        if inner.read_only {
+            return Err(DiskError::ReadOnly);
+        }
+        let shift = inner.sector_shift;
+        let end = sector + (buffers.len() >> shift) as u64;
+        if end > self.sector_count() {
+            return Err(DiskError::IllegalBlock);
+        }
+        let mut data = vec![0; buffers.len()];
+        buffers.reader().read(&mut data)?;
+
+        let mut state = inner.state.write();
+        let state = &mut *state;
+        state.generation += 1;
+        let generation = state.generation;
+        let mut n = sector;
+        while n < end {
+            let index = n / CHUNK_SECTORS;
+            let chunk_start = index * CHUNK_SECTORS;
+            let range = n - chunk_start..end.min(chunk_start + CHUNK_SECTORS) - chunk_start;
+            let chunk = state.chunks.entry(index).or_insert_with(|| Chunk {
+                data: vec![0; inner.chunk_len()].into(),
+                present: 0,
+                generation,
+            });
+            let src = ((n - sector) as usize) << shift;
+            let len = ((range.end - range.start) as usize) << shift;
+            chunk.data[(range.start as usize) << shift..][..len]
+                .copy_from_slice(&data[src..src + len]);
+            let mask = chunk_mask(range.clone());
+            state.sectors += u64::from((mask & !chunk.present).count_ones());
+            chunk.present |= mask;
+            chunk.generation = generation;
+            n = chunk_start + range.end;
+        }
+        Ok(())
+    }
+
+    async fn sync_cache(&self) -> Result<(), DiskError> {
+        // The overlay is not persistent, so there is nothing to flush.
+        Ok(())
+    }
+
+    async fn unmap(
+        &self,
+        _sector: u64,
+        _count: u64,
+        _block_level_only: bool,
+    ) -> Result<(), DiskError> {
+        Ok(())
+    }
+
+    fn unmap_behavior(&self) -> UnmapBehavior {
+        UnmapBehavior::Ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::handle_requests;
+    use super::Inner;
+    use super::OverlayDisk;
+    use disk_backend::Disk;
+    use disk_backend_resources::OverlayDiskRequest;
+    use disk_ramdisk::ram_disk;
+    use guestmem::GuestMemory;
+    use mesh::rpc::RpcSend;
+    use pal_async::async_test;
+    use pal_async::task::Spawn;
+    use pal_async::DefaultDriver;
+    use scsi_buffers::OwnedRequestBuffers;
+    use std::sync::Arc;
+
+    const SECTOR: usize = 512;
+
+    async fn write(disk: &Disk, mem: &GuestMemory, sector: u64, data: &[u8]) {
+        mem.write_at(0, data).unwrap();
+        disk.write_vectored(
+            &OwnedRequestBuffers::linear(0, data.len(), false).buffer(mem),
+            sector,
+            false,
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn read(disk: &Disk, mem: &GuestMemory, sector: u64, count: usize) -> Vec<u8> {
+        let len = count * SECTOR;
+        disk.read_vectored(
+            &OwnedRequestBuffers::linear(0, len, true).buffer(mem),
+            sector,
+        )
+        .await
+        .unwrap();
+        let mut buf = vec![0; len];
+        mem.read_at(0, &mut buf).unwrap();
+        buf
+    }
+
+    /// Returns the base disk, the overlay disk, and the overlay state, with
+    /// sectors 2 and 3 written in the overlay.
+    async fn setup(mem: &GuestMemory) -> (Disk, Disk, Arc<Inner>) {
+        let base = ram_disk(0x100000, false).unwrap();
+        write(&base, mem, 0, &[1; 8 * SECTOR]).await;
+        let overlay = OverlayDisk::new(base.clone(), false);
+        let inner = overlay.inner.clone();
+        let overlay = Disk::new(overlay).unwrap();
+        write(&overlay, mem, 2, &[2; 2 * SECTOR]).await;
+        (base, overlay, inner)
+    }
+
+    fn expected(overlay: bool) -> Vec<u8> {
+        let mut data = vec![1; 8 * SECTOR];
+        if overlay {
+            data[2 * SECTOR..4 * SECTOR].fill(2);
+        }
+        data
+    }
+
+    #[async_test]
+    async fn copy_on_write() {
+        let mem = GuestMemory::allocate(0x10000);
+        let (base, overlay, _) = setup(&mem).await;
+        assert_eq!(read(&overlay, &mem, 0, 8).await, expected(true));
+        assert_eq!(read(&base, &mem, 0, 8).await, expected(false));
+    }
+
+    #[async_test]
+    async fn discard() {
+        let mem = GuestMemory::allocate(0x10000);
+        let (_, overlay, inner) = setup(&mem).await;
+        inner.discard().await;
+        assert!(inner.state.read().chunks.is_empty());
+        assert_eq!(read(&overlay, &mem, 0, 8).await, expected(false));
+    }
+
+    #[async_test]
+    async fn commit() {
+        let mem = GuestMemory::allocate(0x10000);
+        let (base, overlay, inner) = setup(&mem).await;
+        inner.commit().await.unwrap();
+        assert!(inner.state.read().chunks.is_empty());
+        assert_eq!(read(&base, &mem, 0, 8).await, expected(true));
+        assert_eq!(read(&overlay, &mem, 0, 8).await, expected(true));
+    }
+
+    #[async_test]
+    async fn across_chunks() {
+        let mem = GuestMemory::allocate(0x10000);
+        let (base, overlay, inner) = setup(&mem).await;
+        // Sectors 62..66 span the first two chunks.
+        write(&overlay, &mem, 62, &[3; 4 * SECTOR]).await;
+        assert_eq!(inner.state.read().chunks.len(), 2);
+        assert_eq!(inner.state.read().sectors, 6);
+
+        let mut data = vec![0; 10 * SECTOR];
+        data[2 * SECTOR..6 * SECTOR].fill(3);
+        assert_eq!(read(&overlay, &mem, 60, 10).await, data);
+        assert_eq!(read(&base, &mem, 60, 10).await, vec![0; 10 * SECTOR]);
+
+        inner.commit().await.unwrap();
+        assert_eq!(inner.state.read().sectors, 0);
+        assert_eq!(read(&base, &mem, 60, 10).await, data);
+        assert_eq!(read(&base, &mem, 0, 8).await, expected(true));
+    }
+
+    #[async_test]
+    async fn requests(driver: DefaultDriver) {
+        let mem = GuestMemory::allocate(0x10000);
+        let (base, overlay, inner) = setup(&mem).await;
+        let (send, recv) = mesh::channel();
+        driver
+            .spawn("overlay", handle_requests(Arc::downgrade(&inner), recv))
+            .detach();
+
+        send.call_failable(OverlayDiskRequest::Commit, ())
+            .await
+            .unwrap();
+        assert_eq!(read(&base, &mem, 0, 8).await, expected(true));
+
+        write(&overlay, &mem, 0, &[4; SECTOR]).await;
+        send.call(OverlayDiskRequest::Discard, ()).await.unwrap();
+        assert_eq!(read(&overlay, &mem, 0, 8).await, expected(true));
+    }
+}
@@ -4,8 +4,14 @@
 //! Provides a basic implementation of SCSI persistent reservations on top of
 //! any other disk type.
 //!
-//! Since these reservations are stored locally in memory, this is not useful
-//! for actually sharing a disk between VMs. This is just useful for testing.
+//! By default, reservations are stored locally in memory, which is just useful
+//! for testing. Alternatively, reservations can be stored in a state file
+//! shared by multiple VMs that are attached to the same backing disk, which is
+//! sufficient to test shared-storage guests such as failover clusters.
+
+mod lock;
+mod shared;
+mod state;
 
 use async_trait::async_trait;
 use disk_backend::pr;
@@ -16,23 +22,28 @@
 use disk_backend::DiskError;
 use disk_backend::DiskIo;
 use disk_backend_resources::DiskWithReservationsHandle;
+use disk_backend_resources::DiskWithSharedReservationsHandle;
 use inspect::Inspect;
 use parking_lot::Mutex;
 use scsi_buffers::RequestBuffers;
+use shared::SharedState;
+use state::ReservationState;
 use std::future::Future;
-use std::num::NonZeroU64;
-use std::num::Wrapping;
+use std::io;
 use thiserror::Error;
 use vm_resource::declare_static_async_resolver;
 use vm_resource::kind::DiskHandleKind;
 use vm_resource::AsyncResolveResource;
 use vm_resource::ResolveError;
 use vm_resource::ResourceResolver;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
 
 pub struct DiskWithReservationsResolver;
 declare_static_async_resolver!(
     DiskWithReservationsResolver,
-    (DiskHandleKind, DiskWithReservationsHandle)
+    (DiskHandleKind, DiskWithReservationsHandle),
+    (DiskHandleKind, DiskWithSharedReservationsHandle)
 );
 
 #[derive(Debug, Error)]
@@ -41,6 +52,8 @@ pub enum ResolvePrDiskError {
     Resolve(#[source] ResolveError),
     #[error("invalid disk")]
     InvalidDisk(#[source] disk_backend::InvalidDisk),
+    #[error("failed to open reservation state file")]
+    State(#[source] io::Error),
 }
 
 #[async_trait]
@@ -66,33 +79,113 @@ async fn resolve(
     }
 }
 
+#[async_trait]
+impl AsyncResolveResource<DiskHandleKind, DiskWithSharedReservationsHandle>
+    for DiskWithReservationsResolver
+{
+    type Output = ResolvedDisk;
+    type Error = ResolvePrDiskError;
+
+    async fn resolve(
+        &self,
+        resolver: &ResourceResolver,
+        rsrc: DiskWithSharedReservationsHandle,
+        input: ResolveDiskParameters<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let inner = resolver
+            .resolve(rsrc.disk, input)
+            .await
+            .map_err(ResolvePrDiskError::Resolve)?;
+
+        let disk = DiskWithReservations::new_shared(inner.0, rsrc.state_file)
+            .map_err(ResolvePrDiskError::State)?;
+        ResolvedDisk::new(disk).map_err(ResolvePrDiskError::InvalidDisk)
+    }
+}
+
 /// A disk wrapper that adds persistent reservations support to any disk type.
 ///
-/// The reservations are handled locally in memory, so they cannot be used to
-/// actually share a disk.
+/// IOs that conflict with the current reservation fail with
+/// [`DiskError::ReservationConflict`].
 #[derive(Inspect)]
 pub struct DiskWithReservations {
     inner: Disk,
+    /// The ID of this client of the disk.
+    #[inspect(hex)]
+    client: u64,
     #[inspect(flatten)]
-    state: Mutex<ReservationState>,
+    state: State,
+}
+
+enum State {
+    Local(Mutex<ReservationState>),
+    Shared(SharedState),
 }
 
-#[derive(Default, Debug, Inspect)]
-struct ReservationState {
-    generation: Wrapping<u32>,
-    registered_key: Option<NonZeroU64>,
-    reservation_type: Option<ReservationType>,
-    persist_through_power_loss: bool,
+impl Inspect for State {
+    fn inspect(&self, req: inspect::Request<'_>) {
+        // Report the cached shared state, since inspect can't wait for file
+        // IO.
+        let state = match self {
+            State::Local(state) => state.lock().clone(),
+            State::Shared(state) => state.cached(),
+        };
+        req.respond()
+            .field("shared", matches!(self, State::Shared(_)))
+            .merge(&state);
+    }
+}
+
+impl State {
+    async fn read_with<R>(&self, f: impl FnOnce(&ReservationState) -> R) -> Result<R, DiskError> {
+        match self {
+            State::Local(state) => Ok(f(&state.lock())),
+            State::Shared(state) => state.read_with(f).await.map_err(DiskError::Io),
+        }
+    }
+
+    async fn update<R: 'static + Send>(
+        &self,
+        f: impl 'static + Send + FnOnce(&mut ReservationState) -> Result<R, DiskError>,
+    ) -> Result<R, DiskError> {
+        match self {
+            State::Local(state) => f(&mut state.lock()),
+            State::Shared(state) => state.update(f).await,
+        }
+    }
+
+    async fn check_io(&self, client: u64, write: bool) -> Result<(), DiskError> {
+        self.read_with(|state| state.check_io(client, write))
+            .await?
+    }
 }
 
 impl DiskWithReservations {
-    /// Wraps `inner` with persistent reservations support.
+    /// Wraps `inner` with persistent reservations support, storing the
+    /// reservation state in memory.
     pub fn new(inner: Disk) -> Self {
         Self {
             inner,
-            state: Default::default(),
+            client: 0,
+            state: State::Local(Default::default()),
         }
     }
+
+    /// Wraps `inner` with persistent reservations support, storing the
+    /// reservation state in `state_file`.
+    ///
+    /// Other VMs can share the disk by wrapping the same backing disk with the
+    /// same state file. Each instance gets a new random client ID, so
+    /// registrations made by a previous instance are not recognized, just as
+    /// if they were made by another host.
+    pub fn new_shared(inner: Disk, state_file: std::fs::File) -> io::Result<Self> {
+        let client = u64::read_from_prefix(guid::Guid::new_random().as_bytes()).unwrap();
+        Ok(Self {
+            inner,
+            client,
+            state: State::Shared(SharedState::new(state_file)?),
+        })
+    }
 }
 
 impl DiskIo for DiskWithReservations {
@@ -124,13 +217,14 @@ fn is_read_only(&self) -> bool {
         self.inner.is_read_only()
     }
 
-    fn unmap(
+    async fn unmap(
         &self,
         sector: u64,
         count: u64,
         block_level_only: bool,
-    ) -> impl Future<Output = Result<(), DiskError>> + Send {
-        self.inner.unmap(sector, count, block_level_only)
+    ) -> Result<(), DiskError> {
+        self.state.check_io(self.client, true).await?;
+        self.inner.unmap(sector, count, block_level_only).await
     }
 
     fn unmap_behavior(&self) -> disk_backend::UnmapBehavior {
@@ -150,6 +244,7 @@ async fn read_vectored(
         buffers: &RequestBuffers<'_>,
         sector: u64,
     ) -> Result<(), DiskError> {
+        self.state.check_io(self.client, false).await?;
         self.inner.read_vectored(buffers, sector).await
     }
 
@@ -159,6 +254,7 @@ async fn write_vectored(
         sector: u64,
         fua: bool,
     ) -> Result<(), DiskError> {
+        self.state.check_io(self.client, true).await?;
         self.inner.write_vectored(buffers, sector, fua).await
     }
 
@@ -175,31 +271,15 @@ fn capabilities(&self) -> pr::ReservationCapabilities {
             exclusive_access: true,
             write_exclusive_registrants_only: true,
             exclusive_access_registrants_only: true,
-            write_exclusive_all_registrants: false,
-            exclusive_access_all_registrants: false,
+            write_exclusive_all_registrants: true,
+            exclusive_access_all_registrants: true,
             persist_through_power_loss: true,
         }
     }
 
     async fn report(&self) -> Result<pr::ReservationReport, DiskError> {
         tracing::info!("reading full status");
-        let state = self.state.lock();
-        let report = pr::ReservationReport {
-            generation: state.generation.0,
-            reservation_type: state.reservation_type,
-            persist_through_power_loss: state.persist_through_power_loss,
-            controllers: state
-                .registered_key
-                .iter()
-                .map(|&key| pr::RegisteredController {
-                    key: key.get(),
-                    host_id: vec![0; 8],
-                    controller_id: 0,
-                    holds_reservation: state.reservation_type.is_some(),
-                })
-                .collect(),
-        };
-        Ok(report)
+        self.state.read_with(|state| state.report()).await
     }
 
     async fn register(
@@ -208,61 +288,31 @@ async fn register(
         new_key: u64,
         ptpl: Option<bool>,
     ) -> Result<(), DiskError> {
-        let mut state = self.state.lock();
-        if let Some(current_key) = current_key {
-            if state.registered_key != NonZeroU64::new(current_key) {
-                return Err(DiskError::ReservationConflict);
-            }
-        }
-        let new_key = NonZeroU64::new(new_key);
-        state.registered_key = new_key;
-        if new_key.is_none() {
-            state.reservation_type = None;
-        }
-        if let Some(ptpl) = ptpl {
-            state.persist_through_power_loss = ptpl;
-        }
-        state.generation += 1;
-        Ok(())
+        let client = self.client;
+        self.state
+            .update(move |state| state.register(client, current_key, new_key, ptpl))
+            .await
     }
 
     async fn reserve(&self, key: u64, reservation_type: ReservationType) -> Result<(), DiskError> {
-        let mut state = self.state.lock();
-        if state.registered_key.is_none()
-            || state.registered_key != NonZeroU64::new(key)
-            || (state.reservation_type.is_some()
-                && state.reservation_type != Some(reservation_type))
-        {
-            return Err(DiskError::ReservationConflict);
-        }
-        state.reservation_type = Some(reservation_type);
-        Ok(())
+        let client = self.client;
+        self.state
+            .update(move |state| state.reserve(client, key, reservation_type))
+            .await
     }
 
     async fn release(&self, key: u64, reservation_type: ReservationType) -> Result<(), DiskError> {
-        let mut state = self.state.lock();
-        if state.registered_key.is_none() || state.registered_key != NonZeroU64::new(key) {
-            return Err(DiskError::ReservationConflict);
-        }
-
-        if state.reservation_type.is_some() {
-            if state.reservation_type != Some(reservation_type) {
-                return Err(DiskError::InvalidInput);
-            }
-            state.reservation_type = None;
-        }
-        Ok(())
+        let client = self.client;
+        self.state
+            .update(move |state| state.release(client, key, reservation_type))
+            .await
     }
 
     async fn clear(&self, key: u64) -> Result<(), DiskError> {
-        let mut state = self.state.lock();
-        if state.registered_key.is_none() || state.registered_key != NonZeroU64::new(key) {
-            return Err(DiskError::ReservationConflict);
-        }
-        state.registered_key = None;
-        state.reservation_type = None;
-        state.generation += 1;
-        Ok(())
+        let client = self.client;
+        self.state
+            .update(move |state| state.clear(client, key))
+            .await
     }
 
     async fn preempt(
@@ -272,19 +322,9 @@ async fn preempt(
         reservation_type: ReservationType,
         _abort: bool,
     ) -> Result<(), DiskError> {
-        let mut state = self.state.lock();
-        if state.registered_key.is_none() || state.registered_key != NonZeroU64::new(current_key) {
-            return Err(DiskError::ReservationConflict);
-        }
-        if state.registered_key != NonZeroU64::new(preempt_key)
-            || (state.reservation_type.is_some()
-                && state.reservation_type != Some(reservation_type))
-        {
-            return Err(DiskError::InvalidInput);
-        }
-
-        state.reservation_type = None;
-        state.generation += 1;
-        Ok(())
+        let client = self.client;
+        self.state
+            .update(move |state| state.preempt(client, current_key, preempt_key, reservation_type))
+            .await
     }
 }
@@ -0,0 +1,133 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Advisory whole-file locks, used to serialize access to shared reservation
+//! state across processes.
+
+// UNSAFETY: Calling platform file locking APIs.
+#![allow(unsafe_code)]
+
+use std::fs;
+use std::io;
+
+/// A held lock on a file, released on drop.
+pub(crate) struct FileLock<'a>(&'a fs::File);
+
+impl<'a> FileLock<'a> {
+    /// Waits for and acquires a shared lock on `file`.
+    pub fn shared(file: &'a fs::File) -> io::Result<Self> {
+        sys::lock(file, false)?;
+        Ok(Self(file))
+    }
+
+    /// Waits for and acquires an exclusive lock on `file`.
+    pub fn exclusive(file: &'a fs::File) -> io::Result<Self> {
+        sys::lock(file, true)?;
+        Ok(Self(file))
+    }
+}
+
+impl Drop for FileLock<'_> {
+    fn drop(&mut self) {
+        if let Err(err) = sys::unlock(self.0) {
+            tracing::warn!(
+                error = &err as &dyn std::error::Error,
+                "failed to unlock reservation state file"
+            );
+        }
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs;
+    use std::io;
+    use std::os::unix::prelude::*;
+
+    fn flock(file: &fs::File, op: i32) -> io::Result<()> {
+        loop {
+            // SAFETY: flock has no memory safety requirements.
+            if unsafe { libc::flock(file.as_raw_fd(), op) } == 0 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    pub fn lock(file: &fs::File, exclusive: bool) -> io::Result<()> {
+        flock(
+            file,
+            if exclusive {
+                libc::LOCK_EX
+            } else {
+                libc::LOCK_SH
+            },
+        )
+    }
+
+    pub fn unlock(file: &fs::File) -> io::Result<()> {
+        flock(file, libc::LOCK_UN)
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::fs;
+    use std::io;
+    use std::os::windows::prelude::*;
+    use windows_sys::Win32::Storage::FileSystem::LockFileEx;
+    use windows_sys::Win32::Storage::FileSystem::UnlockFileEx;
+    use windows_sys::Win32::Storage::FileSystem::LOCKFILE_EXCLUSIVE_LOCK;
+    use windows_sys::Win32::System::IO::OVERLAPPED;
+
+    pub fn lock(file: &fs::File, exclusive: bool) -> io::Result<()> {
+        let flags = if exclusive {
+            LOCKFILE_EXCLUSIVE_LOCK
+        } else {
+            0
+        };
+        // SAFETY: OVERLAPPED is a plain C struct, for which all zeroes is a
+        // valid value, meaning offset zero.
+        let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+        // SAFETY: the handle is valid, and the file is opened for synchronous
+        // IO, so the call completes before `overlapped` goes out of scope.
+        let r = unsafe {
+            LockFileEx(
+                file.as_raw_handle() as _,
+                flags,
+                0,
+                u32::MAX,
+                u32::MAX,
+                &mut overlapped,
+            )
+        };
+        if r == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn unlock(file: &fs::File) -> io::Result<()> {
+        // SAFETY: OVERLAPPED is a plain C struct, for which all zeroes is a
+        // valid value, meaning offset zero.
+        let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+        // SAFETY: the handle is valid, and the file is opened for synchronous
+        // IO, so the call completes before `overlapped` goes out of scope.
+        let r = unsafe {
+            UnlockFileEx(
+                file.as_raw_handle() as _,
+                0,
+                u32::MAX,
+                u32::MAX,
+                &mut overlapped,
+            )
+        };
+        if r == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Reservation state stored in a file, so that it can be shared between
+//! processes.
+
+use crate::lock::FileLock;
+use crate::state::ReservationState;
+use crate::state::HEADER_LEN;
+use blocking::unblock;
+use disk_backend::DiskError;
+use parking_lot::Mutex;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::sync::Arc;
+
+/// Reservation state stored in a file.
+///
+/// Each access takes an advisory lock on the file, so multiple processes
+/// (each running a VM that has the disk attached) can safely share it. The
+/// state is cached in memory and only reread when the sequence number in the
+/// file's header shows that another client changed it.
+pub(crate) struct SharedState {
+    // The mutex serializes use of the file position within this process.
+    file: Arc<Mutex<fs::File>>,
+    cache: Mutex<Cached>,
+}
+
+struct Cached {
+    sequence: u64,
+    state: ReservationState,
+}
+
+impl SharedState {
+    /// Opens the shared state in `file`, which may be empty.
+    pub fn new(file: fs::File) -> io::Result<Self> {
+        let (sequence, state) = {
+            let _lock = FileLock::shared(&file)?;
+            read_locked(&file)?
+        };
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            cache: Mutex::new(Cached { sequence, state }),
+        })
+    }
+
+    /// Returns the most recently read state, without accessing the file.
+    pub fn cached(&self) -> ReservationState {
+        self.cache.lock().state.clone()
+    }
+
+    /// Calls `f` with the current state, rereading it from the file first if
+    /// it has changed.
+    pub async fn read_with<R>(&self, f: impl FnOnce(&ReservationState) -> R) -> io::Result<R> {
+        let file = self.file.clone();
+        let sequence = self.cache.lock().sequence;
+        let changed = unblock(move || {
+            let file = file.lock();
+            let _lock = FileLock::shared(&file)?;
+            if read_sequence(&file)? == sequence {
+                return Ok(None);
+            }
+            read_locked(&file).map(Some)
+        })
+        .await?;
+
+        let mut cache = self.cache.lock();
+        if let Some((sequence, state)) = changed {
+            *cache = Cached { sequence, state };
+        }
+        Ok(f(&cache.state))
+    }
+
+    /// Updates the state with `f`, writing it back to the file if it was
+    /// changed.
+    pub async fn update<R: 'static + Send>(
+        &self,
+        f: impl 'static + Send + FnOnce(&mut ReservationState) -> Result<R, DiskError>,
+    ) -> Result<R, DiskError> {
+        let file = self.file.clone();
+        let (r, sequence, state) = unblock(move || {
+            let file = file.lock();
+            let _lock = FileLock::exclusive(&file).map_err(DiskError::Io)?;
+            let (mut sequence, mut state) = read_locked(&file).map_err(DiskError::Io)?;
+            let old_state = state.clone();
+            let r = f(&mut state)?;
+            if state != old_state {
+                sequence = sequence.wrapping_add(1);
+                write_locked(&file, sequence, &state).map_err(DiskError::Io)?;
+            }
+            Ok::<_, DiskError>((r, sequence, state))
+        })
+        .await?;
+
+        *self.cache.lock() = Cached { sequence, state };
+        Ok(r)
+    }
+}
+
+fn read_sequence(mut file: &fs::File) -> io::Result<u64> {
+    let mut data = Vec::with_capacity(HEADER_LEN);
+    file.rewind()?;
+    file.take(HEADER_LEN as u64).read_to_end(&mut data)?;
+    ReservationState::sequence_from_bytes(&data)
+}
+
+fn read_locked(mut file: &fs::File) -> io::Result<(u64, ReservationState)> {
+    let mut data = Vec::new();
+    file.rewind()?;
+    file.read_to_end(&mut data)?;
+    ReservationState::from_bytes(&data)
+}
+
+fn write_locked(mut file: &fs::File, sequence: u64, state: &ReservationState) -> io::Result<()> {
+    let data = state.to_bytes(sequence);
+    file.rewind()?;
+    file.write_all(&data)?;
+    file.set_len(data.len() as u64)?;
+    file.sync_data()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedState;
+    use disk_backend::pr::ReservationType;
+    use disk_backend::DiskError;
+    use pal_async::async_test;
+    use std::fs;
+
+    #[async_test]
+    async fn shared_between_instances() {
+        let path = tempfile::NamedTempFile::new().unwrap();
+        let open = || {
+            SharedState::new(
+                fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open(path.path())
+                    .unwrap(),
+            )
+            .unwrap()
+        };
+        let a = open();
+        let b = open();
+
+        a.update(|s| s.register(1, None, 0x11, None)).await.unwrap();
+        a.update(|s| s.reserve(1, 0x11, ReservationType::ExclusiveAccess))
+            .await
+            .unwrap();
+
+        // The other instance sees the new reservation.
+        assert!(matches!(
+            b.read_with(|s| s.check_io(2, false)).await.unwrap(),
+            Err(DiskError::ReservationConflict)
+        ));
+
+        // Preempting removes the first instance's registration and
+        // reservation.
+        b.update(|s| s.register(2, None, 0x22, None)).await.unwrap();
+        b.update(|s| s.preempt(2, 0x22, 0x11, ReservationType::WriteExclusive))
+            .await
+            .unwrap();
+        a.read_with(|s| s.check_io(1, false))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            a.read_with(|s| s.check_io(1, true)).await.unwrap(),
+            Err(DiskError::ReservationConflict)
+        ));
+        assert_eq!(a.cached().report().controllers.len(), 1);
+    }
+}
@@ -0,0 +1,519 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Reservation state and the rules for updating it.
+
+use disk_backend::pr;
+use disk_backend::pr::ReservationType;
+use disk_backend::DiskError;
+use inspect::Inspect;
+use std::io;
+use std::num::NonZeroU64;
+use std::num::Wrapping;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+/// The registrations and reservation for a disk, across all of its clients.
+///
+/// Each client is identified by an opaque ID, which plays the role of the
+/// SCSI I_T nexus.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Inspect)]
+pub(crate) struct ReservationState {
+    generation: Wrapping<u32>,
+    persist_through_power_loss: bool,
+    #[inspect(iter_by_index)]
+    registrations: Vec<Registration>,
+    reservation: Option<Reservation>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Inspect)]
+struct Registration {
+    #[inspect(hex)]
+    client: u64,
+    key: NonZeroU64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Inspect)]
+struct Reservation {
+    #[inspect(hex)]
+    client: u64,
+    reservation_type: ReservationType,
+}
+
+fn is_all_registrants(reservation_type: ReservationType) -> bool {
+    matches!(
+        reservation_type,
+        ReservationType::WriteExclusiveAllRegistrants
+            | ReservationType::ExclusiveAccessAllRegistrants
+    )
+}
+
+impl ReservationState {
+    fn key(&self, client: u64) -> Option<NonZeroU64> {
+        self.registrations
+            .iter()
+            .find(|r| r.client == client)
+            .map(|r| r.key)
+    }
+
+    /// Fails unless `client` is registered with `key`.
+    fn check_key(&self, client: u64, key: u64) -> Result<(), DiskError> {
+        match self.key(client) {
+            Some(k) if k.get() == key => Ok(()),
+            _ => Err(DiskError::ReservationConflict),
+        }
+    }
+
+    fn holds_reservation(&self, client: u64) -> bool {
+        match self.reservation {
+            Some(r) if is_all_registrants(r.reservation_type) => self.key(client).is_some(),
+            Some(r) => r.client == client,
+            None => false,
+        }
+    }
+
+    /// Fails if the reservation prevents `client` from reading or writing.
+    pub fn check_io(&self, client: u64, write: bool) -> Result<(), DiskError> {
+        let Some(reservation) = self.reservation else {
+            return Ok(());
+        };
+        let allowed = match reservation.reservation_type {
+            ReservationType::WriteExclusive => !write || reservation.client == client,
+            ReservationType::ExclusiveAccess => reservation.client == client,
+            ReservationType::WriteExclusiveRegistrantsOnly
+            | ReservationType::WriteExclusiveAllRegistrants => !write || self.key(client).is_some(),
+            ReservationType::ExclusiveAccessRegistrantsOnly
+            | ReservationType::ExclusiveAccessAllRegistrants => self.key(client).is_some(),
+        };
+        if allowed {
+            Ok(())
+        } else {
+            Err(DiskError::ReservationConflict)
+        }
+    }
+
+    pub fn report(&self) -> pr::ReservationReport {
+        pr::ReservationReport {
+            generation: self.generation.0,
+            reservation_type: self.reservation.map(|r| r.reservation_type),
+            persist_through_power_loss: self.persist_through_power_loss,
+            controllers: self
+                .registrations
+                .iter()
+                .map(|r| pr::RegisteredController {
+                    key: r.key.get(),
+                    host_id: r.client.to_le_bytes().to_vec(),
+                    controller_id: 0,
+                    holds_reservation: self.holds_reservation(r.client),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn register(
+        &mut self,
+        client: u64,
+        current_key: Option<u64>,
+        new_key: u64,
+        ptpl: Option<bool>,
+    ) -> Result<(), DiskError> {
+        if let Some(current_key) = current_key {
+            if self.key(client) != NonZeroU64::new(current_key) {
+                return Err(DiskError::ReservationConflict);
+            }
+        }
+        match NonZeroU64::new(new_key) {
+            Some(key) => match self.registrations.iter_mut().find(|r| r.client == client) {
+                Some(r) => r.key = key,
+                None => self.registrations.push(Registration { client, key }),
+            },
+            None => {
+                let holds_reservation = self.holds_reservation(client);
+                self.registrations.retain(|r| r.client != client);
+                // An all registrants reservation lasts until the last
+                // registrant goes away. Other reservations are released when
+                // the holder unregisters.
+                if holds_reservation
+                    && (self.registrations.is_empty()
+                        || !is_all_registrants(self.reservation.unwrap().reservation_type))
+                {
+                    self.reservation = None;
+                }
+            }
+        }
+        if let Some(ptpl) = ptpl {
+            self.persist_through_power_loss = ptpl;
+        }
+        self.generation += 1;
+        Ok(())
+    }
+
+    pub fn reserve(
+        &mut self,
+        client: u64,
+        key: u64,
+        reservation_type: ReservationType,
+    ) -> Result<(), DiskError> {
+        self.check_key(client, key)?;
+        match self.reservation {
+            None => {
+                self.reservation = Some(Reservation {
+                    client,
+                    reservation_type,
+                });
+                Ok(())
+            }
+            Some(r) if self.holds_reservation(client) && r.reservation_type == reservation_type => {
+                Ok(())
+            }
+            Some(_) => Err(DiskError::ReservationConflict),
+        }
+    }
+
+    pub fn release(
+        &mut self,
+        client: u64,
+        key: u64,
+        reservation_type: ReservationType,
+    ) -> Result<(), DiskError> {
+        self.check_key(client, key)?;
+        match self.reservation {
+            Some(r) if self.holds_reservation(client) => {
+                if r.reservation_type != reservation_type {
+                    return Err(DiskError::InvalidInput);
+                }
+                self.reservation = None;
+                Ok(())
+            }
+            // Releasing when not holding the reservation is a no-op.
+            _ => Ok(()),
+        }
+    }
+
+    pub fn clear(&mut self, client: u64, key: u64) -> Result<(), DiskError> {
+        self.check_key(client, key)?;
+        self.registrations.clear();
+        self.reservation = None;
+        self.generation += 1;
+        Ok(())
+    }
+
+    pub fn preempt(
+        &mut self,
+        client: u64,
+        current_key: u64,
+        preempt_key: u64,
+        reservation_type: ReservationType,
+    ) -> Result<(), DiskError> {
+        self.check_key(client, current_key)?;
+        let preempt_key = NonZeroU64::new(preempt_key);
+        // Determine whether this preempts the reservation, or only the
+        // registrations with `preempt_key`.
+        let preempts_reservation = match (self.reservation, preempt_key) {
+            // A zero key preempts an all registrants reservation, along with
+            // all other registrations.
+            (Some(r), None) if is_all_registrants(r.reservation_type) => true,
+            (Some(r), Some(key)) if !is_all_registrants(r.reservation_type) => {
+                self.key(r.client) == Some(key)
+            }
+            (_, None) => return Err(DiskError::InvalidInput),
+            (_, Some(key)) => {
+                if !self
+                    .registrations
+                    .iter()
+                    .any(|r| r.client != client && r.key == key)
+                {
+                    return Err(DiskError::ReservationConflict);
+                }
+                false
+            }
+        };
+        self.registrations
+            .retain(|r| r.client == client || preempt_key.is_some_and(|k| r.key != k));
+        if preempts_reservation {
+            self.reservation = Some(Reservation {
+                client,
+                reservation_type,
+            });
+        }
+        self.generation += 1;
+        Ok(())
+    }
+}
+
+/// "OVMMPR01"
+const STATE_MAGIC: u64 = u64::from_le_bytes(*b"OVMMPR01");
+const FLAG_PTPL: u32 = 1 << 0;
+const FLAG_RESERVED: u32 = 1 << 1;
+
+/// The on-disk format of the state, followed by `registration_count`
+/// [`FileRegistration`] entries.
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+struct FileHeader {
+    magic: u64,
+    generation: u32,
+    flags: u32,
+    /// Incremented each time the state is written, so that clients can tell
+    /// when their cached copy is stale.
+    sequence: u64,
+    reservation_client: u64,
+    reservation_type: u32,
+    registration_count: u32,
+}
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+struct FileRegistration {
+    client: u64,
+    key: u64,
+}
+
+/// The length of the file header, which is enough to read the sequence number.
+pub(crate) const HEADER_LEN: usize = size_of::<FileHeader>();
+
+const RESERVATION_TYPES: [ReservationType; 6] = [
+    ReservationType::WriteExclusive,
+    ReservationType::ExclusiveAccess,
+    ReservationType::WriteExclusiveRegistrantsOnly,
+    ReservationType::ExclusiveAccessRegistrantsOnly,
+    ReservationType::WriteExclusiveAllRegistrants,
+    ReservationType::ExclusiveAccessAllRegistrants,
+];
+
+impl ReservationState {
+    /// Serializes the state for storing in a file, with the given sequence
+    /// number.
+    pub fn to_bytes(&self, sequence: u64) -> Vec<u8> {
+        let mut flags = 0;
+        if self.persist_through_power_loss {
+            flags |= FLAG_PTPL;
+        }
+        let (reservation_client, reservation_type) = match self.reservation {
+            Some(r) => {
+                flags |= FLAG_RESERVED;
+                let index = RESERVATION_TYPES
+                    .iter()
+                    .position(|&t| t == r.reservation_type)
+                    .unwrap();
+                (r.client, index as u32)
+            }
+            None => (0, 0),
+        };
+        let header = FileHeader {
+            magic: STATE_MAGIC,
+            generation: self.generation.0,
+            flags,
+            sequence,
+            reservation_client,
+            reservation_type,
+            registration_count: self.registrations.len() as u32,
+        };
+        let mut data = header.as_bytes().to_vec();
+        for r in &self.registrations {
+            data.extend_from_slice(
+                FileRegistration {
+                    client: r.client,
+                    key: r.key.get(),
+                }
+                .as_bytes(),
+            );
+        }
+        data
+    }
+
+    /// Returns the sequence number from the first [`HEADER_LEN`] bytes of a
+    /// file. An empty file has sequence number zero.
+    pub fn sequence_from_bytes(data: &[u8]) -> io::Result<u64> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+        Ok(read_header(data)?.sequence)
+    }
+
+    /// Deserializes the state and its sequence number from a file. An empty
+    /// file is treated as the initial state.
+    pub fn from_bytes(data: &[u8]) -> io::Result<(u64, Self)> {
+        if data.is_empty() {
+            return Ok((0, Self::default()));
+        }
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid reservation state");
+        let header = read_header(data)?;
+        let registrations = data[size_of::<FileHeader>()..]
+            .chunks_exact(size_of::<FileRegistration>())
+            .take(header.registration_count as usize)
+            .map(|entry| {
+                let entry = FileRegistration::read_from(entry).unwrap();
+                Some(Registration {
+                    client: entry.client,
+                    key: NonZeroU64::new(entry.key)?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(invalid)?;
+        if registrations.len() != header.registration_count as usize {
+            return Err(invalid());
+        }
+        let reservation = if header.flags & FLAG_RESERVED != 0 {
+            Some(Reservation {
+                client: header.reservation_client,
+                reservation_type: *RESERVATION_TYPES
+                    .get(header.reservation_type as usize)
+                    .ok_or_else(invalid)?,
+            })
+        } else {
+            None
+        };
+        let state = Self {
+            generation: Wrapping(header.generation),
+            persist_through_power_loss: header.flags & FLAG_PTPL != 0,
+            registrations,
+            reservation,
+        };
+        Ok((header.sequence, state))
+    }
+}
+
+fn read_header(data: &[u8]) -> io::Result<FileHeader> {
+    FileHeader::read_from_prefix(data)
+        .filter(|header| header.magic == STATE_MAGIC)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid reservation state"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReservationState;
+    use disk_backend::pr::ReservationType;
+    use disk_backend::DiskError;
+
+    #[test]
+    fn write_exclusive_preempt() {
+        let mut state = ReservationState::default();
+        state.register(1, None, 0x11, None).unwrap();
+        state.register(2, None, 0x22, None).unwrap();
+        state
+            .reserve(1, 0x11, ReservationType::WriteExclusive)
+            .unwrap();
+
+        state.check_io(1, true).unwrap();
+        state.check_io(2, false).unwrap();
+        assert!(matches!(
+            state.check_io(2, true),
+            Err(DiskError::ReservationConflict)
+        ));
+        assert!(matches!(
+            state.reserve(2, 0x22, ReservationType::WriteExclusive),
+            Err(DiskError::ReservationConflict)
+        ));
+
+        // Client 2 takes over, removing client 1's registration.
+        state
+            .preempt(2, 0x22, 0x11, ReservationType::ExclusiveAccess)
+            .unwrap();
+        let report = state.report();
+        assert_eq!(report.controllers.len(), 1);
+        assert_eq!(
+            report.reservation_type,
+            Some(ReservationType::ExclusiveAccess)
+        );
+        assert!(matches!(
+            state.check_io(1, false),
+            Err(DiskError::ReservationConflict)
+        ));
+        state.check_io(2, true).unwrap();
+
+        let round_trip = ReservationState::from_bytes(&state.to_bytes(5)).unwrap();
+        assert_eq!(round_trip, (5, state));
+    }
+
+    #[test]
+    fn preempt_registrations_only() {
+        let mut state = ReservationState::default();
+        state.register(1, None, 0x11, None).unwrap();
+        state.register(2, None, 0x22, None).unwrap();
+        state.register(3, None, 0x33, None).unwrap();
+        state
+            .reserve(1, 0x11, ReservationType::WriteExclusiveRegistrantsOnly)
+            .unwrap();
+
+        // Preempting a key that doesn't hold the reservation removes only
+        // its registration.
+        state
+            .preempt(2, 0x22, 0x33, ReservationType::ExclusiveAccess)
+            .unwrap();
+        let report = state.report();
+        assert_eq!(report.controllers.len(), 2);
+        assert_eq!(
+            report.reservation_type,
+            Some(ReservationType::WriteExclusiveRegistrantsOnly)
+        );
+        assert!(report.controllers[0].holds_reservation);
+        assert!(state.check_io(3, true).is_err());
+
+        // Unknown keys conflict, and zero keys are invalid.
+        assert!(matches!(
+            state.preempt(2, 0x22, 0x44, ReservationType::ExclusiveAccess),
+            Err(DiskError::ReservationConflict)
+        ));
+        assert!(matches!(
+            state.preempt(2, 0x22, 0, ReservationType::ExclusiveAccess),
+            Err(DiskError::InvalidInput)
+        ));
+    }
+
+    #[test]
+    fn preempt_all_registrants() {
+        let mut state = ReservationState::default();
+        state.register(1, None, 0x11, None).unwrap();
+        state.register(2, None, 0x22, None).unwrap();
+        state.register(3, None, 0x33, None).unwrap();
+        state
+            .reserve(1, 0x11, ReservationType::WriteExclusiveAllRegistrants)
+            .unwrap();
+
+        // A non-zero key only removes that registration.
+        state
+            .preempt(2, 0x22, 0x33, ReservationType::ExclusiveAccess)
+            .unwrap();
+        let report = state.report();
+        assert_eq!(report.controllers.len(), 2);
+        assert_eq!(
+            report.reservation_type,
+            Some(ReservationType::WriteExclusiveAllRegistrants)
+        );
+
+        // A zero key removes all other registrations and takes over the
+        // reservation.
+        state
+            .preempt(2, 0x22, 0, ReservationType::ExclusiveAccess)
+            .unwrap();
+        let report = state.report();
+        assert_eq!(report.controllers.len(), 1);
+        assert_eq!(report.controllers[0].key, 0x22);
+        assert!(report.controllers[0].holds_reservation);
+        assert_eq!(
+            report.reservation_type,
+            Some(ReservationType::ExclusiveAccess)
+        );
+        assert!(state.check_io(1, false).is_err());
+    }
+
+    #[test]
+    fn registrants_only() {
+        let mut state = ReservationState::default();
+        state.register(1, None, 0x11, None).unwrap();
+        state
+            .reserve(1, 0x11, ReservationType::ExclusiveAccessAllRegistrants)
+            .unwrap();
+        assert!(state.check_io(2, false).is_err());
+        state.register(2, Some(0), 0x22, None).unwrap();
+        state.check_io(2, true).unwrap();
+
+        // The reservation lasts until the last registrant is gone.
+        state.register(1, Some(0x11), 0, None).unwrap();
+        assert!(state.report().reservation_type.is_some());
+        state.register(2, Some(0x22), 0, None).unwrap();
+        assert!(state.report().reservation_type.is_none());
+    }
+}
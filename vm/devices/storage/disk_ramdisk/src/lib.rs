@@ -6,6 +6,7 @@
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 
+mod persist;
 pub mod resolver;
 
 use anyhow::Context;
@@ -21,14 +22,24 @@
 use guestmem::MemoryRead;
 use guestmem::MemoryWrite;
 use inspect::Inspect;
+use parking_lot::Mutex;
 use parking_lot::RwLock;
+use persist::SnapshotData;
+use persist::SnapshotFile;
 use scsi_buffers::RequestBuffers;
 use std::collections::btree_map::Entry;
 use std::collections::BTreeMap;
 use std::fmt;
 use std::fmt::Debug;
+use std::fs::File;
+use std::io;
+use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
 use thiserror::Error;
 
 /// A disk backed entirely by RAM.
@@ -36,11 +47,12 @@
 #[inspect(extra = "Self::inspect_extra")]
 pub struct RamLayer {
     #[inspect(flatten)]
-    state: RwLock<RamState>,
+    state: Arc<RwLock<RamState>>,
     #[inspect(skip)]
     sector_count: AtomicU64,
     #[inspect(skip)]
     resize_event: event_listener::Event,
+    persistence: Option<Persistence>,
 }
 
 #[derive(Inspect)]
@@ -88,6 +100,24 @@ pub enum Error {
     /// The disk has no sectors.
     #[error("disk has no sectors")]
     EmptyDisk,
+    /// The backing file could not be read, or it does not contain a valid
+    /// snapshot.
+    #[error("failed to load ram disk from backing file")]
+    Load(#[source] io::Error),
+    /// The size of the snapshot in the backing file does not match the
+    /// requested disk size.
+    #[error(
+        "backing file disk size {snapshot_size:#x} does not match requested size {disk_size:#x}"
+    )]
+    SizeMismatch {
+        /// The disk size stored in the backing file.
+        snapshot_size: u64,
+        /// The requested disk size.
+        disk_size: u64,
+    },
+    /// The flush thread could not be started.
+    #[error("failed to start ram disk flush thread")]
+    Thread(#[source] io::Error),
 }
 
 struct Sector([u8; 512]);
@@ -115,16 +145,102 @@ pub fn new(size: Option<u64>) -> Result<Self, Error> {
             0
         };
         Ok(Self {
-            state: RwLock::new(RamState {
+            state: Arc::new(RwLock::new(RamState {
                 data: BTreeMap::new(),
                 sector_count,
                 zero_after: sector_count,
-            }),
+            })),
             sector_count: sector_count.into(),
             resize_event: Default::default(),
+            persistence: None,
         })
     }
 
+    /// Makes a new RAM disk of `size` bytes whose contents are loaded from
+    /// and persisted to `file`.
+    ///
+    /// If `file` is empty, the disk starts out empty, as with [`Self::new`].
+    /// Otherwise, the disk is loaded from the most recent snapshot in the file,
+    /// and `size`, if specified, must match the size of the snapshot.
+    ///
+    /// A new snapshot is written every `flush_interval` if the disk contents
+    /// have changed, and again when the layer is dropped. Each snapshot is
+    /// crash consistent: if the process exits while writing one, the previous
+    /// snapshot is loaded next time.
+    pub fn with_backing_file(
+        size: Option<u64>,
+        file: File,
+        flush_interval: Option<Duration>,
+    ) -> Result<Self, Error> {
+        let mut this = Self::new(size)?;
+        let (snapshot_file, snapshot) = SnapshotFile::open(file).map_err(Error::Load)?;
+        if let Some(snapshot) = snapshot {
+            let disk_size = snapshot.sector_count * SECTOR_SIZE as u64;
+            if let Some(size) = size {
+                if size != disk_size {
+                    return Err(Error::SizeMismatch {
+                        snapshot_size: disk_size,
+                        disk_size: size,
+                    });
+                }
+            }
+            tracing::info!(
+                disk_size,
+                committed_sectors = snapshot.data.len(),
+                "loaded ram disk from backing file"
+            );
+            *this.sector_count.get_mut() = snapshot.sector_count;
+            *this.state.write() = RamState {
+                data: snapshot.data,
+                sector_count: snapshot.sector_count,
+                zero_after: snapshot.zero_after,
+            };
+        }
+
+        let flusher = Arc::new(Flusher {
+            state: this.state.clone(),
+            file: Mutex::new(snapshot_file),
+            dirty: AtomicBool::new(false),
+            flush_count: AtomicU64::new(0),
+            failed_flush_count: AtomicU64::new(0),
+        });
+        let (shutdown, thread) = if let Some(flush_interval) = flush_interval {
+            let (send, recv) = mpsc::channel::<()>();
+            let thread = std::thread::Builder::new()
+                .name("ramdisk-flush".into())
+                .spawn({
+                    let flusher = flusher.clone();
+                    move || {
+                        // Flush until the sender is dropped.
+                        while let Err(mpsc::RecvTimeoutError::Timeout) =
+                            recv.recv_timeout(flush_interval)
+                        {
+                            flusher.flush();
+                        }
+                    }
+                })
+                .map_err(Error::Thread)?;
+            (Some(send), Some(thread))
+        } else {
+            (None, None)
+        };
+        this.persistence = Some(Persistence {
+            flusher,
+            flush_interval,
+            shutdown,
+            thread,
+        });
+        Ok(this)
+    }
+
+    /// Marks the disk contents as changed since the last snapshot. Must be
+    /// called with the state lock held for write.
+    fn set_dirty(&self) {
+        if let Some(persistence) = &self.persistence {
+            persistence.flusher.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+
     fn resize(&self, new_sector_count: u64) -> anyhow::Result<()> {
         if new_sector_count == 0 {
             anyhow::bail!("invalid sector count");
@@ -140,6 +256,7 @@ fn resize(&self, new_sector_count: u64) -> anyhow::Result<()> {
             // FUTURE: remove uses of .sector_count() in the IO path,
             // eliminating the need for this.
             self.sector_count.store(new_sector_count, Ordering::Relaxed);
+            self.set_dirty();
             state.data.split_off(&new_sector_count)
         };
         self.resize_event.notify(usize::MAX);
@@ -158,6 +275,7 @@ fn write_maybe_overwrite(
         if sector + count as u64 > state.sector_count {
             return Err(DiskError::IllegalBlock);
         }
+        self.set_dirty();
         for i in 0..count {
             let cur = i + sector as usize;
             let buf = buffers.subrange(i * SECTOR_SIZE as usize, SECTOR_SIZE as usize);
@@ -289,6 +407,7 @@ async fn unmap(
         if sector_offset + sector_count > state.sector_count {
             return Err(DiskError::IllegalBlock);
         }
+        self.set_dirty();
         if !next_is_zero {
             // This would create a hole of zeroes, which we cannot represent in
             // the tree. Ignore the unmap.
@@ -338,6 +457,67 @@ fn on_attach(&mut self, lower_sector_count: Option<u64>) {
     }
 }
 
+/// The backing file of a persistent RAM layer.
+#[derive(Inspect)]
+struct Persistence {
+    #[inspect(flatten)]
+    flusher: Arc<Flusher>,
+    #[inspect(debug)]
+    flush_interval: Option<Duration>,
+    #[inspect(skip)]
+    shutdown: Option<mpsc::Sender<()>>,
+    #[inspect(skip)]
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Persistence {
+    fn drop(&mut self) {
+        // Stop the flush thread and write a final snapshot.
+        drop(self.shutdown.take());
+        if let Some(thread) = self.thread.take() {
+            thread.join().unwrap();
+        }
+        self.flusher.flush();
+    }
+}
+
+#[derive(Inspect)]
+struct Flusher {
+    #[inspect(skip)]
+    state: Arc<RwLock<RamState>>,
+    #[inspect(skip)]
+    file: Mutex<SnapshotFile>,
+    dirty: AtomicBool,
+    flush_count: AtomicU64,
+    failed_flush_count: AtomicU64,
+}
+
+impl Flusher {
+    /// Writes a snapshot of the disk to the backing file, if the disk has
+    /// changed since the last snapshot.
+    fn flush(&self) {
+        let mut file = self.file.lock();
+        let snapshot = {
+            let state = self.state.read();
+            if !self.dirty.swap(false, Ordering::Relaxed) {
+                return;
+            }
+            SnapshotData::new(state.sector_count, state.zero_after, &state.data)
+        };
+        if let Err(err) = file.write(&snapshot) {
+            // Try again next time.
+            self.dirty.store(true, Ordering::Relaxed);
+            self.failed_flush_count.fetch_add(1, Ordering::Relaxed);
+            tracing::error!(
+                error = &err as &dyn std::error::Error,
+                "failed to write ram disk snapshot"
+            );
+            return;
+        }
+        self.flush_count.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
 impl WriteNoOverwrite for RamLayer {
     async fn write_no_overwrite(
         &self,
@@ -511,6 +691,40 @@ async fn test_resize() {
         }
     }
 
+    #[async_test]
+    async fn test_backing_file() {
+        const SIZE: usize = 64 * 1024;
+        const SECTORS: usize = SIZE / SECTOR_USIZE;
+
+        let guest_mem = GuestMemory::allocate(SIZE);
+        let file = tempfile::tempfile().unwrap();
+        {
+            let mut layer =
+                RamLayer::with_backing_file(Some(SIZE as u64), file.try_clone().unwrap(), None)
+                    .unwrap();
+            write_layer(&guest_mem, &mut layer, 0, SECTORS, 3).await;
+        }
+
+        // The size must match the persisted size.
+        RamLayer::with_backing_file(Some(SIZE as u64 * 2), file.try_clone().unwrap(), None)
+            .unwrap_err();
+
+        let layer = RamLayer::with_backing_file(None, file, None).unwrap();
+        assert_eq!(layer.sector_count(), SECTORS as u64);
+        let mut disk = LayeredDisk::new(
+            false,
+            vec![LayerConfiguration {
+                layer: DiskLayer::new(layer),
+                write_through: false,
+                read_cache: false,
+            }],
+        )
+        .unwrap();
+        guest_mem.write_at(0, &[0; SIZE]).unwrap();
+        read(&guest_mem, &mut disk, 0, SECTORS).await;
+        check(&guest_mem, 0, 0, SECTORS, 3);
+    }
+
     #[async_test]
     async fn test_unmap() {
         const SIZE: usize = 1024 * 1024;
@@ -0,0 +1,282 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Crash-consistent snapshots of a RAM layer's contents in a backing file.
+//!
+//! The file starts with two header slots, each describing a snapshot by its
+//! generation number and the location of its data. A new snapshot is written
+//! to a region of the file that does not overlap the current snapshot, and
+//! then made current by writing its header into the slot that does not hold
+//! the current snapshot. If the process crashes at any point during this
+//! sequence, the previous snapshot is still intact and is used on the next
+//! load.
+
+use super::Sector;
+use super::SECTOR_SIZE;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+const MAGIC: [u8; 8] = *b"OVMMRAM1";
+const HEADER_SLOT_SIZE: u64 = 4096;
+const DATA_START: u64 = 2 * HEADER_SLOT_SIZE;
+const ENTRY_SIZE: usize = size_of::<u64>() + SECTOR_SIZE as usize;
+
+#[repr(C)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+struct Header {
+    magic: [u8; 8],
+    generation: u64,
+    sector_count: u64,
+    zero_after: u64,
+    data_offset: u64,
+    entry_count: u64,
+    data_crc: u32,
+    /// The CRC of the preceding fields.
+    header_crc: u32,
+}
+
+impl Header {
+    fn compute_crc(&self) -> u32 {
+        crc32fast::hash(&self.as_bytes()[..std::mem::offset_of!(Self, header_crc)])
+    }
+
+    fn data_len(&self) -> u64 {
+        self.entry_count * ENTRY_SIZE as u64
+    }
+
+    fn data_end(&self) -> u64 {
+        self.data_offset + self.data_len()
+    }
+}
+
+/// The contents of a loaded snapshot.
+pub struct Snapshot {
+    pub sector_count: u64,
+    pub zero_after: u64,
+    pub data: BTreeMap<u64, Sector>,
+}
+
+/// A snapshot of the layer's contents, serialized and ready to be written.
+pub struct SnapshotData {
+    sector_count: u64,
+    zero_after: u64,
+    entry_count: u64,
+    buf: Vec<u8>,
+}
+
+impl SnapshotData {
+    pub fn new(sector_count: u64, zero_after: u64, data: &BTreeMap<u64, Sector>) -> Self {
+        let mut buf = Vec::with_capacity(data.len() * ENTRY_SIZE);
+        for (&sector, data) in data {
+            buf.extend_from_slice(sector.as_bytes());
+            buf.extend_from_slice(&data.0);
+        }
+        Self {
+            sector_count,
+            zero_after,
+            entry_count: data.len() as u64,
+            buf,
+        }
+    }
+}
+
+/// The backing file of a persistent RAM layer.
+pub struct SnapshotFile {
+    file: File,
+    /// The header slot and header of the current snapshot.
+    current: Option<(usize, Header)>,
+}
+
+impl SnapshotFile {
+    /// Opens the snapshot file, returning the most recent valid snapshot, if
+    /// there is one.
+    ///
+    /// Fails if the file is not empty but contains no valid snapshot.
+    pub fn open(mut file: File) -> io::Result<(Self, Option<Snapshot>)> {
+        let len = file.metadata()?.len();
+        let mut headers = Vec::new();
+        for slot in 0..2 {
+            let offset = slot as u64 * HEADER_SLOT_SIZE;
+            if len < offset + size_of::<Header>() as u64 {
+                continue;
+            }
+            let mut header = Header::new_zeroed();
+            file.seek(SeekFrom::Start(offset))?;
+            file.read_exact(header.as_bytes_mut())?;
+            if header.magic == MAGIC
+                && header.header_crc == header.compute_crc()
+                && header.data_offset >= DATA_START
+                && header.data_end() <= len
+            {
+                headers.push((slot, header));
+            }
+        }
+
+        // Try the newest snapshot first, falling back to the older one if the
+        // newest one's data is damaged.
+        headers.sort_by_key(|(_, header)| std::cmp::Reverse(header.generation));
+        for (slot, header) in headers {
+            let mut buf = vec![0; header.data_len() as usize];
+            file.seek(SeekFrom::Start(header.data_offset))?;
+            file.read_exact(&mut buf)?;
+            if crc32fast::hash(&buf) != header.data_crc {
+                tracing::warn!(
+                    slot,
+                    generation = header.generation,
+                    "ram disk snapshot data is corrupt"
+                );
+                continue;
+            }
+            let data = buf
+                .chunks_exact(ENTRY_SIZE)
+                .map(|entry| {
+                    let (sector, data) = entry.split_at(size_of::<u64>());
+                    (
+                        u64::read_from(sector).unwrap(),
+                        Sector(data.try_into().unwrap()),
+                    )
+                })
+                .collect();
+            let snapshot = Snapshot {
+                sector_count: header.sector_count,
+                zero_after: header.zero_after,
+                data,
+            };
+            return Ok((
+                Self {
+                    file,
+                    current: Some((slot, header)),
+                },
+                Some(snapshot),
+            ));
+        }
+
+        if len != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no valid ram disk snapshot",
+            ));
+        }
+        Ok((
+            Self {
+                file,
+                current: None,
+            },
+            None,
+        ))
+    }
+
+    /// Writes a new snapshot and makes it current.
+    pub fn write(&mut self, snapshot: &SnapshotData) -> io::Result<()> {
+        let len = snapshot.buf.len() as u64;
+        // Write the data to the start of the data region if it fits before
+        // the current snapshot's data, or after the current snapshot's data
+        // otherwise.
+        let (slot, generation, data_offset) = match &self.current {
+            None => (0, 1, DATA_START),
+            Some((slot, current)) => {
+                let data_offset = if DATA_START + len <= current.data_offset {
+                    DATA_START
+                } else {
+                    current.data_end().next_multiple_of(HEADER_SLOT_SIZE)
+                };
+                (1 - slot, current.generation + 1, data_offset)
+            }
+        };
+
+        self.file.seek(SeekFrom::Start(data_offset))?;
+        self.file.write_all(&snapshot.buf)?;
+        self.file.sync_data()?;
+
+        let mut header = Header {
+            magic: MAGIC,
+            generation,
+            sector_count: snapshot.sector_count,
+            zero_after: snapshot.zero_after,
+            data_offset,
+            entry_count: snapshot.entry_count,
+            data_crc: crc32fast::hash(&snapshot.buf),
+            header_crc: 0,
+        };
+        header.header_crc = header.compute_crc();
+        self.file
+            .seek(SeekFrom::Start(slot as u64 * HEADER_SLOT_SIZE))?;
+        self.file.write_all(header.as_bytes())?;
+        self.file.sync_data()?;
+
+        // The previous snapshot is no longer needed, so release any space
+        // beyond the new one.
+        self.file.set_len(header.data_end())?;
+        self.current = Some((slot, header));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SnapshotData;
+    use super::SnapshotFile;
+    use crate::Sector;
+    use std::collections::BTreeMap;
+    use std::io::Seek;
+    use std::io::SeekFrom;
+    use std::io::Write;
+
+    fn data(sectors: &[(u64, u8)]) -> BTreeMap<u64, Sector> {
+        sectors
+            .iter()
+            .map(|&(sector, fill)| (sector, Sector([fill; 512])))
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let file = tempfile::tempfile().unwrap();
+        let (mut snapshots, snapshot) = SnapshotFile::open(file.try_clone().unwrap()).unwrap();
+        assert!(snapshot.is_none());
+
+        snapshots
+            .write(&SnapshotData::new(100, 100, &data(&[(1, 1), (5, 5)])))
+            .unwrap();
+        snapshots
+            .write(&SnapshotData::new(100, 50, &data(&[(1, 2)])))
+            .unwrap();
+
+        let (_, snapshot) = SnapshotFile::open(file.try_clone().unwrap()).unwrap();
+        let snapshot = snapshot.unwrap();
+        assert_eq!(snapshot.sector_count, 100);
+        assert_eq!(snapshot.zero_after, 50);
+        assert_eq!(snapshot.data.len(), 1);
+        assert_eq!(snapshot.data[&1].0, [2; 512]);
+    }
+
+    #[test]
+    fn torn_snapshot() {
+        let mut file = tempfile::tempfile().unwrap();
+        let (mut snapshots, _) = SnapshotFile::open(file.try_clone().unwrap()).unwrap();
+        snapshots
+            .write(&SnapshotData::new(100, 100, &data(&[(1, 1)])))
+            .unwrap();
+        snapshots
+            .write(&SnapshotData::new(100, 100, &data(&[(1, 2), (2, 2)])))
+            .unwrap();
+
+        // Corrupt the newest snapshot's header, as if the process crashed
+        // while writing it.
+        file.seek(SeekFrom::Start(4096 + 8)).unwrap();
+        file.write_all(&[0xff; 8]).unwrap();
+
+        let (_, snapshot) = SnapshotFile::open(file).unwrap();
+        let snapshot = snapshot.unwrap();
+        assert_eq!(snapshot.data.len(), 1);
+        assert_eq!(snapshot.data[&1].0, [1; 512]);
+    }
+}
@@ -5,17 +5,23 @@
 
 use super::Error;
 use super::RamLayer;
+use disk_backend_resources::layer::PersistentRamDiskLayerHandle;
 use disk_backend_resources::layer::RamDiskLayerHandle;
 use disk_layered::resolve::ResolveDiskLayerParameters;
 use disk_layered::resolve::ResolvedDiskLayer;
+use std::time::Duration;
 use vm_resource::declare_static_resolver;
 use vm_resource::kind::DiskLayerHandleKind;
 use vm_resource::ResolveResource;
 
-/// Resolver for a [`RamDiskLayerHandle`].
+/// Resolver for [`RamDiskLayerHandle`] and [`PersistentRamDiskLayerHandle`].
 pub struct RamDiskResolver;
 
-declare_static_resolver!(RamDiskResolver, (DiskLayerHandleKind, RamDiskLayerHandle));
+declare_static_resolver!(
+    RamDiskResolver,
+    (DiskLayerHandleKind, RamDiskLayerHandle),
+    (DiskLayerHandleKind, PersistentRamDiskLayerHandle)
+);
 
 /// Error type for [`RamDiskResolver`].
 #[derive(Debug, Error)]
@@ -39,3 +45,23 @@ fn resolve(
         ))
     }
 }
+
+impl ResolveResource<DiskLayerHandleKind, PersistentRamDiskLayerHandle> for RamDiskResolver {
+    type Output = ResolvedDiskLayer;
+    type Error = ResolveRamDiskError;
+
+    fn resolve(
+        &self,
+        rsrc: PersistentRamDiskLayerHandle,
+        _input: ResolveDiskLayerParameters<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(ResolvedDiskLayer::new(
+            RamLayer::with_backing_file(
+                rsrc.len,
+                rsrc.file,
+                rsrc.flush_interval_ms.map(Duration::from_millis),
+            )
+            .map_err(ResolveRamDiskError::Ram)?,
+        ))
+    }
+}
@@ -137,6 +137,33 @@ pub fn make_fixed(mut file: &File) -> Result<(), OpenError> {
         Ok(())
     }
 
+    /// Shrinks a fixed VHD to a disk size of `disk_size` bytes, discarding the
+    /// end of the disk.
+    ///
+    /// The new footer is written and flushed before the file is truncated, so
+    /// the file remains a valid VHD (of either size) if this is interrupted.
+    pub fn shrink_fixed(mut file: &File, disk_size: u64) -> Result<(), OpenError> {
+        let len = file.metadata()?.len();
+        if len < VhdFooter::LEN || len % VhdFooter::ALIGNMENT != 0 {
+            return Err(OpenError::InvalidFileSize(len));
+        }
+        file.seek(io::SeekFrom::End(-512))?;
+        let mut footer: VhdFooter = FromZeroes::new_zeroed();
+        file.read_exact(footer.as_bytes_mut())?;
+        let metadata = Metadata::from_footer(footer, len)?;
+
+        // The new footer must not overlap the old one.
+        if disk_size % VhdFooter::ALIGNMENT != 0 || disk_size + VhdFooter::LEN > metadata.disk_size
+        {
+            return Err(OpenError::InvalidDiskSize(disk_size));
+        }
+        file.seek(io::SeekFrom::Start(disk_size))?;
+        file.write_all(VhdFooter::new_fixed(disk_size, metadata.unique_id).as_bytes())?;
+        file.sync_data()?;
+        file.set_len(disk_size + VhdFooter::LEN)?;
+        Ok(())
+    }
+
     /// Opens a fixed VHD.
     pub fn open_fixed(mut file: File, read_only: bool) -> Result<Self, OpenError> {
         let meta = file.metadata()?;
@@ -266,4 +293,33 @@ async fn open_fixed() {
         mem.read_at(0, buf.as_bytes_mut()).unwrap();
         assert!(buf.iter().copied().eq(1000_u32 * 128..1001 * 128));
     }
+
+    #[async_test]
+    async fn shrink_fixed() {
+        let mut file = tempfile::tempfile().unwrap();
+        let data = (0..0x100000_u32).collect::<Vec<_>>();
+        file.write_all(data.as_bytes()).unwrap();
+        Vhd1Disk::make_fixed(&file).unwrap();
+
+        // Keeping the same size or growing is not allowed.
+        Vhd1Disk::shrink_fixed(&file, 0x400000).unwrap_err();
+        Vhd1Disk::shrink_fixed(&file, 0x400000 + 512).unwrap_err();
+
+        Vhd1Disk::shrink_fixed(&file, 0x100000).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 0x100000 + 512);
+        let vhd = Disk::new(Vhd1Disk::open_fixed(file, false).unwrap()).unwrap();
+        assert_eq!(vhd.sector_count(), 0x100000 / 512);
+
+        let mem = GuestMemory::allocate(0x1000);
+
+        let mut buf = [0_u32; 128];
+        vhd.read_vectored(
+            &OwnedRequestBuffers::linear(0, 512, true).buffer(&mem),
+            1000,
+        )
+        .await
+        .unwrap();
+        mem.read_at(0, buf.as_bytes_mut()).unwrap();
+        assert!(buf.iter().copied().eq(1000_u32 * 128..1001 * 128));
+    }
 }
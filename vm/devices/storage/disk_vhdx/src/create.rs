@@ -0,0 +1,320 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Creation of new dynamic and differencing VHDX files.
+
+use crate::image::write_header;
+use crate::image::Image;
+use crate::io::FileExt;
+use crate::OpenError;
+use guid::Guid;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+use vhdx_defs::crc32c;
+use vhdx_defs::FileIdentifier;
+use vhdx_defs::FileParameters;
+use vhdx_defs::Header;
+use vhdx_defs::MetadataTableEntry;
+use vhdx_defs::MetadataTableHeader;
+use vhdx_defs::ParentLocatorEntry;
+use vhdx_defs::ParentLocatorHeader;
+use vhdx_defs::RegionTableEntry;
+use vhdx_defs::RegionTableHeader;
+use vhdx_defs::MB;
+use vhdx_defs::SECTORS_PER_CHUNK;
+use zerocopy::AsBytes;
+use zerocopy::FromZeroes;
+
+const LOG_OFFSET: u64 = MB;
+const LOG_LENGTH: u32 = MB as u32;
+const METADATA_OFFSET: u64 = 2 * MB;
+const METADATA_LENGTH: u32 = MB as u32;
+const BAT_OFFSET: u64 = 3 * MB;
+
+/// An error creating a VHDX file.
+#[derive(Debug, Error)]
+pub enum CreateError {
+    #[error("io error")]
+    Io(#[from] io::Error),
+    #[error("invalid block size: {0:#x}")]
+    InvalidBlockSize(u32),
+    #[error("invalid sector size")]
+    InvalidSectorSize,
+    #[error("invalid disk size: {0:#x}")]
+    InvalidDiskSize(u64),
+    #[error("failed to open parent disk")]
+    Parent(#[source] OpenError),
+}
+
+/// Parameters for a new VHDX file.
+#[derive(Debug, Clone)]
+pub struct CreateParams {
+    /// The virtual disk size in bytes.
+    pub disk_size: u64,
+    /// The payload block size in bytes.
+    pub block_size: u32,
+    pub logical_sector_size: u32,
+    pub physical_sector_size: u32,
+}
+
+impl CreateParams {
+    /// Returns parameters for a disk of `disk_size` bytes with the default
+    /// block and sector sizes.
+    pub fn new(disk_size: u64) -> Self {
+        Self {
+            disk_size,
+            block_size: FileParameters::DEFAULT_BLOCK_SIZE,
+            logical_sector_size: 512,
+            physical_sector_size: 4096,
+        }
+    }
+}
+
+/// Formats `file` as an empty dynamic VHDX.
+pub fn create(file: &File, params: &CreateParams) -> Result<(), CreateError> {
+    write_image(file, params, None)
+}
+
+/// Formats `file`, located at `path`, as a differencing VHDX whose parent is
+/// the VHDX at `parent_path`.
+///
+/// The new disk inherits the parent's size and geometry.
+pub fn create_differencing(
+    file: &File,
+    path: &Path,
+    parent_path: &Path,
+) -> Result<(), CreateError> {
+    let parent = Image::open(File::open(parent_path)?, true).map_err(CreateError::Parent)?;
+    let parent_params = parent.params();
+    let params = CreateParams {
+        disk_size: parent_params.disk_size,
+        block_size: parent_params.block_size,
+        logical_sector_size: parent_params.logical_sector_size,
+        physical_sector_size: parent_params.physical_sector_size,
+    };
+
+    let linkage = format!("{{{}}}", parent.data_write_guid());
+    let parent_path = parent_path.canonicalize()?;
+    let mut locator = vec![(vhdx_defs::parent_locator_keys::PARENT_LINKAGE, linkage)];
+    let dir = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .canonicalize()?;
+    if parent_path.parent() == Some(&dir) {
+        if let Some(name) = parent_path.file_name().and_then(|n| n.to_str()) {
+            locator.push((
+                vhdx_defs::parent_locator_keys::RELATIVE_PATH,
+                format!(".\\{name}"),
+            ));
+        }
+    }
+    if let Some(path) = parent_path.to_str() {
+        locator.push((
+            vhdx_defs::parent_locator_keys::ABSOLUTE_WIN32_PATH,
+            path.to_owned(),
+        ));
+    }
+    write_image(file, &params, Some(&locator))
+}
+
+fn utf16_bytes(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+/// Builds the parent locator metadata item from key-value pairs.
+fn parent_locator_item(entries: &[(&str, String)]) -> Vec<u8> {
+    let header_len =
+        size_of::<ParentLocatorHeader>() + entries.len() * size_of::<ParentLocatorEntry>();
+    let mut item = vec![0; header_len];
+    ParentLocatorHeader {
+        locator_type: vhdx_defs::PARENT_LOCATOR_VHDX,
+        reserved: 0,
+        key_value_count: entries.len() as u16,
+    }
+    .write_to_prefix(&mut item)
+    .unwrap();
+    for (i, (key, value)) in entries.iter().enumerate() {
+        let key = utf16_bytes(key);
+        let value = utf16_bytes(value);
+        let entry = ParentLocatorEntry {
+            key_offset: item.len() as u32,
+            value_offset: (item.len() + key.len()) as u32,
+            key_length: key.len() as u16,
+            value_length: value.len() as u16,
+        };
+        item.extend_from_slice(&key);
+        item.extend_from_slice(&value);
+        entry
+            .write_to_prefix(
+                &mut item[size_of::<ParentLocatorHeader>() + i * size_of::<ParentLocatorEntry>()..],
+            )
+            .unwrap();
+    }
+    item
+}
+
+fn write_image(
+    file: &File,
+    params: &CreateParams,
+    parent_locator: Option<&[(&str, String)]>,
+) -> Result<(), CreateError> {
+    if !params.block_size.is_power_of_two()
+        || !(FileParameters::MIN_BLOCK_SIZE..=FileParameters::MAX_BLOCK_SIZE)
+            .contains(&params.block_size)
+    {
+        return Err(CreateError::InvalidBlockSize(params.block_size));
+    }
+    if !matches!(params.logical_sector_size, 512 | 4096)
+        || !matches!(params.physical_sector_size, 512 | 4096)
+    {
+        return Err(CreateError::InvalidSectorSize);
+    }
+    if params.disk_size == 0
+        || params.disk_size > crate::image::MAX_DISK_SIZE
+        || params.disk_size % params.logical_sector_size as u64 != 0
+    {
+        return Err(CreateError::InvalidDiskSize(params.disk_size));
+    }
+
+    let block_size = params.block_size as u64;
+    let chunk_ratio = SECTORS_PER_CHUNK * params.logical_sector_size as u64 / block_size;
+    let data_blocks = params.disk_size.div_ceil(block_size);
+    let bat_entries = if parent_locator.is_some() {
+        data_blocks.div_ceil(chunk_ratio) * (chunk_ratio + 1)
+    } else {
+        data_blocks + (data_blocks - 1) / chunk_ratio
+    };
+    let bat_length = (bat_entries * size_of::<u64>() as u64).next_multiple_of(MB);
+
+    // Start from an empty file so that the BAT and all other unwritten
+    // structures are zero.
+    file.set_len(0)?;
+    file.set_len(BAT_OFFSET + bat_length)?;
+
+    let mut ident = FileIdentifier::new_zeroed();
+    ident.signature = FileIdentifier::SIGNATURE;
+    for (c, d) in "openvmm".encode_utf16().zip(&mut ident.creator) {
+        *d = c;
+    }
+    file.write_all_at(ident.as_bytes(), vhdx_defs::FILE_IDENTIFIER_OFFSET)?;
+
+    let mut table = vec![0; RegionTableHeader::SIZE];
+    RegionTableHeader {
+        signature: RegionTableHeader::SIGNATURE,
+        checksum: 0,
+        entry_count: 2,
+        reserved: 0,
+    }
+    .write_to_prefix(&mut table)
+    .unwrap();
+    let regions = [
+        RegionTableEntry {
+            guid: vhdx_defs::BAT_REGION,
+            file_offset: BAT_OFFSET,
+            length: bat_length as u32,
+            flags: RegionTableEntry::FLAG_REQUIRED,
+        },
+        RegionTableEntry {
+            guid: vhdx_defs::METADATA_REGION,
+            file_offset: METADATA_OFFSET,
+            length: METADATA_LENGTH,
+            flags: RegionTableEntry::FLAG_REQUIRED,
+        },
+    ];
+    regions
+        .write_to_prefix(&mut table[size_of::<RegionTableHeader>()..])
+        .unwrap();
+    let checksum = crc32c(&table);
+    table[4..8].copy_from_slice(checksum.as_bytes());
+    file.write_all_at(&table, vhdx_defs::REGION_TABLE_1_OFFSET)?;
+    file.write_all_at(&table, vhdx_defs::REGION_TABLE_2_OFFSET)?;
+
+    let file_parameters = FileParameters {
+        block_size: params.block_size,
+        flags: if parent_locator.is_some() {
+            FileParameters::FLAG_HAS_PARENT
+        } else {
+            0
+        },
+    };
+    let virtual_disk =
+        MetadataTableEntry::FLAG_IS_VIRTUAL_DISK | MetadataTableEntry::FLAG_IS_REQUIRED;
+    let mut items = vec![
+        (
+            vhdx_defs::FILE_PARAMETERS_ITEM,
+            MetadataTableEntry::FLAG_IS_REQUIRED,
+            file_parameters.as_bytes().to_vec(),
+        ),
+        (
+            vhdx_defs::VIRTUAL_DISK_SIZE_ITEM,
+            virtual_disk,
+            params.disk_size.as_bytes().to_vec(),
+        ),
+        (
+            vhdx_defs::VIRTUAL_DISK_ID_ITEM,
+            virtual_disk,
+            Guid::new_random().as_bytes().to_vec(),
+        ),
+        (
+            vhdx_defs::LOGICAL_SECTOR_SIZE_ITEM,
+            virtual_disk,
+            params.logical_sector_size.as_bytes().to_vec(),
+        ),
+        (
+            vhdx_defs::PHYSICAL_SECTOR_SIZE_ITEM,
+            virtual_disk,
+            params.physical_sector_size.as_bytes().to_vec(),
+        ),
+    ];
+    if let Some(entries) = parent_locator {
+        items.push((
+            vhdx_defs::PARENT_LOCATOR_ITEM,
+            MetadataTableEntry::FLAG_IS_REQUIRED,
+            parent_locator_item(entries),
+        ));
+    }
+
+    let mut metadata = vec![0; MetadataTableHeader::SIZE];
+    MetadataTableHeader {
+        signature: MetadataTableHeader::SIGNATURE,
+        reserved: 0,
+        entry_count: items.len() as u16,
+        reserved2: [0; 5],
+    }
+    .write_to_prefix(&mut metadata)
+    .unwrap();
+    for (i, (item_id, flags, data)) in items.iter().enumerate() {
+        MetadataTableEntry {
+            item_id: *item_id,
+            offset: metadata.len() as u32,
+            length: data.len() as u32,
+            flags: *flags,
+            reserved: 0,
+        }
+        .write_to_prefix(
+            &mut metadata[size_of::<MetadataTableHeader>() + i * size_of::<MetadataTableEntry>()..],
+        )
+        .unwrap();
+        metadata.extend_from_slice(data);
+    }
+    file.write_all_at(&metadata, METADATA_OFFSET)?;
+
+    // Write both headers, leaving the second one current.
+    let mut header = Header::new_zeroed();
+    header.signature = Header::SIGNATURE;
+    header.file_write_guid = Guid::new_random();
+    header.data_write_guid = Guid::new_random();
+    header.log_version = Header::LOG_VERSION;
+    header.version = Header::VERSION;
+    header.log_length = LOG_LENGTH;
+    header.log_offset = LOG_OFFSET;
+    let mut header_index = 1;
+    write_header(file, &mut header, &mut header_index)?;
+    write_header(file, &mut header, &mut header_index)?;
+
+    file.sync_all()?;
+    Ok(())
+}
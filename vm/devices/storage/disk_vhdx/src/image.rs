@@ -0,0 +1,731 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Parsing and IO for a single VHDX file.
+
+use crate::io::FileExt;
+use crate::log::LogWriter;
+use crate::OpenError;
+use guid::Guid;
+use parking_lot::RwLock;
+use std::fs::File;
+use std::io;
+use vhdx_defs::crc32c;
+use vhdx_defs::BatEntry;
+use vhdx_defs::FileIdentifier;
+use vhdx_defs::FileParameters;
+use vhdx_defs::Header;
+use vhdx_defs::MetadataTableEntry;
+use vhdx_defs::MetadataTableHeader;
+use vhdx_defs::ParentLocatorEntry;
+use vhdx_defs::ParentLocatorHeader;
+use vhdx_defs::PayloadBlockState;
+use vhdx_defs::RegionTableEntry;
+use vhdx_defs::RegionTableHeader;
+use vhdx_defs::SectorBitmapState;
+use vhdx_defs::LOG_SECTOR_SIZE;
+use vhdx_defs::MB;
+use vhdx_defs::SECTORS_PER_CHUNK;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+/// The largest virtual disk size allowed by the specification.
+pub(crate) const MAX_DISK_SIZE: u64 = 64 * 1024 * 1024 * MB;
+
+/// The virtual disk parameters from the metadata region.
+#[derive(Debug, Clone)]
+pub struct Params {
+    pub block_size: u32,
+    pub disk_size: u64,
+    pub logical_sector_size: u32,
+    pub physical_sector_size: u32,
+    pub disk_id: Guid,
+    pub has_parent: bool,
+}
+
+/// The parent locator of a differencing disk.
+#[derive(Debug, Clone, Default)]
+pub struct ParentLocator {
+    /// The data write GUID of the parent.
+    pub linkage: Guid,
+    /// An alternate data write GUID for the parent.
+    pub linkage2: Option<Guid>,
+    pub relative_path: Option<String>,
+    pub volume_path: Option<String>,
+    pub absolute_win32_path: Option<String>,
+}
+
+/// An open VHDX file.
+pub struct Image {
+    file: File,
+    read_only: bool,
+    params: Params,
+    parent_locator: Option<ParentLocator>,
+    /// The data write GUID at open time, used to validate parent linkage.
+    data_write_guid: Guid,
+    bat_offset: u64,
+    chunk_ratio: u64,
+    state: RwLock<State>,
+}
+
+struct State {
+    /// The entire BAT region, including any padding entries.
+    bat: Vec<BatEntry>,
+    header: Header,
+    /// The index (0 or 1) of the current header.
+    header_index: usize,
+    /// The offset at which to allocate the next block.
+    file_end: u64,
+    /// The log writer, present once the file has been prepared for writes.
+    log: Option<LogWriter>,
+}
+
+fn header_checksum(header: &Header) -> u32 {
+    let mut header = *header;
+    header.checksum = 0;
+    crc32c(header.as_bytes())
+}
+
+fn header_offset(index: usize) -> u64 {
+    [vhdx_defs::HEADER_1_OFFSET, vhdx_defs::HEADER_2_OFFSET][index]
+}
+
+/// Reads both headers, returning the valid one with the highest sequence
+/// number.
+fn read_current_header(file: &File) -> Result<(Header, usize), OpenError> {
+    let mut current: Option<(Header, usize)> = None;
+    for index in 0..2 {
+        let mut header = Header::new_zeroed();
+        file.read_exact_at(header.as_bytes_mut(), header_offset(index))?;
+        if header.signature != Header::SIGNATURE || header.checksum != header_checksum(&header) {
+            continue;
+        }
+        if current
+            .as_ref()
+            .map_or(true, |(c, _)| c.sequence_number < header.sequence_number)
+        {
+            current = Some((header, index));
+        }
+    }
+    current.ok_or(OpenError::InvalidHeader)
+}
+
+/// Writes `header` with an incremented sequence number over the non-current
+/// header, making it current.
+pub(crate) fn write_header(
+    file: &File,
+    header: &mut Header,
+    header_index: &mut usize,
+) -> io::Result<()> {
+    header.sequence_number += 1;
+    header.checksum = header_checksum(header);
+    let index = 1 - *header_index;
+    file.write_all_at(header.as_bytes(), header_offset(index))?;
+    file.sync_data()?;
+    *header_index = index;
+    Ok(())
+}
+
+struct Regions {
+    bat: RegionTableEntry,
+    metadata: RegionTableEntry,
+}
+
+fn read_region_table(file: &File, offset: u64) -> Result<Regions, OpenError> {
+    let mut table = vec![0; RegionTableHeader::SIZE];
+    file.read_exact_at(&mut table, offset)?;
+    let header = RegionTableHeader::read_from_prefix(&table).unwrap();
+    if header.signature != RegionTableHeader::SIGNATURE
+        || header.entry_count > RegionTableHeader::MAX_ENTRIES
+    {
+        return Err(OpenError::InvalidRegionTable);
+    }
+    table[4..8].fill(0);
+    if crc32c(&table) != header.checksum {
+        return Err(OpenError::InvalidRegionTable);
+    }
+    let entries = &table[size_of::<RegionTableHeader>()..];
+    let (mut bat, mut metadata) = (None, None);
+    for i in 0..header.entry_count as usize {
+        let entry =
+            RegionTableEntry::read_from_prefix(&entries[i * size_of::<RegionTableEntry>()..])
+                .unwrap();
+        if entry.file_offset % MB != 0
+            || entry.file_offset < vhdx_defs::HEADER_SECTION_SIZE
+            || entry.length as u64 % MB != 0
+        {
+            return Err(OpenError::InvalidRegionTable);
+        }
+        match entry.guid {
+            vhdx_defs::BAT_REGION => bat = Some(entry),
+            vhdx_defs::METADATA_REGION => metadata = Some(entry),
+            guid if entry.flags & RegionTableEntry::FLAG_REQUIRED != 0 => {
+                return Err(OpenError::UnsupportedRegion(guid))
+            }
+            _ => {}
+        }
+    }
+    Ok(Regions {
+        bat: bat.ok_or(OpenError::InvalidRegionTable)?,
+        metadata: metadata.ok_or(OpenError::InvalidRegionTable)?,
+    })
+}
+
+fn utf16_string(data: &[u8]) -> Option<String> {
+    if data.len() % 2 != 0 {
+        return None;
+    }
+    let chars = data
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    String::from_utf16(&chars).ok()
+}
+
+fn parse_parent_locator(data: &[u8]) -> Result<ParentLocator, OpenError> {
+    let header = ParentLocatorHeader::read_from_prefix(data).ok_or(OpenError::InvalidMetadata)?;
+    if header.locator_type != vhdx_defs::PARENT_LOCATOR_VHDX {
+        return Err(OpenError::UnsupportedParentLocator(header.locator_type));
+    }
+    let mut linkage = None;
+    let mut locator = ParentLocator::default();
+    let entries = &data[size_of::<ParentLocatorHeader>()..];
+    for i in 0..header.key_value_count as usize {
+        let entry = entries
+            .get(i * size_of::<ParentLocatorEntry>()..)
+            .and_then(ParentLocatorEntry::read_from_prefix)
+            .ok_or(OpenError::InvalidMetadata)?;
+        let string = |offset: u32, len: u16| {
+            data.get(offset as usize..)
+                .and_then(|d| d.get(..len as usize))
+                .and_then(utf16_string)
+                .ok_or(OpenError::InvalidMetadata)
+        };
+        let key = string(entry.key_offset, entry.key_length)?;
+        let value = string(entry.value_offset, entry.value_length)?;
+        let guid = |value: &str| {
+            value
+                .parse::<Guid>()
+                .map_err(|_| OpenError::InvalidMetadata)
+        };
+        use vhdx_defs::parent_locator_keys::*;
+        match key.as_str() {
+            PARENT_LINKAGE => linkage = Some(guid(&value)?),
+            PARENT_LINKAGE2 => locator.linkage2 = Some(guid(&value)?),
+            RELATIVE_PATH => locator.relative_path = Some(value),
+            VOLUME_PATH => locator.volume_path = Some(value),
+            ABSOLUTE_WIN32_PATH => locator.absolute_win32_path = Some(value),
+            _ => {}
+        }
+    }
+    locator.linkage = linkage.ok_or(OpenError::InvalidMetadata)?;
+    Ok(locator)
+}
+
+fn read_metadata(
+    file: &File,
+    region: &RegionTableEntry,
+) -> Result<(Params, Option<ParentLocator>), OpenError> {
+    let mut table = vec![0; MetadataTableHeader::SIZE];
+    file.read_exact_at(&mut table, region.file_offset)?;
+    let header = MetadataTableHeader::read_from_prefix(&table).unwrap();
+    if header.signature != MetadataTableHeader::SIGNATURE
+        || header.entry_count > MetadataTableHeader::MAX_ENTRIES
+    {
+        return Err(OpenError::InvalidMetadata);
+    }
+
+    let read_item = |entry: &MetadataTableEntry| -> Result<Vec<u8>, OpenError> {
+        if (entry.offset as usize) < MetadataTableHeader::SIZE
+            || entry.offset as u64 + entry.length as u64 > region.length as u64
+        {
+            return Err(OpenError::InvalidMetadata);
+        }
+        let mut data = vec![0; entry.length as usize];
+        file.read_exact_at(&mut data, region.file_offset + entry.offset as u64)?;
+        Ok(data)
+    };
+    fn item<T: FromBytes>(data: &[u8]) -> Result<T, OpenError> {
+        T::read_from_prefix(data).ok_or(OpenError::InvalidMetadata)
+    }
+
+    let mut file_parameters = None;
+    let mut disk_size = None;
+    let mut disk_id = None;
+    let mut logical_sector_size = None;
+    let mut physical_sector_size = None;
+    let mut parent_locator = None;
+    let entries = &table[size_of::<MetadataTableHeader>()..];
+    for i in 0..header.entry_count as usize {
+        let entry =
+            MetadataTableEntry::read_from_prefix(&entries[i * size_of::<MetadataTableEntry>()..])
+                .unwrap();
+        if entry.flags & MetadataTableEntry::FLAG_IS_USER != 0 {
+            continue;
+        }
+        match entry.item_id {
+            vhdx_defs::FILE_PARAMETERS_ITEM => {
+                file_parameters = Some(item::<FileParameters>(&read_item(&entry)?)?)
+            }
+            vhdx_defs::VIRTUAL_DISK_SIZE_ITEM => {
+                disk_size = Some(item::<u64>(&read_item(&entry)?)?)
+            }
+            vhdx_defs::VIRTUAL_DISK_ID_ITEM => disk_id = Some(item::<Guid>(&read_item(&entry)?)?),
+            vhdx_defs::LOGICAL_SECTOR_SIZE_ITEM => {
+                logical_sector_size = Some(item::<u32>(&read_item(&entry)?)?)
+            }
+            vhdx_defs::PHYSICAL_SECTOR_SIZE_ITEM => {
+                physical_sector_size = Some(item::<u32>(&read_item(&entry)?)?)
+            }
+            vhdx_defs::PARENT_LOCATOR_ITEM => {
+                parent_locator = Some(parse_parent_locator(&read_item(&entry)?)?)
+            }
+            guid if entry.flags & MetadataTableEntry::FLAG_IS_REQUIRED != 0 => {
+                return Err(OpenError::UnsupportedMetadata(guid))
+            }
+            _ => {}
+        }
+    }
+
+    let file_parameters = file_parameters.ok_or(OpenError::InvalidMetadata)?;
+    let params = Params {
+        block_size: file_parameters.block_size,
+        disk_size: disk_size.ok_or(OpenError::InvalidMetadata)?,
+        logical_sector_size: logical_sector_size.ok_or(OpenError::InvalidMetadata)?,
+        physical_sector_size: physical_sector_size.ok_or(OpenError::InvalidMetadata)?,
+        disk_id: disk_id.ok_or(OpenError::InvalidMetadata)?,
+        has_parent: file_parameters.flags & FileParameters::FLAG_HAS_PARENT != 0,
+    };
+    if !params.block_size.is_power_of_two()
+        || !(FileParameters::MIN_BLOCK_SIZE..=FileParameters::MAX_BLOCK_SIZE)
+            .contains(&params.block_size)
+    {
+        return Err(OpenError::InvalidBlockSize(params.block_size));
+    }
+    if !matches!(params.logical_sector_size, 512 | 4096)
+        || !matches!(params.physical_sector_size, 512 | 4096)
+    {
+        return Err(OpenError::InvalidSectorSize);
+    }
+    if params.disk_size == 0
+        || params.disk_size > MAX_DISK_SIZE
+        || params.disk_size % params.logical_sector_size as u64 != 0
+    {
+        return Err(OpenError::InvalidDiskSize(params.disk_size));
+    }
+    if params.has_parent != parent_locator.is_some() {
+        return Err(OpenError::InvalidMetadata);
+    }
+    Ok((params, parent_locator))
+}
+
+impl Image {
+    /// Opens a VHDX file, replaying its log if necessary.
+    ///
+    /// Replaying the log requires write access, so a file with a non-empty log
+    /// cannot be opened read-only.
+    pub fn open(file: File, read_only: bool) -> Result<Self, OpenError> {
+        let mut ident = FileIdentifier::new_zeroed();
+        file.read_exact_at(ident.as_bytes_mut(), vhdx_defs::FILE_IDENTIFIER_OFFSET)?;
+        if ident.signature != FileIdentifier::SIGNATURE {
+            return Err(OpenError::NotVhdx);
+        }
+
+        let (mut header, mut header_index) = read_current_header(&file)?;
+        if header.version != Header::VERSION {
+            return Err(OpenError::UnsupportedVersion(header.version));
+        }
+        if header.log_offset % MB != 0
+            || header.log_offset < vhdx_defs::HEADER_SECTION_SIZE
+            || header.log_length == 0
+            || header.log_length as u64 % MB != 0
+        {
+            return Err(OpenError::InvalidHeader);
+        }
+        if !header.log_guid.is_zero() {
+            if read_only {
+                return Err(OpenError::LogReplayRequired);
+            }
+            crate::log::replay(&file, header.log_offset, header.log_length, header.log_guid)
+                .map_err(OpenError::LogReplay)?;
+            header.log_guid = Guid::ZERO;
+            write_header(&file, &mut header, &mut header_index)?;
+        }
+
+        let regions = match read_region_table(&file, vhdx_defs::REGION_TABLE_1_OFFSET) {
+            Ok(regions) => regions,
+            Err(err @ OpenError::Io(_)) => return Err(err),
+            Err(_) => read_region_table(&file, vhdx_defs::REGION_TABLE_2_OFFSET)?,
+        };
+        let (params, parent_locator) = read_metadata(&file, &regions.metadata)?;
+
+        let block_size = params.block_size as u64;
+        let chunk_ratio = SECTORS_PER_CHUNK * params.logical_sector_size as u64 / block_size;
+        let data_blocks = params.disk_size.div_ceil(block_size);
+        let bat_entries = if params.has_parent {
+            data_blocks.div_ceil(chunk_ratio) * (chunk_ratio + 1)
+        } else {
+            data_blocks + (data_blocks - 1) / chunk_ratio
+        };
+        if (regions.bat.length as u64) < bat_entries * size_of::<BatEntry>() as u64 {
+            return Err(OpenError::InvalidBat);
+        }
+        let mut bat = vec![BatEntry::new(); regions.bat.length as usize / size_of::<BatEntry>()];
+        file.read_exact_at(bat.as_bytes_mut(), regions.bat.file_offset)?;
+
+        // Allocate new blocks past the end of the file and of any existing
+        // block, whichever is later.
+        let mut file_end = file.metadata()?.len().next_multiple_of(MB);
+        for (i, entry) in bat.iter().enumerate().take(bat_entries as usize) {
+            let is_bitmap = i as u64 % (chunk_ratio + 1) == chunk_ratio;
+            let len = if is_bitmap {
+                if SectorBitmapState(entry.state()) != SectorBitmapState::PRESENT {
+                    continue;
+                }
+                vhdx_defs::SECTOR_BITMAP_BLOCK_SIZE
+            } else {
+                match PayloadBlockState(entry.state()) {
+                    PayloadBlockState::FULLY_PRESENT | PayloadBlockState::PARTIALLY_PRESENT => {
+                        block_size
+                    }
+                    _ => continue,
+                }
+            };
+            file_end = file_end.max(entry.file_offset_mb() * MB + len);
+        }
+
+        Ok(Self {
+            file,
+            read_only,
+            data_write_guid: header.data_write_guid,
+            params,
+            parent_locator,
+            bat_offset: regions.bat.file_offset,
+            chunk_ratio,
+            state: RwLock::new(State {
+                bat,
+                header,
+                header_index,
+                file_end,
+                log: None,
+            }),
+        })
+    }
+
+    pub fn params(&self) -> &Params {
+        &self.params
+    }
+
+    pub fn parent_locator(&self) -> Option<&ParentLocator> {
+        self.parent_locator.as_ref()
+    }
+
+    pub fn data_write_guid(&self) -> Guid {
+        self.data_write_guid
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    pub fn file_size(&self) -> u64 {
+        self.state.read().file_end
+    }
+
+    fn payload_index(&self, block: u64) -> usize {
+        (block + block / self.chunk_ratio) as usize
+    }
+
+    fn bitmap_index(&self, chunk: u64) -> usize {
+        (chunk * (self.chunk_ratio + 1) + self.chunk_ratio) as usize
+    }
+
+    /// Reads from the virtual disk at byte `offset`, falling back to `parents`
+    /// (starting with the immediate parent) for sectors not present in this
+    /// file.
+    ///
+    /// Reads beyond the end of the virtual disk return zeroes, which can
+    /// happen when a parent is smaller than its child.
+    pub fn read(&self, parents: &[Image], mut offset: u64, mut buf: &mut [u8]) -> io::Result<()> {
+        let block_size = self.params.block_size as u64;
+        while !buf.is_empty() {
+            if offset >= self.params.disk_size {
+                buf.fill(0);
+                break;
+            }
+            let block = offset / block_size;
+            let block_offset = offset % block_size;
+            let len = (block_size - block_offset)
+                .min(self.params.disk_size - offset)
+                .min(buf.len() as u64) as usize;
+            let (this, rest) = buf.split_at_mut(len);
+            let entry = self.state.read().bat[self.payload_index(block)];
+            match PayloadBlockState(entry.state()) {
+                PayloadBlockState::PARTIALLY_PRESENT if self.params.has_parent => {
+                    self.read_partial(parents, block, entry, offset, this)?
+                }
+                PayloadBlockState::FULLY_PRESENT | PayloadBlockState::PARTIALLY_PRESENT => self
+                    .file
+                    .read_exact_at(this, entry.file_offset_mb() * MB + block_offset)?,
+                PayloadBlockState::ZERO => this.fill(0),
+                _ if self.params.has_parent => read_parent(parents, offset, this)?,
+                _ => this.fill(0),
+            }
+            offset += len as u64;
+            buf = rest;
+        }
+        Ok(())
+    }
+
+    /// Reads the sector bitmap bits for `count` sectors starting at
+    /// `sector`, all within one chunk.
+    fn read_bitmap(&self, sector: u64, count: u64) -> io::Result<Option<(Vec<u8>, u64)>> {
+        let chunk = sector / SECTORS_PER_CHUNK;
+        let entry = self.state.read().bat[self.bitmap_index(chunk)];
+        if SectorBitmapState(entry.state()) != SectorBitmapState::PRESENT {
+            return Ok(None);
+        }
+        let first = sector % SECTORS_PER_CHUNK;
+        let byte_start = first / 8;
+        let byte_end = (first + count).div_ceil(8);
+        let mut bitmap = vec![0; (byte_end - byte_start) as usize];
+        self.file
+            .read_exact_at(&mut bitmap, entry.file_offset_mb() * MB + byte_start)?;
+        // Return the bitmap along with the bit index of `sector` in it.
+        Ok(Some((bitmap, first % 8)))
+    }
+
+    fn read_partial(
+        &self,
+        parents: &[Image],
+        block: u64,
+        entry: BatEntry,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> io::Result<()> {
+        let sector_size = self.params.logical_sector_size as u64;
+        let sector = offset / sector_size;
+        let count = buf.len() as u64 / sector_size;
+        let Some((bitmap, bit)) = self.read_bitmap(sector, count)? else {
+            return read_parent(parents, offset, buf);
+        };
+        let present = |i: u64| {
+            let bit = bit + i;
+            bitmap[(bit / 8) as usize] & (1 << (bit % 8)) != 0
+        };
+        let block_start = block * self.params.block_size as u64;
+        let mut i = 0;
+        while i < count {
+            let is_present = present(i);
+            let mut n = 1;
+            while i + n < count && present(i + n) == is_present {
+                n += 1;
+            }
+            let run_offset = offset + i * sector_size;
+            let run = &mut buf[(i * sector_size) as usize..((i + n) * sector_size) as usize];
+            if is_present {
+                self.file.read_exact_at(
+                    run,
+                    entry.file_offset_mb() * MB + (run_offset - block_start),
+                )?;
+            } else {
+                read_parent(parents, run_offset, run)?;
+            }
+            i += n;
+        }
+        Ok(())
+    }
+
+    /// Updates the header to claim the file for writing, the first time it is
+    /// written to.
+    fn prepare_write(&self) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::ErrorKind::PermissionDenied.into());
+        }
+        if self.state.read().log.is_some() {
+            return Ok(());
+        }
+        let mut state = self.state.write();
+        let state = &mut *state;
+        if state.log.is_some() {
+            return Ok(());
+        }
+        let log = LogWriter::new(state.header.log_offset, state.header.log_length);
+        let mut header = state.header;
+        header.file_write_guid = Guid::new_random();
+        header.data_write_guid = Guid::new_random();
+        header.log_guid = log.guid();
+        write_header(&self.file, &mut header, &mut state.header_index)?;
+        state.header = header;
+        state.log = Some(log);
+        Ok(())
+    }
+
+    /// Writes to the virtual disk at byte `offset`, allocating blocks as
+    /// necessary. Newly allocated blocks in a differencing disk are populated
+    /// from `parents`.
+    pub fn write(&self, parents: &[Image], mut offset: u64, mut data: &[u8]) -> io::Result<()> {
+        self.prepare_write()?;
+        let block_size = self.params.block_size as u64;
+        while !data.is_empty() {
+            let block = offset / block_size;
+            let block_offset = offset % block_size;
+            let len = (block_size - block_offset).min(data.len() as u64) as usize;
+            let (this, rest) = data.split_at(len);
+            let entry = self.state.read().bat[self.payload_index(block)];
+            match PayloadBlockState(entry.state()) {
+                PayloadBlockState::PARTIALLY_PRESENT if self.params.has_parent => {
+                    self.file
+                        .write_all_at(this, entry.file_offset_mb() * MB + block_offset)?;
+                    self.mark_present(offset, len as u64)?;
+                }
+                PayloadBlockState::FULLY_PRESENT | PayloadBlockState::PARTIALLY_PRESENT => self
+                    .file
+                    .write_all_at(this, entry.file_offset_mb() * MB + block_offset)?,
+                _ => self.allocate(parents, block, block_offset, this)?,
+            }
+            offset += len as u64;
+            data = rest;
+        }
+        Ok(())
+    }
+
+    /// Allocates `block` and writes `data` at `block_offset` within it.
+    fn allocate(
+        &self,
+        parents: &[Image],
+        block: u64,
+        block_offset: u64,
+        data: &[u8],
+    ) -> io::Result<()> {
+        let mut state = self.state.write();
+        let index = self.payload_index(block);
+        let entry = state.bat[index];
+        let state_kind = PayloadBlockState(entry.state());
+        if matches!(
+            state_kind,
+            PayloadBlockState::FULLY_PRESENT | PayloadBlockState::PARTIALLY_PRESENT
+        ) {
+            // Raced with another allocation of the same block.
+            drop(state);
+            let offset = block * self.params.block_size as u64 + block_offset;
+            return self.write(parents, offset, data);
+        }
+
+        let block_size = self.params.block_size as u64;
+        let mut buf = vec![0; block_size as usize];
+        if self.params.has_parent && state_kind != PayloadBlockState::ZERO {
+            read_parent(parents, block * block_size, &mut buf)?;
+        }
+        buf[block_offset as usize..][..data.len()].copy_from_slice(data);
+
+        // Write and flush the data before the BAT entry refers to it.
+        let file_offset = state.file_end;
+        self.file.write_all_at(&buf, file_offset)?;
+        self.file.sync_data()?;
+        state.file_end = file_offset + block_size;
+        state.bat[index] = BatEntry::new()
+            .with_state(PayloadBlockState::FULLY_PRESENT.0)
+            .with_file_offset_mb(file_offset / MB);
+        self.commit_bat(&mut state, index)
+    }
+
+    /// Writes the BAT page containing `index` through the log.
+    fn commit_bat(&self, state: &mut State, index: usize) -> io::Result<()> {
+        let page_offset = index * size_of::<BatEntry>() / LOG_SECTOR_SIZE * LOG_SECTOR_SIZE;
+        let page: &[u8; LOG_SECTOR_SIZE] = state.bat.as_bytes()[page_offset..][..LOG_SECTOR_SIZE]
+            .try_into()
+            .unwrap();
+        state.log.as_mut().unwrap().commit(
+            &self.file,
+            &[(self.bat_offset + page_offset as u64, page)],
+            state.file_end,
+        )
+    }
+
+    /// Marks the sectors in the given byte range as present in the sector
+    /// bitmap.
+    fn mark_present(&self, offset: u64, len: u64) -> io::Result<()> {
+        let sector_size = self.params.logical_sector_size as u64;
+        let sector = offset / sector_size;
+        let count = len / sector_size;
+        let mut state = self.state.write();
+        let chunk = sector / SECTORS_PER_CHUNK;
+        let entry = state.bat[self.bitmap_index(chunk)];
+        if SectorBitmapState(entry.state()) != SectorBitmapState::PRESENT {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "partially present block has no sector bitmap",
+            ));
+        }
+        let bitmap_offset = entry.file_offset_mb() * MB;
+        let first = sector % SECTORS_PER_CHUNK;
+        let page_size = LOG_SECTOR_SIZE as u64;
+        let page_start = first / 8 / page_size * page_size;
+        let page_end = (first + count).div_ceil(8).next_multiple_of(page_size);
+        let mut pages = vec![[0; LOG_SECTOR_SIZE]; ((page_end - page_start) / page_size) as usize];
+        self.file
+            .read_exact_at(pages.as_bytes_mut(), bitmap_offset + page_start)?;
+        let bits = pages.as_bytes_mut();
+        for bit in first - page_start * 8..first - page_start * 8 + count {
+            bits[(bit / 8) as usize] |= 1 << (bit % 8);
+        }
+        let file_end = state.file_end;
+        let log = state.log.as_mut().unwrap();
+        let pages = pages
+            .iter()
+            .enumerate()
+            .map(|(i, page)| (bitmap_offset + page_start + i as u64 * page_size, page))
+            .collect::<Vec<_>>();
+        for pages in pages.chunks(log.max_pages()) {
+            log.commit(&self.file, pages, file_end)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        self.file.sync_all()
+    }
+}
+
+fn read_parent(parents: &[Image], offset: u64, buf: &mut [u8]) -> io::Result<()> {
+    match parents.split_first() {
+        Some((parent, rest)) => parent.read(rest, offset, buf),
+        None => {
+            buf.fill(0);
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+impl Image {
+    /// Allocates a block and writes its BAT entry to the log without applying
+    /// it, as if the process crashed mid-update.
+    pub fn write_block_unapplied(&self, block: u64, data: &[u8]) -> io::Result<()> {
+        self.prepare_write()?;
+        let mut state = self.state.write();
+        let state = &mut *state;
+        let block_size = self.params.block_size as u64;
+        let mut buf = vec![0; block_size as usize];
+        buf[..data.len()].copy_from_slice(data);
+        let file_offset = state.file_end;
+        self.file.write_all_at(&buf, file_offset)?;
+        state.file_end += block_size;
+        let index = self.payload_index(block);
+        let mut bat = state.bat.clone();
+        bat[index] = BatEntry::new()
+            .with_state(PayloadBlockState::FULLY_PRESENT.0)
+            .with_file_offset_mb(file_offset / MB);
+        let page_offset = index * size_of::<BatEntry>() / LOG_SECTOR_SIZE * LOG_SECTOR_SIZE;
+        let page: &[u8; LOG_SECTOR_SIZE] = bat.as_bytes()[page_offset..][..LOG_SECTOR_SIZE]
+            .try_into()
+            .unwrap();
+        state.log.as_mut().unwrap().write_only(
+            &self.file,
+            &[(self.bat_offset + page_offset as u64, page)],
+            state.file_end,
+        )
+    }
+}
@@ -0,0 +1,510 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! A native VHDX disk implementation, supporting dynamic and differencing
+//! disks.
+//!
+//! Unlike `disk_vhdmp`, this does not rely on the Windows VHD stack, so the
+//! same images can be used on any host OS. Metadata updates are written through
+//! the VHDX log, and a log left behind by an unclean shutdown is replayed when
+//! the disk is opened for write.
+
+#![forbid(unsafe_code)]
+
+mod create;
+mod image;
+mod io;
+mod log;
+
+pub use create::create;
+pub use create::create_differencing;
+pub use create::CreateError;
+pub use create::CreateParams;
+pub use log::ReplayError;
+
+use blocking::unblock;
+use disk_backend::resolve::ResolveDiskParameters;
+use disk_backend::resolve::ResolvedDisk;
+use disk_backend::DiskError;
+use disk_backend::DiskIo;
+use disk_backend_resources::VhdxDiskHandle;
+use guestmem::MemoryRead;
+use guestmem::MemoryWrite;
+use guid::Guid;
+use image::Image;
+use image::ParentLocator;
+use inspect::Inspect;
+use scsi_buffers::RequestBuffers;
+use std::fs::File;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use thiserror::Error;
+use vm_resource::declare_static_resolver;
+use vm_resource::kind::DiskHandleKind;
+use vm_resource::ResolveResource;
+
+/// The maximum length of a differencing chain, to guard against cycles.
+const MAX_CHAIN_DEPTH: usize = 64;
+
+pub struct VhdxDiskResolver;
+declare_static_resolver!(VhdxDiskResolver, (DiskHandleKind, VhdxDiskHandle));
+
+#[derive(Debug, Error)]
+pub enum ResolveVhdxDiskError {
+    #[error("failed to open VHDX")]
+    Open(#[source] OpenError),
+    #[error("invalid disk")]
+    InvalidDisk(#[source] disk_backend::InvalidDisk),
+}
+
+impl ResolveResource<DiskHandleKind, VhdxDiskHandle> for VhdxDiskResolver {
+    type Output = ResolvedDisk;
+    type Error = ResolveVhdxDiskError;
+
+    fn resolve(
+        &self,
+        rsrc: VhdxDiskHandle,
+        params: ResolveDiskParameters<'_>,
+    ) -> Result<Self::Output, Self::Error> {
+        let disk = VhdxDisk::open(rsrc.file, rsrc.parents, params.read_only)
+            .map_err(ResolveVhdxDiskError::Open)?;
+        ResolvedDisk::new(disk).map_err(ResolveVhdxDiskError::InvalidDisk)
+    }
+}
+
+/// An error encountered while opening a VHDX.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum OpenError {
+    #[error("io error")]
+    Io(#[from] std::io::Error),
+    #[error("not a VHDX file")]
+    NotVhdx,
+    #[error("no valid VHDX header")]
+    InvalidHeader,
+    #[error("unsupported VHDX version: {0}")]
+    UnsupportedVersion(u16),
+    #[error("invalid region table")]
+    InvalidRegionTable,
+    #[error("unsupported required region {0}")]
+    UnsupportedRegion(Guid),
+    #[error("invalid metadata")]
+    InvalidMetadata,
+    #[error("unsupported required metadata item {0}")]
+    UnsupportedMetadata(Guid),
+    #[error("unsupported parent locator type {0}")]
+    UnsupportedParentLocator(Guid),
+    #[error("invalid block size: {0:#x}")]
+    InvalidBlockSize(u32),
+    #[error("invalid sector size")]
+    InvalidSectorSize,
+    #[error("invalid disk size: {0:#x}")]
+    InvalidDiskSize(u64),
+    #[error("block allocation table is too small")]
+    InvalidBat,
+    #[error("the log must be replayed, which requires write access")]
+    LogReplayRequired,
+    #[error("failed to replay the log")]
+    LogReplay(#[source] ReplayError),
+    #[error("differencing disk parent was not provided")]
+    MissingParent,
+    #[error("more parents provided than are in the differencing chain")]
+    UnexpectedParent,
+    #[error("parent {0} does not match the child's parent linkage")]
+    ParentLinkageMismatch(usize),
+    #[error("parent {0} has a different sector size from its child")]
+    ParentSectorSizeMismatch(usize),
+    #[error("could not find parent of {}", .0.display())]
+    ParentNotFound(PathBuf),
+    #[error("differencing chain is too long")]
+    ChainTooDeep,
+}
+
+/// An open VHDX disk, along with any parents.
+pub struct VhdxDisk {
+    /// The images in the chain, starting with the child.
+    chain: Arc<[Image]>,
+    sector_shift: u32,
+}
+
+impl Inspect for VhdxDisk {
+    fn inspect(&self, req: inspect::Request<'_>) {
+        let params = self.chain[0].params();
+        req.respond()
+            .field("disk_size", params.disk_size)
+            .field("block_size", params.block_size)
+            .field("logical_sector_size", params.logical_sector_size)
+            .field("physical_sector_size", params.physical_sector_size)
+            .field("file_size", self.chain[0].file_size())
+            .field("chain_depth", self.chain.len());
+    }
+}
+
+impl VhdxDisk {
+    /// Opens a VHDX file.
+    ///
+    /// If the file is a differencing disk, `parents` must contain its parent
+    /// files, starting with the immediate parent. See [`open_chain`] to find
+    /// these automatically.
+    pub fn open(file: File, parents: Vec<File>, read_only: bool) -> Result<Self, OpenError> {
+        let mut chain = vec![Image::open(file, read_only)?];
+        let mut parents = parents.into_iter();
+        loop {
+            let child = chain.last().unwrap();
+            let Some(locator) = child.parent_locator() else {
+                break;
+            };
+            let file = parents.next().ok_or(OpenError::MissingParent)?;
+            let parent = Image::open(file, true)?;
+            let index = chain.len() - 1;
+            if parent.data_write_guid() != locator.linkage
+                && Some(parent.data_write_guid()) != locator.linkage2
+            {
+                return Err(OpenError::ParentLinkageMismatch(index));
+            }
+            if parent.params().logical_sector_size != child.params().logical_sector_size {
+                return Err(OpenError::ParentSectorSizeMismatch(index));
+            }
+            chain.push(parent);
+        }
+        if parents.next().is_some() {
+            return Err(OpenError::UnexpectedParent);
+        }
+        let sector_shift = chain[0].params().logical_sector_size.trailing_zeros();
+        Ok(Self {
+            chain: chain.into(),
+            sector_shift,
+        })
+    }
+
+    /// Returns true if this is a differencing disk.
+    pub fn has_parent(&self) -> bool {
+        self.chain.len() > 1
+    }
+}
+
+fn native_path(path: &str) -> PathBuf {
+    if cfg!(windows) {
+        path.into()
+    } else {
+        path.replace('\\', "/").into()
+    }
+}
+
+/// Finds the parent of the disk at `path` from its parent locator.
+fn find_parent(path: &Path, locator: &ParentLocator) -> Option<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let relative = locator
+        .relative_path
+        .as_deref()
+        .map(|p| dir.join(native_path(p)));
+    let absolute = [&locator.absolute_win32_path, &locator.volume_path]
+        .into_iter()
+        .flatten()
+        .map(|p| native_path(p));
+    relative.into_iter().chain(absolute).find(|p| p.exists())
+}
+
+/// Opens the VHDX at `path` along with its chain of parents, located via each
+/// disk's parent locator.
+pub fn open_chain(path: &Path, read_only: bool) -> Result<VhdxDiskHandle, OpenError> {
+    let file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(!read_only)
+        .open(path)?;
+    let mut parents = Vec::new();
+    let mut path = path.to_owned();
+    let mut locator = Image::open(file.try_clone()?, read_only)?
+        .parent_locator()
+        .cloned();
+    while let Some(l) = locator {
+        if parents.len() == MAX_CHAIN_DEPTH {
+            return Err(OpenError::ChainTooDeep);
+        }
+        path = find_parent(&path, &l).ok_or(OpenError::ParentNotFound(path))?;
+        let parent = File::open(&path)?;
+        locator = Image::open(parent.try_clone()?, true)?
+            .parent_locator()
+            .cloned();
+        parents.push(parent);
+    }
+    Ok(VhdxDiskHandle { file, parents })
+}
+
+impl DiskIo for VhdxDisk {
+    fn disk_type(&self) -> &str {
+        "vhdx"
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.chain[0].params().disk_size >> self.sector_shift
+    }
+
+    fn sector_size(&self) -> u32 {
+        self.chain[0].params().logical_sector_size
+    }
+
+    fn is_read_only(&self) -> bool {
+        self.chain[0].is_read_only()
+    }
+
+    fn disk_id(&self) -> Option<[u8; 16]> {
+        Some(self.chain[0].params().disk_id.into())
+    }
+
+    fn physical_sector_size(&self) -> u32 {
+        self.chain[0].params().physical_sector_size
+    }
+
+    fn is_fua_respected(&self) -> bool {
+        true
+    }
+
+    async fn read_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+    ) -> Result<(), DiskError> {
+        let offset = sector << self.sector_shift;
+        if offset + buffers.len() as u64 > self.chain[0].params().disk_size {
+            return Err(DiskError::IllegalBlock);
+        }
+        let mut buffer = vec![0; buffers.len()];
+        let chain = self.chain.clone();
+        let buffer = unblock(move || -> Result<_, std::io::Error> {
+            chain[0].read(&chain[1..], offset, &mut buffer)?;
+            Ok(buffer)
+        })
+        .await
+        .map_err(DiskError::Io)?;
+        buffers.writer().write(&buffer)?;
+        Ok(())
+    }
+
+    async fn write_vectored(
+        &self,
+        buffers: &RequestBuffers<'_>,
+        sector: u64,
+        fua: bool,
+    ) -> Result<(), DiskError> {
+        if self.is_read_only() {
+            return Err(DiskError::ReadOnly);
+        }
+        let offset = sector << self.sector_shift;
+        if offset + buffers.len() as u64 > self.chain[0].params().disk_size {
+            return Err(DiskError::IllegalBlock);
+        }
+        let mut buffer = vec![0; buffers.len()];
+        buffers.reader().read(&mut buffer)?;
+        let chain = self.chain.clone();
+        unblock(move || {
+            chain[0].write(&chain[1..], offset, &buffer)?;
+            if fua {
+                chain[0].flush()?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(DiskError::Io)?;
+        Ok(())
+    }
+
+    async fn sync_cache(&self) -> Result<(), DiskError> {
+        let chain = self.chain.clone();
+        unblock(move || chain[0].flush())
+            .await
+            .map_err(DiskError::Io)?;
+        Ok(())
+    }
+
+    async fn unmap(
+        &self,
+        _sector: u64,
+        _count: u64,
+        _block_level_only: bool,
+    ) -> Result<(), DiskError> {
+        Ok(())
+    }
+
+    fn unmap_behavior(&self) -> disk_backend::UnmapBehavior {
+        disk_backend::UnmapBehavior::Ignored
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::create;
+    use super::create_differencing;
+    use super::open_chain;
+    use super::CreateParams;
+    use super::OpenError;
+    use super::VhdxDisk;
+    use crate::image::Image;
+    use disk_backend::Disk;
+    use guestmem::GuestMemory;
+    use pal_async::async_test;
+    use scsi_buffers::OwnedRequestBuffers;
+    use std::fs::File;
+    use vhdx_defs::MB;
+
+    const SECTOR: usize = 512;
+
+    fn new_disk(path: &std::path::Path, disk_size: u64) {
+        let file = File::create_new(path).unwrap();
+        create(
+            &file,
+            &CreateParams {
+                block_size: MB as u32,
+                ..CreateParams::new(disk_size)
+            },
+        )
+        .unwrap();
+    }
+
+    async fn write(disk: &Disk, mem: &GuestMemory, sector: u64, data: &[u8]) {
+        mem.write_at(0, data).unwrap();
+        disk.write_vectored(
+            &OwnedRequestBuffers::linear(0, data.len(), false).buffer(mem),
+            sector,
+            false,
+        )
+        .await
+        .unwrap();
+    }
+
+    async fn read(disk: &Disk, mem: &GuestMemory, sector: u64, len: usize) -> Vec<u8> {
+        disk.read_vectored(
+            &OwnedRequestBuffers::linear(0, len, true).buffer(mem),
+            sector,
+        )
+        .await
+        .unwrap();
+        let mut buf = vec![0; len];
+        mem.read_at(0, &mut buf).unwrap();
+        buf
+    }
+
+    fn open(path: &std::path::Path, read_only: bool) -> Disk {
+        let handle = open_chain(path, read_only).unwrap();
+        Disk::new(VhdxDisk::open(handle.file, handle.parents, read_only).unwrap()).unwrap()
+    }
+
+    #[async_test]
+    async fn dynamic_read_write() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk.vhdx");
+        new_disk(&path, 64 * MB);
+        let mem = GuestMemory::allocate(0x10000);
+        let data = (0..4 * SECTOR).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+
+        {
+            let disk = open(&path, false);
+            assert_eq!(disk.sector_count(), 64 * MB / SECTOR as u64);
+            assert!(read(&disk, &mem, 0, SECTOR).await.iter().all(|&b| b == 0));
+            // Straddle the first block boundary.
+            write(&disk, &mem, 2046, &data).await;
+            assert_eq!(read(&disk, &mem, 2046, data.len()).await, data);
+        }
+
+        let disk = open(&path, true);
+        assert_eq!(read(&disk, &mem, 2046, data.len()).await, data);
+        assert!(read(&disk, &mem, 2050, SECTOR)
+            .await
+            .iter()
+            .all(|&b| b == 0));
+    }
+
+    #[async_test]
+    async fn differencing() {
+        let dir = tempfile::tempdir().unwrap();
+        let parent_path = dir.path().join("parent.vhdx");
+        let child_path = dir.path().join("child.vhdx");
+        new_disk(&parent_path, 16 * MB);
+        let mem = GuestMemory::allocate(0x10000);
+        let parent_data = vec![0xaa; 4 * SECTOR];
+        let child_data = vec![0x55; SECTOR];
+
+        {
+            let parent = open(&parent_path, false);
+            write(&parent, &mem, 100, &parent_data).await;
+        }
+
+        let child_file = File::create_new(&child_path).unwrap();
+        create_differencing(&child_file, &child_path, &parent_path).unwrap();
+        drop(child_file);
+
+        let child = open(&child_path, false);
+        assert_eq!(
+            read(&child, &mem, 100, parent_data.len()).await,
+            parent_data
+        );
+        write(&child, &mem, 101, &child_data).await;
+        let mut expected = parent_data.clone();
+        expected[SECTOR..2 * SECTOR].copy_from_slice(&child_data);
+        assert_eq!(read(&child, &mem, 100, expected.len()).await, expected);
+        drop(child);
+
+        // The parent is unchanged.
+        let parent = open(&parent_path, true);
+        assert_eq!(
+            read(&parent, &mem, 100, parent_data.len()).await,
+            parent_data
+        );
+    }
+
+    #[test]
+    fn parent_linkage_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let parent_path = dir.path().join("parent.vhdx");
+        let child_path = dir.path().join("child.vhdx");
+        new_disk(&parent_path, 16 * MB);
+        let child_file = File::create_new(&child_path).unwrap();
+        create_differencing(&child_file, &child_path, &parent_path).unwrap();
+
+        // Modifying the parent changes its data write GUID.
+        let parent = Image::open(
+            File::options()
+                .read(true)
+                .write(true)
+                .open(&parent_path)
+                .unwrap(),
+            false,
+        )
+        .unwrap();
+        parent.write(&[], 0, &[1; SECTOR]).unwrap();
+        drop(parent);
+
+        let err = VhdxDisk::open(child_file, vec![File::open(&parent_path).unwrap()], true)
+            .err()
+            .unwrap();
+        assert!(
+            matches!(err, OpenError::ParentLinkageMismatch(0)),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn log_replay() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("disk.vhdx");
+        new_disk(&path, 16 * MB);
+        let open_file = || File::options().read(true).write(true).open(&path).unwrap();
+
+        let image = Image::open(open_file(), false).unwrap();
+        image.write_block_unapplied(3, &[0x77; SECTOR]).unwrap();
+        drop(image);
+
+        let err = Image::open(open_file(), true).err().unwrap();
+        assert!(matches!(err, OpenError::LogReplayRequired), "{err:?}");
+
+        let image = Image::open(open_file(), false).unwrap();
+        let mut buf = [0; SECTOR];
+        image.read(&[], 3 * MB, &mut buf).unwrap();
+        assert_eq!(buf, [0x77; SECTOR]);
+        drop(image);
+
+        // The log is clean after replay.
+        Image::open(open_file(), true).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! The VHDX metadata log.
+//!
+//! All metadata updates (BAT and sector bitmap changes) are first written to
+//! the log and flushed, and only then written in place. After an unclean
+//! shutdown, the log is replayed on the next open to bring the metadata back
+//! to a consistent state.
+
+use crate::io::FileExt;
+use guid::Guid;
+use std::fs::File;
+use std::io;
+use vhdx_defs::crc32c;
+use vhdx_defs::DataDescriptor;
+use vhdx_defs::DataSector;
+use vhdx_defs::LogEntryHeader;
+use vhdx_defs::ZeroDescriptor;
+use vhdx_defs::DESCRIPTOR_SIZE;
+use vhdx_defs::LOG_SECTOR_SIZE;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+
+const ENTRY_HEADER_SIZE: usize = size_of::<LogEntryHeader>();
+
+/// An error replaying the log.
+#[derive(Debug, thiserror::Error)]
+pub enum ReplayError {
+    #[error("io error")]
+    Io(#[from] io::Error),
+    #[error("file is smaller than the log's flushed file offset")]
+    TruncatedFile,
+}
+
+/// A validated log entry.
+struct Entry {
+    offset: u32,
+    header: LogEntryHeader,
+    writes: Vec<Write>,
+}
+
+enum Write {
+    Data {
+        file_offset: u64,
+        data: Box<[u8; LOG_SECTOR_SIZE]>,
+    },
+    Zero {
+        file_offset: u64,
+        length: u64,
+    },
+}
+
+/// Reads `len` bytes starting at `offset` in the circular log buffer.
+fn read_circular(log: &[u8], offset: usize, len: usize) -> Option<Vec<u8>> {
+    if len > log.len() {
+        return None;
+    }
+    let first = (log.len() - offset).min(len);
+    let mut data = Vec::with_capacity(len);
+    data.extend_from_slice(&log[offset..offset + first]);
+    data.extend_from_slice(&log[..len - first]);
+    Some(data)
+}
+
+/// Parses and validates the entry at `offset`, returning `None` if there is no
+/// valid entry for this log there.
+fn parse_entry(log: &[u8], offset: usize, log_guid: Guid) -> Option<Entry> {
+    let header = LogEntryHeader::read_from_prefix(&read_circular(log, offset, LOG_SECTOR_SIZE)?)?;
+    if header.signature != LogEntryHeader::SIGNATURE || header.log_guid != log_guid {
+        return None;
+    }
+    let len = header.entry_length as usize;
+    if len == 0 || len % LOG_SECTOR_SIZE != 0 {
+        return None;
+    }
+    let mut data = read_circular(log, offset, len)?;
+    data[4..8].fill(0);
+    if crc32c(&data) != header.checksum {
+        return None;
+    }
+
+    let count = header.descriptor_count as usize;
+    let descriptor_sectors =
+        (ENTRY_HEADER_SIZE + count * DESCRIPTOR_SIZE).div_ceil(LOG_SECTOR_SIZE);
+    let mut data_sector = descriptor_sectors;
+    let mut writes = Vec::with_capacity(count);
+    for i in 0..count {
+        let desc = data.get(ENTRY_HEADER_SIZE + i * DESCRIPTOR_SIZE..)?;
+        let signature = u32::read_from_prefix(desc)?;
+        if signature == ZeroDescriptor::SIGNATURE {
+            let desc = ZeroDescriptor::read_from_prefix(desc)?;
+            if desc.sequence_number != header.sequence_number
+                || desc.file_offset % LOG_SECTOR_SIZE as u64 != 0
+                || desc.zero_length % LOG_SECTOR_SIZE as u64 != 0
+            {
+                return None;
+            }
+            writes.push(Write::Zero {
+                file_offset: desc.file_offset,
+                length: desc.zero_length,
+            });
+        } else if signature == DataDescriptor::SIGNATURE {
+            let desc = DataDescriptor::read_from_prefix(desc)?;
+            let sector = DataSector::read_from(
+                data.get(data_sector * LOG_SECTOR_SIZE..)?
+                    .get(..LOG_SECTOR_SIZE)?,
+            )?;
+            let sector_sequence = (sector.sequence_high as u64) << 32 | sector.sequence_low as u64;
+            if desc.sequence_number != header.sequence_number
+                || desc.file_offset % LOG_SECTOR_SIZE as u64 != 0
+                || sector.signature != DataSector::SIGNATURE
+                || sector_sequence != header.sequence_number
+            {
+                return None;
+            }
+            let mut buf = Box::new([0; LOG_SECTOR_SIZE]);
+            buf[..8].copy_from_slice(&desc.leading_bytes);
+            buf[8..LOG_SECTOR_SIZE - 4].copy_from_slice(&sector.data);
+            buf[LOG_SECTOR_SIZE - 4..].copy_from_slice(&desc.trailing_bytes);
+            writes.push(Write::Data {
+                file_offset: desc.file_offset,
+                data: buf,
+            });
+            data_sector += 1;
+        } else {
+            return None;
+        }
+    }
+    if data_sector * LOG_SECTOR_SIZE > len {
+        return None;
+    }
+    Some(Entry {
+        offset: offset as u32,
+        header,
+        writes,
+    })
+}
+
+/// Finds the active sequence in the log: the sequence of consecutive entries
+/// with the highest sequence number whose head's tail points into the
+/// sequence. Returns the entries from the tail to the head.
+fn find_active_sequence(log: &[u8], log_guid: Guid) -> Option<Vec<Entry>> {
+    let mut best: Option<Vec<Entry>> = None;
+    for start in (0..log.len()).step_by(LOG_SECTOR_SIZE) {
+        let Some(first) = parse_entry(log, start, log_guid) else {
+            continue;
+        };
+        let mut sequence = vec![first];
+        loop {
+            let last = sequence.last().unwrap();
+            let next = (last.offset as usize + last.header.entry_length as usize) % log.len();
+            match parse_entry(log, next, log_guid) {
+                Some(entry) if entry.header.sequence_number == last.header.sequence_number + 1 => {
+                    sequence.push(entry)
+                }
+                _ => break,
+            }
+        }
+        let head = &sequence.last().unwrap().header;
+        let Some(tail) = sequence.iter().position(|e| e.offset == head.tail) else {
+            continue;
+        };
+        let head_sequence = head.sequence_number;
+        if best.as_ref().map_or(true, |best| {
+            best.last().unwrap().header.sequence_number < head_sequence
+        }) {
+            sequence.drain(..tail);
+            best = Some(sequence);
+        }
+    }
+    best
+}
+
+/// Replays the active sequence of the log for `log_guid`, if any.
+///
+/// Returns true if any entries were replayed.
+pub fn replay(
+    file: &File,
+    log_offset: u64,
+    log_length: u32,
+    log_guid: Guid,
+) -> Result<bool, ReplayError> {
+    let mut log = vec![0; log_length as usize];
+    file.read_exact_at(&mut log, log_offset)?;
+    let Some(entries) = find_active_sequence(&log, log_guid) else {
+        return Ok(false);
+    };
+    let head = entries.last().unwrap().header;
+    if file.metadata()?.len() < head.flushed_file_offset {
+        return Err(ReplayError::TruncatedFile);
+    }
+    tracing::info!(
+        entries = entries.len(),
+        sequence_number = head.sequence_number,
+        "replaying vhdx log"
+    );
+    let zeroes = vec![0; 64 * LOG_SECTOR_SIZE];
+    for entry in &entries {
+        for write in &entry.writes {
+            match write {
+                Write::Data { file_offset, data } => file.write_all_at(&data[..], *file_offset)?,
+                Write::Zero {
+                    file_offset,
+                    length,
+                } => {
+                    let mut offset = *file_offset;
+                    let end = file_offset + length;
+                    while offset < end {
+                        let n = (end - offset).min(zeroes.len() as u64) as usize;
+                        file.write_all_at(&zeroes[..n], offset)?;
+                        offset += n as u64;
+                    }
+                }
+            }
+        }
+    }
+    if file.metadata()?.len() < head.last_file_offset {
+        file.set_len(head.last_file_offset)?;
+    }
+    file.sync_all()?;
+    Ok(true)
+}
+
+/// Writes metadata updates through the log.
+///
+/// Each update is written as a single-entry sequence at the start of the log
+/// and applied in place immediately after it is flushed, so the log never
+/// holds more than one entry that still needs to be replayed.
+#[derive(Debug)]
+pub struct LogWriter {
+    offset: u64,
+    length: u32,
+    guid: Guid,
+    sequence_number: u64,
+}
+
+impl LogWriter {
+    pub fn new(offset: u64, length: u32) -> Self {
+        Self {
+            offset,
+            length,
+            guid: Guid::new_random(),
+            sequence_number: 0,
+        }
+    }
+
+    /// The log GUID, which must be stored in the header before any entries are
+    /// written.
+    pub fn guid(&self) -> Guid {
+        self.guid
+    }
+
+    /// The maximum number of pages that can be written in one update.
+    pub fn max_pages(&self) -> usize {
+        let sectors = self.length as usize / LOG_SECTOR_SIZE;
+        // Leave room for one descriptor sector.
+        sectors
+            .saturating_sub(1)
+            .min((LOG_SECTOR_SIZE - ENTRY_HEADER_SIZE) / DESCRIPTOR_SIZE)
+    }
+
+    /// Writes `pages` (pairs of 4KB-aligned file offset and contents) to the
+    /// log, flushes, and then writes them in place.
+    ///
+    /// `file_size` must be the current (flushed) file size.
+    pub fn commit(
+        &mut self,
+        file: &File,
+        pages: &[(u64, &[u8; LOG_SECTOR_SIZE])],
+        file_size: u64,
+    ) -> io::Result<()> {
+        let entry = self.build_entry(pages, file_size);
+        file.write_all_at(&entry, self.offset)?;
+        file.sync_data()?;
+        for (offset, page) in pages {
+            file.write_all_at(&page[..], *offset)?;
+        }
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Writes `pages` to the log without applying them, simulating a crash
+    /// between the two steps of [`Self::commit`].
+    #[cfg(test)]
+    pub fn write_only(
+        &mut self,
+        file: &File,
+        pages: &[(u64, &[u8; LOG_SECTOR_SIZE])],
+        file_size: u64,
+    ) -> io::Result<()> {
+        let entry = self.build_entry(pages, file_size);
+        file.write_all_at(&entry, self.offset)
+    }
+
+    fn build_entry(&mut self, pages: &[(u64, &[u8; LOG_SECTOR_SIZE])], file_size: u64) -> Vec<u8> {
+        assert!(pages.len() <= self.max_pages());
+        self.sequence_number += 1;
+        let sequence_number = self.sequence_number;
+        let descriptor_sectors =
+            (ENTRY_HEADER_SIZE + pages.len() * DESCRIPTOR_SIZE).div_ceil(LOG_SECTOR_SIZE);
+        let len = (descriptor_sectors + pages.len()) * LOG_SECTOR_SIZE;
+        let mut entry = vec![0; len];
+        LogEntryHeader {
+            signature: LogEntryHeader::SIGNATURE,
+            checksum: 0,
+            entry_length: len as u32,
+            tail: 0,
+            sequence_number,
+            descriptor_count: pages.len() as u32,
+            reserved: 0,
+            log_guid: self.guid,
+            flushed_file_offset: file_size,
+            last_file_offset: file_size,
+        }
+        .write_to_prefix(&mut entry)
+        .unwrap();
+        for (i, (file_offset, page)) in pages.iter().enumerate() {
+            DataDescriptor {
+                signature: DataDescriptor::SIGNATURE,
+                trailing_bytes: page[LOG_SECTOR_SIZE - 4..].try_into().unwrap(),
+                leading_bytes: page[..8].try_into().unwrap(),
+                file_offset: *file_offset,
+                sequence_number,
+            }
+            .write_to_prefix(&mut entry[ENTRY_HEADER_SIZE + i * DESCRIPTOR_SIZE..])
+            .unwrap();
+            DataSector {
+                signature: DataSector::SIGNATURE,
+                sequence_high: (sequence_number >> 32) as u32,
+                data: page[8..LOG_SECTOR_SIZE - 4].try_into().unwrap(),
+                sequence_low: sequence_number as u32,
+            }
+            .write_to_prefix(&mut entry[(descriptor_sectors + i) * LOG_SECTOR_SIZE..])
+            .unwrap();
+        }
+        let checksum = crc32c(&entry);
+        entry[4..8].copy_from_slice(checksum.as_bytes());
+        entry
+    }
+}
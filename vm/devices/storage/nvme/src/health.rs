@@ -0,0 +1,174 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Controller health state, reported via the SMART / health information and
+//! error information log pages.
+//!
+//! The IO counters reflect actual guest activity. The remaining values are
+//! synthetic, and the ones that monitoring software typically alerts on can be
+//! changed via inspect to inject faults.
+
+use crate::spec;
+use inspect::AtomicMut;
+use inspect::Inspect;
+use parking_lot::Mutex;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicU16;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering;
+use std::time::Instant;
+use zerocopy::AsBytes;
+use zerocopy::FromZeroes;
+
+/// The number of error log entries retained.
+pub const ERROR_LOG_ENTRIES: usize = 16;
+
+/// The warning composite temperature threshold, in Kelvin (70 C).
+pub const WARNING_TEMPERATURE: u16 = 343;
+/// The critical composite temperature threshold, in Kelvin (85 C).
+pub const CRITICAL_TEMPERATURE: u16 = 358;
+
+const DEFAULT_TEMPERATURE: u16 = 313;
+const AVAILABLE_SPARE_THRESHOLD: u8 = 10;
+
+pub struct HealthState {
+    start_time: Instant,
+    sectors_read: AtomicU64,
+    sectors_written: AtomicU64,
+    host_read_commands: AtomicU64,
+    host_write_commands: AtomicU64,
+    media_errors: AtomicU64,
+    critical_warning: AtomicU8,
+    temperature: AtomicU16,
+    available_spare: AtomicU8,
+    percentage_used: AtomicU8,
+    error_log: Mutex<ErrorLog>,
+}
+
+#[derive(Default)]
+struct ErrorLog {
+    error_count: u64,
+    entries: VecDeque<spec::ErrorInformationLogEntry>,
+}
+
+impl Inspect for HealthState {
+    fn inspect(&self, req: inspect::Request<'_>) {
+        req.respond()
+            .field("sectors_read", &self.sectors_read)
+            .field("sectors_written", &self.sectors_written)
+            .field("host_read_commands", &self.host_read_commands)
+            .field("host_write_commands", &self.host_write_commands)
+            .field("media_errors", &self.media_errors)
+            .field("error_count", self.error_log.lock().error_count)
+            .field("power_on_hours", self.power_on_hours())
+            .field("critical_warning", AtomicMut(&self.critical_warning))
+            .field("temperature", AtomicMut(&self.temperature))
+            .field("available_spare", AtomicMut(&self.available_spare))
+            .field("percentage_used", AtomicMut(&self.percentage_used));
+    }
+}
+
+impl HealthState {
+    pub fn new() -> Self {
+        Self {
+            start_time: Instant::now(),
+            sectors_read: AtomicU64::new(0),
+            sectors_written: AtomicU64::new(0),
+            host_read_commands: AtomicU64::new(0),
+            host_write_commands: AtomicU64::new(0),
+            media_errors: AtomicU64::new(0),
+            critical_warning: AtomicU8::new(0),
+            temperature: AtomicU16::new(DEFAULT_TEMPERATURE),
+            available_spare: AtomicU8::new(100),
+            percentage_used: AtomicU8::new(0),
+            error_log: Default::default(),
+        }
+    }
+
+    /// Records a completed read of `bytes` bytes.
+    pub fn record_read(&self, bytes: usize) {
+        self.host_read_commands.fetch_add(1, Ordering::Relaxed);
+        self.sectors_read
+            .fetch_add((bytes / 512) as u64, Ordering::Relaxed);
+    }
+
+    /// Records a completed write of `bytes` bytes.
+    pub fn record_write(&self, bytes: usize) {
+        self.host_write_commands.fetch_add(1, Ordering::Relaxed);
+        self.sectors_written
+            .fetch_add((bytes / 512) as u64, Ordering::Relaxed);
+    }
+
+    /// Records a failed command in the error log.
+    pub fn record_error(&self, sqid: u16, cid: u16, nsid: u32, status: spec::Status) {
+        if status.status_code_type() == spec::StatusCodeType::MEDIA_ERROR {
+            self.media_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        let mut log = self.error_log.lock();
+        log.error_count += 1;
+        let entry = spec::ErrorInformationLogEntry {
+            error_count: log.error_count,
+            sqid,
+            cid,
+            status: spec::CompletionStatus::new().with_status(status.0),
+            // Not available.
+            parameter_error_location: 0xffff,
+            nsid,
+            ..FromZeroes::new_zeroed()
+        };
+        if log.entries.len() == ERROR_LOG_ENTRIES {
+            log.entries.pop_back();
+        }
+        log.entries.push_front(entry);
+    }
+
+    fn power_on_hours(&self) -> u64 {
+        self.start_time.elapsed().as_secs() / 3600
+    }
+
+    /// Returns the SMART / health information log page.
+    pub fn smart_log(&self) -> spec::SmartHealthInformation {
+        // Data units are thousands of 512-byte units, rounded up.
+        let data_units = |sectors: &AtomicU64| sectors.load(Ordering::Relaxed).div_ceil(1000);
+        let temperature = self.temperature.load(Ordering::Relaxed);
+        let available_spare = self.available_spare.load(Ordering::Relaxed);
+
+        // Derive the spare and temperature warnings from the current values,
+        // in addition to any bits that were injected directly.
+        let injected = spec::CriticalWarning::from(self.critical_warning.load(Ordering::Relaxed));
+        let critical_warning = injected
+            .with_available_spare(
+                injected.available_spare() || available_spare < AVAILABLE_SPARE_THRESHOLD,
+            )
+            .with_temperature(injected.temperature() || temperature >= WARNING_TEMPERATURE);
+
+        spec::SmartHealthInformation {
+            critical_warning,
+            composite_temperature: temperature.to_le_bytes(),
+            available_spare,
+            available_spare_threshold: AVAILABLE_SPARE_THRESHOLD,
+            percentage_used: self.percentage_used.load(Ordering::Relaxed),
+            data_units_read: (data_units(&self.sectors_read) as u128).into(),
+            data_units_written: (data_units(&self.sectors_written) as u128).into(),
+            host_read_commands: (self.host_read_commands.load(Ordering::Relaxed) as u128).into(),
+            host_write_commands: (self.host_write_commands.load(Ordering::Relaxed) as u128).into(),
+            power_cycles: 1u128.into(),
+            power_on_hours: (self.power_on_hours() as u128).into(),
+            media_errors: (self.media_errors.load(Ordering::Relaxed) as u128).into(),
+            error_log_entries: (self.error_log.lock().error_count as u128).into(),
+            ..FromZeroes::new_zeroed()
+        }
+    }
+
+    /// Returns the error information log entries, most recent first.
+    pub fn error_log(&self) -> Vec<u8> {
+        self.error_log
+            .lock()
+            .entries
+            .iter()
+            .flat_map(|entry| entry.as_bytes())
+            .copied()
+            .collect()
+    }
+}
@@ -7,6 +7,7 @@
 #![warn(missing_docs)]
 
 mod error;
+mod health;
 mod namespace;
 mod pci;
 mod prp;
@@ -7,17 +7,23 @@
 
 use crate::error::CommandResult;
 use crate::error::NvmeError;
+use crate::health::HealthState;
 use crate::prp::PrpRange;
 use crate::spec;
 use crate::spec::nvm;
 use disk_backend::Disk;
+use disk_backend::UnmapBehavior;
 use guestmem::GuestMemory;
 use inspect::Inspect;
 use scsi_buffers::RequestBuffers;
+use std::sync::Arc;
 use zerocopy::AsBytes;
 use zerocopy::FromBytes;
 use zerocopy::FromZeroes;
 
+/// DLFEAT value indicating that deallocated blocks read as zeroes.
+const DLFEAT_READ_ZEROES: u8 = 0x1;
+
 /// An NVMe namespace built on top of a [`Disk`].
 #[derive(Inspect)]
 pub struct Namespace {
@@ -26,16 +32,19 @@ pub struct Namespace {
     mem: GuestMemory,
     block_shift: u32,
     pr: bool,
+    #[inspect(skip)]
+    health: Arc<HealthState>,
 }
 
 impl Namespace {
-    pub fn new(mem: GuestMemory, nsid: u32, disk: Disk) -> Self {
+    pub fn new(mem: GuestMemory, nsid: u32, disk: Disk, health: Arc<HealthState>) -> Self {
         Self {
             block_shift: disk.sector_size().trailing_zeros(),
             pr: disk.pr().is_some(),
             mem,
             disk,
             nsid,
+            health,
         }
     }
 
@@ -56,13 +65,38 @@ pub fn identify(&self, buf: &mut [u8]) {
             nvm::ReservationCapabilities::new()
         };
 
+        // Report the deallocate granularity and read behavior so that the
+        // guest can issue deallocates that the backing store can act on.
+        let (nsfeat, dlfeat, npdg) = match self.disk.unmap_behavior() {
+            UnmapBehavior::Ignored => (nvm::Nsfeat::new(), 0, 0),
+            behavior => {
+                let npdg = (self.disk.optimal_unmap_sectors().max(1) - 1)
+                    .try_into()
+                    .unwrap_or(u16::MAX);
+                let dlfeat = if behavior == UnmapBehavior::Zeroes {
+                    DLFEAT_READ_ZEROES
+                } else {
+                    0
+                };
+                (
+                    nvm::Nsfeat::new().with_thinp(true).with_optperf(true),
+                    dlfeat,
+                    npdg,
+                )
+            }
+        };
+
         *id = nvm::IdentifyNamespace {
             nsze: size,
             ncap: size,
             nuse: size,
+            nsfeat,
             nlbaf: 0,
             flbas: nvm::Flbas::new().with_low_index(0),
             rescap,
+            dlfeat,
+            npdg,
+            npda: npdg,
             ..FromZeroes::new_zeroed()
         };
         id.lbaf[0] = nvm::Lbaf::new().with_lbads(self.block_shift as u8);
@@ -145,6 +179,7 @@ pub async fn nvm_command(
                     .read_vectored(&buffers, lba)
                     .await
                     .map_err(map_disk_error)?;
+                self.health.record_read(byte_count);
             }
             nvm::NvmOpcode::WRITE => {
                 let cdw10 = nvm::Cdw10ReadWrite::from(command.cdw10);
@@ -170,6 +205,7 @@ pub async fn nvm_command(
                     .write_vectored(&buffers, lba, cdw12.fua())
                     .await
                     .map_err(map_disk_error)?;
+                self.health.record_write(byte_count);
             }
             nvm::NvmOpcode::FLUSH => {
                 tracing::debug!(nsid = self.nsid, "flush");
@@ -185,8 +221,21 @@ pub async fn nvm_command(
                     PrpRange::parse(&self.mem, size_of_val(dsm_ranges.as_ref()), command.dptr)?;
                 prp.read(&self.mem, dsm_ranges.as_bytes_mut())?;
                 tracing::debug!(nsid = self.nsid, ?cdw11, ?dsm_ranges, "dsm");
-                if cdw11.ad() {
+                let disk_sector_count = self.disk.sector_count();
+                for range in dsm_ranges.as_ref() {
+                    if disk_sector_count < range.starting_lba
+                        || disk_sector_count - range.starting_lba < range.lba_count.into()
+                    {
+                        return Err(spec::Status::LBA_OUT_OF_RANGE.into());
+                    }
+                }
+                // Deallocation is advisory, so there is no need to pass it
+                // down to disks that ignore it.
+                if cdw11.ad() && self.disk.unmap_behavior() != UnmapBehavior::Ignored {
                     for range in dsm_ranges.as_ref() {
+                        if range.lba_count == 0 {
+                            continue;
+                        }
                         self.disk
                             .unmap(range.starting_lba, range.lba_count.into(), false)
                             .await
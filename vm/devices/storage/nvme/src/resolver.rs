@@ -6,10 +6,14 @@
 use crate::NsidConflict;
 use crate::NvmeController;
 use crate::NvmeControllerCaps;
+use crate::NvmeControllerClient;
 use async_trait::async_trait;
 use disk_backend::resolve::ResolveDiskParameters;
+use futures::StreamExt;
 use nvme_resources::NamespaceDefinition;
 use nvme_resources::NvmeControllerHandle;
+use nvme_resources::NvmeControllerRequest;
+use pal_async::task::Spawn;
 use pci_resources::ResolvePciDeviceHandleParams;
 use pci_resources::ResolvedPciDevice;
 use thiserror::Error;
@@ -18,6 +22,7 @@
 use vm_resource::AsyncResolveResource;
 use vm_resource::ResolveError;
 use vm_resource::ResourceResolver;
+use vmcore::vm_task::VmTaskDriverSource;
 
 /// Resource resolver for [`NvmeControllerHandle`].
 pub struct NvmeControllerResolver;
@@ -39,6 +44,8 @@ pub enum Error {
     },
     #[error(transparent)]
     NsidConflict(NsidConflict),
+    #[error("namespace {0} not found")]
+    NamespaceNotFound(u32),
 }
 
 #[async_trait]
@@ -74,7 +81,7 @@ async fn resolve(
                     disk,
                     ResolveDiskParameters {
                         read_only,
-                        _async_trait_workaround: &(),
+                        driver_source: input.driver_source,
                     },
                 )
                 .await
@@ -85,6 +92,75 @@ async fn resolve(
                 .await
                 .map_err(Error::NsidConflict)?;
         }
+
+        if let Some(requests) = resource.requests {
+            input
+                .driver_source
+                .simple()
+                .spawn(
+                    "nvme-requests",
+                    handle_requests(
+                        controller.client(),
+                        resolver.clone(),
+                        input.driver_source.clone(),
+                        requests,
+                    ),
+                )
+                .detach();
+        }
+
         Ok(controller.into())
     }
 }
+
+async fn handle_requests(
+    client: NvmeControllerClient,
+    resolver: ResourceResolver,
+    driver_source: VmTaskDriverSource,
+    mut requests: mesh::Receiver<NvmeControllerRequest>,
+) {
+    while let Some(req) = requests.next().await {
+        match req {
+            NvmeControllerRequest::AddNamespace(rpc) => {
+                rpc.handle_failable(
+                    |NamespaceDefinition {
+                         nsid,
+                         read_only,
+                         disk,
+                     }| {
+                        let resolver = &resolver;
+                        let driver_source = &driver_source;
+                        let client = &client;
+                        async move {
+                            let disk = resolver
+                                .resolve(
+                                    disk,
+                                    ResolveDiskParameters {
+                                        read_only,
+                                        driver_source,
+                                    },
+                                )
+                                .await
+                                .map_err(|source| Error::NamespaceResolve { nsid, source })?;
+                            client
+                                .add_namespace(nsid, disk.0)
+                                .await
+                                .map_err(Error::NsidConflict)
+                        }
+                    },
+                )
+                .await
+            }
+            NvmeControllerRequest::RemoveNamespace(rpc) => {
+                rpc.handle_failable(|nsid| async {
+                    if client.remove_namespace(nsid).await {
+                        Ok(())
+                    } else {
+                        Err(Error::NamespaceNotFound(nsid))
+                    }
+                })
+                .await
+            }
+        }
+    }
+}
@@ -2,5 +2,6 @@
 // Licensed under the MIT License.
 
 mod controller_tests;
+mod namespace_tests;
 mod shadow_doorbell_tests;
 mod test_helpers;
@@ -326,3 +326,47 @@ async fn test_send_identify(driver: DefaultDriver) {
     let cqe = read_completion_from_queue(&gm, &dm1, 0);
     assert_eq!(cqe.status.status(), spec::Status::SUCCESS.0);
 }
+
+#[async_test]
+async fn test_get_health_log_page(driver: DefaultDriver) {
+    let dm1 = PrpRange::new(vec![0], 0, PAGE_SIZE64).unwrap();
+    let dm2 = PrpRange::new(vec![0x1000], 0, PAGE_SIZE64).unwrap();
+    let gm = test_memory();
+    let int_controller = TestPciInterruptController::new();
+
+    let mut nvmec = instantiate_and_build_admin_queue(
+        &dm1,
+        64,
+        &dm2,
+        64,
+        true,
+        Some(&int_controller),
+        driver.clone(),
+        &gm,
+    )
+    .await;
+
+    let mut entry = spec::Command::new_zeroed();
+    entry.cdw0.set_opcode(spec::AdminOpcode::GET_LOG_PAGE.0);
+    entry.nsid = !0;
+    let cdw10 = spec::Cdw10GetLogPage::new()
+        .with_lid(spec::LogPageIdentifier::HEALTH_INFORMATION.0)
+        .with_numdl_z((size_of::<spec::SmartHealthInformation>() / 4 - 1) as u16);
+    entry.cdw10 = u32::from(cdw10);
+    entry.dptr[0] = 0x3000;
+
+    write_command_to_queue(&gm, &dm2, 0, &entry);
+    nvmec.write_bar0(0x1000, 1u32.as_bytes()).unwrap();
+
+    wait_for_msi(driver.clone(), &int_controller, 1000, 0xfeed0000, 0x1111).await;
+
+    let cqe = read_completion_from_queue(&gm, &dm1, 0);
+    assert_eq!(cqe.status.status(), spec::Status::SUCCESS.0);
+
+    let page: spec::SmartHealthInformation = gm.read_plain(0x3000).unwrap();
+    assert_eq!(u8::from(page.critical_warning), 0);
+    assert!(u16::from_le_bytes(page.composite_temperature) > 273);
+    assert_eq!(page.available_spare, 100);
+    assert!(page.available_spare > page.available_spare_threshold);
+    assert_eq!(page.power_cycles.get(), 1);
+}
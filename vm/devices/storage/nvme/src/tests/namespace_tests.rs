@@ -0,0 +1,202 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use crate::health::HealthState;
+use crate::namespace::Namespace;
+use crate::spec;
+use crate::spec::nvm;
+use crate::tests::test_helpers::test_memory;
+use crate::PAGE_SIZE64;
+use disk_backend::Disk;
+use disk_backend::DiskError;
+use disk_backend::DiskIo;
+use disk_backend::UnmapBehavior;
+use guestmem::GuestMemory;
+use inspect::Inspect;
+use pal_async::async_test;
+use parking_lot::Mutex;
+use scsi_buffers::RequestBuffers;
+use std::sync::Arc;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+const SECTOR_COUNT: u64 = 1024;
+
+/// A disk that records the unmaps issued to it.
+#[derive(Inspect)]
+struct UnmapDisk {
+    #[inspect(skip)]
+    unmap_behavior: UnmapBehavior,
+    #[inspect(skip)]
+    unmaps: Arc<Mutex<Vec<(u64, u64)>>>,
+}
+
+impl DiskIo for UnmapDisk {
+    fn disk_type(&self) -> &str {
+        "test"
+    }
+
+    fn sector_count(&self) -> u64 {
+        SECTOR_COUNT
+    }
+
+    fn sector_size(&self) -> u32 {
+        512
+    }
+
+    fn disk_id(&self) -> Option<[u8; 16]> {
+        None
+    }
+
+    fn physical_sector_size(&self) -> u32 {
+        4096
+    }
+
+    fn is_fua_respected(&self) -> bool {
+        false
+    }
+
+    fn is_read_only(&self) -> bool {
+        false
+    }
+
+    async fn read_vectored(
+        &self,
+        _buffers: &RequestBuffers<'_>,
+        _sector: u64,
+    ) -> Result<(), DiskError> {
+        unreachable!()
+    }
+
+    async fn write_vectored(
+        &self,
+        _buffers: &RequestBuffers<'_>,
+        _sector: u64,
+        _fua: bool,
+    ) -> Result<(), DiskError> {
+        unreachable!()
+    }
+
+    async fn sync_cache(&self) -> Result<(), DiskError> {
+        Ok(())
+    }
+
+    async fn unmap(
+        &self,
+        sector: u64,
+        count: u64,
+        _block_level_only: bool,
+    ) -> Result<(), DiskError> {
+        self.unmaps.lock().push((sector, count));
+        Ok(())
+    }
+
+    fn unmap_behavior(&self) -> UnmapBehavior {
+        self.unmap_behavior
+    }
+
+    fn optimal_unmap_sectors(&self) -> u32 {
+        8
+    }
+}
+
+fn namespace(
+    mem: &GuestMemory,
+    unmap_behavior: UnmapBehavior,
+) -> (Namespace, Arc<Mutex<Vec<(u64, u64)>>>) {
+    let unmaps = Arc::new(Mutex::new(Vec::new()));
+    let disk = Disk::new(UnmapDisk {
+        unmap_behavior,
+        unmaps: unmaps.clone(),
+    })
+    .unwrap();
+    let namespace = Namespace::new(mem.clone(), 1, disk, Arc::new(HealthState::new()));
+    (namespace, unmaps)
+}
+
+fn identify(namespace: &Namespace) -> nvm::IdentifyNamespace {
+    let mut buf = [0; 4096];
+    namespace.identify(&mut buf);
+    nvm::IdentifyNamespace::read_from_prefix(&buf[..]).unwrap()
+}
+
+/// Issues a deallocate for `ranges`, staging them at the start of `mem`.
+async fn deallocate(
+    namespace: &Namespace,
+    mem: &GuestMemory,
+    ranges: &[(u64, u32)],
+) -> Result<(), crate::error::NvmeError> {
+    let ranges: Vec<_> = ranges
+        .iter()
+        .map(|&(starting_lba, lba_count)| nvm::DsmRange {
+            context_attributes: 0,
+            lba_count,
+            starting_lba,
+        })
+        .collect();
+    mem.write_at(0, ranges.as_bytes()).unwrap();
+    let command = spec::Command {
+        cdw0: spec::Cdw0::new().with_opcode(nvm::NvmOpcode::DSM.0),
+        nsid: 1,
+        dptr: [0, 0],
+        cdw10: nvm::Cdw10Dsm::new()
+            .with_nr_z((ranges.len() - 1) as u8)
+            .into(),
+        cdw11: nvm::Cdw11Dsm::new().with_ad(true).into(),
+        ..FromZeroes::new_zeroed()
+    };
+    namespace
+        .nvm_command(PAGE_SIZE64 as usize, &command)
+        .await
+        .map(drop)
+}
+
+#[test]
+fn test_identify_deallocate() {
+    let mem = test_memory();
+
+    let (ns, _) = namespace(&mem, UnmapBehavior::Ignored);
+    let id = identify(&ns);
+    assert!(!id.nsfeat.thinp());
+    assert_eq!(id.dlfeat, 0);
+    assert_eq!(id.npdg, 0);
+
+    let (ns, _) = namespace(&mem, UnmapBehavior::Unspecified);
+    let id = identify(&ns);
+    assert!(id.nsfeat.thinp() && id.nsfeat.optperf());
+    assert_eq!(id.dlfeat, 0);
+    assert_eq!(id.npdg, 7);
+    assert_eq!(id.npda, 7);
+
+    let (ns, _) = namespace(&mem, UnmapBehavior::Zeroes);
+    let id = identify(&ns);
+    assert_eq!(id.dlfeat, 1);
+}
+
+#[async_test]
+async fn test_deallocate() {
+    let mem = test_memory();
+    let (ns, unmaps) = namespace(&mem, UnmapBehavior::Unspecified);
+
+    // Empty ranges are skipped.
+    deallocate(&ns, &mem, &[(0, 8), (16, 0), (SECTOR_COUNT - 8, 8)])
+        .await
+        .unwrap();
+    assert_eq!(*unmaps.lock(), [(0, 8), (SECTOR_COUNT - 8, 8)]);
+    unmaps.lock().clear();
+
+    // A range past the end of the disk fails the whole command.
+    deallocate(&ns, &mem, &[(0, 8), (SECTOR_COUNT - 8, 9)])
+        .await
+        .unwrap_err();
+    assert!(unmaps.lock().is_empty());
+}
+
+#[async_test]
+async fn test_deallocate_ignored() {
+    let mem = test_memory();
+    let (ns, unmaps) = namespace(&mem, UnmapBehavior::Ignored);
+    deallocate(&ns, &mem, &[(0, 8)]).await.unwrap();
+    assert!(unmaps.lock().is_empty());
+}
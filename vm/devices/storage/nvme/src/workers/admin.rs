@@ -9,6 +9,8 @@
 use super::MAX_DATA_TRANSFER_SIZE;
 use crate::error::CommandResult;
 use crate::error::NvmeError;
+use crate::health;
+use crate::health::HealthState;
 use crate::namespace::Namespace;
 use crate::prp::PrpRange;
 use crate::queue::CompletionQueue;
@@ -56,7 +58,7 @@
 const IOSQES: u8 = 6;
 const IOCQES: u8 = 4;
 const MAX_ASYNC_EVENT_REQUESTS: u8 = 4; // minimum recommended by spec
-const ERROR_LOG_PAGE_ENTRIES: u8 = 1;
+const FIRMWARE_REVISION: [u8; 8] = *b"v1.00000";
 
 #[derive(Inspect)]
 pub struct AdminConfig {
@@ -81,6 +83,7 @@ pub struct AdminHandler {
     config: AdminConfig,
     #[inspect(iter_by_key)]
     namespaces: BTreeMap<u32, Arc<Namespace>>,
+    health: Arc<HealthState>,
 }
 
 #[derive(Inspect)]
@@ -221,6 +224,7 @@ fn set_max_queues(&mut self, handler: &AdminHandler, num_sqs: u16, num_cqs: u16)
                         handler.config.mem.clone(),
                         i as u16 + 1,
                         self.sq_delete_response.sender(),
+                        handler.health.clone(),
                     )),
                     pending_delete_cid: None,
                     cqid: None,
@@ -359,6 +363,7 @@ pub fn new(driver: VmTaskDriver, config: AdminConfig) -> Self {
             driver,
             config,
             namespaces: Default::default(),
+            health: Arc::new(HealthState::new()),
         }
     }
 
@@ -373,6 +378,7 @@ pub async fn add_namespace(
                 self.config.mem.clone(),
                 nsid,
                 disk,
+                self.health.clone(),
             ))),
             btree_map::Entry::Occupied(_) => return Err(NsidConflict(nsid)),
         };
@@ -510,7 +516,14 @@ async fn process_event(
                             ?opcode,
                             "command error"
                         );
-                        err.into()
+                        let result = CommandResult::from(err);
+                        self.health.record_error(
+                            0,
+                            command.cdw0.cid(),
+                            command.nsid,
+                            result.status,
+                        );
+                        result
                     }
                 };
 
@@ -627,11 +640,13 @@ fn identify_controller(&self) -> spec::IdentifyController {
             frmw: spec::FirmwareUpdates::new().with_ffsro(true).with_nofs(1),
             nn: self.namespaces.keys().copied().max().unwrap_or(0),
             ieee: [0x74, 0xe2, 0x8c], // Microsoft
-            fr: (*b"v1.00000").into(),
+            fr: FIRMWARE_REVISION.into(),
             mn: (*b"MSFT NVMe Accelerator v1.0              ").into(),
             sn: (*b"SN: 000001          ").into(),
             aerl: MAX_ASYNC_EVENT_REQUESTS - 1,
-            elpe: ERROR_LOG_PAGE_ENTRIES - 1,
+            elpe: health::ERROR_LOG_ENTRIES as u8 - 1,
+            wctemp: health::WARNING_TEMPERATURE,
+            cctemp: health::CRITICAL_TEMPERATURE,
             oaes: spec::Oaes::new().with_namespace_attribute(true),
             oncs: spec::Oncs::new()
                 .with_dataset_management(true)
@@ -714,6 +729,21 @@ async fn handle_get_features(
                     .with_wce(true)
                     .into();
             }
+            spec::Feature::TEMPERATURE_THRESHOLD => {
+                let cdw11: spec::Cdw11FeatureTemperatureThreshold = command.cdw11.into();
+                // Only the composite temperature over threshold is
+                // reported. The thresholds are not configurable.
+                let tmpth = if cdw11.tmpsel() == 0 && cdw11.thsel() == 0 {
+                    health::WARNING_TEMPERATURE
+                } else {
+                    0
+                };
+                dw[0] = spec::Cdw11FeatureTemperatureThreshold::new()
+                    .with_tmpth(tmpth)
+                    .with_tmpsel(cdw11.tmpsel())
+                    .with_thsel(cdw11.thsel())
+                    .into();
+            }
             spec::Feature::NVM_RESERVATION_PERSISTENCE => {
                 let namespace = self
                     .namespaces
@@ -969,22 +999,27 @@ fn handle_get_log_page(
 
         match spec::LogPageIdentifier(cdw10.lid()) {
             spec::LogPageIdentifier::ERROR_INFORMATION => {
-                // Write empty log entries.
-                prp.zero(
-                    &self.config.mem,
-                    len.min(ERROR_LOG_PAGE_ENTRIES as usize * 64),
-                )?;
+                // Unused entries must have an error count of zero.
+                let mut log = self.health.error_log();
+                log.resize(health::ERROR_LOG_ENTRIES * 64, 0);
+                log.truncate(len);
+                prp.write(&self.config.mem, &log)?;
             }
             spec::LogPageIdentifier::HEALTH_INFORMATION => {
-                if command.nsid != !0 {
+                // Per-namespace information is not supported (LPA bit 0 is
+                // clear), so only the controller-wide page is available.
+                if command.nsid != !0 && command.nsid != 0 {
                     return Err(spec::Status::INVALID_FIELD_IN_COMMAND.into());
                 }
-                // Write an empty page.
-                prp.zero(&self.config.mem, len.min(512))?;
+                let page = self.health.smart_log();
+                prp.write(&self.config.mem, &page.as_bytes()[..len.min(512)])?;
             }
             spec::LogPageIdentifier::FIRMWARE_SLOT_INFORMATION => {
-                // Write an empty page.
-                prp.zero(&self.config.mem, len.min(512))?;
+                let mut page = spec::FirmwareSlotInformation::new_zeroed();
+                // Slot 1 is active.
+                page.afi = 1;
+                page.frs[0] = FIRMWARE_REVISION.into();
+                prp.write(&self.config.mem, &page.as_bytes()[..len.min(512)])?;
             }
             spec::LogPageIdentifier::CHANGED_NAMESPACE_LIST => {
                 // Zero the whole list.
@@ -5,6 +5,7 @@
 
 use crate::error::CommandResult;
 use crate::error::NvmeError;
+use crate::health::HealthState;
 use crate::namespace::Namespace;
 use crate::queue::CompletionQueue;
 use crate::queue::DoorbellRegister;
@@ -36,6 +37,8 @@ pub struct IoHandler {
     sqid: u16,
     #[inspect(skip)]
     admin_response: mesh::MpscSender<u16>,
+    #[inspect(skip)]
+    health: Arc<HealthState>,
 }
 
 #[derive(Inspect)]
@@ -133,11 +136,17 @@ enum HandlerError {
 }
 
 impl IoHandler {
-    pub fn new(mem: GuestMemory, sqid: u16, admin_response: mesh::MpscSender<u16>) -> Self {
+    pub fn new(
+        mem: GuestMemory,
+        sqid: u16,
+        admin_response: mesh::MpscSender<u16>,
+        health: Arc<HealthState>,
+    ) -> Self {
         Self {
             mem,
             sqid,
             admin_response,
+            health,
         }
     }
 
@@ -215,7 +224,14 @@ enum Event {
                                 opcode = ?io_result.opcode,
                                 "io error"
                             );
-                            err.into()
+                            let result = CommandResult::from(err);
+                            self.health.record_error(
+                                self.sqid,
+                                io_result.cid,
+                                io_result.nsid,
+                                result.status,
+                            );
+                            result
                         }
                     };
                     (io_result.cid, result)
@@ -7,6 +7,7 @@
 #![warn(missing_docs)]
 
 use guid::Guid;
+use mesh::rpc::FailableRpc;
 use mesh::MeshPayload;
 use vm_resource::kind::DiskHandleKind;
 use vm_resource::kind::PciDeviceHandleKind;
@@ -24,6 +25,8 @@ pub struct NvmeControllerHandle {
     pub max_io_queues: u16,
     /// The initial set of namespaces.
     pub namespaces: Vec<NamespaceDefinition>,
+    /// Runtime request channel.
+    pub requests: Option<mesh::Receiver<NvmeControllerRequest>>,
 }
 
 impl ResourceId<PciDeviceHandleKind> for NvmeControllerHandle {
@@ -40,3 +43,12 @@ pub struct NamespaceDefinition {
     /// The backing disk resource.
     pub disk: Resource<DiskHandleKind>,
 }
+
+/// A runtime request to the NVMe controller.
+#[derive(MeshPayload)]
+pub enum NvmeControllerRequest {
+    /// Add a namespace.
+    AddNamespace(FailableRpc<NamespaceDefinition, ()>),
+    /// Remove a namespace, by namespace ID.
+    RemoveNamespace(FailableRpc<u32, ()>),
+}
@@ -787,3 +787,101 @@ pub enum AsynchronousEventInformationNotice: u8 {
         ENDURANCE_GROUP_EVENT_AGGREGATE_LOG_PAGE_CHANGE = 6,
     }
 }
+
+open_enum! {
+    pub enum AsynchronousEventInformationHealth: u8 {
+        NVM_SUBSYSTEM_RELIABILITY = 0,
+        TEMPERATURE_THRESHOLD = 1,
+        SPARE_BELOW_THRESHOLD = 2,
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct ErrorInformationLogEntry {
+    pub error_count: u64,
+    pub sqid: u16,
+    pub cid: u16,
+    pub status: CompletionStatus,
+    pub parameter_error_location: u16,
+    pub lba: u64,
+    pub nsid: u32,
+    pub vendor_specific: u8,
+    pub trtype: u8,
+    pub rsvd: [u8; 2],
+    pub command_specific: u64,
+    pub trtype_specific: u16,
+    pub rsvd2: [u8; 22],
+}
+
+const _: () = assert!(size_of::<ErrorInformationLogEntry>() == 64);
+
+#[derive(Inspect)]
+#[bitfield(u8)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+pub struct CriticalWarning {
+    pub available_spare: bool,
+    pub temperature: bool,
+    pub reliability: bool,
+    pub read_only: bool,
+    pub volatile_memory_backup: bool,
+    pub persistent_memory_region: bool,
+    #[bits(2)]
+    pub rsvd: u8,
+}
+
+/// The SMART / Health Information log page. All temperatures are in Kelvin.
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct SmartHealthInformation {
+    pub critical_warning: CriticalWarning,
+    pub composite_temperature: [u8; 2],
+    pub available_spare: u8,
+    pub available_spare_threshold: u8,
+    pub percentage_used: u8,
+    pub endurance_group_critical_warning_summary: u8,
+    pub rsvd: [u8; 25],
+    /// In thousands of 512-byte units.
+    pub data_units_read: U128LE,
+    /// In thousands of 512-byte units.
+    pub data_units_written: U128LE,
+    pub host_read_commands: U128LE,
+    pub host_write_commands: U128LE,
+    /// In minutes.
+    pub controller_busy_time: U128LE,
+    pub power_cycles: U128LE,
+    pub power_on_hours: U128LE,
+    pub unsafe_shutdowns: U128LE,
+    pub media_errors: U128LE,
+    pub error_log_entries: U128LE,
+    pub warning_composite_temperature_time: u32,
+    pub critical_composite_temperature_time: u32,
+    pub temperature_sensors: [u16; 8],
+    pub thermal_management_transition_count: [u32; 2],
+    pub total_thermal_management_time: [u32; 2],
+    pub rsvd2: [u8; 280],
+}
+
+const _: () = assert!(size_of::<SmartHealthInformation>() == 512);
+
+#[repr(C)]
+#[derive(Debug, AsBytes, FromBytes, FromZeroes)]
+pub struct FirmwareSlotInformation {
+    pub afi: u8,
+    pub rsvd: [u8; 7],
+    pub frs: [AsciiString<8>; 7],
+    pub rsvd2: [u8; 448],
+}
+
+const _: () = assert!(size_of::<FirmwareSlotInformation>() == 512);
+
+#[bitfield(u32)]
+pub struct Cdw11FeatureTemperatureThreshold {
+    pub tmpth: u16,
+    #[bits(4)]
+    pub tmpsel: u8,
+    #[bits(2)]
+    pub thsel: u8,
+    #[bits(10)]
+    _rsvd: u16,
+}
@@ -420,7 +420,10 @@ fn handle_vpd_logical_block_provisioning(
         };
 
         if self.scsi_parameters.support_unmap {
-            page.flags = 0x80;
+            page.flags = 0x80; // LBPU
+            if self.scsi_parameters.unmap_reads_zeroes {
+                page.flags |= 0x04; // LBPRZ
+            }
         }
 
         write_vpd_page(
@@ -124,6 +124,7 @@ struct ScsiParameters {
     write_cache_enabled: bool,
     support_odx: bool,
     support_unmap: bool,
+    unmap_reads_zeroes: bool,
     support_get_lba_status: bool,
     maximum_transfer_length: usize,
     identity: DiskIdentity,
@@ -186,11 +187,13 @@ pub fn new(disk: Disk, disk_parameters: DiskParameters) -> Self {
                 support_odx: odx.unwrap_or(false),
                 support_get_lba_status: get_lba_status,
                 support_unmap: unmap.unwrap_or(disk.unmap_behavior() != UnmapBehavior::Ignored),
+                unmap_reads_zeroes: disk.unmap_behavior() == UnmapBehavior::Zeroes,
                 maximum_transfer_length: max_transfer_length.unwrap_or(8 * 1024 * 1024),
                 identity: identity.unwrap_or_else(DiskIdentity::msft),
                 serial_number,
                 medium_rotation_rate: medium_rotation_rate.unwrap_or(1), // non-rotating media (SSD)
-                optimal_unmap_sectors: optimal_unmap_sectors.unwrap_or(1),
+                optimal_unmap_sectors: optimal_unmap_sectors
+                    .unwrap_or_else(|| disk.optimal_unmap_sectors()),
             }
         };
 
@@ -560,8 +563,11 @@ fn handle_service_action_in16(
                 if self.scsi_parameters.support_unmap {
                     // report trim capabilities:
                     //  - trim is supported
-                    //  - read zero after trim is not supported
+                    //  - read zero after trim, if the disk guarantees it
                     data.lowest_aligned_block_msb |= scsi::READ_CAPACITY16_LBPME;
+                    if self.scsi_parameters.unmap_reads_zeroes {
+                        data.lowest_aligned_block_msb |= scsi::READ_CAPACITY16_LBPRZ;
+                    }
                 }
 
                 let tx = std::cmp::min(external_data.len(), size_of::<scsi::ReadCapacity16Data>());
@@ -23,6 +23,7 @@
 use vm_resource::AsyncResolveResource;
 use vm_resource::ResolveError;
 use vm_resource::ResourceResolver;
+use vmcore::vm_task::VmTaskDriverSource;
 
 /// A resolver for [`SimpleScsiDiskHandle`] and [`SimpleScsiDvdHandle`].
 pub struct SimpleScsiResolver;
@@ -48,14 +49,14 @@ async fn resolve(
         &self,
         resolver: &ResourceResolver,
         resource: SimpleScsiDiskHandle,
-        _: ResolveScsiDeviceHandleParams<'_>,
+        input: ResolveScsiDeviceHandleParams<'_>,
     ) -> Result<Self::Output, Self::Error> {
         let disk = resolver
             .resolve(
                 resource.disk,
                 ResolveDiskParameters {
                     read_only: resource.read_only,
-                    _async_trait_workaround: &(),
+                    driver_source: input.driver_source,
                 },
             )
             .await
@@ -84,7 +85,7 @@ async fn resolve(
                         media,
                         ResolveDiskParameters {
                             read_only: true,
-                            _async_trait_workaround: &(),
+                            driver_source: input.driver_source,
                         },
                     )
                     .await
@@ -103,7 +104,12 @@ async fn resolve(
                 .simple()
                 .spawn(
                     "dvd-requests",
-                    handle_dvd_requests(Arc::downgrade(&dvd), resolver.clone(), requests),
+                    handle_dvd_requests(
+                        Arc::downgrade(&dvd),
+                        resolver.clone(),
+                        input.driver_source.clone(),
+                        requests,
+                    ),
                 )
                 .detach();
         }
@@ -115,6 +121,7 @@ async fn resolve(
 async fn handle_dvd_requests(
     dvd: Weak<SimpleScsiDvd>,
     resolver: ResourceResolver,
+    driver_source: VmTaskDriverSource,
     mut requests: mesh::Receiver<SimpleScsiDvdRequest>,
 ) {
     while let Some(req) = requests.next().await {
@@ -128,7 +135,7 @@ async fn handle_dvd_requests(
                                     resource,
                                     ResolveDiskParameters {
                                         read_only: true,
-                                        _async_trait_workaround: &(),
+                                        driver_source: &driver_source,
                                     },
                                 )
                                 .await
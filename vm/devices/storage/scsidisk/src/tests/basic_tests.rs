@@ -12,8 +12,11 @@
 use super::test_helpers::new_atapi_disk;
 use super::test_helpers::new_scsi_disk;
 use super::test_helpers::new_scsi_dvd;
+use super::test_helpers::TestDisk;
 use crate::scsi;
 use crate::SimpleScsiDisk;
+use disk_backend::Disk;
+use disk_backend::UnmapBehavior;
 use guestmem::GuestMemory;
 use pal_async::async_test;
 use scsi::AdditionalSenseCode;
@@ -30,6 +33,7 @@
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use zerocopy::AsBytes;
+use zerocopy::FromZeroes;
 
 fn save_scsi_disk(scsi_disk: &SimpleScsiDisk) -> ScsiDiskSavedState {
     let saved_state = if let Some(ScsiSavedState::ScsiDisk(saved_state)) = scsi_disk.save().unwrap()
@@ -180,6 +184,55 @@ fn resize(new_sector_count: Option<u64>) {
     }
 }
 
+/// Returns the provisioning bits reported by READ CAPACITY (16) for a disk
+/// with the given unmap behavior.
+async fn read_capacity16_provisioning(unmap_behavior: UnmapBehavior) -> u8 {
+    let (mut disk, _state) = TestDisk::new(512, 4096, 1024, false, false);
+    disk.unmap_behavior = unmap_behavior;
+    disk.optimal_unmap_sectors = 8;
+    let scsi_disk = SimpleScsiDisk::new(Disk::new(disk).unwrap(), Default::default());
+    assert_eq!(scsi_disk.scsi_parameters.optimal_unmap_sectors, 8);
+    assert_eq!(
+        scsi_disk.scsi_parameters.support_unmap,
+        unmap_behavior != UnmapBehavior::Ignored
+    );
+
+    let len = size_of::<scsi::ReadCapacity16Data>();
+    let cdb = scsi::ServiceActionIn16 {
+        operation_code: ScsiOp::SERVICE_ACTION_IN16,
+        service_action: scsi::SERVICE_ACTION_READ_CAPACITY16,
+        allocation_length: (len as u32).to_be_bytes(),
+        ..FromZeroes::new_zeroed()
+    };
+    let request = Request {
+        cdb: cdb.as_bytes().try_into().unwrap(),
+        srb_flags: 0,
+    };
+    let guest_mem = GuestMemory::allocate(4096);
+    let external_data = OwnedRequestBuffers::linear(0, len, true);
+    check_execute_scsi_pass(&scsi_disk, &external_data.buffer(&guest_mem), &request).await;
+
+    let mut data = scsi::ReadCapacity16Data::new_zeroed();
+    guest_mem.read_at(0, data.as_bytes_mut()).unwrap();
+    data.lowest_aligned_block_msb & (scsi::READ_CAPACITY16_LBPME | scsi::READ_CAPACITY16_LBPRZ)
+}
+
+#[async_test]
+async fn validate_unmap_provisioning() {
+    assert_eq!(
+        read_capacity16_provisioning(UnmapBehavior::Ignored).await,
+        0
+    );
+    assert_eq!(
+        read_capacity16_provisioning(UnmapBehavior::Unspecified).await,
+        scsi::READ_CAPACITY16_LBPME
+    );
+    assert_eq!(
+        read_capacity16_provisioning(UnmapBehavior::Zeroes).await,
+        scsi::READ_CAPACITY16_LBPME | scsi::READ_CAPACITY16_LBPRZ
+    );
+}
+
 #[test]
 fn validate_new_scsi_disk() {
     let _disk = new_scsi_disk(512, 4096, 1024, false, false, false);
@@ -38,6 +38,8 @@ pub struct TestDisk {
     pub sector_size: u32,
     pub physical_sector_size: u32,
     pub read_only: bool,
+    pub unmap_behavior: disk_backend::UnmapBehavior,
+    pub optimal_unmap_sectors: u32,
     pub state: Arc<Mutex<TestDiskStorageState>>,
 }
 
@@ -68,6 +70,8 @@ pub fn new(
             TestDisk {
                 sector_size: logical_sector_size,
                 read_only,
+                unmap_behavior: disk_backend::UnmapBehavior::Ignored,
+                optimal_unmap_sectors: 1,
                 state: state.clone(),
                 physical_sector_size,
             },
@@ -154,7 +158,11 @@ async fn unmap(
     }
 
     fn unmap_behavior(&self) -> disk_backend::UnmapBehavior {
-        disk_backend::UnmapBehavior::Ignored
+        self.unmap_behavior
+    }
+
+    fn optimal_unmap_sectors(&self) -> u32 {
+        self.optimal_unmap_sectors
     }
 }
 
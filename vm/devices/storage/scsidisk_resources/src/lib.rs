@@ -61,7 +61,8 @@ pub struct DiskParameters {
     pub unmap: Option<bool>,
     /// The maximum transfer length for IOs (TODO: or is it for write same?)
     pub max_transfer_length: Option<usize>,
-    /// The minimum optimal number of sectors to unmap in a request.
+    /// The minimum optimal number of sectors to unmap in a request. If
+    /// `None`, the disk's optimal unmap granularity is used.
     pub optimal_unmap_sectors: Option<u32>,
     /// Report LBA status to the guest.
     ///
@@ -36,6 +36,7 @@
 use thiserror::Error;
 use virtio::DeviceTraits;
 use virtio::DeviceTraitsSharedMemory;
+use virtio::QueueResources;
 use virtio::Resources;
 use virtio::VirtioDevice;
 use virtio::VirtioQueue;
@@ -99,11 +100,41 @@ struct NetStatus {
     _reserved: u16,
 }
 
+// These correspond to VIRTIO_NET_RSS_HASH_TYPE_ flags.
+#[bitfield(u32)]
+#[derive(AsBytes, FromBytes, FromZeroes)]
+struct RssHashTypes {
+    pub ipv4: bool,
+    pub tcpv4: bool,
+    pub udpv4: bool,
+    pub ipv6: bool,
+    pub tcpv6: bool,
+    pub udpv6: bool,
+    pub ipv6_ex: bool,
+    pub tcpv6_ex: bool,
+    pub udpv6_ex: bool,
+    #[bits(23)]
+    _reserved: u32,
+}
+
 const DEFAULT_MTU: u16 = 1514;
 
-#[allow(dead_code)]
 const VIRTIO_NET_MAX_QUEUES: u16 = 0x8000;
 
+const RSS_MAX_KEY_SIZE: u8 = 40;
+const RSS_MAX_INDIRECTION_TABLE_LENGTH: u16 = 128;
+
+// Control virtqueue command classes, commands, and acks.
+const VIRTIO_NET_CTRL_MQ: u8 = 4;
+const VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET: u8 = 0;
+const VIRTIO_NET_CTRL_MQ_RSS_CONFIG: u8 = 1;
+const VIRTIO_NET_OK: u8 = 0;
+const VIRTIO_NET_ERR: u8 = 1;
+
+/// The largest control command accepted from the guest. The largest supported
+/// command, `VIRTIO_NET_CTRL_MQ_RSS_CONFIG`, is well under this.
+const MAX_CONTROL_COMMAND_SIZE: u64 = 4096;
+
 #[repr(C)]
 struct NetConfig {
     pub mac: [u8; 6],
@@ -175,6 +206,23 @@ struct VirtioNetHeader {
     pub padding_reserved: u16, // Only if VIRTIO_NET_F_HASH_REPORT negotiated
 }
 
+#[derive(AsBytes, FromBytes, FromZeroes)]
+#[repr(C)]
+struct VirtioNetCtrlHeader {
+    pub class: u8,
+    pub command: u8,
+}
+
+/// The fixed-size prefix of `virtio_net_rss_config`. It is followed by the
+/// indirection table, `max_tx_vq`, and the hash key.
+#[derive(AsBytes, FromBytes, FromZeroes)]
+#[repr(C)]
+struct VirtioNetRssConfig {
+    pub hash_types: u32,
+    pub indirection_table_mask: u16,
+    pub unclassified_queue: u16,
+}
+
 fn header_size() -> usize {
     // TODO: Verify hash flags are not set, since header size would be larger in that case.
     offset_of!(VirtioNetHeader, hash_value)
@@ -203,10 +251,17 @@ fn drop(&mut self) {}
 impl VirtioDevice for Device {
     fn traits(&self) -> DeviceTraits {
         // TODO: Add network features based on endpoint capabilities (NetworkFeatures::VIRTIO_NET_F_*)
+        let multiqueue = self.registers.max_virtqueue_pairs > 1;
+        let features = NetworkFeatures::new()
+            .with_mac(true)
+            .with_ctrl_vq(multiqueue)
+            .with_mq(multiqueue)
+            .with_rss(multiqueue);
         DeviceTraits {
             device_id: 1,
-            device_features: NetworkFeatures::new().with_mac(true).into(),
-            max_queues: 2 * self.registers.max_virtqueue_pairs,
+            device_features: features.into(),
+            // The control queue follows the last transmit queue.
+            max_queues: 2 * self.registers.max_virtqueue_pairs + multiqueue as u16,
             device_register_length: size_of::<NetConfig>() as u32,
             shared_memory: DeviceTraitsSharedMemory { id: 0, size: 0 },
         }
@@ -224,7 +279,7 @@ fn read_registers_u32(&self, offset: u16) -> u32 {
             16 => {
                 (self.registers.duplex as u32)
                     | ((self.registers.rss_max_key_size as u32) << 8)
-                    | ((self.registers.rss_max_indirection_table_length as u32) << 24)
+                    | ((self.registers.rss_max_indirection_table_length as u32) << 16)
             }
             20 => self.registers.supported_hash_types,
             _ => 0,
@@ -234,7 +289,27 @@ fn read_registers_u32(&self, offset: u16) -> u32 {
     fn write_registers_u32(&mut self, _offset: u16, _val: u32) {}
 
     fn enable(&mut self, resources: Resources) {
+        let features = NetworkFeatures::from(resources.features);
         let mut queue_resources: Vec<_> = resources.queues.into_iter().collect();
+
+        // The control queue comes after the receive and transmit queues for
+        // all the queue pairs the guest might use.
+        let mut control_queue = None;
+        if features.ctrl_vq() {
+            let index = if features.mq() {
+                2 * self.registers.max_virtqueue_pairs as usize
+            } else {
+                2
+            };
+            if index < queue_resources.len() {
+                let control_resources = queue_resources.remove(index);
+                if control_resources.params.enable {
+                    control_queue =
+                        self.create_queue(resources.features, control_resources, "control");
+                }
+            }
+        }
+
         let mut workers = Vec::with_capacity(queue_resources.len() / 2);
         while queue_resources.len() > 1 {
             let mut next = queue_resources.drain(..2);
@@ -245,63 +320,26 @@ fn enable(&mut self, resources: Resources) {
             }
 
             let rx_queue_size = rx_resources.params.size;
-            let rx_queue_event = PolledWait::new(&self.adapter.driver, rx_resources.event);
-            if let Err(err) = rx_queue_event {
-                tracing::error!(
-                    err = &err as &dyn std::error::Error,
-                    "Failed creating queue event"
-                );
-                continue;
-            }
-            let rx_queue = VirtioQueue::new(
-                resources.features,
-                rx_resources.params,
-                self.memory.clone(),
-                rx_resources.notify,
-                rx_queue_event.unwrap(),
-            );
-            if let Err(err) = rx_queue {
-                tracing::error!(
-                    err = &err as &dyn std::error::Error,
-                    "Failed creating virtio net receive queue"
-                );
+            let Some(rx_queue) = self.create_queue(resources.features, rx_resources, "receive")
+            else {
                 continue;
-            }
-
+            };
             let tx_queue_size = tx_resources.params.size;
-            let tx_queue_event = PolledWait::new(&self.adapter.driver, tx_resources.event);
-            if let Err(err) = tx_queue_event {
-                tracing::error!(
-                    err = &err as &dyn std::error::Error,
-                    "Failed creating queue event"
-                );
-                continue;
-            }
-            let tx_queue = VirtioQueue::new(
-                resources.features,
-                tx_resources.params,
-                self.memory.clone(),
-                tx_resources.notify,
-                tx_queue_event.unwrap(),
-            );
-            if let Err(err) = tx_queue {
-                tracing::error!(
-                    err = &err as &dyn std::error::Error,
-                    "Failed creating virtio net transmit queue"
-                );
+            let Some(tx_queue) = self.create_queue(resources.features, tx_resources, "transmit")
+            else {
                 continue;
-            }
+            };
             workers.push(VirtioState {
-                rx_queue: rx_queue.unwrap(),
+                rx_queue,
                 rx_queue_size,
-                tx_queue: tx_queue.unwrap(),
+                tx_queue,
                 tx_queue_size,
             });
         }
 
         let (tx, rx) = mesh::channel();
         self.coordinator_send = Some(tx);
-        self.insert_coordinator(rx, workers.len() as u16);
+        self.insert_coordinator(rx, workers.len() as u16, control_queue);
         for (i, virtio_state) in workers.into_iter().enumerate() {
             self.insert_worker(virtio_state, i);
         }
@@ -424,10 +462,15 @@ pub fn build(
         endpoint: Box<dyn Endpoint>,
         mac_address: MacAddress,
     ) -> Device {
-        // TODO: Implement VIRTIO_NET_F_MQ and VIRTIO_NET_F_RSS logic based on mulitqueue support.
-        // let multiqueue = endpoint.multiqueue_support();
-        // let max_queues = self.max_queues.clamp(1, multiqueue.max_queues.min(VIRTIO_NET_MAX_QUEUES));
-        let max_queues = 1;
+        let multiqueue = endpoint.multiqueue_support();
+        // Leave room in the virtio queue count for the control queue.
+        let max_queues = self.max_queues.clamp(
+            1,
+            multiqueue
+                .max_queues
+                .min(VIRTIO_NET_MAX_QUEUES)
+                .min((u16::MAX - 1) / 2),
+        );
 
         let driver = driver_source.simple();
         let adapter = Arc::new(Adapter {
@@ -449,9 +492,9 @@ pub fn build(
             mtu: DEFAULT_MTU,
             speed: 0xffffffff,
             duplex: 0xff,
-            rss_max_key_size: 0,
-            rss_max_indirection_table_length: 0,
-            supported_hash_types: 0,
+            rss_max_key_size: RSS_MAX_KEY_SIZE,
+            rss_max_indirection_table_length: RSS_MAX_INDIRECTION_TABLE_LENGTH,
+            supported_hash_types: supported_hash_types().into(),
         };
 
         Device {
@@ -478,7 +521,43 @@ fn inspect_mut(&mut self, req: inspect::Request<'_>) {
 }
 
 impl Device {
-    fn insert_coordinator(&mut self, recv: mesh::Receiver<CoordinatorMessage>, num_queues: u16) {
+    fn create_queue(
+        &self,
+        features: u64,
+        resources: QueueResources,
+        kind: &str,
+    ) -> Option<VirtioQueue> {
+        let queue_event = PolledWait::new(&self.adapter.driver, resources.event)
+            .inspect_err(|err| {
+                tracing::error!(
+                    err = err as &dyn std::error::Error,
+                    "Failed creating queue event"
+                );
+            })
+            .ok()?;
+        VirtioQueue::new(
+            features,
+            resources.params,
+            self.memory.clone(),
+            resources.notify,
+            queue_event,
+        )
+        .inspect_err(|err| {
+            tracing::error!(
+                err = err as &dyn std::error::Error,
+                kind,
+                "Failed creating virtio net queue"
+            );
+        })
+        .ok()
+    }
+
+    fn insert_coordinator(
+        &mut self,
+        recv: mesh::Receiver<CoordinatorMessage>,
+        num_queues: u16,
+        control_queue: Option<VirtioQueue>,
+    ) {
         self.coordinator.insert(
             &self.adapter.driver,
             "virtio-net-coordinator".to_string(),
@@ -488,6 +567,10 @@ fn insert_coordinator(&mut self, recv: mesh::Receiver<CoordinatorMessage>, num_q
                     .map(|_| TaskControl::new(NetQueue { state: None }))
                     .collect(),
                 num_queues,
+                active_queues: 1,
+                rss: None,
+                control_queue,
+                memory: self.memory.clone(),
                 restart: true,
             },
         );
@@ -531,10 +614,117 @@ enum CoordinatorMessage {
 struct Coordinator {
     recv: mesh::Receiver<CoordinatorMessage>,
     workers: Vec<TaskControl<NetQueue, Worker>>,
+    /// The number of queue pairs enabled by the guest.
     num_queues: u16,
+    /// The number of queue pairs the guest has asked to use, via the control
+    /// queue. Only the first pair is used until the guest says otherwise.
+    active_queues: u16,
+    rss: Option<RssState>,
+    control_queue: Option<VirtioQueue>,
+    memory: GuestMemory,
     restart: bool,
 }
 
+/// The RSS configuration programmed by the guest.
+#[derive(Debug, PartialEq, Inspect)]
+struct RssState {
+    #[inspect(hex)]
+    hash_types: u32,
+    #[inspect(iter_by_index)]
+    indirection_table: Vec<u16>,
+    #[inspect(with = "|x| inspect::AsBytes(x)")]
+    key: Vec<u8>,
+}
+
+fn supported_hash_types() -> RssHashTypes {
+    RssHashTypes::new()
+        .with_ipv4(true)
+        .with_tcpv4(true)
+        .with_ipv6(true)
+        .with_tcpv6(true)
+}
+
+#[derive(Debug, Error)]
+enum ControlError {
+    #[error("control command of {0:#x} bytes is too large")]
+    TooLarge(u64),
+    #[error("control command is truncated")]
+    Truncated,
+    #[error("failed to access control command")]
+    Memory(#[source] guestmem::GuestMemoryError),
+    #[error("unsupported control command class {0} command {1}")]
+    Unsupported(u8, u8),
+    #[error("invalid queue pair count {0}")]
+    InvalidQueuePairs(u16),
+    #[error("invalid indirection table length {0}")]
+    InvalidIndirectionTableLength(usize),
+    #[error("invalid receive queue {0}")]
+    InvalidQueue(u16),
+    #[error("invalid hash key length {0}")]
+    InvalidKeyLength(u8),
+    #[error("unsupported hash types {0:#x}")]
+    UnsupportedHashTypes(u32),
+}
+
+/// Parses a `VIRTIO_NET_CTRL_MQ_RSS_CONFIG` command, returning the new RSS
+/// state (or `None` if RSS is being disabled) and the number of queue pairs
+/// to use.
+fn parse_rss_config(data: &[u8], max_queues: u16) -> Result<(Option<RssState>, u16), ControlError> {
+    let (config, data) = read_prefix::<VirtioNetRssConfig>(data)?;
+    let table_len = config.indirection_table_mask as usize + 1;
+    if !table_len.is_power_of_two() || table_len > RSS_MAX_INDIRECTION_TABLE_LENGTH as usize {
+        return Err(ControlError::InvalidIndirectionTableLength(table_len));
+    }
+    let table_bytes = data.get(..table_len * 2).ok_or(ControlError::Truncated)?;
+    let indirection_table = table_bytes
+        .chunks_exact(2)
+        .map(|x| u16::from_le_bytes([x[0], x[1]]))
+        .collect::<Vec<_>>();
+    let (max_tx_vq, data) = read_prefix::<u16>(&data[table_len * 2..])?;
+    let (key_len, data) = read_prefix::<u8>(data)?;
+    if key_len > RSS_MAX_KEY_SIZE {
+        return Err(ControlError::InvalidKeyLength(key_len));
+    }
+    let key = data
+        .get(..key_len as usize)
+        .ok_or(ControlError::Truncated)?
+        .to_vec();
+
+    if max_tx_vq == 0 || max_tx_vq > max_queues {
+        return Err(ControlError::InvalidQueuePairs(max_tx_vq));
+    }
+    if let Some(&queue) = indirection_table
+        .iter()
+        .chain([&config.unclassified_queue])
+        .find(|&&queue| queue >= max_queues)
+    {
+        return Err(ControlError::InvalidQueue(queue));
+    }
+    if config.hash_types & !u32::from(supported_hash_types()) != 0 {
+        return Err(ControlError::UnsupportedHashTypes(config.hash_types));
+    }
+
+    // Receive queues referenced by the indirection table must be active even
+    // if the guest does not transmit on them.
+    let queues = indirection_table
+        .iter()
+        .map(|&queue| queue + 1)
+        .fold(max_tx_vq, u16::max);
+
+    // A hash type of zero disables RSS.
+    let rss = (config.hash_types != 0).then_some(RssState {
+        hash_types: config.hash_types,
+        indirection_table,
+        key,
+    });
+    Ok((rss, queues))
+}
+
+fn read_prefix<T: FromBytes>(data: &[u8]) -> Result<(T, &[u8]), ControlError> {
+    let value = T::read_from_prefix(data).ok_or(ControlError::Truncated)?;
+    Ok((value, &data[size_of::<T>()..]))
+}
+
 struct CoordinatorState {
     endpoint: Box<dyn Endpoint>,
     adapter: Arc<Adapter>,
@@ -556,12 +746,14 @@ fn inspect_mut(&mut self, req: inspect::Request<'_>, coordinator: Option<&mut Co
             .field_mut("endpoint", self.endpoint.as_mut());
 
         if let Some(coordinator) = coordinator {
-            resp.fields_mut(
-                "queues",
-                coordinator.workers[..coordinator.num_queues as usize]
-                    .iter_mut()
-                    .enumerate(),
-            );
+            resp.field("active_queues", coordinator.active_queues)
+                .field("rss", &coordinator.rss)
+                .fields_mut(
+                    "queues",
+                    coordinator.workers[..coordinator.num_queues as usize]
+                        .iter_mut()
+                        .enumerate(),
+                );
         }
     }
 }
@@ -600,22 +792,39 @@ enum Message {
                 Internal(CoordinatorMessage),
                 ChannelDisconnected,
                 UpdateFromEndpoint(EndpointAction),
+                Control(Result<VirtioQueueCallbackWork, std::io::Error>),
             }
             let message = {
+                let recv = &mut self.recv;
+                let control_queue = &mut self.control_queue;
                 let wait_for_message = async {
-                    let internal_msg = self
-                        .recv
+                    let internal_msg = recv
                         .next()
                         .map(|x| x.map_or(Message::ChannelDisconnected, Message::Internal));
                     let endpoint_restart = state
                         .endpoint
                         .wait_for_endpoint_action()
                         .map(Message::UpdateFromEndpoint);
-                    (internal_msg, endpoint_restart).race().await
+                    let control = async {
+                        match control_queue {
+                            Some(queue) => queue.next().await.expect("queue never completes"),
+                            None => pending().await,
+                        }
+                    }
+                    .map(Message::Control);
+                    (internal_msg, endpoint_restart, control).race().await
                 };
                 stop.until_stopped(wait_for_message).await?
             };
             match message {
+                Message::Control(Ok(work)) => self.handle_control(work),
+                Message::Control(Err(err)) => {
+                    tracing::error!(
+                        error = &err as &dyn std::error::Error,
+                        "control queue failure"
+                    );
+                    self.control_queue = None;
+                }
                 Message::UpdateFromEndpoint(EndpointAction::RestartRequired) => self.restart = true,
                 Message::UpdateFromEndpoint(EndpointAction::LinkStatusNotify(_)) => {
                     tracing::error!("unexpected link status notification")
@@ -629,6 +838,60 @@ enum Message {
         Ok(())
     }
 
+    fn handle_control(&mut self, mut work: VirtioQueueCallbackWork) {
+        let ack = match self.handle_control_command(&work) {
+            Ok(()) => VIRTIO_NET_OK,
+            Err(err) => {
+                tracelimit::warn_ratelimited!(
+                    error = &err as &dyn std::error::Error,
+                    "failed control command"
+                );
+                VIRTIO_NET_ERR
+            }
+        };
+        if let Err(err) = work.write(&self.memory, &[ack]) {
+            tracelimit::warn_ratelimited!(
+                error = &err as &dyn std::error::Error,
+                "failed to write control command ack"
+            );
+        }
+        work.complete(1);
+    }
+
+    fn handle_control_command(
+        &mut self,
+        work: &VirtioQueueCallbackWork,
+    ) -> Result<(), ControlError> {
+        let len = work.get_payload_length(false);
+        if len > MAX_CONTROL_COMMAND_SIZE {
+            return Err(ControlError::TooLarge(len));
+        }
+        let mut data = vec![0; len as usize];
+        work.read(&self.memory, &mut data)
+            .map_err(ControlError::Memory)?;
+        let (header, data) = read_prefix::<VirtioNetCtrlHeader>(&data)?;
+        let max_queues = self.workers.len() as u16;
+        let (rss, active_queues) = match (header.class, header.command) {
+            (VIRTIO_NET_CTRL_MQ, VIRTIO_NET_CTRL_MQ_VQ_PAIRS_SET) => {
+                let (queues, _) = read_prefix::<u16>(data)?;
+                if queues == 0 || queues > max_queues {
+                    return Err(ControlError::InvalidQueuePairs(queues));
+                }
+                (None, queues)
+            }
+            (VIRTIO_NET_CTRL_MQ, VIRTIO_NET_CTRL_MQ_RSS_CONFIG) => {
+                parse_rss_config(data, max_queues)?
+            }
+            (class, command) => return Err(ControlError::Unsupported(class, command)),
+        };
+        if rss != self.rss || active_queues != self.active_queues {
+            self.rss = rss;
+            self.active_queues = active_queues;
+            self.restart = true;
+        }
+        Ok(())
+    }
+
     async fn stop_workers(&mut self) {
         for worker in &mut self.workers {
             worker.stop().await;
@@ -641,8 +904,9 @@ async fn restart_queues(&mut self, c_state: &mut CoordinatorState) -> Result<(),
             worker.task_mut().state = None;
         }
 
-        let (rx_pools, ready_packets): (Vec<_>, Vec<_>) = self
-            .workers
+        // Only workers for queue pairs enabled by the guest have state.
+        let num_queues = self.active_queues.min(self.num_queues) as usize;
+        let (rx_pools, ready_packets): (Vec<_>, Vec<_>) = self.workers[..num_queues]
             .iter()
             .map(|worker| {
                 let pool = worker
@@ -666,14 +930,20 @@ async fn restart_queues(&mut self, c_state: &mut CoordinatorState) -> Result<(),
             });
         }
 
+        let rss = self.rss.as_ref().map(|rss| net_backend::RssConfig {
+            key: &rss.key,
+            indirection_table: &rss.indirection_table,
+            flags: 0,
+        });
+
         let mut queues = Vec::new();
         c_state
             .endpoint
-            .get_queues(queue_config, None, &mut queues)
+            .get_queues(queue_config, rss.as_ref(), &mut queues)
             .await
             .map_err(WorkerError::Endpoint)?;
 
-        assert_eq!(queues.len(), self.workers.len());
+        assert_eq!(queues.len(), num_queues);
 
         for (worker, queue) in self.workers.iter_mut().zip(queues) {
             worker.task_mut().state = Some(EndpointQueueState { queue });
@@ -995,3 +1265,73 @@ fn complete_tx_packet(&mut self, id: TxId) -> Result<(), WorkerError> {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rss_config(hash_types: u32, table: &[u16], max_tx_vq: u16, key: &[u8]) -> Vec<u8> {
+        let mut data = VirtioNetRssConfig {
+            hash_types,
+            indirection_table_mask: table.len() as u16 - 1,
+            unclassified_queue: 0,
+        }
+        .as_bytes()
+        .to_vec();
+        data.extend(table.iter().flat_map(|x| x.to_le_bytes()));
+        data.extend(max_tx_vq.to_le_bytes());
+        data.push(key.len() as u8);
+        data.extend(key);
+        data
+    }
+
+    #[test]
+    fn test_parse_rss_config() {
+        let hash_types = supported_hash_types().into();
+        let data = rss_config(hash_types, &[0, 1, 2, 3], 2, &[0xaa; 40]);
+        let (rss, queues) = parse_rss_config(&data, 4).unwrap();
+        assert_eq!(
+            rss,
+            Some(RssState {
+                hash_types,
+                indirection_table: vec![0, 1, 2, 3],
+                key: vec![0xaa; 40],
+            })
+        );
+        // The indirection table references more queues than max_tx_vq.
+        assert_eq!(queues, 4);
+
+        let data = rss_config(0, &[0], 3, &[]);
+        assert_eq!(parse_rss_config(&data, 4).unwrap(), (None, 3));
+    }
+
+    #[test]
+    fn test_parse_rss_config_invalid() {
+        let hash_types = supported_hash_types().into();
+        let data = rss_config(hash_types, &[0, 1, 2], 1, &[]);
+        assert!(matches!(
+            parse_rss_config(&data, 4),
+            Err(ControlError::InvalidIndirectionTableLength(3))
+        ));
+        let data = rss_config(hash_types, &[0, 4], 1, &[]);
+        assert!(matches!(
+            parse_rss_config(&data, 4),
+            Err(ControlError::InvalidQueue(4))
+        ));
+        let data = rss_config(hash_types, &[0, 1], 0, &[]);
+        assert!(matches!(
+            parse_rss_config(&data, 4),
+            Err(ControlError::InvalidQueuePairs(0))
+        ));
+        let data = rss_config(RssHashTypes::new().with_udpv4(true).into(), &[0], 1, &[]);
+        assert!(matches!(
+            parse_rss_config(&data, 4),
+            Err(ControlError::UnsupportedHashTypes(_))
+        ));
+        let data = rss_config(hash_types, &[0], 1, &[1; 8]);
+        assert!(matches!(
+            parse_rss_config(&data[..data.len() - 1], 4),
+            Err(ControlError::Truncated)
+        ));
+    }
+}
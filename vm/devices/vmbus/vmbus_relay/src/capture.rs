@@ -0,0 +1,141 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Best-effort capture of the ring buffer traffic of relayed channels.
+//!
+//! The relay is not in the data path of relayed channels: VTL0 and the host
+//! exchange packets directly through ring buffers in VTL0 memory. Instead, the
+//! rings are sampled periodically, and any newly written packets are written
+//! out in pcapng format, with one interface per channel and direction. Packets
+//! that are written and overwritten between two samples are lost.
+
+use anyhow::Context;
+use pal_async::driver::Driver;
+use pal_async::timer::PolledTimer;
+use pcap_file::pcapng::blocks::enhanced_packet::EnhancedPacketBlock;
+use pcap_file::pcapng::blocks::interface_description::InterfaceDescriptionBlock;
+use pcap_file::pcapng::blocks::interface_description::InterfaceDescriptionOption;
+use pcap_file::pcapng::PcapNgWriter;
+use pcap_file::DataLink;
+use std::borrow::Cow;
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use vmbus_channel::gpadl_ring::GpadlRingMem;
+use vmbus_ring::RingObserver;
+
+/// How often the rings are sampled.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// The ring buffers of an open relayed channel.
+pub struct CaptureRings {
+    pub(crate) channel_id: u32,
+    pub(crate) guest_to_host: GpadlRingMem,
+    pub(crate) host_to_guest: GpadlRingMem,
+    /// Cleared when the channel is closed, after which the ring memory may be
+    /// reused by the guest.
+    pub(crate) open: Arc<AtomicBool>,
+}
+
+/// Limits on a capture. The capture stops when either is reached.
+#[derive(Debug, Clone)]
+pub struct CaptureLimits {
+    /// How long to capture for.
+    pub duration: Duration,
+    /// The maximum number of packet bytes to capture.
+    pub max_bytes: u64,
+}
+
+/// The result of a capture.
+#[derive(Debug, Default)]
+pub struct CaptureStats {
+    /// The number of packets captured.
+    pub packets: u64,
+    /// The number of packet bytes captured.
+    pub bytes: u64,
+    /// The number of times the capture fell behind a ring and had to skip
+    /// ahead, losing packets.
+    pub resyncs: u64,
+}
+
+/// Captures the traffic on `rings` to `writer`, in pcapng format.
+pub async fn capture_rings(
+    driver: &impl Driver,
+    rings: Vec<CaptureRings>,
+    writer: impl Write,
+    limits: CaptureLimits,
+) -> anyhow::Result<CaptureStats> {
+    let mut writer = PcapNgWriter::new(writer).context("failed to write pcapng header")?;
+    let mut observers = Vec::new();
+    for channel in rings {
+        for (direction, mem) in [
+            ("guest-to-host", channel.guest_to_host),
+            ("host-to-guest", channel.host_to_guest),
+        ] {
+            writer
+                .write_pcapng_block(InterfaceDescriptionBlock {
+                    linktype: DataLink::USER0,
+                    snaplen: 0,
+                    options: vec![InterfaceDescriptionOption::IfName(
+                        format!("channel {} {direction}", channel.channel_id).into(),
+                    )],
+                })
+                .context("failed to write interface block")?;
+            observers.push((
+                RingObserver::new(mem).context("invalid ring")?,
+                channel.open.clone(),
+            ));
+        }
+    }
+
+    let mut stats = CaptureStats::default();
+    let mut timer = PolledTimer::new(driver);
+    let deadline = Instant::now() + limits.duration;
+    while Instant::now() < deadline && stats.bytes < limits.max_bytes {
+        timer.sleep(POLL_INTERVAL).await;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut result = Ok(());
+        for (interface_id, (observer, open)) in observers.iter_mut().enumerate() {
+            if !open.load(Ordering::Relaxed) {
+                continue;
+            }
+            let r = observer.poll(|packet| {
+                if result.is_err() {
+                    return;
+                }
+                stats.packets += 1;
+                stats.bytes += packet.len() as u64;
+                result = writer
+                    .write_pcapng_block(EnhancedPacketBlock {
+                        interface_id: interface_id as u32,
+                        timestamp,
+                        original_len: packet.len() as u32,
+                        data: Cow::Borrowed(packet),
+                        options: vec![],
+                    })
+                    .map(drop);
+            });
+            if r.is_err() {
+                stats.resyncs += 1;
+            }
+        }
+        result.context("failed to write packet")?;
+
+        // Stop once all the channels have been closed.
+        if observers
+            .iter()
+            .all(|(_, open)| !open.load(Ordering::Relaxed))
+        {
+            break;
+        }
+    }
+
+    Ok(stats)
+}
@@ -0,0 +1,182 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Filtering policy for channels offered by the host.
+
+use guid::Guid;
+use inspect::Inspect;
+
+/// The action the relay takes for a channel offered by the host.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Inspect)]
+pub enum ChannelFilterAction {
+    /// Relay the channel to the guest, or hand it to a VTL2 device if one has
+    /// registered to intercept it.
+    Allow,
+    /// Ignore the channel entirely.
+    Deny,
+    /// Only hand the channel to a VTL2 device. If no device has registered to
+    /// intercept it, the channel is ignored rather than relayed to the guest.
+    TerminateInVtl2,
+}
+
+/// A rule matching host channels by interface and/or instance ID.
+#[derive(Debug, Clone, Inspect)]
+pub struct ChannelFilterRule {
+    /// The interface ID to match, or any if `None`.
+    pub interface_id: Option<Guid>,
+    /// The instance ID to match, or any if `None`.
+    pub instance_id: Option<Guid>,
+    /// The action to take for matching channels.
+    pub action: ChannelFilterAction,
+}
+
+impl ChannelFilterRule {
+    fn matches(&self, interface_id: &Guid, instance_id: &Guid) -> bool {
+        self.interface_id
+            .as_ref()
+            .map_or(true, |id| id == interface_id)
+            && self
+                .instance_id
+                .as_ref()
+                .map_or(true, |id| id == instance_id)
+    }
+}
+
+/// The policy used to decide how to handle each channel offered by the host.
+///
+/// Rules are evaluated in order, and the first matching rule determines the
+/// action. Channels that match no rule get `default_action`.
+#[derive(Debug, Clone, Inspect)]
+pub struct ChannelFilterPolicy {
+    /// The action for channels that do not match any rule.
+    pub default_action: ChannelFilterAction,
+    /// The rules to evaluate.
+    #[inspect(iter_by_index)]
+    pub rules: Vec<ChannelFilterRule>,
+}
+
+impl Default for ChannelFilterPolicy {
+    fn default() -> Self {
+        Self {
+            default_action: ChannelFilterAction::Allow,
+            rules: Vec::new(),
+        }
+    }
+}
+
+impl ChannelFilterPolicy {
+    /// Returns the action to take for a channel with the given IDs.
+    pub fn action(&self, interface_id: &Guid, instance_id: &Guid) -> ChannelFilterAction {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(interface_id, instance_id))
+            .map_or(self.default_action, |rule| rule.action)
+    }
+
+    /// Returns whether a channel with the given IDs should be blocked.
+    ///
+    /// `intercepted` indicates whether a VTL2 device has registered to
+    /// intercept the channel. `restored` is the decision saved for the channel
+    /// before a servicing operation, if there was one; it takes precedence
+    /// over the policy, since the guest may already be using the channel.
+    /// Channels without a saved decision are evaluated against the policy.
+    pub(crate) fn is_filtered(
+        &self,
+        interface_id: &Guid,
+        instance_id: &Guid,
+        intercepted: bool,
+        restored: Option<bool>,
+    ) -> bool {
+        if let Some(filtered) = restored {
+            return filtered;
+        }
+        match self.action(interface_id, instance_id) {
+            ChannelFilterAction::Allow => false,
+            ChannelFilterAction::Deny => true,
+            ChannelFilterAction::TerminateInVtl2 => !intercepted,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERFACE_A: Guid = Guid::from_static_str("ba6163d9-04a1-4d29-b605-72e2ffb1dc7f");
+    const INTERFACE_B: Guid = Guid::from_static_str("f8615163-df3e-46c5-913f-f2d2f965ed0e");
+    const INSTANCE_A: Guid = Guid::from_static_str("0bf355d5-0cae-411e-9662-86c3035556ae");
+    const INSTANCE_B: Guid = Guid::from_static_str("9e14fd10-19cb-4da5-b667-e8e38a436cb8");
+
+    #[test]
+    fn test_default_allows() {
+        let policy = ChannelFilterPolicy::default();
+        assert_eq!(
+            policy.action(&INTERFACE_A, &INSTANCE_A),
+            ChannelFilterAction::Allow
+        );
+    }
+
+    #[test]
+    fn test_first_match_wins() {
+        let policy = ChannelFilterPolicy {
+            default_action: ChannelFilterAction::Deny,
+            rules: vec![
+                ChannelFilterRule {
+                    interface_id: None,
+                    instance_id: Some(INSTANCE_B),
+                    action: ChannelFilterAction::TerminateInVtl2,
+                },
+                ChannelFilterRule {
+                    interface_id: Some(INTERFACE_A),
+                    instance_id: None,
+                    action: ChannelFilterAction::Allow,
+                },
+            ],
+        };
+
+        assert_eq!(
+            policy.action(&INTERFACE_A, &INSTANCE_A),
+            ChannelFilterAction::Allow
+        );
+        assert_eq!(
+            policy.action(&INTERFACE_A, &INSTANCE_B),
+            ChannelFilterAction::TerminateInVtl2
+        );
+        assert_eq!(
+            policy.action(&INTERFACE_B, &INSTANCE_A),
+            ChannelFilterAction::Deny
+        );
+    }
+
+    #[test]
+    fn test_terminate_in_vtl2() {
+        let policy = ChannelFilterPolicy {
+            default_action: ChannelFilterAction::TerminateInVtl2,
+            rules: Vec::new(),
+        };
+
+        assert!(!policy.is_filtered(&INTERFACE_A, &INSTANCE_A, true, None));
+        assert!(policy.is_filtered(&INTERFACE_A, &INSTANCE_A, false, None));
+    }
+
+    #[test]
+    fn test_restore() {
+        let policy = ChannelFilterPolicy {
+            default_action: ChannelFilterAction::Allow,
+            rules: vec![ChannelFilterRule {
+                interface_id: Some(INTERFACE_A),
+                instance_id: None,
+                action: ChannelFilterAction::Deny,
+            }],
+        };
+
+        // Saved decisions are kept even if the policy now disagrees.
+        assert!(!policy.is_filtered(&INTERFACE_A, &INSTANCE_A, false, Some(false)));
+        assert!(policy.is_filtered(&INTERFACE_B, &INSTANCE_A, false, Some(true)));
+
+        // Channels with no saved decision, such as ones offered by the host
+        // after the save, are subject to the policy.
+        assert!(policy.is_filtered(&INTERFACE_A, &INSTANCE_B, false, None));
+        assert!(!policy.is_filtered(&INTERFACE_B, &INSTANCE_B, false, None));
+    }
+}
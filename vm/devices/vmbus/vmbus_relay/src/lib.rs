@@ -4,9 +4,19 @@
 #![cfg(target_os = "linux")]
 #![forbid(unsafe_code)]
 
+mod capture;
+mod filter;
 mod hvsock;
 mod saved_state;
 
+pub use capture::capture_rings;
+pub use capture::CaptureLimits;
+pub use capture::CaptureRings;
+pub use capture::CaptureStats;
+pub use filter::ChannelFilterAction;
+pub use filter::ChannelFilterPolicy;
+pub use filter::ChannelFilterRule;
+
 use anyhow::Context;
 use anyhow::Result;
 use client::ClientNotification;
@@ -19,6 +29,7 @@
 use futures::FutureExt;
 use futures::Stream;
 use futures::StreamExt;
+use guestmem::GuestMemory;
 use guid::Guid;
 use hcl::ioctl::HypercallError;
 use hcl::vmbus::HclVmbus;
@@ -56,7 +67,11 @@
 use vmbus_channel::bus::ChannelServerRequest;
 use vmbus_channel::bus::GpadlRequest;
 use vmbus_channel::bus::ModifyRequest;
+use vmbus_channel::bus::OpenData;
 use vmbus_channel::bus::OpenRequest;
+use vmbus_channel::gpadl::GpadlMap;
+use vmbus_channel::gpadl_ring::AlignedGpadlView;
+use vmbus_channel::gpadl_ring::GpadlRingMem;
 use vmbus_client as client;
 use vmbus_client::VmbusClient;
 use vmbus_core::protocol;
@@ -64,7 +79,9 @@
 use vmbus_core::protocol::FeatureFlags;
 use vmbus_core::protocol::GpadlId;
 use vmbus_core::HvsockConnectRequest;
+use vmbus_core::HvsockConnectResult;
 use vmbus_core::VersionInfo;
+use vmbus_ring::gparange::MultiPagedRangeBuf;
 use vmbus_server::HvsockRelayChannelHalf;
 use vmbus_server::ModifyConnectionResponse;
 use vmbus_server::OfferInfo;
@@ -108,6 +125,8 @@ pub async fn new(
         control: Arc<VmbusServerControl>,
         channel: VmbusRelayChannelHalf,
         hvsock_relay: HvsockRelayChannelHalf,
+        filter_policy: ChannelFilterPolicy,
+        gm: GuestMemory,
     ) -> Result<Self> {
         // Open an HCL vmbus fd for issuing synic requests.
         let hcl_vmbus = Arc::new(HclVmbus::new().context("failed to open hcl_vmbus")?);
@@ -136,6 +155,8 @@ pub async fn new(
             hcl_vmbus,
             channel.response_send,
             hvsock_relay,
+            filter_policy,
+            gm,
         );
 
         let (task_send, task_recv) = mesh::channel();
@@ -347,6 +368,7 @@ enum RelayChannelRequest {
     Start,
     Stop(Rpc<(), ()>),
     Save(Rpc<(), saved_state::Channel>),
+    CaptureRings(Rpc<(), Result<CaptureRings>>),
 }
 
 impl Debug for RelayChannelRequest {
@@ -355,11 +377,13 @@ fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
             RelayChannelRequest::Start => f.pad("Start"),
             RelayChannelRequest::Stop(..) => f.pad("Stop"),
             RelayChannelRequest::Save(..) => f.pad("Save channel"),
+            RelayChannelRequest::CaptureRings(..) => f.pad("CaptureRings"),
         }
     }
 }
 
 struct RelayChannelInfo {
+    instance_id: Guid,
     _channel_worker: Task<()>,
     relay_request_send: mesh::Sender<RelayChannelRequest>,
     server_request_send: mesh::Sender<ChannelServerRequest>,
@@ -368,6 +392,9 @@ struct RelayChannelInfo {
 enum ChannelInfo {
     Relay(RelayChannelInfo),
     Intercept(Guid),
+    /// The channel was blocked by the filter policy and is not offered to
+    /// anyone.
+    Filtered,
 }
 
 impl RelayChannelInfo {
@@ -410,6 +437,19 @@ struct RelayChannel {
     interrupt_relay: Option<InterruptRelay>,
     /// RPCs for gpadls that are waiting for a torndown message.
     gpadls_tearing_down: HashMap<GpadlId, Rpc<GpadlId, ()>>,
+    /// The GPADLs created by the guest, used to find the ring buffers for
+    /// packet capture.
+    gpadls: HashMap<GpadlId, MultiPagedRangeBuf<Vec<u64>>>,
+    /// The ring buffers of the channel while it is open.
+    open_rings: Option<OpenRings>,
+    /// Guest memory backing the ring buffers.
+    gm: GuestMemory,
+}
+
+struct OpenRings {
+    open_data: OpenData,
+    /// Shared with any active captures, to stop them when the channel closes.
+    open: Arc<AtomicBool>,
 }
 
 struct RelayChannelTask {
@@ -466,6 +506,13 @@ async fn handle_open_channel(&mut self, open_request: &OpenRequest) -> Result<bo
             )
             .await?;
 
+        if opened {
+            self.channel.open_rings = Some(OpenRings {
+                open_data,
+                open: Arc::new(AtomicBool::new(true)),
+            });
+        }
+
         Ok(opened)
     }
 
@@ -475,24 +522,36 @@ fn handle_close_channel(&mut self) {
             .request_send
             .send(client::ChannelRequest::Close);
 
+        if let Some(rings) = self.channel.open_rings.take() {
+            rings.open.store(false, Ordering::Relaxed);
+        }
         self.channel.interrupt_relay = None;
         self.channel.connection_id.store(0, Ordering::SeqCst);
     }
 
     /// Relay gpadl request from VTL0 to the Host and respond with gpadl created.
     async fn handle_gpadl(&mut self, request: GpadlRequest) -> Result<bool> {
+        let id = request.id;
+        let buf = MultiPagedRangeBuf::new(request.count.into(), request.buf.clone());
         let created = self
             .channel
             .request_send
             .call(client::ChannelRequest::Gpadl, request)
             .await?;
 
+        if created {
+            if let Ok(buf) = buf {
+                self.channel.gpadls.insert(id, buf);
+            }
+        }
+
         Ok(created)
     }
 
     fn handle_gpadl_teardown(&mut self, rpc: Rpc<GpadlId, ()>) {
         let gpadl_id = rpc.0;
         tracing::trace!(gpadl_id = gpadl_id.0, "Tearing down GPADL");
+        self.channel.gpadls.remove(&gpadl_id);
 
         let _ = &self
             .channel
@@ -587,9 +646,46 @@ fn handle_relay_request(&mut self, request: RelayChannelRequest) {
             RelayChannelRequest::Start => self.running = true,
             RelayChannelRequest::Stop(rpc) => rpc.handle_sync(|()| self.running = false),
             RelayChannelRequest::Save(rpc) => rpc.handle_sync(|_| self.handle_save()),
+            RelayChannelRequest::CaptureRings(rpc) => {
+                rpc.handle_sync(|()| self.handle_capture_rings())
+            }
         }
     }
 
+    /// Maps the ring buffers of the open channel, for packet capture.
+    fn handle_capture_rings(&self) -> Result<CaptureRings> {
+        // The ring buffer GPADL is not known if the channel was opened before
+        // the relay was restored.
+        let rings = self
+            .channel
+            .open_rings
+            .as_ref()
+            .context("channel is not open, or was opened before VTL2 was serviced")?;
+        let gpadl = self
+            .channel
+            .gpadls
+            .get(&rings.open_data.ring_gpadl_id)
+            .context("unknown ring buffer gpadl")?;
+
+        let gpadl_map = GpadlMap::new();
+        gpadl_map.add(rings.open_data.ring_gpadl_id, gpadl.clone());
+        let gpadl = gpadl_map
+            .view()
+            .map(rings.open_data.ring_gpadl_id)
+            .expect("gpadl was just added");
+        let (guest_to_host, host_to_guest) = AlignedGpadlView::new(gpadl)
+            .ok()
+            .and_then(|gpadl| gpadl.split(rings.open_data.ring_offset).ok())
+            .context("invalid ring buffer gpadl")?;
+
+        Ok(CaptureRings {
+            channel_id: self.channel.channel_id.0,
+            guest_to_host: GpadlRingMem::new(guest_to_host, &self.channel.gm)?,
+            host_to_guest: GpadlRingMem::new(host_to_guest, &self.channel.gm)?,
+            open: rings.open.clone(),
+        })
+    }
+
     /// Request dispatch loop
     async fn run(&mut self) {
         loop {
@@ -680,6 +776,9 @@ enum TaskRequest {
 
 pub enum RequestFromHandle {
     AddIntercept(Rpc<(Guid, mesh::Sender<InterceptChannelRequest>), Result<()>>),
+    /// Maps the ring buffers of the open relayed channels with the specified
+    /// instance ID, for packet capture.
+    CaptureRings(Rpc<Guid, Result<Vec<CaptureRings>>>),
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -715,7 +814,9 @@ struct RelayTask {
     server_response_send: mesh::Sender<ModifyConnectionResponse>,
     hvsock_relay: HvsockRelayChannelHalf,
     hvsock_tracker: HvsockRequestTracker,
+    filter_policy: ChannelFilterPolicy,
     running: bool,
+    gm: GuestMemory,
 }
 
 impl RelayTask {
@@ -726,6 +827,8 @@ fn new(
         hcl_vmbus: Arc<HclVmbus>,
         server_response_send: mesh::Sender<ModifyConnectionResponse>,
         hvsock_relay: HvsockRelayChannelHalf,
+        filter_policy: ChannelFilterPolicy,
+        gm: GuestMemory,
     ) -> Self {
         Self {
             spawner,
@@ -739,7 +842,9 @@ fn new(
             server_response_send,
             hvsock_relay,
             hvsock_tracker: HvsockRequestTracker::new(),
+            filter_policy,
             running: false,
+            gm,
         }
     }
 
@@ -754,6 +859,36 @@ async fn handle_add_intercept_device(
         Ok(())
     }
 
+    async fn handle_capture_rings(&mut self, instance_id: Guid) -> Result<Vec<CaptureRings>> {
+        let mut rings = Vec::new();
+        let mut last_err = None;
+        for channel in self.channels.values() {
+            let ChannelInfo::Relay(channel) = channel else {
+                continue;
+            };
+            if channel.instance_id != instance_id {
+                continue;
+            }
+            match channel
+                .relay_request_send
+                .call(RelayChannelRequest::CaptureRings, ())
+                .await
+                .context("channel worker gone")
+                .and_then(|r| r)
+            {
+                Ok(r) => rings.push(r),
+                Err(err) => last_err = Some(err),
+            }
+        }
+
+        if rings.is_empty() {
+            return Err(last_err.unwrap_or_else(|| {
+                anyhow::anyhow!("no relayed channel with instance ID {instance_id}")
+            }));
+        }
+        Ok(rings)
+    }
+
     async fn handle_start(&mut self) {
         if !self.running {
             // Resume all channels.
@@ -767,6 +902,7 @@ async fn handle_start(&mut self) {
                         };
                         intercept_channel.send(InterceptChannelRequest::Start);
                     }
+                    ChannelInfo::Filtered => {}
                 }
             }
 
@@ -784,9 +920,9 @@ async fn handle_start(&mut self) {
     async fn handle_stop(&mut self) {
         if self.running {
             // Stop all the channels before the relay itself can stop.
-            join_all(self.channels.values().map(|c| match c {
-                ChannelInfo::Relay(relay) => futures::future::Either::Left(relay.stop()),
-                ChannelInfo::Intercept(id) => futures::future::Either::Right(async {
+            join_all(self.channels.values().filter_map(|c| match c {
+                ChannelInfo::Relay(relay) => Some(futures::future::Either::Left(relay.stop())),
+                ChannelInfo::Intercept(id) => Some(futures::future::Either::Right(async {
                     let id = *id;
                     if let Some(intercept_channel) = self.intercept_channels.get(&id) {
                         if let Err(err) = intercept_channel
@@ -800,7 +936,8 @@ async fn handle_stop(&mut self) {
                             );
                         }
                     }
-                }),
+                })),
+                ChannelInfo::Filtered => None,
             }))
             .await;
 
@@ -836,10 +973,37 @@ async fn handle_offer(
             return Ok(());
         }
 
+        // Apply the filter policy. On restore, keep whatever decision was saved
+        // for the channel, since the guest may already be using channels that
+        // were relayed.
+        let filtered = self.filter_policy.is_filtered(
+            &offer.offer.interface_id,
+            &offer.offer.instance_id,
+            self.intercept_channels
+                .contains_key(&offer.offer.instance_id),
+            restored_channel.map(|c| c.filtered),
+        );
+
         // Check if this channel is for an hvsock request, and if so send a success message to the
-        // server. This is needed because the host will not send a result on success.
+        // server. This is needed because the host will not send a result on success. If the
+        // channel is filtered, fail the request instead.
         if let Some(result) = self.hvsock_tracker.check_offer(&offer.offer) {
-            self.hvsock_relay.response_send.send(result);
+            self.hvsock_relay.response_send.send(HvsockConnectResult {
+                success: !filtered,
+                ..result
+            });
+        }
+
+        if filtered {
+            tracing::info!(
+                channel_id,
+                interface_id = %offer.offer.interface_id,
+                instance_id = %offer.offer.instance_id,
+                "channel blocked by filter policy"
+            );
+            self.channels
+                .insert(ChannelId(channel_id), ChannelInfo::Filtered);
+            return Ok(());
         }
 
         // Check if this channel is being intercepted. A previously relayed
@@ -979,6 +1143,9 @@ async fn handle_offer(
                 hcl_vmbus: Arc::clone(&self.hcl_vmbus),
                 interrupt_relay,
                 gpadls_tearing_down: HashMap::new(),
+                gpadls: HashMap::new(),
+                open_rings: None,
+                gm: self.gm.clone(),
             },
             // New channels start out running.
             running: true,
@@ -991,6 +1158,7 @@ async fn handle_offer(
         self.channels.insert(
             ChannelId(channel_id),
             ChannelInfo::Relay(RelayChannelInfo {
+                instance_id: offer.offer.instance_id,
                 _channel_worker,
                 relay_request_send,
                 server_request_send,
@@ -1040,6 +1208,11 @@ async fn handle_revoke(&mut self, channel_id: ChannelId) {
                     tracing::error!(%id, "intercept device missing during revoke!")
                 };
             }
+            ChannelInfo::Filtered => {
+                self.channels
+                    .remove(&channel_id)
+                    .expect("channel should exist");
+            }
         }
 
         tracing::debug!(channel_id = channel_id.0, "revoked channel");
@@ -1189,6 +1362,7 @@ async fn run(
                 r = from_handle_recv.select_next_some() => {
                     match r {
                         RequestFromHandle::AddIntercept(rpc) => rpc.handle(|(id, send)| self.handle_add_intercept_device(id, send)).await,
+                        RequestFromHandle::CaptureRings(rpc) => rpc.handle(|id| self.handle_capture_rings(id)).await,
                     }
                 }
             }
@@ -1199,7 +1373,15 @@ async fn run(
 impl Inspect for RelayTask {
     fn inspect(&self, req: inspect::Request<'_>) {
         let mut resp = req.respond();
-        resp.field("vmbus_client", &self.vmbus_client);
+        resp.field("vmbus_client", &self.vmbus_client)
+            .field("filter_policy", &self.filter_policy)
+            .field(
+                "filtered_channels",
+                self.channels
+                    .values()
+                    .filter(|c| matches!(c, ChannelInfo::Filtered))
+                    .count(),
+            );
     }
 }
 
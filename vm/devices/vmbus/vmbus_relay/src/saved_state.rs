@@ -56,7 +56,7 @@ pub async fn handle_restore(&mut self, state: SavedState) -> Result<()> {
                 .binary_search_by_key(&offer.offer.offer.channel_id.0, |k| k.channel_id)
                 .ok()
                 .and_then(|i| {
-                    if offer.open || channels[i].intercepted {
+                    if offer.open || channels[i].intercepted || channels[i].filtered {
                         Some(&channels[i])
                     } else {
                         None
@@ -114,8 +114,16 @@ async fn save_channel_state(
                     event_flag: None,
                     intercepted: true,
                     intercepted_save_state,
+                    filtered: false,
                 })
             }
+            ChannelInfo::Filtered => Some(Channel {
+                channel_id: channel_id.0,
+                event_flag: None,
+                intercepted: false,
+                intercepted_save_state: Vec::new(),
+                filtered: true,
+            }),
         }
     }
 }
@@ -132,6 +140,7 @@ pub(crate) fn handle_save(&self) -> Channel {
                 .map(|interrupt| interrupt.event.get_flag_index()),
             intercepted: false,
             intercepted_save_state: Vec::new(),
+            filtered: false,
         }
     }
 }
@@ -188,6 +197,8 @@ pub struct Channel {
     pub intercepted: bool,
     #[mesh(4)]
     pub intercepted_save_state: Vec<u8>,
+    #[mesh(5)]
+    pub filtered: bool,
 }
 
 impl Channel {
@@ -1176,6 +1176,95 @@ pub fn writer_needs_signal<M: RingMem>(mem: M) -> bool {
     })
 }
 
+/// A snapshot of a ring buffer's state, for diagnostics.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RingSnapshot {
+    /// The offset in the data area at which the writer will write next.
+    pub write_offset: u32,
+    /// The number of bytes written but not yet read.
+    pub used: u32,
+    /// Whether the writer is waiting for enough space to write a packet.
+    pub writer_blocked: bool,
+}
+
+/// Returns a snapshot of a ring buffer's state, or `None` if the ring is
+/// invalid.
+pub fn ring_snapshot<M: RingMem>(mem: M) -> Option<RingSnapshot> {
+    let ring = InnerRing::new(mem).ok()?;
+    let control = ring.control();
+    let inp = ring.validate(control.inp().load(Ordering::Relaxed)).ok()?;
+    let outp = ring.validate(control.outp().load(Ordering::Relaxed)).ok()?;
+    let pending_size = control.pending_send_size().load(Ordering::Relaxed);
+    Some(RingSnapshot {
+        write_offset: inp,
+        used: if inp == outp {
+            0
+        } else {
+            ring.available(inp, outp)
+        },
+        writer_blocked: pending_size != 0 && ring.free(inp, outp) < pending_size,
+    })
+}
+
+/// Passively observes the packets written to a ring buffer, without consuming
+/// them or otherwise modifying the ring state.
+///
+/// This is meant for diagnostics. The producer and consumer keep using the ring
+/// concurrently, so a packet that is written and then overwritten between two
+/// calls to [`RingObserver::poll`] is missed, and may be reported as corrupt
+/// ring data instead.
+pub struct RingObserver<M: RingMem> {
+    inner: InnerRing<M>,
+    next: u32,
+    buf: Vec<u8>,
+}
+
+impl<M: RingMem> RingObserver<M> {
+    /// Returns a new observer, which reports packets written after this call.
+    pub fn new(mem: M) -> Result<Self, Error> {
+        let inner = InnerRing::new(mem)?;
+        let next = inner.validate(inner.control().inp().load(Ordering::Acquire))?;
+        Ok(Self {
+            inner,
+            next,
+            buf: Vec::new(),
+        })
+    }
+
+    /// Calls `f` with each packet (including its descriptor, but not its
+    /// footer) written since the last call.
+    ///
+    /// If the ring data is inconsistent, skips ahead to the current write
+    /// position and returns an error.
+    pub fn poll(&mut self, mut f: impl FnMut(&[u8])) -> Result<(), Error> {
+        let inp = self
+            .inner
+            .validate(self.inner.control().inp().load(Ordering::Acquire))?;
+        while self.next != inp {
+            let avail = self.inner.available(inp, self.next);
+            let mut desc = PacketDescriptor::new_zeroed();
+            self.inner
+                .mem
+                .read_aligned(self.next as usize, desc.as_bytes_mut());
+            let len = desc.length8 as u32 * 8;
+            if desc.length8 < desc.data_offset8
+                || desc.data_offset8 < 2
+                || avail < len + size_of::<Footer>() as u32
+            {
+                self.next = inp;
+                return Err(Error::InvalidDescriptorLengths);
+            }
+            self.buf.resize(len as usize, 0);
+            self.inner.mem.read_at(self.next as usize, &mut self.buf);
+            f(&self.buf);
+            self.next = self
+                .inner
+                .add_pointer(self.next, len + size_of::<Footer>() as u32);
+        }
+        Ok(())
+    }
+}
+
 impl<M: RingMem> Debug for InnerRing<M> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("InnerRing")
@@ -1300,6 +1389,28 @@ fn test_ring() {
         assert_eq!(p, &msg[..]);
     }
 
+    #[test]
+    fn test_ring_snapshot() {
+        let rmem = FlatRingMem::new(16384);
+        let mut in_ring = IncomingRing::new(&rmem).unwrap();
+        let mut out_ring = OutgoingRing::new(&rmem).unwrap();
+
+        let snapshot = ring_snapshot(&rmem).unwrap();
+        assert_eq!(snapshot.write_offset, 0);
+        assert_eq!(snapshot.used, 0);
+
+        write_simple(&mut out_ring, &[1; 100]).unwrap();
+        let snapshot = ring_snapshot(&rmem).unwrap();
+        assert_ne!(snapshot.write_offset, 0);
+        assert_eq!(snapshot.used, snapshot.write_offset);
+        assert!(!snapshot.writer_blocked);
+
+        read_simple(&mut in_ring);
+        let after_read = ring_snapshot(&rmem).unwrap();
+        assert_eq!(after_read.write_offset, snapshot.write_offset);
+        assert_eq!(after_read.used, 0);
+    }
+
     #[test]
     fn test_interrupt_mask() {
         let rmem = FlatRingMem::new(16384);
@@ -1345,4 +1456,31 @@ fn test_pending_send_size() {
         assert!(read_simple(&mut in_ring).1);
         assert!(!read_simple(&mut in_ring).1);
     }
+
+    #[test]
+    fn test_observer() {
+        let rmem = FlatRingMem::new(16384);
+        let mut in_ring = IncomingRing::new(&rmem).unwrap();
+        let mut out_ring = OutgoingRing::new(&rmem).unwrap();
+
+        // Packets written before the observer is created are not reported.
+        write_simple(&mut out_ring, &[1; 8]).unwrap();
+        let mut observer = RingObserver::new(&rmem).unwrap();
+
+        // Consuming packets does not hide them from the observer, and observing
+        // them does not consume them.
+        write_simple(&mut out_ring, &[2; 8]).unwrap();
+        write_simple(&mut out_ring, &[3; 4000]).unwrap();
+        assert_eq!(read_simple(&mut in_ring).0, &[1; 8]);
+        assert_eq!(read_simple(&mut in_ring).0, &[2; 8]);
+        let mut packets = Vec::new();
+        observer.poll(|p| packets.push(p.to_vec())).unwrap();
+        assert_eq!(packets.len(), 2);
+        assert_eq!(&packets[0][16..], &[2; 8]);
+        assert_eq!(&packets[1][16..], &[3; 4000]);
+        assert_eq!(read_simple(&mut in_ring).0, &[3; 4000]);
+
+        // Nothing new has been written.
+        observer.poll(|_| panic!()).unwrap();
+    }
 }
@@ -8,6 +8,7 @@
 pub mod hvsock;
 mod monitor;
 mod proxyintegration;
+mod stats;
 
 /// The GUID type used for vmbus channel identifiers.
 pub type Guid = guid::Guid;
@@ -44,6 +45,7 @@
 use pal_async::task::Task;
 use pal_event::Event;
 use ring::PAGE_SIZE;
+use stats::ChannelStats;
 use std::collections::HashMap;
 use std::future::Future;
 use std::pin::Pin;
@@ -214,9 +216,34 @@ fn inspect(&self, req: inspect::Request<'_>) {
     }
 }
 
-struct ChannelEvent(Interrupt);
+struct ChannelEvent {
+    interrupt: Interrupt,
+    stats: Arc<ChannelStats>,
+}
+
+impl ChannelEvent {
+    /// Delivers a guest-to-host signal, recording it in the channel stats.
+    fn signal(&self) {
+        self.stats.guest_to_host();
+        self.interrupt.deliver();
+    }
+}
 
 impl EventPort for ChannelEvent {
+    fn handle_event(&self, _flag: u16) {
+        self.signal();
+    }
+
+    fn os_event(&self) -> Option<&Event> {
+        self.interrupt.event()
+    }
+}
+
+/// The event port shared by all channels for pre-Win8 guests, which use the
+/// channel bitmap to indicate which channels were signaled.
+struct SharedChannelEvent(Interrupt);
+
+impl EventPort for SharedChannelEvent {
     fn handle_event(&self, _flag: u16) {
         self.0.deliver();
     }
@@ -649,6 +676,7 @@ struct Channel {
     guest_to_host_event: Arc<ChannelEvent>,
     guest_event_port: Box<dyn GuestEventPort>,
     flags: protocol::OfferFlags,
+    stats: Arc<ChannelStats>,
 }
 
 enum ChannelState {
@@ -685,6 +713,7 @@ fn handle_offer(&mut self, mut info: OfferInfo) -> anyhow::Result<()> {
 
         let id = self.next_seq;
         self.next_seq += 1;
+        let stats = Arc::new(ChannelStats::new());
         self.inner.channels.insert(
             offer_id,
             Channel {
@@ -692,10 +721,14 @@ fn handle_offer(&mut self, mut info: OfferInfo) -> anyhow::Result<()> {
                 send: info.request_send,
                 state: ChannelState::Closed,
                 gpadls: GpadlMap::new(),
-                guest_to_host_event: Arc::new(ChannelEvent(info.event)),
+                guest_to_host_event: Arc::new(ChannelEvent {
+                    interrupt: info.event,
+                    stats: stats.clone(),
+                }),
                 guest_event_port,
                 seq: id,
                 flags,
+                stats,
             },
         );
 
@@ -765,6 +798,7 @@ fn handle_open(&mut self, offer_id: OfferId, ok: bool) {
                 .get_mut(&offer_id)
                 .expect("channel still exists");
             channel.state = ChannelState::Closed;
+            channel.stats.detach_rings();
             protocol::STATUS_UNSUCCESSFUL
         };
         self.server
@@ -824,16 +858,13 @@ fn handle_restore_channel(
         }
 
         let open_request = params.map(|params| {
-            let (channel, interrupt) = self.inner.open_channel(offer_id, &params);
-            OpenRequest::new(
-                params.open_data,
-                interrupt,
-                self.server
-                    .get_version()
-                    .expect("must be connected")
-                    .feature_flags,
-                channel.flags,
-            )
+            let feature_flags = self
+                .server
+                .get_version()
+                .expect("must be connected")
+                .feature_flags;
+            let (channel, interrupt) = self.inner.open_channel(offer_id, &params, feature_flags);
+            OpenRequest::new(params.open_data, interrupt, feature_flags, channel.flags)
         });
         let result = RestoreResult {
             open_request,
@@ -1063,7 +1094,7 @@ fn unstick_channel(&self, channel: &Channel, force: bool) -> anyhow::Result<()>
         {
             if force {
                 tracing::info!(channel = %channel.key, "waking host and guest");
-                channel.guest_to_host_event.0.deliver();
+                channel.guest_to_host_event.interrupt.deliver();
                 host_to_guest_interrupt.deliver();
                 return Ok(());
             }
@@ -1113,7 +1144,7 @@ fn unstick_incoming_ring(
         let incoming_mem = GpadlRingMem::new(in_gpadl, &self.inner.gm)?;
         if ring::reader_needs_signal(&incoming_mem) {
             tracing::info!(channel = %channel.key, "waking host for incoming ring");
-            channel.guest_to_host_event.0.deliver();
+            channel.guest_to_host_event.interrupt.deliver();
         }
         if ring::writer_needs_signal(&incoming_mem) {
             tracing::info!(channel = %channel.key, "waking guest for incoming ring");
@@ -1135,7 +1166,7 @@ fn unstick_outgoing_ring(
         }
         if ring::writer_needs_signal(&outgoing_mem) {
             tracing::info!(channel = %channel.key, "waking host for outgoing ring");
-            channel.guest_to_host_event.0.deliver();
+            channel.guest_to_host_event.interrupt.deliver();
         }
         Ok(())
     }
@@ -1167,7 +1198,8 @@ fn handle<I: 'static + Send, R: 'static + Send>(
 
         let response = match action {
             channels::Action::Open(open_params, version) => {
-                let (channel, interrupt) = self.open_channel(offer_id, &open_params);
+                let (channel, interrupt) =
+                    self.open_channel(offer_id, &open_params, version.feature_flags);
                 handle(
                     offer_id,
                     channel,
@@ -1189,6 +1221,7 @@ fn handle<I: 'static + Send, R: 'static + Send>(
                 }
 
                 channel.guest_event_port.clear();
+                channel.stats.detach_rings();
                 handle(offer_id, channel, ChannelRequest::Close, (), |()| {
                     ChannelResponse::Close
                 })
@@ -1293,25 +1326,20 @@ fn forward_unhandled(&mut self, request: InitiateContactRequest) {
     fn inspect(&self, version: Option<VersionInfo>, offer_id: OfferId, req: inspect::Request<'_>) {
         let channel = self.channels.get(&offer_id).expect("should exist");
         let mut resp = req.respond();
+        resp.field("stats", &*channel.stats);
         if let ChannelState::Open { open_params, .. } = &channel.state {
-            let mem = if self.private_gm.is_some()
-                && channel.flags.confidential_ring_buffer()
-                && version
-                    .expect("must be connected")
-                    .feature_flags
-                    .confidential_channels()
-            {
-                self.private_gm.as_ref().unwrap()
-            } else {
-                &self.gm
-            };
-
-            inspect_rings(
-                &mut resp,
-                mem,
-                channel.gpadls.clone(),
-                &open_params.open_data,
+            let mem = ring_memory(
+                &self.gm,
+                self.private_gm.as_ref(),
+                channel.flags,
+                version.expect("must be connected").feature_flags,
             );
+            if let Some((incoming_mem, outgoing_mem)) =
+                ring_mems(mem, &channel.gpadls, &open_params.open_data)
+            {
+                resp.child("incoming_ring", |req| ring::inspect_ring(incoming_mem, req));
+                resp.child("outgoing_ring", |req| ring::inspect_ring(outgoing_mem, req));
+            }
         }
     }
 
@@ -1344,17 +1372,31 @@ fn open_channel(
         &mut self,
         offer_id: OfferId,
         open_params: &OpenParams,
+        feature_flags: protocol::FeatureFlags,
     ) -> (&mut Channel, Interrupt) {
         let channel = self
             .channels
             .get_mut(&offer_id)
             .expect("channel does not exist");
 
+        let ring_gm = ring_memory(
+            &self.gm,
+            self.private_gm.as_ref(),
+            channel.flags,
+            feature_flags,
+        );
+        if let Some((incoming, outgoing)) =
+            ring_mems(ring_gm, &channel.gpadls, &open_params.open_data)
+        {
+            channel.stats.attach_rings(incoming, outgoing);
+        }
+
         // Always register with the channel bitmap; if Win7, this may be unnecessary.
         if let Some(channel_bitmap) = self.channel_bitmap.as_ref() {
+            let event = channel.guest_to_host_event.clone();
             channel_bitmap.register_channel(
                 open_params.event_flag,
-                channel.guest_to_host_event.0.clone(),
+                Interrupt::from_fn(move || event.signal()),
             );
         }
         // Always set up an event port; if V1, this will be unused.
@@ -1393,13 +1435,16 @@ fn open_channel(
                 .map(|monitor| monitor.register_monitor(monitor_id, open_params.connection_id))
         });
 
+        // Only count interrupts from the device, not the ones the server
+        // itself sends to unstick the channel.
+        let device_interrupt = channel.stats.wrap_host_to_guest(interrupt.clone());
         channel.state = ChannelState::Open {
             open_params: *open_params,
             _event_port: event_port,
             monitor,
-            host_to_guest_interrupt: interrupt.clone(),
+            host_to_guest_interrupt: interrupt,
         };
-        (channel, interrupt)
+        (channel, device_interrupt)
     }
 
     /// If the client specified an interrupt page, map it into host memory and
@@ -1438,7 +1483,7 @@ fn map_interrupt_page(
         self.shared_event_port = Some(self.synic.add_event_port(
             SHARED_EVENT_CONNECTION_ID,
             self.vtl,
-            Arc::new(ChannelEvent(interrupt)),
+            Arc::new(SharedChannelEvent(interrupt)),
         )?);
 
         Ok(())
@@ -1539,25 +1584,40 @@ async fn offer(&self, request: OfferInput) -> anyhow::Result<OfferResources> {
 }
 
 /// Inspects the specified ring buffer state by directly accessing guest memory.
-fn inspect_rings(
-    resp: &mut inspect::Response<'_>,
+/// Returns the memory that holds a channel's ring buffers.
+fn ring_memory<'a>(
+    gm: &'a GuestMemory,
+    private_gm: Option<&'a GuestMemory>,
+    flags: protocol::OfferFlags,
+    feature_flags: protocol::FeatureFlags,
+) -> &'a GuestMemory {
+    match private_gm {
+        Some(private_gm)
+            if flags.confidential_ring_buffer() && feature_flags.confidential_channels() =>
+        {
+            private_gm
+        }
+        _ => gm,
+    }
+}
+
+/// Returns the incoming and outgoing ring buffers of an open channel.
+fn ring_mems(
     gm: &GuestMemory,
-    gpadl_map: Arc<GpadlMap>,
+    gpadl_map: &Arc<GpadlMap>,
     open_data: &OpenData,
-) -> Option<()> {
+) -> Option<(GpadlRingMem, GpadlRingMem)> {
     let gpadl = gpadl_map
+        .clone()
         .view()
         .map(GpadlId(open_data.ring_gpadl_id.0))
         .ok()?;
     let aligned = AlignedGpadlView::new(gpadl).ok()?;
     let (in_gpadl, out_gpadl) = aligned.split(open_data.ring_offset).ok()?;
-    if let Ok(incoming_mem) = GpadlRingMem::new(in_gpadl, gm) {
-        resp.child("incoming_ring", |req| ring::inspect_ring(incoming_mem, req));
-    }
-    if let Ok(outgoing_mem) = GpadlRingMem::new(out_gpadl, gm) {
-        resp.child("outgoing_ring", |req| ring::inspect_ring(outgoing_mem, req));
-    }
-    Some(())
+    Some((
+        GpadlRingMem::new(in_gpadl, gm).ok()?,
+        GpadlRingMem::new(out_gpadl, gm).ok()?,
+    ))
 }
 
 pub(crate) struct MessageSender {
@@ -0,0 +1,207 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Per-channel performance counters.
+
+use inspect::Inspect;
+use inspect_counters::Counter;
+use inspect_counters::Histogram;
+use inspect_counters::SharedCounter;
+use parking_lot::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+use vmbus_channel::gpadl_ring::GpadlRingMem;
+use vmbus_ring as ring;
+use vmbus_ring::RingMem;
+use vmbus_ring::RingSnapshot;
+use vmcore::interrupt::Interrupt;
+
+/// Interrupt and ring statistics for a single channel, tracked across opens
+/// for the lifetime of the offer.
+#[derive(Debug, Inspect)]
+pub(crate) struct ChannelStats {
+    /// Guest-to-host signals received by the server. Signals that the synic
+    /// delivers directly to an OS event bypass the server and are not
+    /// counted.
+    guest_to_host_interrupts: SharedCounter,
+    /// Host-to-guest interrupts posted by the device.
+    host_to_guest_interrupts: SharedCounter,
+    /// Time from a guest-to-host signal until the next host-to-guest
+    /// interrupt, in microseconds.
+    response_latency_us: Mutex<Histogram<16>>,
+    /// The ring the guest writes to.
+    incoming_ring: Mutex<RingStats>,
+    /// The ring the host writes to.
+    outgoing_ring: Mutex<RingStats>,
+    #[inspect(skip)]
+    epoch: Instant,
+    /// Time of the oldest unanswered guest-to-host signal, as nanoseconds
+    /// since `epoch` plus one, or zero if there is none.
+    #[inspect(skip)]
+    pending_signal: AtomicU64,
+}
+
+/// Statistics for one direction of a channel.
+///
+/// The server does not touch the ring contents, so these are computed from
+/// the ring indices, sampled each time an interrupt passes through the server
+/// in either direction and when the channel is closed. A writer that wraps
+/// the entire ring between two samples is not fully counted, so the byte
+/// count is a lower bound.
+#[derive(Debug, Default, Inspect)]
+struct RingStats {
+    /// Bytes written to the ring, including packet headers.
+    bytes: Counter,
+    /// Times the ring was seen to go from non-empty to empty.
+    empty_transitions: Counter,
+    /// Times the writer was seen to go from writing to waiting for space.
+    full_transitions: Counter,
+    #[inspect(skip)]
+    mem: Option<GpadlRingMem>,
+    #[inspect(skip)]
+    last: Option<RingSnapshot>,
+}
+
+impl RingStats {
+    fn attach(&mut self, mem: GpadlRingMem) {
+        self.last = ring::ring_snapshot(&mem);
+        self.mem = Some(mem);
+    }
+
+    fn detach(&mut self) {
+        self.sample();
+        self.mem = None;
+        self.last = None;
+    }
+
+    fn sample(&mut self) {
+        if let Some(mem) = &self.mem {
+            if let Some(snapshot) = ring::ring_snapshot(mem) {
+                self.update(snapshot, mem.len() as u64);
+            }
+        }
+    }
+
+    fn update(&mut self, snapshot: RingSnapshot, len: u64) {
+        if let Some(last) = self.last {
+            self.bytes
+                .add((snapshot.write_offset as u64 + len - last.write_offset as u64) % len);
+            if snapshot.used == 0 && last.used != 0 {
+                self.empty_transitions.increment();
+            }
+            if snapshot.writer_blocked && !last.writer_blocked {
+                self.full_transitions.increment();
+            }
+        }
+        self.last = Some(snapshot);
+    }
+}
+
+impl ChannelStats {
+    pub fn new() -> Self {
+        Self {
+            guest_to_host_interrupts: SharedCounter::new(),
+            host_to_guest_interrupts: SharedCounter::new(),
+            response_latency_us: Mutex::new(Histogram::new()),
+            incoming_ring: Mutex::new(RingStats::default()),
+            outgoing_ring: Mutex::new(RingStats::default()),
+            epoch: Instant::now(),
+            pending_signal: AtomicU64::new(0),
+        }
+    }
+
+    fn now(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64 + 1
+    }
+
+    /// Starts sampling the ring buffers of a newly opened channel.
+    pub fn attach_rings(&self, incoming: GpadlRingMem, outgoing: GpadlRingMem) {
+        self.incoming_ring.lock().attach(incoming);
+        self.outgoing_ring.lock().attach(outgoing);
+    }
+
+    /// Takes a final sample of the ring buffers and stops sampling them,
+    /// since the guest may reuse their memory once the channel is closed.
+    pub fn detach_rings(&self) {
+        self.incoming_ring.lock().detach();
+        self.outgoing_ring.lock().detach();
+    }
+
+    fn sample_rings(&self) {
+        self.incoming_ring.lock().sample();
+        self.outgoing_ring.lock().sample();
+    }
+
+    /// Records a guest-to-host signal.
+    pub fn guest_to_host(&self) {
+        self.guest_to_host_interrupts.increment();
+        self.sample_rings();
+        // Only track the oldest outstanding signal.
+        let _ = self.pending_signal.compare_exchange(
+            0,
+            self.now(),
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        );
+    }
+
+    /// Records a host-to-guest interrupt.
+    pub fn host_to_guest(&self) {
+        self.host_to_guest_interrupts.increment();
+        self.sample_rings();
+        let signaled = self.pending_signal.swap(0, Ordering::Relaxed);
+        if signaled != 0 {
+            let latency_us = self.now().saturating_sub(signaled) / 1000;
+            self.response_latency_us.lock().add_sample(latency_us);
+        }
+    }
+
+    /// Wraps `interrupt` so that each host-to-guest interrupt is recorded.
+    pub fn wrap_host_to_guest(self: &Arc<Self>, interrupt: Interrupt) -> Interrupt {
+        let stats = self.clone();
+        Interrupt::from_fn(move || {
+            stats.host_to_guest();
+            interrupt.deliver();
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interrupt_counts() {
+        let stats = Arc::new(ChannelStats::new());
+        let interrupt = stats.wrap_host_to_guest(Interrupt::null());
+        stats.guest_to_host();
+        stats.guest_to_host();
+        interrupt.deliver();
+        interrupt.deliver();
+        assert_eq!(stats.guest_to_host_interrupts.get(), 2);
+        assert_eq!(stats.host_to_guest_interrupts.get(), 2);
+        // Only the first interrupt after a signal is a response.
+        assert_eq!(stats.pending_signal.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn test_ring_stats() {
+        let snapshot = |write_offset, used, writer_blocked| RingSnapshot {
+            write_offset,
+            used,
+            writer_blocked,
+        };
+        let mut stats = RingStats::default();
+        stats.update(snapshot(0x100, 0, false), 0x1000);
+        stats.update(snapshot(0x300, 0x200, false), 0x1000);
+        // The writer wrapped and filled the ring.
+        stats.update(snapshot(0x200, 0xff8, true), 0x1000);
+        stats.update(snapshot(0x200, 0, false), 0x1000);
+
+        assert_eq!(stats.bytes.get(), 0x1100);
+        assert_eq!(stats.empty_transitions.get(), 1);
+        assert_eq!(stats.full_transitions.get(), 1);
+    }
+}
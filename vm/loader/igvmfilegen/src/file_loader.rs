@@ -7,6 +7,8 @@
 use crate::identity_mapping::SnpMeasurement;
 use crate::identity_mapping::TdxMeasurement;
 use crate::identity_mapping::VbsMeasurement;
+use crate::measurement_report::MeasuredPage;
+use crate::signed_measurement::generate_snp_id_block;
 use crate::signed_measurement::generate_snp_measurement;
 use crate::signed_measurement::generate_tdx_measurement;
 use crate::signed_measurement::generate_vbs_measurement;
@@ -36,6 +38,7 @@
 use igvm_defs::IGVM_VHS_PARAMETER_INSERT;
 use igvm_defs::IGVM_VHS_SUPPORTED_PLATFORM;
 use igvm_defs::PAGE_SIZE_4K;
+use igvmfilegen_config::SnpIdBlockConfig;
 use loader::importer::Aarch64Register;
 use loader::importer::BootPageAcceptance;
 use loader::importer::GuestArch;
@@ -106,6 +109,8 @@ pub struct IgvmLoader<R: VbsRegister + GuestArch> {
     isolation_type: LoaderIsolationType,
     paravisor_present: bool,
     imported_regions_config_page: Option<u64>,
+    snp_id_block: Option<SnpIdBlockConfig>,
+    measured_pages: Option<Vec<MeasuredPage>>,
 }
 
 pub struct IgvmVtlLoader<'a, R: VbsRegister + GuestArch> {
@@ -170,13 +175,15 @@ fn init(
         Box<dyn VpContextBuilder<Register = Self>>,
     );
 
-    /// Generate a measurement based on isolation type.
+    /// Generate a measurement based on isolation type. If `pages` is
+    /// provided, the contribution of each page is appended to it.
     fn generate_measurement(
         isolation: LoaderIsolationType,
         initialization_headers: &[IgvmInitializationHeader],
         directive_headers: &[IgvmDirectiveHeader],
         svn: u32,
         debug_enabled: bool,
+        pages: Option<&mut Vec<MeasuredPage>>,
     ) -> anyhow::Result<Option<Measurement>>;
 
     /// The IGVM file revision to use for the built igvm file.
@@ -264,11 +271,13 @@ fn generate_measurement(
         directive_headers: &[IgvmDirectiveHeader],
         svn: u32,
         debug_enabled: bool,
+        pages: Option<&mut Vec<MeasuredPage>>,
     ) -> anyhow::Result<Option<Measurement>> {
         let measurement = match isolation {
             LoaderIsolationType::Snp { .. } => {
-                let ld = generate_snp_measurement(initialization_headers, directive_headers, svn)
-                    .context("generating snp measurement failed")?;
+                let ld =
+                    generate_snp_measurement(initialization_headers, directive_headers, svn, pages)
+                        .context("generating snp measurement failed")?;
                 Some(Measurement::Snp(SnpMeasurement::new(
                     ld,
                     svn,
@@ -276,7 +285,7 @@ fn generate_measurement(
                 )))
             }
             LoaderIsolationType::Tdx { .. } => {
-                let mrtd = generate_tdx_measurement(directive_headers)
+                let mrtd = generate_tdx_measurement(directive_headers, pages)
                     .context("generating tdx measurement failed")?;
                 Some(Measurement::Tdx(TdxMeasurement::new(
                     mrtd,
@@ -285,8 +294,9 @@ fn generate_measurement(
                 )))
             }
             LoaderIsolationType::Vbs { enable_debug } => {
-                let boot_digest = generate_vbs_measurement(directive_headers, enable_debug, svn)
-                    .context("generating vbs measurement failed")?;
+                let boot_digest =
+                    generate_vbs_measurement(directive_headers, enable_debug, svn, pages)
+                        .context("generating vbs measurement failed")?;
                 Some(Measurement::Vbs(VbsMeasurement::new(
                     boot_digest,
                     svn,
@@ -325,6 +335,7 @@ fn generate_measurement(
         _directive_headers: &[IgvmDirectiveHeader],
         _svn: u32,
         _debug_enabled: bool,
+        _pages: Option<&mut Vec<MeasuredPage>>,
     ) -> anyhow::Result<Option<Measurement>> {
         Ok(None)
     }
@@ -487,6 +498,7 @@ pub struct IgvmOutput {
     pub guest: IgvmFile,
     pub map: MapFile,
     pub doc: Option<Measurement>,
+    pub measured_pages: Option<Vec<MeasuredPage>>,
 }
 
 impl<R: IgvmLoaderRegister + GuestArch + 'static> IgvmLoader<R> {
@@ -536,9 +548,23 @@ pub fn new(with_paravisor: bool, isolation_type: LoaderIsolationType) -> Self {
             isolation_type,
             paravisor_present: with_paravisor,
             imported_regions_config_page: None,
+            snp_id_block: None,
+            measured_pages: None,
         }
     }
 
+    /// Embed an ID block signed with the keys in `config` when finalizing
+    /// the file. Only valid for SNP.
+    pub fn set_snp_id_block(&mut self, config: SnpIdBlockConfig) {
+        self.snp_id_block = Some(config);
+    }
+
+    /// Record the contribution of each page to the launch measurement when
+    /// finalizing the file, returned in [`IgvmOutput::measured_pages`].
+    pub fn record_measured_pages(&mut self) {
+        self.measured_pages = Some(Vec::new());
+    }
+
     fn generate_cryptographic_hash_of_shared_pages(&mut self) -> Vec<u8> {
         // Sort the page data directives by GPA to ensure the hash is consistent.
         self.page_data_directives
@@ -662,8 +688,28 @@ pub fn finalize(mut self, guest_svn: u32) -> anyhow::Result<IgvmOutput> {
             &self.directives,
             guest_svn,
             self.confidential_debug(),
+            self.measured_pages.as_mut(),
         )?;
 
+        // The ID block is not measured, so it can be generated from the
+        // launch digest and added afterwards.
+        if let Some(config) = &self.snp_id_block {
+            let (LoaderIsolationType::Snp { policy, .. }, Some(Measurement::Snp(measurement))) =
+                (self.isolation_type, &doc)
+            else {
+                anyhow::bail!("an ID block can only be generated for SNP");
+            };
+            let id_block = generate_snp_id_block(
+                measurement.series[0].reference.snp_ld,
+                policy.into(),
+                guest_svn,
+                config,
+            )
+            .context("generating snp id block failed")?;
+            self.directives
+                .push(IgvmDirectiveHeader::SnpIdBlock(Box::new(id_block)));
+        }
+
         // Display a report about the build igvm file's layout.
         let map_file = MapFile {
             isolation: self.isolation_type,
@@ -707,6 +753,7 @@ pub fn finalize(mut self, guest_svn: u32) -> anyhow::Result<IgvmOutput> {
             guest: igvm_file,
             map: map_file,
             doc,
+            measured_pages: self.measured_pages,
         };
         Ok(output)
     }
@@ -1282,6 +1329,44 @@ fn test_snp_measurement() {
         assert_eq!(ref_ld, snp_measurement.series[0].reference.snp_ld);
     }
 
+    #[test]
+    fn test_snp_measured_pages() {
+        let mut loader = IgvmLoader::<X86Register>::new(
+            true,
+            LoaderIsolationType::Snp {
+                shared_gpa_boundary_bits: Some(39),
+                policy: SnpPolicy::from((0x1 << 17) | (0x1 << 16) | (0x1f)),
+                injection_type: InjectionType::Restricted,
+            },
+        );
+        loader.record_measured_pages();
+        let data = vec![0, 5];
+        loader
+            .import_pages(0, 2, "data", BootPageAcceptance::Exclusive, &data)
+            .unwrap();
+        loader
+            .import_pages(5, 1, "data", BootPageAcceptance::ExclusiveUnmeasured, &data)
+            .unwrap();
+        loader
+            .import_pages(20, 1, "data", BootPageAcceptance::Shared, &data)
+            .unwrap();
+
+        let igvm_output = loader.finalize(1).unwrap();
+        let pages = igvm_output.measured_pages.expect("pages");
+        let page = |gpa| pages.iter().find(|page| page.gpa == gpa);
+
+        let normal = page(0).expect("measured");
+        assert_eq!(normal.page_type, "normal");
+        assert!(normal.contents_digest.is_some());
+        assert_eq!(page(PAGE_SIZE_4K).expect("measured").page_type, "normal");
+
+        let unmeasured = page(5 * PAGE_SIZE_4K).expect("measured");
+        assert_eq!(unmeasured.page_type, "unmeasured");
+        assert!(unmeasured.contents_digest.is_none());
+
+        assert!(page(20 * PAGE_SIZE_4K).is_none());
+    }
+
     #[test]
     fn test_tdx_measurement() {
         let ref_mrtd: [u8; 48] = [
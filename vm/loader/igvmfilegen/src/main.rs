@@ -5,11 +5,16 @@
 
 mod file_loader;
 mod identity_mapping;
+mod measurement_report;
 mod signed_measurement;
+mod uki;
 mod vp_context_builder;
 
 use crate::file_loader::IgvmLoader;
 use crate::file_loader::LoaderIsolationType;
+use crate::measurement_report::GuestMeasurement;
+use crate::measurement_report::MeasurementReport;
+use crate::uki::Uki;
 use anyhow::bail;
 use anyhow::Context;
 use clap::Parser;
@@ -36,6 +41,7 @@
 use loader::paravisor::CommandLineType;
 use loader::paravisor::Vtl0Config;
 use loader::paravisor::Vtl0Linux;
+use std::ffi::CString;
 use std::io::Write;
 use std::path::PathBuf;
 use tracing_subscriber::filter::LevelFilter;
@@ -69,9 +75,20 @@ enum Options {
         /// Additional debug validation when building IGVM files
         #[clap(long)]
         debug_validation: bool,
+        /// Also write the launch measurements and the pages contributing to
+        /// them to `<output>-measurements.json`, in the given format
+        #[clap(long, value_enum)]
+        measurement_output: Option<MeasurementOutput>,
     },
 }
 
+/// The format of the measurement report.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum MeasurementOutput {
+    /// A JSON document, for attestation reference value pipelines
+    Json,
+}
+
 // TODO: Potential CLI flags:
 //       --report: Dump additional data like what memory ranges were accepted with what values
 
@@ -105,6 +122,7 @@ fn main() -> anyhow::Result<()> {
             resources,
             output,
             debug_validation,
+            measurement_output,
         } => {
             // Read the config from the JSON manifest path.
             let config: Config = serde_json::from_str(
@@ -137,12 +155,14 @@ fn main() -> anyhow::Result<()> {
                     resources,
                     debug_validation || cfg!(debug_assertions),
                     output,
+                    measurement_output,
                 ),
                 igvmfilegen_config::GuestArch::Aarch64 => create_igvm_file::<Aarch64Register>(
                     config,
                     resources,
                     debug_validation || cfg!(debug_assertions),
                     output,
+                    measurement_output,
                 ),
             }
         }
@@ -155,11 +175,13 @@ fn create_igvm_file<R: IgvmfilegenRegister + GuestArch + 'static>(
     resources: Resources,
     debug_validation: bool,
     output: PathBuf,
+    measurement_output: Option<MeasurementOutput>,
 ) -> anyhow::Result<()> {
     tracing::debug!(?igvm_config, "Creating IGVM file",);
 
     let mut igvm_file: Option<IgvmFile> = None;
     let mut map_files = Vec::new();
+    let mut guest_measurements = Vec::new();
     let base_path = output.file_stem().unwrap();
     for config in igvm_config.guest_configs {
         // Max VTL must be 2 or 0.
@@ -181,6 +203,7 @@ fn create_igvm_file<R: IgvmfilegenRegister + GuestArch + 'static>(
                 policy,
                 enable_debug,
                 injection_type,
+                id_block: _,
             } => LoaderIsolationType::Snp {
                 shared_gpa_boundary_bits,
                 policy: SnpPolicy::from(policy).with_debug(enable_debug as u8),
@@ -205,6 +228,16 @@ fn create_igvm_file<R: IgvmfilegenRegister + GuestArch + 'static>(
         let with_paravisor = config.max_vtl == 2;
 
         let mut loader = IgvmLoader::<R>::new(with_paravisor, loader_isolation_type);
+        if let ConfigIsolationType::Snp {
+            id_block: Some(id_block),
+            ..
+        } = config.isolation_type
+        {
+            loader.set_snp_id_block(id_block);
+        }
+        if measurement_output.is_some() {
+            loader.record_measured_pages();
+        }
 
         load_image(&mut loader.loader(), &config.image, &resources)?;
 
@@ -222,6 +255,10 @@ fn create_igvm_file<R: IgvmfilegenRegister + GuestArch + 'static>(
 
         map_files.push(igvm_output.map);
 
+        if let (Some(doc), Some(pages)) = (&igvm_output.doc, igvm_output.measured_pages) {
+            guest_measurements.push(GuestMeasurement::new(isolation_string, doc, pages));
+        }
+
         if let Some(doc) = igvm_output.doc {
             // Write the measurement document to a file with the same name,
             // but with -[isolation].json extension.
@@ -251,6 +288,24 @@ fn create_igvm_file<R: IgvmfilegenRegister + GuestArch + 'static>(
         }
     }
 
+    if let Some(MeasurementOutput::Json) = measurement_output {
+        let report_path = {
+            let mut name = base_path.to_os_string();
+            name.push("-measurements.json");
+            output.with_file_name(name)
+        };
+        tracing::info!(
+            path = %report_path.display(),
+            "Writing measurement report",
+        );
+        let report = MeasurementReport::new(guest_measurements);
+        fs_err::write(
+            report_path,
+            serde_json::to_string_pretty(&report).expect("json string"),
+        )
+        .context("writing measurement report")?;
+    }
+
     let mut igvm_binary = Vec::new();
     let igvm_file = igvm_file.expect("should have an igvm file");
     igvm_file
@@ -417,6 +472,7 @@ fn load_openhcl<F>(
         sidecar: Option<&mut F>,
         command_line: CommandLineType<'_>,
         initrd: Option<&[u8]>,
+        compress_kernel: bool,
         memory_page_base: Option<u64>,
         memory_page_count: u64,
         vtl0_config: Vtl0Config<'_>,
@@ -459,6 +515,7 @@ fn load_openhcl<F>(
         sidecar: Option<&mut F>,
         command_line: CommandLineType<'_>,
         initrd: Option<&[u8]>,
+        compress_kernel: bool,
         memory_page_base: Option<u64>,
         memory_page_count: u64,
         vtl0_config: Vtl0Config<'_>,
@@ -473,6 +530,7 @@ fn load_openhcl<F>(
             sidecar,
             command_line,
             initrd,
+            compress_kernel,
             memory_page_base,
             memory_page_count,
             vtl0_config,
@@ -515,6 +573,7 @@ fn load_openhcl<F>(
         _sidecar: Option<&mut F>,
         command_line: CommandLineType<'_>,
         initrd: Option<&[u8]>,
+        _compress_kernel: bool,
         memory_page_base: Option<u64>,
         memory_page_count: u64,
         vtl0_config: Vtl0Config<'_>,
@@ -560,11 +619,17 @@ fn load_image<'a, R: IgvmfilegenRegister + GuestArch + 'static>(
             memory_page_count,
             uefi,
             ref linux,
+            ref initrd_overlays,
+            compress_kernel,
         } => {
             if uefi && linux.is_some() {
                 anyhow::bail!("cannot include both UEFI and Linux images in OpenHCL image");
             }
 
+            if compress_kernel && loader.loader().arch() != GuestArchKind::X86_64 {
+                anyhow::bail!("OpenHCL kernel compression is only supported on x86_64");
+            }
+
             let kernel_path = resources
                 .get(ResourceType::UnderhillKernel)
                 .expect("validated present");
@@ -577,10 +642,12 @@ fn load_image<'a, R: IgvmfilegenRegister + GuestArch + 'static>(
                 let initrd_path = resources
                     .get(ResourceType::UnderhillInitrd)
                     .expect("validated present");
-                Some(fs_err::read(initrd_path).context(format!(
+                let mut initrd = fs_err::read(initrd_path).context(format!(
                     "reading underhill initrd at {}",
                     initrd_path.display()
-                ))?)
+                ))?;
+                append_initrd_overlays(&mut initrd, initrd_overlays)?;
+                Some(initrd)
             };
 
             let shim_path = resources
@@ -597,6 +664,7 @@ fn load_image<'a, R: IgvmfilegenRegister + GuestArch + 'static>(
                 };
 
             let initrd_slice = initrd.as_deref();
+            let linux_command_line;
 
             // TODO: While the paravisor supports multiple things that can be
             // loaded in VTL0, we don't yet have updated file builder config for
@@ -615,12 +683,14 @@ fn load_image<'a, R: IgvmfilegenRegister + GuestArch + 'static>(
                     supports_linux: None,
                 }
             } else if let Some(linux) = linux {
-                let load_info = load_linux(&mut loader.nested_loader(), linux, resources)?;
+                let (load_info, command_line) =
+                    load_linux(&mut loader.nested_loader(), linux, resources)?;
+                linux_command_line = command_line;
                 Vtl0Config {
                     supports_pcat: false,
                     supports_uefi: None,
                     supports_linux: Some(Vtl0Linux {
-                        command_line: &linux.command_line,
+                        command_line: &linux_command_line,
                         load_info,
                     }),
                 }
@@ -655,6 +725,7 @@ fn load_image<'a, R: IgvmfilegenRegister + GuestArch + 'static>(
                 sidecar.as_mut(),
                 command_line,
                 initrd_slice,
+                compress_kernel,
                 memory_page_base,
                 memory_page_count,
                 vtl0_load_config,
@@ -684,31 +755,59 @@ fn load_uefi<R: IgvmfilegenRegister + GuestArch + 'static>(
     Ok(load_info)
 }
 
+/// Loads the Linux kernel and initrd, returning the load info and the
+/// command line to boot the kernel with.
 fn load_linux<R: IgvmfilegenRegister + GuestArch + 'static>(
     loader: &mut IgvmVtlLoader<'_, R>,
     config: &LinuxImage,
     resources: &Resources,
-) -> Result<loader::linux::LoadInfo, anyhow::Error> {
+) -> Result<(loader::linux::LoadInfo, CString), anyhow::Error> {
     let LinuxImage {
         use_initrd,
-        command_line: _,
+        ref command_line,
+        from_uki,
+        ref initrd_overlays,
     } = *config;
-    let kernel_path = resources
-        .get(ResourceType::LinuxKernel)
-        .expect("validated present");
-    let mut kernel = fs_err::File::open(kernel_path).context(format!(
-        "reading vtl0 kernel image at {}",
-        kernel_path.display()
-    ))?;
-    let initrd_vec = if use_initrd {
-        let initrd_path = resources
-            .get(ResourceType::LinuxInitrd)
+    let (kernel, mut initrd_vec, command_line) = if from_uki {
+        let uki_path = resources
+            .get(ResourceType::LinuxUki)
             .expect("validated present");
-        fs_err::read(initrd_path)
-            .context(format!("reading vtl0 initrd at {}", initrd_path.display()))?
+        let uki = Uki::parse(
+            &fs_err::read(uki_path)
+                .context(format!("reading vtl0 uki at {}", uki_path.display()))?,
+        )
+        .context(format!("parsing vtl0 uki at {}", uki_path.display()))?;
+        let initrd = if use_initrd {
+            uki.initrd.context("uki has no initrd")?
+        } else {
+            Vec::new()
+        };
+        let command_line = if command_line.is_empty() {
+            uki.command_line.unwrap_or_default()
+        } else {
+            command_line.clone()
+        };
+        (uki.kernel, initrd, command_line)
     } else {
-        Vec::new()
+        let kernel_path = resources
+            .get(ResourceType::LinuxKernel)
+            .expect("validated present");
+        let kernel = fs_err::read(kernel_path).context(format!(
+            "reading vtl0 kernel image at {}",
+            kernel_path.display()
+        ))?;
+        let initrd = if use_initrd {
+            let initrd_path = resources
+                .get(ResourceType::LinuxInitrd)
+                .expect("validated present");
+            fs_err::read(initrd_path)
+                .context(format!("reading vtl0 initrd at {}", initrd_path.display()))?
+        } else {
+            Vec::new()
+        };
+        (kernel, initrd, command_line.clone())
     };
+    append_initrd_overlays(&mut initrd_vec, initrd_overlays)?;
     let initrd = if initrd_vec.is_empty() {
         None
     } else {
@@ -717,7 +816,22 @@ fn load_linux<R: IgvmfilegenRegister + GuestArch + 'static>(
             initrd: &initrd_vec,
         })
     };
-    let load_info = R::load_linux_kernel_and_initrd(loader, &mut kernel, 0, initrd, None)
-        .context("loading linux kernel and initrd")?;
-    Ok(load_info)
+    let load_info =
+        R::load_linux_kernel_and_initrd(loader, &mut std::io::Cursor::new(kernel), 0, initrd, None)
+            .context("loading linux kernel and initrd")?;
+    Ok((load_info, command_line))
+}
+
+/// Appends each of `overlays` to `initrd`, following the Linux convention for
+/// multiple concatenated initrds.
+fn append_initrd_overlays(initrd: &mut Vec<u8>, overlays: &[PathBuf]) -> anyhow::Result<()> {
+    for overlay in overlays {
+        // Each initrd must start 4 byte aligned. The kernel skips the zero
+        // padding between them.
+        initrd.resize(initrd.len().next_multiple_of(4), 0);
+        let data = fs_err::read(overlay)
+            .context(format!("reading initrd overlay at {}", overlay.display()))?;
+        initrd.extend_from_slice(&data);
+    }
+    Ok(())
 }
@@ -0,0 +1,101 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Implements machine-readable reports of the launch measurements of built
+//! IGVM files, for use by attestation reference value pipelines.
+//!
+//! Besides the launch digest of each guest, the report lists how each page
+//! contributed to it, in measurement order, so that differences between two
+//! builds can be traced back to the pages that caused them.
+
+use crate::identity_mapping::Measurement;
+use serde::Serialize;
+
+/// The version of the report schema, incremented on incompatible changes.
+const MEASUREMENT_REPORT_VERSION: u32 = 1;
+
+/// The measurements of all the guests in an IGVM file.
+#[derive(Serialize, Debug)]
+pub struct MeasurementReport {
+    pub version: u32,
+    pub guests: Vec<GuestMeasurement>,
+}
+
+impl MeasurementReport {
+    pub fn new(guests: Vec<GuestMeasurement>) -> Self {
+        Self {
+            version: MEASUREMENT_REPORT_VERSION,
+            guests,
+        }
+    }
+}
+
+/// The measurement of a single guest.
+#[derive(Serialize, Debug)]
+pub struct GuestMeasurement {
+    /// The isolation type, as in the manifest.
+    pub isolation: &'static str,
+    pub guest_svn: u32,
+    pub debug_enabled: bool,
+    /// The hash algorithm of the launch digest.
+    pub digest_algorithm: &'static str,
+    /// The launch digest: the SNP launch digest, the TDX MRTD, or the VBS
+    /// boot digest. Upper case hex.
+    pub launch_digest: String,
+    /// The pages contributing to the launch digest, in measurement order.
+    pub pages: Vec<MeasuredPage>,
+}
+
+impl GuestMeasurement {
+    pub fn new(isolation: &'static str, doc: &Measurement, pages: Vec<MeasuredPage>) -> Self {
+        let (digest_algorithm, launch_digest, guest_svn, debug_enabled) = match doc {
+            Measurement::Snp(m) => {
+                let m = &m.series[0];
+                (
+                    "sha384",
+                    hex::encode_upper(m.reference.snp_ld),
+                    m.endorsement.snp_isvsvn,
+                    m.endorsement.build_info.debug_build,
+                )
+            }
+            Measurement::Tdx(m) => {
+                let m = &m.series[0];
+                (
+                    "sha384",
+                    hex::encode_upper(m.reference.tdx_mrtd),
+                    m.endorsement.tdx_isvsvn,
+                    m.endorsement.build_info.debug_build,
+                )
+            }
+            Measurement::Vbs(m) => {
+                let m = &m.series[0];
+                (
+                    "sha256",
+                    hex::encode_upper(m.reference.vbs_boot_digest),
+                    m.endorsement.vbs_isvsvn,
+                    m.endorsement.build_info.debug_build,
+                )
+            }
+        };
+        Self {
+            isolation,
+            guest_svn,
+            debug_enabled,
+            digest_algorithm,
+            launch_digest,
+            pages,
+        }
+    }
+}
+
+/// The contribution of a single page to a launch digest.
+#[derive(Serialize, Debug)]
+pub struct MeasuredPage {
+    pub gpa: u64,
+    /// How the page was measured, such as `normal`, `unmeasured` or `vmsa`.
+    pub page_type: &'static str,
+    /// The digest of the zero extended page contents, using the launch
+    /// digest's hash algorithm. Not present if the contents are not measured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contents_digest: Option<String>,
+}
@@ -4,12 +4,27 @@
 //! Creates a digest for supported isolation types which can be signed externally.
 
 pub mod snp;
+#[cfg(unix)]
+pub mod snp_id_block;
 pub mod tdx;
 pub mod vbs;
 
 pub use snp::generate_snp_measurement;
+#[cfg(unix)]
+pub use snp_id_block::generate_snp_id_block;
 pub use tdx::generate_tdx_measurement;
 pub use vbs::generate_vbs_measurement;
 
 const SHA_256_OUTPUT_SIZE_BYTES: usize = 32;
 const SHA_384_OUTPUT_SIZE_BYTES: usize = 48;
+
+/// Signing SNP ID blocks requires openssl, which is only used on unix.
+#[cfg(not(unix))]
+pub fn generate_snp_id_block(
+    _ld: [u8; SHA_384_OUTPUT_SIZE_BYTES],
+    _policy: u64,
+    _svn: u32,
+    _config: &igvmfilegen_config::SnpIdBlockConfig,
+) -> anyhow::Result<igvm_defs::IGVM_VHS_SNP_ID_BLOCK> {
+    anyhow::bail!("signing SNP ID blocks is only supported on unix")
+}
@@ -5,6 +5,7 @@
 
 use super::SHA_384_OUTPUT_SIZE_BYTES;
 use crate::file_loader::DEFAULT_COMPATIBILITY_MASK;
+use crate::measurement_report::MeasuredPage;
 use igvm::IgvmDirectiveHeader;
 use igvm::IgvmInitializationHeader;
 use igvm_defs::IgvmPageDataType;
@@ -26,10 +27,13 @@ pub enum Error {
 
 /// Iterate through all headers, creating a launch digest which is then signed,
 /// returning an [`IgvmDirectiveHeader::SnpIdBlock`]
+///
+/// If `pages` is provided, the contribution of each page is appended to it.
 pub fn generate_snp_measurement(
     initialization_headers: &[IgvmInitializationHeader],
     directive_headers: &[IgvmDirectiveHeader],
     svn: u32,
+    mut pages: Option<&mut Vec<MeasuredPage>>,
 ) -> Result<[u8; SHA_384_OUTPUT_SIZE_BYTES], Error> {
     let mut parameter_area_table = HashMap::new();
     const PAGE_SIZE_4K_USIZE: usize = PAGE_SIZE_4K as usize;
@@ -75,6 +79,14 @@ pub fn generate_snp_measurement(
             None => [0; SHA_384_OUTPUT_SIZE_BYTES].into(),
         };
 
+        if let Some(pages) = pages.as_deref_mut() {
+            pages.push(MeasuredPage {
+                gpa,
+                page_type: page_type_name(page_type),
+                contents_digest: page_data.map(|_| hex::encode_upper(hash_contents)),
+            });
+        }
+
         let info = SnpPageInfo {
             digest_current: launch_digest,
             contents: hash_contents.into(),
@@ -214,3 +226,15 @@ pub fn generate_snp_measurement(
     tracing::info!("SNP ID Block {:x?}", psp_id_block);
     Ok(psp_id_block.ld)
 }
+
+fn page_type_name(page_type: SnpPageType) -> &'static str {
+    match page_type {
+        SnpPageType::NORMAL => "normal",
+        SnpPageType::VMSA => "vmsa",
+        SnpPageType::ZERO => "zero",
+        SnpPageType::UNMEASURED => "unmeasured",
+        SnpPageType::SECRETS => "secrets",
+        SnpPageType::CPUID => "cpuid",
+        _ => "unknown",
+    }
+}
@@ -0,0 +1,317 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Support for signing SNP ID blocks, so that the guest can only be launched
+//! with the expected launch digest and policy.
+
+use super::SHA_384_OUTPUT_SIZE_BYTES;
+use crate::file_loader::DEFAULT_COMPATIBILITY_MASK;
+use anyhow::Context;
+use igvm_defs::IGVM_VHS_SNP_ID_BLOCK;
+use igvm_defs::IGVM_VHS_SNP_ID_BLOCK_PUBLIC_KEY;
+use igvm_defs::IGVM_VHS_SNP_ID_BLOCK_SIGNATURE;
+use igvmfilegen_config::SnpIdBlockConfig;
+use igvmfilegen_config::SnpSigningKey;
+use openssl::bn::BigNum;
+use openssl::bn::BigNumContext;
+use openssl::bn::BigNumRef;
+use openssl::ec::EcKey;
+use openssl::ecdsa::EcdsaSig;
+use openssl::nid::Nid;
+use openssl::pkey::Private;
+use openssl::pkey::Public;
+use sha2::Digest;
+use sha2::Sha384;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+use x86defs::snp::SnpPspIdBlock;
+use zerocopy::AsBytes;
+use zerocopy::FromZeroes;
+
+/// The ID block version supported by the PSP.
+const ID_BLOCK_VERSION: u32 = 1;
+/// The ECDSA P-384 with SHA-384 signature algorithm.
+const ALGORITHM_ECDSA_P384_SHA384: u32 = 1;
+/// The P-384 curve.
+const CURVE_P384: u32 = 2;
+/// The size of the PSP's public key structure, which is signed by the author
+/// key.
+const PSP_PUBLIC_KEY_SIZE: usize = 0x404;
+/// The size of the ECDSA signature components and public key coordinates.
+const ECDSA_COMPONENT_SIZE: usize = 72;
+
+/// Generates the ID block for a guest with the launch digest `ld`, signed
+/// with the keys in `config`.
+///
+/// The policy is not stored in the ID block, but is signed, so it must match
+/// the policy in the file's initialization headers.
+pub fn generate_snp_id_block(
+    ld: [u8; SHA_384_OUTPUT_SIZE_BYTES],
+    policy: u64,
+    svn: u32,
+    config: &SnpIdBlockConfig,
+) -> anyhow::Result<IGVM_VHS_SNP_ID_BLOCK> {
+    let family_id = parse_id(&config.family_id).context("invalid family id")?;
+    let image_id = parse_id(&config.image_id).context("invalid image id")?;
+    let id_key = Signer::new(&config.id_key).context("failed to load id key")?;
+    let author_key = config
+        .author_key
+        .as_ref()
+        .map(Signer::new)
+        .transpose()
+        .context("failed to load author key")?;
+
+    build_id_block(
+        SnpPspIdBlock {
+            ld,
+            family_id,
+            image_id,
+            version: ID_BLOCK_VERSION,
+            guest_svn: svn,
+            policy,
+        },
+        &id_key,
+        author_key.as_ref(),
+    )
+}
+
+fn build_id_block(
+    psp_id_block: SnpPspIdBlock,
+    id_key: &Signer,
+    author_key: Option<&Signer>,
+) -> anyhow::Result<IGVM_VHS_SNP_ID_BLOCK> {
+    let id_key_signature = id_key
+        .sign(psp_id_block.as_bytes())
+        .context("failed to sign id block")?;
+    let id_public_key = id_key.public_key()?;
+
+    let mut id_block = IGVM_VHS_SNP_ID_BLOCK {
+        compatibility_mask: DEFAULT_COMPATIBILITY_MASK,
+        author_key_enabled: 0,
+        reserved: [0; 3],
+        ld: psp_id_block.ld,
+        family_id: psp_id_block.family_id,
+        image_id: psp_id_block.image_id,
+        version: psp_id_block.version,
+        guest_svn: psp_id_block.guest_svn,
+        id_key_algorithm: ALGORITHM_ECDSA_P384_SHA384,
+        author_key_algorithm: 0,
+        id_key_signature,
+        id_public_key,
+        author_key_signature: IGVM_VHS_SNP_ID_BLOCK_SIGNATURE::new_zeroed(),
+        author_public_key: IGVM_VHS_SNP_ID_BLOCK_PUBLIC_KEY::new_zeroed(),
+    };
+
+    if let Some(author_key) = author_key {
+        id_block.author_key_enabled = 1;
+        id_block.author_key_algorithm = ALGORITHM_ECDSA_P384_SHA384;
+        id_block.author_key_signature = author_key
+            .sign(&psp_public_key(&id_block.id_public_key))
+            .context("failed to sign id key")?;
+        id_block.author_public_key = author_key.public_key()?;
+    }
+
+    Ok(id_block)
+}
+
+/// Converts an ID to the zero padded form in the ID block.
+fn parse_id(id: &str) -> anyhow::Result<[u8; 16]> {
+    let mut value = [0; 16];
+    if !id.is_ascii() || id.len() > value.len() {
+        anyhow::bail!("{id:?} is not at most {} ASCII characters", value.len());
+    }
+    value[..id.len()].copy_from_slice(id.as_bytes());
+    Ok(value)
+}
+
+/// Returns the public key in the PSP's format, which is what the author key
+/// signs.
+fn psp_public_key(key: &IGVM_VHS_SNP_ID_BLOCK_PUBLIC_KEY) -> Vec<u8> {
+    let mut data = vec![0; PSP_PUBLIC_KEY_SIZE];
+    data[..4].copy_from_slice(key.curve.as_bytes());
+    data[4..4 + ECDSA_COMPONENT_SIZE].copy_from_slice(&key.qx);
+    data[4 + ECDSA_COMPONENT_SIZE..4 + 2 * ECDSA_COMPONENT_SIZE].copy_from_slice(&key.qy);
+    data
+}
+
+/// Converts a big number to the PSP's zero extended little endian format.
+fn to_psp_component(n: &BigNumRef) -> [u8; ECDSA_COMPONENT_SIZE] {
+    let mut value = [0; ECDSA_COMPONENT_SIZE];
+    let be = n.to_vec();
+    for (dst, src) in value.iter_mut().zip(be.iter().rev()) {
+        *dst = *src;
+    }
+    value
+}
+
+/// An ECDSA P-384 signing key.
+enum Signer {
+    PrivateKey(EcKey<Private>),
+    Command {
+        public_key: EcKey<Public>,
+        command: Vec<String>,
+    },
+}
+
+impl Signer {
+    fn new(key: &SnpSigningKey) -> anyhow::Result<Self> {
+        let signer = match key {
+            SnpSigningKey::PrivateKey(path) => {
+                let pem = fs_err::read(path)?;
+                Signer::PrivateKey(
+                    EcKey::private_key_from_pem(&pem).context("failed to parse private key")?,
+                )
+            }
+            SnpSigningKey::Command {
+                public_key,
+                command,
+            } => {
+                if command.is_empty() {
+                    anyhow::bail!("no signing command specified");
+                }
+                let pem = fs_err::read(public_key)?;
+                Signer::Command {
+                    public_key: EcKey::public_key_from_pem(&pem)
+                        .context("failed to parse public key")?,
+                    command: command.clone(),
+                }
+            }
+        };
+
+        if signer.verifying_key()?.group().curve_name() != Some(Nid::SECP384R1) {
+            anyhow::bail!("signing keys must be on the P-384 curve");
+        }
+        Ok(signer)
+    }
+
+    fn verifying_key(&self) -> anyhow::Result<EcKey<Public>> {
+        let key = match self {
+            Signer::PrivateKey(key) => EcKey::from_public_key(key.group(), key.public_key())?,
+            Signer::Command { public_key, .. } => public_key.clone(),
+        };
+        Ok(key)
+    }
+
+    fn public_key(&self) -> anyhow::Result<IGVM_VHS_SNP_ID_BLOCK_PUBLIC_KEY> {
+        let key = self.verifying_key()?;
+        let mut ctx = BigNumContext::new()?;
+        let mut x = BigNum::new()?;
+        let mut y = BigNum::new()?;
+        key.public_key()
+            .affine_coordinates_gfp(key.group(), &mut x, &mut y, &mut ctx)?;
+        Ok(IGVM_VHS_SNP_ID_BLOCK_PUBLIC_KEY {
+            curve: CURVE_P384,
+            reserved: 0,
+            qx: to_psp_component(&x),
+            qy: to_psp_component(&y),
+        })
+    }
+
+    /// Signs the SHA-384 digest of `data`.
+    fn sign(&self, data: &[u8]) -> anyhow::Result<IGVM_VHS_SNP_ID_BLOCK_SIGNATURE> {
+        let digest = Sha384::digest(data);
+        let signature = match self {
+            Signer::PrivateKey(key) => EcdsaSig::sign(&digest, key)?,
+            Signer::Command { command, .. } => {
+                let mut child = Command::new(&command[0])
+                    .args(&command[1..])
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .spawn()
+                    .with_context(|| format!("failed to launch {:?}", command[0]))?;
+                child.stdin.take().unwrap().write_all(data)?;
+                let output = child.wait_with_output()?;
+                if !output.status.success() {
+                    anyhow::bail!("signing command failed: {}", output.status);
+                }
+                EcdsaSig::from_der(&output.stdout).context("failed to parse signature")?
+            }
+        };
+
+        // Catch misconfigured external signers here rather than at launch.
+        if !signature.verify(&digest, &self.verifying_key()?)? {
+            anyhow::bail!("signature does not match the public key");
+        }
+
+        Ok(IGVM_VHS_SNP_ID_BLOCK_SIGNATURE {
+            r_comp: to_psp_component(signature.r()),
+            s_comp: to_psp_component(signature.s()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::EcGroup;
+
+    fn from_psp_component(value: &[u8; ECDSA_COMPONENT_SIZE]) -> BigNum {
+        let be: Vec<u8> = value.iter().rev().copied().collect();
+        BigNum::from_slice(&be).unwrap()
+    }
+
+    fn verify(
+        key: &IGVM_VHS_SNP_ID_BLOCK_PUBLIC_KEY,
+        signature: &IGVM_VHS_SNP_ID_BLOCK_SIGNATURE,
+        data: &[u8],
+    ) -> bool {
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let key = EcKey::from_public_key_affine_coordinates(
+            &group,
+            &from_psp_component(&key.qx),
+            &from_psp_component(&key.qy),
+        )
+        .unwrap();
+        let signature = EcdsaSig::from_private_components(
+            from_psp_component(&signature.r_comp),
+            from_psp_component(&signature.s_comp),
+        )
+        .unwrap();
+        signature.verify(&Sha384::digest(data), &key).unwrap()
+    }
+
+    #[test]
+    fn id_block_signatures() {
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let id_key = Signer::PrivateKey(EcKey::generate(&group).unwrap());
+        let author_key = Signer::PrivateKey(EcKey::generate(&group).unwrap());
+
+        let psp_id_block = SnpPspIdBlock {
+            ld: [0xab; 48],
+            family_id: parse_id("msft").unwrap(),
+            image_id: parse_id("underhill").unwrap(),
+            version: ID_BLOCK_VERSION,
+            guest_svn: 3,
+            policy: 0x3001f,
+        };
+
+        let id_block = build_id_block(psp_id_block, &id_key, None).unwrap();
+        assert_eq!(id_block.author_key_enabled, 0);
+        assert!(verify(
+            &id_block.id_public_key,
+            &id_block.id_key_signature,
+            psp_id_block.as_bytes()
+        ));
+
+        let id_block = build_id_block(psp_id_block, &id_key, Some(&author_key)).unwrap();
+        assert_eq!(id_block.author_key_enabled, 1);
+        assert!(verify(
+            &id_block.id_public_key,
+            &id_block.id_key_signature,
+            psp_id_block.as_bytes()
+        ));
+        assert!(verify(
+            &id_block.author_public_key,
+            &id_block.author_key_signature,
+            &psp_public_key(&id_block.id_public_key)
+        ));
+    }
+
+    #[test]
+    fn invalid_id() {
+        assert!(parse_id("0123456789abcdef").is_ok());
+        assert!(parse_id("0123456789abcdefg").is_err());
+        assert!(parse_id("caf\u{e9}").is_err());
+    }
+}
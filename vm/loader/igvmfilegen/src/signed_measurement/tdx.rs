@@ -5,6 +5,7 @@
 
 use super::SHA_384_OUTPUT_SIZE_BYTES;
 use crate::file_loader::DEFAULT_COMPATIBILITY_MASK;
+use crate::measurement_report::MeasuredPage;
 use igvm::IgvmDirectiveHeader;
 use igvm_defs::PAGE_SIZE_4K;
 use sha2::Digest;
@@ -50,8 +51,11 @@ pub struct TdxMrExtend {
 }
 
 /// Iterate through all headers to create the MRTD.
+///
+/// If `pages` is provided, the contribution of each page is appended to it.
 pub fn generate_tdx_measurement(
     directive_headers: &[IgvmDirectiveHeader],
+    mut pages: Option<&mut Vec<MeasuredPage>>,
 ) -> Result<[u8; SHA_384_OUTPUT_SIZE_BYTES], Error> {
     let mut parameter_area_table = HashMap::new();
     const PAGE_SIZE_4K_USIZE: usize = PAGE_SIZE_4K as usize;
@@ -84,6 +88,16 @@ pub fn generate_tdx_measurement(
                 }
             };
 
+            if let Some(pages) = pages.as_deref_mut() {
+                pages.push(MeasuredPage {
+                    gpa,
+                    page_type: "normal",
+                    contents_digest: Some(hex::encode_upper(Sha384::digest(
+                        data.unwrap_or(&[0; PAGE_SIZE_4K_USIZE]),
+                    ))),
+                });
+            }
+
             // Hash the contents of the 4K page, 256 bytes at a time.
             for offset in (0..PAGE_SIZE_4K).step_by(TDX_EXTEND_CHUNK_SIZE) {
                 let mut mr_extend = TdxMrExtend {
@@ -101,6 +115,12 @@ pub fn generate_tdx_measurement(
                 }
                 hasher.update(mr_extend.as_bytes());
             }
+        } else if let Some(pages) = pages.as_deref_mut() {
+            pages.push(MeasuredPage {
+                gpa,
+                page_type: "unmeasured",
+                contents_digest: None,
+            });
         };
     };
 
@@ -5,6 +5,7 @@
 
 use super::SHA_256_OUTPUT_SIZE_BYTES;
 use crate::file_loader::DEFAULT_COMPATIBILITY_MASK;
+use crate::measurement_report::MeasuredPage;
 use igvm::IgvmDirectiveHeader;
 use igvm_defs::IgvmPageDataType;
 use igvm_defs::VbsDigestAlgorithm;
@@ -35,14 +36,17 @@ pub enum Error {
 
 /// Iterate through all headers, creating a boot measurement which is then signed,
 /// returning an [`IgvmDirectiveHeader::VbsMeasurement`]
+///
+/// If `pages` is provided, the contribution of each page is appended to it.
 pub fn generate_vbs_measurement(
     directive_headers: &[IgvmDirectiveHeader],
     enable_debug: bool,
     svn: u32,
+    pages: Option<&mut Vec<MeasuredPage>>,
 ) -> Result<[u8; SHA_256_OUTPUT_SIZE_BYTES], Error> {
     const VBS_COMPATIBILITY_MASK: u32 = DEFAULT_COMPATIBILITY_MASK;
 
-    let mut digest = VbsDigestor::new()?;
+    let mut digest = VbsDigestor::new(pages)?;
     let mut parameter_area_table = HashMap::new();
     let mut bsp_regs = Vec::new();
 
@@ -187,14 +191,16 @@ pub fn generate_vbs_measurement(
     Ok(boot_measurement.boot_measurement_digest)
 }
 
-struct VbsDigestor {
+struct VbsDigestor<'a> {
     digest: [u8; SHA_256_OUTPUT_SIZE_BYTES],
+    pages: Option<&'a mut Vec<MeasuredPage>>,
 }
 
-impl VbsDigestor {
-    fn new() -> Result<VbsDigestor, Error> {
+impl<'a> VbsDigestor<'a> {
+    fn new(pages: Option<&'a mut Vec<MeasuredPage>>) -> Result<Self, Error> {
         Ok(VbsDigestor {
             digest: [0; SHA_256_OUTPUT_SIZE_BYTES],
+            pages,
         })
     }
 
@@ -216,6 +222,21 @@ fn record_gpa_page(
             // If page is under 4K bytes, pad to full length which will be hashed with page and chunk data
             let padding = vec![0; PAGE_SIZE_4K as usize - import_data.len()];
             let page_number = gpa_page_base + page;
+            if let Some(pages) = self.pages.as_deref_mut() {
+                let unmeasured = page_metadata.data_unmeasured();
+                pages.push(MeasuredPage {
+                    gpa: page_number * PAGE_SIZE_4K,
+                    page_type: if unmeasured { "unmeasured" } else { "normal" },
+                    contents_digest: (!unmeasured).then(|| {
+                        hex::encode_upper(
+                            Sha256::new()
+                                .chain_update(import_data)
+                                .chain_update(&padding)
+                                .finalize(),
+                        )
+                    }),
+                });
+            }
             let chunk = VpGpaPageChunk {
                 header: VbsChunkHeader {
                     byte_count: VBS_VP_CHUNK_SIZE_BYTES as u32,
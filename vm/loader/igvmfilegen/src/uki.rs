@@ -0,0 +1,138 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Extracts the Linux kernel, initrd and command line from a Unified Kernel
+//! Image (UKI).
+//!
+//! A UKI is a PE image combining an EFI stub with the kernel in the `.linux`
+//! section, and optionally the initrd and command line in the `.initrd` and
+//! `.cmdline` sections. The EFI stub itself is not used, since the kernel is
+//! booted directly.
+
+use anyhow::Context;
+use object::read::pe::PeFile64;
+use object::Object;
+use object::ObjectSection;
+use std::ffi::CString;
+
+/// The contents of a UKI.
+#[derive(Debug)]
+pub struct Uki {
+    pub kernel: Vec<u8>,
+    pub initrd: Option<Vec<u8>>,
+    pub command_line: Option<CString>,
+}
+
+impl Uki {
+    /// Parses the UKI in `data`.
+    pub fn parse(data: &[u8]) -> anyhow::Result<Self> {
+        let pe = PeFile64::parse(data).context("not a 64-bit PE image")?;
+        let section = |name: &str| -> anyhow::Result<Option<&[u8]>> {
+            // Multi-profile UKIs can repeat sections after a `.profile`
+            // section. Only the base profile is supported.
+            let Some(section) = pe.section_by_name(name) else {
+                return Ok(None);
+            };
+            let data = section
+                .data()
+                .with_context(|| format!("failed to read {name} section"))?;
+            Ok(Some(data))
+        };
+
+        let kernel = section(".linux")?.context("no .linux section")?.to_vec();
+        let initrd = section(".initrd")?.map(<[u8]>::to_vec);
+        let command_line = section(".cmdline")?
+            .map(|data| {
+                // The command line is conventionally NUL or newline
+                // terminated.
+                let end = data
+                    .iter()
+                    .rposition(|&c| c != 0 && !c.is_ascii_whitespace())
+                    .map_or(0, |i| i + 1);
+                CString::new(&data[..end]).context("command line contains a NUL")
+            })
+            .transpose()?;
+
+        Ok(Self {
+            kernel,
+            initrd,
+            command_line,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE_ALIGNMENT: usize = 0x200;
+
+    /// Builds a minimal PE32+ image with the given sections.
+    fn build_pe(sections: &[(&str, &[u8])]) -> Vec<u8> {
+        const PE_OFFSET: usize = 0x40;
+        const OPTIONAL_HEADER_SIZE: usize = 240;
+        let section_table = PE_OFFSET + 4 + 20 + OPTIONAL_HEADER_SIZE;
+        let headers_size = (section_table + sections.len() * 40).next_multiple_of(FILE_ALIGNMENT);
+
+        let mut pe = vec![0; headers_size];
+        pe[..2].copy_from_slice(b"MZ");
+        pe[0x3c..0x40].copy_from_slice(&(PE_OFFSET as u32).to_le_bytes());
+        pe[PE_OFFSET..PE_OFFSET + 4].copy_from_slice(b"PE\0\0");
+
+        let coff = PE_OFFSET + 4;
+        pe[coff..coff + 2].copy_from_slice(&0x8664u16.to_le_bytes());
+        pe[coff + 2..coff + 4].copy_from_slice(&(sections.len() as u16).to_le_bytes());
+        pe[coff + 16..coff + 18].copy_from_slice(&(OPTIONAL_HEADER_SIZE as u16).to_le_bytes());
+        pe[coff + 18..coff + 20].copy_from_slice(&0x22u16.to_le_bytes());
+
+        let optional = coff + 20;
+        pe[optional..optional + 2].copy_from_slice(&0x20bu16.to_le_bytes());
+        pe[optional + 32..optional + 36].copy_from_slice(&0x1000u32.to_le_bytes());
+        pe[optional + 36..optional + 40].copy_from_slice(&(FILE_ALIGNMENT as u32).to_le_bytes());
+        pe[optional + 60..optional + 64].copy_from_slice(&(headers_size as u32).to_le_bytes());
+        pe[optional + 108..optional + 112].copy_from_slice(&16u32.to_le_bytes());
+
+        for (i, (name, data)) in sections.iter().enumerate() {
+            let header = section_table + i * 40;
+            let offset = pe.len();
+            let raw_size = data.len().next_multiple_of(FILE_ALIGNMENT);
+            pe[header..header + name.len()].copy_from_slice(name.as_bytes());
+            pe[header + 8..header + 12].copy_from_slice(&(data.len() as u32).to_le_bytes());
+            pe[header + 12..header + 16].copy_from_slice(&(0x1000 * (i as u32 + 1)).to_le_bytes());
+            pe[header + 16..header + 20].copy_from_slice(&(raw_size as u32).to_le_bytes());
+            pe[header + 20..header + 24].copy_from_slice(&(offset as u32).to_le_bytes());
+            pe.extend_from_slice(data);
+            pe.resize(offset + raw_size, 0);
+        }
+        pe
+    }
+
+    #[test]
+    fn parse_uki() {
+        let pe = build_pe(&[
+            (".text", b"stub".as_slice()),
+            (".cmdline", b"console=ttyS0 quiet\n\0".as_slice()),
+            (".linux", b"kernel".as_slice()),
+            (".initrd", b"initrd".as_slice()),
+        ]);
+        let uki = Uki::parse(&pe).unwrap();
+        assert_eq!(uki.kernel, b"kernel");
+        assert_eq!(uki.initrd.as_deref(), Some(&b"initrd"[..]));
+        assert_eq!(uki.command_line.as_deref(), Some(c"console=ttyS0 quiet"));
+    }
+
+    #[test]
+    fn parse_uki_kernel_only() {
+        let pe = build_pe(&[(".linux", b"kernel".as_slice())]);
+        let uki = Uki::parse(&pe).unwrap();
+        assert_eq!(uki.kernel, b"kernel");
+        assert!(uki.initrd.is_none());
+        assert!(uki.command_line.is_none());
+    }
+
+    #[test]
+    fn parse_not_uki() {
+        assert!(Uki::parse(&build_pe(&[(".text", b"stub".as_slice())])).is_err());
+        assert!(Uki::parse(b"not a pe file").is_err());
+    }
+}
@@ -30,6 +30,39 @@ pub enum SnpInjectionType {
     Restricted,
 }
 
+/// The configuration of a signed SEV-SNP ID block.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub struct SnpIdBlockConfig {
+    /// The family ID of the image, as up to 16 bytes of ASCII.
+    pub family_id: String,
+    /// The image ID of the image, as up to 16 bytes of ASCII.
+    pub image_id: String,
+    /// The key signing the ID block.
+    pub id_key: SnpSigningKey,
+    /// The optional author key, signing the ID key.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author_key: Option<SnpSigningKey>,
+}
+
+/// An ECDSA P-384 key used to sign an SEV-SNP ID block. Relative paths are
+/// relative to the current directory.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum SnpSigningKey {
+    /// A PEM encoded private key.
+    PrivateKey(PathBuf),
+    /// An external signer, such as a PKCS#11 token or a KMS.
+    Command {
+        /// The PEM encoded public key of the signer.
+        public_key: PathBuf,
+        /// The program to run and its arguments. The data to sign is written
+        /// to its stdin, and it must write a DER encoded ECDSA signature of
+        /// the SHA-384 digest of the data to its stdout.
+        command: Vec<String>,
+    },
+}
+
 /// The isolation type that should be used for the loader.
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "snake_case")]
@@ -53,6 +86,11 @@ pub enum ConfigIsolationType {
         enable_debug: bool,
         /// The interrupt injection type to use for the highest vmpl.
         injection_type: SnpInjectionType,
+        /// If specified, a signed ID block is embedded in the file, so that
+        /// the guest can only be launched with the expected launch digest and
+        /// policy.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id_block: Option<SnpIdBlockConfig>,
     },
     /// Intel TDX.
     Tdx {
@@ -92,6 +130,15 @@ pub enum Image {
         /// Include the Linux kernel for loading into the guest.
         #[serde(skip_serializing_if = "Option::is_none")]
         linux: Option<LinuxImage>,
+        /// Initrds to append, in order, to the paravisor initrd. See
+        /// [`LinuxImage::initrd_overlays`].
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        initrd_overlays: Vec<PathBuf>,
+        /// Store the paravisor kernel deflate compressed, to be decompressed
+        /// by the boot shim. The initrd is not affected, since it is expected
+        /// to be compressed already. Only supported on x86_64.
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        compress_kernel: bool,
     },
     /// Load the Linux kernel.
     /// TODO: Currently, this only works with underhill.
@@ -103,8 +150,19 @@ pub enum Image {
 pub struct LinuxImage {
     /// Load with an initrd.
     pub use_initrd: bool,
-    /// The command line to boot the kernel with.
+    /// The command line to boot the kernel with. If empty and the kernel is
+    /// loaded from a UKI, the UKI's command line is used.
     pub command_line: CString,
+    /// Extract the kernel, initrd and command line from a Unified Kernel
+    /// Image, rather than using separate kernel and initrd resources.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub from_uki: bool,
+    /// Initrds to append, in order, to the initrd. The kernel unpacks each
+    /// in turn over the previous ones, so these can add or replace files in
+    /// the base initrd without rebuilding it. Relative paths are relative to
+    /// the current directory.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub initrd_overlays: Vec<PathBuf>,
 }
 
 impl Image {
@@ -131,6 +189,9 @@ pub fn required_resources(&self) -> Vec<ResourceType> {
 
 impl LinuxImage {
     fn required_resources(&self) -> Vec<ResourceType> {
+        if self.from_uki {
+            return vec![ResourceType::LinuxUki];
+        }
         [ResourceType::LinuxKernel]
             .into_iter()
             .chain(if self.use_initrd {
@@ -198,6 +259,9 @@ pub enum ResourceType {
     UnderhillSidecar,
     LinuxKernel,
     LinuxInitrd,
+    /// A Unified Kernel Image containing the Linux kernel, and optionally an
+    /// initrd and command line.
+    LinuxUki,
 }
 
 /// Resources used by igvmfilegen to generate IGVM files. These are generated by
@@ -332,4 +396,55 @@ fn missing_resources() {
         let result = resources.check_required(&required);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn parse_snp_id_block() {
+        let isolation = r#"{
+            "snp": {
+                "shared_gpa_boundary_bits": 46,
+                "policy": 196639,
+                "enable_debug": false,
+                "injection_type": "restricted",
+                "id_block": {
+                    "family_id": "msft",
+                    "image_id": "underhill",
+                    "id_key": { "private_key": "id_key.pem" },
+                    "author_key": {
+                        "command": {
+                            "public_key": "author_key.pub.pem",
+                            "command": ["sign-with-kms", "author-key"]
+                        }
+                    }
+                }
+            }
+        }"#;
+        let isolation: ConfigIsolationType = serde_json::from_str(isolation).unwrap();
+        let ConfigIsolationType::Snp {
+            id_block: Some(id_block),
+            ..
+        } = isolation
+        else {
+            panic!("expected an id block");
+        };
+        assert!(matches!(id_block.id_key, SnpSigningKey::PrivateKey(_)));
+        assert!(matches!(
+            id_block.author_key,
+            Some(SnpSigningKey::Command { .. })
+        ));
+
+        // The ID block is optional.
+        let isolation = r#"{
+            "snp": {
+                "shared_gpa_boundary_bits": 46,
+                "policy": 196639,
+                "enable_debug": false,
+                "injection_type": "restricted"
+            }
+        }"#;
+        let isolation: ConfigIsolationType = serde_json::from_str(isolation).unwrap();
+        assert!(matches!(
+            isolation,
+            ConfigIsolationType::Snp { id_block: None, .. }
+        ));
+    }
 }
@@ -52,6 +52,18 @@ pub struct ShimParamsRaw {
     pub bounce_buffer_start: i64,
     /// The size of the bounce buffer range. This is 0 if unavailable.
     pub bounce_buffer_size: u64,
+    /// The offset to the raw deflate compressed kernel image, which the shim
+    /// decompresses to the kernel image range. This is 0 if the kernel is not
+    /// compressed.
+    pub compressed_kernel_offset: i64,
+    /// The size of the compressed kernel image. This is 0 if the kernel is
+    /// not compressed.
+    pub compressed_kernel_size: u64,
+    /// The offset to the kernel image range, where the compressed kernel is
+    /// decompressed to.
+    pub kernel_image_offset: i64,
+    /// The size of the kernel image range.
+    pub kernel_image_size: u64,
 }
 
 open_enum! {
@@ -10,6 +10,7 @@
 use crate::importer::ImageLoad;
 use crate::importer::IsolationConfig;
 use crate::importer::IsolationType;
+use crate::importer::ParameterAreaIndex;
 use crate::importer::SegmentRegister;
 use crate::importer::StartupMemoryType;
 use crate::importer::TableRegister;
@@ -99,6 +100,9 @@ pub enum CommandLineType<'a> {
 ///
 /// An optional initrd may be specified.
 ///
+/// If `compress_kernel` is set, the kernel is stored deflate compressed after
+/// the initrd, and decompressed to its load address by the boot shim.
+///
 /// An optional `memory_page_base` may be specified. This will disable
 /// relocation support for underhill.
 pub fn load_openhcl_x64<F>(
@@ -108,6 +112,7 @@ pub fn load_openhcl_x64<F>(
     sidecar: Option<&mut F>,
     command_line: CommandLineType<'_>,
     initrd: Option<&[u8]>,
+    compress_kernel: bool,
     memory_page_base: Option<u64>,
     memory_page_count: u64,
     vtl0_config: Vtl0Config<'_>,
@@ -137,6 +142,7 @@ pub fn load_openhcl_x64<F>(
     // page tables
     // IGVM parameters
     // reserved vtl2 ranges
+    // compressed kernel, if configured
     // initrd
     // openhcl_boot
     // sidecar, if configured
@@ -202,8 +208,19 @@ pub fn load_openhcl_x64<F>(
     // appear if CONFIG_RELOCATABLE is set.
     // Assume that at least the kernel entry contains PIC and no loader
     // assistance with the relocations records (if any) is required.
+    //
+    // If the kernel is to be compressed, capture its pages instead of
+    // importing them. The kernel range is left out of the IGVM file, and is
+    // accepted by the shim like any other unimported VTL2 memory before the
+    // kernel is decompressed into it.
+    let mut kernel_capture = None;
+    let kernel_importer: &mut dyn ImageLoad<X86Register> = if compress_kernel {
+        kernel_capture.insert(KernelCapture::new(&mut *importer, offset))
+    } else {
+        &mut *importer
+    };
     let load_info = crate::elf::load_static_elf(
-        importer,
+        kernel_importer,
         kernel_image,
         offset,
         0,
@@ -214,10 +231,12 @@ pub fn load_openhcl_x64<F>(
     .map_err(|e| Error::Kernel(crate::linux::Error::ElfLoader(e)))?;
     tracing::trace!("Kernel loaded at {load_info:x?}");
     let crate::elf::LoadInfo {
-        minimum_address_used: _min_addr,
+        minimum_address_used: kernel_base,
         next_available_address: mut offset,
         entrypoint: kernel_entrypoint,
     } = load_info;
+    let kernel_image_range = MemoryRange::new(kernel_base..offset);
+    let compressed_kernel = kernel_capture.map(|capture| capture.compress(kernel_image_range));
 
     assert_eq!(offset & (HV_PAGE_SIZE - 1), 0);
 
@@ -284,6 +303,29 @@ pub fn load_openhcl_x64<F>(
         None
     };
 
+    let compressed_kernel = if let Some(compressed) = compressed_kernel {
+        tracing::debug!(
+            kernel_size = kernel_image_range.len(),
+            compressed_size = compressed.len(),
+            "compressed the kernel"
+        );
+
+        let compressed_base = offset;
+        let compressed_size = align_up_to_page_size(compressed.len() as u64);
+        importer.import_pages(
+            compressed_base / HV_PAGE_SIZE,
+            compressed_size / HV_PAGE_SIZE,
+            "underhill-kernel-compressed",
+            kernel_acceptance,
+            &compressed,
+        )?;
+
+        offset += compressed_size;
+        Some((compressed_base, compressed.len() as u64))
+    } else {
+        None
+    };
+
     let gdt_base_address = offset;
     let gdt_size = HV_PAGE_SIZE;
     offset += gdt_size;
@@ -467,6 +509,11 @@ pub fn load_openhcl_x64<F>(
         used_end: calculate_shim_offset(offset),
         bounce_buffer_start: bounce_buffer.map_or(0, |r| calculate_shim_offset(r.start())),
         bounce_buffer_size: bounce_buffer.map_or(0, |r| r.len()),
+        compressed_kernel_offset: compressed_kernel
+            .map_or(0, |(base, _)| calculate_shim_offset(base)),
+        compressed_kernel_size: compressed_kernel.map_or(0, |(_, size)| size),
+        kernel_image_offset: calculate_shim_offset(kernel_image_range.start()),
+        kernel_image_size: kernel_image_range.len(),
     };
 
     tracing::debug!(boot_params_base, "shim gpa");
@@ -795,6 +842,153 @@ pub fn load_openhcl_x64<F>(
     Ok(())
 }
 
+/// An importer that captures the pages of the kernel image so that they can be
+/// compressed, forwarding everything else to the real importer.
+struct KernelCapture<'a> {
+    importer: &'a mut dyn ImageLoad<X86Register>,
+    base: u64,
+    image: Vec<u8>,
+}
+
+impl<'a> KernelCapture<'a> {
+    fn new(importer: &'a mut dyn ImageLoad<X86Register>, base: u64) -> Self {
+        Self {
+            importer,
+            base,
+            image: Vec::new(),
+        }
+    }
+
+    /// Returns the raw deflate compressed contents of `range`, which must
+    /// contain all the captured pages.
+    fn compress(mut self, range: MemoryRange) -> Vec<u8> {
+        assert_eq!(range.start(), self.base);
+        assert!(self.image.len() as u64 <= range.len());
+        self.image.resize(range.len() as usize, 0);
+        miniz_oxide::deflate::compress_to_vec(&self.image, 9)
+    }
+}
+
+impl ImageLoad<X86Register> for KernelCapture<'_> {
+    fn isolation_config(&self) -> IsolationConfig {
+        self.importer.isolation_config()
+    }
+
+    fn create_parameter_area(
+        &mut self,
+        page_base: u64,
+        page_count: u32,
+        debug_tag: &str,
+    ) -> anyhow::Result<ParameterAreaIndex> {
+        self.importer
+            .create_parameter_area(page_base, page_count, debug_tag)
+    }
+
+    fn create_parameter_area_with_data(
+        &mut self,
+        page_base: u64,
+        page_count: u32,
+        debug_tag: &str,
+        initial_data: &[u8],
+    ) -> anyhow::Result<ParameterAreaIndex> {
+        self.importer.create_parameter_area_with_data(
+            page_base,
+            page_count,
+            debug_tag,
+            initial_data,
+        )
+    }
+
+    fn import_parameter(
+        &mut self,
+        parameter_area: ParameterAreaIndex,
+        byte_offset: u32,
+        parameter_type: IgvmParameterType,
+    ) -> anyhow::Result<()> {
+        self.importer
+            .import_parameter(parameter_area, byte_offset, parameter_type)
+    }
+
+    fn import_pages(
+        &mut self,
+        page_base: u64,
+        page_count: u64,
+        debug_tag: &str,
+        _acceptance: BootPageAcceptance,
+        data: &[u8],
+    ) -> anyhow::Result<()> {
+        let start = (page_base * HV_PAGE_SIZE)
+            .checked_sub(self.base)
+            .ok_or_else(|| anyhow::anyhow!("{debug_tag} pages are below the kernel base"))?
+            as usize;
+        let end = start + (page_count * HV_PAGE_SIZE) as usize;
+        if data.len() > end - start {
+            anyhow::bail!("{debug_tag} data is larger than the imported pages");
+        }
+        if self.image.len() < end {
+            self.image.resize(end, 0);
+        }
+        self.image[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn import_vp_register(&mut self, register: X86Register) -> anyhow::Result<()> {
+        self.importer.import_vp_register(register)
+    }
+
+    fn verify_startup_memory_available(
+        &mut self,
+        page_base: u64,
+        page_count: u64,
+        memory_type: StartupMemoryType,
+    ) -> anyhow::Result<()> {
+        self.importer
+            .verify_startup_memory_available(page_base, page_count, memory_type)
+    }
+
+    fn set_vp_context_page(&mut self, page_base: u64) -> anyhow::Result<()> {
+        self.importer.set_vp_context_page(page_base)
+    }
+
+    fn relocation_region(
+        &mut self,
+        gpa: u64,
+        size_bytes: u64,
+        relocation_alignment: u64,
+        minimum_relocation_gpa: u64,
+        maximum_relocation_gpa: u64,
+        apply_rip_offset: bool,
+        apply_gdtr_offset: bool,
+        vp_index: u16,
+    ) -> anyhow::Result<()> {
+        self.importer.relocation_region(
+            gpa,
+            size_bytes,
+            relocation_alignment,
+            minimum_relocation_gpa,
+            maximum_relocation_gpa,
+            apply_rip_offset,
+            apply_gdtr_offset,
+            vp_index,
+        )
+    }
+
+    fn page_table_relocation(
+        &mut self,
+        page_table_gpa: u64,
+        size_pages: u64,
+        used_pages: u64,
+        vp_index: u16,
+    ) -> anyhow::Result<()> {
+        self.importer
+            .page_table_relocation(page_table_gpa, size_pages, used_pages, vp_index)
+    }
+
+    fn set_imported_regions_config_page(&mut self, page_base: u64) {
+        self.importer.set_imported_regions_config_page(page_base)
+    }
+}
+
 /// Create a hypervisor SNP CPUID page with the default values.
 fn create_snp_cpuid_page() -> HV_PSP_CPUID_PAGE {
     let mut cpuid_page = HV_PSP_CPUID_PAGE::default();
@@ -1043,6 +1237,10 @@ pub fn load_openhcl_arm64<F>(
         used_end: calculate_shim_offset(next_addr),
         bounce_buffer_start: 0,
         bounce_buffer_size: 0,
+        compressed_kernel_offset: 0,
+        compressed_kernel_size: 0,
+        kernel_image_offset: 0,
+        kernel_image_size: 0,
     };
 
     importer
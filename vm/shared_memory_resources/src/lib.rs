@@ -0,0 +1,48 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Resources for sharing guest RAM with other processes, such as vhost-user
+//! device backends, by file descriptor.
+
+#![cfg(unix)]
+#![forbid(unsafe_code)]
+#![warn(missing_docs)]
+
+use memory_range::MemoryRange;
+use std::os::fd::OwnedFd;
+use std::sync::Arc;
+use vm_resource::CanResolveTo;
+use vm_resource::ResourceKind;
+
+/// Resource kind for the shared memory backing guest RAM.
+///
+/// This is resolved with [`vm_resource::PlatformResource`], and only if the
+/// VMM's guest RAM is backed by a shareable memory object.
+pub enum SharedGuestMemoryHandleKind {}
+
+impl ResourceKind for SharedGuestMemoryHandleKind {
+    const NAME: &'static str = "shared_guest_memory";
+}
+
+impl CanResolveTo<SharedGuestMemory> for SharedGuestMemoryHandleKind {
+    type Input<'a> = ();
+}
+
+/// The shared memory object backing guest RAM, and the location of each range
+/// of guest RAM within it.
+#[derive(Debug, Clone)]
+pub struct SharedGuestMemory {
+    /// The shared memory object.
+    pub fd: Arc<OwnedFd>,
+    /// The guest RAM regions backed by `fd`, in ascending address order.
+    pub regions: Vec<SharedMemoryRegion>,
+}
+
+/// A range of guest RAM in a [`SharedGuestMemory`].
+#[derive(Debug, Copy, Clone)]
+pub struct SharedMemoryRegion {
+    /// The guest physical address range.
+    pub range: MemoryRange,
+    /// The offset of the start of the range in the shared memory object.
+    pub offset: u64,
+}
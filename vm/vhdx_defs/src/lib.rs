@@ -0,0 +1,342 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! VHDX file format definitions, as described in the VHDX format
+//! specification (v1.00).
+
+#![no_std]
+
+use bitfield_struct::bitfield;
+use guid::Guid;
+use open_enum::open_enum;
+use static_assertions::const_assert_eq;
+use zerocopy::AsBytes;
+use zerocopy::FromBytes;
+use zerocopy::FromZeroes;
+
+pub const KB: u64 = 1024;
+pub const MB: u64 = 1024 * KB;
+
+/// The alignment of all regions, payload blocks, and sector bitmap blocks.
+pub const REGION_ALIGNMENT: u64 = MB;
+
+/// The size of the header section at the start of the file.
+pub const HEADER_SECTION_SIZE: u64 = MB;
+
+pub const FILE_IDENTIFIER_OFFSET: u64 = 0;
+pub const HEADER_1_OFFSET: u64 = 64 * KB;
+pub const HEADER_2_OFFSET: u64 = 128 * KB;
+pub const REGION_TABLE_1_OFFSET: u64 = 192 * KB;
+pub const REGION_TABLE_2_OFFSET: u64 = 256 * KB;
+
+/// The file type identifier at offset 0.
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct FileIdentifier {
+    pub signature: u64,
+    /// UTF-16 name of the creating application. Informational only.
+    pub creator: [u16; 256],
+}
+
+impl FileIdentifier {
+    pub const SIGNATURE: u64 = u64::from_le_bytes(*b"vhdxfile");
+}
+
+/// One of the two file headers.
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct Header {
+    pub signature: u32,
+    pub checksum: u32,
+    pub sequence_number: u64,
+    pub file_write_guid: Guid,
+    pub data_write_guid: Guid,
+    pub log_guid: Guid,
+    pub log_version: u16,
+    pub version: u16,
+    pub log_length: u32,
+    pub log_offset: u64,
+    pub reserved: [u8; 4016],
+}
+
+const_assert_eq!(core::mem::size_of::<Header>(), Header::SIZE);
+
+impl Header {
+    pub const SIGNATURE: u32 = u32::from_le_bytes(*b"head");
+    pub const SIZE: usize = 4096;
+    pub const VERSION: u16 = 1;
+    pub const LOG_VERSION: u16 = 0;
+}
+
+/// The header of one of the two region tables.
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct RegionTableHeader {
+    pub signature: u32,
+    pub checksum: u32,
+    pub entry_count: u32,
+    pub reserved: u32,
+}
+
+impl RegionTableHeader {
+    pub const SIGNATURE: u32 = u32::from_le_bytes(*b"regi");
+    /// The size of the region table, over which the checksum is computed.
+    pub const SIZE: usize = 64 * KB as usize;
+    pub const MAX_ENTRIES: u32 = 2047;
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct RegionTableEntry {
+    pub guid: Guid,
+    pub file_offset: u64,
+    pub length: u32,
+    pub flags: u32,
+}
+
+impl RegionTableEntry {
+    pub const FLAG_REQUIRED: u32 = 0x1;
+}
+
+pub const BAT_REGION: Guid = Guid::from_static_str("2dc27766-f623-4200-9d64-115e9bfd4a08");
+pub const METADATA_REGION: Guid = Guid::from_static_str("8b7ca206-4790-4b9a-b8fe-575f050f886e");
+
+/// The header of the metadata table, at the start of the metadata region.
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct MetadataTableHeader {
+    pub signature: u64,
+    pub reserved: u16,
+    pub entry_count: u16,
+    pub reserved2: [u32; 5],
+}
+
+impl MetadataTableHeader {
+    pub const SIGNATURE: u64 = u64::from_le_bytes(*b"metadata");
+    /// The size of the metadata table. Item data is stored after it.
+    pub const SIZE: usize = 64 * KB as usize;
+    pub const MAX_ENTRIES: u16 = 2047;
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct MetadataTableEntry {
+    pub item_id: Guid,
+    /// Offset relative to the start of the metadata region.
+    pub offset: u32,
+    pub length: u32,
+    pub flags: u32,
+    pub reserved: u32,
+}
+
+impl MetadataTableEntry {
+    pub const FLAG_IS_USER: u32 = 0x1;
+    pub const FLAG_IS_VIRTUAL_DISK: u32 = 0x2;
+    pub const FLAG_IS_REQUIRED: u32 = 0x4;
+}
+
+pub const FILE_PARAMETERS_ITEM: Guid =
+    Guid::from_static_str("caa16737-fa36-4d43-b3b6-33f0aa44e76b");
+pub const VIRTUAL_DISK_SIZE_ITEM: Guid =
+    Guid::from_static_str("2fa54224-cd1b-4876-b211-5dbed83bf4b8");
+pub const VIRTUAL_DISK_ID_ITEM: Guid =
+    Guid::from_static_str("beca12ab-b2e6-4523-93ef-c309e000c746");
+pub const LOGICAL_SECTOR_SIZE_ITEM: Guid =
+    Guid::from_static_str("8141bf1d-a96f-4709-ba47-f233a8faab5f");
+pub const PHYSICAL_SECTOR_SIZE_ITEM: Guid =
+    Guid::from_static_str("cda348c7-445d-4471-9cc9-e9885251c556");
+pub const PARENT_LOCATOR_ITEM: Guid = Guid::from_static_str("a8d35f2d-b30b-454d-abf7-d3d84834ab0c");
+
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct FileParameters {
+    pub block_size: u32,
+    pub flags: u32,
+}
+
+impl FileParameters {
+    pub const FLAG_LEAVE_BLOCKS_ALLOCATED: u32 = 0x1;
+    pub const FLAG_HAS_PARENT: u32 = 0x2;
+
+    pub const MIN_BLOCK_SIZE: u32 = MB as u32;
+    pub const MAX_BLOCK_SIZE: u32 = 256 * MB as u32;
+    pub const DEFAULT_BLOCK_SIZE: u32 = 32 * MB as u32;
+}
+
+/// The header of the parent locator metadata item.
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct ParentLocatorHeader {
+    pub locator_type: Guid,
+    pub reserved: u16,
+    pub key_value_count: u16,
+}
+
+/// The locator type for VHDX parents.
+pub const PARENT_LOCATOR_VHDX: Guid = Guid::from_static_str("b04aefb7-d19e-4a81-b789-25b8e9445913");
+
+/// A parent locator key-value entry. Offsets are relative to the start of the
+/// parent locator item, and keys and values are UTF-16LE strings.
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct ParentLocatorEntry {
+    pub key_offset: u32,
+    pub value_offset: u32,
+    pub key_length: u16,
+    pub value_length: u16,
+}
+
+pub mod parent_locator_keys {
+    pub const PARENT_LINKAGE: &str = "parent_linkage";
+    pub const PARENT_LINKAGE2: &str = "parent_linkage2";
+    pub const RELATIVE_PATH: &str = "relative_path";
+    pub const VOLUME_PATH: &str = "volume_path";
+    pub const ABSOLUTE_WIN32_PATH: &str = "absolute_win32_path";
+}
+
+/// A block allocation table entry.
+#[bitfield(u64)]
+#[derive(AsBytes, FromBytes, FromZeroes, PartialEq, Eq)]
+pub struct BatEntry {
+    #[bits(3)]
+    pub state: u8,
+    #[bits(17)]
+    pub reserved: u32,
+    /// The file offset of the block, in MB.
+    #[bits(44)]
+    pub file_offset_mb: u64,
+}
+
+open_enum! {
+    /// The state of a payload block BAT entry.
+    pub enum PayloadBlockState: u8 {
+        NOT_PRESENT = 0,
+        UNDEFINED = 1,
+        ZERO = 2,
+        UNMAPPED = 3,
+        FULLY_PRESENT = 6,
+        PARTIALLY_PRESENT = 7,
+    }
+}
+
+open_enum! {
+    /// The state of a sector bitmap BAT entry.
+    pub enum SectorBitmapState: u8 {
+        NOT_PRESENT = 0,
+        PRESENT = 6,
+    }
+}
+
+/// The number of sectors described by each sector bitmap block.
+pub const SECTORS_PER_CHUNK: u64 = 1 << 23;
+
+/// The size of a sector bitmap block.
+pub const SECTOR_BITMAP_BLOCK_SIZE: u64 = SECTORS_PER_CHUNK / 8;
+
+/// The size of the log's sectors, and the granularity of logged writes.
+pub const LOG_SECTOR_SIZE: usize = 4096;
+
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct LogEntryHeader {
+    pub signature: u32,
+    pub checksum: u32,
+    pub entry_length: u32,
+    pub tail: u32,
+    pub sequence_number: u64,
+    pub descriptor_count: u32,
+    pub reserved: u32,
+    pub log_guid: Guid,
+    pub flushed_file_offset: u64,
+    pub last_file_offset: u64,
+}
+
+const_assert_eq!(core::mem::size_of::<LogEntryHeader>(), 64);
+
+impl LogEntryHeader {
+    pub const SIGNATURE: u32 = u32::from_le_bytes(*b"loge");
+}
+
+/// A log descriptor for a range to be zeroed.
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct ZeroDescriptor {
+    pub signature: u32,
+    pub reserved: u32,
+    pub zero_length: u64,
+    pub file_offset: u64,
+    pub sequence_number: u64,
+}
+
+impl ZeroDescriptor {
+    pub const SIGNATURE: u32 = u32::from_le_bytes(*b"zero");
+}
+
+/// A log descriptor for a 4KB sector of data, whose contents are split between
+/// the descriptor and a [`DataSector`].
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct DataDescriptor {
+    pub signature: u32,
+    pub trailing_bytes: [u8; 4],
+    pub leading_bytes: [u8; 8],
+    pub file_offset: u64,
+    pub sequence_number: u64,
+}
+
+impl DataDescriptor {
+    pub const SIGNATURE: u32 = u32::from_le_bytes(*b"desc");
+}
+
+/// The size of a log descriptor.
+pub const DESCRIPTOR_SIZE: usize = 32;
+
+const_assert_eq!(core::mem::size_of::<ZeroDescriptor>(), DESCRIPTOR_SIZE);
+const_assert_eq!(core::mem::size_of::<DataDescriptor>(), DESCRIPTOR_SIZE);
+
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes, FromZeroes)]
+pub struct DataSector {
+    pub signature: u32,
+    pub sequence_high: u32,
+    pub data: [u8; 4084],
+    pub sequence_low: u32,
+}
+
+const_assert_eq!(core::mem::size_of::<DataSector>(), LOG_SECTOR_SIZE);
+
+impl DataSector {
+    pub const SIGNATURE: u32 = u32::from_le_bytes(*b"data");
+}
+
+/// Computes the CRC-32C (Castagnoli) checksum of `data`, as used by all VHDX
+/// checksums.
+///
+/// The checksum field of the structure being checksummed must be zero.
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in data {
+        crc = CRC32C_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+    !crc
+}
+
+const CRC32C_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82f63b78
+            } else {
+                crc >> 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
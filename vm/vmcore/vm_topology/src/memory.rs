@@ -126,7 +126,26 @@ pub fn new(
         gaps: &[MemoryRange],
         vtl2_range: Option<MemoryRange>,
     ) -> Result<Self, Error> {
-        if ram_size == 0 || ram_size & (PAGE_SIZE - 1) != 0 {
+        Self::new_with_numa(physical_address_size, &[ram_size], gaps, vtl2_range)
+    }
+
+    /// Makes a new memory layout like [`Self::new`], but with RAM split across
+    /// multiple NUMA nodes.
+    ///
+    /// Node `n` gets `node_sizes[n]` bytes of RAM. RAM is assigned to nodes in
+    /// order starting from address zero, so a node's RAM may be split by an
+    /// MMIO gap. Each size must be a non-zero multiple of the page size.
+    pub fn new_with_numa(
+        physical_address_size: u8,
+        node_sizes: &[u64],
+        gaps: &[MemoryRange],
+        vtl2_range: Option<MemoryRange>,
+    ) -> Result<Self, Error> {
+        if node_sizes.is_empty()
+            || node_sizes
+                .iter()
+                .any(|&size| size == 0 || size & (PAGE_SIZE - 1) != 0)
+        {
             return Err(Error::BadSize);
         }
         if gaps.len() < 2 {
@@ -135,23 +154,31 @@ pub fn new(
 
         validate_ranges(gaps)?;
         let mut ram = Vec::new();
-        let mut remaining = ram_size;
         let mut remaining_gaps = gaps.iter().cloned();
+        let mut next_gap = remaining_gaps.next();
         let mut last_end = 0;
 
-        while remaining > 0 {
-            let (this, next_end) = if let Some(gap) = remaining_gaps.next() {
-                (remaining.min(gap.start() - last_end), gap.end())
-            } else {
-                (remaining, 0)
-            };
-
-            ram.push(MemoryRangeWithNode {
-                range: MemoryRange::new(last_end..last_end + this),
-                vnode: 0,
-            });
-            remaining -= this;
-            last_end = next_end;
+        for (vnode, &size) in node_sizes.iter().enumerate() {
+            let mut remaining = size;
+            while remaining > 0 {
+                let this = if let Some(gap) = next_gap {
+                    if last_end >= gap.start() {
+                        last_end = gap.end();
+                        next_gap = remaining_gaps.next();
+                        continue;
+                    }
+                    remaining.min(gap.start() - last_end)
+                } else {
+                    remaining
+                };
+
+                ram.push(MemoryRangeWithNode {
+                    range: MemoryRange::new(last_end..last_end + this),
+                    vnode: vnode as u32,
+                });
+                remaining -= this;
+                last_end += this;
+            }
         }
 
         Self::build(physical_address_size, ram, gaps.to_vec(), vtl2_range)
@@ -390,6 +417,45 @@ fn layout() {
         assert_eq!(layout.end_of_ram(), TB + 2 * GB);
     }
 
+    #[test]
+    fn numa_layout() {
+        let mmio = &[
+            MemoryRange::new(GB..2 * GB),
+            MemoryRange::new(3 * GB..4 * GB),
+        ];
+
+        let layout = MemoryLayout::new_with_numa(42, &[512 * MB, GB, 2 * GB], mmio, None).unwrap();
+        assert_eq!(
+            layout.ram(),
+            &[
+                MemoryRangeWithNode {
+                    range: MemoryRange::new(0..512 * MB),
+                    vnode: 0
+                },
+                MemoryRangeWithNode {
+                    range: MemoryRange::new(512 * MB..GB),
+                    vnode: 1
+                },
+                MemoryRangeWithNode {
+                    range: MemoryRange::new(2 * GB..2 * GB + 512 * MB),
+                    vnode: 1
+                },
+                MemoryRangeWithNode {
+                    range: MemoryRange::new(2 * GB + 512 * MB..3 * GB),
+                    vnode: 2
+                },
+                MemoryRangeWithNode {
+                    range: MemoryRange::new(4 * GB..5 * GB + 512 * MB),
+                    vnode: 2
+                },
+            ]
+        );
+        assert_eq!(layout.ram_size(), 3 * GB + 512 * MB);
+
+        MemoryLayout::new_with_numa(42, &[], mmio, None).unwrap_err();
+        MemoryLayout::new_with_numa(42, &[GB, 0], mmio, None).unwrap_err();
+    }
+
     #[test]
     fn bad_layout() {
         MemoryLayout::new(42, TB + 1, &[], None).unwrap_err();
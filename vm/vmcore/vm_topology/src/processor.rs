@@ -57,6 +57,7 @@ pub trait ArchTopology: Sized {
 pub struct TopologyBuilder<T: ArchTopology> {
     vps_per_socket: u32,
     smt_enabled: bool,
+    vnodes: Vec<u32>,
     arch: T::BuilderState,
 }
 
@@ -116,6 +117,21 @@ pub fn smt_enabled(&mut self, enabled: bool) -> &mut Self {
         self.smt_enabled = enabled;
         self
     }
+
+    /// Sets the virtual NUMA node of each VP, indexed by VP index.
+    ///
+    /// VPs without an entry get the architecture's default node assignment.
+    pub fn vnodes(&mut self, vnodes: Vec<u32>) -> &mut Self {
+        self.vnodes = vnodes;
+        self
+    }
+
+    fn vnode(&self, vp_index: u32, default: u32) -> u32 {
+        self.vnodes
+            .get(vp_index as usize)
+            .copied()
+            .unwrap_or(default)
+    }
 }
 
 impl<
@@ -80,6 +80,7 @@ pub fn new_aarch64(gic: GicInfo) -> Self {
         Self {
             vps_per_socket: 1,
             smt_enabled: false,
+            vnodes: Vec::new(),
             arch: Aarch64TopologyBuilderState { gic },
         }
     }
@@ -111,7 +112,7 @@ pub fn build(
         self.build_with_vp_info(mpidrs.enumerate().map(|(id, mpidr)| Aarch64VpInfo {
             base: VpInfo {
                 vp_index: VpIndex::new(id as u32),
-                vnode: 0,
+                vnode: self.vnode(id as u32, 0),
             },
             mpidr,
             gicr: self.arch.gic.gic_redistributors_base
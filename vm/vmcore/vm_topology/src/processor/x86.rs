@@ -78,6 +78,7 @@ pub fn new_x86() -> Self {
         Self {
             vps_per_socket: 1,
             smt_enabled: false,
+            vnodes: Vec::new(),
             arch: Default::default(),
         }
     }
@@ -188,6 +189,7 @@ pub fn from_cpuid(
         Ok(Self {
             smt_enabled: threads_per_core > 1 && vps_per_socket > 1,
             vps_per_socket,
+            vnodes: Vec::new(),
             arch: Default::default(),
         })
     }
@@ -214,8 +216,8 @@ pub fn build(
         let socket_offset = self.arch.apic_id_offset / vps_per_socket;
         let vps = (0..proc_count).map(|n| {
             let vp_index = VpIndex::new(n);
-            // FUTURE: support multiple NUMA nodes per socket.
-            let vnode = n / vps_per_socket;
+            // By default, each socket is its own NUMA node.
+            let vnode = self.vnode(n, n / vps_per_socket);
             let socket = socket_offset + n / self.vps_per_socket;
             let proc = n % self.vps_per_socket;
             let apic_id = socket * vps_per_socket + proc;
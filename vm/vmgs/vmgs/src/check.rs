@@ -0,0 +1,362 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Consistency checking of VMGS files.
+//!
+//! Unlike [`Vmgs::open`](crate::Vmgs::open), which stops at the first
+//! problem, the checker reports every problem it finds with either header and
+//! the file table and files it references, for triaging corrupted files.
+//!
+//! Only the file's metadata and the stored (possibly encrypted) file contents
+//! are checked, so no encryption key is needed.
+
+use crate::error::Error;
+use crate::storage::VmgsStorage;
+use crate::vmgs_impl::block_count_to_byte_count;
+use crate::vmgs_impl::compute_crc32;
+use crate::vmgs_impl::get_active_header;
+use crate::vmgs_impl::read_file_table;
+use crate::vmgs_impl::read_headers_inner;
+use crate::vmgs_impl::validate_header;
+use disk_backend::Disk;
+use vmgs_format::FileId;
+use vmgs_format::VmgsHeader;
+use vmgs_format::VMGS_MIN_FILE_BLOCK_OFFSET;
+use vmgs_format::VMGS_VERSION_4_0;
+use zerocopy::AsBytes;
+use zerocopy::FromZeroes;
+
+/// The result of checking one of the two headers of a VMGS file.
+#[derive(Debug)]
+pub struct VmgsHeaderCheck {
+    /// The header's sequence number.
+    pub sequence: u32,
+    /// Whether the header itself is valid.
+    pub valid: bool,
+    /// Problems with the header, its file table, or the files it references.
+    pub issues: Vec<String>,
+}
+
+impl VmgsHeaderCheck {
+    /// Whether the file can be used with this header.
+    pub fn is_consistent(&self) -> bool {
+        self.valid && self.issues.is_empty()
+    }
+}
+
+/// The result of [`check`].
+#[derive(Debug)]
+pub struct VmgsCheckReport {
+    /// The results for each header.
+    pub headers: [VmgsHeaderCheck; 2],
+    /// The index of the header that the file would be opened with, or the
+    /// reason why neither header can be chosen.
+    pub active_header: Result<usize, String>,
+    /// The index of the header that was invalidated to repair the file, if
+    /// any.
+    pub repaired_header: Option<usize>,
+}
+
+impl VmgsCheckReport {
+    /// Whether the file is consistent, either as found or after being
+    /// repaired.
+    pub fn is_consistent(&self) -> bool {
+        match self.active_header {
+            Ok(index) => self.headers[index].is_consistent(),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Checks the consistency of the VMGS file on `disk`.
+///
+/// If `repair` is set and the header the file would be opened with is
+/// inconsistent, but the other header is consistent, the inconsistent header
+/// is invalidated so that the file is opened with the other one. This rolls
+/// the file back to its state before the last write. No other repairs are
+/// attempted.
+///
+/// Only errors accessing the disk are returned as errors. Problems with the
+/// file are reported in the returned [`VmgsCheckReport`].
+pub async fn check(disk: Disk, repair: bool) -> Result<VmgsCheckReport, Error> {
+    let mut storage = VmgsStorage::new(disk);
+    let (header_1, header_2) = read_headers_inner(&mut storage).await?;
+    let headers = [header_1, header_2];
+
+    let mut checks = Vec::new();
+    for header in &headers {
+        checks.push(check_header(&mut storage, header).await?);
+    }
+    let mut checks: [VmgsHeaderCheck; 2] = checks.try_into().unwrap();
+
+    let mut active_header =
+        get_active_header(validate_header(&headers[0]), validate_header(&headers[1]))
+            .map_err(|err| err.to_string());
+
+    let mut repaired_header = None;
+    let consistent = match active_header {
+        Ok(index) => checks[index].is_consistent(),
+        Err(_) => false,
+    };
+    if repair && !consistent {
+        let candidates = [0, 1].map(|index| checks[index].is_consistent());
+        let bad_index = match candidates {
+            [true, false] => Some(1),
+            [false, true] => Some(0),
+            _ => None,
+        };
+        if let Some(bad_index) = bad_index {
+            tracing::info!(bad_index, "invalidating inconsistent VMGS header");
+            storage
+                .write_block(
+                    bad_index as u64 * storage.aligned_header_size(),
+                    VmgsHeader::new_zeroed().as_bytes(),
+                )
+                .await
+                .map_err(Error::WriteDisk)?;
+            storage.flush().await.map_err(Error::FlushDisk)?;
+
+            checks[bad_index]
+                .issues
+                .push("header was invalidated by repair".into());
+            checks[bad_index].valid = false;
+            active_header = Ok(1 - bad_index);
+            repaired_header = Some(bad_index);
+        }
+    }
+
+    Ok(VmgsCheckReport {
+        headers: checks,
+        active_header,
+        repaired_header,
+    })
+}
+
+/// Checks a header, its file table, and the extents of the files it
+/// references.
+async fn check_header(
+    storage: &mut VmgsStorage,
+    header: &VmgsHeader,
+) -> Result<VmgsHeaderCheck, Error> {
+    let mut check = VmgsHeaderCheck {
+        sequence: header.sequence,
+        valid: false,
+        issues: Vec::new(),
+    };
+
+    if let Err(err) = validate_header(header) {
+        check.issues.push(err.to_string());
+        return Ok(check);
+    }
+    check.valid = true;
+
+    let block_capacity = storage.block_capacity();
+    if header.file_table_offset as u64 + header.file_table_size as u64 > block_capacity as u64 {
+        check.issues.push(format!(
+            "file table at block {} extends past the end of the file ({} blocks)",
+            header.file_table_offset, block_capacity
+        ));
+        return Ok(check);
+    }
+
+    let file_table = match read_file_table(storage, header).await {
+        Ok(file_table) => file_table,
+        Err(Error::Checksum) => {
+            check
+                .issues
+                .push("file table does not match its checksum".into());
+            return Ok(check);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let file_table_entry = &file_table.entries[FileId::FILE_TABLE];
+    if file_table_entry.offset != header.file_table_offset
+        || file_table_entry.allocation_size != header.file_table_size
+    {
+        check.issues.push(format!(
+            "file table entry (block {}, {} blocks) does not match header (block {}, {} blocks)",
+            file_table_entry.offset,
+            file_table_entry.allocation_size,
+            header.file_table_offset,
+            header.file_table_size
+        ));
+    }
+
+    // Check each file's extent, collecting the valid ones to check for
+    // overlaps.
+    let mut extents = Vec::new();
+    for (file_id, entry) in file_table.entries.iter().enumerate() {
+        let file_id = FileId(file_id as u32);
+        if entry.allocation_size == 0 {
+            continue;
+        }
+
+        let end = entry.offset as u64 + entry.allocation_size as u64;
+        if entry.offset < VMGS_MIN_FILE_BLOCK_OFFSET || end > block_capacity as u64 {
+            check.issues.push(format!(
+                "file {:?} at blocks {}..{} is outside of the data area (blocks {}..{})",
+                file_id, entry.offset, end, VMGS_MIN_FILE_BLOCK_OFFSET, block_capacity
+            ));
+            continue;
+        }
+        extents.push((entry.offset as u64, end, file_id));
+
+        if entry.valid_data_size > block_count_to_byte_count(entry.allocation_size) {
+            check.issues.push(format!(
+                "file {:?} has {} valid bytes, but only {} blocks allocated",
+                file_id, entry.valid_data_size, entry.allocation_size
+            ));
+            continue;
+        }
+
+        // The file table's own checksum is in the header, and was checked
+        // when reading it.
+        if header.version >= VMGS_VERSION_4_0 && file_id != FileId::FILE_TABLE {
+            let mut buf = vec![0; entry.valid_data_size as usize];
+            storage
+                .read_block(block_count_to_byte_count(entry.offset), &mut buf)
+                .await
+                .map_err(Error::ReadDisk)?;
+            if compute_crc32(&buf) != entry.checksum {
+                check
+                    .issues
+                    .push(format!("file {:?} does not match its checksum", file_id));
+            }
+        }
+    }
+
+    extents.sort_by_key(|&(start, end, _)| (start, end));
+    for pair in extents.windows(2) {
+        let (_, end, file_id) = pair[0];
+        let (next_start, _, next_file_id) = pair[1];
+        if next_start < end {
+            check.issues.push(format!(
+                "files {:?} and {:?} overlap at block {}",
+                file_id, next_file_id, next_start
+            ));
+        }
+    }
+
+    Ok(check)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vmgs;
+    use pal_async::async_test;
+    use vmgs_format::VmgsFileTable;
+
+    fn new_test_file() -> Disk {
+        disk_ramdisk::ram_disk(4 * 1024 * 1024, false).unwrap()
+    }
+
+    #[async_test]
+    async fn check_consistent() {
+        let disk = new_test_file();
+        let mut vmgs = Vmgs::format_new_with_version(disk.clone(), VMGS_VERSION_4_0)
+            .await
+            .unwrap();
+        vmgs.write_file(FileId::BIOS_NVRAM, b"hello world")
+            .await
+            .unwrap();
+        drop(vmgs);
+
+        let report = check(disk, false).await.unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(report.active_header, Ok(1));
+        assert!(report.headers[0].is_consistent());
+        assert!(report.headers[1].is_consistent());
+        assert_eq!(report.repaired_header, None);
+    }
+
+    #[async_test]
+    async fn check_empty() {
+        let report = check(new_test_file(), true).await.unwrap();
+        assert!(!report.is_consistent());
+        assert!(report.active_header.is_err());
+        assert!(!report.headers[0].valid);
+        assert!(!report.headers[1].valid);
+        assert_eq!(report.repaired_header, None);
+    }
+
+    #[async_test]
+    async fn check_repair() {
+        let disk = new_test_file();
+        let mut vmgs = Vmgs::format_new(disk.clone()).await.unwrap();
+        vmgs.write_file(FileId::BIOS_NVRAM, b"hello world")
+            .await
+            .unwrap();
+        vmgs.write_file(FileId::TPM_PPI, b"short sentence")
+            .await
+            .unwrap();
+        drop(vmgs);
+
+        // Make the latest file table's entries overlap.
+        let mut storage = VmgsStorage::new(disk.clone());
+        let (header_1, _) = read_headers_inner(&mut storage).await.unwrap();
+        let mut file_table = read_file_table(&mut storage, &header_1).await.unwrap();
+        file_table.entries[FileId::TPM_PPI].offset = file_table.entries[FileId::BIOS_NVRAM].offset;
+        storage
+            .write_block(
+                block_count_to_byte_count(header_1.file_table_offset),
+                file_table.as_bytes(),
+            )
+            .await
+            .unwrap();
+
+        let report = check(disk.clone(), false).await.unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.active_header, Ok(0));
+        assert_eq!(report.headers[0].issues.len(), 1);
+        assert!(report.headers[1].is_consistent());
+
+        let report = check(disk.clone(), true).await.unwrap();
+        assert!(report.is_consistent());
+        assert_eq!(report.active_header, Ok(1));
+        assert_eq!(report.repaired_header, Some(0));
+
+        let report = check(disk.clone(), false).await.unwrap();
+        assert!(report.is_consistent());
+
+        let mut vmgs = Vmgs::open(disk).await.unwrap();
+        assert_eq!(
+            vmgs.read_file(FileId::BIOS_NVRAM).await.unwrap(),
+            b"hello world"
+        );
+        assert!(vmgs.read_file(FileId::TPM_PPI).await.is_err());
+    }
+
+    #[async_test]
+    async fn check_unrepairable() {
+        let disk = new_test_file();
+        let mut vmgs = Vmgs::format_new(disk.clone()).await.unwrap();
+        vmgs.write_file(FileId::BIOS_NVRAM, b"hello world")
+            .await
+            .unwrap();
+        drop(vmgs);
+
+        // Clobber both file tables.
+        let mut storage = VmgsStorage::new(disk.clone());
+        let (header_1, header_2) = read_headers_inner(&mut storage).await.unwrap();
+        let mut file_table = VmgsFileTable::new_zeroed();
+        file_table.entries[FileId::BIOS_NVRAM].offset = 1;
+        file_table.entries[FileId::BIOS_NVRAM].allocation_size = 1;
+        for header in [header_1, header_2] {
+            storage
+                .write_block(
+                    block_count_to_byte_count(header.file_table_offset),
+                    file_table.as_bytes(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let report = check(disk, true).await.unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.repaired_header, None);
+        assert_eq!(report.headers[0].issues.len(), 2);
+        assert_eq!(report.headers[1].issues.len(), 2);
+    }
+}
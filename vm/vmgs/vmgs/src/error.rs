@@ -61,6 +61,9 @@ pub enum Error {
     /// Cannot read encrypted file - VMGS is locked.
     #[error("cannot read encrypted file - VMGS is locked")]
     ReadEncrypted,
+    /// File contents do not match their checksum.
+    #[error("file contents do not match their checksum")]
+    Checksum,
 
     /// OpenSSL errors.
     #[cfg(feature = "encryption_ossl")]
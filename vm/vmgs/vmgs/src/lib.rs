@@ -23,21 +23,26 @@
 
 #![warn(missing_docs)]
 
+mod check;
 mod encrypt;
 mod error;
 mod storage;
 mod vmgs_impl;
 
+pub use check::VmgsCheckReport;
+pub use check::VmgsHeaderCheck;
 pub use error::Error;
 pub use vmgs_format::EncryptionAlgorithm;
 pub use vmgs_format::FileId;
 #[cfg(feature = "save_restore")]
 pub use vmgs_impl::save_restore;
 pub use vmgs_impl::Vmgs;
+pub use vmgs_impl::VmgsCompactInfo;
 pub use vmgs_impl::VmgsFileInfo;
 
 /// VMGS helper functions
 pub mod vmgs_helpers {
+    pub use crate::check::check;
     pub use crate::vmgs_impl::get_active_header;
     pub use crate::vmgs_impl::read_headers;
     pub use crate::vmgs_impl::validate_header;
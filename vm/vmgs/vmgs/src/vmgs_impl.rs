@@ -30,6 +30,7 @@
 use vmgs_format::VMGS_MIN_FILE_BLOCK_OFFSET;
 use vmgs_format::VMGS_SIGNATURE;
 use vmgs_format::VMGS_VERSION_3_0;
+use vmgs_format::VMGS_VERSION_4_0;
 use zerocopy::AsBytes;
 use zerocopy::FromBytes;
 use zerocopy::FromZeroes;
@@ -43,6 +44,16 @@ pub struct VmgsFileInfo {
     pub valid_bytes: u64,
 }
 
+/// The result of compacting a VMGS file.
+#[derive(Debug)]
+pub struct VmgsCompactInfo {
+    /// Number of files that were moved.
+    pub files_moved: u32,
+    /// Number of bytes from the start of the disk to the end of the last
+    /// allocated block.
+    pub used_bytes: u64,
+}
+
 // Aggregates fully validated data from the FILE_TABLE and EXTENDED_FILE_TABLE
 // control blocks.
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
@@ -58,6 +69,10 @@ struct ResolvedFileControlBlock {
     nonce: VmgsNonce,
     authentication_tag: VmgsAuthTag,
 
+    // CRC32 of the stored contents (V4 only). For the file table itself, this
+    // comes from the header.
+    checksum: u32,
+
     // EXTENDED_FILE_TABLE data
     // ---------------
     attributes: FileAttribute,
@@ -134,12 +149,27 @@ pub fn stat_map(map: &HashMap<FileId, IoStat>) -> impl Inspect + '_ {
 impl Vmgs {
     /// Format and open a new VMGS file.
     pub async fn format_new(disk: Disk) -> Result<Self, Error> {
+        Self::format_new_with_version(disk, VMGS_VERSION_3_0).await
+    }
+
+    /// Format and open a new VMGS file with the specified format version,
+    /// either [`VMGS_VERSION_3_0`] or [`VMGS_VERSION_4_0`].
+    ///
+    /// Version 4.0 files are not supported by older VMGS implementations.
+    pub async fn format_new_with_version(disk: Disk, version: u32) -> Result<Self, Error> {
+        if version != VMGS_VERSION_3_0 && version != VMGS_VERSION_4_0 {
+            return Err(Error::Initialization(format!(
+                "Unsupported version {:#x}",
+                version
+            )));
+        }
+
         let mut storage = VmgsStorage::new(disk);
         tracing::debug!("formatting and initializing VMGS datastore");
         // Errors from validate_file are fatal, as they involve invalid device metadata
         Vmgs::validate_file(&storage)?;
 
-        let active_header = Self::format(&mut storage, VMGS_VERSION_3_0).await?;
+        let active_header = Self::format(&mut storage, version).await?;
 
         Self::finish_open(storage, active_header, 0).await
     }
@@ -161,16 +191,31 @@ pub async fn open(disk: Disk) -> Result<Self, Error> {
             return Err(Error::EmptyFile);
         }
 
-        let active_header_index =
+        let mut active_header_index =
             get_active_header(validate_header(&header_1), validate_header(&header_2))?;
 
-        let active_header = if active_header_index == 0 {
-            header_1
-        } else {
-            header_2
-        };
+        let headers = [header_1, header_2];
 
-        Self::finish_open(storage, active_header, active_header_index).await
+        // If the active file table doesn't match its checksum (e.g. because
+        // the disk lost writes that were supposedly flushed), fall back to the
+        // previous header, if it is intact.
+        if let Err(err @ Error::Checksum) =
+            read_file_table(&mut storage, &headers[active_header_index]).await
+        {
+            let previous_header_index = 1 - active_header_index;
+            let previous_header = &headers[previous_header_index];
+            if validate_header(previous_header).is_err()
+                || read_file_table(&mut storage, previous_header)
+                    .await
+                    .is_err()
+            {
+                return Err(Error::CorruptFormat(format!("Invalid file table: {}", err)));
+            }
+            tracing::warn!("VMGS file table is corrupt, falling back to the previous header");
+            active_header_index = previous_header_index;
+        }
+
+        Self::finish_open(storage, headers[active_header_index], active_header_index).await
     }
 
     async fn finish_open(
@@ -214,26 +259,27 @@ async fn finish_open(
             };
 
         // Read the file table and initialize the internal file metadata.
-        let file_table_size_bytes = block_count_to_byte_count(active_header.file_table_size);
-        let file_table_offset_bytes = block_count_to_byte_count(active_header.file_table_offset);
-
-        let mut file_table_buffer = vec![0; file_table_size_bytes as usize];
+        let file_table = match read_file_table(&mut storage, &active_header).await {
+            Ok(file_table) => file_table,
+            Err(Error::ReadDisk(e)) => {
+                return Err(Error::CorruptFormat(format!(
+                    "Error reading file table: {:?}",
+                    e
+                )));
+            }
+            Err(e) => {
+                return Err(Error::CorruptFormat(format!("Invalid file table: {}", e)));
+            }
+        };
 
-        if let Err(e) = storage
-            .read_block(file_table_offset_bytes, file_table_buffer.as_mut_slice())
-            .await
-        {
-            return Err(Error::CorruptFormat(format!(
-                "Error reading file table: {:?}",
-                e
-            )));
+        let mut file_control_blocks =
+            initialize_file_metadata(&file_table, version, storage.block_capacity())?;
+        if version >= VMGS_VERSION_4_0 {
+            if let Some(fcb) = file_control_blocks.get_mut(&FileId::FILE_TABLE) {
+                fcb.checksum = active_header.file_table_checksum;
+            }
         }
 
-        let file_table = VmgsFileTable::ref_from_prefix(&file_table_buffer).unwrap();
-
-        let file_control_blocks =
-            initialize_file_metadata(file_table, version, storage.block_capacity())?;
-
         Ok(Self {
             storage,
 
@@ -280,6 +326,10 @@ async fn format(storage: &mut VmgsStorage, version: u32) -> Result<VmgsHeader, E
                 VMGS_EXTENDED_FILE_TABLE_BLOCK_SIZE;
             file_table.entries[FileId::EXTENDED_FILE_TABLE].valid_data_size =
                 block_count_to_byte_count(VMGS_EXTENDED_FILE_TABLE_BLOCK_SIZE);
+            if version >= VMGS_VERSION_4_0 {
+                file_table.entries[FileId::EXTENDED_FILE_TABLE].checksum =
+                    compute_crc32(VmgsExtendedFileTable::new_zeroed().as_bytes());
+            }
         }
 
         storage
@@ -290,7 +340,7 @@ async fn format(storage: &mut VmgsStorage, version: u32) -> Result<VmgsHeader, E
             .await
             .map_err(Error::WriteDisk)?;
 
-        initialize_file_metadata(&file_table, VMGS_VERSION_3_0, storage.block_capacity())?;
+        initialize_file_metadata(&file_table, version, storage.block_capacity())?;
 
         // Write an empty extended file table if the datastore supports V3.
         if version >= VMGS_VERSION_3_0 {
@@ -308,11 +358,14 @@ async fn format(storage: &mut VmgsStorage, version: u32) -> Result<VmgsHeader, E
 
         // Write the first header as the valid header
         header.signature = VMGS_SIGNATURE;
-        header.version = VMGS_VERSION_3_0;
+        header.version = version;
         header.sequence = 1;
         header.header_size = size_of::<VmgsHeader>() as u32;
         header.file_table_offset = VMGS_MIN_FILE_BLOCK_OFFSET;
         header.file_table_size = VMGS_FILE_TABLE_BLOCK_SIZE;
+        if version >= VMGS_VERSION_4_0 {
+            header.file_table_checksum = compute_crc32(file_table.as_bytes());
+        }
         header.checksum = compute_crc32(header.as_bytes());
         header.encryption_algorithm = EncryptionAlgorithm::NONE;
 
@@ -440,6 +493,7 @@ struct AllocationBlock {
             attributes: FileAttribute::new(),
             nonce: VmgsNonce::new_zeroed(),
             authentication_tag: VmgsAuthTag::new_zeroed(),
+            checksum: 0,
             encryption_key: VmgsDatastoreKey::new_zeroed(),
         };
         temp_fcbs.push(new_fcb);
@@ -466,14 +520,15 @@ async fn write_file_internal(
             let data_nonce = generate_nonce();
             let mut data_auth_tag = VmgsAuthTag::new_zeroed();
 
-            self.write_encrypted_data(
-                data_fcb.block_offset,
-                &data_encryption_key,
-                &data_nonce,
-                buf,
-                &mut data_auth_tag,
-            )
-            .await?;
+            data_fcb.checksum = self
+                .write_encrypted_data(
+                    data_fcb.block_offset,
+                    &data_encryption_key,
+                    &data_nonce,
+                    buf,
+                    &mut data_auth_tag,
+                )
+                .await?;
 
             // Update the data file control block.
             data_fcb.nonce.copy_from_slice(&data_nonce);
@@ -488,25 +543,12 @@ async fn write_file_internal(
                 .write_block(block_count_to_byte_count(data_fcb.block_offset), buf)
                 .await
                 .map_err(Error::WriteDisk)?;
+            data_fcb.checksum = compute_crc32(buf);
             None
         };
 
         // Initialize the new file table with current metadata for all files.
-        let mut new_file_table = VmgsFileTable::new_zeroed();
-        for (file_id, fcb) in self.fcbs.iter() {
-            let new_file_entry = &mut new_file_table.entries[*file_id];
-
-            new_file_entry.offset = fcb.block_offset;
-            new_file_entry.allocation_size = fcb.allocated_blocks.get();
-            new_file_entry.valid_data_size = fcb.valid_bytes;
-
-            if self.version >= VMGS_VERSION_3_0 {
-                new_file_entry.nonce.copy_from_slice(&fcb.nonce);
-                new_file_entry
-                    .authentication_tag
-                    .copy_from_slice(&fcb.authentication_tag);
-            }
-        }
+        let mut new_file_table = build_file_table(&self.fcbs, self.version);
 
         // Fill in the metadata for the file being written.
         let file_entry = &mut new_file_table.entries[file_id];
@@ -525,6 +567,10 @@ async fn write_file_internal(
                 .copy_from_slice(&data_auth_tag);
         }
 
+        if self.version >= VMGS_VERSION_4_0 {
+            file_entry.checksum = data_fcb.checksum;
+        }
+
         // Fill in the metadata for the new file table itself (file ID 0)
         let file_table_entry = &mut new_file_table.entries[FileId::FILE_TABLE];
         *file_table_entry = vmgs_format::VmgsFileEntry {
@@ -543,6 +589,7 @@ async fn write_file_internal(
                 )
                 .await
                 .map_err(Error::WriteDisk)?;
+            file_table_fcb.checksum = compute_crc32(new_file_table.as_bytes());
         }
 
         // Update the in-memory file control blocks. Updating file_control_block last ensures
@@ -643,6 +690,7 @@ async fn read_file_inner(&mut self, file_id: FileId, decrypt: bool) -> Result<Ve
                 &fcb.encryption_key,
                 &fcb.nonce,
                 &fcb.authentication_tag,
+                self.stored_checksum(&fcb),
                 &mut buf,
             )
             .await?;
@@ -654,6 +702,7 @@ async fn read_file_inner(&mut self, file_id: FileId, decrypt: bool) -> Result<Ve
                 .read_block(byte_offset, &mut buf)
                 .await
                 .map_err(Error::ReadDisk)?;
+            verify_checksum(self.stored_checksum(&fcb), &buf)?;
         }
 
         #[cfg(feature = "inspect")]
@@ -1057,6 +1106,7 @@ pub async fn unlock_with_encryption_key(
             &self_metadata_key,
             &extended_file_header.nonce,
             &extended_file_header.authentication_tag,
+            self.stored_checksum(&extended_file_header),
             &mut extended_file_table_buffer,
         )
         .await
@@ -1078,6 +1128,7 @@ pub async fn unlock_with_encryption_key(
     }
 
     /// Encrypts the plaintext data and writes the encrypted data to the storage.
+    /// Returns the checksum of the encrypted data.
     #[cfg_attr(not(with_encryption), allow(unused_variables))]
     async fn write_encrypted_data(
         &mut self,
@@ -1086,7 +1137,7 @@ async fn write_encrypted_data(
         nonce: &[u8],
         plaintext_data: &[u8],
         authentication_tag: &mut [u8],
-    ) -> Result<(), Error> {
+    ) -> Result<u32, Error> {
         #[cfg(not(with_encryption))]
         unreachable!("Encryption requires the encryption feature");
         #[cfg(with_encryption)]
@@ -1104,11 +1155,13 @@ async fn write_encrypted_data(
                 .await
                 .map_err(Error::WriteDisk)?;
 
-            Ok(())
+            Ok(compute_crc32(&encrypted_text))
         }
     }
 
     /// Decrypts the encrypted data and reads it to the buffer.
+    ///
+    /// If `checksum` is provided, the encrypted data is checked against it.
     #[cfg_attr(not(with_encryption), allow(unused_variables))]
     async fn read_decrypted_data(
         &mut self,
@@ -1116,6 +1169,7 @@ async fn read_decrypted_data(
         decryption_key: &[u8],
         nonce: &[u8],
         authentication_tag: &[u8],
+        checksum: Option<u32>,
         plaintext_data: &mut [u8],
     ) -> Result<(), Error> {
         #[cfg(not(with_encryption))]
@@ -1130,6 +1184,8 @@ async fn read_decrypted_data(
                 .await
                 .map_err(Error::ReadDisk)?;
 
+            verify_checksum(checksum, &buf)?;
+
             // sanity check: encrypted data should never be all zeros. if we
             // find that it is all-zeroes, then that's indicative of some kind
             // of logic error / data corruption
@@ -1339,6 +1395,72 @@ pub async fn remove_encryption_key(&mut self, key_index: usize) -> Result<(), Er
         Ok(())
     }
 
+    /// Replaces the active root key with `new_encryption_key`, by re-wrapping
+    /// the metadata key under it. Returns the index of the new key.
+    ///
+    /// The files remain encrypted with the same keys, so no file data is
+    /// rewritten. The new key is committed by a single header write, and is
+    /// then copied over the previous header as well, so that the replaced key
+    /// can no longer be used to unlock either header.
+    #[cfg(with_encryption)]
+    pub async fn rekey(&mut self, new_encryption_key: &[u8]) -> Result<usize, Error> {
+        if self.version < VMGS_VERSION_3_0 {
+            return Err(Error::Other(anyhow!(
+                "rekey() not supported with VMGS version"
+            )));
+        }
+        let Some(key_index) = self.active_datastore_key_index else {
+            return Err(Error::Other(anyhow!(
+                "rekey() requires an unlocked encrypted datastore"
+            )));
+        };
+        if is_empty_key(new_encryption_key) {
+            return Err(Error::Other(anyhow!("Trying to add empty encryption key")));
+        }
+
+        // Wrap the metadata key with the new datastore key.
+        let metadata_key_nonce = generate_nonce();
+        let mut metadata_key_auth_tag = VmgsAuthTag::new_zeroed();
+        let encrypted_metadata_key = encrypt_metadata_key(
+            new_encryption_key,
+            &metadata_key_nonce,
+            &self.metadata_key,
+            &mut metadata_key_auth_tag,
+        )?;
+
+        let mut encrypted_metadata_keys = self.encrypted_metadata_keys;
+        let new_key = &mut encrypted_metadata_keys[key_index];
+        *new_key = VmgsEncryptionKey::new_zeroed();
+        new_key.nonce.copy_from_slice(&metadata_key_nonce);
+        new_key
+            .authentication_tag
+            .copy_from_slice(&metadata_key_auth_tag);
+        new_key
+            .encryption_key
+            .copy_from_slice(&encrypted_metadata_key);
+
+        // Prepare a new header.
+        let mut new_header = self.prepare_new_header(&self.fcbs[&FileId::FILE_TABLE]);
+        new_header.encryption_algorithm = self.encryption_algorithm;
+        new_header
+            .metadata_keys
+            .copy_from_slice(&encrypted_metadata_keys);
+
+        // Commit the new key.
+        self.update_header(&mut new_header).await?;
+        self.storage.flush().await.map_err(Error::FlushDisk)?;
+
+        // Update the cached keys.
+        self.encrypted_metadata_keys = encrypted_metadata_keys;
+        self.datastore_keys[key_index].copy_from_slice(new_encryption_key);
+
+        // Overwrite the previous header, which still holds the old key.
+        self.update_header(&mut new_header).await?;
+        self.storage.flush().await.map_err(Error::FlushDisk)?;
+
+        Ok(key_index)
+    }
+
     /// Gets the encryption algorithm of the VMGS
     pub fn get_encryption_algorithm(&self) -> EncryptionAlgorithm {
         self.encryption_algorithm
@@ -1354,6 +1476,171 @@ pub fn get_active_datastore_key_index(&self) -> Option<usize> {
         self.active_datastore_key_index
     }
 
+    /// Compacts the file, moving files toward the start of the disk so that
+    /// the free space left behind by earlier writes is merged into a single
+    /// range at the end.
+    ///
+    /// Files are only ever copied into free space, and each move is committed
+    /// by writing a new file table and header, so the file remains valid if
+    /// the operation is interrupted at any point. Files are moved as-is, so
+    /// this does not require the datastore to be unlocked.
+    ///
+    /// A file is only moved if there is a large enough free range below it,
+    /// so the result is not necessarily fully compacted.
+    pub async fn compact(&mut self) -> Result<VmgsCompactInfo, Error> {
+        let mut files_moved = 0;
+        loop {
+            let free = self.free_block_ranges();
+            // The new file table goes in the last free block, out of the way
+            // of the files being moved.
+            let Some(&(last_offset, last_count)) = free.last() else {
+                break;
+            };
+            let file_table_offset = last_offset + last_count - 1;
+
+            let mut files = self
+                .fcbs
+                .iter()
+                .filter(|(file_id, _)| **file_id != FileId::FILE_TABLE)
+                .map(|(file_id, fcb)| (*file_id, *fcb))
+                .collect::<Vec<_>>();
+            files.sort_by_key(|(_, fcb)| std::cmp::Reverse(fcb.block_offset));
+
+            // Move the last file which fits in a free range below it.
+            let next_move = files.into_iter().find_map(|(file_id, fcb)| {
+                free.iter()
+                    .take_while(|(offset, _)| *offset < fcb.block_offset)
+                    .find(|&&(offset, count)| {
+                        let usable = if offset == last_offset {
+                            count - 1
+                        } else {
+                            count
+                        };
+                        usable >= fcb.allocated_blocks.get()
+                    })
+                    .map(|&(offset, _)| (file_id, offset))
+            });
+
+            let Some((file_id, new_offset)) = next_move else {
+                break;
+            };
+            self.relocate(Some((file_id, new_offset)), file_table_offset)
+                .await?;
+            files_moved += 1;
+        }
+
+        // Finally, move the file table itself to the lowest free block.
+        if let Some(&(offset, _)) = self.free_block_ranges().first() {
+            if offset < self.fcbs[&FileId::FILE_TABLE].block_offset {
+                self.relocate(None, offset).await?;
+            }
+        }
+
+        let used_blocks = self
+            .fcbs
+            .values()
+            .map(|fcb| fcb.block_offset + fcb.allocated_blocks.get())
+            .max()
+            .unwrap_or(VMGS_MIN_FILE_BLOCK_OFFSET);
+
+        Ok(VmgsCompactInfo {
+            files_moved,
+            used_bytes: block_count_to_byte_count(used_blocks),
+        })
+    }
+
+    /// Returns the unallocated ranges of blocks, as `(block_offset,
+    /// block_count)`, in offset order.
+    fn free_block_ranges(&self) -> Vec<(u32, u32)> {
+        let mut allocations = self
+            .fcbs
+            .values()
+            .map(|fcb| (fcb.block_offset, fcb.allocated_blocks.get()))
+            .collect::<Vec<_>>();
+        allocations.sort_unstable();
+
+        let mut free = Vec::new();
+        let mut end = VMGS_MIN_FILE_BLOCK_OFFSET;
+        for (offset, count) in allocations {
+            if offset > end {
+                free.push((end, offset - end));
+            }
+            end = end.max(offset + count);
+        }
+        let capacity = self.storage.block_capacity();
+        if capacity > end {
+            free.push((end, capacity - end));
+        }
+        free
+    }
+
+    /// Copies the contents of a file (if any) to free space at a new offset,
+    /// then commits the move by writing a new file table at
+    /// `file_table_offset` and a new header.
+    ///
+    /// The in-memory metadata is only updated once the new header has been
+    /// written.
+    async fn relocate(
+        &mut self,
+        file_move: Option<(FileId, u32)>,
+        file_table_offset: u32,
+    ) -> Result<(), Error> {
+        let mut fcbs = self.fcbs.clone();
+
+        if let Some((file_id, new_offset)) = file_move {
+            let fcb = fcbs.get_mut(&file_id).unwrap();
+            tracing::debug!(
+                ?file_id,
+                from = fcb.block_offset,
+                to = new_offset,
+                "moving VMGS file"
+            );
+
+            // Copy the raw (possibly encrypted) contents. Encrypted data does
+            // not depend on its location, so it remains valid.
+            let mut buf = vec![0; fcb.valid_bytes as usize];
+            self.storage
+                .read_block(block_count_to_byte_count(fcb.block_offset), &mut buf)
+                .await
+                .map_err(Error::ReadDisk)?;
+            self.storage
+                .write_block(block_count_to_byte_count(new_offset), &buf)
+                .await
+                .map_err(Error::WriteDisk)?;
+            fcb.block_offset = new_offset;
+        }
+
+        fcbs.get_mut(&FileId::FILE_TABLE).unwrap().block_offset = file_table_offset;
+        let file_table = build_file_table(&fcbs, self.version);
+        self.storage
+            .write_block(
+                block_count_to_byte_count(file_table_offset),
+                file_table.as_bytes(),
+            )
+            .await
+            .map_err(Error::WriteDisk)?;
+
+        let file_table_fcb = fcbs.get_mut(&FileId::FILE_TABLE).unwrap();
+        file_table_fcb.checksum = compute_crc32(file_table.as_bytes());
+        let file_table_fcb = *file_table_fcb;
+
+        // Data must be hardened on persistent storage before the header is updated.
+        self.storage.flush().await.map_err(Error::FlushDisk)?;
+
+        let mut new_header = self.prepare_new_header(&file_table_fcb);
+        if self.encryption_algorithm != EncryptionAlgorithm::NONE {
+            new_header.encryption_algorithm = self.encryption_algorithm;
+            new_header
+                .metadata_keys
+                .copy_from_slice(&self.encrypted_metadata_keys);
+        }
+        self.update_header(&mut new_header).await?;
+        self.storage.flush().await.map_err(Error::FlushDisk)?;
+
+        self.fcbs = fcbs;
+        Ok(())
+    }
+
     fn prepare_new_header(&self, file_table_fcb: &ResolvedFileControlBlock) -> VmgsHeader {
         VmgsHeader {
             signature: VMGS_SIGNATURE,
@@ -1361,9 +1648,19 @@ fn prepare_new_header(&self, file_table_fcb: &ResolvedFileControlBlock) -> VmgsH
             header_size: size_of::<VmgsHeader>() as u32,
             file_table_offset: file_table_fcb.block_offset,
             file_table_size: file_table_fcb.allocated_blocks.get(),
+            file_table_checksum: if self.version >= VMGS_VERSION_4_0 {
+                file_table_fcb.checksum
+            } else {
+                0
+            },
             ..VmgsHeader::new_zeroed()
         }
     }
+
+    /// The checksum of a file's stored contents, if the format has one.
+    fn stored_checksum(&self, fcb: &ResolvedFileControlBlock) -> Option<u32> {
+        (self.version >= VMGS_VERSION_4_0).then_some(fcb.checksum)
+    }
 }
 
 /// Read both headers. For compatibility with the V1 format, the headers are
@@ -1372,7 +1669,9 @@ pub async fn read_headers(disk: Disk) -> Result<(VmgsHeader, VmgsHeader), Error>
     read_headers_inner(&mut VmgsStorage::new(disk)).await
 }
 
-async fn read_headers_inner(storage: &mut VmgsStorage) -> Result<(VmgsHeader, VmgsHeader), Error> {
+pub(crate) async fn read_headers_inner(
+    storage: &mut VmgsStorage,
+) -> Result<(VmgsHeader, VmgsHeader), Error> {
     // Read both headers, and determine the active one. For compatibility with
     // the V1 format, the headers are at logical sectors 0 and 1
     let mut first_two_blocks = [0; (VMGS_BYTES_PER_BLOCK * 2) as usize];
@@ -1434,7 +1733,7 @@ pub fn validate_header(header: &VmgsHeader) -> Result<&VmgsHeader, Error> {
             "Invalid header signature",
         )));
     }
-    if header.version != VMGS_VERSION_3_0 {
+    if header.version != VMGS_VERSION_3_0 && header.version != VMGS_VERSION_4_0 {
         return Err(Error::InvalidFormat(String::from("Invalid header version")));
     }
     if header.header_size != size_of::<VmgsHeader>() as u32 {
@@ -1509,6 +1808,11 @@ fn initialize_file_metadata(
             } else {
                 Default::default()
             };
+            let checksum = if version >= VMGS_VERSION_4_0 {
+                file_entry.checksum
+            } else {
+                0
+            };
 
             ResolvedFileControlBlock {
                 block_offset: file_entry.offset,
@@ -1517,6 +1821,7 @@ fn initialize_file_metadata(
 
                 nonce,
                 authentication_tag,
+                checksum,
 
                 attributes: FileAttribute::new(),
                 encryption_key: VmgsDatastoreKey::new_zeroed(),
@@ -1527,8 +1832,65 @@ fn initialize_file_metadata(
     Ok(file_control_blocks)
 }
 
+/// Builds a file table from the file control blocks.
+fn build_file_table(
+    fcbs: &HashMap<FileId, ResolvedFileControlBlock>,
+    version: u32,
+) -> VmgsFileTable {
+    let mut file_table = VmgsFileTable::new_zeroed();
+    for (file_id, fcb) in fcbs.iter() {
+        let file_entry = &mut file_table.entries[*file_id];
+
+        file_entry.offset = fcb.block_offset;
+        file_entry.allocation_size = fcb.allocated_blocks.get();
+        file_entry.valid_data_size = fcb.valid_bytes;
+
+        if version >= VMGS_VERSION_3_0 {
+            file_entry.nonce.copy_from_slice(&fcb.nonce);
+            file_entry
+                .authentication_tag
+                .copy_from_slice(&fcb.authentication_tag);
+        }
+
+        // The file table's own checksum is stored in the header.
+        if version >= VMGS_VERSION_4_0 && *file_id != FileId::FILE_TABLE {
+            file_entry.checksum = fcb.checksum;
+        }
+    }
+    file_table
+}
+
+/// Reads the file table referenced by `header`, verifying its checksum if the
+/// format has one.
+pub(crate) async fn read_file_table(
+    storage: &mut VmgsStorage,
+    header: &VmgsHeader,
+) -> Result<VmgsFileTable, Error> {
+    let mut file_table = VmgsFileTable::new_zeroed();
+    storage
+        .read_block(
+            block_count_to_byte_count(header.file_table_offset),
+            file_table.as_bytes_mut(),
+        )
+        .await
+        .map_err(Error::ReadDisk)?;
+
+    if header.version >= VMGS_VERSION_4_0 {
+        verify_checksum(Some(header.file_table_checksum), file_table.as_bytes())?;
+    }
+    Ok(file_table)
+}
+
+/// Checks `data` against `checksum`, if provided.
+fn verify_checksum(checksum: Option<u32>, data: &[u8]) -> Result<(), Error> {
+    if checksum.is_some_and(|checksum| checksum != compute_crc32(data)) {
+        return Err(Error::Checksum);
+    }
+    Ok(())
+}
+
 /// Convert block count to byte count.
-fn block_count_to_byte_count(block_count: u32) -> u64 {
+pub(crate) fn block_count_to_byte_count(block_count: u32) -> u64 {
     block_count as u64 * VMGS_BYTES_PER_BLOCK as u64
 }
 
@@ -1618,7 +1980,7 @@ fn decrypt_metadata_key(
 }
 
 /// Computes the cr32 checksum for a given byte stream.
-fn compute_crc32(buf: &[u8]) -> u32 {
+pub(crate) fn compute_crc32(buf: &[u8]) -> u32 {
     let mut hasher = crc32fast::Hasher::new();
     hasher.update(buf);
     hasher.finalize()
@@ -1654,6 +2016,8 @@ pub struct SavedResolvedFileControlBlock {
             pub attributes: u32,
             #[mesh(7)]
             pub encryption_key: SavedVmgsDatastoreKey,
+            #[mesh(8)]
+            pub checksum: u32,
         }
 
         #[derive(Protobuf)]
@@ -1744,6 +2108,7 @@ pub fn open_from_saved(disk: Disk, state: state::SavedVmgsState) -> Self {
                             authentication_tag,
                             attributes,
                             encryption_key,
+                            checksum,
                         } = fcb;
 
                         (
@@ -1754,6 +2119,7 @@ pub fn open_from_saved(disk: Disk, state: state::SavedVmgsState) -> Self {
                                 valid_bytes,
                                 nonce,
                                 authentication_tag,
+                                checksum,
                                 attributes: FileAttribute::from(attributes),
                                 encryption_key,
                             },
@@ -1819,6 +2185,7 @@ pub fn save(&self) -> state::SavedVmgsState {
                             valid_bytes,
                             nonce,
                             authentication_tag,
+                            checksum,
                             attributes,
                             encryption_key,
                         } = fcb;
@@ -1833,6 +2200,7 @@ pub fn save(&self) -> state::SavedVmgsState {
                                 authentication_tag: *authentication_tag,
                                 attributes: (*attributes).into(),
                                 encryption_key: *encryption_key,
+                                checksum: *checksum,
                             },
                         )
                     })
@@ -2101,6 +2469,48 @@ async fn test_empty_write() {
         assert_eq!(vmgs.active_header_sequence_number, 2);
     }
 
+    #[async_test]
+    async fn compact() {
+        let disk = new_test_file();
+        let mut vmgs = Vmgs::format_new(disk.clone()).await.unwrap();
+
+        let buf_1 = vec![1; 8 * 1024];
+        let buf_2 = b"short sentence";
+        let buf_3 = b"funny joke";
+
+        // Leave holes at blocks 2 and 5-6.
+        vmgs.write_file(FileId::BIOS_NVRAM, &buf_1).await.unwrap();
+        vmgs.write_file(FileId::TPM_PPI, buf_2).await.unwrap();
+        vmgs.write_file(FileId::BIOS_NVRAM, buf_3).await.unwrap();
+        assert_eq!(vmgs.fcbs[&FileId::FILE_TABLE].block_offset, 4);
+        assert_eq!(vmgs.fcbs[&FileId::BIOS_NVRAM].block_offset, 8);
+        assert_eq!(vmgs.fcbs[&FileId::TPM_PPI].block_offset, 7);
+
+        let info = vmgs.compact().await.unwrap();
+        assert_eq!(info.files_moved, 2);
+        assert_eq!(info.used_bytes, block_count_to_byte_count(6));
+        assert_eq!(vmgs.fcbs[&FileId::BIOS_NVRAM].block_offset, 2);
+        assert_eq!(vmgs.fcbs[&FileId::TPM_PPI].block_offset, 4);
+        assert_eq!(vmgs.fcbs[&FileId::FILE_TABLE].block_offset, 5);
+        let capacity = vmgs.storage.block_capacity();
+        assert_eq!(vmgs.free_block_ranges(), vec![(6, capacity - 6)]);
+
+        assert_eq!(vmgs.read_file(FileId::BIOS_NVRAM).await.unwrap(), buf_3);
+        assert_eq!(vmgs.read_file(FileId::TPM_PPI).await.unwrap(), buf_2);
+
+        // Compacting again is a no-op.
+        let info = vmgs.compact().await.unwrap();
+        assert_eq!(info.files_moved, 0);
+        assert_eq!(info.used_bytes, block_count_to_byte_count(6));
+
+        // Re-open VMGS file and read from the moved files.
+        drop(vmgs);
+        let mut vmgs = Vmgs::open(disk).await.unwrap();
+        assert_eq!(vmgs.fcbs[&FileId::FILE_TABLE].block_offset, 5);
+        assert_eq!(vmgs.read_file(FileId::BIOS_NVRAM).await.unwrap(), buf_3);
+        assert_eq!(vmgs.read_file(FileId::TPM_PPI).await.unwrap(), buf_2);
+    }
+
     // general functions
     #[test]
     fn test_block_count_to_byte_count() {
@@ -2196,6 +2606,86 @@ fn test_initialize_file_metadata() {
         assert!(fcbs[&FileId(3)].allocated_blocks.get() == 3);
     }
 
+    #[async_test]
+    async fn checksums() {
+        let buf_1 = b"hello world";
+        let buf_2 = b"short sentence";
+
+        let disk = new_test_file();
+        let mut vmgs = Vmgs::format_new_with_version(disk.clone(), VMGS_VERSION_4_0)
+            .await
+            .unwrap();
+        vmgs.write_file(FileId::BIOS_NVRAM, buf_1).await.unwrap();
+        vmgs.write_file(FileId::TPM_PPI, buf_2).await.unwrap();
+        drop(vmgs);
+
+        let mut vmgs = Vmgs::open(disk).await.unwrap();
+        assert_eq!(vmgs.version, VMGS_VERSION_4_0);
+        assert_eq!(
+            vmgs.read_file(FileId::BIOS_NVRAM).await.unwrap(),
+            buf_1.to_vec()
+        );
+        assert_eq!(
+            vmgs.read_file(FileId::TPM_PPI).await.unwrap(),
+            buf_2.to_vec()
+        );
+
+        // Corrupt the contents of a file.
+        let block_offset = vmgs.fcbs[&FileId::BIOS_NVRAM].block_offset;
+        vmgs.storage
+            .write_block(block_count_to_byte_count(block_offset), b"jello world")
+            .await
+            .unwrap();
+        let result = vmgs.read_file(FileId::BIOS_NVRAM).await;
+        assert!(matches!(result, Err(Error::Checksum)));
+        assert_eq!(
+            vmgs.read_file(FileId::TPM_PPI).await.unwrap(),
+            buf_2.to_vec()
+        );
+    }
+
+    #[async_test]
+    async fn checksums_file_table_fallback() {
+        let buf_1 = b"hello world";
+        let buf_2 = b"short sentence";
+
+        let disk = new_test_file();
+        let mut vmgs = Vmgs::format_new_with_version(disk.clone(), VMGS_VERSION_4_0)
+            .await
+            .unwrap();
+        vmgs.write_file(FileId::BIOS_NVRAM, buf_1).await.unwrap();
+        vmgs.write_file(FileId::BIOS_NVRAM, buf_2).await.unwrap();
+        assert_eq!(vmgs.active_header_index, 0);
+
+        // Lose the write of the latest file table.
+        let block_offset = vmgs.fcbs[&FileId::FILE_TABLE].block_offset;
+        vmgs.storage
+            .write_block(
+                block_count_to_byte_count(block_offset),
+                VmgsFileTable::new_zeroed().as_bytes(),
+            )
+            .await
+            .unwrap();
+        drop(vmgs);
+
+        // The previous header and file table are used instead.
+        let mut vmgs = Vmgs::open(disk.clone()).await.unwrap();
+        assert_eq!(vmgs.active_header_index, 1);
+        assert_eq!(
+            vmgs.read_file(FileId::BIOS_NVRAM).await.unwrap(),
+            buf_1.to_vec()
+        );
+
+        vmgs.write_file(FileId::BIOS_NVRAM, buf_2).await.unwrap();
+        drop(vmgs);
+
+        let mut vmgs = Vmgs::open(disk).await.unwrap();
+        assert_eq!(
+            vmgs.read_file(FileId::BIOS_NVRAM).await.unwrap(),
+            buf_2.to_vec()
+        );
+    }
+
     #[test]
     fn test_round_up_count() {
         assert!(round_up_count(0, 4096) == 0);
@@ -2441,6 +2931,88 @@ async fn add_new_encryption_key() {
         assert_ne!(read_buf.unwrap(), buf);
     }
 
+    #[cfg(with_encryption)]
+    #[async_test]
+    async fn compact_encrypted() {
+        let disk = new_test_file();
+        let mut vmgs = Vmgs::format_new(disk.clone()).await.unwrap();
+        let encryption_key = [1; VMGS_ENCRYPTION_KEY_SIZE];
+        let buf: Vec<u8> = (0..255).collect();
+        let buf_1 = b"hello world";
+
+        vmgs.add_new_encryption_key(&encryption_key, EncryptionAlgorithm::AES_GCM)
+            .await
+            .unwrap();
+        vmgs.write_file_encrypted(FileId::BIOS_NVRAM, &buf)
+            .await
+            .unwrap();
+        vmgs.write_file(FileId::TPM_PPI, buf_1).await.unwrap();
+        vmgs.write_file_encrypted(FileId::BIOS_NVRAM, &buf)
+            .await
+            .unwrap();
+
+        // Compact without unlocking the datastore.
+        drop(vmgs);
+        let mut vmgs = Vmgs::open(disk.clone()).await.unwrap();
+        let info = vmgs.compact().await.unwrap();
+        assert_ne!(info.files_moved, 0);
+        assert_eq!(vmgs.free_block_ranges().len(), 1);
+
+        drop(vmgs);
+        let mut vmgs = Vmgs::open(disk).await.unwrap();
+        vmgs.unlock_with_encryption_key(&encryption_key)
+            .await
+            .unwrap();
+        assert_eq!(vmgs.read_file(FileId::BIOS_NVRAM).await.unwrap(), buf);
+        assert_eq!(vmgs.read_file(FileId::TPM_PPI).await.unwrap(), buf_1);
+    }
+
+    #[cfg(with_encryption)]
+    #[async_test]
+    async fn rekey() {
+        let buf: Vec<u8> = (0..255).collect();
+        let encryption_key = [1; VMGS_ENCRYPTION_KEY_SIZE];
+        let new_encryption_key = [2; VMGS_ENCRYPTION_KEY_SIZE];
+
+        let disk = new_test_file();
+        let mut vmgs = Vmgs::format_new(disk.clone()).await.unwrap();
+
+        // Rekeying requires an encrypted datastore.
+        let result = vmgs.rekey(&new_encryption_key).await;
+        assert!(matches!(result, Err(Error::Other(_))));
+
+        vmgs.add_new_encryption_key(&encryption_key, EncryptionAlgorithm::AES_GCM)
+            .await
+            .unwrap();
+        vmgs.write_file_encrypted(FileId::BIOS_NVRAM, &buf)
+            .await
+            .unwrap();
+
+        let key_index = vmgs.rekey(&new_encryption_key).await.unwrap();
+        assert_eq!(key_index, 0);
+        let read_buf = vmgs.read_file(FileId::BIOS_NVRAM).await.unwrap();
+        assert_eq!(buf, read_buf);
+
+        // Neither header holds the old key anymore.
+        let (header_1, header_2) = read_headers(disk.clone()).await.unwrap();
+        assert_eq!(
+            header_1.metadata_keys[0].as_bytes(),
+            header_2.metadata_keys[0].as_bytes()
+        );
+
+        drop(vmgs);
+        let mut vmgs = Vmgs::open(disk).await.unwrap();
+        let result = vmgs.unlock_with_encryption_key(&encryption_key).await;
+        assert!(matches!(result, Err(Error::Other(_))));
+        let key_index = vmgs
+            .unlock_with_encryption_key(&new_encryption_key)
+            .await
+            .unwrap();
+        assert_eq!(key_index, 0);
+        let read_buf = vmgs.read_file(FileId::BIOS_NVRAM).await.unwrap();
+        assert_eq!(buf, read_buf);
+    }
+
     #[cfg(with_encryption)]
     #[async_test]
     async fn test_write_file_encrypted() {
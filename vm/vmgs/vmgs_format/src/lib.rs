@@ -50,6 +50,9 @@ pub enum FileId: u32 {
 
 pub const VMGS_VERSION_2_0: u32 = 0x00020000;
 pub const VMGS_VERSION_3_0: u32 = 0x00030000;
+/// Adds checksums of the file table (in the header) and of each file's
+/// contents (in the file table), so that torn or lost writes are detected.
+pub const VMGS_VERSION_4_0: u32 = 0x00040000;
 
 pub const VMGS_SIGNATURE: u64 = u64::from_le_bytes(*b"GUESTRTS"); // identical to the V1 format signature
 
@@ -84,7 +87,12 @@ pub struct VmgsFileEntry {
     pub nonce: VmgsNonce,
     pub authentication_tag: VmgsAuthTag,
 
-    pub reserved: [u8; 20],
+    // V4 fields
+    /// CRC32 of the file's contents, as stored (i.e. after encryption). Not
+    /// used for the file table itself, whose checksum is in the header.
+    pub checksum: u32,
+
+    pub reserved: [u8; 16],
 }
 
 const_assert!(size_of::<VmgsFileEntry>() == 64);
@@ -158,7 +166,10 @@ pub struct VmgsHeader {
     pub encryption_algorithm: EncryptionAlgorithm,
     pub reserved: u16,
     pub metadata_keys: [VmgsEncryptionKey; 2],
-    pub reserved_1: u32,
+
+    // V4 fields
+    /// CRC32 of the file table.
+    pub file_table_checksum: u32,
 }
 
 const_assert!(size_of::<VmgsHeader>() == 168);
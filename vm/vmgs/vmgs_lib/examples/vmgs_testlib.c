@@ -78,6 +78,31 @@ int main()
         goto err;
     }
 
+    uint8_t keyProtector[] = {1, 2, 3, 4};
+    ret = write_attestation_vmgs(vmgsPath, KeyProtector, keyProtector, sizeof(keyProtector));
+    if (ret != 0)
+    {
+        printf("failed to write key protector: %d\n", ret);
+        goto err;
+    }
+
+    uint8_t keyProtectorRead[sizeof(keyProtector)] = {0};
+    uint64_t keyProtectorLen = sizeof(keyProtectorRead);
+    ret = read_attestation_vmgs(vmgsPath, KeyProtector, keyProtectorRead, &keyProtectorLen);
+    if (ret != 0)
+    {
+        printf("failed to read key protector: %d\n", ret);
+        goto err;
+    }
+
+    if (keyProtectorLen != sizeof(keyProtector) ||
+        memcmp(keyProtector, keyProtectorRead, sizeof(keyProtector)) != 0)
+    {
+        ret = -1;
+        printf("comparison failed, read key protector does not match original data\n");
+        goto err;
+    }
+
 err:
     remove(testPath);
     remove(vmgsPath);
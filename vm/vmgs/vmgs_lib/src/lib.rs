@@ -2,18 +2,26 @@
 // Licensed under the MIT License.
 
 //! FFI wrapper to provide access to VMGS functions as a cdylib
+//!
+//! Every function holds an advisory lock on the VMGS file while it is open:
+//! shared for reads, exclusive for writes. Rather than waiting for a
+//! conflicting lock, functions fail with [`VmgsError::FileLocked`].
 
 // UNSAFETY: Exporting no_mangle extern C functions and dealing with the raw
 // pointers necessary to do so.
 #![allow(unsafe_code)]
 
+mod lock;
+
 use core::slice;
 use disk_backend::Disk;
 use disk_vhd1::Vhd1Disk;
 use futures::executor::block_on;
+use lock::FileLock;
 use std::ffi::c_char;
 use std::ffi::CStr;
 use std::fs::File;
+use std::io;
 use std::io::Read;
 use std::path::Path;
 use std::path::PathBuf;
@@ -40,6 +48,7 @@ pub enum VmgsError {
     EncryptionFailed = 12,
     WriteFailed = 13,
     FileExists = 14,
+    FileLocked = 15,
 }
 
 /// Read the contents of a `FileId` in a VMGS file
@@ -104,15 +113,30 @@ pub enum VmgsError {
     VmgsError::Ok
 }
 
-fn open_disk(file_path: &str, read_only: bool) -> Result<Disk, VmgsError> {
+/// Opens the VMGS file at `file_path` and locks it: shared if `read_only`,
+/// exclusive otherwise. The lock is held until the returned [`FileLock`] is
+/// dropped.
+fn open_disk(file_path: &str, read_only: bool) -> Result<(Disk, FileLock), VmgsError> {
     let file = File::options()
         .read(true)
         .write(!read_only)
         .open(file_path)
         .map_err(|_| VmgsError::FileDisk)?;
 
+    let lock = lock_file(&file, !read_only)?;
     let disk = Vhd1Disk::open_fixed(file, read_only).map_err(|_| VmgsError::FileDisk)?;
-    Disk::new(disk).map_err(|_| VmgsError::FileDisk)
+    let disk = Disk::new(disk).map_err(|_| VmgsError::FileDisk)?;
+    Ok((disk, lock))
+}
+
+fn lock_file(file: &File, exclusive: bool) -> Result<FileLock, VmgsError> {
+    FileLock::try_acquire(file, exclusive).map_err(|err| {
+        if err.kind() == io::ErrorKind::WouldBlock {
+            VmgsError::FileLocked
+        } else {
+            VmgsError::FileDisk
+        }
+    })
 }
 
 async fn do_read(
@@ -120,9 +144,8 @@ async fn do_read(
     file_id: FileId,
     key: Option<&[u8]>,
 ) -> Result<Vec<u8>, VmgsError> {
-    let mut vmgs = Vmgs::open(open_disk(file_path, true)?)
-        .await
-        .map_err(|_| VmgsError::InvalidVmgs)?;
+    let (disk, _lock) = open_disk(file_path, true)?;
+    let mut vmgs = Vmgs::open(disk).await.map_err(|_| VmgsError::InvalidVmgs)?;
 
     let info = vmgs
         .get_file_info(file_id)
@@ -209,9 +232,8 @@ async fn do_write(
     file.read_to_end(&mut buf)
         .map_err(|_| VmgsError::CantReadFile)?;
 
-    let mut vmgs = Vmgs::open(open_disk(file_path, false)?)
-        .await
-        .map_err(|_| VmgsError::InvalidVmgs)?;
+    let (disk, _lock) = open_disk(file_path, false)?;
+    let mut vmgs = Vmgs::open(disk).await.map_err(|_| VmgsError::InvalidVmgs)?;
 
     if let Some(encryption_key) = key {
         vmgs.unlock_with_encryption_key(encryption_key)
@@ -310,6 +332,7 @@ async fn do_create(
         .write(true)
         .open(&file_path)
         .map_err(|_| VmgsError::FileDisk)?;
+    let _lock = lock_file(&file, true)?;
 
     file.set_len(file_size).map_err(|_| VmgsError::FileDisk)?;
 
@@ -363,9 +386,8 @@ async fn do_create(
 }
 
 async fn do_query_size(file_path: &str, file_id: FileId) -> Result<u64, VmgsError> {
-    let vmgs = Vmgs::open(open_disk(file_path, true)?)
-        .await
-        .map_err(|_| VmgsError::InvalidVmgs)?;
+    let (disk, _lock) = open_disk(file_path, true)?;
+    let vmgs = Vmgs::open(disk).await.map_err(|_| VmgsError::InvalidVmgs)?;
 
     let info = vmgs
         .get_file_info(file_id)
@@ -373,3 +395,140 @@ async fn do_query_size(file_path: &str, file_id: FileId) -> Result<u64, VmgsErro
 
     Ok(info.valid_bytes)
 }
+
+/// Checks that `file_id` is one of the attestation files, which are never
+/// encrypted, since they hold the keys to unlock the rest of the VMGS file.
+fn check_attestation_file_id(file_id: FileId) -> Result<(), VmgsError> {
+    match file_id {
+        FileId::KEY_PROTECTOR | FileId::HW_KEY_PROTECTOR => Ok(()),
+        _ => Err(VmgsError::InvalidFileID),
+    }
+}
+
+/// Read an attestation `FileId` (`KEY_PROTECTOR` or `HW_KEY_PROTECTOR`) of a
+/// VMGS file
+///
+/// On input, `in_out_len` is the size of `out_buf` in bytes. On success, or if
+/// `out_buf` is too small, it is set to the size of the file. In the latter
+/// case, `VmgsError::InvalidBufSize` is returned, so this can be called with a
+/// zero length to query the size.
+///
+/// Unlike a separate `query_size_vmgs` and `read_vmgs`, the size and contents
+/// are read under a single lock, so they are consistent with each other.
+///
+/// # Safety
+///
+/// `file_path` must point to a valid null-terminated utf-8 string.
+/// `in_out_len` must be nonnull.
+/// `out_buf` must point to a u8 array of size `*in_out_len`, and may only be
+/// null if `*in_out_len` is zero.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn read_attestation_vmgs(
+    file_path: *const c_char,
+    file_id: FileId,
+    out_buf: *mut u8,
+    in_out_len: *mut u64,
+) -> VmgsError {
+    // SAFETY: all passed pointers are checked to be null-terminated and nonnull before access
+    let (file_path, len) = unsafe {
+        if file_path.is_null() || in_out_len.is_null() {
+            return VmgsError::NullParam;
+        }
+        match CStr::from_ptr(file_path).to_str() {
+            Ok(res) => (res, *in_out_len),
+            Err(_res) => return VmgsError::InvalidString,
+        }
+    };
+
+    if let Err(err) = check_attestation_file_id(file_id) {
+        return err;
+    }
+
+    let data = match block_on(do_read(file_path, file_id, None)) {
+        Ok(value) => value,
+        Err(value) => return value,
+    };
+
+    // SAFETY: `in_out_len` is not null
+    unsafe { *in_out_len = data.len() as u64 }
+    if (len as usize) < data.len() {
+        return VmgsError::InvalidBufSize;
+    }
+
+    if !data.is_empty() {
+        // SAFETY: `out_buf` is a pointer to a u8 array of size `len`, which is
+        // at least `data.len()`
+        let buf = unsafe {
+            if out_buf.is_null() {
+                return VmgsError::NullParam;
+            }
+            slice::from_raw_parts_mut(out_buf, data.len())
+        };
+        buf.copy_from_slice(&data);
+    }
+
+    VmgsError::Ok
+}
+
+/// Write an attestation `FileId` (`KEY_PROTECTOR` or `HW_KEY_PROTECTOR`) of a
+/// VMGS file from a buffer
+///
+/// The file is written unencrypted, even if the VMGS file is encrypted, so no
+/// encryption key is needed.
+///
+/// # Safety
+///
+/// `file_path` must point to a valid null-terminated utf-8 string.
+/// `in_buf` must point to a u8 array of size `in_len`, and may only be null if
+/// `in_len` is zero.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn write_attestation_vmgs(
+    file_path: *const c_char,
+    file_id: FileId,
+    in_buf: *const u8,
+    in_len: u64,
+) -> VmgsError {
+    // SAFETY: all passed pointers are checked to be null-terminated and nonnull before access
+    let file_path = unsafe {
+        if file_path.is_null() {
+            return VmgsError::NullParam;
+        }
+        match CStr::from_ptr(file_path).to_str() {
+            Ok(res) => res,
+            Err(_res) => return VmgsError::InvalidString,
+        }
+    };
+
+    let buf: &[u8] = if in_len == 0 {
+        &[]
+    } else {
+        if in_buf.is_null() {
+            return VmgsError::NullParam;
+        }
+        // SAFETY: `in_buf` is a pointer to a u8 array of size `in_len`
+        unsafe { slice::from_raw_parts(in_buf, in_len as usize) }
+    };
+
+    if let Err(err) = check_attestation_file_id(file_id) {
+        return err;
+    }
+
+    match block_on(do_write_attestation(file_path, file_id, buf)) {
+        Ok(_) => VmgsError::Ok,
+        Err(ret) => ret,
+    }
+}
+
+async fn do_write_attestation(
+    file_path: &str,
+    file_id: FileId,
+    buf: &[u8],
+) -> Result<(), VmgsError> {
+    let (disk, _lock) = open_disk(file_path, false)?;
+    let mut vmgs = Vmgs::open(disk).await.map_err(|_| VmgsError::InvalidVmgs)?;
+
+    vmgs.write_file(file_id, buf)
+        .await
+        .map_err(|_| VmgsError::WriteFailed)?;
+    Ok(())
+}
@@ -0,0 +1,126 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Advisory locks on VMGS files, so that concurrent callers of this library
+//! never observe or make interleaved updates.
+
+use std::fs::File;
+use std::io;
+
+/// A held lock on a VMGS file, released on drop.
+pub(crate) struct FileLock(File);
+
+impl FileLock {
+    /// Acquires a shared (`exclusive == false`) or exclusive lock on `file`,
+    /// without waiting. Fails with [`io::ErrorKind::WouldBlock`] if a
+    /// conflicting lock is held.
+    pub fn try_acquire(file: &File, exclusive: bool) -> io::Result<Self> {
+        // Lock through a separate handle, since `file` is handed off to the
+        // disk.
+        let file = file.try_clone()?;
+        sys::try_lock(&file, exclusive)?;
+        Ok(Self(file))
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Closing the handle releases the lock anyway.
+        let _ = sys::unlock(&self.0);
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::prelude::*;
+
+    fn flock(file: &File, op: i32) -> io::Result<()> {
+        loop {
+            // SAFETY: flock has no memory safety requirements.
+            if unsafe { libc::flock(file.as_raw_fd(), op) } == 0 {
+                return Ok(());
+            }
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+        }
+    }
+
+    pub fn try_lock(file: &File, exclusive: bool) -> io::Result<()> {
+        let op = if exclusive {
+            libc::LOCK_EX
+        } else {
+            libc::LOCK_SH
+        };
+        flock(file, op | libc::LOCK_NB)
+    }
+
+    pub fn unlock(file: &File) -> io::Result<()> {
+        flock(file, libc::LOCK_UN)
+    }
+}
+
+#[cfg(windows)]
+mod sys {
+    use std::fs::File;
+    use std::io;
+    use std::os::windows::prelude::*;
+    use windows_sys::Win32::Foundation::ERROR_LOCK_VIOLATION;
+    use windows_sys::Win32::Storage::FileSystem::LockFileEx;
+    use windows_sys::Win32::Storage::FileSystem::UnlockFileEx;
+    use windows_sys::Win32::Storage::FileSystem::LOCKFILE_EXCLUSIVE_LOCK;
+    use windows_sys::Win32::Storage::FileSystem::LOCKFILE_FAIL_IMMEDIATELY;
+    use windows_sys::Win32::System::IO::OVERLAPPED;
+    use windows_sys::Win32::System::IO::OVERLAPPED_0;
+    use windows_sys::Win32::System::IO::OVERLAPPED_0_0;
+
+    /// Byte range locks on Windows are mandatory, so lock a byte far past the
+    /// end of any VMGS file rather than the file contents.
+    const LOCK_OFFSET_HIGH: u32 = u32::MAX;
+
+    fn overlapped() -> OVERLAPPED {
+        // SAFETY: OVERLAPPED is a plain C struct, for which all zeroes is a
+        // valid value.
+        let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+        overlapped.Anonymous = OVERLAPPED_0 {
+            Anonymous: OVERLAPPED_0_0 {
+                Offset: 0,
+                OffsetHigh: LOCK_OFFSET_HIGH,
+            },
+        };
+        overlapped
+    }
+
+    pub fn try_lock(file: &File, exclusive: bool) -> io::Result<()> {
+        let mut flags = LOCKFILE_FAIL_IMMEDIATELY;
+        if exclusive {
+            flags |= LOCKFILE_EXCLUSIVE_LOCK;
+        }
+        let mut overlapped = overlapped();
+        // SAFETY: the handle is valid, and the file is opened for synchronous
+        // IO, so the call completes before `overlapped` goes out of scope.
+        let r = unsafe { LockFileEx(file.as_raw_handle() as _, flags, 0, 1, 0, &mut overlapped) };
+        if r == 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() == Some(ERROR_LOCK_VIOLATION as i32) {
+                return Err(io::ErrorKind::WouldBlock.into());
+            }
+            return Err(err);
+        }
+        Ok(())
+    }
+
+    pub fn unlock(file: &File) -> io::Result<()> {
+        let mut overlapped = overlapped();
+        // SAFETY: the handle is valid, and the file is opened for synchronous
+        // IO, so the call completes before `overlapped` goes out of scope.
+        let r = unsafe { UnlockFileEx(file.as_raw_handle() as _, 0, 1, 0, &mut overlapped) };
+        if r == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
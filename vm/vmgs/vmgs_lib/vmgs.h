@@ -26,6 +26,7 @@ enum VmgsError
     VmgsEncryptionFailed = 12,
     VmgsWriteFailed = 13,
     VmgsFileExists = 14,
+    VmgsFileLocked = 15,
 };
 
 enum FileId
@@ -40,8 +41,15 @@ enum FileId
     VmUniqueId = 7,
     GuestFirmware = 8,
     CustomUefi = 9,
+    GuestWatchdog = 10,
+    HwKeyProtector = 11,
+    GuestSecretKey = 13,
 };
 
+// All functions hold an advisory lock on the VMGS file while it is open:
+// shared for reads, exclusive for writes. If a conflicting lock is held by
+// another caller, they fail with VmgsFileLocked instead of waiting.
+
 // Read from file_id of file_path
 //
 // If reading encrypted data, `use_encryption` must be true
@@ -95,6 +103,34 @@ enum VmgsError query_size_vmgs(
     enum FileId file_id,
     int64_t *out_size);
 
+// Read an attestation file (KeyProtector or HwKeyProtector) of `file_path`
+//
+// On input, `in_out_len` is the size of `out_buf`. On success, or if `out_buf`
+// is too small, it is set to the size of the file. In the latter case,
+// VmgsInvalidBufSize is returned, so call with `*in_out_len` zero to query the
+// size. The size and contents are read under a single lock.
+//
+// `file_path` must point to a valid null-terminated utf-8 string
+// `out_buf` may only be NULL if `*in_out_len` is zero
+enum VmgsError read_attestation_vmgs(
+    const char *file_path,
+    enum FileId file_id,
+    uint8_t *out_buf,
+    uint64_t *in_out_len);
+
+// Write an attestation file (KeyProtector or HwKeyProtector) of `file_path`
+// from `in_buf`
+//
+// Attestation files are never encrypted, so no encryption key is needed.
+//
+// `file_path` must point to a valid null-terminated utf-8 string
+// `in_buf` may only be NULL if `in_len` is zero
+enum VmgsError write_attestation_vmgs(
+    const char *file_path,
+    enum FileId file_id,
+    const uint8_t *in_buf,
+    uint64_t in_len);
+
 #ifdef __cplusplus
 }
 #endif
@@ -17,6 +17,7 @@
 use std::path::PathBuf;
 use thiserror::Error;
 use uefi_nvram::UefiNvramOperation;
+use vmgs::vmgs_helpers::check;
 use vmgs::vmgs_helpers::get_active_header;
 use vmgs::vmgs_helpers::read_headers;
 use vmgs::vmgs_helpers::validate_header;
@@ -28,6 +29,8 @@
 use vmgs_format::VMGS_BYTES_PER_BLOCK;
 use vmgs_format::VMGS_DEFAULT_CAPACITY;
 use vmgs_format::VMGS_ENCRYPTION_KEY_SIZE;
+use vmgs_format::VMGS_VERSION_3_0;
+use vmgs_format::VMGS_VERSION_4_0;
 
 const ONE_MEGA_BYTE: u64 = 1024 * 1024;
 const ONE_GIGA_BYTE: u64 = ONE_MEGA_BYTE * 1024;
@@ -81,6 +84,8 @@ enum Error {
     Json(String),
     #[error("File ID {0:?} already exists. Use `--allow-overwrite` to ignore.")]
     FileIdExists(FileId),
+    #[error("The VMGS file is inconsistent")]
+    Inconsistent,
 }
 
 /// Automation requires certain exit codes to be guaranteed
@@ -153,6 +158,12 @@ enum Options {
         /// this flag allows an existing file to be overwritten.
         #[clap(long, alias = "forcecreate")]
         force_create: bool,
+        /// Create the file in the VMGS 4.0 format, which checksums the
+        /// contents of each file so that torn or lost writes are detected.
+        ///
+        /// VMGS 4.0 files cannot be opened by older VMGS implementations.
+        #[clap(long)]
+        checksums: bool,
     },
     /// Write data into the specified file ID of the VMGS file.
     ///
@@ -218,6 +229,22 @@ enum Options {
         #[clap(short = 'e', long, alias = "encryptionalgorithm", value_parser = parse_encryption_algorithm)]
         encryption_algorithm: EncryptionAlgorithm,
     },
+    /// Rotate the encryption key, re-wrapping the existing data encryption
+    /// key with a new provided key
+    ///
+    /// Unlike `update-key`, the file is updated in a single step, and the
+    /// current key can no longer be used to open the file afterwards. Both key
+    /// files must contain a key that is 32 bytes long.
+    Rekey {
+        #[command(flatten)]
+        file_path: FilePathArg,
+        /// Current encryption key file path.
+        #[clap(short = 'k', long, alias = "keypath")]
+        key_path: PathBuf,
+        /// New encryption key file path.
+        #[clap(short = 'n', long, alias = "newkeypath")]
+        new_key_path: PathBuf,
+    },
     /// Encrypt an existing VMGS file
     Encrypt {
         #[command(flatten)]
@@ -234,6 +261,33 @@ enum Options {
         #[command(flatten)]
         file_path: FilePathArg,
     },
+    /// Compact the VMGS file, moving files toward the start of the file so
+    /// that the free space left behind by earlier writes is merged at the end.
+    ///
+    /// The VMGS file remains valid if the operation is interrupted.
+    Compact {
+        #[command(flatten)]
+        file_path: FilePathArg,
+        /// Shrink the VMGS file to this size after compacting. Must be large
+        /// enough to hold the compacted contents, with room for subsequent
+        /// writes.
+        #[clap(short = 's', long, alias = "filesize")]
+        file_size: Option<u64>,
+    },
+    /// Check the consistency of the VMGS file's headers, file tables and file
+    /// extents, reporting any problems found.
+    ///
+    /// No encryption key is needed, since encrypted files are not decrypted.
+    #[clap(alias = "fsck")]
+    Check {
+        #[command(flatten)]
+        file_path: FilePathArg,
+        /// Repair the file if possible. If the latest header is inconsistent
+        /// but the previous one is not, the latest header is invalidated,
+        /// rolling the file back to its state before the last write.
+        #[clap(long)]
+        repair: bool,
+    },
     /// UEFI NVRAM operations
     UefiNvram {
         #[clap(subcommand)]
@@ -353,6 +407,7 @@ async fn do_main() -> Result<(), Error> {
             key_path,
             encryption_algorithm,
             force_create,
+            checksums,
         } => {
             let encryption_alg_key = encryption_algorithm.map(|x| (x, key_path.unwrap()));
             vmgs_file_create(
@@ -360,6 +415,7 @@ async fn do_main() -> Result<(), Error> {
                 file_size,
                 force_create,
                 encryption_alg_key,
+                checksums,
             )
             .await
         }
@@ -413,6 +469,11 @@ async fn do_main() -> Result<(), Error> {
             )
             .await
         }
+        Options::Rekey {
+            file_path,
+            key_path,
+            new_key_path,
+        } => vmgs_file_rekey(file_path.file_path, key_path, new_key_path).await,
         Options::Encrypt {
             file_path,
             key_path,
@@ -429,6 +490,11 @@ async fn do_main() -> Result<(), Error> {
         Options::QueryEncryption { file_path } => {
             vmgs_file_query_encryption(file_path.file_path).await
         }
+        Options::Compact {
+            file_path,
+            file_size,
+        } => vmgs_file_compact(file_path.file_path, file_size).await,
+        Options::Check { file_path, repair } => vmgs_file_check(file_path.file_path, repair).await,
         Options::UefiNvram { operation } => uefi_nvram::do_command(operation).await,
     }
 }
@@ -467,11 +533,37 @@ async fn vmgs_update_key(
     }
 }
 
+async fn vmgs_file_rekey(
+    file_path: impl AsRef<Path>,
+    key_path: impl AsRef<Path>,
+    new_key_path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let new_encryption_key = read_key_path(new_key_path)?;
+    let mut vmgs = vmgs_file_open(file_path, Some(key_path), OpenMode::ReadWrite, false).await?;
+
+    vmgs_rekey(&mut vmgs, new_encryption_key.as_ref()).await
+}
+
+#[cfg_attr(not(with_encryption), allow(unused_variables))]
+async fn vmgs_rekey(vmgs: &mut Vmgs, new_encryption_key: &[u8]) -> Result<(), Error> {
+    #[cfg(not(with_encryption))]
+    unreachable!("encryption requires the encryption feature");
+    #[cfg(with_encryption)]
+    {
+        vmgs.rekey(new_encryption_key)
+            .await
+            .map_err(Error::EncryptionKey)?;
+
+        Ok(())
+    }
+}
+
 async fn vmgs_file_create(
     path: impl AsRef<Path>,
     file_size: Option<u64>,
     force_create: bool,
     encryption_alg_key: Option<(EncryptionAlgorithm, impl AsRef<Path>)>,
+    checksums: bool,
 ) -> Result<(), Error> {
     let disk = vhdfiledisk_create(path, file_size, force_create)?;
 
@@ -482,7 +574,7 @@ async fn vmgs_file_create(
     let encryption_alg_key =
         encryption_alg_key.map(|(alg, _)| (alg, encryption_key.as_deref().unwrap()));
 
-    let _ = vmgs_create(disk, encryption_alg_key).await?;
+    let _ = vmgs_create(disk, encryption_alg_key, checksums).await?;
 
     println!("Done!");
     Ok(())
@@ -551,8 +643,14 @@ fn vhdfiledisk_create(
 async fn vmgs_create(
     disk: Disk,
     encryption_alg_key: Option<(EncryptionAlgorithm, &[u8])>,
+    checksums: bool,
 ) -> Result<Vmgs, Error> {
-    let mut vmgs = Vmgs::format_new(disk).await?;
+    let version = if checksums {
+        VMGS_VERSION_4_0
+    } else {
+        VMGS_VERSION_3_0
+    };
+    let mut vmgs = Vmgs::format_new_with_version(disk, version).await?;
 
     if let Some((algorithm, encryption_key)) = encryption_alg_key {
         #[cfg(with_encryption)]
@@ -841,11 +939,11 @@ fn vmgs_dump_headers(header1: &VmgsHeader, header2: &VmgsHeader) -> Result<(), E
         "EncryptionKey:", key1_encryption_key, key2_encryption_key
     );
 
-    let key1_reserved1 = format!("0x{:#010x}", header1.reserved_1);
-    let key2_reserved1 = format!("0x{:#010x}", header2.reserved_1);
+    let file_table_checksum1 = format!("0x{:#010x}", header1.file_table_checksum);
+    let file_table_checksum2 = format!("0x{:#010x}", header2.file_table_checksum);
     println!(
         "{0:<23} {1:>70} {2:>70}",
-        "Reserved:", key1_reserved1, key2_reserved1
+        "FileTableChecksum:", file_table_checksum1, file_table_checksum2
     );
 
     println!("{} {} {}\n", "-".repeat(23), "-".repeat(70), "-".repeat(70));
@@ -879,6 +977,54 @@ fn vmgs_dump_headers(header1: &VmgsHeader, header2: &VmgsHeader) -> Result<(), E
     Ok(())
 }
 
+async fn vmgs_file_check(file_path: impl AsRef<Path>, repair: bool) -> Result<(), Error> {
+    let file = fs_err::OpenOptions::new()
+        .read(true)
+        .write(repair)
+        .open(file_path.as_ref())
+        .map_err(Error::VmgsFile)?;
+    vmgs_file_validate(&file)?;
+    let disk = Disk::new(Vhd1Disk::open_fixed(file.into(), !repair).map_err(Error::Vhd1)?)
+        .map_err(Error::InvalidDisk)?;
+
+    let report = check(disk, repair).await?;
+
+    for (index, header) in report.headers.iter().enumerate() {
+        let status = if header.is_consistent() {
+            "[CONSISTENT]"
+        } else if header.valid {
+            "[INCONSISTENT]"
+        } else {
+            "[INVALID]"
+        };
+        println!(
+            "Header {} (sequence {}): {}",
+            index + 1,
+            header.sequence,
+            status
+        );
+        for issue in &header.issues {
+            println!("    {}", issue);
+        }
+    }
+
+    match &report.active_header {
+        Ok(index) => println!("Active header: {}", index + 1),
+        Err(err) => println!("No active header: {}", err),
+    }
+
+    if let Some(index) = report.repaired_header {
+        println!("Repaired: invalidated header {}", index + 1);
+    }
+
+    if !report.is_consistent() {
+        return Err(Error::Inconsistent);
+    }
+
+    println!("No problems found");
+    Ok(())
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 enum OpenMode {
     ReadOnly,
@@ -1009,6 +1155,56 @@ async fn vmgs_file_query_encryption(file_path: impl AsRef<Path>) -> Result<(), E
     Ok(())
 }
 
+async fn vmgs_file_compact(
+    file_path: impl AsRef<Path>,
+    file_size: Option<u64>,
+) -> Result<(), Error> {
+    const SECTOR_SIZE: u64 = 512;
+
+    // Files are moved without decrypting them, so no key is needed.
+    let mut vmgs = vmgs_file_open(
+        file_path.as_ref(),
+        None as Option<PathBuf>,
+        OpenMode::ReadWrite,
+        true,
+    )
+    .await?;
+    let info = vmgs.compact().await?;
+    drop(vmgs);
+
+    println!(
+        "Moved {} files, {} bytes in use",
+        info.files_moved, info.used_bytes
+    );
+
+    if let Some(file_size) = file_size {
+        if file_size < info.used_bytes || file_size % SECTOR_SIZE != 0 {
+            return Err(Error::InvalidVmgsFileSize(
+                file_size,
+                format!(
+                    "Must be a multiple of {} and at least {}",
+                    SECTOR_SIZE, info.used_bytes
+                ),
+            ));
+        }
+
+        println!(
+            "Shrinking file {} to file size {}...",
+            file_path.as_ref().display(),
+            file_size
+        );
+        let file = fs_err::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(file_path.as_ref())
+            .map_err(Error::VmgsFile)?;
+        Vhd1Disk::shrink_fixed(file.file(), file_size).map_err(Error::Vhd1)?;
+    }
+
+    println!("Done!");
+    Ok(())
+}
+
 fn vmgs_file_validate(file: &File) -> Result<(), Error> {
     vmgs_file_validate_not_empty(file)?;
     vmgs_file_validate_not_v1(file)?;
@@ -1105,7 +1301,7 @@ async fn test_vmgs_create(
         encryption_alg_key: Option<(EncryptionAlgorithm, &[u8])>,
     ) -> Result<(), Error> {
         let disk = vhdfiledisk_create(path, file_size, force_create)?;
-        let _ = vmgs_create(disk, encryption_alg_key).await?;
+        let _ = vmgs_create(disk, encryption_alg_key, false).await?;
         Ok(())
     }
 
@@ -1211,6 +1407,46 @@ async fn read_write_file() {
         assert_eq!(buf, read_buf);
     }
 
+    #[async_test]
+    async fn read_write_file_checksums() {
+        let (_dir, path) = new_path();
+        let buf = b"Plain text data".to_vec();
+
+        let disk = vhdfiledisk_create(&path, None, false).unwrap();
+        let _ = vmgs_create(disk, None, true).await.unwrap();
+
+        let mut vmgs = test_vmgs_open(&path, OpenMode::ReadWrite, None, false)
+            .await
+            .unwrap();
+        vmgs_write(&mut vmgs, FileId::ATTEST, &buf, false, false)
+            .await
+            .unwrap();
+        drop(vmgs);
+
+        let mut vmgs = test_vmgs_open(&path, OpenMode::ReadOnly, None, false)
+            .await
+            .unwrap();
+        let read_buf = vmgs_read(&mut vmgs, FileId::ATTEST, false).await.unwrap();
+
+        assert_eq!(buf, read_buf);
+    }
+
+    #[async_test]
+    async fn check_file() {
+        let (_dir, path) = new_path();
+
+        test_vmgs_create(&path, None, false, None).await.unwrap();
+        let mut vmgs = test_vmgs_open(&path, OpenMode::ReadWrite, None, false)
+            .await
+            .unwrap();
+        vmgs_write(&mut vmgs, FileId::ATTEST, b"Plain text data", false, false)
+            .await
+            .unwrap();
+        drop(vmgs);
+
+        vmgs_file_check(&path, false).await.unwrap();
+    }
+
     #[async_test]
     async fn multiple_write_file() {
         let (_dir, path) = new_path();
@@ -1254,6 +1490,45 @@ async fn multiple_write_file() {
         assert_eq!(buf_3, read_buf_3);
     }
 
+    #[async_test]
+    async fn compact_file() {
+        let (_dir, path) = new_path();
+        let buf_1 = vec![1; 64 * 1024];
+        let buf_2 = b"Other super secret data".to_vec();
+
+        test_vmgs_create(&path, None, false, None).await.unwrap();
+
+        let mut vmgs = test_vmgs_open(&path, OpenMode::ReadWrite, None, false)
+            .await
+            .unwrap();
+        vmgs_write(&mut vmgs, FileId::BIOS_NVRAM, &buf_1, false, false)
+            .await
+            .unwrap();
+        vmgs_write(&mut vmgs, FileId::TPM_PPI, &buf_2, false, false)
+            .await
+            .unwrap();
+        vmgs_write(&mut vmgs, FileId::BIOS_NVRAM, &buf_2, false, true)
+            .await
+            .unwrap();
+        drop(vmgs);
+
+        // Too small to hold the contents.
+        let result = vmgs_file_compact(&path, Some(4 * VMGS_BYTES_PER_BLOCK as u64)).await;
+        assert!(matches!(result, Err(Error::InvalidVmgsFileSize(..))));
+
+        vmgs_file_compact(&path, Some(ONE_MEGA_BYTE)).await.unwrap();
+
+        let mut vmgs = test_vmgs_open(&path, OpenMode::ReadOnly, None, false)
+            .await
+            .unwrap();
+        let read_buf_1 = vmgs_read(&mut vmgs, FileId::BIOS_NVRAM, false)
+            .await
+            .unwrap();
+        let read_buf_2 = vmgs_read(&mut vmgs, FileId::TPM_PPI, false).await.unwrap();
+        assert_eq!(buf_2, read_buf_1);
+        assert_eq!(buf_2, read_buf_2);
+    }
+
     #[cfg(with_encryption)]
     #[async_test]
     async fn read_write_encrypted_file() {
@@ -1461,6 +1736,52 @@ async fn test_update_encryption_key() {
         assert!(result.is_err());
     }
 
+    #[cfg(with_encryption)]
+    #[async_test]
+    async fn test_rekey() {
+        let (_dir, path) = new_path();
+        let encryption_key = vec![5; 32];
+        let new_encryption_key = vec![6; 32];
+        let buf_1 = b"123".to_vec();
+
+        test_vmgs_create(
+            &path,
+            None,
+            false,
+            Some((EncryptionAlgorithm::AES_GCM, &encryption_key)),
+        )
+        .await
+        .unwrap();
+
+        {
+            let mut vmgs = test_vmgs_open(&path, OpenMode::ReadWrite, Some(&encryption_key), false)
+                .await
+                .unwrap();
+
+            vmgs_write(&mut vmgs, FileId::BIOS_NVRAM, &buf_1, true, false)
+                .await
+                .unwrap();
+
+            vmgs_rekey(&mut vmgs, &new_encryption_key).await.unwrap();
+        }
+
+        {
+            let mut vmgs =
+                test_vmgs_open(&path, OpenMode::ReadOnly, Some(&new_encryption_key), false)
+                    .await
+                    .unwrap();
+
+            let read_buf = vmgs_read(&mut vmgs, FileId::BIOS_NVRAM, true)
+                .await
+                .unwrap();
+            assert!(read_buf == buf_1);
+        }
+
+        // Old key should no longer work
+        let result = test_vmgs_open(&path, OpenMode::ReadOnly, Some(&encryption_key), false).await;
+        assert!(result.is_err());
+    }
+
     #[cfg(with_encryption)]
     #[async_test]
     async fn test_add_encryption_key() {
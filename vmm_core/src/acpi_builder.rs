@@ -173,6 +173,41 @@ fn with_srat<F, R>(&self, f: F) -> R
         ))
     }
 
+    /// Returns whether RAM is split across multiple NUMA nodes, in which case
+    /// the guest needs a SLIT to describe the distances between them.
+    fn has_numa_ram(&self) -> bool {
+        self.mem_layout.ram().iter().any(|range| range.vnode != 0)
+    }
+
+    /// Returns the number of NUMA nodes used by the processors and RAM.
+    fn vnode_count(&self) -> u32 {
+        self.processor_topology
+            .vps()
+            .map(|vp| vp.vnode)
+            .chain(self.mem_layout.ram().iter().map(|range| range.vnode))
+            .max()
+            .map_or(1, |vnode| vnode + 1)
+    }
+
+    fn with_slit<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&acpi::builder::Table<'_>) -> R,
+    {
+        // FUTURE: derive the distances from the host topology.
+        let count = self.vnode_count() as usize;
+        let mut distances = vec![acpi_spec::slit::SLIT_REMOTE_DISTANCE; count * count];
+        for i in 0..count {
+            distances[i * count + i] = acpi_spec::slit::SLIT_LOCAL_DISTANCE;
+        }
+
+        (f)(&acpi::builder::Table::new_dyn(
+            acpi_spec::slit::SLIT_REVISION,
+            None,
+            &acpi_spec::slit::SlitHeader::new(count as u64),
+            &[distances.as_slice()],
+        ))
+    }
+
     fn with_madt<F, R>(&self, f: F) -> R
     where
         F: FnOnce(&acpi::builder::Table<'_>) -> R,
@@ -529,6 +564,9 @@ fn build_acpi_tables_inner(&self, gpa: u64, dsdt: &[u8]) -> BuiltAcpiTables {
 
         self.with_madt(|t| b.append(t));
         self.with_srat(|t| b.append(t));
+        if self.has_numa_ram() {
+            self.with_slit(|t| b.append(t));
+        }
         if self.cache_topology.is_some() {
             self.with_pptt(|t| b.append(t));
         }
@@ -550,6 +588,16 @@ pub fn build_srat(&self) -> Vec<u8> {
         self.with_srat(|t| t.to_vec(&OEM_INFO))
     }
 
+    /// Helper method to construct a SLIT without constructing the rest of the
+    /// ACPI tables.
+    ///
+    /// Returns `None` if all RAM is in a single NUMA node, since no SLIT is
+    /// needed.
+    pub fn build_slit(&self) -> Option<Vec<u8>> {
+        self.has_numa_ram()
+            .then(|| self.with_slit(|t| t.to_vec(&OEM_INFO)))
+    }
+
     /// Helper method to construct a PPTT without constructing the rest of the
     /// ACPI tables.
     ///
@@ -641,4 +689,22 @@ fn test_basic_madt_cpu() {
             apic_ids.iter().map(|e| Some(*e)).collect::<Vec<_>>()
         );
     }
+
+    #[test]
+    fn test_slit() {
+        let mem = new_mem();
+        let topology = TopologyBuilder::new_x86().build(4).unwrap();
+        assert!(new_builder(&mem, &topology).build_slit().is_none());
+
+        let mem = MemoryLayout::new_with_numa(42, &[GB, GB], &MMIO, None).unwrap();
+        let topology = TopologyBuilder::new_x86()
+            .vnodes(vec![0, 0, 1, 1])
+            .build(4)
+            .unwrap();
+        let slit = new_builder(&mem, &topology).build_slit().unwrap();
+        let header_len = size_of::<acpi_spec::Header>();
+        assert_eq!(&slit[..4], b"SLIT");
+        assert_eq!(slit[header_len..header_len + 8], 2u64.to_le_bytes());
+        assert_eq!(slit[header_len + 8..], [10, 20, 20, 10]);
+    }
 }
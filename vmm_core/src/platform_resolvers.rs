@@ -36,3 +36,23 @@ fn resolve(
         .into())
     }
 }
+
+/// Platform resolver for the shared memory backing guest RAM.
+#[cfg(unix)]
+pub struct SharedGuestMemoryResolver(pub shared_memory_resources::SharedGuestMemory);
+
+#[cfg(unix)]
+impl ResolveResource<shared_memory_resources::SharedGuestMemoryHandleKind, PlatformResource>
+    for SharedGuestMemoryResolver
+{
+    type Output = shared_memory_resources::SharedGuestMemory;
+    type Error = Infallible;
+
+    fn resolve(
+        &self,
+        _resource: PlatformResource,
+        _input: (),
+    ) -> Result<Self::Output, Self::Error> {
+        Ok(self.0.clone())
+    }
+}
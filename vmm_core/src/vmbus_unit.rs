@@ -5,6 +5,7 @@
 
 #![warn(missing_docs)]
 
+use guid::Guid;
 use inspect::Inspect;
 use pal_async::task::Spawn;
 use state_unit::run_async_unit;
@@ -137,6 +138,13 @@ pub async fn revoke(self) -> T {
     }
 }
 
+impl ChannelUnit<dyn VmbusDevice> {
+    /// Revokes a channel.
+    pub async fn revoke(self) -> Box<dyn VmbusDevice> {
+        self.0.revoke().await.unwrap()
+    }
+}
+
 impl<T: 'static + VmbusDevice + ?Sized> StateUnit for &'_ ChannelUnit<T> {
     async fn start(&mut self) {
         self.0.start();
@@ -223,18 +231,22 @@ async fn restore(&mut self, state: SavedStateBlob) -> Result<(), RestoreError> {
 }
 
 /// Offers a channel, creates a unit for it, and adds it to `state_units`.
+///
+/// Returns the channel's instance ID along with the unit, so that the device
+/// can be found again to remove it.
 pub async fn offer_vmbus_device_handle_unit(
     driver_source: &VmTaskDriverSource,
     state_units: &StateUnits,
     vmbus: &VmbusServerHandle,
     resolver: &ResourceResolver,
     resource: Resource<VmbusDeviceHandleKind>,
-) -> anyhow::Result<SpawnedUnit<ChannelUnit<dyn VmbusDevice>>> {
+) -> anyhow::Result<(Guid, SpawnedUnit<ChannelUnit<dyn VmbusDevice>>)> {
     let channel = resolver
         .resolve(resource, ResolveVmbusDeviceHandleParams { driver_source })
         .await?;
     let offer = channel.0.offer();
-    let name = format!("{}:{}", offer.interface_name, offer.instance_id);
+    let instance_id = offer.instance_id;
+    let name = format!("{}:{}", offer.interface_name, instance_id);
     let handle =
         offer_generic_channel(&driver_source.simple(), vmbus.control.as_ref(), channel.0).await?;
     let unit = state_units
@@ -243,5 +255,5 @@ pub async fn offer_vmbus_device_handle_unit(
         .spawn(driver_source.simple(), |recv| {
             run_async_unit(ChannelUnit(handle), recv)
         })?;
-    Ok(unit)
+    Ok((instance_id, unit))
 }
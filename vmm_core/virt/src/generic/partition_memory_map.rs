@@ -43,6 +43,36 @@ fn pin_range(&self, _addr: u64, _size: u64) -> Result<(), anyhow::Error> {
         Ok(())
     }
 
+    /// Enables or disables dirty page tracking for the ranges mapped in the
+    /// given guest physical address range.
+    ///
+    /// As with `unmap_range`, any overlapped ranges must be completely
+    /// contained in the specified range. Ranges mapped later start with
+    /// tracking disabled.
+    fn set_dirty_tracking(
+        &self,
+        _addr: u64,
+        _size: u64,
+        _enable: bool,
+    ) -> Result<(), anyhow::Error> {
+        Err(anyhow::anyhow!("dirty page tracking not supported"))
+    }
+
+    /// Fetches and clears the dirty page state for the ranges mapped in the
+    /// given guest physical address range, which must have tracking enabled.
+    ///
+    /// For each 4KB page written since the last call, sets the corresponding
+    /// bit in `bitmap`, where bit 0 is the page at `addr`. Other bits are left
+    /// unchanged.
+    fn get_and_clear_dirty_bitmap(
+        &self,
+        _addr: u64,
+        _size: u64,
+        _bitmap: &mut [u64],
+    ) -> Result<(), anyhow::Error> {
+        Err(anyhow::anyhow!("dirty page tracking not supported"))
+    }
+
     /// Maps a range residing in a remote process.
     ///
     /// This may fail if the range overlaps any other mapped range.
@@ -52,6 +52,8 @@ pub enum KvmError {
 struct KvmMemoryRange {
     host_addr: *mut u8,
     range: MemoryRange,
+    readonly: bool,
+    log_dirty: bool,
 }
 
 unsafe impl Sync for KvmMemoryRange {}
@@ -160,12 +162,20 @@ unsafe fn map_region(
         }
         let slot_to_use = slot_to_use.unwrap();
         unsafe {
-            self.kvm
-                .set_user_memory_region(slot_to_use as u32, data, size, addr, readonly)?
+            self.kvm.set_user_memory_region(
+                slot_to_use as u32,
+                data,
+                size,
+                addr,
+                readonly,
+                false,
+            )?
         };
         state.ranges[slot_to_use] = Some(KvmMemoryRange {
             host_addr: data,
             range: MemoryRange::new(addr..addr + size as u64),
+            readonly,
+            log_dirty: false,
         });
         Ok(())
     }
@@ -207,6 +217,7 @@ fn unmap_range(&self, addr: u64, size: u64) -> Result<(), virt::Error> {
                         0,
                         0,
                         false,
+                        false,
                     )?;
                 }
                 *entry = None;
@@ -219,4 +230,73 @@ fn unmap_range(&self, addr: u64, size: u64) -> Result<(), virt::Error> {
         }
         Ok(())
     }
+
+    fn set_dirty_tracking(&self, addr: u64, size: u64, enable: bool) -> Result<(), virt::Error> {
+        let range = MemoryRange::new(addr..addr + size);
+        let mut state = self.memory.lock();
+        for (slot, entry) in state.ranges.iter_mut().enumerate() {
+            let Some(kvm_range) = entry else { continue };
+            if range.contains(&kvm_range.range) {
+                // SAFETY: updating the flags of an existing slot does not change
+                // its memory references.
+                unsafe {
+                    self.kvm.set_user_memory_region(
+                        slot as u32,
+                        kvm_range.host_addr,
+                        kvm_range.range.len() as usize,
+                        kvm_range.range.start(),
+                        kvm_range.readonly,
+                        enable,
+                    )?;
+                }
+                kvm_range.log_dirty = enable;
+            } else {
+                assert!(
+                    !range.overlaps(&kvm_range.range),
+                    "can only track existing ranges of exact size"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn get_and_clear_dirty_bitmap(
+        &self,
+        addr: u64,
+        size: u64,
+        bitmap: &mut [u64],
+    ) -> Result<(), virt::Error> {
+        const PAGE_SIZE: u64 = 4096;
+        assert!(bitmap.len() as u64 * 64 >= size / PAGE_SIZE);
+        let range = MemoryRange::new(addr..addr + size);
+        let state = self.memory.lock();
+        for (slot, entry) in state.ranges.iter().enumerate() {
+            let Some(kvm_range) = entry else { continue };
+            if !range.contains(&kvm_range.range) {
+                assert!(
+                    !range.overlaps(&kvm_range.range),
+                    "can only track existing ranges of exact size"
+                );
+                continue;
+            }
+            if !kvm_range.log_dirty {
+                return Err(virt::Error::msg(format!(
+                    "dirty page tracking not enabled for {}",
+                    kvm_range.range
+                )));
+            }
+            let pages = kvm_range.range.len() / PAGE_SIZE;
+            let mut slot_bitmap = vec![0u64; pages.div_ceil(64) as usize];
+            // SAFETY: the bitmap has a bit for each page in the slot.
+            unsafe { self.kvm.get_dirty_log(slot as u32, &mut slot_bitmap)? };
+            let first = (kvm_range.range.start() - addr) / PAGE_SIZE;
+            for page in 0..pages {
+                if slot_bitmap[(page / 64) as usize] & (1 << (page % 64)) != 0 {
+                    let bit = first + page;
+                    bitmap[(bit / 64) as usize] |= 1 << (bit % 64);
+                }
+            }
+        }
+        Ok(())
+    }
 }
@@ -31,6 +31,7 @@ fn resolve(&self, id: ErasedArtifactHandle) -> anyhow::Result<PathBuf> {
             _ if id == common::PIPETTE_LINUX_X64 => pipette_path(MachineArch::X86_64, PipetteFlavor::Linux),
             _ if id == common::PIPETTE_WINDOWS_AARCH64 => pipette_path(MachineArch::Aarch64, PipetteFlavor::Windows),
             _ if id == common::PIPETTE_LINUX_AARCH64 => pipette_path(MachineArch::Aarch64, PipetteFlavor::Linux),
+            _ if id == common::PIPETTE_FREEBSD_X64 => pipette_path(MachineArch::X86_64, PipetteFlavor::FreeBsd),
 
             _ if id == common::TEST_LOG_DIRECTORY => test_log_directory_path(),
 
@@ -73,6 +74,7 @@ fn resolve(&self, id: ErasedArtifactHandle) -> anyhow::Result<PathBuf> {
 enum PipetteFlavor {
     Windows,
     Linux,
+    FreeBsd,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
@@ -159,6 +161,7 @@ fn pipette_path(arch: MachineArch, os_flavor: PipetteFlavor) -> anyhow::Result<P
     let (target_suffixes, binary) = match os_flavor {
         PipetteFlavor::Windows => (vec!["pc-windows-msvc", "pc-windows-gnu"], "pipette.exe"),
         PipetteFlavor::Linux => (vec!["unknown-linux-musl"], "pipette"),
+        PipetteFlavor::FreeBsd => (vec!["unknown-freebsd"], "pipette"),
     };
     for (index, target_suffix) in target_suffixes.iter().enumerate() {
         let target = format!("{}-{}", target_arch_path(arch), target_suffix);
@@ -214,6 +214,8 @@ fn maybe_pipette(
                 ::petri_artifacts_common::artifacts::PIPETTE_WINDOWS_AARCH64
             ));
         }
+        // The FreeBSD pipette isn't built by CI, so tests that use it require
+        // it explicitly.
         _ => {}
     }
     deps
@@ -464,15 +466,10 @@ macro_rules! image_info {
     }
 
     match &*word.to_string() {
-        "freebsd_13_2_x64" => match generation {
-            Generation::Gen1 => Ok(image_info!(
-                ::petri_artifacts_vmm_test::artifacts::test_vhd::FREE_BSD_13_2_X64
-            )),
-            Generation::Gen2 => Err(Error::new(
-                word.span(),
-                "FreeBSD 13.2 is not available for UEFI",
-            )),
-        },
+        // The VHD is partitioned to boot with either BIOS or UEFI.
+        "freebsd_13_2_x64" => Ok(image_info!(
+            ::petri_artifacts_vmm_test::artifacts::test_vhd::FREE_BSD_13_2_X64
+        )),
         "windows_datacenter_core_2022_x64" => match generation {
             Generation::Gen1 => Ok(image_info!(
                 ::petri_artifacts_vmm_test::artifacts::test_vhd::GEN1_WINDOWS_DATA_CENTER_CORE2022_X64
@@ -613,7 +610,7 @@ fn parse_extra_deps(input: ParseStream<'_>) -> syn::Result<Vec<Path>> {
 /// Valid VHD options are:
 /// - `ubuntu_2204_server_x64`: Canonical's provided Ubuntu Linux 22.04 cloudimg disk image
 /// - `windows_datacenter_core_2022_x64`: Our provided Windows Datacenter Core 2022 VHD
-/// - `freebsd_13_2_x64`: The FreeBSD Project's provided FreeBSD 13.2 VHD
+/// - `freebsd_13_2_x64`: The FreeBSD Project's provided FreeBSD 13.2 VHD (PCAT or UEFI)
 ///
 /// Valid x64 ISO options are:
 /// - `freebsd_13_2_x64`: The FreeBSD Project's provided FreeBSD 13.2 installer ISO
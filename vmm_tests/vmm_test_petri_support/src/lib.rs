@@ -74,7 +74,12 @@ fn require_hvlite_standard(self, pipette_flavor: Option<(MachineArch, OsFlavor)>
                 (MachineArch::Aarch64, OsFlavor::Linux) => {
                     petri_artifacts_common::artifacts::PIPETTE_LINUX_AARCH64.erase()
                 }
-                (_, OsFlavor::FreeBsd) => panic!("pipette not supported on FreeBSD guests"),
+                (MachineArch::X86_64, OsFlavor::FreeBsd) => {
+                    petri_artifacts_common::artifacts::PIPETTE_FREEBSD_X64.erase()
+                }
+                (MachineArch::Aarch64, OsFlavor::FreeBsd) => {
+                    panic!("pipette not supported on FreeBSD aarch64 guests")
+                }
                 (_, OsFlavor::Uefi) => panic!("pipette not supported on UEFI guests"),
             };
             s.require(artifact)
@@ -3,7 +3,9 @@
 
 //! Integration tests that run on more than one architecture.
 
+use petri::BootBudget;
 use petri::PetriVmConfig;
+use std::time::Duration;
 use vmm_core_defs::HaltReason;
 use vmm_test_macros::vmm_test;
 
@@ -34,3 +36,29 @@ async fn boot(config: PetriVmConfig) -> anyhow::Result<()> {
     assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
     Ok(())
 }
+
+/// Boot test that fails if the boot is unexpectedly slow.
+#[vmm_test(
+    linux_direct_x64,
+    uefi_x64(vhd(ubuntu_2204_server_x64)),
+    uefi_aarch64(vhd(ubuntu_2404_server_aarch64))
+)]
+async fn boot_budget(config: PetriVmConfig) -> anyhow::Result<()> {
+    // Leave plenty of headroom for slow CI machines, so this only catches
+    // large regressions.
+    let (vm, agent) = config
+        .with_boot_budget(BootBudget {
+            uefi: Some(Duration::from_secs(30)),
+            kernel: Some(Duration::from_secs(60)),
+            agent: Some(Duration::from_secs(120)),
+        })
+        .run()
+        .await?;
+    let times = vm.boot_times();
+    assert_eq!(times.len(), 1);
+    assert!(times[0].kernel.is_some());
+    assert!(times[0].agent.is_some());
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+    Ok(())
+}
@@ -3,8 +3,12 @@
 
 //! Integration tests for x86_64 guests.
 
+mod freebsd;
+#[cfg(windows)]
+mod hyperv;
 mod openhcl_linux_direct;
 mod openhcl_uefi;
+mod topology;
 
 use anyhow::Context;
 use petri::pipette::cmd;
@@ -16,7 +20,11 @@
 use vmm_test_macros::vmm_test;
 
 /// Basic boot test with no agent for unsupported guests.
-#[vmm_test(pcat_x64(vhd(freebsd_13_2_x64)), pcat_x64(iso(freebsd_13_2_x64)))]
+#[vmm_test(
+    pcat_x64(vhd(freebsd_13_2_x64)),
+    pcat_x64(iso(freebsd_13_2_x64)),
+    uefi_x64(vhd(freebsd_13_2_x64))
+)]
 async fn boot_no_agent(config: PetriVmConfig) -> anyhow::Result<()> {
     let mut vm = config.run_without_agent().await?;
     vm.wait_for_successful_boot_event().await?;
@@ -110,6 +118,37 @@ async fn reboot(config: PetriVmConfig) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Validate that crashing the guest through pipette resets the VM, and that the
+/// crash dumps it leaves behind can be collected after it restarts.
+// TODO: Add Windows guests, which write MEMORY.DMP, once #523 is fixed.
+#[vmm_test(linux_direct_x64, openhcl_linux_direct_x64)]
+async fn guest_crash(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    let (mut vm, agent) = config.run().await?;
+
+    // There is no crash kernel in the test initrd, so the kernel cannot write
+    // a dump.
+    assert!(agent
+        .configure_crash_dump(petri::pipette::CrashDumpKind::Kernel)
+        .await
+        .is_err());
+
+    agent.crash().await?;
+    assert_eq!(vm.wait_for_halt().await?, HaltReason::Reset);
+    vm.reset().await?;
+
+    let agent = vm.wait_for_agent().await?;
+    let dir = tempfile::tempdir()?;
+    let dumps = vm.collect_crash_dumps(&agent, dir.path()).await?;
+    for dump in &dumps {
+        assert!(dump.exists());
+    }
+
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+
+    Ok(())
+}
+
 /// Basic VBS reboot test.
 #[vmm_test(
     openhcl_uefi_x64[vbs](vhd(windows_datacenter_core_2022_x64)),
@@ -156,6 +195,32 @@ async fn frontpage_tpm(config: PetriVmConfig) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Validate that screenshots of the synthetic video framebuffer can be
+/// captured, saved, and compared against a reference image.
+#[vmm_test(
+    uefi_x64(vhd(ubuntu_2204_server_x64)),
+    uefi_x64(vhd(windows_datacenter_core_2022_x64))
+)]
+async fn screenshot(config: PetriVmConfig) -> anyhow::Result<()> {
+    let (vm, agent) = config.run().await?;
+
+    // The guest has drawn its console or login screen by the time the agent
+    // is running.
+    let screenshot = vm.save_screenshot("booted")?;
+    assert!(!screenshot.is_blank());
+
+    // The screen may change slightly, such as a blinking cursor, so compare
+    // against the first screenshot with a small tolerance.
+    let dir = tempfile::tempdir()?;
+    let reference = dir.path().join("reference.png");
+    screenshot.save(&reference)?;
+    vm.assert_screenshot("booted_again", &reference, 0.02)?;
+
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+    Ok(())
+}
+
 /// Boot our guest-test UEFI image, which will run some tests,
 /// and then purposefully triple fault itself via an expiring
 /// watchdog timer.
@@ -235,6 +300,238 @@ async fn file_transfer_test(config: PetriVmConfig) -> Result<(), anyhow::Error>
     Ok(())
 }
 
+/// Test pushing and pulling a file larger than a transfer chunk.
+#[vmm_test(
+    linux_direct_x64,
+    openhcl_uefi_x64(vhd(windows_datacenter_core_2022_x64)),
+    uefi_x64(vhd(ubuntu_2204_server_x64))
+)]
+async fn file_push_pull(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    const FILE_NAME: &str = "test.bin";
+
+    let (mut vm, agent) = config.run().await?;
+
+    let dir = tempfile::tempdir()?;
+    let contents: Vec<u8> = (0..1024 * 1024 + 123).map(|i| (i % 251) as u8).collect();
+    let host_path = dir.path().join("push.bin");
+    std::fs::write(&host_path, &contents)?;
+
+    vm.copy_to_guest(&agent, &host_path, FILE_NAME).await?;
+    assert_eq!(agent.read_file(FILE_NAME).await?, contents);
+
+    let pulled_path = dir.path().join("pull.bin");
+    vm.copy_from_guest(&agent, FILE_NAME, &pulled_path).await?;
+    assert_eq!(std::fs::read(&pulled_path)?, contents);
+
+    assert!(vm
+        .copy_from_guest(&agent, "does-not-exist.bin", dir.path().join("missing.bin"))
+        .await
+        .is_err());
+
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+
+    Ok(())
+}
+
+/// Test querying structured information about the guest.
+#[vmm_test(
+    linux_direct_x64,
+    openhcl_uefi_x64(vhd(windows_datacenter_core_2022_x64)),
+    uefi_x64(vhd(ubuntu_2204_server_x64))
+)]
+async fn guest_info(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    let os_flavor = config.os_flavor();
+    let (vm, agent) = config.run().await?;
+
+    let info = agent.guest_info().await?;
+    tracing::info!(?info, "guest info");
+    assert!(!info.hostname.is_empty());
+    assert!(!info.os_version.is_empty());
+    assert!(!info.interfaces.is_empty());
+    match os_flavor {
+        OsFlavor::Windows => {
+            assert!(info.os_name.contains("Windows"));
+            assert!(info.kernel_cmdline.is_none());
+            assert!(info.filesystem("C:\\").is_some());
+        }
+        _ => {
+            assert!(info
+                .kernel_cmdline
+                .as_deref()
+                .is_some_and(|c| !c.is_empty()));
+            assert!(info.filesystem("/proc").is_some());
+        }
+    }
+
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+
+    Ok(())
+}
+
+/// Test forwarding TCP connections from the host to the guest and back.
+#[vmm_test(linux_direct_x64, uefi_x64(vhd(ubuntu_2204_server_x64)))]
+async fn tcp_forwarding(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    let (mut vm, agent) = config.run().await?;
+
+    // Run an echo server on the host, and reach it from the host via a
+    // listener in the guest, so that the connection crosses pipette twice.
+    let echo = std::net::TcpListener::bind((std::net::Ipv4Addr::LOCALHOST, 0))?;
+    let echo_address = echo.local_addr()?;
+    std::thread::spawn(move || {
+        for stream in echo.incoming() {
+            let Ok(mut stream) = stream else { break };
+            let _ = std::io::copy(&mut stream.try_clone().unwrap(), &mut stream);
+        }
+    });
+
+    let from_guest = vm
+        .forward_tcp_from_guest(
+            &agent,
+            (std::net::Ipv4Addr::LOCALHOST, 0).into(),
+            echo_address,
+        )
+        .await?;
+    let to_guest = vm.forward_tcp_to_guest(&agent, from_guest.listen_address())?;
+
+    // Use blocking sockets on a separate thread to avoid stalling the
+    // executor that is running the forwarding.
+    let host_address = to_guest.listen_address();
+    let (send, recv) = mesh::oneshot();
+    std::thread::spawn(move || {
+        send.send((|| {
+            use std::io::Read;
+            use std::io::Write;
+            let mut stream = std::net::TcpStream::connect(host_address)?;
+            stream.write_all(b"hello from the host")?;
+            stream.shutdown(std::net::Shutdown::Write)?;
+            let mut echoed = Vec::new();
+            stream.read_to_end(&mut echoed)?;
+            std::io::Result::Ok(echoed)
+        })())
+    });
+    let echoed = recv.await?.context("tcp round trip failed")?;
+    assert_eq!(echoed, b"hello from the host");
+
+    drop(to_guest);
+    drop(from_guest);
+
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+
+    Ok(())
+}
+
+/// Test driving an interactive shell through a pseudo-terminal.
+#[vmm_test(linux_direct_x64, uefi_x64(vhd(ubuntu_2204_server_x64)))]
+async fn pty_exec(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    use futures::AsyncReadExt;
+    use futures::AsyncWriteExt;
+    use petri::pipette::process::Stdio;
+
+    let (vm, agent) = config.run().await?;
+
+    let mut child = agent
+        .command("sh")
+        .pty(24, 80)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .await?;
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+
+    // Reads terminal output until `expected` appears.
+    async fn read_until(stdout: &mut mesh::pipe::ReadPipe, expected: &str) -> anyhow::Result<()> {
+        let mut output = Vec::new();
+        let mut buf = [0; 1024];
+        while !String::from_utf8_lossy(&output).contains(expected) {
+            let n = stdout.read(&mut buf).await?;
+            anyhow::ensure!(n != 0, "terminal closed before {expected:?}");
+            output.extend_from_slice(&buf[..n]);
+        }
+        Ok(())
+    }
+
+    stdin.write_all(b"stty size\n").await?;
+    read_until(&mut stdout, "24 80").await?;
+
+    child.resize(40, 120).await?;
+    stdin.write_all(b"stty size\n").await?;
+    read_until(&mut stdout, "40 120").await?;
+
+    const SIGKILL: i32 = 9;
+    child.signal(SIGKILL).await?;
+    assert_eq!(child.wait().await?.signal(), Some(SIGKILL));
+
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+
+    Ok(())
+}
+
+/// Save and restore the VM while the guest is running, checking that the
+/// guest continues undisturbed.
+#[vmm_test(
+    linux_direct_x64,
+    uefi_x64(vhd(ubuntu_2204_server_x64)),
+    uefi_x64(vhd(windows_datacenter_core_2022_x64))
+)]
+async fn save_restore(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    let (mut vm, agent) = config.run().await?;
+
+    vm.save_restore(&agent, 3).await?;
+
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+
+    Ok(())
+}
+
+/// Inject IO errors into a data disk and check that the guest sees them, and
+/// recovers once they are cleared.
+#[vmm_test(linux_direct_x64)]
+async fn disk_fault_injection(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    const DISK_SIZE: u64 = 64 * 1024 * 1024;
+    let (config, faults) = config.with_fault_disk(DISK_SIZE);
+    let (vm, agent) = config.run().await?;
+
+    // Find the data disk by its size, in 512-byte sectors.
+    let sh = agent.unix_shell();
+    let mut disk = None;
+    for name in cmd!(sh, "ls /sys/block").read().await?.split_whitespace() {
+        let size = sh.read_file(format!("/sys/block/{name}/size")).await?;
+        if size.trim().parse::<u64>().ok() == Some(DISK_SIZE / 512) {
+            disk = Some(format!("/dev/{name}"));
+        }
+    }
+    let disk = disk.context("data disk not found")?;
+
+    cmd!(sh, "dd if=/dev/zero of={disk} bs=4096 count=1 oflag=direct")
+        .run()
+        .await?;
+
+    faults.set(petri::DiskFaults {
+        fail_writes: true,
+        ..Default::default()
+    });
+    let result = cmd!(sh, "dd if=/dev/zero of={disk} bs=4096 count=1 oflag=direct")
+        .run()
+        .await;
+    assert!(result.is_err(), "write succeeded despite injected fault");
+
+    faults.clear();
+    cmd!(sh, "dd if=/dev/zero of={disk} bs=4096 count=1 oflag=direct")
+        .run()
+        .await?;
+
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+
+    Ok(())
+}
+
 /// Boot Linux and have it dump MTRR related output.
 #[vmm_test(linux_direct_x64, openhcl_linux_direct_x64)]
 async fn mtrrs(config: PetriVmConfig) -> Result<(), anyhow::Error> {
@@ -0,0 +1,41 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Integration tests for FreeBSD guests with pipette.
+//!
+//! CI doesn't build pipette for FreeBSD, so these are ignored by default. To
+//! run them, build pipette for `x86_64-unknown-freebsd` first.
+
+use petri::BootImageConfig;
+use petri::Firmware;
+use petri::PetriVmConfig;
+use petri::UefiGuest;
+use petri_artifacts_common::tags::MachineArch;
+use petri_artifacts_vmm_test::artifacts::test_vhd::FREE_BSD_13_2_X64;
+use vmm_core_defs::HaltReason;
+
+/// Basic boot test of a FreeBSD guest on UEFI, with pipette.
+#[pal_async::async_test]
+#[ignore = "requires pipette built for FreeBSD"]
+async fn uefi_x64_freebsd_13_2_x64_boot(driver: pal_async::DefaultDriver) -> anyhow::Result<()> {
+    let resolver = crate::prelude::vmm_tests_artifact_resolver()
+        .require(petri_artifacts_common::artifacts::TEST_LOG_DIRECTORY)
+        .require(petri_artifacts_vmm_test::artifacts::OPENVMM_NATIVE)
+        .require(petri_artifacts_common::artifacts::PIPETTE_FREEBSD_X64)
+        .require(FREE_BSD_13_2_X64)
+        .finalize();
+    let config = PetriVmConfig::new(
+        Firmware::Uefi {
+            guest: UefiGuest::Vhd(BootImageConfig::from_vhd(FREE_BSD_13_2_X64)),
+        },
+        MachineArch::X86_64,
+        resolver,
+        &driver,
+    )?;
+
+    let (vm, agent) = config.run().await?;
+    agent.ping().await?;
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+    Ok(())
+}
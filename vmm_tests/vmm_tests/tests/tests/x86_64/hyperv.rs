@@ -0,0 +1,42 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Integration tests that run x86_64 guests on Hyper-V instead of OpenVMM.
+//!
+//! These require the Hyper-V PowerShell module and administrator privileges,
+//! so they are ignored by default.
+
+use petri::hyperv::PetriVmConfigHyperV;
+use petri::BootImageConfig;
+use petri::Firmware;
+use petri::UefiGuest;
+use petri_artifacts_common::tags::MachineArch;
+use petri_artifacts_vmm_test::artifacts::test_vhd::UBUNTU_2204_SERVER_X64;
+use vmm_core_defs::HaltReason;
+
+/// Basic boot test of a Linux guest on Hyper-V, with pipette.
+#[pal_async::async_test]
+#[ignore = "requires Hyper-V and administrator privileges"]
+async fn hyperv_uefi_x64_ubuntu_2204_server_x64_boot(
+    driver: pal_async::DefaultDriver,
+) -> anyhow::Result<()> {
+    let resolver = crate::prelude::vmm_tests_artifact_resolver()
+        .require(petri_artifacts_common::artifacts::TEST_LOG_DIRECTORY)
+        .require(petri_artifacts_common::artifacts::PIPETTE_LINUX_X64)
+        .require(UBUNTU_2204_SERVER_X64)
+        .finalize();
+    let config = PetriVmConfigHyperV::new(
+        Firmware::Uefi {
+            guest: UefiGuest::Vhd(BootImageConfig::from_vhd(UBUNTU_2204_SERVER_X64)),
+        },
+        MachineArch::X86_64,
+        resolver,
+        &driver,
+    )?;
+
+    let (vm, agent) = config.run().await?;
+    agent.ping().await?;
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+    Ok(())
+}
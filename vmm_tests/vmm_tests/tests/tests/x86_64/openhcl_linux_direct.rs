@@ -16,10 +16,12 @@
 use nvme_resources::NamespaceDefinition;
 use nvme_resources::NvmeControllerHandle;
 use petri::pipette::cmd;
+use petri::pipette::guest_info::InterfaceAddress;
 use petri::PetriVmConfig;
 use scsidisk_resources::SimpleScsiDiskHandle;
 use scsidisk_resources::SimpleScsiDvdHandle;
 use scsidisk_resources::SimpleScsiDvdRequest;
+use std::net::Ipv4Addr;
 use storvsp_resources::ScsiControllerHandle;
 use storvsp_resources::ScsiDeviceAndPath;
 use storvsp_resources::ScsiPath;
@@ -47,9 +49,15 @@ async fn boot_openhcl_linux_mana_nic(config: PetriVmConfig) -> Result<(), anyhow
                 resource: GdmaDeviceHandle {
                     vports: vec![VportDefinition {
                         mac_address: [0x00, 0x15, 0x5D, 0x12, 0x12, 0x12].into(),
-                        endpoint: net_backend_resources::consomme::ConsommeHandle { cidr: None }
-                            .into_resource(),
+                        endpoint: net_backend_resources::consomme::ConsommeHandle {
+                            cidr: None,
+                            ports: Vec::new(),
+                            dhcp: Vec::new(),
+                            requests: None,
+                        }
+                        .into_resource(),
                     }],
+                    rdma: false,
                 }
                 .into_resource(),
             });
@@ -71,11 +79,15 @@ async fn boot_openhcl_linux_mana_nic(config: PetriVmConfig) -> Result<(), anyhow
     let sh = agent.unix_shell();
     cmd!(sh, "ifconfig eth0 up").run().await?;
     cmd!(sh, "udhcpc eth0").run().await?;
-    let output = cmd!(sh, "ifconfig eth0").read().await?;
+    let info = agent.guest_info().await?;
+    let eth0 = info.interface("eth0").context("no eth0 interface")?;
     // Validate that we see a mana nic with the expected MAC address and IPs.
-    assert!(output.contains("HWaddr 00:15:5D:12:12:12"));
-    assert!(output.contains("inet addr:10.0.0.2"));
-    assert!(output.contains("inet6 addr: fe80::215:5dff:fe12:1212/64"));
+    assert_eq!(eth0.mac_address.as_deref(), Some("00:15:5d:12:12:12"));
+    assert!(eth0.has_address(Ipv4Addr::new(10, 0, 0, 2)));
+    assert!(eth0.addresses.contains(&InterfaceAddress {
+        address: "fe80::215:5dff:fe12:1212".parse()?,
+        prefix_len: 64,
+    }));
 
     agent.power_off().await?;
     assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
@@ -101,6 +113,14 @@ async fn mana_nic_shared_pool(config: PetriVmConfig) -> Result<(), anyhow::Error
     .await
 }
 
+/// Test an OpenHCL Linux direct VM with a MANA nic assigned to VTL2 (backed by
+/// the MANA emulator), and vmbus relay, with the worker processes' seccomp
+/// filters enforced. Any syscall outside the policy kills the process.
+#[vmm_test(openhcl_linux_direct_x64)]
+async fn mana_nic_seccomp_strict(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    boot_openhcl_linux_mana_nic(config.with_openhcl_command_line("OPENHCL_SECCOMP=strict")).await
+}
+
 /// Test an OpenHCL Linux direct VM with a SCSI disk assigned to VTL2, and
 /// vmbus relay. This should expose a disk to VTL0 via vmbus.
 #[vmm_test(openhcl_linux_direct_x64)]
@@ -152,6 +172,7 @@ async fn storvsp(config: PetriVmConfig) -> Result<(), anyhow::Error> {
                     subsystem_id: NVME_INSTANCE,
                     max_io_queues: 64,
                     msix_count: 64,
+                    requests: None,
                     namespaces: vec![NamespaceDefinition {
                         nsid: vtl2_nsid,
                         disk: (LayeredDiskHandle::single_layer(RamDiskLayerHandle {
@@ -412,6 +433,7 @@ async fn openhcl_linux_stripe_storvsp(config: PetriVmConfig) -> Result<(), anyho
                         subsystem_id: NVME_INSTANCE_1,
                         max_io_queues: 64,
                         msix_count: 64,
+                        requests: None,
                         namespaces: vec![NamespaceDefinition {
                             nsid: vtl2_nsid,
                             disk: (LayeredDiskHandle::single_layer(RamDiskLayerHandle {
@@ -430,6 +452,7 @@ async fn openhcl_linux_stripe_storvsp(config: PetriVmConfig) -> Result<(), anyho
                         subsystem_id: NVME_INSTANCE_2,
                         max_io_queues: 64,
                         msix_count: 64,
+                        requests: None,
                         namespaces: vec![NamespaceDefinition {
                             nsid: vtl2_nsid,
                             disk: (LayeredDiskHandle::single_layer(RamDiskLayerHandle {
@@ -607,3 +630,30 @@ async fn openhcl_linux_vtl2_ram_self_allocate(config: PetriVmConfig) -> Result<(
 
     Ok(())
 }
+
+/// Service OpenHCL to a new IGVM file and back while the guest is running,
+/// and check that the guest survives both directions.
+///
+/// Both directions currently use the latest build; a released build can be
+/// passed as the old version to test compatibility with it.
+#[vmm_test(openhcl_linux_direct_x64)]
+async fn openhcl_servicing_upgrade_downgrade(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    use petri_artifacts_vmm_test::artifacts::openhcl_igvm::LATEST_LINUX_DIRECT_TEST_X64;
+
+    let (mut vm, agent) = config
+        .with_custom_openhcl(LATEST_LINUX_DIRECT_TEST_X64)
+        .run()
+        .await?;
+
+    vm.service_openhcl_upgrade_downgrade(
+        &agent,
+        LATEST_LINUX_DIRECT_TEST_X64,
+        LATEST_LINUX_DIRECT_TEST_X64,
+    )
+    .await?;
+
+    agent.power_off().await?;
+    assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+
+    Ok(())
+}
@@ -41,6 +41,14 @@ async fn nvme_relay_shared_pool(config: PetriVmConfig) -> Result<(), anyhow::Err
     nvme_relay_test_core(config, "OPENHCL_ENABLE_SHARED_VISIBILITY_POOL=1").await
 }
 
+/// Test an OpenHCL uefi VM with a NVME disk assigned to VTL2 that boots
+/// linux, with vmbus relay, with the worker processes' seccomp filters
+/// enforced. Any syscall outside the policy kills the process.
+#[vmm_test(openhcl_uefi_x64[nvme](vhd(ubuntu_2204_server_x64)))]
+async fn nvme_relay_seccomp_strict(config: PetriVmConfig) -> Result<(), anyhow::Error> {
+    nvme_relay_test_core(config, "OPENHCL_SECCOMP=strict").await
+}
+
 /// Boot the UEFI firmware, with a VTL2 range automatically configured by
 /// hvlite.
 #[vmm_test(openhcl_uefi_x64(none))]
@@ -0,0 +1,156 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Integration tests with multiple x86_64 VMs in a single test.
+
+#![cfg_attr(guest_arch = "aarch64", allow(dead_code, unused_imports))]
+
+use petri::pipette::cmd;
+use petri::pipette::PipetteClient;
+use petri::Firmware;
+use petri::PetriTopology;
+use petri_artifacts_common::tags::MachineArch;
+use vmm_core_defs::HaltReason;
+
+/// Assigns `ip` to the NIC with `mac_address` and brings it up.
+async fn configure_nic(
+    agent: &PipetteClient,
+    mac_address: [u8; 6],
+    ip: &str,
+) -> anyhow::Result<()> {
+    let mac_address = mac_address.map(|b| format!("{b:02x}")).join(":");
+    let sh = agent.unix_shell();
+    let mut found = None;
+    for nic in cmd!(sh, "ls /sys/class/net")
+        .read()
+        .await?
+        .split_whitespace()
+    {
+        let address = sh
+            .read_file(format!("/sys/class/net/{nic}/address"))
+            .await?;
+        if address.trim() == mac_address {
+            found = Some(nic.to_owned());
+            break;
+        }
+    }
+    let nic = found.ok_or_else(|| anyhow::anyhow!("no nic with mac address {mac_address}"))?;
+    cmd!(sh, "ip addr add {ip}/24 dev {nic}").run().await?;
+    cmd!(sh, "ip link set {nic} up").run().await?;
+    Ok(())
+}
+
+/// Returns the path of the disk with `sectors` 512-byte sectors.
+async fn find_disk(agent: &PipetteClient, sectors: u64) -> anyhow::Result<String> {
+    let sh = agent.unix_shell();
+    for disk in cmd!(sh, "ls /sys/block").read().await?.split_whitespace() {
+        let size = sh.read_file(format!("/sys/block/{disk}/size")).await?;
+        if size.trim() == sectors.to_string() {
+            return Ok(format!("/dev/{disk}"));
+        }
+    }
+    anyhow::bail!("no disk with {sectors} sectors")
+}
+
+/// Boot two Linux VMs on a shared network and have them ping each other.
+#[cfg(guest_arch = "x86_64")]
+#[pal_async::async_test]
+async fn linux_direct_x64_two_vm_network(driver: pal_async::DefaultDriver) -> anyhow::Result<()> {
+    let resolver = crate::prelude::vmm_tests_artifact_resolver()
+        .require(petri_artifacts_common::artifacts::TEST_LOG_DIRECTORY)
+        .require(petri_artifacts_vmm_test::artifacts::OPENVMM_NATIVE)
+        .require(petri_artifacts_vmm_test::artifacts::loadable::LINUX_DIRECT_TEST_INITRD_X64)
+        .require(petri_artifacts_vmm_test::artifacts::loadable::LINUX_DIRECT_TEST_KERNEL_X64)
+        .require(petri_artifacts_common::artifacts::PIPETTE_LINUX_X64)
+        .finalize();
+
+    let mut topology = PetriTopology::new(resolver, &driver)?;
+    let network = topology.network()?;
+    let (client, client_mac) = topology
+        .vm("client", Firmware::LinuxDirect, MachineArch::X86_64)?
+        .with_network(&network)?;
+    let (server, server_mac) = topology
+        .vm("server", Firmware::LinuxDirect, MachineArch::X86_64)?
+        .with_network(&network)?;
+
+    let mut vms = PetriTopology::run_all(vec![client, server]).await?;
+    let (server_vm, server_agent) = vms.pop().unwrap();
+    let (client_vm, client_agent) = vms.pop().unwrap();
+
+    configure_nic(&client_agent, client_mac.to_bytes(), "192.168.100.1").await?;
+    configure_nic(&server_agent, server_mac.to_bytes(), "192.168.100.2").await?;
+
+    let sh = client_agent.unix_shell();
+    cmd!(sh, "ping -c 1 -W 10 192.168.100.2").run().await?;
+    let sh = server_agent.unix_shell();
+    cmd!(sh, "ping -c 1 -W 10 192.168.100.1").run().await?;
+
+    for (vm, agent) in [(client_vm, client_agent), (server_vm, server_agent)] {
+        agent.power_off().await?;
+        assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+    }
+    Ok(())
+}
+
+/// Boot two Linux VMs on a shared network with a shared disk. One writes to
+/// the disk and the other reads the data back, after checking that it can
+/// reach the writer over the network.
+#[cfg(guest_arch = "x86_64")]
+#[pal_async::async_test]
+async fn linux_direct_x64_two_vm_shared_disk(
+    driver: pal_async::DefaultDriver,
+) -> anyhow::Result<()> {
+    const DISK_SECTORS: u64 = 0x800;
+    const DATA: &str = "petri shared disk";
+
+    let resolver = crate::prelude::vmm_tests_artifact_resolver()
+        .require(petri_artifacts_common::artifacts::TEST_LOG_DIRECTORY)
+        .require(petri_artifacts_vmm_test::artifacts::OPENVMM_NATIVE)
+        .require(petri_artifacts_vmm_test::artifacts::loadable::LINUX_DIRECT_TEST_INITRD_X64)
+        .require(petri_artifacts_vmm_test::artifacts::loadable::LINUX_DIRECT_TEST_KERNEL_X64)
+        .require(petri_artifacts_common::artifacts::PIPETTE_LINUX_X64)
+        .finalize();
+
+    let mut topology = PetriTopology::new(resolver, &driver)?;
+    let network = topology.network()?;
+    let disk = topology.shared_disk(DISK_SECTORS * 512)?;
+    let (writer, writer_mac) = topology
+        .vm("writer", Firmware::LinuxDirect, MachineArch::X86_64)?
+        .with_shared_disk(&disk)?
+        .with_network(&network)?;
+    let (reader, reader_mac) = topology
+        .vm("reader", Firmware::LinuxDirect, MachineArch::X86_64)?
+        .with_shared_disk(&disk)?
+        .with_network(&network)?;
+
+    let mut vms = PetriTopology::run_all(vec![writer, reader]).await?;
+    let (reader_vm, reader_agent) = vms.pop().unwrap();
+    let (writer_vm, writer_agent) = vms.pop().unwrap();
+
+    configure_nic(&writer_agent, writer_mac.to_bytes(), "192.168.100.1").await?;
+    configure_nic(&reader_agent, reader_mac.to_bytes(), "192.168.100.2").await?;
+
+    let disk = find_disk(&writer_agent, DISK_SECTORS).await?;
+    let sh = writer_agent.unix_shell();
+    cmd!(sh, "dd of={disk} bs=512 count=1 conv=sync,fsync")
+        .stdin(DATA)
+        .run()
+        .await?;
+
+    let disk = find_disk(&reader_agent, DISK_SECTORS).await?;
+    let sh = reader_agent.unix_shell();
+    cmd!(sh, "ping -c 1 -W 10 192.168.100.1").run().await?;
+    // Drop any data cached when the disk was scanned at boot, so that the
+    // read goes to the shared disk.
+    cmd!(sh, "sh -c 'echo 3 > /proc/sys/vm/drop_caches'")
+        .run()
+        .await?;
+    let data = cmd!(sh, "dd if={disk} bs=512 count=1").read().await?;
+    assert_eq!(data.trim_end_matches('\0'), DATA);
+
+    for (vm, agent) in [(writer_vm, writer_agent), (reader_vm, reader_agent)] {
+        agent.power_off().await?;
+        assert_eq!(vm.wait_for_teardown().await?, HaltReason::PowerOff);
+    }
+    Ok(())
+}